@@ -16,6 +16,7 @@ var offlineFundamentals = [
   "favicon.ico",
   "logo.svg",
   "tailwind.css",
+  "theme.css",
 ];
 
 /* The install event fires when the service worker is first installed.
@@ -0,0 +1,17 @@
+//! Criterion benchmarks of script building and signing, see [`scrow::bench`].
+
+// The dependencies are used by the library, which builds the workloads.
+#![allow(unused_crate_dependencies)]
+// `criterion_group!` generates an undocumented function.
+#![allow(missing_docs)]
+
+use criterion::{Criterion, criterion_group, criterion_main};
+
+fn escrow(c: &mut Criterion) {
+    for mut workload in scrow::bench::workloads() {
+        c.bench_function(&workload.name.clone(), |b| b.iter(|| workload.run()));
+    }
+}
+
+criterion_group!(benches, escrow);
+criterion_main!(benches);
@@ -0,0 +1,7 @@
+//! Parses untrusted keys and form inputs.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| scrow::fuzz::key_parsing(data));
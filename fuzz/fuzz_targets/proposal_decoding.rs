@@ -0,0 +1,7 @@
+//! Decodes untrusted offer and acceptance events.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| scrow::fuzz::proposal_decoding(data));
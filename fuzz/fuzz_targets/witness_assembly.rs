@@ -0,0 +1,7 @@
+//! Assembles and audits untrusted escrow spends.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| scrow::fuzz::witness_assembly(data));
@@ -0,0 +1,358 @@
+# English messages of Satoshi Escrow, the fallback of every other language.
+
+## Navigation
+
+nav-home = Home
+nav-create = Create
+nav-sign = Sign
+nav-combine = Combine
+nav-broadcast = Broadcast
+nav-spend = Spend
+nav-settings = Settings
+nav-menu = Menu
+
+## Settings
+
+settings-title = Settings
+settings-network = Default Bitcoin Network
+settings-language = Language
+settings-theme = Theme
+settings-display-unit = Show Amounts In
+settings-min-fee-rate = Minimum Fee Rate (sat/vB)
+settings-max-fee-rate = Maximum Fee Rate (sat/vB)
+settings-confirmation-target = Default Confirmation Target (blocks)
+settings-restore-defaults = Restore Defaults
+settings-save = Save Settings
+settings-saved = Settings saved successfully!
+settings-import-follows-npub = Import Nostr Follows of (npub)
+settings-import-follows = Import Follows
+settings-imported-follows = Imported { $count } new contacts.
+settings-export-address-book = Export Address Book
+settings-notifications = Notifications
+settings-notify-counterparty-signed = When the counterparty signs
+settings-notify-funding-confirmed = When an escrow is funded
+settings-notify-timelock-expiring = When a dispute timelock is expiring
+settings-allow-notifications = Allow Browser Notifications
+settings-notifications-allowed = Browser notifications are allowed.
+settings-notifications-denied = Browser notifications are blocked, allow them in the browser settings.
+settings-save-notifications = Save Notifications
+settings-about = About Satoshi Escrow
+settings-version = Version: { $version }
+settings-description = A Bitcoin non-custodial peer-to-peer dispute resolution tool. All code is open source and runs entirely in your browser.
+settings-github = View on GitHub
+
+## Escrow review
+
+review-deposit-address = Deposit Address
+review-total-deposit = Total Deposit
+review-buyer-amount = Buyer Escrow Amount
+review-seller-amount = Seller Escrow Amount
+review-buyer-address = Buyer's Resolution Address
+review-seller-address = Seller's Resolution Address
+review-fee = Resolution Fee
+review-dispute = Dispute Resolution
+review-dispute-after = { $arbitrator } after { $blocks } blocks
+review-collaborative = None, collaborative only
+review-conditions = Spending Conditions
+
+## Forms
+
+form-unsigned-tx = Unsigned Transaction
+form-signed-tx = Signed Transaction
+form-signed-tx-placeholder = Signed transaction will appear here...
+form-network = Bitcoin Network
+form-buyer-npub = Buyer Nostr Public Key (npub)
+form-seller-npub = Seller Nostr Public Key (npub)
+form-arbitrator-npub = Arbitrator Nostr Public Key (npub)
+form-buyer-amount = Buyer Escrow Amount (BTC)
+form-seller-amount = Seller Escrow Amount (BTC)
+form-funding-txid = Escrow funding Transaction ID
+form-fee-rate = Fee rate (sats/vByte)
+form-target-blocks = Target Blocks
+form-arbitrator-details = Arbitrator Details (for Dispute Resolution)
+form-signature = Signature
+button-transaction = Transaction
+button-sign-transaction = Sign Transaction
+button-continue-sign = Continue to Sign
+button-continue-combine = Continue to Combine
+button-continue-broadcast = Continue to Broadcast
+button-back = Back
+button-next = Next
+
+## Inputs
+
+input-pick-contact = Pick from address book...
+input-contact-payout-address = Their saved payout address { $address } is not the address of their npub, which receives the escrow payouts.
+input-amount-range = Amount must be between 0.00000001 and 100 BTC.
+input-block = { $count } block
+input-blocks = { $count } blocks
+input-fee-rate-range = Fee rate must be between { $min } and { $max } sat/vB.
+input-esplora = Esplora API Backend URL
+input-esplora-invalid = Invalid URL format. URL should start with http:// or https://
+input-esplora-default = Default for mainnet: https://mempool.space/api
+input-esplora-default-endpoint = Default Esplora endpoint: { $endpoint }
+input-esplora-current-endpoint = Current Esplora endpoint: { $endpoint }
+input-relays = Nostr Relays
+input-relays-invalid = Invalid relay URL. Relay URLs should start with wss://
+input-relays-help = One relay per line. Messages are published to all of them.
+input-relays-check = Check relays
+input-relays-online = Online, { $latency } ms
+input-relays-offline = Offline
+input-relays-unknown = Not checked
+input-proxies = SOCKS5 Proxies
+input-proxies-invalid = Invalid proxy. Use host:port, or esplora = host:port to only proxy Esplora.
+input-proxies-help = Use { $proxy } to connect through a local Tor daemon. backend = direct connects without a proxy.
+input-address-book = Address Book
+input-address-book-invalid = Invalid address book. Expected a JSON list of contacts with npub and label.
+input-address-book-help = Contacts with their npub, label, optional payout_address and trust_notes. Paste JSON to import, copy to export.
+input-template = Escrow Template (optional)
+input-template-loaded = Loaded template "{ $name }". Review every step before sharing.
+input-template-help = Paste a community template to fill in the amounts, fee rate, arbitrator and timelock. Only arbitrators in your address book are accepted.
+input-timelock-days = Timelock (Days)
+input-timelock-hours = Timelock (Hours)
+input-timelock-days-range = Days should be between 0 and 1,000.
+input-timelock-hours-range = Hours should be between 0 and 23.
+input-escrow-type = Escrow Type
+input-escrow-type-a = A - Collaborative (2-of-2)
+input-escrow-type-b = B - Dispute: First Party + Arbitrator
+input-escrow-type-c = C - Dispute: Second Party + Arbitrator
+input-nsec = Your Nostr Secret Key (nsec)
+input-nsec-help = Your key is never stored or transmitted. All signing happens locally.
+input-mnemonic = Or Your Recovery Phrase (NIP-06)
+input-mnemonic-words = 12 or 24 words
+input-passphrase = Passphrase (optional)
+input-account = Account 0
+input-accounts = Accounts
+input-accounts-help = Your Nostr identities. Only their npub and label are saved: unlock an account with its key each time you open the app.
+input-account-label = Account Label
+input-account-add = Add or Unlock Account
+input-account-added = Account unlocked.
+input-account-generate = Generate New Account
+input-account-generated = Account generated.
+input-account-write-down = Write down this recovery phrase and keep it safe. It is the only backup of the new account.
+input-account-backup-reminder = Recovery phrase not backed up yet.
+input-account-backup-phrase = Type the Recovery Phrase Back to Confirm Its Backup
+input-account-confirm-backup = Confirm Backup
+input-account-backed-up = Recovery phrase backup confirmed.
+input-account-unlocked = Unlocked
+input-account-locked = Locked
+input-account-lock = Lock
+input-account-remove = Remove
+input-account-removed = Account removed.
+input-account-signing = Signing Account
+input-pick-account = Pick an unlocked account...
+input-account-signing-help = Unlock accounts in Settings, or enter a key below to sign with it.
+input-session-store = Session Store
+input-session-store-help = Saved escrow sessions hold partial signatures. Protect them with a passphrase: the first unlock sets it, and encrypts them.
+input-session-store-passphrase = Passphrase
+input-session-store-new-passphrase = New Passphrase
+input-session-store-unlock = Unlock
+input-session-store-unlocked = Session store unlocked.
+input-session-store-lock = Lock
+input-session-store-locked = Session store locked.
+input-session-store-change = Change Passphrase
+input-session-store-changed = Passphrase changed.
+input-txid-invalid = Invalid transaction ID. Please enter a valid transaction ID.
+input-tx-placeholder = Paste the transaction here...
+input-tx-invalid = Invalid transaction format. The transaction should be a hexadecimal string.
+input-signature-placeholder = Paste the signature here...
+input-signature-invalid = Invalid signature format.
+input-destination = Your Destination Address
+input-destination-placeholder = Enter your destination address...
+input-address-invalid = Invalid Bitcoin address format. Please check and try again.
+output-signature-placeholder = Signature will appear here...
+output-identity-verified = ✓ Verified { $address }
+output-identity-mismatch = ✗ { $address } does not belong to this npub
+
+## Home
+
+home-tagline = A Bitcoin non-custodial peer-to-peer dispute resolution using only Nostr keys.
+home-motivation = Motivation
+home-motivation-text = When a Buyer wants to purchase something for a certain amount of BTC from a Seller but doesn't trust them, they can use our 2-of-2 multisig escrow address. The Buyer and Seller both lock their BTC in a multisig address. Both parties only need their respective Nostr secret keys (nsec) and each other's Nostr public keys (npub).
+home-how = How it works
+home-success = If the trade is successful:
+home-success-sign = Both parties sign to release the funds
+home-success-buyer = Buyer receives his BTC back
+home-success-seller = Seller receives his BTC back
+home-dispute = If there's a dispute:
+home-dispute-arbitrator = Parties can choose a trusted third party (arbitrator)
+home-dispute-timelock = Arbitrator can help resolve the dispute after a timelock period
+home-dispute-signatures = Resolution requires 2-of-3 signatures (Buyer/Seller + Arbitrator)
+home-technical = Technical Implementation
+home-technical-text = We use Pay-to-Taproot (P2TR) multisig script path spends with a verified unknown discrete-log unspendable internal key. The system supports two resolution paths:
+home-collaborative = Collaborative Resolution
+home-collaborative-text = 2-of-2 multisig between Buyer and Seller without timelocks.
+home-dispute-resolution = Dispute Resolution
+home-dispute-resolution-text = 2-of-3 multisig between either party and the arbitrator with timelock.
+home-security = Important Security Notice
+home-security-text = This application can be used offline on an air-gapped computer for maximum security. All transactions can be generated, and signed through the webpage offline.
+home-getting-started = Getting Started
+home-step-create = 1. Create Escrow
+home-step-create-text = Set up a new escrow address using npubs and specify amounts.
+home-step-sign = 2. Sign Transaction
+home-step-sign-text = Sign the transaction using your nsec key.
+home-step-combine = 3. Combine Signatures
+home-step-combine-text = Combine the signatures into a signed transaction.
+home-step-broadcast = 4. Broadcast
+home-step-broadcast-text = Broadcast the signed transaction to the Bitcoin network.
+home-step-spend = 5. Spend
+home-step-spend-text = Spend from the resolution address derived from your npub using your nsec.
+nav-logo = Satoshi Escrow Logo
+footer = Satoshi Escrow - Open Source Bitcoin Dispute Resolution
+
+## Create
+
+create-title = Create Escrow
+create-progress = Progress
+create-step-parties = Parties
+create-step-amounts = Amounts & Fees
+create-step-dispute = Timelock & Arbitrator
+create-step-review = Review
+create-step-share = Share Proposal
+create-role = Your Role
+create-role-buyer = Buyer
+create-role-seller = Seller
+create-buyer-contact = Buyer from Address Book
+create-seller-contact = Seller from Address Book
+create-arbitrator-contact = Arbitrator from Address Book
+create-fee-split = The resolution transaction fee is split evenly between buyer and seller.
+create-arbitrator-optional = Optional: leave the arbitrator empty for a collaborative 2-of-2 escrow without timelock.
+create-share = Send the proposal to your counterparty. They derive the same deposit address from it before funding.
+create-proposal = Proposal
+create-payment-request = Payment Request
+create-fund-faucet = Fund from Faucet
+create-faucet-sent = Faucet sent transaction { $txid }, waiting for it...
+create-faucet-funded = Faucet funded the escrow with { $amount } in transaction { $txid }.
+create-funding-warning = Deposit a single transaction to the escrow address and inform the transaction ID.
+create-invalid-funding-txid = Invalid input: funding transaction ID.
+create-unsigned-resolution = Unsigned Escrow Resolution Transaction
+create-tx-placeholder = Transaction data will appear here...
+create-generate = Generate Transaction
+create-proposal-button = Create Proposal
+
+## Sign
+
+sign-title = Sign Escrow
+sign-decision-reason = Decision Reason (when signing as the arbitrator)
+sign-publishing-decision = Publishing the decision...
+sign-decision-sent = Decision sent to the participants
+
+## Combine
+
+combine-title = Combine Signatures
+combine-npub-1 = First Nostr Public Key (npub)
+combine-npub-2 = Second Nostr Public Key (npub)
+combine-signature-1 = First Signature
+combine-signature-2 = Second Signature
+combine-signature-arbitrator = Arbitrator Signature
+
+## Broadcast
+
+broadcast-title = Broadcast Transaction
+broadcast-error = Error broadcasting transaction: { $reason }
+broadcast-success = Transaction Broadcasted Successfully
+broadcast-txid = Transaction ID:
+broadcast-explorer = View on Block Explorer
+broadcast-failed = Broadcast Failed
+
+## Spend
+
+spend-title = Spend from Resolution Address
+spend-npub = Your Nostr Public Key (npub)
+spend-txid = Escrow Resolution Transaction ID
+spend-vout = Escrow Resolution Transaction Output Index
+spend-amount = Total Locked Amount (BTC)
+spend-address = Your Resolution Address
+
+## Transactions
+
+inspector-txid = Transaction ID
+inspector-size = Size
+inspector-lock-time = Lock Time
+inspector-fee = Fee
+inspector-fee-unknown = Unknown, previous outputs not found
+inspector-input = Input { $index }
+inspector-sequence = Sequence { $sequence }
+inspector-spends = , spends { $amount }
+inspector-output = Output { $index }: { $amount }
+witness-signature = Signature for { $signer }
+witness-key-path = Key path signature
+witness-leaf-timelocked = Leaf script, timelocked { $blocks } blocks
+witness-leaf = Leaf script
+witness-control-block = Control block, depth { $depth }
+witness-annex = Annex
+witness-unknown = Witness item
+export-title = Export
+export-help = Broadcast the transaction with another wallet or block explorer.
+export-hex = Raw Hex
+export-psbt = PSBT (base64)
+export-bbqr = BBQr ({ $parts } QR codes)
+
+## Contract summaries
+
+summary = Funds can be spent { $clauses }.
+summary-clause = by { $signers } { $timing }
+summary-and = and
+summary-or = or
+summary-anytime = at any time
+summary-after-blocks = after { $blocks } blocks (~{ $duration })
+summary-minute = { $count } minute
+summary-minutes = { $count } minutes
+summary-hour = { $count } hour
+summary-hours = { $count } hours
+summary-day = { $count } day
+summary-days = { $count } days
+party-participant-1 = participant 1
+party-participant-2 = participant 2
+party-arbitrator = the arbitrator
+party-unknown = unknown key { $key }
+party-buyer = the buyer
+party-seller = the seller
+
+## Notifications
+
+notification-counterparty-signed-title = Counterparty signed
+notification-counterparty-signed-body = { $counterparty } signed their part of the escrow { $escrow }.
+notification-funding-confirmed-title = Escrow funded
+notification-funding-confirmed-body = The funding of { $escrow } has { $confirmations } confirmations.
+notification-timelock-expiring-title = Dispute timelock expiring
+notification-timelock-expiring-body = The arbitrator can resolve { $escrow } in { $blocks } blocks, from height { $height }.
+notification-timelock-expired-title = Dispute timelock expired
+notification-timelock-expired-body = The arbitrator can resolve { $escrow } since height { $height }.
+
+## Errors
+
+error-wrong-inputs = Invalid input: { $reason }.
+error-invalid-escrow-type = Unknown escrow type "{ $escrow_type }".
+error-invalid-network = Unknown network "{ $network }".
+error-invariant-violation = Refusing to sign: { $report }.
+error-relay-quorum = Only { $accepted } of { $required } Nostr relays accepted the message.
+error-trust-proof = Could not prove that no party can spend alone: { $reason }.
+error-funding-mismatch = The escrow is funded with { $actual } instead of the agreed { $expected }.
+error-unsupported-script-template = The escrow uses script template version { $version }, which this version of Satoshi Escrow does not support. Update it to continue.
+error-template = Invalid escrow template: { $reason }.
+error-protocol = Invalid escrow negotiation: { $reason }.
+error-broadcast-rejected = The network rejected the transaction: { $reason }.
+error-non-standard = The network would refuse the transaction: { $reason }.
+error-musig = Cooperative signing failed: { $reason }.
+error-adaptor = Secret-locked signing failed: { $reason }.
+error-notification = Could not show the notification: { $reason }.
+error-context = { $context }: { $message }
+error-address = Invalid Bitcoin address.
+error-amount = Invalid Bitcoin amount.
+error-transaction-decode = Invalid transaction hex.
+error-secp256k1 = Invalid key or signature.
+error-nostr = Invalid Nostr key.
+error-nostr-event = Invalid Nostr event.
+error-nostr-event-builder = Could not sign the Nostr event.
+error-address-not-owned = The address does not belong to the given Nostr key.
+error-sighash = Could not compute the transaction signature hash.
+error-taproot-builder = Could not build the escrow script tree.
+error-rounding = Amounts do not add up: check the escrow amounts and fee.
+error-expected-one-funding = The escrow address must be funded by exactly one transaction.
+error-esplora = Could not reach the Esplora server.
+error-expired = The escrow proposal has expired.
+error-relay = Could not reach the Nostr relays.
+error-nip05 = Could not verify the Nostr address.
+error-http = Could not reach the server.
+error-storage = Could not access the browser storage.
@@ -0,0 +1,358 @@
+# Mensagens do Satoshi Escrow em português do Brasil.
+
+## Navegação
+
+nav-home = Início
+nav-create = Criar
+nav-sign = Assinar
+nav-combine = Combinar
+nav-broadcast = Transmitir
+nav-spend = Gastar
+nav-settings = Configurações
+nav-menu = Menu
+
+## Configurações
+
+settings-title = Configurações
+settings-network = Rede Bitcoin padrão
+settings-language = Idioma
+settings-theme = Tema
+settings-display-unit = Mostrar valores em
+settings-min-fee-rate = Taxa mínima (sat/vB)
+settings-max-fee-rate = Taxa máxima (sat/vB)
+settings-confirmation-target = Meta de confirmação padrão (blocos)
+settings-restore-defaults = Restaurar padrões
+settings-save = Salvar configurações
+settings-saved = Configurações salvas com sucesso!
+settings-import-follows-npub = Importar quem (npub) segue no Nostr
+settings-import-follows = Importar seguidos
+settings-imported-follows = { $count } novos contatos importados.
+settings-export-address-book = Exportar agenda de contatos
+settings-notifications = Notificações
+settings-notify-counterparty-signed = Quando a contraparte assinar
+settings-notify-funding-confirmed = Quando um escrow for financiado
+settings-notify-timelock-expiring = Quando um timelock de disputa estiver expirando
+settings-allow-notifications = Permitir notificações do navegador
+settings-notifications-allowed = As notificações do navegador estão permitidas.
+settings-notifications-denied = As notificações do navegador estão bloqueadas, permita-as nas configurações do navegador.
+settings-save-notifications = Salvar notificações
+settings-about = Sobre o Satoshi Escrow
+settings-version = Versão: { $version }
+settings-description = Uma ferramenta de resolução de disputas peer-to-peer e sem custódia para Bitcoin. Todo o código é aberto e roda inteiramente no seu navegador.
+settings-github = Ver no GitHub
+
+## Revisão do escrow
+
+review-deposit-address = Endereço de depósito
+review-total-deposit = Depósito total
+review-buyer-amount = Valor do comprador no escrow
+review-seller-amount = Valor do vendedor no escrow
+review-buyer-address = Endereço de resolução do comprador
+review-seller-address = Endereço de resolução do vendedor
+review-fee = Taxa de resolução
+review-dispute = Resolução de disputas
+review-dispute-after = { $arbitrator } após { $blocks } blocos
+review-collaborative = Nenhuma, apenas colaborativa
+review-conditions = Condições de gasto
+
+## Formulários
+
+form-unsigned-tx = Transação não assinada
+form-signed-tx = Transação assinada
+form-signed-tx-placeholder = A transação assinada aparecerá aqui...
+form-network = Rede Bitcoin
+form-buyer-npub = Chave pública Nostr do comprador (npub)
+form-seller-npub = Chave pública Nostr do vendedor (npub)
+form-arbitrator-npub = Chave pública Nostr do árbitro (npub)
+form-buyer-amount = Valor do comprador no escrow (BTC)
+form-seller-amount = Valor do vendedor no escrow (BTC)
+form-funding-txid = ID da transação de depósito do escrow
+form-fee-rate = Taxa (sats/vByte)
+form-target-blocks = Blocos alvo
+form-arbitrator-details = Dados do árbitro (para resolução de disputas)
+form-signature = Assinatura
+button-transaction = Transação
+button-sign-transaction = Assinar transação
+button-continue-sign = Continuar para assinar
+button-continue-combine = Continuar para combinar
+button-continue-broadcast = Continuar para transmitir
+button-back = Voltar
+button-next = Avançar
+
+## Campos
+
+input-pick-contact = Escolher da agenda de contatos...
+input-contact-payout-address = O endereço de pagamento salvo { $address } não é o endereço da npub do contato, que recebe os pagamentos do escrow.
+input-amount-range = O valor deve estar entre 0,00000001 e 100 BTC.
+input-block = { $count } bloco
+input-blocks = { $count } blocos
+input-fee-rate-range = A taxa deve estar entre { $min } e { $max } sat/vB.
+input-esplora = URL da API Esplora
+input-esplora-invalid = Formato de URL inválido. A URL deve começar com http:// ou https://
+input-esplora-default = Padrão para a mainnet: https://mempool.space/api
+input-esplora-default-endpoint = Endpoint Esplora padrão: { $endpoint }
+input-esplora-current-endpoint = Endpoint Esplora atual: { $endpoint }
+input-relays = Relays Nostr
+input-relays-invalid = URL de relay inválida. As URLs de relays devem começar com wss://
+input-relays-help = Um relay por linha. As mensagens são publicadas em todos eles.
+input-relays-check = Verificar relays
+input-relays-online = Online, { $latency } ms
+input-relays-offline = Offline
+input-relays-unknown = Não verificado
+input-proxies = Proxies SOCKS5
+input-proxies-invalid = Proxy inválido. Use host:porta, ou esplora = host:porta para usar o proxy só no Esplora.
+input-proxies-help = Use { $proxy } para conectar por um daemon Tor local. backend = direct conecta sem proxy.
+input-address-book = Agenda de contatos
+input-address-book-invalid = Agenda de contatos inválida. Esperada uma lista JSON de contatos com npub e label.
+input-address-book-help = Contatos com npub, label e, opcionalmente, payout_address e trust_notes. Cole um JSON para importar, copie para exportar.
+input-template = Modelo de escrow (opcional)
+input-template-loaded = Modelo "{ $name }" carregado. Revise cada etapa antes de compartilhar.
+input-template-help = Cole um modelo da comunidade para preencher os valores, a taxa, o árbitro e o bloqueio de tempo. Só são aceitos árbitros da sua agenda de contatos.
+input-timelock-days = Bloqueio de tempo (dias)
+input-timelock-hours = Bloqueio de tempo (horas)
+input-timelock-days-range = Os dias devem estar entre 0 e 1.000.
+input-timelock-hours-range = As horas devem estar entre 0 e 23.
+input-escrow-type = Tipo de escrow
+input-escrow-type-a = A - Colaborativo (2-de-2)
+input-escrow-type-b = B - Disputa: primeira parte + árbitro
+input-escrow-type-c = C - Disputa: segunda parte + árbitro
+input-nsec = Sua chave secreta Nostr (nsec)
+input-nsec-help = Sua chave nunca é armazenada nem transmitida. Toda assinatura acontece localmente.
+input-mnemonic = Ou sua frase de recuperação (NIP-06)
+input-mnemonic-words = 12 ou 24 palavras
+input-passphrase = Senha adicional (opcional)
+input-account = Conta 0
+input-accounts = Contas
+input-accounts-help = Suas identidades Nostr. Apenas o npub e o nome são salvos: desbloqueie uma conta com sua chave a cada vez que abrir o app.
+input-account-label = Nome da Conta
+input-account-add = Adicionar ou Desbloquear Conta
+input-account-added = Conta desbloqueada.
+input-account-generate = Gerar Nova Conta
+input-account-generated = Conta gerada.
+input-account-write-down = Anote esta frase de recuperação e guarde-a em segurança. Ela é o único backup da nova conta.
+input-account-backup-reminder = Frase de recuperação ainda sem backup.
+input-account-backup-phrase = Digite a Frase de Recuperação para Confirmar o Backup
+input-account-confirm-backup = Confirmar Backup
+input-account-backed-up = Backup da frase de recuperação confirmado.
+input-account-unlocked = Desbloqueada
+input-account-locked = Bloqueada
+input-account-lock = Bloquear
+input-account-remove = Remover
+input-account-removed = Conta removida.
+input-account-signing = Conta que Assina
+input-pick-account = Escolher uma conta desbloqueada...
+input-account-signing-help = Desbloqueie contas nas Configurações, ou digite uma chave abaixo para assinar com ela.
+input-session-store = Sessões Salvas
+input-session-store-help = As sessões de escrow salvas contêm assinaturas parciais. Proteja-as com uma senha: o primeiro desbloqueio a define e as criptografa.
+input-session-store-passphrase = Senha
+input-session-store-new-passphrase = Nova Senha
+input-session-store-unlock = Desbloquear
+input-session-store-unlocked = Sessões desbloqueadas.
+input-session-store-lock = Bloquear
+input-session-store-locked = Sessões bloqueadas.
+input-session-store-change = Alterar Senha
+input-session-store-changed = Senha alterada.
+input-txid-invalid = ID de transação inválido. Informe um ID de transação válido.
+input-tx-placeholder = Cole a transação aqui...
+input-tx-invalid = Formato de transação inválido. A transação deve ser uma string hexadecimal.
+input-signature-placeholder = Cole a assinatura aqui...
+input-signature-invalid = Formato de assinatura inválido.
+input-destination = Seu endereço de destino
+input-destination-placeholder = Informe seu endereço de destino...
+input-address-invalid = Formato de endereço Bitcoin inválido. Verifique e tente novamente.
+output-signature-placeholder = A assinatura aparecerá aqui...
+output-identity-verified = ✓ { $address } verificado
+output-identity-mismatch = ✗ { $address } não pertence a esta npub
+
+## Início
+
+home-tagline = Resolução de disputas Bitcoin peer-to-peer e sem custódia, usando apenas chaves Nostr.
+home-motivation = Motivação
+home-motivation-text = Quando um comprador quer comprar algo de um vendedor por uma certa quantia de BTC, mas não confia nele, pode usar nosso endereço de escrow multisig 2-de-2. O comprador e o vendedor bloqueiam seus BTC em um endereço multisig. Cada parte só precisa da sua chave secreta Nostr (nsec) e da chave pública Nostr (npub) da outra.
+home-how = Como funciona
+home-success = Se a negociação for bem-sucedida:
+home-success-sign = As duas partes assinam para liberar os fundos
+home-success-buyer = O comprador recebe seus BTC de volta
+home-success-seller = O vendedor recebe seus BTC de volta
+home-dispute = Se houver uma disputa:
+home-dispute-arbitrator = As partes podem escolher um terceiro de confiança (árbitro)
+home-dispute-timelock = O árbitro pode ajudar a resolver a disputa após um período de bloqueio
+home-dispute-signatures = A resolução exige 2 de 3 assinaturas (comprador/vendedor + árbitro)
+home-technical = Implementação técnica
+home-technical-text = Usamos gastos multisig pelo caminho de script Pay-to-Taproot (P2TR), com uma chave interna ingastável de logaritmo discreto desconhecido e verificável. O sistema oferece dois caminhos de resolução:
+home-collaborative = Resolução colaborativa
+home-collaborative-text = Multisig 2-de-2 entre comprador e vendedor, sem bloqueio de tempo.
+home-dispute-resolution = Resolução de disputas
+home-dispute-resolution-text = Multisig 2-de-3 entre qualquer uma das partes e o árbitro, com bloqueio de tempo.
+home-security = Aviso de segurança importante
+home-security-text = Este aplicativo pode ser usado offline, em um computador isolado, para máxima segurança. Todas as transações podem ser geradas e assinadas pela página sem conexão.
+home-getting-started = Primeiros passos
+home-step-create = 1. Criar escrow
+home-step-create-text = Configure um novo endereço de escrow com npubs e defina os valores.
+home-step-sign = 2. Assinar transação
+home-step-sign-text = Assine a transação com sua chave nsec.
+home-step-combine = 3. Combinar assinaturas
+home-step-combine-text = Combine as assinaturas em uma transação assinada.
+home-step-broadcast = 4. Transmitir
+home-step-broadcast-text = Transmita a transação assinada para a rede Bitcoin.
+home-step-spend = 5. Gastar
+home-step-spend-text = Gaste do endereço de resolução derivado da sua npub usando sua nsec.
+nav-logo = Logo do Satoshi Escrow
+footer = Satoshi Escrow - Resolução de disputas Bitcoin de código aberto
+
+## Criar
+
+create-title = Criar escrow
+create-progress = Progresso
+create-step-parties = Partes
+create-step-amounts = Valores e taxas
+create-step-dispute = Bloqueio de tempo e árbitro
+create-step-review = Revisão
+create-step-share = Compartilhar proposta
+create-role = Seu papel
+create-role-buyer = Comprador
+create-role-seller = Vendedor
+create-buyer-contact = Comprador da agenda de contatos
+create-seller-contact = Vendedor da agenda de contatos
+create-arbitrator-contact = Árbitro da agenda de contatos
+create-fee-split = A taxa da transação de resolução é dividida igualmente entre comprador e vendedor.
+create-arbitrator-optional = Opcional: deixe o árbitro vazio para um escrow colaborativo 2-de-2 sem bloqueio de tempo.
+create-share = Envie a proposta para a outra parte. Ela deriva dela o mesmo endereço de depósito antes de depositar.
+create-proposal = Proposta
+create-payment-request = Pedido de pagamento
+create-fund-faucet = Depositar pelo faucet
+create-faucet-sent = O faucet enviou a transação { $txid }, aguardando...
+create-faucet-funded = O faucet depositou { $amount } no escrow na transação { $txid }.
+create-funding-warning = Deposite uma única transação no endereço do escrow e informe o ID da transação.
+create-invalid-funding-txid = Entrada inválida: ID da transação de depósito.
+create-unsigned-resolution = Transação de resolução do escrow não assinada
+create-tx-placeholder = Os dados da transação aparecerão aqui...
+create-generate = Gerar transação
+create-proposal-button = Criar proposta
+
+## Assinar
+
+sign-title = Assinar escrow
+sign-decision-reason = Motivo da decisão (ao assinar como árbitro)
+sign-publishing-decision = Publicando a decisão...
+sign-decision-sent = Decisão enviada aos participantes
+
+## Combinar
+
+combine-title = Combinar assinaturas
+combine-npub-1 = Primeira chave pública Nostr (npub)
+combine-npub-2 = Segunda chave pública Nostr (npub)
+combine-signature-1 = Primeira assinatura
+combine-signature-2 = Segunda assinatura
+combine-signature-arbitrator = Assinatura do árbitro
+
+## Transmitir
+
+broadcast-title = Transmitir transação
+broadcast-error = Erro ao transmitir a transação: { $reason }
+broadcast-success = Transação transmitida com sucesso
+broadcast-txid = ID da transação:
+broadcast-explorer = Ver no explorador de blocos
+broadcast-failed = Falha na transmissão
+
+## Gastar
+
+spend-title = Gastar do endereço de resolução
+spend-npub = Sua chave pública Nostr (npub)
+spend-txid = ID da transação de resolução do escrow
+spend-vout = Índice da saída da transação de resolução do escrow
+spend-amount = Valor total bloqueado (BTC)
+spend-address = Seu endereço de resolução
+
+## Transações
+
+inspector-txid = ID da transação
+inspector-size = Tamanho
+inspector-lock-time = Lock time
+inspector-fee = Taxa
+inspector-fee-unknown = Desconhecida, saídas anteriores não encontradas
+inspector-input = Entrada { $index }
+inspector-sequence = Sequência { $sequence }
+inspector-spends = , gasta { $amount }
+inspector-output = Saída { $index }: { $amount }
+witness-signature = Assinatura de { $signer }
+witness-key-path = Assinatura pelo caminho da chave
+witness-leaf-timelocked = Script da folha, bloqueado por { $blocks } blocos
+witness-leaf = Script da folha
+witness-control-block = Bloco de controle, profundidade { $depth }
+witness-annex = Anexo
+witness-unknown = Item da testemunha
+export-title = Exportar
+export-help = Transmita a transação com outra carteira ou explorador de blocos.
+export-hex = Hex bruto
+export-psbt = PSBT (base64)
+export-bbqr = BBQr ({ $parts } códigos QR)
+
+## Resumos de contrato
+
+summary = Os fundos podem ser gastos { $clauses }.
+summary-clause = { $timing }, com as assinaturas de: { $signers }
+summary-and = e
+summary-or = ou
+summary-anytime = a qualquer momento
+summary-after-blocks = após { $blocks } blocos (~{ $duration })
+summary-minute = { $count } minuto
+summary-minutes = { $count } minutos
+summary-hour = { $count } hora
+summary-hours = { $count } horas
+summary-day = { $count } dia
+summary-days = { $count } dias
+party-participant-1 = participante 1
+party-participant-2 = participante 2
+party-arbitrator = árbitro
+party-unknown = chave desconhecida { $key }
+party-buyer = comprador
+party-seller = vendedor
+
+## Notificações
+
+notification-counterparty-signed-title = Contraparte assinou
+notification-counterparty-signed-body = { $counterparty } assinou sua parte do escrow { $escrow }.
+notification-funding-confirmed-title = Escrow financiado
+notification-funding-confirmed-body = O financiamento de { $escrow } tem { $confirmations } confirmações.
+notification-timelock-expiring-title = Timelock de disputa expirando
+notification-timelock-expiring-body = O árbitro poderá resolver { $escrow } em { $blocks } blocos, a partir da altura { $height }.
+notification-timelock-expired-title = Timelock de disputa expirado
+notification-timelock-expired-body = O árbitro pode resolver { $escrow } desde a altura { $height }.
+
+## Erros
+
+error-wrong-inputs = Entrada inválida: { $reason }.
+error-invalid-escrow-type = Tipo de escrow desconhecido "{ $escrow_type }".
+error-invalid-network = Rede desconhecida "{ $network }".
+error-invariant-violation = Assinatura recusada: { $report }.
+error-relay-quorum = Apenas { $accepted } de { $required } relays Nostr aceitaram a mensagem.
+error-trust-proof = Não foi possível provar que nenhuma parte pode gastar sozinha: { $reason }.
+error-funding-mismatch = O escrow foi financiado com { $actual } em vez dos { $expected } combinados.
+error-unsupported-script-template = O escrow usa a versão { $version } do modelo de script, que esta versão do Satoshi Escrow não suporta. Atualize-o para continuar.
+error-template = Modelo de escrow inválido: { $reason }.
+error-protocol = Negociação de escrow inválida: { $reason }.
+error-broadcast-rejected = A rede rejeitou a transação: { $reason }.
+error-non-standard = A rede recusaria a transação: { $reason }.
+error-musig = A assinatura cooperativa falhou: { $reason }.
+error-adaptor = A assinatura condicionada ao segredo falhou: { $reason }.
+error-notification = Não foi possível mostrar a notificação: { $reason }.
+error-context = { $context }: { $message }
+error-address = Endereço Bitcoin inválido.
+error-amount = Valor em Bitcoin inválido.
+error-transaction-decode = Hex de transação inválido.
+error-secp256k1 = Chave ou assinatura inválida.
+error-nostr = Chave Nostr inválida.
+error-nostr-event = Evento Nostr inválido.
+error-nostr-event-builder = Não foi possível assinar o evento Nostr.
+error-address-not-owned = O endereço não pertence à chave Nostr informada.
+error-sighash = Não foi possível calcular o hash de assinatura da transação.
+error-taproot-builder = Não foi possível construir a árvore de scripts do escrow.
+error-rounding = Os valores não fecham: confira os valores do escrow e a taxa.
+error-expected-one-funding = O endereço do escrow deve ser financiado por exatamente uma transação.
+error-esplora = Não foi possível acessar o servidor Esplora.
+error-expired = A proposta de escrow expirou.
+error-relay = Não foi possível acessar os relays Nostr.
+error-nip05 = Não foi possível verificar o endereço Nostr.
+error-http = Não foi possível acessar o servidor.
+error-storage = Não foi possível acessar o armazenamento do navegador.
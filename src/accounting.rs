@@ -0,0 +1,409 @@
+//! Accounting export of completed escrows.
+//!
+//! An [`EscrowRecord`] sums up an escrow once resolved, cancelled or spent by a conflicting
+//! transaction: when it was funded and resolved, the amounts and fees, the counterparty,
+//! the transactions and the outcome. Merchants hand the records to their accountant as
+//! CSV, see [`to_csv`], or JSON, see [`to_json`].
+//!
+//! The fee of the resolution needs its [`Transaction`], and its fiat value a [`Price`]
+//! quoted when it was resolved, which the caller only passes if the price feed is enabled.
+
+use std::fmt::Write as _;
+
+use bitcoin::{Amount, Transaction, Txid};
+use nostr::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    history::HistoryEvent,
+    keys::Npub,
+    price::{FiatAmount, Price},
+    protocol::{Handshake, Role, Session, SessionId},
+    scripts::EscrowScript,
+};
+
+/// Columns of the CSV export, in order.
+pub(crate) const CSV_HEADER: [&str; 15] = [
+    "session_id",
+    "role",
+    "counterparty",
+    "funded_at",
+    "resolved_at",
+    "outcome",
+    "amount_buyer_sat",
+    "amount_seller_sat",
+    "funded_sat",
+    "network_fee_sat",
+    "platform_fee_sat",
+    "funding_txid",
+    "resolution_txid",
+    "fiat_currency",
+    "fiat_value",
+];
+
+/// How an escrow ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Outcome {
+    /// Both participants signed the resolution.
+    Collaborative,
+    /// A participant and the arbitrator signed the resolution after the timelock.
+    Arbitrated,
+    /// A participant cancelled the escrow before it was resolved.
+    Cancelled,
+    /// The escrow was spent by a transaction nobody signed for in the session.
+    Conflict,
+}
+
+impl Outcome {
+    /// Name of the outcome in the export.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Outcome::Collaborative => "collaborative",
+            Outcome::Arbitrated => "arbitrated",
+            Outcome::Cancelled => "cancelled",
+            Outcome::Conflict => "conflict",
+        }
+    }
+}
+
+/// A completed escrow, as handed to an accountant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct EscrowRecord {
+    /// Negotiation of the escrow.
+    pub(crate) session_id: SessionId,
+    /// The user's role in the escrow.
+    pub(crate) role: Role,
+    /// The other participant.
+    pub(crate) counterparty: Npub,
+    /// When the escrow was funded, if it was.
+    pub(crate) funded_at: Option<Timestamp>,
+    /// When the escrow was resolved, cancelled or spent.
+    pub(crate) resolved_at: Timestamp,
+    /// How the escrow ended.
+    pub(crate) outcome: Outcome,
+    /// Buyer's escrow amount.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount_buyer: Amount,
+    /// Seller's escrow amount.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount_seller: Amount,
+    /// Total amount paid to the escrow.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) funded: Amount,
+    /// Mining fee of the resolution, if its transaction is known.
+    #[serde(with = "bitcoin::amount::serde::as_sat::opt")]
+    pub(crate) network_fee: Option<Amount>,
+    /// Platform fee paid by the resolution.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) platform_fee: Amount,
+    /// The original funding transaction, if any.
+    pub(crate) funding_txid: Option<Txid>,
+    /// The transaction spending the escrow, if any.
+    pub(crate) resolution_txid: Option<Txid>,
+    /// Value of the escrow amounts at the price quoted at resolution, if any.
+    pub(crate) fiat_value: Option<FiatAmount>,
+}
+
+impl EscrowRecord {
+    /// The record of the escrow of `session` from the point of view of the participant `npub`,
+    /// or `None` if it is not completed yet.
+    ///
+    /// `resolution` is the transaction that spent the escrow, to compute the mining fee,
+    /// and `price` the price quoted at resolution, to value the escrow in fiat.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `npub` is not a participant of the escrow, or `resolution` is not the
+    /// transaction that spent it.
+    pub(crate) fn new(
+        session: &Session,
+        npub: &Npub,
+        resolution: Option<&Transaction>,
+        price: Option<&Price>,
+    ) -> Result<Option<Self>, Error> {
+        let Handshake::Agreed {
+            offer, acceptance, ..
+        } = &session.handshake
+        else {
+            return Ok(None);
+        };
+        let Some((outcome, resolution_txid, resolved_at)) = resolution_of(session) else {
+            return Ok(None);
+        };
+
+        let (buyer, seller) = offer.participants(&acceptance.acceptor);
+        let (role, counterparty) = if npub.nostr() == *buyer {
+            (Role::Buyer, *seller)
+        } else if npub.nostr() == *seller {
+            (Role::Seller, *buyer)
+        } else {
+            return Err(Error::WrongInputs(format!(
+                "{npub} is not a participant of the escrow"
+            )));
+        };
+
+        let (amount_buyer, amount_seller) = session.handshake.amounts()?;
+        let total = amount_buyer
+            .checked_add(amount_seller)
+            .ok_or(Error::Rounding)?;
+        let funded = session
+            .funding
+            .as_ref()
+            .map_or(Amount::ZERO, |funding| funding.amount());
+        let network_fee = match resolution {
+            Some(tx) => {
+                if Some(tx.compute_txid()) != resolution_txid {
+                    return Err(Error::WrongInputs(format!(
+                        "Transaction {} did not resolve the escrow",
+                        tx.compute_txid()
+                    )));
+                }
+                let outputs = tx.output.iter().map(|output| output.value).sum();
+                Some(funded.checked_sub(outputs).ok_or(Error::Rounding)?)
+            }
+            None => None,
+        };
+        let platform_fee = match (&offer.platform_fee, resolution_txid) {
+            (Some(platform_fee), Some(_)) => platform_fee.amount(total),
+            _ => Amount::ZERO,
+        };
+
+        Ok(Some(Self {
+            session_id: session.handshake.session_id()?,
+            role,
+            counterparty: Npub::from(counterparty),
+            funded_at: session.history().entries().iter().find_map(|entry| {
+                matches!(entry.event, HistoryEvent::Funded { .. }).then_some(entry.at)
+            }),
+            resolved_at,
+            outcome,
+            amount_buyer,
+            amount_seller,
+            funded,
+            network_fee,
+            platform_fee,
+            funding_txid: session
+                .funding
+                .as_ref()
+                .and_then(|funding| funding.outputs.first())
+                .map(|output| output.outpoint.txid),
+            resolution_txid,
+            fiat_value: price.map(|price| price.to_fiat(total)),
+        }))
+    }
+}
+
+/// How the escrow of `session` ended, the transaction that spent it and when,
+/// from its history.
+pub(crate) fn resolution_of(session: &Session) -> Option<(Outcome, Option<Txid>, Timestamp)> {
+    let funding_txids: Vec<Txid> = session
+        .funding
+        .iter()
+        .flat_map(|funding| &funding.outputs)
+        .map(|output| output.outpoint.txid)
+        .collect();
+    let entries = session.history().entries();
+    if let Some(conflict) = &session.conflict {
+        let txid = conflict.spend.txid;
+        let at = entries.iter().find_map(|entry| {
+            (entry.event == HistoryEvent::Conflict { txid }).then_some(entry.at)
+        })?;
+        return Some((Outcome::Conflict, Some(txid), at));
+    }
+    let confirmed = entries.iter().find_map(|entry| match entry.event {
+        HistoryEvent::Confirmed { txid, .. } if !funding_txids.contains(&txid) => {
+            Some((txid, entry.at))
+        }
+        _ => None,
+    });
+    if let Some((txid, at)) = confirmed {
+        // Key path spends have no leaf signatures, and need both participants.
+        let outcome = match session
+            .signatures
+            .iter()
+            .find(|signatures| signatures.txid == txid)
+            .map(|signatures| signatures.escrow_script)
+        {
+            Some(EscrowScript::B | EscrowScript::C) => Outcome::Arbitrated,
+            Some(EscrowScript::A) | None => Outcome::Collaborative,
+        };
+        return Some((outcome, Some(txid), at));
+    }
+    entries.iter().find_map(|entry| {
+        matches!(entry.event, HistoryEvent::Cancelled { .. }).then_some((
+            Outcome::Cancelled,
+            None,
+            entry.at,
+        ))
+    })
+}
+
+/// Quotes a CSV `field` if it holds a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The `records` as CSV, with a [`CSV_HEADER`] line.
+///
+/// Dates are in UTC ISO 8601, amounts in sats and fiat values in decimal units of
+/// their currency, empty if unknown.
+pub(crate) fn to_csv(records: &[EscrowRecord]) -> String {
+    let mut csv = CSV_HEADER.join(",");
+    csv.push('\n');
+    for record in records {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let fields = [
+            record.session_id.to_string(),
+            format!("{:?}", record.role).to_lowercase(),
+            record.counterparty.to_string(),
+            optional(record.funded_at.map(|at| at.to_human_datetime())),
+            record.resolved_at.to_human_datetime(),
+            record.outcome.name().to_string(),
+            record.amount_buyer.to_sat().to_string(),
+            record.amount_seller.to_sat().to_string(),
+            record.funded.to_sat().to_string(),
+            optional(record.network_fee.map(|fee| fee.to_sat().to_string())),
+            record.platform_fee.to_sat().to_string(),
+            optional(record.funding_txid.map(|txid| txid.to_string())),
+            optional(record.resolution_txid.map(|txid| txid.to_string())),
+            optional(record.fiat_value.map(|value| value.currency.to_string())),
+            optional(
+                record
+                    .fiat_value
+                    .map(|value| format!("{}.{:02}", value.cents / 100, value.cents % 100)),
+            ),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        let _ = writeln!(csv, "{}", fields.join(","));
+    }
+    csv
+}
+
+/// The `records` as a pretty-printed JSON array.
+///
+/// Dates are UNIX timestamps and amounts are in sats.
+pub(crate) fn to_json(records: &[EscrowRecord]) -> Result<String, Error> {
+    serde_json::to_string_pretty(records)
+        .map_err(|e| Error::Storage(format!("Could not serialize records: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{OutPoint, ScriptBuf, TxOut, absolute, hashes::Hash, transaction::Version};
+    use nostr::Keys;
+
+    use super::*;
+    use crate::{
+        funding::{Funding, FundingOutput},
+        price::{Currency, PriceProvider},
+        protocol::{DEFAULT_OFFER_VALIDITY, Offer, offer},
+    };
+
+    #[test]
+    fn escrow_records() {
+        let buyer = Keys::generate();
+        let seller = Keys::generate();
+        let now = Timestamp::from(1_700_000_000);
+        let offer = Offer {
+            role: Role::Buyer,
+            amount_seller: Amount::ZERO,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(buyer.public_key(), None)
+        };
+        let (_, offer_event) = Handshake::offer(buyer.secret_key(), offer, now).unwrap();
+        let (agreed, _) = Handshake::accept(seller.secret_key(), &offer_event, None, now).unwrap();
+        let mut session = Session::new(agreed);
+        let npub_buyer = Npub::from(buyer.public_key());
+
+        let funding_txid = Txid::from_byte_array([1; 32]);
+        session.funding = Some(Funding {
+            expected: Amount::from_sat(100_000),
+            outputs: vec![FundingOutput {
+                outpoint: OutPoint::new(funding_txid, 0),
+                amount: Amount::from_sat(100_000),
+            }],
+        });
+        session.record_funded(funding_txid, Amount::from_sat(100_000), now + 60);
+        session.record_confirmation(funding_txid, 100, now + 600);
+        // Funded but not resolved yet.
+        assert_eq!(
+            EscrowRecord::new(&session, &npub_buyer, None, None).unwrap(),
+            None
+        );
+
+        let resolution = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let resolution_txid = resolution.compute_txid();
+        session.record_confirmation(resolution_txid, 110, now + 6_000);
+        let price = Price {
+            currency: Currency::Brl,
+            cents_per_btc: 50_000_000,
+            provider: PriceProvider::Mempool,
+            time: now + 6_000,
+        };
+        let record = EscrowRecord::new(&session, &npub_buyer, Some(&resolution), Some(&price))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.role, Role::Buyer);
+        assert_eq!(record.counterparty, Npub::from(seller.public_key()));
+        assert_eq!(record.outcome, Outcome::Collaborative);
+        assert_eq!(record.funded_at, Some(now + 60));
+        assert_eq!(record.resolved_at, now + 6_000);
+        assert_eq!(record.network_fee, Some(Amount::from_sat(1_000)));
+        assert_eq!(record.funding_txid, Some(funding_txid));
+        assert_eq!(record.resolution_txid, Some(resolution_txid));
+        assert_eq!(record.fiat_value.unwrap().cents, 50_000);
+
+        // Not the transaction that resolved the escrow, or not a participant.
+        assert!(
+            EscrowRecord::new(
+                &session,
+                &npub_buyer,
+                Some(&Transaction {
+                    output: Vec::new(),
+                    ..resolution.clone()
+                }),
+                None
+            )
+            .is_err()
+        );
+        assert!(
+            EscrowRecord::new(
+                &session,
+                &Npub::from(Keys::generate().public_key()),
+                None,
+                None
+            )
+            .is_err()
+        );
+
+        let csv = to_csv(std::slice::from_ref(&record));
+        let mut lines = csv.lines();
+        assert_eq!(lines.next().unwrap(), CSV_HEADER.join(","));
+        let row: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(row.len(), CSV_HEADER.len());
+        assert_eq!(row[1], "buyer");
+        assert_eq!(row[5], "collaborative");
+        assert_eq!(row[9], "1000");
+        assert_eq!(row[13], "BRL");
+        assert_eq!(row[14], "500.00");
+        assert_eq!(csv_field("a \"b\", c"), "\"a \"\"b\"\", c\"");
+
+        let json = to_json(std::slice::from_ref(&record)).unwrap();
+        let restored: Vec<EscrowRecord> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, vec![record]);
+    }
+}
@@ -0,0 +1,441 @@
+//! Several Nostr identities, each signing its own escrows.
+//!
+//! Users can register several nsecs, such as a personal and a business identity,
+//! and pick which one takes part in each escrow.
+//! [`Accounts`] persisted in [`Storage`] only hold each identity's `npub`, its label
+//! and the [`SessionId`]s it takes part in: the secrets stay in the in-memory [`Keystore`]
+//! while the app runs, so registering an identity never writes an nsec to the device.
+//!
+//! Accounts are identified by their [`Npub`], the handle signing code such as
+//! [`sign_escrow_tx`](crate::sign::sign_escrow_tx) takes along with the [`Keystore`]
+//! instead of a raw nsec, so secrets never leave the keystore but to sign.
+//!
+//! Identities generated inside scrow come from a [`RecoveryPhrase`], and their account
+//! reminds the user to write it down until they [confirm the backup](Accounts::confirm_backup).
+
+use std::fmt;
+
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, keys::Npub, recovery::RecoveryPhrase, secret::SecretNsec};
+#[cfg(feature = "serde-types")]
+use crate::{
+    protocol::{Handshake, Session, SessionId, deserialize, serialize},
+    storage::Storage,
+    vault::{Vault, VaultStorage, locked},
+};
+
+/// [`Storage`] key of the [`Accounts`].
+#[cfg(feature = "serde-types")]
+pub(crate) const ACCOUNTS_KEY: &str = "scrow.accounts";
+
+/// A registered identity.
+#[cfg(feature = "serde-types")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Account {
+    /// Public key of the identity, identifying the account.
+    pub(crate) npub: Npub,
+    /// Name shown for the account, such as "Personal".
+    pub(crate) label: String,
+    /// Escrows the identity takes part in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) sessions: Vec<SessionId>,
+    /// Whether the identity was generated in scrow and its recovery phrase
+    /// is not backed up yet.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) needs_backup: bool,
+}
+
+/// Registered identities, in registration order, with at most one per `npub`.
+#[cfg(feature = "serde-types")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Accounts {
+    accounts: Vec<Account>,
+}
+
+#[cfg(feature = "serde-types")]
+impl Accounts {
+    /// Loads the accounts from `storage`, or none if none were saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(ACCOUNTS_KEY)? {
+            Some(json) => deserialize(&json),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the accounts to `storage`.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        storage.set(ACCOUNTS_KEY, &serialize(self)?)
+    }
+
+    /// All accounts, in registration order.
+    pub(crate) fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    /// The account of `npub`, if registered.
+    pub(crate) fn get(&self, npub: &Npub) -> Option<&Account> {
+        self.accounts.iter().find(|account| account.npub == *npub)
+    }
+
+    /// Registers the identity `npub` under `label`, or renames it if already registered.
+    pub(crate) fn register(&mut self, npub: Npub, label: &str) -> Result<(), Error> {
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(Error::WrongInputs("Account label is empty".to_string()));
+        }
+        match self
+            .accounts
+            .iter_mut()
+            .find(|account| account.npub == npub)
+        {
+            Some(account) => account.label = label.to_string(),
+            None => self.accounts.push(Account {
+                npub,
+                label: label.to_string(),
+                sessions: Vec::new(),
+                needs_backup: false,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Registers an identity generated by [`Keystore::generate`], reminding the user
+    /// to back up its recovery phrase.
+    pub(crate) fn register_generated(&mut self, npub: Npub, label: &str) -> Result<(), Error> {
+        self.register(npub, label)?;
+        if let Some(account) = self.accounts.iter_mut().find(|a| a.npub == npub) {
+            account.needs_backup = true;
+        }
+        Ok(())
+    }
+
+    /// The accounts whose recovery phrase is not backed up yet.
+    pub(crate) fn backup_reminders(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.iter().filter(|account| account.needs_backup)
+    }
+
+    /// Marks the recovery phrase of the account of `npub` as backed up,
+    /// once the user typed it back.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the account is not registered, or if `phrase` and `passphrase`
+    /// don't recover its identity.
+    pub(crate) fn confirm_backup(
+        &mut self,
+        npub: &Npub,
+        phrase: &RecoveryPhrase,
+        passphrase: &str,
+    ) -> Result<(), Error> {
+        let account = self
+            .accounts
+            .iter_mut()
+            .find(|account| account.npub == *npub)
+            .ok_or_else(|| Error::WrongInputs(format!("Unknown account {npub}")))?;
+        if Npub::from(phrase.to_nsec(passphrase, 0)?.public_key()) != *npub {
+            return Err(Error::WrongInputs(
+                "Recovery phrase does not match the account".to_string(),
+            ));
+        }
+        account.needs_backup = false;
+        Ok(())
+    }
+
+    /// Removes the account of `npub`, if registered.
+    ///
+    /// Its sessions are kept in storage, without an identity until reassigned.
+    pub(crate) fn remove(&mut self, npub: &Npub) -> Option<Account> {
+        let index = self.accounts.iter().position(|a| a.npub == *npub)?;
+        Some(self.accounts.remove(index))
+    }
+
+    /// The account taking part in the session `id`, if any.
+    pub(crate) fn account_of(&self, id: &SessionId) -> Option<&Account> {
+        self.accounts
+            .iter()
+            .find(|account| account.sessions.contains(id))
+    }
+
+    /// Makes the account of `npub` the identity of `session`, instead of any other account.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the account is not registered or is not a participant of the escrow.
+    pub(crate) fn assign(&mut self, session: &Session, npub: &Npub) -> Result<(), Error> {
+        if self.get(npub).is_none() {
+            return Err(Error::WrongInputs(format!("Unknown account {npub}")));
+        }
+        if !is_participant(session, npub)? {
+            return Err(Error::WrongInputs(format!(
+                "Account {npub} does not take part in the escrow"
+            )));
+        }
+        let id = session.id()?;
+        for account in &mut self.accounts {
+            account.sessions.retain(|session| *session != id);
+            if account.npub == *npub {
+                account.sessions.push(id);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `npub` is the offerer of `session`, or one of the keys of its agreed escrow.
+#[cfg(feature = "serde-types")]
+fn is_participant(session: &Session, npub: &Npub) -> Result<bool, Error> {
+    let npub = npub.nostr();
+    if session.handshake.negotiated_offer().offerer == npub {
+        return Ok(true);
+    }
+    if !matches!(session.handshake, Handshake::Agreed { .. }) {
+        return Ok(false);
+    }
+    let config = session.escrow_config()?;
+    Ok([
+        Some(config.npub_1),
+        Some(config.npub_2),
+        config.npub_arbitrator,
+    ]
+    .contains(&Some(npub)))
+}
+
+/// Secrets of the unlocked [`Account`]s, held in memory only.
+///
+/// Signing code asks for the secret of an account with [`Keystore::nsec`],
+/// given its handle, such as the [`Account`] of a session.
+/// The stored sessions are read through [`Keystore::sessions`] once the [`Vault`] is unlocked.
+#[derive(Default)]
+pub(crate) struct Keystore {
+    secrets: Vec<(Npub, SecretNsec)>,
+    #[cfg(feature = "serde-types")]
+    vault: Option<Vault>,
+}
+
+impl Keystore {
+    /// Unlocks the account of `nsec`, returning its handle.
+    pub(crate) fn unlock(&mut self, nsec: SecretNsec) -> Npub {
+        let npub = Npub::from(nsec.public_key());
+        self.secrets.retain(|(unlocked, _)| *unlocked != npub);
+        self.secrets.push((npub, nsec));
+        npub
+    }
+
+    /// Generates a new identity from a random recovery phrase of `words` words,
+    /// protected by the optional BIP-39 `passphrase`, and unlocks it.
+    ///
+    /// The phrase is only returned here: show it to the user to back it up,
+    /// then register the identity with [`Accounts::register_generated`].
+    pub(crate) fn generate(
+        &mut self,
+        words: usize,
+        passphrase: &str,
+    ) -> Result<(Npub, RecoveryPhrase), Error> {
+        let phrase = RecoveryPhrase::generate(words)?;
+        let npub = self.recover(&phrase, passphrase)?;
+        Ok((npub, phrase))
+    }
+
+    /// Unlocks the identity of a backed up recovery phrase, returning its handle.
+    pub(crate) fn recover(
+        &mut self,
+        phrase: &RecoveryPhrase,
+        passphrase: &str,
+    ) -> Result<Npub, Error> {
+        Ok(self.unlock(phrase.to_nsec(passphrase, 0)?))
+    }
+
+    /// Unlocks the encrypted session store of `storage` with the keystore `passphrase`,
+    /// encrypting it on first use.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn unlock_sessions(
+        &mut self,
+        storage: &impl Storage,
+        passphrase: &str,
+    ) -> Result<(), Error> {
+        let vault = if Vault::exists(storage)? {
+            Vault::unlock(storage, passphrase)?
+        } else {
+            Vault::create(storage, passphrase)?
+        };
+        self.vault = Some(vault);
+        Ok(())
+    }
+
+    /// Locks the session store, wiping its key from memory.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn lock_sessions(&mut self) {
+        self.vault = None;
+    }
+
+    /// Whether the session store was unlocked with its passphrase.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn sessions_unlocked(&self) -> bool {
+        self.vault.is_some()
+    }
+
+    /// Protects the session store of `storage` with `passphrase` instead of the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the session store is locked or `passphrase` is empty.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn change_passphrase(
+        &self,
+        storage: &impl Storage,
+        passphrase: &str,
+    ) -> Result<(), Error> {
+        self.vault
+            .as_ref()
+            .ok_or_else(locked)?
+            .change_passphrase(storage, passphrase)
+    }
+
+    /// `storage`, with the sessions decrypted, for loading and saving [`Session`]s.
+    ///
+    /// Until a passphrase protects the session store, sessions are read and saved in clear.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the session store is locked.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn sessions<'a, S: Storage>(
+        &'a self,
+        storage: &'a S,
+    ) -> Result<VaultStorage<'a, S>, Error> {
+        match &self.vault {
+            Some(vault) => Ok(vault.storage(storage)),
+            None if !Vault::exists(storage)? => Ok(VaultStorage::clear(storage)),
+            None => Err(locked()),
+        }
+    }
+
+    /// Locks the account of `npub`, wiping its secret from memory.
+    ///
+    /// Returns whether it was unlocked.
+    pub(crate) fn lock(&mut self, npub: &Npub) -> bool {
+        let unlocked = self.secrets.len();
+        self.secrets.retain(|(account, _)| account != npub);
+        self.secrets.len() != unlocked
+    }
+
+    /// Whether the account of `npub` is unlocked.
+    pub(crate) fn is_unlocked(&self, npub: &Npub) -> bool {
+        self.secrets.iter().any(|(account, _)| account == npub)
+    }
+
+    /// The handles of the unlocked accounts, in unlocking order.
+    pub(crate) fn unlocked(&self) -> impl Iterator<Item = &Npub> {
+        self.secrets.iter().map(|(npub, _)| npub)
+    }
+
+    /// A copy of the secret of the account of `npub`, for the signing APIs.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the account is locked.
+    pub(crate) fn nsec(&self, npub: &Npub) -> Result<SecretNsec, Error> {
+        self.secrets
+            .iter()
+            .find(|(account, _)| account == npub)
+            .map(|(_, nsec)| nsec.duplicate())
+            .ok_or_else(|| Error::WrongInputs(format!("Account {npub} is locked")))
+    }
+}
+
+impl fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                self.secrets
+                    .iter()
+                    .map(|(npub, nsec)| (npub.to_string(), nsec)),
+            )
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "serde-types"))]
+mod tests {
+    use bitcoin::Amount;
+    use nostr::Timestamp;
+
+    use super::*;
+    use crate::{
+        protocol::{DEFAULT_OFFER_VALIDITY, Offer, offer},
+        recovery::nsec_from_mnemonic,
+        storage::MemoryStorage,
+    };
+
+    #[test]
+    fn accounts() {
+        let now = Timestamp::now();
+        let mut keystore = Keystore::default();
+        let personal = keystore.unlock(SecretNsec::generate());
+        let business = keystore.unlock(SecretNsec::generate());
+        let mut accounts = Accounts::default();
+        accounts.register(personal, " Personal ").unwrap();
+        accounts.register(business, "Business").unwrap();
+        assert!(accounts.register(business, " ").is_err());
+
+        let offer = Offer {
+            amount_seller: Amount::ZERO,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(business.nostr(), None)
+        };
+        let (handshake, _) = keystore
+            .nsec(&business)
+            .unwrap()
+            .with_nostr_secret_key(|secret_key| Handshake::offer(secret_key, offer, now))
+            .unwrap();
+        let session = Session::new(handshake);
+        let id = session.id().unwrap();
+        assert!(accounts.assign(&session, &personal).is_err());
+        accounts.assign(&session, &business).unwrap();
+        assert_eq!(accounts.account_of(&id).unwrap().label, "Business");
+
+        let storage = MemoryStorage::default();
+        accounts.save(&storage).unwrap();
+        let accounts = Accounts::load(&storage).unwrap();
+        assert_eq!(accounts.accounts()[0].label, "Personal");
+        let account = accounts.account_of(&id).unwrap().npub;
+        let nsec = keystore.nsec(&account).unwrap();
+        assert_eq!(Npub::from(nsec.public_key()), business);
+
+        assert!(keystore.lock(&business));
+        assert!(!keystore.is_unlocked(&business));
+        assert_eq!(keystore.unlocked().collect::<Vec<_>>(), [&personal]);
+        assert!(keystore.nsec(&account).is_err());
+        assert!(!format!("{keystore:?}").contains("nsec1"));
+    }
+
+    #[test]
+    fn recovery_phrase_backup() {
+        let mut keystore = Keystore::default();
+        let (npub, phrase) = keystore.generate(12, "").unwrap();
+        let mut accounts = Accounts::default();
+        accounts.register_generated(npub, "Personal").unwrap();
+        assert_eq!(accounts.backup_reminders().count(), 1);
+
+        let other = RecoveryPhrase::generate(12).unwrap();
+        assert!(accounts.confirm_backup(&npub, &other, "").is_err());
+        assert!(accounts.confirm_backup(&npub, &phrase, "extra").is_err());
+        let typed = phrase.words().collect::<Vec<_>>().join(" ").to_uppercase();
+        let typed = RecoveryPhrase::parse(&typed).unwrap();
+        accounts.confirm_backup(&npub, &typed, "").unwrap();
+        assert_eq!(accounts.backup_reminders().count(), 0);
+
+        // Another device recovers the same identity from the phrase.
+        keystore.lock(&npub);
+        let mut device = Keystore::default();
+        assert_eq!(device.recover(&typed, "").unwrap(), npub);
+        assert!(device.is_unlocked(&npub));
+        let words = typed.words().collect::<Vec<_>>().join(" ");
+        let mut unlock = |account| device.unlock(nsec_from_mnemonic(&words, "", account).unwrap());
+        assert_eq!(unlock(0), npub);
+        assert_ne!(unlock(1), npub);
+    }
+}
@@ -0,0 +1,398 @@
+//! BIP-340 adaptor signatures, for escrow releases that reveal a secret.
+//!
+//! An [`AdaptorSignature`] is a signature made incomplete by an [`AdaptorPoint`] `T = t·G`:
+//! anyone can check it will become a valid BIP-340 signature once the [`AdaptorSecret`] `t`
+//! is added, and whoever sees the completed signature learns `t`.
+//!
+//! This makes the seller's release signature contingent on a secret, such as a delivery code
+//! or a payment preimage, instead of on plain 2-of-2 cooperation:
+//!
+//! 1. The buyer derives the [`AdaptorSecret`] from the secret and shares its [`AdaptorPoint`].
+//! 2. The seller signs the release leaf with [`adaptor_sign_leaf`] and shares the adaptor
+//!    signature, which the buyer checks with [`verify_adaptor_signature`].
+//! 3. The buyer completes it with [`AdaptorSignature::complete`] to broadcast the release,
+//!    and the seller recovers the secret from the witness with [`AdaptorSignature::extract`].
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{
+    Script, Transaction, TxOut,
+    hex::{DisplayHex, FromHex},
+};
+use nostr::key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey};
+use secp256k1::{
+    Message, Parity, PublicKey, SECP256K1, Scalar, SecretKey, XOnlyPublicKey, schnorr,
+};
+
+use crate::{
+    error::Error, logging::REDACTED, message::tagged_hash, secret::SecretNsec,
+    sign::script_spend_message, util::npub_to_x_only_public_key,
+};
+
+/// Tag of the hash deriving an [`AdaptorSecret`] from a shared secret.
+const SECRET_TAG: &[u8] = b"scrow/adaptor/secret";
+
+/// Tag of the hash deriving the signing nonce.
+const NONCE_TAG: &[u8] = b"scrow/adaptor/nonce";
+
+/// Tag of the BIP-340 challenge hash.
+const CHALLENGE_TAG: &[u8] = b"BIP0340/challenge";
+
+/// Size in bytes of a serialized [`AdaptorSignature`]:
+/// the compressed nonce and adaptor points, and the scalar.
+const ADAPTOR_SIGNATURE_SIZE: usize = 98;
+
+/// Hashes `data` with `tag` into a non-zero scalar.
+fn hash_to_scalar(tag: &[u8], data: &[u8]) -> Result<SecretKey, Error> {
+    Ok(SecretKey::from_slice(&tagged_hash(tag, data))?)
+}
+
+/// `a + b mod n`.
+fn add(a: SecretKey, b: SecretKey) -> Result<SecretKey, Error> {
+    Ok(a.add_tweak(&Scalar::from(b))?)
+}
+
+/// `a * b mod n`.
+fn mul(a: SecretKey, b: SecretKey) -> Result<SecretKey, Error> {
+    Ok(a.mul_tweak(&Scalar::from(b))?)
+}
+
+/// The BIP-340 challenge of a signature with `nonce` by `key` on `message`.
+pub(crate) fn challenge(
+    nonce: &PublicKey,
+    key: &PublicKey,
+    message: &Message,
+) -> Result<SecretKey, Error> {
+    let mut data = nonce.x_only_public_key().0.serialize().to_vec();
+    data.extend_from_slice(&key.x_only_public_key().0.serialize());
+    data.extend_from_slice(message.as_ref());
+    hash_to_scalar(CHALLENGE_TAG, &data)
+}
+
+/// The secret `t` that completes an [`AdaptorSignature`].
+///
+/// It is not [`Clone`], and is erased when dropped.
+#[derive(PartialEq, Eq)]
+pub(crate) struct AdaptorSecret(SecretKey);
+
+impl AdaptorSecret {
+    /// Derives the secret from a shared `secret`, such as a delivery code or a payment preimage.
+    ///
+    /// Surrounding whitespace of codes typed by hand is ignored.
+    pub(crate) fn derive(secret: &[u8]) -> Result<Self, Error> {
+        Ok(Self(hash_to_scalar(SECRET_TAG, secret.trim_ascii())?))
+    }
+
+    /// The secret revealed by an oracle's BIP-340 attestation `signature`: its scalar `s`,
+    /// whose point is anticipated from the announced nonce, see [`crate::oracle`].
+    pub(crate) fn from_attestation(signature: &schnorr::Signature) -> Result<Self, Error> {
+        Ok(Self(SecretKey::from_slice(&signature.as_ref()[32..])?))
+    }
+
+    /// The [`AdaptorPoint`] `t·G` of the secret, safe to share.
+    pub(crate) fn point(&self) -> AdaptorPoint {
+        AdaptorPoint(PublicKey::from_secret_key(SECP256K1, &self.0))
+    }
+
+    /// The secret as a signing key, for leaves locked to the x-only key of its point.
+    pub(crate) fn to_nsec(&self) -> SecretNsec {
+        SecretNsec::from(
+            NostrSecretKey::from_slice(&self.0.secret_bytes()).expect("valid secret key"),
+        )
+    }
+}
+
+impl fmt::Debug for AdaptorSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AdaptorSecret").field(&REDACTED).finish()
+    }
+}
+
+impl Drop for AdaptorSecret {
+    fn drop(&mut self) {
+        self.0.non_secure_erase();
+    }
+}
+
+/// The public point `T = t·G` of an [`AdaptorSecret`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AdaptorPoint(PublicKey);
+
+impl AdaptorPoint {
+    /// The x-only key of the point, to lock a leaf to its secret.
+    pub(crate) fn x_only_public_key(&self) -> XOnlyPublicKey {
+        self.0.x_only_public_key().0
+    }
+}
+
+impl From<PublicKey> for AdaptorPoint {
+    fn from(point: PublicKey) -> Self {
+        Self(point)
+    }
+}
+
+impl fmt::Display for AdaptorPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.serialize().to_lower_hex_string())
+    }
+}
+
+impl FromStr for AdaptorPoint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = <[u8; 33]>::from_hex(s.trim())
+            .map_err(|_| Error::Adaptor("an adaptor point must be 33 bytes of hex".to_string()))?;
+        Ok(Self(PublicKey::from_slice(&bytes)?))
+    }
+}
+
+/// A BIP-340 signature missing the [`AdaptorSecret`] of its [`AdaptorPoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AdaptorSignature {
+    /// Nonce of the completed signature, `R = k·G + T`.
+    nonce: PublicKey,
+    /// The adaptor point `T`.
+    adaptor: AdaptorPoint,
+    /// `s' = k + e·x`, negated nonce included when `R` has an odd Y coordinate.
+    s: SecretKey,
+}
+
+impl AdaptorSignature {
+    /// The [`AdaptorPoint`] the signature is locked to.
+    pub(crate) fn adaptor(&self) -> AdaptorPoint {
+        self.adaptor
+    }
+
+    /// Whether the completed signature negates its nonce, as `R` has an odd Y coordinate.
+    fn negated(&self) -> bool {
+        self.nonce.x_only_public_key().1 == Parity::Odd
+    }
+
+    /// Completes the signature with the `secret` of its [`AdaptorPoint`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if `secret` is not the secret of the adaptor point.
+    pub(crate) fn complete(&self, secret: &AdaptorSecret) -> Result<schnorr::Signature, Error> {
+        if secret.point() != self.adaptor {
+            return Err(Error::Adaptor(
+                "the secret does not match the adaptor point".to_string(),
+            ));
+        }
+        let t = if self.negated() {
+            secret.0.negate()
+        } else {
+            secret.0
+        };
+        let s = add(self.s, t)?;
+        let mut bytes = [0; 64];
+        bytes[..32].copy_from_slice(&self.nonce.x_only_public_key().0.serialize());
+        bytes[32..].copy_from_slice(&s.secret_bytes());
+        Ok(schnorr::Signature::from_slice(&bytes)?)
+    }
+
+    /// Recovers the [`AdaptorSecret`] from the completed `signature`, such as one read from
+    /// the witness of the broadcast release.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `signature` is not the completion of this adaptor signature.
+    pub(crate) fn extract(&self, signature: &schnorr::Signature) -> Result<AdaptorSecret, Error> {
+        let bytes = signature.serialize();
+        let mismatch = || Error::Adaptor("the signature does not complete this one".to_string());
+        if bytes[..32] != self.nonce.x_only_public_key().0.serialize() {
+            return Err(mismatch());
+        }
+        let s = SecretKey::from_slice(&bytes[32..]).map_err(|_| mismatch())?;
+        let t = add(s, self.s.negate()).map_err(|_| mismatch())?;
+        let secret = AdaptorSecret(if self.negated() { t.negate() } else { t });
+        if secret.point() != self.adaptor {
+            return Err(mismatch());
+        }
+        Ok(secret)
+    }
+
+    /// The 98-byte serialization of the signature.
+    pub(crate) fn serialize(&self) -> [u8; ADAPTOR_SIGNATURE_SIZE] {
+        let mut bytes = [0; ADAPTOR_SIGNATURE_SIZE];
+        bytes[..33].copy_from_slice(&self.nonce.serialize());
+        bytes[33..66].copy_from_slice(&self.adaptor.0.serialize());
+        bytes[66..].copy_from_slice(&self.s.secret_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for AdaptorSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.serialize().to_lower_hex_string())
+    }
+}
+
+impl FromStr for AdaptorSignature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = <[u8; ADAPTOR_SIGNATURE_SIZE]>::from_hex(s.trim()).map_err(|_| {
+            Error::Adaptor(format!(
+                "an adaptor signature must be {ADAPTOR_SIGNATURE_SIZE} bytes of hex"
+            ))
+        })?;
+        Ok(Self {
+            nonce: PublicKey::from_slice(&bytes[..33])?,
+            adaptor: AdaptorPoint(PublicKey::from_slice(&bytes[33..66])?),
+            s: SecretKey::from_slice(&bytes[66..])?,
+        })
+    }
+}
+
+/// Signs `message` with `nsec`, locked to `adaptor`.
+///
+/// The nonce mixes fresh randomness with the secret key, the adaptor point and the message,
+/// so a weak random number generator alone does not lead to nonce reuse.
+pub(crate) fn adaptor_sign(
+    nsec: &SecretNsec,
+    message: &Message,
+    adaptor: &AdaptorPoint,
+) -> Result<AdaptorSignature, Error> {
+    let mut x = nsec.with_keypair(|keypair| {
+        let secret_key = SecretKey::from_keypair(keypair);
+        // BIP-340 keys are even: odd signers negate their secret key.
+        match keypair.x_only_public_key().1 {
+            Parity::Even => secret_key,
+            Parity::Odd => secret_key.negate(),
+        }
+    });
+    let key = PublicKey::from_secret_key(SECP256K1, &x);
+
+    let mut data = NostrSecretKey::generate().secret_bytes().to_vec();
+    data.extend_from_slice(&tagged_hash(NONCE_TAG, &x.secret_bytes()));
+    data.extend_from_slice(&adaptor.0.serialize());
+    data.extend_from_slice(message.as_ref());
+    let mut k = hash_to_scalar(NONCE_TAG, &data)?;
+    let nonce = PublicKey::from_secret_key(SECP256K1, &k).combine(&adaptor.0)?;
+    if nonce.x_only_public_key().1 == Parity::Odd {
+        k = k.negate();
+    }
+
+    let e = challenge(&nonce, &key, message)?;
+    let s = add(k, mul(e, x)?);
+    k.non_secure_erase();
+    x.non_secure_erase();
+    Ok(AdaptorSignature {
+        nonce,
+        adaptor: *adaptor,
+        s: s?,
+    })
+}
+
+/// Verifies that `signature` by `npub` on `message` becomes a valid BIP-340 signature
+/// once completed with the secret of its [`AdaptorPoint`].
+///
+/// # Errors
+///
+/// Errors if the adaptor signature is invalid.
+pub(crate) fn verify_adaptor_signature(
+    signature: &AdaptorSignature,
+    npub: &NostrPublicKey,
+    message: &Message,
+) -> Result<(), Error> {
+    let key = npub_to_x_only_public_key(npub)?.public_key(Parity::Even);
+    let e = challenge(&signature.nonce, &key, message)?;
+    // s'·G = ±(R - T) + e·P
+    let mut nonce = signature
+        .nonce
+        .combine(&signature.adaptor.0.negate(SECP256K1))?;
+    if signature.negated() {
+        nonce = nonce.negate(SECP256K1);
+    }
+    let expected = nonce.combine(&key.mul_tweak(SECP256K1, &Scalar::from(e))?)?;
+    if PublicKey::from_secret_key(SECP256K1, &signature.s) != expected {
+        return Err(Error::Adaptor(format!(
+            "invalid adaptor signature from {npub}"
+        )));
+    }
+    Ok(())
+}
+
+/// Signs the spend of input `index` of `tx` through the `locking_script` leaf with `nsec`,
+/// locked to `adaptor`.
+///
+/// Once completed, the signature goes into the [`LeafSignatures`](crate::sign::LeafSignatures)
+/// of the leaf like any other.
+pub(crate) fn adaptor_sign_leaf(
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    locking_script: &Script,
+    nsec: &SecretNsec,
+    adaptor: &AdaptorPoint,
+) -> Result<AdaptorSignature, Error> {
+    let message = script_spend_message(tx, index, prevouts, locking_script)?;
+    adaptor_sign(nsec, &message, adaptor)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::{Hash, sha256};
+
+    use super::*;
+
+    #[test]
+    fn reveal_to_release() {
+        let message = Message::from_digest(sha256::Hash::hash(b"release").to_byte_array());
+        for parity in [Parity::Odd, Parity::Even] {
+            let seller = SecretNsec::generate_with_parity(parity);
+            let npub = seller.public_key();
+            let secret = AdaptorSecret::derive(b" 482-193 \n").unwrap();
+            assert_eq!(secret, AdaptorSecret::derive(b"482-193").unwrap());
+            let adaptor = secret.point();
+            assert_eq!(
+                adaptor.to_string().parse::<AdaptorPoint>().unwrap(),
+                adaptor
+            );
+
+            // Both nonce parities, with fresh randomness each time.
+            for _ in 0..8 {
+                let adaptor_signature = adaptor_sign(&seller, &message, &adaptor).unwrap();
+                assert_eq!(
+                    adaptor_signature
+                        .to_string()
+                        .parse::<AdaptorSignature>()
+                        .unwrap(),
+                    adaptor_signature
+                );
+                verify_adaptor_signature(&adaptor_signature, &npub, &message).unwrap();
+                let other = Message::from_digest([1; 32]);
+                assert!(verify_adaptor_signature(&adaptor_signature, &npub, &other).is_err());
+
+                // Not a valid signature until completed.
+                let incomplete = schnorr::Signature::from_slice(
+                    &[
+                        &adaptor_signature.nonce.x_only_public_key().0.serialize()[..],
+                        &adaptor_signature.s.secret_bytes()[..],
+                    ]
+                    .concat(),
+                )
+                .unwrap();
+                assert!(
+                    SECP256K1
+                        .verify_schnorr(&incomplete, &message, &seller.x_only_public_key())
+                        .is_err()
+                );
+                assert!(
+                    adaptor_signature
+                        .complete(&AdaptorSecret::derive(b"a wrong code").unwrap())
+                        .is_err()
+                );
+
+                let signature = adaptor_signature.complete(&secret).unwrap();
+                SECP256K1
+                    .verify_schnorr(&signature, &message, &seller.x_only_public_key())
+                    .unwrap();
+                assert_eq!(adaptor_signature.extract(&signature).unwrap(), secret);
+                assert!(adaptor_signature.extract(&incomplete).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Address book of contacts and their payout addresses.
+//!
+//! Contacts are keyed by their Nostr public key, persisted as JSON in [`Storage`],
+//! and can be exported and imported to move them between devices.
+
+use bitcoin::{Address, Network, address::NetworkUnchecked};
+use nostr::key::PublicKey as NostrPublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    storage::Storage,
+    util::{npub_to_address, verify_address_ownership},
+};
+
+/// [`Storage`] key of the address book.
+pub(crate) const ADDRESS_BOOK_KEY: &str = "scrow.address_book";
+
+/// A contact in the [`AddressBook`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Contact {
+    /// Contact's Nostr public key.
+    pub(crate) npub: NostrPublicKey,
+    /// Name shown for the contact.
+    pub(crate) label: String,
+    /// Payout address, if not the contact's `npub`-derived address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) payout_address: Option<Address<NetworkUnchecked>>,
+    /// Free-form notes on how far the contact can be trusted.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) trust_notes: String,
+}
+
+impl Contact {
+    /// The contact's payout [`Address`] on `network`.
+    ///
+    /// Falls back to the `npub`-derived address if no payout address is set.
+    pub(crate) fn payout_address(&self, network: Network) -> Result<Address, Error> {
+        match &self.payout_address {
+            Some(address) => Ok(address.clone().require_network(network)?),
+            None => npub_to_address(&self.npub, network),
+        }
+    }
+
+    /// Whether the payout address on `network` is the contact's `npub`-derived address.
+    pub(crate) fn is_npub_address(&self, network: Network) -> bool {
+        self.payout_address(network)
+            .is_ok_and(|address| verify_address_ownership(&address, &self.npub).is_ok())
+    }
+}
+
+/// Contacts, in insertion order, with at most one per `npub`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct AddressBook {
+    contacts: Vec<Contact>,
+}
+
+impl AddressBook {
+    /// Loads the address book from `storage`, or an empty one if none was saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(ADDRESS_BOOK_KEY)? {
+            Some(json) => Self::from_json(&json),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the address book to `storage`.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        storage.set(ADDRESS_BOOK_KEY, &self.to_json()?)
+    }
+
+    /// All contacts, in insertion order.
+    pub(crate) fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
+    /// The contact with the given `npub`, if any.
+    pub(crate) fn get(&self, npub: &NostrPublicKey) -> Option<&Contact> {
+        self.contacts.iter().find(|contact| contact.npub == *npub)
+    }
+
+    /// Adds a contact, replacing the one with the same `npub` if any.
+    ///
+    /// Returns the replaced contact.
+    pub(crate) fn upsert(&mut self, contact: Contact) -> Option<Contact> {
+        match self.contacts.iter_mut().find(|c| c.npub == contact.npub) {
+            Some(existing) => Some(std::mem::replace(existing, contact)),
+            None => {
+                self.contacts.push(contact);
+                None
+            }
+        }
+    }
+
+    /// Exports the address book as JSON.
+    pub(crate) fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| Error::WrongInputs(format!("Could not export address book: {e}")))
+    }
+
+    /// Parses an address book exported with [`AddressBook::to_json`].
+    ///
+    /// Contacts appearing more than once keep their last entry.
+    pub(crate) fn from_json(json: &str) -> Result<Self, Error> {
+        let contacts = serde_json::from_str::<Vec<Contact>>(json)
+            .map_err(|e| Error::WrongInputs(format!("Invalid address book: {e}")))?;
+        let mut address_book = Self::default();
+        address_book.import(contacts);
+        Ok(address_book)
+    }
+
+    /// Merges `contacts` into the address book, replacing existing contacts with the same `npub`.
+    ///
+    /// Returns the number of new contacts.
+    pub(crate) fn import(&mut self, contacts: impl IntoIterator<Item = Contact>) -> usize {
+        let mut added = 0;
+        for contact in contacts {
+            if self.upsert(contact).is_none() {
+                added += 1;
+            }
+        }
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::Keys;
+
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    fn contact(label: &str) -> Contact {
+        Contact {
+            npub: Keys::generate().public_key(),
+            label: label.to_string(),
+            payout_address: None,
+            trust_notes: String::new(),
+        }
+    }
+
+    #[test]
+    fn persist_and_import() {
+        let storage = MemoryStorage::default();
+        assert_eq!(AddressBook::load(&storage).unwrap(), AddressBook::default());
+
+        let alice = contact("Alice");
+        let bob = Contact {
+            payout_address: Some(
+                "tb1pw9lk5k85v58rn2s8ccdxcp62khvqyj9rzdg6el5f5nagdfesv88sez0tc9"
+                    .parse()
+                    .unwrap(),
+            ),
+            trust_notes: "Traded twice".to_string(),
+            ..contact("Bob")
+        };
+        let mut address_book = AddressBook::default();
+        assert!(address_book.upsert(alice.clone()).is_none());
+        assert!(address_book.upsert(bob.clone()).is_none());
+        address_book.save(&storage).unwrap();
+        assert_eq!(AddressBook::load(&storage).unwrap(), address_book);
+
+        assert!(alice.is_npub_address(Network::Testnet));
+        assert!(!bob.is_npub_address(Network::Testnet));
+        assert!(bob.payout_address(Network::Bitcoin).is_err());
+
+        let renamed = Contact {
+            label: "Alice (new)".to_string(),
+            ..alice.clone()
+        };
+        let mut imported = AddressBook::from_json(&address_book.to_json().unwrap()).unwrap();
+        assert_eq!(imported.import([renamed.clone(), contact("Carol")]), 1);
+        assert_eq!(imported.contacts().len(), 3);
+        assert_eq!(imported.get(&alice.npub), Some(&renamed));
+        assert_eq!(imported.get(&bob.npub), Some(&bob));
+        assert!(AddressBook::from_json("{}").is_err());
+    }
+}
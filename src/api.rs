@@ -0,0 +1,4971 @@
+//! Transport-agnostic request/response API of the escrow engine.
+//!
+//! Every operation is a typed [`Method`] with its own parameters,
+//! wrapped in a JSON-RPC 2.0 style [`Request`] and answered by a [`Response`]
+//! carrying either a result or an [`ApiError`] with the stable [`Error::code`].
+//! [`handle_json`] drives the API over any message channel that carries strings,
+//! such as a web worker's `postMessage` or, later, mobile bindings.
+//!
+//! Transactions travel as consensus hex, amounts as satoshis
+//! and keys as Nostr `npub`/`nsec` strings or hex.
+use std::time::Duration;
+
+use bitcoin::{
+    Address, Amount, FeeRate, Network, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxOut,
+    Txid, XOnlyPublicKey, absolute, address::NetworkUnchecked, bip32::Fingerprint, consensus,
+    hex::DisplayHex,
+};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{Event, EventId, Filter, Timestamp, key::PublicKey as NostrPublicKey};
+use secp256k1::schnorr;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    accounts::Keystore,
+    adaptor::{AdaptorSecret, AdaptorSignature, adaptor_sign_leaf, verify_adaptor_signature},
+    arbitration::{Arbitration, is_arbitrator},
+    arbitrators::{ArbitratorAd, ArbitratorFilter, list_arbitrators},
+    bip21::PaymentRequest,
+    bond::{BondOutcome, BondTerms, BondedEscrow},
+    cancel::{Cancellation, cancel_funding_psbt, receive_cancellations},
+    chain::ChainBuilder,
+    cofunding::{CofundingBuilder, CofundingTx, Contribution},
+    decision::{Decision, receive_decision},
+    decode::parse_tx_hex,
+    draft::EscrowDraft,
+    error::Error,
+    export::{DEFAULT_BBQR_PART_LEN, export, export_escrow_utxo, nip06_key_source},
+    funding::fund_escrow_tx,
+    gift_wrap::wrap_to_recipients,
+    invariants::SigningInvariants,
+    keys::Npub,
+    message::{sign_bip322_simple, sign_message, verify_bip322_simple, verify_message},
+    musig::{
+        AggregateNonce, PartialSignature, PublicNonce, aggregate_signatures,
+        verify_partial_signature,
+    },
+    network::{Chain, NetworkProfile},
+    offline::SigningBundle,
+    oracle::{
+        OracleAttestation, OracleEscrow, OracleEvent, sign_oracle_refund, sign_oracle_resolution,
+    },
+    payjoin::sign_original,
+    platform_fee::{PlatformFee, check_platform_fee},
+    policy::{CompiledPolicy, PolicyLeaf},
+    price::{Currency, FiatAmount, Price},
+    protocol::{
+        Acceptance, DEFAULT_OFFER_VALIDITY, Handshake, Offer, Session, SessionId, serialize,
+    },
+    receipts::{DueMessage, Receipt, receive_receipts},
+    reputation::{Feedback, Outcome, Reputation},
+    rotation::{KeyRotation, remaining_timelock},
+    scripts::{EscrowConfig, EscrowScript, ScriptTemplate, SpendPath},
+    secret::SecretNsec,
+    settings::FeeRateLimits,
+    sign::{
+        BatchSigner, LeafSignatures, SignerSignature, combine_signatures, combine_sweep_signatures,
+        key_spend_message, script_spend_message, sign_escrow_tx, sign_sweep_tx,
+        with_key_spend_signature,
+    },
+    silent_payments::{
+        EcdhShare, SilentPaymentAddress, payout_scripts, redirect_payout, verify_silent_resolution,
+    },
+    summary::{ContractSummary, describe_escrow},
+    swap::{
+        Settlement, SwapHtlc, htlc_refund_tx, settle_payout, sign_htlc_refund, verify_swap_payout,
+    },
+    trust::TrustProof,
+    tx::{
+        ExpiredEscrow, Split, anti_fee_sniping_lock_time, build_sweep_tx, escrow_tx,
+        estimate_spend_weight, resolution_tx, split_resolution_tx, verify_split_resolution,
+    },
+    util::{sign_address_challenge, verify_address_ownership_with_challenge},
+    wallet::{Coin, CoinControl, CoinSelection, SelectedCoins, select_coins, sweep_tx},
+};
+
+/// Version of the JSON-RPC protocol spoken by the API.
+pub(crate) const JSONRPC_VERSION: &str = "2.0";
+
+/// A call to the escrow engine.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Request {
+    /// Identifier echoed in the [`Response`], to match concurrent calls.
+    #[serde(default)]
+    pub(crate) id: Value,
+    /// The operation and its parameters.
+    #[serde(flatten)]
+    pub(crate) method: Method,
+}
+
+/// An operation of the escrow engine.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub(crate) enum Method {
+    /// Derives the escrow address, returning an [`AddressResult`].
+    EscrowAddress(EscrowParams),
+    /// Generates the [`TrustProof`] of an escrow.
+    TrustProof(EscrowParams),
+    /// Summarizes who can spend an escrow and when, returning a [`SummaryResult`].
+    DescribeEscrow(DescribeEscrowParams),
+    /// Builds the unsigned escrow resolution transaction, returning a [`TransactionResult`].
+    EscrowTx(EscrowTxParams),
+    /// Builds the unsigned key path sweep of a resolution address,
+    /// returning a [`TransactionResult`].
+    ResolutionTx(ResolutionTxParams),
+    /// Signs an escrow leaf spend, returning a [`SignatureResult`],
+    /// or an [`ArbitratedSignature`](crate::arbitration::ArbitratedSignature)
+    /// when signed by the escrow's arbitrator.
+    SignEscrowTx(Box<SignEscrowTxParams>),
+    /// Combines the signatures of an escrow leaf spend, returning a [`TransactionResult`].
+    CombineSignatures(CombineSignaturesParams),
+    /// Combines the MuSig2 partial signatures of a cooperative key path spend,
+    /// returning a [`TransactionResult`].
+    AggregateKeySpend(AggregateKeySpendParams),
+    /// Bundles an escrow leaf spend for offline signing, returning a [`SigningBundle`].
+    SigningBundle(SigningBundleParams),
+    /// Signs a [`SigningBundle`] on an offline machine, returning a [`SignatureResult`],
+    /// or an [`ArbitratedSignature`](crate::arbitration::ArbitratedSignature)
+    /// when signed by the escrow's arbitrator.
+    SignBundle(Box<SignBundleParams>),
+    /// Imports the signature of a [`SigningBundle`] made offline,
+    /// returning the [`LeafSignatures`] to combine with the other signers'.
+    ImportSignature(Box<ImportSignatureParams>),
+    /// Signs the event sending the signatures of a session to the other signers of its leaf,
+    /// returning an [`EventResult`] to send with [`Method::SendMessage`].
+    SignaturesEvent(Box<SignaturesEventParams>),
+    /// Adds the signatures another participant sent to a session, once checked against
+    /// the local transaction, returning a [`ReceivedSignaturesResult`].
+    ReceiveSignatures(Box<ReceiveSignaturesParams>),
+    /// Derives the adaptor point a release is locked to from a shared secret,
+    /// such as a delivery code, returning an [`AdaptorPointResult`].
+    AdaptorPoint(AdaptorPointParams),
+    /// Signs an escrow leaf spend locked to an adaptor point,
+    /// returning an [`AdaptorSignatureResult`].
+    AdaptorSignLeaf(Box<AdaptorSignLeafParams>),
+    /// Checks another signer's adaptor signature of an escrow leaf spend,
+    /// returning the [`AdaptorPointResult`] it is locked to.
+    VerifyAdaptorSignature(Box<VerifyAdaptorSignatureParams>),
+    /// Completes an adaptor signature with the shared secret of its point,
+    /// returning a [`SignatureResult`].
+    CompleteAdaptorSignature(CompleteAdaptorSignatureParams),
+    /// Recovers the secret of an adaptor signature from its completion, such as one read
+    /// from the witness of the broadcast release, returning an [`AdaptorSecretResult`].
+    ExtractAdaptorSecret(ExtractAdaptorSecretParams),
+    /// Compiles a spending policy into custom escrow leaves, returning a [`PolicyResult`].
+    CompilePolicy(PolicyParams),
+    /// Signs the spend of a policy escrow through one of its leaves,
+    /// returning a [`SignatureResult`].
+    SignPolicyLeaf(Box<SignPolicyLeafParams>),
+    /// Checks the signatures of a policy leaf and combines them into the spend,
+    /// returning a [`TransactionResult`].
+    CombinePolicySignatures(Box<CombinePolicySignaturesParams>),
+    /// Exports a signed transaction, returning an [`ExportResult`].
+    ExportTx(ExportTxParams),
+    /// Signs a message with a Nostr key, returning a [`SignatureResult`].
+    SignMessage(SignMessageParams),
+    /// Verifies a message signature, returning a [`VerifiedResult`].
+    VerifyMessage(VerifyMessageParams),
+    /// Signs a message as the owner of a resolution address, returning a BIP-322 [`ProofResult`].
+    SignAddressMessage(SignAddressMessageParams),
+    /// Verifies a BIP-322 proof of a message for an address, returning a [`VerifiedResult`].
+    VerifyAddressMessage(VerifyAddressMessageParams),
+    /// Signs an ownership challenge with the key of the npub's P2TR address,
+    /// returning a [`SignatureResult`].
+    SignAddressChallenge(SignAddressChallengeParams),
+    /// Verifies that an address is the npub's P2TR address and that its owner signed
+    /// a challenge, returning a [`VerifiedResult`].
+    VerifyAddressChallenge(VerifyAddressChallengeParams),
+    /// Converts a key given as an npub, hex or nsec, returning a [`KeyResult`].
+    KeyInfo(KeyInfoParams),
+    /// Finds the output of a transaction paying a BIP-21 payment request,
+    /// returning a [`PaymentResult`].
+    FindPayment(FindPaymentParams),
+    /// Selects the wallet coins funding an escrow, returning the [`SelectedCoins`].
+    SelectCoins(SelectCoinsParams),
+    /// Builds and signs the sweep of every wallet coin to another address,
+    /// returning a [`TransactionResult`].
+    SweepWallet(SweepWalletParams),
+    /// Starts a negotiation as the offerer, returning a [`NegotiationResult`].
+    Offer(Box<OfferParams>),
+    /// Accepts an offer event as the counterparty, returning a [`NegotiationResult`].
+    AcceptOffer(Box<AcceptOfferParams>),
+    /// The relay filter of the acceptances of a negotiation, returning a [`FilterResult`].
+    AcceptanceFilter(SessionIdParams),
+    /// Completes the offerer's negotiation with the acceptance event,
+    /// returning a [`SessionResult`].
+    ReceiveAcceptance(Box<ReceiveAcceptanceParams>),
+    /// Builds the unsigned escrow transaction of an agreed negotiation,
+    /// returning a [`TransactionResult`].
+    AgreedEscrowTx(Box<AgreedEscrowTxParams>),
+    /// Receives the arbitrator's decision of a session from the gift wraps of a participant,
+    /// checked against the resolution transaction, returning a [`DecisionResult`].
+    ReceiveDecision(Box<ReceiveDecisionParams>),
+    /// Requests the amount missing from an underfunded session, returning a [`TopUpResult`].
+    TopUpRequest(Box<TopUpRequestParams>),
+    /// Makes the escrow transaction of a funded session spend all of its funding,
+    /// refunding any excess, returning a [`FundedTxResult`].
+    FundEscrowTx(Box<FundEscrowTxParams>),
+    /// Builds the transaction funding the escrow of a session from both participants'
+    /// contributions, with their signatures so far, returning a [`CofundingResult`].
+    CofundEscrowTx(Box<CofundEscrowTxParams>),
+    /// Signs the participant's inputs of the transaction funding the escrow of a session
+    /// from both participants' contributions, returning the [`CofundingSignatures`].
+    SignCofunding(Box<SignCofundingParams>),
+    /// The `bitcoin:` URI funding the buyer's share of a session's escrow with a payjoin
+    /// through the seller's endpoint, returning a [`PaymentRequestResult`].
+    PayjoinRequest(Box<PayjoinRequestParams>),
+    /// Signs the buyer's original payment of a payjoin request, to post to the seller's
+    /// endpoint, returning a [`PsbtResult`].
+    SignPayjoinOriginal(Box<SignPayjoinOriginalParams>),
+    /// Builds the offer of a complete escrow draft, charging the platform fee if any,
+    /// returning a [`ProposalResult`].
+    ProposeEscrow(Box<ProposeEscrowParams>),
+    /// Builds the fee a platform charges to its npub's address, returning a [`PlatformFee`].
+    PlatformFee(PlatformFeeParams),
+    /// Cancels a session before its funding confirms, returning a [`CancelResult`].
+    CancelSession(Box<CancelSessionParams>),
+    /// Receives the other participants' cancellations of a session from the gift wraps
+    /// of a participant, returning a [`CancellationsResult`].
+    ReceiveCancellations(Box<ReceiveCancellationsParams>),
+    /// Queues a message of a session to be published until the counterparty acknowledges it,
+    /// returning a [`SessionResult`].
+    SendMessage(Box<SendMessageParams>),
+    /// Lists the messages of a session due to be published, with their delivery status,
+    /// returning a [`DueMessagesResult`].
+    DueMessages(Box<DueMessagesParams>),
+    /// Records an attempt to publish a message of a session, returning a [`SessionResult`].
+    RecordPublished(Box<RecordPublishedParams>),
+    /// Acknowledges a message received in a session, returning a [`GiftWrapsResult`]
+    /// with the receipt to publish.
+    AcknowledgeMessage(Box<AcknowledgeMessageParams>),
+    /// Builds the filter of the receipts of a session not fetched yet,
+    /// returning a [`SessionFilterResult`].
+    ReceiptFilter(Box<ReceiptFilterParams>),
+    /// Receives the receipts of a session's messages from the gift wraps of a participant,
+    /// returning a [`ReceiptsResult`].
+    ReceiveReceipts(Box<ReceiveReceiptsParams>),
+    /// Builds the replacement of an unconfirmed funding transaction refunding the funder,
+    /// returning a [`PsbtResult`].
+    CancelFunding(CancelFundingParams),
+    /// Builds a resolution splitting the escrow between the participants,
+    /// returning a [`TransactionResult`].
+    SplitResolutionTx(Box<SplitResolutionTxParams>),
+    /// Checks that a resolution only pays the participants, returning a [`PayoutsResult`].
+    VerifySplitResolution(VerifySplitResolutionParams),
+    /// The participant's share of the silent payment secret of an escrow with a key path
+    /// for a silent payment address, returning an [`EcdhShare`].
+    SilentPaymentShare(SilentPaymentShareParams),
+    /// Pays participants of a split resolution to silent payment addresses
+    /// with both participants' shares, returning a [`TransactionResult`].
+    SilentResolutionTx(Box<SilentResolutionTxParams>),
+    /// Checks that a resolution only pays the participants, some of them to silent payment
+    /// addresses, returning a [`PayoutsResult`].
+    VerifySilentResolution(Box<VerifySilentResolutionParams>),
+    /// Builds the resolution of a bonded escrow for an outcome,
+    /// returning a [`TransactionResult`].
+    BondResolutionTx(Box<BondResolutionTxParams>),
+    /// Builds the refund of a bonded escrow both parties sign before funding it,
+    /// returning a [`TransactionResult`].
+    BondTimeoutTx(Box<BondTimeoutTxParams>),
+    /// Checks that a resolution pays the outcome of a bonded escrow,
+    /// returning a [`PayoutsResult`].
+    VerifyBondResolution(Box<VerifyBondResolutionParams>),
+    /// Rebuilds the submarine-swap HTLC of a Lightning payout from its invoice, checked
+    /// against the provider's lockup address, returning a [`SwapHtlcResult`].
+    SwapHtlc(Box<SwapHtlcParams>),
+    /// Points a participant's payout in a resolution to their settlement,
+    /// returning a [`TransactionResult`].
+    SettlePayout(Box<SettlePayoutParams>),
+    /// Checks that a resolution pays a swap HTLC before signing it,
+    /// returning the [`SwapHtlcResult`] it pays.
+    VerifySwapPayout(Box<VerifySwapPayoutParams>),
+    /// Takes an unclaimed swap payout back after its timeout,
+    /// returning a signed [`TransactionResult`].
+    HtlcRefund(Box<HtlcRefundParams>),
+    /// Builds an escrow resolved by an oracle's attestation of an announced event,
+    /// returning an [`OracleEscrowResult`].
+    OracleEscrow(Box<OracleEscrowParams>),
+    /// Pays an oracle escrow to the winner of the attested outcome,
+    /// returning a signed [`TransactionResult`].
+    OracleResolution(Box<OracleResolutionParams>),
+    /// Takes an oracle escrow back once its refund timelock expired without an attestation,
+    /// returning a signed [`TransactionResult`].
+    OracleRefund(Box<OracleRefundParams>),
+    /// Sweeps escrows whose dispute timelock expired into a single address,
+    /// returning a [`FundedTxResult`].
+    SweepExpiredEscrows(SweepExpiredEscrowsParams),
+    /// Signs every input of a sweep of expired escrows, returning a [`SweepSignaturesResult`].
+    SignSweep(Box<SignSweepParams>),
+    /// Combines the participant's and the arbitrator's signatures of a sweep of expired
+    /// escrows, returning a [`TransactionResult`] to broadcast.
+    CombineSweepSignatures(Box<CombineSweepSignaturesParams>),
+    /// Estimates the size of the resolution spending an escrow through a spend path,
+    /// returning a [`SizeResult`].
+    EstimateSpend(EstimateSpendParams),
+    /// Exports the escrow UTXO spent by a transaction for an external wallet to sign,
+    /// returning an [`EscrowUtxoResult`].
+    ExportEscrowUtxo(Box<ExportEscrowUtxoParams>),
+    /// Signs the spend of a session's escrow into an escrow with new terms, finalizing it
+    /// with the counterparty's signatures, returning a [`ChainResult`].
+    ChainEscrowTx(Box<ChainEscrowTxParams>),
+    /// Replaces the participant's key in a session, returning a [`RotationResult`]
+    /// with the rotation event to publish.
+    RotateKey(Box<RotateKeyParams>),
+    /// The relay filter of the key rotations of a session, returning a [`FilterResult`].
+    RotationFilter(SessionIdParams),
+    /// Records the counterparty's key rotation event in a session,
+    /// returning a [`RotationResult`].
+    ReceiveRotation(Box<ReceiveRotationParams>),
+    /// Rates the counterparty of a settled escrow, returning an [`EventResult`]
+    /// with the feedback event to publish.
+    Feedback(Box<FeedbackParams>),
+    /// The relay filter of the feedback about an npub, returning a [`FilterResult`].
+    FeedbackFilter(NpubParams),
+    /// Aggregates the feedback events about an npub, returning a [`ReputationResult`].
+    Reputation(ReputationParams),
+    /// Signs an arbitrator's ad of its terms, returning an [`EventResult`]
+    /// with the ad event to publish.
+    AdvertiseArbitrator(Box<AdvertiseArbitratorParams>),
+    /// Lists the arbitrators meeting some criteria among ad events, with the relay filter
+    /// to fetch them with, returning an [`ArbitratorsResult`].
+    ListArbitrators(ListArbitratorsParams),
+}
+
+/// Parameters of the methods that only need the escrow.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct EscrowParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+}
+
+/// Parameters of [`Method::DescribeEscrow`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DescribeEscrowParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// The chain, by name, for its block timing, see [`Chain`].
+    /// Defaults to 10-minute blocks.
+    #[serde(default)]
+    pub(crate) chain: Option<String>,
+    /// Seconds between blocks, overriding the chain's,
+    /// such as for regtest nodes mining at a custom pace.
+    #[serde(default)]
+    pub(crate) block_interval_secs: Option<u64>,
+}
+
+/// Parameters of [`Method::EscrowTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct EscrowTxParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// Payout of the first participant.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount_1: Amount,
+    /// Payout of the second participant.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount_2: Amount,
+    /// Transaction funding the escrow at output 0.
+    pub(crate) funding_txid: Txid,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// Current block height, for an anti-fee-sniping lock time.
+    #[serde(default)]
+    pub(crate) lock_time_height: Option<u32>,
+}
+
+/// Parameters of [`Method::ResolutionTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ResolutionTxParams {
+    /// The resolution address output being swept.
+    pub(crate) outpoint: OutPoint,
+    /// Amount of the output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// Where the funds go.
+    pub(crate) destination: Address<NetworkUnchecked>,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// Current block height, for an anti-fee-sniping lock time.
+    #[serde(default)]
+    pub(crate) lock_time_height: Option<u32>,
+}
+
+/// Parameters of [`Method::SplitResolutionTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SplitResolutionTxParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// The escrow output.
+    pub(crate) funding: OutPoint,
+    /// Amount of the escrow output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// How the escrow is split between the participants.
+    pub(crate) split: Split,
+    /// Transaction fee, shared in proportion to the payouts.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// The leaf being spent.
+    pub(crate) escrow_script: EscrowScript,
+    /// Current block height, for an anti-fee-sniping lock time.
+    #[serde(default)]
+    pub(crate) lock_time_height: Option<u32>,
+}
+
+/// Parameters of [`Method::VerifySplitResolution`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct VerifySplitResolutionParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// The resolution transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Amount of the escrow output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+}
+
+/// Parameters of [`Method::SilentPaymentShare`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SilentPaymentShareParams {
+    /// The escrow, which must have a key path.
+    pub(crate) config: EscrowConfig,
+    /// The silent payment address paid by the resolution.
+    pub(crate) address: String,
+    /// Participant's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// A participant paid to a silent payment address instead of its npub's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SilentPayout {
+    /// The participant.
+    pub(crate) npub: NostrPublicKey,
+    /// The participant's silent payment address.
+    pub(crate) address: String,
+}
+
+/// Parameters of [`Method::SilentResolutionTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SilentResolutionTxParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// The split resolution, in hex, see [`Method::SplitResolutionTx`].
+    pub(crate) tx_hex: String,
+    /// The participants paid to silent payment addresses.
+    pub(crate) payouts: Vec<SilentPayout>,
+    /// Both participants' shares for every scan key of the addresses.
+    pub(crate) shares: Vec<EcdhShare>,
+}
+
+/// Parameters of [`Method::VerifySilentResolution`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct VerifySilentResolutionParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// The resolution transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Amount of the escrow output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// The participants paid to silent payment addresses.
+    pub(crate) payouts: Vec<SilentPayout>,
+    /// Both participants' shares for every scan key of the addresses.
+    pub(crate) shares: Vec<EcdhShare>,
+}
+
+/// Parameters of [`Method::BondResolutionTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BondResolutionTxParams {
+    /// The escrow, with the buyer as first participant.
+    pub(crate) config: EscrowConfig,
+    /// The locked amounts.
+    pub(crate) terms: BondTerms,
+    /// How the escrow ends.
+    pub(crate) outcome: BondOutcome,
+    /// The escrow output.
+    pub(crate) funding: OutPoint,
+    /// Transaction fee, shared in proportion to the payouts.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// Current block height, for an anti-fee-sniping lock time.
+    #[serde(default)]
+    pub(crate) lock_time_height: Option<u32>,
+}
+
+/// Parameters of [`Method::BondTimeoutTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BondTimeoutTxParams {
+    /// The escrow, with the buyer as first participant.
+    pub(crate) config: EscrowConfig,
+    /// The locked amounts.
+    pub(crate) terms: BondTerms,
+    /// The escrow output.
+    pub(crate) funding: OutPoint,
+    /// Transaction fee, shared in proportion to the payouts.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// Height from which the refund can be broadcast,
+    /// past the dispute timelock and the arbitrator's ruling.
+    pub(crate) timeout_height: u32,
+}
+
+/// Parameters of [`Method::VerifyBondResolution`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct VerifyBondResolutionParams {
+    /// The escrow, with the buyer as first participant.
+    pub(crate) config: EscrowConfig,
+    /// The locked amounts.
+    pub(crate) terms: BondTerms,
+    /// The resolution transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// The outcome it must pay.
+    pub(crate) outcome: BondOutcome,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+}
+
+/// Parameters of [`Method::SwapHtlc`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SwapHtlcParams {
+    /// The participant's BOLT11 invoice the provider pays.
+    pub(crate) invoice: String,
+    /// Key the provider claims the HTLC with.
+    pub(crate) claim_key: XOnlyPublicKey,
+    /// The participant the payout is for.
+    pub(crate) refund_npub: NostrPublicKey,
+    /// Block height after which the participant can take the payout back.
+    pub(crate) timeout_height: u32,
+    /// Amount the provider expects in the HTLC.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) lockup_amount: Amount,
+    /// The provider's lockup address.
+    pub(crate) lockup_address: String,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+}
+
+/// Parameters of [`Method::SettlePayout`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SettlePayoutParams {
+    /// Unsigned resolution, in hex.
+    pub(crate) tx_hex: String,
+    /// How the participant receives their payout.
+    pub(crate) settlement: Settlement,
+}
+
+/// Parameters of [`Method::VerifySwapPayout`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct VerifySwapPayoutParams {
+    /// Unsigned resolution, in hex.
+    pub(crate) tx_hex: String,
+    /// The swap HTLC it must pay.
+    pub(crate) htlc: SwapHtlc,
+}
+
+/// Parameters of [`Method::HtlcRefund`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct HtlcRefundParams {
+    /// The swap HTLC.
+    pub(crate) htlc: SwapHtlc,
+    /// The unclaimed HTLC output.
+    pub(crate) outpoint: OutPoint,
+    /// Where the payout goes back to.
+    pub(crate) destination: Address<NetworkUnchecked>,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// The participant's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::OracleEscrow`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OracleEscrowParams {
+    /// The buyer's `npub`.
+    pub(crate) npub_1: NostrPublicKey,
+    /// The seller's `npub`.
+    pub(crate) npub_2: NostrPublicKey,
+    /// Key the oracle attests with.
+    pub(crate) oracle: XOnlyPublicKey,
+    /// Nonce the oracle announced for the event.
+    pub(crate) nonce: XOnlyPublicKey,
+    /// ID of the event, such as a tracking number.
+    pub(crate) event_id: String,
+    /// Possible outcomes of the event.
+    pub(crate) outcomes: Vec<String>,
+    /// Who gets the escrow on each outcome, in the same order.
+    pub(crate) winners: Vec<NostrPublicKey>,
+    /// Who gets the escrow back if the oracle never attests.
+    pub(crate) refund: NostrPublicKey,
+    /// Relative timelock of the refund, in blocks.
+    pub(crate) refund_timelock: u32,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+}
+
+/// Parameters of [`Method::OracleResolution`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct OracleResolutionParams {
+    /// The escrow.
+    pub(crate) escrow: OracleEscrow,
+    /// The escrow output.
+    pub(crate) funding: OutPoint,
+    /// Amount of the escrow output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// The oracle's attestation of the outcome.
+    pub(crate) attestation: OracleAttestation,
+    /// The winner's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::OracleRefund`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct OracleRefundParams {
+    /// The escrow.
+    pub(crate) escrow: OracleEscrow,
+    /// The escrow output.
+    pub(crate) funding: OutPoint,
+    /// Amount of the escrow output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// The refund recipient's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::SweepExpiredEscrows`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SweepExpiredEscrowsParams {
+    /// The escrows to sweep.
+    pub(crate) escrows: Vec<ExpiredEscrow>,
+    /// Where the funds go.
+    pub(crate) destination: Address<NetworkUnchecked>,
+    /// Fee rate of the sweep, in sat/vB.
+    pub(crate) fee_rate: u64,
+}
+
+/// Parameters of [`Method::SignSweep`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignSweepParams {
+    /// Unsigned sweep transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// The escrows swept, in input order.
+    pub(crate) escrows: Vec<ExpiredEscrow>,
+    /// What the signer agreed to, checked before signing.
+    pub(crate) invariants: SigningInvariants,
+    /// Signer's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::CombineSweepSignatures`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CombineSweepSignaturesParams {
+    /// Unsigned sweep transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// The escrows swept, in input order.
+    pub(crate) escrows: Vec<ExpiredEscrow>,
+    /// The participant's signatures, in input order.
+    pub(crate) participant_signatures: Vec<schnorr::Signature>,
+    /// The arbitrator's signatures, in input order.
+    pub(crate) arbitrator_signatures: Vec<schnorr::Signature>,
+}
+
+/// Parameters of [`Method::EstimateSpend`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct EstimateSpendParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// How the escrow is spent.
+    pub(crate) path: SpendPath,
+}
+
+/// Parameters of [`Method::SignEscrowTx`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignEscrowTxParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// What the signer agreed to, including the outputs spent by every input,
+    /// checked before signing.
+    pub(crate) invariants: SigningInvariants,
+    /// The leaf being spent.
+    pub(crate) escrow_script: EscrowScript,
+    /// The dispute records the arbitrator signs with, required of the escrow's arbitrator.
+    #[serde(default)]
+    pub(crate) arbitration: Option<Arbitration>,
+    /// The participant's session of the escrow, to sign through [`Session::sign`].
+    #[serde(default)]
+    pub(crate) session: Option<Session>,
+    /// Confirmations of the escrow funding, checked against the session's
+    /// [`Session::min_confirmations`] before signing.
+    #[serde(default)]
+    pub(crate) funding_confirmations: u32,
+    /// Signer's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::CombineSignatures`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CombineSignaturesParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// The leaf being spent.
+    pub(crate) escrow_script: EscrowScript,
+    /// Signatures of the leaf's signers, in witness order, see [`EscrowConfig::signers`].
+    pub(crate) signatures: Vec<schnorr::Signature>,
+}
+
+/// Parameters of [`Method::AggregateKeySpend`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AggregateKeySpendParams {
+    /// The escrow, which must have a key path, see [`EscrowConfig::has_key_path`].
+    pub(crate) config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// Public nonces of both participants, in participant order.
+    pub(crate) nonces: Vec<PublicNonce>,
+    /// Partial signatures of both participants, in participant order.
+    pub(crate) partial_signatures: Vec<PartialSignature>,
+}
+
+/// Parameters of [`Method::SigningBundle`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SigningBundleParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// The leaf being spent.
+    pub(crate) escrow_script: EscrowScript,
+}
+
+/// Parameters of [`Method::SignBundle`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignBundleParams {
+    /// The bundle to sign.
+    pub(crate) bundle: SigningBundle,
+    /// What the signer agreed to, checked against the bundle before signing.
+    pub(crate) invariants: SigningInvariants,
+    /// The dispute records the arbitrator signs with, required of the escrow's arbitrator.
+    #[serde(default)]
+    pub(crate) arbitration: Option<Arbitration>,
+    /// Signer's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::ImportSignature`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ImportSignatureParams {
+    /// The signed bundle.
+    pub(crate) bundle: SigningBundle,
+    /// The offline signer.
+    pub(crate) npub: NostrPublicKey,
+    /// The signature made offline.
+    pub(crate) signature: schnorr::Signature,
+}
+
+/// Parameters of [`Method::SignaturesEvent`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignaturesEventParams {
+    /// The agreed session the signatures are bound to.
+    pub(crate) session: Session,
+    /// The signatures to send.
+    pub(crate) signatures: LeafSignatures,
+    /// Sender's Nostr secret key, signing the event.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::ReceiveSignatures`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReceiveSignaturesParams {
+    /// The agreed session the signatures are bound to.
+    pub(crate) session: Session,
+    /// The received signatures, verified together if they sign several inputs.
+    #[serde(default)]
+    pub(crate) signatures: Vec<LeafSignatures>,
+    /// The received signatures events, added to the `signatures`.
+    #[serde(default)]
+    pub(crate) events: Vec<Event>,
+    /// The transaction built locally, in hex, which the signatures must sign.
+    pub(crate) tx_hex: String,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+}
+
+/// Parameters of [`Method::AdaptorPoint`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct AdaptorPointParams {
+    /// The shared secret, such as a delivery code.
+    pub(crate) secret: String,
+}
+
+/// Parameters of [`Method::AdaptorSignLeaf`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct AdaptorSignLeafParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// The leaf being spent.
+    pub(crate) escrow_script: EscrowScript,
+    /// The adaptor point the signature is locked to, in hex.
+    pub(crate) adaptor: String,
+    /// Signer's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::VerifyAdaptorSignature`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct VerifyAdaptorSignatureParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// The leaf being spent.
+    pub(crate) escrow_script: EscrowScript,
+    /// The adaptor signature, in hex.
+    pub(crate) adaptor_signature: String,
+    /// The signer.
+    pub(crate) npub: NostrPublicKey,
+}
+
+/// Parameters of [`Method::CompleteAdaptorSignature`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct CompleteAdaptorSignatureParams {
+    /// The adaptor signature, in hex.
+    pub(crate) adaptor_signature: String,
+    /// The shared secret its point was derived from, see [`Method::AdaptorPoint`].
+    pub(crate) secret: String,
+}
+
+/// Parameters of [`Method::ExtractAdaptorSecret`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ExtractAdaptorSecretParams {
+    /// The adaptor signature, in hex.
+    pub(crate) adaptor_signature: String,
+    /// Its completed signature.
+    pub(crate) signature: schnorr::Signature,
+}
+
+/// Parameters of [`Method::CompilePolicy`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PolicyParams {
+    /// The spending policy, see [`crate::policy`].
+    pub(crate) policy: String,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+}
+
+/// Parameters of [`Method::SignPolicyLeaf`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignPolicyLeafParams {
+    /// The spending policy of the escrow.
+    pub(crate) policy: String,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// Locking script of the leaf being spent.
+    pub(crate) script: ScriptBuf,
+    /// What the signer agreed to, checked before signing.
+    pub(crate) invariants: SigningInvariants,
+    /// Signer's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::CombinePolicySignatures`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CombinePolicySignaturesParams {
+    /// The spending policy of the escrow.
+    pub(crate) policy: String,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// Locking script of the leaf being spent.
+    pub(crate) script: ScriptBuf,
+    /// Signatures of the leaf's signers, in any order.
+    pub(crate) signatures: Vec<SignerSignature>,
+}
+
+/// Parameters of [`Method::ExportTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ExportTxParams {
+    /// Signed transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Outputs spent by every input of the transaction, in input order, if known.
+    #[serde(default)]
+    pub(crate) prevouts: Option<Vec<TxOut>>,
+}
+
+/// Parameters of [`Method::ExportEscrowUtxo`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ExportEscrowUtxoParams {
+    /// The escrow.
+    pub(crate) config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// The keys the wallet holds, by their NIP-06 derivation.
+    #[serde(default)]
+    pub(crate) origins: Vec<KeyOrigin>,
+}
+
+/// A Nostr key derived from the seed of a wallet with NIP-06.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct KeyOrigin {
+    /// The key.
+    pub(crate) npub: NostrPublicKey,
+    /// Fingerprint of the wallet's seed.
+    pub(crate) fingerprint: Fingerprint,
+    /// NIP-06 account of the key.
+    #[serde(default)]
+    pub(crate) account: u32,
+}
+
+/// Parameters of [`Method::SignMessage`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignMessageParams {
+    /// The signed message.
+    pub(crate) message: String,
+    /// Signer's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::VerifyMessage`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct VerifyMessageParams {
+    /// The signed message.
+    pub(crate) message: String,
+    /// Signer's Nostr public key.
+    pub(crate) npub: NostrPublicKey,
+    /// The signature.
+    pub(crate) signature: schnorr::Signature,
+}
+
+/// Parameters of [`Method::SignAddressMessage`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignAddressMessageParams {
+    /// The signed message.
+    pub(crate) message: String,
+    /// The resolution address of `nsec`.
+    pub(crate) address: Address<NetworkUnchecked>,
+    /// Signer's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::VerifyAddressMessage`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct VerifyAddressMessageParams {
+    /// The signed message.
+    pub(crate) message: String,
+    /// The signer's P2TR address.
+    pub(crate) address: Address<NetworkUnchecked>,
+    /// The base64-encoded BIP-322 simple proof.
+    pub(crate) proof: String,
+}
+
+/// Parameters of [`Method::SignAddressChallenge`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignAddressChallengeParams {
+    /// The challenge, such as one naming the escrow the address is a payout of.
+    pub(crate) challenge: String,
+    /// Signer's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::VerifyAddressChallenge`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct VerifyAddressChallengeParams {
+    /// The address whose ownership is proven.
+    pub(crate) address: Address<NetworkUnchecked>,
+    /// Nostr public key the address is claimed to belong to.
+    pub(crate) npub: NostrPublicKey,
+    /// The signed challenge.
+    pub(crate) challenge: String,
+    /// The signature of the challenge.
+    pub(crate) signature: schnorr::Signature,
+}
+
+/// Parameters of [`Method::KeyInfo`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct KeyInfoParams {
+    /// The public key as an npub or hex, or the secret key as an nsec.
+    pub(crate) key: String,
+    /// Network of the key's address.
+    pub(crate) network: Network,
+}
+
+/// Parameters of [`Method::FindPayment`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FindPaymentParams {
+    /// The `bitcoin:` URI of the payment request.
+    pub(crate) uri: String,
+    /// Network the request's address must be on.
+    pub(crate) network: Network,
+    /// The paying transaction, in hex.
+    pub(crate) tx_hex: String,
+}
+
+/// Parameters of [`Method::SelectCoins`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SelectCoinsParams {
+    /// The coins of the wallet.
+    pub(crate) coins: Vec<Coin>,
+    /// Labels and freezes of the coins.
+    #[serde(default)]
+    pub(crate) control: CoinControl,
+    /// How to pick the coins, automatically by default.
+    #[serde(default)]
+    pub(crate) selection: CoinSelection,
+    /// Amount to pay to the escrow.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// Fee rate of the funding transaction, in sat/vB.
+    pub(crate) fee_rate: u64,
+    /// Where the change goes.
+    pub(crate) change_address: Address<NetworkUnchecked>,
+}
+
+/// Parameters of [`Method::SweepWallet`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SweepWalletParams {
+    /// The coins of the wallet, all swept.
+    pub(crate) coins: Vec<Coin>,
+    /// Nostr secret key of the wallet.
+    pub(crate) nsec: SecretNsec,
+    /// Where the funds go.
+    pub(crate) destination: Address<NetworkUnchecked>,
+    /// Fee rate of the sweep, in sat/vB.
+    pub(crate) fee_rate: u64,
+    /// Current block height, for an anti-fee-sniping lock time.
+    #[serde(default)]
+    pub(crate) lock_time_height: Option<u32>,
+}
+
+/// Parameters of [`Method::Offer`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct OfferParams {
+    /// The proposed escrow, by the offerer of `nsec`.
+    pub(crate) offer: Offer,
+    /// Offerer's Nostr secret key, signing the offer event.
+    pub(crate) nsec: SecretNsec,
+    /// Amounts in fiat replacing the offer's, if it is denominated in fiat.
+    #[serde(default)]
+    pub(crate) fiat: Option<FiatOfferParams>,
+}
+
+/// Fiat amounts of an offer, in [`OfferParams`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FiatOfferParams {
+    /// Currency of the amounts and the price.
+    pub(crate) currency: Currency,
+    /// Buyer's amount, such as `500` or `499.90`.
+    pub(crate) amount_buyer: String,
+    /// Seller's amount, such as `50`.
+    pub(crate) amount_seller: String,
+    /// Current price, quoting the amounts in sats until the acceptance locks them.
+    pub(crate) price: Price,
+}
+
+/// Parameters of [`Method::AcceptOffer`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct AcceptOfferParams {
+    /// The offer event, as published by the offerer.
+    pub(crate) offer_event: Event,
+    /// Current price, locking the amounts of offers denominated in fiat.
+    #[serde(default)]
+    pub(crate) price: Option<Price>,
+    /// Fee the hosting platform charges, rejecting offers that don't pay exactly it.
+    #[serde(default)]
+    pub(crate) platform_fee: Option<PlatformFee>,
+    /// Acceptor's Nostr secret key, signing the acceptance event.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::AcceptanceFilter`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SessionIdParams {
+    /// The [`SessionId`] of the negotiation.
+    pub(crate) session_id: SessionId,
+}
+
+/// Parameters of [`Method::ReceiveAcceptance`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReceiveAcceptanceParams {
+    /// The offerer's session, waiting for an acceptance.
+    pub(crate) session: Session,
+    /// The acceptance event, as published by the acceptor.
+    pub(crate) acceptance_event: Event,
+}
+
+/// Parameters of [`Method::AgreedEscrowTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AgreedEscrowTxParams {
+    /// The agreed session.
+    pub(crate) session: Session,
+    /// Transaction funding the escrow at output 0.
+    pub(crate) funding_txid: Txid,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+}
+
+/// Parameters of [`Method::ChainEscrowTx`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct ChainEscrowTxParams {
+    /// The participant's agreed session.
+    pub(crate) session: Session,
+    /// Participant's Nostr secret key.
+    pub(crate) nsec: SecretNsec,
+    /// The escrow output.
+    pub(crate) funding: OutPoint,
+    /// Amount of the escrow output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// Arbitrator and timelock in blocks of the new escrow, [`None`] for a collaborative one.
+    pub(crate) dispute: Option<(NostrPublicKey, u32)>,
+    /// Script template of the new escrow, the current one's by default.
+    #[serde(default)]
+    pub(crate) template: Option<ScriptTemplate>,
+    /// Fee of the chain transaction, taken from the buyer's amount first.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// The counterparty's signatures, once received.
+    #[serde(default)]
+    pub(crate) signatures: Option<LeafSignatures>,
+    /// Expiry of the offer of the new escrow, [`DEFAULT_OFFER_VALIDITY`] from now by default.
+    #[serde(default)]
+    pub(crate) expires_at: Option<Timestamp>,
+}
+
+/// Parameters of [`Method::RotateKey`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct RotateKeyParams {
+    /// The participant's agreed session.
+    pub(crate) session: Session,
+    /// Participant's Nostr secret key being replaced, signing the rotation event.
+    pub(crate) nsec: SecretNsec,
+    /// Replacement Nostr secret key.
+    pub(crate) new_nsec: SecretNsec,
+    /// The escrow output.
+    pub(crate) funding: OutPoint,
+    /// Amount of the escrow output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// Fee of the migration transaction.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// Height the funding confirmed at, so a dispute escrow keeps only the timelock left.
+    #[serde(default)]
+    pub(crate) funding_height: Option<u32>,
+    /// Current block height.
+    #[serde(default)]
+    pub(crate) tip_height: Option<u32>,
+}
+
+/// Parameters of [`Method::ReceiveRotation`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReceiveRotationParams {
+    /// The participant's agreed session.
+    pub(crate) session: Session,
+    /// The rotation event, as published by the counterparty.
+    pub(crate) event: Event,
+    /// The escrow output.
+    pub(crate) funding: OutPoint,
+    /// Amount of the escrow output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+}
+
+/// Parameters of [`Method::Feedback`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct FeedbackParams {
+    /// The author's agreed session.
+    pub(crate) session: Session,
+    /// How the escrow ended.
+    pub(crate) outcome: Outcome,
+    /// Rating of the counterparty, from 1 to 5.
+    pub(crate) rating: u8,
+    /// Free-form comment.
+    #[serde(default)]
+    pub(crate) comment: String,
+    /// Author's Nostr secret key, signing the feedback event.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of the methods that only need an npub.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct NpubParams {
+    /// The npub.
+    pub(crate) npub: NostrPublicKey,
+}
+
+/// Parameters of [`Method::Reputation`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReputationParams {
+    /// The rated npub.
+    pub(crate) npub: NostrPublicKey,
+    /// The feedback events, as fetched from the relays with [`Method::FeedbackFilter`].
+    pub(crate) events: Vec<Event>,
+}
+
+/// Parameters of [`Method::AdvertiseArbitrator`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct AdvertiseArbitratorParams {
+    /// The arbitrator's terms.
+    pub(crate) ad: ArbitratorAd,
+    /// Arbitrator's Nostr secret key, signing the ad event.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::ListArbitrators`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ListArbitratorsParams {
+    /// The ad events, as fetched from the relays with the filter of the result.
+    #[serde(default)]
+    pub(crate) events: Vec<Event>,
+    /// Network of the escrow, if any.
+    #[serde(default)]
+    pub(crate) network: Option<Network>,
+    /// Highest acceptable fee in basis points, if any.
+    #[serde(default)]
+    pub(crate) max_fee_bps: Option<u16>,
+    /// Longest acceptable response time in hours, if any.
+    #[serde(default)]
+    pub(crate) max_response_hours: Option<u32>,
+    /// Amount of the escrow, to quote the arbitrators' fees, if any.
+    #[serde(default, with = "bitcoin::amount::serde::as_sat::opt")]
+    pub(crate) amount: Option<Amount>,
+}
+
+/// Parameters of [`Method::ReceiveDecision`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReceiveDecisionParams {
+    /// The participant's agreed session.
+    pub(crate) session: Session,
+    /// The gift wraps addressed to the participant, as fetched from the relays.
+    pub(crate) gift_wraps: Vec<Event>,
+    /// Participant's Nostr secret key, unwrapping the gift wraps.
+    pub(crate) nsec: SecretNsec,
+    /// The resolution transaction, in hex.
+    pub(crate) tx_hex: String,
+}
+
+/// Parameters of [`Method::PayjoinRequest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PayjoinRequestParams {
+    /// The agreed session.
+    pub(crate) session: Session,
+    /// The seller's payjoin endpoint, the `pj` parameter of the URI.
+    pub(crate) endpoint: String,
+}
+
+/// Parameters of [`Method::SignPayjoinOriginal`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignPayjoinOriginalParams {
+    /// The payjoin request, a `bitcoin:` URI with a `pj` endpoint.
+    pub(crate) uri: String,
+    /// Network of the request's address.
+    pub(crate) network: Network,
+    /// The coins paying the request and the change, see [`Method::SelectCoins`].
+    pub(crate) coins: SelectedCoins,
+    /// Buyer's Nostr secret key, owning the coins.
+    pub(crate) nsec: SecretNsec,
+    /// Current block height, for an anti-fee-sniping lock time.
+    #[serde(default)]
+    pub(crate) lock_time_height: Option<u32>,
+}
+
+/// Parameters of [`Method::TopUpRequest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TopUpRequestParams {
+    /// The agreed session, with its recorded funding.
+    pub(crate) session: Session,
+}
+
+/// Parameters of [`Method::FundEscrowTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FundEscrowTxParams {
+    /// The agreed session, with its recorded funding.
+    pub(crate) session: Session,
+    /// Unsigned escrow transaction for the agreed amounts, in hex.
+    pub(crate) tx_hex: String,
+    /// Where the excess of an overfunded escrow goes.
+    pub(crate) excess_to: Address<NetworkUnchecked>,
+}
+
+/// Parameters of [`Method::ProposeEscrow`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ProposeEscrowParams {
+    /// The escrow parameters, as entered in the creation wizard.
+    pub(crate) draft: EscrowDraft,
+    /// Fee of the hosting platform, if any.
+    #[serde(default)]
+    pub(crate) platform_fee: Option<PlatformFee>,
+    /// Current block height, as anti-fee-sniping lock time of the escrow transaction.
+    #[serde(default)]
+    pub(crate) lock_time_height: Option<u32>,
+}
+
+/// Parameters of [`Method::PlatformFee`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PlatformFeeParams {
+    /// The platform's npub, deriving the address the fee is paid to.
+    pub(crate) npub: NostrPublicKey,
+    /// Network of the escrows the fee is charged on.
+    pub(crate) network: Network,
+    /// Fee in basis points of the escrow amount.
+    pub(crate) bps: u16,
+}
+
+/// Parameters of [`Method::CofundEscrowTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CofundEscrowTxParams {
+    /// The agreed session.
+    pub(crate) session: Session,
+    /// The participants' contributions, see [`CofundingBuilder`].
+    pub(crate) contributions: Vec<Contribution>,
+    /// Signatures of the participants' inputs, as answered by [`Method::SignCofunding`].
+    #[serde(default)]
+    pub(crate) signatures: Vec<CofundingSignatures>,
+}
+
+/// Parameters of [`Method::SignCofunding`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignCofundingParams {
+    /// The agreed session.
+    pub(crate) session: Session,
+    /// The participants' contributions, see [`CofundingBuilder`].
+    pub(crate) contributions: Vec<Contribution>,
+    /// Participant's Nostr secret key, owning its contributed inputs.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::CancelSession`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct CancelSessionParams {
+    /// The participant's session.
+    pub(crate) session: Session,
+    /// Participant's Nostr secret key, signing the cancellation.
+    pub(crate) nsec: SecretNsec,
+    /// The unconfirmed funding transaction being abandoned, if it was sent.
+    #[serde(default)]
+    pub(crate) funding_txid: Option<Txid>,
+    /// Why the participant cancels.
+    #[serde(default)]
+    pub(crate) reason: String,
+    /// Whether the funding confirmed, after which the escrow can only be resolved.
+    #[serde(default)]
+    pub(crate) funding_confirmed: bool,
+}
+
+/// Parameters of [`Method::ReceiveCancellations`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReceiveCancellationsParams {
+    /// The participant's session.
+    pub(crate) session: Session,
+    /// The gift wraps addressed to the participant, as fetched from the relays.
+    pub(crate) gift_wraps: Vec<Event>,
+    /// Participant's Nostr secret key, unwrapping the gift wraps.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::SendMessage`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SendMessageParams {
+    /// The session sending the message.
+    pub(crate) session: Session,
+    /// The signed message, such as an acceptance.
+    pub(crate) event: Event,
+}
+
+/// Parameters of [`Method::DueMessages`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct DueMessagesParams {
+    /// The session of the messages.
+    pub(crate) session: Session,
+    /// Sender's Nostr secret key, gift wrapping the messages.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::RecordPublished`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RecordPublishedParams {
+    /// The session of the message.
+    pub(crate) session: Session,
+    /// ID of the message.
+    pub(crate) event_id: EventId,
+    /// Whether relays accepted it.
+    pub(crate) published: bool,
+}
+
+/// Parameters of [`Method::AcknowledgeMessage`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct AcknowledgeMessageParams {
+    /// The session the message was received in.
+    pub(crate) session: Session,
+    /// ID of the message.
+    pub(crate) event_id: EventId,
+    /// Author of the message, receiving the receipt.
+    pub(crate) author: NostrPublicKey,
+    /// Acknowledging participant's Nostr secret key, signing the receipt.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::ReceiptFilter`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReceiptFilterParams {
+    /// The session of the messages.
+    pub(crate) session: Session,
+    /// Npub of the participant the receipts are gift wrapped to.
+    pub(crate) npub: NostrPublicKey,
+}
+
+/// Parameters of [`Method::ReceiveReceipts`].
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReceiveReceiptsParams {
+    /// The participant's session.
+    pub(crate) session: Session,
+    /// The gift wraps addressed to the participant, as fetched from the relays.
+    pub(crate) gift_wraps: Vec<Event>,
+    /// Participant's Nostr secret key, unwrapping the gift wraps.
+    pub(crate) nsec: SecretNsec,
+}
+
+/// Parameters of [`Method::CancelFunding`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CancelFundingParams {
+    /// The unconfirmed funding transaction, signaling replace-by-fee, in hex.
+    pub(crate) tx_hex: String,
+    /// Outputs spent by every input of the funding transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// Where the funder's coins go back to.
+    pub(crate) refund_address: Address<NetworkUnchecked>,
+    /// Fee rate of the replacement, in sat/vB.
+    pub(crate) fee_rate: u64,
+    /// Fee rates the user accepts.
+    #[serde(default)]
+    pub(crate) fee_rates: FeeRateLimits,
+}
+
+/// Result of [`Method::EscrowAddress`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AddressResult {
+    /// The escrow address.
+    pub(crate) address: Address<NetworkUnchecked>,
+}
+
+/// Result of [`Method::SwapHtlc`] and [`Method::VerifySwapPayout`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SwapHtlcResult {
+    /// The swap HTLC, for [`Settlement::Lightning`].
+    pub(crate) htlc: SwapHtlc,
+    /// Its address.
+    pub(crate) address: Address<NetworkUnchecked>,
+}
+
+/// Result of [`Method::OracleEscrow`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct OracleEscrowResult {
+    /// The escrow, to resolve or refund.
+    pub(crate) escrow: OracleEscrow,
+    /// Its address.
+    pub(crate) address: Address<NetworkUnchecked>,
+}
+
+/// Result of [`Method::DescribeEscrow`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SummaryResult {
+    /// The summary in English.
+    pub(crate) text: String,
+    /// The structured summary, to translate.
+    pub(crate) summary: ContractSummary,
+}
+
+/// Result of the methods building a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TransactionResult {
+    /// The transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Its transaction ID.
+    pub(crate) txid: Txid,
+}
+
+/// Result of [`Method::VerifySplitResolution`] and [`Method::VerifyBondResolution`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PayoutsResult {
+    /// Payout of the first participant.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) payout_1: Amount,
+    /// Payout of the second participant.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) payout_2: Amount,
+}
+
+/// Result of [`Method::EstimateSpend`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SizeResult {
+    /// Virtual size of the resolution, in vbytes.
+    pub(crate) vbytes: u64,
+}
+
+/// Result of the signing methods.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SignatureResult {
+    /// The signature.
+    pub(crate) signature: schnorr::Signature,
+}
+
+/// Result of [`Method::SignSweep`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SweepSignaturesResult {
+    /// One signature per input, in input order.
+    pub(crate) signatures: Vec<schnorr::Signature>,
+}
+
+/// Result of [`Method::ExportEscrowUtxo`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct EscrowUtxoResult {
+    /// Output descriptor of the escrow, with its checksum.
+    pub(crate) descriptor: String,
+    /// The escrow UTXO.
+    pub(crate) outpoint: OutPoint,
+    /// The escrow output.
+    pub(crate) prevout: TxOut,
+    /// Unsigned PSBT of the spend, in base64.
+    pub(crate) psbt: String,
+    /// BBQr parts of the PSBT.
+    pub(crate) bbqr: Vec<String>,
+}
+
+/// Result of [`Method::ExportTx`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ExportResult {
+    /// Raw transaction, in hex.
+    pub(crate) hex: String,
+    /// Finalized PSBT, in base64.
+    pub(crate) psbt: String,
+    /// BBQr parts of the transaction.
+    pub(crate) bbqr: Vec<String>,
+}
+
+/// Result of the methods starting a negotiation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct NegotiationResult {
+    /// The participant's session, to persist.
+    pub(crate) session: Session,
+    /// The event to publish to the relays.
+    pub(crate) event: Event,
+}
+
+/// Result of the methods updating a session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SessionResult {
+    /// The updated session, to persist.
+    pub(crate) session: Session,
+}
+
+/// Result of [`Method::ReceiveSignatures`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReceivedSignaturesResult {
+    /// The updated session, to persist.
+    pub(crate) session: Session,
+    /// Whether any signature was new, `false` if they were all delivered before.
+    pub(crate) added: bool,
+}
+
+/// Result of [`Method::AdaptorPoint`] and [`Method::VerifyAdaptorSignature`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AdaptorPointResult {
+    /// The adaptor point, in hex, safe to share.
+    pub(crate) point: String,
+}
+
+/// Result of [`Method::AdaptorSignLeaf`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AdaptorSignatureResult {
+    /// The adaptor signature, in hex.
+    pub(crate) adaptor_signature: String,
+}
+
+/// Result of [`Method::ExtractAdaptorSecret`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AdaptorSecretResult {
+    /// The recovered secret as a Nostr secret key, in hex,
+    /// for leaves locked to the x-only key of its point.
+    pub(crate) nsec: String,
+}
+
+/// Result of [`Method::CompilePolicy`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PolicyResult {
+    /// The policy, normalized.
+    pub(crate) policy: String,
+    /// The escrow address.
+    pub(crate) address: Address<NetworkUnchecked>,
+    /// Every leaf of the escrow, likeliest first.
+    pub(crate) leaves: Vec<PolicyLeafResult>,
+}
+
+/// A leaf of a [`PolicyResult`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PolicyLeafResult {
+    /// Locking script of the leaf.
+    pub(crate) script: ScriptBuf,
+    /// The keys that may sign the leaf.
+    pub(crate) signers: Vec<NostrPublicKey>,
+    /// The lock time the spending transaction needs.
+    pub(crate) lock_time: absolute::LockTime,
+    /// The sequence the spending input needs.
+    pub(crate) sequence: Sequence,
+}
+
+/// Result of [`Method::SignAddressMessage`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ProofResult {
+    /// The base64-encoded BIP-322 simple proof.
+    pub(crate) proof: String,
+}
+
+/// Result of [`Method::FindPayment`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PaymentResult {
+    /// The output paying the request, if the transaction pays it.
+    pub(crate) outpoint: Option<OutPoint>,
+}
+
+/// Result of [`Method::VerifyMessage`] and [`Method::VerifyAddressMessage`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct VerifiedResult {
+    /// Whether the signature is valid.
+    pub(crate) valid: bool,
+}
+
+/// Result of [`Method::KeyInfo`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct KeyResult {
+    /// The public key, as an npub.
+    pub(crate) npub: String,
+    /// The public key, in hex.
+    pub(crate) hex: String,
+    /// The P2TR key path address of the key.
+    pub(crate) address: Address<NetworkUnchecked>,
+}
+
+/// Result of [`Method::AcceptanceFilter`], [`Method::RotationFilter`]
+/// and [`Method::FeedbackFilter`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FilterResult {
+    /// The filter to subscribe to on the relays.
+    pub(crate) filter: Filter,
+}
+
+/// Result of [`Method::ReceiveDecision`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DecisionResult {
+    /// The session, which won't receive the same decision again.
+    pub(crate) session: Session,
+    /// The arbitrator's decision, if received.
+    pub(crate) decision: Option<Decision>,
+}
+
+/// Result of [`Method::CofundEscrowTx`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CofundingResult {
+    /// The transaction, in hex, signed and ready to broadcast once complete.
+    pub(crate) tx_hex: String,
+    /// The transaction ID.
+    pub(crate) txid: Txid,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// Transaction fee.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// Whether every input is signed.
+    pub(crate) complete: bool,
+}
+
+/// Signatures of a participant's inputs of a collaborative funding transaction,
+/// the result of [`Method::SignCofunding`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CofundingSignatures {
+    /// The signing participant.
+    pub(crate) npub: NostrPublicKey,
+    /// One signature per input of the participant, in input order.
+    pub(crate) signatures: Vec<schnorr::Signature>,
+}
+
+/// Result of [`Method::PayjoinRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PaymentRequestResult {
+    /// The `bitcoin:` URI.
+    pub(crate) uri: String,
+}
+
+/// Result of [`Method::TopUpRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TopUpResult {
+    /// The `bitcoin:` URI of the missing amount, unless the escrow is not underfunded.
+    pub(crate) uri: Option<String>,
+    /// The missing amount in bitcoin and, if the escrow is denominated in fiat,
+    /// at the price of the negotiation, such as `0.001 BTC (R$ 500.00)`.
+    pub(crate) missing: Option<String>,
+}
+
+/// Result of [`Method::FundEscrowTx`] and [`Method::SweepExpiredEscrows`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FundedTxResult {
+    /// The transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// The transaction ID.
+    pub(crate) txid: Txid,
+    /// Outputs spent by every input of the transaction, in input order, to sign it.
+    pub(crate) prevouts: Vec<TxOut>,
+}
+
+/// Result of [`Method::ProposeEscrow`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ProposalResult {
+    /// The offer, to start a negotiation with.
+    pub(crate) offer: Offer,
+    /// The `bitcoin:` URI funding the escrow address.
+    pub(crate) uri: String,
+}
+
+/// Result of [`Method::ChainEscrowTx`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ChainResult {
+    /// The chain transaction, in hex, signed once `complete`.
+    pub(crate) tx_hex: String,
+    /// The transaction ID, funding the new escrow at output 0.
+    pub(crate) txid: Txid,
+    /// The signatures so far, the participant's included, to send to the counterparty.
+    pub(crate) signatures: LeafSignatures,
+    /// Whether both participants signed.
+    pub(crate) complete: bool,
+    /// The offer opening the session of the new escrow, for the offerer to publish.
+    pub(crate) offer: Offer,
+}
+
+/// Result of [`Method::RotateKey`] and [`Method::ReceiveRotation`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RotationResult {
+    /// The updated session, to persist.
+    pub(crate) session: Session,
+    /// The escrow the coins migrate to.
+    pub(crate) config: EscrowConfig,
+    /// The unsigned migration transaction, for both participants to sign.
+    pub(crate) migration: TransactionResult,
+    /// The rotation event to publish to the relays, when rotating one's own key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) event: Option<Event>,
+}
+
+/// Result of the methods signing an event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct EventResult {
+    /// The event to publish to the relays.
+    pub(crate) event: Event,
+}
+
+/// Result of [`Method::Reputation`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReputationResult {
+    /// Escrows rated as completed.
+    pub(crate) completed: usize,
+    /// Escrows rated as disputed.
+    pub(crate) disputed: usize,
+    /// Distinct authors of the feedback.
+    pub(crate) authors: usize,
+    /// Average rating, from 1 to 5, if any escrow was rated.
+    pub(crate) average_rating: Option<f64>,
+    /// Score from 0 to 100, if any escrow was rated.
+    pub(crate) score: Option<u8>,
+}
+
+/// An arbitrator of [`ArbitratorsResult`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ArbitratorEntry {
+    /// The arbitrator's latest ad.
+    pub(crate) ad: ArbitratorAd,
+    /// Creation time of the ad event.
+    pub(crate) published_at: Timestamp,
+    /// The arbitrator's fee on the escrow amount, if given.
+    #[serde(default, with = "bitcoin::amount::serde::as_sat::opt")]
+    pub(crate) fee: Option<Amount>,
+}
+
+/// Result of [`Method::ListArbitrators`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ArbitratorsResult {
+    /// The filter of the arbitrator ads to fetch from the relays.
+    pub(crate) filter: Filter,
+    /// The arbitrators meeting the criteria, cheapest then fastest first.
+    pub(crate) arbitrators: Vec<ArbitratorEntry>,
+}
+
+/// Result of [`Method::CancelSession`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CancelResult {
+    /// The updated session, to persist.
+    pub(crate) session: Session,
+    /// Whether every participant cancelled the escrow.
+    pub(crate) cancelled: bool,
+    /// The cancellation gift wrapped to each participant, to publish to the relays.
+    pub(crate) gift_wraps: Vec<Event>,
+}
+
+/// Result of [`Method::ReceiveCancellations`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CancellationsResult {
+    /// The updated session, to persist.
+    pub(crate) session: Session,
+    /// The cancellations received.
+    pub(crate) cancellations: Vec<Cancellation>,
+    /// Whether every participant cancelled the escrow.
+    pub(crate) cancelled: bool,
+}
+
+/// Result of [`Method::DueMessages`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct DueMessagesResult {
+    /// The messages to publish now, each recorded with [`Method::RecordPublished`].
+    pub(crate) messages: Vec<DueMessage>,
+}
+
+/// Result of the methods gift wrapping an event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct GiftWrapsResult {
+    /// The event gift wrapped to each recipient, to publish to the relays.
+    pub(crate) gift_wraps: Vec<Event>,
+}
+
+/// Result of [`Method::ReceiptFilter`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SessionFilterResult {
+    /// The updated session, to persist once the filter is fetched.
+    pub(crate) session: Session,
+    /// The filter to fetch from the relays.
+    pub(crate) filter: Filter,
+}
+
+/// Result of [`Method::ReceiveReceipts`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReceiptsResult {
+    /// The updated session, to persist.
+    pub(crate) session: Session,
+    /// IDs of the messages newly acknowledged.
+    pub(crate) acked: Vec<EventId>,
+}
+
+/// Result of [`Method::CancelFunding`] and [`Method::SignPayjoinOriginal`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PsbtResult {
+    /// The PSBT, in base64: unsigned to sign with the funder's wallet,
+    /// or finalized for a payjoin original.
+    pub(crate) psbt: String,
+}
+
+/// A failed call, safe to show to the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ApiError {
+    /// Stable [`Error::code`].
+    pub(crate) code: u16,
+    /// [`Error::user_message`].
+    pub(crate) message: String,
+}
+
+impl From<&Error> for ApiError {
+    fn from(error: &Error) -> Self {
+        Self {
+            code: error.code(),
+            message: error.user_message(),
+        }
+    }
+}
+
+/// Answer to a [`Request`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Response {
+    /// Always [`JSONRPC_VERSION`].
+    pub(crate) jsonrpc: String,
+    /// The [`Request::id`] answered.
+    pub(crate) id: Value,
+    /// The method's result, on success.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) result: Option<Value>,
+    /// Why the call failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<ApiError>,
+}
+
+impl Response {
+    /// The response to the call `id` with the outcome of its method.
+    fn new(id: Value, outcome: Result<Value, Error>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(ApiError::from(&error))),
+        };
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// Runs a [`Request`] through the escrow engine.
+///
+/// Never panics on bad input: every failure is reported as an [`ApiError`].
+pub(crate) fn handle(request: Request) -> Response {
+    #[cfg(debug_assertions)]
+    trace!(id = %request.id, method = ?request.method, "API request");
+    Response::new(request.id, call(request.method))
+}
+
+/// Runs a JSON [`Request`] through the escrow engine, returning the JSON [`Response`].
+pub(crate) fn handle_json(request: &str) -> String {
+    let response = match serde_json::from_str::<Request>(request) {
+        Ok(request) => handle(request),
+        Err(e) => Response::new(
+            Value::Null,
+            Err(Error::WrongInputs(format!("Malformed request: {e}"))),
+        ),
+    };
+    serialize(&response).expect("responses always serialize")
+}
+
+/// Runs a [`Method`], serializing its typed result.
+fn call(method: Method) -> Result<Value, Error> {
+    match method {
+        Method::EscrowAddress(EscrowParams { config }) => to_value(AddressResult {
+            address: config.address()?.into_unchecked(),
+        }),
+        Method::TrustProof(EscrowParams { config }) => to_value(TrustProof::generate(&config)?),
+        Method::DescribeEscrow(params) => {
+            let chain = match params.chain {
+                Some(chain) => chain.parse::<Chain>()?,
+                None => Chain::default(),
+            };
+            let mut profile = NetworkProfile::from(chain);
+            if let Some(secs) = params.block_interval_secs {
+                profile = profile.with_block_interval(Duration::from_secs(secs));
+            }
+            let summary = describe_escrow(&params.config, &profile)?;
+            to_value(SummaryResult {
+                text: summary.to_string(),
+                summary,
+            })
+        }
+        Method::EscrowTx(params) => {
+            let tx = escrow_tx(
+                &params.config.npub_1,
+                &params.config.npub_2,
+                params.config.timelock_duration,
+                params.amount_1,
+                params.amount_2,
+                params.funding_txid,
+                params.fee,
+                params.config.network,
+                lock_time(params.lock_time_height)?,
+            )?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::ResolutionTx(params) => {
+            if params.fee >= params.amount {
+                return Err(Error::Rounding);
+            }
+            let tx = resolution_tx(
+                params.amount,
+                params.outpoint.txid,
+                params.outpoint.vout,
+                &params.destination.assume_checked(),
+                params.fee,
+                lock_time(params.lock_time_height)?,
+            );
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::SplitResolutionTx(params) => {
+            let tx = split_resolution_tx(
+                &params.config,
+                params.funding,
+                params.amount,
+                params.split,
+                params.fee,
+                params.escrow_script,
+                lock_time(params.lock_time_height)?,
+            )?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::VerifySplitResolution(params) => {
+            let (payout_1, payout_2) = verify_split_resolution(
+                &parse_tx_hex(&params.tx_hex)?,
+                &params.config,
+                params.amount,
+                params.fee,
+            )?;
+            to_value(PayoutsResult { payout_1, payout_2 })
+        }
+        Method::SilentPaymentShare(params) => {
+            let address = params.address.parse::<SilentPaymentAddress>()?;
+            to_value(EcdhShare::new(&params.config, &params.nsec, &address.scan)?)
+        }
+        Method::SilentResolutionTx(params) => {
+            let mut tx = parse_tx_hex(&params.tx_hex)?;
+            let scripts = silent_payouts(&params.config, &tx, &params.payouts, &params.shares)?;
+            for (npub, script) in scripts {
+                redirect_payout(&mut tx, &params.config, &npub, script)?;
+            }
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::VerifySilentResolution(params) => {
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let scripts = silent_payouts(&params.config, &tx, &params.payouts, &params.shares)?;
+            let (payout_1, payout_2) =
+                verify_silent_resolution(&tx, &params.config, params.amount, params.fee, &scripts)?;
+            to_value(PayoutsResult { payout_1, payout_2 })
+        }
+        Method::BondResolutionTx(params) => {
+            let tx = BondedEscrow::new(params.config, params.terms)?.resolution_tx(
+                params.outcome,
+                params.funding,
+                params.fee,
+                lock_time(params.lock_time_height)?,
+            )?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::BondTimeoutTx(params) => {
+            let tx = BondedEscrow::new(params.config, params.terms)?.timeout_tx(
+                params.funding,
+                params.fee,
+                params.timeout_height,
+            )?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::VerifyBondResolution(params) => {
+            let bond = BondedEscrow::new(params.config, params.terms)?;
+            bond.verify_resolution(&parse_tx_hex(&params.tx_hex)?, params.outcome, params.fee)?;
+            let (payout_1, payout_2) = bond.payouts(params.outcome, params.fee)?;
+            to_value(PayoutsResult { payout_1, payout_2 })
+        }
+        Method::SwapHtlc(params) => {
+            let htlc = SwapHtlc::new(
+                &params.invoice.parse()?,
+                params.claim_key,
+                params.refund_npub,
+                params.timeout_height,
+                params.lockup_amount,
+                params.network,
+                Timestamp::now(),
+            )?;
+            htlc.verify_lockup_address(&params.lockup_address)?;
+            to_value(SwapHtlcResult {
+                address: htlc.address()?.into_unchecked(),
+                htlc,
+            })
+        }
+        Method::SettlePayout(params) => to_value(TransactionResult::from(&settle_payout(
+            parse_tx_hex(&params.tx_hex)?,
+            &params.settlement,
+        )?)),
+        Method::VerifySwapPayout(params) => {
+            verify_swap_payout(&parse_tx_hex(&params.tx_hex)?, &params.htlc)?;
+            to_value(SwapHtlcResult {
+                address: params.htlc.address()?.into_unchecked(),
+                htlc: params.htlc,
+            })
+        }
+        Method::HtlcRefund(params) => {
+            let destination = params.destination.require_network(params.htlc.network)?;
+            let tx = htlc_refund_tx(&params.htlc, params.outpoint, &destination, params.fee)?;
+            to_value(TransactionResult::from(&sign_htlc_refund(
+                tx,
+                &params.htlc,
+                &params.nsec,
+            )?))
+        }
+        Method::OracleEscrow(params) => {
+            let params = *params;
+            let event = OracleEvent::new(
+                params.oracle,
+                params.nonce,
+                params.event_id,
+                params.outcomes,
+            )?;
+            let escrow = OracleEscrow::new(
+                params.npub_1,
+                params.npub_2,
+                event,
+                params.winners,
+                params.refund,
+                params.refund_timelock,
+                params.network,
+            )?;
+            to_value(OracleEscrowResult {
+                address: escrow.address()?.into_unchecked(),
+                escrow,
+            })
+        }
+        Method::OracleResolution(params) => {
+            let escrow = &params.escrow;
+            let tx = escrow.resolution_tx(
+                params.funding,
+                params.amount,
+                params.fee,
+                &params.attestation.outcome,
+            )?;
+            let prevouts = vec![escrow.txout(params.amount)?];
+            to_value(TransactionResult::from(&sign_oracle_resolution(
+                tx,
+                0,
+                prevouts,
+                escrow,
+                &params.attestation,
+                &params.nsec,
+            )?))
+        }
+        Method::OracleRefund(params) => {
+            let escrow = &params.escrow;
+            let tx = escrow.refund_tx(params.funding, params.amount, params.fee)?;
+            let prevouts = vec![escrow.txout(params.amount)?];
+            to_value(TransactionResult::from(&sign_oracle_refund(
+                tx,
+                0,
+                prevouts,
+                escrow,
+                &params.nsec,
+            )?))
+        }
+        Method::SweepExpiredEscrows(params) => {
+            let fee_rate = FeeRate::from_sat_per_vb(params.fee_rate).ok_or_else(|| {
+                Error::WrongInputs(format!("Invalid fee rate {} sat/vB", params.fee_rate))
+            })?;
+            let tx = build_sweep_tx(
+                &params.escrows,
+                &params.destination.assume_checked(),
+                fee_rate,
+            )?;
+            to_value(FundedTxResult {
+                tx_hex: consensus::serialize(&tx).to_lower_hex_string(),
+                txid: tx.compute_txid(),
+                prevouts: params
+                    .escrows
+                    .iter()
+                    .map(ExpiredEscrow::prevout)
+                    .collect::<Result<_, _>>()?,
+            })
+        }
+        Method::SignSweep(params) => {
+            let SignSweepParams {
+                tx_hex,
+                escrows,
+                invariants,
+                nsec,
+            } = *params;
+            to_value(SweepSignaturesResult {
+                signatures: sign_sweep_tx(&parse_tx_hex(&tx_hex)?, nsec, &escrows, &invariants)?,
+            })
+        }
+        Method::CombineSweepSignatures(params) => {
+            let tx = combine_sweep_signatures(
+                parse_tx_hex(&params.tx_hex)?,
+                &params.escrows,
+                &params.participant_signatures,
+                &params.arbitrator_signatures,
+            )?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::EstimateSpend(params) => to_value(SizeResult {
+            vbytes: estimate_spend_weight(&params.config, params.path)?,
+        }),
+        Method::SignEscrowTx(params) => {
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let config = &params.config;
+            let nsec = params.nsec;
+            if is_arbitrator(config, &nsec.public_key()) {
+                params.invariants.check(&tx)?;
+                return to_value(require_arbitration(params.arbitration)?.sign(
+                    &tx,
+                    params.input_index,
+                    params.invariants.prevouts,
+                    params.escrow_script,
+                    &nsec,
+                    Timestamp::now(),
+                )?);
+            }
+            if let Some(mut session) = params.session {
+                if session.escrow_config()? != *config {
+                    return Err(Error::Protocol("Session is of another escrow".to_string()));
+                }
+                return to_value(SignatureResult {
+                    signature: session.sign(
+                        &tx,
+                        params.input_index,
+                        params.escrow_script,
+                        &params.invariants,
+                        &nsec,
+                        params.funding_confirmations,
+                    )?,
+                });
+            }
+            let mut keystore = Keystore::default();
+            let account = keystore.unlock(nsec);
+            let signature = sign_escrow_tx(
+                &tx,
+                params.input_index,
+                &keystore,
+                &account,
+                &config.npub_1,
+                &config.npub_2,
+                config.npub_arbitrator.as_ref(),
+                config.timelock_duration,
+                &params.invariants,
+                params.escrow_script,
+            )?;
+            to_value(SignatureResult { signature })
+        }
+        Method::CombineSignatures(params) => {
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let signers = params.config.signers(params.escrow_script)?;
+            if params.signatures.len() != signers.len() {
+                return Err(Error::WrongInputs(format!(
+                    "Expected {} signatures, got {}",
+                    signers.len(),
+                    params.signatures.len()
+                )));
+            }
+            let tx = combine_signatures(
+                tx,
+                params.input_index,
+                params.signatures.iter().collect(),
+                &params.config.script(params.escrow_script)?,
+                &params.config.spend_info()?,
+            )?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::AggregateKeySpend(params) => {
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let config = &params.config;
+            let context = config.key_agg()?;
+            let message = key_spend_message(&tx, params.input_index, &params.prevouts)?;
+            let participants = [config.npub_1, config.npub_2];
+            if params.nonces.len() != participants.len()
+                || params.partial_signatures.len() != participants.len()
+            {
+                return Err(Error::WrongInputs(format!(
+                    "Expected a nonce and a partial signature of each of the {} participants",
+                    participants.len()
+                )));
+            }
+            let aggregate_nonce = AggregateNonce::sum(&params.nonces)?;
+            for ((npub, nonce), partial_signature) in participants
+                .iter()
+                .zip(&params.nonces)
+                .zip(&params.partial_signatures)
+            {
+                verify_partial_signature(
+                    &context,
+                    partial_signature,
+                    nonce,
+                    npub,
+                    &aggregate_nonce,
+                    &message,
+                )?;
+            }
+            let signature = aggregate_signatures(
+                &context,
+                &aggregate_nonce,
+                &message,
+                &params.partial_signatures,
+            )?;
+            let tx = with_key_spend_signature(&tx, params.input_index, signature)?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::SigningBundle(params) => to_value(SigningBundle::new(
+            None,
+            params.config,
+            &parse_tx_hex(&params.tx_hex)?,
+            params.input_index,
+            params.prevouts,
+            params.escrow_script,
+        )?),
+        Method::SignBundle(params) => {
+            let nsec = params.nsec;
+            if is_arbitrator(&params.bundle.config, &nsec.public_key()) {
+                return to_value(params.bundle.arbitrate(
+                    &nsec,
+                    &params.invariants,
+                    &require_arbitration(params.arbitration)?,
+                    Timestamp::now(),
+                )?);
+            }
+            to_value(SignatureResult {
+                signature: params.bundle.sign(&nsec, &params.invariants)?,
+            })
+        }
+        Method::ImportSignature(params) => to_value(
+            params
+                .bundle
+                .import_signature(params.npub, params.signature)?,
+        ),
+        Method::SignaturesEvent(params) => {
+            let SignaturesEventParams {
+                session,
+                mut signatures,
+                nsec,
+            } = *params;
+            session.check()?;
+            let id = session.id()?;
+            if signatures
+                .session_id
+                .is_some_and(|session_id| session_id != id)
+            {
+                return Err(Error::Protocol(
+                    "Signatures are for another session".to_string(),
+                ));
+            }
+            signatures.session_id = Some(id);
+            let signers = session.escrow_config()?.signers(signatures.escrow_script)?;
+            to_value(EventResult {
+                event: nsec.with_nostr_secret_key(|nsec| signatures.to_event(nsec, &signers))?,
+            })
+        }
+        Method::ReceiveSignatures(params) => {
+            let ReceiveSignaturesParams {
+                mut session,
+                mut signatures,
+                events,
+                tx_hex,
+                prevouts,
+            } = *params;
+            session.check()?;
+            for event in &events {
+                signatures.push(LeafSignatures::from_event(event)?);
+            }
+            let tx = parse_tx_hex(&tx_hex)?;
+            let context = session.escrow_context()?;
+            let added = match <[LeafSignatures; 1]>::try_from(signatures.clone()) {
+                Ok([signatures]) => {
+                    session.receive_signatures(signatures, &tx, &prevouts, &context)?
+                }
+                Err(batch) => session.receive_signature_batch(batch, &tx, &prevouts, &context)?,
+            };
+            if added {
+                let now = Timestamp::now();
+                for signatures in &signatures {
+                    session.record_signatures(signatures, now);
+                }
+            }
+            to_value(ReceivedSignaturesResult { session, added })
+        }
+        Method::AdaptorPoint(params) => to_value(AdaptorPointResult {
+            point: AdaptorSecret::derive(params.secret.as_bytes())?
+                .point()
+                .to_string(),
+        }),
+        Method::AdaptorSignLeaf(params) => {
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let signature = adaptor_sign_leaf(
+                &tx,
+                params.input_index,
+                &params.prevouts,
+                &params.config.script(params.escrow_script)?,
+                &params.nsec,
+                &params.adaptor.parse()?,
+            )?;
+            to_value(AdaptorSignatureResult {
+                adaptor_signature: signature.to_string(),
+            })
+        }
+        Method::VerifyAdaptorSignature(params) => {
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let signature = params.adaptor_signature.parse::<AdaptorSignature>()?;
+            let message = script_spend_message(
+                &tx,
+                params.input_index,
+                &params.prevouts,
+                &params.config.script(params.escrow_script)?,
+            )?;
+            verify_adaptor_signature(&signature, &params.npub, &message)?;
+            to_value(AdaptorPointResult {
+                point: signature.adaptor().to_string(),
+            })
+        }
+        Method::CompleteAdaptorSignature(params) => to_value(SignatureResult {
+            signature: params
+                .adaptor_signature
+                .parse::<AdaptorSignature>()?
+                .complete(&AdaptorSecret::derive(params.secret.as_bytes())?)?,
+        }),
+        Method::CompilePolicy(params) => {
+            let compiled = compile_policy(&params.policy, params.network)?;
+            to_value(PolicyResult {
+                policy: compiled.policy().to_string(),
+                address: compiled.address().as_unchecked().clone(),
+                leaves: compiled
+                    .leaves()
+                    .iter()
+                    .map(|leaf| PolicyLeafResult {
+                        script: leaf.script.clone(),
+                        signers: leaf.signers(),
+                        lock_time: leaf.lock_time(),
+                        sequence: leaf.sequence(),
+                    })
+                    .collect(),
+            })
+        }
+        Method::SignPolicyLeaf(params) => {
+            let compiled = compile_policy(&params.policy, params.network)?;
+            let leaf = policy_leaf(&compiled, &params.script)?;
+            if !leaf.signers().contains(&params.nsec.public_key()) {
+                return Err(Error::WrongInputs(format!(
+                    "{} does not sign the leaf",
+                    params.nsec.public_key()
+                )));
+            }
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            to_value(SignatureResult {
+                signature: BatchSigner::new(&tx, &params.invariants)?.sign(
+                    params.input_index,
+                    &leaf.script,
+                    &params.nsec,
+                )?,
+            })
+        }
+        Method::CombinePolicySignatures(params) => {
+            let compiled = compile_policy(&params.policy, params.network)?;
+            let leaf = policy_leaf(&compiled, &params.script)?;
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            compiled.verify(
+                &tx,
+                params.input_index,
+                &params.prevouts,
+                leaf,
+                &params.signatures,
+            )?;
+            let tx = compiled.combine(tx, params.input_index, leaf, &params.signatures)?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::ExtractAdaptorSecret(params) => {
+            let secret = params
+                .adaptor_signature
+                .parse::<AdaptorSignature>()?
+                .extract(&params.signature)?;
+            to_value(AdaptorSecretResult {
+                nsec: secret
+                    .to_nsec()
+                    .with_nostr_secret_key(|key| key.to_secret_hex()),
+            })
+        }
+        Method::ExportTx(params) => {
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let export = export(&tx, params.prevouts.as_deref(), DEFAULT_BBQR_PART_LEN)?;
+            to_value(ExportResult {
+                hex: export.hex,
+                psbt: export.psbt,
+                bbqr: export.bbqr,
+            })
+        }
+        Method::ExportEscrowUtxo(params) => {
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let origins = params
+                .origins
+                .iter()
+                .map(|origin| {
+                    Ok((
+                        origin.npub,
+                        nip06_key_source(origin.fingerprint, origin.account)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let export = export_escrow_utxo(
+                &tx,
+                params.input_index,
+                &params.prevouts,
+                &params.config.context()?,
+                &origins,
+                DEFAULT_BBQR_PART_LEN,
+            )?;
+            to_value(EscrowUtxoResult {
+                descriptor: export.descriptor,
+                outpoint: export.outpoint,
+                prevout: export.prevout,
+                psbt: export.psbt,
+                bbqr: export.bbqr,
+            })
+        }
+        Method::SignMessage(params) => to_value(SignatureResult {
+            signature: sign_message(params.nsec, &params.message),
+        }),
+        Method::VerifyMessage(params) => to_value(VerifiedResult {
+            valid: verify_message(&params.npub, &params.message, &params.signature).is_ok(),
+        }),
+        Method::SignAddressMessage(params) => to_value(ProofResult {
+            proof: sign_bip322_simple(
+                params.nsec,
+                &params.address.assume_checked(),
+                &params.message,
+            )?,
+        }),
+        Method::VerifyAddressMessage(params) => to_value(VerifiedResult {
+            valid: verify_bip322_simple(
+                &params.address.assume_checked(),
+                &params.message,
+                &params.proof,
+            )
+            .is_ok(),
+        }),
+        Method::SignAddressChallenge(params) => to_value(SignatureResult {
+            signature: sign_address_challenge(params.nsec, &params.challenge),
+        }),
+        Method::VerifyAddressChallenge(params) => to_value(VerifiedResult {
+            valid: verify_address_ownership_with_challenge(
+                &params.address.assume_checked(),
+                &params.npub,
+                &params.challenge,
+                &params.signature,
+            )
+            .is_ok(),
+        }),
+        Method::KeyInfo(params) => {
+            // A hex key is public, only an nsec is read as a secret key.
+            let npub = if params.key.trim().starts_with("nsec") {
+                params.key.parse::<SecretNsec>()?.npub()
+            } else {
+                params.key.parse::<Npub>()?
+            };
+            to_value(KeyResult {
+                npub: npub.to_string(),
+                hex: npub.to_hex(),
+                address: npub.address(params.network)?.into_unchecked(),
+            })
+        }
+        Method::FindPayment(params) => {
+            let request = params.uri.parse::<PaymentRequest>()?;
+            request.address(params.network)?;
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            to_value(PaymentResult {
+                outpoint: request.find_payment(&tx),
+            })
+        }
+        Method::SelectCoins(params) => {
+            let fee_rate = FeeRate::from_sat_per_vb(params.fee_rate).ok_or_else(|| {
+                Error::WrongInputs(format!("Invalid fee rate {} sat/vB", params.fee_rate))
+            })?;
+            to_value(select_coins(
+                &params.coins,
+                &params.control,
+                &params.selection,
+                params.amount,
+                fee_rate,
+                &params.change_address.assume_checked(),
+            )?)
+        }
+        Method::SweepWallet(params) => {
+            let fee_rate = FeeRate::from_sat_per_vb(params.fee_rate).ok_or_else(|| {
+                Error::WrongInputs(format!("Invalid fee rate {} sat/vB", params.fee_rate))
+            })?;
+            let tx = sweep_tx(
+                &params.coins,
+                &params.nsec,
+                &params.destination.assume_checked(),
+                fee_rate,
+                lock_time(params.lock_time_height)?,
+            )?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::Offer(params) => {
+            let OfferParams { offer, nsec, fiat } = *params;
+            let offer = match fiat {
+                Some(fiat) => offer.in_fiat(
+                    FiatAmount::parse(fiat.currency, &fiat.amount_buyer)?,
+                    FiatAmount::parse(fiat.currency, &fiat.amount_seller)?,
+                    fiat.price,
+                )?,
+                None => offer,
+            };
+            let (handshake, event) =
+                nsec.with_nostr_secret_key(|nsec| Handshake::offer(nsec, offer, Timestamp::now()))?;
+            to_value(NegotiationResult {
+                session: Session::new(handshake),
+                event,
+            })
+        }
+        Method::AcceptOffer(params) => {
+            let offer = Offer::from_event(&params.offer_event)?;
+            check_platform_fee(offer.platform_fee.as_ref(), params.platform_fee.as_ref())?;
+            let (handshake, event) = params.nsec.with_nostr_secret_key(|nsec| {
+                Handshake::accept(nsec, &params.offer_event, params.price, Timestamp::now())
+            })?;
+            to_value(NegotiationResult {
+                session: Session::new(handshake),
+                event,
+            })
+        }
+        Method::AcceptanceFilter(params) => to_value(FilterResult {
+            filter: Acceptance::filter(&params.session_id),
+        }),
+        Method::ReceiveAcceptance(params) => {
+            let mut session = params.session;
+            session.check()?;
+            let event = &params.acceptance_event;
+            let now = Timestamp::now();
+            session.handshake = session.handshake.receive(event, now)?;
+            session.record_received(event.id, event.kind.as_u16(), event.pubkey, now);
+            to_value(SessionResult { session })
+        }
+        Method::AgreedEscrowTx(params) => {
+            params.session.check()?;
+            let Handshake::Agreed {
+                offer, acceptance, ..
+            } = &params.session.handshake
+            else {
+                return Err(Error::Protocol("Escrow is not agreed yet".to_string()));
+            };
+            let tx = offer.escrow_tx(
+                &acceptance.acceptor,
+                acceptance.price.as_ref(),
+                params.funding_txid,
+                params.fee,
+            )?;
+            to_value(TransactionResult::from(&tx))
+        }
+        Method::ReceiveDecision(params) => {
+            let mut session = params.session;
+            session.check()?;
+            let tx = parse_tx_hex(&params.tx_hex)?;
+            let decision = receive_decision(&mut session, &params.gift_wraps, &params.nsec)?;
+            if let Some(decision) = &decision {
+                decision.verify(&session.handshake, &tx)?;
+            }
+            to_value(DecisionResult { session, decision })
+        }
+        Method::TopUpRequest(params) => {
+            let session = params.session;
+            session.check()?;
+            let request = match (&session.funding, session.handshake.escrow_address()) {
+                (Some(funding), Some(escrow_address)) => {
+                    let address = escrow_address.to_string();
+                    funding.top_up_request(escrow_address, &address[address.len() - 8..])
+                }
+                _ => None,
+            };
+            to_value(TopUpResult {
+                missing: request
+                    .as_ref()
+                    .and_then(|request| request.amount)
+                    .map(|amount| session.display_amount(amount)),
+                uri: request.map(|request| request.to_string()),
+            })
+        }
+        Method::PayjoinRequest(params) => {
+            let session = params.session;
+            session.check()?;
+            let Some(escrow_address) = session.handshake.escrow_address() else {
+                return Err(Error::Protocol("Escrow is not agreed yet".to_string()));
+            };
+            let (amount_buyer, _) = session.handshake.amounts()?;
+            let address = escrow_address.to_string();
+            let request = PaymentRequest::escrow_funding(
+                escrow_address,
+                amount_buyer,
+                &address[address.len() - 8..],
+            )
+            .with_payjoin(params.endpoint);
+            to_value(PaymentRequestResult {
+                uri: request.to_string(),
+            })
+        }
+        Method::SignPayjoinOriginal(params) => {
+            let request = params.uri.parse::<PaymentRequest>()?;
+            if request.payjoin.is_none() {
+                return Err(Error::WrongInputs(
+                    "The payment request has no payjoin endpoint".to_string(),
+                ));
+            }
+            let value = request.amount.ok_or_else(|| {
+                Error::WrongInputs("The payment request has no amount".to_string())
+            })?;
+            let payment = TxOut {
+                value,
+                script_pubkey: request.address(params.network)?.script_pubkey(),
+            };
+            let outputs = std::iter::once(payment)
+                .chain(params.coins.change)
+                .collect();
+            let psbt = sign_original(
+                &params.coins.inputs,
+                outputs,
+                lock_time(params.lock_time_height)?,
+                &params.nsec,
+            )?;
+            to_value(PsbtResult {
+                psbt: psbt.to_string(),
+            })
+        }
+        Method::FundEscrowTx(params) => {
+            let session = params.session;
+            session.check()?;
+            let (Some(funding), Some(escrow_address)) =
+                (&session.funding, session.handshake.escrow_address())
+            else {
+                return Err(Error::Protocol("Escrow is not funded yet".to_string()));
+            };
+            let network = session.handshake.negotiated_offer().network;
+            let excess_to = params.excess_to.require_network(network)?;
+            let tx = fund_escrow_tx(parse_tx_hex(&params.tx_hex)?, funding, &excess_to)?;
+            to_value(FundedTxResult {
+                tx_hex: consensus::serialize(&tx).to_lower_hex_string(),
+                txid: tx.compute_txid(),
+                prevouts: funding.prevouts(escrow_address),
+            })
+        }
+        Method::CofundEscrowTx(params) => {
+            let mut cofunding = cofunding_tx(&params.session, params.contributions)?;
+            for CofundingSignatures { npub, signatures } in &params.signatures {
+                cofunding.add_signatures(npub, signatures)?;
+            }
+            let fee = cofunding.fee();
+            let prevouts = cofunding.prevouts.clone();
+            let complete = cofunding.is_complete();
+            let tx = if complete {
+                cofunding.finalize()?
+            } else {
+                cofunding.tx
+            };
+            to_value(CofundingResult {
+                tx_hex: consensus::serialize(&tx).to_lower_hex_string(),
+                txid: tx.compute_txid(),
+                prevouts,
+                fee,
+                complete,
+            })
+        }
+        Method::SignCofunding(params) => {
+            let cofunding = cofunding_tx(&params.session, params.contributions)?;
+            to_value(CofundingSignatures {
+                npub: params.nsec.public_key(),
+                signatures: cofunding.sign(params.nsec)?,
+            })
+        }
+        Method::PlatformFee(params) => to_value(PlatformFee::to_npub(
+            &params.npub,
+            params.network,
+            params.bps,
+        )?),
+        Method::ProposeEscrow(params) => {
+            if let Some((step, e)) = params.draft.first_invalid_step() {
+                return Err(Error::WrongInputs(format!("Invalid {step:?} step: {e}")));
+            }
+            let mut proposal = params.draft.build()?;
+            if let Some(platform_fee) = params.platform_fee {
+                proposal = proposal.with_platform_fee(platform_fee)?;
+            }
+            to_value(ProposalResult {
+                offer: proposal.offer(Timestamp::now(), params.lock_time_height)?,
+                uri: proposal.payment_request().to_string(),
+            })
+        }
+        Method::ChainEscrowTx(params) => {
+            params.session.check()?;
+            let Handshake::Agreed {
+                offer, acceptance, ..
+            } = &params.session.handshake
+            else {
+                return Err(Error::Protocol("Escrow is not agreed yet".to_string()));
+            };
+            let from = offer.escrow_config(&acceptance.acceptor)?;
+            let mut builder = ChainBuilder::new(&from, params.funding, params.amount)
+                .dispute(params.dispute)
+                .fee(params.fee);
+            if let Some(template) = params.template {
+                builder = builder.template(template);
+            }
+            let chain = builder.build()?;
+            let mut signatures = chain.sign(&params.nsec)?;
+            if let Some(received) = &params.signatures {
+                received.verify(
+                    &chain.tx,
+                    std::slice::from_ref(&chain.prevout),
+                    &from.context()?,
+                )?;
+                for signature in &received.signatures {
+                    signatures.insert(signature.npub, signature.signature);
+                }
+            }
+            let complete = signatures.signatures.len() == 2;
+            let tx = if complete {
+                chain.finalize(&signatures)?
+            } else {
+                chain.tx.clone()
+            };
+            let expires_at = params
+                .expires_at
+                .unwrap_or_else(|| Timestamp::now() + DEFAULT_OFFER_VALIDITY);
+            to_value(ChainResult {
+                tx_hex: consensus::serialize(&tx).to_lower_hex_string(),
+                txid: tx.compute_txid(),
+                signatures,
+                complete,
+                offer: chain.next_offer(offer, &acceptance.acceptor, expires_at)?,
+            })
+        }
+        Method::RotateKey(params) => {
+            let RotateKeyParams {
+                mut session,
+                nsec,
+                new_nsec,
+                funding,
+                amount,
+                fee,
+                funding_height,
+                tip_height,
+            } = *params;
+            session.check()?;
+            let config = session.escrow_config()?;
+            let timelock_duration = config.timelock_duration.map(|timelock_duration| {
+                match (funding_height, tip_height) {
+                    (Some(funding_height), Some(tip_height)) => {
+                        remaining_timelock(timelock_duration, funding_height, tip_height)
+                    }
+                    _ => timelock_duration,
+                }
+            });
+            let rotation = KeyRotation::new(
+                session.id()?,
+                &config,
+                nsec.public_key(),
+                new_nsec,
+                timelock_duration,
+                fee,
+            )?;
+            let event =
+                nsec.with_nostr_secret_key(|secret_key| rotation.to_event(secret_key, &config))?;
+            let migration = rotation.migration_tx(&config, funding, amount)?;
+            to_value(RotationResult {
+                config: session.rotate(rotation)?,
+                session,
+                migration: TransactionResult::from(&migration),
+                event: Some(event),
+            })
+        }
+        Method::RotationFilter(params) => to_value(FilterResult {
+            filter: KeyRotation::filter(&params.session_id),
+        }),
+        Method::ReceiveRotation(params) => {
+            let mut session = params.session;
+            session.check()?;
+            let event = &params.event;
+            let rotation = KeyRotation::from_event(event)?;
+            let migration =
+                rotation.migration_tx(&session.escrow_config()?, params.funding, params.amount)?;
+            let config = session.rotate(rotation)?;
+            let now = Timestamp::now();
+            session.record_received(event.id, event.kind.as_u16(), event.pubkey, now);
+            session.record_state(now);
+            to_value(RotationResult {
+                config,
+                session,
+                migration: TransactionResult::from(&migration),
+                event: None,
+            })
+        }
+        Method::Feedback(params) => {
+            let FeedbackParams {
+                session,
+                outcome,
+                rating,
+                comment,
+                nsec,
+            } = *params;
+            session.check()?;
+            let feedback = Feedback::new(
+                &session.handshake,
+                nsec.public_key(),
+                outcome,
+                rating,
+                comment,
+            )?;
+            to_value(EventResult {
+                event: nsec.with_nostr_secret_key(|nsec| feedback.to_event(nsec))?,
+            })
+        }
+        Method::FeedbackFilter(params) => to_value(FilterResult {
+            filter: Feedback::filter(&params.npub),
+        }),
+        Method::Reputation(params) => {
+            let feedback = params
+                .events
+                .iter()
+                .filter_map(|event| Feedback::from_event(event).ok())
+                .collect::<Vec<_>>();
+            let reputation = Reputation::new(&params.npub, &feedback);
+            to_value(ReputationResult {
+                completed: reputation.completed,
+                disputed: reputation.disputed,
+                authors: reputation.authors,
+                average_rating: reputation.average_rating(),
+                score: reputation.score(),
+            })
+        }
+        Method::AdvertiseArbitrator(params) => to_value(EventResult {
+            event: params
+                .nsec
+                .with_nostr_secret_key(|nsec| params.ad.to_event(nsec))?,
+        }),
+        Method::ListArbitrators(params) => {
+            let filter = ArbitratorFilter {
+                network: params.network,
+                max_fee_bps: params.max_fee_bps,
+                max_response_hours: params.max_response_hours,
+            };
+            let arbitrators = list_arbitrators(&params.events, &filter)
+                .into_iter()
+                .map(|listing| ArbitratorEntry {
+                    fee: params.amount.map(|amount| listing.ad.fee(amount)),
+                    ad: listing.ad,
+                    published_at: listing.published_at,
+                })
+                .collect();
+            to_value(ArbitratorsResult {
+                filter: ArbitratorAd::filter(),
+                arbitrators,
+            })
+        }
+        Method::CancelSession(params) => {
+            let CancelSessionParams {
+                mut session,
+                nsec,
+                funding_txid,
+                reason,
+                funding_confirmed,
+            } = *params;
+            session.check()?;
+            let cancellation =
+                Cancellation::new(&session.handshake, nsec.public_key(), funding_txid, reason)?;
+            let event = nsec.with_nostr_secret_key(|secret_key| {
+                cancellation.to_event(secret_key, &session.handshake)
+            })?;
+            let cancelled = session.cancel(cancellation, funding_confirmed)?;
+            to_value(CancelResult {
+                session,
+                cancelled,
+                gift_wraps: wrap_to_recipients(&event, &nsec, Timestamp::now())?,
+            })
+        }
+        Method::ReceiveCancellations(params) => {
+            let mut session = params.session;
+            session.check()?;
+            let cancellations =
+                receive_cancellations(&mut session, &params.gift_wraps, &params.nsec)?;
+            session.record_state(Timestamp::now());
+            to_value(CancellationsResult {
+                cancelled: session.is_cancelled(),
+                session,
+                cancellations,
+            })
+        }
+        Method::SendMessage(params) => {
+            let SendMessageParams { mut session, event } = *params;
+            session.check()?;
+            session.send(event, Timestamp::now());
+            to_value(SessionResult { session })
+        }
+        Method::DueMessages(params) => {
+            params.session.check()?;
+            to_value(DueMessagesResult {
+                messages: params
+                    .session
+                    .due_messages(&params.nsec, Timestamp::now())?,
+            })
+        }
+        Method::RecordPublished(params) => {
+            let RecordPublishedParams {
+                mut session,
+                event_id,
+                published,
+            } = *params;
+            session.check()?;
+            session.record_published(&event_id, published, Timestamp::now());
+            to_value(SessionResult { session })
+        }
+        Method::AcknowledgeMessage(params) => {
+            let AcknowledgeMessageParams {
+                session,
+                event_id,
+                author,
+                nsec,
+            } = *params;
+            session.check()?;
+            let receipt = Receipt::new(session.id()?, event_id, nsec.public_key());
+            let event =
+                nsec.with_nostr_secret_key(|secret_key| receipt.to_event(secret_key, &author))?;
+            to_value(GiftWrapsResult {
+                gift_wraps: wrap_to_recipients(&event, &nsec, Timestamp::now())?,
+            })
+        }
+        Method::ReceiptFilter(params) => {
+            let ReceiptFilterParams { mut session, npub } = *params;
+            session.check()?;
+            let filter = session.receipt_filter(&npub, Timestamp::now())?;
+            to_value(SessionFilterResult { session, filter })
+        }
+        Method::ReceiveReceipts(params) => {
+            let mut session = params.session;
+            session.check()?;
+            let acked = receive_receipts(&mut session, &params.gift_wraps, &params.nsec)?;
+            to_value(ReceiptsResult { session, acked })
+        }
+        Method::CancelFunding(params) => {
+            let fee_rate = FeeRate::from_sat_per_vb(params.fee_rate).ok_or_else(|| {
+                Error::WrongInputs(format!("Invalid fee rate {} sat/vB", params.fee_rate))
+            })?;
+            let psbt = cancel_funding_psbt(
+                &parse_tx_hex(&params.tx_hex)?,
+                params.prevouts,
+                &params.refund_address.assume_checked(),
+                fee_rate,
+                &params.fee_rates,
+            )?;
+            to_value(PsbtResult {
+                psbt: psbt.to_string(),
+            })
+        }
+    }
+}
+
+impl From<&Transaction> for TransactionResult {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            tx_hex: consensus::serialize(tx).to_lower_hex_string(),
+            txid: tx.compute_txid(),
+        }
+    }
+}
+
+/// The silent payment scripts of `payouts` in the resolution `tx` of the escrow `config`,
+/// from both participants' `shares`.
+fn silent_payouts(
+    config: &EscrowConfig,
+    tx: &Transaction,
+    payouts: &[SilentPayout],
+    shares: &[EcdhShare],
+) -> Result<Vec<(NostrPublicKey, ScriptBuf)>, Error> {
+    let funding = tx
+        .input
+        .first()
+        .map(|input| input.previous_output)
+        .ok_or_else(|| Error::WrongInputs("Resolution has no inputs".to_string()))?;
+    let addresses = payouts
+        .iter()
+        .map(|payout| payout.address.parse::<SilentPaymentAddress>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let scripts = payout_scripts(config, funding, &addresses, shares)?;
+    Ok(payouts
+        .iter()
+        .map(|payout| payout.npub)
+        .zip(scripts)
+        .collect())
+}
+
+/// The lock time of a transaction built at `height`, if any.
+fn lock_time(height: Option<u32>) -> Result<absolute::LockTime, Error> {
+    height.map_or(Ok(absolute::LockTime::ZERO), anti_fee_sniping_lock_time)
+}
+
+/// The `arbitration` an arbitrator signs with, required since they only sign
+/// what their dispute records allow.
+fn require_arbitration(arbitration: Option<Arbitration>) -> Result<Arbitration, Error> {
+    arbitration.ok_or_else(|| {
+        Error::WrongInputs("Arbitrators sign through their dispute records".to_string())
+    })
+}
+
+/// The transaction funding the escrow of the agreed `session` from the `contributions`.
+fn cofunding_tx(session: &Session, contributions: Vec<Contribution>) -> Result<CofundingTx, Error> {
+    session.check()?;
+    let Handshake::Agreed {
+        offer, acceptance, ..
+    } = &session.handshake
+    else {
+        return Err(Error::Protocol("Escrow is not agreed yet".to_string()));
+    };
+    contributions
+        .into_iter()
+        .try_fold(
+            CofundingBuilder::new(offer, &acceptance.acceptor)?,
+            CofundingBuilder::contribute,
+        )?
+        .build()
+}
+
+/// Serializes a typed method result.
+/// Compiles the spending `policy` of an escrow on `network`.
+fn compile_policy(policy: &str, network: Network) -> Result<CompiledPolicy, Error> {
+    CompiledPolicy::compile(&policy.parse()?, network)
+}
+
+/// The leaf of `compiled` whose locking script is `script`.
+fn policy_leaf<'a>(compiled: &'a CompiledPolicy, script: &Script) -> Result<&'a PolicyLeaf, Error> {
+    compiled
+        .find_leaf(script)
+        .ok_or_else(|| Error::WrongInputs("Script is not a leaf of the policy".to_string()))
+}
+
+fn to_value<T: Serialize>(result: T) -> Result<Value, Error> {
+    serde_json::to_value(result).map_err(|e| Error::Protocol(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Psbt, Sequence, TxIn, hashes::Hash, transaction};
+    use nostr::{Kind, nips::nip19::ToBech32};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        adaptor::AdaptorPoint,
+        arbitrators::ARBITRATOR_AD_VERSION,
+        cofunding::FundingInput,
+        draft::{WizardStep, draft},
+        payjoin::PayjoinReceiver,
+        price::PriceProvider,
+        protocol::{deserialize, offer},
+        receipts::DeliveryStatus,
+        scripts::CURRENT_SCRIPT_TEMPLATE,
+        summary::Timing,
+        util::npub_to_address,
+    };
+
+    /// The typed result of running `method`, panicking on failure.
+    fn call_ok<T: for<'de> Deserialize<'de>>(method: Method) -> T {
+        let response = handle(Request {
+            id: Value::Null,
+            method,
+        });
+        assert_eq!(response.error, None);
+        serde_json::from_value(response.result.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn handle_requests() {
+        let nsec_1 = SecretNsec::generate();
+        let npub_2 = SecretNsec::generate().public_key();
+        let config = EscrowConfig {
+            npub_1: nsec_1.public_key(),
+            npub_2,
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+
+        let request = json!({
+            "id": 1,
+            "method": "escrow_address",
+            "params": { "config": config },
+        });
+        let response: Response = deserialize(&handle_json(&request.to_string())).unwrap();
+        assert_eq!(response.id, json!(1));
+        assert_eq!(response.error, None);
+        let address: AddressResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(address.address.assume_checked(), config.address().unwrap());
+
+        let request = json!({
+            "method": "describe_escrow",
+            "params": { "config": config, "chain": "Regtest" },
+        });
+        let response: Response = deserialize(&handle_json(&request.to_string())).unwrap();
+        let summary: SummaryResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(summary.summary.conditions.len(), 1);
+        assert!(summary.text.contains("at any time"));
+
+        // Regtest nodes can mine at their own pace.
+        let disputed = EscrowConfig {
+            npub_arbitrator: Some(SecretNsec::generate().public_key()),
+            timelock_duration: Some(144),
+            ..config
+        };
+        let summary: SummaryResult = call_ok(Method::DescribeEscrow(DescribeEscrowParams {
+            config: disputed,
+            chain: Some("Regtest".to_string()),
+            block_interval_secs: Some(1),
+        }));
+        assert!(summary.summary.conditions.iter().any(|condition| {
+            condition.timing
+                == Timing::AfterBlocks {
+                    blocks: 144,
+                    seconds: 144,
+                }
+        }));
+
+        let response = handle(Request {
+            id: json!("tx"),
+            method: Method::EscrowTx(EscrowTxParams {
+                config,
+                amount_1: Amount::from_sat(50_000),
+                amount_2: Amount::from_sat(49_000),
+                funding_txid: Txid::all_zeros(),
+                fee: Amount::from_sat(1_000),
+                lock_time_height: Some(100),
+            }),
+        });
+        let tx: TransactionResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        let tx = parse_tx_hex(&tx.tx_hex).unwrap();
+        assert_eq!(tx.lock_time, absolute::LockTime::from_height(100).unwrap());
+
+        let payout = &tx.output[0];
+        let address = Address::from_script(&payout.script_pubkey, Network::Regtest).unwrap();
+        let find_payment = |amount: Amount, network: Network| {
+            call(Method::FindPayment(FindPaymentParams {
+                uri: PaymentRequest::escrow_funding(&address, amount, "1").to_string(),
+                network,
+                tx_hex: consensus::serialize(&tx).to_lower_hex_string(),
+            }))
+            .map(|value| serde_json::from_value::<PaymentResult>(value).unwrap())
+        };
+        assert_eq!(
+            find_payment(payout.value, Network::Regtest)
+                .unwrap()
+                .outpoint,
+            Some(OutPoint::new(tx.compute_txid(), 0))
+        );
+        assert_eq!(
+            find_payment(Amount::from_sat(1), Network::Regtest)
+                .unwrap()
+                .outpoint,
+            None
+        );
+        assert!(find_payment(payout.value, Network::Bitcoin).is_err());
+
+        // Failures carry the stable error code, and never echo secrets,
+        // neither in errors nor in debug output.
+        let request = json!({
+            "id": 3,
+            "method": "sign_message",
+            "params": { "message": "hello", "nsec": "nsec1invalid" },
+        });
+        let response: Response = deserialize(&handle_json(&request.to_string())).unwrap();
+        assert_eq!(response.result, None);
+        let error = response.error.unwrap();
+        assert_eq!(error.code, 100);
+        assert!(!error.message.contains("nsec1invalid"));
+        let params = SignMessageParams {
+            message: "hello".to_string(),
+            nsec: SecretNsec::generate(),
+        };
+        let hex = params
+            .nsec
+            .with_nostr_secret_key(|secret_key| secret_key.to_secret_hex());
+        assert!(!format!("{params:?}").contains(&hex));
+
+        let change_address = config.address().unwrap();
+        let request = json!({
+            "method": "select_coins",
+            "params": {
+                "coins": [{
+                    "outpoint": OutPoint::new(Txid::all_zeros(), 0),
+                    "prevout": { "value": 50_000, "script_pubkey": change_address.script_pubkey() },
+                    "confirmed": true,
+                }],
+                "selection": { "manual": [OutPoint::new(Txid::all_zeros(), 0)] },
+                "amount": 30_000,
+                "fee_rate": 1,
+                "change_address": change_address,
+            },
+        });
+        let response: Response = deserialize(&handle_json(&request.to_string())).unwrap();
+        let selected: SelectedCoins = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(selected.inputs.len(), 1);
+        assert_eq!(
+            selected.change.unwrap().value + selected.fee,
+            Amount::from_sat(20_000)
+        );
+
+        let nsec = SecretNsec::generate();
+        let wallet_address = npub_to_address(&nsec.public_key(), Network::Regtest).unwrap();
+        let response = handle(Request {
+            id: json!(4),
+            method: Method::SweepWallet(SweepWalletParams {
+                coins: vec![Coin {
+                    outpoint: OutPoint::new(Txid::all_zeros(), 1),
+                    prevout: TxOut {
+                        value: Amount::from_sat(50_000),
+                        script_pubkey: wallet_address.script_pubkey(),
+                    },
+                    confirmed: true,
+                }],
+                nsec,
+                destination: change_address.into_unchecked(),
+                fee_rate: 1,
+                lock_time_height: None,
+            }),
+        });
+        let tx: TransactionResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        let tx = parse_tx_hex(&tx.tx_hex).unwrap();
+        assert_eq!(tx.input[0].witness.len(), 1);
+        assert_eq!(tx.output[0].value, Amount::from_sat(50_000 - 111));
+
+        let response: Response = deserialize(&handle_json(r#"{"method":"unknown"}"#)).unwrap();
+        assert_eq!(response.id, Value::Null);
+        assert_eq!(response.error.unwrap().code, 100);
+    }
+
+    #[test]
+    fn address_messages() {
+        let nsec = SecretNsec::generate();
+        let address = npub_to_address(&nsec.public_key(), Network::Regtest).unwrap();
+        let signed: ProofResult = call_ok(Method::SignAddressMessage(SignAddressMessageParams {
+            message: "hello".to_string(),
+            address: address.clone().into_unchecked(),
+            nsec: nsec.duplicate(),
+        }));
+        let verify = |message: &str| {
+            call_ok::<VerifiedResult>(Method::VerifyAddressMessage(VerifyAddressMessageParams {
+                message: message.to_string(),
+                address: address.clone().into_unchecked(),
+                proof: signed.proof.clone(),
+            }))
+            .valid
+        };
+        assert!(verify("hello"));
+        assert!(!verify("goodbye"));
+
+        let signed: SignatureResult =
+            call_ok(Method::SignAddressChallenge(SignAddressChallengeParams {
+                challenge: "escrow 42 payout".to_string(),
+                nsec: nsec.duplicate(),
+            }));
+        let verify = |npub: NostrPublicKey, challenge: &str| {
+            call_ok::<VerifiedResult>(Method::VerifyAddressChallenge(
+                VerifyAddressChallengeParams {
+                    address: address.clone().into_unchecked(),
+                    npub,
+                    challenge: challenge.to_string(),
+                    signature: signed.signature,
+                },
+            ))
+            .valid
+        };
+        assert!(verify(nsec.public_key(), "escrow 42 payout"));
+        assert!(!verify(nsec.public_key(), "escrow 43 payout"));
+        assert!(!verify(
+            SecretNsec::generate().public_key(),
+            "escrow 42 payout"
+        ));
+    }
+
+    #[test]
+    fn key_info() {
+        let nsec = SecretNsec::generate();
+        let npub = nsec.npub();
+        let info = |key: String| {
+            call(Method::KeyInfo(KeyInfoParams {
+                key,
+                network: Network::Regtest,
+            }))
+        };
+        let expected = KeyResult {
+            npub: npub.to_string(),
+            hex: npub.to_hex(),
+            address: npub.address(Network::Regtest).unwrap().into_unchecked(),
+        };
+        let expected = serde_json::to_value(expected).unwrap();
+        assert_eq!(info(npub.to_string()).unwrap(), expected);
+        assert_eq!(info(npub.to_hex()).unwrap(), expected);
+        let bech32 = nsec.with_nostr_secret_key(|secret_key| secret_key.to_bech32().unwrap());
+        assert_eq!(info(bech32).unwrap(), expected);
+        assert!(info("npub1".to_string()).is_err());
+    }
+
+    #[test]
+    fn platform_fee() {
+        let offerer = SecretNsec::generate();
+        let fee = |bps| {
+            call(Method::PlatformFee(PlatformFeeParams {
+                npub: SecretNsec::generate().public_key(),
+                network: Network::Regtest,
+                bps,
+            }))
+            .map(|value| serde_json::from_value::<PlatformFee>(value).unwrap())
+        };
+        let platform_fee = fee(100).unwrap();
+        assert!(fee(0).is_err());
+
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: Offer {
+                platform_fee: Some(platform_fee.clone()),
+                ..offer(offerer.public_key(), None)
+            },
+            nsec: offerer,
+            fiat: None,
+        })));
+        let accept = |platform_fee| {
+            call(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event.clone(),
+                price: None,
+                platform_fee,
+                nsec: SecretNsec::generate(),
+            })))
+        };
+        accept(Some(platform_fee)).unwrap();
+        let error = accept(Some(fee(100).unwrap())).unwrap_err();
+        assert!(error.to_string().contains("does not charge"));
+        // Acceptors outside the platform don't pay its fee either.
+        let error = accept(None).unwrap_err();
+        assert!(error.to_string().contains("does not charge"));
+    }
+
+    #[test]
+    fn negotiate() {
+        let offerer = SecretNsec::generate();
+        let acceptor = SecretNsec::generate();
+
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer,
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor,
+            })));
+        let request = json!({
+            "method": "receive_acceptance",
+            "params": { "session": offered.session, "acceptance_event": accepted.event },
+        });
+        let response: Response = deserialize(&handle_json(&request.to_string())).unwrap();
+        let received: SessionResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(received.session.handshake, accepted.session.handshake);
+        assert_eq!(received.session.history().entries().len(), 1);
+        let filter: FilterResult = call_ok(Method::AcceptanceFilter(SessionIdParams {
+            session_id: received.session.id().unwrap(),
+        }));
+        assert!(filter.filter.match_event(&accepted.event));
+        assert!(received.session.handshake.escrow_address().is_some());
+
+        // Both parties build the same escrow transaction.
+        let escrow_tx = |session: Session| {
+            call_ok::<TransactionResult>(Method::AgreedEscrowTx(Box::new(AgreedEscrowTxParams {
+                session,
+                funding_txid: Txid::all_zeros(),
+                fee: Amount::from_sat(1_000),
+            })))
+        };
+        assert_eq!(
+            escrow_tx(received.session.clone()),
+            escrow_tx(accepted.session)
+        );
+        assert!(
+            call(Method::AgreedEscrowTx(Box::new(AgreedEscrowTxParams {
+                session: offered.session,
+                funding_txid: Txid::all_zeros(),
+                fee: Amount::from_sat(1_000),
+            })))
+            .is_err()
+        );
+
+        // An acceptance is only received once.
+        let request = json!({
+            "method": "receive_acceptance",
+            "params": { "session": received.session, "acceptance_event": accepted.event },
+        });
+        let response: Response = deserialize(&handle_json(&request.to_string())).unwrap();
+        assert!(response.error.is_some());
+    }
+
+    #[test]
+    fn bond_resolution() {
+        let config = EscrowConfig {
+            npub_1: SecretNsec::generate().public_key(),
+            npub_2: SecretNsec::generate().public_key(),
+            npub_arbitrator: Some(SecretNsec::generate().public_key()),
+            timelock_duration: Some(144),
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let terms = BondTerms {
+            price: Amount::from_sat(100_000),
+            buyer_bond: Amount::from_sat(10_000),
+            seller_bond: Amount::from_sat(20_000),
+        };
+        let funding = OutPoint::new(Txid::all_zeros(), 0);
+        let fee = Amount::ZERO;
+        let resolution: TransactionResult =
+            call_ok(Method::BondResolutionTx(Box::new(BondResolutionTxParams {
+                config,
+                terms,
+                outcome: BondOutcome::SellerWins,
+                funding,
+                fee,
+                lock_time_height: None,
+            })));
+        let verify = |tx_hex: &str, outcome: BondOutcome| {
+            call(Method::VerifyBondResolution(Box::new(
+                VerifyBondResolutionParams {
+                    config,
+                    terms,
+                    tx_hex: tx_hex.to_string(),
+                    outcome,
+                    fee,
+                },
+            )))
+        };
+        let payouts: PayoutsResult =
+            serde_json::from_value(verify(&resolution.tx_hex, BondOutcome::SellerWins).unwrap())
+                .unwrap();
+        assert_eq!(payouts.payout_1, Amount::ZERO);
+        assert_eq!(payouts.payout_2, Amount::from_sat(130_000));
+        assert!(verify(&resolution.tx_hex, BondOutcome::Completed).is_err());
+
+        let timeout: TransactionResult =
+            call_ok(Method::BondTimeoutTx(Box::new(BondTimeoutTxParams {
+                config,
+                terms,
+                funding,
+                fee,
+                timeout_height: 1_000,
+            })));
+        assert!(verify(&timeout.tx_hex, BondOutcome::Refunded).is_ok());
+        let tx = parse_tx_hex(&timeout.tx_hex).unwrap();
+        assert_eq!(
+            tx.lock_time,
+            absolute::LockTime::from_height(1_000).unwrap()
+        );
+    }
+
+    #[test]
+    fn offline_signature() {
+        let (nsec_1, nsec_2) = (SecretNsec::generate(), SecretNsec::generate());
+        let config = EscrowConfig {
+            npub_1: nsec_1.public_key(),
+            npub_2: nsec_2.public_key(),
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let amount = Amount::from_sat(101_000);
+        let tx = split_resolution_tx(
+            &config,
+            OutPoint::new(Txid::all_zeros(), 0),
+            amount,
+            Split::Percent(70),
+            Amount::from_sat(1_000),
+            EscrowScript::A,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let prevouts = vec![TxOut {
+            value: amount,
+            script_pubkey: config.address().unwrap().script_pubkey(),
+        }];
+        let bundle: SigningBundle = call_ok(Method::SigningBundle(SigningBundleParams {
+            config,
+            tx_hex: TransactionResult::from(&tx).tx_hex,
+            input_index: 0,
+            prevouts: prevouts.clone(),
+            escrow_script: EscrowScript::A,
+        }));
+        let invariants = SigningInvariants::of_local_tx(&tx, prevouts, vec![None]);
+        let signed: SignatureResult = call_ok(Method::SignBundle(Box::new(SignBundleParams {
+            bundle: bundle.clone(),
+            invariants,
+            arbitration: None,
+            nsec: nsec_2,
+        })));
+        let import = |npub: NostrPublicKey| {
+            call(Method::ImportSignature(Box::new(ImportSignatureParams {
+                bundle: bundle.clone(),
+                npub,
+                signature: signed.signature,
+            })))
+        };
+        let imported: LeafSignatures =
+            serde_json::from_value(import(config.npub_2).unwrap()).unwrap();
+        assert_eq!(imported.txid, tx.compute_txid());
+        assert_eq!(imported.signatures.len(), 1);
+        assert!(import(config.npub_1).is_err());
+    }
+
+    #[test]
+    fn split_resolution() {
+        let config = EscrowConfig {
+            npub_1: SecretNsec::generate().public_key(),
+            npub_2: SecretNsec::generate().public_key(),
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let request = json!({
+            "method": "split_resolution_tx",
+            "params": {
+                "config": config,
+                "funding": OutPoint::new(Txid::all_zeros(), 0),
+                "amount": 101_000,
+                "split": { "percent": 70 },
+                "fee": 1_000,
+                "escrow_script": EscrowScript::A,
+            },
+        });
+        let response: Response = deserialize(&handle_json(&request.to_string())).unwrap();
+        let split: TransactionResult = serde_json::from_value(response.result.unwrap()).unwrap();
+
+        let payouts: PayoutsResult =
+            call_ok(Method::VerifySplitResolution(VerifySplitResolutionParams {
+                config,
+                tx_hex: split.tx_hex.clone(),
+                amount: Amount::from_sat(101_000),
+                fee: Amount::from_sat(1_000),
+            }));
+        assert_eq!(payouts.payout_1, Amount::from_sat(70_000));
+        assert_eq!(payouts.payout_2, Amount::from_sat(30_000));
+        let size: SizeResult = call_ok(Method::EstimateSpend(EstimateSpendParams {
+            config,
+            path: SpendPath::Leaf(EscrowScript::A),
+        }));
+        // The signatures and the leaf are not in the unsigned resolution yet.
+        let unsigned = parse_tx_hex(&split.tx_hex).unwrap();
+        assert!(size.vbytes > unsigned.vsize() as u64);
+        assert!(
+            call(Method::VerifySplitResolution(VerifySplitResolutionParams {
+                config,
+                tx_hex: split.tx_hex,
+                amount: Amount::from_sat(101_000),
+                fee: Amount::from_sat(2_000),
+            }))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn export_escrow_utxo() {
+        let config = EscrowConfig {
+            npub_1: SecretNsec::generate().public_key(),
+            npub_2: SecretNsec::generate().public_key(),
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let prevout = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: config.address().unwrap().script_pubkey(),
+        };
+        let tx = escrow_tx(
+            &config.npub_1,
+            &config.npub_2,
+            None,
+            Amount::from_sat(50_000),
+            Amount::from_sat(49_000),
+            Txid::all_zeros(),
+            Amount::from_sat(1_000),
+            config.network,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let exported: EscrowUtxoResult =
+            call_ok(Method::ExportEscrowUtxo(Box::new(ExportEscrowUtxoParams {
+                config,
+                tx_hex: consensus::serialize(&tx).to_lower_hex_string(),
+                input_index: 0,
+                prevouts: vec![prevout.clone()],
+                origins: vec![KeyOrigin {
+                    npub: config.npub_1,
+                    fingerprint: Fingerprint::from([1, 2, 3, 4]),
+                    account: 0,
+                }],
+            })));
+        assert!(exported.descriptor.starts_with("tr("));
+        assert_eq!(exported.prevout, prevout);
+        let psbt = exported.psbt.parse::<Psbt>().unwrap();
+        assert_eq!(psbt.inputs[0].tap_key_origins.len(), 2);
+        assert!(!exported.bbqr.is_empty());
+    }
+
+    #[test]
+    fn sweep_expired_escrows() {
+        let participant = SecretNsec::generate();
+        let arbitrator = SecretNsec::generate();
+        let escrow = ExpiredEscrow {
+            npub_1: participant.public_key(),
+            npub_2: SecretNsec::generate().public_key(),
+            npub_arbitrator: arbitrator.public_key(),
+            timelock_duration: 144,
+            outpoint: OutPoint::new(Txid::all_zeros(), 0),
+            amount: Amount::from_sat(100_000),
+            escrow_script: EscrowScript::B,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let destination = npub_to_address(&escrow.npub_arbitrator, Network::Regtest).unwrap();
+        let swept: FundedTxResult =
+            call_ok(Method::SweepExpiredEscrows(SweepExpiredEscrowsParams {
+                escrows: vec![escrow.clone()],
+                destination: destination.into_unchecked(),
+                fee_rate: 2,
+            }));
+        let tx = parse_tx_hex(&swept.tx_hex).unwrap();
+        assert_eq!(tx.input[0].sequence, Sequence::from_consensus(144));
+        assert_eq!(swept.prevouts, vec![escrow.prevout().unwrap()]);
+
+        // The participant and the arbitrator both sign the leaf of every swept escrow.
+        let invariants = SigningInvariants::of_local_tx(&tx, swept.prevouts, vec![Some(144)]);
+        let sign = |nsec: &SecretNsec| {
+            call_ok::<SweepSignaturesResult>(Method::SignSweep(Box::new(SignSweepParams {
+                tx_hex: swept.tx_hex.clone(),
+                escrows: vec![escrow.clone()],
+                invariants: invariants.clone(),
+                nsec: nsec.duplicate(),
+            })))
+            .signatures
+        };
+        let combine = |arbitrator_signatures| {
+            call(Method::CombineSweepSignatures(Box::new(
+                CombineSweepSignaturesParams {
+                    tx_hex: swept.tx_hex.clone(),
+                    escrows: vec![escrow.clone()],
+                    participant_signatures: sign(&participant),
+                    arbitrator_signatures,
+                },
+            )))
+            .map(|value| serde_json::from_value::<TransactionResult>(value).unwrap())
+        };
+        let signed = combine(sign(&arbitrator)).unwrap();
+        assert_eq!(signed.txid, swept.txid);
+        assert_eq!(
+            parse_tx_hex(&signed.tx_hex).unwrap().input[0].witness.len(),
+            4
+        );
+        assert!(combine(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn receive_signatures() {
+        let offerer = SecretNsec::generate();
+        let acceptor = SecretNsec::generate();
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer.duplicate(),
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor.duplicate(),
+            })));
+        let received: SessionResult = call_ok(Method::ReceiveAcceptance(Box::new(
+            ReceiveAcceptanceParams {
+                session: offered.session,
+                acceptance_event: accepted.event,
+            },
+        )));
+
+        // The acceptor signs the local resolution, bound to the session.
+        let mut acceptor_session = accepted.session;
+        let context = acceptor_session.escrow_context().unwrap();
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(109_000),
+                script_pubkey: npub_to_address(&offerer.public_key(), Network::Regtest)
+                    .unwrap()
+                    .script_pubkey(),
+            }],
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(110_000),
+            script_pubkey: context.address().script_pubkey(),
+        }];
+        let invariants = SigningInvariants::of_local_tx(&tx, prevouts.clone(), vec![None]);
+        acceptor_session
+            .sign(&tx, 0, EscrowScript::A, &invariants, &acceptor, 6)
+            .unwrap();
+        let signatures = acceptor_session.signatures.clone();
+
+        let receive = |session: Session, signatures: Vec<LeafSignatures>, tx: &Transaction| {
+            call(Method::ReceiveSignatures(Box::new(
+                ReceiveSignaturesParams {
+                    session,
+                    signatures,
+                    events: Vec::new(),
+                    tx_hex: consensus::encode::serialize_hex(tx),
+                    prevouts: prevouts.clone(),
+                },
+            )))
+            .map(|value| serde_json::from_value::<ReceivedSignaturesResult>(value).unwrap())
+        };
+        // They are sent as an event tagging the other signer.
+        let sent: EventResult = call_ok(Method::SignaturesEvent(Box::new(SignaturesEventParams {
+            session: acceptor_session,
+            signatures: signatures[0].clone(),
+            nsec: acceptor.duplicate(),
+        })));
+        assert_eq!(
+            sent.event.tags.public_keys().collect::<Vec<_>>(),
+            [&offerer.public_key()]
+        );
+        let first: ReceivedSignaturesResult = call_ok(Method::ReceiveSignatures(Box::new(
+            ReceiveSignaturesParams {
+                session: received.session,
+                signatures: Vec::new(),
+                events: vec![sent.event],
+                tx_hex: consensus::encode::serialize_hex(&tx),
+                prevouts: prevouts.clone(),
+            },
+        )));
+        assert!(first.added);
+        // Delivered again, they add nothing.
+        let again = receive(first.session.clone(), signatures.clone(), &tx).unwrap();
+        assert!(!again.added);
+        assert_eq!(again.session, first.session);
+        // Signatures of another transaction than the local one are rejected.
+        let other = Transaction {
+            lock_time: absolute::LockTime::from_height(1).unwrap(),
+            ..tx
+        };
+        assert!(receive(first.session, signatures, &other).is_err());
+    }
+
+    #[test]
+    fn lightning_settlement() {
+        let provider = SecretNsec::generate();
+        let participant = SecretNsec::generate();
+        let htlc = SwapHtlc {
+            payment_hash: bitcoin::hashes::sha256::Hash::hash(&[42; 32]),
+            claim_key: provider.x_only_public_key(),
+            refund_npub: participant.public_key(),
+            timeout: absolute::LockTime::from_height(1_000).unwrap(),
+            lockup_amount: Amount::from_sat(2_600),
+            network: Network::Regtest,
+        };
+        let payout = npub_to_address(&participant.public_key(), Network::Regtest).unwrap();
+        let resolution = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: htlc.lockup_amount,
+                script_pubkey: payout.script_pubkey(),
+            }],
+        };
+        let verify = |tx_hex: &str| {
+            call(Method::VerifySwapPayout(Box::new(VerifySwapPayoutParams {
+                tx_hex: tx_hex.to_string(),
+                htlc: htlc.clone(),
+            })))
+            .map(|value| serde_json::from_value::<SwapHtlcResult>(value).unwrap())
+        };
+        assert!(verify(&consensus::encode::serialize_hex(&resolution)).is_err());
+
+        let settled: TransactionResult =
+            call_ok(Method::SettlePayout(Box::new(SettlePayoutParams {
+                tx_hex: consensus::encode::serialize_hex(&resolution),
+                settlement: Settlement::Lightning(htlc.clone()),
+            })));
+        let verified = verify(&settled.tx_hex).unwrap();
+        assert_eq!(verified.htlc, htlc);
+        let address = verified.address.assume_checked();
+        assert_eq!(
+            parse_tx_hex(&settled.tx_hex).unwrap().output[0].script_pubkey,
+            address.script_pubkey()
+        );
+
+        // Invoices are parsed before the swap is built.
+        assert!(
+            call(Method::SwapHtlc(Box::new(SwapHtlcParams {
+                invoice: "lnbcrt25u1invalid".to_string(),
+                claim_key: htlc.claim_key,
+                refund_npub: htlc.refund_npub,
+                timeout_height: 1_000,
+                lockup_amount: htlc.lockup_amount,
+                lockup_address: address.to_string(),
+                network: Network::Regtest,
+            })))
+            .is_err()
+        );
+
+        // Only the participant takes the unclaimed payout back.
+        let refund = |nsec: &SecretNsec| {
+            call(Method::HtlcRefund(Box::new(HtlcRefundParams {
+                htlc: htlc.clone(),
+                outpoint: OutPoint::new(parse_tx_hex(&settled.tx_hex).unwrap().compute_txid(), 0),
+                destination: payout.as_unchecked().clone(),
+                fee: Amount::from_sat(200),
+                nsec: nsec.duplicate(),
+            })))
+            .map(|value| serde_json::from_value::<TransactionResult>(value).unwrap())
+        };
+        assert!(refund(&provider).is_err());
+        let refunded = parse_tx_hex(&refund(&participant).unwrap().tx_hex).unwrap();
+        assert_eq!(refunded.lock_time, htlc.timeout);
+        assert_eq!(refunded.output[0].value, Amount::from_sat(2_400));
+        assert_eq!(refunded.input[0].witness.len(), 3);
+    }
+
+    #[test]
+    fn oracle_escrow() {
+        let buyer = SecretNsec::generate();
+        let seller = SecretNsec::generate();
+        let oracle = SecretNsec::generate();
+        let params = OracleEscrowParams {
+            npub_1: buyer.public_key(),
+            npub_2: seller.public_key(),
+            oracle: oracle.x_only_public_key(),
+            nonce: SecretNsec::generate().x_only_public_key(),
+            event_id: "BR123456789".to_string(),
+            outcomes: vec!["delivered".to_string(), "returned".to_string()],
+            winners: vec![seller.public_key(), buyer.public_key()],
+            refund: buyer.public_key(),
+            refund_timelock: 144,
+            network: Network::Regtest,
+        };
+        assert!(
+            call(Method::OracleEscrow(Box::new(OracleEscrowParams {
+                outcomes: vec!["delivered".to_string(), "delivered".to_string()],
+                ..params.clone()
+            })))
+            .is_err()
+        );
+        let created: OracleEscrowResult = call_ok(Method::OracleEscrow(Box::new(params)));
+        assert_eq!(
+            created.address.assume_checked(),
+            created.escrow.address().unwrap()
+        );
+
+        // Without the oracle's attestation, the winner can't claim.
+        let funding = OutPoint::new(Txid::all_zeros(), 0);
+        let forged = OracleAttestation {
+            event_id: "BR123456789".to_string(),
+            outcome: "delivered".to_string(),
+            signature: schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+        };
+        assert!(
+            call(Method::OracleResolution(Box::new(OracleResolutionParams {
+                escrow: created.escrow.clone(),
+                funding,
+                amount: Amount::from_sat(100_000),
+                fee: Amount::from_sat(500),
+                attestation: forged,
+                nsec: seller.duplicate(),
+            })))
+            .is_err()
+        );
+
+        // Only the buyer takes it back, once the refund timelock expired.
+        let refund = |nsec: &SecretNsec| {
+            call(Method::OracleRefund(Box::new(OracleRefundParams {
+                escrow: created.escrow.clone(),
+                funding,
+                amount: Amount::from_sat(100_000),
+                fee: Amount::from_sat(500),
+                nsec: nsec.duplicate(),
+            })))
+            .map(|value| serde_json::from_value::<TransactionResult>(value).unwrap())
+        };
+        assert!(refund(&seller).is_err());
+        let refunded = parse_tx_hex(&refund(&buyer).unwrap().tx_hex).unwrap();
+        assert_eq!(refunded.input[0].sequence, Sequence::from_consensus(144));
+        assert_eq!(refunded.output[0].value, Amount::from_sat(99_500));
+        assert_eq!(refunded.input[0].witness.len(), 3);
+    }
+
+    #[test]
+    fn policy_escrow() {
+        let buyer = SecretNsec::generate();
+        let seller = SecretNsec::generate();
+        let (npub_1, npub_2) = (
+            buyer.public_key().to_bech32().unwrap(),
+            seller.public_key().to_bech32().unwrap(),
+        );
+        let policy = format!("or(and(pk({npub_1}),pk({npub_2})),and(pk({npub_1}),after(900000)))");
+        let compiled: PolicyResult = call_ok(Method::CompilePolicy(PolicyParams {
+            policy: policy.clone(),
+            network: Network::Regtest,
+        }));
+        assert_eq!(compiled.leaves.len(), 2);
+        let cooperative = &compiled.leaves[0];
+        assert_eq!(
+            cooperative.signers,
+            vec![buyer.public_key(), seller.public_key()]
+        );
+        assert_eq!(
+            compiled.leaves[1].lock_time,
+            absolute::LockTime::from_height(900_000).unwrap()
+        );
+
+        let prevout = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: compiled.address.assume_checked_ref().script_pubkey(),
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: cooperative.lock_time,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                sequence: cooperative.sequence,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: npub_to_address(&buyer.public_key(), Network::Regtest)
+                    .unwrap()
+                    .script_pubkey(),
+            }],
+        };
+        let invariants = SigningInvariants::of_local_tx(&tx, vec![prevout.clone()], vec![None]);
+        let sign = |nsec: &SecretNsec, script: &ScriptBuf| {
+            call(Method::SignPolicyLeaf(Box::new(SignPolicyLeafParams {
+                policy: policy.clone(),
+                network: Network::Regtest,
+                tx_hex: consensus::encode::serialize_hex(&tx),
+                input_index: 0,
+                script: script.clone(),
+                invariants: invariants.clone(),
+                nsec: nsec.duplicate(),
+            })))
+            .map(|value| serde_json::from_value::<SignatureResult>(value).unwrap())
+        };
+        // Only the leaf's signers sign it, and only leaves of the policy.
+        assert!(sign(&SecretNsec::generate(), &cooperative.script).is_err());
+        assert!(sign(&buyer, &ScriptBuf::new()).is_err());
+        let signatures: Vec<SignerSignature> = [&buyer, &seller]
+            .into_iter()
+            .map(|nsec| SignerSignature {
+                npub: nsec.public_key(),
+                signature: sign(nsec, &cooperative.script).unwrap().signature,
+            })
+            .collect();
+
+        let combine = |signatures: Vec<SignerSignature>| {
+            call(Method::CombinePolicySignatures(Box::new(
+                CombinePolicySignaturesParams {
+                    policy: policy.clone(),
+                    network: Network::Regtest,
+                    tx_hex: consensus::encode::serialize_hex(&tx),
+                    input_index: 0,
+                    prevouts: vec![prevout.clone()],
+                    script: cooperative.script.clone(),
+                    signatures,
+                },
+            )))
+            .map(|value| serde_json::from_value::<TransactionResult>(value).unwrap())
+        };
+        assert!(combine(signatures[..1].to_vec()).is_err());
+        let signed = parse_tx_hex(&combine(signatures).unwrap().tx_hex).unwrap();
+        assert_eq!(signed.input[0].witness.len(), 4);
+    }
+
+    #[test]
+    fn adaptor_signatures() {
+        let buyer = SecretNsec::generate();
+        let seller = SecretNsec::generate();
+        let config = EscrowConfig {
+            npub_1: buyer.public_key(),
+            npub_2: seller.public_key(),
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: config.address().unwrap().script_pubkey(),
+            }],
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: config.address().unwrap().script_pubkey(),
+        }];
+
+        // The buyer shares the point of the delivery code, surrounding whitespace ignored.
+        let point: AdaptorPointResult = call_ok(Method::AdaptorPoint(AdaptorPointParams {
+            secret: "ABCD-1234".to_string(),
+        }));
+        let typed: AdaptorPointResult = call_ok(Method::AdaptorPoint(AdaptorPointParams {
+            secret: " ABCD-1234\n".to_string(),
+        }));
+        assert_eq!(typed, point);
+
+        let signed: AdaptorSignatureResult =
+            call_ok(Method::AdaptorSignLeaf(Box::new(AdaptorSignLeafParams {
+                config,
+                tx_hex: consensus::encode::serialize_hex(&tx),
+                input_index: 0,
+                prevouts: prevouts.clone(),
+                escrow_script: EscrowScript::A,
+                adaptor: point.point.clone(),
+                nsec: seller.duplicate(),
+            })));
+        let verify = |npub: NostrPublicKey| {
+            call(Method::VerifyAdaptorSignature(Box::new(
+                VerifyAdaptorSignatureParams {
+                    config,
+                    tx_hex: consensus::encode::serialize_hex(&tx),
+                    input_index: 0,
+                    prevouts: prevouts.clone(),
+                    escrow_script: EscrowScript::A,
+                    adaptor_signature: signed.adaptor_signature.clone(),
+                    npub,
+                },
+            )))
+            .map(|value| serde_json::from_value::<AdaptorPointResult>(value).unwrap())
+        };
+        assert_eq!(verify(seller.public_key()).unwrap(), point);
+        assert!(verify(buyer.public_key()).is_err());
+
+        // Only the delivery code completes the signature, which then reveals its secret.
+        let complete = |secret: &str| {
+            call(Method::CompleteAdaptorSignature(
+                CompleteAdaptorSignatureParams {
+                    adaptor_signature: signed.adaptor_signature.clone(),
+                    secret: secret.to_string(),
+                },
+            ))
+            .map(|value| serde_json::from_value::<SignatureResult>(value).unwrap())
+        };
+        assert!(complete("ABCD-1235").is_err());
+        let completed = complete("ABCD-1234").unwrap();
+        let extracted: AdaptorSecretResult =
+            call_ok(Method::ExtractAdaptorSecret(ExtractAdaptorSecretParams {
+                adaptor_signature: signed.adaptor_signature,
+                signature: completed.signature,
+            }));
+        let secret: SecretNsec = extracted.nsec.parse().unwrap();
+        assert_eq!(
+            secret.x_only_public_key(),
+            point
+                .point
+                .parse::<AdaptorPoint>()
+                .unwrap()
+                .x_only_public_key()
+        );
+    }
+
+    #[test]
+    fn delivery_receipts() {
+        let offerer = SecretNsec::generate();
+        let acceptor = SecretNsec::generate();
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer.duplicate(),
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor.duplicate(),
+            })));
+        let acceptance = accepted.event;
+        let received: SessionResult = call_ok(Method::ReceiveAcceptance(Box::new(
+            ReceiveAcceptanceParams {
+                session: offered.session,
+                acceptance_event: acceptance.clone(),
+            },
+        )));
+
+        // The acceptance is published gift wrapped until the offerer acknowledges it.
+        let sent: SessionResult = call_ok(Method::SendMessage(Box::new(SendMessageParams {
+            session: accepted.session,
+            event: acceptance.clone(),
+        })));
+        let due: DueMessagesResult = call_ok(Method::DueMessages(Box::new(DueMessagesParams {
+            session: sent.session.clone(),
+            nsec: acceptor.duplicate(),
+        })));
+        let [message] = due.messages.as_slice() else {
+            panic!("Expected one message due, got {:?}", due.messages);
+        };
+        assert_eq!(message.event_id, acceptance.id);
+        assert_eq!(message.status, DeliveryStatus::Sent);
+        assert!(
+            message
+                .events
+                .iter()
+                .all(|event| event.kind == Kind::GiftWrap)
+        );
+        let published: SessionResult =
+            call_ok(Method::RecordPublished(Box::new(RecordPublishedParams {
+                session: sent.session,
+                event_id: acceptance.id,
+                published: true,
+            })));
+        assert_eq!(
+            published.session.delivery_status(&acceptance.id),
+            Some(DeliveryStatus::SeenOnRelay)
+        );
+
+        let acknowledged: GiftWrapsResult = call_ok(Method::AcknowledgeMessage(Box::new(
+            AcknowledgeMessageParams {
+                session: received.session,
+                event_id: acceptance.id,
+                author: acceptor.public_key(),
+                nsec: offerer,
+            },
+        )));
+        let filtered: SessionFilterResult =
+            call_ok(Method::ReceiptFilter(Box::new(ReceiptFilterParams {
+                session: published.session,
+                npub: acceptor.public_key(),
+            })));
+        let receive = |session: Session| {
+            call_ok::<ReceiptsResult>(Method::ReceiveReceipts(Box::new(ReceiveReceiptsParams {
+                session,
+                gift_wraps: acknowledged.gift_wraps.clone(),
+                nsec: acceptor.duplicate(),
+            })))
+        };
+        let receipts = receive(filtered.session);
+        assert_eq!(receipts.acked, [acceptance.id]);
+        assert_eq!(
+            receipts.session.delivery_status(&acceptance.id),
+            Some(DeliveryStatus::Acked)
+        );
+        // Receipts received again acknowledge nothing.
+        assert!(receive(receipts.session).acked.is_empty());
+    }
+
+    #[test]
+    fn chain_escrow() {
+        let offerer = SecretNsec::generate();
+        let acceptor = SecretNsec::generate();
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer.duplicate(),
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor.duplicate(),
+            })));
+        let received: SessionResult = call_ok(Method::ReceiveAcceptance(Box::new(
+            ReceiveAcceptanceParams {
+                session: offered.session,
+                acceptance_event: accepted.event,
+            },
+        )));
+        let arbitrator = SecretNsec::generate().public_key();
+        let chain = |session: Session, nsec: SecretNsec, signatures: Option<LeafSignatures>| {
+            call(Method::ChainEscrowTx(Box::new(ChainEscrowTxParams {
+                session,
+                nsec,
+                funding: OutPoint::new(Txid::all_zeros(), 0),
+                amount: Amount::from_sat(110_000),
+                dispute: Some((arbitrator, 144)),
+                template: None,
+                fee: Amount::from_sat(1_000),
+                signatures,
+                expires_at: None,
+            })))
+            .and_then(|value| {
+                serde_json::from_value::<ChainResult>(value)
+                    .map_err(|e| Error::Protocol(e.to_string()))
+            })
+        };
+        let offerer_signed = chain(received.session.clone(), offerer.duplicate(), None).unwrap();
+        assert!(!offerer_signed.complete);
+        assert_eq!(offerer_signed.offer.arbitrator, Some(arbitrator));
+        assert_eq!(
+            offerer_signed.offer.amount_buyer + offerer_signed.offer.amount_seller,
+            Amount::from_sat(109_000)
+        );
+
+        // The acceptor's signature completes the chain transaction.
+        let signed = chain(
+            accepted.session,
+            acceptor,
+            Some(offerer_signed.signatures.clone()),
+        )
+        .unwrap();
+        assert!(signed.complete);
+        assert_eq!(signed.txid, offerer_signed.txid);
+        let tx = parse_tx_hex(&signed.tx_hex).unwrap();
+        assert_eq!(tx.input[0].witness.len(), 4);
+
+        // Signatures over another transaction are rejected.
+        let mut forged = offerer_signed.signatures;
+        forged.txid = Txid::all_zeros();
+        assert!(chain(received.session, offerer, Some(forged)).is_err());
+    }
+
+    #[test]
+    fn rotate_key() {
+        let offerer = SecretNsec::generate();
+        let acceptor = SecretNsec::generate();
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer.duplicate(),
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor,
+            })));
+        let received: SessionResult = call_ok(Method::ReceiveAcceptance(Box::new(
+            ReceiveAcceptanceParams {
+                session: offered.session,
+                acceptance_event: accepted.event,
+            },
+        )));
+        let funding = OutPoint::new(Txid::all_zeros(), 0);
+        let amount = Amount::from_sat(110_000);
+
+        let new_nsec = SecretNsec::generate();
+        let new_npub = new_nsec.public_key();
+        let rotated: RotationResult = call_ok(Method::RotateKey(Box::new(RotateKeyParams {
+            session: received.session,
+            nsec: offerer,
+            new_nsec,
+            funding,
+            amount,
+            fee: Amount::from_sat(1_000),
+            funding_height: None,
+            tip_height: None,
+        })));
+        assert!([rotated.config.npub_1, rotated.config.npub_2].contains(&new_npub));
+        let event = rotated.event.unwrap();
+        let filter: FilterResult = call_ok(Method::RotationFilter(SessionIdParams {
+            session_id: rotated.session.id().unwrap(),
+        }));
+        assert!(filter.filter.match_event(&event));
+
+        // The counterparty migrates the escrow to the same rotated escrow.
+        let params = ReceiveRotationParams {
+            session: accepted.session,
+            event,
+            funding,
+            amount,
+        };
+        let received: RotationResult = call_ok(Method::ReceiveRotation(Box::new(params.clone())));
+        assert_eq!(received.config, rotated.config);
+        assert_eq!(received.migration, rotated.migration);
+        assert_eq!(received.session.rotations, rotated.session.rotations);
+        // A rotation is only recorded once.
+        assert!(
+            call(Method::ReceiveRotation(Box::new(ReceiveRotationParams {
+                session: received.session,
+                ..params
+            })))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn cancel_session() {
+        let offerer = SecretNsec::generate();
+        let acceptor = SecretNsec::generate();
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer.duplicate(),
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor.duplicate(),
+            })));
+        let received: SessionResult = call_ok(Method::ReceiveAcceptance(Box::new(
+            ReceiveAcceptanceParams {
+                session: offered.session,
+                acceptance_event: accepted.event,
+            },
+        )));
+
+        // The offerer cancels first, to themselves and the acceptor.
+        let cancelled: CancelResult =
+            call_ok(Method::CancelSession(Box::new(CancelSessionParams {
+                session: received.session,
+                nsec: offerer,
+                funding_txid: None,
+                reason: "Changed my mind".to_string(),
+                funding_confirmed: false,
+            })));
+        assert!(!cancelled.cancelled);
+        assert_eq!(cancelled.gift_wraps.len(), 2);
+
+        let received: CancellationsResult = call_ok(Method::ReceiveCancellations(Box::new(
+            ReceiveCancellationsParams {
+                session: accepted.session,
+                gift_wraps: cancelled.gift_wraps.clone(),
+                nsec: acceptor.duplicate(),
+            },
+        )));
+        assert_eq!(received.cancellations.len(), 1);
+        assert!(!received.cancelled);
+
+        // Confirmed escrows can only be resolved.
+        let cancel = |funding_confirmed| {
+            call(Method::CancelSession(Box::new(CancelSessionParams {
+                session: received.session.clone(),
+                nsec: acceptor.duplicate(),
+                funding_txid: None,
+                reason: String::new(),
+                funding_confirmed,
+            })))
+            .map(|value| serde_json::from_value::<CancelResult>(value).unwrap())
+        };
+        assert!(cancel(true).is_err());
+        assert!(cancel(false).unwrap().cancelled);
+    }
+
+    #[test]
+    fn cancel_funding() {
+        let refund_address =
+            npub_to_address(&SecretNsec::generate().public_key(), Network::Regtest).unwrap();
+        let funding_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(110_000),
+                script_pubkey: refund_address.script_pubkey(),
+            }],
+        };
+        let replaced: PsbtResult = call_ok(Method::CancelFunding(CancelFundingParams {
+            tx_hex: consensus::serialize(&funding_tx).to_lower_hex_string(),
+            prevouts: vec![TxOut {
+                value: Amount::from_sat(120_000),
+                script_pubkey: refund_address.script_pubkey(),
+            }],
+            refund_address: refund_address.into_unchecked(),
+            fee_rate: 2,
+            fee_rates: FeeRateLimits::default(),
+        }));
+        let psbt = replaced.psbt.parse::<Psbt>().unwrap();
+        assert!(psbt.unsigned_tx.is_explicitly_rbf());
+        assert_eq!(
+            psbt.unsigned_tx.input[0].previous_output,
+            funding_tx.input[0].previous_output
+        );
+    }
+
+    #[test]
+    fn fund_agreed_escrow() {
+        let offerer = SecretNsec::generate();
+        let excess_to = npub_to_address(&offerer.public_key(), Network::Regtest).unwrap();
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer,
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: SecretNsec::generate(),
+            })));
+        let mut session = accepted.session;
+        let escrow_address = session.handshake.escrow_address().unwrap().clone();
+        let funding_tx = |amount: u64| Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(amount),
+                script_pubkey: escrow_address.script_pubkey(),
+            }],
+        };
+        let top_up = |session: Session| {
+            call_ok::<TopUpResult>(Method::TopUpRequest(Box::new(TopUpRequestParams {
+                session,
+            })))
+            .uri
+        };
+        let unsigned: TransactionResult =
+            call_ok(Method::AgreedEscrowTx(Box::new(AgreedEscrowTxParams {
+                session: session.clone(),
+                funding_txid: Txid::all_zeros(),
+                fee: Amount::from_sat(1_000),
+            })));
+        let fund = |session: Session| {
+            call(Method::FundEscrowTx(Box::new(FundEscrowTxParams {
+                session,
+                tx_hex: unsigned.tx_hex.clone(),
+                excess_to: excess_to.as_unchecked().clone(),
+            })))
+            .map(|value| serde_json::from_value::<FundedTxResult>(value).unwrap())
+        };
+
+        // Underfunded escrows are topped up, not spent.
+        assert_eq!(top_up(session.clone()), None);
+        session.record_funding(&funding_tx(100_000)).unwrap();
+        let request = top_up(session.clone()).unwrap().parse::<PaymentRequest>();
+        assert_eq!(request.unwrap().amount, Some(Amount::from_sat(10_000)));
+        assert!(fund(session.clone()).is_err());
+
+        // Overfunded escrows refund the excess.
+        session.record_funding(&funding_tx(15_000)).unwrap();
+        assert_eq!(top_up(session.clone()), None);
+        let funded = fund(session).unwrap();
+        let tx = parse_tx_hex(&funded.tx_hex).unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(funded.prevouts.len(), 2);
+        assert!(
+            tx.output
+                .iter()
+                .any(|output| output.script_pubkey == excess_to.script_pubkey())
+        );
+    }
+
+    #[test]
+    fn fiat_offer() {
+        let (offerer, acceptor) = (SecretNsec::generate(), SecretNsec::generate());
+        let price = Price {
+            currency: Currency::Brl,
+            cents_per_btc: 50_000_000,
+            provider: PriceProvider::Mempool,
+            time: Timestamp::now(),
+        };
+        let fiat = |amount_buyer: &str| FiatOfferParams {
+            currency: Currency::Brl,
+            amount_buyer: amount_buyer.to_string(),
+            amount_seller: "50".to_string(),
+            price,
+        };
+        let offer_in = |fiat| {
+            call(Method::Offer(Box::new(OfferParams {
+                offer: offer(offerer.public_key(), None),
+                nsec: offerer.duplicate(),
+                fiat: Some(fiat),
+            })))
+        };
+        assert!(offer_in(fiat("4.555")).is_err());
+        assert!(
+            offer_in(FiatOfferParams {
+                currency: Currency::Usd,
+                ..fiat("500")
+            })
+            .is_err()
+        );
+
+        // The fiat amounts are quoted in sats, and locked at the acceptance's price.
+        let offered: NegotiationResult =
+            serde_json::from_value(offer_in(fiat("500")).unwrap()).unwrap();
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: Some(price),
+                platform_fee: None,
+                nsec: acceptor,
+            })));
+        let top_up: TopUpResult = call_ok(Method::TopUpRequest(Box::new(TopUpRequestParams {
+            session: accepted.session.clone(),
+        })));
+        assert_eq!(top_up.missing, None);
+        let mut session = accepted.session;
+        session
+            .record_funding(&Transaction {
+                version: transaction::Version::TWO,
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![],
+                output: vec![TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: session.handshake.escrow_address().unwrap().script_pubkey(),
+                }],
+            })
+            .unwrap();
+        let top_up: TopUpResult = call_ok(Method::TopUpRequest(Box::new(TopUpRequestParams {
+            session,
+        })));
+        assert_eq!(top_up.missing.unwrap(), "0.001 BTC (R$ 500.00)");
+    }
+
+    #[test]
+    fn payjoin_funding() {
+        let (offerer, acceptor) = (SecretNsec::generate(), SecretNsec::generate());
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer.duplicate(),
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor.duplicate(),
+            })));
+        let session = accepted.session;
+        let requested: PaymentRequestResult =
+            call_ok(Method::PayjoinRequest(Box::new(PayjoinRequestParams {
+                session: session.clone(),
+                endpoint: "https://seller.example/pj".to_string(),
+            })));
+        let request = requested.uri.parse::<PaymentRequest>().unwrap();
+        assert_eq!(
+            request.payjoin.as_deref(),
+            Some("https://seller.example/pj")
+        );
+
+        // The buyer's original pays the request, as the seller's receiver expects it.
+        let address = npub_to_address(&acceptor.public_key(), Network::Regtest).unwrap();
+        let coins = SelectedCoins {
+            inputs: vec![FundingInput {
+                outpoint: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                prevout: TxOut {
+                    value: request.amount.unwrap() + Amount::from_sat(50_000),
+                    script_pubkey: address.script_pubkey(),
+                },
+            }],
+            change: Some(TxOut {
+                value: Amount::from_sat(49_000),
+                script_pubkey: address.script_pubkey(),
+            }),
+            fee: Amount::from_sat(1_000),
+        };
+        let sign = |uri: &str| {
+            call(Method::SignPayjoinOriginal(Box::new(
+                SignPayjoinOriginalParams {
+                    uri: uri.to_string(),
+                    network: Network::Regtest,
+                    coins: coins.clone(),
+                    nsec: acceptor.duplicate(),
+                    lock_time_height: None,
+                },
+            )))
+        };
+        let without_payjoin = PaymentRequest {
+            payjoin: None,
+            ..request.clone()
+        };
+        assert!(sign(&without_payjoin.to_string()).is_err());
+        let original: PsbtResult = serde_json::from_value(sign(&requested.uri).unwrap()).unwrap();
+        let (amount_buyer, _) = session.handshake.amounts().unwrap();
+        let escrow_address = session.handshake.escrow_address().unwrap();
+        assert!(PayjoinReceiver::new(&original.psbt, "v=1", escrow_address, amount_buyer).is_ok());
+    }
+
+    #[test]
+    fn silent_resolution() {
+        let (nsec_1, nsec_2) = (SecretNsec::generate(), SecretNsec::generate());
+        let config = EscrowConfig {
+            npub_1: nsec_1.public_key(),
+            npub_2: nsec_2.public_key(),
+            npub_arbitrator: Some(SecretNsec::generate().public_key()),
+            timelock_duration: Some(144),
+            network: Network::Bitcoin,
+            template: ScriptTemplate::V2,
+        };
+        let split: TransactionResult = call_ok(Method::SplitResolutionTx(Box::new(
+            SplitResolutionTxParams {
+                config,
+                funding: OutPoint::new(Txid::all_zeros(), 0),
+                amount: Amount::from_sat(100_000),
+                split: Split::Percent(30),
+                fee: Amount::from_sat(1_000),
+                escrow_script: EscrowScript::A,
+                lock_time_height: None,
+            },
+        )));
+
+        // Both participants share their part of the secret for the second one's address.
+        let address = "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv";
+        let share = |nsec: &SecretNsec| {
+            call_ok::<EcdhShare>(Method::SilentPaymentShare(SilentPaymentShareParams {
+                config,
+                address: address.to_string(),
+                nsec: nsec.duplicate(),
+            }))
+        };
+        let shares = vec![share(&nsec_1), share(&nsec_2)];
+        let payouts = vec![SilentPayout {
+            npub: config.npub_2,
+            address: address.to_string(),
+        }];
+        let silent: TransactionResult = call_ok(Method::SilentResolutionTx(Box::new(
+            SilentResolutionTxParams {
+                config,
+                tx_hex: split.tx_hex.clone(),
+                payouts: payouts.clone(),
+                shares: shares.clone(),
+            },
+        )));
+        assert_ne!(silent.txid, split.txid);
+        let verify = |payouts: Vec<SilentPayout>, shares: Vec<EcdhShare>| {
+            call(Method::VerifySilentResolution(Box::new(
+                VerifySilentResolutionParams {
+                    config,
+                    tx_hex: silent.tx_hex.clone(),
+                    amount: Amount::from_sat(100_000),
+                    fee: Amount::from_sat(1_000),
+                    payouts,
+                    shares,
+                },
+            )))
+        };
+        let verified: PayoutsResult =
+            serde_json::from_value(verify(payouts.clone(), shares.clone()).unwrap()).unwrap();
+        assert_eq!(verified.payout_1, Amount::from_sat(29_700));
+        assert_eq!(verified.payout_2, Amount::from_sat(69_300));
+        assert!(verify(payouts.clone(), shares[..1].to_vec()).is_err());
+        assert!(verify(Vec::new(), shares).is_err());
+    }
+
+    #[test]
+    fn reputation() {
+        let (offerer, acceptor) = (SecretNsec::generate(), SecretNsec::generate());
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: offer(offerer.public_key(), None),
+            nsec: offerer.duplicate(),
+            fiat: None,
+        })));
+        let feedback = |session: Session, rating: u8| {
+            call(Method::Feedback(Box::new(FeedbackParams {
+                session,
+                outcome: Outcome::Completed,
+                rating,
+                comment: "smooth".to_string(),
+                nsec: acceptor.duplicate(),
+            })))
+        };
+        // Only agreed escrows are rated.
+        assert!(feedback(offered.session, 5).is_err());
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor.duplicate(),
+            })));
+        assert!(feedback(accepted.session.clone(), 6).is_err());
+        let rated: EventResult =
+            serde_json::from_value(feedback(accepted.session, 4).unwrap()).unwrap();
+
+        let filter: FilterResult = call_ok(Method::FeedbackFilter(NpubParams {
+            npub: offerer.public_key(),
+        }));
+        assert!(filter.filter.match_event(&rated.event));
+        let reputation = |npub: NostrPublicKey| {
+            call_ok::<ReputationResult>(Method::Reputation(ReputationParams {
+                npub,
+                events: vec![rated.event.clone(), rated.event.clone()],
+            }))
+        };
+        let offerer_reputation = reputation(offerer.public_key());
+        assert_eq!(offerer_reputation.completed, 1);
+        assert_eq!(offerer_reputation.authors, 1);
+        assert_eq!(offerer_reputation.average_rating, Some(4.0));
+        assert!(offerer_reputation.score.is_some());
+        assert_eq!(reputation(acceptor.public_key()).score, None);
+    }
+
+    #[test]
+    fn arbitrator_directory() {
+        let arbitrator = SecretNsec::generate();
+        let ad = |fee_bps: u16| ArbitratorAd {
+            version: ARBITRATOR_AD_VERSION,
+            npub: arbitrator.public_key(),
+            networks: vec![Network::Signet],
+            fee_bps,
+            response_hours: 24,
+            contact: "arbitrator@example.com".to_string(),
+        };
+        let advertise = |ad: ArbitratorAd| {
+            call(Method::AdvertiseArbitrator(Box::new(
+                AdvertiseArbitratorParams {
+                    ad,
+                    nsec: arbitrator.duplicate(),
+                },
+            )))
+        };
+        assert!(advertise(ad(20_000)).is_err());
+        let advertised: EventResult = serde_json::from_value(advertise(ad(100)).unwrap()).unwrap();
+
+        let list = |network: Network| {
+            call_ok::<ArbitratorsResult>(Method::ListArbitrators(ListArbitratorsParams {
+                events: vec![advertised.event.clone()],
+                network: Some(network),
+                max_fee_bps: None,
+                max_response_hours: None,
+                amount: Some(Amount::from_sat(500_000)),
+            }))
+        };
+        let listed = list(Network::Signet);
+        assert!(listed.filter.match_event(&advertised.event));
+        assert_eq!(listed.arbitrators.len(), 1);
+        assert_eq!(listed.arbitrators[0].ad, ad(100));
+        assert_eq!(listed.arbitrators[0].fee, Some(Amount::from_sat(5_000)));
+        assert!(list(Network::Bitcoin).arbitrators.is_empty());
+    }
+
+    #[test]
+    fn cofund_escrow() {
+        let (offerer, acceptor) = (SecretNsec::generate(), SecretNsec::generate());
+        let offered: NegotiationResult = call_ok(Method::Offer(Box::new(OfferParams {
+            offer: Offer {
+                amount_seller: Amount::from_sat(20_000),
+                ..offer(offerer.public_key(), None)
+            },
+            nsec: offerer.duplicate(),
+            fiat: None,
+        })));
+        let accepted: NegotiationResult =
+            call_ok(Method::AcceptOffer(Box::new(AcceptOfferParams {
+                offer_event: offered.event,
+                price: None,
+                platform_fee: None,
+                nsec: acceptor.duplicate(),
+            })));
+        let session = accepted.session;
+        let agreed = session.handshake.negotiated_offer();
+        let total = agreed.amount_buyer + agreed.amount_seller;
+        let contribution = |nsec: &SecretNsec, seed: u8| Contribution {
+            npub: nsec.public_key(),
+            inputs: vec![FundingInput {
+                outpoint: OutPoint::new(Txid::from_byte_array([seed; 32]), 0),
+                prevout: TxOut {
+                    value: total,
+                    script_pubkey: npub_to_address(&nsec.public_key(), Network::Regtest)
+                        .unwrap()
+                        .script_pubkey(),
+                },
+            }],
+            change: None,
+        };
+        let contributions = vec![contribution(&offerer, 1), contribution(&acceptor, 2)];
+        let sign = |nsec: &SecretNsec| {
+            call_ok::<CofundingSignatures>(Method::SignCofunding(Box::new(SignCofundingParams {
+                session: session.clone(),
+                contributions: contributions.clone(),
+                nsec: nsec.duplicate(),
+            })))
+        };
+        let cofund = |signatures: Vec<CofundingSignatures>| {
+            call_ok::<CofundingResult>(Method::CofundEscrowTx(Box::new(CofundEscrowTxParams {
+                session: session.clone(),
+                contributions: contributions.clone(),
+                signatures,
+            })))
+        };
+        let unsigned = cofund(vec![]);
+        assert!(!unsigned.complete);
+        assert_eq!(unsigned.fee, total);
+        assert_eq!(unsigned.prevouts.len(), 2);
+        let signed = cofund(vec![sign(&offerer), sign(&acceptor)]);
+        assert!(signed.complete);
+        assert_eq!(signed.txid, unsigned.txid);
+        let tx = parse_tx_hex(&signed.tx_hex).unwrap();
+        assert!(tx.input.iter().all(|input| input.witness.len() == 1));
+    }
+
+    #[test]
+    fn propose_escrow() {
+        let draft = draft();
+        let propose = |draft: EscrowDraft, platform_fee: Option<PlatformFee>| {
+            call(Method::ProposeEscrow(Box::new(ProposeEscrowParams {
+                draft,
+                platform_fee,
+                lock_time_height: Some(850_000),
+            })))
+            .map(|value| serde_json::from_value::<ProposalResult>(value).unwrap())
+        };
+
+        let proposed = propose(draft.clone(), None).unwrap();
+        assert_eq!(proposed.offer.lock_time_height, Some(850_000));
+        let request = proposed.uri.parse::<PaymentRequest>().unwrap();
+        assert_eq!(request.amount, Some(Amount::from_sat(150_000)));
+
+        let platform_fee: PlatformFee = call_ok(Method::PlatformFee(PlatformFeeParams {
+            npub: SecretNsec::generate().public_key(),
+            network: Network::Signet,
+            bps: 100,
+        }));
+        let hosted = propose(draft.clone(), Some(platform_fee.clone())).unwrap();
+        assert_eq!(hosted.offer.platform_fee, Some(platform_fee));
+
+        // Incomplete drafts name the first step to fix.
+        let invalid = EscrowDraft {
+            amount_seller: "0.00000001".to_string(),
+            ..draft
+        };
+        let error = propose(invalid.clone(), None).unwrap_err();
+        assert_eq!(
+            invalid.first_invalid_step().map(|(step, _)| step),
+            Some(WizardStep::Amounts)
+        );
+        assert!(error.to_string().contains("Invalid Amounts step"));
+    }
+}
@@ -0,0 +1,623 @@
+//! Arbitrator mode: what the app signs when acting as an arbitrator.
+//!
+//! An arbitrator co-signs resolutions built by someone else, so a participant, or anyone
+//! impersonating one, can ask them to sign a transaction paying the escrow elsewhere.
+//! In arbitrator mode, the payout addresses of the participants are registered in a
+//! [`DisputeRecord`] when the dispute is opened, and the arbitrator records their ruling in it.
+//! [`ArbitratorMode::arbitrate`] then only signs resolutions that:
+//!
+//! - spend nothing but the disputed escrow,
+//! - pay nothing but the registered payout addresses and the escrow's [`PlatformFee`],
+//! - split what is left after fees as ruled, so both participants bear the fees in
+//!   proportion to their share,
+//! - and pay a mining fee within the user's [`FeeRateLimits`].
+//!
+//! Every signing path checks [`is_arbitrator`] and signs an arbitrator's key
+//! through an [`Arbitration`], so none of them bypasses the records.
+//!
+//! The records are persisted in [`Storage`] under [`ARBITRATOR_DISPUTES_KEY`].
+
+use bitcoin::{Address, Amount, Network, Transaction, TxOut, address::NetworkUnchecked};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{
+    Event, Timestamp,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use secp256k1::schnorr;
+use serde::{Deserialize, Serialize};
+
+#[cfg(debug_assertions)]
+use crate::logging::session_span;
+#[cfg(feature = "serde-types")]
+use crate::protocol::Session;
+use crate::{
+    decision::{agreed, arbitrate},
+    error::Error,
+    gift_wrap::wrap_to_recipients,
+    invariants::{SigningInvariants, leaf_timelock},
+    platform_fee::PlatformFee,
+    protocol::{Handshake, Role, SessionId},
+    scripts::{EscrowConfig, EscrowScript},
+    secret::SecretNsec,
+    settings::FeeRateLimits,
+    storage::Storage,
+    tx::placeholder_leaf_witness,
+    util::npub_to_address,
+};
+
+/// [`Storage`] key of the disputes of the arbitrator.
+pub(crate) const ARBITRATOR_DISPUTES_KEY: &str = "scrow.arbitrator_disputes";
+
+/// Basis points in a whole.
+const BPS: u64 = 10_000;
+
+/// A participant's registered payout address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Payout {
+    /// Participant's role in the escrow.
+    pub(crate) role: Role,
+    /// Participant's Nostr public key.
+    pub(crate) npub: NostrPublicKey,
+    /// Address the participant is paid to.
+    pub(crate) address: Address<NetworkUnchecked>,
+}
+
+/// What the arbitrator registered about a dispute, and how they ruled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct DisputeRecord {
+    /// Negotiation of the disputed escrow.
+    pub(crate) session_id: SessionId,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+    /// Payout addresses of the participants, buyer first.
+    pub(crate) payouts: Vec<Payout>,
+    /// Platform fee of the escrow, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) platform_fee: Option<PlatformFee>,
+    /// Buyer's share of the escrow after fees, in basis points, once ruled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) buyer_share_bps: Option<u16>,
+}
+
+impl DisputeRecord {
+    /// Opens the record of the dispute of the agreed `handshake`,
+    /// registering the `npub`-derived addresses of the participants.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow is not agreed or has no arbitrator.
+    pub(crate) fn open(handshake: &Handshake) -> Result<Self, Error> {
+        let (offer, acceptor) = agreed(handshake)?;
+        if offer.arbitrator.is_none() {
+            return Err(Error::Protocol("Escrow has no arbitrator".to_string()));
+        }
+        let (npub_buyer, npub_seller) = offer.participants(acceptor);
+        let payouts = [(Role::Buyer, npub_buyer), (Role::Seller, npub_seller)]
+            .into_iter()
+            .map(|(role, npub)| {
+                Ok(Payout {
+                    role,
+                    npub: *npub,
+                    address: npub_to_address(npub, offer.network)?.into_unchecked(),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self {
+            session_id: offer.session_id()?,
+            network: offer.network,
+            payouts,
+            platform_fee: offer.platform_fee.clone(),
+            buyer_share_bps: None,
+        })
+    }
+
+    /// The registered payout [`Address`] of the participant with `role`.
+    pub(crate) fn payout_address(&self, role: Role) -> Result<Address, Error> {
+        let payout = self
+            .payouts
+            .iter()
+            .find(|payout| payout.role == role)
+            .ok_or_else(|| {
+                Error::Protocol(format!("No payout address registered for the {role:?}"))
+            })?;
+        Ok(payout.address.clone().require_network(self.network)?)
+    }
+
+    /// Records the ruling: the buyer gets `buyer_share_bps` of the escrow after fees,
+    /// the seller the rest.
+    pub(crate) fn rule(&mut self, buyer_share_bps: u16) -> Result<(), Error> {
+        if u64::from(buyer_share_bps) > BPS {
+            return Err(Error::WrongInputs(format!(
+                "Buyer's share must be at most {BPS} bps, got {buyer_share_bps}"
+            )));
+        }
+        self.buyer_share_bps = Some(buyer_share_bps);
+        Ok(())
+    }
+
+    /// What the buyer and the seller get out of `distributed`, as ruled.
+    pub(crate) fn split(&self, distributed: Amount) -> Result<(Amount, Amount), Error> {
+        let bps = self
+            .buyer_share_bps
+            .ok_or_else(|| Error::Protocol("The dispute was not ruled yet".to_string()))?;
+        let buyer = u128::from(distributed.to_sat()) * u128::from(bps) / u128::from(BPS);
+        let buyer = Amount::from_sat(u64::try_from(buyer).map_err(|_| Error::Rounding)?);
+        Ok((buyer, distributed - buyer))
+    }
+
+    /// Checks that the resolution `tx` of `handshake`, spending `prevouts` through
+    /// `escrow_script`, is one the arbitrator may sign.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the record is for another escrow or not ruled yet, `tx` spends anything but
+    /// the escrow, pays anyone but the registered payout addresses and the platform fee,
+    /// doesn't split what is left as ruled, or pays a fee rate above `limits`.
+    pub(crate) fn check(
+        &self,
+        handshake: &Handshake,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        escrow_script: EscrowScript,
+        limits: &FeeRateLimits,
+    ) -> Result<(), Error> {
+        let (offer, acceptor) = agreed(handshake)?;
+        if self.session_id != offer.session_id()? {
+            return Err(Error::Protocol(
+                "Dispute record is for another session".to_string(),
+            ));
+        }
+
+        // Only the escrow, so the signature can't move anything else.
+        let escrow_script_pubkey = offer.escrow_address(acceptor)?.script_pubkey();
+        if prevouts.len() != tx.input.len()
+            || prevouts
+                .iter()
+                .any(|prevout| prevout.script_pubkey != escrow_script_pubkey)
+        {
+            return Err(Error::WrongInputs(
+                "Arbitrators only sign spends of the disputed escrow".to_string(),
+            ));
+        }
+        let escrow_value = prevouts
+            .iter()
+            .try_fold(Amount::ZERO, |total, prevout| {
+                total.checked_add(prevout.value)
+            })
+            .ok_or(Error::Rounding)?;
+
+        // Only the registered payout addresses and the platform fee.
+        let buyer = self.payout_address(Role::Buyer)?.script_pubkey();
+        let seller = self.payout_address(Role::Seller)?.script_pubkey();
+        let platform = match &self.platform_fee {
+            Some(platform_fee) => Some(platform_fee.address(self.network)?.script_pubkey()),
+            None => None,
+        };
+        let (mut paid_buyer, mut paid_seller) = (Amount::ZERO, Amount::ZERO);
+        for output in &tx.output {
+            if output.script_pubkey == buyer {
+                paid_buyer = paid_buyer
+                    .checked_add(output.value)
+                    .ok_or(Error::Rounding)?;
+            } else if output.script_pubkey == seller {
+                paid_seller = paid_seller
+                    .checked_add(output.value)
+                    .ok_or(Error::Rounding)?;
+            } else if Some(&output.script_pubkey) != platform.as_ref() {
+                return Err(Error::Protocol(format!(
+                    "Resolution pays {} which is not a registered payout address",
+                    Address::from_script(&output.script_pubkey, self.network)
+                        .map_or_else(|_| output.script_pubkey.to_string(), |a| a.to_string())
+                )));
+            }
+        }
+        if let Some(platform_fee) = &self.platform_fee {
+            let (amount_buyer, amount_seller) = offer.locked_amounts(handshake.price())?;
+            let locked = amount_buyer
+                .checked_add(amount_seller)
+                .ok_or(Error::Rounding)?;
+            platform_fee.verify(tx, locked, self.network)?;
+        }
+
+        // What is left after fees, split as ruled.
+        let distributed = paid_buyer.checked_add(paid_seller).ok_or(Error::Rounding)?;
+        if (paid_buyer, paid_seller) != self.split(distributed)? {
+            return Err(Error::Protocol(format!(
+                "Resolution pays {paid_buyer} to the buyer and {paid_seller} to the seller, \
+                 not the ruled split"
+            )));
+        }
+
+        // A mining fee within the limits, counted on the signed size.
+        let output_value = tx.output.iter().map(|output| output.value).sum::<Amount>();
+        let fee = escrow_value.checked_sub(output_value).ok_or_else(|| {
+            Error::WrongInputs("Resolution spends more than the escrow".to_string())
+        })?;
+        let config = offer.escrow_config(acceptor)?;
+        let witness =
+            placeholder_leaf_witness(&config.script(escrow_script)?, &config.spend_info()?)?;
+        let mut signed = tx.clone();
+        for input in &mut signed.input {
+            input.witness = witness.clone();
+        }
+        let max_fee = limits
+            .max()
+            .fee_wu(signed.weight())
+            .ok_or(Error::Rounding)?;
+        if fee > max_fee {
+            return Err(Error::WrongInputs(format!(
+                "Resolution fee of {fee} is above the {max_fee} allowed by the fee rate limits"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Disputes the user arbitrates, at most one per session.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct ArbitratorMode {
+    disputes: Vec<DisputeRecord>,
+}
+
+impl ArbitratorMode {
+    /// Loads the disputes from `storage`, none if none were saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(ARBITRATOR_DISPUTES_KEY)? {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|e| Error::Storage(format!("Invalid dispute records: {e}"))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the disputes to `storage`.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::Storage(format!("Could not save dispute records: {e}")))?;
+        storage.set(ARBITRATOR_DISPUTES_KEY, &json)
+    }
+
+    /// All disputes, in the order they were opened.
+    pub(crate) fn disputes(&self) -> &[DisputeRecord] {
+        &self.disputes
+    }
+
+    /// The record of the dispute of `session_id`, if opened.
+    pub(crate) fn get(&self, session_id: &SessionId) -> Option<&DisputeRecord> {
+        self.disputes
+            .iter()
+            .find(|record| record.session_id == *session_id)
+    }
+
+    /// Opens the dispute of `handshake`, registering the payout addresses of the participants.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the dispute is already open, so registered addresses can't be replaced.
+    pub(crate) fn open(&mut self, handshake: &Handshake) -> Result<&mut DisputeRecord, Error> {
+        let record = DisputeRecord::open(handshake)?;
+        if self.get(&record.session_id).is_some() {
+            return Err(Error::Protocol(format!(
+                "Dispute {} is already open",
+                record.session_id
+            )));
+        }
+        #[cfg(debug_assertions)]
+        session_span(&record.session_id)
+            .in_scope(|| trace!(payouts = ?record.payouts, "opened dispute"));
+        self.disputes.push(record);
+        Ok(self.disputes.last_mut().expect("just pushed"))
+    }
+
+    /// Records the ruling of the dispute of `session_id`, see [`DisputeRecord::rule`].
+    pub(crate) fn rule(
+        &mut self,
+        session_id: &SessionId,
+        buyer_share_bps: u16,
+    ) -> Result<(), Error> {
+        self.disputes
+            .iter_mut()
+            .find(|record| record.session_id == *session_id)
+            .ok_or_else(|| Error::Protocol(format!("No dispute open for {session_id}")))?
+            .rule(buyer_share_bps)
+    }
+
+    /// Signs the arbitrator's part of the resolution `tx` of `handshake` with
+    /// [`arbitrate`], once [`DisputeRecord::check`] allows it.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no dispute is open for `handshake` or its record doesn't allow `tx`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn arbitrate(
+        &self,
+        handshake: &Handshake,
+        tx: &Transaction,
+        index: usize,
+        prevouts: Vec<TxOut>,
+        escrow_script: EscrowScript,
+        nsec: &NostrSecretKey,
+        reason: impl Into<String>,
+        limits: &FeeRateLimits,
+    ) -> Result<(schnorr::Signature, Event), Error> {
+        let session_id = handshake.session_id()?;
+        let record = self
+            .get(&session_id)
+            .ok_or_else(|| Error::Protocol(format!("No dispute open for {session_id}")))?;
+        record.check(handshake, tx, &prevouts, escrow_script, limits)?;
+        let (offer, _) = agreed(handshake)?;
+        let timelock = leaf_timelock(escrow_script, offer.timelock_duration)?;
+        let invariants =
+            SigningInvariants::of_local_tx(tx, prevouts, vec![timelock; tx.input.len()]);
+        arbitrate(
+            handshake,
+            tx,
+            index,
+            &invariants,
+            escrow_script,
+            nsec,
+            reason,
+        )
+    }
+
+    /// The agreed handshake of the open dispute over `escrow_address`,
+    /// from the sessions saved in `storage`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no open dispute is over `escrow_address`.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn handshake(
+        &self,
+        storage: &impl Storage,
+        escrow_address: &Address,
+    ) -> Result<Handshake, Error> {
+        for record in &self.disputes {
+            if let Some(session) = Session::load(storage, &record.session_id)?
+                && session.handshake.escrow_address() == Some(escrow_address)
+            {
+                return Ok(session.handshake);
+            }
+        }
+        Err(Error::Protocol(format!(
+            "No dispute open for the escrow {escrow_address}"
+        )))
+    }
+
+    /// The agreed handshake of the open dispute over `escrow_address`, none without
+    /// the sessions, which are only saved with serde.
+    ///
+    /// # Errors
+    ///
+    /// Always errors, as no open dispute can be found.
+    #[cfg(not(feature = "serde-types"))]
+    pub(crate) fn handshake(
+        &self,
+        _storage: &impl Storage,
+        escrow_address: &Address,
+    ) -> Result<Handshake, Error> {
+        Err(Error::Protocol(format!(
+            "No dispute open for the escrow {escrow_address}"
+        )))
+    }
+}
+
+/// Whether `npub` is the arbitrator of the escrow of `config`,
+/// who only signs through an [`Arbitration`].
+pub(crate) fn is_arbitrator(config: &EscrowConfig, npub: &NostrPublicKey) -> bool {
+    config.npub_arbitrator.as_ref() == Some(npub)
+}
+
+/// What the user needs to sign a resolution as an arbitrator,
+/// see [`ArbitratorMode::arbitrate`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct Arbitration {
+    /// The disputes the user arbitrates.
+    pub(crate) mode: ArbitratorMode,
+    /// Negotiation of the disputed escrow.
+    pub(crate) handshake: Handshake,
+    /// Why the arbitrator decided so, sent to the participants.
+    pub(crate) reason: String,
+    /// Fee rate limits of the resolutions the user signs.
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub(crate) limits: FeeRateLimits,
+    /// Confirmations of the escrow funding, as seen online when the arbitration was prepared.
+    pub(crate) funding_confirmations: u32,
+}
+
+/// An arbitrator's signature of a resolution,
+/// with the [`Decision`](crate::decision::Decision) behind it gift wrapped to the participants.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct ArbitratedSignature {
+    /// The arbitrator's signature.
+    pub(crate) signature: schnorr::Signature,
+    /// Gift wraps of the decision, ready to publish.
+    pub(crate) decision: Vec<Event>,
+}
+
+impl Arbitration {
+    /// Signs input `index` of the resolution `tx`, spending `prevouts` through the
+    /// `escrow_script` leaf, with the arbitrator's `nsec` through
+    /// [`ArbitratorMode::arbitrate`], gift wrapping the decision at `now`.
+    ///
+    /// # Errors
+    ///
+    /// Errors before signing if the funding doesn't have the confirmations required to
+    /// resolve the escrow, see [`Handshake::ensure_resolvable`], or if the dispute records don't allow `tx`, see [`ArbitratorMode::arbitrate`].
+    pub(crate) fn sign(
+        &self,
+        tx: &Transaction,
+        index: usize,
+        prevouts: Vec<TxOut>,
+        escrow_script: EscrowScript,
+        nsec: &SecretNsec,
+        now: Timestamp,
+    ) -> Result<ArbitratedSignature, Error> {
+        self.handshake
+            .ensure_resolvable(self.funding_confirmations)?;
+        let (signature, event) = nsec.with_nostr_secret_key(|secret_key| {
+            self.mode.arbitrate(
+                &self.handshake,
+                tx,
+                index,
+                prevouts,
+                escrow_script,
+                secret_key,
+                self.reason.clone(),
+                &self.limits,
+            )
+        })?;
+        Ok(ArbitratedSignature {
+            signature,
+            decision: wrap_to_recipients(&event, nsec, now)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{OutPoint, Sequence, TxIn, Witness, absolute, transaction};
+    use nostr::{Keys, Timestamp};
+
+    use crate::{protocol::offer, storage::MemoryStorage};
+
+    use super::*;
+
+    #[test]
+    fn arbitrator_signing_policy() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let network = Network::Regtest;
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let (_, offer_event) =
+            Handshake::offer(keys_a.secret_key(), offer, Timestamp::now()).unwrap();
+        let (handshake, _) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, Timestamp::now()).unwrap();
+        let session_id = handshake.session_id().unwrap();
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(110_000),
+            script_pubkey: handshake.escrow_address().unwrap().script_pubkey(),
+        }];
+        let pay = |npub: &NostrPublicKey, value: u64| TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: npub_to_address(npub, network).unwrap().script_pubkey(),
+        };
+        // The buyer, B, gets 75% of the escrow after fees.
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Default::default(),
+                sequence: Sequence::from_height(144),
+                witness: Witness::new(),
+            }],
+            output: vec![
+                pay(&keys_b.public_key(), 81_750),
+                pay(&keys_a.public_key(), 27_250),
+            ],
+        };
+        let limits = FeeRateLimits {
+            max_sat_per_vb: 50,
+            ..FeeRateLimits::default()
+        };
+        let sign = |mode: &ArbitratorMode, tx: &Transaction| {
+            mode.arbitrate(
+                &handshake,
+                tx,
+                0,
+                prevouts.clone(),
+                EscrowScript::B,
+                keys_arbitrator.secret_key(),
+                "Goods damaged",
+                &limits,
+            )
+        };
+
+        // Nothing is signed before the dispute is opened and ruled.
+        let mut mode = ArbitratorMode::default();
+        assert!(sign(&mode, &tx).is_err());
+        mode.open(&handshake).unwrap();
+        assert!(mode.open(&handshake).is_err());
+        assert!(sign(&mode, &tx).is_err());
+        assert!(mode.rule(&session_id, 10_001).is_err());
+        mode.rule(&session_id, 7_500).unwrap();
+        sign(&mode, &tx).unwrap();
+
+        // The records survive a restart.
+        let storage = MemoryStorage::default();
+        mode.save(&storage).unwrap();
+        let mode = ArbitratorMode::load(&storage).unwrap();
+        assert_eq!(mode.disputes().len(), 1);
+        let (signature, _) = sign(&mode, &tx).unwrap();
+
+        // Signing through an arbitration also gift wraps the decision,
+        // once the funding has the confirmations required to resolve.
+        let unconfirmed = Arbitration {
+            mode: mode.clone(),
+            handshake: handshake.clone(),
+            reason: "Goods damaged".to_string(),
+            limits,
+            funding_confirmations: 0,
+        };
+        assert!(
+            unconfirmed
+                .sign(
+                    &tx,
+                    0,
+                    prevouts.clone(),
+                    EscrowScript::B,
+                    &SecretNsec::from(keys_arbitrator.secret_key().clone()),
+                    Timestamp::now(),
+                )
+                .is_err()
+        );
+        let arbitration = Arbitration {
+            funding_confirmations: handshake.negotiated_offer().min_confirmations(),
+            ..unconfirmed
+        };
+        let arbitrated = arbitration
+            .sign(
+                &tx,
+                0,
+                prevouts.clone(),
+                EscrowScript::B,
+                &SecretNsec::from(keys_arbitrator.secret_key().clone()),
+                Timestamp::now(),
+            )
+            .unwrap();
+        assert_eq!(arbitrated.signature, signature);
+        assert!(!arbitrated.decision.is_empty());
+        let (offer, acceptor) = agreed(&handshake).unwrap();
+        let config = offer.escrow_config(acceptor).unwrap();
+        assert!(is_arbitrator(&config, &keys_arbitrator.public_key()));
+        assert!(!is_arbitrator(&config, &keys_a.public_key()));
+
+        // Exfiltration to an unregistered address is refused.
+        let thief = Keys::generate().public_key();
+        let mut exfiltration = tx.clone();
+        exfiltration.output[1] = pay(&thief, 27_250);
+        assert!(sign(&mode, &exfiltration).is_err());
+        // So is another split, or a fee above the limits.
+        let mut skewed = tx.clone();
+        skewed.output[0].value = Amount::from_sat(82_750);
+        skewed.output[1].value = Amount::from_sat(26_250);
+        assert!(sign(&mode, &skewed).is_err());
+        let mut overpaying = tx.clone();
+        overpaying.output[0].value = Amount::from_sat(30_000);
+        overpaying.output[1].value = Amount::from_sat(10_000);
+        assert!(sign(&mode, &overpaying).is_err());
+        // And spending anything but the escrow.
+        let mut foreign = prevouts.clone();
+        foreign[0].script_pubkey = pay(&thief, 0).script_pubkey;
+        let record = mode.get(&session_id).unwrap();
+        assert!(
+            record
+                .check(&handshake, &tx, &foreign, EscrowScript::B, &limits)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Directory of arbitrators advertising their services over Nostr.
+//!
+//! Arbitrators publish an [`ArbitratorAd`] as an addressable event: the networks they
+//! arbitrate on, their fee and expected response time, and how to reach them.
+//! Publishing a new ad replaces the previous one on the relays,
+//! so [`list_arbitrators`] only ever sees each arbitrator's latest terms,
+//! and parties creating a dispute escrow can pick an arbitrator from a live directory.
+
+use bitcoin::{Amount, Network};
+use nostr::{
+    Event, EventBuilder, Filter, Keys, Kind, Tag, Timestamp,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{canonical, error::Error, protocol::check_event};
+
+/// Version of the [`ArbitratorAd`] message.
+pub(crate) const ARBITRATOR_AD_VERSION: u8 = 1;
+
+/// Nostr event kind of an [`ArbitratorAd`], addressable so new ads replace old ones.
+pub(crate) const ARBITRATOR_AD_KIND: u16 = 38_383;
+
+/// Identifier tag of [`ArbitratorAd`] events, one per arbitrator.
+const ARBITRATOR_AD_IDENTIFIER: &str = "scrow/arbitrator";
+
+/// Basis points in a whole.
+const BPS: u64 = 10_000;
+
+/// An arbitrator's advertised terms.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ArbitratorAd {
+    /// Message version, see [`ARBITRATOR_AD_VERSION`].
+    pub(crate) version: u8,
+    /// Arbitrator's Nostr public key.
+    pub(crate) npub: NostrPublicKey,
+    /// Networks the arbitrator takes escrows on.
+    pub(crate) networks: Vec<Network>,
+    /// Fee of a resolution, in basis points of the escrow amount.
+    pub(crate) fee_bps: u16,
+    /// Expected time to answer a dispute, in hours.
+    pub(crate) response_hours: u32,
+    /// How to reach the arbitrator, such as a NIP-05 address or a website.
+    pub(crate) contact: String,
+}
+
+impl ArbitratorAd {
+    /// Checks the version, the networks and the fee.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.version != ARBITRATOR_AD_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported arbitrator ad version {}",
+                self.version
+            )));
+        }
+        if self.networks.is_empty() {
+            return Err(Error::WrongInputs(
+                "Arbitrator ad has no network".to_string(),
+            ));
+        }
+        if u64::from(self.fee_bps) > BPS {
+            return Err(Error::WrongInputs(format!(
+                "Arbitrator fee of {} bps is more than the escrow",
+                self.fee_bps
+            )));
+        }
+        Ok(())
+    }
+
+    /// The arbitrator's fee on an escrow of `amount`, rounded down.
+    pub(crate) fn fee(&self, amount: Amount) -> Amount {
+        let fee = u128::from(amount.to_sat()) * u128::from(self.fee_bps) / u128::from(BPS);
+        Amount::from_sat(u64::try_from(fee).expect("fee is at most the amount"))
+    }
+
+    /// Builds and signs the ad [`Event`].
+    pub(crate) fn to_event(&self, nsec: &NostrSecretKey) -> Result<Event, Error> {
+        self.validate()?;
+        let keys = Keys::new(nsec.clone());
+        if keys.public_key() != self.npub {
+            return Err(Error::Protocol(
+                "Arbitrator ad must be signed by the arbitrator".to_string(),
+            ));
+        }
+        Ok(
+            EventBuilder::new(Kind::Custom(ARBITRATOR_AD_KIND), canonical::encode(self)?)
+                .tag(Tag::identifier(ARBITRATOR_AD_IDENTIFIER))
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses and validates an ad [`Event`], checking it is signed by the arbitrator.
+    pub(crate) fn from_event(event: &Event) -> Result<Self, Error> {
+        check_event(event, ARBITRATOR_AD_KIND)?;
+        let ad: ArbitratorAd = canonical::decode(&event.content)?;
+        if ad.npub != event.pubkey {
+            return Err(Error::Protocol(
+                "Arbitrator ad is not signed by the arbitrator".to_string(),
+            ));
+        }
+        ad.validate()?;
+        Ok(ad)
+    }
+
+    /// The [`Filter`] of every arbitrator ad.
+    pub(crate) fn filter() -> Filter {
+        Filter::new()
+            .kind(Kind::Custom(ARBITRATOR_AD_KIND))
+            .identifier(ARBITRATOR_AD_IDENTIFIER)
+    }
+}
+
+/// Criteria an arbitrator must meet to be listed by [`list_arbitrators`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ArbitratorFilter {
+    /// Network of the escrow, if any.
+    pub(crate) network: Option<Network>,
+    /// Highest acceptable fee in basis points, if any.
+    pub(crate) max_fee_bps: Option<u16>,
+    /// Longest acceptable response time in hours, if any.
+    pub(crate) max_response_hours: Option<u32>,
+}
+
+impl ArbitratorFilter {
+    /// Whether `ad` meets the criteria.
+    pub(crate) fn matches(&self, ad: &ArbitratorAd) -> bool {
+        self.network
+            .is_none_or(|network| ad.networks.contains(&network))
+            && self.max_fee_bps.is_none_or(|max| ad.fee_bps <= max)
+            && self
+                .max_response_hours
+                .is_none_or(|max| ad.response_hours <= max)
+    }
+}
+
+/// A listed [`ArbitratorAd`], with when it was published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ArbitratorListing {
+    /// The arbitrator's latest ad.
+    pub(crate) ad: ArbitratorAd,
+    /// Creation time of the ad event.
+    pub(crate) published_at: Timestamp,
+}
+
+/// Lists the arbitrators meeting `filter` from ad `events`, cheapest then fastest first.
+///
+/// Only the latest valid ad of each arbitrator is kept, and invalid ads are skipped.
+pub(crate) fn list_arbitrators<'a>(
+    events: impl IntoIterator<Item = &'a Event>,
+    filter: &ArbitratorFilter,
+) -> Vec<ArbitratorListing> {
+    let mut listings: Vec<ArbitratorListing> = Vec::new();
+    for event in events {
+        let Ok(ad) = ArbitratorAd::from_event(event) else {
+            continue;
+        };
+        let listing = ArbitratorListing {
+            ad,
+            published_at: event.created_at,
+        };
+        match listings.iter_mut().find(|l| l.ad.npub == listing.ad.npub) {
+            Some(existing) if existing.published_at >= listing.published_at => {}
+            Some(existing) => *existing = listing,
+            None => listings.push(listing),
+        }
+    }
+    listings.retain(|listing| filter.matches(&listing.ad));
+    listings.sort_by_key(|listing| (listing.ad.fee_bps, listing.ad.response_hours));
+    listings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arbitrator_directory() {
+        let ad_event = |keys: &Keys, fee_bps: u16, response_hours: u32, created_at: u64| {
+            let ad = ArbitratorAd {
+                version: ARBITRATOR_AD_VERSION,
+                npub: keys.public_key(),
+                networks: vec![Network::Bitcoin, Network::Signet],
+                fee_bps,
+                response_hours,
+                contact: "arbitrator@example.com".to_string(),
+            };
+            EventBuilder::new(
+                Kind::Custom(ARBITRATOR_AD_KIND),
+                canonical::encode(&ad).unwrap(),
+            )
+            .tag(Tag::identifier(ARBITRATOR_AD_IDENTIFIER))
+            .custom_created_at(Timestamp::from(created_at))
+            .sign_with_keys(keys)
+            .unwrap()
+        };
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let carol = Keys::generate();
+        let events = [
+            ad_event(&alice, 100, 24, 1),
+            // Alice's newer ad replaces the older one.
+            ad_event(&alice, 50, 48, 2),
+            ad_event(&bob, 50, 12, 1),
+            ad_event(&carol, 300, 1, 1),
+        ];
+        assert_eq!(
+            ArbitratorAd::from_event(&events[0])
+                .unwrap()
+                .fee(Amount::from_sat(123_456)),
+            Amount::from_sat(1_234)
+        );
+
+        let listings = list_arbitrators(&events, &ArbitratorFilter::default());
+        let npubs = listings.iter().map(|l| l.ad.npub).collect::<Vec<_>>();
+        assert_eq!(
+            npubs,
+            [bob.public_key(), alice.public_key(), carol.public_key()]
+        );
+        assert_eq!(listings[1].ad.fee_bps, 50);
+
+        let filter = ArbitratorFilter {
+            network: Some(Network::Bitcoin),
+            max_fee_bps: Some(100),
+            max_response_hours: Some(24),
+        };
+        let listings = list_arbitrators(&events, &filter);
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].ad.npub, bob.public_key());
+        let filter = ArbitratorFilter {
+            network: Some(Network::Regtest),
+            ..Default::default()
+        };
+        assert!(list_arbitrators(&events, &filter).is_empty());
+
+        // Ads signed by someone else than the arbitrator they name are skipped.
+        let forged = ArbitratorAd {
+            npub: alice.public_key(),
+            ..ArbitratorAd::from_event(&events[2]).unwrap()
+        };
+        assert!(forged.to_event(bob.secret_key()).is_err());
+    }
+}
@@ -0,0 +1,709 @@
+//! Read-only audit of escrows already on chain.
+
+use bitcoin::{
+    Amount, OutPoint, Transaction, TxOut, Txid,
+    hashes::Hash,
+    sighash::{Prevouts, SighashCache},
+    taproot,
+};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::key::PublicKey as NostrPublicKey;
+use secp256k1::{Message, SECP256K1};
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, ResultExt},
+    esplora::EsploraClient,
+    network::NetworkProfile,
+    scripts::{EscrowConfig, EscrowContext, KeyOrdering, ScriptTemplate, SpendPath},
+    util::{blocks_for_duration, days_hours, npub_to_x_only_public_key},
+};
+
+/// Signature check of a single signer of a leaf spend.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct SignerAudit {
+    /// The key that was expected to sign.
+    pub(crate) npub: NostrPublicKey,
+    /// Whether the witness holds a valid signature from `npub`.
+    pub(crate) valid: bool,
+}
+
+/// Audit of the transaction spending an escrow output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct SpendAudit {
+    /// Spending transaction ID.
+    pub(crate) txid: Txid,
+    /// Index of the input spending the escrow output.
+    pub(crate) input_index: usize,
+    /// The spending path used,
+    /// or `None` if the input spends a script that is not part of the claimed escrow.
+    pub(crate) path: Option<SpendPath>,
+    /// The signers of the leaf, in witness order.
+    ///
+    /// Empty unless `path` is a [`SpendPath::Leaf`].
+    pub(crate) signers: Vec<SignerAudit>,
+    /// Outputs of the spending transaction.
+    pub(crate) payouts: Vec<TxOut>,
+    /// Block height of the spending transaction, if confirmed.
+    pub(crate) confirmed_height: Option<u32>,
+}
+
+/// Structured report of an escrow audit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AuditReport {
+    /// Funding transaction ID.
+    pub(crate) funding_txid: Txid,
+    /// The escrow output in the funding transaction,
+    /// or `None` if no output matches the claimed escrow configuration.
+    pub(crate) outpoint: Option<OutPoint>,
+    /// Amount locked in the escrow output.
+    pub(crate) amount: Option<Amount>,
+    /// Block height of the funding transaction, if confirmed.
+    pub(crate) confirmed_height: Option<u32>,
+    /// The spend of the escrow output, if spent.
+    pub(crate) spend: Option<SpendAudit>,
+    /// The escrow the funding transaction pays instead, if it doesn't pay the claimed one.
+    pub(crate) near_miss: Option<NearMiss>,
+}
+
+impl AuditReport {
+    /// Whether the funding transaction pays to the claimed escrow configuration.
+    pub(crate) fn matches_config(&self) -> bool {
+        self.outpoint.is_some()
+    }
+
+    /// Whether the escrow output was spent through one of its leaves
+    /// with valid signatures from all of the leaf's signers.
+    pub(crate) fn is_valid_spend(&self) -> bool {
+        self.spend.as_ref().is_some_and(|spend| {
+            matches!(spend.path, Some(SpendPath::Leaf(_))) && spend.signers.iter().all(|s| s.valid)
+        })
+    }
+}
+
+/// Timelocks, in days, counterparties commonly pick instead of the agreed one:
+/// a day, a week, two weeks and a month.
+const COMMON_TIMELOCK_DAYS: [u32; 4] = [1, 7, 14, 30];
+
+/// The single parameter of a [`NearMiss`] that differs from the claimed escrow.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub(crate) enum Divergence {
+    /// The participants' keys are in the opposite order.
+    SwappedKeys,
+    /// The participants' keys are sorted, see [`KeyOrdering::Sorted`].
+    SortedKeys,
+    /// The dispute timelock differs.
+    Timelock {
+        /// Timelock of the claimed escrow.
+        expected: u32,
+        /// Timelock of the funded escrow.
+        found: u32,
+    },
+    /// The script template differs.
+    Template {
+        /// Template of the claimed escrow.
+        expected: ScriptTemplate,
+        /// Template of the funded escrow.
+        found: ScriptTemplate,
+    },
+    /// The funded escrow has no arbitrator, so no dispute path.
+    MissingArbitrator,
+}
+
+impl Divergence {
+    /// Describes the divergence for the UI.
+    pub(crate) fn description(&self) -> String {
+        match self {
+            Divergence::SwappedKeys => "participant keys swapped".to_string(),
+            Divergence::SortedKeys => "participant keys sorted".to_string(),
+            Divergence::Timelock { expected, found } => {
+                format!("timelock of {found} blocks instead of {expected}")
+            }
+            Divergence::Template { expected, found } => {
+                format!("script template {found:?} instead of {expected:?}")
+            }
+            Divergence::MissingArbitrator => "no arbitrator".to_string(),
+        }
+    }
+}
+
+/// An output of the funding transaction paying an escrow that differs from the claimed one
+/// by a single parameter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct NearMiss {
+    /// The output paying the other escrow.
+    pub(crate) outpoint: OutPoint,
+    /// Amount locked in it.
+    pub(crate) amount: Amount,
+    /// The escrow actually funded.
+    pub(crate) config: EscrowConfig,
+    /// How it differs from the claimed escrow.
+    pub(crate) divergence: Divergence,
+}
+
+impl NearMiss {
+    /// Finds the output of `funding_tx` paying a near miss of `config`, if any.
+    ///
+    /// The candidates are tried in the order of [`near_miss_candidates`],
+    /// so the same funding is always reported with the same divergence.
+    pub(crate) fn scan(
+        config: &EscrowConfig,
+        profile: &NetworkProfile,
+        funding_tx: &Transaction,
+    ) -> Option<Self> {
+        let funding_txid = funding_tx.compute_txid();
+        near_miss_candidates(config, profile)
+            .into_iter()
+            .find_map(|(divergence, candidate)| {
+                let script_pubkey = candidate.context().ok()?.address().script_pubkey();
+                let (vout, output) = funding_tx
+                    .output
+                    .iter()
+                    .enumerate()
+                    .find(|(_, output)| output.script_pubkey == script_pubkey)?;
+                Some(Self {
+                    outpoint: OutPoint::new(funding_txid, vout as u32),
+                    amount: output.value,
+                    config: candidate,
+                    divergence,
+                })
+            })
+    }
+
+    /// Describes the near miss for the UI.
+    pub(crate) fn description(&self) -> String {
+        format!(
+            "Funding pays {} to an escrow with {}",
+            self.outpoint,
+            self.divergence.description()
+        )
+    }
+}
+
+/// Escrows differing from `config` by a single parameter, in a fixed order:
+/// swapped and sorted keys, timelocks off by one block or set to a common value
+/// in the blocks of `profile`, the other script templates, and no arbitrator.
+///
+/// Candidates deriving the same address as `config` or an earlier candidate are skipped.
+pub(crate) fn near_miss_candidates(
+    config: &EscrowConfig,
+    profile: &NetworkProfile,
+) -> Vec<(Divergence, EscrowConfig)> {
+    let mut candidates = vec![
+        (
+            Divergence::SwappedKeys,
+            EscrowConfig {
+                npub_1: config.npub_2,
+                npub_2: config.npub_1,
+                ..*config
+            },
+        ),
+        (
+            Divergence::SortedKeys,
+            config.with_key_ordering(KeyOrdering::Sorted),
+        ),
+    ];
+    if let Some(expected) = config.timelock_duration {
+        let timelocks = [expected.checked_sub(1), expected.checked_add(1)]
+            .into_iter()
+            .flatten()
+            .chain(
+                COMMON_TIMELOCK_DAYS
+                    .into_iter()
+                    .filter_map(|days| blocks_for_duration(days_hours(days, 0), profile)),
+            );
+        for found in timelocks {
+            candidates.push((
+                Divergence::Timelock { expected, found },
+                EscrowConfig {
+                    timelock_duration: Some(found),
+                    ..*config
+                },
+            ));
+        }
+    }
+    for found in [ScriptTemplate::V1, ScriptTemplate::V2] {
+        candidates.push((
+            Divergence::Template {
+                expected: config.template,
+                found,
+            },
+            EscrowConfig {
+                template: found,
+                ..*config
+            },
+        ));
+    }
+    if config.npub_arbitrator.is_some() {
+        candidates.push((
+            Divergence::MissingArbitrator,
+            EscrowConfig {
+                npub_arbitrator: None,
+                timelock_duration: None,
+                ..*config
+            },
+        ));
+    }
+
+    let mut seen = Vec::new();
+    if let Ok(address) = config.address() {
+        seen.push(address);
+    }
+    candidates.retain(|(_, candidate)| match candidate.address() {
+        Ok(address) if !seen.contains(&address) => {
+            seen.push(address);
+            true
+        }
+        _ => false,
+    });
+    candidates
+}
+
+/// Audits an escrow given its funding [`Txid`] and claimed [`EscrowConfig`].
+///
+/// Fetches the funding transaction, checks that one of its outputs pays to the escrow
+/// address and, if that output was spent, which leaf was used and who signed.
+///
+/// Near misses are searched with the block timing of `profile`, the chain of the escrow.
+///
+/// This never signs nor broadcasts anything.
+pub(crate) async fn audit_escrow(
+    client: &EsploraClient,
+    funding_txid: Txid,
+    config: &EscrowConfig,
+    profile: &NetworkProfile,
+) -> Result<AuditReport, Error> {
+    let funding_tx = client
+        .get_tx(&funding_txid)
+        .await
+        .context("fetching funding transaction")?
+        .ok_or_else(|| {
+            Error::WrongInputs(format!("Funding transaction {funding_txid} not found"))
+        })?;
+    let confirmed_height = client
+        .get_tx_status(&funding_txid)
+        .await
+        .context("fetching funding transaction status")?
+        .block_height;
+
+    let context = config.context()?;
+    let script_pubkey = context.address().script_pubkey();
+    let Some((vout, output)) = funding_tx
+        .output
+        .iter()
+        .enumerate()
+        .find(|(_, output)| output.script_pubkey == script_pubkey)
+    else {
+        let near_miss = NearMiss::scan(config, profile, &funding_tx);
+        #[cfg(debug_assertions)]
+        trace!(%funding_txid, ?near_miss, "funding transaction does not pay to the escrow");
+        return Ok(AuditReport {
+            funding_txid,
+            outpoint: None,
+            amount: None,
+            confirmed_height,
+            spend: None,
+            near_miss,
+        });
+    };
+    let outpoint = OutPoint::new(funding_txid, vout as u32);
+    #[cfg(debug_assertions)]
+    trace!(%outpoint, amount = %output.value, "escrow output found");
+
+    let spend = match client
+        .get_output_status(&funding_txid, vout as u64)
+        .await
+        .context("fetching escrow output status")?
+    {
+        Some(status) if status.spent => match (status.txid, status.vin) {
+            (Some(spending_txid), Some(vin)) => {
+                let spending_tx = client
+                    .get_tx(&spending_txid)
+                    .await
+                    .context("fetching spending transaction")?
+                    .ok_or_else(|| {
+                        Error::WrongInputs(format!(
+                            "Spending transaction {spending_txid} not found"
+                        ))
+                    })?;
+                let prevouts = fetch_prevouts(client, &spending_tx, &funding_tx).await?;
+                let mut spend =
+                    analyze_context_spend(&spending_tx, vin as usize, &prevouts, &context)?;
+                spend.confirmed_height = status.status.and_then(|status| status.block_height);
+                Some(spend)
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    Ok(AuditReport {
+        funding_txid,
+        outpoint: Some(outpoint),
+        amount: Some(output.value),
+        confirmed_height,
+        spend,
+        near_miss: None,
+    })
+}
+
+/// Fetches the outputs spent by every input of `tx`, reusing `funding_tx` when possible.
+async fn fetch_prevouts(
+    client: &EsploraClient,
+    tx: &Transaction,
+    funding_tx: &Transaction,
+) -> Result<Vec<TxOut>, Error> {
+    let funding_txid = funding_tx.compute_txid();
+    let mut prevouts = Vec::with_capacity(tx.input.len());
+    for input in &tx.input {
+        let previous_output = input.previous_output;
+        let previous_tx = if previous_output.txid == funding_txid {
+            funding_tx.clone()
+        } else {
+            client
+                .get_tx(&previous_output.txid)
+                .await
+                .context(format!("fetching input {previous_output}"))?
+                .ok_or_else(|| {
+                    Error::WrongInputs(format!("Transaction {} not found", previous_output.txid))
+                })?
+        };
+        let prevout = previous_tx
+            .output
+            .get(previous_output.vout as usize)
+            .cloned()
+            .ok_or_else(|| Error::WrongInputs(format!("Output {previous_output} not found")))?;
+        prevouts.push(prevout);
+    }
+    Ok(prevouts)
+}
+
+/// Analyzes how input `input_index` of `tx` spends an escrow output with the given [`EscrowConfig`].
+///
+/// `prevouts` must hold the outputs spent by every input of `tx`, in input order.
+/// Signatures are checked against the keys of the matched leaf.
+#[cfg(any(fuzzing, test))]
+pub(crate) fn analyze_spend(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    config: &EscrowConfig,
+) -> Result<SpendAudit, Error> {
+    analyze_context_spend(tx, input_index, prevouts, &config.context()?)
+}
+
+/// Analyzes how input `input_index` of `tx` spends the escrow of `context`,
+/// whose leaf scripts and hashes are precomputed, see [`EscrowConfig::context`].
+pub(crate) fn analyze_context_spend(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    context: &EscrowContext,
+) -> Result<SpendAudit, Error> {
+    let input = tx
+        .input
+        .get(input_index)
+        .ok_or_else(|| Error::WrongInputs(format!("Transaction has no input {input_index}")))?;
+    if prevouts.len() != tx.input.len() {
+        return Err(Error::WrongInputs(format!(
+            "Expected {} prevouts, got {}",
+            tx.input.len(),
+            prevouts.len()
+        )));
+    }
+
+    let mut audit = SpendAudit {
+        txid: tx.compute_txid(),
+        input_index,
+        path: None,
+        signers: Vec::new(),
+        payouts: tx.output.clone(),
+        confirmed_height: None,
+    };
+
+    let witness = &input.witness;
+    if witness.len() == 1 {
+        audit.path = Some(SpendPath::KeyPath);
+        return Ok(audit);
+    }
+    let Some(script) = witness.tapscript() else {
+        return Ok(audit);
+    };
+    let Some(leaf) = context.find_leaf(script) else {
+        return Ok(audit);
+    };
+    audit.path = Some(SpendPath::Leaf(leaf.escrow_script));
+
+    // Witness is `<sig_1> <sig_2> <script> <control block>`.
+    let mut sighash_cache = SighashCache::new(tx);
+    let signers = context.config().signers(leaf.escrow_script)?;
+    for (npub, signature) in signers.into_iter().zip(witness.iter()) {
+        let valid = match taproot::Signature::from_slice(signature) {
+            Ok(signature) => {
+                let sighash = sighash_cache
+                    .taproot_script_spend_signature_hash(
+                        input_index,
+                        &Prevouts::All(prevouts),
+                        leaf.leaf_hash,
+                        signature.sighash_type,
+                    )
+                    .context(format!("computing sighash for input {input_index}"))?;
+                let message = Message::from_digest(*sighash.as_byte_array());
+                let public_key = npub_to_x_only_public_key(&npub)?;
+                SECP256K1
+                    .verify_schnorr(&signature.signature, &message, &public_key)
+                    .is_ok()
+            }
+            Err(_) => false,
+        };
+        audit.signers.push(SignerAudit { npub, valid });
+    }
+    #[cfg(debug_assertions)]
+    trace!(txid = %audit.txid, leaf = ?leaf.escrow_script, signers = ?audit.signers, "escrow spend analyzed");
+
+    Ok(audit)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, transaction::Version};
+
+    use super::*;
+    use crate::{
+        accounts::Keystore,
+        invariants::SigningInvariants,
+        keys::Npub,
+        network::Chain,
+        scripts::{CURRENT_SCRIPT_TEMPLATE, EscrowScript},
+        secret::SecretNsec,
+        sign::{combine_signatures, sign_escrow_tx},
+        tx::escrow_tx,
+    };
+
+    fn generate_nostr_keys() -> (SecretNsec, NostrPublicKey) {
+        let nsec = SecretNsec::generate();
+        let npub = nsec.public_key();
+        (nsec, npub)
+    }
+
+    #[test]
+    fn analyze_dispute_spend() {
+        let (nsec_1, npub_1) = generate_nostr_keys();
+        let (_, npub_2) = generate_nostr_keys();
+        let (nsec_arb, npub_arb) = generate_nostr_keys();
+        let config = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: Some(npub_arb),
+            timelock_duration: Some(10),
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: config.address().unwrap().script_pubkey(),
+        }];
+        let funding_txid = Transaction {
+            version: Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: prevouts.clone(),
+        }
+        .compute_txid();
+        let unsigned = escrow_tx(
+            &npub_1,
+            &npub_2,
+            Some(10),
+            Amount::from_sat(50_000),
+            Amount::from_sat(50_000),
+            funding_txid,
+            Amount::from_sat(1_000),
+            Network::Regtest,
+            bitcoin::absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let invariants =
+            SigningInvariants::of_local_tx(&unsigned, prevouts.clone(), vec![Some(10)]);
+
+        let mut keystore = Keystore::default();
+        let account_1 = keystore.unlock(nsec_1);
+        let account_arb = keystore.unlock(nsec_arb);
+        let sign = |account: &Npub| {
+            sign_escrow_tx(
+                &unsigned,
+                0,
+                &keystore,
+                account,
+                &npub_1,
+                &npub_2,
+                Some(&npub_arb),
+                Some(10),
+                &invariants,
+                EscrowScript::B,
+            )
+            .unwrap()
+        };
+        let sig_1 = sign(&account_1);
+        let sig_arb = sign(&account_arb);
+        let script = config.script(EscrowScript::B).unwrap();
+        let spend_info = config.spend_info().unwrap();
+        let signed = combine_signatures(
+            unsigned.clone(),
+            0,
+            vec![&sig_1, &sig_arb],
+            &script,
+            &spend_info,
+        )
+        .unwrap();
+
+        let audit = analyze_spend(&signed, 0, &prevouts, &config).unwrap();
+        assert_eq!(audit.path, Some(SpendPath::Leaf(EscrowScript::B)));
+        assert_eq!(
+            audit.signers,
+            vec![
+                SignerAudit {
+                    npub: npub_1,
+                    valid: true
+                },
+                SignerAudit {
+                    npub: npub_arb,
+                    valid: true
+                },
+            ]
+        );
+        assert_eq!(audit.payouts, signed.output);
+
+        // Swapped signatures must not verify.
+        let swapped =
+            combine_signatures(unsigned, 0, vec![&sig_arb, &sig_1], &script, &spend_info).unwrap();
+        let audit = analyze_spend(&swapped, 0, &prevouts, &config).unwrap();
+        assert!(audit.signers.iter().all(|s| !s.valid));
+
+        // A different arbitrator does not match any leaf.
+        let (_, other_arb) = generate_nostr_keys();
+        let other = EscrowConfig {
+            npub_arbitrator: Some(other_arb),
+            ..config
+        };
+        let audit = analyze_spend(&signed, 0, &prevouts, &other).unwrap();
+        assert_eq!(audit.path, None);
+        assert!(audit.signers.is_empty());
+    }
+
+    #[test]
+    fn near_miss_funding() {
+        let (_, npub_1) = generate_nostr_keys();
+        let (_, npub_2) = generate_nostr_keys();
+        let (_, npub_arb) = generate_nostr_keys();
+        let config = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: Some(npub_arb),
+            timelock_duration: Some(144),
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let profile = NetworkProfile::from(Chain::Regtest);
+        let candidates = near_miss_candidates(&config, &profile);
+        assert!(
+            candidates
+                .iter()
+                .all(|(_, candidate)| candidate.address().unwrap() != config.address().unwrap())
+        );
+        let funding_tx = |funded: &EscrowConfig| Transaction {
+            version: Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(5_000),
+                    script_pubkey: bitcoin::ScriptBuf::new(),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: funded.address().unwrap().script_pubkey(),
+                },
+            ],
+        };
+
+        // Each divergence is reported as the parameter that differs.
+        let swapped = EscrowConfig {
+            npub_1: npub_2,
+            npub_2: npub_1,
+            ..config
+        };
+        let near_miss = NearMiss::scan(&config, &profile, &funding_tx(&swapped)).unwrap();
+        assert_eq!(near_miss.divergence, Divergence::SwappedKeys);
+        assert_eq!(near_miss.outpoint.vout, 1);
+        assert_eq!(near_miss.amount, Amount::from_sat(100_000));
+        assert_eq!(near_miss.config, swapped);
+        let off_by_one = EscrowConfig {
+            timelock_duration: Some(145),
+            ..config
+        };
+        let near_miss = NearMiss::scan(&config, &profile, &funding_tx(&off_by_one)).unwrap();
+        assert_eq!(
+            near_miss.divergence,
+            Divergence::Timelock {
+                expected: 144,
+                found: 145
+            }
+        );
+        assert!(near_miss.description().contains("timelock of 145 blocks"));
+        let collaborative = EscrowConfig {
+            npub_arbitrator: None,
+            timelock_duration: None,
+            ..config
+        };
+        let near_miss = NearMiss::scan(&config, &profile, &funding_tx(&collaborative)).unwrap();
+        assert_eq!(near_miss.divergence, Divergence::MissingArbitrator);
+
+        // Common timelocks are counted in the blocks of the chain.
+        let week_on_mutinynet = EscrowConfig {
+            timelock_duration: Some(20_160),
+            ..config
+        };
+        let near_miss = NearMiss::scan(
+            &config,
+            &NetworkProfile::from(Chain::Mutinynet),
+            &funding_tx(&week_on_mutinynet),
+        )
+        .unwrap();
+        assert_eq!(
+            near_miss.divergence,
+            Divergence::Timelock {
+                expected: 144,
+                found: 20_160
+            }
+        );
+        assert_eq!(
+            NearMiss::scan(&config, &profile, &funding_tx(&week_on_mutinynet)),
+            None
+        );
+
+        // The claimed escrow itself or an unrelated one is no near miss.
+        assert_eq!(
+            NearMiss::scan(&config, &profile, &funding_tx(&config)),
+            None
+        );
+        let (_, stranger) = generate_nostr_keys();
+        let unrelated = EscrowConfig {
+            npub_2: stranger,
+            ..config
+        };
+        assert_eq!(
+            NearMiss::scan(&config, &profile, &funding_tx(&unrelated)),
+            None
+        );
+    }
+}
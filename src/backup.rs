@@ -0,0 +1,318 @@
+//! Encrypted backups of the whole app state, to move between devices.
+//!
+//! A [`Backup`] holds every [`Session`], watched escrow and contact, and the relay list.
+//! It is encrypted to the user's own Nostr key with NIP-44, so restoring it on another
+//! device, say from the browser to the desktop app, only needs the same nsec.
+//!
+//! Backups are versioned: [`Backup::decrypt`] migrates older backups to
+//! [`BACKUP_VERSION`] before restoring them, and rejects backups of newer versions of scrow.
+
+use nostr::{
+    key::PublicKey as NostrPublicKey,
+    nips::nip44::{self, Version},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    address_book::AddressBook,
+    error::Error,
+    protocol::{Session, deserialize, serialize},
+    relays::parse_relays,
+    secret::SecretNsec,
+    storage::Storage,
+    watch::WatchSession,
+};
+
+/// Version of the [`Backup`] format.
+pub(crate) const BACKUP_VERSION: u8 = 1;
+
+/// Version of the [`EncryptedBackup`] envelope.
+pub(crate) const ENCRYPTED_BACKUP_VERSION: u8 = 1;
+
+/// Largest plaintext chunk encrypted at once, below the NIP-44 limit of 65535 bytes.
+const CHUNK_LEN: usize = 60_000;
+
+/// Upgrade of the [`Backup`] JSON from one version to the next.
+type Migration = fn(&mut Value) -> Result<(), Error>;
+
+/// Upgrades of the [`Backup`] JSON, the one at index `i` from version `i + 1` to `i + 2`.
+const MIGRATIONS: &[Migration] = &[];
+
+/// The whole app state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Backup {
+    /// Format version, see [`BACKUP_VERSION`].
+    pub(crate) version: u8,
+    /// Escrow negotiations, with their signatures.
+    #[serde(default)]
+    pub(crate) sessions: Vec<Session>,
+    /// Escrows watched without a key.
+    #[serde(default)]
+    pub(crate) watched: Vec<WatchSession>,
+    /// Contacts.
+    #[serde(default)]
+    pub(crate) address_book: AddressBook,
+    /// Relay URLs.
+    #[serde(default)]
+    pub(crate) relays: Vec<String>,
+}
+
+/// A [`Backup`] encrypted with NIP-44 to its owner's key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct EncryptedBackup {
+    /// Envelope version, see [`ENCRYPTED_BACKUP_VERSION`].
+    version: u8,
+    /// Owner of the backup, who can decrypt it.
+    npub: NostrPublicKey,
+    /// NIP-44 payloads of the consecutive chunks of the backup JSON.
+    chunks: Vec<String>,
+}
+
+/// What [`Backup::restore`] added to the storage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct RestoreSummary {
+    /// Sessions added.
+    pub(crate) sessions: usize,
+    /// Watched escrows added.
+    pub(crate) watched: usize,
+    /// Contacts added.
+    pub(crate) contacts: usize,
+}
+
+impl Backup {
+    /// Collects the state persisted in `storage` and the `relays` configuration,
+    /// as parsed by [`parse_relays`].
+    pub(crate) fn collect(storage: &impl Storage, relays: &str) -> Result<Self, Error> {
+        let sessions = Session::list(storage)?
+            .iter()
+            .filter_map(|id| Session::load(storage, id).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        let watched = WatchSession::list(storage)?
+            .iter()
+            .filter_map(|txid| WatchSession::load(storage, txid).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            version: BACKUP_VERSION,
+            sessions,
+            watched,
+            address_book: AddressBook::load(storage)?,
+            relays: parse_relays(relays)?,
+        })
+    }
+
+    /// Encrypts the backup to the key of `nsec`.
+    pub(crate) fn encrypt(&self, nsec: &SecretNsec) -> Result<String, Error> {
+        let json = serialize(self)?;
+        let npub = nsec.public_key();
+        let chunks = nsec.with_nostr_secret_key(|secret_key| {
+            split_chunks(&json)
+                .map(|chunk| nip44::encrypt(secret_key, &npub, chunk, Version::V2))
+                .collect::<Result<Vec<_>, _>>()
+        });
+        let chunks =
+            chunks.map_err(|e| Error::Storage(format!("Could not encrypt backup: {e}")))?;
+        serialize(&EncryptedBackup {
+            version: ENCRYPTED_BACKUP_VERSION,
+            npub,
+            chunks,
+        })
+    }
+
+    /// Decrypts a backup made by [`Backup::encrypt`] with `nsec`,
+    /// migrating it to [`BACKUP_VERSION`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the backup is not for `nsec`, was tampered with,
+    /// or comes from a newer version of scrow.
+    pub(crate) fn decrypt(data: &str, nsec: &SecretNsec) -> Result<Self, Error> {
+        let encrypted = deserialize::<EncryptedBackup>(data.trim())?;
+        if encrypted.version != ENCRYPTED_BACKUP_VERSION {
+            return Err(Error::Storage(format!(
+                "Unsupported backup version {}",
+                encrypted.version
+            )));
+        }
+        if encrypted.npub != nsec.public_key() {
+            return Err(Error::WrongInputs(
+                "Backup was made with another key".to_string(),
+            ));
+        }
+        let json = nsec.with_nostr_secret_key(|secret_key| {
+            encrypted
+                .chunks
+                .iter()
+                .map(|chunk| nip44::decrypt(secret_key, &encrypted.npub, chunk))
+                .collect::<Result<String, _>>()
+        });
+        let json = json.map_err(|_| Error::Storage("Backup could not be decrypted".to_string()))?;
+        let mut value = deserialize::<Value>(&json)?;
+        migrate(&mut value)?;
+        let backup = serde_json::from_value::<Self>(value)
+            .map_err(|e| Error::Storage(format!("Invalid backup: {e}")))?;
+        for session in &backup.sessions {
+            session.check()?;
+        }
+        Ok(backup)
+    }
+
+    /// Restores the backup into `storage`.
+    ///
+    /// Sessions and watched escrows already in `storage` are kept as they are,
+    /// so restoring never loses local progress, and contacts are merged.
+    /// The [`Backup::relays`] are left for the caller to apply.
+    pub(crate) fn restore(&self, storage: &impl Storage) -> Result<RestoreSummary, Error> {
+        let mut summary = RestoreSummary::default();
+        let existing = Session::list(storage)?;
+        for session in &self.sessions {
+            if !existing.contains(&session.id()?) {
+                session.save(storage)?;
+                summary.sessions += 1;
+            }
+        }
+        let existing = WatchSession::list(storage)?;
+        for watch in &self.watched {
+            if !existing.contains(&watch.funding_txid) {
+                watch.save(storage)?;
+                summary.watched += 1;
+            }
+        }
+        let mut address_book = AddressBook::load(storage)?;
+        let local = address_book.contacts().len();
+        for contact in self.address_book.contacts() {
+            if address_book.get(&contact.npub).is_none() {
+                address_book.upsert(contact.clone());
+            }
+        }
+        summary.contacts = address_book.contacts().len() - local;
+        address_book.save(storage)?;
+        Ok(summary)
+    }
+}
+
+/// Upgrades a backup JSON `value` to [`BACKUP_VERSION`].
+fn migrate(value: &mut Value) -> Result<(), Error> {
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .and_then(|version| u8::try_from(version).ok())
+        .ok_or_else(|| Error::Storage("Backup has no version".to_string()))?;
+    if version == 0 || version > BACKUP_VERSION {
+        return Err(Error::Storage(format!(
+            "Backup version {version} is not supported, update scrow to restore it"
+        )));
+    }
+    for migration in &MIGRATIONS[usize::from(version - 1)..] {
+        migration(value)?;
+    }
+    value["version"] = Value::from(BACKUP_VERSION);
+    Ok(())
+}
+
+/// Splits `text` in chunks of at most [`CHUNK_LEN`] bytes, on character boundaries.
+pub(crate) fn split_chunks(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut end = rest.len().min(CHUNK_LEN);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Amount, Network, Txid, hashes::Hash};
+    use nostr::{Keys, Timestamp};
+
+    use super::*;
+    use crate::{
+        address_book::Contact,
+        protocol::{DEFAULT_OFFER_VALIDITY, Handshake, Offer, Role, offer},
+        scripts::{EscrowConfig, ScriptTemplate},
+        storage::MemoryStorage,
+        watch::WATCH_SESSION_VERSION,
+    };
+
+    #[test]
+    fn backup_roundtrip() {
+        let nsec = SecretNsec::generate();
+        let contact = |label: &str| Contact {
+            npub: SecretNsec::generate().public_key(),
+            label: label.to_string(),
+            payout_address: None,
+            trust_notes: "x".repeat(CHUNK_LEN),
+        };
+        let storage = MemoryStorage::default();
+        let mut address_book = AddressBook::default();
+        address_book.upsert(contact("alice"));
+        address_book.upsert(contact("bob"));
+        address_book.save(&storage).unwrap();
+        let watch = WatchSession {
+            version: WATCH_SESSION_VERSION,
+            config: EscrowConfig {
+                npub_1: nsec.public_key(),
+                npub_2: SecretNsec::generate().public_key(),
+                npub_arbitrator: None,
+                timelock_duration: None,
+                network: Network::Regtest,
+                template: ScriptTemplate::V1,
+            },
+            funding_txid: Txid::from_byte_array([1; 32]),
+            label: "order".to_string(),
+        };
+        watch.save(&storage).unwrap();
+        let keys = Keys::generate();
+        let now = Timestamp::now();
+        let offer = Offer {
+            role: Role::Buyer,
+            amount_seller: Amount::ZERO,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(keys.public_key(), None)
+        };
+        let (handshake, _) = Handshake::offer(keys.secret_key(), offer, now).unwrap();
+        let session = Session::new(handshake);
+        session.save(&storage).unwrap();
+
+        let backup = Backup::collect(&storage, "wss://nos.lol").unwrap();
+        let encrypted = backup.encrypt(&nsec).unwrap();
+        assert!(!encrypted.contains("alice"));
+        let envelope = deserialize::<EncryptedBackup>(&encrypted).unwrap();
+        assert!(envelope.chunks.len() > 1);
+        assert!(Backup::decrypt(&encrypted, &SecretNsec::generate()).is_err());
+        let decrypted = Backup::decrypt(&encrypted, &nsec).unwrap();
+        assert_eq!(decrypted, backup);
+        assert_eq!(decrypted.relays, vec!["wss://nos.lol"]);
+
+        // Restoring on a new device brings everything, restoring twice adds nothing.
+        let device = MemoryStorage::default();
+        let summary = decrypted.restore(&device).unwrap();
+        assert_eq!(summary.sessions, 1);
+        assert_eq!(summary.watched, 1);
+        assert_eq!(summary.contacts, 2);
+        assert_eq!(
+            WatchSession::load(&device, &watch.funding_txid).unwrap(),
+            Some(watch)
+        );
+        assert_eq!(
+            Session::load(&device, &session.id().unwrap()).unwrap(),
+            Some(session)
+        );
+        assert_eq!(
+            decrypted.restore(&device).unwrap(),
+            RestoreSummary::default()
+        );
+
+        // Backups from a newer version are rejected.
+        let mut value = serde_json::to_value(&backup).unwrap();
+        value["version"] = Value::from(BACKUP_VERSION + 1);
+        assert!(migrate(&mut value).is_err());
+    }
+}
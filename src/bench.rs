@@ -0,0 +1,240 @@
+//! Benchmarks of script building and signing.
+//!
+//! They time the hot paths of every escrow: building the leaf scripts, deriving the address,
+//! computing sighashes, verifying signatures and assembling witnesses, for both the single leaf of a collaborative
+//! escrow and the three leaves of a dispute escrow, so caching work can be measured and
+//! regressions caught as the tap tree grows.
+//!
+//! This module only builds the [`Workload`]s, [criterion](https://docs.rs/criterion) times them
+//! from `benches/escrow.rs` and compares each run with the saved baseline:
+//!
+//! ```text
+//! cargo bench --bench escrow
+//! ```
+
+use std::{fmt, hint::black_box, rc::Rc};
+
+use bitcoin::{Amount, Network, Transaction, TxOut, Txid, absolute, hashes::Hash};
+
+use crate::{
+    invariants::SigningInvariants,
+    scripts::{EscrowConfig, EscrowContext, EscrowScript, ScriptTemplate, escrow_scripts},
+    secret::SecretNsec,
+    sign::{
+        BatchSigner, LeafSignatures, combine_leaf_signatures, combine_signatures,
+        script_spend_message, verify_leaf_signatures,
+    },
+    tx::resolution_tx,
+};
+
+/// A named piece of work to time.
+pub struct Workload {
+    /// Name of the benchmark, with the escrow variant it runs on.
+    pub name: String,
+    /// The work, its result passed through [`black_box`].
+    work: Box<dyn FnMut()>,
+}
+
+impl fmt::Debug for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Workload")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl Workload {
+    /// The workload `name` running `work`.
+    fn new<T>(name: String, mut work: impl FnMut() -> T + 'static) -> Self {
+        Self {
+            name,
+            work: Box::new(move || {
+                black_box(work());
+            }),
+        }
+    }
+
+    /// Runs the work once.
+    pub fn run(&mut self) {
+        (self.work)();
+    }
+}
+
+/// An escrow with a funded spend to benchmark.
+struct Fixture {
+    /// Name of the escrow variant.
+    name: &'static str,
+    /// Signers of leaf `A`.
+    nsecs: [SecretNsec; 2],
+    /// Terms of the escrow.
+    config: EscrowConfig,
+    /// Precomputed Taproot data of the escrow.
+    context: EscrowContext,
+    /// Unsigned spend of the escrow through leaf `A`.
+    tx: Transaction,
+    /// Invariants approving `tx`, spending the escrow output.
+    invariants: SigningInvariants,
+}
+
+impl Fixture {
+    /// The escrow, with a dispute path if `arbitrated`.
+    fn new(arbitrated: bool) -> Self {
+        let nsecs = [SecretNsec::generate(), SecretNsec::generate()];
+        let config = EscrowConfig {
+            npub_1: nsecs[0].public_key(),
+            npub_2: nsecs[1].public_key(),
+            npub_arbitrator: arbitrated.then(|| SecretNsec::generate().public_key()),
+            timelock_duration: arbitrated.then_some(144),
+            network: Network::Regtest,
+            template: ScriptTemplate::V1,
+        };
+        let context = config.context().unwrap();
+        let amount = Amount::from_sat(100_000);
+        let tx = resolution_tx(
+            amount,
+            Txid::from_byte_array([1; 32]),
+            0,
+            context.address(),
+            Amount::from_sat(1_000),
+            absolute::LockTime::ZERO,
+        );
+        let prevouts = vec![TxOut {
+            value: amount,
+            script_pubkey: context.address().script_pubkey(),
+        }];
+        let invariants = SigningInvariants::of_local_tx(&tx, prevouts, vec![None]);
+        Self {
+            name: if arbitrated {
+                "dispute"
+            } else {
+                "collaborative"
+            },
+            nsecs,
+            config,
+            context,
+            tx,
+            invariants,
+        }
+    }
+
+    /// Both signatures of leaf `A`.
+    fn signatures(&self) -> LeafSignatures {
+        let script = self.config.script(EscrowScript::A).unwrap();
+        let mut signer = BatchSigner::new(&self.tx, &self.invariants).unwrap();
+        let mut signatures = LeafSignatures::new(self.tx.compute_txid(), 0, EscrowScript::A);
+        for nsec in &self.nsecs {
+            signatures.insert(nsec.public_key(), signer.sign(0, &script, nsec).unwrap());
+        }
+        signatures
+    }
+
+    /// Every benchmark of the escrow.
+    fn workloads(self) -> Vec<Workload> {
+        let fixture = Rc::new(self);
+        let script = fixture.config.script(EscrowScript::A).unwrap();
+        let signatures = fixture.signatures();
+        let name = |bench: &str| format!("{bench} ({})", fixture.name);
+        let config = fixture.config;
+        vec![
+            Workload::new(name("escrow_scripts"), move || {
+                config
+                    .leaves()
+                    .iter()
+                    .map(|leaf| {
+                        escrow_scripts(
+                            &config.npub_1,
+                            &config.npub_2,
+                            config.npub_arbitrator.as_ref(),
+                            config.timelock_duration,
+                            *leaf,
+                        )
+                        .unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            }),
+            Workload::new(name("address"), move || config.address().unwrap()),
+            Workload::new(name("escrow_context"), move || config.context().unwrap()),
+            Workload::new(name("sighash"), {
+                let (fixture, script) = (fixture.clone(), script.clone());
+                move || {
+                    script_spend_message(&fixture.tx, 0, &fixture.invariants.prevouts, &script)
+                        .unwrap()
+                }
+            }),
+            Workload::new(name("sign"), {
+                let fixture = fixture.clone();
+                move || {
+                    BatchSigner::new(&fixture.tx, &fixture.invariants)
+                        .unwrap()
+                        .sign_leaf(0, &fixture.context, EscrowScript::A, &fixture.nsecs[0])
+                        .unwrap()
+                }
+            }),
+            Workload::new(name("witness"), {
+                let (fixture, signatures) = (fixture.clone(), signatures.clone());
+                move || {
+                    combine_signatures(
+                        fixture.tx.clone(),
+                        0,
+                        signatures.ordered(&config).unwrap(),
+                        &script,
+                        &config.spend_info().unwrap(),
+                    )
+                    .unwrap()
+                }
+            }),
+            Workload::new(name("verify"), {
+                let (fixture, signatures) = (fixture.clone(), signatures.clone());
+                move || {
+                    verify_leaf_signatures(
+                        std::slice::from_ref(&signatures),
+                        &fixture.tx,
+                        &fixture.invariants.prevouts,
+                        &fixture.context,
+                    )
+                    .unwrap()
+                }
+            }),
+            Workload::new(name("witness_cached"), {
+                let fixture = fixture.clone();
+                move || {
+                    combine_leaf_signatures(fixture.tx.clone(), &signatures, &fixture.context)
+                        .unwrap()
+                }
+            }),
+        ]
+    }
+}
+
+/// Every benchmark, on a collaborative and a dispute escrow.
+pub fn workloads() -> Vec<Workload> {
+    [Fixture::new(false), Fixture::new(true)]
+        .into_iter()
+        .flat_map(Fixture::workloads)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workloads_run() {
+        // Benchmarks must never time failing paths.
+        for arbitrated in [false, true] {
+            let fixture = Fixture::new(arbitrated);
+            let signed = combine_leaf_signatures(
+                fixture.tx.clone(),
+                &fixture.signatures(),
+                &fixture.context,
+            )
+            .unwrap();
+            assert_eq!(signed.input[0].witness.len(), 4);
+        }
+        let mut workloads = workloads();
+        assert_eq!(workloads.len(), 16);
+        for workload in &mut workloads {
+            workload.run();
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! [BIP-21](https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki) payment requests
+//! for funding escrows.
+//!
+//! The funding step is shown as a `bitcoin:` URI with the escrow address and the exact amount,
+//! so a wallet can fund the escrow with a single scan,
+//! and funding transactions can be matched against the request.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{
+    Address, Amount, Denomination, Network, OutPoint, Transaction, address::NetworkUnchecked,
+};
+
+use crate::error::Error;
+
+/// URI scheme of BIP-21 payment requests.
+const SCHEME: &str = "bitcoin:";
+
+/// A BIP-21 payment request for the funding of an escrow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PaymentRequest {
+    /// Address to pay to.
+    pub(crate) address: Address<NetworkUnchecked>,
+    /// Exact amount to pay.
+    pub(crate) amount: Option<Amount>,
+    /// Label of the payment, shown by wallets.
+    pub(crate) label: Option<String>,
+    /// Message of the payment, shown by wallets.
+    pub(crate) message: Option<String>,
+    /// [BIP-78](https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki) payjoin endpoint
+    /// of the receiver, the `pj` parameter, see [`crate::payjoin`].
+    pub(crate) payjoin: Option<String>,
+}
+
+impl PaymentRequest {
+    /// Creates the request funding the escrow `escrow_id` at `address` with exactly `amount`.
+    pub(crate) fn escrow_funding(address: &Address, amount: Amount, escrow_id: &str) -> Self {
+        Self {
+            address: address.as_unchecked().clone(),
+            amount: Some(amount),
+            label: Some(format!("Escrow {escrow_id}")),
+            message: Some("Satoshi Escrow funding".to_string()),
+            payjoin: None,
+        }
+    }
+
+    /// Lets the payer fund the request with a payjoin through the receiver's `endpoint`.
+    pub(crate) fn with_payjoin(self, endpoint: impl Into<String>) -> Self {
+        Self {
+            payjoin: Some(endpoint.into()),
+            ..self
+        }
+    }
+
+    /// The address to pay to, if it is valid on `network`.
+    pub(crate) fn address(&self, network: Network) -> Result<Address, Error> {
+        Ok(self.address.clone().require_network(network)?)
+    }
+
+    /// Finds the output of `tx` paying this request.
+    ///
+    /// The output must pay to the address, and the exact amount if the request has one.
+    pub(crate) fn find_payment(&self, tx: &Transaction) -> Option<OutPoint> {
+        let script_pubkey = self.address.assume_checked_ref().script_pubkey();
+        let vout = tx.output.iter().position(|output| {
+            output.script_pubkey == script_pubkey
+                && self.amount.is_none_or(|amount| output.value == amount)
+        })?;
+        Some(OutPoint::new(tx.compute_txid(), vout as u32))
+    }
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{SCHEME}{}", self.address.assume_checked_ref())?;
+        let mut separator = '?';
+        let mut param = |f: &mut fmt::Formatter<'_>, key: &str, value: &str| {
+            let result = write!(f, "{separator}{key}={}", percent_encode(value));
+            separator = '&';
+            result
+        };
+        if let Some(amount) = self.amount {
+            param(f, "amount", &amount.to_string_in(Denomination::Bitcoin))?;
+        }
+        if let Some(label) = &self.label {
+            param(f, "label", label)?;
+        }
+        if let Some(message) = &self.message {
+            param(f, "message", message)?;
+        }
+        if let Some(payjoin) = &self.payjoin {
+            param(f, "pj", payjoin)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = Error;
+
+    /// Parses a `bitcoin:` URI.
+    ///
+    /// Unknown parameters are ignored, except required `req-` ones, which are rejected.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let rest = s
+            .get(..SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(SCHEME))
+            .map(|_| &s[SCHEME.len()..])
+            .ok_or_else(|| Error::WrongInputs(format!("Not a {SCHEME} URI")))?;
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut request = Self {
+            address: address.parse()?,
+            amount: None,
+            label: None,
+            message: None,
+            payjoin: None,
+        };
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = percent_decode(value)?;
+            match key {
+                "amount" => {
+                    let amount = Amount::from_str_in(&value, Denomination::Bitcoin)
+                        .map_err(|e| Error::WrongInputs(format!("Invalid amount {value}: {e}")))?;
+                    request.amount = Some(amount);
+                }
+                "label" => request.label = Some(value),
+                "message" => request.message = Some(value),
+                "pj" => request.payjoin = Some(value),
+                key if key.starts_with("req-") => {
+                    return Err(Error::WrongInputs(format!(
+                        "Unsupported required parameter {key}"
+                    )));
+                }
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// Percent-encodes everything but RFC 3986 unreserved characters.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Decodes a percent-encoded UTF-8 value.
+fn percent_decode(value: &str) -> Result<String, Error> {
+    let invalid = || Error::WrongInputs(format!("Invalid percent-encoding in {value}"));
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut iter = value.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [
+                iter.next().ok_or_else(invalid)?,
+                iter.next().ok_or_else(invalid)?,
+            ];
+            let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{TxOut, absolute, transaction};
+    use nostr::Keys;
+
+    use crate::util::npub_to_address;
+
+    use super::*;
+
+    #[test]
+    fn payment_request_roundtrip() {
+        let address = npub_to_address(&Keys::generate().public_key(), Network::Testnet).unwrap();
+        let request = PaymentRequest::escrow_funding(&address, Amount::from_sat(1_050_000), "42");
+        let uri = request.to_string();
+        assert_eq!(
+            uri,
+            format!(
+                "bitcoin:{address}?amount=0.0105&label=Escrow%2042&message=Satoshi%20Escrow%20funding"
+            )
+        );
+        assert_eq!(uri.parse::<PaymentRequest>().unwrap(), request);
+        assert_eq!(request.address(Network::Testnet).unwrap(), address);
+        assert!(request.address(Network::Bitcoin).is_err());
+
+        let parsed = format!("BITCOIN:{address}?amount=1&foo=bar&label=caf%C3%A9")
+            .parse::<PaymentRequest>()
+            .unwrap();
+        assert_eq!(parsed.amount, Some(Amount::ONE_BTC));
+        assert_eq!(parsed.label.as_deref(), Some("café"));
+        assert_eq!(parsed.payjoin, None);
+
+        let payjoin = request.clone().with_payjoin("https://example.com/pj?v=1");
+        let uri = payjoin.to_string();
+        assert!(uri.ends_with("&pj=https%3A%2F%2Fexample.com%2Fpj%3Fv%3D1"));
+        assert_eq!(uri.parse::<PaymentRequest>().unwrap(), payjoin);
+        assert!(
+            format!("bitcoin:{address}?req-pop=1")
+                .parse::<PaymentRequest>()
+                .is_err()
+        );
+        assert!(
+            format!("bitcoin:{address}?amount=abc")
+                .parse::<PaymentRequest>()
+                .is_err()
+        );
+        assert!(
+            format!("bitcoin:{address}?label=%ZZ")
+                .parse::<PaymentRequest>()
+                .is_err()
+        );
+        assert!(address.to_string().parse::<PaymentRequest>().is_err());
+    }
+
+    #[test]
+    fn find_payment() {
+        let address = npub_to_address(&Keys::generate().public_key(), Network::Regtest).unwrap();
+        let request = PaymentRequest::escrow_funding(&address, Amount::from_sat(100_000), "42");
+        let mut tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(99_999),
+                    script_pubkey: address.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: address.script_pubkey(),
+                },
+            ],
+        };
+        assert_eq!(
+            request.find_payment(&tx),
+            Some(OutPoint::new(tx.compute_txid(), 1))
+        );
+        tx.output.pop();
+        assert_eq!(request.find_payment(&tx), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! Mutual-collateral escrows, where both parties lock a bond next to the price.
+//!
+//! The buyer funds the price and its bond, the seller its bond,
+//! for instance with a [`CofundingBuilder`](crate::cofunding::CofundingBuilder).
+//! A bond is returned to its owner unless the arbitrator rules against them,
+//! which makes walking away from a trade costly for both sides.
+//!
+//! The escrow uses the usual dispute tree of [`escrow_scripts`](crate::scripts::escrow_scripts):
+//!
+//! - [`BondOutcome::Completed`] and [`BondOutcome::Refunded`] are agreed through leaf `A`.
+//!   A refund is also pre-signed through leaf `A` with an absolute lock time,
+//!   see [`BondedEscrow::timeout_tx`], so the funds are never stuck.
+//! - [`BondOutcome::BuyerWins`] and [`BondOutcome::SellerWins`] are decided by the arbitrator
+//!   through leaves `B` and `C`, the losing party forfeiting its bond to the winner.
+
+use bitcoin::{Amount, OutPoint, Transaction, absolute};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    scripts::{EscrowConfig, EscrowScript},
+    tx::{Split, split_resolution_tx, verify_split_resolution},
+};
+
+/// The amounts locked in a bonded escrow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct BondTerms {
+    /// Price paid by the buyer to the seller.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) price: Amount,
+    /// Collateral locked by the buyer.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) buyer_bond: Amount,
+    /// Collateral locked by the seller.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) seller_bond: Amount,
+}
+
+impl BondTerms {
+    /// What the buyer and the seller pay into the escrow,
+    /// the `amount_buyer` and `amount_seller` of the [`Offer`](crate::protocol::Offer).
+    pub(crate) fn amounts(&self) -> Result<(Amount, Amount), Error> {
+        let buyer = self
+            .price
+            .checked_add(self.buyer_bond)
+            .ok_or(Error::Rounding)?;
+        Ok((buyer, self.seller_bond))
+    }
+
+    /// Total amount locked in the escrow.
+    pub(crate) fn total(&self) -> Result<Amount, Error> {
+        let (buyer, seller) = self.amounts()?;
+        buyer.checked_add(seller).ok_or(Error::Rounding)
+    }
+
+    /// What the buyer and the seller get for `outcome`, before the fee.
+    pub(crate) fn gross_payouts(&self, outcome: BondOutcome) -> Result<(Amount, Amount), Error> {
+        let total = self.total()?;
+        let buyer = match outcome {
+            // The price goes to the seller, each bond back to its owner.
+            BondOutcome::Completed => self.buyer_bond,
+            // The price goes back to the buyer, each bond back to its owner.
+            BondOutcome::Refunded => self.price + self.buyer_bond,
+            // The seller forfeits its bond to the buyer.
+            BondOutcome::BuyerWins => total,
+            // The buyer forfeits its bond to the seller.
+            BondOutcome::SellerWins => Amount::ZERO,
+        };
+        Ok((buyer, total - buyer))
+    }
+}
+
+/// How a bonded escrow ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub(crate) enum BondOutcome {
+    /// The trade went through: the seller gets the price and both bonds are returned.
+    Completed,
+    /// The trade was called off or timed out: the buyer is refunded and both bonds are returned.
+    Refunded,
+    /// The arbitrator ruled for the buyer, who gets everything back plus the seller's bond.
+    BuyerWins,
+    /// The arbitrator ruled for the seller, who gets the price plus both bonds.
+    SellerWins,
+}
+
+impl BondOutcome {
+    /// The leaf the outcome is spent through.
+    pub(crate) fn escrow_script(self) -> EscrowScript {
+        match self {
+            BondOutcome::Completed | BondOutcome::Refunded => EscrowScript::A,
+            BondOutcome::BuyerWins => EscrowScript::B,
+            BondOutcome::SellerWins => EscrowScript::C,
+        }
+    }
+}
+
+/// A dispute escrow holding the price and both parties' bonds,
+/// with the buyer as first participant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct BondedEscrow {
+    /// The escrow, with the buyer as `npub_1` and the seller as `npub_2`.
+    pub(crate) config: EscrowConfig,
+    /// The locked amounts.
+    pub(crate) terms: BondTerms,
+}
+
+impl BondedEscrow {
+    /// Creates a bonded escrow.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow has no arbitrator or timelock to rule on disputes,
+    /// the price is zero, or the amounts overflow.
+    pub(crate) fn new(config: EscrowConfig, terms: BondTerms) -> Result<Self, Error> {
+        if config.npub_arbitrator.is_none() || config.timelock_duration.is_none() {
+            return Err(Error::WrongInputs(
+                "A bonded escrow needs an arbitrator and a timelock".to_string(),
+            ));
+        }
+        if terms.price == Amount::ZERO {
+            return Err(Error::WrongInputs("The price must not be zero".to_string()));
+        }
+        terms.total()?;
+        config.spend_info()?;
+        Ok(Self { config, terms })
+    }
+
+    /// What the buyer and the seller get for `outcome`, after paying `fee`.
+    ///
+    /// The fee is shared in proportion to the payouts, rounding in favor of the buyer.
+    pub(crate) fn payouts(
+        &self,
+        outcome: BondOutcome,
+        fee: Amount,
+    ) -> Result<(Amount, Amount), Error> {
+        let total = self.terms.total()?;
+        let (buyer, seller) = self.terms.gross_payouts(outcome)?;
+        if fee > total {
+            return Err(Error::Rounding);
+        }
+        let fee_buyer = u64::try_from(
+            u128::from(fee.to_sat()) * u128::from(buyer.to_sat()) / u128::from(total.to_sat()),
+        )
+        .map(Amount::from_sat)
+        .map_err(|_| Error::Rounding)?;
+        let fee_seller = fee - fee_buyer;
+        Ok((
+            buyer.checked_sub(fee_buyer).ok_or(Error::Rounding)?,
+            seller.checked_sub(fee_seller).ok_or(Error::Rounding)?,
+        ))
+    }
+
+    /// Builds the resolution [`Transaction`] of `outcome`, spending the escrow `funding` output.
+    ///
+    /// Agreed outcomes are signed by both parties, arbitrated ones by the arbitrator
+    /// and the winner once the dispute timelock expired.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the fee exceeds the escrow or a payout is dust.
+    pub(crate) fn resolution_tx(
+        &self,
+        outcome: BondOutcome,
+        funding: OutPoint,
+        fee: Amount,
+        lock_time: absolute::LockTime,
+    ) -> Result<Transaction, Error> {
+        let (buyer, _) = self.payouts(outcome, fee)?;
+        #[cfg(debug_assertions)]
+        trace!(?outcome, %buyer, %fee, "bonded escrow buyer payout");
+        split_resolution_tx(
+            &self.config,
+            funding,
+            self.terms.total()?,
+            Split::First(buyer),
+            fee,
+            outcome.escrow_script(),
+            lock_time,
+        )
+    }
+
+    /// Builds the refund [`Transaction`] that both parties sign before funding,
+    /// spendable once the chain reaches `timeout_height`.
+    ///
+    /// It returns the price to the buyer and both bonds if nothing else happened by then,
+    /// so `timeout_height` must leave time for the dispute timelock to expire
+    /// and the arbitrator to rule first.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `timeout_height` is not a valid block height.
+    pub(crate) fn timeout_tx(
+        &self,
+        funding: OutPoint,
+        fee: Amount,
+        timeout_height: u32,
+    ) -> Result<Transaction, Error> {
+        let lock_time = absolute::LockTime::from_height(timeout_height)
+            .map_err(|_| Error::WrongInputs(format!("Invalid timeout height {timeout_height}")))?;
+        if lock_time == absolute::LockTime::ZERO {
+            return Err(Error::WrongInputs(
+                "The timeout must not be zero".to_string(),
+            ));
+        }
+        self.resolution_tx(BondOutcome::Refunded, funding, fee, lock_time)
+    }
+
+    /// Checks that `tx` resolves the escrow as `outcome` with `fee`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `tx` pays anyone else or pays the parties other amounts.
+    pub(crate) fn verify_resolution(
+        &self,
+        tx: &Transaction,
+        outcome: BondOutcome,
+        fee: Amount,
+    ) -> Result<(), Error> {
+        let payouts = verify_split_resolution(tx, &self.config, self.terms.total()?, fee)?;
+        if payouts != self.payouts(outcome, fee)? {
+            return Err(Error::WrongInputs(format!(
+                "Transaction does not pay the {outcome:?} outcome"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, Sequence, Txid, hashes::Hash};
+    use nostr::Keys;
+
+    use super::*;
+    use crate::{scripts::ScriptTemplate, util::npub_to_address};
+
+    #[test]
+    fn bond_outcomes() {
+        let (buyer, seller, arbitrator) = (
+            Keys::generate().public_key(),
+            Keys::generate().public_key(),
+            Keys::generate().public_key(),
+        );
+        let config = EscrowConfig {
+            npub_1: buyer,
+            npub_2: seller,
+            npub_arbitrator: Some(arbitrator),
+            timelock_duration: Some(144),
+            network: Network::Regtest,
+            template: ScriptTemplate::V1,
+        };
+        let terms = BondTerms {
+            price: Amount::from_sat(100_000),
+            buyer_bond: Amount::from_sat(10_000),
+            seller_bond: Amount::from_sat(20_000),
+        };
+        assert_eq!(
+            terms.amounts().unwrap(),
+            (Amount::from_sat(110_000), Amount::from_sat(20_000))
+        );
+        assert!(
+            BondedEscrow::new(
+                EscrowConfig {
+                    npub_arbitrator: None,
+                    ..config
+                },
+                terms
+            )
+            .is_err()
+        );
+        let escrow = BondedEscrow::new(config, terms).unwrap();
+
+        let fee = Amount::from_sat(1_300);
+        for (outcome, expected) in [
+            (BondOutcome::Completed, (9_900, 118_800)),
+            (BondOutcome::Refunded, (108_900, 19_800)),
+            (BondOutcome::BuyerWins, (128_700, 0)),
+            (BondOutcome::SellerWins, (0, 128_700)),
+        ] {
+            let expected = (Amount::from_sat(expected.0), Amount::from_sat(expected.1));
+            assert_eq!(
+                escrow.payouts(outcome, fee).unwrap(),
+                expected,
+                "{outcome:?}"
+            );
+        }
+
+        let funding = OutPoint::new(Txid::all_zeros(), 0);
+        let tx = escrow
+            .resolution_tx(
+                BondOutcome::Completed,
+                funding,
+                fee,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+        escrow
+            .verify_resolution(&tx, BondOutcome::Completed, fee)
+            .unwrap();
+        assert!(
+            escrow
+                .verify_resolution(&tx, BondOutcome::Refunded, fee)
+                .is_err()
+        );
+
+        // Arbitrated outcomes pay the winner alone, through its dispute leaf.
+        let tx = escrow
+            .resolution_tx(
+                BondOutcome::SellerWins,
+                funding,
+                fee,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(
+            tx.output[0].script_pubkey,
+            npub_to_address(&seller, Network::Regtest)
+                .unwrap()
+                .script_pubkey()
+        );
+        assert_eq!(tx.input[0].sequence, Sequence::from_consensus(144));
+
+        let timeout = escrow.timeout_tx(funding, fee, 1_000).unwrap();
+        assert_eq!(
+            timeout.lock_time,
+            absolute::LockTime::from_height(1_000).unwrap()
+        );
+        escrow
+            .verify_resolution(&timeout, BondOutcome::Refunded, fee)
+            .unwrap();
+        assert!(escrow.timeout_tx(funding, fee, 0).is_err());
+        assert!(escrow.timeout_tx(funding, fee, 500_000_000).is_err());
+    }
+}
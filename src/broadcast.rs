@@ -0,0 +1,531 @@
+//! Broadcasts transactions to several backends at once, retrying with backoff.
+//!
+//! A single Esplora server going down, or a flaky connection in the browser,
+//! should not keep a signed escrow resolution from reaching the network.
+//! The [`Broadcaster`] submits the transaction to every [`BroadcastBackend`] concurrently,
+//! retries transient failures, and reconciles the results: the transaction is out
+//! as soon as one backend accepted it or already knew it.
+//!
+//! Natively, one's own Bitcoin Core node is also a [`CoreRpcBackend`], which can
+//! submit a [`Package`] of parents and the child paying for them.
+//!
+//! Run [`check_standard`](crate::standardness::check_standard) first, so a transaction
+//! the backends would refuse fails with an error the user can act on.
+
+use std::{fmt, time::Duration};
+
+use bitcoin::Transaction;
+#[cfg(not(target_arch = "wasm32"))]
+use bitcoin::{
+    base64::{Engine, engine::general_purpose::STANDARD},
+    consensus::encode::serialize_hex,
+};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+#[cfg(not(target_arch = "wasm32"))]
+use serde_json::{Value, json};
+
+use crate::{
+    error::Error,
+    esplora::{EsploraClient, broadcast_transaction, create_client},
+    network::Chain,
+    proxy::ProxySettings,
+    runtime::{join_all, sleep},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{package::Package, runtime::post_json_with_headers};
+
+/// Messages of nodes that already have the transaction, in their mempool or in a block.
+const ALREADY_KNOWN: [&str; 4] = [
+    "txn-already-in-mempool",
+    "txn-already-known",
+    "transaction already in block chain",
+    "transaction outputs already in utxo set",
+];
+
+/// A server transactions can be broadcast to.
+///
+/// Implemented over Esplora, and by mocks in tests.
+pub(crate) trait BroadcastBackend {
+    /// Name of the backend, such as its URL.
+    fn name(&self) -> &str;
+
+    /// Submits `transaction` once.
+    async fn submit(&self, transaction: &Transaction) -> Result<(), Error>;
+}
+
+/// An Esplora server, such as mempool.space or one's own node.
+#[derive(Debug)]
+pub(crate) struct EsploraBackend {
+    url: String,
+    client: EsploraClient,
+}
+
+impl EsploraBackend {
+    /// Creates a backend for the Esplora API at `url`.
+    pub(crate) fn new(url: &str, proxies: &ProxySettings) -> Result<Self, Error> {
+        Ok(Self {
+            url: url.to_string(),
+            client: create_client(url, proxies)?,
+        })
+    }
+}
+
+impl BroadcastBackend for EsploraBackend {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn submit(&self, transaction: &Transaction) -> Result<(), Error> {
+        broadcast_transaction(&self.client, transaction).await
+    }
+}
+
+/// A Bitcoin Core node, through its JSON-RPC interface.
+///
+/// Requests use JSON-RPC 2.0, so Bitcoin Core 28 and later answer errors with a success
+/// status and the error in the body, classified like Esplora's.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(crate) struct CoreRpcBackend {
+    url: String,
+    /// `Authorization` header of the RPC user and password, if any.
+    authorization: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CoreRpcBackend {
+    /// Creates a backend for the RPC interface at `url`,
+    /// authenticated with the RPC `user` and `password` if given.
+    pub(crate) fn new(url: &str, credentials: Option<(&str, &str)>) -> Self {
+        Self {
+            url: url.to_string(),
+            authorization: credentials.map(|(user, password)| {
+                format!("Basic {}", STANDARD.encode(format!("{user}:{password}")))
+            }),
+        }
+    }
+
+    /// Calls the RPC `method` with `params`, returning its result.
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`Error::BroadcastRejected`] if the node answered with an error.
+    async fn call(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": "scrow",
+            "method": method,
+            "params": params,
+        });
+        let headers = self
+            .authorization
+            .as_deref()
+            .map(|authorization| vec![("Authorization", authorization)])
+            .unwrap_or_default();
+        let response = post_json_with_headers(&self.url, &body.to_string(), &headers).await?;
+        let mut response: Value = serde_json::from_str(&response)
+            .map_err(|e| Error::Http(format!("{}: malformed RPC response: {e}", self.url)))?;
+        if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+            return Err(Error::BroadcastRejected(
+                error["message"]
+                    .as_str()
+                    .map_or_else(|| error.to_string(), str::to_string),
+            ));
+        }
+        Ok(response
+            .get_mut("result")
+            .map(Value::take)
+            .unwrap_or_default())
+    }
+
+    /// Submits the parents and child of `package` together with `submitpackage`,
+    /// so parents below the minimum relay fee rate are relayed with the child paying for them.
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`Error::BroadcastRejected`] if the node refused any transaction
+    /// of the package.
+    pub(crate) async fn submit_package(&self, package: &Package) -> Result<(), Error> {
+        let transactions = package
+            .transactions()
+            .iter()
+            .map(serialize_hex)
+            .collect::<Vec<_>>();
+        let result = self.call("submitpackage", json!([transactions])).await?;
+        #[cfg(debug_assertions)]
+        trace!(backend = %self.url, %result, "submitted package");
+        let message = result["package_msg"].as_str().unwrap_or_default();
+        if message == "success" {
+            return Ok(());
+        }
+        let errors = result["tx-results"]
+            .as_object()
+            .into_iter()
+            .flat_map(|results| results.values())
+            .filter_map(|result| {
+                Some(format!(
+                    "{}: {}",
+                    result["txid"].as_str()?,
+                    result["error"].as_str()?
+                ))
+            })
+            .collect::<Vec<_>>();
+        Err(Error::BroadcastRejected(if errors.is_empty() {
+            message.to_string()
+        } else {
+            format!("{message} ({})", errors.join(", "))
+        }))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BroadcastBackend for CoreRpcBackend {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn submit(&self, transaction: &Transaction) -> Result<(), Error> {
+        self.call("sendrawtransaction", json!([serialize_hex(transaction)]))
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Esplora URLs to broadcast to: the configured `esplora_endpoint`, which may be one's own node,
+/// and the default server of the `chain`.
+pub(crate) fn backend_urls(esplora_endpoint: &str, chain: Chain) -> Vec<String> {
+    let mut urls = vec![esplora_endpoint.trim().to_string()];
+    let default = chain.esplora_endpoint();
+    if urls[0].trim_end_matches('/') != default {
+        urls.push(default.to_string());
+    }
+    urls.retain(|url| !url.is_empty());
+    urls
+}
+
+/// How a backend answered a broadcast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BroadcastStatus {
+    /// The backend accepted the transaction.
+    Accepted,
+    /// The backend already had the transaction, in its mempool or in a block.
+    AlreadyKnown,
+    /// The backend refused the transaction, such as for a too low fee or an invalid signature.
+    ///
+    /// Retrying won't help.
+    Rejected(String),
+    /// The backend could not be reached, even after retrying.
+    Unreachable(String),
+}
+
+impl BroadcastStatus {
+    /// Classifies the result of a single submission.
+    fn from_result(result: Result<(), Error>) -> Self {
+        let error = match result {
+            Ok(()) => return BroadcastStatus::Accepted,
+            Err(error) => error,
+        };
+        match error.root_cause() {
+            Error::Esplora(esplora_client::Error::HttpResponse { status, message }) => {
+                let lowercase = message.to_lowercase();
+                if ALREADY_KNOWN.iter().any(|known| lowercase.contains(known)) {
+                    BroadcastStatus::AlreadyKnown
+                } else if (400..500).contains(status) && *status != 429 {
+                    BroadcastStatus::Rejected(message.clone())
+                } else {
+                    BroadcastStatus::Unreachable(format!("HTTP status {status}"))
+                }
+            }
+            // Bitcoin Core RPC errors.
+            Error::BroadcastRejected(message) => {
+                let lowercase = message.to_lowercase();
+                if ALREADY_KNOWN.iter().any(|known| lowercase.contains(known)) {
+                    BroadcastStatus::AlreadyKnown
+                } else {
+                    BroadcastStatus::Rejected(message.clone())
+                }
+            }
+            _ => BroadcastStatus::Unreachable(error.to_string()),
+        }
+    }
+
+    /// Whether the transaction reached the network through the backend.
+    pub(crate) fn is_success(&self) -> bool {
+        matches!(
+            self,
+            BroadcastStatus::Accepted | BroadcastStatus::AlreadyKnown
+        )
+    }
+}
+
+impl fmt::Display for BroadcastStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BroadcastStatus::Accepted => f.write_str("accepted"),
+            BroadcastStatus::AlreadyKnown => f.write_str("already known"),
+            BroadcastStatus::Rejected(reason) => write!(f, "rejected: {reason}"),
+            BroadcastStatus::Unreachable(reason) => write!(f, "unreachable: {reason}"),
+        }
+    }
+}
+
+/// How often and how long to retry a backend that could not be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    /// Attempts per backend, including the first one.
+    pub(crate) max_attempts: u32,
+    /// Wait before the first retry, doubled after every retry.
+    pub(crate) initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Results of broadcasting a transaction to every backend.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct BroadcastReport {
+    /// The final status of each backend, with its name, in backend order.
+    pub(crate) statuses: Vec<(String, BroadcastStatus)>,
+}
+
+impl BroadcastReport {
+    /// Reconciles the backends' answers.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no backend accepted or already knew the transaction,
+    /// with [`Error::BroadcastRejected`] if any of them refused it.
+    pub(crate) fn outcome(&self) -> Result<(), Error> {
+        if self.statuses.iter().any(|(_, status)| status.is_success()) {
+            return Ok(());
+        }
+        if let Some((_, BroadcastStatus::Rejected(reason))) = self
+            .statuses
+            .iter()
+            .find(|(_, status)| matches!(status, BroadcastStatus::Rejected(_)))
+        {
+            return Err(Error::BroadcastRejected(reason.clone()));
+        }
+        Err(Error::Http(format!("No backend could be reached: {self}")))
+    }
+}
+
+impl fmt::Display for BroadcastReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let statuses = self
+            .statuses
+            .iter()
+            .map(|(name, status)| format!("{name} {status}"))
+            .collect::<Vec<_>>();
+        f.write_str(&statuses.join(", "))
+    }
+}
+
+/// Broadcasts transactions to several backends concurrently.
+#[derive(Debug)]
+pub(crate) struct Broadcaster<B> {
+    backends: Vec<B>,
+    retry: RetryPolicy,
+}
+
+impl<B: BroadcastBackend> Broadcaster<B> {
+    /// Creates a broadcaster over `backends`.
+    pub(crate) fn new(backends: Vec<B>, retry: RetryPolicy) -> Self {
+        Self { backends, retry }
+    }
+
+    /// Submits `transaction` to every backend concurrently, retrying unreachable ones.
+    ///
+    /// Call [`BroadcastReport::outcome`] on the result to know whether the broadcast succeeded.
+    pub(crate) async fn broadcast(&self, transaction: &Transaction) -> BroadcastReport {
+        let statuses = join_all(
+            self.backends
+                .iter()
+                .map(|backend| self.broadcast_to(backend, transaction)),
+        )
+        .await;
+        let report = BroadcastReport {
+            statuses: self
+                .backends
+                .iter()
+                .map(|backend| backend.name().to_string())
+                .zip(statuses)
+                .collect(),
+        };
+        #[cfg(debug_assertions)]
+        trace!(txid = %transaction.compute_txid(), %report, "broadcast transaction");
+        report
+    }
+
+    /// Submits `transaction` to a single backend, retrying with exponential backoff
+    /// while it can't be reached.
+    async fn broadcast_to(&self, backend: &B, transaction: &Transaction) -> BroadcastStatus {
+        let mut backoff = self.retry.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let status = BroadcastStatus::from_result(backend.submit(transaction).await);
+            #[cfg(debug_assertions)]
+            trace!(backend = backend.name(), %attempt, %status, "broadcast attempt");
+            if !matches!(status, BroadcastStatus::Unreachable(_))
+                || attempt >= self.retry.max_attempts
+            {
+                return status;
+            }
+            sleep(backoff).await;
+            backoff = backoff.saturating_mul(2);
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use bitcoin::{absolute, transaction};
+
+    use super::*;
+
+    /// Backend answering with a scripted sequence of results, then with the last one.
+    #[derive(Debug)]
+    struct MockBackend {
+        name: String,
+        answers: Vec<Option<(u16, &'static str)>>,
+        attempts: Cell<usize>,
+    }
+
+    impl MockBackend {
+        fn new(name: &str, answers: Vec<Option<(u16, &'static str)>>) -> Self {
+            Self {
+                name: name.to_string(),
+                answers,
+                attempts: Cell::new(0),
+            }
+        }
+    }
+
+    impl BroadcastBackend for MockBackend {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn submit(&self, _transaction: &Transaction) -> Result<(), Error> {
+            let attempt = self.attempts.get();
+            self.attempts.set(attempt + 1);
+            match self.answers[attempt.min(self.answers.len() - 1)] {
+                None => Ok(()),
+                Some((status, message)) => {
+                    Err(Error::Esplora(esplora_client::Error::HttpResponse {
+                        status,
+                        message: message.to_string(),
+                    }))
+                }
+            }
+        }
+    }
+
+    fn mock_broadcaster(backends: Vec<MockBackend>) -> Broadcaster<MockBackend> {
+        Broadcaster::new(
+            backends,
+            RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(1),
+            },
+        )
+    }
+
+    fn transaction() -> Transaction {
+        Transaction {
+            version: transaction::Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn fan_out_and_reconcile() {
+        const ALREADY_IN_MEMPOOL: &str =
+            r#"sendrawtransaction RPC error: {"code":-27,"message":"txn-already-in-mempool"}"#;
+        const MIN_RELAY_FEE: &str =
+            r#"sendrawtransaction RPC error: {"code":-26,"message":"min relay fee not met"}"#;
+
+        // Flaky backends are retried, and a known transaction counts as broadcast.
+        let broadcaster = mock_broadcaster(vec![
+            MockBackend::new("own node", vec![Some((503, "")), None]),
+            MockBackend::new("esplora", vec![Some((400, ALREADY_IN_MEMPOOL))]),
+            MockBackend::new("mempool.space", vec![Some((502, ""))]),
+        ]);
+        let report = broadcaster.broadcast(&transaction()).await;
+        assert_eq!(
+            report
+                .statuses
+                .iter()
+                .map(|(_, status)| status.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                BroadcastStatus::Accepted,
+                BroadcastStatus::AlreadyKnown,
+                BroadcastStatus::Unreachable("HTTP status 502".to_string()),
+            ]
+        );
+        assert_eq!(broadcaster.backends[0].attempts.get(), 2);
+        // Rejections and known transactions are not retried.
+        assert_eq!(broadcaster.backends[1].attempts.get(), 1);
+        assert_eq!(broadcaster.backends[2].attempts.get(), 3);
+        assert!(report.outcome().is_ok());
+
+        // A policy rejection is reported over unreachable backends.
+        let broadcaster = mock_broadcaster(vec![
+            MockBackend::new("esplora", vec![Some((400, MIN_RELAY_FEE))]),
+            MockBackend::new("mempool.space", vec![Some((429, ""))]),
+        ]);
+        let report = broadcaster.broadcast(&transaction()).await;
+        assert!(matches!(
+            report.outcome(),
+            Err(Error::BroadcastRejected(reason)) if reason == MIN_RELAY_FEE
+        ));
+        assert_eq!(broadcaster.backends[0].attempts.get(), 1);
+
+        let broadcaster =
+            mock_broadcaster(vec![MockBackend::new("esplora", vec![Some((500, ""))])]);
+        let report = broadcaster.broadcast(&transaction()).await;
+        assert!(matches!(report.outcome(), Err(Error::Http(_))));
+
+        // Bitcoin Core RPC errors are classified like Esplora's.
+        let rpc_error = |message: &str| {
+            BroadcastStatus::from_result(Err(Error::BroadcastRejected(message.to_string())))
+        };
+        assert_eq!(
+            rpc_error("txn-already-known"),
+            BroadcastStatus::AlreadyKnown
+        );
+        assert_eq!(
+            rpc_error("min relay fee not met"),
+            BroadcastStatus::Rejected("min relay fee not met".to_string())
+        );
+    }
+
+    #[test]
+    fn default_backends() {
+        assert_eq!(
+            backend_urls("https://mempool.space/api/", Chain::Mainnet),
+            vec!["https://mempool.space/api/"]
+        );
+        assert_eq!(
+            backend_urls("http://127.0.0.1:3002/api", Chain::Mutinynet),
+            vec!["http://127.0.0.1:3002/api", "https://mutinynet.com/api"]
+        );
+        assert_eq!(
+            backend_urls("", Chain::Signet),
+            vec!["https://mempool.space/signet/api"]
+        );
+    }
+}
@@ -0,0 +1,477 @@
+//! Cooperative cancellation of escrows before their funding confirms.
+//!
+//! Until the funding transaction confirms, or if it was never sent, the participants
+//! can call the escrow off: each sends a [`Cancellation`] event tagged with
+//! the [`SessionId`], gift wrapped to the other participants with
+//! [`wrap_to_recipients`](crate::gift_wrap::wrap_to_recipients),
+//! and the session is cancelled once every participant did, see [`receive_cancellations`].
+//! A pending offer only needs the offerer's cancellation.
+//!
+//! If the funding transaction signals replace-by-fee, the funder takes the coins back
+//! with [`cancel_funding_psbt`], which double-spends its inputs to the funder's own address
+//! before the escrow output ever confirms.
+use bitcoin::{Address, Amount, FeeRate, Psbt, Sequence, Transaction, TxIn, TxOut, Txid, absolute};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{
+    Event, EventBuilder, Filter, Keys, Kind, Tag, UnsignedEvent,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(debug_assertions)]
+use crate::logging::session_span;
+use crate::{
+    canonical,
+    error::Error,
+    protocol::{Handshake, SessionId, check_rumor, check_session_tag},
+    settings::FeeRateLimits,
+};
+#[cfg(feature = "serde-types")]
+use crate::{gift_wrap::receive_wrapped, protocol::Session, secret::SecretNsec};
+
+/// Version of the [`Cancellation`] message.
+pub(crate) const CANCELLATION_VERSION: u8 = 1;
+
+/// Nostr event kind of a [`Cancellation`].
+pub(crate) const CANCELLATION_KIND: u16 = 8_386;
+
+/// Minimum fee rate increase of a replacement transaction, as relayed by Bitcoin Core.
+const INCREMENTAL_RELAY_FEE_RATE: FeeRate = FeeRate::from_sat_per_vb_unchecked(1);
+
+/// A participant's signed request to call off an escrow that is not funded yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Cancellation {
+    /// Message version, see [`CANCELLATION_VERSION`].
+    pub(crate) version: u8,
+    /// Negotiation of the cancelled escrow.
+    pub(crate) session_id: SessionId,
+    /// Nostr public key of the cancelling participant.
+    pub(crate) npub: NostrPublicKey,
+    /// The unconfirmed funding transaction being abandoned, if it was sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) funding_txid: Option<Txid>,
+    /// Why the participant cancels.
+    pub(crate) reason: String,
+}
+
+impl Cancellation {
+    /// Records the cancellation of `handshake` by the participant `npub`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `npub` is not a participant of the escrow.
+    pub(crate) fn new(
+        handshake: &Handshake,
+        npub: NostrPublicKey,
+        funding_txid: Option<Txid>,
+        reason: impl Into<String>,
+    ) -> Result<Self, Error> {
+        let cancellation = Self {
+            version: CANCELLATION_VERSION,
+            session_id: handshake.session_id()?,
+            npub,
+            funding_txid,
+            reason: reason.into(),
+        };
+        cancellation.verify(handshake)?;
+        #[cfg(debug_assertions)]
+        session_span(&cancellation.session_id)
+            .in_scope(|| trace!(%npub, reason = %cancellation.reason, "cancelled"));
+        Ok(cancellation)
+    }
+
+    /// Verifies that the cancellation is for `handshake` and from one of its participants.
+    pub(crate) fn verify(&self, handshake: &Handshake) -> Result<(), Error> {
+        if self.version != CANCELLATION_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported cancellation version {}",
+                self.version
+            )));
+        }
+        if self.session_id != handshake.session_id()? {
+            return Err(Error::Protocol(
+                "Cancellation is for another session".to_string(),
+            ));
+        }
+        if !participants(handshake).contains(&self.npub) {
+            return Err(Error::Protocol(
+                "Cancellation is not from a participant".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Builds and signs the cancellation [`Event`], addressed to the other participants.
+    pub(crate) fn to_event(
+        &self,
+        nsec: &NostrSecretKey,
+        handshake: &Handshake,
+    ) -> Result<Event, Error> {
+        let keys = Keys::new(nsec.clone());
+        if keys.public_key() != self.npub {
+            return Err(Error::Protocol(
+                "Cancellation must be signed by the cancelling participant".to_string(),
+            ));
+        }
+        let mut tags = vec![Tag::identifier(self.session_id.to_string())];
+        tags.extend(
+            participants(handshake)
+                .into_iter()
+                .filter(|npub| *npub != self.npub)
+                .map(Tag::public_key),
+        );
+        Ok(
+            EventBuilder::new(Kind::Custom(CANCELLATION_KIND), canonical::encode(self)?)
+                .tags(tags)
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses a cancellation rumor, checking it is from the participant it names.
+    ///
+    /// The cancellation still has to be verified against its escrow with
+    /// [`Cancellation::verify`].
+    pub(crate) fn from_rumor(rumor: &UnsignedEvent) -> Result<Self, Error> {
+        check_rumor(rumor, CANCELLATION_KIND)?;
+        let cancellation: Cancellation = canonical::decode(&rumor.content)?;
+        if cancellation.npub != rumor.pubkey {
+            return Err(Error::Protocol(
+                "Cancellation is not from the cancelling participant".to_string(),
+            ));
+        }
+        check_session_tag(&rumor.tags, &cancellation.session_id)?;
+        Ok(cancellation)
+    }
+
+    /// The [`Filter`] of the cancellations in the negotiation `session_id`.
+    pub(crate) fn filter(session_id: &SessionId) -> Filter {
+        Filter::new()
+            .kind(Kind::Custom(CANCELLATION_KIND))
+            .identifier(session_id.to_string())
+    }
+}
+
+/// The participants whose [`Cancellation`]s cancel `handshake`:
+/// the offerer of a pending offer, or both parties of an agreed escrow.
+pub(crate) fn participants(handshake: &Handshake) -> Vec<NostrPublicKey> {
+    match handshake {
+        Handshake::Offered { offer, .. } | Handshake::Expired { offer, .. } => {
+            vec![offer.offerer]
+        }
+        Handshake::Agreed {
+            offer, acceptance, ..
+        } => {
+            let (npub_buyer, npub_seller) = offer.participants(&acceptance.acceptor);
+            vec![*npub_buyer, *npub_seller]
+        }
+    }
+}
+
+/// Whether `cancellations` cancel `handshake`, that is all its [`participants`] cancelled.
+///
+/// Cancellations that don't verify against `handshake` are ignored.
+pub(crate) fn is_cancelled(handshake: &Handshake, cancellations: &[Cancellation]) -> bool {
+    participants(handshake).iter().all(|npub| {
+        cancellations.iter().any(|cancellation| {
+            cancellation.npub == *npub && cancellation.verify(handshake).is_ok()
+        })
+    })
+}
+
+/// Receives the [`Cancellation`]s of `session` from `gift_wraps` addressed to `nsec`,
+/// as fetched from the relays with [`filter`](crate::gift_wrap::filter),
+/// adding the ones from participants who hadn't cancelled yet to the session.
+///
+/// Returns the cancellations added. Rumors that fail to parse or verify are skipped,
+/// and the ones already received by the session are not received again.
+#[cfg(feature = "serde-types")]
+pub(crate) fn receive_cancellations(
+    session: &mut Session,
+    gift_wraps: &[Event],
+    nsec: &SecretNsec,
+) -> Result<Vec<Cancellation>, Error> {
+    let filter = Cancellation::filter(&session.id()?);
+    let rumors = receive_wrapped(gift_wraps, nsec, &filter, &mut session.received);
+    let mut cancellations = Vec::new();
+    for rumor in &rumors {
+        let Ok(cancellation) = Cancellation::from_rumor(rumor) else {
+            continue;
+        };
+        if cancellation.verify(&session.handshake).is_ok()
+            && !session
+                .cancellations
+                .iter()
+                .any(|c| c.npub == cancellation.npub)
+        {
+            session.cancellations.push(cancellation.clone());
+            cancellations.push(cancellation);
+        }
+    }
+    Ok(cancellations)
+}
+
+/// Builds the unsigned replacement of an unconfirmed `funding_tx`, paying its inputs back
+/// to the funder's `refund_address` instead of the escrow.
+///
+/// `prevouts` must hold the outputs spent by every input of `funding_tx`, in input order,
+/// and are set as witness UTXOs so the funder's wallet can sign the returned [`Psbt`].
+/// The replacement keeps the inputs and has a single output, so it weighs at most as much as
+/// `funding_tx` once signed with the same wallet; its fee is that weight at `fee_rate`,
+/// and at least what replace-by-fee requires to outbid `funding_tx`.
+/// Both `fee_rate` and the resulting fee rate must be within the user's `limits`.
+///
+/// # Errors
+///
+/// Errors if `funding_tx` doesn't signal replace-by-fee, `prevouts` doesn't match its inputs,
+/// the fee rate is outside `limits`, or the refund would be dust.
+pub(crate) fn cancel_funding_psbt(
+    funding_tx: &Transaction,
+    prevouts: Vec<TxOut>,
+    refund_address: &Address,
+    fee_rate: FeeRate,
+    limits: &FeeRateLimits,
+) -> Result<Psbt, Error> {
+    let fee_rate = limits.check(fee_rate)?;
+    if !funding_tx.is_explicitly_rbf() {
+        return Err(Error::WrongInputs(
+            "the funding transaction does not signal replace-by-fee".to_string(),
+        ));
+    }
+    if prevouts.len() != funding_tx.input.len() {
+        return Err(Error::WrongInputs(format!(
+            "expected {} prevouts, got {}",
+            funding_tx.input.len(),
+            prevouts.len()
+        )));
+    }
+    let input_value = prevouts.iter().map(|prevout| prevout.value).sum::<Amount>();
+    let output_value = funding_tx
+        .output
+        .iter()
+        .map(|output| output.value)
+        .sum::<Amount>();
+    let original_fee = input_value
+        .checked_sub(output_value)
+        .ok_or_else(|| Error::WrongInputs("prevouts don't cover the funding".to_string()))?;
+
+    let weight = funding_tx.weight();
+    let too_high = || Error::WrongInputs("the replacement fee is too high".to_string());
+    let min_fee = INCREMENTAL_RELAY_FEE_RATE
+        .fee_wu(weight)
+        .and_then(|increment| original_fee.checked_add(increment))
+        .ok_or_else(too_high)?;
+    let fee = fee_rate.fee_wu(weight).ok_or_else(too_high)?.max(min_fee);
+    // Outbidding `funding_tx` may take more than `fee_rate`, but never more than allowed.
+    if fee > limits.max().fee_wu(weight).ok_or_else(too_high)? {
+        return Err(too_high());
+    }
+    let script_pubkey = refund_address.script_pubkey();
+    let refund = input_value
+        .checked_sub(fee)
+        .filter(|refund| *refund >= script_pubkey.minimal_non_dust())
+        .ok_or_else(|| Error::WrongInputs(format!("a fee of {fee} leaves a dust refund")))?;
+
+    let replacement = Transaction {
+        version: funding_tx.version,
+        lock_time: absolute::LockTime::ZERO,
+        input: funding_tx
+            .input
+            .iter()
+            .map(|input| TxIn {
+                previous_output: input.previous_output,
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: refund,
+            script_pubkey,
+        }],
+    };
+    #[cfg(debug_assertions)]
+    trace!(funding_txid = %funding_tx.compute_txid(), %original_fee, %fee, %refund, "built funding cancellation");
+    let mut psbt =
+        Psbt::from_unsigned_tx(replacement).expect("inputs have empty scripts and witnesses");
+    for (input, prevout) in psbt.inputs.iter_mut().zip(prevouts) {
+        input.witness_utxo = Some(prevout);
+    }
+    Ok(psbt)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, OutPoint, ScriptBuf, Witness, hashes::Hash, transaction};
+    use nostr::Timestamp;
+
+    use crate::{
+        gift_wrap::{unwrap, wrap},
+        protocol::{Session, offer},
+        util::npub_to_address,
+    };
+
+    use super::*;
+
+    #[test]
+    fn cooperative_cancellation() {
+        let (keys_a, keys_b, keys_other) = (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer = offer(keys_a.public_key(), None);
+        let (offered, offer_event) =
+            Handshake::offer(keys_a.secret_key(), offer, Timestamp::now()).unwrap();
+        let (agreed, _) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, Timestamp::now()).unwrap();
+
+        // A pending offer is withdrawn by its offerer alone.
+        let cancellation_a =
+            Cancellation::new(&offered, keys_a.public_key(), None, "Changed my mind").unwrap();
+        assert!(is_cancelled(
+            &offered,
+            std::slice::from_ref(&cancellation_a)
+        ));
+        assert!(Cancellation::new(&offered, keys_b.public_key(), None, "").is_err());
+
+        // An agreed escrow needs both parties.
+        let cancellation_a =
+            Cancellation::new(&agreed, keys_a.public_key(), None, "Changed my mind").unwrap();
+        assert!(!is_cancelled(
+            &agreed,
+            std::slice::from_ref(&cancellation_a)
+        ));
+        let event = Cancellation::new(&agreed, keys_b.public_key(), Some(Txid::all_zeros()), "Ok")
+            .unwrap()
+            .to_event(keys_b.secret_key(), &agreed)
+            .unwrap();
+        let gift_wrap = wrap(
+            &event,
+            &SecretNsec::from(keys_b.secret_key().clone()),
+            &keys_a.public_key(),
+            Timestamp::now(),
+        )
+        .unwrap();
+        let cancellation_b = Cancellation::from_rumor(
+            &unwrap(&gift_wrap, &SecretNsec::from(keys_a.secret_key().clone())).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(cancellation_b.funding_txid, Some(Txid::all_zeros()));
+        assert!(is_cancelled(
+            &agreed,
+            &[cancellation_a.clone(), cancellation_b.clone()]
+        ));
+
+        // Sessions are only cancelled before the funding confirms.
+        let mut session = Session::new(agreed.clone());
+        assert!(session.cancel(cancellation_a.clone(), true).is_err());
+        assert!(!session.cancel(cancellation_a.clone(), false).unwrap());
+        assert!(session.cancel(cancellation_b, false).unwrap());
+        assert_eq!(
+            Session::from_json(&session.to_json().unwrap()).unwrap(),
+            session
+        );
+
+        // Only participants cancel, with their own keys.
+        assert!(Cancellation::new(&agreed, keys_other.public_key(), None, "").is_err());
+        assert!(
+            cancellation_a
+                .to_event(keys_b.secret_key(), &agreed)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn cancel_funding_with_rbf() {
+        let network = Network::Regtest;
+        let refund_address = npub_to_address(&Keys::generate().public_key(), network).unwrap();
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(120_000),
+            script_pubkey: refund_address.script_pubkey(),
+        }];
+        let funding_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::from_slice(&[[0; 64]]),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(110_000),
+                script_pubkey: refund_address.script_pubkey(),
+            }],
+        };
+
+        let limits = FeeRateLimits::default();
+        let psbt = cancel_funding_psbt(
+            &funding_tx,
+            prevouts.clone(),
+            &refund_address,
+            FeeRate::from_sat_per_vb_unchecked(2),
+            &limits,
+        )
+        .unwrap();
+        let replacement = &psbt.unsigned_tx;
+        assert_eq!(
+            replacement.input[0].previous_output,
+            funding_tx.input[0].previous_output
+        );
+        assert!(replacement.is_explicitly_rbf());
+        assert_eq!(psbt.inputs[0].witness_utxo, Some(prevouts[0].clone()));
+        // The original fee of 10,000 sats outbids 2 sat/vB, so the replacement adds 1 sat/vB.
+        let fee = 120_000 - replacement.output[0].value.to_sat();
+        assert!(fee > 10_000 && fee <= 10_000 + funding_tx.weight().to_vbytes_ceil());
+
+        // Non-replaceable funding can't be cancelled.
+        let mut final_tx = funding_tx.clone();
+        final_tx.input[0].sequence = Sequence::MAX;
+        assert!(
+            cancel_funding_psbt(
+                &final_tx,
+                prevouts.clone(),
+                &refund_address,
+                FeeRate::from_sat_per_vb_unchecked(2),
+                &limits,
+            )
+            .is_err()
+        );
+        // Nor bumped past the fee rate limits, even to outbid the original fee.
+        assert!(
+            cancel_funding_psbt(
+                &funding_tx,
+                prevouts.clone(),
+                &refund_address,
+                FeeRate::from_sat_per_vb_unchecked(10_000),
+                &limits,
+            )
+            .is_err()
+        );
+        let low_ceiling = FeeRateLimits {
+            max_sat_per_vb: 2,
+            ..limits
+        };
+        assert!(
+            cancel_funding_psbt(
+                &funding_tx,
+                prevouts.clone(),
+                &refund_address,
+                FeeRate::from_sat_per_vb_unchecked(2),
+                &low_ceiling,
+            )
+            .is_err()
+        );
+        // Nor refunded below dust.
+        let high_ceiling = FeeRateLimits {
+            max_sat_per_vb: 100_000,
+            ..limits
+        };
+        assert!(
+            cancel_funding_psbt(
+                &funding_tx,
+                prevouts,
+                &refund_address,
+                FeeRate::from_sat_per_vb_unchecked(10_000),
+                &high_ceiling,
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Canonical JSON of signed protocol messages.
+//!
+//! Offers, acceptances, decisions and the other messages exchanged over Nostr are signed as
+//! the JSON content of their event. [`encode`] writes that JSON in a single canonical form,
+//! the subset of the JSON Canonicalization Scheme (RFC 8785) the messages need, so every
+//! platform and version produces the same bytes for the same message:
+//!
+//! - no whitespace;
+//! - object members sorted by the UTF-16 code units of their names;
+//! - strings escaped as in RFC 8785, everything else written as UTF-8;
+//! - integers only, as amounts are in sats and shares in basis points.
+//!
+//! [`decode`] parses messages of any version, canonical or not, but rejects duplicate member
+//! names, which parsers on other platforms would resolve differently than the signer meant.
+
+use std::fmt;
+
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{self, DeserializeOwned, MapAccess, SeqAccess, Visitor},
+};
+use serde_json::{Map, Number, Value};
+
+use crate::error::Error;
+
+/// Serializes `message` as canonical JSON.
+///
+/// # Errors
+///
+/// Errors if `message` doesn't serialize to JSON or holds a non-integer number.
+pub(crate) fn encode<T: Serialize>(message: &T) -> Result<String, Error> {
+    let value = serde_json::to_value(message).map_err(|e| Error::Protocol(e.to_string()))?;
+    let mut json = String::new();
+    write_value(&value, &mut json)?;
+    Ok(json)
+}
+
+/// Parses a JSON message, rejecting duplicate member names.
+///
+/// # Errors
+///
+/// Errors if `content` is not JSON of a `T`, or repeats a member name.
+pub(crate) fn decode<T: DeserializeOwned>(content: &str) -> Result<T, Error> {
+    let StrictValue(value) = serde_json::from_str(content)
+        .map_err(|e| Error::Protocol(format!("Malformed message: {e}")))?;
+    T::deserialize(value).map_err(|e| Error::Protocol(format!("Malformed message: {e}")))
+}
+
+/// Writes `value` as canonical JSON to `json`.
+fn write_value(value: &Value, json: &mut String) -> Result<(), Error> {
+    match value {
+        Value::Null => json.push_str("null"),
+        Value::Bool(bool) => json.push_str(if *bool { "true" } else { "false" }),
+        Value::Number(number) => write_number(number, json)?,
+        Value::String(string) => write_string(string, json),
+        Value::Array(array) => {
+            json.push('[');
+            for (index, item) in array.iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                write_value(item, json)?;
+            }
+            json.push(']');
+        }
+        Value::Object(object) => {
+            let mut members = object.iter().collect::<Vec<_>>();
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            json.push('{');
+            for (index, (name, member)) in members.into_iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                write_string(name, json);
+                json.push(':');
+                write_value(member, json)?;
+            }
+            json.push('}');
+        }
+    }
+    Ok(())
+}
+
+/// Writes the integer `number` to `json`.
+fn write_number(number: &Number, json: &mut String) -> Result<(), Error> {
+    if number.is_f64() {
+        return Err(Error::Protocol(format!(
+            "Number {number} is not an integer, which signed messages must use"
+        )));
+    }
+    json.push_str(&number.to_string());
+    Ok(())
+}
+
+/// Writes `string` as a JSON string to `json`.
+///
+/// `serde_json` escapes exactly what RFC 8785 does: quotes, backslashes and control
+/// characters, with the short escapes where they exist and lowercase `\u00xx` otherwise.
+fn write_string(string: &str, json: &mut String) {
+    // Serializing a string never fails.
+    json.push_str(&serde_json::to_string(string).unwrap_or_default());
+}
+
+/// A JSON [`Value`] parsed without duplicate member names.
+struct StrictValue(Value);
+
+impl<'de> Deserialize<'de> for StrictValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(StrictVisitor).map(StrictValue)
+    }
+}
+
+/// [`Visitor`] of a [`StrictValue`].
+struct StrictVisitor;
+
+impl<'de> Visitor<'de> for StrictVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Number::from_f64(value)
+            .map(Value::Number)
+            .ok_or_else(|| E::custom(format!("invalid number {value}")))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut array = Vec::new();
+        while let Some(StrictValue(item)) = seq.next_element()? {
+            array.push(item);
+        }
+        Ok(Value::Array(array))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut object = Map::new();
+        while let Some(name) = map.next_key::<String>()? {
+            if object.contains_key(&name) {
+                return Err(de::Error::custom(format!("duplicate member \"{name}\"")));
+            }
+            let StrictValue(member) = map.next_value()?;
+            object.insert(name, member);
+        }
+        Ok(Value::Object(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Whether `content` is JSON in its canonical form, as written by [`encode`].
+    fn is_canonical(content: &str) -> bool {
+        serde_json::from_str::<StrictValue>(content)
+            .ok()
+            .and_then(|StrictValue(value)| encode(&value).ok())
+            .is_some_and(|canonical| canonical == content)
+    }
+
+    #[test]
+    fn canonical_json() {
+        // Members sorted by UTF-16 code units: the surrogates of "\u{1f600}" sort before
+        // "\u{ff21}", unlike in UTF-8.
+        let message = json!({
+            "version": 1,
+            "amount": 100_000,
+            "\u{1f600}": null,
+            "\u{ff21}": [true, -1, "tab\tquote\"\u{1}"],
+            "nested": { "b": "", "a": {} },
+        });
+        let canonical = encode(&message).unwrap();
+        assert_eq!(
+            canonical,
+            "{\"amount\":100000,\"nested\":{\"a\":{},\"b\":\"\"},\"version\":1,\
+             \"\u{1f600}\":null,\"\u{ff21}\":[true,-1,\"tab\\tquote\\\"\\u0001\"]}"
+        );
+        assert!(is_canonical(&canonical));
+        assert!(!is_canonical(&message.to_string()));
+        assert!(!is_canonical("{\"a\": 1}"));
+        assert_eq!(decode::<Value>(&canonical).unwrap(), message);
+
+        // Non-canonical messages of older versions still parse, ambiguous ones don't.
+        assert_eq!(
+            decode::<Value>("{ \"b\": 1, \"a\": 2 }").unwrap(),
+            json!({ "a": 2, "b": 1 })
+        );
+        assert!(decode::<Value>("{\"a\":1,\"a\":2}").is_err());
+        assert!(decode::<Value>("[{\"a\":{\"b\":1,\"b\":1}}]").is_err());
+        assert!(encode(&json!({ "rate": 1.5 })).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! Chaining escrows: spending an escrow output straight into a new escrow.
+//!
+//! Participants who want to change the terms of a funded escrow, for instance to extend
+//! its deadline or replace the arbitrator, don't need to resolve it first:
+//!
+//! 1. Either participant builds the same [`ChainTx`] with a [`ChainBuilder`], paying the
+//!    whole escrow minus the fee to the new escrow through the collaborative leaf `A`.
+//! 2. Both sign it with [`ChainTx::sign`], and either combines the signatures
+//!    with [`ChainTx::finalize`].
+//! 3. The offerer publishes [`ChainTx::next_offer`] to open the session of the new escrow,
+//!    whose funding is the chain transaction itself.
+//!
+//! Participants and their amounts are kept, only the fee is taken from the buyer's side.
+//! Replacing a participant's key is a [`KeyRotation`](crate::rotation::KeyRotation) instead.
+
+use bitcoin::{Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, absolute, transaction};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{Timestamp, key::PublicKey as NostrPublicKey};
+
+use crate::{
+    error::Error,
+    invariants::{SigningInvariants, leaf_timelock},
+    protocol::Offer,
+    scripts::{EscrowConfig, EscrowScript, ScriptTemplate},
+    secret::SecretNsec,
+    sign::{BatchSigner, LeafSignatures, combine_signatures},
+};
+
+/// The leaf spent by a [`ChainTx`], signed by both participants.
+const CHAIN_LEAF: EscrowScript = EscrowScript::A;
+
+/// Builds a [`ChainTx`] moving an escrow into one with new terms.
+#[derive(Debug, Clone)]
+pub(crate) struct ChainBuilder {
+    /// Escrow holding the coins.
+    from: EscrowConfig,
+    /// Escrow receiving the coins, starting with the terms of `from`.
+    to: EscrowConfig,
+    /// Escrow output being spent.
+    funding: OutPoint,
+    /// Amount of the escrow output.
+    amount: Amount,
+    /// Fee of the chain transaction.
+    fee: Amount,
+}
+
+impl ChainBuilder {
+    /// Starts chaining the `amount` of the escrow `from`, held at `funding`,
+    /// into an escrow with the same terms.
+    pub(crate) fn new(from: &EscrowConfig, funding: OutPoint, amount: Amount) -> Self {
+        Self {
+            from: *from,
+            to: *from,
+            funding,
+            amount,
+            fee: Amount::ZERO,
+        }
+    }
+
+    /// Sets the arbitrator and timelock duration of the new escrow,
+    /// [`None`] for a collaborative escrow.
+    pub(crate) fn dispute(mut self, dispute: Option<(NostrPublicKey, u32)>) -> Self {
+        self.to.npub_arbitrator = dispute.map(|(arbitrator, _)| arbitrator);
+        self.to.timelock_duration = dispute.map(|(_, timelock)| timelock);
+        self
+    }
+
+    /// Sets the [`ScriptTemplate`] of the new escrow.
+    pub(crate) fn template(mut self, template: ScriptTemplate) -> Self {
+        self.to.template = template;
+        self
+    }
+
+    /// Sets the fee of the chain transaction.
+    pub(crate) fn fee(mut self, fee: Amount) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    /// Builds the unsigned [`ChainTx`].
+    ///
+    /// It spends the collaborative leaf `A` and has no lock time,
+    /// so both participants build the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the terms don't change, the arbitrator is a participant,
+    /// the timelock doesn't fit a relative lock time, or the fee leaves a dust escrow.
+    pub(crate) fn build(self) -> Result<ChainTx, Error> {
+        let Self {
+            from,
+            to,
+            funding,
+            amount,
+            fee,
+        } = self;
+        if to == from {
+            return Err(Error::WrongInputs(
+                "Chained escrow must change the terms".to_string(),
+            ));
+        }
+        if to
+            .npub_arbitrator
+            .is_some_and(|arbitrator| arbitrator == to.npub_1 || arbitrator == to.npub_2)
+        {
+            return Err(Error::WrongInputs(
+                "Arbitrator must not be a participant".to_string(),
+            ));
+        }
+        // Relative timelocks in blocks are 16 bits.
+        if let Some(timelock) = to
+            .timelock_duration
+            .filter(|timelock| *timelock == 0 || *timelock > u32::from(u16::MAX))
+        {
+            return Err(Error::WrongInputs(format!(
+                "Invalid timelock of {timelock} blocks"
+            )));
+        }
+        let script_pubkey = to.address()?.script_pubkey();
+        let value = amount
+            .checked_sub(fee)
+            .filter(|value| *value >= script_pubkey.minimal_non_dust())
+            .ok_or_else(|| Error::WrongInputs(format!("A fee of {fee} leaves a dust escrow")))?;
+        let tx = Transaction {
+            version: transaction::Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: funding,
+                sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey,
+            }],
+        };
+        let prevout = TxOut {
+            value: amount,
+            script_pubkey: from.address()?.script_pubkey(),
+        };
+        #[cfg(debug_assertions)]
+        trace!(txid = %tx.compute_txid(), %value, "built chain transaction");
+        Ok(ChainTx {
+            from,
+            to,
+            tx,
+            prevout,
+        })
+    }
+}
+
+/// An unsigned transaction spending an escrow into a new escrow, see [`ChainBuilder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ChainTx {
+    /// Escrow being spent.
+    pub(crate) from: EscrowConfig,
+    /// Escrow being funded.
+    pub(crate) to: EscrowConfig,
+    /// The unsigned transaction.
+    pub(crate) tx: Transaction,
+    /// The escrow output spent by the transaction.
+    pub(crate) prevout: TxOut,
+}
+
+impl ChainTx {
+    /// The amount locked in the new escrow.
+    pub(crate) fn value(&self) -> Amount {
+        self.tx.output[0].value
+    }
+
+    /// The fee paid by the transaction.
+    pub(crate) fn fee(&self) -> Amount {
+        self.prevout.value - self.value()
+    }
+
+    /// Signs the transaction with a participant's `nsec`,
+    /// returning the [`LeafSignatures`] to send to the counterparty.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `nsec` is not a participant of the escrow being spent.
+    pub(crate) fn sign(&self, nsec: &SecretNsec) -> Result<LeafSignatures, Error> {
+        let npub = nsec.public_key();
+        if npub != self.from.npub_1 && npub != self.from.npub_2 {
+            return Err(Error::WrongInputs(
+                "Only participants sign a chain transaction".to_string(),
+            ));
+        }
+        let script = self.from.script(CHAIN_LEAF)?;
+        let invariants = SigningInvariants::of_local_tx(
+            &self.tx,
+            vec![self.prevout.clone()],
+            vec![leaf_timelock(CHAIN_LEAF, self.from.timelock_duration)?],
+        );
+        let signature = BatchSigner::new(&self.tx, &invariants)?.sign(0, &script, nsec)?;
+        let mut signatures = LeafSignatures::new(self.tx.compute_txid(), 0, CHAIN_LEAF);
+        signatures.insert(npub, signature);
+        Ok(signatures)
+    }
+
+    /// Combines both participants' `signatures` into the signed transaction.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the signatures are for another transaction or one is missing.
+    pub(crate) fn finalize(&self, signatures: &LeafSignatures) -> Result<Transaction, Error> {
+        if signatures.txid != self.tx.compute_txid()
+            || signatures.input_index != 0
+            || signatures.escrow_script != CHAIN_LEAF
+        {
+            return Err(Error::WrongInputs(
+                "Signatures are for another transaction".to_string(),
+            ));
+        }
+        combine_signatures(
+            self.tx.clone(),
+            0,
+            signatures.ordered(&self.from)?,
+            &self.from.script(CHAIN_LEAF)?,
+            &self.from.spend_info()?,
+        )
+    }
+
+    /// The [`Offer`] opening the session of the new escrow,
+    /// continuing the `previous` offer accepted by `acceptor`.
+    ///
+    /// The offer keeps the roles and amounts of `previous`, minus the fee taken from
+    /// the buyer's amount first, so the chain transaction exactly funds it.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `previous` is not the offer of the escrow being spent.
+    pub(crate) fn next_offer(
+        &self,
+        previous: &Offer,
+        acceptor: &NostrPublicKey,
+        expires_at: Timestamp,
+    ) -> Result<Offer, Error> {
+        if previous.escrow_config(acceptor)? != self.from {
+            return Err(Error::WrongInputs(
+                "Offer is not of the chained escrow".to_string(),
+            ));
+        }
+        let fee = self.fee();
+        let buyer_fee = fee.min(previous.amount_buyer);
+        let offer = Offer {
+            counterparty: Some(*acceptor),
+            amount_buyer: previous.amount_buyer - buyer_fee,
+            amount_seller: previous
+                .amount_seller
+                .checked_sub(fee - buyer_fee)
+                .ok_or(Error::Rounding)?,
+            arbitrator: self.to.npub_arbitrator,
+            timelock_duration: self.to.timelock_duration,
+            expires_at,
+            lock_time_height: None,
+            script_template: self.to.template.into(),
+            fiat: None,
+            platform_fee: None,
+            ..previous.clone()
+        };
+        offer.validate()?;
+        if offer.amount_buyer + offer.amount_seller != self.value() {
+            return Err(Error::FundingMismatch {
+                expected: self.value(),
+                actual: offer.amount_buyer + offer.amount_seller,
+            });
+        }
+        Ok(offer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Txid, hashes::Hash};
+
+    use super::*;
+    use crate::{
+        audit::analyze_spend,
+        protocol::{DEFAULT_OFFER_VALIDITY, Role, offer},
+    };
+
+    #[test]
+    fn chain_into_new_escrow() {
+        let (buyer, seller) = (SecretNsec::generate(), SecretNsec::generate());
+        let (arbitrator, new_arbitrator) = (SecretNsec::generate(), SecretNsec::generate());
+        let now = Timestamp::now();
+        let offer = Offer {
+            role: Role::Buyer,
+            counterparty: Some(seller.public_key()),
+            amount_seller: Amount::from_sat(20_000),
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(buyer.public_key(), Some(arbitrator.public_key()))
+        };
+        let from = offer.escrow_config(&seller.public_key()).unwrap();
+        let funding = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let amount = Amount::from_sat(120_000);
+
+        // Unchanged terms and dust escrows are rejected.
+        assert!(ChainBuilder::new(&from, funding, amount).build().is_err());
+        let builder = ChainBuilder::new(&from, funding, amount)
+            .dispute(Some((new_arbitrator.public_key(), 1_008)))
+            .template(ScriptTemplate::V2);
+        assert!(builder.clone().fee(amount).build().is_err());
+        assert!(
+            ChainBuilder::new(&from, funding, amount)
+                .dispute(Some((buyer.public_key(), 1_008)))
+                .build()
+                .is_err()
+        );
+
+        let chain = builder.fee(Amount::from_sat(1_000)).build().unwrap();
+        assert_eq!(chain.value(), Amount::from_sat(119_000));
+        assert_eq!(chain.fee(), Amount::from_sat(1_000));
+        assert_eq!(chain.to.npub_arbitrator, Some(new_arbitrator.public_key()));
+        assert_eq!(
+            chain.tx.output[0].script_pubkey,
+            chain.to.address().unwrap().script_pubkey()
+        );
+
+        // Both participants sign the old leaf, the arbitrator can't.
+        assert!(chain.sign(&arbitrator).is_err());
+        let mut signatures = chain.sign(&buyer).unwrap();
+        assert!(chain.finalize(&signatures).is_err());
+        for signature in chain.sign(&seller).unwrap().signatures {
+            signatures.insert(signature.npub, signature.signature);
+        }
+        let signed = chain.finalize(&signatures).unwrap();
+        let audit = analyze_spend(&signed, 0, std::slice::from_ref(&chain.prevout), &from).unwrap();
+        assert_eq!(audit.signers.len(), 2);
+        assert!(audit.signers.iter().all(|signer| signer.valid));
+
+        // The next offer is funded exactly by the chain transaction.
+        let expires_at = now + DEFAULT_OFFER_VALIDITY;
+        let next = chain
+            .next_offer(&offer, &seller.public_key(), expires_at)
+            .unwrap();
+        assert_eq!(next.amount_buyer, Amount::from_sat(99_000));
+        assert_eq!(next.amount_seller, Amount::from_sat(20_000));
+        assert_eq!(next.escrow_config(&seller.public_key()).unwrap(), chain.to);
+        assert_ne!(next.session_id().unwrap(), offer.session_id().unwrap());
+        assert!(
+            chain
+                .next_offer(&next, &seller.public_key(), expires_at)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Escrows funded by both participants in a single transaction.
+//!
+//! The buyer may fund the price and the seller a bond, as agreed in the [`Offer`].
+//! Each party brings a [`Contribution`] of P2TR inputs and optional change,
+//! [`CofundingBuilder`] merges them into one transaction paying the escrow,
+//! and each party then signs only its own inputs of the [`CofundingTx`].
+//!
+//! Both parties build the same transaction from the same contributions:
+//! the buyer's inputs and change come first, then the seller's.
+
+use bitcoin::{
+    Address, Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, XOnlyPublicKey,
+    transaction::Version,
+};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::key::PublicKey as NostrPublicKey;
+use secp256k1::{SECP256K1, schnorr};
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    protocol::Offer,
+    secret::SecretNsec,
+    sign::{key_spend_message, sign_key_spend, with_key_spend_signature},
+};
+
+/// An input brought by a funder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct FundingInput {
+    /// The spent UTXO.
+    pub(crate) outpoint: OutPoint,
+    /// The spent output, which must be P2TR so it can be signed through its key path.
+    pub(crate) prevout: TxOut,
+}
+
+/// A participant's share of a collaborative funding transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct Contribution {
+    /// The contributing participant.
+    pub(crate) npub: NostrPublicKey,
+    /// The inputs the participant spends.
+    pub(crate) inputs: Vec<FundingInput>,
+    /// Change paid back to the participant, if any.
+    pub(crate) change: Option<TxOut>,
+}
+
+impl Contribution {
+    /// Total amount of the inputs.
+    pub(crate) fn input_amount(&self) -> Amount {
+        self.inputs.iter().map(|input| input.prevout.value).sum()
+    }
+
+    /// What the participant puts into the transaction: its inputs minus its change.
+    ///
+    /// Anything above its agreed amount goes to the fee.
+    pub(crate) fn net_amount(&self) -> Result<Amount, Error> {
+        let change = self
+            .change
+            .as_ref()
+            .map_or(Amount::ZERO, |change| change.value);
+        self.input_amount()
+            .checked_sub(change)
+            .ok_or(Error::FundingMismatch {
+                expected: change,
+                actual: self.input_amount(),
+            })
+    }
+}
+
+/// Builds the transaction funding an escrow from both participants' [`Contribution`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CofundingBuilder {
+    /// The agreed offer.
+    offer: Offer,
+    /// The buyer's Nostr public key.
+    npub_buyer: NostrPublicKey,
+    /// The seller's Nostr public key.
+    npub_seller: NostrPublicKey,
+    /// The escrow address, paid both agreed amounts.
+    escrow_address: Address,
+    /// The buyer's contribution, once added.
+    buyer: Option<Contribution>,
+    /// The seller's contribution, once added.
+    seller: Option<Contribution>,
+}
+
+impl CofundingBuilder {
+    /// Starts a collaborative funding of the escrow of `offer`, accepted by `acceptor`.
+    pub(crate) fn new(offer: &Offer, acceptor: &NostrPublicKey) -> Result<Self, Error> {
+        offer.validate()?;
+        let (npub_buyer, npub_seller) = offer.participants(acceptor);
+        Ok(Self {
+            offer: offer.clone(),
+            npub_buyer: *npub_buyer,
+            npub_seller: *npub_seller,
+            escrow_address: offer.escrow_address(acceptor)?,
+            buyer: None,
+            seller: None,
+        })
+    }
+
+    /// Adds a participant's `contribution`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the contribution is not from a participant or was already added,
+    /// has no inputs, spends an input twice, spends a non-P2TR output,
+    /// or has change below the dust limit.
+    pub(crate) fn contribute(mut self, contribution: Contribution) -> Result<Self, Error> {
+        let slot = if contribution.npub == self.npub_buyer {
+            &mut self.buyer
+        } else if contribution.npub == self.npub_seller {
+            &mut self.seller
+        } else {
+            return Err(Error::WrongInputs(format!(
+                "{} is not a participant of the escrow",
+                contribution.npub
+            )));
+        };
+        if slot.is_some() {
+            return Err(Error::WrongInputs(format!(
+                "{} already contributed",
+                contribution.npub
+            )));
+        }
+        if contribution.inputs.is_empty() {
+            return Err(Error::WrongInputs("Contribution has no inputs".to_string()));
+        }
+        if let Some(input) = contribution
+            .inputs
+            .iter()
+            .find(|input| !input.prevout.script_pubkey.is_p2tr())
+        {
+            return Err(Error::WrongInputs(format!(
+                "Input {} is not a P2TR output",
+                input.outpoint
+            )));
+        }
+        if let Some(change) = &contribution.change
+            && change.value < change.script_pubkey.minimal_non_dust()
+        {
+            return Err(Error::WrongInputs(format!(
+                "Change of {} is dust",
+                change.value
+            )));
+        }
+        *slot = Some(contribution);
+
+        let mut outpoints = self
+            .contributions()
+            .flat_map(|contribution| &contribution.inputs)
+            .map(|input| input.outpoint)
+            .collect::<Vec<_>>();
+        let inputs = outpoints.len();
+        outpoints.sort();
+        outpoints.dedup();
+        if outpoints.len() != inputs {
+            return Err(Error::WrongInputs(
+                "Contributions spend the same input twice".to_string(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// The contributions added so far, the buyer's first.
+    fn contributions(&self) -> impl Iterator<Item = &Contribution> {
+        self.buyer.iter().chain(&self.seller)
+    }
+
+    /// Builds the unsigned funding transaction.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a participant with a non-zero agreed amount did not contribute,
+    /// or a contribution does not cover its agreed amount.
+    pub(crate) fn build(&self) -> Result<CofundingTx, Error> {
+        for (contribution, agreed, role) in [
+            (&self.buyer, self.offer.amount_buyer, "buyer"),
+            (&self.seller, self.offer.amount_seller, "seller"),
+        ] {
+            let net = match contribution {
+                Some(contribution) => contribution.net_amount()?,
+                None if agreed == Amount::ZERO => continue,
+                None => {
+                    return Err(Error::WrongInputs(format!("The {role} did not contribute")));
+                }
+            };
+            if net < agreed {
+                return Err(Error::FundingMismatch {
+                    expected: agreed,
+                    actual: net,
+                });
+            }
+        }
+
+        let mut inputs = Vec::new();
+        let mut outputs = vec![TxOut {
+            value: self.offer.amount_buyer + self.offer.amount_seller,
+            script_pubkey: self.escrow_address.script_pubkey(),
+        }];
+        for contribution in self.contributions() {
+            inputs.extend(
+                contribution
+                    .inputs
+                    .iter()
+                    .map(|input| (contribution.npub, input.clone())),
+            );
+            outputs.extend(contribution.change.clone());
+        }
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: self.offer.lock_time()?,
+            // Never final, so the lock time is always enforced.
+            input: inputs
+                .iter()
+                .map(|(_, input)| TxIn {
+                    previous_output: input.outpoint,
+                    sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+                    ..Default::default()
+                })
+                .collect(),
+            output: outputs,
+        };
+        #[cfg(debug_assertions)]
+        trace!(txid = %tx.compute_txid(), inputs = %tx.input.len(), "collaborative funding transaction");
+        Ok(CofundingTx {
+            owners: inputs.iter().map(|(npub, _)| *npub).collect(),
+            prevouts: inputs.into_iter().map(|(_, input)| input.prevout).collect(),
+            tx,
+        })
+    }
+}
+
+/// A collaborative funding transaction, signed input by input by its owners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CofundingTx {
+    /// The funding transaction, paying the escrow in its first output.
+    pub(crate) tx: Transaction,
+    /// The outputs spent by every input, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// The participant who owns every input, in input order.
+    pub(crate) owners: Vec<NostrPublicKey>,
+}
+
+impl CofundingTx {
+    /// Indexes of the inputs owned by `npub`.
+    pub(crate) fn inputs_of(&self, npub: &NostrPublicKey) -> Vec<usize> {
+        self.owners
+            .iter()
+            .enumerate()
+            .filter(|(_, owner)| *owner == npub)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// The fee paid by the transaction.
+    pub(crate) fn fee(&self) -> Amount {
+        let inputs: Amount = self.prevouts.iter().map(|prevout| prevout.value).sum();
+        let outputs: Amount = self.tx.output.iter().map(|output| output.value).sum();
+        inputs - outputs
+    }
+
+    /// Signs every input owned by the [`SecretNsec`]'s participant, consuming it.
+    ///
+    /// Returns one [`schnorr::Signature`] per input of [`CofundingTx::inputs_of`],
+    /// to send to the other participant.
+    /// Only inputs paying to the participant's `npub` address can be signed here;
+    /// other inputs must be signed by their wallet.
+    pub(crate) fn sign(&self, nsec: SecretNsec) -> Result<Vec<schnorr::Signature>, Error> {
+        let npub = nsec.public_key();
+        self.inputs_of(&npub)
+            .into_iter()
+            .map(|index| sign_key_spend(&self.tx, index, &nsec, &self.prevouts))
+            .collect()
+    }
+
+    /// Adds the `signatures` of `npub`'s inputs, in the order of [`CofundingTx::inputs_of`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if a signature does not verify against the output key of its input.
+    pub(crate) fn add_signatures(
+        &mut self,
+        npub: &NostrPublicKey,
+        signatures: &[schnorr::Signature],
+    ) -> Result<(), Error> {
+        let indexes = self.inputs_of(npub);
+        if indexes.len() != signatures.len() {
+            return Err(Error::WrongInputs(format!(
+                "Expected {} signatures, got {}",
+                indexes.len(),
+                signatures.len()
+            )));
+        }
+        for (index, signature) in indexes.into_iter().zip(signatures) {
+            let message = key_spend_message(&self.tx, index, &self.prevouts)?;
+            let output_key =
+                XOnlyPublicKey::from_slice(&self.prevouts[index].script_pubkey.as_bytes()[2..])?;
+            SECP256K1
+                .verify_schnorr(signature, &message, &output_key)
+                .map_err(|_| Error::WrongInputs(format!("Invalid signature for input {index}")))?;
+            self.tx = with_key_spend_signature(&self.tx, index, *signature)?;
+        }
+        Ok(())
+    }
+
+    /// Whether every input is signed.
+    pub(crate) fn is_complete(&self) -> bool {
+        self.tx.input.iter().all(|input| !input.witness.is_empty())
+    }
+
+    /// The signed funding transaction, ready to broadcast.
+    ///
+    /// # Errors
+    ///
+    /// Errors if an input is still unsigned.
+    pub(crate) fn finalize(self) -> Result<Transaction, Error> {
+        if let Some(index) = self
+            .tx
+            .input
+            .iter()
+            .position(|input| input.witness.is_empty())
+        {
+            return Err(Error::WrongInputs(format!(
+                "Input {index} of {} is not signed",
+                self.owners[index]
+            )));
+        }
+        Ok(self.tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, Txid, hashes::Hash};
+
+    use super::*;
+    use crate::{
+        funding::{Funding, FundingStatus},
+        protocol::offer,
+        util::npub_to_address,
+    };
+
+    fn contribution(nsec: &SecretNsec, seed: u8, amounts: &[u64], change: u64) -> Contribution {
+        let address = npub_to_address(&nsec.public_key(), Network::Regtest).unwrap();
+        Contribution {
+            npub: nsec.public_key(),
+            inputs: amounts
+                .iter()
+                .enumerate()
+                .map(|(vout, amount)| FundingInput {
+                    outpoint: OutPoint::new(Txid::from_byte_array([seed; 32]), vout as u32),
+                    prevout: TxOut {
+                        value: Amount::from_sat(*amount),
+                        script_pubkey: address.script_pubkey(),
+                    },
+                })
+                .collect(),
+            change: (change > 0).then(|| TxOut {
+                value: Amount::from_sat(change),
+                script_pubkey: address.script_pubkey(),
+            }),
+        }
+    }
+
+    #[test]
+    fn both_parties_fund() {
+        let (nsec_buyer, nsec_seller) = (SecretNsec::generate(), SecretNsec::generate());
+        let (npub_buyer, npub_seller) = (nsec_buyer.public_key(), nsec_seller.public_key());
+        let offer = Offer {
+            counterparty: Some(npub_buyer),
+            amount_seller: Amount::from_sat(20_000),
+            ..offer(npub_seller, None)
+        };
+        let builder = CofundingBuilder::new(&offer, &npub_buyer)
+            .unwrap()
+            .contribute(contribution(&nsec_buyer, 1, &[60_000, 50_000], 9_000))
+            .unwrap();
+        assert!(builder.build().is_err());
+
+        // Contributions are checked against the offer.
+        let short = contribution(&nsec_seller, 2, &[15_000], 0);
+        let error = builder
+            .clone()
+            .contribute(short)
+            .unwrap()
+            .build()
+            .unwrap_err();
+        assert_eq!(error.code(), 305);
+        let stranger = contribution(&SecretNsec::generate(), 3, &[50_000], 0);
+        assert!(builder.clone().contribute(stranger).is_err());
+        let reused = contribution(&nsec_seller, 1, &[60_000], 0);
+        assert!(builder.clone().contribute(reused).is_err());
+
+        let builder = builder
+            .contribute(contribution(&nsec_seller, 2, &[21_000], 0))
+            .unwrap();
+        assert!(
+            builder
+                .clone()
+                .contribute(contribution(&nsec_seller, 2, &[21_000], 0))
+                .is_err()
+        );
+        let mut cofunding = builder.build().unwrap();
+        assert_eq!(cofunding.tx.input.len(), 3);
+        assert_eq!(cofunding.inputs_of(&npub_buyer), [0, 1]);
+        assert_eq!(cofunding.inputs_of(&npub_seller), [2]);
+        assert_eq!(cofunding.fee(), Amount::from_sat(2_000));
+
+        // Each party signs its own inputs only.
+        let signatures_buyer = cofunding.sign(nsec_buyer.duplicate()).unwrap();
+        let signatures_seller = cofunding.sign(nsec_seller.duplicate()).unwrap();
+        assert_eq!(signatures_buyer.len(), 2);
+        assert!(
+            cofunding
+                .add_signatures(&npub_buyer, &signatures_seller)
+                .is_err()
+        );
+        cofunding
+            .add_signatures(&npub_buyer, &signatures_buyer)
+            .unwrap();
+        assert!(!cofunding.is_complete());
+        assert!(cofunding.clone().finalize().is_err());
+        cofunding
+            .add_signatures(&npub_seller, &signatures_seller)
+            .unwrap();
+        assert!(cofunding.is_complete());
+        let tx = cofunding.finalize().unwrap();
+
+        let escrow_address = offer.escrow_address(&npub_buyer).unwrap();
+        let mut funding = Funding::new(offer.amount_buyer + offer.amount_seller);
+        funding.add_tx(&tx, &escrow_address);
+        assert_eq!(funding.status(), FundingStatus::Exact);
+    }
+}
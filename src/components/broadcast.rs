@@ -6,16 +6,23 @@ use dioxus::prelude::*;
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::{info, trace};
 
-use crate::ESPLORA_ENDPOINT;
-use crate::esplora::{broadcast_transaction, create_client};
+use crate::broadcast::{Broadcaster, EsploraBackend, RetryPolicy, backend_urls};
+use crate::i18n::{tr, tr_args};
+use crate::network::Chain;
+use crate::proxy::ProxySettings;
+use crate::standardness::check_standard;
+use crate::{ESPLORA_ENDPOINT, LANGUAGE, NETWORK, PROXIES};
 
-use super::{Footer, NetworkInput, PrimaryButton, TransactionInput};
+use super::{
+    Footer, NetworkInput, PrimaryButton, TransactionExporter, TransactionInput,
+    TransactionInspector,
+};
 
 /// Broadcast escrow transaction component.
 #[component]
 pub(crate) fn Broadcast() -> Element {
     let signed_tx = use_signal(String::new);
-    let mut broadcast_result_str = use_signal(String::new);
+    let mut broadcast_result = use_signal(|| None::<Result<(), String>>);
     let mut broadcasted_txid = use_signal(String::new);
     let esplora_base_url = use_memo(move || {
         let esplora_endpoint = ESPLORA_ENDPOINT.read().clone();
@@ -28,19 +35,23 @@ pub(crate) fn Broadcast() -> Element {
     rsx! {
         main { class: "max-w-7xl mx-auto py-6 sm:px-6 lg:px-8",
             div { class: "px-4 py-6 sm:px-0",
-                h1 { class: "text-2xl font-bold text-gray-900 mb-6", "Broadcast Transaction" }
+                h1 { class: "text-2xl font-bold text-gray-900 mb-6", {tr(LANGUAGE(), "broadcast-title")} }
 
                 div { class: "bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
                         div { class: "space-y-6",
                             TransactionInput {
                                 update_var: signed_tx,
-                                label: "Signed Transaction",
+                                label: tr(LANGUAGE(), "form-signed-tx"),
                                 id: "signed-tx",
                             }
 
+                            TransactionInspector { tx_hex: signed_tx }
+
+                            TransactionExporter { tx_hex: signed_tx }
+
                             div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
-                                NetworkInput { id: "network", label: "Bitcoin Network" }
+                                NetworkInput { id: "network", label: tr(LANGUAGE(), "form-network") }
                             }
 
                             div { class: "pt-5",
@@ -49,7 +60,13 @@ pub(crate) fn Broadcast() -> Element {
                                         onclick: move |_| {
                                             #[cfg(debug_assertions)]
                                             info!(% ESPLORA_ENDPOINT, "Created esplora client");
-                                            let esplora_client = create_client(&ESPLORA_ENDPOINT.read()).unwrap();
+                                            let proxies = ProxySettings::parse(&PROXIES.read()).unwrap();
+                                            let chain = NETWORK.read().parse::<Chain>().unwrap_or_default();
+                                            let backends = backend_urls(&ESPLORA_ENDPOINT.read(), chain)
+                                                .iter()
+                                                .filter_map(|url| EsploraBackend::new(url, &proxies).ok())
+                                                .collect::<Vec<_>>();
+                                            let broadcaster = Broadcaster::new(backends, RetryPolicy::default());
                                             let signed_tx: Transaction = consensus::encode::deserialize_hex(
                                                     &signed_tx.read(),
                                                 )
@@ -57,29 +74,33 @@ pub(crate) fn Broadcast() -> Element {
                                             let txid = signed_tx.compute_txid();
                                             broadcasted_txid.set(txid.to_string());
                                             spawn(async move {
-                                                let broadcast_result = broadcast_transaction(&esplora_client, &signed_tx)
-                                                    .await;
+                                                // Catch what the network would refuse with a clearer error.
+                                                let outcome = match check_standard(&signed_tx, None) {
+                                                    Ok(()) => broadcaster.broadcast(&signed_tx).await.outcome(),
+                                                    Err(err) => Err(err),
+                                                };
                                                 #[cfg(debug_assertions)]
-                                                info!(? broadcast_result, "broadcast_result");
-                                                match broadcast_result {
+                                                info!(? outcome, "broadcast_result");
+                                                match outcome {
                                                     Ok(_) => {
                                                         #[cfg(debug_assertions)]
                                                         info!(% txid, "Transaction broadcasted successfully");
-                                                        broadcast_result_str.set("Success".to_string());
+                                                        broadcast_result.set(Some(Ok(())));
                                                     }
                                                     Err(err) => {
                                                         #[cfg(debug_assertions)]
                                                         trace!(% txid, ? err, "Transaction broadcast failed");
-                                                        let error_string = format!(
-                                                            "Error broadcasting transaction: {}",
-                                                            err,
+                                                        let error_string = tr_args(
+                                                            LANGUAGE(),
+                                                            "broadcast-error",
+                                                            &[("reason", &err.user_message())],
                                                         );
-                                                        broadcast_result_str.set(error_string);
+                                                        broadcast_result.set(Some(Err(error_string)));
                                                     }
                                                 }
                                             });
                                         },
-                                        text: "Broadcast Transaction",
+                                        text: tr(LANGUAGE(), "broadcast-title"),
                                     }
                                 }
                             }
@@ -89,7 +110,7 @@ pub(crate) fn Broadcast() -> Element {
 
                 // Success State
                 if !broadcasted_txid.read().is_empty()
-                    && broadcast_result_str.read().starts_with("Success")
+                    && matches!(*broadcast_result.read(), Some(Ok(())))
                 {
                     // Result Section
                     div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
@@ -115,11 +136,12 @@ pub(crate) fn Broadcast() -> Element {
                                     }
                                     div { class: "ml-3",
                                         h3 { class: "text-sm font-medium text-green-800",
-                                            "Transaction Broadcasted Successfully"
+                                            {tr(LANGUAGE(), "broadcast-success")}
                                         }
                                         div { class: "mt-2 text-sm text-green-700",
                                             p {
-                                                "Transaction ID: "
+                                                {tr(LANGUAGE(), "broadcast-txid")}
+                                                " "
                                                 span { class: "font-mono break-all",
                                                     {broadcasted_txid}
                                                 }
@@ -131,7 +153,7 @@ pub(crate) fn Broadcast() -> Element {
                                                     href: format!("{}tx/{}", esplora_base_url.read(), broadcasted_txid.read()),
                                                     target: "_blank",
                                                     class: "bg-green-50 px-2 py-1.5 rounded-md text-sm font-medium text-green-800 hover:bg-green-100 focus:outline-none focus:ring-2 focus:ring-offset-2 focus:ring-offset-green-50 focus:ring-green-600",
-                                                    "View on Block Explorer"
+                                                    {tr(LANGUAGE(), "broadcast-explorer")}
                                                 }
                                             }
                                         }
@@ -141,7 +163,7 @@ pub(crate) fn Broadcast() -> Element {
                         }
                     }
                 } else if !broadcasted_txid.read().is_empty()
-                    && broadcast_result_str.read().starts_with("Error")
+                    && matches!(*broadcast_result.read(), Some(Err(_)))
                 {
                     // Result Section
                     div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
@@ -178,10 +200,10 @@ pub(crate) fn Broadcast() -> Element {
                                     }
                                     div { class: "ml-3",
                                         h3 { class: "text-sm font-medium text-red-800",
-                                            "Broadcast Failed"
+                                            {tr(LANGUAGE(), "broadcast-failed")}
                                         }
                                         div { class: "mt-2 text-sm text-red-700",
-                                            p { {broadcast_result_str} }
+                                            p { {broadcast_result.read().clone().and_then(Result::err).unwrap_or_default()} }
                                         }
                                     }
                                 }
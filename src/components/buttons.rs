@@ -8,6 +8,8 @@ use web_sys::window;
 use dioxus::logger::tracing;
 
 use crate::Route;
+#[cfg(debug_assertions)]
+use crate::logging::Redacted;
 
 /// Copy button component.
 #[component]
@@ -29,7 +31,7 @@ pub(crate) fn CopyButton(text: String, clipboard_text: String) -> Element {
                 match JsFuture::from(clipboard.write_text(&clipboard_text)).await {
                     Ok(_) => {
                         #[cfg(debug_assertions)]
-                        tracing::info!(text = %clipboard_text, "Copied to clipboard");
+                        tracing::info!(text = %Redacted(&clipboard_text), "Copied to clipboard");
                     }
                     Err(_e) => {
                         #[cfg(debug_assertions)]
@@ -5,13 +5,19 @@ use dioxus::prelude::*;
 
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::{info, trace};
+
+#[cfg(debug_assertions)]
+use crate::logging::TxSummary;
 use secp256k1::schnorr;
 
 use crate::{
-    Route,
+    LANGUAGE, NETWORK, Route,
+    error::Error,
+    i18n::tr,
+    network::{Chain, NetworkProfile},
     scripts::{escrow_scripts, escrow_spend_info},
     sign::combine_signatures,
-    util::{days_to_blocks, hours_to_blocks, parse_escrow_type, parse_npub},
+    util::{blocks_for_duration, days_hours, parse_escrow_type, parse_npub},
 };
 
 use super::{
@@ -36,7 +42,7 @@ pub(crate) fn Combine() -> Element {
     rsx! {
         main { class: "max-w-7xl mx-auto py-6 sm:px-6 lg:px-8",
             div { class: "px-4 py-6 sm:px-0",
-                h1 { class: "text-2xl font-bold text-gray-900 mb-6", "Combine Signatures" }
+                h1 { class: "text-2xl font-bold text-gray-900 mb-6", {tr(LANGUAGE(), "combine-title")} }
 
                 div { class: "bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
@@ -44,7 +50,7 @@ pub(crate) fn Combine() -> Element {
 
                             TransactionInput {
                                 update_var: unsigned_tx,
-                                label: "Unsigned Transaction",
+                                label: tr(LANGUAGE(), "form-unsigned-tx"),
                                 id: "unsigned-tx",
                             }
 
@@ -53,25 +59,25 @@ pub(crate) fn Combine() -> Element {
 
                                 NpubInput {
                                     id: "npub_1",
-                                    label: "First Nostr Public Key (npub)",
+                                    label: tr(LANGUAGE(), "combine-npub-1"),
                                     update_var: npub_buyer,
                                 }
 
                                 NpubInput {
                                     id: "npub_2",
-                                    label: "Second Nostr Public Key (npub)",
+                                    label: tr(LANGUAGE(), "combine-npub-2"),
                                     update_var: npub_seller,
                                 }
 
                                 SignatureInput {
                                     update_var: signature_1,
-                                    label: "First Signature",
+                                    label: tr(LANGUAGE(), "combine-signature-1"),
                                     id: "signature1",
                                 }
 
                                 SignatureInput {
                                     update_var: signature_2,
-                                    label: "Second Signature",
+                                    label: tr(LANGUAGE(), "combine-signature-2"),
                                     id: "signature2",
                                 }
 
@@ -82,14 +88,14 @@ pub(crate) fn Combine() -> Element {
                                 id: "arbitrator-section",
                                 class: "border-t border-gray-200 pt-6",
                                 h3 { class: "text-lg font-medium text-gray-900",
-                                    "Arbitrator Details (for Dispute Resolution)"
+                                    {tr(LANGUAGE(), "form-arbitrator-details")}
                                 }
 
                                 div { class: "mt-4 grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
 
                                     NpubInput {
                                         id: "npub_arbitrator",
-                                        label: "Arbitrator Nostr Public Key (npub)",
+                                        label: tr(LANGUAGE(), "form-arbitrator-npub"),
                                         update_var: npub_arbitrator,
                                     }
 
@@ -102,7 +108,7 @@ pub(crate) fn Combine() -> Element {
 
                                     SignatureInput {
                                         update_var: signature_arbitrator,
-                                        label: "Arbitrator Signature",
+                                        label: tr(LANGUAGE(), "combine-signature-arbitrator"),
                                         id: "signaturearb",
                                     }
                                 }
@@ -118,33 +124,70 @@ pub(crate) fn Combine() -> Element {
                                                 % signature_arbitrator, % timelock_days, % timelock_hours, % escrow_type,
                                                 "Clicked Combine Signatures"
                                             );
-                                            let npub_buyer = parse_npub(&npub_buyer.read()).unwrap();
-                                            let npub_seller = parse_npub(&npub_seller.read()).unwrap();
+                                            // The npubs and signatures come from the counterparties, so they must not panic.
+                                            let npub_buyer = match parse_npub(&npub_buyer.read()) {
+                                                Ok(npub_buyer) => npub_buyer,
+                                                Err(e) => {
+                                                    signed_tx_str.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
+                                            let npub_seller = match parse_npub(&npub_seller.read()) {
+                                                Ok(npub_seller) => npub_seller,
+                                                Err(e) => {
+                                                    signed_tx_str.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
                                             let escrow_type = parse_escrow_type(&escrow_type.read()).unwrap();
                                             let unsigned_tx: Transaction = consensus::encode::deserialize_hex(
                                                     &unsigned_tx.read(),
                                                 )
                                                 .unwrap();
-                                            let signatures: Vec<schnorr::Signature> = vec![
+                                            let signatures = vec![
                                                 signature_1.read(),
                                                 signature_2.read(),
                                                 signature_arbitrator.read(),
                                             ]
                                                 .into_iter()
                                                 .filter(|s| !s.is_empty())
-                                                .map(|s| s.parse::<schnorr::Signature>().unwrap())
-                                                .collect();
+                                                .map(|s| s.parse::<schnorr::Signature>().map_err(Error::from))
+                                                .collect::<Result<Vec<_>, _>>();
+                                            let signatures = match signatures {
+                                                Ok(signatures) => signatures,
+                                                Err(e) => {
+                                                    signed_tx_str.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
                                             let signed_tx = if !npub_arbitrator.read().is_empty() {
                                                 #[cfg(debug_assertions)]
                                                 trace!("dispute escrow combine signatures");
-                                                let npub_arbitrator = parse_npub(&npub_arbitrator.read()).unwrap();
-                                                let timelock_hours = hours_to_blocks(
-                                                    timelock_hours.read().parse::<u32>().unwrap(),
-                                                );
-                                                let timelock_days = days_to_blocks(
-                                                    timelock_days.read().parse::<u32>().unwrap(),
+                                                let npub_arbitrator = match parse_npub(&npub_arbitrator.read()) {
+                                                    Ok(npub_arbitrator) => npub_arbitrator,
+                                                    Err(e) => {
+                                                        signed_tx_str.set(e.user_message());
+                                                        return;
+                                                    }
+                                                };
+                                                let profile = NetworkProfile::from(
+                                                    NETWORK.read().parse::<Chain>().unwrap(),
                                                 );
-                                                let timelock_duration = timelock_days + timelock_hours;
+                                                let Some(timelock_duration) = blocks_for_duration(
+                                                    days_hours(
+                                                        timelock_days.read().parse::<u32>().unwrap(),
+                                                        timelock_hours.read().parse::<u32>().unwrap(),
+                                                    ),
+                                                    &profile,
+                                                ) else {
+                                                    signed_tx_str.set(
+                                                        Error::WrongInputs(
+                                                                format!("timelock is longer than {} blocks", u16::MAX),
+                                                            )
+                                                            .user_message(),
+                                                    );
+                                                    return;
+                                                };
                                                 let locking_script = escrow_scripts(
                                                         &npub_buyer,
                                                         &npub_seller,
@@ -160,14 +203,13 @@ pub(crate) fn Combine() -> Element {
                                                         Some(timelock_duration),
                                                     )
                                                     .unwrap();
-                                                let signed_tx = combine_signatures(
+                                                combine_signatures(
                                                     unsigned_tx,
                                                     0,
                                                     signatures.iter().collect::<Vec<&schnorr::Signature>>(),
                                                     &locking_script,
                                                     &taproot_spend_info,
-                                                );
-                                                consensus::serialize(&signed_tx).as_hex().to_string()
+                                                )
                                             } else {
                                                 #[cfg(debug_assertions)]
                                                 trace!("collaborative escrow combine signatures");
@@ -186,20 +228,29 @@ pub(crate) fn Combine() -> Element {
                                                         None,
                                                     )
                                                     .unwrap();
-                                                let signed_tx = combine_signatures(
+                                                combine_signatures(
                                                     unsigned_tx,
                                                     0,
                                                     signatures.iter().collect::<Vec<&schnorr::Signature>>(),
                                                     &locking_script,
                                                     &taproot_spend_info,
-                                                );
-                                                consensus::serialize(&signed_tx).as_hex().to_string()
+                                                )
+                                            };
+                                            let signed_tx = match signed_tx {
+                                                Ok(signed_tx) => signed_tx,
+                                                Err(e) => {
+                                                    signed_tx_str.set(e.user_message());
+                                                    return;
+                                                }
                                             };
                                             #[cfg(debug_assertions)]
-                                            info!(% signed_tx, "Combined signatures into a signed transaction");
-                                            signed_tx_str.set(signed_tx);
+                                            info!(
+                                                signed_tx = % TxSummary(& signed_tx),
+                                                "Combined signatures into a signed transaction"
+                                            );
+                                            signed_tx_str.set(consensus::serialize(&signed_tx).as_hex().to_string());
                                         },
-                                        text: "Combine Signatures",
+                                        text: tr(LANGUAGE(), "combine-title"),
                                     }
                                 }
                             }
@@ -211,24 +262,24 @@ pub(crate) fn Combine() -> Element {
                 div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
                         h3 { class: "text-lg leading-6 font-medium text-gray-900",
-                            "Signed Transaction"
+                            {tr(LANGUAGE(), "form-signed-tx")}
                         }
 
                         TransactionOutput {
                             update_var: signed_tx_str,
                             label: "",
                             id: "signed-tx",
-                            placeholder: "Signed transaction will appear here...",
+                            placeholder: tr(LANGUAGE(), "form-signed-tx-placeholder"),
                         }
 
                         div { class: "mt-5 flex flex-col space-y-3 sm:flex-row sm:space-y-0 sm:space-x-3",
                             CopyButton {
-                                text: "Transaction",
+                                text: tr(LANGUAGE(), "button-transaction"),
                                 clipboard_text: signed_tx_str,
                             }
                             ContinueButton {
                                 to: Route::Broadcast {},
-                                text: "Continue to Broadcast",
+                                text: tr(LANGUAGE(), "button-continue-broadcast"),
                             }
                         }
                     }
@@ -1,51 +1,88 @@
-//! Create escrow transaction component.
+//! Create escrow wizard component.
 
 use bitcoin::{Amount, Txid, consensus, hex::DisplayHex};
 use dioxus::prelude::*;
+use nostr::Timestamp;
 
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::{info, trace};
 
+#[cfg(debug_assertions)]
+use crate::logging::TxSummary;
+
 use crate::{
-    ESPLORA_ENDPOINT, NETWORK, Route,
-    esplora::{FeeEstimate, create_client, get_fee_estimates},
-    scripts::escrow_address,
-    tx::escrow_tx,
-    util::{
-        P2TR_TX_VBYTE_C, days_to_blocks, hours_to_blocks, npub_to_address, parse_network,
-        parse_npub,
-    },
+    ESPLORA_ENDPOINT, LANGUAGE, NETWORK, PROXIES, Route, SETTINGS,
+    address_book::AddressBook,
+    draft::{EscrowDraft, WizardStep},
+    esplora::{FeeEstimate, create_client, get_block_height, get_fee_estimates},
+    faucet::{DEFAULT_DEPOSIT_TIMEOUT, Faucet, wait_for_deposit},
+    i18n::{Language, tr, tr_args},
+    network::{Chain, NetworkProfile},
+    protocol::{Offer, Role, serialize},
+    proxy::ProxySettings,
+    storage::LocalStorage,
+    summary::{Party, describe_escrow},
+    templates::EscrowTemplate,
+    util::npub_to_address,
 };
 
 use super::{
-    BitcoinInput, ContinueButton, CopyButton, DerivedAddressOutput, FeeRateSelector, Footer,
-    NetworkInput, NpubInput, NpubInputDerivedAddress, PrimaryButton, TimelockInput,
-    TransactionOutput, TxidInput,
+    BitcoinInput, ContactSelect, ContinueButton, CopyButton, FeeRateSelector, Footer, NetworkInput,
+    NpubInput, NpubInputDerivedAddress, PrimaryButton, SecondaryButton, TemplateInput,
+    TimelockInput, TransactionOutput, TxidInput,
 };
 
-/// Create escrow transaction component.
+/// Create escrow wizard component.
+///
+/// Guides the user through the [`WizardStep`]s, validating each one with the
+/// [`EscrowDraft`] builders before moving on.
+/// All steps stay mounted, so going back keeps the inputs.
 #[component]
 pub(crate) fn Create() -> Element {
+    let mut step = use_signal(|| WizardStep::Parties);
+    let mut step_error = use_signal(String::new);
+    let mut role = use_signal(|| Role::Buyer);
     let npub_buyer = use_signal(String::new);
     let npub_seller = use_signal(String::new);
-    let npub_arbitrator = use_signal(String::new);
-    let amount_buyer = use_signal(String::new);
-    let amount_seller = use_signal(String::new);
+    let mut npub_arbitrator = use_signal(String::new);
+    let mut amount_buyer = use_signal(String::new);
+    let mut amount_seller = use_signal(String::new);
     let mut fee_rate = use_signal(String::new);
     let fee_estimates = use_signal(|| Option::<FeeEstimate>::None);
-    let timelock_days = use_signal(String::new);
-    let timelock_hours = use_signal(String::new);
-    let funding_txid = use_signal(String::new);
-    let mut escrow_address_str = use_signal(String::new);
+    let block_height = use_signal(|| Option::<u32>::None);
+    let mut timelock_days = use_signal(String::new);
+    let mut timelock_hours = use_signal(String::new);
+    let mut funding_txid = use_signal(String::new);
+    let mut faucet_status = use_signal(String::new);
     let mut escrow_transaction = use_signal(String::new);
-    let mut derived_address_buyer = use_signal(String::new);
-    let mut derived_address_seller = use_signal(String::new);
+    let derived_address_buyer = use_signal(String::new);
+    let derived_address_seller = use_signal(String::new);
+    let address_book = use_signal(|| AddressBook::load(&LocalStorage).unwrap_or_default());
+
+    let draft = use_memo(move || EscrowDraft {
+        network: NETWORK.read().clone(),
+        role: *role.read(),
+        npub_buyer: npub_buyer.read().clone(),
+        npub_seller: npub_seller.read().clone(),
+        amount_buyer: amount_buyer.read().clone(),
+        amount_seller: amount_seller.read().clone(),
+        fee_rate: fee_rate.read().clone(),
+        fee_rates: SETTINGS().fee_rates,
+        npub_arbitrator: npub_arbitrator.read().clone(),
+        timelock_days: timelock_days.read().clone(),
+        timelock_hours: timelock_hours.read().clone(),
+    });
+    let proposal = use_memo(move || draft.read().build().ok());
+    // The proposal shared with the counterparty, fixed once the share step is reached.
+    let mut offer = use_signal(|| Option::<Offer>::None);
+    let mut offer_json = use_signal(String::new);
 
     use_effect(move || {
-        to_owned![fee_estimates];
+        to_owned![fee_estimates, block_height];
 
         spawn(async move {
-            let esplora_client = create_client(&ESPLORA_ENDPOINT.read()).unwrap();
+            let proxies = ProxySettings::parse(&PROXIES.read()).unwrap();
+            let esplora_client = create_client(&ESPLORA_ENDPOINT.read(), &proxies).unwrap();
             match get_fee_estimates(&esplora_client).await {
                 Ok(estimates) => {
                     #[cfg(debug_assertions)]
@@ -55,26 +92,82 @@ pub(crate) fn Create() -> Element {
                 Err(e) => {
                     #[cfg(debug_assertions)]
                     trace!("Error fetching fee estimates: {}", e);
-                    // Fall back to 3 sat/vB
-                    fee_rate.set("3".to_string());
+                    let fallback = SETTINGS().fee_rates.default_fee_rate(None);
+                    fee_rate.set(fallback.to_sat_per_vb_ceil().to_string());
                 }
             }
+            // Without the tip height, the offer proposes no lock time.
+            if let Ok(height) = get_block_height(&esplora_client).await {
+                block_height.set(Some(height));
+            }
         });
     });
 
+    let visible = move |shown: WizardStep| {
+        if *step.read() == shown {
+            "space-y-6"
+        } else {
+            "hidden"
+        }
+    };
+
+    let progress = move |shown: WizardStep| {
+        let current = *step.read();
+        if shown == current {
+            "border-t-4 border-indigo-600 pt-2 text-sm font-medium text-indigo-600"
+        } else if shown < current {
+            "border-t-4 border-indigo-300 pt-2 text-sm font-medium text-gray-700"
+        } else {
+            "border-t-4 border-gray-200 pt-2 text-sm font-medium text-gray-500"
+        }
+    };
+
     rsx! {
         main { class: "max-w-7xl mx-auto py-6 sm:px-6 lg:px-8",
             div { class: "px-4 py-6 sm:px-0",
-                h1 { class: "text-2xl font-bold text-gray-900 mb-6", "Create Escrow" }
+                h1 { class: "text-2xl font-bold text-gray-900 mb-6", {tr(LANGUAGE(), "create-title")} }
+
+                nav { aria_label: tr(LANGUAGE(), "create-progress"),
+                    ol { class: "mb-6 grid grid-cols-1 gap-2 sm:grid-cols-5",
+                        for wizard_step in WizardStep::ALL {
+                            li { class: progress(wizard_step),
+                                {format!("{}. {}", wizard_step.index() + 1, tr(LANGUAGE(), wizard_step.title_key()))}
+                            }
+                        }
+                    }
+                }
 
                 div { class: "bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
-                        div { class: "space-y-6",
+                        div { class: visible(WizardStep::Parties),
                             div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
+                                NetworkInput { id: "network", label: tr(LANGUAGE(), "form-network") }
+
+                                div { class: "sm:col-span-3",
+                                    label {
+                                        r#for: "role",
+                                        class: "block text-sm font-medium text-gray-700",
+                                        {tr(LANGUAGE(), "create-role")}
+                                    }
+                                    div { class: "mt-1",
+                                        select {
+                                            id: "role",
+                                            name: "role",
+                                            class: "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border",
+                                            oninput: move |event| {
+                                                #[cfg(debug_assertions)]
+                                                trace!(event_value =% event.value(), "Set role");
+                                                role.set(if event.value() == "seller" { Role::Seller } else { Role::Buyer });
+                                            },
+                                            option { value: "buyer", {tr(LANGUAGE(), "create-role-buyer")} }
+                                            option { value: "seller", {tr(LANGUAGE(), "create-role-seller")} }
+                                        }
+                                    }
+                                }
 
                                 NpubInputDerivedAddress {
                                     id: "npub_buyer",
-                                    label: "Buyer Nostr Public Key (npub)",
+                                    label: tr(LANGUAGE(), "form-buyer-npub"),
                                     update_var: npub_buyer,
                                     update_address: derived_address_buyer,
                                     col_span: 3,
@@ -82,245 +175,333 @@ pub(crate) fn Create() -> Element {
 
                                 NpubInputDerivedAddress {
                                     id: "npub_seller",
-                                    label: "Seller Nostr Public Key (npub)",
+                                    label: tr(LANGUAGE(), "form-seller-npub"),
                                     update_var: npub_seller,
                                     update_address: derived_address_seller,
                                     col_span: 3,
                                 }
 
+                                TemplateInput {
+                                    address_book,
+                                    on_import: move |template: EscrowTemplate| {
+                                        let applied = template.apply(&draft.peek());
+                                        amount_buyer.set(applied.amount_buyer);
+                                        amount_seller.set(applied.amount_seller);
+                                        fee_rate.set(applied.fee_rate);
+                                        npub_arbitrator.set(applied.npub_arbitrator);
+                                        timelock_days.set(applied.timelock_days);
+                                        timelock_hours.set(applied.timelock_hours);
+                                    },
+                                }
+
+                                if !address_book.read().contacts().is_empty() {
+                                    ContactSelect {
+                                        id: "contact_buyer",
+                                        label: tr(LANGUAGE(), "create-buyer-contact"),
+                                        update_var: npub_buyer,
+                                        update_address: derived_address_buyer,
+                                        address_book,
+                                        col_span: 3,
+                                    }
+
+                                    ContactSelect {
+                                        id: "contact_seller",
+                                        label: tr(LANGUAGE(), "create-seller-contact"),
+                                        update_var: npub_seller,
+                                        update_address: derived_address_seller,
+                                        address_book,
+                                        col_span: 3,
+                                    }
+                                }
+                            }
+                        }
+
+                        div { class: visible(WizardStep::Amounts),
+                            div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
                                 BitcoinInput {
                                     id: "amount_buyer",
-                                    label: "Buyer Escrow Amount (BTC)",
+                                    label: tr(LANGUAGE(), "form-buyer-amount"),
                                     update_var: amount_buyer,
                                 }
 
                                 BitcoinInput {
                                     id: "amount_seller",
-                                    label: "Seller Escrow Amount (BTC)",
+                                    label: tr(LANGUAGE(), "form-seller-amount"),
                                     update_var: amount_seller,
                                 }
 
                                 FeeRateSelector {
                                     id: "fee",
-                                    label_input: "Fee rate (sats/vByte)",
-                                    label_dropdown: "Target Blocks",
+                                    label_input: tr(LANGUAGE(), "form-fee-rate"),
+                                    label_dropdown: tr(LANGUAGE(), "form-target-blocks"),
                                     update_var: fee_rate,
                                     fee_estimates,
                                 }
-
-                                NetworkInput { id: "network", label: "Bitcoin Network" }
                             }
+                            p { class: "text-xs text-gray-500",
+                                {tr(LANGUAGE(), "create-fee-split")}
+                            }
+                        }
 
-                            div { class: "border-t border-gray-200 pt-6",
-                                h3 { class: "text-lg font-medium text-gray-900",
-                                    "Optional Dispute Resolution"
+                        div { class: visible(WizardStep::Dispute),
+                            p { class: "text-sm text-gray-500",
+                                {tr(LANGUAGE(), "create-arbitrator-optional")}
+                            }
+                            div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
+                                NpubInput {
+                                    id: "npub_arbitrator",
+                                    label: tr(LANGUAGE(), "form-arbitrator-npub"),
+                                    update_var: npub_arbitrator,
                                 }
 
-                                div { class: "mt-4 grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
-
-                                    NpubInput {
-                                        id: "npub_arbitrator",
-                                        label: "Arbitrator Nostr Public Key (npub)",
+                                if !address_book.read().contacts().is_empty() {
+                                    ContactSelect {
+                                        id: "contact_arbitrator",
+                                        label: tr(LANGUAGE(), "create-arbitrator-contact"),
                                         update_var: npub_arbitrator,
-                                    }
-
-                                    TimelockInput {
-                                        update_day_var: timelock_days,
-                                        update_hour_var: timelock_hours,
+                                        address_book,
+                                        col_span: 3,
                                     }
                                 }
-                            }
 
-                            div { class: "border-t border-gray-200 pt-6",
-                                div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
-                                    DerivedAddressOutput {
-                                        update_var: escrow_address_str,
-                                        label: "Deposit Address",
-                                        id: "escrow-address",
-                                        col_span: 3,
-                                    }
+                                TimelockInput {
+                                    update_day_var: timelock_days,
+                                    update_hour_var: timelock_hours,
                                 }
                             }
+                        }
 
-                            div { class: "border-t border-gray-200 pt-6",
-                                div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
-                                    DerivedAddressOutput {
-                                        update_var: derived_address_buyer,
-                                        label: "Buyer's Resolution Address",
-                                        id: "buyer-address",
-                                        col_span: 3,
+                        div { class: visible(WizardStep::Review),
+                            if let Some(proposal) = proposal.read().as_ref() {
+                                dl { class: "grid grid-cols-1 gap-x-4 gap-y-6 sm:grid-cols-2",
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-deposit-address"),
+                                        value: proposal.address.to_string(),
                                     }
-
-                                    DerivedAddressOutput {
-                                        update_var: derived_address_seller,
-                                        label: "Seller's Resolution Address",
-                                        id: "seller-address",
-                                        col_span: 3,
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-total-deposit"),
+                                        value: display_amount(proposal.funding_amount()),
+                                    }
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-buyer-amount"),
+                                        value: display_amount(proposal.amount_buyer),
+                                    }
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-seller-amount"),
+                                        value: display_amount(proposal.amount_seller),
+                                    }
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-buyer-address"),
+                                        value: npub_to_address(&proposal.config.npub_1, proposal.config.network)
+                                            .map(|address| address.to_string())
+                                            .unwrap_or_default(),
+                                    }
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-seller-address"),
+                                        value: npub_to_address(&proposal.config.npub_2, proposal.config.network)
+                                            .map(|address| address.to_string())
+                                            .unwrap_or_default(),
+                                    }
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-fee"),
+                                        value: proposal.fee.to_string(),
+                                    }
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-dispute"),
+                                        value: match (proposal.config.npub_arbitrator, proposal.config.timelock_duration) {
+                                            (Some(arbitrator), Some(timelock)) => {
+                                                tr_args(
+                                                    LANGUAGE(),
+                                                    "review-dispute-after",
+                                                    &[("arbitrator", &arbitrator), ("blocks", &timelock)],
+                                                )
+                                            }
+                                            _ => tr(LANGUAGE(), "review-collaborative"),
+                                        },
+                                    }
+                                    ReviewItem {
+                                        label: tr(LANGUAGE(), "review-conditions"),
+                                        value: NETWORK
+                                            .read()
+                                            .parse::<Chain>()
+                                            .and_then(|chain| describe_escrow(&proposal.config, &NetworkProfile::from(chain)))
+                                            .map(|summary| summary.describe(LANGUAGE(), |party| party_name(LANGUAGE(), party)))
+                                            .unwrap_or_else(|e| e.user_message()),
                                     }
                                 }
                             }
+                        }
 
-
-                            div { class: "mt-5 flex flex-col space-y-3 sm:flex-row sm:space-y-0 sm:space-x-3",
+                        div { class: visible(WizardStep::Share),
+                            p { class: "text-sm text-gray-500",
+                                {tr(LANGUAGE(), "create-share")}
+                            }
+                            div { class: "flex flex-col space-y-3 sm:flex-row sm:space-y-0 sm:space-x-3",
                                 CopyButton {
-                                    text: "Deposit Address",
-                                    clipboard_text: escrow_address_str,
+                                    text: tr(LANGUAGE(), "create-proposal"),
+                                    clipboard_text: offer_json,
                                 }
-                                PrimaryButton {
-                                    onclick: move |_| {
-                                        #[cfg(debug_assertions)]
-                                        trace!(
-                                            % npub_buyer, % npub_seller, % amount_buyer, % amount_seller, % fee_rate, %
-                                            NETWORK, % npub_arbitrator, % timelock_days, % timelock_hours,
-                                            "Clicked Generate Address"
-                                        );
-                                        let npub_buyer = parse_npub(&npub_buyer.read()).unwrap();
-                                        let npub_seller = parse_npub(&npub_seller.read()).unwrap();
-                                        let network = parse_network(&NETWORK.read()).unwrap();
-                                        *derived_address_buyer.write() = npub_to_address(&npub_buyer, network)
-                                            .unwrap()
-                                            .to_string();
-                                        *derived_address_seller.write() = npub_to_address(&npub_seller, network)
-                                            .unwrap()
-                                            .to_string();
-                                        let resolved_escrow_address = if !npub_arbitrator.read().is_empty() {
-                                            #[cfg(debug_assertions)]
-                                            trace!("dispute escrow address");
-                                            let npub_arbitrator = parse_npub(&npub_arbitrator.read()).unwrap();
-                                            let timelock_hours = hours_to_blocks(
-                                                timelock_hours.read().parse::<u32>().unwrap(),
-                                            );
-                                            let timelock_days = days_to_blocks(
-                                                timelock_days.read().parse::<u32>().unwrap(),
-                                            );
-                                            escrow_address(
-                                                    &npub_buyer,
-                                                    &npub_seller,
-                                                    Some(&npub_arbitrator),
-                                                    Some(timelock_days + timelock_hours),
-                                                    network,
-                                                )
-                                                .unwrap()
-                                                .to_string()
-                                        } else {
-                                            #[cfg(debug_assertions)]
-                                            trace!("collaborative escrow address");
-                                            escrow_address(&npub_buyer, &npub_seller, None, None, network)
-                                                .unwrap()
-                                                .to_string()
-                                        };
-                                        #[cfg(debug_assertions)]
-                                        info!(% resolved_escrow_address, "Derived escrow address");
-                                        escrow_address_str.set(resolved_escrow_address);
-                                    },
-                                    text: "Generate Address",
+                                if let Some(proposal) = proposal.read().as_ref() {
+                                    CopyButton {
+                                        text: tr(LANGUAGE(), "review-deposit-address"),
+                                        clipboard_text: proposal.address.to_string(),
+                                    }
+                                    CopyButton {
+                                        text: tr(LANGUAGE(), "create-payment-request"),
+                                        clipboard_text: proposal.payment_request().to_string(),
+                                    }
+                                }
+                                if NETWORK.read().parse::<Chain>().is_ok_and(Chain::has_faucet) {
+                                    SecondaryButton {
+                                        onclick: move |_| {
+                                            let Some(proposal) = proposal.read().clone() else {
+                                                return;
+                                            };
+                                            let Some(faucet) = NETWORK.read().parse::<Chain>().ok().and_then(Faucet::for_chain) else {
+                                                return;
+                                            };
+                                            let esplora_client = ProxySettings::parse(&PROXIES.read())
+                                                .and_then(|proxies| create_client(&ESPLORA_ENDPOINT.read(), &proxies));
+                                            spawn(async move {
+                                                match faucet.request(&proposal.address, proposal.funding_amount()).await {
+                                                    Ok(txid) => {
+                                                        #[cfg(debug_assertions)]
+                                                        info!(% txid, "Faucet funded the escrow");
+                                                        funding_txid.set(txid.to_string());
+                                                        faucet_status.set(tr_args(LANGUAGE(), "create-faucet-sent", &[("txid", &txid)]));
+                                                        let Ok(esplora_client) = esplora_client else {
+                                                            return;
+                                                        };
+                                                        match wait_for_deposit(&esplora_client, &proposal.address, txid, DEFAULT_DEPOSIT_TIMEOUT).await {
+                                                            Ok(deposit) => {
+                                                                faucet_status
+                                                                    .set(
+                                                                        tr_args(
+                                                                            LANGUAGE(),
+                                                                            "create-faucet-funded",
+                                                                            &[("amount", &display_amount(deposit.amount)), ("txid", &txid)],
+                                                                        ),
+                                                                    );
+                                                            }
+                                                            Err(e) => faucet_status.set(e.user_message()),
+                                                        }
+                                                    }
+                                                    Err(e) => faucet_status.set(e.user_message()),
+                                                }
+                                            });
+                                        },
+                                        text: tr(LANGUAGE(), "create-fund-faucet"),
+                                    }
                                 }
                             }
-                        }
-                    }
-                }
-
-                // Result Section (would be shown after button click)
-                div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
-                    div { class: "px-4 py-5 sm:p-6",
-                        h3 { class: "text-lg leading-6 font-medium text-gray-900",
-                            "Escrow Details"
-                        }
-                        div { class: "mt-4 grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
-                            TxidInput {
-                                update_var: funding_txid,
-                                label: "Escrow funding Transaction ID",
-                                warning: "Deposit a single transaction to the escrow address and inform the transaction ID.
-                                This transaction will be used to fund the escrow address.
-                                Note that it should be a coinjoin transaction between buyer and seller,
-                                i.e. should have only one output: the escrow address with the whole total escrow amount.",
+                            if !faucet_status.read().is_empty() {
+                                p { class: "text-sm text-gray-500 break-all", {faucet_status.read().clone()} }
                             }
-                        }
 
+                            div { class: "border-t border-gray-200 pt-6 grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
+                                TxidInput {
+                                    update_var: funding_txid,
+                                    label: tr(LANGUAGE(), "form-funding-txid"),
+                                    warning: tr(LANGUAGE(), "create-funding-warning"),
+                                }
 
-                        div { class: "mt-5 border-t border-gray-200 pt-5",
-                            dl { class: "grid grid-cols-1 gap-x-4 gap-y-6 sm:grid-cols-2",
                                 TransactionOutput {
                                     update_var: escrow_transaction,
-                                    label: "Unsigned Escrow Resolution Transaction",
+                                    label: tr(LANGUAGE(), "create-unsigned-resolution"),
                                     id: "escrow-tx",
-                                    placeholder: "Transaction data will appear here...",
+                                    placeholder: tr(LANGUAGE(), "create-tx-placeholder"),
                                 }
                             }
 
-                            div { class: "mt-5 flex flex-col space-y-3 sm:flex-row sm:space-y-0 sm:space-x-3",
+                            div { class: "flex flex-col space-y-3 sm:flex-row sm:space-y-0 sm:space-x-3",
                                 CopyButton {
-                                    text: "Transaction",
+                                    text: tr(LANGUAGE(), "button-transaction"),
                                     clipboard_text: escrow_transaction,
                                 }
                                 PrimaryButton {
                                     onclick: move |_| {
-                                        #[cfg(debug_assertions)]
-                                        trace!(
-                                            % npub_buyer, % npub_seller, % amount_buyer, % amount_seller, % fee_rate, %
-                                            NETWORK, % npub_arbitrator, % timelock_days, % timelock_hours,
-                                            "Clicked Generate Transaction"
-                                        );
-                                        let npub_buyer = parse_npub(&npub_buyer.read()).unwrap();
-                                        let npub_seller = parse_npub(&npub_seller.read()).unwrap();
-                                        let btc_amount_buyer = Amount::from_btc(
-                                                amount_buyer.read().parse::<f64>().unwrap(),
-                                            )
-                                            .unwrap();
-                                        let btc_amount_seller = Amount::from_btc(
-                                                amount_seller.read().parse::<f64>().unwrap(),
-                                            )
-                                            .unwrap();
-                                        let fee_rate = fee_rate.read().parse::<u64>().unwrap();
-                                        let fee = Amount::from_sat(fee_rate * P2TR_TX_VBYTE_C);
-                                        let network = parse_network(&NETWORK.read()).unwrap();
-                                        let funding_txid = funding_txid.read().parse::<Txid>().unwrap();
-                                        let resolved_escrow_transaction = if !npub_arbitrator.read().is_empty() {
-                                            #[cfg(debug_assertions)]
-                                            trace!("dispute escrow address");
-                                            let timelock_hours = hours_to_blocks(
-                                                timelock_hours.read().parse::<u32>().unwrap(),
-                                            );
-                                            let timelock_days = days_to_blocks(
-                                                timelock_days.read().parse::<u32>().unwrap(),
-                                            );
-                                            let escrow_tx = escrow_tx(
-                                                    &npub_buyer,
-                                                    &npub_seller,
-                                                    Some(timelock_days + timelock_hours),
-                                                    btc_amount_buyer,
-                                                    btc_amount_seller,
-                                                    funding_txid,
-                                                    fee,
-                                                    network,
-                                                )
-                                                .unwrap();
-                                            consensus::serialize(&escrow_tx).as_hex().to_string()
-                                        } else {
-                                            #[cfg(debug_assertions)]
-                                            trace!("collaborative escrow address");
-                                            let escrow_tx = escrow_tx(
-                                                    &npub_buyer,
-                                                    &npub_seller,
-                                                    None,
-                                                    btc_amount_buyer,
-                                                    btc_amount_seller,
-                                                    funding_txid,
-                                                    fee,
-                                                    network,
-                                                )
-                                                .unwrap();
-                                            consensus::serialize(&escrow_tx).as_hex().to_string()
+                                        let Some(proposal) = proposal.read().clone() else {
+                                            return;
                                         };
-                                        #[cfg(debug_assertions)]
-                                        info!(% resolved_escrow_transaction, "Derived escrow transaction");
-                                        escrow_transaction.set(resolved_escrow_transaction);
+                                        let Ok(funding_txid) = funding_txid.read().parse::<Txid>() else {
+                                            step_error.set(tr(LANGUAGE(), "create-invalid-funding-txid"));
+                                            return;
+                                        };
+                                        // The lock time agreed in the offer, so the counterparty builds the same transaction.
+                                        let lock_time = match offer.read().as_ref().map(Offer::lock_time) {
+                                            Some(Ok(lock_time)) => lock_time,
+                                            Some(Err(e)) => {
+                                                step_error.set(e.user_message());
+                                                return;
+                                            }
+                                            None => return,
+                                        };
+                                        match proposal.escrow_tx(funding_txid, lock_time) {
+                                            Ok(tx) => {
+                                                #[cfg(debug_assertions)]
+                                                info!(escrow_tx = % TxSummary(& tx), "Derived escrow transaction");
+                                                step_error.set(String::new());
+                                                escrow_transaction.set(consensus::serialize(&tx).as_hex().to_string());
+                                            }
+                                            Err(e) => step_error.set(e.user_message()),
+                                        }
                                     },
-                                    text: "Generate Transaction",
+                                    text: tr(LANGUAGE(), "create-generate"),
                                 }
                                 ContinueButton {
                                     to: Route::Sign {},
-                                    text: "Continue to Sign",
+                                    text: tr(LANGUAGE(), "button-continue-sign"),
+                                }
+                            }
+                        }
+
+                        if !step_error.read().is_empty() {
+                            p { class: "mt-4 text-sm text-red-600", {step_error.read().clone()} }
+                        }
+
+                        div { class: "mt-6 border-t border-gray-200 pt-5 flex justify-end",
+                            if let Some(previous) = step.read().previous() {
+                                SecondaryButton {
+                                    onclick: move |_| {
+                                        step_error.set(String::new());
+                                        step.set(previous);
+                                    },
+                                    text: tr(LANGUAGE(), "button-back"),
+                                }
+                            }
+                            if let Some(next) = step.read().next() {
+                                PrimaryButton {
+                                    onclick: move |_| {
+                                        let current = *step.read();
+                                        #[cfg(debug_assertions)]
+                                        trace!(?current, draft = ? draft.read(), "Clicked Next");
+                                        if let Err(e) = draft.read().validate(current) {
+                                            step_error.set(e.user_message());
+                                            return;
+                                        }
+                                        if next == WizardStep::Share {
+                                            let shared = proposal
+                                                .read()
+                                                .as_ref()
+                                                .map(|proposal| proposal.offer(Timestamp::now(), *block_height.read()));
+                                            match shared {
+                                                Some(Ok(shared)) => {
+                                                    #[cfg(debug_assertions)]
+                                                    info!(address = % proposal.read().as_ref().unwrap().address, "Escrow proposal ready");
+                                                    offer_json.set(serialize(&shared).unwrap_or_default());
+                                                    offer.set(Some(shared));
+                                                }
+                                                Some(Err(e)) => {
+                                                    step_error.set(e.user_message());
+                                                    return;
+                                                }
+                                                None => return,
+                                            }
+                                        }
+                                        step_error.set(String::new());
+                                        step.set(next);
+                                    },
+                                    text: tr(LANGUAGE(), if next == WizardStep::Share { "create-proposal-button" } else { "button-next" }),
                                 }
                             }
                         }
@@ -331,3 +512,28 @@ pub(crate) fn Create() -> Element {
         Footer {}
     }
 }
+
+/// `amount` in the unit and language picked in the settings.
+fn display_amount(amount: Amount) -> String {
+    SETTINGS().display_unit.format(amount, None, LANGUAGE())
+}
+
+/// Name of a [`Party`] in the wizard in `language`, where the first participant is the buyer.
+fn party_name(language: Language, party: Party) -> String {
+    match party {
+        Party::Participant1 => tr(language, "party-buyer"),
+        Party::Participant2 => tr(language, "party-seller"),
+        party => party.name(language),
+    }
+}
+
+/// A reviewed escrow parameter.
+#[component]
+fn ReviewItem(label: String, value: String) -> Element {
+    rsx! {
+        div { class: "sm:col-span-1",
+            dt { class: "text-sm font-medium text-gray-500", {label} }
+            dd { class: "mt-1 text-sm text-gray-900 break-all", {value} }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Transaction export component.
+
+use dioxus::prelude::*;
+
+use crate::{
+    LANGUAGE,
+    decode::parse_tx_hex,
+    export::{DEFAULT_BBQR_PART_LEN, export},
+    i18n::{tr, tr_args},
+};
+
+use super::CopyButton;
+
+/// Transaction export component.
+///
+/// Offers the signed transaction in `tx_hex` as raw hex, a finalized PSBT and BBQr parts,
+/// to broadcast it with another tool.
+#[component]
+pub(crate) fn TransactionExporter(tx_hex: Signal<String>) -> Element {
+    let exported = use_memo(move || {
+        if tx_hex.read().trim().is_empty() {
+            return None;
+        }
+        Some(
+            parse_tx_hex(&tx_hex.read())
+                .and_then(|tx| export(&tx, None, DEFAULT_BBQR_PART_LEN))
+                .map_err(|e| e.user_message()),
+        )
+    });
+
+    let exported = match &*exported.read() {
+        None => return rsx! {},
+        Some(Err(e)) => {
+            return rsx! {
+                p { class: "sm:col-span-6 text-sm text-red-600", {e.clone()} }
+            };
+        }
+        Some(Ok(exported)) => exported.clone(),
+    };
+    let parts = exported.bbqr.len();
+
+    rsx! {
+        div { class: "sm:col-span-6 space-y-4 rounded-md border border-gray-300 bg-gray-50 p-4",
+            h4 { class: "text-sm font-medium text-gray-700", {tr(LANGUAGE(), "export-title")} }
+            p { class: "text-sm text-gray-500",
+                {tr(LANGUAGE(), "export-help")}
+            }
+            div { class: "flex flex-col space-y-3 sm:flex-row sm:space-y-0 sm:space-x-3",
+                CopyButton { text: tr(LANGUAGE(), "export-hex"), clipboard_text: exported.hex }
+                CopyButton { text: tr(LANGUAGE(), "export-psbt"), clipboard_text: exported.psbt }
+            }
+            details { class: "text-sm",
+                summary { class: "cursor-pointer font-medium text-gray-700",
+                    {tr_args(LANGUAGE(), "export-bbqr", &[("parts", &parts)])}
+                }
+                ol { class: "mt-2 space-y-2",
+                    for part in exported.bbqr {
+                        li { class: "font-mono text-xs break-all text-gray-900", {part} }
+                    }
+                }
+            }
+        }
+    }
+}
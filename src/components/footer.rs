@@ -2,6 +2,8 @@
 
 use dioxus::prelude::*;
 
+use crate::{LANGUAGE, i18n::tr};
+
 /// Footer component.
 #[component]
 pub(crate) fn Footer() -> Element {
@@ -9,7 +11,7 @@ pub(crate) fn Footer() -> Element {
         footer { class: "bg-white mt-12 border-t border-gray-200",
             div { class: "max-w-7xl mx-auto py-6 px-4 sm:px-6 lg:px-8",
                 p { class: "text-center text-gray-500 text-sm",
-                    "Satoshi Escrow - Open Source Bitcoin Dispute Resolution"
+                    {tr(LANGUAGE(), "footer")}
                 }
             }
         }
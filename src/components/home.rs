@@ -2,7 +2,7 @@
 
 use dioxus::prelude::*;
 
-use crate::{Route, components::Footer};
+use crate::{LANGUAGE, Route, components::Footer, i18n::tr};
 
 /// Home page component.
 #[component]
@@ -13,7 +13,7 @@ pub(crate) fn Home() -> Element {
                 div { class: "prose max-w-none",
                     h1 { class: "text-4xl font-bold text-gray-900 mb-4", "Satoshi Escrow" }
                     p { class: "text-xl text-gray-600 mb-8",
-                        "A Bitcoin non-custodial peer-to-peer dispute resolution using only Nostr keys."
+                        {tr(LANGUAGE(), "home-tagline")}
                     }
                     div { class: "mt-5",
                         a {
@@ -30,66 +30,62 @@ pub(crate) fn Home() -> Element {
                                 class: "mr-2",
                                 path { d: "M12 0c-6.626 0-12 5.373-12 12 0 5.302 3.438 9.8 8.207 11.387.599.111.793-.261.793-.577v-2.234c-3.338.726-4.033-1.416-4.033-1.416-.546-1.387-1.333-1.756-1.333-1.756-1.089-.745.083-.729.083-.729 1.205.084 1.839 1.237 1.839 1.237 1.07 1.834 2.807 1.304 3.492.997.107-.775.418-1.305.762-1.604-2.665-.305-5.467-1.334-5.467-5.931 0-1.311.469-2.381 1.236-3.221-.124-.303-.535-1.524.117-3.176 0 0 1.008-.322 3.301 1.23.957-.266 1.983-.399 3.003-.404 1.02.005 2.047.138 3.006.404 2.291-1.552 3.297-1.23 3.297-1.23.653 1.653.242 2.874.118 3.176.77.84 1.235 1.911 1.235 3.221 0 4.609-2.807 5.624-5.479 5.921.43.372.823 1.102.823 2.222v3.293c0 .319.192.694.801.576 4.765-1.589 8.199-6.086 8.199-11.386 0-6.627-5.373-12-12-12z" }
                             }
-                            "View on GitHub"
+                            {tr(LANGUAGE(), "settings-github")}
                         }
                     }
                     div { class: "mt-8",
-                        h2 { class: "text-2xl font-semibold text-gray-900 mb-4", "Motivation" }
+                        h2 { class: "text-2xl font-semibold text-gray-900 mb-4", {tr(LANGUAGE(), "home-motivation")} }
                         p { class: "text-gray-600 mb-4",
-                            "When a Buyer wants to purchase something for a certain amount of BTC from a Seller but doesn't trust them,
-                            they can use our 2-of-2 multisig escrow address. The Buyer and Seller both lock their BTC in a multisig address.
-                            Both parties only need their respective Nostr secret
-                            keys (nsec) and each other's Nostr public keys (npub)."
+                            {tr(LANGUAGE(), "home-motivation-text")}
                         }
                         div { class: "bg-white shadow-sm rounded-lg p-6 mb-8",
                             h3 { class: "text-lg font-semibold text-gray-900 mb-4",
-                                "How it works"
+                                {tr(LANGUAGE(), "home-how")}
                             }
                             ol { class: "list-decimal list-inside space-y-2 text-gray-600",
                                 li {
-                                    "If the trade is successful:"
+                                    {tr(LANGUAGE(), "home-success")}
                                     ul { class: "pl-6 mt-2 list-disc",
-                                        li { "Both parties sign to release the funds" }
-                                        li { "Buyer receives his BTC back" }
-                                        li { "Seller receives his BTC back" }
+                                        li { {tr(LANGUAGE(), "home-success-sign")} }
+                                        li { {tr(LANGUAGE(), "home-success-buyer")} }
+                                        li { {tr(LANGUAGE(), "home-success-seller")} }
                                     }
                                 }
                                 li { class: "pt-2",
-                                    "If there's a dispute:"
+                                    {tr(LANGUAGE(), "home-dispute")}
                                     ul { class: "pl-6 mt-2 list-disc",
-                                        li { "Parties can choose a trusted third party (arbitrator)" }
+                                        li { {tr(LANGUAGE(), "home-dispute-arbitrator")} }
                                         li {
-                                            "Arbitrator can help resolve the dispute after a timelock period"
+                                            {tr(LANGUAGE(), "home-dispute-timelock")}
                                         }
                                         li {
-                                            "Resolution requires 2-of-3 signatures (Buyer/Seller + Arbitrator)"
+                                            {tr(LANGUAGE(), "home-dispute-signatures")}
                                         }
                                     }
                                 }
                             }
                         }
                         h2 { class: "text-2xl font-semibold text-gray-900 mb-4",
-                            "Technical Implementation"
+                            {tr(LANGUAGE(), "home-technical")}
                         }
                         p { class: "text-gray-600 mb-4",
-                            "We use Pay-to-Taproot (P2TR) multisig script path spends with a verified unknown
-                            discrete-log unspendable internal key. The system supports two resolution paths:"
+                            {tr(LANGUAGE(), "home-technical-text")}
                         }
                         div { class: "grid md:grid-cols-2 gap-6 mb-8",
                             div { class: "bg-white shadow-sm rounded-lg p-6",
                                 h3 { class: "text-lg font-semibold text-gray-900 mb-3",
-                                    "Collaborative Resolution"
+                                    {tr(LANGUAGE(), "home-collaborative")}
                                 }
                                 p { class: "text-gray-600",
-                                    "2-of-2 multisig between Buyer and Seller without timelocks."
+                                    {tr(LANGUAGE(), "home-collaborative-text")}
                                 }
                             }
                             div { class: "bg-white shadow-sm rounded-lg p-6",
                                 h3 { class: "text-lg font-semibold text-gray-900 mb-3",
-                                    "Dispute Resolution"
+                                    {tr(LANGUAGE(), "home-dispute-resolution")}
                                 }
                                 p { class: "text-gray-600",
-                                    "2-of-3 multisig between either party and the arbitrator with timelock."
+                                    {tr(LANGUAGE(), "home-dispute-resolution-text")}
                                 }
                             }
                         }
@@ -127,67 +123,66 @@ pub(crate) fn Home() -> Element {
                                 }
                                 div { class: "ml-3",
                                     h3 { class: "text-sm font-medium text-yellow-800",
-                                        "Important Security Notice"
+                                        {tr(LANGUAGE(), "home-security")}
                                     }
                                     div { class: "mt-2 text-sm text-yellow-700",
                                         p {
-                                            "This application can be used offline on an air-gapped computer for maximum security.
-                                            All transactions can be generated, and signed through the webpage offline."
+                                            {tr(LANGUAGE(), "home-security-text")}
                                         }
                                     }
                                 }
                             }
                         }
-                        h2 { class: "text-2xl font-semibold text-gray-900 mb-4", "Getting Started" }
+                        h2 { class: "text-2xl font-semibold text-gray-900 mb-4", {tr(LANGUAGE(), "home-getting-started")} }
                         div { class: "grid md:grid-cols-5 gap-6",
                             Link {
                                 to: Route::Create {},
                                 class: "block bg-white shadow-sm rounded-lg p-6 hover:shadow-md transition-shadow",
                                 h3 { class: "text-lg font-semibold text-gray-900 mb-2",
-                                    "1. Create Escrow"
+                                    {tr(LANGUAGE(), "home-step-create")}
                                 }
                                 p { class: "text-gray-600",
-                                    "Set up a new escrow address using npubs and specify amounts."
+                                    {tr(LANGUAGE(), "home-step-create-text")}
                                 }
                             }
                             Link {
                                 to: Route::Sign {},
                                 class: "block bg-white shadow-sm rounded-lg p-6 hover:shadow-md transition-shadow",
                                 h3 { class: "text-lg font-semibold text-gray-900 mb-2",
-                                    "2. Sign Transaction"
+                                    {tr(LANGUAGE(), "home-step-sign")}
                                 }
                                 p { class: "text-gray-600",
-                                    "Sign the transaction using your nsec key."
+                                    {tr(LANGUAGE(), "home-step-sign-text")}
                                 }
                             }
                             Link {
                                 to: Route::Combine {},
                                 class: "block bg-white shadow-sm rounded-lg p-6 hover:shadow-md transition-shadow",
                                 h3 { class: "text-lg font-semibold text-gray-900 mb-2",
-                                    "3. Combine Signatures"
+                                    {tr(LANGUAGE(), "home-step-combine")}
                                 }
                                 p { class: "text-gray-600",
-                                    "Combine the signatures into a signed transaction."
+                                    {tr(LANGUAGE(), "home-step-combine-text")}
                                 }
                             }
                             Link {
                                 to: Route::Broadcast {},
                                 class: "block bg-white shadow-sm rounded-lg p-6 hover:shadow-md transition-shadow",
                                 h3 { class: "text-lg font-semibold text-gray-900 mb-2",
-                                    "4. Broadcast"
+                                    {tr(LANGUAGE(), "home-step-broadcast")}
                                 }
                                 p { class: "text-gray-600",
-                                    "Broadcast the signed transaction to the Bitcoin network."
+                                    {tr(LANGUAGE(), "home-step-broadcast-text")}
                                 }
                             }
                             Link {
                                 to: Route::Spend {},
                                 class: "block bg-white shadow-sm rounded-lg p-6 hover:shadow-md transition-shadow",
                                 h3 { class: "text-lg font-semibold text-gray-900 mb-2",
-                                    "5. Spend"
+                                    {tr(LANGUAGE(), "home-step-spend")}
                                 }
                                 p { class: "text-gray-600",
-                                    "Spend from the resolution address derived from your npub using your nsec."
+                                    {tr(LANGUAGE(), "home-step-spend-text")}
                                 }
                             }
                         }
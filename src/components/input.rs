@@ -1,31 +1,67 @@
 //! Input Validation Components.
 
-use bitcoin::{Address, Amount, FeeRate, Transaction, Txid, consensus};
+use bitcoin::{Address, Amount, Transaction, Txid, consensus};
 use dioxus::prelude::*;
 
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::trace;
+#[cfg(target_arch = "wasm32")]
+use nostr::Keys;
+use nostr::nips::nip19::ToBech32;
 use secp256k1::schnorr;
 
+#[cfg(target_arch = "wasm32")]
+use crate::relays::{RelayPool, WebSocketTransport};
+#[cfg(feature = "serde-types")]
 use crate::{
-    ESPLORA_ENDPOINT, NETWORK,
+    ACCOUNTS,
+    accounts::{Account, Accounts},
+    recovery::RecoveryPhrase,
+};
+use crate::{
+    ESPLORA_ENDPOINT, KEYSTORE, LANGUAGE, NETWORK, PROXIES, RELAYS, SETTINGS,
+    address_book::AddressBook,
+    contacts::ProfileCache,
+    error::Error,
     esplora::FeeEstimate,
+    i18n::{Language, tr, tr_args},
+    keys::Npub,
+    network::Chain,
+    notifications::{NotificationKind, NotificationPreferences},
+    proxy::{ProxySettings, TOR_PROXY},
+    recovery::nsec_from_mnemonic,
+    relays::{Relay, RelayStatus, parse_relays},
+    settings::{DisplayUnit, Theme},
+    storage::LocalStorage,
+    templates::{EscrowTemplate, TemplatePolicy},
     util::{npub_to_address, parse_network, parse_npub, parse_nsec},
 };
 
+#[cfg(feature = "serde-types")]
+use super::PrimaryButton;
+use super::{IdentityBadge, SecondaryButton};
+
+/// The message shown under a key input, if `input` is not empty and failed to parse.
+fn key_error<T>(result: &Result<T, Error>, input: &str) -> Option<String> {
+    match result {
+        Err(e) if !input.trim().is_empty() => Some(e.user_message()),
+        _ => None,
+    }
+}
+
 /// Nostr `npub` input validation component.
 #[component]
 pub(crate) fn NpubInput(mut update_var: Signal<String>, label: String, id: String) -> Element {
-    let mut has_error = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
     let mut validate_npub = move |input: &str| {
         let result = parse_npub(input);
-        *has_error.write() = result.is_err() && !input.is_empty();
+        error.set(key_error(&result, input));
         if result.is_ok() || input.is_empty() {
             update_var.set(input.to_string());
         }
     };
 
-    let input_class = if *has_error.read() {
+    let input_class = if error.read().is_some() {
         "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
     } else {
         "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border"
@@ -44,6 +80,7 @@ pub(crate) fn NpubInput(mut update_var: Signal<String>, label: String, id: Strin
                     id: id.as_str(),
                     class: input_class,
                     placeholder: "npub...",
+                    value: "{update_var}",
                     oninput: move |event| {
                         #[cfg(debug_assertions)]
                         trace!(% id, % update_var, event_value =% event.value(), "Set npub");
@@ -51,11 +88,10 @@ pub(crate) fn NpubInput(mut update_var: Signal<String>, label: String, id: Strin
                     },
                 }
             }
-            if *has_error.read() {
-                p { class: "mt-2 text-xs text-red-600",
-                    "Invalid npub format. Please enter a valid Nostr public key."
-                }
+            if let Some(error) = error.read().as_ref() {
+                p { class: "mt-2 text-xs text-red-600", "{error}" }
             }
+            IdentityBadge { npub: update_var }
         }
     }
 }
@@ -69,27 +105,26 @@ pub(crate) fn NpubInputDerivedAddress(
     id: String,
     col_span: u8,
 ) -> Element {
-    let mut has_error = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
 
     let mut validate_and_derive = move |input: &str| {
         let parsed_npub = parse_npub(input);
-        has_error.set(parsed_npub.is_err() && !input.is_empty());
+        error.set(key_error(&parsed_npub, input));
 
         update_var.set(input.to_string());
 
-        if let Ok(parsed_npub) = parsed_npub {
-            if let Ok(parsed_network) = parse_network(&NETWORK.read()) {
-                if let Ok(address) = npub_to_address(&parsed_npub, parsed_network) {
-                    let derived_address_str = address.to_string();
-                    #[cfg(debug_assertions)]
-                    trace!(
-                        % derived_address_str, % update_address, event_value =% input,
-                        "Set derived address"
-                    );
-                    update_address.set(derived_address_str);
-                    return;
-                }
-            }
+        if let Ok(parsed_npub) = parsed_npub
+            && let Ok(parsed_network) = parse_network(&NETWORK.read())
+            && let Ok(address) = npub_to_address(&parsed_npub, parsed_network)
+        {
+            let derived_address_str = address.to_string();
+            #[cfg(debug_assertions)]
+            trace!(
+                % derived_address_str, % update_address, event_value =% input,
+                "Set derived address"
+            );
+            update_address.set(derived_address_str);
+            return;
         }
 
         // Clear the address if validation fails
@@ -98,7 +133,7 @@ pub(crate) fn NpubInputDerivedAddress(
         }
     };
 
-    let input_class = if *has_error.read() {
+    let input_class = if error.read().is_some() {
         "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
     } else {
         "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border"
@@ -118,6 +153,7 @@ pub(crate) fn NpubInputDerivedAddress(
                     id: id.as_str(),
                     class: input_class,
                     placeholder: "npub...",
+                    value: "{update_var}",
                     oninput: move |event| {
                         #[cfg(debug_assertions)]
                         trace!(% id, % update_var, event_value =% event.value(), "Set npub");
@@ -125,9 +161,100 @@ pub(crate) fn NpubInputDerivedAddress(
                     },
                 }
             }
-            if *has_error.read() {
-                p { class: "mt-2 text-xs text-red-600",
-                    "Invalid npub format. Please enter a valid Nostr public key."
+            if let Some(error) = error.read().as_ref() {
+                p { class: "mt-2 text-xs text-red-600", "{error}" }
+            }
+            IdentityBadge { npub: update_var }
+        }
+    }
+}
+
+/// Address book contact picker that fills an `npub` and, if given, its derived address.
+#[component]
+pub(crate) fn ContactSelect(
+    mut update_var: Signal<String>,
+    update_address: Option<Signal<String>>,
+    address_book: Signal<AddressBook>,
+    label: String,
+    id: String,
+    col_span: u8,
+) -> Element {
+    let mut trust_notes = use_signal(String::new);
+    let mut other_payout_address = use_signal(|| Option::<Address>::None);
+    let mut picture = use_signal(|| Option::<String>::None);
+    let profiles = use_signal(|| ProfileCache::load(&LocalStorage).unwrap_or_default());
+
+    let mut select_contact = move |input: &str| {
+        let Ok(npub) = parse_npub(input) else {
+            return;
+        };
+        let Some(contact) = address_book.read().get(&npub).cloned() else {
+            return;
+        };
+        update_var.set(input.to_string());
+        let network = parse_network(&NETWORK.read()).ok();
+        if let (Some(mut update_address), Some(network)) = (update_address, network)
+            && let Ok(address) = npub_to_address(&npub, network)
+        {
+            update_address.set(address.to_string());
+        }
+        other_payout_address.set(
+            network
+                .filter(|network| !contact.is_npub_address(*network))
+                .and_then(|network| contact.payout_address(network).ok()),
+        );
+        trust_notes.set(contact.trust_notes);
+        picture.set(
+            profiles
+                .read()
+                .get(&npub)
+                .and_then(|profile| profile.picture.clone()),
+        );
+    };
+
+    let select_class = "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border py-2 px-3";
+
+    rsx! {
+        div { class: format!("sm:col-span-{col_span}").as_str(),
+            label {
+                r#for: id.as_str(),
+                class: "block text-sm font-medium text-gray-700",
+                {label}
+            }
+            div { class: "mt-1",
+                select {
+                    id: id.as_str(),
+                    class: select_class,
+                    onchange: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(% id, event_value =% event.value(), "Select contact");
+                        select_contact(&event.value());
+                    },
+                    option { value: "", {tr(LANGUAGE(), "input-pick-contact")} }
+                    for contact in address_book.read().contacts() {
+                        option {
+                            value: contact.npub.to_bech32().unwrap_or_default(),
+                            selected: contact.npub.to_bech32().is_ok_and(|npub| npub == *update_var.read()),
+                            {contact.label.clone()}
+                        }
+                    }
+                }
+            }
+            div { class: "mt-2 flex items-center space-x-2",
+                if let Some(picture) = picture.read().as_ref() {
+                    img {
+                        class: "h-6 w-6 rounded-full",
+                        src: picture.as_str(),
+                        alt: "",
+                    }
+                }
+                if !trust_notes.read().is_empty() {
+                    p { class: "text-xs text-gray-500", {trust_notes.read().clone()} }
+                }
+            }
+            if let Some(address) = other_payout_address.read().as_ref() {
+                p { class: "mt-1 text-xs text-yellow-700",
+                    {tr_args(LANGUAGE(), "input-contact-payout-address", &[("address", address)])}
                 }
             }
         }
@@ -181,6 +308,7 @@ pub(crate) fn BitcoinInput(mut update_var: Signal<String>, label: String, id: St
                     id: id.as_str(),
                     class: input_class,
                     placeholder: "0.00000000",
+                    value: "{update_var}",
                     oninput: move |event| {
                         #[cfg(debug_assertions)]
                         trace!(% update_var, event_value =% event.value(), "Set Bitcoin amount");
@@ -190,7 +318,7 @@ pub(crate) fn BitcoinInput(mut update_var: Signal<String>, label: String, id: St
             }
             if *has_error.read() {
                 p { class: "mt-2 text-xs text-red-600",
-                    "Amount must be between 0.00000001 and 100 BTC."
+                    {tr(LANGUAGE(), "input-amount-range")}
                 }
             }
         }
@@ -218,7 +346,7 @@ pub(crate) fn FeeRateSelector(
 
         match input.parse::<u64>() {
             Ok(rate) => {
-                let is_valid = rate > 0 && FeeRate::from_sat_per_vb(rate).is_some();
+                let is_valid = SETTINGS().fee_rates.check_sat_per_vb(rate).is_ok();
                 *has_error.write() = !is_valid;
                 update_var.set(input.to_string());
             }
@@ -228,35 +356,40 @@ pub(crate) fn FeeRateSelector(
         }
     };
 
-    let mut selected_target = use_signal(|| "3".to_string()); // Default to 3-block confirmation
+    // Default to the confirmation target of the settings.
+    let mut selected_target =
+        use_signal(|| SETTINGS.peek().fee_rates.confirmation_target.to_string());
     // Simple confirmation options - show just the blocks
-    let confirmation_options = vec![
-        ("1", "1 block"),
-        ("3", "3 blocks"),
-        ("6", "6 blocks"),
-        ("9", "9 blocks"),
-        ("12", "12 blocks"),
-        ("15", "15 blocks"),
-        ("24", "24 blocks"),
-        ("144", "144 blocks"),
-    ];
+    let confirmation_options = [1, 3, 6, 9, 12, 15, 24, 144].map(|blocks| {
+        let key = if blocks == 1 {
+            "input-block"
+        } else {
+            "input-blocks"
+        };
+        (
+            blocks.to_string(),
+            tr_args(LANGUAGE(), key, &[("count", &blocks)]),
+        )
+    });
 
     // Update fee rate when selected target changes or when fee estimates are updated
     use_effect(move || {
         to_owned![update_var, fee_estimates, selected_target];
 
-        if let Some(estimates) = fee_estimates.read().as_ref() {
-            if let Some(fee) = estimates.get(&selected_target.read().parse::<u16>().unwrap_or(3)) {
-                let rounded_fee = fee.ceil() as u64;
-                update_var.set(rounded_fee.to_string());
-
-                #[cfg(debug_assertions)]
-                trace!(
-                    "Updated fee rate to {} for target {} blocks",
-                    rounded_fee,
-                    selected_target.read()
-                );
-            }
+        if let Some(estimates) = fee_estimates.read().as_ref()
+            && let Some(fee) = estimates.get(&selected_target.read().parse::<u16>().unwrap_or(3))
+        {
+            let limits = SETTINGS.peek().fee_rates;
+            let rounded_fee =
+                (fee.ceil() as u64).clamp(limits.min_sat_per_vb, limits.max_sat_per_vb);
+            update_var.set(rounded_fee.to_string());
+
+            #[cfg(debug_assertions)]
+            trace!(
+                "Updated fee rate to {} for target {} blocks",
+                rounded_fee,
+                selected_target.read()
+            );
         }
     });
 
@@ -270,7 +403,8 @@ pub(crate) fn FeeRateSelector(
                     span { class: "block text-sm font-medium text-gray-700", {label_input} }
                     input {
                         r#type: "number",
-                        min: "1",
+                        min: "{SETTINGS().fee_rates.min_sat_per_vb}",
+                        max: "{SETTINGS().fee_rates.max_sat_per_vb}",
                         step: "1",
                         name: id.as_str(),
                         id: id.as_str(),
@@ -306,7 +440,18 @@ pub(crate) fn FeeRateSelector(
                 }
             }
             if *has_error.read() {
-                p { class: "mt-2 text-xs text-red-600", "Fee rate must be a positive integer." }
+                p { class: "mt-2 text-xs text-red-600",
+                    {
+                        tr_args(
+                            LANGUAGE(),
+                            "input-fee-rate-range",
+                            &[
+                                ("min", &SETTINGS().fee_rates.min_sat_per_vb),
+                                ("max", &SETTINGS().fee_rates.max_sat_per_vb),
+                            ],
+                        )
+                    }
+                }
             }
         }
     }
@@ -317,14 +462,11 @@ pub(crate) fn FeeRateSelector(
 pub(crate) fn NetworkInput(label: String, id: String) -> Element {
     // Function to get the default endpoint for a network
     let get_default_endpoint = |network: &str| -> String {
-        match network {
-            "Mainnet" => "https://mempool.space/api",
-            "Testnet" => "https://mempool.space/testnet4/api",
-            "Signet" => "https://mempool.space/signet/api",
-            "Regtest" => "http://127.0.0.1:3002/api",
-            _ => "https://mempool.space/api",
-        }
-        .to_string()
+        network
+            .parse::<Chain>()
+            .unwrap_or_default()
+            .esplora_endpoint()
+            .to_string()
     };
 
     let update_esplora_endpoint = move |network: &str| {
@@ -356,15 +498,225 @@ pub(crate) fn NetworkInput(label: String, id: String) -> Element {
                         update_esplora_endpoint(&network_value);
                     },
                     value: NETWORK.read().clone(),
-                    option { value: "Mainnet", "Mainnet" }
-                    option { value: "Testnet", "Testnet" }
-                    option { value: "Signet", "Signet" }
-                    option { value: "Regtest", "Regtest" }
+                    for chain in Chain::ALL {
+                        option { value: chain.name(), "{chain}" }
+                    }
                 }
             }
             // Using two separate paragraphs
-            p { class: "mt-2 text-xs text-gray-500", "Default Esplora endpoint: {default_endpoint}" }
-            p { class: "text-xs text-gray-500", "Current Esplora endpoint: {ESPLORA_ENDPOINT.read()}" }
+            p { class: "mt-2 text-xs text-gray-500", {tr_args(LANGUAGE(), "input-esplora-default-endpoint", &[("endpoint", &default_endpoint)])} }
+            p { class: "text-xs text-gray-500", {tr_args(LANGUAGE(), "input-esplora-current-endpoint", &[("endpoint", &*ESPLORA_ENDPOINT.read())])} }
+        }
+    }
+}
+
+/// UI language select component.
+#[component]
+pub(crate) fn LanguageInput(label: String, id: String) -> Element {
+    rsx! {
+        div { class: "sm:col-span-3",
+            label {
+                r#for: id.as_str(),
+                class: "block text-sm font-medium text-gray-700",
+                {label}
+            }
+            div { class: "mt-1",
+                select {
+                    id: id.as_str(),
+                    name: "language",
+                    class: "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border",
+                    oninput: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(event_value =% event.value(), "Set language");
+                        if let Ok(language) = event.value().parse::<Language>() {
+                            *LANGUAGE.write() = language;
+                        }
+                    },
+                    value: LANGUAGE().code(),
+                    for language in Language::ALL {
+                        option { value: language.code(), "{language}" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Theme selection component.
+#[component]
+pub(crate) fn ThemeInput(label: String, id: String) -> Element {
+    rsx! {
+        div { class: "sm:col-span-3",
+            label {
+                r#for: id.as_str(),
+                class: "block text-sm font-medium text-gray-700",
+                {label}
+            }
+            div { class: "mt-1",
+                select {
+                    id: id.as_str(),
+                    name: "theme",
+                    class: "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border",
+                    oninput: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(event_value =% event.value(), "Set theme");
+                        if let Ok(theme) = event.value().parse::<Theme>() {
+                            SETTINGS.write().theme = theme;
+                        }
+                    },
+                    value: SETTINGS().theme.name(),
+                    for theme in Theme::ALL {
+                        option { value: theme.name(), "{theme}" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Display unit selection component.
+#[component]
+pub(crate) fn DisplayUnitInput(label: String, id: String) -> Element {
+    rsx! {
+        div { class: "sm:col-span-3",
+            label {
+                r#for: id.as_str(),
+                class: "block text-sm font-medium text-gray-700",
+                {label}
+            }
+            div { class: "mt-1",
+                select {
+                    id: id.as_str(),
+                    name: "display-unit",
+                    class: "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border",
+                    oninput: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(event_value =% event.value(), "Set display unit");
+                        if let Ok(unit) = event.value().parse::<DisplayUnit>() {
+                            SETTINGS.write().display_unit = unit;
+                        }
+                    },
+                    value: SETTINGS().display_unit.name(),
+                    for unit in DisplayUnit::ALL {
+                        option { value: unit.name(), "{unit}" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fee rate limits and confirmation target component.
+///
+/// Invalid limits are kept so the user can finish typing, and rejected when the settings
+/// are saved.
+#[component]
+pub(crate) fn FeeRateLimitsInput() -> Element {
+    let input_class = "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border";
+    let fields: [(&str, String, u64); 3] = [
+        (
+            "min-fee-rate",
+            tr(LANGUAGE(), "settings-min-fee-rate"),
+            SETTINGS().fee_rates.min_sat_per_vb,
+        ),
+        (
+            "max-fee-rate",
+            tr(LANGUAGE(), "settings-max-fee-rate"),
+            SETTINGS().fee_rates.max_sat_per_vb,
+        ),
+        (
+            "confirmation-target",
+            tr(LANGUAGE(), "settings-confirmation-target"),
+            u64::from(SETTINGS().fee_rates.confirmation_target),
+        ),
+    ];
+    rsx! {
+        for (id, label, value) in fields {
+            div { class: "sm:col-span-2",
+                label {
+                    r#for: id,
+                    class: "block text-sm font-medium text-gray-700",
+                    {label}
+                }
+                div { class: "mt-1",
+                    input {
+                        r#type: "number",
+                        min: "0",
+                        step: "1",
+                        id,
+                        class: input_class,
+                        value: "{value}",
+                        oninput: move |event| {
+                            #[cfg(debug_assertions)]
+                            trace!(% id, event_value =% event.value(), "Set fee rate limit");
+                            let Ok(value) = event.value().parse::<u64>() else {
+                                return;
+                            };
+                            let mut settings = SETTINGS.write();
+                            match id {
+                                "min-fee-rate" => settings.fee_rates.min_sat_per_vb = value,
+                                "max-fee-rate" => settings.fee_rates.max_sat_per_vb = value,
+                                _ => {
+                                    settings.fee_rates.confirmation_target = u16::try_from(value)
+                                        .unwrap_or(u16::MAX);
+                                }
+                            }
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Notification preferences component, a checkbox per [`NotificationKind`].
+#[component]
+pub(crate) fn NotificationsInput(preferences: Signal<NotificationPreferences>) -> Element {
+    let kinds = [
+        (
+            NotificationKind::CounterpartySigned,
+            "notify-counterparty-signed",
+            tr(LANGUAGE(), "settings-notify-counterparty-signed"),
+        ),
+        (
+            NotificationKind::FundingConfirmed,
+            "notify-funding-confirmed",
+            tr(LANGUAGE(), "settings-notify-funding-confirmed"),
+        ),
+        (
+            NotificationKind::TimelockExpiring,
+            "notify-timelock-expiring",
+            tr(LANGUAGE(), "settings-notify-timelock-expiring"),
+        ),
+    ];
+    rsx! {
+        for (kind, id, label) in kinds {
+            div { class: "sm:col-span-6 flex items-center",
+                input {
+                    r#type: "checkbox",
+                    id,
+                    class: "h-4 w-4 text-indigo-600 border-gray-300 rounded",
+                    checked: preferences.read().is_enabled(kind),
+                    onchange: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(% id, event_value =% event.value(), "Set notification preference");
+                        let enabled = event.checked();
+                        let mut preferences = preferences.write();
+                        match kind {
+                            NotificationKind::CounterpartySigned => {
+                                preferences.counterparty_signed = enabled;
+                            }
+                            NotificationKind::FundingConfirmed => preferences.funding_confirmed = enabled,
+                            NotificationKind::TimelockExpiring => preferences.timelock_expiring = enabled,
+                        }
+                    },
+                }
+                label {
+                    r#for: id,
+                    class: "ml-2 block text-sm text-gray-700",
+                    {label}
+                }
+            }
         }
     }
 }
@@ -398,7 +750,7 @@ pub(crate) fn EsploraInput() -> Element {
             label {
                 r#for: "esplora-url",
                 class: "block text-sm font-medium text-gray-700",
-                "Esplora API Backend URL"
+                {tr(LANGUAGE(), "input-esplora")}
             }
             div { class: "mt-1",
                 input {
@@ -417,75 +769,380 @@ pub(crate) fn EsploraInput() -> Element {
             }
             if *has_error.read() {
                 p { class: "mt-2 text-xs text-red-600",
-                    "Invalid URL format. URL should start with http:// or https://"
+                    {tr(LANGUAGE(), "input-esplora-invalid")}
                 }
             } else {
                 p { class: "mt-2 text-xs text-gray-500",
-                    "Default for mainnet: https://mempool.space/api"
+                    {tr(LANGUAGE(), "input-esplora-default")}
                 }
             }
         }
     }
 }
 
-/// Timelock input validation component.
-#[component]
-pub(crate) fn TimelockInput(
-    mut update_day_var: Signal<String>,
-    mut update_hour_var: Signal<String>,
-) -> Element {
-    let mut days_has_error = use_signal(|| false);
-    let mut hours_has_error = use_signal(|| false);
+/// Pings the configured relays, returning their health.
+#[cfg(target_arch = "wasm32")]
+async fn check_relays() -> Result<Vec<Relay>, Error> {
+    let mut pool = relay_pool()?;
+    pool.check_health().await;
+    Ok(pool.relays().to_vec())
+}
 
-    let mut validate_days = move |input: &str| {
-        if input.is_empty() {
-            *days_has_error.write() = false;
-            update_day_var.set(input.to_string());
-            return;
-        }
+/// Pings the configured relays, returning their health.
+#[cfg(not(target_arch = "wasm32"))]
+async fn check_relays() -> Result<Vec<Relay>, Error> {
+    Err(Error::Relay(
+        "Nostr relays are only reachable from the browser".to_string(),
+    ))
+}
 
-        match input.parse::<u32>() {
-            Ok(days) => {
-                // A very large value (e.g., over 1,000 days) might be a mistake
-                let is_valid = days <= 1_000;
-                *days_has_error.write() = !is_valid;
-                update_day_var.set(input.to_string());
-            }
-            Err(_) => {
-                *days_has_error.write() = true;
-            }
-        }
+/// How the health of `relay` is shown.
+fn relay_health(relay: &Relay) -> String {
+    match (relay.status, relay.latency) {
+        (RelayStatus::Online, Some(latency)) => tr_args(
+            LANGUAGE(),
+            "input-relays-online",
+            &[("latency", &latency.as_millis())],
+        ),
+        (RelayStatus::Offline, _) => tr(LANGUAGE(), "input-relays-offline"),
+        _ => tr(LANGUAGE(), "input-relays-unknown"),
+    }
+}
+
+/// Nostr relays input validation component.
+#[component]
+pub(crate) fn RelaysInput() -> Element {
+    let mut has_error = use_signal(|| false);
+    let mut health = use_signal(Vec::<Relay>::new);
+    let mut health_error = use_signal(|| None::<String>);
+
+    let mut validate_relays = move |input: &str| {
+        // Relay URLs validation
+        *has_error.write() = parse_relays(input).is_err();
+        *RELAYS.write() = input.to_string();
     };
 
-    let mut validate_hours = move |input: &str| {
-        if input.is_empty() {
-            *hours_has_error.write() = false;
-            update_hour_var.set(input.to_string());
-            return;
-        }
+    let input_class = if *has_error.read() {
+        "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
+    } else {
+        "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border"
+    };
 
-        match input.parse::<u32>() {
-            Ok(hours) => {
-                // Hours should be 0-23
-                let is_valid = hours < 24;
-                *hours_has_error.write() = !is_valid;
-                update_hour_var.set(input.to_string());
+    rsx! {
+        div { class: "sm:col-span-6",
+            label {
+                r#for: "nostr-relays",
+                class: "block text-sm font-medium text-gray-700",
+                {tr(LANGUAGE(), "input-relays")}
             }
-            Err(_) => {
-                *hours_has_error.write() = true;
+            div { class: "mt-1",
+                textarea {
+                    id: "nostr-relays",
+                    name: "nostr-relays",
+                    rows: "3",
+                    class: input_class,
+                    placeholder: "wss://relay.damus.io",
+                    oninput: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(% RELAYS, event_value =% event.value(), "Set Nostr relays");
+                        validate_relays(&event.value());
+                    },
+                    value: RELAYS.read().clone(),
+                }
+            }
+            if *has_error.read() {
+                p { class: "mt-2 text-xs text-red-600",
+                    {tr(LANGUAGE(), "input-relays-invalid")}
+                }
+            } else {
+                p { class: "mt-2 text-xs text-gray-500",
+                    {tr(LANGUAGE(), "input-relays-help")}
+                }
+            }
+            div { class: "mt-2 flex justify-end",
+                SecondaryButton {
+                    text: tr(LANGUAGE(), "input-relays-check"),
+                    onclick: move |_| {
+                        spawn(async move {
+                            match check_relays().await {
+                                Ok(relays) => {
+                                    health.set(relays);
+                                    health_error.set(None);
+                                }
+                                Err(e) => health_error.set(Some(e.user_message())),
+                            }
+                        });
+                    },
+                }
+            }
+            if let Some(error) = health_error() {
+                p { class: "mt-2 text-xs text-red-600", "{error}" }
+            }
+            ul { class: "mt-2 divide-y divide-gray-200",
+                for relay in health() {
+                    li {
+                        key: "{relay.url}",
+                        class: "py-1 flex justify-between text-xs text-gray-700",
+                        span { "{relay.url}" }
+                        span { {relay_health(&relay)} }
+                    }
+                }
             }
         }
+    }
+}
+
+/// SOCKS5 proxy configuration input validation component.
+#[component]
+pub(crate) fn ProxyInput() -> Element {
+    let mut has_error = use_signal(|| false);
+
+    let mut validate_proxies = move |input: &str| {
+        *has_error.write() = ProxySettings::parse(input).is_err();
+        *PROXIES.write() = input.to_string();
     };
 
-    let days_input_class = if *days_has_error.read() {
+    let input_class = if *has_error.read() {
         "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
     } else {
         "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border"
     };
 
-    let hours_input_class = if *hours_has_error.read() {
-        "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
-    } else {
+    rsx! {
+        div { class: "sm:col-span-6",
+            label {
+                r#for: "socks5-proxies",
+                class: "block text-sm font-medium text-gray-700",
+                {tr(LANGUAGE(), "input-proxies")}
+            }
+            div { class: "mt-1",
+                textarea {
+                    id: "socks5-proxies",
+                    name: "socks5-proxies",
+                    rows: "2",
+                    class: input_class,
+                    placeholder: TOR_PROXY,
+                    oninput: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(% PROXIES, event_value =% event.value(), "Set SOCKS5 proxies");
+                        validate_proxies(&event.value());
+                    },
+                    value: PROXIES.read().clone(),
+                }
+            }
+            if *has_error.read() {
+                p { class: "mt-2 text-xs text-red-600",
+                    {tr(LANGUAGE(), "input-proxies-invalid")}
+                }
+            } else {
+                p { class: "mt-2 text-xs text-gray-500",
+                    {tr_args(LANGUAGE(), "input-proxies-help", &[("proxy", &TOR_PROXY)])}
+                }
+            }
+        }
+    }
+}
+
+/// Address book JSON import and export component.
+///
+/// Valid edits replace the address book and are saved right away.
+#[component]
+pub(crate) fn AddressBookInput(mut address_book: Signal<AddressBook>) -> Element {
+    let mut has_error = use_signal(|| false);
+    let mut json = use_signal(|| address_book.read().to_json().unwrap_or_default());
+
+    // Show changes made elsewhere, e.g. imported follows, without reformatting while typing.
+    use_effect(move || {
+        let current = address_book.read();
+        if AddressBook::from_json(&json.peek()).ok().as_ref() != Some(&*current) {
+            json.set(current.to_json().unwrap_or_default());
+        }
+    });
+
+    let mut import_address_book = move |input: &str| {
+        json.set(input.to_string());
+        match AddressBook::from_json(input) {
+            Ok(imported) => {
+                *has_error.write() = imported.save(&LocalStorage).is_err();
+                address_book.set(imported);
+            }
+            Err(_) => *has_error.write() = true,
+        }
+    };
+
+    let input_class = if *has_error.read() {
+        "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50 font-mono"
+    } else {
+        "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border font-mono"
+    };
+
+    rsx! {
+        div { class: "sm:col-span-6",
+            label {
+                r#for: "address-book",
+                class: "block text-sm font-medium text-gray-700",
+                {tr(LANGUAGE(), "input-address-book")}
+            }
+            div { class: "mt-1",
+                textarea {
+                    id: "address-book",
+                    name: "address-book",
+                    rows: "6",
+                    class: input_class,
+                    placeholder: "[]",
+                    oninput: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(event_value =% event.value(), "Set address book");
+                        import_address_book(&event.value());
+                    },
+                    value: "{json}",
+                }
+            }
+            if *has_error.read() {
+                p { class: "mt-2 text-xs text-red-600",
+                    {tr(LANGUAGE(), "input-address-book-invalid")}
+                }
+            } else {
+                p { class: "mt-2 text-xs text-gray-500",
+                    {tr(LANGUAGE(), "input-address-book-help")}
+                }
+            }
+        }
+    }
+}
+
+/// Escrow template import component.
+///
+/// Pasted templates are checked against the [`TemplatePolicy`] of the current network,
+/// allowing the arbitrators of the `address_book`, before `on_import` loads them.
+#[component]
+pub(crate) fn TemplateInput(
+    address_book: Signal<AddressBook>,
+    on_import: EventHandler<EscrowTemplate>,
+) -> Element {
+    let mut json = use_signal(String::new);
+    let mut status = use_signal(|| Option::<Result<String, String>>::None);
+
+    let mut import_template = move |input: &str| {
+        json.set(input.to_string());
+        if input.trim().is_empty() {
+            status.set(None);
+            return;
+        }
+        let chain = NETWORK.read().parse::<Chain>().unwrap_or_default();
+        let policy = TemplatePolicy::new(chain, &address_book.read(), SETTINGS().fee_rates);
+        match EscrowTemplate::import(input, &policy) {
+            Ok(template) => {
+                status.set(Some(Ok(template.name.clone())));
+                on_import.call(template);
+            }
+            Err(e) => status.set(Some(Err(e.user_message()))),
+        }
+    };
+
+    let input_class = if matches!(*status.read(), Some(Err(_))) {
+        "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50 font-mono"
+    } else {
+        "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border font-mono"
+    };
+
+    rsx! {
+        div { class: "sm:col-span-6",
+            label {
+                r#for: "escrow-template",
+                class: "block text-sm font-medium text-gray-700",
+                {tr(LANGUAGE(), "input-template")}
+            }
+            div { class: "mt-1",
+                textarea {
+                    id: "escrow-template",
+                    name: "escrow-template",
+                    rows: "4",
+                    class: input_class,
+                    placeholder: "{{\"version\": 1, \"name\": \"...\"}}",
+                    oninput: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(event_value =% event.value(), "Set escrow template");
+                        import_template(&event.value());
+                    },
+                    value: "{json}",
+                }
+            }
+            match &*status.read() {
+                Some(Ok(name)) => rsx! {
+                    p { class: "mt-2 text-xs text-green-600", {tr_args(LANGUAGE(), "input-template-loaded", &[("name", name)])} }
+                },
+                Some(Err(reason)) => rsx! {
+                    p { class: "mt-2 text-xs text-red-600", "{reason}" }
+                },
+                None => rsx! {
+                    p { class: "mt-2 text-xs text-gray-500",
+                        {tr(LANGUAGE(), "input-template-help")}
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Timelock input validation component.
+#[component]
+pub(crate) fn TimelockInput(
+    mut update_day_var: Signal<String>,
+    mut update_hour_var: Signal<String>,
+) -> Element {
+    let mut days_has_error = use_signal(|| false);
+    let mut hours_has_error = use_signal(|| false);
+
+    let mut validate_days = move |input: &str| {
+        if input.is_empty() {
+            *days_has_error.write() = false;
+            update_day_var.set(input.to_string());
+            return;
+        }
+
+        match input.parse::<u32>() {
+            Ok(days) => {
+                // A very large value (e.g., over 1,000 days) might be a mistake
+                let is_valid = days <= 1_000;
+                *days_has_error.write() = !is_valid;
+                update_day_var.set(input.to_string());
+            }
+            Err(_) => {
+                *days_has_error.write() = true;
+            }
+        }
+    };
+
+    let mut validate_hours = move |input: &str| {
+        if input.is_empty() {
+            *hours_has_error.write() = false;
+            update_hour_var.set(input.to_string());
+            return;
+        }
+
+        match input.parse::<u32>() {
+            Ok(hours) => {
+                // Hours should be 0-23
+                let is_valid = hours < 24;
+                *hours_has_error.write() = !is_valid;
+                update_hour_var.set(input.to_string());
+            }
+            Err(_) => {
+                *hours_has_error.write() = true;
+            }
+        }
+    };
+
+    let days_input_class = if *days_has_error.read() {
+        "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
+    } else {
+        "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border"
+    };
+
+    let hours_input_class = if *hours_has_error.read() {
+        "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
+    } else {
         "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border"
     };
 
@@ -496,7 +1153,7 @@ pub(crate) fn TimelockInput(
                     label {
                         r#for: "timelock-days",
                         class: "block text-sm font-medium text-gray-700",
-                        "Timelock (Days)"
+                        {tr(LANGUAGE(), "input-timelock-days")}
                     }
                     div { class: "mt-1",
                         input {
@@ -507,6 +1164,7 @@ pub(crate) fn TimelockInput(
                             id: "timelock-days",
                             class: days_input_class,
                             placeholder: "0",
+                            value: "{update_day_var}",
                             oninput: move |event| {
                                 #[cfg(debug_assertions)]
                                 trace!(% update_day_var, event_value =% event.value(), "Set timelock days");
@@ -515,14 +1173,14 @@ pub(crate) fn TimelockInput(
                         }
                     }
                     if *days_has_error.read() {
-                        p { class: "mt-2 text-xs text-red-600", "Days should be between 0 and 1,000." }
+                        p { class: "mt-2 text-xs text-red-600", {tr(LANGUAGE(), "input-timelock-days-range")} }
                     }
                 }
                 div {
                     label {
                         r#for: "timelock-hours",
                         class: "block text-sm font-medium text-gray-700",
-                        "Timelock (Hours)"
+                        {tr(LANGUAGE(), "input-timelock-hours")}
                     }
                     div { class: "mt-1",
                         input {
@@ -534,6 +1192,7 @@ pub(crate) fn TimelockInput(
                             id: "timelock-hours",
                             class: hours_input_class,
                             placeholder: "0",
+                            value: "{update_hour_var}",
                             oninput: move |event| {
                                 #[cfg(debug_assertions)]
                                 trace!(% update_hour_var, event_value =% event.value(), "Set timelock hours");
@@ -542,7 +1201,7 @@ pub(crate) fn TimelockInput(
                         }
                     }
                     if *hours_has_error.read() {
-                        p { class: "mt-2 text-xs text-red-600", "Hours should be between 0 and 23." }
+                        p { class: "mt-2 text-xs text-red-600", {tr(LANGUAGE(), "input-timelock-hours-range")} }
                     }
                 }
             }
@@ -569,7 +1228,7 @@ pub(crate) fn EscrowTypeInput(mut update_var: Signal<String>) -> Element {
             label {
                 r#for: "escrow-type",
                 class: "block text-sm font-medium text-gray-700",
-                "Escrow Type"
+                {tr(LANGUAGE(), "input-escrow-type")}
             }
             div { class: "mt-1",
                 select {
@@ -582,9 +1241,9 @@ pub(crate) fn EscrowTypeInput(mut update_var: Signal<String>) -> Element {
                         update_var.set(event.value());
                     },
                     value: current_value,
-                    option { value: "A", "A - Collaborative (2-of-2)" }
-                    option { value: "B", "B - Dispute: First Party + Arbitrator" }
-                    option { value: "C", "C - Dispute: Second Party + Arbitrator" }
+                    option { value: "A", {tr(LANGUAGE(), "input-escrow-type-a")} }
+                    option { value: "B", {tr(LANGUAGE(), "input-escrow-type-b")} }
+                    option { value: "C", {tr(LANGUAGE(), "input-escrow-type-c")} }
                 }
             }
         }
@@ -594,15 +1253,14 @@ pub(crate) fn EscrowTypeInput(mut update_var: Signal<String>) -> Element {
 /// Nostr `nsec` input validation component.
 #[component]
 pub(crate) fn NsecInput(mut update_var: Signal<String>) -> Element {
-    let mut has_error = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
 
     let mut validate_nsec = move |input: &str| {
-        let is_valid = input.is_empty() || parse_nsec(input).is_ok();
-        *has_error.write() = !is_valid && !input.is_empty();
+        error.set(key_error(&parse_nsec(input), input));
         update_var.set(input.to_string());
     };
 
-    let input_class = if *has_error.read() {
+    let input_class = if error.read().is_some() {
         "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
     } else {
         "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border"
@@ -613,7 +1271,7 @@ pub(crate) fn NsecInput(mut update_var: Signal<String>) -> Element {
             label {
                 r#for: "nsec",
                 class: "block text-sm font-medium text-gray-700",
-                "Your Nostr Secret Key (nsec)"
+                {tr(LANGUAGE(), "input-nsec")}
             }
             div { class: "mt-1",
                 input {
@@ -627,13 +1285,514 @@ pub(crate) fn NsecInput(mut update_var: Signal<String>) -> Element {
                     },
                 }
             }
-            if *has_error.read() {
+            if let Some(error) = error.read().as_ref() {
+                p { class: "mt-2 text-xs text-red-600", "{error}" }
+            } else {
                 p { class: "mt-2 text-xs text-red-600",
-                    "Invalid nsec format. Please enter a valid Nostr secret key."
+                    {tr(LANGUAGE(), "input-nsec-help")}
+                }
+            }
+        }
+    }
+}
+
+/// BIP-39 mnemonic input, setting `update_var` to the `nsec` derived with NIP-06.
+///
+/// For users managing their Nostr identity from a seed instead of an `nsec`.
+#[component]
+pub(crate) fn MnemonicInput(mut update_var: Signal<String>) -> Element {
+    let mut words = use_signal(String::new);
+    let mut passphrase = use_signal(String::new);
+    let mut account = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+
+    let mut derive_nsec = move || {
+        let result = match account.read().trim() {
+            "" => Ok(0),
+            account => account
+                .parse::<u32>()
+                .map_err(|_| Error::WrongInputs("Invalid account index".to_string())),
+        }
+        .and_then(|account| nsec_from_mnemonic(&words.read(), &passphrase.read(), account));
+        error.set(key_error(&result, &words.read()));
+        if let Ok(nsec) = result {
+            update_var.set(nsec.with_nostr_secret_key(|secret_key| secret_key.to_secret_hex()));
+        }
+    };
+
+    let input_class = if error.read().is_some() {
+        "shadow-sm focus:ring-red-500 focus:border-red-500 block w-full sm:text-sm border-red-300 rounded-md p-2 border bg-red-50"
+    } else {
+        "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border"
+    };
+
+    rsx! {
+        div { class: "sm:col-span-6",
+            label {
+                r#for: "mnemonic",
+                class: "block text-sm font-medium text-gray-700",
+                {tr(LANGUAGE(), "input-mnemonic")}
+            }
+            div { class: "mt-1 grid grid-cols-1 gap-y-2 gap-x-4 sm:grid-cols-6",
+                input {
+                    r#type: "password",
+                    name: "mnemonic",
+                    id: "mnemonic",
+                    class: "{input_class} sm:col-span-6",
+                    placeholder: tr(LANGUAGE(), "input-mnemonic-words"),
+                    oninput: move |event| {
+                        words.set(event.value());
+                        derive_nsec();
+                    },
+                }
+                input {
+                    r#type: "password",
+                    name: "mnemonic-passphrase",
+                    id: "mnemonic-passphrase",
+                    class: "{input_class} sm:col-span-4",
+                    placeholder: tr(LANGUAGE(), "input-passphrase"),
+                    oninput: move |event| {
+                        passphrase.set(event.value());
+                        derive_nsec();
+                    },
+                }
+                input {
+                    r#type: "number",
+                    min: "0",
+                    name: "mnemonic-account",
+                    id: "mnemonic-account",
+                    class: "{input_class} sm:col-span-2",
+                    placeholder: tr(LANGUAGE(), "input-account"),
+                    oninput: move |event| {
+                        account.set(event.value());
+                        derive_nsec();
+                    },
+                }
+            }
+            if let Some(error) = error.read().as_ref() {
+                p { class: "mt-2 text-xs text-red-600", "{error}" }
+            }
+        }
+    }
+}
+
+/// The handle of the account signing: the account of the typed `nsec`, unlocked into
+/// the [`KEYSTORE`], or else the unlocked `account` picked with [`AccountSelect`].
+pub(crate) fn signing_account(nsec: &str, account: &str) -> Result<Npub, Error> {
+    if !nsec.trim().is_empty() {
+        return Ok(KEYSTORE.write().unlock(parse_nsec(nsec)?));
+    }
+    if account.is_empty() {
+        return Err(Error::WrongInputs(
+            "Pick an unlocked account or enter a key".to_string(),
+        ));
+    }
+    account.parse()
+}
+
+/// The pool of the configured relays, authenticating as the first unlocked account
+/// of the [`KEYSTORE`] to the relays that require it.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn relay_pool() -> Result<RelayPool<WebSocketTransport>, Error> {
+    let keystore = KEYSTORE.read();
+    let transport = match keystore.unlocked().next() {
+        Some(npub) => WebSocketTransport::with_auth(
+            keystore
+                .nsec(npub)?
+                .with_nostr_secret_key(|nsec| Keys::new(nsec.clone())),
+        ),
+        None => WebSocketTransport::default(),
+    };
+    RelayPool::from_config(transport, &RELAYS.read())
+}
+
+/// How the account `npub` is shown: its label if registered, its `npub` otherwise.
+#[cfg(feature = "serde-types")]
+fn account_name(npub: &Npub) -> String {
+    match ACCOUNTS.read().get(npub) {
+        Some(account) => account.label.clone(),
+        None => npub.to_string(),
+    }
+}
+
+/// How the account `npub` is shown: its `npub`, accounts are only registered with serde.
+#[cfg(not(feature = "serde-types"))]
+fn account_name(npub: &Npub) -> String {
+    npub.to_string()
+}
+
+/// Picker of the accounts unlocked in the [`KEYSTORE`], setting `update_var` to the `npub`
+/// of the account signing, see [`signing_account`].
+#[component]
+pub(crate) fn AccountSelect(mut update_var: Signal<String>) -> Element {
+    let unlocked = KEYSTORE.read().unlocked().copied().collect::<Vec<_>>();
+    let select_class = "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border py-2 px-3";
+
+    rsx! {
+        div { class: "sm:col-span-3",
+            label {
+                r#for: "account",
+                class: "block text-sm font-medium text-gray-700",
+                {tr(LANGUAGE(), "input-account-signing")}
+            }
+            div { class: "mt-1",
+                select {
+                    id: "account",
+                    class: select_class,
+                    onchange: move |event| {
+                        #[cfg(debug_assertions)]
+                        trace!(event_value =% event.value(), "Select account");
+                        update_var.set(event.value());
+                    },
+                    option { value: "", {tr(LANGUAGE(), "input-pick-account")} }
+                    for npub in unlocked {
+                        option {
+                            value: npub.to_string(),
+                            selected: npub.to_string() == *update_var.read(),
+                            {account_name(&npub)}
+                        }
+                    }
+                }
+            }
+            p { class: "mt-2 text-xs text-gray-500",
+                {tr(LANGUAGE(), "input-account-signing-help")}
+            }
+        }
+    }
+}
+
+/// Applies `update` to the saved [`Accounts`], then saves and shows them.
+///
+/// They are loaded again first, to keep the sessions the escrow watcher assigned meanwhile.
+#[cfg(feature = "serde-types")]
+fn update_accounts(update: impl FnOnce(&mut Accounts) -> Result<(), Error>) -> Result<(), Error> {
+    let mut accounts = Accounts::load(&LocalStorage)?;
+    update(&mut accounts)?;
+    accounts.save(&LocalStorage)?;
+    *ACCOUNTS.write() = accounts;
+    Ok(())
+}
+
+/// Registered accounts component, to add, generate, back up, lock and remove the identities
+/// signing escrows.
+///
+/// Only the labels, `npub`s and sessions of the [`ACCOUNTS`] are saved: secrets stay in the
+/// [`KEYSTORE`] until the account is locked or the app closed.
+#[cfg(feature = "serde-types")]
+#[component]
+pub(crate) fn AccountsInput() -> Element {
+    let mut label = use_signal(String::new);
+    let nsec = use_signal(String::new);
+    let mut typed_phrase = use_signal(String::new);
+    let mut generated_phrase = use_signal(String::new);
+    let mut status = use_signal(String::new);
+
+    let mut show = move |result: Result<(), Error>, done: &str| {
+        status.set(match result {
+            Ok(()) => tr(LANGUAGE(), done),
+            Err(e) => e.user_message(),
+        });
+    };
+
+    let input_class = "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border";
+
+    rsx! {
+        div { class: "sm:col-span-6",
+            h3 { class: "text-lg leading-6 font-medium text-gray-900",
+                {tr(LANGUAGE(), "input-accounts")}
+            }
+            p { class: "mt-1 text-sm text-gray-500", {tr(LANGUAGE(), "input-accounts-help")} }
+            ul { class: "mt-2 divide-y divide-gray-200",
+                for account in ACCOUNTS.read().accounts().to_vec() {
+                    AccountRow {
+                        key: "{account.npub}",
+                        account: account.clone(),
+                        typed_phrase,
+                        status,
+                    }
+                }
+            }
+        }
+
+        if ACCOUNTS.read().backup_reminders().next().is_some() {
+            div { class: "sm:col-span-6",
+                label {
+                    r#for: "backup-phrase",
+                    class: "block text-sm font-medium text-gray-700",
+                    {tr(LANGUAGE(), "input-account-backup-phrase")}
+                }
+                div { class: "mt-1",
+                    input {
+                        r#type: "password",
+                        name: "backup-phrase",
+                        id: "backup-phrase",
+                        class: input_class,
+                        placeholder: tr(LANGUAGE(), "input-mnemonic-words"),
+                        value: "{typed_phrase}",
+                        oninput: move |event| typed_phrase.set(event.value()),
+                    }
+                }
+            }
+        }
+
+        div { class: "sm:col-span-3",
+            label {
+                r#for: "account-label",
+                class: "block text-sm font-medium text-gray-700",
+                {tr(LANGUAGE(), "input-account-label")}
+            }
+            div { class: "mt-1",
+                input {
+                    r#type: "text",
+                    name: "account-label",
+                    id: "account-label",
+                    class: input_class,
+                    value: "{label}",
+                    oninput: move |event| label.set(event.value()),
+                }
+            }
+        }
+
+        NsecInput { update_var: nsec }
+
+        MnemonicInput { update_var: nsec }
+
+        div { class: "sm:col-span-6 flex justify-end",
+            SecondaryButton {
+                onclick: move |_| {
+                    // 12 words, as most wallets show.
+                    let generated = KEYSTORE.write().generate(12, "");
+                    let result = generated
+                        .and_then(|(npub, phrase)| {
+                            let registered = update_accounts(|accounts| {
+                                accounts.register_generated(npub, &label.read())
+                            });
+                            match registered {
+                                Ok(()) => generated_phrase.set(phrase.words().collect::<Vec<_>>().join(" ")),
+                                Err(_) => {
+                                    KEYSTORE.write().lock(&npub);
+                                }
+                            }
+                            registered
+                        });
+                    show(result, "input-account-generated");
+                },
+                text: tr(LANGUAGE(), "input-account-generate"),
+            }
+            PrimaryButton {
+                onclick: move |_| {
+                    let result = parse_nsec(&nsec.read())
+                        .and_then(|secret| {
+                            let npub = Npub::from(secret.public_key());
+                            // Registered accounts are only unlocked, unless renamed.
+                            if ACCOUNTS.read().get(&npub).is_none() || !label.read().trim().is_empty() {
+                                update_accounts(|accounts| accounts.register(npub, &label.read()))?;
+                            }
+                            KEYSTORE.write().unlock(secret);
+                            Ok(())
+                        });
+                    show(result, "input-account-added");
+                },
+                text: tr(LANGUAGE(), "input-account-add"),
+            }
+        }
+
+        if !generated_phrase.read().is_empty() {
+            div { class: "sm:col-span-6 rounded-md bg-yellow-50 p-4",
+                p { class: "text-sm font-medium text-yellow-800",
+                    {tr(LANGUAGE(), "input-account-write-down")}
+                }
+                p { class: "mt-2 font-mono text-sm text-yellow-900",
+                    {generated_phrase.read().clone()}
+                }
+            }
+        }
+
+        if !status.read().is_empty() {
+            p { class: "sm:col-span-6 text-sm text-gray-500", {status.read().clone()} }
+        }
+    }
+}
+
+/// Registered accounts component, empty: accounts are only saved with serde.
+#[cfg(not(feature = "serde-types"))]
+#[component]
+pub(crate) fn AccountsInput() -> Element {
+    rsx! {}
+}
+
+/// Session store component, to protect the saved sessions with a passphrase,
+/// unlock and lock them, and change the passphrase.
+///
+/// The first unlock sets the passphrase and encrypts the sessions saved so far.
+#[cfg(feature = "serde-types")]
+#[component]
+pub(crate) fn SessionStoreInput() -> Element {
+    let mut passphrase = use_signal(String::new);
+    let mut new_passphrase = use_signal(String::new);
+    let mut status = use_signal(String::new);
+    let unlocked = KEYSTORE.read().sessions_unlocked();
+
+    let mut show = move |result: Result<(), Error>, done: &str| {
+        status.set(match result {
+            Ok(()) => tr(LANGUAGE(), done),
+            Err(e) => e.user_message(),
+        });
+    };
+
+    let input_class = "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border";
+
+    rsx! {
+        div { class: "sm:col-span-6",
+            h3 { class: "text-lg leading-6 font-medium text-gray-900",
+                {tr(LANGUAGE(), "input-session-store")}
+            }
+            p { class: "mt-1 text-sm text-gray-500", {tr(LANGUAGE(), "input-session-store-help")} }
+        }
+
+        div { class: "sm:col-span-3",
+            label {
+                r#for: "session-store-passphrase",
+                class: "block text-sm font-medium text-gray-700",
+                if unlocked {
+                    {tr(LANGUAGE(), "input-session-store-new-passphrase")}
+                } else {
+                    {tr(LANGUAGE(), "input-session-store-passphrase")}
+                }
+            }
+            div { class: "mt-1",
+                input {
+                    r#type: "password",
+                    name: "session-store-passphrase",
+                    id: "session-store-passphrase",
+                    class: input_class,
+                    value: if unlocked { "{new_passphrase}" } else { "{passphrase}" },
+                    oninput: move |event| {
+                        if unlocked {
+                            new_passphrase.set(event.value());
+                        } else {
+                            passphrase.set(event.value());
+                        }
+                    },
+                }
+            }
+        }
+
+        div { class: "sm:col-span-6 flex justify-end",
+            if unlocked {
+                SecondaryButton {
+                    onclick: move |_| {
+                        KEYSTORE.write().lock_sessions();
+                        show(Ok(()), "input-session-store-locked");
+                    },
+                    text: tr(LANGUAGE(), "input-session-store-lock"),
+                }
+                PrimaryButton {
+                    onclick: move |_| {
+                        let result = KEYSTORE.read().change_passphrase(&LocalStorage, &new_passphrase.read());
+                        new_passphrase.set(String::new());
+                        show(result, "input-session-store-changed");
+                    },
+                    text: tr(LANGUAGE(), "input-session-store-change"),
                 }
             } else {
-                p { class: "mt-2 text-xs text-red-600",
-                    "Your key is never stored or transmitted. All signing happens locally."
+                PrimaryButton {
+                    onclick: move |_| {
+                        let result = KEYSTORE.write().unlock_sessions(&LocalStorage, &passphrase.read());
+                        passphrase.set(String::new());
+                        show(result, "input-session-store-unlocked");
+                    },
+                    text: tr(LANGUAGE(), "input-session-store-unlock"),
+                }
+            }
+        }
+
+        if !status.read().is_empty() {
+            p { class: "sm:col-span-6 text-sm text-gray-500", {status.read().clone()} }
+        }
+    }
+}
+
+/// Session store component, empty: sessions are only saved with serde.
+#[cfg(not(feature = "serde-types"))]
+#[component]
+pub(crate) fn SessionStoreInput() -> Element {
+    rsx! {}
+}
+
+/// A registered `account` of [`AccountsInput`], confirming its backup with the
+/// `typed_phrase` and showing the outcome of its actions in `status`.
+#[cfg(feature = "serde-types")]
+#[component]
+fn AccountRow(
+    account: Account,
+    mut typed_phrase: Signal<String>,
+    mut status: Signal<String>,
+) -> Element {
+    let npub = account.npub;
+    let unlocked = KEYSTORE.read().is_unlocked(&npub);
+
+    let mut show = move |result: Result<(), Error>, done: &str| {
+        status.set(match result {
+            Ok(()) => tr(LANGUAGE(), done),
+            Err(e) => e.user_message(),
+        });
+    };
+
+    rsx! {
+        li { class: "py-3 flex items-center justify-between",
+            div {
+                p { class: "text-sm font-medium text-gray-900",
+                    {account.label.clone()}
+                    span { class: "ml-2 text-xs text-gray-500",
+                        if unlocked {
+                            {tr(LANGUAGE(), "input-account-unlocked")}
+                        } else {
+                            {tr(LANGUAGE(), "input-account-locked")}
+                        }
+                    }
+                }
+                p { class: "text-xs text-gray-500 font-mono break-all", "{npub}" }
+                if account.needs_backup {
+                    p { class: "text-xs text-red-600",
+                        {tr(LANGUAGE(), "input-account-backup-reminder")}
+                    }
+                }
+            }
+            div { class: "flex",
+                if account.needs_backup {
+                    SecondaryButton {
+                        onclick: move |_| {
+                            let result = RecoveryPhrase::parse(&typed_phrase.read())
+                                .and_then(|phrase| {
+                                    update_accounts(|accounts| accounts.confirm_backup(&npub, &phrase, ""))
+                                });
+                            if result.is_ok() {
+                                typed_phrase.set(String::new());
+                            }
+                            show(result, "input-account-backed-up");
+                        },
+                        text: tr(LANGUAGE(), "input-account-confirm-backup"),
+                    }
+                }
+                if unlocked {
+                    SecondaryButton {
+                        onclick: move |_| {
+                            KEYSTORE.write().lock(&npub);
+                        },
+                        text: tr(LANGUAGE(), "input-account-lock"),
+                    }
+                }
+                SecondaryButton {
+                    onclick: move |_| {
+                        KEYSTORE.write().lock(&npub);
+                        let result = update_accounts(|accounts| {
+                            accounts.remove(&npub);
+                            Ok(())
+                        });
+                        show(result, "input-account-removed");
+                    },
+                    text: tr(LANGUAGE(), "input-account-remove"),
                 }
             }
         }
@@ -688,7 +1847,7 @@ pub(crate) fn TxidInput(mut update_var: Signal<String>, label: String, warning:
             }
             if *has_error.read() {
                 p { class: "mt-2 text-xs text-red-600",
-                    "Invalid transaction ID. Please enter a valid transaction ID."
+                    {tr(LANGUAGE(), "input-txid-invalid")}
                 }
             }
         }
@@ -738,7 +1897,7 @@ pub(crate) fn TransactionInput(
                     name: id.as_str(),
                     rows: "4",
                     class: input_class,
-                    placeholder: "Paste the transaction here...",
+                    placeholder: tr(LANGUAGE(), "input-tx-placeholder"),
                     oninput: move |event| {
                         #[cfg(debug_assertions)]
                         trace!(% update_var, event_value =% event.value(), "Set transaction");
@@ -749,7 +1908,7 @@ pub(crate) fn TransactionInput(
             }
             if *has_error.read() {
                 p { class: "mt-2 text-xs text-red-600",
-                    "Invalid transaction format. The transaction should be a hexadecimal string."
+                    {tr(LANGUAGE(), "input-tx-invalid")}
                 }
             }
         }
@@ -794,7 +1953,7 @@ pub(crate) fn SignatureInput(mut update_var: Signal<String>, label: String, id:
                     name: id.as_str(),
                     rows: "4",
                     class: input_class,
-                    placeholder: "Paste the signature here...",
+                    placeholder: tr(LANGUAGE(), "input-signature-placeholder"),
                     oninput: move |event| {
                         #[cfg(debug_assertions)]
                         trace!(% update_var, event_value =% event.value(), "Set signature");
@@ -804,7 +1963,7 @@ pub(crate) fn SignatureInput(mut update_var: Signal<String>, label: String, id:
                 }
             }
             if *has_error.read() {
-                p { class: "mt-2 text-xs text-red-600", "Invalid signature format." }
+                p { class: "mt-2 text-xs text-red-600", {tr(LANGUAGE(), "input-signature-invalid")} }
             }
         }
     }
@@ -836,7 +1995,7 @@ pub(crate) fn AddressInput(mut update_var: Signal<String>) -> Element {
             label {
                 r#for: "destination-address",
                 class: "block text-sm font-medium text-gray-700",
-                "Your Destination Address"
+                {tr(LANGUAGE(), "input-destination")}
             }
             div { class: "mt-1",
                 input {
@@ -844,7 +2003,7 @@ pub(crate) fn AddressInput(mut update_var: Signal<String>) -> Element {
                     name: "destination-address",
                     id: "destination-address",
                     class: input_class,
-                    placeholder: "Enter your destination address...",
+                    placeholder: tr(LANGUAGE(), "input-destination-placeholder"),
                     oninput: move |event| {
                         #[cfg(debug_assertions)]
                         trace!(% update_var, event_value =% event.value(), "Set address");
@@ -854,7 +2013,7 @@ pub(crate) fn AddressInput(mut update_var: Signal<String>) -> Element {
             }
             if *has_error.read() {
                 p { class: "mt-2 text-xs text-red-600",
-                    "Invalid Bitcoin address format. Please check and try again."
+                    {tr(LANGUAGE(), "input-address-invalid")}
                 }
             }
         }
@@ -0,0 +1,128 @@
+//! Transaction inspector component.
+
+use bitcoin::Network;
+use dioxus::prelude::*;
+
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+
+use crate::{
+    ESPLORA_ENDPOINT, LANGUAGE, NETWORK, PROXIES, SETTINGS,
+    decode::{decode_tx, parse_tx_hex},
+    esplora::{create_client, get_prevouts},
+    i18n::{tr, tr_args},
+    proxy::ProxySettings,
+    util::parse_network,
+};
+
+/// Transaction inspector component.
+///
+/// Decodes the raw transaction in `tx_hex` into its inputs, labeled witness items and outputs.
+/// The spent outputs are fetched from Esplora to compute the fee.
+#[component]
+pub(crate) fn TransactionInspector(tx_hex: Signal<String>) -> Element {
+    let prevouts = use_resource(move || async move {
+        let tx = parse_tx_hex(&tx_hex.read()).ok()?;
+        let proxies = ProxySettings::parse(&PROXIES.read()).ok()?;
+        let esplora_client = create_client(&ESPLORA_ENDPOINT.read(), &proxies).ok()?;
+        let prevouts = get_prevouts(&esplora_client, &tx).await;
+        #[cfg(debug_assertions)]
+        trace!(?prevouts, "Fetched prevouts");
+        prevouts.ok()
+    });
+    let decoded = use_memo(move || {
+        if tx_hex.read().trim().is_empty() {
+            return None;
+        }
+        let network = parse_network(&NETWORK.read()).unwrap_or(Network::Bitcoin);
+        let prevouts = prevouts.read().clone().flatten();
+        Some(
+            parse_tx_hex(&tx_hex.read())
+                .and_then(|tx| decode_tx(&tx, network, prevouts.as_deref()))
+                .map_err(|e| e.user_message()),
+        )
+    });
+
+    let decoded = match &*decoded.read() {
+        None => return rsx! {},
+        Some(Err(e)) => {
+            return rsx! {
+                p { class: "sm:col-span-6 text-sm text-red-600", {e.clone()} }
+            };
+        }
+        Some(Ok(decoded)) => decoded.clone(),
+    };
+    let unit = SETTINGS().display_unit;
+    let fee = match (decoded.fee, decoded.fee_rate()) {
+        (Some(fee), Some(fee_rate)) => {
+            format!(
+                "{} ({fee_rate:.1} sat/vB)",
+                unit.format(fee, None, LANGUAGE())
+            )
+        }
+        _ => tr(LANGUAGE(), "inspector-fee-unknown"),
+    };
+    let size = format!("{} vB ({})", decoded.vsize(), decoded.weight);
+
+    rsx! {
+        div { class: "sm:col-span-6 space-y-4 rounded-md border border-gray-300 bg-gray-50 p-4",
+            dl { class: "grid grid-cols-1 gap-x-4 gap-y-2 sm:grid-cols-2 text-sm",
+                div {
+                    dt { class: "font-medium text-gray-500", {tr(LANGUAGE(), "inspector-txid")} }
+                    dd { class: "font-mono break-all text-gray-900", "{decoded.txid}" }
+                }
+                div {
+                    dt { class: "font-medium text-gray-500", {tr(LANGUAGE(), "inspector-size")} }
+                    dd { class: "text-gray-900", {size} }
+                }
+                div {
+                    dt { class: "font-medium text-gray-500", {tr(LANGUAGE(), "inspector-lock-time")} }
+                    dd { class: "text-gray-900", "{decoded.lock_time}" }
+                }
+                div {
+                    dt { class: "font-medium text-gray-500", {tr(LANGUAGE(), "inspector-fee")} }
+                    dd { class: "text-gray-900", {fee} }
+                }
+            }
+
+            for (index , input) in decoded.inputs.into_iter().enumerate() {
+                div { class: "border-t border-gray-200 pt-3 text-sm",
+                    h4 { class: "font-medium text-gray-700", {tr_args(LANGUAGE(), "inspector-input", &[("index", &index)])} }
+                    p { class: "font-mono break-all text-gray-900", "{input.previous_output}" }
+                    p { class: "text-gray-500",
+                        {tr_args(LANGUAGE(), "inspector-sequence", &[("sequence", &input.sequence.to_consensus_u32())])}
+                        if let Some(prevout) = input.prevout {
+                            {tr_args(LANGUAGE(), "inspector-spends", &[("amount", &unit.format(prevout.value, None, LANGUAGE()))])}
+                        }
+                    }
+                    dl { class: "mt-2 space-y-1",
+                        for item in input.witness {
+                            div {
+                                dt { class: "text-gray-500", {item.label(LANGUAGE())} }
+                                dd { class: "font-mono text-xs break-all text-gray-900",
+                                    {item.value()}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            for (index , output) in decoded.outputs.into_iter().enumerate() {
+                div { class: "border-t border-gray-200 pt-3 text-sm",
+                    h4 { class: "font-medium text-gray-700",
+                        {tr_args(LANGUAGE(), "inspector-output", &[("index", &index), ("amount", &unit.format(output.value, None, LANGUAGE()))])}
+                    }
+                    p { class: "font-mono break-all text-gray-900",
+                        {
+                            output
+                                .address
+                                .map(|address| address.to_string())
+                                .unwrap_or_else(|| output.script_pubkey.to_asm_string())
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
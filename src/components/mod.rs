@@ -4,9 +4,11 @@ pub(crate) mod broadcast;
 pub(crate) mod buttons;
 pub(crate) mod combine;
 pub(crate) mod create;
+pub(crate) mod export;
 pub(crate) mod footer;
 pub(crate) mod home;
 pub(crate) mod input;
+pub(crate) mod inspector;
 pub(crate) mod navbar;
 pub(crate) mod output;
 pub(crate) mod settings;
@@ -17,15 +19,22 @@ pub(crate) use broadcast::Broadcast;
 pub(crate) use buttons::{ContinueButton, CopyButton, PrimaryButton, SecondaryButton};
 pub(crate) use combine::Combine;
 pub(crate) use create::Create;
+pub(crate) use export::TransactionExporter;
 pub(crate) use footer::Footer;
 pub(crate) use home::Home;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use input::relay_pool;
 pub(crate) use input::{
-    AddressInput, BitcoinInput, EscrowTypeInput, EsploraInput, FeeRateSelector, NetworkInput,
-    NpubInput, NpubInputDerivedAddress, NsecInput, SignatureInput, TimelockInput, TransactionInput,
-    TxidInput, VoutInput,
+    AccountSelect, AccountsInput, AddressBookInput, AddressInput, BitcoinInput, ContactSelect,
+    DisplayUnitInput, EscrowTypeInput, EsploraInput, FeeRateLimitsInput, FeeRateSelector,
+    LanguageInput, MnemonicInput, NetworkInput, NotificationsInput, NpubInput,
+    NpubInputDerivedAddress, NsecInput, ProxyInput, RelaysInput, SessionStoreInput, SignatureInput,
+    TemplateInput, ThemeInput, TimelockInput, TransactionInput, TxidInput, VoutInput,
+    signing_account,
 };
+pub(crate) use inspector::TransactionInspector;
 pub(crate) use navbar::Navbar;
-pub(crate) use output::{DerivedAddressOutput, SignatureOutput, TransactionOutput};
+pub(crate) use output::{DerivedAddressOutput, IdentityBadge, SignatureOutput, TransactionOutput};
 pub(crate) use settings::Settings;
 pub(crate) use sign::Sign;
 pub(crate) use spend::Spend;
@@ -2,7 +2,7 @@
 
 use dioxus::prelude::*;
 
-use crate::{LOGO, Route};
+use crate::{LANGUAGE, LOGO, Route, i18n::tr};
 
 /// Shared navbar component.
 #[component]
@@ -27,7 +27,7 @@ pub(crate) fn Navbar() -> Element {
                             aria_controls: "mobile-menu",
                             aria_expanded: "{is_menu_open}",
                             onclick: move |_| is_menu_open.set(!is_menu_open()),
-                            span { class: "sr-only", "Menu" }
+                            span { class: "sr-only", {tr(LANGUAGE(), "nav-menu")} }
                             i {
                                 // Show different icon based on menu state
                                 if *is_menu_open.read() {
@@ -98,7 +98,7 @@ pub(crate) fn Navbar() -> Element {
                         div { class: "flex-shrink-0 flex items-center",
                             img {
                                 src: LOGO,
-                                alt: "Satoshi Escrow Logo",
+                                alt: tr(LANGUAGE(), "nav-logo"),
                                 class: "h-12 w-12 mr-2",
                             }
                             span { class: "text-xl font-bold text-gray-900", "Satoshi Escrow" }
@@ -109,41 +109,41 @@ pub(crate) fn Navbar() -> Element {
                                 class: if is_active(Route::Home {}) { "border-indigo-500 text-gray-900 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" } else { "border-transparent text-gray-500 hover:border-gray-300 hover:text-gray-700 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" },
                                 aria_current: if is_active(Route::Home {}) { "page" } else { "" },
                                 to: Route::Home {},
-                                "Home"
+                                {tr(LANGUAGE(), "nav-home")}
                             }
                             Link {
                                 class: if is_active(Route::Create {}) { "border-indigo-500 text-gray-900 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" } else { "border-transparent text-gray-500 hover:border-gray-300 hover:text-gray-700 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" },
                                 aria_current: if is_active(Route::Create {}) { "page" } else { "" },
                                 to: Route::Create {},
-                                "Create"
+                                {tr(LANGUAGE(), "nav-create")}
                             }
                             Link {
                                 id: "sign",
                                 class: if is_active(Route::Sign {}) { "border-indigo-500 text-gray-900 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" } else { "border-transparent text-gray-500 hover:border-gray-300 hover:text-gray-700 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" },
                                 aria_current: if is_active(Route::Sign {}) { "page" } else { "" },
                                 to: Route::Sign {},
-                                "Sign"
+                                {tr(LANGUAGE(), "nav-sign")}
                             }
                             Link {
                                 id: "combine",
                                 class: if is_active(Route::Combine {}) { "border-indigo-500 text-gray-900 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" } else { "border-transparent text-gray-500 hover:border-gray-300 hover:text-gray-700 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" },
                                 aria_current: if is_active(Route::Combine {}) { "page" } else { "" },
                                 to: Route::Combine {},
-                                "Combine"
+                                {tr(LANGUAGE(), "nav-combine")}
                             }
                             Link {
                                 id: "broadcast",
                                 class: if is_active(Route::Broadcast {}) { "border-indigo-500 text-gray-900 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" } else { "border-transparent text-gray-500 hover:border-gray-300 hover:text-gray-700 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" },
                                 aria_current: if is_active(Route::Broadcast {}) { "page" } else { "" },
                                 to: Route::Broadcast {},
-                                "Broadcast"
+                                {tr(LANGUAGE(), "nav-broadcast")}
                             }
                             Link {
                                 id: "spend",
                                 class: if is_active(Route::Spend {}) { "border-indigo-500 text-gray-900 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" } else { "border-transparent text-gray-500 hover:border-gray-300 hover:text-gray-700 inline-flex items-center px-1 pt-1 border-b-2 text-sm font-medium" },
                                 aria_current: if is_active(Route::Spend {}) { "page" } else { "" },
                                 to: Route::Spend {},
-                                "Spend"
+                                {tr(LANGUAGE(), "nav-spend")}
                             }
                         }
                     }
@@ -182,7 +182,7 @@ pub(crate) fn Navbar() -> Element {
                                     path { d: "m11 13.73-4 6.93" }
                                 }
                             }
-                            span { class: "sr-only", "Settings" }
+                            span { class: "sr-only", {tr(LANGUAGE(), "nav-settings")} }
                         }
                     }
                 }
@@ -199,7 +199,7 @@ pub(crate) fn Navbar() -> Element {
                             *is_menu_open.write() = false;
                         },
                         to: Route::Home {},
-                        "Home"
+                        {tr(LANGUAGE(), "nav-home")}
                     }
                     Link {
                         id: "create",
@@ -209,7 +209,7 @@ pub(crate) fn Navbar() -> Element {
                             *is_menu_open.write() = false;
                         },
                         to: Route::Create {},
-                        "Create"
+                        {tr(LANGUAGE(), "nav-create")}
                     }
                     Link {
                         id: "sign",
@@ -219,7 +219,7 @@ pub(crate) fn Navbar() -> Element {
                             *is_menu_open.write() = false;
                         },
                         to: Route::Sign {},
-                        "Sign"
+                        {tr(LANGUAGE(), "nav-sign")}
                     }
                     Link {
                         id: "combine",
@@ -229,7 +229,7 @@ pub(crate) fn Navbar() -> Element {
                             *is_menu_open.write() = false;
                         },
                         to: Route::Combine {},
-                        "Combine"
+                        {tr(LANGUAGE(), "nav-combine")}
                     }
                     Link {
                         id: "broadcast",
@@ -239,7 +239,7 @@ pub(crate) fn Navbar() -> Element {
                             *is_menu_open.write() = false;
                         },
                         to: Route::Broadcast {},
-                        "Broadcast"
+                        {tr(LANGUAGE(), "nav-broadcast")}
                     }
                     Link {
                         id: "spend",
@@ -249,7 +249,7 @@ pub(crate) fn Navbar() -> Element {
                             *is_menu_open.write() = false;
                         },
                         to: Route::Spend {},
-                        "Spend"
+                        {tr(LANGUAGE(), "nav-spend")}
                     }
                 }
             }
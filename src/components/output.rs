@@ -2,6 +2,15 @@
 
 use dioxus::prelude::*;
 
+use crate::{
+    LANGUAGE,
+    contacts::ProfileCache,
+    i18n::{tr, tr_args},
+    identity::{IdentityStatus, verify_profile},
+    storage::LocalStorage,
+    util::parse_npub,
+};
+
 /// Transaction output component.
 #[component]
 pub(crate) fn TransactionOutput(
@@ -42,7 +51,7 @@ pub(crate) fn SignatureOutput(mut update_var: Signal<String>) -> Element {
                     readonly: "true",
                     rows: "4",
                     class: "shadow-sm block w-full sm:text-sm border-gray-300 rounded-md p-2 border bg-gray-50",
-                    placeholder: "Signature will appear here...",
+                    placeholder: tr(LANGUAGE(), "output-signature-placeholder"),
                     value: update_var,
                 }
             }
@@ -75,3 +84,28 @@ pub(crate) fn DerivedAddressOutput(
         }
     }
 }
+
+/// NIP-05 badge of an `npub`, verified against the address claimed in its cached profile.
+///
+/// Shows nothing if the profile is unknown or claims no address.
+#[component]
+pub(crate) fn IdentityBadge(npub: Signal<String>) -> Element {
+    let status = use_resource(move || async move {
+        let npub = parse_npub(&npub.read()).ok()?;
+        let profile = ProfileCache::load(&LocalStorage)
+            .ok()?
+            .get(&npub)
+            .cloned()?;
+        verify_profile(&npub, &profile).await.ok()
+    });
+
+    match &*status.read() {
+        Some(Some(IdentityStatus::Verified(address))) => rsx! {
+            p { class: "mt-1 text-xs text-green-600", {tr_args(LANGUAGE(), "output-identity-verified", &[("address", address)])} }
+        },
+        Some(Some(IdentityStatus::Mismatch(address))) => rsx! {
+            p { class: "mt-1 text-xs text-red-600", {tr_args(LANGUAGE(), "output-identity-mismatch", &[("address", address)])} }
+        },
+        _ => rsx! {},
+    }
+}
@@ -2,20 +2,92 @@
 
 use dioxus::prelude::*;
 
-use crate::{ESPLORA_ENDPOINT, NETWORK};
+use crate::{
+    ESPLORA_ENDPOINT, LANGUAGE, NETWORK, PROXIES, RELAYS, SETTINGS,
+    address_book::AddressBook,
+    error::Error,
+    i18n::{detect_language, tr, tr_args},
+    network::Chain,
+    notifications::NotificationPreferences,
+    proxy::ProxySettings,
+    relays::parse_relays,
+    settings::Settings as AppSettings,
+    storage::LocalStorage,
+};
+#[cfg(target_arch = "wasm32")]
+use crate::{contacts::sync_follows, notifications::request_permission, util::parse_npub};
 
-use super::{EsploraInput, Footer, NetworkInput, PrimaryButton, SecondaryButton};
+#[cfg(target_arch = "wasm32")]
+use super::relay_pool;
+
+use super::{
+    AccountsInput, AddressBookInput, CopyButton, DisplayUnitInput, EsploraInput,
+    FeeRateLimitsInput, Footer, LanguageInput, NetworkInput, NotificationsInput, NpubInput,
+    PrimaryButton, ProxyInput, RelaysInput, SecondaryButton, SessionStoreInput, ThemeInput,
+};
+
+/// Imports the NIP-02 follows of `npub` from the configured relays into the address book.
+#[cfg(target_arch = "wasm32")]
+async fn import_follows(npub: &str, mut address_book: Signal<AddressBook>) -> Result<usize, Error> {
+    let npub = parse_npub(npub)?;
+    let mut pool = relay_pool()?;
+    let mut updated = address_book.read().clone();
+    let added = sync_follows(&mut pool, &npub, &mut updated, &LocalStorage).await?;
+    address_book.set(updated);
+    Ok(added)
+}
+
+/// Imports the NIP-02 follows of `npub` from the configured relays into the address book.
+#[cfg(not(target_arch = "wasm32"))]
+async fn import_follows(_npub: &str, _address_book: Signal<AddressBook>) -> Result<usize, Error> {
+    Err(Error::Relay(
+        "Nostr relays are only reachable from the browser".to_string(),
+    ))
+}
+
+/// Asks the browser to show notifications, returning whether it may.
+#[cfg(target_arch = "wasm32")]
+async fn allow_notifications() -> bool {
+    request_permission().await
+}
+
+/// Desktop notifications need no permission.
+#[cfg(not(target_arch = "wasm32"))]
+async fn allow_notifications() -> bool {
+    true
+}
+
+/// Saves the current theme, display unit, fee rate limits, network, relays and proxies
+/// as the [`AppSettings`].
+fn save_settings() -> Result<(), Error> {
+    let mut settings = SETTINGS.read().clone();
+    settings.network = NETWORK.read().parse::<Chain>()?;
+    settings.relays = parse_relays(&RELAYS.read())?;
+    settings.proxies = ProxySettings::parse(&PROXIES.read())?.to_config();
+    settings.save(&LocalStorage)?;
+    *SETTINGS.write() = settings;
+    Ok(())
+}
 
 /// Settings component.
 #[component]
 pub(crate) fn Settings() -> Element {
-    let settings_saved = use_signal(|| false);
+    let mut settings_saved = use_signal(|| false);
+    let mut save_error = use_signal(|| None::<String>);
+    let address_book = use_signal(|| AddressBook::load(&LocalStorage).unwrap_or_default());
+    let npub_follows = use_signal(String::new);
+    let mut follows_status = use_signal(String::new);
+    let notification_preferences =
+        use_signal(|| NotificationPreferences::load(&LocalStorage).unwrap_or_default());
+    let mut notifications_status = use_signal(String::new);
 
     // Read the current values from global state
     rsx! {
         main { class: "max-w-7xl mx-auto py-6 sm:px-6 lg:px-8",
             div { class: "px-4 py-6 sm:px-0",
-                h1 { class: "text-2xl font-bold text-gray-900 mb-6", "Settings" }
+                h1 { class: "text-2xl font-bold text-gray-900 mb-6",
+                    {tr(LANGUAGE(), "settings-title")}
+                }
 
                 div { class: "bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
@@ -23,27 +95,63 @@ pub(crate) fn Settings() -> Element {
                             div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
                                 NetworkInput {
                                     id: "network",
-                                    label: "Default Bitcoin Network",
+                                    label: tr(LANGUAGE(), "settings-network"),
+                                }
+
+                                LanguageInput {
+                                    id: "language",
+                                    label: tr(LANGUAGE(), "settings-language"),
+                                }
+
+                                ThemeInput {
+                                    id: "theme",
+                                    label: tr(LANGUAGE(), "settings-theme"),
+                                }
+
+                                DisplayUnitInput {
+                                    id: "display-unit",
+                                    label: tr(LANGUAGE(), "settings-display-unit"),
                                 }
 
+                                FeeRateLimitsInput {}
+
                                 EsploraInput {}
+
+                                RelaysInput {}
+
+                                // Browsers can't connect through SOCKS proxies.
+                                if cfg!(not(target_arch = "wasm32")) {
+                                    ProxyInput {}
+                                }
                             }
 
                             div { class: "pt-5",
                                 div { class: "flex justify-end space-x-3",
                                     SecondaryButton {
                                         onclick: move |_| {
-                                            *NETWORK.write() = "Mainnet".to_string();
-                                            *ESPLORA_ENDPOINT.write() = "https://mempool.space/api".to_string();
+                                            let defaults = AppSettings::default();
+                                            *NETWORK.write() = defaults.network.name().to_string();
+                                            *ESPLORA_ENDPOINT.write() = defaults.network.esplora_endpoint().to_string();
+                                            *RELAYS.write() = defaults.relays_config();
+                                            PROXIES.write().clear();
+                                            *LANGUAGE.write() = detect_language();
+                                            *SETTINGS.write() = defaults;
                                         },
-                                        text: "Restore Defaults",
+                                        text: tr(LANGUAGE(), "settings-restore-defaults"),
                                     }
                                     PrimaryButton {
-                                        onclick: move |_| {},
-                                        text: "Save Settings",
+                                        onclick: move |_| {
+                                            let result = save_settings();
+                                            settings_saved.set(result.is_ok());
+                                            save_error.set(result.err().map(|e| e.user_message()));
+                                        },
+                                        text: tr(LANGUAGE(), "settings-save"),
                                     }
                                 }
                             }
+                            if let Some(error) = save_error.read().as_ref() {
+                                p { class: "mt-2 text-sm text-red-600", "{error}" }
+                            }
                             if *settings_saved.read() {
                                 div {
                                     class: "flex items-center text-sm text-green-600 mt-2",
@@ -59,7 +167,7 @@ pub(crate) fn Settings() -> Element {
                                             clip_rule: "evenodd",
                                         }
                                     }
-                                    "Settings saved successfully!"
+                                    {tr(LANGUAGE(), "settings-saved")}
                                 }
                             }
                         }
@@ -69,13 +177,123 @@ pub(crate) fn Settings() -> Element {
                 div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
                         h3 { class: "text-lg leading-6 font-medium text-gray-900",
-                            "About Satoshi Escrow"
+                            {tr(LANGUAGE(), "settings-notifications")}
+                        }
+
+                        div { class: "mt-4 grid grid-cols-1 gap-y-4 gap-x-4 sm:grid-cols-6",
+                            NotificationsInput { preferences: notification_preferences }
+                        }
+
+                        div { class: "mt-5 flex justify-end space-x-3",
+                            // Browsers ask the user before showing notifications.
+                            if cfg!(target_arch = "wasm32") {
+                                SecondaryButton {
+                                    onclick: move |_| {
+                                        spawn(async move {
+                                            let status = if allow_notifications().await {
+                                                "settings-notifications-allowed"
+                                            } else {
+                                                "settings-notifications-denied"
+                                            };
+                                            notifications_status.set(tr(LANGUAGE(), status));
+                                        });
+                                    },
+                                    text: tr(LANGUAGE(), "settings-allow-notifications"),
+                                }
+                            }
+                            PrimaryButton {
+                                onclick: move |_| {
+                                    let status = match notification_preferences.read().save(&LocalStorage) {
+                                        Ok(()) => tr(LANGUAGE(), "settings-saved"),
+                                        Err(e) => e.user_message(),
+                                    };
+                                    notifications_status.set(status);
+                                },
+                                text: tr(LANGUAGE(), "settings-save-notifications"),
+                            }
+                        }
+
+                        if !notifications_status.read().is_empty() {
+                            p { class: "mt-2 text-sm text-gray-500",
+                                {notifications_status.read().clone()}
+                            }
+                        }
+                    }
+                }
+
+                // Accounts are saved as JSON.
+                if cfg!(feature = "serde-types") {
+                    div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
+                        div { class: "px-4 py-5 sm:p-6",
+                            div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
+                                AccountsInput {}
+                            }
+                        }
+                    }
+                    div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
+                        div { class: "px-4 py-5 sm:p-6",
+                            div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
+                                SessionStoreInput {}
+                            }
+                        }
+                    }
+                }
+
+                div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
+                    div { class: "px-4 py-5 sm:p-6",
+                        div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
+                            NpubInput {
+                                id: "npub_follows",
+                                label: tr(LANGUAGE(), "settings-import-follows-npub"),
+                                update_var: npub_follows,
+                            }
+
+                            div { class: "sm:col-span-3 flex items-end",
+                                PrimaryButton {
+                                    onclick: move |_| {
+                                        spawn(async move {
+                                            let npub = npub_follows.read().clone();
+                                            let status = match import_follows(&npub, address_book).await {
+                                                Ok(added) => {
+                                                    tr_args(LANGUAGE(), "settings-imported-follows", &[("count", &added)])
+                                                }
+                                                Err(e) => e.user_message(),
+                                            };
+                                            follows_status.set(status);
+                                        });
+                                    },
+                                    text: tr(LANGUAGE(), "settings-import-follows"),
+                                }
+                            }
+
+                            if !follows_status.read().is_empty() {
+                                p { class: "sm:col-span-6 text-sm text-gray-500",
+                                    {follows_status.read().clone()}
+                                }
+                            }
+
+                            AddressBookInput { address_book }
+                        }
+
+                        div { class: "mt-5 flex justify-end",
+                            CopyButton {
+                                text: tr(LANGUAGE(), "settings-export-address-book"),
+                                clipboard_text: address_book.read().to_json().unwrap_or_default(),
+                            }
+                        }
+                    }
+                }
+
+                div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
+                    div { class: "px-4 py-5 sm:p-6",
+                        h3 { class: "text-lg leading-6 font-medium text-gray-900",
+                            {tr(LANGUAGE(), "settings-about")}
                         }
 
                         div { class: "mt-2 max-w-xl text-sm text-gray-500",
-                            p { "Version: 0.5.0" }
+                            p { {tr_args(LANGUAGE(), "settings-version", &[("version", &"0.5.0")])} }
                             p { class: "mt-2",
-                                "A Bitcoin non-custodial peer-to-peer dispute resolution tool. All code is open source and runs entirely in your browser."
+                                {tr(LANGUAGE(), "settings-description")}
                             }
                         }
 
@@ -94,7 +312,7 @@ pub(crate) fn Settings() -> Element {
                                     class: "mr-2",
                                     path { d: "M12 0c-6.626 0-12 5.373-12 12 0 5.302 3.438 9.8 8.207 11.387.599.111.793-.261.793-.577v-2.234c-3.338.726-4.033-1.416-4.033-1.416-.546-1.387-1.333-1.756-1.333-1.756-1.089-.745.083-.729.083-.729 1.205.084 1.839 1.237 1.839 1.237 1.07 1.834 2.807 1.304 3.492.997.107-.775.418-1.305.762-1.604-2.665-.305-5.467-1.334-5.467-5.931 0-1.311.469-2.381 1.236-3.221-.124-.303-.535-1.524.117-3.176 0 0 1.008-.322 3.301 1.23.957-.266 1.983-.399 3.003-.404 1.02.005 2.047.138 3.006.404 2.291-1.552 3.297-1.23 3.297-1.23.653 1.653.242 2.874.118 3.176.77.84 1.235 1.911 1.235 3.221 0 4.609-2.807 5.624-5.479 5.921.43.372.823 1.102.823 2.222v3.293c0 .319.192.694.801.576 4.765-1.589 8.199-6.086 8.199-11.386 0-6.627-5.373-12-12-12z" }
                                 }
-                                "View on GitHub"
+                                {tr(LANGUAGE(), "settings-github")}
                             }
                         }
                     }
@@ -1,25 +1,75 @@
 //! Sign escrow transaction component.
 
-use bitcoin::{Amount, Transaction, TxOut, consensus};
+use bitcoin::{Address, Amount, OutPoint, Transaction, TxOut, Txid, consensus};
 use dioxus::prelude::*;
+use nostr::{Event, Timestamp};
 
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::{info, trace};
 
+#[cfg(target_arch = "wasm32")]
+use crate::relays::DEFAULT_QUORUM;
 use crate::{
-    NETWORK, Route,
+    ESPLORA_ENDPOINT, KEYSTORE, LANGUAGE, NETWORK, PROXIES, Route, SETTINGS,
+    arbitration::{ArbitratedSignature, Arbitration, ArbitratorMode},
+    error::Error,
+    esplora::{create_client, get_confirmations},
+    i18n::tr,
+    invariants::{ApprovedOutputs, DEFAULT_MAX_FEE_RATE, SigningInvariants, leaf_timelock},
+    network::{Chain, NetworkProfile},
+    protocol::Handshake,
+    proxy::ProxySettings,
     scripts::escrow_address,
     sign::sign_escrow_tx,
+    storage::LocalStorage,
+    tx::escrow_tx,
     util::{
-        days_to_blocks, hours_to_blocks, parse_escrow_type, parse_network, parse_npub, parse_nsec,
+        P2TR_TX_VBYTE_C, blocks_for_duration, days_hours, parse_escrow_type, parse_network,
+        parse_npub,
     },
 };
 
+#[cfg(target_arch = "wasm32")]
+use super::relay_pool;
+
 use super::{
-    BitcoinInput, ContinueButton, CopyButton, EscrowTypeInput, Footer, NetworkInput, NpubInput,
-    NsecInput, PrimaryButton, SignatureOutput, TimelockInput, TransactionInput, TxidInput,
+    AccountSelect, BitcoinInput, ContinueButton, CopyButton, EscrowTypeInput, Footer,
+    MnemonicInput, NetworkInput, NpubInput, NsecInput, PrimaryButton, SignatureOutput,
+    TimelockInput, TransactionInput, TransactionInspector, TxidInput, signing_account,
 };
 
+/// Publishes the gift wraps of an arbitrator's `decision` to the configured relays.
+#[cfg(target_arch = "wasm32")]
+async fn publish_decision(decision: &[Event]) -> Result<(), Error> {
+    let mut pool = relay_pool()?;
+    for gift_wrap in decision {
+        pool.publish(gift_wrap, DEFAULT_QUORUM).await?;
+    }
+    Ok(())
+}
+
+/// Publishes the gift wraps of an arbitrator's `decision` to the configured relays.
+#[cfg(not(target_arch = "wasm32"))]
+async fn publish_decision(_decision: &[Event]) -> Result<(), Error> {
+    Err(Error::Relay(
+        "Nostr relays are only reachable from the browser".to_string(),
+    ))
+}
+
+/// The agreed handshake of the dispute `mode` has open over `escrow_address`,
+/// read from the sessions once the session store is unlocked.
+#[cfg(feature = "serde-types")]
+fn dispute_handshake(mode: &ArbitratorMode, escrow_address: &Address) -> Result<Handshake, Error> {
+    mode.handshake(&KEYSTORE.read().sessions(&LocalStorage)?, escrow_address)
+}
+
+/// The agreed handshake of the dispute `mode` has open over `escrow_address`,
+/// none without the sessions, which are only saved with serde.
+#[cfg(not(feature = "serde-types"))]
+fn dispute_handshake(mode: &ArbitratorMode, escrow_address: &Address) -> Result<Handshake, Error> {
+    mode.handshake(&LocalStorage, escrow_address)
+}
+
 /// Sign escrow transaction component.
 #[component]
 pub(crate) fn Sign() -> Element {
@@ -29,15 +79,19 @@ pub(crate) fn Sign() -> Element {
     let npub_buyer = use_signal(String::new);
     let npub_seller = use_signal(String::new);
     let nsec = use_signal(String::new);
+    let account = use_signal(String::new);
     let npub_arbitrator = use_signal(String::new);
-    let amount_total = use_signal(String::new);
+    let amount_buyer = use_signal(String::new);
+    let amount_seller = use_signal(String::new);
     let timelock_days = use_signal(String::new);
     let timelock_hours = use_signal(String::new);
     let funding_txid = use_signal(String::new);
+    let mut reason = use_signal(String::new);
+    let mut decision_status = use_signal(String::new);
     let var_name = rsx! {
         main { class: "max-w-7xl mx-auto py-6 sm:px-6 lg:px-8",
             div { class: "px-4 py-6 sm:px-0",
-                h1 { class: "text-2xl font-bold text-gray-900 mb-6", "Sign Escrow" }
+                h1 { class: "text-2xl font-bold text-gray-900 mb-6", {tr(LANGUAGE(), "sign-title")} }
 
                 div { class: "bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
@@ -45,54 +99,66 @@ pub(crate) fn Sign() -> Element {
 
                             TransactionInput {
                                 update_var: unsigned_tx,
-                                label: "Unsigned Transaction",
+                                label: tr(LANGUAGE(), "form-unsigned-tx"),
                                 id: "unsigned-tx",
                             }
 
+                            TransactionInspector { tx_hex: unsigned_tx }
+
                             div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
-                                NetworkInput { id: "network", label: "Bitcoin Network" }
+                                NetworkInput { id: "network", label: tr(LANGUAGE(), "form-network") }
 
                                 EscrowTypeInput { update_var: escrow_type }
 
                                 NpubInput {
                                     id: "npub_buyer",
-                                    label: "Buyer Nostr Public Key (npub)",
+                                    label: tr(LANGUAGE(), "form-buyer-npub"),
                                     update_var: npub_buyer,
                                 }
 
                                 NpubInput {
                                     id: "npub_seller",
-                                    label: "Seller Nostr Public Key (npub)",
+                                    label: tr(LANGUAGE(), "form-seller-npub"),
                                     update_var: npub_seller,
                                 }
 
                                 TxidInput {
-                                    label: "Escrow funding Transaction ID",
+                                    label: tr(LANGUAGE(), "form-funding-txid"),
                                     update_var: funding_txid,
                                     warning: "",
                                 }
 
                                 BitcoinInput {
-                                    id: "amount",
-                                    label: "Total Locked Escrow Amount (BTC)",
-                                    update_var: amount_total,
+                                    id: "amount_buyer",
+                                    label: tr(LANGUAGE(), "form-buyer-amount"),
+                                    update_var: amount_buyer,
+                                }
+
+                                BitcoinInput {
+                                    id: "amount_seller",
+                                    label: tr(LANGUAGE(), "form-seller-amount"),
+                                    update_var: amount_seller,
                                 }
 
+                                AccountSelect { update_var: account }
+
                                 NsecInput { update_var: nsec }
+
+                                MnemonicInput { update_var: nsec }
                             }
 
                             div {
                                 id: "arbitrator-section",
                                 class: "border-t border-gray-200 pt-6",
                                 h3 { class: "text-lg font-medium text-gray-900",
-                                    "Arbitrator Details (for Dispute Resolution)"
+                                    {tr(LANGUAGE(), "form-arbitrator-details")}
                                 }
 
                                 div { class: "mt-4 grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
 
                                     NpubInput {
                                         id: "npub_arbitrator",
-                                        label: "Arbitrator Nostr Public Key (npub)",
+                                        label: tr(LANGUAGE(), "form-arbitrator-npub"),
                                         update_var: npub_arbitrator,
                                     }
 
@@ -100,6 +166,24 @@ pub(crate) fn Sign() -> Element {
                                         update_day_var: timelock_days,
                                         update_hour_var: timelock_hours,
                                     }
+
+                                    div { class: "sm:col-span-6",
+                                        label {
+                                            r#for: "decision_reason",
+                                            class: "block text-sm font-medium text-gray-700",
+                                            {tr(LANGUAGE(), "sign-decision-reason")}
+                                        }
+                                        div { class: "mt-1",
+                                            input {
+                                                r#type: "text",
+                                                name: "decision_reason",
+                                                id: "decision_reason",
+                                                class: "shadow-sm focus:ring-indigo-500 focus:border-indigo-500 block w-full sm:text-sm border-gray-300 rounded-md p-2 border",
+                                                value: "{reason}",
+                                                oninput: move |event| reason.set(event.value()),
+                                            }
+                                        }
+                                    }
                                 }
                             }
 
@@ -109,16 +193,36 @@ pub(crate) fn Sign() -> Element {
                                         onclick: move |_| {
                                             #[cfg(debug_assertions)]
                                             trace!(
-                                                % npub_buyer, % npub_seller, % amount_total, % NETWORK, % npub_arbitrator, %
+                                                % npub_buyer, % npub_seller, % amount_buyer, % amount_seller, % NETWORK, % npub_arbitrator, %
                                                 timelock_days, % timelock_hours, % escrow_type,
                                                 "Clicked Generate Transaction"
                                             );
-                                            let npub_buyer = parse_npub(&npub_buyer.read()).unwrap();
-                                            let npub_seller = parse_npub(&npub_seller.read()).unwrap();
-                                            let nsec = parse_nsec(&nsec.read()).unwrap();
+                                            let npub_buyer = match parse_npub(&npub_buyer.read()) {
+                                                Ok(npub_buyer) => npub_buyer,
+                                                Err(e) => {
+                                                    signature.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
+                                            let npub_seller = match parse_npub(&npub_seller.read()) {
+                                                Ok(npub_seller) => npub_seller,
+                                                Err(e) => {
+                                                    signature.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
+                                            let account = match signing_account(&nsec.read(), &account.read()) {
+                                                Ok(account) => account,
+                                                Err(e) => {
+                                                    signature.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
                                             let escrow_type = parse_escrow_type(&escrow_type.read()).unwrap();
-                                            let btc_amount_total = Amount::from_btc(
-                                                    amount_total.read().parse::<f64>().unwrap(),
+                                            let amount_buyer = Amount::from_btc(amount_buyer.read().parse::<f64>().unwrap())
+                                                .unwrap();
+                                            let amount_seller = Amount::from_btc(
+                                                    amount_seller.read().parse::<f64>().unwrap(),
                                                 )
                                                 .unwrap();
                                             let network = parse_network(&NETWORK.read()).unwrap();
@@ -126,74 +230,183 @@ pub(crate) fn Sign() -> Element {
                                                     &unsigned_tx.read(),
                                                 )
                                                 .unwrap();
-                                            let signature_str = if !npub_arbitrator.read().is_empty() {
+                                            let funding_txid = funding_txid.read().parse::<Txid>().unwrap();
+                                            let (npub_arbitrator, timelock_duration) = if !npub_arbitrator
+                                                .read()
+                                                .is_empty()
+                                            {
                                                 #[cfg(debug_assertions)]
                                                 trace!("dispute escrow sign");
-                                                let npub_arbitrator = parse_npub(&npub_arbitrator.read()).unwrap();
-                                                let timelock_hours = hours_to_blocks(
-                                                    timelock_hours.read().parse::<u32>().unwrap(),
-                                                );
-                                                let timelock_days = days_to_blocks(
-                                                    timelock_days.read().parse::<u32>().unwrap(),
+                                                let npub_arbitrator = match parse_npub(&npub_arbitrator.read()) {
+                                                    Ok(npub_arbitrator) => npub_arbitrator,
+                                                    Err(e) => {
+                                                        signature.set(e.user_message());
+                                                        return;
+                                                    }
+                                                };
+                                                let profile = NetworkProfile::from(
+                                                    NETWORK.read().parse::<Chain>().unwrap(),
                                                 );
-                                                let timelock_duration = timelock_days + timelock_hours;
-                                                let escrow_address = escrow_address(
-                                                        &npub_buyer,
-                                                        &npub_seller,
-                                                        Some(&npub_arbitrator),
-                                                        Some(timelock_duration),
-                                                        network,
-                                                    )
-                                                    .unwrap();
-                                                let prevout = TxOut {
-                                                    value: btc_amount_total,
-                                                    script_pubkey: escrow_address.script_pubkey(),
+                                                let Some(timelock_duration) = blocks_for_duration(
+                                                    days_hours(
+                                                        timelock_days.read().parse::<u32>().unwrap(),
+                                                        timelock_hours.read().parse::<u32>().unwrap(),
+                                                    ),
+                                                    &profile,
+                                                ) else {
+                                                    signature.set(
+                                                        Error::WrongInputs(
+                                                                format!("timelock is longer than {} blocks", u16::MAX),
+                                                            )
+                                                            .user_message(),
+                                                    );
+                                                    return;
                                                 };
-                                                sign_escrow_tx(
-                                                        &unsigned_tx,
-                                                        0,
-                                                        &nsec,
-                                                        &npub_buyer,
-                                                        &npub_seller,
-                                                        Some(&npub_arbitrator),
-                                                        Some(timelock_days + timelock_hours),
-                                                        vec![prevout],
-                                                        escrow_type,
-                                                    )
-                                                    .unwrap()
+                                                (Some(npub_arbitrator), Some(timelock_duration))
                                             } else {
                                                 #[cfg(debug_assertions)]
                                                 trace!("collaborative escrow sign");
-                                                let escrow_address = escrow_address(
-                                                        &npub_buyer,
-                                                        &npub_seller,
-                                                        None,
-                                                        None,
-                                                        network,
-                                                    )
-                                                    .unwrap();
-                                                let prevout = TxOut {
-                                                    value: btc_amount_total,
-                                                    script_pubkey: escrow_address.script_pubkey(),
+                                                (None, None)
+                                            };
+                                            let escrow_address = escrow_address(
+                                                    &npub_buyer,
+                                                    &npub_seller,
+                                                    npub_arbitrator.as_ref(),
+                                                    timelock_duration,
+                                                    network,
+                                                )
+                                                .unwrap();
+                                            let prevout = TxOut {
+                                                value: amount_buyer + amount_seller,
+                                                script_pubkey: escrow_address.script_pubkey(),
+                                            };
+                                            // The approved draft is the escrow transaction of the amounts entered,
+                                            // with the fee of the transaction shared by both, up to the maximum fee.
+                                            let fee = prevout
+                                                .value
+                                                .checked_sub(unsigned_tx.output.iter().map(|txout| txout.value).sum())
+                                                .unwrap_or(Amount::ZERO);
+                                            let draft = match escrow_tx(
+                                                &npub_buyer,
+                                                &npub_seller,
+                                                timelock_duration,
+                                                amount_buyer,
+                                                amount_seller,
+                                                funding_txid,
+                                                fee,
+                                                network,
+                                                unsigned_tx.lock_time,
+                                            ) {
+                                                Ok(draft) => draft,
+                                                Err(e) => {
+                                                    signature.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
+                                            let timelock = match leaf_timelock(escrow_type, timelock_duration) {
+                                                Ok(timelock) => timelock,
+                                                Err(e) => {
+                                                    signature.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
+                                            let invariants = SigningInvariants {
+                                                funding_outpoints: vec![OutPoint::new(funding_txid, 0)],
+                                                prevouts: vec![prevout],
+                                                approved_outputs: ApprovedOutputs::Draft(draft.output),
+                                                max_fee: DEFAULT_MAX_FEE_RATE.fee_vb(P2TR_TX_VBYTE_C).unwrap(),
+                                                lock_time: draft.lock_time,
+                                                timelocks: vec![timelock],
+                                            };
+                                            // Arbitrators only sign what their dispute records allow,
+                                            // once the funding has the confirmations required to resolve.
+                                            if npub_arbitrator == Some(account.nostr()) {
+                                                let nsec = match KEYSTORE.read().nsec(&account) {
+                                                    Ok(nsec) => nsec,
+                                                    Err(e) => {
+                                                        signature.set(e.user_message());
+                                                        return;
+                                                    }
                                                 };
-                                                sign_escrow_tx(
-                                                        &unsigned_tx,
-                                                        0,
-                                                        &nsec,
-                                                        &npub_buyer,
-                                                        &npub_seller,
-                                                        None,
-                                                        None,
-                                                        vec![prevout],
-                                                        escrow_type,
-                                                    )
-                                                    .unwrap()
+                                                let arbitration = ArbitratorMode::load(&LocalStorage)
+                                                    .and_then(|mode| {
+                                                        let handshake = dispute_handshake(&mode, &escrow_address)?;
+                                                        Ok(Arbitration {
+                                                            mode,
+                                                            handshake,
+                                                            reason: reason.read().clone(),
+                                                            limits: SETTINGS.read().fee_rates,
+                                                            funding_confirmations: 0,
+                                                        })
+                                                    });
+                                                let esplora_client = ProxySettings::parse(&PROXIES.read())
+                                                    .and_then(|proxies| create_client(&ESPLORA_ENDPOINT.read(), &proxies));
+                                                spawn(async move {
+                                                    let arbitrated: Result<ArbitratedSignature, Error> = async {
+                                                        let mut arbitration = arbitration?;
+                                                        arbitration.funding_confirmations = get_confirmations(
+                                                                &esplora_client?,
+                                                                &funding_txid,
+                                                            )
+                                                            .await?;
+                                                        invariants.check(&unsigned_tx)?;
+                                                        arbitration
+                                                            .sign(
+                                                                &unsigned_tx,
+                                                                0,
+                                                                invariants.prevouts.clone(),
+                                                                escrow_type,
+                                                                &nsec,
+                                                                Timestamp::now(),
+                                                            )
+                                                    }
+                                                        .await;
+                                                    match arbitrated {
+                                                        Ok(arbitrated) => {
+                                                            signature.set(arbitrated.signature.to_string());
+                                                            decision_status.set(tr(LANGUAGE(), "sign-publishing-decision"));
+                                                            decision_status
+                                                                .set(
+                                                                    match publish_decision(&arbitrated.decision).await {
+                                                                        Ok(()) => tr(LANGUAGE(), "sign-decision-sent"),
+                                                                        Err(e) => e.user_message(),
+                                                                    },
+                                                                );
+                                                        }
+                                                        Err(e) => {
+                                                            #[cfg(debug_assertions)]
+                                                            trace!(% e, "Refusing to arbitrate");
+                                                            signature.set(e.user_message());
+                                                        }
+                                                    }
+                                                });
+                                                return;
+                                            }
+                                            let signature_str = match sign_escrow_tx(
+                                                &unsigned_tx,
+                                                0,
+                                                &KEYSTORE.read(),
+                                                &account,
+                                                &npub_buyer,
+                                                &npub_seller,
+                                                npub_arbitrator.as_ref(),
+                                                timelock_duration,
+                                                &invariants,
+                                                escrow_type,
+                                            ) {
+                                                Ok(signature) => signature,
+                                                Err(e) => {
+                                                    #[cfg(debug_assertions)]
+                                                    trace!(% e, "Refusing to sign");
+                                                    signature.set(e.user_message());
+                                                    return;
+                                                }
                                             };
                                             #[cfg(debug_assertions)]
                                             info!(% signature_str, "Generated signature");
                                             signature.set(signature_str.to_string());
                                         },
-                                        text: "Sign Transaction",
+                                        text: tr(LANGUAGE(), "button-sign-transaction"),
                                     }
                                 }
                             }
@@ -205,16 +418,20 @@ pub(crate) fn Sign() -> Element {
                 div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
                         h3 { class: "text-lg leading-6 font-medium text-gray-900",
-                            "Signature"
+                            {tr(LANGUAGE(), "form-signature")}
                         }
 
                         SignatureOutput { update_var: signature }
 
+                        if !decision_status.read().is_empty() {
+                            p { class: "mt-2 text-sm text-gray-500", "{decision_status}" }
+                        }
+
                         div { class: "mt-5 flex flex-col space-y-3 sm:flex-row sm:space-y-0 sm:space-x-3",
-                            CopyButton { text: "Signature", clipboard_text: signature }
+                            CopyButton { text: tr(LANGUAGE(), "form-signature"), clipboard_text: signature }
                             ContinueButton {
                                 to: Route::Combine {},
-                                text: "Continue to Combine",
+                                text: tr(LANGUAGE(), "button-continue-combine"),
                             }
                         }
                     }
@@ -1,23 +1,29 @@
 //! Spend from resolution address component.
 
-use bitcoin::{Address, Amount, TxOut, Txid, consensus, hex::DisplayHex};
+use bitcoin::{Address, Amount, TxOut, Txid, absolute, consensus, hex::DisplayHex};
 use dioxus::prelude::*;
 
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::trace;
 
+#[cfg(debug_assertions)]
+use crate::logging::TxSummary;
+
 use crate::{
-    ESPLORA_ENDPOINT, NETWORK, Route,
-    esplora::{FeeEstimate, create_client, get_fee_estimates},
+    ESPLORA_ENDPOINT, KEYSTORE, LANGUAGE, NETWORK, PROXIES, Route, SETTINGS,
+    esplora::{FeeEstimate, create_client, get_block_height, get_fee_estimates},
+    i18n::tr,
+    invariants::SigningInvariants,
+    proxy::ProxySettings,
     sign::sign_resolution_tx,
-    tx::resolution_tx,
-    util::{P2TR_TX_VBYTE_KEY_PATH, parse_network, parse_nsec},
+    tx::{anti_fee_sniping_lock_time, resolution_tx},
+    util::{P2TR_TX_VBYTE_KEY_PATH, parse_network},
 };
 
 use super::{
-    AddressInput, BitcoinInput, ContinueButton, CopyButton, DerivedAddressOutput, FeeRateSelector,
-    Footer, NetworkInput, NpubInputDerivedAddress, NsecInput, PrimaryButton, TransactionOutput,
-    TxidInput, VoutInput,
+    AccountSelect, AddressInput, BitcoinInput, ContinueButton, CopyButton, DerivedAddressOutput,
+    FeeRateSelector, Footer, MnemonicInput, NetworkInput, NpubInputDerivedAddress, NsecInput,
+    PrimaryButton, TransactionOutput, TxidInput, VoutInput, signing_account,
 };
 
 /// Spend from resolution address component.
@@ -29,16 +35,19 @@ pub(crate) fn Spend() -> Element {
     let amount = use_signal(String::new);
     let mut fee_rate = use_signal(String::new);
     let fee_estimates = use_signal(|| Option::<FeeEstimate>::None);
+    let block_height = use_signal(|| Option::<u32>::None);
     let vout = use_signal(|| "0".to_string());
     let derived_address = use_signal(String::new);
     let nsec = use_signal(String::new);
+    let account = use_signal(String::new);
     let mut signed_tx_str = use_signal(String::new);
 
     use_effect(move || {
-        to_owned![fee_estimates];
+        to_owned![fee_estimates, block_height];
 
         spawn(async move {
-            let esplora_client = create_client(&ESPLORA_ENDPOINT.read()).unwrap();
+            let proxies = ProxySettings::parse(&PROXIES.read()).unwrap();
+            let esplora_client = create_client(&ESPLORA_ENDPOINT.read(), &proxies).unwrap();
             match get_fee_estimates(&esplora_client).await {
                 Ok(estimates) => {
                     #[cfg(debug_assertions)]
@@ -48,41 +57,45 @@ pub(crate) fn Spend() -> Element {
                 Err(e) => {
                     #[cfg(debug_assertions)]
                     trace!(%e, "Error fetching fee estimates: {}", e);
-                    // Fall back to 1 sat/vB
-                    fee_rate.set("1".to_string());
+                    let fallback = SETTINGS().fee_rates.default_fee_rate(None);
+                    fee_rate.set(fallback.to_sat_per_vb_ceil().to_string());
                 }
             }
+            // Without the tip height, fall back to no lock time.
+            if let Ok(height) = get_block_height(&esplora_client).await {
+                block_height.set(Some(height));
+            }
         });
     });
 
     rsx! {
         main { class: "max-w-7xl mx-auto py-6 sm:px-6 lg:px-8",
             div { class: "px-4 py-6 sm:px-0",
-                h1 { class: "text-2xl font-bold text-gray-900 mb-6", "Spend from Resolution Address" }
+                h1 { class: "text-2xl font-bold text-gray-900 mb-6", {tr(LANGUAGE(), "spend-title")} }
 
                 div { class: "bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
                         div { class: "space-y-6",
                             div { class: "grid grid-cols-1 gap-y-6 gap-x-4 sm:grid-cols-6",
-                                NetworkInput { id: "network", label: "Bitcoin Network" }
+                                NetworkInput { id: "network", label: tr(LANGUAGE(), "form-network") }
 
                                 NpubInputDerivedAddress {
                                     id: "npub",
-                                    label: "Your Nostr Public Key (npub)",
+                                    label: tr(LANGUAGE(), "spend-npub"),
                                     update_var: npub,
                                     update_address: derived_address,
                                     col_span: 3,
                                 }
 
                                 TxidInput {
-                                    label: "Escrow Resolution Transaction ID",
+                                    label: tr(LANGUAGE(), "spend-txid"),
                                     update_var: escrow_txid,
                                     warning: "",
                                 }
 
                                 VoutInput {
                                     id: "escrow_vout",
-                                    label: "Escrow Resolution Transaction Output Index",
+                                    label: tr(LANGUAGE(), "spend-vout"),
                                     update_var: vout,
                                 }
 
@@ -90,26 +103,30 @@ pub(crate) fn Spend() -> Element {
 
                                 BitcoinInput {
                                     id: "amount",
-                                    label: "Total Locked Amount (BTC)",
+                                    label: tr(LANGUAGE(), "spend-amount"),
                                     update_var: amount,
                                 }
 
                                 FeeRateSelector {
                                     id: "fee",
-                                    label_input: "Fee rate (sats/vByte)",
-                                    label_dropdown: "Target Blocks",
+                                    label_input: tr(LANGUAGE(), "form-fee-rate"),
+                                    label_dropdown: tr(LANGUAGE(), "form-target-blocks"),
                                     update_var: fee_rate,
                                     fee_estimates,
                                 }
 
                                 DerivedAddressOutput {
                                     update_var: derived_address,
-                                    label: "Your Resolution Address",
+                                    label: tr(LANGUAGE(), "spend-address"),
                                     id: "derived-address",
                                     col_span: 3,
                                 }
 
+                                AccountSelect { update_var: account }
+
                                 NsecInput { update_var: nsec }
+
+                                MnemonicInput { update_var: nsec }
                             }
 
                             div { class: "pt-5",
@@ -121,7 +138,13 @@ pub(crate) fn Spend() -> Element {
                                                 % npub, % amount, % NETWORK, % escrow_txid, % derived_address,
                                                 "Clicked Sign Transaction"
                                             );
-                                            let nsec = parse_nsec(&nsec.read()).unwrap();
+                                            let account = match signing_account(&nsec.read(), &account.read()) {
+                                                Ok(account) => account,
+                                                Err(e) => {
+                                                    signed_tx_str.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
                                             let btc_amount = Amount::from_btc(amount.read().parse::<f64>().unwrap())
                                                 .unwrap();
                                             let network = parse_network(&NETWORK.read()).unwrap();
@@ -140,30 +163,53 @@ pub(crate) fn Spend() -> Element {
                                                 .require_network(network)
                                                 .unwrap();
                                             let fee_rate = fee_rate.read().parse::<u64>().unwrap();
-                                            let fee = Amount::from_sat(fee_rate * P2TR_TX_VBYTE_KEY_PATH);
+                                            let fee = match SETTINGS().fee_rates.check_sat_per_vb(fee_rate) {
+                                                Ok(fee_rate) => fee_rate.fee_vb(P2TR_TX_VBYTE_KEY_PATH).unwrap(),
+                                                Err(e) => {
+                                                    signed_tx_str.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
+                                            // Anti-fee-sniping when the tip height is known.
+                                            let lock_time = match *block_height.read() {
+                                                Some(height) => anti_fee_sniping_lock_time(height).unwrap(),
+                                                None => absolute::LockTime::ZERO,
+                                            };
                                             let unsigned_tx = resolution_tx(
                                                 btc_amount,
                                                 escrow_txid,
                                                 vout,
                                                 &destination_address,
                                                 fee,
+                                                lock_time,
                                             );
                                             #[cfg(debug_assertions)]
                                             trace!(
-                                                unsigned_tx = % consensus::serialize(& unsigned_tx).as_hex(),
+                                                unsigned_tx = % TxSummary(& unsigned_tx),
                                                 "Created unsigned resolution transaction"
                                             );
                                             let prevout = TxOut {
                                                 value: btc_amount,
                                                 script_pubkey: derived_address.script_pubkey(),
                                             };
-                                            let signed_tx = sign_resolution_tx(&unsigned_tx, &nsec, prevout);
-                                            let signed_tx = consensus::serialize(&signed_tx).as_hex().to_string();
+                                            // Built above from what the user entered.
+                                            let invariants = SigningInvariants::of_local_tx(
+                                                &unsigned_tx,
+                                                vec![prevout],
+                                                vec![None],
+                                            );
+                                            let signed_tx = match sign_resolution_tx(&unsigned_tx, &KEYSTORE.read(), &account, &invariants) {
+                                                Ok(signed_tx) => signed_tx,
+                                                Err(e) => {
+                                                    signed_tx_str.set(e.user_message());
+                                                    return;
+                                                }
+                                            };
                                             #[cfg(debug_assertions)]
-                                            trace!(% signed_tx, "Signed resolution transaction");
-                                            signed_tx_str.set(signed_tx);
+                                            trace!(signed_tx = % TxSummary(& signed_tx), "Signed resolution transaction");
+                                            signed_tx_str.set(consensus::serialize(&signed_tx).as_hex().to_string());
                                         },
-                                        text: "Sign Transaction",
+                                        text: tr(LANGUAGE(), "button-sign-transaction"),
                                     }
                                 }
                             }
@@ -175,24 +221,24 @@ pub(crate) fn Spend() -> Element {
                 div { class: "mt-8 bg-white shadow overflow-hidden sm:rounded-lg",
                     div { class: "px-4 py-5 sm:p-6",
                         h3 { class: "text-lg leading-6 font-medium text-gray-900",
-                            "Signed Transaction"
+                            {tr(LANGUAGE(), "form-signed-tx")}
                         }
 
                         TransactionOutput {
                             update_var: signed_tx_str,
                             label: "",
                             id: "signed-tx",
-                            placeholder: "Signed transaction will appear here...",
+                            placeholder: tr(LANGUAGE(), "form-signed-tx-placeholder"),
                         }
 
                         div { class: "mt-5 flex flex-col space-y-3 sm:flex-row sm:space-y-0 sm:space-x-3",
                             CopyButton {
-                                text: "Transaction",
+                                text: tr(LANGUAGE(), "button-transaction"),
                                 clipboard_text: signed_tx_str,
                             }
                             ContinueButton {
                                 to: Route::Broadcast {},
-                                text: "Continue to Broadcast",
+                                text: tr(LANGUAGE(), "button-continue-broadcast"),
                             }
                         }
                     }
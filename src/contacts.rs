@@ -0,0 +1,317 @@
+//! Nostr follow lists (NIP-02) and profile metadata (NIP-01 kind 0).
+//!
+//! Lets users pick counterparties and arbitrators among the people they follow.
+//! Profiles are cached in [`Storage`] so names and pictures show up without
+//! waiting for the relays.
+
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{Event, Filter, Kind, Timestamp, key::PublicKey as NostrPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address_book::{AddressBook, Contact},
+    error::Error,
+    keys::Npub,
+    relays::{RelayPool, RelayTransport},
+    storage::Storage,
+};
+
+/// [`Storage`] key of the [`ProfileCache`].
+pub(crate) const PROFILE_CACHE_KEY: &str = "scrow.profiles";
+
+/// Display metadata of a Nostr user, from their kind 0 event.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Profile {
+    /// Short name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) name: Option<String>,
+    /// Display name, preferred over `name` when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) display_name: Option<String>,
+    /// Picture URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) picture: Option<String>,
+    /// Claimed NIP-05 address, see [`crate::identity`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) nip05: Option<String>,
+}
+
+impl Profile {
+    /// Parses the profile from a kind 0 [`Event`].
+    ///
+    /// Unknown and malformed fields are ignored, since clients publish all sorts of metadata.
+    pub(crate) fn from_event(event: &Event) -> Result<Self, Error> {
+        if event.kind != Kind::Metadata {
+            return Err(Error::WrongInputs(format!(
+                "Expected a metadata event, got kind {}",
+                event.kind
+            )));
+        }
+        let value = serde_json::from_str::<serde_json::Value>(&event.content)
+            .map_err(|e| Error::WrongInputs(format!("Malformed profile metadata: {e}")))?;
+        let text = |key: &str| {
+            value
+                .get(key)
+                .and_then(serde_json::Value::as_str)
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+                .map(str::to_string)
+        };
+        Ok(Self {
+            name: text("name"),
+            display_name: text("display_name"),
+            picture: text("picture"),
+            nip05: text("nip05"),
+        })
+    }
+
+    /// Name to show for the profile, if any.
+    pub(crate) fn label(&self) -> Option<&str> {
+        self.display_name.as_deref().or(self.name.as_deref())
+    }
+}
+
+/// A cached [`Profile`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CachedProfile {
+    /// Owner of the profile.
+    pub(crate) npub: NostrPublicKey,
+    /// The profile.
+    pub(crate) profile: Profile,
+    /// Creation time of the event the profile was read from.
+    pub(crate) created_at: Timestamp,
+}
+
+/// [`Profile`]s of the users seen so far, at most one per `npub`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct ProfileCache {
+    profiles: Vec<CachedProfile>,
+}
+
+impl ProfileCache {
+    /// Loads the cache from `storage`, or an empty one if none was saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(PROFILE_CACHE_KEY)? {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|e| Error::Storage(format!("Invalid profile cache: {e}"))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the cache to `storage`.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::Storage(format!("Could not serialize profile cache: {e}")))?;
+        storage.set(PROFILE_CACHE_KEY, &json)
+    }
+
+    /// The cached profile of `npub`, if any.
+    pub(crate) fn get(&self, npub: &NostrPublicKey) -> Option<&Profile> {
+        self.profiles
+            .iter()
+            .find(|cached| cached.npub == *npub)
+            .map(|cached| &cached.profile)
+    }
+
+    /// Updates the cache with kind 0 `events`, keeping the newest profile of each user.
+    ///
+    /// Returns the number of profiles added or replaced.
+    pub(crate) fn update<'a>(&mut self, events: impl IntoIterator<Item = &'a Event>) -> usize {
+        let mut updated = 0;
+        for event in events {
+            let Ok(profile) = Profile::from_event(event) else {
+                continue;
+            };
+            let cached = CachedProfile {
+                npub: event.pubkey,
+                profile,
+                created_at: event.created_at,
+            };
+            match self.profiles.iter_mut().find(|c| c.npub == event.pubkey) {
+                Some(existing) if existing.created_at >= event.created_at => continue,
+                Some(existing) => *existing = cached,
+                None => self.profiles.push(cached),
+            }
+            updated += 1;
+        }
+        updated
+    }
+}
+
+/// Parses the followed public keys from a NIP-02 contact list [`Event`], in list order.
+///
+/// Malformed and duplicate `p` tags are skipped.
+pub(crate) fn parse_follow_list(event: &Event) -> Result<Vec<NostrPublicKey>, Error> {
+    if event.kind != Kind::ContactList {
+        return Err(Error::WrongInputs(format!(
+            "Expected a contact list event, got kind {}",
+            event.kind
+        )));
+    }
+    let mut follows = Vec::new();
+    for tag in event.tags.iter() {
+        if let [kind, public_key, ..] = tag.as_slice() {
+            if kind != "p" {
+                continue;
+            }
+            if let Ok(public_key) = NostrPublicKey::from_hex(public_key.as_str())
+                && !follows.contains(&public_key)
+            {
+                follows.push(public_key);
+            }
+        }
+    }
+    Ok(follows)
+}
+
+/// Fetches the follow list of `npub` from a [`RelayPool`].
+///
+/// Returns an empty list if `npub` never published one.
+pub(crate) async fn fetch_follow_list<T: RelayTransport>(
+    pool: &mut RelayPool<T>,
+    npub: &NostrPublicKey,
+) -> Result<Vec<NostrPublicKey>, Error> {
+    let filter = Filter::new().kind(Kind::ContactList).author(*npub);
+    // Contact lists are replaceable, only the newest one counts.
+    match pool.fetch(&filter).await?.last() {
+        Some(event) => parse_follow_list(event),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Fetches the profiles of `npubs` from a [`RelayPool`] into the [`ProfileCache`].
+///
+/// Returns the number of profiles added or replaced.
+pub(crate) async fn fetch_profiles<T: RelayTransport>(
+    pool: &mut RelayPool<T>,
+    npubs: &[NostrPublicKey],
+    cache: &mut ProfileCache,
+) -> Result<usize, Error> {
+    if npubs.is_empty() {
+        return Ok(0);
+    }
+    let filter = Filter::new()
+        .kind(Kind::Metadata)
+        .authors(npubs.iter().copied());
+    let events = pool.fetch(&filter).await?;
+    #[cfg(debug_assertions)]
+    trace!(
+        requested = npubs.len(),
+        found = events.len(),
+        "fetched profiles"
+    );
+    Ok(cache.update(&events))
+}
+
+/// Adds the follows of a user to the [`AddressBook`], labeled with their cached profile name.
+///
+/// Follows already in the address book are left untouched.
+/// Returns the number of new contacts.
+pub(crate) fn import_follows(
+    address_book: &mut AddressBook,
+    follows: &[NostrPublicKey],
+    cache: &ProfileCache,
+) -> usize {
+    let new_contacts = follows
+        .iter()
+        .filter(|npub| address_book.get(npub).is_none())
+        .map(|npub| Contact {
+            npub: *npub,
+            label: cache
+                .get(npub)
+                .and_then(Profile::label)
+                .map(str::to_string)
+                .unwrap_or_else(|| short_npub(npub)),
+            payout_address: None,
+            trust_notes: String::new(),
+        })
+        .collect::<Vec<_>>();
+    address_book.import(new_contacts)
+}
+
+/// Imports the follows of `npub` into the [`AddressBook`], refreshing their cached profiles.
+///
+/// Both the profiles and the address book are saved to `storage`.
+/// Returns the number of new contacts.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub(crate) async fn sync_follows<T: RelayTransport>(
+    pool: &mut RelayPool<T>,
+    npub: &NostrPublicKey,
+    address_book: &mut AddressBook,
+    storage: &impl Storage,
+) -> Result<usize, Error> {
+    let follows = fetch_follow_list(pool, npub).await?;
+    let mut cache = ProfileCache::load(storage).unwrap_or_default();
+    fetch_profiles(pool, &follows, &mut cache).await?;
+    cache.save(storage)?;
+    let added = import_follows(address_book, &follows, &cache);
+    address_book.save(storage)?;
+    Ok(added)
+}
+
+/// Abbreviated `npub` used as a label when no profile name is known.
+fn short_npub(npub: &NostrPublicKey) -> String {
+    let npub = Npub::from(*npub).to_string();
+    format!("{}…{}", &npub[..12], &npub[npub.len() - 6..])
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::{EventBuilder, Keys, Tag};
+
+    use super::*;
+
+    fn metadata_event(keys: &Keys, content: &str, created_at: u64) -> Event {
+        EventBuilder::new(Kind::Metadata, content)
+            .custom_created_at(Timestamp::from(created_at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn follow_list() {
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let event = EventBuilder::new(Kind::ContactList, "")
+            .tags([
+                Tag::public_key(alice.public_key()),
+                Tag::public_key(bob.public_key()),
+                Tag::public_key(alice.public_key()),
+                Tag::hashtag("scrow"),
+            ])
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        assert_eq!(
+            parse_follow_list(&event).unwrap(),
+            vec![alice.public_key(), bob.public_key()]
+        );
+        assert!(parse_follow_list(&metadata_event(&alice, "{}", 1)).is_err());
+    }
+
+    #[test]
+    fn profile_cache() {
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let mut cache = ProfileCache::default();
+        let updated = cache.update(&[
+            metadata_event(&alice, r#"{"name":"alice","picture":"https://a.png"}"#, 2),
+            metadata_event(&alice, r#"{"name":"old alice"}"#, 1),
+            metadata_event(&bob, "not json", 1),
+        ]);
+        assert_eq!(updated, 1);
+        let profile = cache.get(&alice.public_key()).unwrap();
+        assert_eq!(profile.label(), Some("alice"));
+        assert_eq!(profile.picture.as_deref(), Some("https://a.png"));
+        assert!(cache.get(&bob.public_key()).is_none());
+
+        let storage = crate::storage::MemoryStorage::default();
+        cache.save(&storage).unwrap();
+        assert_eq!(ProfileCache::load(&storage).unwrap(), cache);
+
+        let mut address_book = AddressBook::default();
+        let follows = [alice.public_key(), bob.public_key()];
+        assert_eq!(import_follows(&mut address_book, &follows, &cache), 2);
+        assert_eq!(address_book.contacts()[0].label, "alice");
+        assert_eq!(import_follows(&mut address_book, &follows, &cache), 0);
+    }
+}
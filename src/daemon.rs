@@ -0,0 +1,2342 @@
+//! `scrowd`, the escrow engine as a daemon, in native builds.
+//!
+//! Services integrate escrows programmatically through a small REST API over HTTP/1.1,
+//! served by [`hyper`] and routed by [`axum`], answering JSON:
+//!
+//! - `GET /health`: liveness, without authentication.
+//! - `POST /v1/rpc`: a JSON-RPC [`Request`](crate::api::Request), as handled by [`handle_json`].
+//! - `POST /v1/{method}`: the parameters of the [`Method`](crate::api::Method) `method`,
+//!   answered with its [`Response`](crate::api::Response).
+//! - `GET /v1/sessions`, `GET /v1/sessions/{id}`, `PUT /v1/sessions/{id}`:
+//!   the persisted escrow negotiations, see [`Session`].
+//! - `GET /v1/sessions/{id}/history`: the history of a session as a plain text audit log,
+//!   see [`Session::audit_log`].
+//! - `GET /v1/sessions/{id}/funding`: the confirmations of the funding of a session out of
+//!   the ones required before resolution, and whether it can still be reversed.
+//! - `GET /v1/escrows/{id}`: the sessions of a funded escrow, merged,
+//!   by its canonical ID, see [`Session::find_funded`].
+//! - `GET /v1/accounting/{npub}` and `GET /v1/accounting/{npub}/csv`: the accounting
+//!   [`EscrowRecord`]s of the npub's completed escrows, as JSON or as CSV.
+//!   Both answer 423 while the session store is locked.
+//! - `POST /v1/dashboard/{npub}`, with an [`EscrowQuery`] body: a page of the npub's
+//!   escrows, filtered and ordered for the dashboard, see [`list_escrows`].
+//! - `POST /v1/vault/unlock` and `PUT /v1/vault/passphrase`, with a `{"passphrase": ...}`
+//!   body, and `POST /v1/vault/lock`: the encrypted session store, see [`Vault`](crate::vault).
+//!   The first unlock encrypts it, and until then, and while it is locked,
+//!   the sessions and escrows are answered 423, only their IDs are listed.
+//! - `POST /v1/musig/nonce` and `POST /v1/musig/sign`: the two MuSig2 rounds of a cooperative
+//!   key path close, see [`MusigRound`], whose partial signatures are combined by the
+//!   `aggregate_key_spend` method. The secret nonce waits for the second round
+//!   in the session store, so both answer 423 while it is locked.
+//! - `POST /v1/backup`, with a `{"nsec": ...}` body, and `POST /v1/restore`, with the
+//!   `backup` next to the nsec: the encrypted [`Backup`] of the sessions, watched escrows
+//!   and contacts, to move them to another device. Both answer 423 while the session store
+//!   is locked.
+//! - `GET /v1/watched`, `GET`, `PUT` and `DELETE /v1/watched/{txid}`:
+//!   the watched escrows, see [`WatchSession`].
+//! - `GET /v1/watched/{txid}/report`: audits a watched escrow on chain,
+//!   answering its plain text report, see [`WatchSession::audit_report`].
+//! - `GET /v1/wallet/{npub}`: the coins of the npub's wallet on the chain in the settings,
+//!   with their labels and freezes, see [`list_coins`].
+//! - `GET /v1/coins` and `PUT /v1/coins/{outpoint}`, with a `{"label": ..., "frozen": ...}`
+//!   body: the [`CoinControl`] of the wallet's coins.
+//! - `POST /v1/wallet/sweep`, with the `nsec`, a `destination` address and a `fee_rate`
+//!   in sat/vB: sweeps every coin of the nsec's wallet, answering the signed transaction
+//!   to broadcast, see [`sweep_wallet`].
+//! - `GET /v1/webhooks` and `PUT /v1/webhooks`, with the `webhooks` to notify, each with its
+//!   `url`, `secret` and optional `transitions`, and the `confirmations` a resolution needs:
+//!   the [`Webhooks`] notified of the watched escrows' transitions. Listing them leaves out
+//!   their secrets.
+//! - `GET /v1/timelock-warnings` and `PUT /v1/timelock-warnings`, with a `{"thresholds": ...}`
+//!   body in seconds: how long before a dispute timelock expires the user is warned, see
+//!   [`WarningThresholds`].
+//! - `GET /v1/disputes` and `POST /v1/disputes`, with the agreed handshake of a disputed
+//!   escrow: the disputes of the [`ArbitratorMode`], opened with the payout addresses of the
+//!   participants. `PUT /v1/disputes/{id}/ruling`, with a `{"buyer_share_bps": ...}` body,
+//!   records the ruling of the dispute of a session.
+//! - `GET /v1/templates/schema`: the JSON Schema of shared escrow templates, for their
+//!   authors, see [`TEMPLATE_SCHEMA`].
+//! - `GET /v1/diagnostics`: a sanitized [`DiagnosticsBundle`] to attach to bug reports.
+//! - `POST /v1/broadcast`, with a `{"tx_hex": ...}` body: broadcasts a signed transaction
+//!   through the Bitcoin Core node if configured, the Esplora backend otherwise,
+//!   see [`Broadcaster`], recording it in the history of the optional `session_id`.
+//! - `POST /v1/broadcast/package`: submits a [`PackageBody`] of unconfirmed parents and
+//!   the child paying for them through the Bitcoin Core node, see [`Package`].
+//! - `POST /v1/faucet`, with a `{"npub": ...}` body and an optional `amount` in satoshis:
+//!   has the [`Faucet`] of the test chain in the settings pay the npub's address.
+//! - `POST /v1/payjoin/send`: posts the buyer's original payment to the seller's payjoin
+//!   endpoint, answering the signed payjoin, or the original if the payjoin fails,
+//!   see [`PayjoinSender`].
+//! - `POST /v1/payjoin/receive`: answers a payjoin request forwarded by the seller's endpoint
+//!   with the seller's signed proposal, see [`PayjoinReceiver`].
+//! - `GET /v1/price/{currency}`: the [`Price`](crate::price::Price) of a bitcoin in
+//!   `currency`, such as `BRL`, to denominate offers in fiat and accept them.
+//!
+//! Every `/v1` route requires one of the configured API keys,
+//! as `Authorization: Bearer <key>`.
+//! Connections carry a single request, which has [`REQUEST_TIMEOUT`] to be sent and answered,
+//! and at most [`MAX_CONNECTIONS`] are served at once.
+//! State lives in a [`FileStorage`] directory, and a background watcher refreshes the
+//! watched escrows, notifies the [`Webhooks`] of their transitions, and shows desktop
+//! [`Notification`]s of funding confirmations and expiring timelocks.
+//! Once the session store is unlocked, it also records the funding and the spend of the
+//! sessions' escrows, logging spends by transactions the sessions didn't sign, and warns
+//! of the sessions' expiring dispute timelocks.
+//!
+//! The daemon runs when the binary is invoked as `scrowd`, or as `scrow daemon`,
+//! and is configured with the `SCROWD_*` environment variables, see [`DaemonConfig::from_env`].
+
+use std::{
+    convert::Infallible,
+    env, fmt,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+};
+use bitcoin::{
+    Address, Amount, FeeRate, OutPoint, Psbt, TxOut, Txid, address::NetworkUnchecked,
+    consensus::encode::serialize_hex,
+};
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use nostr::{Timestamp, key::PublicKey as NostrPublicKey};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::{net::TcpListener, sync::Semaphore, time::timeout};
+
+use crate::{
+    accounting::{EscrowRecord, to_csv, to_json},
+    accounts::Keystore,
+    api::{ApiError, handle_json},
+    arbitration::ArbitratorMode,
+    audit::audit_escrow,
+    backup::Backup,
+    broadcast::{Broadcaster, CoreRpcBackend, EsploraBackend, RetryPolicy},
+    cofunding::FundingInput,
+    dashboard::{EscrowQuery, list_escrows},
+    decode::parse_tx_hex,
+    diagnostics::{DiagnosticsBundle, NetworkDiagnostics},
+    error::Error,
+    esplora::{EsploraClient, create_client, get_block_height, get_confirmations},
+    expiry::WarningThresholds,
+    faucet::{DEFAULT_FAUCET_AMOUNT, Faucet},
+    funding::fetch_funding_txs,
+    i18n::detect_language,
+    invariants::SigningInvariants,
+    keys::Npub,
+    logging::Redacted,
+    mempool::{FundingRisk, check_funding},
+    musig::{KeyAggContext, PublicNonce, commit_nonce, sign_with_stored_nonce},
+    network::NetworkProfile,
+    notifications::{DesktopNotifier, EscrowWatcher, Refreshed, chain_of},
+    package::Package,
+    payjoin::{PayjoinParams, PayjoinReceiver, PayjoinSender},
+    price::{Currency, DEFAULT_PRICE_PROVIDERS, PriceProvider, fetch_price, parse_price_providers},
+    protocol::{Handshake, Session, SessionId, deserialize, serialize},
+    proxy::ProxySettings,
+    scripts::EscrowConfig,
+    secret::SecretNsec,
+    settings::Settings,
+    sign::key_spend_message,
+    storage::{FileStorage, Storage},
+    templates::TEMPLATE_SCHEMA,
+    vault::VaultStorage,
+    wallet::{CoinControl, list_coins, sweep_wallet},
+    watch::{WatchSession, WatchStatus},
+    webhooks::{DEFAULT_CONFIRMATIONS, Transition, Webhook, Webhooks},
+};
+
+/// Address the daemon listens on by default, local connections only.
+pub(crate) const DEFAULT_ADDR: &str = "127.0.0.1:8383";
+
+/// Directory of the daemon's state by default, relative to the working directory.
+pub(crate) const DEFAULT_DATA_DIR: &str = "scrowd-data";
+
+/// Esplora backend of the watcher by default.
+pub(crate) const DEFAULT_ESPLORA_URL: &str = "https://mempool.space/api";
+
+/// Time between two refreshes of the watched escrows by default.
+pub(crate) const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Largest request body accepted.
+const MAX_BODY_LEN: usize = 1 << 20;
+
+/// Most connections served at once, the next ones are answered 503.
+const MAX_CONNECTIONS: usize = 64;
+
+/// Time allowed to send a whole request and read its response,
+/// after which the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configuration of the daemon.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct DaemonConfig {
+    /// Address to listen on.
+    pub(crate) addr: SocketAddr,
+    /// Keys accepted in the `Authorization` header.
+    pub(crate) api_keys: Vec<String>,
+    /// Directory of the [`FileStorage`].
+    pub(crate) data_dir: PathBuf,
+    /// Esplora backend of the watcher.
+    pub(crate) esplora_url: String,
+    /// SOCKS5 proxies to connect to the Esplora backend through.
+    pub(crate) proxies: ProxySettings,
+    /// JSON-RPC interface of one's own Bitcoin Core node to broadcast through, if any.
+    pub(crate) core_rpc_url: Option<String>,
+    /// RPC user and password of the Bitcoin Core node, if it requires them.
+    pub(crate) core_rpc_credentials: Option<(String, String)>,
+    /// Faucet API to use instead of the chain's, such as a self-hosted one.
+    pub(crate) faucet_url: Option<String>,
+    /// Providers of the price route, in order.
+    pub(crate) price_providers: Vec<PriceProvider>,
+    /// Time between two refreshes of the watched escrows.
+    pub(crate) watch_interval: Duration,
+}
+
+impl DaemonConfig {
+    /// Reads the configuration from the environment:
+    ///
+    /// - `SCROWD_API_KEYS`: comma-separated API keys, required.
+    /// - `SCROWD_ADDR`: address to listen on, [`DEFAULT_ADDR`] if unset.
+    /// - `SCROWD_DATA_DIR`: state directory, [`DEFAULT_DATA_DIR`] if unset.
+    /// - `SCROWD_ESPLORA_URL`: Esplora backend, [`DEFAULT_ESPLORA_URL`] if unset.
+    /// - `SCROWD_PROXIES`: [proxy configuration](crate::proxy) with its lines separated
+    ///   by `;`, such as `127.0.0.1:9050` to connect over Tor, no proxy if unset.
+    /// - `SCROWD_WATCH_INTERVAL`: seconds between refreshes, [`DEFAULT_WATCH_INTERVAL`] if unset.
+    /// - `SCROWD_CORE_RPC_URL`: JSON-RPC interface of a Bitcoin Core node to broadcast through,
+    ///   authenticated with `SCROWD_CORE_RPC_USER` and `SCROWD_CORE_RPC_PASSWORD` if set.
+    /// - `SCROWD_FAUCET_URL`: faucet API of the test chain, the chain's own if unset.
+    /// - `SCROWD_PRICE_PROVIDERS`: comma-separated price providers, such as `mempool,coingecko`,
+    ///   [`DEFAULT_PRICE_PROVIDERS`] if unset.
+    pub(crate) fn from_env() -> Result<Self, Error> {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.trim().is_empty());
+        let api_keys = var("SCROWD_API_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if api_keys.is_empty() {
+            return Err(Error::WrongInputs(
+                "SCROWD_API_KEYS must hold at least one API key".to_string(),
+            ));
+        }
+        let addr = var("SCROWD_ADDR").unwrap_or_else(|| DEFAULT_ADDR.to_string());
+        let addr = addr
+            .trim()
+            .parse()
+            .map_err(|_| Error::WrongInputs(format!("Invalid SCROWD_ADDR {addr}")))?;
+        let watch_interval = match var("SCROWD_WATCH_INTERVAL") {
+            Some(secs) => secs
+                .trim()
+                .parse()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .ok_or_else(|| {
+                    Error::WrongInputs(format!("Invalid SCROWD_WATCH_INTERVAL {secs}"))
+                })?,
+            None => DEFAULT_WATCH_INTERVAL,
+        };
+        let price_providers = match var("SCROWD_PRICE_PROVIDERS") {
+            Some(providers) => parse_price_providers(&providers)?,
+            None => DEFAULT_PRICE_PROVIDERS.to_vec(),
+        };
+        let proxies = match var("SCROWD_PROXIES") {
+            Some(proxies) => ProxySettings::parse(&proxies.replace(';', "\n"))?,
+            None => ProxySettings::default(),
+        };
+        Ok(Self {
+            addr,
+            api_keys,
+            data_dir: var("SCROWD_DATA_DIR")
+                .unwrap_or_else(|| DEFAULT_DATA_DIR.to_string())
+                .into(),
+            esplora_url: var("SCROWD_ESPLORA_URL")
+                .unwrap_or_else(|| DEFAULT_ESPLORA_URL.to_string()),
+            proxies,
+            core_rpc_url: var("SCROWD_CORE_RPC_URL"),
+            core_rpc_credentials: var("SCROWD_CORE_RPC_USER").zip(var("SCROWD_CORE_RPC_PASSWORD")),
+            faucet_url: var("SCROWD_FAUCET_URL"),
+            price_providers,
+            watch_interval,
+        })
+    }
+
+    /// The Bitcoin Core node to broadcast through, if configured.
+    fn core_rpc(&self) -> Option<CoreRpcBackend> {
+        let credentials = self
+            .core_rpc_credentials
+            .as_ref()
+            .map(|(user, password)| (user.as_str(), password.as_str()));
+        self.core_rpc_url
+            .as_deref()
+            .map(|url| CoreRpcBackend::new(url, credentials))
+    }
+
+    /// Whether the request `headers` carry one of the API keys.
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(key) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        // Compare every key in constant time, so keys can't be guessed byte by byte.
+        self.api_keys.iter().fold(false, |found, api_key| {
+            let equal = api_key.len() == key.len()
+                && api_key
+                    .bytes()
+                    .zip(key.bytes())
+                    .fold(0, |diff, (a, b)| diff | (a ^ b))
+                    == 0;
+            found | equal
+        })
+    }
+}
+
+impl fmt::Debug for DaemonConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DaemonConfig")
+            .field("addr", &self.addr)
+            .field("api_keys", &Redacted(self.api_keys.len()))
+            .field("data_dir", &self.data_dir)
+            .field("esplora_url", &self.esplora_url)
+            .field("proxies", &self.proxies)
+            .field("core_rpc_url", &self.core_rpc_url)
+            .field(
+                "core_rpc_credentials",
+                &self.core_rpc_credentials.as_ref().map(Redacted),
+            )
+            .field("faucet_url", &self.faucet_url)
+            .field("price_providers", &self.price_providers)
+            .field("watch_interval", &self.watch_interval)
+            .finish()
+    }
+}
+
+/// Whether the binary was invoked as the daemon: as `scrowd`, or as `scrow daemon`.
+pub(crate) fn invoked() -> bool {
+    let mut args = env::args();
+    let program = args.next().map(PathBuf::from);
+    program
+        .as_deref()
+        .and_then(|program| program.file_stem())
+        .is_some_and(|name| name == "scrowd")
+        || args.next().as_deref() == Some("daemon")
+}
+
+/// Runs the daemon with `config`: the watcher in the background, and the API until it fails.
+pub(crate) fn run(config: DaemonConfig) -> Result<(), Error> {
+    let storage = FileStorage::open(&config.data_dir)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| Error::Http(format!("Could not start the runtime: {e}")))?;
+    let listener = runtime
+        .block_on(TcpListener::bind(config.addr))
+        .map_err(|e| Error::WrongInputs(format!("Could not listen on {}: {e}", config.addr)))?;
+    let client = create_client(&config.esplora_url, &config.proxies)?;
+    let keystore = Arc::new(Mutex::new(Keystore::default()));
+    let watcher_storage = storage.clone();
+    let watcher_keystore = Arc::clone(&keystore);
+    let interval = config.watch_interval;
+    thread::spawn(move || watch(&client, &watcher_storage, &watcher_keystore, interval));
+    eprintln!("scrowd listening on {}", config.addr);
+    runtime.block_on(serve(listener, router(config, storage, keystore)));
+    Ok(())
+}
+
+/// Serves `app` on `listener`, one request per connection.
+///
+/// Connections past [`MAX_CONNECTIONS`] are answered 503,
+/// and every connection is dropped after [`REQUEST_TIMEOUT`], however slow the client is.
+async fn serve(listener: TcpListener, app: Router) {
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let permit = Arc::clone(&connections).try_acquire_owned();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let io = TokioIo::new(stream);
+            let mut builder = http1::Builder::new();
+            builder.keep_alive(false);
+            // The client may be gone, or past its deadline, nothing is left to do then.
+            let _ = match permit {
+                Ok(_permit) => {
+                    let service = TowerToHyperService::new(app);
+                    timeout(REQUEST_TIMEOUT, builder.serve_connection(io, service)).await
+                }
+                Err(_) => {
+                    let service = service_fn(busy);
+                    timeout(REQUEST_TIMEOUT, builder.serve_connection(io, service)).await
+                }
+            };
+        });
+    }
+}
+
+/// Answers a connection past [`MAX_CONNECTIONS`].
+async fn busy(_request: hyper::Request<Incoming>) -> Result<Response, Infallible> {
+    let busy = Error::Http("Too many connections, retry later".to_string());
+    Ok(HttpResponse::error(StatusCode::SERVICE_UNAVAILABLE, &busy).into_response())
+}
+
+/// Refreshes the watched escrows of `storage` every `interval`, notifying the webhooks
+/// and the user, and notifies the user of the counterparty signatures once `keystore`
+/// unlocked the session store.
+///
+/// Escrows found at startup are not notified of the state they are already in,
+/// only of the timelock warnings already due.
+fn watch(
+    client: &EsploraClient,
+    storage: &impl Storage,
+    keystore: &Mutex<Keystore>,
+    interval: Duration,
+) {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("scrowd: the watcher could not start: {e}");
+            return;
+        }
+    };
+    let mut watcher = EscrowWatcher::new(DesktopNotifier);
+    let language = detect_language();
+    loop {
+        let webhooks = Webhooks::load(storage).unwrap_or_default();
+        let started = watcher.is_started();
+        let now = Timestamp::now();
+        for refreshed in runtime.block_on(watcher.refresh(client, storage, now, language)) {
+            let Refreshed {
+                watch,
+                previous,
+                status,
+                notified,
+            } = refreshed;
+            if started {
+                let failures = runtime.block_on(webhooks.notify(&watch, previous, status, now));
+                for (url, transition, e) in failures {
+                    eprintln!("scrowd: could not notify {url} of {transition}: {e}");
+                }
+            }
+            if let Err(e) = notified {
+                eprintln!(
+                    "scrowd: could not notify the user of {}: {e}",
+                    watch.funding_txid
+                );
+            }
+        }
+        if let Err(e) = runtime.block_on(refresh_funding(client, storage, keystore)) {
+            eprintln!("scrowd: could not refresh the escrow funding: {e}");
+        }
+        if let Err(e) = runtime.block_on(refresh_spends(client, storage, keystore)) {
+            eprintln!("scrowd: could not refresh the escrow spends: {e}");
+        }
+        let tip_height = runtime.block_on(get_block_height(client));
+        let keystore = keystore.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = watcher.notify_signatures(storage, &keystore, now, language) {
+            eprintln!("scrowd: could not notify the user of signatures: {e}");
+        }
+        if let Ok(sessions) = keystore.sessions(storage)
+            && let Err(e) = Session::expire_offers(&sessions, now)
+        {
+            eprintln!("scrowd: could not expire offers: {e}");
+        }
+        let mut sleep = interval;
+        match tip_height.and_then(|tip_height| {
+            watcher.notify_timelocks(storage, &keystore, tip_height, now, language)
+        }) {
+            // Wake up in time for the next warning rather than a whole interval late.
+            Ok(Some(next)) => {
+                sleep = sleep.min(Duration::from_secs(
+                    next.as_u64().saturating_sub(now.as_u64()),
+                ));
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("scrowd: could not warn the user of expiring timelocks: {e}"),
+        }
+        drop(keystore);
+        thread::sleep(sleep);
+    }
+}
+/// Records the transactions funding the agreed sessions in `storage`, and in their history,
+/// once `keystore` unlocked the session store, flagging under- and overfunded escrows
+/// and logging unconfirmed fundings that can still be reversed.
+///
+/// The keystore is not held while fetching from `client`.
+async fn refresh_funding(
+    client: &EsploraClient,
+    storage: &impl Storage,
+    keystore: &Mutex<Keystore>,
+) -> Result<(), Error> {
+    let lock = || keystore.lock().unwrap_or_else(PoisonError::into_inner);
+    let escrows = {
+        let keystore = lock();
+        let Ok(sessions) = keystore.sessions(storage) else {
+            return Ok(());
+        };
+        let mut escrows = Vec::new();
+        for id in Session::list(&sessions)? {
+            let session = Session::load(&sessions, &id)?;
+            if let Some(address) = session.and_then(|s| s.handshake.escrow_address().cloned()) {
+                escrows.push((id, address));
+            }
+        }
+        escrows
+    };
+    for (id, escrow_address) in escrows {
+        let txs = fetch_funding_txs(client, &escrow_address).await?;
+        let escrow_script = escrow_address.script_pubkey();
+        let mut risk = None;
+        for tx in &txs {
+            if tx
+                .output
+                .iter()
+                .any(|output| output.script_pubkey == escrow_script)
+                && let Some(found) = check_funding(client, tx).await?
+            {
+                risk.get_or_insert(found);
+            }
+        }
+        let keystore = lock();
+        let Ok(sessions) = keystore.sessions(storage) else {
+            return Ok(());
+        };
+        let Some(mut session) = Session::load(&sessions, &id)? else {
+            continue;
+        };
+        let recorded = session.clone();
+        let now = Timestamp::now();
+        for tx in &txs {
+            session.record_funding(tx)?;
+            let txid = tx.compute_txid();
+            let amount = session
+                .funding
+                .iter()
+                .flat_map(|funding| &funding.outputs)
+                .filter(|output| output.outpoint.txid == txid)
+                .map(|output| output.amount)
+                .sum::<Amount>();
+            if amount > Amount::ZERO {
+                session.record_funded(txid, amount, now);
+            }
+        }
+        if let Some(risk) = session.record_funding_risk(risk)
+            && recorded.funding_risk.as_ref() != Some(risk)
+        {
+            eprintln!("scrowd: session {id}: {}", risk.description());
+        }
+        if session != recorded {
+            session.save(&sessions)?;
+        }
+    }
+    Ok(())
+}
+
+/// Records the spends of the funded sessions' escrows in `storage`, and their confirmation
+/// in the sessions' history, once `keystore` unlocked the session store,
+/// reporting spends by transactions the sessions didn't sign.
+///
+/// The keystore is not held while fetching from `client`.
+async fn refresh_spends(
+    client: &EsploraClient,
+    storage: &impl Storage,
+    keystore: &Mutex<Keystore>,
+) -> Result<(), Error> {
+    let lock = || keystore.lock().unwrap_or_else(PoisonError::into_inner);
+    let chain = Settings::load(storage).unwrap_or_default().network;
+    let escrows = {
+        let keystore = lock();
+        let Ok(sessions) = keystore.sessions(storage) else {
+            return Ok(());
+        };
+        let mut escrows = Vec::new();
+        for id in Session::list(&sessions)? {
+            let Some(session) = Session::load(&sessions, &id)? else {
+                continue;
+            };
+            let funding = session.funding.as_ref().and_then(|f| f.outputs.first());
+            if let (Some(funding), Ok(config)) = (funding, session.escrow_config()) {
+                escrows.push((id, funding.outpoint.txid, config));
+            }
+        }
+        escrows
+    };
+    for (id, funding_txid, config) in escrows {
+        let profile = NetworkProfile::from(chain_of(config.network, chain));
+        let report = audit_escrow(client, funding_txid, &config, &profile).await?;
+        let Some(spend) = &report.spend else {
+            continue;
+        };
+        let keystore = lock();
+        let Ok(sessions) = keystore.sessions(storage) else {
+            return Ok(());
+        };
+        let Some(mut session) = Session::load(&sessions, &id)? else {
+            continue;
+        };
+        let recorded = session.clone();
+        if let Some(conflict) = session.record_spend(spend)
+            && recorded.conflict.as_ref() != Some(conflict)
+        {
+            eprintln!("scrowd: escrow {id}: {}", conflict.description());
+        }
+        let now = Timestamp::now();
+        session.record_state(now);
+        if let Some(height) = spend.confirmed_height {
+            session.record_confirmation(spend.txid, height, now);
+        }
+        if session != recorded {
+            session.save(&sessions)?;
+        }
+    }
+    Ok(())
+}
+
+/// State of the API handlers.
+#[derive(Debug)]
+struct Daemon<S> {
+    /// Configuration, for the API keys.
+    config: DaemonConfig,
+    /// Where the sessions and watched escrows are.
+    storage: S,
+    /// Unlocks the session store, shared with the watcher.
+    keystore: Arc<Mutex<Keystore>>,
+}
+
+impl<S: Storage> Daemon<S> {
+    /// The keystore, even if a handler panicked while holding it.
+    fn keystore(&self) -> MutexGuard<'_, Keystore> {
+        self.keystore.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Answers with `f` of the storage with the sessions decrypted,
+    /// or 423 until the session store is unlocked.
+    fn with_sessions(
+        &self,
+        f: impl FnOnce(&VaultStorage<'_, S>) -> Result<HttpResponse, Error>,
+    ) -> Result<HttpResponse, Error> {
+        let keystore = self.keystore();
+        if !keystore.sessions_unlocked() {
+            return Ok(HttpResponse::locked());
+        }
+        f(&keystore.sessions(&self.storage)?)
+    }
+}
+
+/// The [`Daemon`] state, as extracted by the handlers.
+type Shared<S> = State<Arc<Daemon<S>>>;
+
+/// The routes of the API over the state in `storage`, with the session store unlocked
+/// by `keystore`, see the [module documentation](self).
+fn router<S: Storage + Send + Sync + 'static>(
+    config: DaemonConfig,
+    storage: S,
+    keystore: Arc<Mutex<Keystore>>,
+) -> Router {
+    let daemon = Arc::new(Daemon {
+        config,
+        storage,
+        keystore,
+    });
+    let v1 = Router::new()
+        .route("/rpc", post(rpc))
+        .route("/{method}", post(call_method))
+        .route("/sessions", get(list_sessions::<S>))
+        .route(
+            "/sessions/{id}",
+            get(get_session::<S>).put(put_session::<S>),
+        )
+        .route("/sessions/{id}/history", get(session_history::<S>))
+        .route("/sessions/{id}/funding", get(session_funding::<S>))
+        .route("/escrows/{id}", get(get_escrow::<S>))
+        .route("/dashboard/{npub}", post(dashboard::<S>))
+        .route("/accounting/{npub}", get(accounting::<S>))
+        .route("/accounting/{npub}/csv", get(accounting_csv::<S>))
+        .route("/vault/unlock", post(unlock_vault::<S>))
+        .route("/vault/lock", post(lock_vault::<S>))
+        .route("/vault/passphrase", put(change_passphrase::<S>))
+        .route("/backup", post(backup::<S>))
+        .route("/restore", post(restore::<S>))
+        .route("/musig/nonce", post(musig_nonce::<S>))
+        .route("/musig/sign", post(musig_sign::<S>))
+        .route("/watched", get(list_watched::<S>))
+        .route(
+            "/watched/{txid}",
+            get(get_watched::<S>)
+                .put(put_watched::<S>)
+                .delete(delete_watched::<S>),
+        )
+        .route("/watched/{txid}/report", get(watched_report::<S>))
+        .route("/wallet/{npub}", get(wallet_coins::<S>))
+        .route("/wallet/sweep", post(sweep::<S>))
+        .route("/coins", get(list_coin_labels::<S>))
+        .route("/coins/{outpoint}", put(put_coin_label::<S>))
+        .route("/webhooks", get(list_webhooks::<S>).put(put_webhooks::<S>))
+        .route(
+            "/timelock-warnings",
+            get(get_timelock_warnings::<S>).put(put_timelock_warnings::<S>),
+        )
+        .route("/disputes", get(list_disputes::<S>).post(open_dispute::<S>))
+        .route("/disputes/{id}/ruling", put(rule_dispute::<S>))
+        .route("/templates/schema", get(template_schema))
+        .route("/diagnostics", get(diagnostics::<S>))
+        .route("/broadcast", post(broadcast::<S>))
+        .route("/broadcast/package", post(broadcast_package::<S>))
+        .route("/faucet", post(faucet::<S>))
+        .route("/payjoin/send", post(payjoin_send))
+        .route("/payjoin/receive", post(payjoin_receive::<S>))
+        .route("/price/{currency}", get(price::<S>))
+        .fallback(not_found)
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&daemon),
+            authorize::<S>,
+        ));
+    Router::new()
+        .route("/health", get(health))
+        .nest("/v1", v1)
+        .fallback(not_found)
+        .layer(DefaultBodyLimit::max(MAX_BODY_LEN))
+        .with_state(daemon)
+}
+
+/// A response with a JSON body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HttpResponse {
+    /// Status code.
+    status: StatusCode,
+    /// JSON body.
+    body: String,
+}
+
+impl HttpResponse {
+    /// A response of `status` with the JSON `body`.
+    fn json(status: StatusCode, body: &Value) -> Self {
+        Self {
+            status,
+            body: body.to_string(),
+        }
+    }
+
+    /// A 200 response with the serialized `value`.
+    fn ok(value: &impl serde::Serialize) -> Self {
+        match serialize(value) {
+            Ok(body) => Self {
+                status: StatusCode::OK,
+                body,
+            },
+            Err(e) => Self::error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+        }
+    }
+
+    /// A response of `status` with the [`ApiError`] of `error`.
+    fn error(status: StatusCode, error: &Error) -> Self {
+        Self::json(status, &json!({ "error": ApiError::from(error) }))
+    }
+
+    /// A 404 response.
+    fn not_found() -> Self {
+        Self::error(
+            StatusCode::NOT_FOUND,
+            &Error::WrongInputs("Not found".to_string()),
+        )
+    }
+
+    /// A 423 response, for the sessions until the session store is unlocked.
+    fn locked() -> Self {
+        Self::error(StatusCode::LOCKED, &crate::vault::locked())
+    }
+}
+
+impl IntoResponse for HttpResponse {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            [(header::CONTENT_TYPE, "application/json")],
+            self.body,
+        )
+            .into_response()
+    }
+}
+
+impl IntoResponse for Error {
+    /// A 500 response for storage failures, a 400 one otherwise.
+    fn into_response(self) -> Response {
+        let status = if matches!(self, Error::Storage(_)) {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::BAD_REQUEST
+        };
+        HttpResponse::error(status, &self).into_response()
+    }
+}
+
+/// Answers `GET /health`.
+async fn health() -> HttpResponse {
+    HttpResponse::json(StatusCode::OK, &json!({ "status": "ok" }))
+}
+
+/// Answers the requests without a route.
+async fn not_found() -> HttpResponse {
+    HttpResponse::not_found()
+}
+
+/// Lets `request` through if it carries one of the API keys, answers 401 otherwise.
+async fn authorize<S>(State(daemon): Shared<S>, request: Request, next: Next) -> Response {
+    if !daemon.config.authorized(request.headers()) {
+        let e = Error::WrongInputs("Missing or invalid API key".to_string());
+        return HttpResponse::error(StatusCode::UNAUTHORIZED, &e).into_response();
+    }
+    next.run(request).await
+}
+
+/// The request `body` as text.
+fn text(body: &Bytes) -> Result<&str, Error> {
+    std::str::from_utf8(body)
+        .map_err(|_| Error::WrongInputs("Malformed HTTP request: body is not UTF-8".to_string()))
+}
+
+/// Runs the JSON-RPC request `body`.
+async fn rpc(body: Bytes) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse {
+        status: StatusCode::OK,
+        body: handle_json(text(&body)?),
+    })
+}
+
+/// Calls the API `method` with the JSON `body` as parameters, none if the body is empty.
+async fn call_method(Path(method): Path<String>, body: Bytes) -> Result<HttpResponse, Error> {
+    let params = text(&body)?;
+    let mut request = json!({ "id": Value::Null, "method": method });
+    if !params.trim().is_empty() {
+        request["params"] = deserialize::<Value>(params)?;
+    }
+    Ok(HttpResponse {
+        status: StatusCode::OK,
+        body: handle_json(&request.to_string()),
+    })
+}
+
+/// Lists the [`SessionId`]s in storage.
+async fn list_sessions<S: Storage>(State(daemon): Shared<S>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::ok(&Session::list(&daemon.storage)?))
+}
+
+/// Loads the session `id`.
+async fn get_session<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(id): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.parse()?;
+    daemon.with_sessions(|sessions| {
+        let session = Session::load(sessions, &id)?;
+        Ok(session.map_or_else(HttpResponse::not_found, |session| {
+            HttpResponse::ok(&session)
+        }))
+    })
+}
+
+/// Answers the history of the session `id` as a plain text audit log,
+/// see [`Session::audit_log`].
+async fn session_history<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(id): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.parse()?;
+    daemon.with_sessions(|sessions| match Session::load(sessions, &id)? {
+        Some(session) => Ok(HttpResponse::json(
+            StatusCode::OK,
+            &json!({ "log": session.audit_log()? }),
+        )),
+        None => Ok(HttpResponse::not_found()),
+    })
+}
+
+/// Answers the confirmations of the funding of the session `id` out of the
+/// [`Session::min_confirmations`] before resolution, found by the Esplora backend,
+/// and whether the unconfirmed funding can still be reversed, see [`Session::is_at_risk`].
+async fn session_funding<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(id): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.parse()?;
+    let session = {
+        let keystore = daemon.keystore();
+        if !keystore.sessions_unlocked() {
+            return Ok(HttpResponse::locked());
+        }
+        Session::load(&keystore.sessions(&daemon.storage)?, &id)?
+    };
+    let Some(session) = session else {
+        return Ok(HttpResponse::not_found());
+    };
+    let funding_txid = session
+        .funding
+        .as_ref()
+        .and_then(|funding| funding.outputs.first())
+        .map(|output| output.outpoint.txid);
+    let confirmations = match funding_txid {
+        Some(txid) => {
+            let client = create_client(&daemon.config.esplora_url, &daemon.config.proxies)?;
+            get_confirmations(&client, &txid).await?
+        }
+        None => 0,
+    };
+    let status = match confirmations {
+        0 => WatchStatus::Unconfirmed,
+        confirmations => WatchStatus::Funded {
+            confirmations,
+            timelock_height: None,
+        },
+    };
+    let progress = session.funding_progress(status);
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({
+            "confirmations": progress.confirmations,
+            "required": progress.required,
+            "complete": progress.is_complete(),
+            "progress": progress.description(),
+            "at_risk": session.is_at_risk(),
+            "risk": session.funding_risk.as_ref().map(FundingRisk::description),
+        }),
+    ))
+}
+
+/// The [`EscrowRecord`]s of the completed escrows of `npub` among the sessions in storage,
+/// with the mining fee of the resolutions found by the Esplora backend,
+/// or `None` until the session store is unlocked.
+async fn escrow_records<S: Storage>(
+    daemon: &Daemon<S>,
+    npub: &Npub,
+) -> Result<Option<Vec<EscrowRecord>>, Error> {
+    let sessions = {
+        let keystore = daemon.keystore();
+        if !keystore.sessions_unlocked() {
+            return Ok(None);
+        }
+        let sessions = keystore.sessions(&daemon.storage)?;
+        let mut loaded = Vec::new();
+        for id in Session::list(&sessions)? {
+            loaded.extend(Session::load(&sessions, &id)?);
+        }
+        loaded
+    };
+    let client = create_client(&daemon.config.esplora_url, &daemon.config.proxies)?;
+    let mut records = Vec::new();
+    for session in &sessions {
+        // Sessions of other participants, or not completed yet, have no record.
+        let Ok(Some(record)) = EscrowRecord::new(session, npub, None, None) else {
+            continue;
+        };
+        let resolution = match record.resolution_txid {
+            Some(txid) => client.get_tx(&txid).await.ok().flatten(),
+            None => None,
+        };
+        records.push(match resolution {
+            Some(tx) => EscrowRecord::new(session, npub, Some(&tx), None)?.unwrap_or(record),
+            None => record,
+        });
+    }
+    Ok(Some(records))
+}
+
+/// Answers the page of the escrows of `npub` selected by the [`EscrowQuery`] JSON `body`.
+async fn dashboard<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(npub): Path<String>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let npub = NostrPublicKey::from(npub.parse::<Npub>()?);
+    let query: EscrowQuery = deserialize(text(&body)?)?;
+    daemon.with_sessions(|sessions| {
+        Ok(HttpResponse::ok(&list_escrows(
+            sessions,
+            &npub,
+            &query,
+            Timestamp::now(),
+        )?))
+    })
+}
+
+/// Answers the [`EscrowRecord`]s of the completed escrows of `npub`, see [`to_json`].
+async fn accounting<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(npub): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let Some(records) = escrow_records(&daemon, &npub.parse()?).await? else {
+        return Ok(HttpResponse::locked());
+    };
+    Ok(HttpResponse {
+        status: StatusCode::OK,
+        body: to_json(&records)?,
+    })
+}
+
+/// Answers the [`EscrowRecord`]s of the completed escrows of `npub` as CSV,
+/// see [`to_csv`].
+async fn accounting_csv<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(npub): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let Some(records) = escrow_records(&daemon, &npub.parse()?).await? else {
+        return Ok(HttpResponse::locked());
+    };
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "csv": to_csv(&records) }),
+    ))
+}
+
+/// Saves the session JSON `body` under `id`, which must be its [`SessionId`], encrypted.
+async fn put_session<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let id = id.parse::<SessionId>()?;
+    let session = Session::from_json(text(&body)?)?;
+    if session.id()? != id {
+        return Err(Error::WrongInputs(format!("Session is not {id}")));
+    }
+    daemon.with_sessions(|sessions| {
+        session.save(sessions)?;
+        Ok(HttpResponse::json(StatusCode::OK, &json!({ "id": id })))
+    })
+}
+
+/// Loads the sessions of the funded escrow `id`, merged.
+async fn get_escrow<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(id): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.parse()?;
+    daemon.with_sessions(|sessions| {
+        let session = Session::find_funded(sessions, &id)?;
+        Ok(session.map_or_else(HttpResponse::not_found, |session| {
+            HttpResponse::ok(&session)
+        }))
+    })
+}
+
+/// Body of the session store routes.
+#[derive(Deserialize)]
+struct Passphrase {
+    /// The passphrase of the session store.
+    passphrase: String,
+}
+
+/// Unlocks the session store with the passphrase of the JSON `body`,
+/// encrypting the sessions on first use.
+async fn unlock_vault<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let Passphrase { passphrase } = deserialize(text(&body)?)?;
+    daemon
+        .keystore()
+        .unlock_sessions(&daemon.storage, &passphrase)?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "unlocked": true }),
+    ))
+}
+
+/// Locks the session store.
+async fn lock_vault<S: Storage>(State(daemon): Shared<S>) -> HttpResponse {
+    daemon.keystore().lock_sessions();
+    HttpResponse::json(StatusCode::OK, &json!({ "unlocked": false }))
+}
+
+/// Protects the unlocked session store with the passphrase of the JSON `body` instead.
+async fn change_passphrase<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let Passphrase { passphrase } = deserialize(text(&body)?)?;
+    let keystore = daemon.keystore();
+    if !keystore.sessions_unlocked() {
+        return Ok(HttpResponse::locked());
+    }
+    keystore.change_passphrase(&daemon.storage, &passphrase)?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "unlocked": true }),
+    ))
+}
+
+/// Body of the backup route.
+#[derive(Deserialize)]
+struct BackupBody {
+    /// Nostr secret key the backup is encrypted to.
+    nsec: SecretNsec,
+}
+
+/// Body of the restore route.
+#[derive(Deserialize)]
+struct RestoreBody {
+    /// Nostr secret key the backup is encrypted to.
+    nsec: SecretNsec,
+    /// The encrypted backup.
+    backup: String,
+}
+
+/// Backs the state up, encrypted to the nsec of the [`BackupBody`] JSON `body`.
+///
+/// The daemon has no relays of its own, so the backup has none.
+async fn backup<S: Storage>(State(daemon): Shared<S>, body: Bytes) -> Result<HttpResponse, Error> {
+    let BackupBody { nsec } = deserialize(text(&body)?)?;
+    daemon.with_sessions(|sessions| {
+        let backup = Backup::collect(sessions, "")?.encrypt(&nsec)?;
+        Ok(HttpResponse::json(
+            StatusCode::OK,
+            &json!({ "backup": backup }),
+        ))
+    })
+}
+
+/// Restores the backup of the [`RestoreBody`] JSON `body`, keeping the local state,
+/// answering what was added.
+async fn restore<S: Storage>(State(daemon): Shared<S>, body: Bytes) -> Result<HttpResponse, Error> {
+    let RestoreBody { nsec, backup } = deserialize(text(&body)?)?;
+    let backup = Backup::decrypt(&backup, &nsec)?;
+    daemon.with_sessions(|sessions| {
+        let summary = backup.restore(sessions)?;
+        Ok(HttpResponse::json(
+            StatusCode::OK,
+            &json!({
+                "sessions": summary.sessions,
+                "watched": summary.watched,
+                "contacts": summary.contacts,
+            }),
+        ))
+    })
+}
+
+/// Body of the MuSig2 signing rounds of a cooperative key path close.
+#[derive(Deserialize)]
+struct MusigRound {
+    /// The escrow, which must have a key path, see [`EscrowConfig::has_key_path`].
+    config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    tx_hex: String,
+    /// Index of the input spending the escrow.
+    input_index: usize,
+    /// What the signer agreed to, checked before each round.
+    invariants: SigningInvariants,
+    /// Public nonces of both participants, in participant order, for the second round.
+    #[serde(default)]
+    nonces: Vec<PublicNonce>,
+    /// Signer's Nostr secret key.
+    nsec: SecretNsec,
+}
+
+impl MusigRound {
+    /// The key aggregation context and the message of the key path spend,
+    /// once the transaction satisfies the invariants.
+    fn prepare(&self) -> Result<(KeyAggContext, secp256k1::Message), Error> {
+        let tx = parse_tx_hex(&self.tx_hex)?;
+        self.invariants.check(&tx)?;
+        let message = key_spend_message(&tx, self.input_index, &self.invariants.prevouts)?;
+        Ok((self.config.key_agg()?, message))
+    }
+}
+
+/// First MuSig2 round of the [`MusigRound`] JSON `body`: commits to a nonce,
+/// answering the public nonce to share with the other participant.
+async fn musig_nonce<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let round = deserialize::<MusigRound>(text(&body)?)?;
+    let (context, message) = round.prepare()?;
+    daemon.with_sessions(|sessions| {
+        let public_nonce = commit_nonce(sessions, &round.nsec, &context, &message)?;
+        Ok(HttpResponse::json(
+            StatusCode::OK,
+            &json!({ "public_nonce": public_nonce }),
+        ))
+    })
+}
+
+/// Second MuSig2 round of the [`MusigRound`] JSON `body`: signs with the committed nonce,
+/// answering the partial signature to combine with `aggregate_key_spend`.
+async fn musig_sign<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let round = deserialize::<MusigRound>(text(&body)?)?;
+    let (context, message) = round.prepare()?;
+    daemon.with_sessions(|sessions| {
+        let partial_signature =
+            sign_with_stored_nonce(sessions, &round.nsec, &context, &round.nonces, &message)?;
+        Ok(HttpResponse::json(
+            StatusCode::OK,
+            &json!({ "partial_signature": partial_signature }),
+        ))
+    })
+}
+
+/// Lists the funding [`Txid`]s of the watched escrows.
+async fn list_watched<S: Storage>(State(daemon): Shared<S>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::ok(&WatchSession::list(&daemon.storage)?))
+}
+
+/// Loads the watched escrow funded by `txid`.
+async fn get_watched<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(txid): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let watch = WatchSession::load(&daemon.storage, &parse_txid(&txid)?)?;
+    Ok(watch.map_or_else(HttpResponse::not_found, |watch| HttpResponse::ok(&watch)))
+}
+
+/// Saves the watch session JSON `body` under `txid`, which must be its funding [`Txid`].
+async fn put_watched<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(txid): Path<String>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let txid = parse_txid(&txid)?;
+    let watch = deserialize::<WatchSession>(text(&body)?)?;
+    if watch.funding_txid != txid {
+        return Err(Error::WrongInputs(format!("Watched escrow is not {txid}")));
+    }
+    WatchSession::new(watch.config, txid, watch.label)?.save(&daemon.storage)?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "funding_txid": txid }),
+    ))
+}
+
+/// Audits the watched escrow funded by `txid` on the Esplora backend,
+/// answering its audit report.
+async fn watched_report<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(txid): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let Some(watch) = WatchSession::load(&daemon.storage, &parse_txid(&txid)?)? else {
+        return Ok(HttpResponse::not_found());
+    };
+    let settings = Settings::load(&daemon.storage)?;
+    let profile = NetworkProfile::from(chain_of(watch.config.network, settings.network));
+    let client = create_client(&daemon.config.esplora_url, &daemon.config.proxies)?;
+    let (report, status) = watch.refresh(&client, None, &profile).await?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "report": watch.audit_report(&report, status)? }),
+    ))
+}
+
+/// Stops watching the escrow funded by `txid`.
+async fn delete_watched<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(txid): Path<String>,
+) -> Result<HttpResponse, Error> {
+    WatchSession::remove(&daemon.storage, &parse_txid(&txid)?)?;
+    Ok(HttpResponse::json(StatusCode::OK, &json!({})))
+}
+
+/// Answers the published JSON Schema of escrow templates, see [`TEMPLATE_SCHEMA`].
+async fn template_schema() -> HttpResponse {
+    HttpResponse {
+        status: StatusCode::OK,
+        body: TEMPLATE_SCHEMA.to_string(),
+    }
+}
+
+/// The [`DiagnosticsBundle`] of the sessions in storage, without logs:
+/// the daemon's are on its standard error.
+///
+/// Encrypted sessions are only diagnosed once the session store is unlocked.
+async fn diagnostics<S: Storage>(State(daemon): Shared<S>) -> Result<HttpResponse, Error> {
+    let settings = Settings::load(&daemon.storage)?;
+    let network = NetworkDiagnostics::new(
+        settings.network,
+        &daemon.config.esplora_url,
+        &daemon.config.proxies,
+        &settings,
+    );
+    let keystore = daemon.keystore();
+    let bundle = match keystore.sessions(&daemon.storage) {
+        Ok(sessions) => DiagnosticsBundle::collect(&sessions, network, "", Timestamp::now())?,
+        Err(_) => DiagnosticsBundle::collect(&daemon.storage, network, "", Timestamp::now())?,
+    };
+    Ok(HttpResponse::ok(&bundle))
+}
+
+/// Body of the broadcast route.
+#[derive(Deserialize)]
+struct BroadcastBody {
+    /// Signed transaction, in hex.
+    tx_hex: String,
+    /// Session whose history records the broadcast, if any.
+    #[serde(default)]
+    session_id: Option<SessionId>,
+}
+
+/// Broadcasts the signed transaction of the [`BroadcastBody`] JSON `body`, answering its
+/// [`Txid`] and how each backend answered.
+///
+/// Recording the broadcast in a session requires the session store unlocked,
+/// so nothing is broadcast while it is locked.
+async fn broadcast<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let BroadcastBody { tx_hex, session_id } = deserialize(text(&body)?)?;
+    let tx = parse_tx_hex(&tx_hex)?;
+    if session_id.is_some() && !daemon.keystore().sessions_unlocked() {
+        return Ok(HttpResponse::locked());
+    }
+    let config = &daemon.config;
+    let report = match config.core_rpc() {
+        Some(core_rpc) => {
+            Broadcaster::new(vec![core_rpc], RetryPolicy::default())
+                .broadcast(&tx)
+                .await
+        }
+        None => {
+            let esplora = EsploraBackend::new(&config.esplora_url, &config.proxies)?;
+            Broadcaster::new(vec![esplora], RetryPolicy::default())
+                .broadcast(&tx)
+                .await
+        }
+    };
+    report.outcome()?;
+    if let Some(id) = session_id {
+        let keystore = daemon.keystore();
+        let sessions = keystore.sessions(&daemon.storage)?;
+        if let Some(mut session) = Session::load(&sessions, &id)? {
+            session.record_broadcast(tx.compute_txid(), Timestamp::now());
+            session.save(&sessions)?;
+        }
+    }
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "txid": tx.compute_txid(), "report": report.to_string() }),
+    ))
+}
+
+/// Lists the coins of the wallet of `npub` on the chain in the settings,
+/// with their labels and freezes.
+async fn wallet_coins<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(npub): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let network = Settings::load(&daemon.storage)?.network.network();
+    let address = npub.parse::<Npub>()?.address(network)?;
+    let client = create_client(&daemon.config.esplora_url, &daemon.config.proxies)?;
+    let control = CoinControl::load(&daemon.storage)?;
+    let coins = list_coins(&client, &address)
+        .await?
+        .into_iter()
+        .map(|coin| {
+            json!({
+                "outpoint": coin.outpoint,
+                "value": coin.prevout.value.to_sat(),
+                "confirmed": coin.confirmed,
+                "label": control.label(&coin.outpoint),
+                "frozen": control.is_frozen(&coin.outpoint),
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "address": address, "coins": coins }),
+    ))
+}
+
+/// Every labeled or frozen coin of the [`CoinControl`].
+async fn list_coin_labels<S: Storage>(State(daemon): Shared<S>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::ok(
+        &CoinControl::load(&daemon.storage)?.labels(),
+    ))
+}
+
+/// Body of the coin label route.
+#[derive(Deserialize)]
+struct CoinLabelBody {
+    /// Where the coin came from, removed if blank.
+    #[serde(default)]
+    label: String,
+    /// Whether the coin must never be selected.
+    #[serde(default)]
+    frozen: bool,
+}
+
+/// Labels and freezes the coin at `outpoint` as the [`CoinLabelBody`] JSON `body` says.
+async fn put_coin_label<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(outpoint): Path<String>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let outpoint = outpoint
+        .parse()
+        .map_err(|_| Error::WrongInputs(format!("Invalid outpoint {outpoint}")))?;
+    let CoinLabelBody { label, frozen } = deserialize(text(&body)?)?;
+    let mut control = CoinControl::load(&daemon.storage)?;
+    control.set_label(outpoint, &label);
+    control.set_frozen(outpoint, frozen);
+    control.save(&daemon.storage)?;
+    Ok(HttpResponse::json(StatusCode::OK, &json!({})))
+}
+
+/// Answers the disputes of the [`ArbitratorMode`], in the order they were opened.
+async fn list_disputes<S: Storage>(State(daemon): Shared<S>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::ok(
+        &ArbitratorMode::load(&daemon.storage)?.disputes(),
+    ))
+}
+
+/// Opens the dispute of the agreed [`Handshake`] JSON `body`, answering its record.
+async fn open_dispute<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let handshake: Handshake = deserialize(text(&body)?)?;
+    let mut mode = ArbitratorMode::load(&daemon.storage)?;
+    let record = mode.open(&handshake)?.clone();
+    mode.save(&daemon.storage)?;
+    Ok(HttpResponse::ok(&record))
+}
+
+/// Body of the dispute ruling route.
+#[derive(Deserialize)]
+struct RulingBody {
+    /// The buyer's share of the escrow, in basis points.
+    buyer_share_bps: u16,
+}
+
+/// Records the ruling of the [`RulingBody`] JSON `body` for the dispute of the session `id`.
+async fn rule_dispute<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let id = id.parse()?;
+    let RulingBody { buyer_share_bps } = deserialize(text(&body)?)?;
+    let mut mode = ArbitratorMode::load(&daemon.storage)?;
+    mode.rule(&id, buyer_share_bps)?;
+    mode.save(&daemon.storage)?;
+    Ok(HttpResponse::ok(&mode.get(&id)))
+}
+
+/// Answers the webhooks notified of the watched escrows' transitions, without their secrets.
+async fn list_webhooks<S: Storage>(State(daemon): Shared<S>) -> Result<HttpResponse, Error> {
+    let Webhooks {
+        webhooks,
+        confirmations,
+    } = Webhooks::load(&daemon.storage)?;
+    let webhooks = webhooks
+        .iter()
+        .map(|webhook| json!({ "url": webhook.url, "transitions": webhook.transitions }))
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "webhooks": webhooks, "confirmations": confirmations }),
+    ))
+}
+
+/// A webhook in a [`WebhooksBody`].
+#[derive(Deserialize)]
+struct WebhookBody {
+    /// URL the payloads are posted to.
+    url: String,
+    /// Secret shared with the receiver, keying the payload signatures.
+    secret: String,
+    /// Transitions to notify, all of them if empty.
+    #[serde(default)]
+    transitions: Vec<Transition>,
+}
+
+/// Answers how long before a dispute timelock expires the user is warned, in seconds.
+async fn get_timelock_warnings<S: Storage>(
+    State(daemon): Shared<S>,
+) -> Result<HttpResponse, Error> {
+    let thresholds = WarningThresholds::load(&daemon.storage)?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "thresholds": thresholds }),
+    ))
+}
+
+/// Body of the timelock warnings route.
+#[derive(Deserialize)]
+struct TimelockWarningsBody {
+    /// Seconds before the expiry to warn at, replacing the saved ones.
+    thresholds: WarningThresholds,
+}
+
+/// Replaces the timelock warnings with the ones of the [`TimelockWarningsBody`] JSON `body`,
+/// answering them as saved, largest first.
+async fn put_timelock_warnings<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let TimelockWarningsBody { thresholds } = deserialize(text(&body)?)?;
+    thresholds.save(&daemon.storage)?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "thresholds": thresholds }),
+    ))
+}
+
+/// Body of the webhooks route.
+#[derive(Deserialize)]
+struct WebhooksBody {
+    /// The webhooks, replacing the registered ones.
+    webhooks: Vec<WebhookBody>,
+    /// Confirmations a resolution needs to be confirmed, [`DEFAULT_CONFIRMATIONS`] if absent.
+    #[serde(default)]
+    confirmations: Option<u32>,
+}
+
+/// Replaces the webhooks with the ones of the [`WebhooksBody`] JSON `body`.
+async fn put_webhooks<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let WebhooksBody {
+        webhooks,
+        confirmations,
+    } = deserialize(text(&body)?)?;
+    let webhooks = webhooks
+        .into_iter()
+        .map(|body| {
+            Ok(Webhook {
+                transitions: body.transitions,
+                ..Webhook::new(&body.url, &body.secret)?
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    Webhooks {
+        webhooks,
+        confirmations: confirmations.unwrap_or(DEFAULT_CONFIRMATIONS),
+    }
+    .save(&daemon.storage)?;
+    Ok(HttpResponse::json(StatusCode::OK, &json!({})))
+}
+
+/// Body of the wallet sweep route.
+#[derive(Deserialize)]
+struct SweepBody {
+    /// Nostr secret key of the wallet.
+    nsec: SecretNsec,
+    /// Where the coins go.
+    destination: Address<NetworkUnchecked>,
+    /// Fee rate of the sweep, in sat/vB.
+    fee_rate: u64,
+}
+
+/// Sweeps every coin of the wallet of the [`SweepBody`] JSON `body` on the chain
+/// in the settings, answering the signed transaction to broadcast.
+async fn sweep<S: Storage>(State(daemon): Shared<S>, body: Bytes) -> Result<HttpResponse, Error> {
+    let SweepBody {
+        nsec,
+        destination,
+        fee_rate,
+    } = deserialize(text(&body)?)?;
+    let network = Settings::load(&daemon.storage)?.network.network();
+    let destination = destination.require_network(network)?;
+    let fee_rate = FeeRate::from_sat_per_vb(fee_rate)
+        .ok_or_else(|| Error::WrongInputs(format!("Invalid fee rate {fee_rate} sat/vB")))?;
+    let client = create_client(&daemon.config.esplora_url, &daemon.config.proxies)?;
+    let tx = sweep_wallet(&client, &nsec, &destination, fee_rate, network).await?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "tx_hex": serialize_hex(&tx), "txid": tx.compute_txid() }),
+    ))
+}
+
+/// Body of the faucet route.
+#[derive(Deserialize)]
+struct FaucetBody {
+    /// Whose address to fund.
+    npub: NostrPublicKey,
+    /// Amount to request, [`DEFAULT_FAUCET_AMOUNT`] by default.
+    #[serde(default, with = "bitcoin::amount::serde::as_sat::opt")]
+    amount: Option<Amount>,
+}
+
+/// Has the faucet of the chain in the settings fund the npub of the [`FaucetBody`]
+/// JSON `body`, answering the funded address and the faucet's [`Txid`].
+async fn faucet<S: Storage>(State(daemon): Shared<S>, body: Bytes) -> Result<HttpResponse, Error> {
+    let FaucetBody { npub, amount } = deserialize(text(&body)?)?;
+    let chain = Settings::load(&daemon.storage)?.network;
+    let mut faucet = Faucet::for_chain(chain)
+        .ok_or_else(|| Error::WrongInputs(format!("{chain} has no faucet")))?;
+    if let Some(url) = &daemon.config.faucet_url {
+        faucet = faucet.with_url(url);
+    }
+    let (address, txid) = faucet
+        .fund_npub(&npub, amount.unwrap_or(DEFAULT_FAUCET_AMOUNT))
+        .await?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "address": address, "txid": txid }),
+    ))
+}
+
+/// Body of the payjoin send route.
+#[derive(Deserialize)]
+struct PayjoinSendBody {
+    /// The signed original payment, in base64, see the `sign_payjoin_original` method.
+    original: String,
+    /// The payjoin request paid by the original.
+    uri: String,
+    /// Minimum fee rate of the payjoin, in sat/vB.
+    #[serde(default)]
+    min_fee_rate: Option<u64>,
+    /// Buyer's Nostr secret key, signing the payjoin.
+    nsec: SecretNsec,
+}
+
+/// Posts the original payment of the [`PayjoinSendBody`] JSON `body` to the seller's
+/// endpoint, answering the signed payjoin, or the original to broadcast instead
+/// with the error if the payjoin fails.
+async fn payjoin_send(body: Bytes) -> Result<HttpResponse, Error> {
+    let PayjoinSendBody {
+        original,
+        uri,
+        min_fee_rate,
+        nsec,
+    } = deserialize(text(&body)?)?;
+    let original = original
+        .trim()
+        .parse::<Psbt>()
+        .map_err(|e| Error::WrongInputs(format!("Invalid original transaction: {e}")))?;
+    let min_fee_rate = min_fee_rate
+        .map(|rate| {
+            FeeRate::from_sat_per_vb(rate)
+                .ok_or_else(|| Error::WrongInputs(format!("Invalid fee rate {rate} sat/vB")))
+        })
+        .transpose()?;
+    let params = PayjoinParams {
+        additional_fee: None,
+        min_fee_rate,
+    };
+    let sender = PayjoinSender::new(original, &uri.parse()?, params)?;
+    let (tx, error) = match sender.send(&nsec).await {
+        Ok(tx) => (tx, None),
+        Err(e) => (sender.fallback_tx(), Some(e.to_string())),
+    };
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({
+            "tx_hex": serialize_hex(&tx),
+            "txid": tx.compute_txid(),
+            "payjoin": error.is_none(),
+            "error": error,
+        }),
+    ))
+}
+
+/// Body of the payjoin receive route.
+#[derive(Deserialize)]
+struct PayjoinReceiveBody {
+    /// The agreed session, whose escrow the payjoin funds.
+    session: Session,
+    /// Body of the buyer's payjoin request, its base64 original payment.
+    original: String,
+    /// Query of the buyer's payjoin request.
+    query: String,
+    /// The seller's coins, paying its share of the escrow.
+    inputs: Vec<FundingInput>,
+    /// Where the change of the coins goes.
+    change_address: Address<NetworkUnchecked>,
+    /// Seller's Nostr secret key, owning the coins.
+    nsec: SecretNsec,
+}
+
+/// Answers the payjoin request of the [`PayjoinReceiveBody`] JSON `body` with the seller's
+/// signed proposal, once the original pays the buyer's share of the escrow and can be
+/// broadcast instead, with the original to broadcast if the buyer never does.
+async fn payjoin_receive<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let body: PayjoinReceiveBody = deserialize(text(&body)?)?;
+    body.session.check()?;
+    let escrow_address = body
+        .session
+        .handshake
+        .escrow_address()
+        .ok_or_else(|| Error::Protocol("Escrow is not agreed yet".to_string()))?;
+    let (amount_buyer, amount_seller) = body.session.handshake.amounts()?;
+    let receiver = PayjoinReceiver::new(&body.original, &body.query, escrow_address, amount_buyer)?;
+    let client = create_client(&daemon.config.esplora_url, &daemon.config.proxies)?;
+    receiver.check_broadcastable(&client).await?;
+    let proposal = receiver.contribute(
+        &body.inputs,
+        amount_seller,
+        &body.change_address.assume_checked(),
+        &body.nsec,
+    )?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({
+            "proposal": proposal.to_string(),
+            "fallback_tx_hex": serialize_hex(&receiver.fallback_tx()),
+        }),
+    ))
+}
+
+/// Fetches the [`Price`](crate::price::Price) of a bitcoin in `currency`
+/// from the configured providers.
+async fn price<S: Storage>(
+    State(daemon): Shared<S>,
+    Path(currency): Path<String>,
+) -> Result<HttpResponse, Error> {
+    let currency = currency.parse::<Currency>()?;
+    let price = fetch_price(&daemon.config.price_providers, currency, Timestamp::now()).await?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({ "price": price }),
+    ))
+}
+
+/// Body of the package broadcast route.
+#[derive(Deserialize)]
+struct PackageBody {
+    /// Unconfirmed parents in topological order, in hex.
+    parents: Vec<String>,
+    /// The child spending every parent, in hex.
+    child: String,
+    /// The outputs spent by the package from outside of it.
+    prevouts: Vec<(OutPoint, TxOut)>,
+}
+
+/// Submits the [`PackageBody`] JSON `body` through the Bitcoin Core node,
+/// within the fee rate limits of the [`Settings`], answering the package's [`Txid`]s and fee.
+async fn broadcast_package<S: Storage>(
+    State(daemon): Shared<S>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    let body = deserialize::<PackageBody>(text(&body)?)?;
+    let parents = body
+        .parents
+        .iter()
+        .map(|parent| parse_tx_hex(parent))
+        .collect::<Result<Vec<_>, _>>()?;
+    let settings = Settings::load(&daemon.storage)?;
+    let package = Package::new(
+        parents,
+        parse_tx_hex(&body.child)?,
+        &body.prevouts,
+        &settings.fee_rates,
+    )?;
+    let core_rpc = daemon.config.core_rpc().ok_or_else(|| {
+        Error::WrongInputs("Packages are submitted through SCROWD_CORE_RPC_URL".to_string())
+    })?;
+    core_rpc.submit_package(&package).await?;
+    Ok(HttpResponse::json(
+        StatusCode::OK,
+        &json!({
+            "txids": package.txids(),
+            "child": package.child().compute_txid(),
+            "fee": package.fee().to_sat(),
+            "fee_rate": package.fee_rate().to_sat_per_vb_ceil(),
+        }),
+    ))
+}
+
+/// Parses a [`Txid`] path segment.
+fn parse_txid(txid: &str) -> Result<Txid, Error> {
+    txid.parse()
+        .map_err(|_| Error::WrongInputs(format!("Invalid transaction ID {txid}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        Amount, Network, ScriptBuf, Transaction, TxIn, absolute, consensus, hashes::Hash,
+        hex::DisplayHex, transaction,
+    };
+
+    use super::*;
+    use crate::{
+        accounting::CSV_HEADER,
+        invariants::ApprovedOutputs,
+        protocol::{DEFAULT_OFFER_VALIDITY, Offer, offer},
+        scripts::{CURRENT_SCRIPT_TEMPLATE, ScriptTemplate},
+        storage::MemoryStorage,
+        tx::escrow_tx,
+        util::npub_to_address,
+        watch::WATCH_SESSION_VERSION,
+    };
+
+    #[tokio::test]
+    async fn daemon_routes() {
+        let config = DaemonConfig {
+            addr: DEFAULT_ADDR.parse().unwrap(),
+            api_keys: vec!["key-1".to_string(), "key-2".to_string()],
+            data_dir: DEFAULT_DATA_DIR.into(),
+            esplora_url: DEFAULT_ESPLORA_URL.to_string(),
+            proxies: ProxySettings::default(),
+            core_rpc_url: None,
+            core_rpc_credentials: Some(("user".to_string(), "key-1".to_string())),
+            faucet_url: None,
+            price_providers: Vec::new(),
+            watch_interval: DEFAULT_WATCH_INTERVAL,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve(
+            listener,
+            router(
+                config.clone(),
+                MemoryStorage::default(),
+                Arc::new(Mutex::new(Keystore::default())),
+            ),
+        ));
+        let client = reqwest::Client::new();
+        let request = async |method: &str, path: &str, key: Option<&str>, body: &str| {
+            let method = method.parse::<reqwest::Method>().unwrap();
+            let mut request = client
+                .request(method, format!("{url}{path}"))
+                .body(body.to_string());
+            if let Some(key) = key {
+                request = request.bearer_auth(key);
+            }
+            let response = request.send().await.unwrap();
+            let status = response.status().as_u16();
+            (status, response.text().await.unwrap())
+        };
+
+        assert_eq!(request("GET", "/health", None, "").await.0, 200);
+        let large = "a".repeat(MAX_BODY_LEN + 1);
+        assert_eq!(
+            request("POST", "/v1/rpc", Some("key-1"), &large).await.0,
+            413
+        );
+        assert_eq!(request("GET", "/v1/watched", None, "").await.0, 401);
+        assert_eq!(
+            request("GET", "/v1/watched", Some("key-3"), "").await.0,
+            401
+        );
+        assert_eq!(
+            request("GET", "/v1/unknown/route", Some("key-2"), "")
+                .await
+                .0,
+            404
+        );
+        assert!(!format!("{config:?}").contains("key-1"));
+
+        // API methods are reachable by name, with their parameters as body.
+        let escrow = EscrowConfig {
+            npub_1: SecretNsec::generate().public_key(),
+            npub_2: SecretNsec::generate().public_key(),
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let body = json!({ "config": escrow }).to_string();
+        let (_, response) = request("POST", "/v1/escrow_address", Some("key-1"), &body).await;
+        let response = deserialize::<Value>(&response).unwrap();
+        assert_eq!(
+            response["result"]["address"],
+            json!(escrow.address().unwrap())
+        );
+
+        // Watched escrows are stored, listed and removed.
+        let watch = WatchSession {
+            version: WATCH_SESSION_VERSION,
+            config: escrow,
+            funding_txid: Txid::all_zeros(),
+            label: "order 42".to_string(),
+        };
+        let path = format!("/v1/watched/{}", watch.funding_txid);
+        let body = serialize(&watch).unwrap();
+        assert_eq!(request("PUT", &path, Some("key-1"), &body).await.0, 200);
+        let other = format!("/v1/watched/{}", Txid::from_byte_array([1; 32]));
+        assert_eq!(request("PUT", &other, Some("key-1"), &body).await.0, 400);
+        let (_, listed) = request("GET", "/v1/watched", Some("key-1"), "").await;
+        assert_eq!(
+            deserialize::<Vec<Txid>>(&listed).unwrap(),
+            [watch.funding_txid]
+        );
+        let (_, loaded) = request("GET", &path, Some("key-1"), "").await;
+        assert_eq!(deserialize::<WatchSession>(&loaded).unwrap(), watch);
+        assert_eq!(request("DELETE", &path, Some("key-1"), "").await.0, 200);
+        assert_eq!(request("GET", &path, Some("key-1"), "").await.0, 404);
+        let report = format!("{path}/report");
+        assert_eq!(request("GET", &report, Some("key-1"), "").await.0, 404);
+        assert_eq!(
+            request("GET", "/v1/sessions", Some("key-1"), "").await.1,
+            "[]"
+        );
+
+        // Sessions are only read and written once the session store is unlocked.
+        let offerer = SecretNsec::generate();
+        let offer = Offer {
+            amount_seller: Amount::ZERO,
+            expires_at: Timestamp::now() + DEFAULT_OFFER_VALIDITY,
+            ..offer(offerer.public_key(), None)
+        };
+        let (handshake, _) = offerer
+            .with_nostr_secret_key(|secret_key| {
+                Handshake::offer(secret_key, offer, Timestamp::now())
+            })
+            .unwrap();
+        let session = Session::new(handshake);
+        let path = format!("/v1/sessions/{}", session.id().unwrap());
+        let body = serialize(&session).unwrap();
+        assert_eq!(request("PUT", &path, Some("key-1"), &body).await.0, 423);
+        let passphrase = json!({ "passphrase": "correct horse" }).to_string();
+        assert_eq!(
+            request("PUT", "/v1/vault/passphrase", Some("key-1"), &passphrase)
+                .await
+                .0,
+            423
+        );
+        assert_eq!(
+            request("POST", "/v1/vault/unlock", Some("key-1"), &passphrase)
+                .await
+                .0,
+            200
+        );
+        assert_eq!(request("PUT", &path, Some("key-1"), &body).await.0, 200);
+        let (_, loaded) = request("GET", &path, Some("key-1"), "").await;
+        assert_eq!(Session::from_json(&loaded).unwrap(), session);
+        let (status, history) = request("GET", &format!("{path}/history"), Some("key-1"), "").await;
+        assert_eq!(status, 200);
+        let log = deserialize::<Value>(&history).unwrap()["log"].clone();
+        assert!(
+            log.as_str()
+                .unwrap()
+                .starts_with("Escrow history of session")
+        );
+        let (status, funding) = request("GET", &format!("{path}/funding"), Some("key-1"), "").await;
+        assert_eq!(status, 200);
+        let funding = deserialize::<Value>(&funding).unwrap();
+        assert_eq!(funding["confirmations"], json!(0));
+        assert_eq!(funding["complete"], json!(false));
+        assert_eq!(funding["at_risk"], json!(false));
+        assert_eq!(funding["risk"], Value::Null);
+        // The dashboard lists the escrows of the npub.
+        let dashboard = format!("/v1/dashboard/{}", Npub::from(offerer.public_key()));
+        let (status, page) = request("POST", &dashboard, Some("key-1"), "{}").await;
+        assert_eq!(status, 200);
+        let page = deserialize::<Value>(&page).unwrap();
+        assert_eq!(page["listings"].as_array().unwrap().len(), 1);
+        let stranger = format!(
+            "/v1/dashboard/{}",
+            Npub::from(SecretNsec::generate().public_key())
+        );
+        let (_, page) = request("POST", &stranger, Some("key-1"), "{}").await;
+        assert_eq!(deserialize::<Value>(&page).unwrap()["listings"], json!([]));
+        // Only completed escrows are exported for accounting.
+        let accounting = format!("/v1/accounting/{}", Npub::from(offerer.public_key()));
+        let (status, records) = request("GET", &accounting, Some("key-1"), "").await;
+        assert_eq!(status, 200);
+        assert_eq!(deserialize::<Value>(&records).unwrap(), json!([]));
+        let (_, csv) = request("GET", &format!("{accounting}/csv"), Some("key-1"), "").await;
+        let csv = deserialize::<Value>(&csv).unwrap()["csv"].clone();
+        assert_eq!(csv, json!(format!("{}\n", CSV_HEADER.join(","))));
+
+        // Backups restore nothing already in the storage, and only with their nsec.
+        let nsec = SecretNsec::generate();
+        let nsec_json =
+            |nsec: &SecretNsec| json!(nsec.with_nostr_secret_key(|key| key.to_secret_hex()));
+        let body = json!({ "nsec": nsec_json(&nsec) }).to_string();
+        let (status, backup) = request("POST", "/v1/backup", Some("key-1"), &body).await;
+        assert_eq!(status, 200);
+        let backup = deserialize::<Value>(&backup).unwrap()["backup"].clone();
+        let body = json!({ "nsec": nsec_json(&nsec), "backup": backup }).to_string();
+        let (status, restored) = request("POST", "/v1/restore", Some("key-1"), &body).await;
+        assert_eq!(status, 200);
+        assert_eq!(deserialize::<Value>(&restored).unwrap()["sessions"], 0);
+        let other = json!({ "nsec": nsec_json(&SecretNsec::generate()), "backup": backup });
+        assert_eq!(
+            request("POST", "/v1/restore", Some("key-1"), &other.to_string())
+                .await
+                .0,
+            400
+        );
+        assert_eq!(
+            request("POST", "/v1/vault/lock", Some("key-1"), "").await.0,
+            200
+        );
+        assert_eq!(request("GET", &path, Some("key-1"), "").await.0, 423);
+        let wrong = json!({ "passphrase": "wrong horse" }).to_string();
+        assert_eq!(
+            request("POST", "/v1/vault/unlock", Some("key-1"), &wrong)
+                .await
+                .0,
+            400
+        );
+        assert_eq!(
+            request("POST", "/v1/vault/unlock", Some("key-1"), &passphrase)
+                .await
+                .0,
+            200
+        );
+        let changed = json!({ "passphrase": "battery staple" }).to_string();
+        assert_eq!(
+            request("PUT", "/v1/vault/passphrase", Some("key-1"), &changed)
+                .await
+                .0,
+            200
+        );
+        request("POST", "/v1/vault/lock", Some("key-1"), "").await;
+        assert_eq!(
+            request("POST", "/v1/vault/unlock", Some("key-1"), &changed)
+                .await
+                .0,
+            200
+        );
+        assert_eq!(request("GET", &path, Some("key-1"), "").await.0, 200);
+
+        // Both participants run the MuSig2 rounds of a key path close, which is then combined.
+        let nsecs = [SecretNsec::generate(), SecretNsec::generate()];
+        let escrow = EscrowConfig {
+            npub_1: nsecs[0].public_key(),
+            npub_2: nsecs[1].public_key(),
+            npub_arbitrator: Some(SecretNsec::generate().public_key()),
+            timelock_duration: Some(144),
+            network: Network::Regtest,
+            template: ScriptTemplate::V2,
+        };
+        let funding = OutPoint::new(Txid::all_zeros(), 0);
+        let tx = escrow_tx(
+            &escrow.npub_1,
+            &escrow.npub_2,
+            None,
+            Amount::from_sat(50_000),
+            Amount::from_sat(49_000),
+            funding.txid,
+            Amount::from_sat(1_000),
+            Network::Regtest,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let invariants = SigningInvariants {
+            funding_outpoints: vec![funding],
+            prevouts: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: escrow.address().unwrap().script_pubkey(),
+            }],
+            approved_outputs: ApprovedOutputs::Draft(tx.output.clone()),
+            max_fee: Amount::from_sat(10_000),
+            lock_time: absolute::LockTime::ZERO,
+            timelocks: vec![None],
+        };
+        let tx_hex = consensus::encode::serialize_hex(&tx);
+        let round = |nsec: &SecretNsec, nonces: &[Value]| {
+            json!({
+                "config": escrow,
+                "tx_hex": tx_hex,
+                "input_index": 0,
+                "invariants": invariants,
+                "nonces": nonces,
+                "nsec": nsec.with_nostr_secret_key(|secret_key| secret_key.to_secret_hex()),
+            })
+            .to_string()
+        };
+        let mut nonces = Vec::new();
+        for nsec in &nsecs {
+            let (status, nonce) =
+                request("POST", "/v1/musig/nonce", Some("key-1"), &round(nsec, &[])).await;
+            assert_eq!(status, 200);
+            nonces.push(deserialize::<Value>(&nonce).unwrap()["public_nonce"].clone());
+        }
+        let mut partial_signatures = Vec::new();
+        for nsec in &nsecs {
+            let (status, signed) = request(
+                "POST",
+                "/v1/musig/sign",
+                Some("key-1"),
+                &round(nsec, &nonces),
+            )
+            .await;
+            assert_eq!(status, 200);
+            partial_signatures
+                .push(deserialize::<Value>(&signed).unwrap()["partial_signature"].clone());
+        }
+        // Secret nonces are used once.
+        assert_eq!(
+            request(
+                "POST",
+                "/v1/musig/sign",
+                Some("key-1"),
+                &round(&nsecs[0], &nonces)
+            )
+            .await
+            .0,
+            400
+        );
+        let body = json!({
+            "config": escrow,
+            "tx_hex": tx_hex,
+            "input_index": 0,
+            "prevouts": invariants.prevouts,
+            "nonces": nonces,
+            "partial_signatures": partial_signatures,
+        })
+        .to_string();
+        let (_, response) = request("POST", "/v1/aggregate_key_spend", Some("key-1"), &body).await;
+        let response = deserialize::<Value>(&response).unwrap();
+        let signed = parse_tx_hex(response["result"]["tx_hex"].as_str().unwrap()).unwrap();
+        assert_eq!(signed.input[0].witness.len(), 1);
+        request("POST", "/v1/vault/lock", Some("key-1"), "").await;
+        assert_eq!(
+            request(
+                "POST",
+                "/v1/musig/nonce",
+                Some("key-1"),
+                &round(&nsecs[0], &[])
+            )
+            .await
+            .0,
+            423
+        );
+
+        // Diagnostics are sanitized and behind authentication too.
+        let (status, schema) = request("GET", "/v1/templates/schema", Some("key-1"), "").await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            deserialize::<Value>(&schema).unwrap()["$id"],
+            json!("https://satoshiescrow.com/schemas/escrow-template.schema.json")
+        );
+        assert_eq!(request("GET", "/v1/diagnostics", None, "").await.0, 401);
+        let (_, diagnostics) = request("GET", "/v1/diagnostics", Some("key-1"), "").await;
+        let diagnostics = deserialize::<Value>(&diagnostics).unwrap();
+        assert_eq!(
+            diagnostics["network"]["esplora_endpoint"],
+            json!(DEFAULT_ESPLORA_URL)
+        );
+        // The encrypted session is left out while the session store is locked.
+        assert_eq!(diagnostics["sessions"], json!([]));
+
+        // Only signed transactions are broadcast.
+        let body = json!({ "tx_hex": "00" }).to_string();
+        assert_eq!(
+            request("POST", "/v1/broadcast", Some("key-1"), &body)
+                .await
+                .0,
+            400
+        );
+        // Packages are checked before they are submitted, only through Bitcoin Core.
+        let parent = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let child = Transaction {
+            input: vec![TxIn {
+                previous_output: OutPoint::new(parent.compute_txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+            ..parent.clone()
+        };
+        let package = |prevouts: Vec<(OutPoint, TxOut)>| {
+            json!({
+                "parents": [consensus::serialize(&parent).to_lower_hex_string()],
+                "child": consensus::serialize(&child).to_lower_hex_string(),
+                "prevouts": prevouts,
+            })
+            .to_string()
+        };
+        let (status, response) = request(
+            "POST",
+            "/v1/broadcast/package",
+            Some("key-1"),
+            &package(vec![]),
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert!(response.contains("Missing the output"));
+        let prevout = TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        };
+        let (status, response) = request(
+            "POST",
+            "/v1/broadcast/package",
+            Some("key-1"),
+            &package(vec![(parent.input[0].previous_output, prevout)]),
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert!(response.contains("SCROWD_CORE_RPC_URL"));
+
+        // Only test chains have a faucet.
+        let body = json!({ "npub": SecretNsec::generate().public_key() }).to_string();
+        let (status, response) = request("POST", "/v1/faucet", Some("key-1"), &body).await;
+        assert_eq!(status, 400);
+        assert!(response.contains("has no faucet"));
+
+        // Prices are quoted in the supported currencies, by the configured providers.
+        let (status, response) = request("GET", "/v1/price/XAU", Some("key-1"), "").await;
+        assert_eq!(status, 400);
+        assert!(response.contains("Unsupported currency"));
+        let (status, response) = request("GET", "/v1/price/brl", Some("key-1"), "").await;
+        assert_eq!(status, 400);
+        assert!(response.contains("No price provider quotes BRL"));
+
+        // Coins are labeled and frozen in the coin control.
+        let outpoint = OutPoint::new(Txid::all_zeros(), 1);
+        let path = format!("/v1/coins/{outpoint}");
+        let body = json!({ "label": " exchange ", "frozen": true }).to_string();
+        assert_eq!(request("PUT", &path, Some("key-1"), &body).await.0, 200);
+        let (status, labels) = request("GET", "/v1/coins", Some("key-1"), "").await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            deserialize::<Value>(&labels).unwrap(),
+            json!([{ "outpoint": outpoint, "label": "exchange", "frozen": true }])
+        );
+        assert_eq!(request("PUT", &path, Some("key-1"), "{}").await.0, 200);
+        let (_, labels) = request("GET", "/v1/coins", Some("key-1"), "").await;
+        assert_eq!(labels, "[]");
+
+        // Arbitrators open disputes from the agreed handshake, and rule them.
+        let arbitrator = SecretNsec::generate();
+        let (_, offer_event) = offerer
+            .with_nostr_secret_key(|secret_key| {
+                Handshake::offer(
+                    secret_key,
+                    crate::protocol::offer(offerer.public_key(), Some(arbitrator.public_key())),
+                    Timestamp::now(),
+                )
+            })
+            .unwrap();
+        let (disputed, _) = SecretNsec::generate()
+            .with_nostr_secret_key(|secret_key| {
+                Handshake::accept(secret_key, &offer_event, None, Timestamp::now())
+            })
+            .unwrap();
+        let body = serialize(&disputed).unwrap();
+        let (status, record) = request("POST", "/v1/disputes", Some("key-1"), &body).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            deserialize::<Value>(&record).unwrap()["buyer_share_bps"],
+            Value::Null
+        );
+        assert_eq!(
+            request("POST", "/v1/disputes", Some("key-1"), &body)
+                .await
+                .0,
+            400
+        );
+        let ruling = format!("/v1/disputes/{}/ruling", disputed.session_id().unwrap());
+        let body = json!({ "buyer_share_bps": 7_500 }).to_string();
+        assert_eq!(request("PUT", &ruling, Some("key-1"), &body).await.0, 200);
+        let (_, disputes) = request("GET", "/v1/disputes", Some("key-1"), "").await;
+        let disputes = deserialize::<Value>(&disputes).unwrap();
+        assert_eq!(disputes.as_array().unwrap().len(), 1);
+        assert_eq!(disputes[0]["buyer_share_bps"], json!(7_500));
+
+        // Webhooks are registered with their secrets, which are never answered back.
+        let body = json!({
+            "webhooks": [
+                {
+                    "url": "https://merchant.example/hook",
+                    "secret": "hunter2",
+                    "transitions": ["funded"],
+                },
+            ],
+        });
+        let (status, _) = request("PUT", "/v1/webhooks", Some("key-1"), &body.to_string()).await;
+        assert_eq!(status, 200);
+        let (status, webhooks) = request("GET", "/v1/webhooks", Some("key-1"), "").await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            deserialize::<Value>(&webhooks).unwrap(),
+            json!({
+                "webhooks": [{ "url": "https://merchant.example/hook", "transitions": ["funded"] }],
+                "confirmations": 6,
+            })
+        );
+        let body =
+            json!({ "webhooks": [{ "url": "ftp://merchant.example", "secret": "hunter2" }] });
+        let (status, _) = request("PUT", "/v1/webhooks", Some("key-1"), &body.to_string()).await;
+        assert_eq!(status, 400);
+
+        // Timelock warnings are saved largest first, always warning at the expiry.
+        let (status, _) = request(
+            "PUT",
+            "/v1/timelock-warnings",
+            Some("key-1"),
+            &json!({ "thresholds": [3_600, 86_400] }).to_string(),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let (status, warnings) = request("GET", "/v1/timelock-warnings", Some("key-1"), "").await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            deserialize::<Value>(&warnings).unwrap(),
+            json!({ "thresholds": [86_400, 3_600, 0] })
+        );
+
+        let body = json!({
+            "nsec": nsec_json(&nsec),
+            "destination": npub_to_address(&nsec.public_key(), Network::Regtest).unwrap(),
+            "fee_rate": 1,
+        });
+        let (status, response) =
+            request("POST", "/v1/wallet/sweep", Some("key-1"), &body.to_string()).await;
+        assert_eq!(status, 400);
+        assert!(response.contains("Invalid Bitcoin address"));
+
+        // Payjoins fund agreed escrows from valid originals.
+        let body = json!({
+            "original": "cHNidP8=",
+            "uri": "bitcoin:bcrt1qsomething?pj=https://seller.example/pj",
+            "nsec": nsec_json(&nsec),
+        });
+        let (status, response) =
+            request("POST", "/v1/payjoin/send", Some("key-1"), &body.to_string()).await;
+        assert_eq!(status, 400);
+        assert!(response.contains("Invalid original transaction"));
+        let body = json!({
+            "session": session,
+            "original": "cHNidP8=",
+            "query": "v=1",
+            "inputs": [],
+            "change_address": "bcrt1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqc8gma6",
+            "nsec": nsec_json(&nsec),
+        });
+        let (status, response) = request(
+            "POST",
+            "/v1/payjoin/receive",
+            Some("key-1"),
+            &body.to_string(),
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert!(response.contains("not agreed"));
+    }
+}
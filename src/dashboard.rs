@@ -0,0 +1,478 @@
+//! Listing of the escrows persisted in [`Storage`], for the dashboard.
+//!
+//! Power sellers run dozens of escrows at once. [`list_escrows`] loads every persisted
+//! [`Session`] as an [`EscrowListing`] from the user's point of view, and an [`EscrowQuery`]
+//! filters them by state, counterparty, network, amount and age, sorts them,
+//! and returns the requested [`EscrowPage`].
+
+use std::time::Duration;
+
+use bitcoin::{Amount, Network};
+use nostr::{Timestamp, key::PublicKey as NostrPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accounting::{Outcome, resolution_of},
+    error::Error,
+    protocol::{Handshake, Role, Session, SessionId},
+    storage::Storage,
+};
+
+/// Most listings in an [`EscrowPage`], so a dashboard never renders every escrow at once.
+pub(crate) const MAX_PAGE_SIZE: usize = 100;
+
+/// Where an escrow stands, as shown on the dashboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EscrowState {
+    /// Offer published, waiting for an acceptance.
+    Offered,
+    /// Offer expired before being accepted.
+    Expired,
+    /// Both parties agreed, the escrow is not funded yet.
+    AwaitingFunding,
+    /// The escrow holds its funds.
+    Funded,
+    /// The escrow was resolved, collaboratively or by the arbitrator.
+    Resolved,
+    /// A participant cancelled the escrow.
+    Cancelled,
+    /// The escrow was spent by a transaction nobody signed for in the session.
+    Conflict,
+}
+
+impl EscrowState {
+    /// The state of the escrow of `session` at `now`.
+    pub(crate) fn of(session: &Session, now: Timestamp) -> Self {
+        if session.conflict.is_some() {
+            return EscrowState::Conflict;
+        }
+        match resolution_of(session) {
+            Some((Outcome::Collaborative | Outcome::Arbitrated, ..)) => {
+                return EscrowState::Resolved;
+            }
+            Some((Outcome::Cancelled, ..)) => return EscrowState::Cancelled,
+            Some((Outcome::Conflict, ..)) => return EscrowState::Conflict,
+            None => {}
+        }
+        if session.is_cancelled() {
+            return EscrowState::Cancelled;
+        }
+        match &session.handshake {
+            Handshake::Offered { offer, .. } if !offer.is_expired(now) => EscrowState::Offered,
+            Handshake::Offered { .. } | Handshake::Expired { .. } => EscrowState::Expired,
+            Handshake::Agreed { .. } if session.funding.is_some() => EscrowState::Funded,
+            Handshake::Agreed { .. } => EscrowState::AwaitingFunding,
+        }
+    }
+
+    /// Whether the escrow still needs the user's attention.
+    pub(crate) fn is_open(self) -> bool {
+        matches!(
+            self,
+            EscrowState::Offered | EscrowState::AwaitingFunding | EscrowState::Funded
+        )
+    }
+}
+
+/// An escrow as listed on the dashboard, from the point of view of one participant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct EscrowListing {
+    /// Negotiation of the escrow.
+    pub(crate) session_id: SessionId,
+    /// Where the escrow stands.
+    pub(crate) state: EscrowState,
+    /// The user's role in the escrow.
+    pub(crate) role: Role,
+    /// The other participant, unknown while an open offer waits for an acceptance.
+    pub(crate) counterparty: Option<NostrPublicKey>,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+    /// Total escrow amount of both participants.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+    /// When the session first recorded something, if it did.
+    pub(crate) created_at: Option<Timestamp>,
+}
+
+impl EscrowListing {
+    /// The listing of the escrow of `session` for the participant `npub` at `now`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `npub` is not a participant of the escrow.
+    pub(crate) fn new(
+        session: &Session,
+        npub: &NostrPublicKey,
+        now: Timestamp,
+    ) -> Result<Self, Error> {
+        let offer = session.handshake.negotiated_offer();
+        let (role, counterparty) = match &session.handshake {
+            Handshake::Agreed { acceptance, .. } => {
+                let (buyer, seller) = offer.participants(&acceptance.acceptor);
+                if npub == buyer {
+                    (Role::Buyer, Some(*seller))
+                } else if npub == seller {
+                    (Role::Seller, Some(*buyer))
+                } else {
+                    return Err(not_participant(npub));
+                }
+            }
+            Handshake::Offered { .. } | Handshake::Expired { .. } => {
+                if *npub == offer.offerer {
+                    (offer.role, offer.counterparty)
+                } else if offer.counterparty == Some(*npub) {
+                    let role = match offer.role {
+                        Role::Buyer => Role::Seller,
+                        Role::Seller => Role::Buyer,
+                    };
+                    (role, Some(offer.offerer))
+                } else {
+                    return Err(not_participant(npub));
+                }
+            }
+        };
+        let (amount_buyer, amount_seller) = session.handshake.amounts()?;
+        Ok(Self {
+            session_id: session.id()?,
+            state: EscrowState::of(session, now),
+            role,
+            counterparty,
+            network: offer.network,
+            amount: amount_buyer
+                .checked_add(amount_seller)
+                .ok_or(Error::Rounding)?,
+            created_at: session.history().entries().first().map(|entry| entry.at),
+        })
+    }
+
+    /// Age of the escrow at `now`, if its creation is known.
+    pub(crate) fn age(&self, now: Timestamp) -> Option<Duration> {
+        let created_at = self.created_at?;
+        Some(Duration::from_secs(
+            now.as_u64().saturating_sub(created_at.as_u64()),
+        ))
+    }
+}
+
+/// The error of `npub` not being a participant of an escrow.
+fn not_participant(npub: &NostrPublicKey) -> Error {
+    Error::WrongInputs(format!("{npub} is not a participant of the escrow"))
+}
+
+/// What [`EscrowListing`]s are sorted by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EscrowSort {
+    /// Newest first, escrows of unknown age last.
+    #[default]
+    Newest,
+    /// Oldest first, escrows of unknown age last.
+    Oldest,
+    /// Largest amount first.
+    Largest,
+    /// Smallest amount first.
+    Smallest,
+    /// By [`EscrowState`], open escrows first, then newest first.
+    State,
+}
+
+/// Filters, order and page of the escrows to list.
+///
+/// Unset filters match every escrow.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct EscrowQuery {
+    /// States to list, every state if empty.
+    pub(crate) states: Vec<EscrowState>,
+    /// Only escrows with this counterparty.
+    pub(crate) counterparty: Option<NostrPublicKey>,
+    /// Only escrows on this network.
+    pub(crate) network: Option<Network>,
+    /// Only escrows of at least this amount.
+    #[serde(with = "bitcoin::amount::serde::as_sat::opt")]
+    pub(crate) min_amount: Option<Amount>,
+    /// Only escrows of at most this amount.
+    #[serde(with = "bitcoin::amount::serde::as_sat::opt")]
+    pub(crate) max_amount: Option<Amount>,
+    /// Only escrows at least this old, excluding the ones of unknown age.
+    pub(crate) min_age: Option<Duration>,
+    /// Only escrows at most this old, excluding the ones of unknown age.
+    pub(crate) max_age: Option<Duration>,
+    /// Order of the listings.
+    pub(crate) sort: EscrowSort,
+    /// Listings to skip, for pagination.
+    pub(crate) offset: usize,
+    /// Most listings to return, at most [`MAX_PAGE_SIZE`], [`MAX_PAGE_SIZE`] if zero.
+    pub(crate) limit: usize,
+}
+
+/// A page of the listings matching an [`EscrowQuery`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct EscrowPage {
+    /// Listings of the page, in the requested order.
+    pub(crate) listings: Vec<EscrowListing>,
+    /// Listings matching the query over all pages.
+    pub(crate) total: usize,
+    /// Offset of the next page, if any.
+    pub(crate) next_offset: Option<usize>,
+}
+
+impl EscrowQuery {
+    /// Whether `listing` passes the filters at `now`.
+    pub(crate) fn matches(&self, listing: &EscrowListing, now: Timestamp) -> bool {
+        let age = listing.age(now);
+        (self.states.is_empty() || self.states.contains(&listing.state))
+            && self
+                .counterparty
+                .is_none_or(|npub| listing.counterparty == Some(npub))
+            && self
+                .network
+                .is_none_or(|network| listing.network == network)
+            && self.min_amount.is_none_or(|min| listing.amount >= min)
+            && self.max_amount.is_none_or(|max| listing.amount <= max)
+            && self
+                .min_age
+                .is_none_or(|min| age.is_some_and(|age| age >= min))
+            && self
+                .max_age
+                .is_none_or(|max| age.is_some_and(|age| age <= max))
+    }
+
+    /// Filters, sorts and paginates `listings` at `now`.
+    ///
+    /// Listings comparing equal keep the order of their [`SessionId`]s,
+    /// so pages never overlap.
+    pub(crate) fn apply(&self, listings: Vec<EscrowListing>, now: Timestamp) -> EscrowPage {
+        let mut listings = listings
+            .into_iter()
+            .filter(|listing| self.matches(listing, now))
+            .collect::<Vec<_>>();
+        listings.sort_by(|a, b| {
+            // `None` sorts first, so only oldest first has to move unknown ages last.
+            let newest = || b.created_at.cmp(&a.created_at);
+            let oldest = || {
+                a.created_at
+                    .is_none()
+                    .cmp(&b.created_at.is_none())
+                    .then(a.created_at.cmp(&b.created_at))
+            };
+            match self.sort {
+                EscrowSort::Newest => newest(),
+                EscrowSort::Oldest => oldest(),
+                EscrowSort::Largest => b.amount.cmp(&a.amount),
+                EscrowSort::Smallest => a.amount.cmp(&b.amount),
+                EscrowSort::State => b
+                    .state
+                    .is_open()
+                    .cmp(&a.state.is_open())
+                    .then(a.state.cmp(&b.state))
+                    .then_with(newest),
+            }
+            .then(a.session_id.cmp(&b.session_id))
+        });
+        let total = listings.len();
+        let limit = match self.limit {
+            0 => MAX_PAGE_SIZE,
+            limit => limit.min(MAX_PAGE_SIZE),
+        };
+        let listings = listings
+            .into_iter()
+            .skip(self.offset)
+            .take(limit)
+            .collect::<Vec<_>>();
+        let end = self.offset.saturating_add(listings.len());
+        EscrowPage {
+            listings,
+            total,
+            next_offset: (end < total).then_some(end),
+        }
+    }
+}
+
+/// Lists the escrows persisted in `storage` in which `npub` participates, as
+/// selected by `query` at `now`.
+///
+/// Sessions of other identities sharing the storage are skipped.
+///
+/// # Errors
+///
+/// Errors if a session can't be loaded, such as when the vault is locked.
+pub(crate) fn list_escrows(
+    storage: &impl Storage,
+    npub: &NostrPublicKey,
+    query: &EscrowQuery,
+    now: Timestamp,
+) -> Result<EscrowPage, Error> {
+    let mut listings = Vec::new();
+    for id in Session::list(storage)? {
+        let Some(session) = Session::load(storage, &id)? else {
+            continue;
+        };
+        if let Ok(listing) = EscrowListing::new(&session, npub, now) {
+            listings.push(listing);
+        }
+    }
+    Ok(query.apply(listings, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{OutPoint, Txid, hashes::Hash};
+    use nostr::Keys;
+
+    use super::*;
+    use crate::{
+        funding::{Funding, FundingOutput},
+        history::HistoryEvent,
+        protocol::{OFFER_KIND, Offer},
+        storage::MemoryStorage,
+    };
+
+    /// Offers stay open past the queries, made a few days after sending them.
+    const OFFER_VALIDITY: Duration = Duration::from_secs(7 * 86_400);
+
+    fn offer(offerer: &Keys, amount: u64, network: Network, now: Timestamp) -> Offer {
+        Offer {
+            network,
+            amount_buyer: Amount::from_sat(amount),
+            amount_seller: Amount::ZERO,
+            expires_at: now + OFFER_VALIDITY,
+            ..crate::protocol::offer(offerer.public_key(), None)
+        }
+    }
+
+    #[test]
+    fn dashboard_queries() {
+        let seller = Keys::generate();
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let now = Timestamp::from(1_700_000_000);
+        let storage = MemoryStorage::default();
+
+        // An open offer, an escrow awaiting funding and a funded one, sent a day apart.
+        let (open, open_event) = Handshake::offer(
+            seller.secret_key(),
+            offer(&seller, 10_000, Network::Regtest, now),
+            now,
+        )
+        .unwrap();
+        let mut sessions = vec![(Session::new(open), open_event.id)];
+        for (buyer, amount, network) in [
+            (&alice, 50_000, Network::Regtest),
+            (&bob, 200_000, Network::Signet),
+        ] {
+            let (_, event) = Handshake::offer(
+                seller.secret_key(),
+                offer(&seller, amount, network, now),
+                now,
+            )
+            .unwrap();
+            let (agreed, _) = Handshake::accept(buyer.secret_key(), &event, None, now).unwrap();
+            sessions.push((Session::new(agreed), event.id));
+        }
+        sessions[2].0.funding = Some(Funding {
+            expected: Amount::from_sat(200_000),
+            outputs: vec![FundingOutput {
+                outpoint: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                amount: Amount::from_sat(200_000),
+            }],
+        });
+        for (days, (session, event_id)) in sessions.iter_mut().enumerate() {
+            let sent = HistoryEvent::MessageSent {
+                event_id: *event_id,
+                kind: OFFER_KIND,
+            };
+            session
+                .history
+                .record(sent, now + Duration::from_secs(86_400 * days as u64));
+            session.save(&storage).unwrap();
+        }
+        let now = now + Duration::from_secs(86_400 * 3);
+
+        let list = |query: &EscrowQuery| {
+            list_escrows(&storage, &seller.public_key(), query, now)
+                .unwrap()
+                .listings
+                .iter()
+                .map(|listing| listing.amount.to_sat())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(list(&EscrowQuery::default()), [200_000, 50_000, 10_000]);
+        let states = |states: &[EscrowState]| EscrowQuery {
+            states: states.to_vec(),
+            ..EscrowQuery::default()
+        };
+        assert_eq!(list(&states(&[EscrowState::Offered])), [10_000]);
+        assert_eq!(list(&states(&[EscrowState::Funded])), [200_000]);
+        assert_eq!(
+            list(&EscrowQuery {
+                counterparty: Some(alice.public_key()),
+                ..EscrowQuery::default()
+            }),
+            [50_000]
+        );
+        assert_eq!(
+            list(&EscrowQuery {
+                network: Some(Network::Regtest),
+                sort: EscrowSort::Smallest,
+                ..EscrowQuery::default()
+            }),
+            [10_000, 50_000]
+        );
+        assert_eq!(
+            list(&EscrowQuery {
+                min_amount: Some(Amount::from_sat(20_000)),
+                max_amount: Some(Amount::from_sat(100_000)),
+                ..EscrowQuery::default()
+            }),
+            [50_000]
+        );
+        assert_eq!(
+            list(&EscrowQuery {
+                min_age: Some(Duration::from_secs(86_400 * 2)),
+                sort: EscrowSort::Oldest,
+                ..EscrowQuery::default()
+            }),
+            [10_000, 50_000]
+        );
+
+        // Pages don't overlap and tell where the next one starts.
+        let query = EscrowQuery {
+            sort: EscrowSort::Largest,
+            limit: 2,
+            ..EscrowQuery::default()
+        };
+        let page = list_escrows(&storage, &seller.public_key(), &query, now).unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.next_offset, Some(2));
+        let next = EscrowQuery { offset: 2, ..query };
+        let page = list_escrows(&storage, &seller.public_key(), &next, now).unwrap();
+        assert_eq!(page.listings[0].amount, Amount::from_sat(10_000));
+        assert_eq!(page.next_offset, None);
+
+        // Escrows of someone else are not listed, and offers eventually expire.
+        let stranger = Keys::generate().public_key();
+        assert_eq!(
+            list_escrows(&storage, &stranger, &EscrowQuery::default(), now)
+                .unwrap()
+                .total,
+            0
+        );
+        let listing = EscrowListing::new(&sessions[1].0, &alice.public_key(), now).unwrap();
+        assert_eq!(listing.role, Role::Buyer);
+        assert_eq!(listing.counterparty, Some(seller.public_key()));
+        let later = now + OFFER_VALIDITY;
+        assert_eq!(list(&states(&[EscrowState::Offered])), [10_000]);
+        assert_eq!(
+            list_escrows(
+                &storage,
+                &seller.public_key(),
+                &states(&[EscrowState::Expired]),
+                later
+            )
+            .unwrap()
+            .total,
+            1
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Arbitrator decision records.
+//!
+//! When an arbitrator co-signs a resolution, they also send a [`Decision`] event
+//! stating who gets what and why, tagged with the [`SessionId`] of the escrow
+//! and gift wrapped to the participants.
+//! Participants receive it with [`receive_decision`] and check it against the resolution
+//! transaction with [`Decision::verify`], keeping a record independent of the chain.
+use bitcoin::{Amount, Transaction, Txid};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{
+    Event, EventBuilder, Filter, Keys, Kind, Tag, UnsignedEvent,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use secp256k1::schnorr;
+use serde::{Deserialize, Serialize};
+
+#[cfg(debug_assertions)]
+use crate::logging::session_span;
+use crate::{
+    accounts::Keystore,
+    canonical,
+    error::Error,
+    invariants::SigningInvariants,
+    protocol::{Handshake, Offer, Role, SessionId, check_rumor, check_session_tag},
+    scripts::EscrowScript,
+    secret::SecretNsec,
+    sign::sign_escrow_tx,
+    util::npub_to_address,
+};
+#[cfg(feature = "serde-types")]
+use crate::{gift_wrap::receive_wrapped, protocol::Session};
+
+/// Version of the [`Decision`] message.
+pub(crate) const DECISION_VERSION: u8 = 1;
+
+/// Nostr event kind of a [`Decision`].
+pub(crate) const DECISION_KIND: u16 = 8_385;
+
+/// What a participant gets from a resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Award {
+    /// Participant's role in the escrow.
+    pub(crate) role: Role,
+    /// Participant's Nostr public key.
+    pub(crate) npub: NostrPublicKey,
+    /// Amount paid to the participant's `npub`-derived address.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount: Amount,
+}
+
+/// An arbitrator's signed statement of how a disputed escrow was resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Decision {
+    /// Message version, see [`DECISION_VERSION`].
+    pub(crate) version: u8,
+    /// Negotiation of the resolved escrow.
+    pub(crate) session_id: SessionId,
+    /// Arbitrator's Nostr public key.
+    pub(crate) arbitrator: NostrPublicKey,
+    /// ID of the resolution transaction.
+    pub(crate) txid: Txid,
+    /// What each participant gets, buyer first.
+    pub(crate) awards: Vec<Award>,
+    /// Why the arbitrator decided so.
+    pub(crate) reason: String,
+}
+
+impl Decision {
+    /// Records the decision of resolving the agreed `handshake` with `tx`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow is not agreed, has no arbitrator,
+    /// or `tx` pays anyone else than the participants.
+    pub(crate) fn new(
+        handshake: &Handshake,
+        tx: &Transaction,
+        reason: impl Into<String>,
+    ) -> Result<Self, Error> {
+        let (offer, acceptor) = agreed(handshake)?;
+        Ok(Self {
+            version: DECISION_VERSION,
+            session_id: offer.session_id()?,
+            arbitrator: offer
+                .arbitrator
+                .ok_or_else(|| Error::Protocol("Escrow has no arbitrator".to_string()))?,
+            txid: tx.compute_txid(),
+            awards: awards(offer, acceptor, tx)?,
+            reason: reason.into(),
+        })
+    }
+
+    /// Verifies that the decision describes the resolution of `handshake` with `tx`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the decision is for another escrow, arbitrator or transaction,
+    /// or misstates what the participants get.
+    pub(crate) fn verify(&self, handshake: &Handshake, tx: &Transaction) -> Result<(), Error> {
+        if self.version != DECISION_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported decision version {}",
+                self.version
+            )));
+        }
+        let (offer, acceptor) = agreed(handshake)?;
+        if self.session_id != offer.session_id()? {
+            return Err(Error::Protocol(
+                "Decision is for another session".to_string(),
+            ));
+        }
+        if Some(self.arbitrator) != offer.arbitrator {
+            return Err(Error::Protocol(
+                "Decision is not from the escrow's arbitrator".to_string(),
+            ));
+        }
+        if self.txid != tx.compute_txid() || self.awards != awards(offer, acceptor, tx)? {
+            return Err(Error::Protocol(
+                "Decision does not match the resolution transaction".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Builds and signs the decision [`Event`], addressed to the participants.
+    pub(crate) fn to_event(&self, nsec: &NostrSecretKey) -> Result<Event, Error> {
+        let keys = Keys::new(nsec.clone());
+        if keys.public_key() != self.arbitrator {
+            return Err(Error::Protocol(
+                "Decision must be signed by the arbitrator".to_string(),
+            ));
+        }
+        let mut tags = vec![Tag::identifier(self.session_id.to_string())];
+        tags.extend(self.awards.iter().map(|award| Tag::public_key(award.npub)));
+        Ok(
+            EventBuilder::new(Kind::Custom(DECISION_KIND), canonical::encode(self)?)
+                .tags(tags)
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses a decision rumor, checking it is from the arbitrator it names.
+    ///
+    /// The decision still has to be verified against its escrow with [`Decision::verify`].
+    pub(crate) fn from_rumor(rumor: &UnsignedEvent) -> Result<Self, Error> {
+        check_rumor(rumor, DECISION_KIND)?;
+        let decision: Decision = canonical::decode(&rumor.content)?;
+        if decision.arbitrator != rumor.pubkey {
+            return Err(Error::Protocol(
+                "Decision is not from the arbitrator".to_string(),
+            ));
+        }
+        check_session_tag(&rumor.tags, &decision.session_id)?;
+        Ok(decision)
+    }
+
+    /// The [`Filter`] of the decisions of `arbitrator` in the negotiation `session_id`.
+    pub(crate) fn filter(session_id: &SessionId, arbitrator: &NostrPublicKey) -> Filter {
+        Filter::new()
+            .kind(Kind::Custom(DECISION_KIND))
+            .author(*arbitrator)
+            .identifier(session_id.to_string())
+    }
+}
+
+/// Signs the arbitrator's part of the resolution `tx` of `handshake`
+/// and records the [`Decision`] behind it.
+///
+/// Input `index` is signed through the `escrow_script` leaf shared with the participant
+/// the arbitrator sides with.
+/// Returns the signature and the decision [`Event`] to publish.
+///
+/// # Errors
+///
+/// Errors if `nsec` is not the arbitrator's, `escrow_script` is not an arbitrator leaf
+/// or `tx` breaks the signing `invariants`.
+pub(crate) fn arbitrate(
+    handshake: &Handshake,
+    tx: &Transaction,
+    index: usize,
+    invariants: &SigningInvariants,
+    escrow_script: EscrowScript,
+    nsec: &NostrSecretKey,
+    reason: impl Into<String>,
+) -> Result<(schnorr::Signature, Event), Error> {
+    if escrow_script == EscrowScript::A {
+        return Err(Error::WrongInputs(
+            "Arbitrators sign through leaf B or C".to_string(),
+        ));
+    }
+    let decision = Decision::new(handshake, tx, reason)?;
+    let event = decision.to_event(nsec)?;
+    let (offer, acceptor) = agreed(handshake)?;
+    let (npub_buyer, npub_seller) = offer.participants(acceptor);
+    let mut keystore = Keystore::default();
+    let arbitrator = keystore.unlock(SecretNsec::from(nsec.clone()));
+    let signature = sign_escrow_tx(
+        tx,
+        index,
+        &keystore,
+        &arbitrator,
+        npub_buyer,
+        npub_seller,
+        offer.arbitrator.as_ref(),
+        offer.timelock_duration,
+        invariants,
+        escrow_script,
+    )?;
+    #[cfg(debug_assertions)]
+    session_span(&decision.session_id)
+        .in_scope(|| trace!(txid = %decision.txid, awards = ?decision.awards, "arbitrated"));
+    Ok((signature, event))
+}
+
+/// Receives the [`Decision`] of the arbitrator of `session` from `gift_wraps` addressed
+/// to `nsec`, as fetched from the relays with [`filter`](crate::gift_wrap::filter).
+///
+/// Rumors that fail to parse are skipped, and the newest decision the session didn't
+/// receive yet is returned.
+#[cfg(feature = "serde-types")]
+pub(crate) fn receive_decision(
+    session: &mut Session,
+    gift_wraps: &[Event],
+    nsec: &SecretNsec,
+) -> Result<Option<Decision>, Error> {
+    let (offer, _) = agreed(&session.handshake)?;
+    let Some(arbitrator) = offer.arbitrator else {
+        return Ok(None);
+    };
+    let filter = Decision::filter(&offer.session_id()?, &arbitrator);
+    Ok(
+        receive_wrapped(gift_wraps, nsec, &filter, &mut session.received)
+            .iter()
+            .rev()
+            .find_map(|rumor| Decision::from_rumor(rumor).ok()),
+    )
+}
+
+/// The offer and acceptor of an agreed `handshake`.
+pub(crate) fn agreed(handshake: &Handshake) -> Result<(&Offer, &NostrPublicKey), Error> {
+    match handshake {
+        Handshake::Agreed {
+            offer, acceptance, ..
+        } => Ok((offer, &acceptance.acceptor)),
+        Handshake::Offered { .. } | Handshake::Expired { .. } => {
+            Err(Error::Protocol("Escrow is not agreed yet".to_string()))
+        }
+    }
+}
+
+/// What each participant of the escrow gets from `tx`, buyer first.
+fn awards(offer: &Offer, acceptor: &NostrPublicKey, tx: &Transaction) -> Result<Vec<Award>, Error> {
+    let (npub_buyer, npub_seller) = offer.participants(acceptor);
+    let mut paid = 0;
+    let mut awards = Vec::with_capacity(2);
+    for (role, npub) in [(Role::Buyer, npub_buyer), (Role::Seller, npub_seller)] {
+        let script_pubkey = npub_to_address(npub, offer.network)?.script_pubkey();
+        let outputs = tx
+            .output
+            .iter()
+            .filter(|output| output.script_pubkey == script_pubkey);
+        paid += outputs.clone().count();
+        awards.push(Award {
+            role,
+            npub: *npub,
+            amount: outputs.map(|output| output.value).sum(),
+        });
+    }
+    if paid != tx.output.len() {
+        return Err(Error::Protocol(
+            "Resolution pays someone else than the participants".to_string(),
+        ));
+    }
+    Ok(awards)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{OutPoint, Sequence, TxIn, TxOut, Witness, absolute, transaction};
+    use nostr::Timestamp;
+
+    use crate::{
+        gift_wrap::{unwrap, wrap},
+        protocol::offer,
+    };
+
+    use super::*;
+
+    #[test]
+    fn arbitrator_decision() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let network = bitcoin::Network::Regtest;
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let (_, offer_event) =
+            Handshake::offer(keys_a.secret_key(), offer, Timestamp::now()).unwrap();
+        let (handshake, _) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, Timestamp::now()).unwrap();
+
+        // The arbitrator sides with the buyer, B.
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Default::default(),
+                sequence: Sequence::from_height(144),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(109_000),
+                script_pubkey: npub_to_address(&keys_b.public_key(), network)
+                    .unwrap()
+                    .script_pubkey(),
+            }],
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(110_000),
+            script_pubkey: handshake.escrow_address().unwrap().script_pubkey(),
+        }];
+        let invariants = SigningInvariants::of_local_tx(&tx, prevouts, vec![Some(144)]);
+        assert!(
+            arbitrate(
+                &handshake,
+                &tx,
+                0,
+                &invariants,
+                EscrowScript::B,
+                keys_b.secret_key(),
+                "Goods never shipped",
+            )
+            .is_err()
+        );
+        let (_, event) = arbitrate(
+            &handshake,
+            &tx,
+            0,
+            &invariants,
+            EscrowScript::B,
+            keys_arbitrator.secret_key(),
+            "Goods never shipped",
+        )
+        .unwrap();
+
+        let gift_wrap = wrap(
+            &event,
+            &SecretNsec::from(keys_arbitrator.secret_key().clone()),
+            &keys_b.public_key(),
+            Timestamp::now(),
+        )
+        .unwrap();
+        let decision = Decision::from_rumor(
+            &unwrap(&gift_wrap, &SecretNsec::from(keys_b.secret_key().clone())).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(decision.reason, "Goods never shipped");
+        assert_eq!(decision.awards[0].npub, keys_b.public_key());
+        assert_eq!(decision.awards[0].amount, Amount::from_sat(109_000));
+        assert_eq!(decision.awards[1].amount, Amount::ZERO);
+        decision.verify(&handshake, &tx).unwrap();
+
+        // The decision is received once per session.
+        #[cfg(feature = "serde-types")]
+        {
+            let nsec_b = SecretNsec::from(keys_b.secret_key().clone());
+            let mut session = Session::new(handshake.clone());
+            let gift_wraps = [gift_wrap.clone(), gift_wrap.clone()];
+            assert_eq!(
+                receive_decision(&mut session, &gift_wraps, &nsec_b).unwrap(),
+                Some(decision.clone())
+            );
+            assert_eq!(
+                receive_decision(&mut session, &gift_wraps, &nsec_b).unwrap(),
+                None
+            );
+        }
+
+        // A decision misstating the resolution is rejected.
+        let mut other_tx = tx.clone();
+        other_tx.output[0].script_pubkey = npub_to_address(&keys_a.public_key(), network)
+            .unwrap()
+            .script_pubkey();
+        assert!(decision.verify(&handshake, &other_tx).is_err());
+        assert!(Decision::new(&handshake, &other_tx, "").is_ok());
+        other_tx.output[0].script_pubkey = handshake.escrow_address().unwrap().script_pubkey();
+        assert!(Decision::new(&handshake, &other_tx, "").is_err());
+    }
+}
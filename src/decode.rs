@@ -0,0 +1,453 @@
+//! Decoding of raw transactions, for inspection before signing or broadcasting.
+//!
+//! Witness items of Taproot inputs are labeled:
+//! script path spends hold the signatures, the leaf script and the control block,
+//! and each signature is matched with the key it signs for in the leaf script.
+
+use bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxOut, Txid, Weight,
+    absolute, consensus,
+    hex::DisplayHex,
+    opcodes::all::OP_CSV,
+    script::Instruction,
+    taproot::{ControlBlock, LeafVersion, Signature as TaprootSignature, TAPROOT_ANNEX_PREFIX},
+    transaction::Version,
+};
+use nostr::key::PublicKey as NostrPublicKey;
+
+use crate::{
+    error::Error,
+    i18n::{Language, tr, tr_args},
+    keys::Npub,
+};
+
+/// A labeled item of an input witness.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum WitnessItem {
+    /// Schnorr signature.
+    Signature {
+        signature: TaprootSignature,
+        /// The key it signs for in the leaf script, [`None`] for key path spends.
+        signer: Option<NostrPublicKey>,
+    },
+    /// Leaf script of a script path spend.
+    LeafScript {
+        script: ScriptBuf,
+        /// Keys checked by the script, in script order.
+        keys: Vec<NostrPublicKey>,
+        /// Relative timelock in blocks, if the script has one.
+        timelock: Option<u32>,
+    },
+    /// Control block of a script path spend.
+    ControlBlock(ControlBlock),
+    /// Annex.
+    Annex(Vec<u8>),
+    /// Anything else, such as the witness of a non-Taproot input.
+    Unknown(Vec<u8>),
+}
+
+impl WitnessItem {
+    /// Human-readable label of the item, in `language`.
+    pub(crate) fn label(&self, language: Language) -> String {
+        match self {
+            WitnessItem::Signature {
+                signer: Some(signer),
+                ..
+            } => tr_args(
+                language,
+                "witness-signature",
+                &[("signer", &Npub::from(*signer))],
+            ),
+            WitnessItem::Signature { signer: None, .. } => tr(language, "witness-key-path"),
+            WitnessItem::LeafScript {
+                timelock: Some(timelock),
+                ..
+            } => tr_args(language, "witness-leaf-timelocked", &[("blocks", timelock)]),
+            WitnessItem::LeafScript { timelock: None, .. } => tr(language, "witness-leaf"),
+            WitnessItem::ControlBlock(control_block) => tr_args(
+                language,
+                "witness-control-block",
+                &[("depth", &control_block.merkle_branch.len())],
+            ),
+            WitnessItem::Annex(_) => tr(language, "witness-annex"),
+            WitnessItem::Unknown(_) => tr(language, "witness-unknown"),
+        }
+    }
+
+    /// The item's value: scripts in ASM, everything else in hex.
+    pub(crate) fn value(&self) -> String {
+        match self {
+            WitnessItem::Signature { signature, .. } => signature.to_vec().to_lower_hex_string(),
+            WitnessItem::LeafScript { script, .. } => script.to_asm_string(),
+            WitnessItem::ControlBlock(control_block) => {
+                control_block.serialize().to_lower_hex_string()
+            }
+            WitnessItem::Annex(bytes) | WitnessItem::Unknown(bytes) => bytes.to_lower_hex_string(),
+        }
+    }
+}
+
+/// A decoded transaction input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DecodedInput {
+    pub(crate) previous_output: OutPoint,
+    pub(crate) sequence: Sequence,
+    /// The spent output, if known.
+    pub(crate) prevout: Option<TxOut>,
+    pub(crate) witness: Vec<WitnessItem>,
+}
+
+/// A decoded transaction output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DecodedOutput {
+    pub(crate) value: Amount,
+    pub(crate) script_pubkey: ScriptBuf,
+    /// Address of `script_pubkey`, if it has one.
+    pub(crate) address: Option<Address>,
+}
+
+/// A decoded [`Transaction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DecodedTx {
+    pub(crate) txid: Txid,
+    pub(crate) version: Version,
+    pub(crate) lock_time: absolute::LockTime,
+    pub(crate) weight: Weight,
+    pub(crate) inputs: Vec<DecodedInput>,
+    pub(crate) outputs: Vec<DecodedOutput>,
+    /// Fee paid, known only with the spent outputs.
+    pub(crate) fee: Option<Amount>,
+}
+
+impl DecodedTx {
+    /// Virtual size, in vbytes.
+    pub(crate) fn vsize(&self) -> u64 {
+        self.weight.to_vbytes_ceil()
+    }
+
+    /// Fee rate in sats per vbyte, if the fee is known.
+    pub(crate) fn fee_rate(&self) -> Option<f64> {
+        self.fee
+            .map(|fee| fee.to_sat() as f64 / self.vsize() as f64)
+    }
+}
+
+/// Parses a raw [`Transaction`] in hex.
+pub(crate) fn parse_tx_hex(tx_hex: &str) -> Result<Transaction, Error> {
+    Ok(consensus::encode::deserialize_hex(tx_hex.trim())?)
+}
+
+/// Decodes a [`Transaction`], deriving output addresses for `network`.
+///
+/// `prevouts` must hold the outputs spent by every input of `tx`, in input order;
+/// without them, input amounts and the fee are unknown.
+///
+/// # Errors
+///
+/// Errors if `prevouts` doesn't match the inputs or the outputs spend more than the inputs.
+pub(crate) fn decode_tx(
+    tx: &Transaction,
+    network: Network,
+    prevouts: Option<&[TxOut]>,
+) -> Result<DecodedTx, Error> {
+    let prevouts_len = prevouts.map_or(tx.input.len(), <[TxOut]>::len);
+    if prevouts_len != tx.input.len() {
+        return Err(Error::WrongInputs(format!(
+            "Expected {} prevouts, got {prevouts_len}",
+            tx.input.len()
+        )));
+    }
+
+    let inputs = tx
+        .input
+        .iter()
+        .enumerate()
+        .map(|(index, input)| DecodedInput {
+            previous_output: input.previous_output,
+            sequence: input.sequence,
+            prevout: prevouts.map(|prevouts| prevouts[index].clone()),
+            witness: decode_witness(
+                &input.witness.to_vec(),
+                prevouts.is_none_or(|prevouts| prevouts[index].script_pubkey.is_p2tr()),
+            ),
+        })
+        .collect();
+    let outputs = tx
+        .output
+        .iter()
+        .map(|output| DecodedOutput {
+            value: output.value,
+            script_pubkey: output.script_pubkey.clone(),
+            address: Address::from_script(&output.script_pubkey, network).ok(),
+        })
+        .collect();
+
+    let fee = match prevouts {
+        Some(prevouts) => {
+            let input_value = prevouts.iter().map(|prevout| prevout.value).sum::<Amount>();
+            let output_value = tx.output.iter().map(|output| output.value).sum::<Amount>();
+            let fee = input_value.checked_sub(output_value).ok_or_else(|| {
+                Error::WrongInputs(format!(
+                    "Outputs of {output_value} exceed inputs of {input_value}"
+                ))
+            })?;
+            Some(fee)
+        }
+        None => None,
+    };
+
+    Ok(DecodedTx {
+        txid: tx.compute_txid(),
+        version: tx.version,
+        lock_time: tx.lock_time,
+        weight: tx.weight(),
+        inputs,
+        outputs,
+        fee,
+    })
+}
+
+/// Labels the items of a witness, assuming a Taproot spend unless `is_taproot` is `false`.
+///
+/// Witnesses that don't parse as Taproot spends are left [`WitnessItem::Unknown`].
+fn decode_witness(items: &[Vec<u8>], is_taproot: bool) -> Vec<WitnessItem> {
+    let unknown = || {
+        items
+            .iter()
+            .cloned()
+            .map(WitnessItem::Unknown)
+            .collect::<Vec<_>>()
+    };
+    if !is_taproot {
+        return unknown();
+    }
+
+    let (items, annex) = match items.split_last() {
+        Some((last, rest)) if !rest.is_empty() && last.first() == Some(&TAPROOT_ANNEX_PREFIX) => {
+            (rest, Some(WitnessItem::Annex(last.clone())))
+        }
+        _ => (items, None),
+    };
+
+    let mut decoded = match items {
+        // Key path spend.
+        [signature] => match TaprootSignature::from_slice(signature) {
+            Ok(signature) => vec![WitnessItem::Signature {
+                signature,
+                signer: None,
+            }],
+            Err(_) => return unknown(),
+        },
+        // Script path spend: `<sig_n> ... <sig_1> <script> <control block>`.
+        [signatures @ .., script, control_block] => {
+            let Ok(control_block) = ControlBlock::decode(control_block) else {
+                return unknown();
+            };
+            if control_block.leaf_version != LeafVersion::TapScript {
+                return unknown();
+            }
+            let script = ScriptBuf::from_bytes(script.clone());
+            let (keys, timelock) = leaf_keys(&script);
+            // Scripts check the last key against the top of the stack,
+            // so signatures are in reverse script order.
+            let mut signers = keys.iter().rev();
+            let mut decoded = Vec::with_capacity(signatures.len() + 2);
+            for signature in signatures {
+                match TaprootSignature::from_slice(signature) {
+                    Ok(signature) => decoded.push(WitnessItem::Signature {
+                        signature,
+                        signer: signers.next().copied(),
+                    }),
+                    Err(_) => decoded.push(WitnessItem::Unknown(signature.clone())),
+                }
+            }
+            decoded.push(WitnessItem::LeafScript {
+                script,
+                keys,
+                timelock,
+            });
+            decoded.push(WitnessItem::ControlBlock(control_block));
+            decoded
+        }
+        [] => return unknown(),
+    };
+    decoded.extend(annex);
+    decoded
+}
+
+/// The keys pushed by a leaf script, in script order,
+/// and its relative timelock in blocks, if it has one.
+fn leaf_keys(script: &ScriptBuf) -> (Vec<NostrPublicKey>, Option<u32>) {
+    let mut keys = Vec::new();
+    let mut timelock = None;
+    let mut previous = None;
+    for instruction in script.instructions().flatten() {
+        match instruction {
+            Instruction::PushBytes(bytes) if bytes.len() == 32 => {
+                if let Ok(key) = NostrPublicKey::from_slice(bytes.as_bytes()) {
+                    keys.push(key);
+                }
+            }
+            Instruction::Op(OP_CSV) => {
+                timelock = previous
+                    .and_then(|previous: Instruction<'_>| previous.script_num())
+                    .and_then(|blocks| u32::try_from(blocks).ok());
+            }
+            _ => {}
+        }
+        previous = Some(instruction);
+    }
+    (keys, timelock)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Witness, hashes::Hash};
+
+    use super::*;
+    use crate::{
+        accounts::Keystore,
+        invariants::SigningInvariants,
+        scripts::{CURRENT_SCRIPT_TEMPLATE, EscrowConfig, EscrowScript},
+        secret::SecretNsec,
+        sign::{combine_signatures, sign_escrow_tx},
+        tx::escrow_tx,
+    };
+
+    #[test]
+    fn decode_escrow_spend() {
+        let nsec_1 = SecretNsec::generate();
+        let npub_1 = nsec_1.public_key();
+        let npub_2 = SecretNsec::generate().public_key();
+        let nsec_arb = SecretNsec::generate();
+        let npub_arb = nsec_arb.public_key();
+        let config = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: Some(npub_arb),
+            timelock_duration: Some(144),
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: config.address().unwrap().script_pubkey(),
+        }];
+        let unsigned = escrow_tx(
+            &npub_1,
+            &npub_2,
+            Some(144),
+            Amount::from_sat(50_000),
+            Amount::from_sat(50_000),
+            Txid::all_zeros(),
+            Amount::from_sat(1_000),
+            Network::Regtest,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let invariants =
+            SigningInvariants::of_local_tx(&unsigned, prevouts.clone(), vec![Some(144)]);
+        let mut keystore = Keystore::default();
+        let account_1 = keystore.unlock(nsec_1);
+        let account_arb = keystore.unlock(nsec_arb);
+        let sign = |account| {
+            sign_escrow_tx(
+                &unsigned,
+                0,
+                &keystore,
+                account,
+                &npub_1,
+                &npub_2,
+                Some(&npub_arb),
+                Some(144),
+                &invariants,
+                EscrowScript::B,
+            )
+            .unwrap()
+        };
+        let sig_1 = sign(&account_1);
+        let sig_arb = sign(&account_arb);
+        let script = config.script(EscrowScript::B).unwrap();
+        let signed = combine_signatures(
+            unsigned.clone(),
+            0,
+            vec![&sig_1, &sig_arb],
+            &script,
+            &config.spend_info().unwrap(),
+        )
+        .unwrap();
+
+        let tx_hex = consensus::encode::serialize_hex(&signed);
+        let decoded = decode_tx(
+            &parse_tx_hex(&tx_hex).unwrap(),
+            Network::Regtest,
+            Some(&prevouts),
+        )
+        .unwrap();
+        assert_eq!(decoded.txid, signed.compute_txid());
+        assert_eq!(decoded.fee, Some(Amount::from_sat(1_000)));
+        assert!(decoded.fee_rate().unwrap() > 1.0);
+        assert_eq!(decoded.outputs.len(), 2);
+        assert!(
+            decoded
+                .outputs
+                .iter()
+                .all(|output| output.address.is_some())
+        );
+
+        let witness = &decoded.inputs[0].witness;
+        assert_eq!(witness.len(), 4);
+        assert!(matches!(
+            witness[0],
+            WitnessItem::Signature { signer: Some(signer), .. } if signer == npub_1
+        ));
+        assert!(matches!(
+            witness[1],
+            WitnessItem::Signature { signer: Some(signer), .. } if signer == npub_arb
+        ));
+        assert_eq!(
+            witness[2],
+            WitnessItem::LeafScript {
+                script,
+                keys: vec![npub_arb, npub_1],
+                timelock: Some(144),
+            }
+        );
+        assert_eq!(
+            witness[2].label(Language::En),
+            "Leaf script, timelocked 144 blocks"
+        );
+        assert_eq!(witness[3].label(Language::En), "Control block, depth 2");
+
+        // Unsigned transactions decode without witness nor fee.
+        let decoded = decode_tx(&unsigned, Network::Regtest, None).unwrap();
+        assert!(decoded.inputs[0].witness.is_empty());
+        assert_eq!(decoded.fee, None);
+
+        // Prevouts must match the inputs and cover the outputs.
+        assert!(decode_tx(&signed, Network::Regtest, Some(&[])).is_err());
+        let short = [TxOut {
+            value: Amount::from_sat(1_000),
+            ..prevouts[0].clone()
+        }];
+        assert!(decode_tx(&signed, Network::Regtest, Some(&short)).is_err());
+
+        // Non-Taproot witnesses are left undecoded.
+        let legacy = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                witness: Witness::from_slice(&[vec![0x30; 71], vec![0x02; 33]]),
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+        let decoded = decode_tx(&legacy, Network::Regtest, None).unwrap();
+        assert!(
+            decoded.inputs[0]
+                .witness
+                .iter()
+                .all(|item| matches!(item, WitnessItem::Unknown(_)))
+        );
+    }
+}
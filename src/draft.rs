@@ -0,0 +1,509 @@
+//! Escrow drafts, validated one wizard step at a time.
+//!
+//! The creation wizard collects the escrow parameters as text, in [`WizardStep`] order.
+//! Each step is validated with the same builders that derive the escrow address and
+//! transactions, so a draft that passes review is one both counterparties can reconstruct.
+
+use bitcoin::{Address, Amount, Denomination, Network, Transaction, Txid, absolute, hashes::Hash};
+use nostr::{Timestamp, key::PublicKey as NostrPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bip21::PaymentRequest,
+    error::{Error, ResultExt},
+    network::{Chain, NetworkProfile},
+    platform_fee::PlatformFee,
+    protocol::{DEFAULT_OFFER_VALIDITY, Offer, PROTOCOL_VERSION, Role},
+    scripts::{CURRENT_SCRIPT_TEMPLATE, EscrowConfig},
+    settings::FeeRateLimits,
+    tx::escrow_tx,
+    util::{P2TR_TX_VBYTE_C, blocks_for_duration, days_hours, parse_network, parse_npub},
+};
+
+/// A step of the escrow creation wizard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum WizardStep {
+    /// Network, participants and the user's role.
+    Parties,
+    /// Escrow amounts and resolution fee rate.
+    Amounts,
+    /// Optional arbitrator and dispute timelock.
+    Dispute,
+    /// Review of the derived escrow.
+    Review,
+    /// Sharing of the proposal with the counterparty.
+    Share,
+}
+
+impl WizardStep {
+    /// All steps, in order.
+    pub(crate) const ALL: [WizardStep; 5] = [
+        WizardStep::Parties,
+        WizardStep::Amounts,
+        WizardStep::Dispute,
+        WizardStep::Review,
+        WizardStep::Share,
+    ];
+
+    /// Key of the step's title in the [translations](crate::i18n).
+    pub(crate) fn title_key(self) -> &'static str {
+        match self {
+            WizardStep::Parties => "create-step-parties",
+            WizardStep::Amounts => "create-step-amounts",
+            WizardStep::Dispute => "create-step-dispute",
+            WizardStep::Review => "create-step-review",
+            WizardStep::Share => "create-step-share",
+        }
+    }
+
+    /// Position of the step, starting at 0.
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The next step, [`None`] on the last one.
+    pub(crate) fn next(self) -> Option<Self> {
+        Self::ALL.get(self.index() + 1).copied()
+    }
+
+    /// The previous step, [`None`] on the first one.
+    pub(crate) fn previous(self) -> Option<Self> {
+        self.index().checked_sub(1).map(|index| Self::ALL[index])
+    }
+}
+
+/// Escrow parameters as entered in the wizard.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct EscrowDraft {
+    /// Network name, as in the network selector.
+    pub(crate) network: String,
+    /// Role of the user creating the escrow.
+    pub(crate) role: Role,
+    pub(crate) npub_buyer: String,
+    pub(crate) npub_seller: String,
+    /// Buyer's escrow amount in BTC.
+    pub(crate) amount_buyer: String,
+    /// Seller's escrow amount in BTC.
+    pub(crate) amount_seller: String,
+    /// Resolution fee rate in sats/vByte.
+    pub(crate) fee_rate: String,
+    /// Fee rates the user accepts, from the [`Settings`](crate::settings::Settings).
+    #[serde(default)]
+    pub(crate) fee_rates: FeeRateLimits,
+    /// Arbitrator `npub`, empty for collaborative escrows.
+    pub(crate) npub_arbitrator: String,
+    pub(crate) timelock_days: String,
+    pub(crate) timelock_hours: String,
+}
+
+impl EscrowDraft {
+    /// Validates the inputs of `step` and of all steps before it.
+    pub(crate) fn validate(&self, step: WizardStep) -> Result<(), Error> {
+        let (network, npub_buyer, npub_seller) = self.parties()?;
+        if step >= WizardStep::Amounts {
+            self.amounts(network, &npub_buyer, &npub_seller)?;
+        }
+        if step >= WizardStep::Dispute {
+            self.dispute(&npub_buyer, &npub_seller)?;
+        }
+        if step >= WizardStep::Review {
+            self.build()?;
+        }
+        Ok(())
+    }
+
+    /// The first step with invalid inputs and its error, [`None`] if the draft is complete.
+    pub(crate) fn first_invalid_step(&self) -> Option<(WizardStep, Error)> {
+        WizardStep::ALL
+            .into_iter()
+            .find_map(|step| self.validate(step).err().map(|e| (step, e)))
+    }
+
+    /// Builds the escrow from a complete draft.
+    pub(crate) fn build(&self) -> Result<EscrowProposal, Error> {
+        let (network, npub_buyer, npub_seller) = self.parties()?;
+        let (amount_buyer, amount_seller, fee) =
+            self.amounts(network, &npub_buyer, &npub_seller)?;
+        let dispute = self.dispute(&npub_buyer, &npub_seller)?;
+        let config = EscrowConfig {
+            npub_1: npub_buyer,
+            npub_2: npub_seller,
+            npub_arbitrator: dispute.map(|(npub, _)| npub),
+            timelock_duration: dispute.map(|(_, timelock)| timelock),
+            network,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        Ok(EscrowProposal {
+            address: config.address()?,
+            config,
+            role: self.role,
+            amount_buyer,
+            amount_seller,
+            fee,
+            platform_fee: None,
+        })
+    }
+
+    /// Network and participants.
+    fn parties(&self) -> Result<(Network, NostrPublicKey, NostrPublicKey), Error> {
+        let network = parse_network(&self.network)?;
+        let npub_buyer = parse_npub(self.npub_buyer.trim()).context("Buyer npub")?;
+        let npub_seller = parse_npub(self.npub_seller.trim()).context("Seller npub")?;
+        if npub_buyer == npub_seller {
+            return Err(Error::WrongInputs(
+                "buyer and seller must be different".to_string(),
+            ));
+        }
+        Ok((network, npub_buyer, npub_seller))
+    }
+
+    /// Escrow amounts and resolution fee, checked by building a resolution transaction.
+    fn amounts(
+        &self,
+        network: Network,
+        npub_buyer: &NostrPublicKey,
+        npub_seller: &NostrPublicKey,
+    ) -> Result<(Amount, Amount, Amount), Error> {
+        let parse_btc = |amount: &str| -> Result<Amount, Error> {
+            Ok(Amount::from_str_in(amount.trim(), Denomination::Bitcoin)?)
+        };
+        let amount_buyer = parse_btc(&self.amount_buyer).context("Buyer amount")?;
+        let amount_seller = parse_btc(&self.amount_seller).context("Seller amount")?;
+        if amount_buyer == Amount::ZERO && amount_seller == Amount::ZERO {
+            return Err(Error::WrongInputs("the escrow has no amount".to_string()));
+        }
+        let fee_rate = self
+            .fee_rate
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| Error::WrongInputs("invalid fee rate".to_string()))?;
+        let fee = self
+            .fee_rates
+            .check_sat_per_vb(fee_rate)?
+            .fee_vb(P2TR_TX_VBYTE_C)
+            .ok_or(Error::Rounding)?;
+        // Both parties pay half the fee out of their amount.
+        escrow_tx(
+            npub_buyer,
+            npub_seller,
+            None,
+            amount_buyer,
+            amount_seller,
+            Txid::all_zeros(),
+            fee,
+            network,
+            absolute::LockTime::ZERO,
+        )?;
+        Ok((amount_buyer, amount_seller, fee))
+    }
+
+    /// Arbitrator and timelock in blocks, [`None`] for collaborative escrows.
+    fn dispute(
+        &self,
+        npub_buyer: &NostrPublicKey,
+        npub_seller: &NostrPublicKey,
+    ) -> Result<Option<(NostrPublicKey, u32)>, Error> {
+        let parse_count = |count: &str, unit: &str| -> Result<u32, Error> {
+            match count.trim() {
+                "" => Ok(0),
+                count => count
+                    .parse()
+                    .map_err(|_| Error::WrongInputs(format!("invalid number of {unit}"))),
+            }
+        };
+        let profile = NetworkProfile::from(self.network.parse::<Chain>()?);
+        let duration = days_hours(
+            parse_count(&self.timelock_days, "days")?,
+            parse_count(&self.timelock_hours, "hours")?,
+        );
+        let timelock = blocks_for_duration(duration, &profile).ok_or_else(|| {
+            Error::WrongInputs(format!("timelock is longer than {} blocks", u16::MAX))
+        })?;
+        if self.npub_arbitrator.trim().is_empty() {
+            if timelock > 0 {
+                return Err(Error::WrongInputs(
+                    "a timelock needs an arbitrator".to_string(),
+                ));
+            }
+            return Ok(None);
+        }
+        let npub_arbitrator = parse_npub(self.npub_arbitrator.trim()).context("Arbitrator npub")?;
+        if npub_arbitrator == *npub_buyer || npub_arbitrator == *npub_seller {
+            return Err(Error::WrongInputs(
+                "the arbitrator must not be the buyer or the seller".to_string(),
+            ));
+        }
+        // Relative timelocks in blocks are 16 bits.
+        if timelock == 0 || timelock > u16::MAX as u32 {
+            return Err(Error::WrongInputs(format!(
+                "the timelock must be between 1 and {} blocks",
+                u16::MAX
+            )));
+        }
+        Ok(Some((npub_arbitrator, timelock)))
+    }
+}
+
+/// An escrow built from a complete [`EscrowDraft`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EscrowProposal {
+    /// The escrow output, with the buyer as first participant.
+    pub(crate) config: EscrowConfig,
+    /// Role of the user creating the escrow.
+    pub(crate) role: Role,
+    pub(crate) amount_buyer: Amount,
+    pub(crate) amount_seller: Amount,
+    /// Fee of the resolution transaction.
+    pub(crate) fee: Amount,
+    /// The escrow address.
+    pub(crate) address: Address,
+    /// Fee of the hosting platform, if any.
+    pub(crate) platform_fee: Option<PlatformFee>,
+}
+
+impl EscrowProposal {
+    /// Amount to fund the escrow address with.
+    pub(crate) fn funding_amount(&self) -> Amount {
+        self.amount_buyer + self.amount_seller
+    }
+
+    /// Charges the `platform_fee` of a hosted deployment on the resolution.
+    pub(crate) fn with_platform_fee(self, platform_fee: PlatformFee) -> Result<Self, Error> {
+        platform_fee.validate(self.config.network)?;
+        let proposal = Self {
+            platform_fee: Some(platform_fee),
+            ..self
+        };
+        proposal.escrow_tx(Txid::all_zeros(), absolute::LockTime::ZERO)?;
+        Ok(proposal)
+    }
+
+    /// BIP-21 request to fund the escrow address.
+    pub(crate) fn payment_request(&self) -> PaymentRequest {
+        let address = self.address.to_string();
+        let escrow_id = &address[address.len() - 8..];
+        PaymentRequest::escrow_funding(&self.address, self.funding_amount(), escrow_id)
+    }
+
+    /// The [`Offer`] proposing this escrow to the counterparty, valid for
+    /// [`DEFAULT_OFFER_VALIDITY`] from `now`.
+    ///
+    /// The escrow transaction uses `lock_time_height` as anti-fee-sniping lock time, if known.
+    pub(crate) fn offer(
+        &self,
+        now: Timestamp,
+        lock_time_height: Option<u32>,
+    ) -> Result<Offer, Error> {
+        let (offerer, counterparty) = match self.role {
+            Role::Buyer => (self.config.npub_1, self.config.npub_2),
+            Role::Seller => (self.config.npub_2, self.config.npub_1),
+        };
+        let offer = Offer {
+            version: PROTOCOL_VERSION,
+            network: self.config.network,
+            offerer,
+            role: self.role,
+            counterparty: Some(counterparty),
+            amount_buyer: self.amount_buyer,
+            amount_seller: self.amount_seller,
+            arbitrator: self.config.npub_arbitrator,
+            timelock_duration: self.config.timelock_duration,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            lock_time_height,
+            script_template: self.config.template.into(),
+            fiat: None,
+            platform_fee: self.platform_fee.clone(),
+            min_confirmations: None,
+        };
+        offer.validate()?;
+        Ok(offer)
+    }
+
+    /// The unsigned resolution [`Transaction`] spending the escrow funded by `funding_txid`.
+    pub(crate) fn escrow_tx(
+        &self,
+        funding_txid: Txid,
+        lock_time: absolute::LockTime,
+    ) -> Result<Transaction, Error> {
+        let mut tx = escrow_tx(
+            &self.config.npub_1,
+            &self.config.npub_2,
+            self.config.timelock_duration,
+            self.amount_buyer,
+            self.amount_seller,
+            funding_txid,
+            self.fee,
+            self.config.network,
+            lock_time,
+        )?;
+        if let Some(platform_fee) = &self.platform_fee {
+            platform_fee.apply(
+                &mut tx,
+                self.amount_buyer,
+                self.amount_seller,
+                self.config.network,
+            )?;
+        }
+        Ok(tx)
+    }
+}
+
+/// A complete signet [`EscrowDraft`] of the seller, escrowing 0.001 BTC of the buyer
+/// and 0.0005 BTC of the seller, disputed through an arbitrator after a day.
+#[cfg(test)]
+pub(crate) fn draft() -> EscrowDraft {
+    use nostr::Keys;
+
+    EscrowDraft {
+        network: "Signet".to_string(),
+        role: Role::Seller,
+        npub_buyer: Keys::generate().public_key().to_hex(),
+        npub_seller: Keys::generate().public_key().to_hex(),
+        amount_buyer: "0.001".to_string(),
+        amount_seller: "0.0005".to_string(),
+        fee_rate: "2".to_string(),
+        fee_rates: FeeRateLimits::default(),
+        npub_arbitrator: Keys::generate().public_key().to_hex(),
+        timelock_days: "1".to_string(),
+        timelock_hours: String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::Keys;
+
+    use super::*;
+    use crate::platform_fee::check_platform_fee;
+
+    #[test]
+    fn steps_are_validated_in_order() {
+        assert_eq!(WizardStep::Parties.previous(), None);
+        assert_eq!(WizardStep::Review.next(), Some(WizardStep::Share));
+        assert_eq!(WizardStep::Share.next(), None);
+
+        let draft = draft();
+        assert!(draft.first_invalid_step().is_none());
+
+        let invalid = EscrowDraft {
+            amount_seller: "0.00000001".to_string(),
+            ..draft.clone()
+        };
+        assert!(invalid.validate(WizardStep::Parties).is_ok());
+        assert!(matches!(
+            invalid.first_invalid_step(),
+            Some((WizardStep::Amounts, Error::Rounding))
+        ));
+
+        // Fee rates outside the limits of the settings are typos.
+        for fee_rate in ["0", "1001"] {
+            let invalid = EscrowDraft {
+                fee_rate: fee_rate.to_string(),
+                ..draft.clone()
+            };
+            assert_eq!(invalid.first_invalid_step().unwrap().0, WizardStep::Amounts);
+        }
+
+        for invalid in [
+            EscrowDraft {
+                npub_seller: draft.npub_buyer.clone(),
+                ..draft.clone()
+            },
+            EscrowDraft {
+                network: "Dogecoin".to_string(),
+                ..draft.clone()
+            },
+        ] {
+            assert_eq!(invalid.first_invalid_step().unwrap().0, WizardStep::Parties);
+        }
+        for invalid in [
+            EscrowDraft {
+                npub_arbitrator: draft.npub_seller.clone(),
+                ..draft.clone()
+            },
+            EscrowDraft {
+                timelock_days: String::new(),
+                ..draft.clone()
+            },
+            EscrowDraft {
+                npub_arbitrator: String::new(),
+                ..draft.clone()
+            },
+        ] {
+            assert_eq!(invalid.first_invalid_step().unwrap().0, WizardStep::Dispute);
+        }
+    }
+
+    #[test]
+    fn proposal_matches_core_builders() {
+        let draft = draft();
+        let proposal = draft.build().unwrap();
+        assert_eq!(proposal.config.timelock_duration, Some(144));
+        assert_eq!(proposal.funding_amount(), Amount::from_sat(150_000));
+        assert_eq!(proposal.fee, Amount::from_sat(2 * P2TR_TX_VBYTE_C));
+
+        // The counterparty derives the same address and transaction from the offer.
+        let offer = proposal.offer(Timestamp::now(), Some(850_000)).unwrap();
+        assert_eq!(offer.offerer, proposal.config.npub_2);
+        let acceptor = proposal.config.npub_1;
+        assert_eq!(offer.escrow_address(&acceptor).unwrap(), proposal.address);
+        let funding_txid = Txid::all_zeros();
+        assert_eq!(
+            offer
+                .escrow_tx(&acceptor, None, funding_txid, proposal.fee)
+                .unwrap(),
+            proposal
+                .escrow_tx(funding_txid, offer.lock_time().unwrap())
+                .unwrap()
+        );
+
+        // A platform fee is paid by both participants, and agreed through the offer.
+        let platform = Keys::generate().public_key();
+        let platform_fee = PlatformFee::to_npub(&platform, Network::Signet, 100).unwrap();
+        let hosted = proposal
+            .clone()
+            .with_platform_fee(platform_fee.clone())
+            .unwrap();
+        let offer = hosted.offer(Timestamp::now(), None).unwrap();
+        check_platform_fee(offer.platform_fee.as_ref(), Some(&platform_fee)).unwrap();
+        assert!(check_platform_fee(None, Some(&platform_fee)).is_err());
+        let tx = offer
+            .escrow_tx(&acceptor, None, funding_txid, proposal.fee)
+            .unwrap();
+        assert_eq!(
+            tx,
+            hosted
+                .escrow_tx(funding_txid, absolute::LockTime::ZERO)
+                .unwrap()
+        );
+        platform_fee
+            .verify(&tx, proposal.funding_amount(), Network::Signet)
+            .unwrap();
+        assert_eq!(tx.output[2].value, Amount::from_sat(1_500));
+        let plain = proposal
+            .escrow_tx(funding_txid, absolute::LockTime::ZERO)
+            .unwrap();
+        assert_eq!(
+            plain.output[0].value - tx.output[0].value,
+            Amount::from_sat(1_000)
+        );
+        assert!(
+            platform_fee
+                .verify(&plain, proposal.funding_amount(), Network::Signet)
+                .is_err()
+        );
+
+        let collaborative = EscrowDraft {
+            npub_arbitrator: String::new(),
+            timelock_days: String::new(),
+            ..draft
+        }
+        .build()
+        .unwrap();
+        assert_eq!(collaborative.config.leaves().len(), 1);
+        assert_eq!(
+            collaborative.payment_request().amount,
+            Some(Amount::from_sat(150_000))
+        );
+    }
+}
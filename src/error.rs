@@ -1,9 +1,17 @@
 //! Errors related to Bitcoin scripts, transaction building and signing, network operations, string parsing, and other common errors.
 
+use std::fmt::Display;
+
 use thiserror::Error;
 
+use crate::i18n::{Language, language, tr, tr_args};
+
 /// Errors related to Bitcoin scripts, transaction building and signing,
 /// network operations, string parsing, and other common errors.
+///
+/// Every variant maps to a stable numeric [`Error::code`] that is safe to
+/// hand across the WASM boundary, and to a [`Error::user_message`] that is
+/// safe to render in the UI.
 #[derive(Debug, Error)]
 pub(crate) enum Error {
     #[error("Wrong Inputs: {0}")]
@@ -15,6 +23,12 @@ pub(crate) enum Error {
     #[error("Nostr key error: {0}")]
     Nostr(#[from] nostr::key::Error),
 
+    #[error("Nostr event error: {0}")]
+    NostrEvent(#[from] nostr::event::Error),
+
+    #[error("Nostr event builder error: {0}")]
+    NostrEventBuilder(#[from] nostr::event::builder::Error),
+
     #[error("Taproot Builder error: {0}")]
     TaprootBuilder(#[from] bitcoin::taproot::TaprootBuilderError),
 
@@ -30,6 +44,295 @@ pub(crate) enum Error {
     #[error("Esplora error: {0}")]
     Esplora(#[from] esplora_client::Error),
 
+    #[error("Nostr relay error: {0}")]
+    Relay(String),
+
+    #[error("Only {accepted} of {required} relays accepted the event")]
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    RelayQuorum { accepted: usize, required: usize },
+
     #[error("Expected exactly one funding transaction")]
     ExpectedOneFundingTransaction,
+
+    #[error("Signing invariants violated: {0}")]
+    InvariantViolation(crate::invariants::InvariantReport),
+
+    #[error("Trust proof error: {0}")]
+    TrustProof(String),
+
+    #[error("Escrow funded with {actual} instead of {expected}")]
+    FundingMismatch {
+        expected: bitcoin::Amount,
+        actual: bitcoin::Amount,
+    },
+
+    #[error("Unsupported script template version {0}")]
+    UnsupportedScriptTemplate(u8),
+
+    #[error("Transaction rejected: {0}")]
+    BroadcastRejected(String),
+
+    #[error("Non-standard transaction: {0}")]
+    NonStandard(String),
+
+    #[error("Sighash error: {0}")]
+    Sighash(#[from] bitcoin::sighash::TaprootError),
+
+    #[error("Address error: {0}")]
+    Address(#[from] bitcoin::address::ParseError),
+
+    #[error("Amount error: {0}")]
+    Amount(#[from] bitcoin::amount::ParseAmountError),
+
+    #[error("Transaction decoding error: {0}")]
+    TransactionDecode(#[from] bitcoin::consensus::encode::FromHexError),
+
+    #[error("Invalid escrow template: {0}")]
+    Template(String),
+
+    #[error("Address {0} is not owned by the given npub")]
+    AddressNotOwned(String),
+
+    #[error("MuSig2 error: {0}")]
+    MuSig(String),
+
+    #[error("Adaptor signature error: {0}")]
+    Adaptor(String),
+
+    #[error("NIP-05 error: {0}")]
+    Nip05(String),
+
+    #[error("HTTP error: {0}")]
+    Http(String),
+
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+
+    #[error("Proposal expired at {0}")]
+    Expired(nostr::Timestamp),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Notification error: {0}")]
+    Notification(String),
+
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Stable numeric code for this error.
+    ///
+    /// Codes are grouped by domain and never reused:
+    /// `1xx` inputs and parsing, `2xx` keys and cryptography,
+    /// `3xx` scripts and transactions, `4xx` network, `5xx` escrow negotiation,
+    /// `6xx` local storage, `7xx` notifications.
+    /// Context wrappers report the code of the underlying error.
+    pub(crate) fn code(&self) -> u16 {
+        match self {
+            Error::WrongInputs(_) => 100,
+            Error::InvalidEscrowType(_) => 101,
+            Error::InvalidNetwork(_) => 102,
+            Error::Address(_) => 103,
+            Error::Amount(_) => 104,
+            Error::TransactionDecode(_) => 105,
+            Error::Template(_) => 106,
+            Error::Secp256k1(_) => 200,
+            Error::Nostr(_) => 201,
+            Error::Sighash(_) => 202,
+            Error::NostrEvent(_) => 203,
+            Error::NostrEventBuilder(_) => 204,
+            Error::AddressNotOwned(_) => 205,
+            Error::MuSig(_) => 206,
+            Error::Adaptor(_) => 207,
+            Error::TaprootBuilder(_) => 300,
+            Error::Rounding => 301,
+            Error::ExpectedOneFundingTransaction => 302,
+            Error::InvariantViolation(_) => 303,
+            Error::TrustProof(_) => 304,
+            Error::FundingMismatch { .. } => 305,
+            Error::UnsupportedScriptTemplate(_) => 306,
+            Error::BroadcastRejected(_) => 307,
+            Error::NonStandard(_) => 308,
+            Error::Esplora(_) => 400,
+            Error::Relay(_) => 401,
+            Error::RelayQuorum { .. } => 402,
+            Error::Nip05(_) => 403,
+            Error::Http(_) => 404,
+            Error::Protocol(_) => 500,
+            Error::Expired(_) => 501,
+            Error::Storage(_) => 600,
+            Error::Notification(_) => 700,
+            Error::Context { source, .. } => source.code(),
+        }
+    }
+
+    /// UI-safe description of this error, in the current [`Language`].
+    ///
+    /// Never includes key material or raw library output, only the
+    /// context added by the caller and a fixed description of the cause.
+    pub(crate) fn user_message(&self) -> String {
+        self.localized_message(language())
+    }
+
+    /// UI-safe description of this error in `language`, see [`Error::user_message`].
+    pub(crate) fn localized_message(&self, language: Language) -> String {
+        let key = match self {
+            Error::WrongInputs(reason) => {
+                return tr_args(language, "error-wrong-inputs", &[("reason", reason)]);
+            }
+            Error::InvalidEscrowType(escrow_type) => {
+                return tr_args(
+                    language,
+                    "error-invalid-escrow-type",
+                    &[("escrow_type", escrow_type)],
+                );
+            }
+            Error::InvalidNetwork(network) => {
+                return tr_args(language, "error-invalid-network", &[("network", network)]);
+            }
+            Error::InvariantViolation(report) => {
+                return tr_args(language, "error-invariant-violation", &[("report", report)]);
+            }
+            Error::RelayQuorum { accepted, required } => {
+                return tr_args(
+                    language,
+                    "error-relay-quorum",
+                    &[("accepted", accepted), ("required", required)],
+                );
+            }
+            Error::TrustProof(reason) => {
+                return tr_args(language, "error-trust-proof", &[("reason", reason)]);
+            }
+            Error::FundingMismatch { expected, actual } => {
+                return tr_args(
+                    language,
+                    "error-funding-mismatch",
+                    &[("expected", expected), ("actual", actual)],
+                );
+            }
+            Error::UnsupportedScriptTemplate(version) => {
+                return tr_args(
+                    language,
+                    "error-unsupported-script-template",
+                    &[("version", version)],
+                );
+            }
+            Error::Template(reason) => {
+                return tr_args(language, "error-template", &[("reason", reason)]);
+            }
+            Error::Protocol(reason) => {
+                return tr_args(language, "error-protocol", &[("reason", reason)]);
+            }
+            Error::BroadcastRejected(reason) => {
+                return tr_args(language, "error-broadcast-rejected", &[("reason", reason)]);
+            }
+            Error::NonStandard(reason) => {
+                return tr_args(language, "error-non-standard", &[("reason", reason)]);
+            }
+            Error::MuSig(reason) => {
+                return tr_args(language, "error-musig", &[("reason", reason)]);
+            }
+            Error::Adaptor(reason) => {
+                return tr_args(language, "error-adaptor", &[("reason", reason)]);
+            }
+            Error::Notification(reason) => {
+                return tr_args(language, "error-notification", &[("reason", reason)]);
+            }
+            Error::Context { context, source } => {
+                return tr_args(
+                    language,
+                    "error-context",
+                    &[
+                        ("context", context),
+                        ("message", &source.localized_message(language)),
+                    ],
+                );
+            }
+            Error::Address(_) => "error-address",
+            Error::Amount(_) => "error-amount",
+            Error::TransactionDecode(_) => "error-transaction-decode",
+            Error::Secp256k1(_) => "error-secp256k1",
+            Error::Nostr(_) => "error-nostr",
+            Error::NostrEvent(_) => "error-nostr-event",
+            Error::NostrEventBuilder(_) => "error-nostr-event-builder",
+            Error::AddressNotOwned(_) => "error-address-not-owned",
+            Error::Sighash(_) => "error-sighash",
+            Error::TaprootBuilder(_) => "error-taproot-builder",
+            Error::Rounding => "error-rounding",
+            Error::ExpectedOneFundingTransaction => "error-expected-one-funding",
+            Error::Esplora(_) => "error-esplora",
+            Error::Expired(_) => "error-expired",
+            Error::Relay(_) => "error-relay",
+            Error::Nip05(_) => "error-nip05",
+            Error::Http(_) => "error-http",
+            Error::Storage(_) => "error-storage",
+        };
+        tr(language, key)
+    }
+
+    /// Wraps this error with a description of what was being done.
+    pub(crate) fn context(self, context: impl Display) -> Self {
+        Error::Context {
+            context: context.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Returns the innermost error, skipping all context wrappers.
+    pub(crate) fn root_cause(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root_cause(),
+            e => e,
+        }
+    }
+}
+
+/// Adds context to fallible results.
+pub(crate) trait ResultExt<T> {
+    /// Wraps the error, if any, with a description of what was being done.
+    fn context(self, context: impl Display) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: impl Display) -> Result<T, Error> {
+        self.map_err(|e| e.into().context(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn context_keeps_code_and_chains_source() {
+        let error = Err::<(), _>(Error::Rounding)
+            .context("building escrow transaction")
+            .unwrap_err();
+        assert_eq!(error.code(), Error::Rounding.code());
+        assert!(matches!(error.root_cause(), Error::Rounding));
+        assert_eq!(error.source().unwrap().to_string(), "Rounding error");
+        assert_eq!(
+            error.user_message(),
+            "building escrow transaction: Amounts do not add up: check the escrow amounts and fee."
+        );
+    }
+
+    #[test]
+    fn user_message_hides_library_output() {
+        let error = Error::from(secp256k1::Error::InvalidSecretKey);
+        assert_eq!(error.code(), 200);
+        assert_eq!(error.user_message(), "Invalid key or signature.");
+        assert_eq!(
+            error.localized_message(Language::PtBr),
+            "Chave ou assinatura inválida."
+        );
+    }
 }
@@ -1,31 +1,55 @@
 //! Interactions with Esplora backends.
-#![allow(dead_code)]
-
 use std::collections::HashMap;
 
-use bitcoin::{Address, Amount, Transaction, Txid};
-use esplora_client::{AsyncClient, Builder, r#async::DefaultSleeper};
+use bitcoin::{Address, Amount, Transaction, TxOut, Txid};
+use esplora_client::{AsyncClient, Builder};
 
-use crate::error::Error;
+use crate::{
+    error::Error,
+    proxy::{Backend, ProxySettings},
+    runtime::Sleeper,
+};
 
 /// How Esplora returns fee estimates.
 pub(crate) type FeeEstimate = HashMap<u16, f64>;
 
-/// Creates a new `async` Esplora client.
-pub(crate) fn create_client(url: &str) -> Result<AsyncClient<DefaultSleeper>, Error> {
-    Ok(Builder::new(url).build_async()?)
+/// `async` Esplora client, retrying with the [`runtime`](crate::runtime) of the target.
+pub(crate) type EsploraClient = AsyncClient<Sleeper>;
+
+/// Creates a new `async` Esplora client, connecting through the Esplora proxy of `proxies`.
+///
+/// Proxies are ignored in WASM, see [`proxy`](crate::proxy).
+pub(crate) fn create_client(url: &str, proxies: &ProxySettings) -> Result<EsploraClient, Error> {
+    let mut builder = Builder::new(url);
+    if let Some(proxy) = proxies.proxy(Backend::Esplora) {
+        builder = builder.proxy(&proxy.url());
+    }
+    Ok(builder.build_async_with_sleeper()?)
 }
 
 /// Gets fee estimates from Esplora.
-pub(crate) async fn get_fee_estimates(
-    client: &AsyncClient<DefaultSleeper>,
-) -> Result<FeeEstimate, Error> {
+pub(crate) async fn get_fee_estimates(client: &EsploraClient) -> Result<FeeEstimate, Error> {
     Ok(client.get_fee_estimates().await?)
 }
 
+/// Gets the current block height from Esplora.
+pub(crate) async fn get_block_height(client: &EsploraClient) -> Result<u32, Error> {
+    Ok(client.get_height().await?)
+}
+
+/// Gets the confirmations of the transaction `txid` from Esplora, 0 if unconfirmed.
+pub(crate) async fn get_confirmations(client: &EsploraClient, txid: &Txid) -> Result<u32, Error> {
+    let Some(height) = client.get_tx_status(txid).await?.block_height else {
+        return Ok(0);
+    };
+    let tip_height = get_block_height(client).await?;
+    Ok(tip_height.saturating_sub(height) + 1)
+}
+
 /// Gets balance from Esplora.
+#[allow(dead_code)]
 pub(crate) async fn get_balance(
-    client: &AsyncClient<DefaultSleeper>,
+    client: &EsploraClient,
     address: &Address,
 ) -> Result<Amount, Error> {
     let stats = client.get_address_stats(address).await?;
@@ -37,8 +61,9 @@ pub(crate) async fn get_balance(
 /// Gets funding [`Txid`] from Esplora.
 ///
 /// This assumes a virgin address with just one funding transaction.
+#[allow(dead_code)]
 pub(crate) async fn get_funding_txid(
-    client: &AsyncClient<DefaultSleeper>,
+    client: &EsploraClient,
     address: &Address,
 ) -> Result<Txid, Error> {
     let txs = client.get_address_txs(address, None).await?;
@@ -53,9 +78,32 @@ pub(crate) async fn get_funding_txid(
     Ok(funding_txid)
 }
 
+/// Gets the outputs spent by every input of `transaction`, in input order.
+pub(crate) async fn get_prevouts(
+    client: &EsploraClient,
+    transaction: &Transaction,
+) -> Result<Vec<TxOut>, Error> {
+    let mut prevouts = Vec::with_capacity(transaction.input.len());
+    for input in &transaction.input {
+        let previous_output = input.previous_output;
+        let prevout = client
+            .get_tx(&previous_output.txid)
+            .await?
+            .and_then(|previous_tx| {
+                previous_tx
+                    .output
+                    .get(previous_output.vout as usize)
+                    .cloned()
+            })
+            .ok_or_else(|| Error::WrongInputs(format!("Output {previous_output} not found")))?;
+        prevouts.push(prevout);
+    }
+    Ok(prevouts)
+}
+
 /// Broadcast [`Transaction`].
 pub(crate) async fn broadcast_transaction(
-    client: &AsyncClient<DefaultSleeper>,
+    client: &EsploraClient,
     transaction: &Transaction,
 ) -> Result<(), Error> {
     client.broadcast(transaction).await?;
@@ -81,21 +129,21 @@ mod tests {
 
     #[tokio::test]
     async fn get_fee_works() {
-        let client = create_client(TESTNET4_URL).unwrap();
+        let client = create_client(TESTNET4_URL, &ProxySettings::default()).unwrap();
         let fee_estimates = get_fee_estimates(&client).await.unwrap();
         assert!(!fee_estimates.is_empty());
     }
 
     #[tokio::test]
     async fn get_balance_works() {
-        let client = create_client(TESTNET4_URL).unwrap();
+        let client = create_client(TESTNET4_URL, &ProxySettings::default()).unwrap();
         let balance = get_balance(&client, &TESTNET4_ADDRESS).await.unwrap();
         assert!(balance > Amount::from_sat(0));
     }
 
     #[tokio::test]
     async fn get_funding_txid_works() {
-        let client = create_client(TESTNET4_URL).unwrap();
+        let client = create_client(TESTNET4_URL, &ProxySettings::default()).unwrap();
         let txid = get_funding_txid(&client, &TESTNET4_ADDRESS).await.unwrap();
         let expected = "bf8053a5db5b9d64b9ae49569ddd84c476f711e2971ed519eea777525acc8f09"
             .parse::<Txid>()
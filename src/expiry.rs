@@ -0,0 +1,449 @@
+//! Early warnings of dispute timelocks expiring.
+//!
+//! Once a dispute escrow is funded, its timeout path becomes spendable by the arbitrator
+//! after the timelock, and the party left waiting must act before or right when it does.
+//! A [`TimelockSchedule`] estimates, from the funding height and the block interval of the
+//! [`NetworkProfile`], when the timeout path of an open escrow becomes spendable.
+//! A [`TimelockScheduler`] raises a [`TimelockWarning`] each time one crosses one of the
+//! user's [`WarningThresholds`], and tells when to check again.
+
+use std::time::Duration;
+
+use bitcoin::Txid;
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::Timestamp;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde-types")]
+use crate::{dashboard::EscrowState, history::HistoryEvent, protocol::Session};
+use crate::{
+    error::Error,
+    i18n::{Language, tr, tr_args},
+    network::NetworkProfile,
+    notifications::{Notification, NotificationKind},
+    storage::Storage,
+    watch::{WatchSession, WatchStatus},
+};
+
+/// [`Storage`] key of the [`WarningThresholds`].
+pub(crate) const WARNING_THRESHOLDS_KEY: &str = "scrow.timelock_warnings";
+
+/// Default [`WarningThresholds`]: a week, a day and an hour before the timelock expires.
+pub(crate) const DEFAULT_WARNING_THRESHOLDS: [Duration; 3] = [
+    Duration::from_secs(7 * 24 * 60 * 60),
+    Duration::from_secs(24 * 60 * 60),
+    Duration::from_secs(60 * 60),
+];
+
+/// When the timeout path of a funded dispute escrow becomes spendable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TimelockSchedule {
+    /// The funding transaction, identifying the escrow.
+    pub(crate) funding_txid: Txid,
+    /// Name of the escrow shown to the user.
+    pub(crate) escrow: String,
+    /// Height from which the timeout path is spendable.
+    pub(crate) timelock_height: u32,
+    /// Blocks left until then, at the time of the estimate.
+    pub(crate) blocks_left: u32,
+    /// Estimated time at which the timeout path becomes spendable.
+    pub(crate) expires_at: Timestamp,
+}
+
+impl TimelockSchedule {
+    /// The schedule of the timelock expiring at `timelock_height`, estimated at `now`
+    /// with the chain at `tip_height`.
+    pub(crate) fn new(
+        funding_txid: Txid,
+        escrow: impl Into<String>,
+        timelock_height: u32,
+        tip_height: u32,
+        now: Timestamp,
+        profile: &NetworkProfile,
+    ) -> Self {
+        let blocks_left = timelock_height.saturating_sub(tip_height);
+        let expires_at = now + profile.block_interval * blocks_left;
+        Self {
+            funding_txid,
+            escrow: escrow.into(),
+            timelock_height,
+            blocks_left,
+            expires_at,
+        }
+    }
+
+    /// The schedule of the watched escrow `session` in `status`, [`None`] unless it is funded,
+    /// confirmed and has a dispute path.
+    pub(crate) fn from_watch(
+        session: &WatchSession,
+        status: WatchStatus,
+        tip_height: u32,
+        now: Timestamp,
+        profile: &NetworkProfile,
+    ) -> Option<Self> {
+        let WatchStatus::Funded {
+            timelock_height: Some(timelock_height),
+            ..
+        } = status
+        else {
+            return None;
+        };
+        let escrow = if session.label.is_empty() {
+            session.funding_txid.to_string()
+        } else {
+            session.label.clone()
+        };
+        Some(Self::new(
+            session.funding_txid,
+            escrow,
+            timelock_height,
+            tip_height,
+            now,
+            profile,
+        ))
+    }
+
+    /// The schedule of the escrow of `session`, [`None`] unless it is funded and open,
+    /// its funding confirmed and it has a dispute path.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn from_session(
+        session: &Session,
+        tip_height: u32,
+        now: Timestamp,
+        profile: &NetworkProfile,
+    ) -> Result<Option<Self>, Error> {
+        if EscrowState::of(session, now) != EscrowState::Funded {
+            return Ok(None);
+        }
+        let Some(timelock) = session.escrow_config()?.timelock_duration else {
+            return Ok(None);
+        };
+        let Some(funding_txid) = session
+            .funding
+            .as_ref()
+            .and_then(|funding| funding.outputs.first())
+            .map(|output| output.outpoint.txid)
+        else {
+            return Ok(None);
+        };
+        let confirmed_height =
+            session
+                .history()
+                .entries()
+                .iter()
+                .find_map(|entry| match entry.event {
+                    HistoryEvent::Confirmed { txid, height } if txid == funding_txid => {
+                        Some(height)
+                    }
+                    _ => None,
+                });
+        let escrow = session.id()?.to_string();
+        Ok(confirmed_height.map(|height| {
+            Self::new(
+                funding_txid,
+                escrow,
+                height.saturating_add(timelock),
+                tip_height,
+                now,
+                profile,
+            )
+        }))
+    }
+
+    /// Whether the timeout path is spendable already.
+    pub(crate) fn is_spendable(&self) -> bool {
+        self.blocks_left == 0
+    }
+
+    /// Estimated time left at `now` until the timeout path becomes spendable.
+    ///
+    /// Blocks may come late, so it is never zero until the timelock height is reached.
+    pub(crate) fn remaining(&self, now: Timestamp) -> Duration {
+        if self.is_spendable() {
+            return Duration::ZERO;
+        }
+        Duration::from_secs(self.expires_at.as_u64().saturating_sub(now.as_u64()))
+            .max(Duration::from_secs(1))
+    }
+}
+
+/// How long before a dispute timelock expires the user wants to be warned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<u64>", into = "Vec<u64>")]
+pub(crate) struct WarningThresholds(Vec<Duration>);
+
+impl Default for WarningThresholds {
+    fn default() -> Self {
+        Self::new(DEFAULT_WARNING_THRESHOLDS)
+    }
+}
+
+impl From<Vec<u64>> for WarningThresholds {
+    fn from(secs: Vec<u64>) -> Self {
+        Self::new(secs.into_iter().map(Duration::from_secs))
+    }
+}
+
+impl From<WarningThresholds> for Vec<u64> {
+    fn from(thresholds: WarningThresholds) -> Self {
+        thresholds.0.iter().map(Duration::as_secs).collect()
+    }
+}
+
+impl WarningThresholds {
+    /// The `thresholds`, largest first, always warning when the timeout path becomes spendable.
+    pub(crate) fn new(thresholds: impl IntoIterator<Item = Duration>) -> Self {
+        let mut thresholds = thresholds
+            .into_iter()
+            .map(|threshold| Duration::from_secs(threshold.as_secs()))
+            .chain([Duration::ZERO])
+            .collect::<Vec<_>>();
+        thresholds.sort_unstable_by(|a, b| b.cmp(a));
+        thresholds.dedup();
+        Self(thresholds)
+    }
+
+    /// The thresholds, largest first and ending with [`Duration::ZERO`].
+    pub(crate) fn thresholds(&self) -> &[Duration] {
+        &self.0
+    }
+
+    /// Loads the thresholds from `storage`, the defaults if none were saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(WARNING_THRESHOLDS_KEY)? {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|e| Error::Storage(format!("Invalid timelock warnings: {e}"))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the thresholds to `storage`.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::Storage(format!("Could not save timelock warnings: {e}")))?;
+        storage.set(WARNING_THRESHOLDS_KEY, &json)
+    }
+}
+
+/// A dispute timelock crossed one of the [`WarningThresholds`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TimelockWarning {
+    /// The escrow whose timelock is expiring.
+    pub(crate) schedule: TimelockSchedule,
+    /// The threshold crossed, [`Duration::ZERO`] once the timeout path is spendable.
+    pub(crate) threshold: Duration,
+}
+
+impl TimelockWarning {
+    /// The [`NotificationKind::TimelockExpiring`] notification of the warning.
+    pub(crate) fn notification(&self, language: Language) -> Notification {
+        let schedule = &self.schedule;
+        let (title, body) = if schedule.is_spendable() {
+            (
+                "notification-timelock-expired-title",
+                "notification-timelock-expired-body",
+            )
+        } else {
+            (
+                "notification-timelock-expiring-title",
+                "notification-timelock-expiring-body",
+            )
+        };
+        Notification {
+            kind: NotificationKind::TimelockExpiring,
+            tag: format!(
+                "timelock-expiring:{}:{}",
+                schedule.funding_txid,
+                self.threshold.as_secs()
+            ),
+            title: tr(language, title),
+            body: tr_args(
+                language,
+                body,
+                &[
+                    ("escrow", &schedule.escrow),
+                    ("blocks", &schedule.blocks_left),
+                    ("height", &schedule.timelock_height),
+                ],
+            ),
+        }
+    }
+}
+
+/// Raises each [`TimelockWarning`] once, as escrows cross the [`WarningThresholds`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TimelockScheduler {
+    thresholds: WarningThresholds,
+    /// Thresholds already crossed, per funding transaction.
+    raised: Vec<(Txid, Duration)>,
+}
+
+impl TimelockScheduler {
+    /// Replaces the user's thresholds, such as after a change in the settings.
+    ///
+    /// Thresholds already crossed are not raised again.
+    pub(crate) fn set_thresholds(&mut self, thresholds: WarningThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// The warning of `schedule` at `now`, if it crossed a threshold not raised yet.
+    ///
+    /// When several thresholds were crossed since the last check, only the closest
+    /// to the expiry is raised.
+    pub(crate) fn check(
+        &mut self,
+        schedule: &TimelockSchedule,
+        now: Timestamp,
+    ) -> Option<TimelockWarning> {
+        let remaining = schedule.remaining(now);
+        let crossed = self
+            .thresholds
+            .thresholds()
+            .iter()
+            .copied()
+            .filter(|threshold| remaining <= *threshold)
+            .collect::<Vec<_>>();
+        let closest = *crossed.last()?;
+        if self.raised.contains(&(schedule.funding_txid, closest)) {
+            return None;
+        }
+        for threshold in crossed {
+            if !self.raised.contains(&(schedule.funding_txid, threshold)) {
+                self.raised.push((schedule.funding_txid, threshold));
+            }
+        }
+        #[cfg(debug_assertions)]
+        trace!(funding_txid = %schedule.funding_txid, ?closest, "timelock warning");
+        Some(TimelockWarning {
+            schedule: schedule.clone(),
+            threshold: closest,
+        })
+    }
+
+    /// The warnings of `schedules` at `now`, see [`TimelockScheduler::check`].
+    pub(crate) fn check_all<'a>(
+        &mut self,
+        schedules: impl IntoIterator<Item = &'a TimelockSchedule>,
+        now: Timestamp,
+    ) -> Vec<TimelockWarning> {
+        schedules
+            .into_iter()
+            .filter_map(|schedule| self.check(schedule, now))
+            .collect()
+    }
+
+    /// When to check `schedules` again: the earliest estimated time one of them crosses
+    /// its next threshold, [`None`] if every threshold was crossed.
+    pub(crate) fn next_check<'a>(
+        &self,
+        schedules: impl IntoIterator<Item = &'a TimelockSchedule>,
+        now: Timestamp,
+    ) -> Option<Timestamp> {
+        schedules
+            .into_iter()
+            .filter_map(|schedule| {
+                let remaining = schedule.remaining(now);
+                let next = self
+                    .thresholds
+                    .thresholds()
+                    .iter()
+                    .find(|threshold| remaining > **threshold)?;
+                Some(Timestamp::from(
+                    schedule.expires_at.as_u64().saturating_sub(next.as_secs()),
+                ))
+            })
+            .min()
+    }
+}
+
+/// The [`TimelockSchedule`]s of the open dispute escrows persisted in `storage`,
+/// the soonest to expire first.
+#[cfg(feature = "serde-types")]
+pub(crate) fn schedule_escrows(
+    storage: &impl Storage,
+    tip_height: u32,
+    now: Timestamp,
+    profile: &NetworkProfile,
+) -> Result<Vec<TimelockSchedule>, Error> {
+    let mut schedules = Vec::new();
+    for id in Session::list(storage)? {
+        let Some(session) = Session::load(storage, &id)? else {
+            continue;
+        };
+        if let Some(schedule) = TimelockSchedule::from_session(&session, tip_height, now, profile)?
+        {
+            schedules.push(schedule);
+        }
+    }
+    schedules.sort_by_key(|schedule| schedule.timelock_height);
+    Ok(schedules)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+    use crate::{network::Chain, storage::MemoryStorage};
+
+    #[test]
+    fn timelock_warnings() {
+        let profile = NetworkProfile::from(Chain::Mainnet);
+        let now = Timestamp::from(1_700_000_000);
+        let at = |secs: u64| now + Duration::from_secs(secs);
+        let schedule = |tip_height: u32, now: Timestamp| {
+            TimelockSchedule::new(
+                Txid::all_zeros(),
+                "Order 42",
+                1_100,
+                tip_height,
+                now,
+                &profile,
+            )
+        };
+        // Two days of blocks left, estimated at ten minutes each.
+        let early = schedule(812, now);
+        assert_eq!(early.blocks_left, 288);
+        assert_eq!(early.expires_at, at(288 * 600));
+
+        let storage = MemoryStorage::default();
+        WarningThresholds::new([Duration::from_secs(24 * 60 * 60)])
+            .save(&storage)
+            .unwrap();
+        let thresholds = WarningThresholds::load(&storage).unwrap();
+        assert_eq!(thresholds.thresholds().last(), Some(&Duration::ZERO));
+        let mut scheduler = TimelockScheduler::default();
+        scheduler.set_thresholds(thresholds);
+        assert_eq!(scheduler.check(&early, now), None);
+        // Next check when a day is left.
+        assert_eq!(scheduler.next_check([&early], now), Some(at(144 * 600)));
+
+        // Crossing the day threshold warns once.
+        let warning = scheduler.check(&early, at(145 * 600)).unwrap();
+        assert_eq!(warning.threshold, Duration::from_secs(24 * 60 * 60));
+        let notification = warning.notification(Language::En);
+        assert_eq!(notification.kind, NotificationKind::TimelockExpiring);
+        assert!(notification.body.contains("Order 42"));
+        assert_eq!(scheduler.check(&early, at(146 * 600)), None);
+        // Late blocks never make the timeout path spendable early.
+        assert_eq!(scheduler.check(&early, at(300 * 600)), None);
+        assert_eq!(
+            scheduler.next_check([&early], at(300 * 600)),
+            Some(at(288 * 600))
+        );
+
+        // Once the timelock height is reached, right when it matters.
+        let expired = schedule(1_100, at(300 * 600));
+        let warning = scheduler.check(&expired, at(300 * 600)).unwrap();
+        assert_eq!(warning.threshold, Duration::ZERO);
+        assert!(warning.notification(Language::En).title.contains("expired"));
+        assert_eq!(scheduler.next_check([&expired], at(300 * 600)), None);
+
+        // Several thresholds crossed at once raise only the closest.
+        let mut scheduler = TimelockScheduler::default();
+        let warnings = scheduler.check_all([&schedule(1_095, now)], now);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].threshold, Duration::from_secs(60 * 60));
+    }
+}
@@ -0,0 +1,605 @@
+//! Exports signed transactions in the encodings other wallets and broadcasters accept,
+//! for when scrow's own broadcaster fails.
+//!
+//! - Raw consensus hex, accepted by every Esplora, Bitcoin Core and block explorer.
+//! - A finalized base64 [`Psbt`], for wallets that only import PSBTs.
+//! - [BBQr](https://bbqr.org) parts, to scan the transaction into an air-gapped
+//!   or mobile wallet as a series of QR codes.
+//!
+//! A participant can also sign a spend of the escrow UTXO with an external wallet,
+//! such as Sparrow or a Coldcard, instead of scrow: [`export_escrow_utxo`] produces the
+//! escrow's output descriptor, the spent prevout, and an unsigned PSBT with every leaf,
+//! control block and key origin the wallet needs.
+
+use bitcoin::{
+    OutPoint, Psbt, ScriptBuf, Transaction, TxOut, Witness,
+    bip32::{ChildNumber, DerivationPath, Fingerprint, KeySource},
+    consensus,
+    hex::DisplayHex,
+    taproot::LeafVersion,
+};
+use nostr::key::PublicKey as NostrPublicKey;
+
+use crate::{error::Error, scripts::EscrowContext, util::npub_to_x_only_public_key};
+
+/// Maximum length of a BBQr part, which fits a version 20 QR code with low error correction
+/// in alphanumeric mode.
+pub(crate) const DEFAULT_BBQR_PART_LEN: usize = 1_200;
+
+/// Length of a BBQr part header.
+const BBQR_HEADER_LEN: usize = 8;
+
+/// Maximum number of BBQr parts, the largest two-digit base 36 number.
+const BBQR_MAX_PARTS: usize = 36 * 36 - 1;
+
+/// RFC 4648 base32 alphabet, which is a subset of the QR alphanumeric mode.
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Characters of output descriptors, in the order of their checksum values, see BIP-380.
+const DESCRIPTOR_INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+
+/// Characters of output descriptor checksums.
+const DESCRIPTOR_CHECKSUM_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// NIP-06 coin type, the derivation path of Nostr keys from a BIP-39 seed.
+const NIP06_COIN_TYPE: u32 = 1237;
+
+/// Digits of the base 36 numbers in BBQr headers.
+const BASE36_DIGITS: &[u8; 36] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Type of the file carried by BBQr parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BbqrFileType {
+    /// A PSBT.
+    Psbt,
+    /// A signed transaction.
+    Transaction,
+}
+
+impl BbqrFileType {
+    /// Code of the file type in the BBQr header.
+    fn code(self) -> char {
+        match self {
+            BbqrFileType::Psbt => 'P',
+            BbqrFileType::Transaction => 'T',
+        }
+    }
+}
+
+/// A signed transaction in every export encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TransactionExport {
+    /// Raw consensus-encoded transaction, in hex.
+    pub(crate) hex: String,
+    /// Finalized PSBT, in base64.
+    pub(crate) psbt: String,
+    /// BBQr parts of the raw transaction, one per QR code.
+    pub(crate) bbqr: Vec<String>,
+}
+
+/// Exports a signed `tx` as raw hex, a finalized base64 PSBT and BBQr parts
+/// of at most `max_part_len` characters.
+///
+/// The spent `prevouts`, if known, are added to the PSBT as witness UTXOs,
+/// which some wallets need to show the fee.
+///
+/// # Errors
+///
+/// Errors if there is not one prevout per input, or the transaction needs too many BBQr parts.
+pub(crate) fn export(
+    tx: &Transaction,
+    prevouts: Option<&[TxOut]>,
+    max_part_len: usize,
+) -> Result<TransactionExport, Error> {
+    let bytes = consensus::serialize(tx);
+    Ok(TransactionExport {
+        hex: bytes.to_lower_hex_string(),
+        psbt: finalized_psbt(tx, prevouts)?.to_string(),
+        bbqr: bbqr_parts(&bytes, BbqrFileType::Transaction, max_part_len)?,
+    })
+}
+
+/// Wraps a signed `tx` in a [`Psbt`] whose inputs are all finalized with their scripts and witnesses.
+///
+/// # Errors
+///
+/// Errors if there is not one prevout per input.
+pub(crate) fn finalized_psbt(tx: &Transaction, prevouts: Option<&[TxOut]>) -> Result<Psbt, Error> {
+    let mut unsigned = tx.clone();
+    for txin in &mut unsigned.input {
+        txin.script_sig = ScriptBuf::new();
+        txin.witness = Witness::new();
+    }
+    let mut psbt =
+        Psbt::from_unsigned_tx(unsigned).expect("inputs have empty scripts and witnesses");
+
+    for (input, txin) in psbt.inputs.iter_mut().zip(&tx.input) {
+        if !txin.script_sig.is_empty() {
+            input.final_script_sig = Some(txin.script_sig.clone());
+        }
+        if !txin.witness.is_empty() {
+            input.final_script_witness = Some(txin.witness.clone());
+        }
+    }
+    if let Some(prevouts) = prevouts {
+        if prevouts.len() != tx.input.len() {
+            return Err(Error::WrongInputs(format!(
+                "Expected {} prevouts, got {}",
+                tx.input.len(),
+                prevouts.len()
+            )));
+        }
+        for (input, prevout) in psbt.inputs.iter_mut().zip(prevouts) {
+            input.witness_utxo = Some(prevout.clone());
+        }
+    }
+    Ok(psbt)
+}
+
+/// Everything an external wallet needs to sign a spend of the escrow UTXO.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EscrowUtxoExport {
+    /// Output descriptor of the escrow, with its checksum, see [`escrow_descriptor`].
+    pub(crate) descriptor: String,
+    /// The escrow UTXO.
+    pub(crate) outpoint: OutPoint,
+    /// The escrow output, spent by the PSBT.
+    pub(crate) prevout: TxOut,
+    /// Unsigned PSBT of the spend, in base64, see [`escrow_psbt`].
+    pub(crate) psbt: String,
+    /// BBQr parts of the PSBT, one per QR code.
+    pub(crate) bbqr: Vec<String>,
+}
+
+/// Exports the escrow UTXO spent by input `index` of the unsigned `tx` for an external wallet:
+/// the escrow descriptor, the prevout, and the PSBT with the escrow's Taproot data.
+///
+/// `prevouts` are the outputs spent by every input of `tx`, in input order,
+/// and `origins` the [`KeySource`]s of the keys the wallet holds, see [`nip06_key_source`].
+/// The PSBT is also split into BBQr parts of at most `max_part_len` characters.
+///
+/// # Errors
+///
+/// Errors if `index` doesn't spend the escrow of `context`, there is not one prevout per input,
+/// or the PSBT needs too many BBQr parts.
+pub(crate) fn export_escrow_utxo(
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    context: &EscrowContext,
+    origins: &[(NostrPublicKey, KeySource)],
+    max_part_len: usize,
+) -> Result<EscrowUtxoExport, Error> {
+    let psbt = escrow_psbt(tx, index, prevouts, context, origins)?;
+    Ok(EscrowUtxoExport {
+        descriptor: escrow_descriptor(context)?,
+        outpoint: tx.input[index].previous_output,
+        prevout: prevouts[index].clone(),
+        psbt: psbt.to_string(),
+        bbqr: bbqr_parts(&psbt.serialize(), BbqrFileType::Psbt, max_part_len)?,
+    })
+}
+
+/// The output descriptor of the escrow of `context`, with its checksum.
+///
+/// Collaborative escrows are a `tr()` descriptor whose leaf `A` is the miniscript
+/// `and_v(v:pk(npub_2),pk(npub_1))`, so wallets can watch and sign it like their own.
+/// The dispute leaves `B` and `C` drop the result of their timelock, which miniscript
+/// can't express, so dispute escrows are an `addr()` descriptor watching the escrow address.
+pub(crate) fn escrow_descriptor(context: &EscrowContext) -> Result<String, Error> {
+    let config = context.config();
+    let descriptor = if config.npub_arbitrator.is_none() {
+        format!(
+            "tr({},and_v(v:pk({}),pk({})))",
+            context.spend_info().internal_key(),
+            npub_to_x_only_public_key(&config.npub_2)?,
+            npub_to_x_only_public_key(&config.npub_1)?
+        )
+    } else {
+        format!("addr({})", context.address())
+    };
+    let checksum = descriptor_checksum(&descriptor)?;
+    Ok(format!("{descriptor}#{checksum}"))
+}
+
+/// Makes a [`Psbt`] of the unsigned `tx` whose input `index` spends the escrow of `context`.
+///
+/// Every input gets its witness UTXO from `prevouts`. The escrow input also gets the
+/// internal key, merkle root and leaves of the escrow, and each signer's key with the
+/// leaves it signs and its origin, from `origins` if known, or else an empty
+/// derivation path from a zero fingerprint.
+///
+/// # Errors
+///
+/// Errors if `index` doesn't spend the escrow of `context`, or there is not one prevout per input.
+pub(crate) fn escrow_psbt(
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    context: &EscrowContext,
+    origins: &[(NostrPublicKey, KeySource)],
+) -> Result<Psbt, Error> {
+    if prevouts.len() != tx.input.len() {
+        return Err(Error::WrongInputs(format!(
+            "Expected {} prevouts, got {}",
+            tx.input.len(),
+            prevouts.len()
+        )));
+    }
+    if prevouts.get(index).map(|prevout| &prevout.script_pubkey)
+        != Some(&context.address().script_pubkey())
+    {
+        return Err(Error::WrongInputs(format!(
+            "Input {index} doesn't spend the escrow"
+        )));
+    }
+    let mut unsigned = tx.clone();
+    for txin in &mut unsigned.input {
+        txin.script_sig = ScriptBuf::new();
+        txin.witness = Witness::new();
+    }
+    let mut psbt =
+        Psbt::from_unsigned_tx(unsigned).expect("inputs have empty scripts and witnesses");
+    for (input, prevout) in psbt.inputs.iter_mut().zip(prevouts) {
+        input.witness_utxo = Some(prevout.clone());
+    }
+
+    let config = context.config();
+    let input = &mut psbt.inputs[index];
+    input.tap_internal_key = Some(context.spend_info().internal_key());
+    input.tap_merkle_root = context.spend_info().merkle_root();
+    for escrow_script in config.leaves() {
+        let leaf = context.leaf(*escrow_script)?;
+        input.tap_scripts.insert(
+            leaf.control_block.clone(),
+            (leaf.script.clone(), LeafVersion::TapScript),
+        );
+        for npub in config.signers(*escrow_script)? {
+            let origin = origins
+                .iter()
+                .find(|(origin_npub, _)| *origin_npub == npub)
+                .map_or_else(
+                    || (Fingerprint::default(), DerivationPath::master()),
+                    |(_, origin)| origin.clone(),
+                );
+            input
+                .tap_key_origins
+                .entry(npub_to_x_only_public_key(&npub)?)
+                .or_insert_with(|| (Vec::new(), origin))
+                .0
+                .push(leaf.leaf_hash);
+        }
+    }
+    Ok(psbt)
+}
+
+/// The [`KeySource`] of the Nostr key of the NIP-06 `account` of the seed of `fingerprint`,
+/// `m/44'/1237'/<account>'/0/0`, which hardware wallets need to recognize the key.
+///
+/// # Errors
+///
+/// Errors if `account` is not a valid hardened index.
+pub(crate) fn nip06_key_source(fingerprint: Fingerprint, account: u32) -> Result<KeySource, Error> {
+    let hardened = |index| {
+        ChildNumber::from_hardened_idx(index)
+            .map_err(|e| Error::WrongInputs(format!("Invalid NIP-06 account {account}: {e}")))
+    };
+    let path = DerivationPath::from(vec![
+        hardened(44)?,
+        hardened(NIP06_COIN_TYPE)?,
+        hardened(account)?,
+        ChildNumber::Normal { index: 0 },
+        ChildNumber::Normal { index: 0 },
+    ]);
+    Ok((fingerprint, path))
+}
+
+/// The BIP-380 checksum of `descriptor`.
+///
+/// # Errors
+///
+/// Errors if `descriptor` has characters descriptors can't hold.
+fn descriptor_checksum(descriptor: &str) -> Result<String, Error> {
+    fn polymod(c: u64, value: u64) -> u64 {
+        const GENERATORS: [u64; 5] = [
+            0xf5_dee5_1989,
+            0xa9_fdca_3312,
+            0x1b_ab10_e32d,
+            0x37_06b1_677a,
+            0x64_4d62_6ffd,
+        ];
+        let top = c >> 35;
+        let mut c = ((c & 0x7_ffff_ffff) << 5) ^ value;
+        for (bit, generator) in GENERATORS.iter().enumerate() {
+            if (top >> bit) & 1 == 1 {
+                c ^= generator;
+            }
+        }
+        c
+    }
+
+    let (mut c, mut class, mut class_count) = (1, 0, 0);
+    for ch in descriptor.chars() {
+        let position = DESCRIPTOR_INPUT_CHARSET
+            .find(ch)
+            .ok_or_else(|| Error::WrongInputs(format!("Invalid descriptor character {ch:?}")))?
+            as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            (class, class_count) = (0, 0);
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Ok((0..8)
+        .map(|j| DESCRIPTOR_CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Splits `data` into base32-encoded BBQr parts of at most `max_part_len` characters,
+/// header included.
+///
+/// Parts are evened out, so the last one is not much shorter than the others,
+/// and each holds a whole number of base32 blocks so it decodes on its own.
+///
+/// # Errors
+///
+/// Errors if `max_part_len` can't hold a header and a base32 block,
+/// or `data` needs more than 1295 parts.
+pub(crate) fn bbqr_parts(
+    data: &[u8],
+    file_type: BbqrFileType,
+    max_part_len: usize,
+) -> Result<Vec<String>, Error> {
+    // Base32 encodes 5 bytes into 8 characters.
+    let max_blocks = max_part_len.saturating_sub(BBQR_HEADER_LEN) / 8;
+    if max_blocks == 0 {
+        return Err(Error::WrongInputs(format!(
+            "BBQr parts of {max_part_len} characters can't hold any data"
+        )));
+    }
+    let blocks = data.len().div_ceil(5).max(1);
+    let count = blocks.div_ceil(max_blocks);
+    if count > BBQR_MAX_PARTS {
+        return Err(Error::WrongInputs(format!(
+            "{count} BBQr parts are needed, at most {BBQR_MAX_PARTS} are supported"
+        )));
+    }
+    let bytes_per_part = blocks.div_ceil(count) * 5;
+
+    Ok(data
+        .chunks(bytes_per_part)
+        .enumerate()
+        .map(|(index, chunk)| {
+            format!(
+                "B$2{}{}{}{}",
+                file_type.code(),
+                base36(count),
+                base36(index),
+                base32(chunk)
+            )
+        })
+        .collect())
+}
+
+/// Two-digit base 36 encoding of `n`, which must be at most [`BBQR_MAX_PARTS`].
+fn base36(n: usize) -> String {
+    [n / 36, n % 36]
+        .iter()
+        .map(|digit| BASE36_DIGITS[*digit] as char)
+        .collect()
+}
+
+/// RFC 4648 base32 encoding of `data`, without padding.
+fn base32(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u16, 0);
+    for byte in data {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Amount, Network, TxIn, Txid, absolute, hashes::Hash, transaction};
+
+    use super::*;
+    use crate::{
+        scripts::{CURRENT_SCRIPT_TEMPLATE, EscrowConfig},
+        secret::SecretNsec,
+    };
+
+    /// Decodes unpadded RFC 4648 base32.
+    fn base32_decode(encoded: &str) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        let (mut buffer, mut bits) = (0u16, 0);
+        for c in encoded.bytes() {
+            let value = BASE32_ALPHABET.iter().position(|a| *a == c).unwrap() as u16;
+            buffer = (buffer << 5) | value;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                decoded.push((buffer >> bits) as u8);
+            }
+        }
+        decoded
+    }
+
+    #[test]
+    fn base32_rfc4648() {
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"f"), "MY");
+        assert_eq!(base32(b"fooba"), "MZXW6YTB");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("MZXW6YTBOI"), b"foobar");
+    }
+
+    #[test]
+    fn export_signed_transaction() {
+        let mut witness = Witness::new();
+        witness.push([1; 64]);
+        let tx = Transaction {
+            version: transaction::Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                witness,
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: ScriptBuf::from_bytes(vec![0x51; 34]),
+                };
+                20
+            ],
+        };
+        let prevout = TxOut {
+            value: Amount::from_sat(1_100_000),
+            script_pubkey: ScriptBuf::new(),
+        };
+
+        let export = export(&tx, Some(std::slice::from_ref(&prevout)), 200).unwrap();
+        assert_eq!(
+            consensus::encode::deserialize_hex::<Transaction>(&export.hex).unwrap(),
+            tx
+        );
+
+        let psbt = export.psbt.parse::<Psbt>().unwrap();
+        assert_eq!(psbt.inputs[0].witness_utxo, Some(prevout));
+        assert_eq!(psbt.extract_tx_unchecked_fee_rate(), tx);
+        assert!(finalized_psbt(&tx, Some(&[])).is_err());
+
+        let parts = &export.bbqr;
+        assert!(parts.len() > 1);
+        let mut data = Vec::new();
+        for (index, part) in parts.iter().enumerate() {
+            assert!(part.len() <= 200);
+            assert_eq!(&part[..4], "B$2T");
+            assert_eq!(&part[4..6], base36(parts.len()));
+            assert_eq!(&part[6..8], base36(index));
+            data.extend(base32_decode(&part[8..]));
+        }
+        assert_eq!(data, consensus::serialize(&tx));
+
+        assert!(bbqr_parts(&data, BbqrFileType::Psbt, 15).is_err());
+        assert!(bbqr_parts(&[0; 10_000], BbqrFileType::Psbt, 16).is_err());
+        assert_eq!(
+            bbqr_parts(b"fooba", BbqrFileType::Psbt, 16).unwrap(),
+            ["B$2P0100MZXW6YTB"]
+        );
+    }
+
+    #[test]
+    fn export_escrow_for_external_wallets() {
+        // BIP-380 test vector.
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert!(descriptor_checksum("raw(dead\u{e9}beef)").is_err());
+
+        let [npub_1, npub_2, npub_arbitrator] =
+            [(); 3].map(|()| SecretNsec::generate().public_key());
+        let config = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let context = config.context().unwrap();
+        let descriptor = escrow_descriptor(&context).unwrap();
+        let (body, checksum) = descriptor.split_once('#').unwrap();
+        assert!(body.starts_with("tr("));
+        assert!(body.contains(&format!(
+            "and_v(v:pk({}),pk({}))",
+            npub_to_x_only_public_key(&npub_2).unwrap(),
+            npub_to_x_only_public_key(&npub_1).unwrap()
+        )));
+        assert_eq!(descriptor_checksum(body).unwrap(), checksum);
+
+        // Dispute escrows are watched by address.
+        let dispute = EscrowConfig {
+            npub_arbitrator: Some(npub_arbitrator),
+            timelock_duration: Some(144),
+            ..config
+        }
+        .context()
+        .unwrap();
+        assert!(
+            escrow_descriptor(&dispute)
+                .unwrap()
+                .starts_with(&format!("addr({})#", dispute.address()))
+        );
+
+        let prevout = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: dispute.address().script_pubkey(),
+        };
+        let outpoint = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let fingerprint = Fingerprint::from([1, 2, 3, 4]);
+        let origin = nip06_key_source(fingerprint, 0).unwrap();
+        assert_eq!(origin.1.to_string(), "44'/1237'/0'/0/0");
+        let export = export_escrow_utxo(
+            &tx,
+            0,
+            std::slice::from_ref(&prevout),
+            &dispute,
+            &[(npub_1, origin.clone())],
+            DEFAULT_BBQR_PART_LEN,
+        )
+        .unwrap();
+        assert_eq!(export.outpoint, outpoint);
+        assert_eq!(export.prevout, prevout);
+        assert!(export.bbqr.iter().all(|part| part.starts_with("B$2P")));
+
+        let psbt = export.psbt.parse::<Psbt>().unwrap();
+        let input = &psbt.inputs[0];
+        assert_eq!(input.witness_utxo, Some(prevout.clone()));
+        assert_eq!(input.tap_scripts.len(), 3);
+        assert_eq!(
+            input.tap_internal_key,
+            Some(dispute.spend_info().internal_key())
+        );
+        // The buyer signs leaves A and B, from their hardware wallet.
+        let (leaves, source) = &input.tap_key_origins[&npub_to_x_only_public_key(&npub_1).unwrap()];
+        assert_eq!(leaves.len(), 2);
+        assert_eq!(source, &origin);
+        let (leaves, source) =
+            &input.tap_key_origins[&npub_to_x_only_public_key(&npub_arbitrator).unwrap()];
+        assert_eq!(leaves.len(), 2);
+        assert_eq!(source.0, Fingerprint::default());
+
+        // The input must spend the escrow.
+        assert!(escrow_psbt(&tx, 0, std::slice::from_ref(&prevout), &context, &[]).is_err());
+        assert!(escrow_psbt(&tx, 1, &[prevout], &dispute, &[]).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! Faucet client for test escrows.
+//!
+//! On chains with a faucet API, such as Mutinynet, a [`Faucet`] requests coins to an
+//! address or to the address derived from a user's npub, and [`wait_for_deposit`]
+//! polls Esplora until the faucet transaction shows up, so demos and tutorials
+//! run end to end without visiting a faucet website.
+use std::time::Duration;
+
+use bitcoin::{Address, Amount, Transaction, Txid};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::key::PublicKey as NostrPublicKey;
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, ResultExt},
+    esplora::EsploraClient,
+    network::Chain,
+    runtime::{Instant, post_json, sleep},
+    util::npub_to_address,
+};
+
+/// Mutinynet faucet API to request coins to an address.
+const MUTINYNET_FAUCET_URL: &str = "https://faucet.mutinynet.com/api/onchain";
+
+/// Amount requested to a user's address by default, enough for a few test escrows.
+pub(crate) const DEFAULT_FAUCET_AMOUNT: Amount = Amount::from_sat(100_000);
+
+/// How often [`wait_for_deposit`] polls Esplora.
+pub(crate) const DEPOSIT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait for a faucet deposit by default.
+pub(crate) const DEFAULT_DEPOSIT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A faucet API of a test chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Faucet {
+    /// The chain the faucet pays on.
+    chain: Chain,
+    /// The faucet API endpoint.
+    url: String,
+}
+
+/// Response of the faucet API.
+#[derive(Debug, Deserialize)]
+struct FaucetResponse {
+    txid: Txid,
+}
+
+/// A faucet payment seen on chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Deposit {
+    /// The faucet transaction.
+    pub(crate) txid: Txid,
+    /// The funded address.
+    pub(crate) address: Address,
+    /// Amount paid to the address.
+    pub(crate) amount: Amount,
+    /// Whether the faucet transaction is confirmed, or still in the mempool.
+    pub(crate) confirmed: bool,
+}
+
+impl Faucet {
+    /// The faucet of `chain`, if it has one, see [`Chain::has_faucet`].
+    pub(crate) fn for_chain(chain: Chain) -> Option<Self> {
+        chain.has_faucet().then(|| Self {
+            chain,
+            url: MUTINYNET_FAUCET_URL.to_string(),
+        })
+    }
+
+    /// Uses the faucet API at `url` instead, such as a self-hosted one.
+        pub(crate) fn with_url(self, url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..self
+        }
+    }
+
+    /// Requests `amount` to `address`, returning the [`Txid`] of the faucet transaction.
+    ///
+    /// The faucet rate limits requests and caps their amount.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `address` is not for the faucet's chain or the faucet refuses the request.
+    pub(crate) async fn request(&self, address: &Address, amount: Amount) -> Result<Txid, Error> {
+        if !address
+            .as_unchecked()
+            .is_valid_for_network(self.chain.network())
+        {
+            return Err(Error::WrongInputs(format!(
+                "{address} is not a {} address",
+                self.chain
+            )));
+        }
+        let request = serde_json::json!({
+            "sats": amount.to_sat(),
+            "address": address.to_string(),
+        });
+        let response = post_json(&self.url, &request.to_string()).await?;
+        let response: FaucetResponse = serde_json::from_str(&response)
+            .map_err(|e| Error::Http(format!("{}: {e}", self.url)))?;
+        #[cfg(debug_assertions)]
+        trace!(%address, %amount, txid = %response.txid, "faucet paid");
+        Ok(response.txid)
+    }
+
+    /// Requests `amount` to the address derived from `npub`,
+    /// returning the address and the [`Txid`] of the faucet transaction.
+        pub(crate) async fn fund_npub(
+        &self,
+        npub: &NostrPublicKey,
+        amount: Amount,
+    ) -> Result<(Address, Txid), Error> {
+        let address = npub_to_address(npub, self.chain.network())?;
+        let txid = self.request(&address, amount).await?;
+        Ok((address, txid))
+    }
+}
+
+/// Polls Esplora until the faucet transaction `txid` paying `address` shows up,
+/// in the mempool or confirmed, for at most `timeout`.
+///
+/// # Errors
+///
+/// Errors if the transaction is not seen in time or doesn't pay `address`.
+pub(crate) async fn wait_for_deposit(
+    client: &EsploraClient,
+    address: &Address,
+    txid: Txid,
+    timeout: Duration,
+) -> Result<Deposit, Error> {
+    let start = Instant::now();
+    loop {
+        if let Some(tx) = client
+            .get_tx(&txid)
+            .await
+            .context("fetching faucet transaction")?
+        {
+            let confirmed = client
+                .get_tx_status(&txid)
+                .await
+                .context("fetching faucet transaction status")?
+                .confirmed;
+            return deposit(&tx, address, confirmed);
+        }
+        if start.elapsed() >= timeout {
+            return Err(Error::Http(format!(
+                "Faucet transaction {txid} not seen after {} seconds",
+                timeout.as_secs()
+            )));
+        }
+        sleep(DEPOSIT_POLL_INTERVAL).await;
+    }
+}
+
+/// The [`Deposit`] of `tx` to `address`.
+fn deposit(tx: &Transaction, address: &Address, confirmed: bool) -> Result<Deposit, Error> {
+    let script_pubkey = address.script_pubkey();
+    let amount = tx
+        .output
+        .iter()
+        .filter(|output| output.script_pubkey == script_pubkey)
+        .map(|output| output.value)
+        .sum::<Amount>();
+    if amount == Amount::ZERO {
+        return Err(Error::WrongInputs(format!(
+            "Faucet transaction {} does not pay {address}",
+            tx.compute_txid()
+        )));
+    }
+    Ok(Deposit {
+        txid: tx.compute_txid(),
+        address: address.clone(),
+        amount,
+        confirmed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, TxOut, absolute, transaction::Version};
+
+    use super::*;
+    use crate::secret::SecretNsec;
+
+    #[test]
+    fn faucet_deposit() {
+        assert!(Faucet::for_chain(Chain::Mainnet).is_none());
+        assert!(Faucet::for_chain(Chain::Signet).is_none());
+        let faucet = Faucet::for_chain(Chain::Mutinynet)
+            .unwrap()
+            .with_url("http://127.0.0.1:3000/api/onchain");
+        assert_eq!(faucet.url, "http://127.0.0.1:3000/api/onchain");
+
+        let address =
+            npub_to_address(&SecretNsec::generate().public_key(), Network::Signet).unwrap();
+        let other = npub_to_address(&SecretNsec::generate().public_key(), Network::Signet).unwrap();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: DEFAULT_FAUCET_AMOUNT,
+                    script_pubkey: address.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(5_000),
+                    script_pubkey: other.script_pubkey(),
+                },
+            ],
+        };
+        let paid = deposit(&tx, &address, false).unwrap();
+        assert_eq!(paid.amount, DEFAULT_FAUCET_AMOUNT);
+        assert_eq!(paid.txid, tx.compute_txid());
+        assert!(!paid.confirmed);
+
+        let unpaid =
+            npub_to_address(&SecretNsec::generate().public_key(), Network::Signet).unwrap();
+        assert!(deposit(&tx, &unpaid, true).is_err());
+    }
+
+    #[tokio::test]
+    async fn faucet_rejects_other_networks() {
+        let faucet = Faucet::for_chain(Chain::Mutinynet).unwrap();
+        let mainnet =
+            npub_to_address(&SecretNsec::generate().public_key(), Network::Bitcoin).unwrap();
+        assert!(matches!(
+            faucet.request(&mainnet, DEFAULT_FAUCET_AMOUNT).await,
+            Err(Error::WrongInputs(_))
+        ));
+    }
+}
@@ -0,0 +1,292 @@
+//! UniFFI exports of the escrow engine, for the iOS and Android apps.
+//!
+//! Native apps reuse the exact consensus-critical code of scrow:
+//! proposal encoding, address derivation, signing, combining and broadcasting.
+//! Keys, transactions and signatures cross the boundary as strings,
+//! and every failure as a [`ScrowError`] with the stable [`Error::code`].
+//!
+//! The exports are ready for a `cdylib` build of the engine,
+//! from which `uniffi-bindgen` generates the Kotlin and Swift bindings.
+//! [`handle_request`] also exposes the whole [`api`](crate::api) as JSON.
+
+use bitcoin::{Address, FeeRate, address::NetworkUnchecked, consensus, hex::DisplayHex};
+use nostr::{Event, JsonUtil, key::SecretKey as NostrSecretKey};
+use secp256k1::schnorr;
+
+use crate::{
+    accounts::Keystore,
+    api::handle_json,
+    broadcast::{Broadcaster, EsploraBackend, RetryPolicy},
+    decode::parse_tx_hex,
+    error::Error,
+    esplora::create_client,
+    invariants::SigningInvariants,
+    protocol::{Offer, deserialize, serialize},
+    proxy::ProxySettings,
+    scripts::{EscrowConfig, ScriptTemplate},
+    sign::{combine_signatures as combine, sign_escrow_tx as sign},
+    standardness::check_standard,
+    util::{parse_escrow_type, parse_network, parse_npub, parse_nsec},
+    wallet,
+};
+
+/// An escrow, with keys as `npub` strings or hex.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub(crate) struct EscrowRecord {
+    /// First participant's Nostr public key.
+    pub(crate) npub_1: String,
+    /// Second participant's Nostr public key.
+    pub(crate) npub_2: String,
+    /// Arbitrator's Nostr public key, for dispute escrows.
+    pub(crate) npub_arbitrator: Option<String>,
+    /// Timelock duration in blocks, for dispute escrows.
+    pub(crate) timelock_duration: Option<u32>,
+    /// Network name, such as `"mainnet"` or `"mutinynet"`.
+    pub(crate) network: String,
+    /// Version of the [`ScriptTemplate`].
+    pub(crate) template: u8,
+}
+
+impl TryFrom<EscrowRecord> for EscrowConfig {
+    type Error = Error;
+
+    fn try_from(record: EscrowRecord) -> Result<Self, Self::Error> {
+        Ok(EscrowConfig {
+            npub_1: parse_npub(&record.npub_1)?,
+            npub_2: parse_npub(&record.npub_2)?,
+            npub_arbitrator: record
+                .npub_arbitrator
+                .as_deref()
+                .map(parse_npub)
+                .transpose()?,
+            timelock_duration: record.timelock_duration,
+            network: parse_network(&record.network)?,
+            template: ScriptTemplate::try_from(record.template)?,
+        })
+    }
+}
+
+/// A failed call, safe to show to the user.
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub(crate) enum ScrowError {
+    /// The call failed.
+    #[error("{message}")]
+    Failed {
+        /// Stable [`Error::code`].
+        code: u16,
+        /// [`Error::user_message`].
+        message: String,
+    },
+}
+
+impl From<Error> for ScrowError {
+    fn from(error: Error) -> Self {
+        ScrowError::Failed {
+            code: error.code(),
+            message: error.user_message(),
+        }
+    }
+}
+
+/// Runs a JSON request of the [`api`](crate::api), returning the JSON response.
+#[uniffi::export]
+pub(crate) fn handle_request(request: String) -> String {
+    handle_json(&request)
+}
+
+/// Derives the escrow address.
+#[uniffi::export]
+pub(crate) fn escrow_address(escrow: EscrowRecord) -> Result<String, ScrowError> {
+    Ok(EscrowConfig::try_from(escrow)?.address()?.to_string())
+}
+
+/// Signs a JSON [`Offer`] with the offerer's `nsec`, returning the Nostr event to publish as JSON.
+#[uniffi::export]
+pub(crate) fn encode_offer(offer_json: String, nsec: String) -> Result<String, ScrowError> {
+    let offer = deserialize::<Offer>(&offer_json)?;
+    offer.validate()?;
+    let nsec = NostrSecretKey::parse(&nsec).map_err(Error::from)?;
+    Ok(offer.to_event(&nsec)?.as_json())
+}
+
+/// Checks an offer Nostr event given as JSON, returning the JSON [`Offer`].
+#[uniffi::export]
+pub(crate) fn decode_offer(event_json: String) -> Result<String, ScrowError> {
+    let event = Event::from_json(&event_json).map_err(Error::from)?;
+    Ok(serialize(&Offer::from_event(&event)?)?)
+}
+
+/// Signs input `input_index` of the escrow transaction `tx_hex` through the leaf
+/// `escrow_script` (`"A"`, `"B"` or `"C"`), returning the signature in hex.
+///
+/// The transaction is checked against the JSON [`SigningInvariants`] first,
+/// which also carry the outputs spent by every input.
+#[uniffi::export]
+pub(crate) fn sign_escrow_tx(
+    escrow: EscrowRecord,
+    tx_hex: String,
+    input_index: u32,
+    invariants_json: String,
+    escrow_script: String,
+    nsec: String,
+) -> Result<String, ScrowError> {
+    let config = EscrowConfig::try_from(escrow)?;
+    let invariants: SigningInvariants = deserialize(&invariants_json)?;
+    let mut keystore = Keystore::default();
+    let account = keystore.unlock(parse_nsec(&nsec)?);
+    let signature = sign(
+        &parse_tx_hex(&tx_hex)?,
+        input_index as usize,
+        &keystore,
+        &account,
+        &config.npub_1,
+        &config.npub_2,
+        config.npub_arbitrator.as_ref(),
+        config.timelock_duration,
+        &invariants,
+        parse_escrow_type(&escrow_script)?,
+    )?;
+    Ok(signature.to_string())
+}
+
+/// Adds the hex `signatures` of the leaf `escrow_script`, in witness order,
+/// to input `input_index` of `tx_hex`, returning the signed transaction in hex.
+#[uniffi::export]
+pub(crate) fn combine_signatures(
+    escrow: EscrowRecord,
+    tx_hex: String,
+    input_index: u32,
+    escrow_script: String,
+    signatures: Vec<String>,
+) -> Result<String, ScrowError> {
+    let config = EscrowConfig::try_from(escrow)?;
+    let escrow_script = parse_escrow_type(&escrow_script)?;
+    let tx = parse_tx_hex(&tx_hex)?;
+    let signatures = signatures
+        .iter()
+        .map(|signature| signature.parse::<schnorr::Signature>().map_err(Error::from))
+        .collect::<Result<Vec<_>, _>>()?;
+    let signers = config.signers(escrow_script)?;
+    if signatures.len() != signers.len() {
+        return Err(Error::WrongInputs(format!(
+            "Expected {} signatures, got {}",
+            signers.len(),
+            signatures.len()
+        ))
+        .into());
+    }
+    let tx = combine(
+        tx,
+        input_index as usize,
+        signatures.iter().collect(),
+        &config.script(escrow_script)?,
+        &config.spend_info()?,
+    )?;
+    Ok(consensus::serialize(&tx).to_lower_hex_string())
+}
+
+/// Broadcasts the signed `tx_hex` to every Esplora server of `esplora_urls` concurrently,
+/// returning its txid.
+///
+/// The servers are reached through the SOCKS5 `proxies`, a [proxy configuration](crate::proxy),
+/// empty to connect directly.
+#[uniffi::export(async_runtime = "tokio")]
+pub(crate) async fn broadcast(
+    tx_hex: String,
+    esplora_urls: Vec<String>,
+    proxies: String,
+) -> Result<String, ScrowError> {
+    let tx = parse_tx_hex(&tx_hex)?;
+    check_standard(&tx, None)?;
+    let proxies = ProxySettings::parse(&proxies)?;
+    let backends = esplora_urls
+        .iter()
+        .map(|url| EsploraBackend::new(url, &proxies))
+        .collect::<Result<Vec<_>, _>>()?;
+    Broadcaster::new(backends, RetryPolicy::default())
+        .broadcast(&tx)
+        .await
+        .outcome()?;
+    Ok(tx.compute_txid().to_string())
+}
+
+/// Sweeps every coin of the `npub`-derived wallet of `nsec` on `network` to `destination`
+/// at `fee_rate` sat/vB, listing the coins from `esplora_url` through the SOCKS5 `proxies`,
+/// to leave scrow entirely.
+///
+/// Returns the signed transaction hex, to [`broadcast`].
+#[uniffi::export(async_runtime = "tokio")]
+pub(crate) async fn sweep_wallet(
+    nsec: String,
+    destination: String,
+    fee_rate: u64,
+    network: String,
+    esplora_url: String,
+    proxies: String,
+) -> Result<String, ScrowError> {
+    let network = parse_network(&network)?;
+    let destination = destination
+        .parse::<Address<NetworkUnchecked>>()
+        .map_err(Error::from)?
+        .require_network(network)
+        .map_err(Error::from)?;
+    let fee_rate = FeeRate::from_sat_per_vb(fee_rate)
+        .ok_or_else(|| Error::WrongInputs(format!("Invalid fee rate {fee_rate} sat/vB")))?;
+    let client = create_client(&esplora_url, &ProxySettings::parse(&proxies)?)?;
+    let tx = wallet::sweep_wallet(
+        &client,
+        &parse_nsec(&nsec)?,
+        &destination,
+        fee_rate,
+        network,
+    )
+    .await?;
+    check_standard(&tx, None)?;
+    Ok(consensus::serialize(&tx).to_lower_hex_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::Keys;
+
+    use super::*;
+    use crate::protocol::{Role, offer};
+
+    #[test]
+    fn mobile_exports() {
+        let (keys_1, keys_2) = (Keys::generate(), Keys::generate());
+        let escrow = EscrowRecord {
+            npub_1: keys_1.public_key().to_hex(),
+            npub_2: keys_2.public_key().to_hex(),
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: "regtest".to_string(),
+            template: 1,
+        };
+        let config = EscrowConfig::try_from(escrow.clone()).unwrap();
+        assert_eq!(
+            escrow_address(escrow.clone()).unwrap(),
+            config.address().unwrap().to_string()
+        );
+        let ScrowError::Failed { code, .. } = escrow_address(EscrowRecord {
+            template: u8::MAX,
+            ..escrow
+        })
+        .unwrap_err();
+        assert_eq!(code, 306);
+
+        let offer = Offer {
+            role: Role::Buyer,
+            counterparty: Some(keys_2.public_key()),
+            amount_seller: bitcoin::Amount::ZERO,
+            ..offer(keys_1.public_key(), None)
+        };
+        let event = encode_offer(
+            serialize(&offer).unwrap(),
+            keys_1.secret_key().to_secret_hex(),
+        )
+        .unwrap();
+        let decoded = deserialize::<Offer>(&decode_offer(event).unwrap()).unwrap();
+        assert_eq!(decoded, offer);
+    }
+}
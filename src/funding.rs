@@ -0,0 +1,317 @@
+//! Checks that escrows are funded with the agreed amount.
+//!
+//! An escrow funded with the wrong amount must not be resolved as if it were right:
+//! an excess would silently go to the miners, and a shortfall makes the resolution
+//! transaction invalid.
+//! Underfunded escrows can be topped up with a [`PaymentRequest`] for the missing amount,
+//! and overfunded ones refund the excess with [`fund_escrow_tx`].
+
+use bitcoin::{Address, Amount, OutPoint, Transaction, TxIn, TxOut};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{bip21::PaymentRequest, error::Error, esplora::EsploraClient};
+
+/// How the funded amount compares to the agreed one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(tag = "status", rename_all = "snake_case")
+)]
+pub(crate) enum FundingStatus {
+    /// Nothing was paid to the escrow yet.
+    Unfunded,
+
+    /// Exactly the agreed amount was paid.
+    Exact,
+
+    /// Less than the agreed amount was paid.
+    Underfunded {
+        #[cfg_attr(
+            feature = "serde-types",
+            serde(with = "bitcoin::amount::serde::as_sat")
+        )]
+        missing: Amount,
+    },
+
+    /// More than the agreed amount was paid.
+    Overfunded {
+        #[cfg_attr(
+            feature = "serde-types",
+            serde(with = "bitcoin::amount::serde::as_sat")
+        )]
+        excess: Amount,
+    },
+}
+
+/// An output paying to the escrow address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct FundingOutput {
+    /// The escrow UTXO.
+    pub(crate) outpoint: OutPoint,
+    /// The amount it holds.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) amount: Amount,
+}
+
+/// The outputs funding an escrow, compared to the agreed amount.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct Funding {
+    /// Agreed amount.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) expected: Amount,
+    /// Outputs paying to the escrow, the original funding first and then any top-ups.
+    pub(crate) outputs: Vec<FundingOutput>,
+}
+
+impl Funding {
+    /// Creates an unfunded escrow of `expected` amount.
+    pub(crate) fn new(expected: Amount) -> Self {
+        Self {
+            expected,
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Adds the outputs of `tx` paying to `escrow_address`.
+    ///
+    /// Returns the number of outputs added.
+    pub(crate) fn add_tx(&mut self, tx: &Transaction, escrow_address: &Address) -> usize {
+        let txid = tx.compute_txid();
+        let script_pubkey = escrow_address.script_pubkey();
+        let mut added = 0;
+        for (vout, output) in tx.output.iter().enumerate() {
+            let outpoint = OutPoint::new(txid, vout as u32);
+            if output.script_pubkey != script_pubkey
+                || self.outputs.iter().any(|o| o.outpoint == outpoint)
+            {
+                continue;
+            }
+            self.outputs.push(FundingOutput {
+                outpoint,
+                amount: output.value,
+            });
+            added += 1;
+        }
+        added
+    }
+
+    /// Total amount paid to the escrow.
+    pub(crate) fn amount(&self) -> Amount {
+        self.outputs.iter().map(|output| output.amount).sum()
+    }
+
+    /// How the funded amount compares to the agreed one.
+    pub(crate) fn status(&self) -> FundingStatus {
+        let amount = self.amount();
+        if self.outputs.is_empty() {
+            FundingStatus::Unfunded
+        } else if amount < self.expected {
+            FundingStatus::Underfunded {
+                missing: self.expected - amount,
+            }
+        } else if amount > self.expected {
+            FundingStatus::Overfunded {
+                excess: amount - self.expected,
+            }
+        } else {
+            FundingStatus::Exact
+        }
+    }
+
+    /// Errors unless the escrow is funded with at least the agreed amount.
+    pub(crate) fn ensure_funded(&self) -> Result<(), Error> {
+        match self.status() {
+            FundingStatus::Exact | FundingStatus::Overfunded { .. } => Ok(()),
+            FundingStatus::Unfunded | FundingStatus::Underfunded { .. } => {
+                Err(Error::FundingMismatch {
+                    expected: self.expected,
+                    actual: self.amount(),
+                })
+            }
+        }
+    }
+
+    /// The payment request topping up an underfunded escrow, if it is underfunded.
+    pub(crate) fn top_up_request(
+        &self,
+        escrow_address: &Address,
+        escrow_id: &str,
+    ) -> Option<PaymentRequest> {
+        match self.status() {
+            FundingStatus::Underfunded { missing } => Some(PaymentRequest::escrow_funding(
+                escrow_address,
+                missing,
+                escrow_id,
+            )),
+            _ => None,
+        }
+    }
+
+    /// The [`TxOut`]s spent by a [`fund_escrow_tx`] transaction, in input order,
+    /// needed for the sighashes.
+    pub(crate) fn prevouts(&self, escrow_address: &Address) -> Vec<TxOut> {
+        self.outputs
+            .iter()
+            .map(|output| TxOut {
+                value: output.amount,
+                script_pubkey: escrow_address.script_pubkey(),
+            })
+            .collect()
+    }
+}
+
+/// Fetches the transactions of `escrow_address` from Esplora, oldest first,
+/// to add to its [`Funding`].
+pub(crate) async fn fetch_funding_txs(
+    client: &EsploraClient,
+    escrow_address: &Address,
+) -> Result<Vec<Transaction>, Error> {
+    let mut txs = Vec::new();
+    // Esplora lists the newest transactions first.
+    for tx in client
+        .get_address_txs(escrow_address, None)
+        .await?
+        .iter()
+        .rev()
+    {
+        if let Some(tx) = client.get_tx(&tx.txid).await? {
+            txs.push(tx);
+        }
+    }
+    #[cfg(debug_assertions)]
+    trace!(%escrow_address, txs = txs.len(), "escrow funding transactions");
+    Ok(txs)
+}
+
+/// Makes an escrow [`Transaction`] spend every output of the [`Funding`],
+/// refunding any excess to `excess_to`.
+///
+/// `tx` is an escrow transaction built by [`escrow_tx`](crate::tx::escrow_tx) for the agreed
+/// amounts, whose fee must also cover the inputs of any top-ups.
+/// The excess is added to the output paying `excess_to`, or to a new output if there is none.
+///
+/// # Errors
+///
+/// Errors if the escrow is underfunded.
+pub(crate) fn fund_escrow_tx(
+    mut tx: Transaction,
+    funding: &Funding,
+    excess_to: &Address,
+) -> Result<Transaction, Error> {
+    funding.ensure_funded()?;
+    let template = tx.input.first().cloned().unwrap_or_default();
+    tx.input = funding
+        .outputs
+        .iter()
+        .map(|output| TxIn {
+            previous_output: output.outpoint,
+            ..template.clone()
+        })
+        .collect();
+
+    if let FundingStatus::Overfunded { excess } = funding.status() {
+        let script_pubkey = excess_to.script_pubkey();
+        match tx
+            .output
+            .iter_mut()
+            .find(|output| output.script_pubkey == script_pubkey)
+        {
+            Some(output) => output.value += excess,
+            None => tx.output.push(TxOut {
+                value: excess,
+                script_pubkey,
+            }),
+        }
+    }
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, Txid, absolute, hashes::Hash, transaction};
+    use nostr::Keys;
+
+    use crate::{scripts::escrow_address, tx::escrow_tx, util::npub_to_address};
+
+    use super::*;
+
+    fn funding_tx(address: &Address, amounts: &[u64]) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: amounts
+                .iter()
+                .map(|amount| TxOut {
+                    value: Amount::from_sat(*amount),
+                    script_pubkey: address.script_pubkey(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn funding_status() {
+        let (npub_1, npub_2) = (Keys::generate().public_key(), Keys::generate().public_key());
+        let escrow = escrow_address(&npub_1, &npub_2, None, None, Network::Regtest).unwrap();
+        let mut funding = Funding::new(Amount::from_sat(100_000));
+        assert_eq!(funding.status(), FundingStatus::Unfunded);
+
+        let underfunding = funding_tx(&escrow, &[60_000]);
+        assert_eq!(funding.add_tx(&underfunding, &escrow), 1);
+        assert_eq!(funding.add_tx(&underfunding, &escrow), 0);
+        assert_eq!(
+            funding.status(),
+            FundingStatus::Underfunded {
+                missing: Amount::from_sat(40_000)
+            }
+        );
+        assert!(funding.ensure_funded().is_err());
+        let top_up = funding.top_up_request(&escrow, "42").unwrap();
+        assert_eq!(top_up.amount, Some(Amount::from_sat(40_000)));
+
+        funding.add_tx(&funding_tx(&escrow, &[50_000]), &escrow);
+        assert_eq!(
+            funding.status(),
+            FundingStatus::Overfunded {
+                excess: Amount::from_sat(10_000)
+            }
+        );
+        assert!(funding.top_up_request(&escrow, "42").is_none());
+
+        let unsigned = escrow_tx(
+            &npub_1,
+            &npub_2,
+            None,
+            Amount::from_sat(50_000),
+            Amount::from_sat(50_000),
+            Txid::all_zeros(),
+            Amount::from_sat(1_000),
+            Network::Regtest,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let refund_to = npub_to_address(&npub_1, Network::Regtest).unwrap();
+        let tx = fund_escrow_tx(unsigned.clone(), &funding, &refund_to).unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.input[1].sequence, unsigned.input[0].sequence);
+        assert_eq!(tx.output[0].value, Amount::from_sat(59_500));
+        assert_eq!(tx.output[1].value, unsigned.output[1].value);
+        assert_eq!(funding.prevouts(&escrow).len(), 2);
+
+        let underfunded = Funding::new(Amount::from_sat(200_000));
+        assert!(fund_escrow_tx(unsigned, &underfunded, &refund_to).is_err());
+    }
+}
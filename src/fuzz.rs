@@ -0,0 +1,293 @@
+//! Fuzz targets for the data a counterparty can hand us.
+//!
+//! Proposals, signatures, transactions and keys pasted or received over Nostr are untrusted,
+//! and a panic aborts the whole WASM app, so each target must turn any input into
+//! either a value or an [`Error`](crate::error::Error), never a panic.
+//!
+//! Every target takes raw bytes and is the body of a `fuzz_target!` of the `fuzz/` crate,
+//! fuzzed with coverage guidance by `cargo fuzz run <target>`.
+//! The module is only built for it, under the `fuzzing` cfg, and for the tests,
+//! which also run every target over fixed mutations of valid inputs on stable toolchains.
+
+use bitcoin::{Amount, Network, Transaction, TxOut, consensus};
+use nostr::{
+    Event, JsonUtil, Keys,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use secp256k1::schnorr;
+
+use crate::{
+    audit::analyze_spend,
+    decode::decode_tx,
+    protocol::{Acceptance, Offer},
+    scripts::{EscrowConfig, EscrowScript, ScriptTemplate},
+    sign::combine_signatures,
+    util::{
+        npub_to_address, npub_to_x_only_public_key, parse_escrow_type, parse_network, parse_npub,
+        parse_nsec,
+    },
+};
+
+/// Length of a [`schnorr::Signature`] in a witness target input.
+const SIGNATURE_LEN: usize = 64;
+
+/// Decodes an offer or acceptance Nostr event from JSON.
+pub fn proposal_decoding(data: &[u8]) {
+    let Ok(json) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(event) = Event::from_json(json) else {
+        return;
+    };
+    let _ = Offer::from_event(&event);
+    let _ = Acceptance::from_event(&event);
+}
+
+/// Assembles and audits the witness of an escrow spend.
+///
+/// The input is laid out as:
+///
+/// 1. the input index;
+/// 2. the escrow, see [`fuzz_config`], and the leaf;
+/// 3. the number of signatures, followed by that many 64 byte signatures;
+/// 4. the consensus-encoded [`Transaction`].
+pub fn witness_assembly(data: &[u8]) {
+    let [index, selector, count, rest @ ..] = data else {
+        return;
+    };
+    let signatures_len = usize::from(*count) * SIGNATURE_LEN;
+    if rest.len() < signatures_len {
+        return;
+    }
+    let (signatures, tx) = rest.split_at(signatures_len);
+    let signatures = signatures
+        .chunks_exact(SIGNATURE_LEN)
+        .filter_map(|signature| schnorr::Signature::from_slice(signature).ok())
+        .collect::<Vec<_>>();
+    let Ok(tx) = consensus::deserialize::<Transaction>(tx) else {
+        return;
+    };
+
+    let config = fuzz_config(*selector);
+    let escrow_script = match selector >> 4 {
+        0 => EscrowScript::A,
+        1 => EscrowScript::B,
+        _ => EscrowScript::C,
+    };
+    let _ = decode_tx(&tx, config.network, None);
+    let Ok(address) = config.address() else {
+        return;
+    };
+    let prevouts = vec![
+        TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: address.script_pubkey(),
+        };
+        tx.input.len()
+    ];
+    let _ = analyze_spend(&tx, usize::from(*index), &prevouts, &config);
+    let (Ok(script), Ok(spend_info)) = (config.script(escrow_script), config.spend_info()) else {
+        return;
+    };
+    if let Ok(signed) = combine_signatures(
+        tx,
+        usize::from(*index),
+        signatures.iter().collect(),
+        &script,
+        &spend_info,
+    ) {
+        let _ = decode_tx(&signed, config.network, Some(&prevouts));
+        let _ = analyze_spend(&signed, usize::from(*index), &prevouts, &config);
+    }
+}
+
+/// Parses keys and the other strings typed in the escrow forms.
+pub fn key_parsing(data: &[u8]) {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(npub) = parse_npub(input) {
+        let _ = npub_to_x_only_public_key(&npub);
+        let _ = npub_to_address(&npub, Network::Bitcoin);
+    }
+    if let Ok(nsec) = parse_nsec(input) {
+        let _ = nsec.public_key();
+    }
+    let _ = parse_escrow_type(input);
+    let _ = parse_network(input);
+}
+
+/// An escrow between fixed keys, picked by the low bits of `selector`:
+/// bit 0 adds an arbitrator, bit 1 a timelock, and bit 2 uses [`ScriptTemplate::V2`].
+///
+/// Inconsistent combinations are kept on purpose, as a counterparty may send them.
+fn fuzz_config(selector: u8) -> EscrowConfig {
+    EscrowConfig {
+        npub_1: fixed_npub(1),
+        npub_2: fixed_npub(2),
+        npub_arbitrator: (selector & 1 != 0).then(|| fixed_npub(3)),
+        timelock_duration: (selector & 2 != 0).then_some(144),
+        network: Network::Regtest,
+        template: if selector & 4 != 0 {
+            ScriptTemplate::V2
+        } else {
+            ScriptTemplate::V1
+        },
+    }
+}
+
+/// The public key of the secret key made of `byte` repeated.
+fn fixed_npub(byte: u8) -> NostrPublicKey {
+    let secret = NostrSecretKey::from_slice(&[byte; 32]).expect("valid secret key");
+    Keys::new(secret).public_key()
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        OutPoint, ScriptBuf, Sequence, TxIn, Witness, absolute, hex::DisplayHex,
+        transaction::Version,
+    };
+    use nostr::nips::nip19::ToBech32;
+
+    use super::*;
+    use crate::protocol::{Role, offer};
+
+    /// Mutations of each seed.
+    const ITERATIONS: usize = 500;
+
+    /// A xorshift generator, so every run mutates the seeds the same way.
+    struct Mutator(u64);
+
+    impl Mutator {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n.max(1) as u64) as usize
+        }
+
+        /// Flips, overwrites, inserts, removes or truncates a few bytes of `seed`.
+        fn mutate(&mut self, seed: &[u8]) -> Vec<u8> {
+            let mut data = seed.to_vec();
+            for _ in 0..=self.below(4) {
+                let at = self.below(data.len());
+                match self.below(5) {
+                    0 if !data.is_empty() => data[at] ^= 1 << self.below(8),
+                    1 if !data.is_empty() => data[at] = self.next() as u8,
+                    2 => data.insert(at, self.next() as u8),
+                    3 if !data.is_empty() => {
+                        data.remove(at);
+                    }
+                    _ => data.truncate(at),
+                }
+            }
+            data
+        }
+    }
+
+    /// Panics with the input to reproduce it if `target` panics on `data`.
+    fn check_no_panic(target: fn(&[u8]), data: &[u8]) {
+        if std::panic::catch_unwind(|| target(data)).is_err() {
+            panic!("Fuzz target panicked on {}", data.to_lower_hex_string());
+        }
+    }
+
+    fn fuzz(target: fn(&[u8]), seeds: &[Vec<u8>]) {
+        let mut mutator = Mutator(0x5c20_11ed);
+        for seed in seeds {
+            check_no_panic(target, seed);
+            for _ in 0..ITERATIONS {
+                check_no_panic(target, &mutator.mutate(seed));
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_targets() {
+        let keys_1 = Keys::new(NostrSecretKey::from_slice(&[1; 32]).unwrap());
+        let offer = Offer {
+            role: Role::Buyer,
+            counterparty: Some(fixed_npub(2)),
+            amount_seller: Amount::ZERO,
+            ..offer(keys_1.public_key(), Some(fixed_npub(3)))
+        };
+        let event = offer.to_event(keys_1.secret_key()).unwrap();
+        fuzz(
+            proposal_decoding,
+            &[event.as_json().into_bytes(), b"{}".to_vec(), Vec::new()],
+        );
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::from_consensus(144),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: fuzz_config(0).address().unwrap().script_pubkey(),
+            }],
+        };
+        let witness_seeds = (0..=0x27)
+            .filter(|selector| selector & 0x0f < 8)
+            .map(|selector| {
+                let mut data = vec![0, selector, 2];
+                data.extend([7; 2 * SIGNATURE_LEN]);
+                data.extend(consensus::serialize(&tx));
+                data
+            })
+            .collect::<Vec<_>>();
+        fuzz(witness_assembly, &witness_seeds);
+
+        fuzz(
+            key_parsing,
+            &[
+                keys_1.public_key().to_bech32().unwrap().into_bytes(),
+                keys_1.secret_key().to_bech32().unwrap().into_bytes(),
+                keys_1.public_key().to_hex().into_bytes(),
+                b"npub1".to_vec(),
+                b"nsec1".to_vec(),
+                b"C".to_vec(),
+                b"mutinynet".to_vec(),
+            ],
+        );
+    }
+
+    #[test]
+    fn malformed_inputs_are_errors() {
+        // Dispute leaves without an arbitrator or a timelock used to panic.
+        assert_eq!(
+            fuzz_config(1).script(EscrowScript::B).unwrap_err().code(),
+            100
+        );
+        assert_eq!(
+            fuzz_config(2).script(EscrowScript::C).unwrap_err().code(),
+            100
+        );
+
+        // So did combining into a missing input or through a script that is not a leaf.
+        let config = fuzz_config(3);
+        let spend_info = config.spend_info().unwrap();
+        let script = config.script(EscrowScript::C).unwrap();
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        assert!(combine_signatures(tx.clone(), 0, Vec::new(), &script, &spend_info).is_err());
+        tx.input.push(TxIn::default());
+        assert!(
+            combine_signatures(tx.clone(), 0, Vec::new(), &ScriptBuf::new(), &spend_info).is_err()
+        );
+        assert!(combine_signatures(tx, 0, Vec::new(), &script, &spend_info).is_ok());
+    }
+}
@@ -0,0 +1,430 @@
+//! NIP-59 gift wrapping of the protocol events sent between participants.
+//!
+//! Acceptances, cancellations, key rotations and decisions name the escrow participants
+//! in their tags, and their [`SessionId`](crate::protocol::SessionId) links them together.
+//! Published as is, they let any relay observer map who escrows with whom.
+//! [`wrap`] strips the signature off an event, seals the resulting [`rumor`] to one recipient
+//! with NIP-44 and wraps the seal in a kind 1059 event signed by a throwaway key,
+//! with a randomized timestamp, so relays only see an unknown key writing to the recipient.
+//!
+//! As NIP-59 requires, the rumor is unsigned, so a leaked rumor doesn't prove who wrote it:
+//! its author is authenticated by the seal, which [`unwrap`] checks is signed by the same key.
+//! A [`ReplayGuard`], persisted with each [`Session`](crate::protocol::Session),
+//! drops the events already received and bounds the gift wraps fetched again.
+
+use std::collections::{BTreeMap, HashSet};
+
+use nostr::{
+    Event, EventBuilder, EventId, Filter, Keys, Kind, Tag, Timestamp, UnsignedEvent,
+    key::PublicKey as NostrPublicKey,
+    nips::nip44::{self, Version},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    protocol::{deserialize, serialize},
+    secret::SecretNsec,
+};
+
+/// Range of the random backdating of seals and gift wraps, in seconds: two days.
+const TIMESTAMP_TWEAK: u64 = 2 * 24 * 60 * 60;
+
+/// Most gift wraps fetched at once, the newest ones.
+const MAX_GIFT_WRAPS: usize = 500;
+
+/// The NIP-59 rumor of `event`: the same event, with the same ID, but unsigned.
+pub(crate) fn rumor(event: &Event) -> UnsignedEvent {
+    UnsignedEvent {
+        id: Some(event.id),
+        pubkey: event.pubkey,
+        created_at: event.created_at,
+        kind: event.kind,
+        tags: event.tags.clone(),
+        content: event.content.clone(),
+    }
+}
+
+/// Seals the [`rumor`] of `event` from the author `nsec` to `recipient`,
+/// and gift wraps the seal.
+///
+/// The signature of `event` stays with its author, who tracks it by its ID.
+///
+/// # Errors
+///
+/// Errors if `event` is not signed by `nsec`.
+pub(crate) fn wrap(
+    event: &Event,
+    nsec: &SecretNsec,
+    recipient: &NostrPublicKey,
+    now: Timestamp,
+) -> Result<Event, Error> {
+    if nsec.public_key() != event.pubkey {
+        return Err(Error::Protocol(
+            "Wrapped events must be signed by their author".to_string(),
+        ));
+    }
+    let seal = nsec.with_nostr_secret_key(|secret_key| {
+        let content = nip44::encrypt(
+            secret_key,
+            recipient,
+            serialize(&rumor(event))?,
+            Version::V2,
+        )
+        .map_err(|e| Error::Protocol(format!("Could not seal event: {e}")))?;
+        Ok::<_, Error>(
+            EventBuilder::new(Kind::Seal, content)
+                .custom_created_at(tweaked(now))
+                .sign_with_keys(&Keys::new(secret_key.clone()))?,
+        )
+    })?;
+
+    let ephemeral = Keys::generate();
+    let content = nip44::encrypt(
+        ephemeral.secret_key(),
+        recipient,
+        serialize(&seal)?,
+        Version::V2,
+    )
+    .map_err(|e| Error::Protocol(format!("Could not wrap event: {e}")))?;
+    Ok(EventBuilder::new(Kind::GiftWrap, content)
+        .tag(Tag::public_key(*recipient))
+        .custom_created_at(tweaked(now))
+        .sign_with_keys(&ephemeral)?)
+}
+
+/// Gift wraps `event` to each npub it tags, and to its author for their other devices.
+pub(crate) fn wrap_to_recipients(
+    event: &Event,
+    nsec: &SecretNsec,
+    now: Timestamp,
+) -> Result<Vec<Event>, Error> {
+    let mut recipients = vec![event.pubkey];
+    let tagged = event.tags.iter().filter_map(|tag| match tag.as_slice() {
+        [kind, npub, ..] if kind == "p" => Some(npub),
+        _ => None,
+    });
+    for npub in tagged {
+        let npub = NostrPublicKey::from_hex(npub)?;
+        if !recipients.contains(&npub) {
+            recipients.push(npub);
+        }
+    }
+    recipients
+        .iter()
+        .map(|recipient| wrap(event, nsec, recipient, now))
+        .collect()
+}
+
+/// Unwraps a gift wrap addressed to `nsec`, returning the rumor it carries.
+///
+/// # Errors
+///
+/// Errors if the gift wrap is for someone else or can't be decrypted, the rumor's ID
+/// doesn't match its content, or the seal is not signed by the author of the rumor.
+pub(crate) fn unwrap(gift_wrap: &Event, nsec: &SecretNsec) -> Result<UnsignedEvent, Error> {
+    let npub = nsec.public_key();
+    if gift_wrap.kind != Kind::GiftWrap {
+        return Err(Error::Protocol(format!(
+            "Expected a gift wrap, got kind {}",
+            gift_wrap.kind
+        )));
+    }
+    gift_wrap.verify()?;
+    let recipient = Tag::public_key(npub);
+    if !gift_wrap.tags.iter().any(|tag| *tag == recipient) {
+        return Err(Error::Protocol(
+            "Gift wrap is addressed to someone else".to_string(),
+        ));
+    }
+    let undecryptable = |_| Error::Protocol("Gift wrap could not be decrypted".to_string());
+    let seal = nsec
+        .with_nostr_secret_key(|secret_key| {
+            nip44::decrypt(secret_key, &gift_wrap.pubkey, &gift_wrap.content)
+        })
+        .map_err(undecryptable)?;
+    let seal: Event = deserialize(&seal)?;
+    if seal.kind != Kind::Seal || !seal.tags.is_empty() {
+        return Err(Error::Protocol(
+            "Gift wrap does not hold a seal".to_string(),
+        ));
+    }
+    seal.verify()?;
+    let rumor = nsec
+        .with_nostr_secret_key(|secret_key| nip44::decrypt(secret_key, &seal.pubkey, &seal.content))
+        .map_err(undecryptable)?;
+    let rumor: UnsignedEvent = deserialize(&rumor)?;
+    let id = EventId::new(
+        &rumor.pubkey,
+        &rumor.created_at,
+        &rumor.kind,
+        rumor.tags.as_slice(),
+        &rumor.content,
+    );
+    if rumor.id != Some(id) {
+        return Err(Error::Protocol(
+            "Rumor ID does not match its content".to_string(),
+        ));
+    }
+    if rumor.pubkey != seal.pubkey {
+        return Err(Error::Protocol(
+            "Sealed rumor is not from the sealer".to_string(),
+        ));
+    }
+    Ok(rumor)
+}
+
+/// The [`Filter`] of the gift wraps addressed to `npub`.
+pub(crate) fn filter(npub: &NostrPublicKey) -> Filter {
+    Filter::new().kind(Kind::GiftWrap).pubkey(*npub)
+}
+
+/// The wrapped events already received, to drop the ones received again,
+/// and when their gift wraps were last fetched, to only fetch the newer ones.
+///
+/// Anyone the author wrapped an event to can wrap it again, and relays can resend
+/// old gift wraps, so the same event may arrive several times under distinct wraps.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ReplayGuard {
+    /// IDs of the rumors received.
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    seen: HashSet<EventId>,
+    /// When the gift wraps were last fetched, by kind of the rumors fetched.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    fetched: BTreeMap<u16, Timestamp>,
+}
+
+impl ReplayGuard {
+    /// Whether nothing was received or fetched yet.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.seen.is_empty() && self.fetched.is_empty()
+    }
+
+    /// Records `rumor` as received.
+    ///
+    /// # Errors
+    ///
+    /// Errors if it was already received.
+    pub(crate) fn check(&mut self, rumor: &UnsignedEvent) -> Result<(), Error> {
+        let id = rumor
+            .id
+            .ok_or_else(|| Error::Protocol("Rumor has no ID".to_string()))?;
+        if !self.seen.insert(id) {
+            return Err(Error::Protocol(format!("Event {id} was already received")));
+        }
+        Ok(())
+    }
+
+    /// The oldest gift wrap that may hold a rumor of `kinds` not fetched yet,
+    /// or `None` if one of them was never fetched.
+    ///
+    /// Gift wraps are backdated by up to [`TIMESTAMP_TWEAK`], so the ones sent since
+    /// the last fetch may be that much older than it.
+    fn since(&self, kinds: &[u16]) -> Option<Timestamp> {
+        let last_fetched = kinds
+            .iter()
+            .map(|kind| self.fetched.get(kind).copied())
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min()?;
+        Some(Timestamp::from(
+            last_fetched.as_u64().saturating_sub(TIMESTAMP_TWEAK),
+        ))
+    }
+
+    /// Records that the gift wraps holding rumors of `kinds` were fetched at `now`.
+    fn fetched(&mut self, kinds: &[u16], now: Timestamp) {
+        for kind in kinds {
+            self.fetched.insert(*kind, now);
+        }
+    }
+}
+
+/// The [`Filter`] of the gift wraps addressed to `npub` that may hold rumors matching
+/// `filter` not received yet, recording that the `guard` fetched them at `now`.
+///
+/// Only the gift wraps sent since the `guard` last fetched rumors of the kinds of `filter`
+/// are fetched, and at most [`MAX_GIFT_WRAPS`] of them.
+pub(crate) fn fetch_filter(
+    npub: &NostrPublicKey,
+    filter: &Filter,
+    guard: &mut ReplayGuard,
+    now: Timestamp,
+) -> Filter {
+    let kinds = filter
+        .kinds
+        .iter()
+        .flatten()
+        .map(|kind| kind.as_u16())
+        .collect::<Vec<_>>();
+    let mut gift_wraps = self::filter(npub).limit(MAX_GIFT_WRAPS);
+    if let Some(since) = guard.since(&kinds) {
+        gift_wraps = gift_wraps.since(since);
+    }
+    guard.fetched(&kinds, now);
+    gift_wraps
+}
+
+/// Unwraps the `gift_wraps` addressed to `nsec`, returning the rumors matching `filter`
+/// the `guard` didn't receive yet, oldest first.
+///
+/// Gift wraps that fail to unwrap are skipped.
+pub(crate) fn receive_wrapped(
+    gift_wraps: &[Event],
+    nsec: &SecretNsec,
+    filter: &Filter,
+    guard: &mut ReplayGuard,
+) -> Vec<UnsignedEvent> {
+    let mut rumors = gift_wraps
+        .iter()
+        .filter_map(|gift_wrap| unwrap(gift_wrap, nsec).ok())
+        .filter(|rumor| matches(filter, rumor))
+        .collect::<Vec<_>>();
+    rumors.sort_by_key(|rumor| rumor.created_at);
+    rumors.retain(|rumor| guard.check(rumor).is_ok());
+    rumors
+}
+
+/// Whether `rumor` matches the kinds, authors and single-letter tags of `filter`.
+fn matches(filter: &Filter, rumor: &UnsignedEvent) -> bool {
+    let matches_kind = filter
+        .kinds
+        .as_ref()
+        .is_none_or(|kinds| kinds.contains(&rumor.kind));
+    let matches_author = filter
+        .authors
+        .as_ref()
+        .is_none_or(|authors| authors.contains(&rumor.pubkey));
+    let matches_tags = filter.generic_tags.iter().all(|(name, values)| {
+        let name = name.to_string();
+        rumor.tags.iter().any(|tag| match tag.as_slice() {
+            [tag_name, value, ..] => *tag_name == name && values.contains(value),
+            _ => false,
+        })
+    });
+    matches_kind && matches_author && matches_tags
+}
+
+/// `now`, backdated by a random time of up to [`TIMESTAMP_TWEAK`],
+/// so the gift wrap's timestamp doesn't tell when it was sent.
+fn tweaked(now: Timestamp) -> Timestamp {
+    let random = Keys::generate().secret_key().secret_bytes();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&random[..8]);
+    let tweak = u64::from_le_bytes(bytes) % TIMESTAMP_TWEAK;
+    Timestamp::from(now.as_u64().saturating_sub(tweak))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{cancel::CANCELLATION_KIND, decision::DECISION_KIND};
+
+    #[test]
+    fn gift_wrap_roundtrip() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let eve = Keys::generate();
+        let [alice_nsec, bob_nsec, eve_nsec] =
+            [&alice, &bob, &eve].map(|keys| SecretNsec::from(keys.secret_key().clone()));
+        let now = Timestamp::now();
+        let event = EventBuilder::new(Kind::Custom(CANCELLATION_KIND), "{}")
+            .tags([
+                Tag::identifier("session"),
+                Tag::public_key(bob.public_key()),
+            ])
+            .sign_with_keys(&alice)
+            .unwrap();
+
+        let gift_wraps = wrap_to_recipients(&event, &alice_nsec, now).unwrap();
+        assert_eq!(gift_wraps.len(), 2);
+        let gift_wrap = &gift_wraps[1];
+        // Relays see neither the author, the session, nor when the event was sent.
+        assert_ne!(gift_wrap.pubkey, alice.public_key());
+        assert_eq!(gift_wrap.tags.len(), 1);
+        assert!(!gift_wrap.content.contains("session"));
+        assert!(gift_wrap.created_at <= now);
+        assert!(gift_wrap.created_at.as_u64() + TIMESTAMP_TWEAK > now.as_u64());
+
+        assert_eq!(unwrap(gift_wrap, &bob_nsec).unwrap(), rumor(&event));
+        assert_eq!(unwrap(&gift_wraps[0], &alice_nsec).unwrap(), rumor(&event));
+        assert!(unwrap(gift_wrap, &eve_nsec).is_err());
+        assert!(unwrap(&event, &bob_nsec).is_err());
+        assert!(wrap(&event, &eve_nsec, &bob.public_key(), now).is_err());
+
+        // Seals `rumor` from `sealer` to Bob, and gift wraps it.
+        let forge = |sealer: &Keys, rumor: &UnsignedEvent| {
+            let content = nip44::encrypt(
+                sealer.secret_key(),
+                &bob.public_key(),
+                serialize(rumor).unwrap(),
+                Version::V2,
+            )
+            .unwrap();
+            let seal = EventBuilder::new(Kind::Seal, content)
+                .sign_with_keys(sealer)
+                .unwrap();
+            let ephemeral = Keys::generate();
+            let content = nip44::encrypt(
+                ephemeral.secret_key(),
+                &bob.public_key(),
+                serialize(&seal).unwrap(),
+                Version::V2,
+            )
+            .unwrap();
+            EventBuilder::new(Kind::GiftWrap, content)
+                .tag(Tag::public_key(bob.public_key()))
+                .sign_with_keys(&ephemeral)
+                .unwrap()
+        };
+        assert!(unwrap(&forge(&alice, &rumor(&event)), &bob_nsec).is_ok());
+        // Eve can't pass her own seal off as Alice's rumor,
+        assert!(unwrap(&forge(&eve, &rumor(&event)), &bob_nsec).is_err());
+        // nor Alice's rumor under another content.
+        let mut tampered = rumor(&event);
+        tampered.content = "{\"reason\":\"forged\"}".to_string();
+        assert!(unwrap(&forge(&alice, &tampered), &bob_nsec).is_err());
+
+        // The same event wrapped twice is received once.
+        let rewrapped = wrap(&event, &alice_nsec, &bob.public_key(), now).unwrap();
+        let mut guard = ReplayGuard::default();
+        assert!(guard.check(&unwrap(gift_wrap, &bob_nsec).unwrap()).is_ok());
+        assert!(
+            guard
+                .check(&unwrap(&rewrapped, &bob_nsec).unwrap())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn replay_guard_bounds_fetches() {
+        let now = Timestamp::from(10 * TIMESTAMP_TWEAK);
+        let mut guard = ReplayGuard::default();
+        assert!(guard.is_empty());
+        assert_eq!(guard.since(&[CANCELLATION_KIND]), None);
+
+        guard.fetched(&[CANCELLATION_KIND], now);
+        // Gift wraps sent after the fetch may be backdated to before it.
+        assert_eq!(
+            guard.since(&[CANCELLATION_KIND]),
+            Some(Timestamp::from(9 * TIMESTAMP_TWEAK))
+        );
+        // Other kinds were never fetched.
+        assert_eq!(guard.since(&[CANCELLATION_KIND, DECISION_KIND]), None);
+        guard.fetched(&[DECISION_KIND], now + Duration::from_secs(60));
+        assert_eq!(
+            guard.since(&[CANCELLATION_KIND, DECISION_KIND]),
+            Some(Timestamp::from(9 * TIMESTAMP_TWEAK))
+        );
+
+        // The guard is persisted with its session.
+        let restored: ReplayGuard = deserialize(&serialize(&guard).unwrap()).unwrap();
+        assert_eq!(restored, guard);
+        assert_eq!(
+            deserialize::<ReplayGuard>("{}").unwrap(),
+            ReplayGuard::default()
+        );
+    }
+}
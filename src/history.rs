@@ -0,0 +1,375 @@
+//! Event-sourced history of an escrow session.
+//!
+//! Each [`Session`] keeps an append-only [`History`] of what happened to the escrow:
+//! the messages exchanged with the counterparty, who signed which spend, the funding,
+//! broadcasts and confirmations, cancellations, key rotations and conflicting spends.
+//! Entries are timestamped when recorded and kept in time order, so the history backs
+//! both the timeline of the escrow in the UI and its audit log, see [`Session::audit_log`].
+//!
+//! The session records messages it sends, see [`Session::send`], and the receipts
+//! acknowledging them. Other entries are recorded by the code observing them, such as
+//! the broadcaster and the watcher, with the `record_*` methods.
+
+use std::fmt::{self, Write as _};
+
+use bitcoin::{Amount, Txid};
+use nostr::{EventId, Timestamp, key::PublicKey as NostrPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    i18n::Language,
+    keys::Npub,
+    protocol::Session,
+    sign::LeafSignatures,
+    units::{Denomination, format_amount},
+};
+
+/// Something that happened to an escrow.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum HistoryEvent {
+    /// The session sent the protocol message `event_id` of `kind`.
+    MessageSent {
+        /// ID of the Nostr event.
+        event_id: EventId,
+        /// Kind of the Nostr event.
+        kind: u16,
+    },
+    /// The session received the protocol message `event_id` of `kind` from `npub`.
+    MessageReceived {
+        /// ID of the Nostr event.
+        event_id: EventId,
+        /// Kind of the Nostr event.
+        kind: u16,
+        /// Author of the message.
+        npub: NostrPublicKey,
+    },
+    /// `npub` acknowledged the message `event_id`.
+    MessageAcked {
+        /// ID of the acknowledged Nostr event.
+        event_id: EventId,
+        /// Author of the receipt.
+        npub: NostrPublicKey,
+    },
+    /// `npub` signed the spend `txid`.
+    Signed {
+        /// Signer.
+        npub: NostrPublicKey,
+        /// Spending transaction.
+        txid: Txid,
+    },
+    /// The transaction `txid` funded the escrow with `amount`.
+    Funded {
+        /// Funding transaction.
+        txid: Txid,
+        /// Amount sent to the escrow.
+        amount: Amount,
+    },
+    /// The transaction `txid` was broadcast.
+    Broadcast {
+        /// Broadcast transaction.
+        txid: Txid,
+    },
+    /// The transaction `txid` confirmed at `height`.
+    Confirmed {
+        /// Confirmed transaction.
+        txid: Txid,
+        /// Height of the block.
+        height: u32,
+    },
+    /// `npub` cancelled the escrow.
+    Cancelled {
+        /// Participant cancelling.
+        npub: NostrPublicKey,
+    },
+    /// `npub` rotated their escrow key.
+    Rotated {
+        /// Participant rotating, with their key before the rotation.
+        npub: NostrPublicKey,
+    },
+    /// The escrow was spent by `txid`, a transaction the session didn't prepare.
+    Conflict {
+        /// Conflicting transaction.
+        txid: Txid,
+    },
+    /// The unconfirmed funding was double-spent by `txid`.
+    FundingConflict {
+        /// Conflicting transaction.
+        txid: Txid,
+    },
+}
+
+impl fmt::Display for HistoryEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let npub = |npub: &NostrPublicKey| Npub::from(*npub);
+        match self {
+            HistoryEvent::MessageSent { event_id, kind } => {
+                write!(f, "Sent message {event_id} (kind {kind})")
+            }
+            HistoryEvent::MessageReceived {
+                event_id,
+                kind,
+                npub: author,
+            } => write!(
+                f,
+                "Received message {event_id} (kind {kind}) from {}",
+                npub(author)
+            ),
+            HistoryEvent::MessageAcked {
+                event_id,
+                npub: author,
+            } => write!(f, "{} acknowledged message {event_id}", npub(author)),
+            HistoryEvent::Signed { npub: signer, txid } => {
+                write!(f, "{} signed transaction {txid}", npub(signer))
+            }
+            HistoryEvent::Funded { txid, amount } => write!(
+                f,
+                "Funded with {} by transaction {txid}",
+                format_amount(*amount, Denomination::Btc, Language::En)
+            ),
+            HistoryEvent::Broadcast { txid } => write!(f, "Broadcast transaction {txid}"),
+            HistoryEvent::Confirmed { txid, height } => {
+                write!(f, "Transaction {txid} confirmed at height {height}")
+            }
+            HistoryEvent::Cancelled { npub: participant } => {
+                write!(f, "{} cancelled the escrow", npub(participant))
+            }
+            HistoryEvent::Rotated { npub: participant } => {
+                write!(f, "{} rotated their key", npub(participant))
+            }
+            HistoryEvent::Conflict { txid } => {
+                write!(f, "Escrow spent by unexpected transaction {txid}")
+            }
+            HistoryEvent::FundingConflict { txid } => {
+                write!(f, "Funding double-spent by transaction {txid}")
+            }
+        }
+    }
+}
+
+/// A [`HistoryEvent`] and when it was recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct HistoryEntry {
+    /// When the event happened.
+    pub(crate) at: Timestamp,
+    /// What happened.
+    pub(crate) event: HistoryEvent,
+}
+
+/// The history of a session, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct History {
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Whether nothing was recorded.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All entries, oldest first.
+    pub(crate) fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Records `event` at `at`, after the entries recorded at or before that time.
+    ///
+    /// Returns `false` if the same event was already recorded, such as a message
+    /// received again, and leaves the history unchanged.
+    pub(crate) fn record(&mut self, event: HistoryEvent, at: Timestamp) -> bool {
+        if self.entries.iter().any(|entry| entry.event == event) {
+            return false;
+        }
+        let index = self.entries.partition_point(|entry| entry.at <= at);
+        self.entries.insert(index, HistoryEntry { at, event });
+        true
+    }
+
+    /// Merges the entries of `other`, a history of the same escrow.
+    pub(crate) fn merge(&mut self, other: History) {
+        for entry in other.entries {
+            self.record(entry.event, entry.at);
+        }
+    }
+}
+
+impl Session {
+    /// The [`History`] of the escrow.
+    pub(crate) fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Records that the protocol message `event_id` of `kind` was received from `npub`.
+    pub(crate) fn record_received(
+        &mut self,
+        event_id: EventId,
+        kind: u16,
+        npub: NostrPublicKey,
+        now: Timestamp,
+    ) {
+        self.history.record(
+            HistoryEvent::MessageReceived {
+                event_id,
+                kind,
+                npub,
+            },
+            now,
+        );
+    }
+
+    /// Records the signers of `signatures`, once added with [`Session::receive_signatures`]
+    /// or [`Session::add_signatures`].
+    pub(crate) fn record_signatures(&mut self, signatures: &LeafSignatures, now: Timestamp) {
+        for signature in &signatures.signatures {
+            self.history.record(
+                HistoryEvent::Signed {
+                    npub: signature.npub,
+                    txid: signatures.txid,
+                },
+                now,
+            );
+        }
+    }
+
+    /// Records that the transaction `txid` funded the escrow with `amount`.
+    pub(crate) fn record_funded(&mut self, txid: Txid, amount: Amount, now: Timestamp) {
+        self.history
+            .record(HistoryEvent::Funded { txid, amount }, now);
+    }
+
+    /// Records that the transaction `txid` was broadcast.
+    pub(crate) fn record_broadcast(&mut self, txid: Txid, now: Timestamp) {
+        self.history.record(HistoryEvent::Broadcast { txid }, now);
+    }
+
+    /// Records that the transaction `txid` confirmed at `height`.
+    pub(crate) fn record_confirmation(&mut self, txid: Txid, height: u32, now: Timestamp) {
+        self.history
+            .record(HistoryEvent::Confirmed { txid, height }, now);
+    }
+
+    /// Records the cancellations, key rotations, conflict and funding conflicts of the session,
+    /// once received or detected.
+    ///
+    /// Entries already recorded are skipped, so this can run after every update.
+    pub(crate) fn record_state(&mut self, now: Timestamp) {
+        let mut events = Vec::new();
+        for cancellation in &self.cancellations {
+            events.push(HistoryEvent::Cancelled {
+                npub: cancellation.npub,
+            });
+        }
+        for rotation in &self.rotations {
+            events.push(HistoryEvent::Rotated {
+                npub: rotation.old_npub,
+            });
+        }
+        if let Some(conflict) = &self.conflict {
+            events.push(HistoryEvent::Conflict {
+                txid: conflict.spend.txid,
+            });
+        }
+        if let Some(risk) = &self.funding_risk {
+            for txid in risk.conflicting_txids() {
+                events.push(HistoryEvent::FundingConflict { txid });
+            }
+        }
+        for event in events {
+            self.history.record(event, now);
+        }
+    }
+
+    /// The history of the escrow as a plain text audit log, one entry per line
+    /// after a header naming the session.
+    pub(crate) fn audit_log(&self) -> Result<String, Error> {
+        let mut log = String::new();
+        // Writing to a `String` never fails.
+        let _ = writeln!(log, "Escrow history of session {}", self.id()?);
+        if let Some(canonical_id) = self.canonical_id() {
+            let _ = writeln!(log, "Funded escrow: {canonical_id}");
+        }
+        for entry in self.history.entries() {
+            let _ = writeln!(log, "{} {}", entry.at.to_human_datetime(), entry.event);
+        }
+        Ok(log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use nostr::Keys;
+
+    use super::*;
+    use crate::protocol::{
+        DEFAULT_OFFER_VALIDITY, Handshake, Offer, Role, deserialize, offer, serialize,
+    };
+
+    #[test]
+    fn session_history() {
+        let buyer = Keys::generate();
+        let seller = Keys::generate();
+        let now = Timestamp::from(1_700_000_000);
+        let offer = Offer {
+            role: Role::Buyer,
+            amount_seller: Amount::ZERO,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(buyer.public_key(), None)
+        };
+        let (_, offer_event) = Handshake::offer(buyer.secret_key(), offer, now).unwrap();
+        let (agreed, acceptance_event) =
+            Handshake::accept(seller.secret_key(), &offer_event, None, now).unwrap();
+        let mut session = Session::new(agreed);
+
+        let funding = Txid::from_byte_array([1; 32]);
+        let spend = Txid::from_byte_array([2; 32]);
+        session.send(acceptance_event.clone(), now);
+        session.record_confirmation(spend, 101, now + 300);
+        session.record_funded(funding, Amount::from_sat(100_000), now + 60);
+        session.record_broadcast(spend, now + 120);
+        session.record_received(
+            offer_event.id,
+            offer_event.kind.as_u16(),
+            buyer.public_key(),
+            now,
+        );
+        // The same message received again is recorded once.
+        session.record_received(
+            offer_event.id,
+            offer_event.kind.as_u16(),
+            buyer.public_key(),
+            now + 10,
+        );
+
+        let history = session.history();
+        assert_eq!(history.entries().len(), 5);
+        assert!(history.entries().windows(2).all(|w| w[0].at <= w[1].at));
+        assert_eq!(
+            history.entries()[0].event,
+            HistoryEvent::MessageSent {
+                event_id: acceptance_event.id,
+                kind: acceptance_event.kind.as_u16(),
+            }
+        );
+        assert_eq!(
+            history.entries().last().unwrap().event,
+            HistoryEvent::Confirmed {
+                txid: spend,
+                height: 101
+            }
+        );
+
+        // The history is persisted with the session.
+        let restored: Session = deserialize(&serialize(&session).unwrap()).unwrap();
+        assert_eq!(restored.history(), session.history());
+
+        let log = session.audit_log().unwrap();
+        assert!(log.starts_with("Escrow history of session"));
+        assert!(log.contains("Funded with 0.001 BTC by transaction"));
+        assert!(log.contains(&format!("Broadcast transaction {spend}")));
+        assert_eq!(log.lines().count(), 6);
+    }
+}
@@ -0,0 +1,239 @@
+//! Translations of user-facing strings.
+//!
+//! Messages live in Fluent files under `locales/`, one per [`Language`], embedded at build time
+//! and formatted by [`fluent_bundle`].
+//! Messages missing from a translation fall back to English, then to their key.
+//!
+//! The current language is process-wide, see [`set_language`], so that error messages
+//! and summaries rendered outside of components follow the language picked in the settings.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        LazyLock,
+        atomic::{AtomicU8, Ordering},
+    },
+};
+
+use fluent_bundle::{FluentArgs, FluentResource, concurrent::FluentBundle};
+use unic_langid::LanguageIdentifier;
+
+use crate::error::Error;
+
+/// A language of the UI.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Language {
+    /// English, the fallback of every other language.
+    #[default]
+    En,
+    /// Brazilian Portuguese.
+    PtBr,
+}
+
+impl Language {
+    /// Every supported language.
+    pub(crate) const ALL: [Language; 2] = [Language::En, Language::PtBr];
+
+    /// BCP 47 tag of the language, such as `pt-BR`.
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::PtBr => "pt-BR",
+        }
+    }
+
+    /// Name of the language, in the language itself.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Language::En => "English",
+            Language::PtBr => "Português (Brasil)",
+        }
+    }
+
+    /// The supported language closest to the BCP 47 `tag`, such as `pt-PT` or `en-US`.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Language::En),
+            "pt" => Some(Language::PtBr),
+            _ => None,
+        }
+    }
+
+    /// The Fluent source of the language.
+    fn source(self) -> &'static str {
+        match self {
+            Language::En => include_str!("../locales/en.ftl"),
+            Language::PtBr => include_str!("../locales/pt-BR.ftl"),
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Language {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Language::ALL
+            .into_iter()
+            .find(|language| language.code().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::WrongInputs(format!("Unsupported language {s}")))
+    }
+}
+
+/// Messages of a language.
+type Bundle = FluentBundle<FluentResource>;
+
+/// Parsed messages of every language, in the order of [`Language::ALL`].
+static BUNDLES: LazyLock<Vec<Bundle>> = LazyLock::new(|| {
+    Language::ALL
+        .iter()
+        .map(|language| parse(*language))
+        .collect()
+});
+
+/// The current language, as an index in [`Language::ALL`].
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+/// Parses the messages of `language`, which are embedded and checked by the tests.
+fn parse(language: Language) -> Bundle {
+    let resource = FluentResource::try_new(language.source().to_string())
+        .unwrap_or_else(|(_, errors)| panic!("invalid {language} messages: {errors:?}"));
+    let id = language
+        .code()
+        .parse::<LanguageIdentifier>()
+        .expect("language codes are valid");
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // Messages end up in plain text, where bidi isolation marks would show.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .unwrap_or_else(|errors| panic!("duplicate {language} messages: {errors:?}"));
+    bundle
+}
+
+/// The messages of `language`.
+fn bundle(language: Language) -> &'static Bundle {
+    &BUNDLES[language as usize]
+}
+
+/// The language user-facing strings are rendered in.
+pub(crate) fn language() -> Language {
+    Language::ALL[usize::from(CURRENT.load(Ordering::Relaxed))]
+}
+
+/// Renders user-facing strings in `language` from now on.
+pub(crate) fn set_language(language: Language) {
+    CURRENT.store(language as u8, Ordering::Relaxed);
+}
+
+/// The language of the browser if supported, English otherwise.
+pub(crate) fn detect_language() -> Language {
+    #[cfg(target_arch = "wasm32")]
+    if let Some(language) = web_sys::window()
+        .and_then(|window| window.navigator().language())
+        .and_then(|tag| Language::from_tag(&tag))
+    {
+        return language;
+    }
+    Language::default()
+}
+
+/// The message `key` in `language`.
+pub(crate) fn tr(language: Language, key: &str) -> String {
+    tr_args(language, key, &[])
+}
+
+/// The message `key` in `language`, with its `{ $name }` placeables replaced by `args`.
+///
+/// Placeables without a matching argument are rendered as `{$name}`.
+pub(crate) fn tr_args(language: Language, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let Some((bundle, pattern)) = [language, Language::En].into_iter().find_map(|language| {
+        let bundle = bundle(language);
+        let pattern = bundle.get_message(key)?.value()?;
+        Some((bundle, pattern))
+    }) else {
+        return key.to_string();
+    };
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.to_string());
+    }
+    // Missing arguments are the only possible errors, and are rendered visibly.
+    let mut errors = Vec::new();
+    bundle
+        .format_pattern(pattern, Some(&fluent_args), &mut errors)
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// The messages of a Fluent `source`, by key, as written.
+    fn messages(source: &str) -> HashMap<&str, &str> {
+        source
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect()
+    }
+
+    #[test]
+    fn translations() {
+        // Every translation has exactly the English messages, with the same placeables.
+        let placeables = |message: &str| {
+            let mut names = message
+                .split('{')
+                .skip(1)
+                .filter_map(|part| part.split_once('}'))
+                .map(|(name, _)| name.trim().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let english = messages(Language::En.source());
+        for language in Language::ALL {
+            let messages = messages(language.source());
+            assert_eq!(messages.len(), english.len(), "{language}");
+            for (key, message) in &english {
+                assert!(bundle(language).has_message(key), "{language}: {key}");
+                let translation = messages
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{language}: {key}"));
+                assert_eq!(
+                    placeables(translation),
+                    placeables(message),
+                    "{language}: {key}"
+                );
+            }
+        }
+
+        assert_eq!(tr(Language::PtBr, "nav-home"), "Início");
+        assert_eq!(
+            tr_args(Language::En, "settings-imported-follows", &[("count", &3)]),
+            "Imported 3 new contacts."
+        );
+        assert_eq!(
+            tr_args(Language::PtBr, "summary-after-blocks", &[("blocks", &144)]),
+            "após 144 blocos (~{$duration})"
+        );
+        assert_eq!(tr(Language::PtBr, "missing-key"), "missing-key");
+
+        assert_eq!(Language::from_tag("pt-PT"), Some(Language::PtBr));
+        assert_eq!(Language::from_tag("en_US"), Some(Language::En));
+        assert_eq!(Language::from_tag("fr"), None);
+        assert_eq!("pt-br".parse::<Language>().unwrap(), Language::PtBr);
+        assert!("fr".parse::<Language>().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Identity verification of Nostr users with NIP-05.
+//!
+//! A NIP-05 address `name@domain` is verified by fetching
+//! `https://domain/.well-known/nostr.json?name=name` and checking that it maps
+//! `name` to the user's public key.
+//! This lets the UI show which counterparties and arbitrators are vouched for by
+//! a domain before any funds are sent to the escrow.
+
+use std::{fmt, str::FromStr};
+
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::key::PublicKey as NostrPublicKey;
+use serde_json::Value;
+
+use crate::{contacts::Profile, error::Error, runtime::get_text};
+
+/// A NIP-05 address, `name@domain`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Nip05Address {
+    /// Local part, lowercase.
+    pub(crate) name: String,
+    /// Domain, lowercase.
+    pub(crate) domain: String,
+}
+
+impl Nip05Address {
+    /// URL of the `nostr.json` document that verifies this address.
+    pub(crate) fn url(&self) -> String {
+        format!(
+            "https://{}/.well-known/nostr.json?name={}",
+            self.domain, self.name
+        )
+    }
+
+    /// Whether a `nostr.json` document maps this address to `npub`.
+    pub(crate) fn verify_document(
+        &self,
+        npub: &NostrPublicKey,
+        document: &str,
+    ) -> Result<bool, Error> {
+        let value = serde_json::from_str::<Value>(document)
+            .map_err(|e| Error::Nip05(format!("Malformed nostr.json from {}: {e}", self.domain)))?;
+        let public_key = value
+            .get("names")
+            .and_then(|names| names.get(&self.name))
+            .and_then(Value::as_str);
+        Ok(public_key.is_some_and(|public_key| public_key.eq_ignore_ascii_case(&npub.to_hex())))
+    }
+}
+
+impl FromStr for Nip05Address {
+    type Err = Error;
+
+    /// Parses `name@domain`, or `domain` as a shorthand for `_@domain`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (name, domain) = s.split_once('@').unwrap_or(("_", s.as_str()));
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        let valid_domain = domain.contains('.')
+            && domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | ':'));
+        if !valid_name || !valid_domain {
+            return Err(Error::WrongInputs(format!(
+                "Invalid NIP-05 address \"{s}\""
+            )));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            domain: domain.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Nip05Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name == "_" {
+            write!(f, "{}", self.domain)
+        } else {
+            write!(f, "{}@{}", self.name, self.domain)
+        }
+    }
+}
+
+/// Result of verifying a user's identity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum IdentityStatus {
+    /// The user has no NIP-05 address.
+    Unverified,
+
+    /// The domain maps the address to the user.
+    Verified(Nip05Address),
+
+    /// The domain does not map the address to the user, so the claim is false.
+    Mismatch(Nip05Address),
+}
+
+/// Fetches the `nostr.json` document of a NIP-05 address.
+pub(crate) async fn fetch_nostr_json(address: &Nip05Address) -> Result<String, Error> {
+    get_text(&address.url())
+        .await
+        .map_err(|e| Error::Nip05(format!("{}: {}", address.domain, e.root_cause())))
+}
+
+/// Verifies that a NIP-05 `address` belongs to `npub`.
+pub(crate) async fn verify_nip05(
+    npub: &NostrPublicKey,
+    address: &Nip05Address,
+) -> Result<IdentityStatus, Error> {
+    let document = fetch_nostr_json(address).await?;
+    let verified = address.verify_document(npub, &document)?;
+    #[cfg(debug_assertions)]
+    trace!(%address, npub = %npub.to_hex(), verified, "NIP-05 verification");
+    Ok(if verified {
+        IdentityStatus::Verified(address.clone())
+    } else {
+        IdentityStatus::Mismatch(address.clone())
+    })
+}
+
+/// Verifies the NIP-05 address claimed in the [`Profile`] of `npub`, if any.
+pub(crate) async fn verify_profile(
+    npub: &NostrPublicKey,
+    profile: &Profile,
+) -> Result<IdentityStatus, Error> {
+    let Some(address) = profile.nip05.as_deref() else {
+        return Ok(IdentityStatus::Unverified);
+    };
+    // An unparsable address can't be verified, but it is not a network failure either.
+    match address.parse::<Nip05Address>() {
+        Ok(address) => verify_nip05(npub, &address).await,
+        Err(_) => Ok(IdentityStatus::Unverified),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::Keys;
+
+    use super::*;
+
+    #[test]
+    fn parse_nip05_address() {
+        let address = "Bob@Example.com".parse::<Nip05Address>().unwrap();
+        assert_eq!(address.name, "bob");
+        assert_eq!(address.domain, "example.com");
+        assert_eq!(
+            address.url(),
+            "https://example.com/.well-known/nostr.json?name=bob"
+        );
+        assert_eq!(address.to_string(), "bob@example.com");
+
+        let root = "example.com".parse::<Nip05Address>().unwrap();
+        assert_eq!(root.name, "_");
+        assert_eq!(root.to_string(), "example.com");
+
+        assert!("bob@".parse::<Nip05Address>().is_err());
+        assert!("bob@localhost".parse::<Nip05Address>().is_err());
+        assert!("b ob@example.com".parse::<Nip05Address>().is_err());
+        assert!("bob@example.com/path?".parse::<Nip05Address>().is_err());
+    }
+
+    #[test]
+    fn verify_nostr_json() {
+        let (bob, mallory) = (Keys::generate().public_key(), Keys::generate().public_key());
+        let address = "bob@example.com".parse::<Nip05Address>().unwrap();
+        let document = format!(
+            r#"{{"names":{{"bob":"{}","alice":"{}"}},"relays":{{}}}}"#,
+            bob.to_hex().to_uppercase(),
+            mallory.to_hex()
+        );
+        assert!(address.verify_document(&bob, &document).unwrap());
+        assert!(!address.verify_document(&mallory, &document).unwrap());
+        assert!(!address.verify_document(&bob, r#"{"names":{}}"#).unwrap());
+        assert!(address.verify_document(&bob, "<html>").is_err());
+    }
+}
@@ -0,0 +1,503 @@
+//! Sign-time invariants for escrow transactions.
+//!
+//! Last line of defense against UI or transport bugs: every check here
+//! runs on the exact [`Transaction`] about to be signed.
+//! The signing entry points of [`sign`](crate::sign) take the invariants as an argument,
+//! so no signature is produced without them.
+
+use std::fmt;
+
+use bitcoin::{
+    Amount, FeeRate, OutPoint, ScriptBuf, Sequence, Transaction, TxOut, absolute, relative,
+    transaction,
+};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, scripts::EscrowScript};
+
+/// Highest fee rate the signer accepts by default.
+pub(crate) const DEFAULT_MAX_FEE_RATE: FeeRate = FeeRate::from_sat_per_vb_unchecked(1_000);
+
+/// Outputs the signer has approved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub(crate) enum ApprovedOutputs {
+    /// Outputs must match this draft exactly, in order.
+    Draft(Vec<TxOut>),
+
+    /// Every output must pay to one of these scripts.
+    Destinations(Vec<ScriptBuf>),
+}
+
+/// Invariants a [`Transaction`] must satisfy before any signature is produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct SigningInvariants {
+    /// The contract's funding outpoints, in input order.
+    pub(crate) funding_outpoints: Vec<OutPoint>,
+    /// The outputs being spent, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// The outputs the signer has approved.
+    pub(crate) approved_outputs: ApprovedOutputs,
+    /// Highest absolute fee the signer accepts.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) max_fee: Amount,
+    /// Lock time agreed with the other signers: [`absolute::LockTime::ZERO`],
+    /// an anti-fee-sniping height or the timeout of an HTLC.
+    pub(crate) lock_time: absolute::LockTime,
+    /// Relative timelock in blocks each input must satisfy, in input order,
+    /// `None` for inputs spent without one, see [`leaf_timelock`].
+    pub(crate) timelocks: Vec<Option<u32>>,
+}
+
+/// A single broken invariant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Violation {
+    /// The inputs are not exactly the contract's funding outpoints.
+    UnexpectedInputs {
+        expected: Vec<OutPoint>,
+        found: Vec<OutPoint>,
+    },
+
+    /// The number of prevouts does not match the number of inputs.
+    PrevoutCount { inputs: usize, prevouts: usize },
+
+    /// The number of timelocks does not match the number of inputs.
+    TimelockCount { inputs: usize, timelocks: usize },
+
+    /// The outputs do not match the approved draft.
+    OutputsDiffer { expected: usize, found: usize },
+
+    /// An output pays to a script that was not approved.
+    UnapprovedOutput {
+        index: usize,
+        script_pubkey: ScriptBuf,
+    },
+
+    /// The outputs spend more than the inputs.
+    OutputsExceedInputs { inputs: Amount, outputs: Amount },
+
+    /// The fee is above the signer's policy.
+    FeeTooHigh { fee: Amount, max_fee: Amount },
+
+    /// The transaction has another absolute locktime than the agreed one.
+    AbsoluteLockTime {
+        expected: absolute::LockTime,
+        found: absolute::LockTime,
+    },
+
+    /// The transaction version does not enable relative timelocks.
+    Version(transaction::Version),
+
+    /// An input timelock is not a valid relative timelock in blocks.
+    InvalidTimelock { index: usize, timelock: u32 },
+
+    /// An input sequence does not satisfy the leaf's relative timelock.
+    Sequence {
+        index: usize,
+        sequence: Sequence,
+        timelock: u32,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::UnexpectedInputs { expected, found } => {
+                write!(
+                    f,
+                    "inputs {found:?} are not the funding outpoints {expected:?}"
+                )
+            }
+            Violation::PrevoutCount { inputs, prevouts } => {
+                write!(f, "{prevouts} prevouts for {inputs} inputs")
+            }
+            Violation::TimelockCount { inputs, timelocks } => {
+                write!(f, "{timelocks} timelocks for {inputs} inputs")
+            }
+            Violation::OutputsDiffer { expected, found } => write!(
+                f,
+                "{found} outputs do not match the {expected} approved outputs"
+            ),
+            Violation::UnapprovedOutput {
+                index,
+                script_pubkey,
+            } => write!(
+                f,
+                "output {index} pays to unapproved script {script_pubkey}"
+            ),
+            Violation::OutputsExceedInputs { inputs, outputs } => {
+                write!(f, "outputs of {outputs} exceed inputs of {inputs}")
+            }
+            Violation::FeeTooHigh { fee, max_fee } => {
+                write!(f, "fee of {fee} is above the maximum of {max_fee}")
+            }
+            Violation::AbsoluteLockTime { expected, found } => {
+                write!(f, "absolute locktime {found} is not the agreed {expected}")
+            }
+            Violation::Version(version) => {
+                write!(f, "version {version} does not enable relative timelocks")
+            }
+            Violation::InvalidTimelock { index, timelock } => {
+                write!(
+                    f,
+                    "input {index} timelock {timelock} is not a valid block timelock"
+                )
+            }
+            Violation::Sequence {
+                index,
+                sequence,
+                timelock,
+            } => write!(
+                f,
+                "input {index} sequence {sequence} does not satisfy the {timelock} blocks timelock"
+            ),
+        }
+    }
+}
+
+/// Every [`Violation`] found in a [`Transaction`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct InvariantReport {
+    /// The broken invariants, empty if the transaction is safe to sign.
+    pub(crate) violations: Vec<Violation>,
+}
+
+impl InvariantReport {
+    /// Whether no invariant was broken.
+    pub(crate) fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for InvariantReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{violation}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The relative timelock the `escrow_script` leaf of an escrow with `timelock_duration`
+/// requires of its input, for [`SigningInvariants::timelocks`].
+///
+/// # Errors
+///
+/// Errors if a dispute leaf has no timelock.
+pub(crate) fn leaf_timelock(
+    escrow_script: EscrowScript,
+    timelock_duration: Option<u32>,
+) -> Result<Option<u32>, Error> {
+    match (escrow_script, timelock_duration) {
+        (EscrowScript::A, _) => Ok(None),
+        (escrow_script, None) => Err(Error::WrongInputs(format!(
+            "Leaf {escrow_script:?} requires a timelock"
+        ))),
+        (_, timelock_duration) => Ok(timelock_duration),
+    }
+}
+
+impl SigningInvariants {
+    /// Invariants approving exactly `tx`, spending `prevouts` with the input `timelocks`.
+    ///
+    /// Only for transactions the signer built itself from terms already agreed,
+    /// such as a refund or a chain transaction, or already checked against them in full,
+    /// like a resolution a [`DisputeRecord`](crate::arbitration::DisputeRecord) allows:
+    /// the outputs, locktime and fee are taken from `tx` as they are.
+    pub(crate) fn of_local_tx(
+        tx: &Transaction,
+        prevouts: Vec<TxOut>,
+        timelocks: Vec<Option<u32>>,
+    ) -> Self {
+        let inputs = prevouts.iter().map(|txout| txout.value).sum::<Amount>();
+        let outputs = tx.output.iter().map(|txout| txout.value).sum::<Amount>();
+        Self {
+            funding_outpoints: tx.input.iter().map(|txin| txin.previous_output).collect(),
+            prevouts,
+            approved_outputs: ApprovedOutputs::Draft(tx.output.clone()),
+            max_fee: inputs.checked_sub(outputs).unwrap_or(Amount::ZERO),
+            lock_time: tx.lock_time,
+            timelocks,
+        }
+    }
+
+    /// Runs the full invariant suite against `tx` and collects every [`Violation`].
+    pub(crate) fn report(&self, tx: &Transaction) -> InvariantReport {
+        let mut violations = Vec::new();
+
+        // Inputs.
+        let found = tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>();
+        if found != self.funding_outpoints {
+            violations.push(Violation::UnexpectedInputs {
+                expected: self.funding_outpoints.clone(),
+                found,
+            });
+        }
+        if self.prevouts.len() != tx.input.len() {
+            violations.push(Violation::PrevoutCount {
+                inputs: tx.input.len(),
+                prevouts: self.prevouts.len(),
+            });
+        }
+
+        // Outputs.
+        match &self.approved_outputs {
+            ApprovedOutputs::Draft(draft) => {
+                if *draft != tx.output {
+                    violations.push(Violation::OutputsDiffer {
+                        expected: draft.len(),
+                        found: tx.output.len(),
+                    });
+                }
+            }
+            ApprovedOutputs::Destinations(scripts) => {
+                for (index, txout) in tx.output.iter().enumerate() {
+                    if !scripts.contains(&txout.script_pubkey) {
+                        violations.push(Violation::UnapprovedOutput {
+                            index,
+                            script_pubkey: txout.script_pubkey.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Fee. Overflowing sums are refused as if outputs exceeded inputs.
+        let inputs = self
+            .prevouts
+            .iter()
+            .try_fold(Amount::ZERO, |acc, txout| acc.checked_add(txout.value))
+            .unwrap_or(Amount::ZERO);
+        let outputs = tx
+            .output
+            .iter()
+            .try_fold(Amount::ZERO, |acc, txout| acc.checked_add(txout.value))
+            .unwrap_or(Amount::MAX);
+        match inputs.checked_sub(outputs) {
+            Some(fee) if fee > self.max_fee => violations.push(Violation::FeeTooHigh {
+                fee,
+                max_fee: self.max_fee,
+            }),
+            Some(_) => {}
+            None => violations.push(Violation::OutputsExceedInputs { inputs, outputs }),
+        }
+
+        // Locktime and sequence.
+        if tx.lock_time != self.lock_time {
+            violations.push(Violation::AbsoluteLockTime {
+                expected: self.lock_time,
+                found: tx.lock_time,
+            });
+        }
+        if self.timelocks.len() != tx.input.len() {
+            violations.push(Violation::TimelockCount {
+                inputs: tx.input.len(),
+                timelocks: self.timelocks.len(),
+            });
+        }
+        if self.timelocks.iter().any(Option::is_some) && tx.version < transaction::Version::TWO {
+            violations.push(Violation::Version(tx.version));
+        }
+        let timelocks = tx.input.iter().zip(&self.timelocks).enumerate();
+        for (index, (txin, timelock)) in timelocks {
+            let Some(timelock) = *timelock else {
+                continue;
+            };
+            // Wider timelocks would be masked into shorter ones by consensus.
+            match u16::try_from(timelock).map(relative::LockTime::from_height) {
+                Ok(lock) => {
+                    if !lock.is_implied_by_sequence(txin.sequence) {
+                        violations.push(Violation::Sequence {
+                            index,
+                            sequence: txin.sequence,
+                            timelock,
+                        });
+                    }
+                }
+                Err(_) => violations.push(Violation::InvalidTimelock { index, timelock }),
+            }
+        }
+
+        #[cfg(debug_assertions)]
+        trace!(violations = %violations.len(), "signing invariants checked");
+        InvariantReport { violations }
+    }
+
+    /// Runs the full invariant suite against `tx`.
+    ///
+    /// # Errors
+    ///
+    /// Errors with the full [`InvariantReport`] if any invariant is broken.
+    pub(crate) fn check(&self, tx: &Transaction) -> Result<(), Error> {
+        let report = self.report(tx);
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(Error::InvariantViolation(report))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, Txid, hashes::Hash};
+
+    use super::*;
+    use crate::{
+        scripts::escrow_address,
+        tx::escrow_tx,
+        util::{npub_to_address, parse_npub},
+    };
+
+    const NPUB_1: &str = "npub1lfsec9a40ntx0hjr9wtuchclar7xcyhrf0gngaz3vt5dhnqdndaq099v6c";
+    const NPUB_2: &str = "npub1ykkf8j4mt0z4hfz5eesqck6a9qcearxq2mlk6f78k3yxhjkpqnxqanyg69";
+    const NPUB_ARBITRATOR: &str = "npub1nckhhhcxm8usszvxt6yku6efp4fpay3saglx6yhtu8pfv3kdqhqsfn0vd7";
+
+    fn setup() -> (Transaction, SigningInvariants) {
+        let npub_1 = parse_npub(NPUB_1).unwrap();
+        let npub_2 = parse_npub(NPUB_2).unwrap();
+        let npub_arbitrator = parse_npub(NPUB_ARBITRATOR).unwrap();
+        let funding_txid = Txid::all_zeros();
+        let tx = escrow_tx(
+            &npub_1,
+            &npub_2,
+            Some(144),
+            Amount::from_sat(50_000),
+            Amount::from_sat(50_000),
+            funding_txid,
+            Amount::from_sat(500),
+            Network::Regtest,
+            bitcoin::absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let escrow_address = escrow_address(
+            &npub_1,
+            &npub_2,
+            Some(&npub_arbitrator),
+            Some(144),
+            Network::Regtest,
+        )
+        .unwrap();
+        let invariants = SigningInvariants {
+            funding_outpoints: vec![OutPoint::new(funding_txid, 0)],
+            prevouts: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: escrow_address.script_pubkey(),
+            }],
+            approved_outputs: ApprovedOutputs::Destinations(vec![
+                npub_to_address(&npub_1, Network::Regtest)
+                    .unwrap()
+                    .script_pubkey(),
+                npub_to_address(&npub_2, Network::Regtest)
+                    .unwrap()
+                    .script_pubkey(),
+            ]),
+            max_fee: Amount::from_sat(1_000),
+            lock_time: absolute::LockTime::ZERO,
+            timelocks: vec![leaf_timelock(EscrowScript::B, Some(144)).unwrap()],
+        };
+        (tx, invariants)
+    }
+
+    #[test]
+    fn escrow_tx_passes() {
+        let (tx, invariants) = setup();
+        assert!(invariants.check(&tx).is_ok());
+
+        let invariants = SigningInvariants {
+            approved_outputs: ApprovedOutputs::Draft(tx.output.clone()),
+            ..invariants
+        };
+        assert!(invariants.check(&tx).is_ok());
+        let local = SigningInvariants::of_local_tx(&tx, invariants.prevouts.clone(), vec![None]);
+        assert!(local.check(&tx).is_ok());
+    }
+
+    #[test]
+    fn tampered_tx_is_refused() {
+        let (mut tx, invariants) = setup();
+        tx.input[0].previous_output.vout = 1;
+        tx.input[0].sequence = Sequence::from_height(10);
+        tx.output[0].value = Amount::from_sat(10_000);
+        tx.output[1].script_pubkey = ScriptBuf::new();
+
+        let report = invariants.report(&tx);
+        assert_eq!(report.violations.len(), 4);
+        assert!(matches!(
+            report.violations[0],
+            Violation::UnexpectedInputs { .. }
+        ));
+        assert!(matches!(
+            report.violations[1],
+            Violation::UnapprovedOutput { index: 1, .. }
+        ));
+        assert!(matches!(report.violations[2], Violation::FeeTooHigh { .. }));
+        assert!(matches!(
+            report.violations[3],
+            Violation::Sequence { index: 0, .. }
+        ));
+        assert!(matches!(
+            invariants.check(&tx),
+            Err(Error::InvariantViolation(_))
+        ));
+    }
+
+    #[test]
+    fn collaborative_leaf_ignores_timelock() {
+        let (mut tx, invariants) = setup();
+        tx.input[0].sequence = Sequence::MAX;
+        let invariants = SigningInvariants {
+            timelocks: vec![leaf_timelock(EscrowScript::A, None).unwrap()],
+            ..invariants
+        };
+        assert!(invariants.check(&tx).is_ok());
+        assert!(leaf_timelock(EscrowScript::C, None).is_err());
+    }
+
+    #[test]
+    fn lock_times_are_checked() {
+        let (mut tx, invariants) = setup();
+        tx.lock_time = absolute::LockTime::from_height(850_000).unwrap();
+        assert!(matches!(
+            invariants.report(&tx).violations[..],
+            [Violation::AbsoluteLockTime { .. }]
+        ));
+        let agreed = SigningInvariants {
+            lock_time: tx.lock_time,
+            ..invariants.clone()
+        };
+        assert!(agreed.check(&tx).is_ok());
+
+        // A timelock over 16 bits would be masked into a much shorter one.
+        tx.input[0].sequence = Sequence::from_consensus(70_000);
+        let wide = SigningInvariants {
+            timelocks: vec![Some(70_000)],
+            ..agreed
+        };
+        assert!(matches!(
+            wide.report(&tx).violations[..],
+            [Violation::InvalidTimelock {
+                index: 0,
+                timelock: 70_000
+            }]
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+//! Nostr keys as used on Bitcoin.
+//!
+//! Escrows are built from Nostr keys, but signed and paid to with Bitcoin keys and addresses.
+//! [`Npub`] and [`SecretNsec`] hold the conversions between the `nostr` and `bitcoin` crates,
+//! so key formats are parsed and validated in one place.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{Address, Network, XOnlyPublicKey};
+use nostr::{
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+    nips::nip19::ToBech32,
+};
+use secp256k1::SECP256K1;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, secret::SecretNsec};
+
+/// Length of a bech32 `npub` or `nsec`:
+/// the human-readable part, the separator, 52 data characters and a 6 character checksum.
+const BECH32_KEY_LEN: usize = 63;
+
+/// Length of a hex public or secret key.
+const HEX_KEY_LEN: usize = 64;
+
+/// A Nostr public key, displayed as an `npub`.
+///
+/// Parses from an `npub` or a hex string, see [`FromStr`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(transparent)
+)]
+pub(crate) struct Npub(NostrPublicKey);
+
+impl Npub {
+    /// The [`XOnlyPublicKey`] of the key, as used in Taproot scripts.
+    pub(crate) fn x_only_public_key(&self) -> Result<XOnlyPublicKey, Error> {
+        Ok(self.0.xonly()?)
+    }
+
+    /// The P2TR key path spend [`Address`] of the key on `network`.
+    pub(crate) fn address(&self, network: Network) -> Result<Address, Error> {
+        Ok(Address::p2tr(
+            SECP256K1,
+            self.x_only_public_key()?,
+            None,
+            network,
+        ))
+    }
+
+    /// The key in hex.
+    pub(crate) fn to_hex(self) -> String {
+        self.0.to_hex()
+    }
+
+    /// The underlying [`NostrPublicKey`].
+    pub(crate) fn nostr(&self) -> NostrPublicKey {
+        self.0
+    }
+}
+
+impl From<NostrPublicKey> for Npub {
+    fn from(npub: NostrPublicKey) -> Self {
+        Self(npub)
+    }
+}
+
+impl From<XOnlyPublicKey> for Npub {
+    fn from(public_key: XOnlyPublicKey) -> Self {
+        Self(public_key.into())
+    }
+}
+
+impl From<Npub> for NostrPublicKey {
+    fn from(npub: Npub) -> Self {
+        npub.0
+    }
+}
+
+impl FromStr for Npub {
+    type Err = Error;
+
+    /// Parses an `npub` or a hex key.
+    ///
+    /// Surrounding whitespace is ignored.
+    /// A secret key, a wrong length or a bad bech32 checksum is an error.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        check_key_format(s, "npub", "nsec")?;
+        Ok(Self(NostrPublicKey::parse(s)?))
+    }
+}
+
+impl fmt::Display for Npub {
+    /// Falls back to hex in the unlikely case bech32 encoding fails.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.to_bech32() {
+            Ok(npub) => f.write_str(&npub),
+            Err(_) => f.write_str(&self.0.to_hex()),
+        }
+    }
+}
+
+impl SecretNsec {
+    /// The [`Npub`] of the secret key.
+    pub(crate) fn npub(&self) -> Npub {
+        self.public_key().into()
+    }
+}
+
+impl FromStr for SecretNsec {
+    type Err = Error;
+
+    /// Parses an `nsec` or a hex key.
+    ///
+    /// Surrounding whitespace is ignored.
+    /// A public key, a wrong length or a bad bech32 checksum is an error.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        check_key_format(s, "nsec", "npub")?;
+        Ok(NostrSecretKey::parse(s)?.into())
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl<'de> Deserialize<'de> for SecretNsec {
+    /// Parses an `nsec` or a hex key string, see [`FromStr`],
+    /// without holding the key in an intermediate [`String`] when the input allows it.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// Visits the key string.
+        struct NsecVisitor;
+
+        impl serde::de::Visitor<'_> for NsecVisitor {
+            type Value = SecretNsec;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an nsec or a hex secret key")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<SecretNsec, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(NsecVisitor)
+    }
+}
+
+/// Checks that `input` is a bech32 key with the human-readable part `hrp`
+/// or a hex key, before decoding it.
+///
+/// Errors never echo `input`, as it may be a secret key.
+fn check_key_format(input: &str, hrp: &str, other_hrp: &str) -> Result<(), Error> {
+    let lowercase = input.to_ascii_lowercase();
+    if lowercase.starts_with(other_hrp) {
+        return Err(Error::WrongInputs(format!(
+            "Expected an {hrp}, got an {other_hrp}"
+        )));
+    }
+    if lowercase.starts_with(&format!("{hrp}1")) {
+        if input.len() != BECH32_KEY_LEN {
+            return Err(Error::WrongInputs(format!(
+                "An {hrp} is {BECH32_KEY_LEN} characters long, got {}",
+                input.len()
+            )));
+        }
+        return Ok(());
+    }
+    if input.len() != HEX_KEY_LEN || !input.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::WrongInputs(format!(
+            "Expected an {hrp} or a {HEX_KEY_LEN} character hex key"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Amount, TxOut, Txid, absolute, hashes::Hash};
+    use secp256k1::{Parity, schnorr};
+
+    use super::*;
+    use crate::{
+        accounts::Keystore,
+        audit::analyze_spend,
+        invariants::SigningInvariants,
+        scripts::{EscrowConfig, EscrowScript, ScriptTemplate},
+        sign::{combine_signatures, key_spend_message, sign_escrow_tx, sign_resolution_tx},
+        tx::resolution_tx,
+    };
+
+    #[test]
+    fn key_conversions() {
+        let npub = "npub1tv7hxxwtw4gcz4n6fpduads7lsmynh5pjedgfhvdctnulrz9rsksjx28xe";
+        let hex = "5b3d7319cb755181567a485bceb61efc3649de81965a84dd8dc2e7cf8c451c2d";
+        let parsed = npub.parse::<Npub>().unwrap();
+        assert_eq!(parsed, hex.parse::<Npub>().unwrap());
+        assert_eq!(parsed.to_string(), npub);
+        assert_eq!(parsed.to_hex(), hex);
+        assert_eq!(parsed.x_only_public_key().unwrap().to_string(), hex);
+        assert_eq!(
+            Npub::from(parsed.x_only_public_key().unwrap()),
+            Npub::from(parsed.nostr())
+        );
+        assert_eq!(
+            parsed.address(Network::Bitcoin).unwrap().to_string(),
+            "bc1pdx0h0xkeyhx79ethugtrutlxvcswffcwa9sx823dyn09wkexdwass7v98m"
+        );
+
+        let nsec = "nsec103m6x7a369k95rhtdn5w5mxsdpgyqprnysdtvhe6m0ef5xuz9d6s6emzda";
+        let secret = nsec.parse::<SecretNsec>().unwrap();
+        assert_eq!(
+            secret.npub().to_hex(),
+            "2d7b3d8028c474251676708ec41f12100685b200ccbb394e5e782d73b233a8eb"
+        );
+        assert_eq!(
+            secret.npub().x_only_public_key().unwrap(),
+            secret.x_only_public_key()
+        );
+    }
+
+    #[test]
+    fn odd_parity_keys() {
+        let nsec_1 = SecretNsec::generate_with_parity(Parity::Odd);
+        let nsec_2 = SecretNsec::generate_with_parity(Parity::Odd);
+        let (npub_1, npub_2) = (nsec_1.npub(), nsec_2.npub());
+
+        // The npub is the x-only key of the nsec, whatever the parity of its point.
+        let keypair_key = nsec_1.with_keypair(|keypair| keypair.x_only_public_key().0);
+        assert_eq!(npub_1.x_only_public_key().unwrap(), keypair_key);
+        let address = npub_1.address(Network::Regtest).unwrap();
+        assert_eq!(
+            address,
+            Address::p2tr(SECP256K1, keypair_key, None, Network::Regtest)
+        );
+
+        // A key path signature of the odd nsec verifies against the npub's address.
+        let amount = Amount::from_sat(100_000);
+        let funding_txid = Txid::from_byte_array([1; 32]);
+        let prevout = TxOut {
+            value: amount,
+            script_pubkey: address.script_pubkey(),
+        };
+        let destination = npub_2.address(Network::Regtest).unwrap();
+        let fee = Amount::from_sat(1_000);
+        let lock_time = absolute::LockTime::ZERO;
+        let unsigned = resolution_tx(amount, funding_txid, 0, &destination, fee, lock_time);
+        let invariants =
+            SigningInvariants::of_local_tx(&unsigned, vec![prevout.clone()], vec![None]);
+        let mut keystore = Keystore::default();
+        let account_1 = keystore.unlock(nsec_1);
+        let account_2 = keystore.unlock(nsec_2);
+        let signed = sign_resolution_tx(&unsigned, &keystore, &account_1, &invariants).unwrap();
+        let signature =
+            schnorr::Signature::from_slice(signed.input[0].witness.nth(0).unwrap()).unwrap();
+        let output_key =
+            XOnlyPublicKey::from_slice(&address.script_pubkey().as_bytes()[2..]).unwrap();
+        let message = key_spend_message(&unsigned, 0, &[prevout]).unwrap();
+        SECP256K1
+            .verify_schnorr(&signature, &message, &output_key)
+            .unwrap();
+
+        // So do script path signatures against the escrow scripts built from the npubs.
+        let config = EscrowConfig {
+            npub_1: npub_1.nostr(),
+            npub_2: npub_2.nostr(),
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: ScriptTemplate::V1,
+        };
+        let prevouts = vec![TxOut {
+            value: amount,
+            script_pubkey: config.address().unwrap().script_pubkey(),
+        }];
+        let unsigned = resolution_tx(amount, funding_txid, 0, &destination, fee, lock_time);
+        let invariants = SigningInvariants::of_local_tx(&unsigned, prevouts.clone(), vec![None]);
+        let [signature_1, signature_2] = [account_1, account_2].map(|account| {
+            sign_escrow_tx(
+                &unsigned,
+                0,
+                &keystore,
+                &account,
+                &config.npub_1,
+                &config.npub_2,
+                None,
+                None,
+                &invariants,
+                EscrowScript::A,
+            )
+            .unwrap()
+        });
+        let signed = combine_signatures(
+            unsigned,
+            0,
+            vec![&signature_1, &signature_2],
+            &config.script(EscrowScript::A).unwrap(),
+            &config.spend_info().unwrap(),
+        )
+        .unwrap();
+        let audit = analyze_spend(&signed, 0, &prevouts, &config).unwrap();
+        assert_eq!(audit.signers.len(), 2);
+        assert!(audit.signers.iter().all(|signer| signer.valid));
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn npub_serde_is_nostr_hex() {
+        let npub = SecretNsec::generate().npub();
+        let json = serde_json::to_string(&npub).unwrap();
+        assert_eq!(json, serde_json::to_string(&npub.nostr()).unwrap());
+        assert_eq!(serde_json::from_str::<Npub>(&json).unwrap(), npub);
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn nsec_deserializes_without_echoing() {
+        let nsec = SecretNsec::generate();
+        let hex = nsec.with_nostr_secret_key(|secret_key| secret_key.to_secret_hex());
+        let parsed = serde_json::from_str::<SecretNsec>(&format!("\"{hex}\"")).unwrap();
+        assert_eq!(parsed.public_key(), nsec.public_key());
+
+        let npub = serde_json::to_string(&nsec.npub().to_string()).unwrap();
+        assert!(serde_json::from_str::<SecretNsec>(&npub).is_err());
+        let wrong = format!("\"{}\"", &hex[1..]);
+        let e = serde_json::from_str::<SecretNsec>(&wrong).unwrap_err();
+        assert!(!e.to_string().contains(&hex[1..]));
+    }
+}
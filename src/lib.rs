@@ -0,0 +1,249 @@
+//! Satoshi Escrow Dixous App
+//!
+//! The whole app is this library, so benchmarks can link against it,
+//! and the `scrow` binary only [`launch`]es it.
+
+#![allow(non_snake_case)]
+
+use dioxus::prelude::*;
+
+// Only the benchmarks use criterion.
+#[cfg(test)]
+use criterion as _;
+#[cfg(debug_assertions)]
+use dioxus::logger::{
+    self,
+    tracing::{Level, info},
+};
+
+#[cfg(feature = "serde-types")]
+pub(crate) mod accounting;
+pub(crate) mod accounts;
+pub(crate) mod adaptor;
+pub(crate) mod address_book;
+#[cfg(feature = "serde-types")]
+pub(crate) mod api;
+pub(crate) mod arbitration;
+pub(crate) mod arbitrators;
+pub(crate) mod audit;
+#[cfg(feature = "serde-types")]
+pub(crate) mod backup;
+pub mod bench;
+pub(crate) mod bip21;
+pub(crate) mod bond;
+pub(crate) mod broadcast;
+pub(crate) mod cancel;
+pub(crate) mod canonical;
+pub(crate) mod chain;
+pub(crate) mod cofunding;
+pub(crate) mod components;
+pub(crate) mod contacts;
+#[cfg(all(feature = "serde-types", not(target_arch = "wasm32")))]
+pub(crate) mod daemon;
+#[cfg(feature = "serde-types")]
+pub(crate) mod dashboard;
+pub(crate) mod decision;
+pub(crate) mod decode;
+#[cfg(feature = "serde-types")]
+pub(crate) mod diagnostics;
+pub(crate) mod draft;
+pub(crate) mod error;
+pub(crate) mod esplora;
+pub(crate) mod expiry;
+pub(crate) mod export;
+pub(crate) mod faucet;
+#[cfg(feature = "uniffi")]
+pub(crate) mod ffi;
+pub(crate) mod funding;
+// Public to the `cargo fuzz` harness in `fuzz/`, which sets the `fuzzing` cfg.
+#[cfg(any(fuzzing, test))]
+pub mod fuzz;
+pub(crate) mod gift_wrap;
+#[cfg(feature = "serde-types")]
+pub(crate) mod history;
+pub(crate) mod i18n;
+pub(crate) mod identity;
+pub(crate) mod invariants;
+pub(crate) mod keys;
+pub(crate) mod logging;
+pub(crate) mod mempool;
+pub(crate) mod message;
+pub(crate) mod musig;
+#[cfg(test)]
+pub(crate) mod mutinynet;
+pub(crate) mod network;
+pub(crate) mod notifications;
+pub(crate) mod offline;
+pub(crate) mod oracle;
+pub(crate) mod package;
+pub(crate) mod payjoin;
+pub(crate) mod platform_fee;
+pub(crate) mod policy;
+pub(crate) mod price;
+pub(crate) mod protocol;
+pub(crate) mod proxy;
+#[cfg(feature = "serde-types")]
+pub(crate) mod receipts;
+pub(crate) mod recovery;
+pub(crate) mod relays;
+pub(crate) mod reputation;
+pub(crate) mod rotation;
+pub(crate) mod runtime;
+pub(crate) mod scripts;
+pub(crate) mod secret;
+pub(crate) mod settings;
+pub(crate) mod sign;
+pub(crate) mod silent_payments;
+pub(crate) mod standardness;
+pub(crate) mod storage;
+pub(crate) mod summary;
+pub(crate) mod swap;
+pub(crate) mod templates;
+#[cfg(test)]
+pub(crate) mod test_vectors;
+#[cfg(any(test, feature = "testkit"))]
+pub(crate) mod testkit;
+pub(crate) mod trust;
+pub(crate) mod tx;
+pub(crate) mod units;
+pub(crate) mod util;
+#[cfg(feature = "serde-types")]
+pub(crate) mod vault;
+pub(crate) mod wallet;
+pub(crate) mod watch;
+#[cfg(all(feature = "serde-types", not(target_arch = "wasm32")))]
+pub(crate) mod webhooks;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+use components::{Broadcast, Combine, Create, Home, Navbar, Settings, Sign, Spend};
+
+#[derive(Debug, Clone, Routable, PartialEq)]
+#[rustfmt::skip]
+enum Route {
+    #[layout(Navbar)]
+        #[route("/")]
+        Home {},
+        #[route("/create")]
+        Create {},
+        #[route("/sign")]
+        Sign {},
+        #[route("/combine")]
+        Combine {},
+        #[route("/broadcast")]
+        Broadcast {},
+        #[route("/spend")]
+        Spend {},
+        #[route("/settings")]
+        Settings {},
+}
+
+const FAVICON: Asset = asset!("/assets/favicon.ico");
+const TAILWIND_CSS: Asset = asset!("/assets/tailwind.css");
+const LOGO: Asset = asset!("/assets/logo.svg");
+const THEME_CSS: Asset = asset!("/assets/theme.css");
+
+/// The saved settings, the defaults if none were saved
+static SETTINGS: GlobalSignal<settings::Settings> =
+    Global::new(|| settings::Settings::load(&storage::LocalStorage).unwrap_or_default());
+
+/// The network, the saved default one at start
+static NETWORK: GlobalSignal<String> = Global::new(|| SETTINGS.peek().network.name().to_string());
+
+/// The esplora endpoint, the saved default network's at start
+static ESPLORA_ENDPOINT: GlobalSignal<String> =
+    Global::new(|| SETTINGS.peek().network.esplora_endpoint().to_string());
+
+/// The Nostr relays, one per line, the saved default ones at start
+static RELAYS: GlobalSignal<String> = Global::new(|| SETTINGS.peek().relays_config());
+
+/// The SOCKS5 proxy configuration, the saved one at start
+static PROXIES: GlobalSignal<String> = Global::new(|| SETTINGS.peek().proxies.clone());
+
+/// The UI language, the browser's by default
+static LANGUAGE: GlobalSignal<i18n::Language> = Global::new(i18n::detect_language);
+
+/// The registered accounts, the saved ones at start
+#[cfg(feature = "serde-types")]
+static ACCOUNTS: GlobalSignal<accounts::Accounts> =
+    Global::new(|| accounts::Accounts::load(&storage::LocalStorage).unwrap_or_default());
+
+/// The secrets of the unlocked accounts, none at start
+static KEYSTORE: GlobalSignal<accounts::Keystore> = Global::new(accounts::Keystore::default);
+
+/// How often the app refreshes the watched escrows while it is open
+#[cfg(feature = "serde-types")]
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Launches the app, or the `scrowd` daemon when invoked as such.
+pub fn launch() {
+    // `scrowd`, or `scrow daemon`, serves the API instead of the web app
+    #[cfg(all(feature = "serde-types", not(target_arch = "wasm32")))]
+    if daemon::invoked() {
+        if let Err(e) = daemon::DaemonConfig::from_env().and_then(daemon::run) {
+            eprintln!("scrowd: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    #[cfg(debug_assertions)]
+    {
+        // init logger for Dioxus
+        logger::init(Level::INFO).expect("failed to init logger");
+    }
+    // launch the web app
+    #[cfg(debug_assertions)]
+    info!("Launching Satoshi Escrow app");
+    dioxus::launch(App);
+}
+
+/// Refreshes the watched escrows every [`WATCH_INTERVAL`], notifying the user of what changed.
+#[cfg(feature = "serde-types")]
+async fn watch_escrows() {
+    #[cfg(target_arch = "wasm32")]
+    let notifier = notifications::BrowserNotifier;
+    #[cfg(not(target_arch = "wasm32"))]
+    let notifier = notifications::DesktopNotifier;
+    let mut watcher = notifications::EscrowWatcher::new(notifier);
+    loop {
+        let client = proxy::ProxySettings::parse(&PROXIES.peek())
+            .and_then(|proxies| esplora::create_client(&ESPLORA_ENDPOINT.peek(), &proxies));
+        // Notifications the browser refuses, such as before the user allows them, are dropped.
+        if let Ok(client) = client {
+            watcher
+                .refresh(
+                    &client,
+                    &storage::LocalStorage,
+                    nostr::Timestamp::now(),
+                    LANGUAGE(),
+                )
+                .await;
+        }
+        watcher
+            .notify_signatures(
+                &storage::LocalStorage,
+                &KEYSTORE.peek(),
+                nostr::Timestamp::now(),
+                LANGUAGE(),
+            )
+            .ok();
+        runtime::sleep(WATCH_INTERVAL).await;
+    }
+}
+
+#[component]
+fn App() -> Element {
+    // Render messages built outside components, such as errors, in the UI language
+    use_effect(|| i18n::set_language(LANGUAGE()));
+    // Notify of the watched escrows while the app is open
+    #[cfg(feature = "serde-types")]
+    use_future(watch_escrows);
+
+    rsx! {
+        document::Link { rel: "icon", href: FAVICON }
+        document::Link { rel: "stylesheet", href: TAILWIND_CSS }
+        document::Link { rel: "stylesheet", href: THEME_CSS }
+        div { class: "min-h-screen {SETTINGS().theme.class()}", Router::<Route> {} }
+    }
+}
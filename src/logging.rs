@@ -0,0 +1,96 @@
+//! Logging policy.
+//!
+//! Log lines must never leak secret material, so secrets are only logged wrapped in
+//! [`Redacted`], and transactions through their [`TxSummary`] rather than their hex.
+//! Negotiation log lines are recorded in a [`session_span`] so the lines of an escrow
+//! can be correlated.
+#![cfg_attr(not(debug_assertions), allow(dead_code))]
+
+use std::fmt;
+
+use bitcoin::Transaction;
+use dioxus::logger::tracing::{Span, info_span};
+
+/// What a [`Redacted`] value is logged as.
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// A value that is masked when formatted, with [`Debug`](fmt::Debug) and [`Display`](fmt::Display)
+/// both printing [`REDACTED`].
+///
+/// Use it for `nsec`s, key pairs, and any user input that could hold them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Redacted<T>(pub(crate) T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// A [`Transaction`] formatted as its txid, size and number of inputs and outputs.
+///
+/// Unlike the transaction hex, the summary holds no witness data.
+#[derive(Clone, Copy)]
+pub(crate) struct TxSummary<'a>(pub(crate) &'a Transaction);
+
+impl fmt::Debug for TxSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for TxSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} inputs, {} outputs, {} vB)",
+            self.0.compute_txid(),
+            self.0.input.len(),
+            self.0.output.len(),
+            self.0.vsize()
+        )
+    }
+}
+
+/// A span that tags every log line recorded in it with the escrow `session_id`.
+pub(crate) fn session_span(session_id: &impl fmt::Display) -> Span {
+    info_span!("session", id = %session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute, transaction};
+    use nostr::Keys;
+
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let keys = Keys::generate();
+        let nsec = keys.secret_key().to_secret_hex();
+        assert_eq!(Redacted(&nsec).to_string(), REDACTED);
+        assert_eq!(format!("{:?}", Redacted(&nsec)), REDACTED);
+        assert!(!format!("{:?}", Some(Redacted(&nsec))).contains(&nsec));
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        assert_eq!(
+            TxSummary(&tx).to_string(),
+            format!(
+                "{} (0 inputs, 0 outputs, {} vB)",
+                tx.compute_txid(),
+                tx.vsize()
+            )
+        );
+    }
+}
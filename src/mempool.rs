@@ -0,0 +1,233 @@
+//! Mempool monitoring of unconfirmed funding transactions.
+//!
+//! Until the funding transaction confirms, the funder can still take the coins back
+//! by double-spending its inputs, with a replace-by-fee bump if the funding signals it,
+//! or through a miner accepting any conflicting transaction otherwise.
+//! A seller shipping against an unconfirmed funding must know it is reversible:
+//! [`check_funding`] flags replaceable fundings and spends of their inputs by other
+//! transactions as a [`FundingRisk`], which the [`Session`](crate::protocol::Session)
+//! keeps until the funding confirms.
+use bitcoin::{OutPoint, Transaction, Txid};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, ResultExt},
+    esplora::EsploraClient,
+};
+
+/// A spend of an input of the funding transaction by another transaction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct FundingConflict {
+    /// The funding input spent.
+    pub(crate) outpoint: OutPoint,
+    /// The conflicting transaction.
+    pub(crate) txid: Txid,
+    /// Whether the conflicting transaction confirmed, so the funding never will.
+    pub(crate) confirmed: bool,
+}
+
+/// Why an unconfirmed funding transaction may never confirm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct FundingRisk {
+    /// The funding transaction at risk.
+    pub(crate) funding_txid: Txid,
+    /// Whether the funding signals replace-by-fee.
+    pub(crate) replaceable: bool,
+    /// Spends of the funding inputs by other transactions.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub(crate) conflicts: Vec<FundingConflict>,
+}
+
+impl FundingRisk {
+    /// The risk of the unconfirmed `funding_tx` given the `spends` of its inputs,
+    /// [`None`] if it doesn't signal replace-by-fee and no other transaction spends its inputs.
+    pub(crate) fn detect(funding_tx: &Transaction, spends: &[FundingConflict]) -> Option<Self> {
+        let funding_txid = funding_tx.compute_txid();
+        let conflicts = spends
+            .iter()
+            .filter(|spend| {
+                spend.txid != funding_txid
+                    && funding_tx
+                        .input
+                        .iter()
+                        .any(|input| input.previous_output == spend.outpoint)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        let replaceable = funding_tx.is_explicitly_rbf();
+        if !replaceable && conflicts.is_empty() {
+            return None;
+        }
+        #[cfg(debug_assertions)]
+        trace!(%funding_txid, replaceable, ?conflicts, "funding at risk");
+        Some(Self {
+            funding_txid,
+            replaceable,
+            conflicts,
+        })
+    }
+
+    /// Whether another transaction spends the funding inputs,
+    /// so the funding is being reversed, not only reversible.
+    pub(crate) fn is_double_spent(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+
+    /// Whether a conflicting transaction confirmed, so the funding never will.
+    pub(crate) fn is_reversed(&self) -> bool {
+        self.conflicts.iter().any(|conflict| conflict.confirmed)
+    }
+
+    /// The conflicting transactions, without duplicates.
+    pub(crate) fn conflicting_txids(&self) -> Vec<Txid> {
+        let mut txids = Vec::new();
+        for conflict in &self.conflicts {
+            if !txids.contains(&conflict.txid) {
+                txids.push(conflict.txid);
+            }
+        }
+        txids
+    }
+
+    /// Describes the risk for the UI.
+    pub(crate) fn description(&self) -> String {
+        let txids = self
+            .conflicting_txids()
+            .iter()
+            .map(Txid::to_string)
+            .collect::<Vec<_>>();
+        if self.is_reversed() {
+            format!(
+                "Funding {} was reversed by {}",
+                self.funding_txid,
+                txids.join(" and ")
+            )
+        } else if self.is_double_spent() {
+            let how = if self.replaceable {
+                "replaced by fee"
+            } else {
+                "double-spent"
+            };
+            format!(
+                "Funding {} is being {how} by {}, don't ship until it confirms",
+                self.funding_txid,
+                txids.join(" and ")
+            )
+        } else {
+            format!(
+                "Funding {} signals replace-by-fee, don't ship until it confirms",
+                self.funding_txid
+            )
+        }
+    }
+}
+
+/// Checks the mempool for the [`FundingRisk`] of the unconfirmed `funding_tx`:
+/// whether it signals replace-by-fee, and which transactions spend its inputs instead.
+///
+/// Returns [`None`] once the funding confirmed, or if nothing threatens it.
+pub(crate) async fn check_funding(
+    client: &EsploraClient,
+    funding_tx: &Transaction,
+) -> Result<Option<FundingRisk>, Error> {
+    let funding_txid = funding_tx.compute_txid();
+    let confirmed = client
+        .get_tx_status(&funding_txid)
+        .await
+        .context("fetching the funding status")?
+        .confirmed;
+    if confirmed {
+        return Ok(None);
+    }
+    let mut spends = Vec::new();
+    for input in &funding_tx.input {
+        let outpoint = input.previous_output;
+        let status = client
+            .get_output_status(&outpoint.txid, u64::from(outpoint.vout))
+            .await
+            .context(format!("fetching the status of {outpoint}"))?;
+        let Some(status) = status.filter(|status| status.spent) else {
+            continue;
+        };
+        if let Some(txid) = status.txid {
+            spends.push(FundingConflict {
+                outpoint,
+                txid,
+                confirmed: status.status.is_some_and(|status| status.confirmed),
+            });
+        }
+    }
+    Ok(FundingRisk::detect(funding_tx, &spends))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Amount, ScriptBuf, Sequence, TxIn, TxOut, absolute, hashes::Hash, transaction};
+
+    use super::*;
+
+    #[test]
+    fn funding_risk() {
+        let outpoint = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let mut funding_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                sequence: Sequence::MAX,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let funding_txid = funding_tx.compute_txid();
+        // Spent by the funding itself, nothing to worry about.
+        let own_spend = FundingConflict {
+            outpoint,
+            txid: funding_txid,
+            confirmed: false,
+        };
+        assert_eq!(FundingRisk::detect(&funding_tx, &[own_spend]), None);
+
+        let conflict = FundingConflict {
+            outpoint,
+            txid: Txid::from_byte_array([2; 32]),
+            confirmed: false,
+        };
+        let unrelated = FundingConflict {
+            outpoint: OutPoint::new(Txid::from_byte_array([3; 32]), 0),
+            ..conflict
+        };
+        let risk = FundingRisk::detect(&funding_tx, &[conflict, unrelated]).unwrap();
+        assert!(!risk.replaceable);
+        assert_eq!(risk.conflicts, vec![conflict]);
+        assert!(risk.is_double_spent() && !risk.is_reversed());
+        assert!(risk.description().contains("double-spent"));
+
+        let confirmed = FundingConflict {
+            confirmed: true,
+            ..conflict
+        };
+        let risk = FundingRisk::detect(&funding_tx, &[confirmed]).unwrap();
+        assert!(risk.is_reversed());
+        assert_eq!(risk.conflicting_txids(), vec![conflict.txid]);
+
+        // A replaceable funding is at risk before anything conflicts.
+        funding_tx.input[0].sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+        let risk = FundingRisk::detect(&funding_tx, &[]).unwrap();
+        assert!(risk.replaceable && !risk.is_double_spent());
+        assert!(risk.description().contains("replace-by-fee"));
+        let risk = FundingRisk::detect(&funding_tx, &[conflict]).unwrap();
+        assert!(risk.description().contains("replaced by fee"));
+    }
+}
@@ -0,0 +1,247 @@
+//! Message signing and verification with Nostr keys.
+//!
+//! [`sign_message`] and [`verify_message`] are plain BIP-340 Schnorr signatures
+//! by the `nsec` over a tagged hash of the message, for authenticating escrow terms.
+//! [`sign_bip322_simple`] and [`verify_bip322_simple`] implement the BIP-322 simple proof
+//! for the P2TR key path address of an `npub`, for authenticating payout addresses
+//! with any wallet that supports BIP-322.
+
+use bitcoin::{
+    Address, Amount, OutPoint, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut,
+    Witness, XOnlyPublicKey, absolute,
+    base64::{Engine, engine::general_purpose::STANDARD},
+    consensus,
+    hashes::{Hash, HashEngine, sha256},
+    key::TapTweak,
+    opcodes::{OP_0, all::OP_RETURN},
+    script::Builder,
+    sighash::{Prevouts, SighashCache},
+    taproot, transaction,
+};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::key::PublicKey as NostrPublicKey;
+use secp256k1::{Message, SECP256K1, schnorr};
+
+use crate::{
+    error::Error,
+    secret::SecretNsec,
+    util::{npub_to_x_only_public_key, verify_address_ownership},
+};
+
+/// Tag of the message hash signed by [`sign_message`].
+const MESSAGE_TAG: &[u8] = b"scrow/message";
+
+/// Tag of the message hash committed to by BIP-322 proofs.
+const BIP322_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// BIP-340 tagged hash of `message`.
+pub(crate) fn tagged_hash(tag: &[u8], message: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine.input(message);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Signs `message` with a [`SecretNsec`], consuming it.
+pub(crate) fn sign_message(nsec: SecretNsec, message: &str) -> schnorr::Signature {
+    let digest = Message::from_digest(tagged_hash(MESSAGE_TAG, message.as_bytes()));
+    nsec.with_keypair(|keypair| SECP256K1.sign_schnorr_no_aux_rand(&digest, keypair))
+}
+
+/// Verifies a `signature` of `message` made by [`sign_message`] with the `npub`'s secret key.
+///
+/// # Errors
+///
+/// Errors if the `npub` is invalid or the `signature` does not match.
+pub(crate) fn verify_message(
+    npub: &NostrPublicKey,
+    message: &str,
+    signature: &schnorr::Signature,
+) -> Result<(), Error> {
+    let x_only_pk = npub_to_x_only_public_key(npub)?;
+    let digest = Message::from_digest(tagged_hash(MESSAGE_TAG, message.as_bytes()));
+    SECP256K1.verify_schnorr(signature, &digest, &x_only_pk)?;
+    Ok(())
+}
+
+/// The BIP-322 virtual `to_spend` and `to_sign` [`Transaction`]s of `message` for `script_pubkey`.
+fn bip322_txs(script_pubkey: ScriptBuf, message: &str) -> (Transaction, Transaction) {
+    let message_hash = tagged_hash(BIP322_TAG, message.as_bytes());
+    let to_spend = Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new()
+                .push_opcode(OP_0)
+                .push_slice(message_hash)
+                .into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey,
+        }],
+    };
+    let to_sign = Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.compute_txid(), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    };
+    (to_spend, to_sign)
+}
+
+/// Sighash of the BIP-322 `to_sign` [`Transaction`] spending `to_spend`.
+fn bip322_sighash(
+    to_spend: &Transaction,
+    to_sign: &Transaction,
+    sighash_type: TapSighashType,
+) -> Result<Message, Error> {
+    let sighash = SighashCache::new(to_sign).taproot_key_spend_signature_hash(
+        0,
+        &Prevouts::All(to_spend.output.as_slice()),
+        sighash_type,
+    )?;
+    Ok(Message::from_digest(sighash.to_byte_array()))
+}
+
+/// Signs `message` for the P2TR key path `address` of a [`SecretNsec`], consuming it,
+/// and returns a base64-encoded BIP-322 simple proof.
+///
+/// # Errors
+///
+/// Errors if `address` is not the key path address of the `nsec`.
+pub(crate) fn sign_bip322_simple(
+    nsec: SecretNsec,
+    address: &Address,
+    message: &str,
+) -> Result<String, Error> {
+    verify_address_ownership(address, &nsec.public_key())?;
+
+    let (to_spend, to_sign) = bip322_txs(address.script_pubkey(), message);
+    let digest = bip322_sighash(&to_spend, &to_sign, TapSighashType::Default)?;
+    let signature = nsec.with_keypair(|keypair| {
+        let mut tweaked = keypair.tap_tweak(SECP256K1, None).to_inner();
+        let signature = SECP256K1.sign_schnorr_no_aux_rand(&digest, &tweaked);
+        tweaked.non_secure_erase();
+        signature
+    });
+    #[cfg(debug_assertions)]
+    trace!(%address, %signature, "BIP-322 simple signature");
+
+    let mut witness = Witness::new();
+    witness.push(signature.as_ref());
+    Ok(STANDARD.encode(consensus::serialize(&witness)))
+}
+
+/// Verifies a base64-encoded BIP-322 simple `proof` of `message` for a P2TR key path `address`.
+///
+/// # Errors
+///
+/// Errors if `address` is not P2TR, the `proof` can't be decoded, or the signature does not match.
+pub(crate) fn verify_bip322_simple(
+    address: &Address,
+    message: &str,
+    proof: &str,
+) -> Result<(), Error> {
+    let script_pubkey = address.script_pubkey();
+    if !script_pubkey.is_p2tr() {
+        return Err(Error::WrongInputs(format!(
+            "BIP-322 simple proofs are only supported for P2TR addresses, got {address}"
+        )));
+    }
+    let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])?;
+
+    let witness = STANDARD
+        .decode(proof)
+        .ok()
+        .and_then(|bytes| consensus::deserialize::<Witness>(&bytes).ok())
+        .ok_or_else(|| Error::WrongInputs("Invalid BIP-322 proof encoding".to_string()))?;
+    let signature = match witness.len() {
+        1 => taproot::Signature::from_slice(&witness[0])
+            .map_err(|e| Error::WrongInputs(format!("Invalid BIP-322 signature: {e}")))?,
+        n => {
+            return Err(Error::WrongInputs(format!(
+                "Expected a single key path signature in the BIP-322 proof, got {n} witness elements"
+            )));
+        }
+    };
+
+    let (to_spend, to_sign) = bip322_txs(script_pubkey, message);
+    let digest = bip322_sighash(&to_spend, &to_sign, signature.sighash_type)?;
+    SECP256K1.verify_schnorr(&signature.signature, &digest, &output_key)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, hex::DisplayHex};
+
+    use crate::util::npub_to_address;
+
+    use super::*;
+
+    fn generate_nostr_keys() -> (SecretNsec, NostrPublicKey) {
+        let nsec = SecretNsec::generate();
+        let npub = nsec.public_key();
+        (nsec, npub)
+    }
+
+    #[test]
+    fn bip322_message_hash() {
+        // Test vectors from BIP-322.
+        assert_eq!(
+            tagged_hash(BIP322_TAG, b"")
+                .as_slice()
+                .to_lower_hex_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            tagged_hash(BIP322_TAG, b"Hello World")
+                .as_slice()
+                .to_lower_hex_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn sign_and_verify_message() {
+        let (nsec, npub) = generate_nostr_keys();
+        let (_, other) = generate_nostr_keys();
+        let message = "escrow of 0.01 BTC, 144 blocks timelock";
+
+        let signature = sign_message(nsec, message);
+        assert!(verify_message(&npub, message, &signature).is_ok());
+        assert!(verify_message(&npub, "escrow of 1 BTC", &signature).is_err());
+        assert!(verify_message(&other, message, &signature).is_err());
+    }
+
+    #[test]
+    fn sign_and_verify_bip322_simple() {
+        let (nsec, npub) = generate_nostr_keys();
+        let (_, other) = generate_nostr_keys();
+        let address = npub_to_address(&npub, Network::Testnet).unwrap();
+        let other_address = npub_to_address(&other, Network::Testnet).unwrap();
+        let message = "payout address for escrow 42";
+
+        let proof = sign_bip322_simple(nsec.duplicate(), &address, message).unwrap();
+        assert!(verify_bip322_simple(&address, message, &proof).is_ok());
+        assert!(verify_bip322_simple(&address, "other message", &proof).is_err());
+        assert!(verify_bip322_simple(&other_address, message, &proof).is_err());
+        assert!(verify_bip322_simple(&address, message, "not base64!").is_err());
+        assert!(sign_bip322_simple(nsec, &other_address, message).is_err());
+    }
+}
@@ -0,0 +1,1127 @@
+//! MuSig2 ([BIP-327](https://github.com/bitcoin/bips/blob/master/bip-0327.mediawiki))
+//! key aggregation and signing, for cooperative key path spends of escrows.
+//!
+//! Escrows with [`ScriptTemplate::V2`](crate::scripts::ScriptTemplate::V2) use the aggregate
+//! of both participants' keys as the Taproot internal key, so when they agree the escrow is spent
+//! with a single signature and none of the leaves, including the arbitrator's, are revealed.
+//!
+//! Signing takes two rounds, exchanging nonces and then partial signatures between participants,
+//! such as through the `scrowd` daemon:
+//!
+//! 1. Each participant creates a nonce with [`generate_nonce`] and shares the [`PublicNonce`].
+//! 2. Each participant signs with [`partial_sign`] on the [`AggregateNonce`] of both public nonces,
+//!    and shares the [`PartialSignature`], which are combined with [`aggregate_signatures`].
+//!
+//! Between the rounds, [`commit_nonce`] keeps the [`SecretNonce`] in the encrypted session
+//! store, and [`sign_with_stored_nonce`] removes it before signing, so it is used at most once.
+//!
+//! Nostr keys are x-only, so each key is lifted to its even point and the keys are sorted
+//! before aggregation, as with BIP-327's `KeySort`.
+//! An aggregate nonce at infinity, which honest signers only produce with negligible probability,
+//! is rejected instead of being replaced by the generator.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{
+    TapNodeHash, TapTweakHash,
+    hex::{DisplayHex, FromHex},
+};
+use nostr::key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey};
+use secp256k1::{
+    Keypair, Message, Parity, PublicKey, SECP256K1, Scalar, SecretKey, XOnlyPublicKey,
+    constants::CURVE_ORDER, schnorr,
+};
+
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error, logging::REDACTED, message::tagged_hash, secret::SecretNsec,
+    util::npub_to_x_only_public_key,
+};
+#[cfg(feature = "serde-types")]
+use crate::{
+    storage::Storage,
+    vault::{VaultStorage, locked},
+};
+
+/// [`Storage`] key prefix of the secret nonces kept between the two signing rounds,
+/// encrypted by the [`Vault`](crate::vault::Vault), see [`commit_nonce`].
+#[cfg(feature = "serde-types")]
+pub(crate) const NONCE_KEY_PREFIX: &str = "scrow.musig_nonce.";
+
+/// Size in bytes of a serialized pair of nonces.
+const NONCE_SIZE: usize = 66;
+
+/// Hashes `data` with `tag` into a scalar, reduced modulo the curve order.
+///
+/// # Errors
+///
+/// Errors if the scalar is zero.
+fn hash_to_scalar(tag: &[u8], data: &[u8]) -> Result<SecretKey, Error> {
+    let mut hash = tagged_hash(tag, data);
+    // The hash is below twice the curve order, so one subtraction reduces it.
+    if hash >= CURVE_ORDER {
+        let mut borrow = false;
+        for (byte, order) in hash.iter_mut().zip(CURVE_ORDER).rev() {
+            let (difference, overflow) = byte.overflowing_sub(order);
+            let (difference, borrow_overflow) = difference.overflowing_sub(u8::from(borrow));
+            *byte = difference;
+            borrow = overflow || borrow_overflow;
+        }
+    }
+    Ok(SecretKey::from_slice(&hash)?)
+}
+
+/// `a + b mod n`.
+fn add(a: SecretKey, b: SecretKey) -> Result<SecretKey, Error> {
+    Ok(a.add_tweak(&Scalar::from(b))?)
+}
+
+/// `a * b mod n`.
+fn mul(a: SecretKey, b: SecretKey) -> Result<SecretKey, Error> {
+    Ok(a.mul_tweak(&Scalar::from(b))?)
+}
+
+/// The plain public key of `npub`, which is the even point of its x-only key.
+///
+/// A Nostr key does not tell the parity of its point, so BIP-327 lifts it to the even one,
+/// and signers whose point is odd negate their secret key, see [`partial_sign`].
+fn plain_public_key(npub: &NostrPublicKey) -> Result<PublicKey, Error> {
+    Ok(npub_to_x_only_public_key(npub)?.public_key(Parity::Even))
+}
+
+/// The secret key of the plain public key of `keypair`'s npub, see [`plain_public_key`].
+fn even_secret_key(keypair: &Keypair) -> SecretKey {
+    let secret_key = keypair.secret_key();
+    if keypair.x_only_public_key().1 == Parity::Odd {
+        secret_key.negate()
+    } else {
+        secret_key
+    }
+}
+
+/// MuSig2 aggregate of the participants' keys, optionally tweaked into a Taproot output key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KeyAggContext {
+    /// The participants' keys, sorted.
+    keys: Vec<PublicKey>,
+    /// The key aggregation coefficient of each key.
+    coefficients: Vec<SecretKey>,
+    /// The aggregate key, used as Taproot internal key.
+    internal_key: PublicKey,
+    /// The internal key tweaked with the script tree, or the internal key if untweaked.
+    output_key: PublicKey,
+    /// The Taproot tweak, if any.
+    tweak: Option<SecretKey>,
+    /// Whether the internal key was negated before tweaking, as it had an odd Y coordinate.
+    internal_key_negated: bool,
+}
+
+impl KeyAggContext {
+    /// Aggregates the keys of `npubs`, in any order.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `npubs` is empty or any of them is invalid.
+    pub(crate) fn new(npubs: &[NostrPublicKey]) -> Result<Self, Error> {
+        let mut keys = npubs
+            .iter()
+            .map(plain_public_key)
+            .collect::<Result<Vec<_>, _>>()?;
+        keys.sort_by_key(|key| key.serialize());
+        Self::aggregate(keys)
+    }
+
+    /// BIP-327's `KeyAgg` of the plain public `keys`, in the given order.
+    fn aggregate(keys: Vec<PublicKey>) -> Result<Self, Error> {
+        if keys.is_empty() {
+            return Err(Error::MuSig("no keys to aggregate".to_string()));
+        }
+        let list = keys
+            .iter()
+            .flat_map(|key| key.serialize())
+            .collect::<Vec<_>>();
+        let list_hash = tagged_hash(b"KeyAgg list", &list);
+        // The second distinct key gets a coefficient of one.
+        let second = keys.iter().find(|key| **key != keys[0]).copied();
+        let one = SecretKey::from_slice(&Scalar::ONE.to_be_bytes())?;
+        let coefficients = keys
+            .iter()
+            .map(|key| {
+                if Some(*key) == second {
+                    return Ok(one);
+                }
+                let mut data = list_hash.to_vec();
+                data.extend_from_slice(&key.serialize());
+                hash_to_scalar(b"KeyAgg coefficient", &data)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let weighted = keys
+            .iter()
+            .zip(&coefficients)
+            .map(|(key, coefficient)| key.mul_tweak(SECP256K1, &Scalar::from(*coefficient)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let internal_key = PublicKey::combine_keys(&weighted.iter().collect::<Vec<_>>())?;
+
+        Ok(Self {
+            keys,
+            coefficients,
+            internal_key,
+            output_key: internal_key,
+            tweak: None,
+            internal_key_negated: false,
+        })
+    }
+
+    /// Tweaks the aggregate key with the script tree `merkle_root`, as in
+    /// [BIP-341](https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki),
+    /// so signatures are valid for the key path of the Taproot output.
+    pub(crate) fn with_merkle_root(self, merkle_root: Option<TapNodeHash>) -> Result<Self, Error> {
+        let (internal_key, parity) = self.internal_key.x_only_public_key();
+        let tweak = TapTweakHash::from_key_and_tweak(internal_key, merkle_root).to_scalar();
+        let internal_key_negated = parity == Parity::Odd;
+        let even_internal_key = if internal_key_negated {
+            self.internal_key.negate(SECP256K1)
+        } else {
+            self.internal_key
+        };
+        let output_key = even_internal_key.add_exp_tweak(SECP256K1, &tweak)?;
+        Ok(Self {
+            output_key,
+            tweak: Some(SecretKey::from_slice(&tweak.to_be_bytes())?),
+            internal_key_negated,
+            ..self
+        })
+    }
+
+    /// The aggregate key, to be used as Taproot internal key.
+    pub(crate) fn internal_key(&self) -> XOnlyPublicKey {
+        self.internal_key.x_only_public_key().0
+    }
+
+    /// The key the aggregate signature is valid for.
+    pub(crate) fn output_key(&self) -> XOnlyPublicKey {
+        self.output_key.x_only_public_key().0
+    }
+
+    /// The key aggregation coefficient of `key`.
+    fn coefficient(&self, key: &PublicKey) -> Result<SecretKey, Error> {
+        self.keys
+            .iter()
+            .position(|k| k == key)
+            .map(|index| self.coefficients[index])
+            .ok_or_else(|| Error::MuSig("the key is not part of the aggregate".to_string()))
+    }
+
+    /// Whether the signers' secret keys must be negated, so the signature is valid
+    /// for the even output key.
+    fn negate_secret_keys(&self) -> bool {
+        let (_, parity) = self.output_key.x_only_public_key();
+        (parity == Parity::Odd) != self.internal_key_negated
+    }
+
+    /// `nsec`'s share of the secret key of the even output key: its secret key,
+    /// negated as needed, times its coefficient.
+    ///
+    /// The shares of every signer plus the [`KeyAggContext::output_tweak`] add up to the
+    /// secret key of the even output key. Erase the share once used.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `nsec` is not one of the aggregated keys.
+    pub(crate) fn signer_secret(&self, nsec: &SecretNsec) -> Result<SecretKey, Error> {
+        nsec.with_keypair(|keypair| {
+            // Nostr keys are x-only, so the aggregated key is the even one.
+            let mut d = even_secret_key(keypair);
+            let share = self.secret_share(d);
+            d.non_secure_erase();
+            share
+        })
+    }
+
+    /// The share of the secret key `d` of one of the aggregated plain public keys.
+    fn secret_share(&self, d: SecretKey) -> Result<SecretKey, Error> {
+        let a = self.coefficient(&d.public_key(SECP256K1))?;
+        let mut d = if self.negate_secret_keys() {
+            d.negate()
+        } else {
+            d
+        };
+        let share = mul(a, d);
+        d.non_secure_erase();
+        share
+    }
+
+    /// The public key of `npub`'s [`KeyAggContext::signer_secret`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if `npub` is not one of the aggregated keys.
+    pub(crate) fn signer_key(&self, npub: &NostrPublicKey) -> Result<PublicKey, Error> {
+        self.key_share(plain_public_key(npub)?)
+    }
+
+    /// The public key of the share of one of the aggregated plain public keys.
+    fn key_share(&self, key: PublicKey) -> Result<PublicKey, Error> {
+        let a = self.coefficient(&key)?;
+        let key = if self.negate_secret_keys() {
+            key.negate(SECP256K1)
+        } else {
+            key
+        };
+        Ok(key.mul_tweak(SECP256K1, &Scalar::from(a))?)
+    }
+
+    /// The Taproot tweak's share of the secret key of the even output key, if tweaked.
+    pub(crate) fn output_tweak(&self) -> Option<SecretKey> {
+        let negate = self.output_key.x_only_public_key().1 == Parity::Odd;
+        self.tweak
+            .map(|tweak| if negate { tweak.negate() } else { tweak })
+    }
+
+    /// The nonce coefficient `b`, the final nonce `R` and the challenge `e` of a signing session.
+    fn session(
+        &self,
+        aggregate_nonce: &AggregateNonce,
+        message: &Message,
+    ) -> Result<(SecretKey, PublicKey, SecretKey), Error> {
+        let output_key = self.output_key().serialize();
+        let mut data = aggregate_nonce.serialize().to_vec();
+        data.extend_from_slice(&output_key);
+        data.extend_from_slice(message.as_ref());
+        let b = hash_to_scalar(b"MuSig/noncecoef", &data)?;
+
+        let [r_1, r_2] = aggregate_nonce.0;
+        let r = r_1.combine(&r_2.mul_tweak(SECP256K1, &Scalar::from(b))?)?;
+
+        let mut data = r.x_only_public_key().0.serialize().to_vec();
+        data.extend_from_slice(&output_key);
+        data.extend_from_slice(message.as_ref());
+        let e = hash_to_scalar(b"BIP0340/challenge", &data)?;
+        Ok((b, r, e))
+    }
+}
+
+/// A pair of secret nonces for a single signing session.
+///
+/// It is not [`Clone`], it is only stored encrypted and removed before use,
+/// see [`commit_nonce`], and [`partial_sign`] consumes it, since reusing a nonce leaks
+/// the secret key.
+pub(crate) struct SecretNonce([SecretKey; 2]);
+
+#[cfg(feature = "serde-types")]
+impl SecretNonce {
+    /// The hex serialization of the nonce pair, to store it encrypted between the rounds.
+    fn to_hex(&self) -> String {
+        let mut bytes = [0; 64];
+        bytes[..32].copy_from_slice(&self.0[0].secret_bytes());
+        bytes[32..].copy_from_slice(&self.0[1].secret_bytes());
+        let hex = bytes.to_lower_hex_string();
+        bytes.fill(0);
+        hex
+    }
+
+    /// Parses a nonce pair serialized by [`SecretNonce::to_hex`].
+    fn from_hex(s: &str) -> Result<Self, Error> {
+        let mut bytes = <[u8; 64]>::from_hex(s)
+            .map_err(|_| Error::MuSig("a secret nonce must be 64 bytes of hex".to_string()))?;
+        let nonce = SecretKey::from_slice(&bytes[..32])
+            .and_then(|k_1| Ok(Self([k_1, SecretKey::from_slice(&bytes[32..])?])));
+        bytes.fill(0);
+        Ok(nonce?)
+    }
+
+    /// The public nonce to share with the other signers.
+    fn public_nonce(&self) -> PublicNonce {
+        PublicNonce(
+            self.0
+                .map(|nonce| PublicKey::from_secret_key(SECP256K1, &nonce)),
+        )
+    }
+}
+
+impl fmt::Debug for SecretNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SecretNonce").field(&REDACTED).finish()
+    }
+}
+
+impl Drop for SecretNonce {
+    fn drop(&mut self) {
+        for nonce in &mut self.0 {
+            nonce.non_secure_erase();
+        }
+    }
+}
+
+/// A pair of public nonces, shared with the other signers before signing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PublicNonce([PublicKey; 2]);
+
+/// The sum of every signer's [`PublicNonce`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AggregateNonce([PublicKey; 2]);
+
+/// Serializes a pair of nonces as two compressed points.
+fn serialize_nonces(nonces: &[PublicKey; 2]) -> [u8; NONCE_SIZE] {
+    let mut bytes = [0; NONCE_SIZE];
+    bytes[..33].copy_from_slice(&nonces[0].serialize());
+    bytes[33..].copy_from_slice(&nonces[1].serialize());
+    bytes
+}
+
+/// Parses a pair of nonces from its hex serialization.
+fn parse_nonces(s: &str) -> Result<[PublicKey; 2], Error> {
+    let bytes = <[u8; NONCE_SIZE]>::from_hex(s)
+        .map_err(|_| Error::MuSig(format!("a nonce must be {NONCE_SIZE} bytes of hex")))?;
+    Ok([
+        PublicKey::from_slice(&bytes[..33])?,
+        PublicKey::from_slice(&bytes[33..])?,
+    ])
+}
+
+impl PublicNonce {
+    /// The 66-byte serialization of the nonce.
+    pub(crate) fn serialize(&self) -> [u8; NONCE_SIZE] {
+        serialize_nonces(&self.0)
+    }
+}
+
+impl fmt::Display for PublicNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.serialize().to_lower_hex_string())
+    }
+}
+
+impl FromStr for PublicNonce {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_nonces(s).map(Self)
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl Serialize for PublicNonce {
+    /// Serializes the nonce as hex, see [`PublicNonce::serialize`].
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl<'de> Deserialize<'de> for PublicNonce {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl AggregateNonce {
+    /// Sums the public nonces of every signer.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `nonces` is empty or the nonces cancel out.
+    pub(crate) fn sum(nonces: &[PublicNonce]) -> Result<Self, Error> {
+        if nonces.is_empty() {
+            return Err(Error::MuSig("no nonces to aggregate".to_string()));
+        }
+        let sum = |i: usize| {
+            PublicKey::combine_keys(&nonces.iter().map(|nonce| &nonce.0[i]).collect::<Vec<_>>())
+        };
+        Ok(Self([sum(0)?, sum(1)?]))
+    }
+
+    /// The 66-byte serialization of the nonce.
+    pub(crate) fn serialize(&self) -> [u8; NONCE_SIZE] {
+        serialize_nonces(&self.0)
+    }
+}
+
+/// A signer's share of the aggregate signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PartialSignature(SecretKey);
+
+impl fmt::Display for PartialSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.secret_bytes().to_lower_hex_string())
+    }
+}
+
+impl FromStr for PartialSignature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = <[u8; 32]>::from_hex(s)
+            .map_err(|_| Error::MuSig("a partial signature must be 32 bytes of hex".to_string()))?;
+        Ok(Self(SecretKey::from_slice(&bytes)?))
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl Serialize for PartialSignature {
+    /// Serializes the partial signature as 32 bytes of hex.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl<'de> Deserialize<'de> for PartialSignature {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Generates a fresh nonce pair for signing `message` with `nsec` under `context`.
+///
+/// The nonces mix fresh randomness with the secret key, the aggregate key and the message,
+/// so a weak random number generator alone does not lead to nonce reuse.
+pub(crate) fn generate_nonce(
+    nsec: &SecretNsec,
+    context: &KeyAggContext,
+    message: &Message,
+) -> Result<(SecretNonce, PublicNonce), Error> {
+    let rand = NostrSecretKey::generate().secret_bytes();
+    nsec.with_keypair(|keypair| {
+        let mut d = even_secret_key(keypair);
+        let nonce = nonce_gen(
+            rand,
+            Some(&d.secret_bytes()),
+            &d.public_key(SECP256K1).serialize(),
+            Some(&context.output_key().serialize()),
+            Some(message.as_ref()),
+            None,
+        );
+        d.non_secure_erase();
+        nonce
+    })
+}
+
+/// BIP-327's `NonceGen` from the randomness `rand`, the signer's secret and plain public key,
+/// the aggregate key, the message and extra input.
+fn nonce_gen(
+    mut rand: [u8; 32],
+    secret_key: Option<&[u8; 32]>,
+    public_key: &[u8; 33],
+    aggregate_key: Option<&[u8; 32]>,
+    message: Option<&[u8]>,
+    extra_input: Option<&[u8]>,
+) -> Result<(SecretNonce, PublicNonce), Error> {
+    if let Some(secret_key) = secret_key {
+        let aux = tagged_hash(b"MuSig/aux", &rand);
+        for ((byte, secret), aux) in rand.iter_mut().zip(secret_key).zip(aux) {
+            *byte = secret ^ aux;
+        }
+    }
+    let aggregate_key = aggregate_key.map_or(&[][..], |key| &key[..]);
+    let extra_input = extra_input.unwrap_or_default();
+
+    let mut data = rand.to_vec();
+    rand.fill(0);
+    data.push(public_key.len() as u8);
+    data.extend_from_slice(public_key);
+    data.push(aggregate_key.len() as u8);
+    data.extend_from_slice(aggregate_key);
+    match message {
+        Some(message) => {
+            data.push(1);
+            data.extend_from_slice(&(message.len() as u64).to_be_bytes());
+            data.extend_from_slice(message);
+        }
+        None => data.push(0),
+    }
+    data.extend_from_slice(&(extra_input.len() as u32).to_be_bytes());
+    data.extend_from_slice(extra_input);
+
+    let mut nonce = |i: u8| {
+        data.push(i);
+        let nonce = hash_to_scalar(b"MuSig/nonce", &data);
+        data.pop();
+        nonce
+    };
+    let nonces = (nonce(0), nonce(1));
+    data.fill(0);
+    let secret_nonce = SecretNonce([nonces.0?, nonces.1?]);
+    let public_nonce = PublicNonce(
+        secret_nonce
+            .0
+            .map(|nonce| PublicKey::from_secret_key(SECP256K1, &nonce)),
+    );
+    Ok((secret_nonce, public_nonce))
+}
+
+/// [`Storage`] key of the secret nonce of `nsec` for signing `message`.
+#[cfg(feature = "serde-types")]
+fn nonce_key(nsec: &SecretNsec, message: &Message) -> String {
+    format!(
+        "{NONCE_KEY_PREFIX}{}.{}",
+        nsec.public_key().to_hex(),
+        message.as_ref().to_lower_hex_string()
+    )
+}
+
+/// First signing round: generates a nonce for signing `message` with `nsec` under `context`,
+/// keeping its secret in the encrypted `storage` for [`sign_with_stored_nonce`].
+///
+/// Returns the [`PublicNonce`] to share with the other signer.
+/// Committing again replaces the stored nonce, so only the last public nonce can be signed with.
+///
+/// # Errors
+///
+/// Errors if `storage` does not encrypt, such as before the session store has a passphrase.
+#[cfg(feature = "serde-types")]
+pub(crate) fn commit_nonce<S: Storage>(
+    storage: &VaultStorage<'_, S>,
+    nsec: &SecretNsec,
+    context: &KeyAggContext,
+    message: &Message,
+) -> Result<PublicNonce, Error> {
+    if !storage.encrypts() {
+        return Err(locked());
+    }
+    let (secret_nonce, public_nonce) = generate_nonce(nsec, context, message)?;
+    storage.set(&nonce_key(nsec, message), &secret_nonce.to_hex())?;
+    Ok(public_nonce)
+}
+
+/// Second signing round: signs `message` with `nsec` on the aggregate of every signer's
+/// `nonces`, with the secret nonce [`commit_nonce`] stored.
+///
+/// The secret nonce is removed from `storage` before signing, so it is never used twice:
+/// after a failure, start again from the first round.
+///
+/// # Errors
+///
+/// Errors if no nonce was committed to `message`, or its public nonce is not in `nonces`.
+#[cfg(feature = "serde-types")]
+pub(crate) fn sign_with_stored_nonce(
+    storage: &impl Storage,
+    nsec: &SecretNsec,
+    context: &KeyAggContext,
+    nonces: &[PublicNonce],
+    message: &Message,
+) -> Result<PartialSignature, Error> {
+    let key = nonce_key(nsec, message);
+    let stored = storage
+        .get(&key)?
+        .ok_or_else(|| Error::MuSig("no nonce committed to this message".to_string()))?;
+    storage.remove(&key)?;
+    let secret_nonce = SecretNonce::from_hex(&stored)?;
+    if !nonces.contains(&secret_nonce.public_nonce()) {
+        return Err(Error::MuSig(
+            "the committed nonce is not among the nonces".to_string(),
+        ));
+    }
+    partial_sign(
+        context,
+        secret_nonce,
+        nsec,
+        &AggregateNonce::sum(nonces)?,
+        message,
+    )
+}
+
+/// Signs `message` with `nsec`, consuming the [`SecretNonce`] of this session.
+///
+/// # Errors
+///
+/// Errors if `nsec` is not one of the aggregated keys.
+pub(crate) fn partial_sign(
+    context: &KeyAggContext,
+    secret_nonce: SecretNonce,
+    nsec: &SecretNsec,
+    aggregate_nonce: &AggregateNonce,
+    message: &Message,
+) -> Result<PartialSignature, Error> {
+    let mut d = context.signer_secret(nsec)?;
+    let signature = sign(context, secret_nonce, &d, aggregate_nonce, message);
+    d.non_secure_erase();
+    signature
+}
+
+/// BIP-327's `Sign` with the signer's [`KeyAggContext::signer_secret`] `d`.
+fn sign(
+    context: &KeyAggContext,
+    secret_nonce: SecretNonce,
+    d: &SecretKey,
+    aggregate_nonce: &AggregateNonce,
+    message: &Message,
+) -> Result<PartialSignature, Error> {
+    let (b, r, e) = context.session(aggregate_nonce, message)?;
+    let [mut k_1, mut k_2] = secret_nonce.0;
+    if r.x_only_public_key().1 == Parity::Odd {
+        k_1 = k_1.negate();
+        k_2 = k_2.negate();
+    }
+    let signature = add(add(k_1, mul(b, k_2)?)?, mul(e, *d)?);
+    k_1.non_secure_erase();
+    k_2.non_secure_erase();
+    Ok(PartialSignature(signature?))
+}
+
+/// Verifies the [`PartialSignature`] of `npub`, made with its [`PublicNonce`].
+///
+/// # Errors
+///
+/// Errors if `npub` is not one of the aggregated keys or the partial signature is invalid.
+pub(crate) fn verify_partial_signature(
+    context: &KeyAggContext,
+    partial_signature: &PartialSignature,
+    public_nonce: &PublicNonce,
+    npub: &NostrPublicKey,
+    aggregate_nonce: &AggregateNonce,
+    message: &Message,
+) -> Result<(), Error> {
+    let key = context.signer_key(npub)?;
+    if !is_valid_partial_signature(
+        context,
+        partial_signature,
+        public_nonce,
+        key,
+        aggregate_nonce,
+        message,
+    )? {
+        return Err(Error::MuSig(format!(
+            "invalid partial signature from {npub}"
+        )));
+    }
+    Ok(())
+}
+
+/// BIP-327's `PartialSigVerifyInternal` with the signer's [`KeyAggContext::signer_key`] `key`.
+fn is_valid_partial_signature(
+    context: &KeyAggContext,
+    partial_signature: &PartialSignature,
+    public_nonce: &PublicNonce,
+    key: PublicKey,
+    aggregate_nonce: &AggregateNonce,
+    message: &Message,
+) -> Result<bool, Error> {
+    let (b, r, e) = context.session(aggregate_nonce, message)?;
+    let [r_1, r_2] = public_nonce.0;
+    let mut nonce = r_1.combine(&r_2.mul_tweak(SECP256K1, &Scalar::from(b))?)?;
+    if r.x_only_public_key().1 == Parity::Odd {
+        nonce = nonce.negate(SECP256K1);
+    }
+    let expected = nonce.combine(&key.mul_tweak(SECP256K1, &Scalar::from(e))?)?;
+    Ok(PublicKey::from_secret_key(SECP256K1, &partial_signature.0) == expected)
+}
+
+/// Combines the [`PartialSignature`]s of every signer into a BIP-340 signature
+/// valid for the [`KeyAggContext::output_key`].
+///
+/// # Errors
+///
+/// Errors if the resulting signature is invalid, such as when a partial signature is missing.
+pub(crate) fn aggregate_signatures(
+    context: &KeyAggContext,
+    aggregate_nonce: &AggregateNonce,
+    message: &Message,
+    partial_signatures: &[PartialSignature],
+) -> Result<schnorr::Signature, Error> {
+    let (_, r, e) = context.session(aggregate_nonce, message)?;
+    let mut terms = partial_signatures
+        .iter()
+        .map(|partial_signature| partial_signature.0)
+        .collect::<Vec<_>>();
+    if let Some(tweak) = context.output_tweak() {
+        terms.push(mul(e, tweak)?);
+    }
+    let s = terms
+        .into_iter()
+        .try_fold(None, |sum: Option<SecretKey>, term| match sum {
+            Some(sum) => add(sum, term).map(Some),
+            None => Ok(Some(term)),
+        })?
+        .ok_or_else(|| Error::MuSig("no partial signatures to aggregate".to_string()))?;
+
+    let mut bytes = [0; 64];
+    bytes[..32].copy_from_slice(&r.x_only_public_key().0.serialize());
+    bytes[32..].copy_from_slice(&s.secret_bytes());
+    let signature = schnorr::Signature::from_slice(&bytes)?;
+    SECP256K1
+        .verify_schnorr(&signature, message, &context.output_key())
+        .map_err(|_| Error::MuSig("the aggregate signature is invalid".to_string()))?;
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::{Hash, sha256};
+
+    use super::*;
+
+    // Test vectors from BIP-327.
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        <[u8; N]>::from_hex(hex).unwrap()
+    }
+
+    fn public_key(hex: &str) -> PublicKey {
+        PublicKey::from_slice(&bytes::<33>(hex)).unwrap()
+    }
+
+    #[test]
+    fn key_agg_vectors() {
+        let keys = [
+            "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+            "03dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659",
+            "023590a94e768f8e1815c2f24b4d80a8e3149316c3518ce7b7ad338368d038ca66",
+        ]
+        .map(public_key);
+        for (indices, expected) in [
+            (
+                vec![0, 1, 2],
+                "90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c",
+            ),
+            (
+                vec![2, 1, 0],
+                "6204de8b083426dc6eaf9502d27024d53fc826bf7d2012148a0575435df54b2b",
+            ),
+            (
+                vec![0, 0, 0],
+                "b436e3bad62b8cd409969a224731c193d051162d8c5ae8b109306127da3aa935",
+            ),
+            (
+                vec![0, 0, 1, 1],
+                "69bc22bfa5d106306e48a20679de1d7389386124d07571d0d872686028c26a3e",
+            ),
+        ] {
+            let context =
+                KeyAggContext::aggregate(indices.iter().map(|&i| keys[i]).collect()).unwrap();
+            assert_eq!(context.internal_key().serialize(), bytes(expected));
+        }
+    }
+
+    #[test]
+    fn nonce_gen_vectors() {
+        let rand = bytes("0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f");
+        let message =
+            bytes::<32>("0101010101010101010101010101010101010101010101010101010101010101");
+        let extra_input =
+            bytes::<32>("0808080808080808080808080808080808080808080808080808080808080808");
+        for (nonce, secret_nonce, public_nonce) in [
+            (
+                nonce_gen(
+                    rand,
+                    Some(&bytes(
+                        "0202020202020202020202020202020202020202020202020202020202020202",
+                    )),
+                    &bytes("024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766"),
+                    Some(&bytes(
+                        "0707070707070707070707070707070707070707070707070707070707070707",
+                    )),
+                    Some(&message),
+                    Some(&extra_input),
+                ),
+                "b114e502beaa4e301dd08a50264172c84e41650e6cb726b410c0694d59effb6495b5caf28d045b973d63e3c99a44b807bde375fd6cb39e46dc4a511708d0e9d2",
+                "02f7be7089e8376eb355272368766b17e88e7db72047d05e56aa881ea52b3b35df02c29c8046fdd0ded4c7e55869137200fbdbfe2eb654267b6d7013602caed3115a",
+            ),
+            (
+                nonce_gen(
+                    rand,
+                    None,
+                    &bytes("02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9"),
+                    None,
+                    None,
+                    None,
+                ),
+                "89bdd787d0284e5e4d5fc572e49e316bab7e21e3b1830de37dfe80156fa41a6d0b17ae8d024c53679699a6fd7944d9c4a366b514baf43088e0708b1023dd2897",
+                "02c96e7cb1e8aa5dac64d872947914198f607d90ecde5200de52978ad5ded63c000299ec5117c2d29edee8a2092587c3909be694d5cff0667d6c02ea4059f7cd9786",
+            ),
+        ] {
+            let (secret, public) = nonce.unwrap();
+            let [k_1, k_2] = secret.0;
+            assert_eq!(
+                [k_1.secret_bytes(), k_2.secret_bytes()].concat(),
+                bytes::<64>(secret_nonce)
+            );
+            assert_eq!(public.to_string(), public_nonce);
+        }
+    }
+
+    #[test]
+    fn sign_verify_vectors() {
+        let secret_key = SecretKey::from_slice(&bytes::<32>(
+            "7fb9e0e687ada1eebf7ecfe2f21e73ebdb51a7d450948dfe8d76d7f2d1007671",
+        ))
+        .unwrap();
+        let keys = [
+            "03935f972da013f80ae011890fa89b67a27b7be6ccb24d3274d18b2d4067f261a9",
+            "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+            "02dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba661",
+        ]
+        .map(public_key);
+        let public_nonces = [
+            "0337c87821afd50a8644d820a8f3e02e499c931865c2360fb43d0a0d20dafe07ea0287bf891d2a6deaebadc909352aa9405d1428c15f4b75f04dae642a95c2548480",
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f817980279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        ]
+        .map(|hex| hex.parse::<PublicNonce>().unwrap());
+        let aggregate_nonce = AggregateNonce(
+            parse_nonces(
+                "028465fcf0bbdbcf443aabcce533d42b4b5a10966ac09a49655e8c42daab8fcd61037496a3cc86926d452cafcfd55d25972ca1675d549310de296bff42f72eeea8c9",
+            )
+            .unwrap(),
+        );
+        let message = Message::from_digest(bytes(
+            "f95466d086770e689964664219266fe5ed215c92ae20bab5c9d79addddf3c0cf",
+        ));
+        let secret_nonce = || {
+            SecretNonce(
+                [
+                    "508b81a611f100a6b2b6b29656590898af488bcf2e1f55cf22e5cfb84421fe61",
+                    "fa27fd49b1d50085b481285e1ca205d55c82cc1b31ff5cd54a489829355901f7",
+                ]
+                .map(|hex| SecretKey::from_slice(&bytes::<32>(hex)).unwrap()),
+            )
+        };
+
+        for (indices, expected) in [
+            (
+                [0, 1, 2],
+                "012abbcb52b3016ac03ad82395a1a415c48b93def78718e62a7a90052fe224fb",
+            ),
+            (
+                [1, 0, 2],
+                "9ff2f7aaa856150cc8819254218d3adeeb0535269051897724f9db3789513a52",
+            ),
+            (
+                [1, 2, 0],
+                "fa23c359f6fac4e7796bb93bc9f0532a95468c539ba20ff86d7c76ed92227900",
+            ),
+        ] {
+            let context = KeyAggContext::aggregate(indices.map(|i| keys[i]).to_vec()).unwrap();
+            let d = context.secret_share(secret_key).unwrap();
+            let signature = sign(&context, secret_nonce(), &d, &aggregate_nonce, &message).unwrap();
+            assert_eq!(signature.to_string(), expected);
+            let key = context.key_share(keys[0]).unwrap();
+            assert!(
+                is_valid_partial_signature(
+                    &context,
+                    &signature,
+                    &public_nonces[0],
+                    key,
+                    &aggregate_nonce,
+                    &message,
+                )
+                .unwrap()
+            );
+        }
+
+        let context = KeyAggContext::aggregate(keys.to_vec()).unwrap();
+        // The signer's key is not part of the aggregate.
+        let others = KeyAggContext::aggregate(keys[1..].to_vec()).unwrap();
+        assert!(others.secret_share(secret_key).is_err());
+        // Wrong signature, and a valid signature checked against the wrong signer.
+        for (signature, signer) in [
+            (
+                "fed54434ad4cfe953fc527dc6a5e5be8f6234907b7c187559557ce87a0541c46",
+                0,
+            ),
+            (
+                "012abbcb52b3016ac03ad82395a1a415c48b93def78718e62a7a90052fe224fb",
+                1,
+            ),
+        ] {
+            assert!(
+                !is_valid_partial_signature(
+                    &context,
+                    &signature.parse().unwrap(),
+                    &public_nonces[signer],
+                    context.key_share(keys[signer]).unwrap(),
+                    &aggregate_nonce,
+                    &message,
+                )
+                .unwrap()
+            );
+        }
+        // A signature exceeding the group size, an invalid nonce and an invalid key.
+        assert!(
+            "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141"
+                .parse::<PartialSignature>()
+                .is_err()
+        );
+        assert!(
+            "0200000000000000000000000000000000000000000000000000000000000000090287bf891d2a6deaebadc909352aa9405d1428c15f4b75f04dae642a95c2548480"
+                .parse::<PublicNonce>()
+                .is_err()
+        );
+        assert!(
+            PublicKey::from_slice(&bytes::<33>(
+                "020000000000000000000000000000000000000000000000000000000000000007"
+            ))
+            .is_err()
+        );
+        // Invalid aggregate nonces.
+        for aggregate_nonce in [
+            "048465fcf0bbdbcf443aabcce533d42b4b5a10966ac09a49655e8c42daab8fcd61037496a3cc86926d452cafcfd55d25972ca1675d549310de296bff42f72eeea8c9",
+            "028465fcf0bbdbcf443aabcce533d42b4b5a10966ac09a49655e8c42daab8fcd61020000000000000000000000000000000000000000000000000000000000000009",
+            "028465fcf0bbdbcf443aabcce533d42b4b5a10966ac09a49655e8c42daab8fcd6102fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc30",
+        ] {
+            assert!(parse_nonces(aggregate_nonce).is_err());
+        }
+    }
+
+    #[test]
+    fn two_party_signing() {
+        // The odd signer must negate its secret key to match its x-only npub.
+        let (nsec_1, nsec_2) = (
+            SecretNsec::generate_with_parity(Parity::Odd),
+            SecretNsec::generate_with_parity(Parity::Even),
+        );
+        let (npub_1, npub_2) = (nsec_1.public_key(), nsec_2.public_key());
+        let message =
+            Message::from_digest(sha256::Hash::hash(b"cooperative close").to_byte_array());
+        let merkle_root = TapNodeHash::from_byte_array([7; 32]);
+
+        // Key order does not matter.
+        let context = KeyAggContext::new(&[npub_1, npub_2])
+            .unwrap()
+            .with_merkle_root(Some(merkle_root))
+            .unwrap();
+        assert_eq!(
+            context,
+            KeyAggContext::new(&[npub_2, npub_1])
+                .unwrap()
+                .with_merkle_root(Some(merkle_root))
+                .unwrap()
+        );
+        let (expected, _) =
+            bitcoin::key::TapTweak::tap_tweak(context.internal_key(), SECP256K1, Some(merkle_root));
+        assert_eq!(context.output_key(), expected.to_inner());
+
+        let (secret_nonce_1, public_nonce_1) = generate_nonce(&nsec_1, &context, &message).unwrap();
+        let (secret_nonce_2, public_nonce_2) = generate_nonce(&nsec_2, &context, &message).unwrap();
+        assert_eq!(
+            public_nonce_1.to_string().parse::<PublicNonce>().unwrap(),
+            public_nonce_1
+        );
+        let aggregate_nonce = AggregateNonce::sum(&[public_nonce_1, public_nonce_2]).unwrap();
+
+        let partial_1 = partial_sign(
+            &context,
+            secret_nonce_1,
+            &nsec_1,
+            &aggregate_nonce,
+            &message,
+        )
+        .unwrap();
+        let partial_2 = partial_sign(
+            &context,
+            secret_nonce_2,
+            &nsec_2,
+            &aggregate_nonce,
+            &message,
+        )
+        .unwrap();
+        assert_eq!(
+            partial_1.to_string().parse::<PartialSignature>().unwrap(),
+            partial_1
+        );
+        verify_partial_signature(
+            &context,
+            &partial_1,
+            &public_nonce_1,
+            &npub_1,
+            &aggregate_nonce,
+            &message,
+        )
+        .unwrap();
+        // A partial signature is bound to its signer's key and nonce.
+        assert!(
+            verify_partial_signature(
+                &context,
+                &partial_1,
+                &public_nonce_2,
+                &npub_2,
+                &aggregate_nonce,
+                &message,
+            )
+            .is_err()
+        );
+
+        let signature = aggregate_signatures(
+            &context,
+            &aggregate_nonce,
+            &message,
+            &[partial_1, partial_2],
+        )
+        .unwrap();
+        assert!(
+            SECP256K1
+                .verify_schnorr(&signature, &message, &context.output_key())
+                .is_ok()
+        );
+        assert!(aggregate_signatures(&context, &aggregate_nonce, &message, &[partial_1]).is_err());
+
+        // An outsider can't sign.
+        let outsider = SecretNsec::generate();
+        let (secret_nonce, _) = generate_nonce(&outsider, &context, &message).unwrap();
+        assert!(
+            partial_sign(
+                &context,
+                secret_nonce,
+                &outsider,
+                &aggregate_nonce,
+                &message
+            )
+            .is_err()
+        );
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn stored_nonces() {
+        use crate::{storage::MemoryStorage, vault::Vault};
+
+        let (nsec_1, nsec_2) = (SecretNsec::generate(), SecretNsec::generate());
+        let context = KeyAggContext::new(&[nsec_1.public_key(), nsec_2.public_key()]).unwrap();
+        let message = Message::from_digest([3; 32]);
+        let storage = MemoryStorage::default();
+
+        // Secret nonces are only stored encrypted.
+        let clear = VaultStorage::clear(&storage);
+        assert!(commit_nonce(&clear, &nsec_1, &context, &message).is_err());
+        let vault = Vault::create(&storage, "passphrase").unwrap();
+        let encrypted = vault.storage(&storage);
+        let nonce_1 = commit_nonce(&encrypted, &nsec_1, &context, &message).unwrap();
+        let stored = storage.get(&nonce_key(&nsec_1, &message)).unwrap().unwrap();
+        assert!(crate::vault::is_encrypted(&stored));
+
+        let (secret_nonce_2, nonce_2) = generate_nonce(&nsec_2, &context, &message).unwrap();
+        let nonces = [nonce_1, nonce_2];
+        let aggregate_nonce = AggregateNonce::sum(&nonces).unwrap();
+        let partial_1 =
+            sign_with_stored_nonce(&encrypted, &nsec_1, &context, &nonces, &message).unwrap();
+        let partial_2 = partial_sign(
+            &context,
+            secret_nonce_2,
+            &nsec_2,
+            &aggregate_nonce,
+            &message,
+        )
+        .unwrap();
+        assert!(
+            aggregate_signatures(
+                &context,
+                &aggregate_nonce,
+                &message,
+                &[partial_1, partial_2]
+            )
+            .is_ok()
+        );
+
+        // A nonce signs once, and only with the public nonce shared.
+        assert!(sign_with_stored_nonce(&encrypted, &nsec_1, &context, &nonces, &message).is_err());
+        commit_nonce(&encrypted, &nsec_1, &context, &message).unwrap();
+        assert!(sign_with_stored_nonce(&encrypted, &nsec_1, &context, &nonces, &message).is_err());
+        assert_eq!(storage.get(&nonce_key(&nsec_1, &message)).unwrap(), None);
+    }
+}
@@ -0,0 +1,318 @@
+//! End-to-end escrows on Mutinynet through the Esplora backend.
+//!
+//! Unlike the regtest tests of [`testkit`](crate::testkit), these run the full flow against
+//! a live network: the participants negotiate an offer, the buyer is paid by the faucet and
+//! funds the escrow at the fee rate Esplora estimates, and both sign and broadcast its
+//! resolution, so real-network behavior of fee estimation and relative timelocks is covered.
+//!
+//! They need network access and take a few minutes of 30-second blocks, so they are
+//! ignored by default:
+//!
+//! ```text
+//! cargo test mutinynet -- --ignored --nocapture --test-threads 1
+//! ```
+//!
+//! `SCROW_MUTINYNET_ESPLORA` overrides the Esplora endpoint of Mutinynet.
+
+use std::time::Duration;
+
+use bitcoin::{
+    Address, Amount, FeeRate, OutPoint, Transaction, TxIn, TxOut, Txid, absolute, transaction,
+};
+use nostr::{Keys, Timestamp};
+
+use crate::{
+    error::Error,
+    esplora::{
+        EsploraClient, broadcast_transaction, create_client, get_block_height, get_fee_estimates,
+    },
+    faucet::{DEFAULT_DEPOSIT_TIMEOUT, DEFAULT_FAUCET_AMOUNT, Faucet, wait_for_deposit},
+    invariants::{SigningInvariants, leaf_timelock},
+    network::Chain,
+    protocol::{DEFAULT_OFFER_VALIDITY, Handshake, Offer, PROTOCOL_VERSION, Role},
+    proxy::ProxySettings,
+    runtime::{Instant, sleep},
+    scripts::{EscrowConfig, EscrowScript, SpendPath},
+    secret::SecretNsec,
+    sign::{BatchSigner, LeafSignatures, combine_leaf_signatures, sign_key_spend},
+    tx::{escrow_tx, estimate_spend_weight},
+    util::{P2TR_TX_VBYTE_KEY_PATH, npub_to_address},
+};
+
+/// Confirmation target of the fee rates, in blocks.
+const CONFIRMATION_TARGET: u16 = 1;
+
+/// Amount each participant puts in the test escrows.
+const ESCROW_AMOUNT: Amount = Amount::from_sat(40_000);
+
+/// Virtual size of a P2TR output, such as the change of the funding transaction.
+const P2TR_OUTPUT_VBYTES: u64 = 43;
+
+/// How long to wait for a transaction to confirm, a few Mutinynet blocks.
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// How often to poll Esplora while waiting.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Esplora client of Mutinynet, or of `SCROW_MUTINYNET_ESPLORA`.
+fn client() -> EsploraClient {
+    let url = std::env::var("SCROW_MUTINYNET_ESPLORA")
+        .unwrap_or_else(|_| Chain::Mutinynet.esplora_endpoint().to_string());
+    create_client(&url, &ProxySettings::default()).unwrap()
+}
+
+/// The fee rate Esplora estimates for [`CONFIRMATION_TARGET`], at least 1 sat/vB.
+async fn fee_rate(client: &EsploraClient) -> FeeRate {
+    let estimates = get_fee_estimates(client).await.unwrap();
+    let sat_per_vb = estimates
+        .get(&CONFIRMATION_TARGET)
+        .map_or(1, |rate| rate.ceil() as u64)
+        .max(1);
+    FeeRate::from_sat_per_vb(sat_per_vb).unwrap()
+}
+
+/// Polls Esplora until `txid` confirms.
+async fn wait_for_confirmation(client: &EsploraClient, txid: Txid) -> Result<u32, Error> {
+    let start = Instant::now();
+    loop {
+        let status = client.get_tx_status(&txid).await?;
+        if let (true, Some(height)) = (status.confirmed, status.block_height) {
+            return Ok(height);
+        }
+        if start.elapsed() >= CONFIRMATION_TIMEOUT {
+            return Err(Error::Http(format!(
+                "Transaction {txid} not confirmed after {} seconds",
+                CONFIRMATION_TIMEOUT.as_secs()
+            )));
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Polls Esplora until the chain reaches `height`.
+async fn wait_for_height(client: &EsploraClient, height: u32) {
+    let start = Instant::now();
+    while get_block_height(client).await.unwrap() < height {
+        assert!(
+            start.elapsed() < CONFIRMATION_TIMEOUT,
+            "height {height} not reached"
+        );
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Negotiates an escrow of [`ESCROW_AMOUNT`] each between a new buyer and seller,
+/// with a new arbitrator and `timelock_duration` if given.
+///
+/// Returns the escrow and the buyer's, seller's and arbitrator's keys.
+fn propose(timelock_duration: Option<u32>) -> (EscrowConfig, [Keys; 3]) {
+    let keys = [Keys::generate(), Keys::generate(), Keys::generate()];
+    let [buyer, seller, arbitrator] = &keys;
+    let now = Timestamp::now();
+    let offer = Offer {
+        version: PROTOCOL_VERSION,
+        network: Chain::Mutinynet.network(),
+        offerer: buyer.public_key(),
+        role: Role::Buyer,
+        counterparty: Some(seller.public_key()),
+        amount_buyer: ESCROW_AMOUNT,
+        amount_seller: ESCROW_AMOUNT,
+        arbitrator: timelock_duration.map(|_| arbitrator.public_key()),
+        timelock_duration,
+        expires_at: now + DEFAULT_OFFER_VALIDITY,
+        lock_time_height: None,
+        script_template: 1,
+        fiat: None,
+        platform_fee: None,
+        min_confirmations: None,
+    };
+    let (_, offer_event) = Handshake::offer(buyer.secret_key(), offer, now).unwrap();
+    let (agreed, _) = Handshake::accept(seller.secret_key(), &offer_event, None, now).unwrap();
+    let Handshake::Agreed {
+        offer,
+        acceptance,
+        escrow_address,
+        ..
+    } = agreed
+    else {
+        panic!("acceptance must agree the escrow");
+    };
+    let config = offer.escrow_config(&acceptance.acceptor).unwrap();
+    assert_eq!(config.address().unwrap(), escrow_address);
+    (config, keys)
+}
+
+/// Has the faucet pay the buyer's address, then funds the escrow from it,
+/// returning the confirmed funding [`Txid`] and the escrow output.
+async fn fund(client: &EsploraClient, config: &EscrowConfig, buyer: &SecretNsec) -> (Txid, TxOut) {
+    let network = config.network;
+    let faucet = Faucet::for_chain(Chain::Mutinynet).unwrap();
+    let (address, faucet_txid) = faucet
+        .fund_npub(&buyer.public_key(), DEFAULT_FAUCET_AMOUNT)
+        .await
+        .unwrap();
+    let deposit = wait_for_deposit(client, &address, faucet_txid, DEFAULT_DEPOSIT_TIMEOUT)
+        .await
+        .unwrap();
+    let faucet_tx = client.get_tx(&faucet_txid).await.unwrap().unwrap();
+    let vout = faucet_tx
+        .output
+        .iter()
+        .position(|output| output.script_pubkey == address.script_pubkey())
+        .unwrap();
+    let prevout = faucet_tx.output[vout].clone();
+
+    // The funding transaction pays the escrow and the change back to the buyer.
+    let escrow = TxOut {
+        value: ESCROW_AMOUNT * 2,
+        script_pubkey: config.address().unwrap().script_pubkey(),
+    };
+    let fee = fee_rate(client)
+        .await
+        .fee_vb(P2TR_TX_VBYTE_KEY_PATH + P2TR_OUTPUT_VBYTES)
+        .unwrap();
+    let change = deposit.amount - escrow.value - fee;
+    let mut tx = Transaction {
+        version: transaction::Version(2),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(faucet_txid, vout as u32),
+            ..Default::default()
+        }],
+        output: vec![
+            escrow.clone(),
+            TxOut {
+                value: change,
+                script_pubkey: npub_to_address(&buyer.public_key(), network)
+                    .unwrap()
+                    .script_pubkey(),
+            },
+        ],
+    };
+    let signature = sign_key_spend(&tx, 0, buyer, &[prevout]).unwrap();
+    tx.input[0].witness.push(signature.as_ref());
+    broadcast_transaction(client, &tx).await.unwrap();
+    let txid = tx.compute_txid();
+    wait_for_confirmation(client, txid).await.unwrap();
+    (txid, escrow)
+}
+
+/// The resolution of the escrow funded by `funding_txid` through `escrow_script`,
+/// signed by `signers`, paying each participant their amount minus half the fee.
+async fn resolve(
+    client: &EsploraClient,
+    config: &EscrowConfig,
+    funding_txid: Txid,
+    escrow: TxOut,
+    escrow_script: EscrowScript,
+    signers: [&SecretNsec; 2],
+) -> Transaction {
+    let fee = fee_rate(client)
+        .await
+        .fee_vb(estimate_spend_weight(config, SpendPath::Leaf(escrow_script)).unwrap())
+        .unwrap();
+    let tx = escrow_tx(
+        &config.npub_1,
+        &config.npub_2,
+        config.timelock_duration,
+        escrow.value / 2,
+        escrow.value / 2,
+        funding_txid,
+        fee,
+        config.network,
+        absolute::LockTime::ZERO,
+    )
+    .unwrap();
+    let context = config.context().unwrap();
+    let invariants = SigningInvariants::of_local_tx(
+        &tx,
+        vec![escrow],
+        vec![leaf_timelock(escrow_script, config.timelock_duration).unwrap()],
+    );
+    let mut signer = BatchSigner::new(&tx, &invariants).unwrap();
+    let mut signatures = LeafSignatures::new(tx.compute_txid(), 0, escrow_script);
+    for nsec in signers {
+        let signature = signer.sign_leaf(0, &context, escrow_script, nsec).unwrap();
+        signatures.insert(nsec.public_key(), signature);
+    }
+    combine_leaf_signatures(tx, &signatures, &context).unwrap()
+}
+
+/// The resolution addresses of the buyer and the seller.
+fn resolution_addresses(config: &EscrowConfig) -> [Address; 2] {
+    [
+        npub_to_address(&config.npub_1, config.network).unwrap(),
+        npub_to_address(&config.npub_2, config.network).unwrap(),
+    ]
+}
+
+#[tokio::test]
+#[ignore = "runs against Mutinynet"]
+async fn collaborative_escrow() {
+    let client = client();
+    let (config, [buyer, seller, _]) = propose(None);
+    let buyer = SecretNsec::from(buyer.secret_key().clone());
+    let seller = SecretNsec::from(seller.secret_key().clone());
+
+    let (funding_txid, escrow) = fund(&client, &config, &buyer).await;
+    let tx = resolve(
+        &client,
+        &config,
+        funding_txid,
+        escrow,
+        EscrowScript::A,
+        [&buyer, &seller],
+    )
+    .await;
+    broadcast_transaction(&client, &tx).await.unwrap();
+    wait_for_confirmation(&client, tx.compute_txid())
+        .await
+        .unwrap();
+
+    // Each participant got their resolution output.
+    let paid = client.get_tx(&tx.compute_txid()).await.unwrap().unwrap();
+    for address in resolution_addresses(&config) {
+        assert!(
+            paid.output
+                .iter()
+                .any(|output| output.script_pubkey == address.script_pubkey())
+        );
+    }
+}
+
+#[tokio::test]
+#[ignore = "runs against Mutinynet"]
+async fn dispute_escrow_waits_for_timelock() {
+    let client = client();
+    let timelock_duration = 2;
+    let (config, [buyer, _, arbitrator]) = propose(Some(timelock_duration));
+    let buyer = SecretNsec::from(buyer.secret_key().clone());
+    let arbitrator = SecretNsec::from(arbitrator.secret_key().clone());
+
+    let (funding_txid, escrow) = fund(&client, &config, &buyer).await;
+    let funding_height = client
+        .get_tx_status(&funding_txid)
+        .await
+        .unwrap()
+        .block_height
+        .unwrap();
+    let tx = resolve(
+        &client,
+        &config,
+        funding_txid,
+        escrow,
+        EscrowScript::B,
+        [&buyer, &arbitrator],
+    )
+    .await;
+
+    // Esplora relays the node's rejection of a spend before the timelock.
+    if get_block_height(&client).await.unwrap() < funding_height + timelock_duration - 1 {
+        assert!(broadcast_transaction(&client, &tx).await.is_err());
+    }
+    wait_for_height(&client, funding_height + timelock_duration - 1).await;
+    broadcast_transaction(&client, &tx).await.unwrap();
+    wait_for_confirmation(&client, tx.compute_txid())
+        .await
+        .unwrap();
+}
@@ -0,0 +1,154 @@
+//! Chains scrow runs on, including custom signets such as Mutinynet.
+//!
+//! A [`Chain`] is more than a [`Network`]: Mutinynet shares the address format and
+//! consensus parameters of [`Network::Signet`], but has its own blocks, Esplora servers,
+//! faucet and, with 30-second blocks, its own block timing.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use bitcoin::Network;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// A chain scrow can create escrows on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum Chain {
+    /// Bitcoin mainnet.
+    #[default]
+    Mainnet,
+    /// Bitcoin testnet.
+    Testnet,
+    /// The default Bitcoin signet.
+    Signet,
+    /// Mutinynet, a custom signet with 30-second blocks.
+    Mutinynet,
+    /// Local regtest.
+    Regtest,
+}
+
+impl Chain {
+    /// All chains, in display order.
+    pub(crate) const ALL: [Chain; 5] = [
+        Chain::Mainnet,
+        Chain::Testnet,
+        Chain::Signet,
+        Chain::Mutinynet,
+        Chain::Regtest,
+    ];
+
+    /// Name of the chain in the settings.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Chain::Mainnet => "Mainnet",
+            Chain::Testnet => "Testnet",
+            Chain::Signet => "Signet",
+            Chain::Mutinynet => "Mutinynet",
+            Chain::Regtest => "Regtest",
+        }
+    }
+
+    /// The [`Network`] of the chain's addresses and consensus parameters.
+    pub(crate) fn network(self) -> Network {
+        match self {
+            Chain::Mainnet => Network::Bitcoin,
+            Chain::Testnet => Network::Testnet,
+            Chain::Signet | Chain::Mutinynet => Network::Signet,
+            Chain::Regtest => Network::Regtest,
+        }
+    }
+
+    /// Default Esplora API of the chain.
+    pub(crate) fn esplora_endpoint(self) -> &'static str {
+        match self {
+            Chain::Mainnet => "https://mempool.space/api",
+            Chain::Testnet => "https://mempool.space/testnet4/api",
+            Chain::Signet => "https://mempool.space/signet/api",
+            Chain::Mutinynet => "https://mutinynet.com/api",
+            Chain::Regtest => "http://127.0.0.1:3002/api",
+        }
+    }
+
+    /// Expected interval between blocks.
+    pub(crate) fn block_interval(self) -> Duration {
+        match self {
+            Chain::Mutinynet => Duration::from_secs(30),
+            _ => Duration::from_secs(10 * 60),
+        }
+    }
+
+    /// Whether the chain has a faucet scrow can request coins from.
+    pub(crate) fn has_faucet(self) -> bool {
+        self == Chain::Mutinynet
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Chain {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Chain::ALL
+            .into_iter()
+            .find(|chain| chain.name() == s)
+            .ok_or_else(|| Error::InvalidNetwork(s.to_string()))
+    }
+}
+
+/// A [`Chain`] with its expected block timing, to convert durations to blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct NetworkProfile {
+    /// The chain.
+    pub(crate) chain: Chain,
+    /// Expected interval between blocks.
+    pub(crate) block_interval: Duration,
+}
+
+impl NetworkProfile {
+    /// Overrides the expected block interval,
+    /// such as for regtest nodes mining at a custom pace.
+    pub(crate) fn with_block_interval(self, block_interval: Duration) -> Self {
+        Self {
+            block_interval,
+            ..self
+        }
+    }
+}
+
+impl From<Chain> for NetworkProfile {
+    fn from(chain: Chain) -> Self {
+        Self {
+            chain,
+            block_interval: chain.block_interval(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_block_timing() {
+        for chain in Chain::ALL {
+            assert_eq!(chain.name().parse::<Chain>().unwrap(), chain);
+        }
+        assert!("Testnet4".parse::<Chain>().is_err());
+        assert_eq!(Chain::Mutinynet.network(), Network::Signet);
+        assert_eq!(
+            NetworkProfile::from(Chain::Mutinynet).block_interval,
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            NetworkProfile::from(Chain::Regtest)
+                .with_block_interval(Duration::from_secs(1))
+                .block_interval,
+            Duration::from_secs(1)
+        );
+    }
+}
@@ -0,0 +1,823 @@
+//! Notifications of escrow events, in the browser and on the desktop.
+//!
+//! The watcher and the protocol layer turn what they observe into [`Notification`]s:
+//! [`Notification::from_watch`] for funding confirmations, [`Notification::from_history`]
+//! for counterparty signatures, and
+//! [`TimelockWarning::notification`](crate::expiry::TimelockWarning::notification)
+//! for dispute timelocks expiring.
+//! A [`Dispatcher`] shows them through a [`Notifier`], once each, skipping the kinds the user
+//! turned off in their [`NotificationPreferences`].
+//!
+//! In the browser, [`BrowserNotifier`] uses the web Notification API once the user granted
+//! it with [`request_permission`]. Natively, [`DesktopNotifier`] runs `notify-send` on Linux
+//! and `osascript` on macOS.
+//!
+//! An [`EscrowWatcher`] refreshes the watched escrows and dispatches their notifications,
+//! in the app while it is open and in the `scrowd` daemon, along with the signatures
+//! counterparties added to the sessions of the registered [`Accounts`].
+
+#[cfg(feature = "serde-types")]
+use std::collections::HashMap;
+
+#[cfg(feature = "serde-types")]
+use bitcoin::{Network, Txid};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+#[cfg(feature = "serde-types")]
+use nostr::{Timestamp, key::PublicKey as NostrPublicKey};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde-types")]
+use crate::{
+    accounts::{Accounts, Keystore},
+    audit::AuditReport,
+    esplora::EsploraClient,
+    expiry::{TimelockSchedule, TimelockScheduler, WarningThresholds, schedule_escrows},
+    history::HistoryEvent,
+    keys::Npub,
+    network::{Chain, NetworkProfile},
+    protocol::Session,
+    settings::Settings,
+};
+use crate::{
+    error::Error,
+    i18n::{Language, tr, tr_args},
+    storage::Storage,
+    watch::{WatchSession, WatchStatus},
+};
+
+/// [`Storage`] key of the [`NotificationPreferences`].
+pub(crate) const NOTIFICATION_PREFERENCES_KEY: &str = "scrow.notifications";
+
+/// Name of the app in desktop notifications.
+const APP_NAME: &str = "Satoshi Escrow";
+
+/// What a [`Notification`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NotificationKind {
+    /// The counterparty signed a spend of the escrow.
+    CounterpartySigned,
+    /// The funding transaction confirmed.
+    FundingConfirmed,
+    /// A dispute timelock crossed a [`WarningThresholds`](crate::expiry::WarningThresholds).
+    TimelockExpiring,
+}
+
+/// A notification to show the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Notification {
+    /// What the notification is about.
+    pub(crate) kind: NotificationKind,
+    /// Identifies the event notified, so it is shown once.
+    pub(crate) tag: String,
+    /// Title of the notification.
+    pub(crate) title: String,
+    /// Text of the notification.
+    pub(crate) body: String,
+}
+
+impl Notification {
+    /// The notifications of the watched escrow `session` in `status`.
+    pub(crate) fn from_watch(
+        session: &WatchSession,
+        status: WatchStatus,
+        language: Language,
+    ) -> Vec<Self> {
+        let WatchStatus::Funded { confirmations, .. } = status else {
+            return Vec::new();
+        };
+        let escrow = if session.label.is_empty() {
+            session.funding_txid.to_string()
+        } else {
+            session.label.clone()
+        };
+        vec![Self {
+            kind: NotificationKind::FundingConfirmed,
+            tag: format!("funding-confirmed:{}", session.funding_txid),
+            title: tr(language, "notification-funding-confirmed-title"),
+            body: tr_args(
+                language,
+                "notification-funding-confirmed-body",
+                &[("escrow", &escrow), ("confirmations", &confirmations)],
+            ),
+        }]
+    }
+
+    /// The notifications of what the counterparty of `npub` did in `session` after `since`.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn from_history(
+        session: &Session,
+        npub: &NostrPublicKey,
+        since: Timestamp,
+        language: Language,
+    ) -> Result<Vec<Self>, Error> {
+        let session_id = session.id()?;
+        Ok(session
+            .history()
+            .entries()
+            .iter()
+            .filter(|entry| entry.at > since)
+            .filter_map(|entry| match entry.event {
+                HistoryEvent::Signed { npub: signer, txid } if signer != *npub => Some(Self {
+                    kind: NotificationKind::CounterpartySigned,
+                    tag: format!("signed:{session_id}:{txid}:{signer}"),
+                    title: tr(language, "notification-counterparty-signed-title"),
+                    body: tr_args(
+                        language,
+                        "notification-counterparty-signed-body",
+                        &[
+                            ("counterparty", &Npub::from(signer)),
+                            ("escrow", &session_id),
+                        ],
+                    ),
+                }),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+/// Which [`NotificationKind`]s the user wants to be notified of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct NotificationPreferences {
+    /// Notify of [`NotificationKind::CounterpartySigned`].
+    pub(crate) counterparty_signed: bool,
+    /// Notify of [`NotificationKind::FundingConfirmed`].
+    pub(crate) funding_confirmed: bool,
+    /// Notify of [`NotificationKind::TimelockExpiring`].
+    pub(crate) timelock_expiring: bool,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        Self {
+            counterparty_signed: true,
+            funding_confirmed: true,
+            timelock_expiring: true,
+        }
+    }
+}
+
+impl NotificationPreferences {
+    /// Whether the user wants to be notified of `kind`.
+    pub(crate) fn is_enabled(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::CounterpartySigned => self.counterparty_signed,
+            NotificationKind::FundingConfirmed => self.funding_confirmed,
+            NotificationKind::TimelockExpiring => self.timelock_expiring,
+        }
+    }
+
+    /// Loads the preferences from `storage`, the defaults if none were saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(NOTIFICATION_PREFERENCES_KEY)? {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|e| Error::Storage(format!("Invalid notification preferences: {e}"))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the preferences to `storage`.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::Storage(format!("Could not save notification preferences: {e}")))?;
+        storage.set(NOTIFICATION_PREFERENCES_KEY, &json)
+    }
+}
+
+/// Shows [`Notification`]s to the user.
+pub(crate) trait Notifier {
+    /// Shows `notification`.
+    fn notify(&self, notification: &Notification) -> Result<(), Error>;
+}
+
+/// [`Notifier`] of the web Notification API.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct BrowserNotifier;
+
+#[cfg(target_arch = "wasm32")]
+impl Notifier for BrowserNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), Error> {
+        if web_sys::Notification::permission() != web_sys::NotificationPermission::Granted {
+            return Err(Error::Notification(
+                "notifications are not allowed".to_string(),
+            ));
+        }
+        let options = web_sys::NotificationOptions::new();
+        options.set_body(&notification.body);
+        options.set_tag(&notification.tag);
+        web_sys::Notification::new_with_options(&notification.title, &options)
+            .map_err(|_| Error::Notification("the browser refused it".to_string()))?;
+        Ok(())
+    }
+}
+
+/// Asks the user to allow notifications, returning whether they are allowed.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn request_permission() -> bool {
+    if let Ok(promise) = web_sys::Notification::request_permission() {
+        let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+    }
+    web_sys::Notification::permission() == web_sys::NotificationPermission::Granted
+}
+
+/// [`Notifier`] of the desktop: `notify-send` on Linux and `osascript` on macOS.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DesktopNotifier;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Notifier for DesktopNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), Error> {
+        use std::process::{Command, Stdio};
+
+        let mut command = if cfg!(target_os = "macos") {
+            // The texts are passed as arguments, so they need no AppleScript escaping.
+            let mut command = Command::new("osascript");
+            command.args([
+                "-e",
+                "on run argv",
+                "-e",
+                "display notification (item 2 of argv) with title (item 1 of argv)",
+                "-e",
+                "end run",
+            ]);
+            command
+        } else if cfg!(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "openbsd"
+        )) {
+            let mut command = Command::new("notify-send");
+            command.args(["--app-name", APP_NAME]);
+            command
+        } else {
+            return Err(Error::Notification(
+                "desktop notifications are not supported on this platform".to_string(),
+            ));
+        };
+        let status = command
+            .arg(&notification.title)
+            .arg(&notification.body)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| Error::Notification(e.to_string()))?;
+        if !status.success() {
+            return Err(Error::Notification(format!(
+                "the notifier exited with {status}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Shows the notifications the user wants, once each.
+#[derive(Debug)]
+pub(crate) struct Dispatcher<N> {
+    notifier: N,
+    preferences: NotificationPreferences,
+    /// Tags of the notifications shown so far.
+    shown: Vec<String>,
+}
+
+impl<N: Notifier> Dispatcher<N> {
+    /// A dispatcher showing notifications through `notifier` as the user `preferences`.
+    pub(crate) fn new(notifier: N, preferences: NotificationPreferences) -> Self {
+        Self {
+            notifier,
+            preferences,
+            shown: Vec::new(),
+        }
+    }
+
+    /// Replaces the user preferences, such as after a change in the settings.
+    pub(crate) fn set_preferences(&mut self, preferences: NotificationPreferences) {
+        self.preferences = preferences;
+    }
+
+    /// Shows the `notifications` of enabled kinds that were not shown yet.
+    ///
+    /// Returns how many were shown.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the notifier fails, leaving the failed notification and the following ones
+    /// to be shown again by the next dispatch.
+    pub(crate) fn dispatch(
+        &mut self,
+        notifications: impl IntoIterator<Item = Notification>,
+    ) -> Result<usize, Error> {
+        let mut count = 0;
+        for notification in notifications {
+            if !self.preferences.is_enabled(notification.kind)
+                || self.shown.contains(&notification.tag)
+            {
+                continue;
+            }
+            self.notifier.notify(&notification)?;
+            #[cfg(debug_assertions)]
+            trace!(tag = %notification.tag, "notified");
+            self.shown.push(notification.tag);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// A watched escrow refreshed by an [`EscrowWatcher`].
+#[cfg(feature = "serde-types")]
+#[derive(Debug)]
+pub(crate) struct Refreshed {
+    /// The watched escrow.
+    pub(crate) watch: WatchSession,
+    /// Its status at the previous refresh, if any.
+    pub(crate) previous: Option<WatchStatus>,
+    /// Its status now.
+    pub(crate) status: WatchStatus,
+    /// How many notifications were shown, errors if the notifier failed.
+    pub(crate) notified: Result<usize, Error>,
+}
+
+/// Refreshes the escrows watched in a [`Storage`] and notifies the user of what changed.
+///
+/// Escrows found by the first refresh are not notified of the state they are already in,
+/// only of the timelock warnings already due, nor of the signatures already in their sessions.
+#[cfg(feature = "serde-types")]
+#[derive(Debug)]
+pub(crate) struct EscrowWatcher<N> {
+    statuses: HashMap<Txid, WatchStatus>,
+    dispatcher: Dispatcher<N>,
+    scheduler: TimelockScheduler,
+    started: bool,
+    /// When the sessions were last read for signatures.
+    signatures_read_at: Option<Timestamp>,
+}
+
+#[cfg(feature = "serde-types")]
+impl<N: Notifier> EscrowWatcher<N> {
+    /// A watcher notifying the user through `notifier`.
+    pub(crate) fn new(notifier: N) -> Self {
+        Self {
+            statuses: HashMap::new(),
+            dispatcher: Dispatcher::new(notifier, NotificationPreferences::default()),
+            scheduler: TimelockScheduler::default(),
+            started: false,
+            signatures_read_at: None,
+        }
+    }
+
+    /// Whether the escrows were refreshed before, so their previous status is known.
+    pub(crate) fn is_started(&self) -> bool {
+        self.started
+    }
+
+    /// Refreshes every escrow watched in `storage` on chain with `client`,
+    /// notifying the user in `language` as they prefer, at `now`.
+    ///
+    /// Escrows that could not be refreshed are skipped until the next refresh.
+    pub(crate) async fn refresh(
+        &mut self,
+        client: &EsploraClient,
+        storage: &impl Storage,
+        now: Timestamp,
+        language: Language,
+    ) -> Vec<Refreshed> {
+        let watched = WatchSession::list(storage).unwrap_or_default();
+        let chain = Settings::load(storage).unwrap_or_default().network;
+        self.dispatcher
+            .set_preferences(NotificationPreferences::load(storage).unwrap_or_default());
+        self.scheduler
+            .set_thresholds(WarningThresholds::load(storage).unwrap_or_default());
+        let mut refreshed = Vec::new();
+        for txid in &watched {
+            let Ok(Some(watch)) = WatchSession::load(storage, txid) else {
+                continue;
+            };
+            let profile = NetworkProfile::from(chain_of(watch.config.network, chain));
+            let Ok((report, status)) = watch.refresh(client, None, &profile).await else {
+                continue;
+            };
+            let previous = self.statuses.insert(*txid, status);
+            let mut notifications = watch_notifications(
+                &watch,
+                &report,
+                previous,
+                status,
+                &mut self.scheduler,
+                &profile,
+                now,
+                language,
+            );
+            if !self.is_started() {
+                // The scheduler raises each warning once, so those due now are shown now.
+                notifications
+                    .retain(|notification| notification.kind == NotificationKind::TimelockExpiring);
+            }
+            let notified = self.dispatcher.dispatch(notifications);
+            refreshed.push(Refreshed {
+                watch,
+                previous,
+                status,
+                notified,
+            });
+        }
+        self.statuses.retain(|txid, _| watched.contains(txid));
+        self.started = true;
+        refreshed
+    }
+
+    /// Notifies the user in `language` of the signatures counterparties added to the sessions
+    /// in `storage` since the previous call, at `now`, see [`session_notifications`].
+    ///
+    /// Sessions are read through the session store of `keystore`: while it is locked,
+    /// nothing is notified, and the signatures added meanwhile are once it is unlocked.
+    ///
+    /// Returns how many notifications were shown.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the notifier fails, the failed notifications are not shown again.
+    pub(crate) fn notify_signatures(
+        &mut self,
+        storage: &impl Storage,
+        keystore: &Keystore,
+        now: Timestamp,
+        language: Language,
+    ) -> Result<usize, Error> {
+        let Ok(sessions) = keystore.sessions(storage) else {
+            return Ok(0);
+        };
+        let Some(since) = self.signatures_read_at.replace(now) else {
+            return Ok(0);
+        };
+        self.dispatcher
+            .dispatch(session_notifications(&sessions, since, language))
+    }
+
+    /// Warns the user in `language` of the dispute timelocks of the sessions in `storage`
+    /// crossing their thresholds at `now`, with the chain at `tip_height`,
+    /// see [`schedule_escrows`].
+    ///
+    /// Sessions are read through the session store of `keystore`: while it is locked,
+    /// nothing is checked. Escrows also watched are warned of once.
+    ///
+    /// Returns when to check again, [`None`] if no threshold is left to cross.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the sessions can't be read or the notifier fails.
+    pub(crate) fn notify_timelocks(
+        &mut self,
+        storage: &impl Storage,
+        keystore: &Keystore,
+        tip_height: u32,
+        now: Timestamp,
+        language: Language,
+    ) -> Result<Option<Timestamp>, Error> {
+        let Ok(sessions) = keystore.sessions(storage) else {
+            return Ok(None);
+        };
+        let profile = NetworkProfile::from(Settings::load(storage).unwrap_or_default().network);
+        let schedules = schedule_escrows(&sessions, tip_height, now, &profile)?;
+        let warnings = self.scheduler.check_all(&schedules, now);
+        self.dispatcher.dispatch(
+            warnings
+                .iter()
+                .map(|warning| warning.notification(language)),
+        )?;
+        Ok(self.scheduler.next_check(&schedules, now))
+    }
+}
+
+/// The [`Notification`]s of the watched escrow `watch` moving from `previous` to `status`,
+/// including the timelock warnings `scheduler` raises at `now`, estimated with the block
+/// interval of `profile`.
+#[cfg(feature = "serde-types")]
+#[expect(clippy::too_many_arguments)]
+fn watch_notifications(
+    watch: &WatchSession,
+    report: &AuditReport,
+    previous: Option<WatchStatus>,
+    status: WatchStatus,
+    scheduler: &mut TimelockScheduler,
+    profile: &NetworkProfile,
+    now: Timestamp,
+    language: Language,
+) -> Vec<Notification> {
+    let mut notifications = Vec::new();
+    if !matches!(previous, Some(WatchStatus::Funded { .. })) {
+        notifications.extend(Notification::from_watch(watch, status, language));
+    }
+    let (WatchStatus::Funded { confirmations, .. }, Some(funding_height)) =
+        (status, report.confirmed_height)
+    else {
+        return notifications;
+    };
+    let tip_height = funding_height + confirmations.saturating_sub(1);
+    if let Some(schedule) = TimelockSchedule::from_watch(watch, status, tip_height, now, profile) {
+        notifications.extend(
+            scheduler
+                .check(&schedule, now)
+                .map(|warning| warning.notification(language)),
+        );
+    }
+    notifications
+}
+
+/// The [`Notification`]s of the signatures counterparties added after `since`
+/// to the sessions in `storage` of the registered [`Accounts`], in `language`.
+///
+/// A session no account takes part in yet is assigned to the first registered account
+/// taking part in it, and the accounts saved.
+/// Sessions that can't be loaded, such as encrypted ones, are skipped.
+#[cfg(feature = "serde-types")]
+fn session_notifications(
+    storage: &impl Storage,
+    since: Timestamp,
+    language: Language,
+) -> Vec<Notification> {
+    let Ok(mut accounts) = Accounts::load(storage) else {
+        return Vec::new();
+    };
+    let registered = accounts
+        .accounts()
+        .iter()
+        .map(|account| account.npub)
+        .collect::<Vec<_>>();
+    let mut assigned = false;
+    let mut notifications = Vec::new();
+    for id in Session::list(storage).unwrap_or_default() {
+        let Ok(Some(session)) = Session::load(storage, &id) else {
+            continue;
+        };
+        let npub = match accounts.account_of(&id) {
+            Some(account) => account.npub,
+            None => {
+                let Some(npub) = registered
+                    .iter()
+                    .find(|npub| accounts.assign(&session, npub).is_ok())
+                else {
+                    continue;
+                };
+                assigned = true;
+                *npub
+            }
+        };
+        notifications.extend(
+            Notification::from_history(&session, &npub.nostr(), since, language)
+                .unwrap_or_default(),
+        );
+    }
+    if assigned {
+        // Assignments that fail to save are made again at the next refresh.
+        accounts.save(storage).ok();
+    }
+    notifications
+}
+
+/// `preferred` if it is on `network`, the first [`Chain`] on `network` otherwise.
+///
+/// Signet and Mutinynet share a network, so the settings tell their block timing apart.
+#[cfg(feature = "serde-types")]
+pub(crate) fn chain_of(network: Network, preferred: Chain) -> Chain {
+    if preferred.network() == network {
+        return preferred;
+    }
+    Chain::ALL
+        .into_iter()
+        .find(|chain| chain.network() == network)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use bitcoin::{Network, Txid, hashes::Hash};
+    use nostr::Timestamp;
+
+    use super::*;
+    use crate::{
+        expiry::{TimelockSchedule, TimelockScheduler},
+        network::{Chain, NetworkProfile},
+        scripts::CURRENT_SCRIPT_TEMPLATE,
+        secret::SecretNsec,
+        storage::MemoryStorage,
+    };
+    #[cfg(feature = "serde-types")]
+    use crate::{
+        protocol::{DEFAULT_OFFER_VALIDITY, Handshake, Offer, offer},
+        scripts::EscrowConfig,
+        watch::WATCH_SESSION_VERSION,
+    };
+
+    /// [`Notifier`] recording the titles it shows.
+    #[derive(Debug, Default)]
+    struct Recorder(RefCell<Vec<String>>);
+
+    impl Notifier for &Recorder {
+        fn notify(&self, notification: &Notification) -> Result<(), Error> {
+            self.0.borrow_mut().push(notification.title.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn notification_dispatch() {
+        let session = WatchSession::new(
+            EscrowConfig {
+                npub_1: SecretNsec::generate().public_key(),
+                npub_2: SecretNsec::generate().public_key(),
+                npub_arbitrator: Some(SecretNsec::generate().public_key()),
+                timelock_duration: Some(1_000),
+                network: Network::Regtest,
+                template: CURRENT_SCRIPT_TEMPLATE,
+            },
+            Txid::all_zeros(),
+            "Order 42",
+        )
+        .unwrap();
+        let funded = |confirmations: u32| WatchStatus::Funded {
+            confirmations,
+            timelock_height: Some(1_100),
+        };
+        let notify = |status: WatchStatus| Notification::from_watch(&session, status, Language::En);
+        assert!(notify(WatchStatus::Unconfirmed).is_empty());
+        let notifications = notify(funded(1));
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].kind, NotificationKind::FundingConfirmed);
+        assert!(notifications[0].body.contains("Order 42"));
+        let profile = NetworkProfile::from(Chain::Mainnet);
+        let now = Timestamp::from(1_700_000_000);
+        let timelock = |now: Timestamp| {
+            let schedule =
+                TimelockSchedule::from_watch(&session, funded(957), 1_056, now, &profile).unwrap();
+            vec![
+                TimelockScheduler::default()
+                    .check(&schedule, now)
+                    .unwrap()
+                    .notification(Language::En),
+            ]
+        };
+        assert!(timelock(now)[0].body.contains("44 blocks"));
+
+        // Each notification is shown once, if enabled.
+        let recorder = Recorder::default();
+        let storage = MemoryStorage::default();
+        let preferences = NotificationPreferences {
+            funding_confirmed: false,
+            ..NotificationPreferences::load(&storage).unwrap()
+        };
+        preferences.save(&storage).unwrap();
+        let preferences = NotificationPreferences::load(&storage).unwrap();
+        let mut dispatcher = Dispatcher::new(&recorder, preferences);
+        assert_eq!(dispatcher.dispatch(notify(funded(957))).unwrap(), 0);
+        assert_eq!(dispatcher.dispatch(timelock(now)).unwrap(), 1);
+        assert_eq!(dispatcher.dispatch(timelock(now)).unwrap(), 0);
+        dispatcher.set_preferences(NotificationPreferences::default());
+        assert_eq!(dispatcher.dispatch(notify(funded(958))).unwrap(), 1);
+        assert_eq!(
+            *recorder.0.borrow(),
+            ["Dispute timelock expiring", "Escrow funded"]
+        );
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn watcher_notifications() {
+        let watch = WatchSession {
+            version: WATCH_SESSION_VERSION,
+            config: EscrowConfig {
+                npub_1: SecretNsec::generate().public_key(),
+                npub_2: SecretNsec::generate().public_key(),
+                npub_arbitrator: Some(SecretNsec::generate().public_key()),
+                timelock_duration: Some(2_016),
+                network: Network::Signet,
+                template: CURRENT_SCRIPT_TEMPLATE,
+            },
+            funding_txid: Txid::all_zeros(),
+            label: "order 42".to_string(),
+        };
+        let report = AuditReport {
+            funding_txid: watch.funding_txid,
+            outpoint: None,
+            amount: None,
+            confirmed_height: Some(100),
+            spend: None,
+            near_miss: None,
+        };
+        let funded = WatchStatus::Funded {
+            confirmations: 1,
+            timelock_height: Some(2_115),
+        };
+        let now = Timestamp::from(1_700_000_000);
+        let kinds = |previous, chain, scheduler: &mut TimelockScheduler| {
+            watch_notifications(
+                &watch,
+                &report,
+                previous,
+                funded,
+                scheduler,
+                &NetworkProfile::from(chain),
+                now,
+                Language::En,
+            )
+            .into_iter()
+            .map(|notification| notification.kind)
+            .collect::<Vec<_>>()
+        };
+
+        // The escrow is on the preferred chain only if it shares its network.
+        assert_eq!(
+            chain_of(Network::Signet, Chain::Mutinynet),
+            Chain::Mutinynet
+        );
+        assert_eq!(chain_of(Network::Signet, Chain::Mainnet), Chain::Signet);
+        assert_eq!(chain_of(Network::Regtest, Chain::Signet), Chain::Regtest);
+
+        // The funding confirmation is notified once funded, the timelock two weeks away is not.
+        let mut scheduler = TimelockScheduler::default();
+        let previous = Some(WatchStatus::Unconfirmed);
+        assert_eq!(
+            kinds(previous, Chain::Signet, &mut scheduler),
+            [NotificationKind::FundingConfirmed]
+        );
+        assert_eq!(kinds(Some(funded), Chain::Signet, &mut scheduler), []);
+
+        // With 30-second blocks the timelock is less than a day away, which is warned once.
+        assert_eq!(
+            kinds(Some(funded), Chain::Mutinynet, &mut scheduler),
+            [NotificationKind::TimelockExpiring]
+        );
+        assert_eq!(kinds(Some(funded), Chain::Mutinynet, &mut scheduler), []);
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn counterparty_signatures() {
+        let now = Timestamp::now();
+        let nsec = SecretNsec::generate();
+        let npub = Npub::from(nsec.public_key());
+        let offer = Offer {
+            amount_seller: bitcoin::Amount::ZERO,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(npub.nostr(), None)
+        };
+        let (handshake, _) = nsec
+            .with_nostr_secret_key(|secret_key| Handshake::offer(secret_key, offer, now))
+            .unwrap();
+        let mut session = Session::new(handshake);
+        let id = session.id().unwrap();
+        let counterparty = SecretNsec::generate().public_key();
+        let signed = |npub, byte| HistoryEvent::Signed {
+            npub,
+            txid: Txid::from_byte_array([byte; 32]),
+        };
+        let before = Timestamp::from(now.as_u64() - 60);
+        let after = Timestamp::from(now.as_u64() + 60);
+        session.history.record(signed(counterparty, 1), before);
+        session.history.record(signed(counterparty, 2), after);
+        session.history.record(signed(npub.nostr(), 2), after);
+        let storage = MemoryStorage::default();
+        session.save(&storage).unwrap();
+
+        // Sessions of no registered account are not notified.
+        assert!(session_notifications(&storage, now, Language::En).is_empty());
+
+        // The session is assigned to the account offering it, notified of its counterparty.
+        let mut accounts = Accounts::default();
+        accounts.register(npub, "Personal").unwrap();
+        accounts.save(&storage).unwrap();
+        let notifications = session_notifications(&storage, now, Language::En);
+        assert_eq!(
+            notifications
+                .iter()
+                .map(|notification| notification.kind)
+                .collect::<Vec<_>>(),
+            [NotificationKind::CounterpartySigned]
+        );
+        let accounts = Accounts::load(&storage).unwrap();
+        assert_eq!(accounts.account_of(&id).unwrap().npub, npub);
+
+        // The watcher reads the sessions only while their store is unlocked.
+        let recorder = Recorder::default();
+        let mut watcher = EscrowWatcher::new(&recorder);
+        let mut keystore = Keystore::default();
+        keystore.unlock_sessions(&storage, "passphrase").unwrap();
+        keystore.lock_sessions();
+        let notify = |watcher: &mut EscrowWatcher<_>, keystore: &Keystore, now| {
+            watcher
+                .notify_signatures(&storage, keystore, now, Language::En)
+                .unwrap()
+        };
+        assert_eq!(notify(&mut watcher, &keystore, before), 0);
+        keystore.unlock_sessions(&storage, "passphrase").unwrap();
+        assert_eq!(notify(&mut watcher, &keystore, before), 0);
+        assert_eq!(notify(&mut watcher, &keystore, now), 1);
+        assert_eq!(recorder.0.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,387 @@
+//! Offline signing of escrow spends, for arbitrators keeping their nsec air-gapped.
+//!
+//! The online party exports a [`SigningBundle`] with exactly what signing needs:
+//! the unsigned transaction, the outputs it spends, the leaf script and the escrow terms.
+//! The offline machine checks the bundle against the [`SigningInvariants`] its user agreed to
+//! and signs it through the `sign_bundle` API method, emitting just the Taproot signature,
+//! which the online party imports back with [`SigningBundle::import_signature`].
+//! Arbitrators sign through their [`Arbitration`], so their dispute records are enforced
+//! offline too, and also emit the decision for the online party to publish.
+
+use bitcoin::{ScriptBuf, Transaction, TxOut, consensus, hex::DisplayHex};
+use nostr::{Timestamp, key::PublicKey as NostrPublicKey};
+use secp256k1::{SECP256K1, schnorr};
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arbitration::{ArbitratedSignature, Arbitration, is_arbitrator},
+    decode::parse_tx_hex,
+    error::Error,
+    invariants::SigningInvariants,
+    protocol::SessionId,
+    scripts::{EscrowConfig, EscrowScript},
+    secret::SecretNsec,
+    sign::{BatchSigner, LeafSignatures, script_spend_message},
+    util::npub_to_x_only_public_key,
+};
+
+/// Version of the [`SigningBundle`] format.
+pub(crate) const SIGNING_BUNDLE_VERSION: u8 = 1;
+
+/// Everything needed to sign an escrow leaf spend on an offline machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct SigningBundle {
+    /// Format version, see [`SIGNING_BUNDLE_VERSION`].
+    pub(crate) version: u8,
+    /// Negotiation of the escrow, if known.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub(crate) session_id: Option<SessionId>,
+    /// Terms of the escrow being spent.
+    pub(crate) config: EscrowConfig,
+    /// Unsigned transaction, in hex.
+    pub(crate) tx_hex: String,
+    /// Index of the input spending the escrow.
+    pub(crate) input_index: usize,
+    /// Outputs spent by every input of the transaction, in input order.
+    pub(crate) prevouts: Vec<TxOut>,
+    /// The leaf being spent.
+    pub(crate) escrow_script: EscrowScript,
+    /// Script of the leaf, checked against the terms before signing.
+    pub(crate) leaf_script: ScriptBuf,
+}
+
+impl SigningBundle {
+    /// Bundles the spend of input `input_index` of `tx` through the `escrow_script` leaf
+    /// of the escrow `config`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the bundle doesn't check out, see [`SigningBundle::verify`].
+    pub(crate) fn new(
+        session_id: Option<SessionId>,
+        config: EscrowConfig,
+        tx: &Transaction,
+        input_index: usize,
+        prevouts: Vec<TxOut>,
+        escrow_script: EscrowScript,
+    ) -> Result<Self, Error> {
+        let bundle = Self {
+            version: SIGNING_BUNDLE_VERSION,
+            session_id,
+            config,
+            tx_hex: consensus::serialize(tx).to_lower_hex_string(),
+            input_index,
+            prevouts,
+            escrow_script,
+            leaf_script: config.script(escrow_script)?,
+        };
+        bundle.verify()?;
+        Ok(bundle)
+    }
+
+    /// Checks that the bundle spends the escrow of its terms through its leaf,
+    /// returning the unsigned transaction.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the format version is unsupported, the transaction doesn't parse,
+    /// there is not one prevout per input, or the leaf script or spent output
+    /// don't match the terms.
+    pub(crate) fn verify(&self) -> Result<Transaction, Error> {
+        if self.version != SIGNING_BUNDLE_VERSION {
+            return Err(Error::WrongInputs(format!(
+                "Unsupported signing bundle version {}",
+                self.version
+            )));
+        }
+        let tx = parse_tx_hex(&self.tx_hex)?;
+        if self.prevouts.len() != tx.input.len() {
+            return Err(Error::WrongInputs(format!(
+                "Expected {} prevouts, got {}",
+                tx.input.len(),
+                self.prevouts.len()
+            )));
+        }
+        if self.leaf_script != self.config.script(self.escrow_script)? {
+            return Err(Error::WrongInputs(
+                "Leaf script does not match the escrow terms".to_string(),
+            ));
+        }
+        let prevout = self.prevouts.get(self.input_index).ok_or_else(|| {
+            Error::WrongInputs(format!("Transaction has no input {}", self.input_index))
+        })?;
+        if prevout.script_pubkey != self.config.address()?.script_pubkey() {
+            return Err(Error::WrongInputs(
+                "Signed input does not spend the escrow".to_string(),
+            ));
+        }
+        Ok(tx)
+    }
+
+    /// Signs the bundle with the `nsec` of one of the participants signing the leaf,
+    /// if its transaction keeps the `invariants` the signer agreed to.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the bundle doesn't check out, spends other outputs than the invariants,
+    /// breaks them, or `nsec` is not a participant signing the leaf:
+    /// arbitrators sign with [`SigningBundle::arbitrate`].
+    pub(crate) fn sign(
+        &self,
+        nsec: &SecretNsec,
+        invariants: &SigningInvariants,
+    ) -> Result<schnorr::Signature, Error> {
+        let tx = self.verify()?;
+        let npub = nsec.public_key();
+        self.check_signer(&npub)?;
+        if is_arbitrator(&self.config, &npub) {
+            return Err(Error::WrongInputs(
+                "Arbitrators sign bundles through their dispute records".to_string(),
+            ));
+        }
+        self.check_prevouts(invariants)?;
+        BatchSigner::new(&tx, invariants)?.sign(self.input_index, &self.leaf_script, nsec)
+    }
+
+    /// Signs the bundle with the arbitrator's `nsec` through their `arbitration`,
+    /// if its transaction keeps the `invariants` the arbitrator agreed to,
+    /// gift wrapping the decision at `now`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the bundle doesn't check out, is for another session than the arbitration,
+    /// breaks the invariants or the dispute records don't allow it,
+    /// see [`Arbitration::sign`].
+    pub(crate) fn arbitrate(
+        &self,
+        nsec: &SecretNsec,
+        invariants: &SigningInvariants,
+        arbitration: &Arbitration,
+        now: Timestamp,
+    ) -> Result<ArbitratedSignature, Error> {
+        let tx = self.verify()?;
+        self.check_signer(&nsec.public_key())?;
+        if let Some(session_id) = self.session_id
+            && session_id != arbitration.handshake.session_id()?
+        {
+            return Err(Error::WrongInputs(
+                "Bundle is for another session than the dispute".to_string(),
+            ));
+        }
+        self.check_prevouts(invariants)?;
+        invariants.check(&tx)?;
+        arbitration.sign(
+            &tx,
+            self.input_index,
+            self.prevouts.clone(),
+            self.escrow_script,
+            nsec,
+            now,
+        )
+    }
+
+    /// Imports the `signature` of `npub` made offline,
+    /// returning it as [`LeafSignatures`] to combine with the other signer's.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `npub` is not a signer of the leaf or the signature doesn't verify.
+    pub(crate) fn import_signature(
+        &self,
+        npub: NostrPublicKey,
+        signature: schnorr::Signature,
+    ) -> Result<LeafSignatures, Error> {
+        let tx = self.verify()?;
+        self.check_signer(&npub)?;
+        let message =
+            script_spend_message(&tx, self.input_index, &self.prevouts, &self.leaf_script)?;
+        SECP256K1.verify_schnorr(&signature, &message, &npub_to_x_only_public_key(&npub)?)?;
+        let mut signatures =
+            LeafSignatures::new(tx.compute_txid(), self.input_index, self.escrow_script);
+        signatures.session_id = self.session_id;
+        signatures.insert(npub, signature);
+        Ok(signatures)
+    }
+
+    /// Checks that the bundle spends the outputs of the `invariants`.
+    fn check_prevouts(&self, invariants: &SigningInvariants) -> Result<(), Error> {
+        if invariants.prevouts != self.prevouts {
+            return Err(Error::WrongInputs(
+                "Bundle spends other outputs than agreed".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that `npub` signs the leaf of the bundle.
+    fn check_signer(&self, npub: &NostrPublicKey) -> Result<(), Error> {
+        if !self.config.signers(self.escrow_script)?.contains(npub) {
+            return Err(Error::WrongInputs(format!(
+                "Key is not a signer of leaf {:?}",
+                self.escrow_script
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Amount, Network, OutPoint, Sequence, TxIn, absolute, transaction};
+    use nostr::Keys;
+
+    use super::*;
+    use crate::{
+        arbitration::ArbitratorMode,
+        audit::analyze_spend,
+        decision::agreed,
+        invariants::ApprovedOutputs,
+        protocol::{Handshake, offer},
+        settings::FeeRateLimits,
+        sign::combine_signatures,
+    };
+
+    #[test]
+    fn offline_arbitrator_signature() {
+        let (keys_seller, keys_buyer, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let [seller, buyer, arbitrator] = [&keys_seller, &keys_buyer, &keys_arbitrator]
+            .map(|keys| SecretNsec::from(keys.secret_key().clone()));
+        let network = Network::Regtest;
+        let offer = offer(keys_seller.public_key(), Some(keys_arbitrator.public_key()));
+        let (_, offer_event) =
+            Handshake::offer(keys_seller.secret_key(), offer, Timestamp::now()).unwrap();
+        let (handshake, _) = Handshake::accept(
+            keys_buyer.secret_key(),
+            &offer_event,
+            None,
+            Timestamp::now(),
+        )
+        .unwrap();
+        let session_id = handshake.session_id().unwrap();
+        let (offer, acceptor) = agreed(&handshake).unwrap();
+        let config = offer.escrow_config(acceptor).unwrap();
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(110_000),
+            script_pubkey: config.address().unwrap().script_pubkey(),
+        }];
+        let pay = |nsec: &SecretNsec, value: u64| TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: nsec.npub().address(network).unwrap().script_pubkey(),
+        };
+        // The arbitrator rules 75% of the escrow after fees for the buyer.
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                sequence: Sequence::from_height(144),
+                ..Default::default()
+            }],
+            output: vec![pay(&buyer, 81_750), pay(&seller, 27_250)],
+        };
+        let invariants = SigningInvariants {
+            funding_outpoints: vec![OutPoint::default()],
+            prevouts: prevouts.clone(),
+            approved_outputs: ApprovedOutputs::Destinations(
+                [&buyer, &seller]
+                    .map(|nsec| pay(nsec, 0).script_pubkey)
+                    .to_vec(),
+            ),
+            max_fee: Amount::from_sat(1_000),
+            lock_time: absolute::LockTime::ZERO,
+            timelocks: vec![Some(144)],
+        };
+        let mut mode = ArbitratorMode::default();
+        mode.open(&handshake).unwrap();
+        mode.rule(&session_id, 7_500).unwrap();
+        let arbitration = Arbitration {
+            mode,
+            handshake: handshake.clone(),
+            reason: "Goods damaged".to_string(),
+            limits: FeeRateLimits::default(),
+            funding_confirmations: 1,
+        };
+        let bundle = SigningBundle::new(
+            Some(session_id),
+            config,
+            &tx,
+            0,
+            prevouts.clone(),
+            EscrowScript::B,
+        )
+        .unwrap();
+
+        // The offline machine only gets the bundle and emits the signature,
+        // through the dispute records for the arbitrator.
+        let now = Timestamp::now();
+        assert!(bundle.sign(&arbitrator, &invariants).is_err());
+        let arbitrated = bundle
+            .arbitrate(&arbitrator, &invariants, &arbitration, now)
+            .unwrap();
+        assert!(!arbitrated.decision.is_empty());
+        assert!(bundle.sign(&seller, &invariants).is_err());
+
+        // Nor anything the signers didn't agree to.
+        let fee_bump = SigningInvariants {
+            max_fee: Amount::from_sat(500),
+            ..invariants.clone()
+        };
+        assert!(bundle.sign(&buyer, &fee_bump).is_err());
+        assert!(
+            bundle
+                .arbitrate(&arbitrator, &fee_bump, &arbitration, now)
+                .is_err()
+        );
+        let unruled = Arbitration {
+            mode: ArbitratorMode::default(),
+            ..arbitration.clone()
+        };
+        assert!(
+            bundle
+                .arbitrate(&arbitrator, &invariants, &unruled, now)
+                .is_err()
+        );
+
+        // The online party imports it, rejecting signatures by the wrong key.
+        let signature = arbitrated.signature;
+        assert!(
+            bundle
+                .import_signature(buyer.public_key(), signature)
+                .is_err()
+        );
+        let mut signatures = bundle
+            .import_signature(arbitrator.public_key(), signature)
+            .unwrap();
+        signatures.insert(
+            buyer.public_key(),
+            bundle.sign(&buyer, &invariants).unwrap(),
+        );
+        let signed = combine_signatures(
+            tx,
+            0,
+            signatures.ordered(&config).unwrap(),
+            &bundle.leaf_script,
+            &config.spend_info().unwrap(),
+        )
+        .unwrap();
+        let audit = analyze_spend(&signed, 0, &prevouts, &config).unwrap();
+        assert!(audit.signers.iter().all(|signer| signer.valid));
+
+        // Tampered terms are caught offline.
+        let tampered = SigningBundle {
+            escrow_script: EscrowScript::C,
+            ..bundle
+        };
+        assert!(
+            tampered
+                .arbitrate(&arbitrator, &invariants, &arbitration, now)
+                .is_err()
+        );
+    }
+}
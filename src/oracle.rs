@@ -0,0 +1,675 @@
+//! Escrows resolved by an oracle's attestation, DLC-style, without a human arbitrator.
+//!
+//! Some disputes are objective, such as whether a tracking service saw the shipment delivered.
+//! An oracle announces such an [`OracleEvent`] with its key `P` and a one-time nonce `R`,
+//! and later attests to the outcome `m` with the BIP-340 signature `(R, s)`.
+//! Since `s·G = R + e·P` with `e` the challenge of `m`, the attestation point of each outcome
+//! is known in advance, and `s` is its [`AdaptorSecret`].
+//!
+//! An [`OracleEscrow`] has, next to the collaborative leaf `A`, a leaf per outcome
+//! that needs a signature by the outcome's winner and one by its attestation point:
+//!
+//! ```text
+//! <attestation point> OP_CHECKSIGVERIFY <winner> OP_CHECKSIG
+//! ```
+//!
+//! Once the oracle attests, only the winner can spend through the leaf of the attested
+//! outcome, with [`sign_oracle_resolution`]. The attestation points can also lock
+//! [`adaptor_sign_leaf`](crate::adaptor::adaptor_sign_leaf) signatures on leaf `A`.
+//!
+//! So the coins don't stay locked if the oracle never attests, a refund leaf lets the
+//! funder take them back once the escrow is [`OracleEscrow::refund_timelock`] blocks old,
+//! with [`sign_oracle_refund`]:
+//!
+//! ```text
+//! <refund timelock> OP_CHECKSEQUENCEVERIFY OP_DROP <refund> OP_CHECKSIG
+//! ```
+//!
+//! An oracle signing two outcomes of an event with the same nonce reveals its secret key,
+//! which keeps it from equivocating.
+
+use bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    XOnlyPublicKey, absolute,
+    opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CSV, OP_DROP},
+    taproot::{ControlBlock, LeafVersion, TaprootBuilder, TaprootSpendInfo},
+    transaction,
+};
+use nostr::key::PublicKey as NostrPublicKey;
+use secp256k1::{Message, Parity, SECP256K1, Scalar, schnorr};
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adaptor::{AdaptorPoint, AdaptorSecret, challenge},
+    error::Error,
+    invariants::SigningInvariants,
+    message::tagged_hash,
+    scripts::{EscrowScript, UNSPENDABLE_PUBLIC_KEY, escrow_scripts},
+    secret::SecretNsec,
+    sign::{BatchSigner, leaf_witness},
+    util::{npub_to_address, npub_to_x_only_public_key},
+};
+
+/// Tag of the hash of an outcome the oracle signs.
+const OUTCOME_TAG: &[u8] = b"scrow/oracle/outcome";
+
+/// An event announced by an oracle, before it happens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct OracleEvent {
+    /// Key the oracle attests with.
+    pub(crate) oracle: XOnlyPublicKey,
+    /// Nonce the oracle commits to for this event.
+    pub(crate) nonce: XOnlyPublicKey,
+    /// ID of the event, such as a tracking number.
+    pub(crate) event_id: String,
+    /// Possible outcomes, such as `delivered` and `returned`.
+    pub(crate) outcomes: Vec<String>,
+}
+
+impl OracleEvent {
+    /// Checks that the event has distinct outcomes to attest to.
+    pub(crate) fn new(
+        oracle: XOnlyPublicKey,
+        nonce: XOnlyPublicKey,
+        event_id: String,
+        outcomes: Vec<String>,
+    ) -> Result<Self, Error> {
+        if outcomes.len() < 2 {
+            return Err(Error::WrongInputs(
+                "An oracle event needs at least two outcomes".to_string(),
+            ));
+        }
+        if outcomes
+            .iter()
+            .enumerate()
+            .any(|(i, outcome)| outcomes[..i].contains(outcome))
+        {
+            return Err(Error::WrongInputs(
+                "Oracle event outcomes must be distinct".to_string(),
+            ));
+        }
+        Ok(Self {
+            oracle,
+            nonce,
+            event_id,
+            outcomes,
+        })
+    }
+
+    /// The message the oracle signs to attest to `outcome`.
+    pub(crate) fn outcome_message(&self, outcome: &str) -> Result<Message, Error> {
+        if !self.outcomes.iter().any(|o| o == outcome) {
+            return Err(Error::WrongInputs(format!(
+                "Unknown outcome of {}: {outcome}",
+                self.event_id
+            )));
+        }
+        let mut data = self.event_id.as_bytes().to_vec();
+        data.push(0);
+        data.extend_from_slice(outcome.as_bytes());
+        Ok(Message::from_digest(tagged_hash(OUTCOME_TAG, &data)))
+    }
+
+    /// The point `R + e·P` the attestation of `outcome` reveals the secret of.
+    pub(crate) fn attestation_point(&self, outcome: &str) -> Result<AdaptorPoint, Error> {
+        let nonce = self.nonce.public_key(Parity::Even);
+        let oracle = self.oracle.public_key(Parity::Even);
+        let e = challenge(&nonce, &oracle, &self.outcome_message(outcome)?)?;
+        let point = oracle
+            .mul_tweak(SECP256K1, &Scalar::from(e))?
+            .combine(&nonce)?;
+        Ok(AdaptorPoint::from(point))
+    }
+
+    /// Checks that `attestation` is the oracle's signature of one of the outcomes,
+    /// with the announced nonce.
+    pub(crate) fn verify_attestation(&self, attestation: &OracleAttestation) -> Result<(), Error> {
+        let invalid = || {
+            Error::WrongInputs(format!(
+                "Invalid attestation of {} by the oracle",
+                self.event_id
+            ))
+        };
+        if attestation.event_id != self.event_id
+            || attestation.signature.as_ref()[..32] != self.nonce.serialize()
+        {
+            return Err(invalid());
+        }
+        let message = self.outcome_message(&attestation.outcome)?;
+        SECP256K1
+            .verify_schnorr(&attestation.signature, &message, &self.oracle)
+            .map_err(|_| invalid())
+    }
+}
+
+/// An oracle's signature of the outcome of an [`OracleEvent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct OracleAttestation {
+    /// ID of the event.
+    pub(crate) event_id: String,
+    /// The outcome attested to.
+    pub(crate) outcome: String,
+    /// BIP-340 signature of the outcome with the announced nonce.
+    pub(crate) signature: schnorr::Signature,
+}
+
+impl OracleAttestation {
+    /// The secret of the attestation point of the outcome.
+    pub(crate) fn adaptor_secret(&self) -> Result<AdaptorSecret, Error> {
+        AdaptorSecret::from_attestation(&self.signature)
+    }
+}
+
+/// An escrow resolved either collaboratively or by an oracle's attestation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct OracleEscrow {
+    /// The buyer's `npub`.
+    pub(crate) npub_1: NostrPublicKey,
+    /// The seller's `npub`.
+    pub(crate) npub_2: NostrPublicKey,
+    /// The event the oracle attests to.
+    pub(crate) event: OracleEvent,
+    /// Who gets the escrow on each outcome of the event, in the same order.
+    pub(crate) winners: Vec<NostrPublicKey>,
+    /// Who gets the escrow back if the oracle never attests, usually the buyer who funded it.
+    pub(crate) refund: NostrPublicKey,
+    /// Relative timelock in blocks after which [`OracleEscrow::refund`] can spend the escrow.
+    pub(crate) refund_timelock: u32,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+}
+
+impl OracleEscrow {
+    /// An escrow between `npub_1` and `npub_2` paying `winners[i]` on `event.outcomes[i]`,
+    /// or `refund` after `refund_timelock` blocks.
+    pub(crate) fn new(
+        npub_1: NostrPublicKey,
+        npub_2: NostrPublicKey,
+        event: OracleEvent,
+        winners: Vec<NostrPublicKey>,
+        refund: NostrPublicKey,
+        refund_timelock: u32,
+        network: Network,
+    ) -> Result<Self, Error> {
+        if winners.len() != event.outcomes.len() {
+            return Err(Error::WrongInputs(format!(
+                "Expected {} winners, got {}",
+                event.outcomes.len(),
+                winners.len()
+            )));
+        }
+        if winners
+            .iter()
+            .any(|winner| *winner != npub_1 && *winner != npub_2)
+        {
+            return Err(Error::WrongInputs(
+                "Winners must be participants of the escrow".to_string(),
+            ));
+        }
+        if refund != npub_1 && refund != npub_2 {
+            return Err(Error::WrongInputs(
+                "The refund must go to a participant of the escrow".to_string(),
+            ));
+        }
+        if refund_timelock == 0 || refund_timelock > u32::from(u16::MAX) {
+            return Err(Error::WrongInputs(format!(
+                "Refund timelock must be between 1 and {} blocks",
+                u16::MAX
+            )));
+        }
+        Ok(Self {
+            npub_1,
+            npub_2,
+            event,
+            winners,
+            refund,
+            refund_timelock,
+            network,
+        })
+    }
+
+    /// The winner of `outcome`.
+    pub(crate) fn winner(&self, outcome: &str) -> Result<&NostrPublicKey, Error> {
+        self.event
+            .outcomes
+            .iter()
+            .position(|o| o == outcome)
+            .map(|i| &self.winners[i])
+            .ok_or_else(|| Error::WrongInputs(format!("Unknown outcome: {outcome}")))
+    }
+
+    /// The leaf the winner of `outcome` spends through once the oracle attests to it.
+    pub(crate) fn oracle_script(&self, outcome: &str) -> Result<ScriptBuf, Error> {
+        let winner = npub_to_x_only_public_key(self.winner(outcome)?)?;
+        let point = self.event.attestation_point(outcome)?;
+        Ok(ScriptBuf::builder()
+            .push_x_only_key(&point.x_only_public_key())
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_x_only_key(&winner)
+            .push_opcode(OP_CHECKSIG)
+            .into_script())
+    }
+
+    /// The leaf [`OracleEscrow::refund`] spends through after the refund timelock.
+    pub(crate) fn refund_script(&self) -> Result<ScriptBuf, Error> {
+        Ok(ScriptBuf::builder()
+            .push_sequence(Sequence::from_consensus(self.refund_timelock))
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&npub_to_x_only_public_key(&self.refund)?)
+            .push_opcode(OP_CHECKSIG)
+            .into_script())
+    }
+
+    /// The Taproot tree of the collaborative leaf `A`, the oracle leaves and the refund leaf.
+    ///
+    /// Leaf `A`, the expected spend, weighs as much as all the other leaves.
+    pub(crate) fn spend_info(&self) -> Result<TaprootSpendInfo, Error> {
+        let collaborative =
+            escrow_scripts(&self.npub_1, &self.npub_2, None, None, EscrowScript::A)?;
+        let mut leaves = vec![(self.winners.len() as u32 + 1, collaborative)];
+        for outcome in &self.event.outcomes {
+            leaves.push((1, self.oracle_script(outcome)?));
+        }
+        leaves.push((1, self.refund_script()?));
+        TaprootBuilder::with_huffman_tree(leaves)?
+            .finalize(SECP256K1, *UNSPENDABLE_PUBLIC_KEY)
+            .map_err(|_| {
+                Error::WrongInputs("Could not finalize the oracle escrow tree".to_string())
+            })
+    }
+
+    /// The address of the escrow.
+    pub(crate) fn address(&self) -> Result<Address, Error> {
+        let spend_info = self.spend_info()?;
+        Ok(Address::p2tr(
+            SECP256K1,
+            spend_info.internal_key(),
+            spend_info.merkle_root(),
+            self.network,
+        ))
+    }
+
+    /// The escrow output of `amount`, spent by its resolution or refund.
+    pub(crate) fn txout(&self, amount: Amount) -> Result<TxOut, Error> {
+        Ok(TxOut {
+            value: amount,
+            script_pubkey: self.address()?.script_pubkey(),
+        })
+    }
+
+    /// The transaction paying the escrow of `amount` at `funding` to the winner of `outcome`,
+    /// minus `fee`.
+    pub(crate) fn resolution_tx(
+        &self,
+        funding: OutPoint,
+        amount: Amount,
+        fee: Amount,
+        outcome: &str,
+    ) -> Result<Transaction, Error> {
+        let value = amount
+            .checked_sub(fee)
+            .ok_or_else(|| Error::WrongInputs(format!("Fee {fee} exceeds the escrow amount")))?;
+        Ok(Transaction {
+            version: transaction::Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: funding,
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: npub_to_address(self.winner(outcome)?, self.network)?
+                    .script_pubkey(),
+            }],
+        })
+    }
+
+    /// The transaction paying the escrow of `amount` at `funding` back to
+    /// [`OracleEscrow::refund`], minus `fee`, valid once the refund timelock expired.
+    pub(crate) fn refund_tx(
+        &self,
+        funding: OutPoint,
+        amount: Amount,
+        fee: Amount,
+    ) -> Result<Transaction, Error> {
+        let value = amount
+            .checked_sub(fee)
+            .ok_or_else(|| Error::WrongInputs(format!("Fee {fee} exceeds the escrow amount")))?;
+        Ok(Transaction {
+            version: transaction::Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: funding,
+                sequence: Sequence::from_consensus(self.refund_timelock),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: npub_to_address(&self.refund, self.network)?.script_pubkey(),
+            }],
+        })
+    }
+
+    /// The control block of the leaf `script`.
+    fn control_block(&self, script: &ScriptBuf) -> Result<ControlBlock, Error> {
+        self.spend_info()?
+            .control_block(&(script.clone(), LeafVersion::TapScript))
+            .ok_or_else(|| Error::WrongInputs("Script is not a leaf of the escrow".to_string()))
+    }
+}
+
+/// Signs input `index` of `tx`, spending `prevouts[index]` from `escrow`, through the leaf of
+/// the outcome of `attestation` with the winner's `nsec` and the revealed secret.
+///
+/// # Errors
+///
+/// Errors if the attestation is not the oracle's, or `nsec` is not the outcome's winner.
+pub(crate) fn sign_oracle_resolution(
+    mut tx: Transaction,
+    index: usize,
+    prevouts: Vec<TxOut>,
+    escrow: &OracleEscrow,
+    attestation: &OracleAttestation,
+    nsec: &SecretNsec,
+) -> Result<Transaction, Error> {
+    escrow.event.verify_attestation(attestation)?;
+    if nsec.public_key() != *escrow.winner(&attestation.outcome)? {
+        return Err(Error::WrongInputs(format!(
+            "Only the winner of {} can claim the escrow",
+            attestation.outcome
+        )));
+    }
+    let script = escrow.oracle_script(&attestation.outcome)?;
+    let secret = attestation.adaptor_secret()?.to_nsec();
+    // The winner built `tx` paying themselves, and the outcome leaves have no timelock.
+    let invariants = SigningInvariants::of_local_tx(&tx, prevouts, vec![None; tx.input.len()]);
+    let (winner_signature, attestation_signature) = {
+        let mut signer = BatchSigner::new(&tx, &invariants)?;
+        (
+            signer.sign(index, &script, nsec)?,
+            signer.sign(index, &script, &secret)?,
+        )
+    };
+    let control_block = escrow.control_block(&script)?;
+    // The attestation key is checked first, so its signature goes on top of the stack.
+    tx.input[index].witness = leaf_witness(
+        vec![&winner_signature, &attestation_signature],
+        &script,
+        &control_block,
+    );
+    Ok(tx)
+}
+
+/// Signs input `index` of `tx`, spending `prevouts[index]` from `escrow`, through the refund
+/// leaf with the `nsec` of [`OracleEscrow::refund`].
+///
+/// # Errors
+///
+/// Errors if `nsec` is not the refund's, or the input doesn't wait for the refund timelock.
+pub(crate) fn sign_oracle_refund(
+    mut tx: Transaction,
+    index: usize,
+    prevouts: Vec<TxOut>,
+    escrow: &OracleEscrow,
+    nsec: &SecretNsec,
+) -> Result<Transaction, Error> {
+    if nsec.public_key() != escrow.refund {
+        return Err(Error::WrongInputs(
+            "Only the refund's recipient can take the escrow back".to_string(),
+        ));
+    }
+    let script = escrow.refund_script()?;
+    // The refund's recipient built `tx` paying themselves, only the timelock is checked.
+    let mut timelocks = vec![None; tx.input.len()];
+    if let Some(timelock) = timelocks.get_mut(index) {
+        *timelock = Some(escrow.refund_timelock);
+    }
+    let invariants = SigningInvariants::of_local_tx(&tx, prevouts, timelocks);
+    let signature = BatchSigner::new(&tx, &invariants)?.sign(index, &script, nsec)?;
+    let control_block = escrow.control_block(&script)?;
+    tx.input[index].witness = leaf_witness(vec![&signature], &script, &control_block);
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Txid, hashes::Hash};
+    use nostr::key::SecretKey as NostrSecretKey;
+    use secp256k1::SecretKey;
+
+    use super::*;
+    use crate::{
+        adaptor::{adaptor_sign, verify_adaptor_signature},
+        sign::script_spend_message,
+        testkit::{COINBASE_AMOUNT, RegtestEscrowHarness},
+    };
+
+    /// Attests to `outcome` of `event` as the oracle of `key`, with the nonce of `nonce`.
+    fn attest(
+        event: &OracleEvent,
+        key: &SecretKey,
+        nonce: &SecretKey,
+        outcome: &str,
+    ) -> OracleAttestation {
+        let even = |secret: &SecretKey| match secret.x_only_public_key(SECP256K1).1 {
+            Parity::Even => *secret,
+            Parity::Odd => secret.negate(),
+        };
+        let (x, k) = (even(key), even(nonce));
+        let message = event.outcome_message(outcome).unwrap();
+        let e = challenge(&k.public_key(SECP256K1), &x.public_key(SECP256K1), &message).unwrap();
+        let s = k
+            .add_tweak(&Scalar::from(e.mul_tweak(&Scalar::from(x)).unwrap()))
+            .unwrap();
+        let mut bytes = [0; 64];
+        bytes[..32].copy_from_slice(&event.nonce.serialize());
+        bytes[32..].copy_from_slice(&s.secret_bytes());
+        OracleAttestation {
+            event_id: event.event_id.clone(),
+            outcome: outcome.to_string(),
+            signature: schnorr::Signature::from_slice(&bytes).unwrap(),
+        }
+    }
+
+    #[test]
+    fn oracle_resolution() {
+        let oracle_key = SecretKey::from_slice(&[11; 32]).unwrap();
+        let nonce = SecretKey::from_slice(&[13; 32]).unwrap();
+        let event = OracleEvent::new(
+            oracle_key.x_only_public_key(SECP256K1).0,
+            nonce.x_only_public_key(SECP256K1).0,
+            "BR123456789".to_string(),
+            vec!["delivered".to_string(), "returned".to_string()],
+        )
+        .unwrap();
+        assert!(
+            OracleEvent::new(
+                event.oracle,
+                event.nonce,
+                event.event_id.clone(),
+                vec!["delivered".to_string(), "delivered".to_string()],
+            )
+            .is_err()
+        );
+
+        let buyer = SecretNsec::from(NostrSecretKey::from_slice(&[3; 32]).unwrap());
+        let seller = SecretNsec::from(NostrSecretKey::from_slice(&[5; 32]).unwrap());
+        let escrow = OracleEscrow::new(
+            buyer.public_key(),
+            seller.public_key(),
+            event.clone(),
+            vec![seller.public_key(), buyer.public_key()],
+            buyer.public_key(),
+            144,
+            Network::Regtest,
+        )
+        .unwrap();
+        assert!(
+            OracleEscrow::new(
+                buyer.public_key(),
+                seller.public_key(),
+                event.clone(),
+                vec![seller.public_key()],
+                buyer.public_key(),
+                144,
+                Network::Regtest,
+            )
+            .is_err()
+        );
+        assert!(
+            OracleEscrow::new(
+                buyer.public_key(),
+                seller.public_key(),
+                event.clone(),
+                vec![seller.public_key(), buyer.public_key()],
+                buyer.public_key(),
+                0,
+                Network::Regtest,
+            )
+            .is_err()
+        );
+
+        // The attestation is a valid signature revealing the secret of the anticipated point.
+        let attestation = attest(&event, &oracle_key, &nonce, "delivered");
+        event.verify_attestation(&attestation).unwrap();
+        assert_eq!(
+            attestation.adaptor_secret().unwrap().point(),
+            event.attestation_point("delivered").unwrap()
+        );
+        assert!(event.attestation_point("lost").is_err());
+        let mut forged = attestation.clone();
+        forged.outcome = "returned".to_string();
+        assert!(event.verify_attestation(&forged).is_err());
+        let other_nonce = SecretKey::from_slice(&[17; 32]).unwrap();
+        let mut reannounced = event.clone();
+        reannounced.nonce = other_nonce.x_only_public_key(SECP256K1).0;
+        assert!(
+            event
+                .verify_attestation(&attest(
+                    &reannounced,
+                    &oracle_key,
+                    &other_nonce,
+                    "delivered"
+                ))
+                .is_err()
+        );
+
+        // Only the seller claims through the leaf of the attested delivery.
+        let prevout = escrow.txout(Amount::from_sat(100_000)).unwrap();
+        let funding = OutPoint::new(Txid::all_zeros(), 0);
+        let tx = escrow
+            .resolution_tx(funding, prevout.value, Amount::from_sat(500), "delivered")
+            .unwrap();
+        assert_eq!(
+            tx.output[0].script_pubkey,
+            npub_to_address(&seller.public_key(), Network::Regtest)
+                .unwrap()
+                .script_pubkey()
+        );
+        assert!(
+            sign_oracle_resolution(
+                tx.clone(),
+                0,
+                vec![prevout.clone()],
+                &escrow,
+                &attestation,
+                &buyer
+            )
+            .is_err()
+        );
+        let signed =
+            sign_oracle_resolution(tx, 0, vec![prevout.clone()], &escrow, &attestation, &seller)
+                .unwrap();
+        let witness = &signed.input[0].witness;
+        assert_eq!(witness.len(), 4);
+        let script = escrow.oracle_script("delivered").unwrap();
+        let message = script_spend_message(&signed, 0, &[prevout], &script).unwrap();
+        let keys = [
+            npub_to_x_only_public_key(&seller.public_key()).unwrap(),
+            event
+                .attestation_point("delivered")
+                .unwrap()
+                .x_only_public_key(),
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            let signature = schnorr::Signature::from_slice(witness.nth(i).unwrap()).unwrap();
+            SECP256K1.verify_schnorr(&signature, &message, key).unwrap();
+        }
+
+        // The attestation points also lock adaptor signatures on the collaborative leaf.
+        let point = event.attestation_point("returned").unwrap();
+        let signature = adaptor_sign(&seller, &message, &point).unwrap();
+        verify_adaptor_signature(&signature, &seller.public_key(), &message).unwrap();
+        let returned = attest(&event, &oracle_key, &nonce, "returned");
+        let completed = signature
+            .complete(&returned.adaptor_secret().unwrap())
+            .unwrap();
+        SECP256K1
+            .verify_schnorr(
+                &completed,
+                &message,
+                &npub_to_x_only_public_key(&seller.public_key()).unwrap(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn oracle_refund() {
+        let harness = RegtestEscrowHarness::new();
+        let network = harness.network();
+        let oracle_key = SecretKey::from_slice(&[11; 32]).unwrap();
+        let nonce = SecretKey::from_slice(&[13; 32]).unwrap();
+        let event = OracleEvent::new(
+            oracle_key.x_only_public_key(SECP256K1).0,
+            nonce.x_only_public_key(SECP256K1).0,
+            "BR123456789".to_string(),
+            vec!["delivered".to_string(), "returned".to_string()],
+        )
+        .unwrap();
+        let buyer = SecretNsec::generate();
+        let seller = SecretNsec::generate();
+        let refund_timelock = 6;
+        let escrow = OracleEscrow::new(
+            buyer.public_key(),
+            seller.public_key(),
+            event,
+            vec![seller.public_key(), buyer.public_key()],
+            buyer.public_key(),
+            refund_timelock,
+            network,
+        )
+        .unwrap();
+
+        // The buyer funds the escrow, and the oracle never attests.
+        let amount = COINBASE_AMOUNT - Amount::from_sat(1_000);
+        let address = escrow.address().unwrap();
+        let txid = harness.fund_escrow(&buyer, &address, amount);
+        let prevout = TxOut {
+            value: amount,
+            script_pubkey: address.script_pubkey(),
+        };
+        let tx = escrow
+            .refund_tx(OutPoint::new(txid, 0), amount, Amount::from_sat(1_000))
+            .unwrap();
+
+        // Only the buyer signs the refund, and only once it waits for the timelock.
+        assert!(
+            sign_oracle_refund(tx.clone(), 0, vec![prevout.clone()], &escrow, &seller).is_err()
+        );
+        let mut early = tx.clone();
+        early.input[0].sequence = Sequence::from_height(refund_timelock as u16 - 1);
+        assert!(sign_oracle_refund(early, 0, vec![prevout.clone()], &escrow, &buyer).is_err());
+        let signed = sign_oracle_refund(tx, 0, vec![prevout], &escrow, &buyer).unwrap();
+
+        // The node only accepts the refund once the funding is `refund_timelock` blocks deep.
+        harness.assert_rejected(&signed);
+        harness.mine(refund_timelock as usize - 1);
+        harness.assert_accepted(&signed);
+    }
+}
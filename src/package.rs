@@ -0,0 +1,244 @@
+//! Packages of unconfirmed parents and the child paying for them.
+//!
+//! A parent paying too little to enter the mempool on its own, such as a zero-fee
+//! escrow transaction or a resolution bumped by a child (CPFP), only relays together with
+//! its child, submitted as one package with `submitpackage` through a
+//! [`CoreRpcBackend`](crate::broadcast::CoreRpcBackend).
+//! [`Package::new`] checks what Bitcoin Core checks before relaying it: a child with its
+//! unconfirmed parents, topologically sorted, without conflicts, and a package fee rate
+//! within the user's [`FeeRateLimits`].
+use std::collections::HashMap;
+
+use bitcoin::{Amount, FeeRate, OutPoint, Transaction, TxOut, Txid, Weight};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+
+use crate::{error::Error, settings::FeeRateLimits};
+
+/// Most transactions in a package, as relayed by Bitcoin Core.
+pub(crate) const MAX_PACKAGE_COUNT: usize = 25;
+
+/// Most weight of a package, as relayed by Bitcoin Core.
+pub(crate) const MAX_PACKAGE_WEIGHT: Weight = Weight::from_wu(404_000);
+
+/// A child transaction and its unconfirmed parents, to be submitted together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Package {
+    /// The parents in topological order, then the child.
+    transactions: Vec<Transaction>,
+    /// Fee paid by the whole package.
+    fee: Amount,
+}
+
+impl Package {
+    /// Builds the package of `parents`, in topological order, and the `child` spending
+    /// every one of them.
+    ///
+    /// `prevouts` are the outputs spent by the package from outside of it,
+    /// needed for its fee.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the package is too large, a parent is not spent by the child or spends
+    /// a later one, two inputs spend the same output, a prevout is missing,
+    /// or the package fee rate is outside `limits`.
+    pub(crate) fn new(
+        parents: Vec<Transaction>,
+        child: Transaction,
+        prevouts: &[(OutPoint, TxOut)],
+        limits: &FeeRateLimits,
+    ) -> Result<Self, Error> {
+        let mut transactions = parents;
+        transactions.push(child);
+        if transactions.len() < 2 || transactions.len() > MAX_PACKAGE_COUNT {
+            return Err(Error::WrongInputs(format!(
+                "A package has 2 to {MAX_PACKAGE_COUNT} transactions, not {}",
+                transactions.len()
+            )));
+        }
+        let weight = transactions
+            .iter()
+            .map(Transaction::weight)
+            .fold(Weight::ZERO, |total, weight| total + weight);
+        if weight > MAX_PACKAGE_WEIGHT {
+            return Err(Error::WrongInputs(format!(
+                "Package of {weight:#} is heavier than {MAX_PACKAGE_WEIGHT:#}"
+            )));
+        }
+
+        // Outputs available to each transaction: the external ones and the earlier ones'.
+        let mut available = prevouts.iter().cloned().collect::<HashMap<_, _>>();
+        let txids = transactions
+            .iter()
+            .map(Transaction::compute_txid)
+            .collect::<Vec<_>>();
+        let mut spent = Vec::new();
+        let mut fee = Amount::ZERO;
+        for (index, tx) in transactions.iter().enumerate() {
+            if txids[..index].contains(&txids[index]) {
+                return Err(Error::WrongInputs(format!(
+                    "Transaction {} is twice in the package",
+                    txids[index]
+                )));
+            }
+            let mut input_value = Amount::ZERO;
+            for input in &tx.input {
+                let outpoint = input.previous_output;
+                if spent.contains(&outpoint) {
+                    return Err(Error::WrongInputs(format!(
+                        "Package spends {outpoint} twice"
+                    )));
+                }
+                if txids[index + 1..].contains(&outpoint.txid) {
+                    return Err(Error::WrongInputs(format!(
+                        "Transaction {} spends a later transaction of the package",
+                        txids[index]
+                    )));
+                }
+                let prevout = available.get(&outpoint).ok_or_else(|| {
+                    Error::WrongInputs(format!(
+                        "Missing the output {outpoint} spent by the package"
+                    ))
+                })?;
+                input_value = input_value
+                    .checked_add(prevout.value)
+                    .ok_or(Error::Rounding)?;
+                spent.push(outpoint);
+            }
+            let output_value = tx.output.iter().map(|output| output.value).sum::<Amount>();
+            let tx_fee = input_value.checked_sub(output_value).ok_or_else(|| {
+                Error::WrongInputs(format!(
+                    "Transaction {} spends more than its inputs",
+                    txids[index]
+                ))
+            })?;
+            fee = fee.checked_add(tx_fee).ok_or(Error::Rounding)?;
+            for (vout, output) in tx.output.iter().enumerate() {
+                available.insert(OutPoint::new(txids[index], vout as u32), output.clone());
+            }
+        }
+
+        // Bitcoin Core only relays a child with its parents.
+        let child = &transactions[transactions.len() - 1];
+        for parent in &txids[..txids.len() - 1] {
+            if !child
+                .input
+                .iter()
+                .any(|input| input.previous_output.txid == *parent)
+            {
+                return Err(Error::WrongInputs(format!(
+                    "Parent {parent} is not spent by the child of the package"
+                )));
+            }
+        }
+        limits.check(fee / weight)?;
+        #[cfg(debug_assertions)]
+        trace!(child = %txids[txids.len() - 1], %fee, %weight, "built package");
+        Ok(Self { transactions, fee })
+    }
+
+    /// The parents in topological order, then the child.
+    pub(crate) fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// The child paying for the parents.
+    pub(crate) fn child(&self) -> &Transaction {
+        &self.transactions[self.transactions.len() - 1]
+    }
+
+    /// [`Txid`]s of the transactions, parents first.
+    pub(crate) fn txids(&self) -> Vec<Txid> {
+        self.transactions
+            .iter()
+            .map(Transaction::compute_txid)
+            .collect()
+    }
+
+    /// Fee paid by the whole package.
+    pub(crate) fn fee(&self) -> Amount {
+        self.fee
+    }
+
+    /// Weight of the whole package.
+    pub(crate) fn weight(&self) -> Weight {
+        self.transactions
+            .iter()
+            .map(Transaction::weight)
+            .fold(Weight::ZERO, |total, weight| total + weight)
+    }
+
+    /// Fee rate of the whole package, the one miners see.
+    pub(crate) fn fee_rate(&self) -> FeeRate {
+        self.fee / self.weight()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{ScriptBuf, Sequence, TxIn, Witness, absolute, hashes::Hash, transaction};
+
+    use super::*;
+
+    fn tx(inputs: &[OutPoint], values: &[u64]) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::from_slice(&[[0; 64]]),
+                })
+                .collect(),
+            output: values
+                .iter()
+                .map(|value| TxOut {
+                    value: Amount::from_sat(*value),
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn package_topology_and_fees() {
+        let limits = FeeRateLimits::default();
+        let utxo = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let fee_utxo = OutPoint::new(Txid::from_byte_array([2; 32]), 0);
+        let prevouts = [utxo, fee_utxo].map(|outpoint| {
+            (
+                outpoint,
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: ScriptBuf::new(),
+                },
+            )
+        });
+
+        // A zero-fee parent, paid for by its child.
+        let parent = tx(&[utxo], &[100_000]);
+        let parent_output = OutPoint::new(parent.compute_txid(), 0);
+        let child = tx(&[parent_output, fee_utxo], &[199_000]);
+        let package =
+            Package::new(vec![parent.clone()], child.clone(), &prevouts, &limits).unwrap();
+        assert_eq!(package.fee(), Amount::from_sat(1_000));
+        assert_eq!(package.child(), &child);
+        assert_eq!(package.txids()[0], parent.compute_txid());
+        assert!(package.fee_rate() >= limits.min());
+
+        // The fee rate must be within the limits.
+        let stingy = tx(&[parent_output, fee_utxo], &[199_990]);
+        assert!(Package::new(vec![parent.clone()], stingy, &prevouts, &limits).is_err());
+        // The child must spend every parent, after them.
+        let unrelated = tx(&[fee_utxo], &[99_000]);
+        assert!(Package::new(vec![parent.clone()], unrelated, &prevouts, &limits).is_err());
+        assert!(Package::new(vec![child.clone()], parent.clone(), &prevouts, &limits).is_err());
+        // Without conflicts or unknown inputs.
+        let conflicting = tx(&[parent_output, utxo], &[100_000]);
+        assert!(Package::new(vec![parent.clone()], conflicting, &prevouts, &limits).is_err());
+        assert!(Package::new(vec![parent], child, &prevouts[..1], &limits).is_err());
+    }
+}
@@ -0,0 +1,753 @@
+//! [BIP-78](https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki) payjoins
+//! funding escrows.
+//!
+//! The buyer signs its payment to the escrow as usual, but instead of broadcasting it,
+//! the [`PayjoinSender`] posts this original transaction to the seller's payjoin endpoint,
+//! the `pj` parameter of the [`PaymentRequest`]. The seller's [`PayjoinReceiver`] adds its own
+//! inputs, paying its share of the escrow into the same output, and sends the proposal back.
+//! The buyer checks it, signs its inputs again and broadcasts it, so the funding transaction
+//! spends inputs of both parties, breaking the common input ownership heuristic.
+//! The seller can broadcast the original instead if the payjoin never shows up.
+//!
+//! Only version 1 of the protocol over HTTP is implemented, with P2TR inputs on both sides.
+//! The receiver pays the fees of its own inputs and change at the fee rate of the original,
+//! and never lowers or substitutes the sender's outputs, since the escrow output is fixed.
+//! Receivers process request bodies and queries, so they can sit behind any HTTP server;
+//! browsers can't host one, so the receiver role is for native builds.
+
+use bitcoin::{
+    Address, Amount, FeeRate, Psbt, Sequence, Transaction, TxIn, TxOut, Weight, Witness,
+    XOnlyPublicKey, absolute, transaction::Version,
+};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use secp256k1::{SECP256K1, schnorr};
+
+use crate::{
+    bip21::PaymentRequest,
+    cofunding::FundingInput,
+    error::{Error, ResultExt},
+    esplora::{EsploraClient, get_prevouts},
+    export::finalized_psbt,
+    runtime::post_text,
+    secret::SecretNsec,
+    sign::{key_spend_message, sign_key_spend, with_key_spend_signature},
+};
+
+/// Version of the payjoin protocol.
+pub(crate) const PAYJOIN_VERSION: u8 = 1;
+
+/// Weight of a P2TR input spent through its key path.
+const P2TR_INPUT_WEIGHT: Weight = Weight::from_wu(230);
+
+/// Weight of a P2TR output.
+const P2TR_OUTPUT_WEIGHT: Weight = Weight::from_wu(172);
+
+/// Parameters of a payjoin request, sent by the sender in the endpoint's query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PayjoinParams {
+    /// The sender's output the receiver may lower to pay for its inputs,
+    /// and by how much at most.
+    pub(crate) additional_fee: Option<(usize, Amount)>,
+    /// Minimum fee rate of the proposal.
+    pub(crate) min_fee_rate: Option<FeeRate>,
+}
+
+impl PayjoinParams {
+    /// The query of the payjoin request.
+    ///
+    /// Output substitution is always disabled, as the escrow address must be paid.
+    pub(crate) fn to_query(&self) -> String {
+        let mut query = format!("v={PAYJOIN_VERSION}");
+        if let Some((index, amount)) = self.additional_fee {
+            query.push_str(&format!(
+                "&additionalfeeoutputindex={index}&maxadditionalfeecontribution={}",
+                amount.to_sat()
+            ));
+        }
+        query.push_str("&disableoutputsubstitution=true");
+        if let Some(fee_rate) = self.min_fee_rate {
+            query.push_str(&format!("&minfeerate={}", fee_rate.to_sat_per_vb_ceil()));
+        }
+        query
+    }
+
+    /// Parses the query of a payjoin request.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the version is not supported or a parameter is invalid.
+    pub(crate) fn from_query(query: &str) -> Result<Self, Error> {
+        let invalid = |key: &str, value: &str| {
+            Error::Protocol(format!("Invalid payjoin parameter {key}={value}"))
+        };
+        let mut version = None;
+        let mut index = None;
+        let mut amount = None;
+        let mut params = Self::default();
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match key {
+                "v" => version = Some(value.parse::<u8>().map_err(|_| invalid(key, value))?),
+                "additionalfeeoutputindex" => {
+                    index = Some(value.parse::<usize>().map_err(|_| invalid(key, value))?);
+                }
+                "maxadditionalfeecontribution" => {
+                    let sats = value.parse::<u64>().map_err(|_| invalid(key, value))?;
+                    amount = Some(Amount::from_sat(sats));
+                }
+                "minfeerate" => {
+                    let sat_per_vb = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|rate| rate.is_finite() && *rate >= 0.0)
+                        .ok_or_else(|| invalid(key, value))?;
+                    let sat_per_kwu = (sat_per_vb * 250.0).ceil() as u64;
+                    params.min_fee_rate = Some(FeeRate::from_sat_per_kwu(sat_per_kwu));
+                }
+                _ => {}
+            }
+        }
+        if version != Some(PAYJOIN_VERSION) {
+            return Err(Error::Protocol(format!(
+                "Unsupported payjoin version {}",
+                version.map_or("none".to_string(), |version| version.to_string())
+            )));
+        }
+        if let (Some(index), Some(amount)) = (index, amount) {
+            params.additional_fee = Some((index, amount));
+        }
+        Ok(params)
+    }
+}
+
+/// Signs a transaction paying `outputs` from `inputs` of the [`SecretNsec`]'s `npub` address,
+/// as a finalized [`Psbt`] to send as the original of a payjoin.
+pub(crate) fn sign_original(
+    inputs: &[FundingInput],
+    outputs: Vec<TxOut>,
+    lock_time: absolute::LockTime,
+    nsec: &SecretNsec,
+) -> Result<Psbt, Error> {
+    if inputs.is_empty() {
+        return Err(Error::WrongInputs("Payment has no inputs".to_string()));
+    }
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time,
+        input: inputs
+            .iter()
+            .map(|input| TxIn {
+                previous_output: input.outpoint,
+                sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+                ..Default::default()
+            })
+            .collect(),
+        output: outputs,
+    };
+    let prevouts = inputs
+        .iter()
+        .map(|input| input.prevout.clone())
+        .collect::<Vec<_>>();
+    for index in 0..inputs.len() {
+        let signature = sign_key_spend(&tx, index, nsec, &prevouts)?;
+        tx = with_key_spend_signature(&tx, index, signature)?;
+    }
+    finalized_psbt(&tx, Some(&prevouts[..]))
+}
+
+/// The outputs spent by a finalized original [`Psbt`], in input order.
+///
+/// # Errors
+///
+/// Errors if an input is not finalized or spends a non-P2TR output.
+fn original_prevouts(original: &Psbt) -> Result<Vec<TxOut>, Error> {
+    if original.inputs.is_empty() {
+        return Err(Error::Protocol(
+            "Original transaction has no inputs".to_string(),
+        ));
+    }
+    original
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            if input
+                .final_script_witness
+                .as_ref()
+                .is_none_or(Witness::is_empty)
+            {
+                return Err(Error::Protocol(format!(
+                    "Input {index} of the original transaction is not finalized"
+                )));
+            }
+            input
+                .witness_utxo
+                .clone()
+                .filter(|prevout| prevout.script_pubkey.is_p2tr())
+                .ok_or_else(|| {
+                    Error::Protocol(format!(
+                        "Input {index} of the original transaction does not spend a P2TR output"
+                    ))
+                })
+        })
+        .collect()
+}
+
+/// The fee of `tx` spending `prevouts`.
+fn fee(tx: &Transaction, prevouts: &[TxOut]) -> Result<Amount, Error> {
+    let inputs: Amount = prevouts.iter().map(|prevout| prevout.value).sum();
+    let outputs: Amount = tx.output.iter().map(|output| output.value).sum();
+    inputs.checked_sub(outputs).ok_or(Error::FundingMismatch {
+        expected: outputs,
+        actual: inputs,
+    })
+}
+
+/// Verifies the key path spend `witness` of input `index` against the output key of its prevout.
+fn verify_key_spend(
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    witness: &Witness,
+) -> Result<schnorr::Signature, Error> {
+    let invalid = || Error::Protocol(format!("Invalid signature for input {index}"));
+    let signature = match witness.iter().collect::<Vec<_>>()[..] {
+        [signature] => schnorr::Signature::from_slice(signature).map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+    let message = key_spend_message(tx, index, prevouts)?;
+    let output_key = XOnlyPublicKey::from_slice(&prevouts[index].script_pubkey.as_bytes()[2..])?;
+    SECP256K1
+        .verify_schnorr(&signature, &message, &output_key)
+        .map_err(|_| invalid())?;
+    Ok(signature)
+}
+
+/// The buyer's side of a payjoin.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PayjoinSender {
+    /// The signed original transaction.
+    original: Psbt,
+    /// The outputs spent by the original, in input order.
+    prevouts: Vec<TxOut>,
+    /// Index of the escrow output in the original.
+    escrow_vout: usize,
+    /// The receiver's payjoin endpoint.
+    endpoint: String,
+    /// Parameters of the request.
+    params: PayjoinParams,
+}
+
+impl PayjoinSender {
+    /// Starts a payjoin of the `original` transaction, see [`sign_original`], paying `request`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the request has no payjoin endpoint, the original does not pay it,
+    /// is not finalized, or the additional fee output is the escrow output.
+    pub(crate) fn new(
+        original: Psbt,
+        request: &PaymentRequest,
+        params: PayjoinParams,
+    ) -> Result<Self, Error> {
+        let endpoint = request.payjoin.clone().ok_or_else(|| {
+            Error::WrongInputs("The payment request has no payjoin endpoint".to_string())
+        })?;
+        let prevouts = original_prevouts(&original)?;
+        let escrow_vout = request
+            .find_payment(&original.unsigned_tx)
+            .ok_or_else(|| {
+                Error::WrongInputs("The original transaction does not pay the request".to_string())
+            })?
+            .vout as usize;
+        if let Some((index, _)) = params.additional_fee
+            && (index == escrow_vout || index >= original.unsigned_tx.output.len())
+        {
+            return Err(Error::WrongInputs(format!(
+                "Output {index} can't pay additional fees"
+            )));
+        }
+        Ok(Self {
+            original,
+            prevouts,
+            escrow_vout,
+            endpoint,
+            params,
+        })
+    }
+
+    /// The original transaction, to broadcast if the payjoin fails.
+    pub(crate) fn fallback_tx(&self) -> Transaction {
+        self.original.clone().extract_tx_unchecked_fee_rate()
+    }
+
+    /// URL of the payjoin request, the endpoint with the parameters in its query.
+    pub(crate) fn url(&self) -> String {
+        let separator = if self.endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        format!("{}{separator}{}", self.endpoint, self.params.to_query())
+    }
+
+    /// Body of the payjoin request, the base64 original [`Psbt`].
+    pub(crate) fn body(&self) -> String {
+        self.original.to_string()
+    }
+
+    /// Posts the original to the receiver and returns the checked payjoin,
+    /// signed with the [`SecretNsec`] and ready to broadcast.
+    pub(crate) async fn send(&self, nsec: &SecretNsec) -> Result<Transaction, Error> {
+        let response = post_text(&self.url(), &self.body()).await?;
+        self.process_response(&response, nsec)
+    }
+
+    /// Checks the receiver's base64 `proposal` and signs the sender's inputs
+    /// with the [`SecretNsec`], returning the payjoin ready to broadcast.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the proposal changes the original beyond adding the receiver's inputs,
+    /// raising the escrow output and adding outputs, if an input of the receiver is not
+    /// a signed P2TR key path spend, or if the sender pays more fees than allowed.
+    pub(crate) fn process_response(
+        &self,
+        proposal: &str,
+        nsec: &SecretNsec,
+    ) -> Result<Transaction, Error> {
+        let proposal = proposal
+            .trim()
+            .parse::<Psbt>()
+            .map_err(|e| Error::Protocol(format!("Invalid payjoin proposal: {e}")))?;
+        let original = &self.original.unsigned_tx;
+        let mut tx = proposal.unsigned_tx.clone();
+        if tx.version != original.version || tx.lock_time != original.lock_time {
+            return Err(Error::Protocol(
+                "Payjoin proposal changed the version or lock time".to_string(),
+            ));
+        }
+
+        // Inputs: the original ones, plus the receiver's signed P2TR ones.
+        let sequence = original.input[0].sequence;
+        let mut prevouts = Vec::with_capacity(tx.input.len());
+        let mut sender_inputs = Vec::with_capacity(original.input.len());
+        let mut receiver_inputs = Vec::new();
+        for (index, (txin, input)) in tx.input.iter().zip(&proposal.inputs).enumerate() {
+            if txin.sequence != sequence {
+                return Err(Error::Protocol(format!(
+                    "Payjoin proposal changed the sequence of input {index}"
+                )));
+            }
+            match original
+                .input
+                .iter()
+                .position(|original| original.previous_output == txin.previous_output)
+            {
+                Some(position) => {
+                    prevouts.push(self.prevouts[position].clone());
+                    sender_inputs.push(index);
+                }
+                None => {
+                    let prevout = input
+                        .witness_utxo
+                        .clone()
+                        .filter(|prevout| prevout.script_pubkey.is_p2tr())
+                        .ok_or_else(|| {
+                            Error::Protocol(format!(
+                                "Input {index} of the receiver does not spend a P2TR output"
+                            ))
+                        })?;
+                    let witness = input.final_script_witness.clone().ok_or_else(|| {
+                        Error::Protocol(format!("Input {index} of the receiver is not signed"))
+                    })?;
+                    prevouts.push(prevout);
+                    receiver_inputs.push((index, witness));
+                }
+            }
+        }
+        if sender_inputs.len() != original.input.len() {
+            return Err(Error::Protocol(
+                "Payjoin proposal dropped inputs of the original".to_string(),
+            ));
+        }
+        if receiver_inputs.is_empty() {
+            return Err(Error::Protocol(
+                "Payjoin proposal has no input of the receiver".to_string(),
+            ));
+        }
+
+        // Outputs: the original ones in order, with the escrow output raised and the additional
+        // fee output lowered by at most the allowed contribution, plus the receiver's ones.
+        let mut contribution = Amount::ZERO;
+        let mut originals = original.output.iter().enumerate().peekable();
+        for output in &tx.output {
+            let Some((vout, expected)) =
+                originals.next_if(|(_, expected)| expected.script_pubkey == output.script_pubkey)
+            else {
+                continue;
+            };
+            let allowed = match self.params.additional_fee {
+                Some((index, max)) if index == vout => {
+                    contribution = expected.value.checked_sub(output.value).unwrap_or_default();
+                    contribution <= max && output.value <= expected.value
+                }
+                _ if vout == self.escrow_vout => output.value >= expected.value,
+                _ => output.value == expected.value,
+            };
+            if !allowed {
+                return Err(Error::Protocol(format!(
+                    "Payjoin proposal changed output {vout} of the original"
+                )));
+            }
+        }
+        if originals.next().is_some() {
+            return Err(Error::Protocol(
+                "Payjoin proposal dropped outputs of the original".to_string(),
+            ));
+        }
+
+        // The sender only pays for fees, never for the receiver's outputs.
+        let original_fee = fee(original, &self.prevouts)?;
+        let proposal_fee = fee(&tx, &prevouts)?;
+        if proposal_fee < original_fee + contribution {
+            return Err(Error::Protocol(
+                "Payjoin proposal makes the sender pay the receiver".to_string(),
+            ));
+        }
+
+        for index in sender_inputs {
+            let signature = sign_key_spend(&tx, index, nsec, &prevouts)?;
+            tx = with_key_spend_signature(&tx, index, signature)?;
+        }
+        for (index, witness) in receiver_inputs {
+            verify_key_spend(&tx, index, &prevouts, &witness)?;
+            tx.input[index].witness = witness;
+        }
+        if let Some(min_fee_rate) = self.params.min_fee_rate
+            && proposal_fee / tx.weight() < min_fee_rate
+        {
+            return Err(Error::Protocol(
+                "Payjoin proposal is below the minimum fee rate".to_string(),
+            ));
+        }
+        #[cfg(debug_assertions)]
+        trace!(txid = %tx.compute_txid(), inputs = %tx.input.len(), "payjoin signed");
+        Ok(tx)
+    }
+}
+
+/// The seller's side of a payjoin.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PayjoinReceiver {
+    /// The sender's signed original transaction.
+    original: Psbt,
+    /// The outputs spent by the original, in input order.
+    prevouts: Vec<TxOut>,
+    /// Index of the escrow output in the original.
+    escrow_vout: usize,
+    /// Parameters of the request.
+    params: PayjoinParams,
+}
+
+impl PayjoinReceiver {
+    /// Checks the payjoin request with `body` and `query`, whose original must pay
+    /// exactly `amount` to `escrow_address`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the version is not supported, or the original is not finalized,
+    /// spends non-P2TR outputs, or does not pay the escrow.
+    pub(crate) fn new(
+        body: &str,
+        query: &str,
+        escrow_address: &Address,
+        amount: Amount,
+    ) -> Result<Self, Error> {
+        let params = PayjoinParams::from_query(query)?;
+        let original = body
+            .trim()
+            .parse::<Psbt>()
+            .map_err(|e| Error::Protocol(format!("Invalid original transaction: {e}")))?;
+        let prevouts = original_prevouts(&original)?;
+        fee(&original.unsigned_tx, &prevouts)?;
+        let script_pubkey = escrow_address.script_pubkey();
+        let escrow_vout = original
+            .unsigned_tx
+            .output
+            .iter()
+            .position(|output| output.script_pubkey == script_pubkey && output.value == amount)
+            .ok_or_else(|| {
+                Error::Protocol(format!(
+                    "Original transaction does not pay {amount} to {escrow_address}"
+                ))
+            })?;
+        Ok(Self {
+            original,
+            prevouts,
+            escrow_vout,
+            params,
+        })
+    }
+
+    /// The original transaction, to broadcast if the sender never broadcasts the payjoin.
+    pub(crate) fn fallback_tx(&self) -> Transaction {
+        self.original.clone().extract_tx_unchecked_fee_rate()
+    }
+
+    /// Checks with Esplora that the original spends the outputs it claims, unspent,
+    /// so [`PayjoinReceiver::fallback_tx`] can be broadcast.
+    pub(crate) async fn check_broadcastable(&self, client: &EsploraClient) -> Result<(), Error> {
+        let tx = &self.original.unsigned_tx;
+        if get_prevouts(client, tx).await? != self.prevouts {
+            return Err(Error::Protocol(
+                "Original transaction lies about its inputs".to_string(),
+            ));
+        }
+        for input in &tx.input {
+            let outpoint = input.previous_output;
+            let spent = client
+                .get_output_status(&outpoint.txid, u64::from(outpoint.vout))
+                .await
+                .context(format!("fetching the status of {outpoint}"))?
+                .is_some_and(|status| status.spent);
+            if spent {
+                return Err(Error::Protocol(format!(
+                    "Original transaction spends the spent output {outpoint}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the receiver's `inputs` of the [`SecretNsec`]'s `npub` address, paying `amount`
+    /// more to the escrow and the rest, minus the fees of the added inputs and change,
+    /// to `change_address`. Returns the signed proposal, to send back base64-encoded.
+    ///
+    /// Fees are paid at the fee rate of the original, or the requested minimum if higher.
+    /// Change below the dust limit goes to fees.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `inputs` is empty, spends non-P2TR outputs or inputs of the original,
+    /// or does not cover `amount` and its fees.
+    pub(crate) fn contribute(
+        &self,
+        inputs: &[FundingInput],
+        amount: Amount,
+        change_address: &Address,
+        nsec: &SecretNsec,
+    ) -> Result<Psbt, Error> {
+        if inputs.is_empty() {
+            return Err(Error::WrongInputs("Contribution has no inputs".to_string()));
+        }
+        let original = &self.original.unsigned_tx;
+        if let Some(input) = inputs.iter().find(|input| {
+            !input.prevout.script_pubkey.is_p2tr()
+                || original
+                    .input
+                    .iter()
+                    .any(|txin| txin.previous_output == input.outpoint)
+        }) {
+            return Err(Error::WrongInputs(format!(
+                "Input {} can't be contributed",
+                input.outpoint
+            )));
+        }
+
+        let fallback = self.fallback_tx();
+        let original_fee_rate = fee(original, &self.prevouts)? / fallback.weight();
+        let fee_rate = self
+            .params
+            .min_fee_rate
+            .map_or(original_fee_rate, |min| min.max(original_fee_rate));
+        let input_amount: Amount = inputs.iter().map(|input| input.prevout.value).sum();
+        let input_weight = P2TR_INPUT_WEIGHT * inputs.len() as u64;
+        let fee_without_change = fee_rate.fee_wu(input_weight).ok_or(Error::Rounding)?;
+        let fee_with_change = fee_rate
+            .fee_wu(input_weight + P2TR_OUTPUT_WEIGHT)
+            .ok_or(Error::Rounding)?;
+        let available = input_amount
+            .checked_sub(amount + fee_without_change)
+            .ok_or(Error::FundingMismatch {
+                expected: amount + fee_without_change,
+                actual: input_amount,
+            })?;
+        let change_script = change_address.script_pubkey();
+        let change = (available >= fee_with_change - fee_without_change)
+            .then(|| available - (fee_with_change - fee_without_change))
+            .filter(|change| *change >= change_script.minimal_non_dust())
+            .map(|value| TxOut {
+                value,
+                script_pubkey: change_script,
+            });
+
+        // The receiver's inputs and change go last, with the sequence of the original.
+        let mut tx = original.clone();
+        tx.output[self.escrow_vout].value += amount;
+        let sequence = original.input[0].sequence;
+        tx.input.extend(inputs.iter().map(|input| TxIn {
+            previous_output: input.outpoint,
+            sequence,
+            ..Default::default()
+        }));
+        tx.output.extend(change);
+        let prevouts = self
+            .prevouts
+            .iter()
+            .cloned()
+            .chain(inputs.iter().map(|input| input.prevout.clone()))
+            .collect::<Vec<_>>();
+
+        let mut proposal = Psbt::from_unsigned_tx(tx.clone())
+            .map_err(|e| Error::Protocol(format!("Invalid payjoin proposal: {e}")))?;
+        for index in original.input.len()..tx.input.len() {
+            let signature = sign_key_spend(&tx, index, nsec, &prevouts)?;
+            let signed = with_key_spend_signature(&tx, index, signature)?;
+            let input = &mut proposal.inputs[index];
+            input.witness_utxo = Some(prevouts[index].clone());
+            input.final_script_witness = Some(signed.input[index].witness.clone());
+        }
+        #[cfg(debug_assertions)]
+        trace!(txid = %tx.compute_txid(), %amount, "payjoin proposal");
+        Ok(proposal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, OutPoint, Txid, hashes::Hash};
+
+    use super::*;
+    use crate::util::npub_to_address;
+
+    fn funding_input(address: &Address, seed: u8, amount: u64) -> FundingInput {
+        FundingInput {
+            outpoint: OutPoint::new(Txid::from_byte_array([seed; 32]), 0),
+            prevout: TxOut {
+                value: Amount::from_sat(amount),
+                script_pubkey: address.script_pubkey(),
+            },
+        }
+    }
+
+    #[test]
+    fn payjoin_params() {
+        let params = PayjoinParams {
+            additional_fee: Some((1, Amount::from_sat(500))),
+            min_fee_rate: Some(FeeRate::from_sat_per_vb_unchecked(2)),
+        };
+        let query = params.to_query();
+        assert_eq!(
+            query,
+            "v=1&additionalfeeoutputindex=1&maxadditionalfeecontribution=500\
+             &disableoutputsubstitution=true&minfeerate=2"
+        );
+        assert_eq!(PayjoinParams::from_query(&query).unwrap(), params);
+        assert_eq!(
+            PayjoinParams::from_query("v=1&minfeerate=1.5")
+                .unwrap()
+                .min_fee_rate,
+            Some(FeeRate::from_sat_per_kwu(375))
+        );
+        assert!(PayjoinParams::from_query("v=2").is_err());
+        assert!(PayjoinParams::from_query("additionalfeeoutputindex=1").is_err());
+        assert!(PayjoinParams::from_query("v=1&minfeerate=-1").is_err());
+    }
+
+    #[test]
+    fn payjoin_roundtrip() {
+        let (nsec_buyer, nsec_seller) = (SecretNsec::generate(), SecretNsec::generate());
+        let buyer = npub_to_address(&nsec_buyer.public_key(), Network::Regtest).unwrap();
+        let seller = npub_to_address(&nsec_seller.public_key(), Network::Regtest).unwrap();
+        let escrow =
+            npub_to_address(&SecretNsec::generate().public_key(), Network::Regtest).unwrap();
+
+        // The buyer pays 50k to the escrow, with 9k of change and 1k of fees.
+        let original = sign_original(
+            &[funding_input(&buyer, 1, 60_000)],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: escrow.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(9_000),
+                    script_pubkey: buyer.script_pubkey(),
+                },
+            ],
+            absolute::LockTime::ZERO,
+            &nsec_buyer,
+        )
+        .unwrap();
+        let request = PaymentRequest::escrow_funding(&escrow, Amount::from_sat(50_000), "1");
+        let params = PayjoinParams {
+            additional_fee: Some((1, Amount::from_sat(500))),
+            min_fee_rate: None,
+        };
+        assert!(PayjoinSender::new(original.clone(), &request, params.clone()).is_err());
+        let request = request.with_payjoin("https://seller.example/pj");
+        let sender = PayjoinSender::new(original.clone(), &request, params).unwrap();
+        let url = sender.url();
+        assert!(url.starts_with("https://seller.example/pj?v=1&"));
+
+        // The seller adds 20k to the escrow from a 30k input.
+        let (_, query) = url.split_once('?').unwrap();
+        assert!(
+            PayjoinReceiver::new(&sender.body(), query, &escrow, Amount::from_sat(40_000)).is_err()
+        );
+        let receiver =
+            PayjoinReceiver::new(&sender.body(), query, &escrow, Amount::from_sat(50_000)).unwrap();
+        assert_eq!(receiver.fallback_tx(), sender.fallback_tx());
+        let seller_input = funding_input(&seller, 2, 30_000);
+        assert!(
+            receiver
+                .contribute(
+                    std::slice::from_ref(&seller_input),
+                    Amount::from_sat(40_000),
+                    &seller,
+                    &nsec_seller
+                )
+                .is_err()
+        );
+        let proposal = receiver
+            .contribute(
+                &[seller_input],
+                Amount::from_sat(20_000),
+                &seller,
+                &nsec_seller,
+            )
+            .unwrap();
+        assert!(proposal.inputs[0].final_script_witness.is_none());
+
+        let tx = sender
+            .process_response(&proposal.to_string(), &nsec_buyer)
+            .unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert!(tx.input.iter().all(|input| !input.witness.is_empty()));
+        assert_eq!(tx.output[0].value, Amount::from_sat(70_000));
+        assert_eq!(tx.output[1].value, Amount::from_sat(9_000));
+        let seller_change = tx.output[2].value;
+        assert!(seller_change < Amount::from_sat(10_000));
+        assert!(seller_change > Amount::from_sat(9_000));
+
+        // The sender rejects proposals taking more than allowed.
+        let tampered = |change: &dyn Fn(&mut Transaction)| {
+            let mut proposal = proposal.clone();
+            change(&mut proposal.unsigned_tx);
+            sender.process_response(&proposal.to_string(), &nsec_buyer)
+        };
+        assert!(tampered(&|tx| tx.output[0].value = Amount::from_sat(49_000)).is_err());
+        assert!(tampered(&|tx| tx.output[1].value = Amount::from_sat(8_000)).is_err());
+        assert!(tampered(&|tx| tx.output[2].value += Amount::from_sat(100)).is_err());
+        assert!(
+            tampered(&|tx| {
+                tx.output.remove(1);
+            })
+            .is_err()
+        );
+        assert!(tampered(&|tx| tx.input[1].sequence = Sequence::MAX).is_err());
+        assert!(sender.process_response("not a psbt", &nsec_buyer).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Platform fees of hosted deployments.
+//!
+//! A hosted deployment of scrow can charge a [`PlatformFee`]: a share of the escrow,
+//! in basis points, paid to the platform's address by the resolution transaction.
+//! The fee is part of the [`Offer`], so it is covered by the [`SessionId`] the acceptance
+//! commits to: once agreed, neither side can add it, change it or strip it.
+//! Deployments check that offers carry their fee with [`check_platform_fee`] when accepting them,
+//! and the platform never holds the escrowed coins.
+//!
+//! [`Offer`]: crate::protocol::Offer
+//! [`SessionId`]: crate::protocol::SessionId
+
+use bitcoin::{Address, Amount, Network, Transaction, TxOut, address::NetworkUnchecked};
+use nostr::key::PublicKey as NostrPublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, util::npub_to_address};
+
+/// Basis points in a whole.
+const BPS: u64 = 10_000;
+
+/// Highest platform fee, in basis points: 10% of the escrow.
+pub(crate) const MAX_PLATFORM_FEE_BPS: u16 = 1_000;
+
+/// A platform's share of an escrow, paid on resolution.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct PlatformFee {
+    /// Address the fee is paid to.
+    pub(crate) address: Address<NetworkUnchecked>,
+    /// Fee in basis points of the escrow amount.
+    pub(crate) bps: u16,
+}
+
+impl PlatformFee {
+    /// A fee of `bps` paid to the `npub`-derived address of the platform on `network`.
+    pub(crate) fn to_npub(
+        npub: &NostrPublicKey,
+        network: Network,
+        bps: u16,
+    ) -> Result<Self, Error> {
+        let fee = Self {
+            address: npub_to_address(npub, network)?.into_unchecked(),
+            bps,
+        };
+        fee.validate(network)?;
+        Ok(fee)
+    }
+
+    /// Checks the fee rate and that the address is for `network`.
+    pub(crate) fn validate(&self, network: Network) -> Result<(), Error> {
+        if self.bps == 0 || self.bps > MAX_PLATFORM_FEE_BPS {
+            return Err(Error::Protocol(format!(
+                "Platform fee must be between 1 and {MAX_PLATFORM_FEE_BPS} bps, got {}",
+                self.bps
+            )));
+        }
+        self.address(network)?;
+        Ok(())
+    }
+
+    /// The fee [`Address`], checked to be for `network`.
+    pub(crate) fn address(&self, network: Network) -> Result<Address, Error> {
+        Ok(self.address.clone().require_network(network)?)
+    }
+
+    /// The fee on an escrow of `amount`, rounded down.
+    pub(crate) fn amount(&self, amount: Amount) -> Amount {
+        let fee = u128::from(amount.to_sat()) * u128::from(self.bps) / u128::from(BPS);
+        Amount::from_sat(u64::try_from(fee).expect("fee is at most the amount"))
+    }
+
+    /// The shares of the fee of the buyer and the seller,
+    /// in proportion to their `amount_buyer` and `amount_seller`.
+    pub(crate) fn shares(
+        &self,
+        amount_buyer: Amount,
+        amount_seller: Amount,
+    ) -> Result<(Amount, Amount), Error> {
+        let total = amount_buyer
+            .checked_add(amount_seller)
+            .ok_or(Error::Rounding)?;
+        let fee = self.amount(total);
+        let share_buyer = u128::from(fee.to_sat()) * u128::from(amount_buyer.to_sat())
+            / u128::from(total.to_sat().max(1));
+        let share_buyer =
+            Amount::from_sat(u64::try_from(share_buyer).map_err(|_| Error::Rounding)?);
+        Ok((share_buyer, fee - share_buyer))
+    }
+
+    /// Adds the fee output to the resolution `tx` paying the buyer's and the seller's
+    /// escrow amounts with its first and second outputs, taking each one's [`shares`].
+    ///
+    /// [`shares`]: PlatformFee::shares
+    ///
+    /// # Errors
+    ///
+    /// Errors if a participant's output can't cover their share or the fee output is dust.
+    pub(crate) fn apply(
+        &self,
+        tx: &mut Transaction,
+        amount_buyer: Amount,
+        amount_seller: Amount,
+        network: Network,
+    ) -> Result<(), Error> {
+        let script_pubkey = self.address(network)?.script_pubkey();
+        let (share_buyer, share_seller) = self.shares(amount_buyer, amount_seller)?;
+        let value = share_buyer + share_seller;
+        if value < script_pubkey.minimal_non_dust() {
+            return Err(Error::WrongInputs(format!(
+                "Platform fee of {value} is dust"
+            )));
+        }
+        let [output_buyer, output_seller, ..] = tx.output.as_mut_slice() else {
+            return Err(Error::WrongInputs(
+                "Resolution must pay both participants".to_string(),
+            ));
+        };
+        for (output, share) in [(output_buyer, share_buyer), (output_seller, share_seller)] {
+            output.value = output.value.checked_sub(share).ok_or(Error::Rounding)?;
+        }
+        tx.output.push(TxOut {
+            value,
+            script_pubkey,
+        });
+        Ok(())
+    }
+
+    /// Checks that `tx` pays the fee on an escrow of `amount`, in a single output.
+    pub(crate) fn verify(
+        &self,
+        tx: &Transaction,
+        amount: Amount,
+        network: Network,
+    ) -> Result<(), Error> {
+        let script_pubkey = self.address(network)?.script_pubkey();
+        let outputs = tx
+            .output
+            .iter()
+            .filter(|output| output.script_pubkey == script_pubkey)
+            .collect::<Vec<_>>();
+        let expected = self.amount(amount);
+        match outputs.as_slice() {
+            [output] if output.value == expected => Ok(()),
+            _ => Err(Error::Protocol(format!(
+                "Resolution does not pay the platform fee of {expected}"
+            ))),
+        }
+    }
+}
+
+/// Checks that the `offered` fee is the platform fee `required` by the deployment, if any.
+///
+/// Offers without the required fee, with another fee, or with a fee the deployment doesn't
+/// charge are all rejected.
+pub(crate) fn check_platform_fee(
+    offered: Option<&PlatformFee>,
+    required: Option<&PlatformFee>,
+) -> Result<(), Error> {
+    if offered == required {
+        return Ok(());
+    }
+    match offered {
+        None => Err(Error::Protocol(
+            "Offer does not pay the platform fee".to_string(),
+        )),
+        Some(_) => Err(Error::Protocol(
+            "Offer pays a platform fee the platform does not charge".to_string(),
+        )),
+    }
+}
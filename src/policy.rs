@@ -0,0 +1,820 @@
+//! Custom escrow leaves from spending policies.
+//!
+//! Advanced users describe who can spend an escrow with a subset of the miniscript policy
+//! language, which [`CompiledPolicy::compile`] turns into a tap tree:
+//!
+//! - `pk(NPUB)`: a signature by `NPUB`, in bech32 or hex.
+//! - `after(N)`: the absolute lock time `N`, a block height or a Unix time.
+//! - `older(N)`: the relative lock time `N` since the escrow was funded.
+//! - `and(X,Y)`: both `X` and `Y`.
+//! - `or(X,Y)`: either `X` or `Y`, optionally weighted by how likely each is, as in
+//!   `or(9@X,1@Y)`.
+//! - `thresh(K,X,Y,...)`: `K` of the sub-policies.
+//!
+//! The policy is expanded into its alternatives, one leaf each, so the spend only reveals
+//! the conditions it meets. The likeliest leaves are placed closest to the root.
+//! A `thresh` of keys alone compiles to a single leaf with `OP_CHECKSIGADD`.
+//!
+//! For example, the leaves of the arbitrated escrow of
+//! [`escrow_scripts`](crate::scripts::escrow_scripts) come from
+//! `or(9@and(pk(SELLER),pk(BUYER)),thresh(1,and(older(N),and(pk(ARBITRATOR),pk(BUYER))),...))`,
+//! and a seller releasing to the buyer alone after a delivery window is
+//! `or(and(pk(BUYER),pk(SELLER)),and(pk(BUYER),after(HEIGHT)))`.
+//!
+//! The leaves are signed with [`BatchSigner::sign`](crate::sign::BatchSigner::sign) like
+//! escrow leaves. [`CompiledPolicy::verify`] checks the collected [`SignerSignature`]s,
+//! and [`CompiledPolicy::combine`] builds the witness.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{
+    Address, Network, Script, ScriptBuf, Sequence, Transaction, TxOut, Witness, absolute,
+    opcodes::all::{
+        OP_CHECKSIG, OP_CHECKSIGADD, OP_CHECKSIGVERIFY, OP_CLTV, OP_CSV, OP_DROP, OP_NUMEQUAL,
+        OP_NUMEQUALVERIFY,
+    },
+    script::Builder,
+    taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder},
+};
+use nostr::key::PublicKey as NostrPublicKey;
+use secp256k1::SECP256K1;
+
+use crate::{
+    error::Error,
+    scripts::UNSPENDABLE_PUBLIC_KEY,
+    sign::{SignerSignature, script_spend_message},
+    util::{npub_to_x_only_public_key, parse_npub},
+};
+
+/// Most leaves a policy may expand to, so `thresh` of large sub-policies can't blow up.
+const MAX_LEAVES: usize = 64;
+
+/// Lock times from this value on are relative or absolute times rather than heights,
+/// and with the disable bit, not lock times at all.
+const LOCK_TIME_LIMIT: u32 = 1 << 31;
+
+/// A spending policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Policy {
+    /// A signature by the key.
+    Pk(NostrPublicKey),
+    /// An absolute lock time.
+    After(u32),
+    /// A relative lock time.
+    Older(u32),
+    /// Both sub-policies.
+    And(Box<Policy>, Box<Policy>),
+    /// Either sub-policy, with its weight.
+    Or(Vec<(u32, Policy)>),
+    /// The threshold of the sub-policies.
+    Thresh(usize, Vec<Policy>),
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Policy::Pk(npub) => write!(f, "pk({npub})"),
+            Policy::After(n) => write!(f, "after({n})"),
+            Policy::Older(n) => write!(f, "older({n})"),
+            Policy::And(a, b) => write!(f, "and({a},{b})"),
+            Policy::Or(branches) => {
+                f.write_str("or(")?;
+                for (i, (weight, policy)) in branches.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    if *weight != 1 {
+                        write!(f, "{weight}@")?;
+                    }
+                    write!(f, "{policy}")?;
+                }
+                f.write_str(")")
+            }
+            Policy::Thresh(k, subs) => {
+                write!(f, "thresh({k}")?;
+                for sub in subs {
+                    write!(f, ",{sub}")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { input: s, pos: 0 };
+        let policy = parser.policy()?;
+        parser.skip_whitespace();
+        if parser.pos != s.len() {
+            return Err(parser.error("trailing input"));
+        }
+        Ok(policy)
+    }
+}
+
+/// Recursive descent parser of [`Policy`].
+struct Parser<'a> {
+    /// The policy text.
+    input: &'a str,
+    /// Byte offset of the next character.
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, reason: &str) -> Error {
+        Error::WrongInputs(format!("Invalid policy at {}: {reason}", self.pos))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.input.len() - self.rest().trim_start().len();
+    }
+
+    /// Consumes `c` if it is next.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{c}'")))
+        }
+    }
+
+    /// The next run of characters for which `f` holds.
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let end = rest.find(|c: char| !f(c)).unwrap_or(rest.len());
+        self.pos += end;
+        &rest[..end]
+    }
+
+    fn number(&mut self) -> Result<u32, Error> {
+        self.take_while(|c| c.is_ascii_digit())
+            .parse()
+            .map_err(|_| self.error("expected a number"))
+    }
+
+    /// A branch of `or`, with an optional `N@` weight.
+    fn weighted(&mut self) -> Result<(u32, Policy), Error> {
+        let start = self.pos;
+        if !self.take_while(|c| c.is_ascii_digit()).is_empty() && self.eat('@') {
+            self.pos = start;
+            let weight = self.number()?;
+            self.expect('@')?;
+            if weight == 0 {
+                return Err(self.error("weights must be positive"));
+            }
+            return Ok((weight, self.policy()?));
+        }
+        self.pos = start;
+        Ok((1, self.policy()?))
+    }
+
+    fn policy(&mut self) -> Result<Policy, Error> {
+        let name = self.take_while(|c| c.is_ascii_alphabetic());
+        self.expect('(')?;
+        let policy = match name {
+            "pk" => {
+                let npub = self.take_while(|c| c.is_ascii_alphanumeric());
+                Policy::Pk(parse_npub(npub).map_err(|_| self.error("expected an npub"))?)
+            }
+            "after" => Policy::After(self.number()?),
+            "older" => Policy::Older(self.number()?),
+            "and" => {
+                let a = self.policy()?;
+                self.expect(',')?;
+                Policy::And(Box::new(a), Box::new(self.policy()?))
+            }
+            "or" => {
+                let a = self.weighted()?;
+                self.expect(',')?;
+                Policy::Or(vec![a, self.weighted()?])
+            }
+            "thresh" => {
+                let k = self.number()? as usize;
+                let mut subs = Vec::new();
+                while self.eat(',') {
+                    subs.push(self.policy()?);
+                }
+                Policy::Thresh(k, subs)
+            }
+            _ => return Err(self.error(&format!("unknown fragment '{name}'"))),
+        };
+        self.expect(')')?;
+        Ok(policy)
+    }
+}
+
+/// A signature check of a leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Check {
+    /// A signature by the key.
+    Key(NostrPublicKey),
+    /// `k` signatures by the keys.
+    Multi(usize, Vec<NostrPublicKey>),
+}
+
+/// The conditions of a leaf: one alternative of the policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Conditions {
+    /// Absolute lock time, if any.
+    after: Option<u32>,
+    /// Relative lock time, if any.
+    older: Option<u32>,
+    /// Signature checks, in script order.
+    checks: Vec<Check>,
+}
+
+/// The later of two lock times of the same kind.
+fn later(
+    a: Option<u32>,
+    b: Option<u32>,
+    is_height: impl Fn(u32) -> bool,
+) -> Result<Option<u32>, Error> {
+    match (a, b) {
+        (Some(a), Some(b)) if is_height(a) != is_height(b) => Err(Error::WrongInputs(
+            "Invalid policy: a leaf mixes block heights and times".to_string(),
+        )),
+        (a, b) => Ok(a.max(b)),
+    }
+}
+
+impl Conditions {
+    /// Both sets of conditions.
+    fn and(&self, other: &Conditions) -> Result<Self, Error> {
+        let mut checks = self.checks.clone();
+        checks.extend(other.checks.iter().cloned());
+        Ok(Self {
+            after: later(self.after, other.after, |n| {
+                absolute::LockTime::from_consensus(n).is_block_height()
+            })?,
+            older: later(self.older, other.older, |n| {
+                Sequence::from_consensus(n).is_height_locked()
+            })?,
+            checks,
+        })
+    }
+
+    /// The signers of the leaf, in the order the script checks them.
+    fn keys(&self) -> Vec<NostrPublicKey> {
+        self.checks
+            .iter()
+            .flat_map(|check| match check {
+                Check::Key(npub) => vec![*npub],
+                Check::Multi(_, npubs) => npubs.clone(),
+            })
+            .collect()
+    }
+
+    /// The leaf script: the lock times, then the signature checks.
+    fn script(&self) -> Result<ScriptBuf, Error> {
+        if self.checks.is_empty() {
+            return Err(Error::WrongInputs(
+                "Invalid policy: a leaf without keys can be spent by anyone".to_string(),
+            ));
+        }
+        let keys = self.keys();
+        if keys
+            .iter()
+            .enumerate()
+            .any(|(i, npub)| keys[..i].contains(npub))
+        {
+            return Err(Error::WrongInputs(
+                "Invalid policy: a leaf checks a key twice".to_string(),
+            ));
+        }
+        let mut builder = Builder::new();
+        if let Some(after) = self.after {
+            builder = builder
+                .push_lock_time(absolute::LockTime::from_consensus(after))
+                .push_opcode(OP_CLTV)
+                .push_opcode(OP_DROP);
+        }
+        if let Some(older) = self.older {
+            builder = builder
+                .push_sequence(Sequence::from_consensus(older))
+                .push_opcode(OP_CSV)
+                .push_opcode(OP_DROP);
+        }
+        for (i, check) in self.checks.iter().enumerate() {
+            let last = i == self.checks.len() - 1;
+            match check {
+                Check::Key(npub) => {
+                    builder = builder
+                        .push_x_only_key(&npub_to_x_only_public_key(npub)?)
+                        .push_opcode(if last { OP_CHECKSIG } else { OP_CHECKSIGVERIFY });
+                }
+                Check::Multi(k, npubs) => {
+                    for (j, npub) in npubs.iter().enumerate() {
+                        builder = builder
+                            .push_x_only_key(&npub_to_x_only_public_key(npub)?)
+                            .push_opcode(if j == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD });
+                    }
+                    builder = builder.push_int(*k as i64).push_opcode(if last {
+                        OP_NUMEQUAL
+                    } else {
+                        OP_NUMEQUALVERIFY
+                    });
+                }
+            }
+        }
+        Ok(builder.into_script())
+    }
+}
+
+/// Every `k`-element subset of `0..n`, in lexicographic order.
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![Vec::new()];
+    }
+    (k - 1..n)
+        .flat_map(|last| {
+            combinations(last, k - 1).into_iter().map(move |mut c| {
+                c.push(last);
+                c
+            })
+        })
+        .collect()
+}
+
+impl Policy {
+    /// The alternatives of the policy, with their weights.
+    fn alternatives(&self) -> Result<Vec<(u32, Conditions)>, Error> {
+        let alternatives = match self {
+            Policy::Pk(npub) => vec![(
+                1,
+                Conditions {
+                    checks: vec![Check::Key(*npub)],
+                    ..Default::default()
+                },
+            )],
+            Policy::After(n) | Policy::Older(n) if *n == 0 || *n >= LOCK_TIME_LIMIT => {
+                return Err(Error::WrongInputs(format!(
+                    "Invalid policy: lock time {n} out of range"
+                )));
+            }
+            Policy::After(n) => vec![(
+                1,
+                Conditions {
+                    after: Some(*n),
+                    ..Default::default()
+                },
+            )],
+            Policy::Older(n) => vec![(
+                1,
+                Conditions {
+                    older: Some(*n),
+                    ..Default::default()
+                },
+            )],
+            Policy::And(a, b) => {
+                let (a, b) = (a.alternatives()?, b.alternatives()?);
+                let mut alternatives = Vec::new();
+                for (weight_a, a) in &a {
+                    for (weight_b, b) in &b {
+                        alternatives.push((weight_a.saturating_mul(*weight_b), a.and(b)?));
+                    }
+                }
+                alternatives
+            }
+            Policy::Or(branches) => {
+                let mut alternatives = Vec::new();
+                for (weight, branch) in branches {
+                    for (sub_weight, conditions) in branch.alternatives()? {
+                        alternatives.push((weight.saturating_mul(sub_weight), conditions));
+                    }
+                }
+                alternatives
+            }
+            Policy::Thresh(k, subs) if *k == 0 || *k > subs.len() => {
+                return Err(Error::WrongInputs(format!(
+                    "Invalid policy: threshold {k} of {} sub-policies",
+                    subs.len()
+                )));
+            }
+            Policy::Thresh(k, subs) => {
+                let npubs: Vec<NostrPublicKey> = subs
+                    .iter()
+                    .filter_map(|sub| match sub {
+                        Policy::Pk(npub) => Some(*npub),
+                        _ => None,
+                    })
+                    .collect();
+                if npubs.len() == subs.len() && subs.len() > 1 {
+                    vec![(
+                        1,
+                        Conditions {
+                            checks: vec![Check::Multi(*k, npubs)],
+                            ..Default::default()
+                        },
+                    )]
+                } else {
+                    // Any `k` of the sub-policies: the `or` of their `and`s.
+                    let mut alternatives = Vec::new();
+                    for combination in combinations(subs.len(), *k) {
+                        let mut partial = vec![(1_u32, Conditions::default())];
+                        for i in combination {
+                            let mut next = Vec::new();
+                            for (weight_a, a) in &partial {
+                                for (weight_b, b) in subs[i].alternatives()? {
+                                    next.push((weight_a.saturating_mul(weight_b), a.and(&b)?));
+                                }
+                            }
+                            partial = next;
+                            if partial.len() > MAX_LEAVES {
+                                break;
+                            }
+                        }
+                        alternatives.extend(partial);
+                        if alternatives.len() > MAX_LEAVES {
+                            break;
+                        }
+                    }
+                    alternatives
+                }
+            }
+        };
+        if alternatives.len() > MAX_LEAVES {
+            return Err(Error::WrongInputs(format!(
+                "Invalid policy: more than {MAX_LEAVES} leaves"
+            )));
+        }
+        Ok(alternatives)
+    }
+}
+
+/// A leaf of a [`CompiledPolicy`], with everything needed to sign and spend it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PolicyLeaf {
+    /// The conditions of the leaf.
+    conditions: Conditions,
+    /// Locking script of the leaf.
+    pub(crate) script: ScriptBuf,
+    /// Hash of the leaf, committed to by its sighashes.
+    pub(crate) leaf_hash: TapLeafHash,
+    /// Control block proving the leaf is in the tap tree.
+    pub(crate) control_block: ControlBlock,
+}
+
+impl PolicyLeaf {
+    /// The keys that may sign the leaf, in the order the script checks them.
+    pub(crate) fn signers(&self) -> Vec<NostrPublicKey> {
+        self.conditions.keys()
+    }
+
+    /// The lock time the spending transaction needs.
+    pub(crate) fn lock_time(&self) -> absolute::LockTime {
+        self.conditions
+            .after
+            .map_or(absolute::LockTime::ZERO, absolute::LockTime::from_consensus)
+    }
+
+    /// The sequence the spending input needs.
+    pub(crate) fn sequence(&self) -> Sequence {
+        self.conditions
+            .older
+            .map_or(Sequence::ENABLE_RBF_NO_LOCKTIME, Sequence::from_consensus)
+    }
+
+    /// The witness spending the leaf with `signatures`.
+    ///
+    /// Each `pk` needs its signature, and each `thresh` of keys exactly its threshold,
+    /// extra signatures being left out.
+    fn witness(&self, signatures: &[SignerSignature]) -> Result<Witness, Error> {
+        let find = |npub: &NostrPublicKey| {
+            signatures
+                .iter()
+                .find(|s| s.npub == *npub)
+                .map(|s| s.signature)
+        };
+        // Elements in the order the script consumes them, top of the stack first.
+        let mut elements = Vec::new();
+        for check in &self.conditions.checks {
+            match check {
+                Check::Key(npub) => {
+                    let signature = find(npub).ok_or_else(|| {
+                        Error::WrongInputs(format!("Missing signature of {npub}"))
+                    })?;
+                    elements.push(signature.as_ref().to_vec());
+                }
+                Check::Multi(k, npubs) => {
+                    let mut signed = 0;
+                    for npub in npubs {
+                        match find(npub).filter(|_| signed < *k) {
+                            Some(signature) => {
+                                signed += 1;
+                                elements.push(signature.as_ref().to_vec());
+                            }
+                            None => elements.push(Vec::new()),
+                        }
+                    }
+                    if signed < *k {
+                        return Err(Error::WrongInputs(format!(
+                            "Missing signatures: {signed} of {k}"
+                        )));
+                    }
+                }
+            }
+        }
+        let mut witness = Witness::new();
+        for element in elements.iter().rev() {
+            witness.push(element);
+        }
+        witness.push(self.script.as_bytes());
+        witness.push(self.control_block.serialize());
+        Ok(witness)
+    }
+}
+
+/// A policy compiled to a tap tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CompiledPolicy {
+    /// The source policy.
+    policy: Policy,
+    /// Address of the escrow output.
+    address: Address,
+    /// Every leaf of the tap tree, in policy order.
+    leaves: Vec<PolicyLeaf>,
+}
+
+impl CompiledPolicy {
+    /// Compiles `policy` into leaves under the unspendable internal key, weighting the tree
+    /// by the `or` weights.
+    pub(crate) fn compile(policy: &Policy, network: Network) -> Result<Self, Error> {
+        let alternatives = policy.alternatives()?;
+        let mut weighted = Vec::with_capacity(alternatives.len());
+        let mut leaves: Vec<Conditions> = Vec::with_capacity(alternatives.len());
+        for (weight, conditions) in alternatives {
+            let script = conditions.script()?;
+            // Identical alternatives share their leaf.
+            if leaves.contains(&conditions) {
+                continue;
+            }
+            weighted.push((weight, script));
+            leaves.push(conditions);
+        }
+        let spend_info = TaprootBuilder::with_huffman_tree(weighted.clone())?
+            .finalize(SECP256K1, *UNSPENDABLE_PUBLIC_KEY)
+            .map_err(|_| Error::WrongInputs("Could not finalize the policy tree".to_string()))?;
+        let leaves = leaves
+            .into_iter()
+            .zip(weighted)
+            .map(|(conditions, (_, script))| {
+                let control_block = spend_info
+                    .control_block(&(script.clone(), LeafVersion::TapScript))
+                    .ok_or_else(|| {
+                        Error::WrongInputs("Leaf is not in the policy tree".to_string())
+                    })?;
+                Ok(PolicyLeaf {
+                    conditions,
+                    leaf_hash: TapLeafHash::from_script(&script, LeafVersion::TapScript),
+                    script,
+                    control_block,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let address = Address::p2tr(
+            SECP256K1,
+            spend_info.internal_key(),
+            spend_info.merkle_root(),
+            network,
+        );
+        Ok(Self {
+            policy: policy.clone(),
+            address,
+            leaves,
+        })
+    }
+
+    /// The source policy.
+    pub(crate) fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// The escrow [`Address`].
+    pub(crate) fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Every leaf of the tap tree.
+    pub(crate) fn leaves(&self) -> &[PolicyLeaf] {
+        &self.leaves
+    }
+
+    /// The leaf whose locking script is `script`, if any.
+    pub(crate) fn find_leaf(&self, script: &Script) -> Option<&PolicyLeaf> {
+        self.leaves
+            .iter()
+            .find(|leaf| leaf.script.as_script() == script)
+    }
+
+    /// Checks that every signature is from a signer of `leaf` and signs the sighash of
+    /// input `index` of `tx`, spending `prevouts`, through it.
+    pub(crate) fn verify(
+        &self,
+        tx: &Transaction,
+        index: usize,
+        prevouts: &[TxOut],
+        leaf: &PolicyLeaf,
+        signatures: &[SignerSignature],
+    ) -> Result<(), Error> {
+        let signers = leaf.signers();
+        let message = script_spend_message(tx, index, prevouts, &leaf.script)?;
+        for SignerSignature { npub, signature } in signatures {
+            if !signers.contains(npub) {
+                return Err(Error::Protocol(format!("{npub} does not sign the leaf")));
+            }
+            SECP256K1
+                .verify_schnorr(signature, &message, &npub_to_x_only_public_key(npub)?)
+                .map_err(|_| {
+                    Error::Protocol(format!(
+                        "Signature of {npub} does not match the sighash of {}",
+                        tx.compute_txid()
+                    ))
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Combines `signatures` into input `index` of `tx`, spending through `leaf`.
+    ///
+    /// Fails if a signature is missing, or if `tx` doesn't meet the lock times of `leaf`.
+    pub(crate) fn combine(
+        &self,
+        mut tx: Transaction,
+        index: usize,
+        leaf: &PolicyLeaf,
+        signatures: &[SignerSignature],
+    ) -> Result<Transaction, Error> {
+        let input = tx
+            .input
+            .get_mut(index)
+            .ok_or_else(|| Error::WrongInputs(format!("Transaction has no input {index}")))?;
+        if leaf.conditions.older.is_some() && input.sequence != leaf.sequence() {
+            return Err(Error::WrongInputs(format!(
+                "Input {index} needs sequence {}",
+                leaf.sequence()
+            )));
+        }
+        input.witness = leaf.witness(signatures)?;
+        if leaf.conditions.after.is_some() && tx.lock_time != leaf.lock_time() {
+            return Err(Error::WrongInputs(format!(
+                "Transaction needs lock time {}",
+                leaf.lock_time()
+            )));
+        }
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Amount, OutPoint, TxIn, Txid, hashes::Hash, transaction};
+    use nostr::key::SecretKey as NostrSecretKey;
+
+    use super::*;
+    use crate::{
+        invariants::SigningInvariants,
+        scripts::{EscrowScript, escrow_scripts},
+        secret::SecretNsec,
+        sign::BatchSigner,
+    };
+
+    fn nsec(byte: u8) -> SecretNsec {
+        SecretNsec::from(NostrSecretKey::from_slice(&[byte; 32]).unwrap())
+    }
+
+    #[test]
+    fn policy_compilation() {
+        let [buyer, seller, arbitrator] = [nsec(1), nsec(2), nsec(3)].map(|n| n.public_key());
+        let timelock = 144;
+
+        // The arbitrated escrow, written as a policy, compiles to the same leaves.
+        let policy: Policy = format!(
+            "or(9@and(pk({seller}),pk({buyer})), thresh(1, \
+             and(older({timelock}),and(pk({arbitrator}),pk({buyer}))), \
+             and(older({timelock}),and(pk({arbitrator}),pk({seller})))))"
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(policy.to_string().parse::<Policy>().unwrap(), policy);
+        let compiled = CompiledPolicy::compile(&policy, Network::Regtest).unwrap();
+        assert_eq!(compiled.leaves().len(), 3);
+        for escrow_script in [EscrowScript::A, EscrowScript::B, EscrowScript::C] {
+            let script = escrow_scripts(
+                &buyer,
+                &seller,
+                Some(&arbitrator),
+                Some(timelock),
+                escrow_script,
+            )
+            .unwrap();
+            assert!(compiled.find_leaf(&script).is_some(), "{escrow_script:?}");
+        }
+        // The likeliest leaf is the closest to the root.
+        assert_eq!(compiled.leaves()[0].control_block.merkle_branch.len(), 1);
+
+        for invalid in [
+            "pk(npub)".to_string(),
+            "thresh(3,after(1),after(2))".to_string(),
+            "after(1)".to_string(),
+            format!("and(pk({buyer}),after(0))"),
+            format!("and(pk({buyer}),pk({buyer}))"),
+            format!("and(after(100),and(after(1700000000),pk({buyer})))"),
+            format!("or(0@pk({buyer}),pk({seller}))"),
+            format!("pk({buyer}) pk({seller})"),
+        ] {
+            assert!(
+                invalid
+                    .parse::<Policy>()
+                    .and_then(|policy| CompiledPolicy::compile(&policy, Network::Regtest))
+                    .is_err(),
+                "{invalid}"
+            );
+        }
+
+        // A 2-of-3 with a timeout for the buyer alone signs and combines like any leaf.
+        let policy: Policy = format!(
+            "or(thresh(2,pk({buyer}),pk({seller}),pk({arbitrator})),and(pk({buyer}),after(900000)))"
+        )
+        .parse()
+        .unwrap();
+        let compiled = CompiledPolicy::compile(&policy, Network::Regtest).unwrap();
+        let multi = &compiled.leaves()[0];
+        assert_eq!(multi.signers(), vec![buyer, seller, arbitrator]);
+        let prevout = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: compiled.address().script_pubkey(),
+        };
+        let tx = Transaction {
+            version: transaction::Version(2),
+            lock_time: multi.lock_time(),
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                sequence: multi.sequence(),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: compiled.address().script_pubkey(),
+            }],
+        };
+        let invariants = SigningInvariants::of_local_tx(&tx, vec![prevout.clone()], vec![None]);
+        let mut signer = BatchSigner::new(&tx, &invariants).unwrap();
+        let signatures: Vec<SignerSignature> = [nsec(2), nsec(3)]
+            .iter()
+            .map(|nsec| SignerSignature {
+                npub: nsec.public_key(),
+                signature: signer.sign(0, &multi.script, nsec).unwrap(),
+            })
+            .collect();
+        compiled
+            .verify(&tx, 0, std::slice::from_ref(&prevout), multi, &signatures)
+            .unwrap();
+        assert!(
+            compiled
+                .verify(&tx, 0, &[prevout], &compiled.leaves()[1], &signatures)
+                .is_err()
+        );
+        assert!(
+            compiled
+                .combine(tx.clone(), 0, multi, &signatures[..1])
+                .is_err()
+        );
+        let signed = compiled.combine(tx.clone(), 0, multi, &signatures).unwrap();
+        let witness = &signed.input[0].witness;
+        // The arbitrator's signature, the seller's and the buyer's empty one, then the leaf.
+        assert_eq!(witness.len(), 5);
+        assert_eq!(witness.nth(0).unwrap(), signatures[1].signature.as_ref());
+        assert_eq!(witness.nth(1).unwrap(), signatures[0].signature.as_ref());
+        assert!(witness.nth(2).unwrap().is_empty());
+
+        // The timeout leaf needs its lock time.
+        let timeout = &compiled.leaves()[1];
+        assert_eq!(
+            timeout.lock_time(),
+            absolute::LockTime::from_height(900_000).unwrap()
+        );
+        let signature = SignerSignature {
+            npub: buyer,
+            signature: signer.sign(0, &timeout.script, &nsec(1)).unwrap(),
+        };
+        assert!(compiled.combine(tx, 0, timeout, &[signature]).is_err());
+    }
+}
@@ -0,0 +1,475 @@
+//! Bitcoin price feeds, for escrows denominated in fiat.
+//!
+//! An [`Offer`](crate::protocol::Offer) can set its amounts in a fiat [`Currency`],
+//! such as "R$ 500 of BTC", with [`FiatTerms`].
+//! The acceptor quotes the [`Price`] from one of the configured [`PriceProvider`]s
+//! when accepting, which locks the amounts in sats for the rest of the session.
+//! The offerer checks the quote against the one of the offer, within [`MAX_PRICE_DEVIATION_BPS`],
+//! so neither party can pick a price that suits them.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::Amount;
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::Timestamp;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::Error,
+    i18n::Language,
+    runtime::get_text,
+    units::{Denomination, format_amount},
+};
+
+/// Providers queried by default, in order.
+pub(crate) const DEFAULT_PRICE_PROVIDERS: [PriceProvider; 2] =
+    [PriceProvider::Mempool, PriceProvider::CoinGecko];
+
+/// Largest difference between the price at acceptance and the price of the offer,
+/// in basis points.
+pub(crate) const MAX_PRICE_DEVIATION_BPS: u64 = 500;
+
+/// Cents in a unit of every supported currency.
+const CENTS: u64 = 100;
+
+/// A fiat currency escrows can be denominated in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum Currency {
+    /// US dollar.
+    Usd,
+    /// Euro.
+    Eur,
+    /// Brazilian real.
+    Brl,
+}
+
+impl Currency {
+    /// All supported currencies.
+    pub(crate) const ALL: [Currency; 3] = [Currency::Usd, Currency::Eur, Currency::Brl];
+
+    /// ISO 4217 code of the currency.
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Brl => "BRL",
+        }
+    }
+
+    /// Symbol of the currency, such as `R$`.
+    pub(crate) fn symbol(self) -> &'static str {
+        match self {
+            Currency::Usd => "$",
+            Currency::Eur => "€",
+            Currency::Brl => "R$",
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Currency::ALL
+            .into_iter()
+            .find(|currency| currency.code().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| Error::WrongInputs(format!("Unsupported currency {s}")))
+    }
+}
+
+/// An amount of fiat money, in cents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct FiatAmount {
+    /// Currency of the amount.
+    pub(crate) currency: Currency,
+    /// Amount in cents of the currency.
+    pub(crate) cents: u64,
+}
+
+impl FiatAmount {
+    /// Parses a decimal `amount` of `currency`, such as `500` or `499.90`.
+    pub(crate) fn parse(currency: Currency, amount: &str) -> Result<Self, Error> {
+        let invalid = || Error::WrongInputs(format!("Invalid {currency} amount {amount}"));
+        let (units, fraction) = amount.trim().split_once('.').unwrap_or((amount.trim(), ""));
+        if units.is_empty() || fraction.len() > 2 {
+            return Err(invalid());
+        }
+        let units = units.parse::<u64>().map_err(|_| invalid())?;
+        let fraction = match fraction {
+            "" => 0,
+            fraction => format!("{fraction:0<2}")
+                .parse::<u64>()
+                .map_err(|_| invalid())?,
+        };
+        let cents = units
+            .checked_mul(CENTS)
+            .and_then(|cents| cents.checked_add(fraction))
+            .ok_or_else(invalid)?;
+        Ok(Self { currency, cents })
+    }
+}
+
+impl fmt::Display for FiatAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}.{:02}",
+            self.currency.symbol(),
+            self.cents / CENTS,
+            self.cents % CENTS
+        )
+    }
+}
+
+/// A source of bitcoin prices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PriceProvider {
+    /// The mempool.space price API.
+    Mempool,
+    /// The CoinGecko simple price API.
+    CoinGecko,
+}
+
+impl PriceProvider {
+    /// All supported providers.
+    pub(crate) const ALL: [PriceProvider; 2] = [PriceProvider::Mempool, PriceProvider::CoinGecko];
+
+    /// Name of the provider in the settings.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            PriceProvider::Mempool => "mempool",
+            PriceProvider::CoinGecko => "coingecko",
+        }
+    }
+
+    /// URL of the price of bitcoin in `currency`, [`None`] if the provider doesn't quote it.
+    pub(crate) fn url(self, currency: Currency) -> Option<String> {
+        match self {
+            PriceProvider::Mempool => (currency != Currency::Brl)
+                .then(|| "https://mempool.space/api/v1/prices".to_string()),
+            PriceProvider::CoinGecko => Some(format!(
+                "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies={}",
+                currency.code().to_lowercase()
+            )),
+        }
+    }
+
+    /// Parses the price of a bitcoin in `currency` out of a `response` of the provider, in cents.
+    pub(crate) fn parse(self, currency: Currency, response: &str) -> Result<u64, Error> {
+        let value = serde_json::from_str::<Value>(response)
+            .map_err(|e| Error::Http(format!("Invalid {} price: {e}", self.name())))?;
+        let price = match self {
+            PriceProvider::Mempool => value.get(currency.code()),
+            PriceProvider::CoinGecko => value
+                .get("bitcoin")
+                .and_then(|prices| prices.get(currency.code().to_lowercase())),
+        };
+        price
+            .and_then(Value::as_f64)
+            .filter(|price| price.is_finite() && *price > 0.0)
+            .map(|price| (price * CENTS as f64).round() as u64)
+            .ok_or_else(|| Error::Http(format!("No {currency} price from {}", self.name())))
+    }
+}
+
+impl fmt::Display for PriceProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PriceProvider {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PriceProvider::ALL
+            .into_iter()
+            .find(|provider| provider.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| Error::WrongInputs(format!("Unknown price provider {s}")))
+    }
+}
+
+/// Parses a list of price providers separated by newlines, commas or spaces.
+pub(crate) fn parse_price_providers(config: &str) -> Result<Vec<PriceProvider>, Error> {
+    let mut providers = Vec::new();
+    for provider in config
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|provider| !provider.is_empty())
+    {
+        let provider = provider.parse::<PriceProvider>()?;
+        if !providers.contains(&provider) {
+            providers.push(provider);
+        }
+    }
+    Ok(providers)
+}
+
+/// The price of a bitcoin quoted by a [`PriceProvider`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct Price {
+    /// Currency of the price.
+    pub(crate) currency: Currency,
+    /// Price of a bitcoin, in cents.
+    pub(crate) cents_per_btc: u64,
+    /// Provider of the quote.
+    pub(crate) provider: PriceProvider,
+    /// When the price was quoted.
+    pub(crate) time: Timestamp,
+}
+
+impl Price {
+    /// Converts a fiat `amount` to bitcoin at this price, to the nearest sat.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `amount` is in another currency.
+    pub(crate) fn to_sats(self, amount: &FiatAmount) -> Result<Amount, Error> {
+        if amount.currency != self.currency || self.cents_per_btc == 0 {
+            return Err(Error::WrongInputs(format!(
+                "Can't convert {} with a {} price",
+                amount.currency, self.currency
+            )));
+        }
+        let sats = (u128::from(amount.cents) * u128::from(Amount::ONE_BTC.to_sat())
+            + u128::from(self.cents_per_btc) / 2)
+            / u128::from(self.cents_per_btc);
+        u64::try_from(sats)
+            .map(Amount::from_sat)
+            .map_err(|_| Error::Rounding)
+    }
+
+    /// Converts `amount` to fiat at this price, to the nearest cent.
+    pub(crate) fn to_fiat(self, amount: Amount) -> FiatAmount {
+        let one_btc = u128::from(Amount::ONE_BTC.to_sat());
+        let cents =
+            (u128::from(amount.to_sat()) * u128::from(self.cents_per_btc) + one_btc / 2) / one_btc;
+        FiatAmount {
+            currency: self.currency,
+            cents: u64::try_from(cents).unwrap_or(u64::MAX),
+        }
+    }
+
+    /// Difference with the `other` price of the same currency, in basis points of this one.
+    pub(crate) fn deviation_bps(&self, other: &Price) -> u64 {
+        let difference = self.cents_per_btc.abs_diff(other.cents_per_btc);
+        let bps = u128::from(difference) * 10_000 / u128::from(self.cents_per_btc.max(1));
+        u64::try_from(bps).unwrap_or(u64::MAX)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let price = FiatAmount {
+            currency: self.currency,
+            cents: self.cents_per_btc,
+        };
+        write!(f, "{price}/BTC ({})", self.provider)
+    }
+}
+
+/// Amounts of an escrow denominated in fiat.
+///
+/// The sat amounts of the offer are quoted at [`FiatTerms::price`],
+/// and locked at the price of the acceptance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct FiatTerms {
+    /// Buyer's escrow amount.
+    pub(crate) amount_buyer: FiatAmount,
+    /// Seller's escrow amount.
+    pub(crate) amount_seller: FiatAmount,
+    /// Price the offer was quoted at.
+    pub(crate) price: Price,
+}
+
+impl FiatTerms {
+    /// Checks that the amounts and the price are in the same currency.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let currency = self.price.currency;
+        if self.amount_buyer.currency != currency || self.amount_seller.currency != currency {
+            return Err(Error::Protocol(
+                "Fiat amounts and price must be in the same currency".to_string(),
+            ));
+        }
+        if self.amount_buyer.cents == 0 && self.amount_seller.cents == 0 {
+            return Err(Error::Protocol("Offer has no fiat amount".to_string()));
+        }
+        if self.price.cents_per_btc == 0 {
+            return Err(Error::Protocol("Offer has no price".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The buyer's and seller's amounts at `price`.
+    pub(crate) fn amounts(&self, price: &Price) -> Result<(Amount, Amount), Error> {
+        Ok((
+            price.to_sats(&self.amount_buyer)?,
+            price.to_sats(&self.amount_seller)?,
+        ))
+    }
+
+    /// Checks that the acceptance `price` is in the currency of the terms, not older than
+    /// the price of the offer and within [`MAX_PRICE_DEVIATION_BPS`] of it.
+    pub(crate) fn check_price(&self, price: &Price) -> Result<(), Error> {
+        if price.currency != self.price.currency {
+            return Err(Error::Protocol(format!(
+                "Price is in {} instead of {}",
+                price.currency, self.price.currency
+            )));
+        }
+        if price.time < self.price.time {
+            return Err(Error::Protocol("Price is older than the offer".to_string()));
+        }
+        let deviation = self.price.deviation_bps(price);
+        if deviation > MAX_PRICE_DEVIATION_BPS {
+            return Err(Error::Protocol(format!(
+                "Price {price} is {deviation} basis points away from the offer's {}",
+                self.price
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Shows `amount` with its value at `price`, if any, such as `0.001 BTC (R$ 500.00)`.
+pub(crate) fn display_amount(amount: Amount, price: Option<&Price>) -> String {
+    let bitcoin = format_amount(amount, Denomination::Btc, Language::En);
+    match price {
+        Some(price) => format!("{bitcoin} ({})", price.to_fiat(amount)),
+        None => bitcoin,
+    }
+}
+
+/// Fetches the price of a bitcoin in `currency` from the first of `providers` quoting it,
+/// timestamped `now`.
+///
+/// # Errors
+///
+/// Errors if no provider quotes the currency or all of them fail.
+pub(crate) async fn fetch_price(
+    providers: &[PriceProvider],
+    currency: Currency,
+    now: Timestamp,
+) -> Result<Price, Error> {
+    let mut last_error = Error::WrongInputs(format!("No price provider quotes {currency}"));
+    for provider in providers {
+        let Some(url) = provider.url(currency) else {
+            continue;
+        };
+        match get_text(&url)
+            .await
+            .and_then(|response| provider.parse(currency, &response))
+        {
+            Ok(cents_per_btc) => {
+                return Ok(Price {
+                    currency,
+                    cents_per_btc,
+                    provider: *provider,
+                    time: now,
+                });
+            }
+            Err(e) => {
+                #[cfg(debug_assertions)]
+                trace!(%provider, error = %e, "price provider failed");
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fiat_conversions() {
+        let price = Price {
+            currency: Currency::Brl,
+            cents_per_btc: 50_000_000,
+            provider: PriceProvider::CoinGecko,
+            time: Timestamp::from(1_700_000_000),
+        };
+        let amount = FiatAmount::parse(Currency::Brl, "500").unwrap();
+        assert_eq!(amount.to_string(), "R$ 500.00");
+        assert_eq!(price.to_sats(&amount).unwrap(), Amount::from_sat(100_000));
+        assert_eq!(price.to_fiat(Amount::from_sat(100_000)), amount);
+        assert_eq!(
+            display_amount(Amount::from_sat(100_000), Some(&price)),
+            "0.001 BTC (R$ 500.00)"
+        );
+        assert_eq!(FiatAmount::parse(Currency::Usd, "4.5").unwrap().cents, 450);
+        assert!(FiatAmount::parse(Currency::Usd, "4.555").is_err());
+        assert!(FiatAmount::parse(Currency::Usd, "-1").is_err());
+        let dollars = FiatAmount::parse(Currency::Usd, "1").unwrap();
+        assert!(price.to_sats(&dollars).is_err());
+
+        let terms = FiatTerms {
+            amount_buyer: amount,
+            amount_seller: FiatAmount {
+                currency: Currency::Brl,
+                cents: 0,
+            },
+            price,
+        };
+        terms.validate().unwrap();
+        let later = |cents_per_btc| Price {
+            cents_per_btc,
+            time: Timestamp::from(1_700_000_060),
+            ..price
+        };
+        terms.check_price(&later(52_000_000)).unwrap();
+        assert!(terms.check_price(&later(60_000_000)).is_err());
+        assert!(
+            terms
+                .check_price(&Price {
+                    time: Timestamp::from(1_600_000_000),
+                    ..price
+                })
+                .is_err()
+        );
+        assert_eq!(
+            terms.amounts(&later(40_000_000)).unwrap(),
+            (Amount::from_sat(125_000), Amount::ZERO)
+        );
+    }
+
+    #[test]
+    fn price_providers() {
+        assert_eq!(
+            PriceProvider::Mempool
+                .parse(
+                    Currency::Usd,
+                    r#"{"time":1703252411,"USD":43753,"EUR":40545}"#
+                )
+                .unwrap(),
+            4_375_300
+        );
+        assert_eq!(
+            PriceProvider::CoinGecko
+                .parse(Currency::Brl, r#"{"bitcoin":{"brl":350123.45}}"#)
+                .unwrap(),
+            35_012_345
+        );
+        assert!(
+            PriceProvider::Mempool
+                .parse(Currency::Brl, r#"{"USD":43753}"#)
+                .is_err()
+        );
+        assert!(PriceProvider::Mempool.url(Currency::Brl).is_none());
+        assert_eq!(
+            parse_price_providers("coingecko, mempool\ncoingecko").unwrap(),
+            vec![PriceProvider::CoinGecko, PriceProvider::Mempool]
+        );
+        assert!(parse_price_providers("kraken").is_err());
+    }
+}
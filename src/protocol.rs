@@ -0,0 +1,2217 @@
+//! Offer/accept escrow negotiation over Nostr.
+//!
+//! Party A publishes an [`Offer`] event with the escrow parameters.
+//! Party B answers with an [`Acceptance`] event containing their `npub`,
+//! resolution address and the escrow address they derived, gift wrapped to party A
+//! so relays can't tell who took the offer, see [`gift_wrap`](crate::gift_wrap).
+//! Both sides validate each other's messages with the same rules,
+//! so a completed [`Handshake`] guarantees that both derived the same escrow address.
+//!
+//! Offers expire: they can't be accepted after their expiry timestamp,
+//! which is also published as a NIP-40 expiration tag so relays can drop them.
+//!
+//! Every negotiation is identified by the [`SessionId`] of its offer,
+//! which tags its events, signatures, persisted [`Session`] and log lines,
+//! so several escrows with the same counterparty can run side by side.
+//! Once funded, an escrow also has a canonical [`SessionId`] derived from its output,
+//! see [`SessionId::funded`], on which sessions created independently converge.
+//!
+//! Offers can be denominated in fiat with [`FiatTerms`]: the acceptor then quotes
+//! the [`Price`] in its [`Acceptance`], which locks the sat amounts of the escrow.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use bitcoin::{
+    Address, Amount, Network, OutPoint, Script, Transaction, Txid, absolute,
+    address::NetworkUnchecked,
+    consensus,
+    hashes::{Hash, sha256},
+};
+#[cfg(feature = "serde-types")]
+use bitcoin::{TxOut, secp256k1::schnorr};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{
+    Event, EventBuilder, EventId, Filter, Keys, Kind, Tag, Tags, Timestamp, UnsignedEvent,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(debug_assertions)]
+use crate::logging::session_span;
+#[cfg(feature = "serde-types")]
+use crate::{
+    audit::SpendAudit,
+    cancel::{Cancellation, is_cancelled},
+    funding::{Funding, FundingStatus},
+    gift_wrap::ReplayGuard,
+    history::History,
+    invariants::SigningInvariants,
+    mempool::FundingRisk,
+    price::display_amount,
+    receipts::Outbox,
+    rotation::KeyRotation,
+    scripts::{EscrowContext, EscrowScript},
+    secret::SecretNsec,
+    sign::{BatchSigner, LeafSignatures, verify_leaf_signatures},
+    storage::Storage,
+    vault::is_encrypted,
+    watch::{ConfirmationProgress, Conflict, WatchStatus},
+};
+use crate::{
+    canonical,
+    error::Error,
+    message::tagged_hash,
+    platform_fee::PlatformFee,
+    price::{FiatAmount, FiatTerms, Price},
+    scripts::{EscrowConfig, ScriptTemplate},
+    tx::{anti_fee_sniping_lock_time, escrow_tx},
+    util::npub_to_address,
+};
+
+/// Version of the negotiation messages.
+pub(crate) const PROTOCOL_VERSION: u8 = 1;
+
+/// Version of the persisted [`Session`] format.
+#[cfg(feature = "serde-types")]
+pub(crate) const SESSION_VERSION: u8 = 1;
+
+/// Nostr event kind of an [`Offer`].
+pub(crate) const OFFER_KIND: u16 = 8_383;
+
+/// Nostr event kind of an [`Acceptance`].
+pub(crate) const ACCEPTANCE_KIND: u16 = 8_384;
+
+/// Hashtag added to [`Offer`] events so they can be discovered.
+pub(crate) const OFFER_HASHTAG: &str = "scrow";
+
+/// Tag of the [`SessionId`] hash.
+const SESSION_ID_TAG: &[u8] = b"scrow/session";
+
+/// Tag of the canonical [`SessionId`] hash of a funded escrow.
+const FUNDED_SESSION_ID_TAG: &[u8] = b"scrow/session/funded";
+
+/// [`Storage`] key of the IDs of the persisted [`Session`]s.
+#[cfg(feature = "serde-types")]
+pub(crate) const SESSIONS_KEY: &str = "scrow.sessions";
+
+/// Prefix of the [`Storage`] keys of the persisted [`Session`]s, followed by their ID.
+#[cfg(feature = "serde-types")]
+pub(crate) const SESSION_KEY_PREFIX: &str = "scrow.session.";
+
+/// Default validity of an [`Offer`].
+pub(crate) const DEFAULT_OFFER_VALIDITY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Time allowed for an [`Acceptance`] created before expiry to reach the offerer.
+pub(crate) const ACCEPTANCE_GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+/// Most confirmations an [`Offer`] can require of the funding before resolution.
+pub(crate) const MAX_MIN_CONFIRMATIONS: u32 = 144;
+
+/// Confirmations required of the funding of escrows below each total amount,
+/// 6 above the last, see [`default_min_confirmations`].
+const MIN_CONFIRMATIONS_TIERS: [(Amount, u32); 5] = [
+    (Amount::from_sat(1_000_000), 1),
+    (Amount::from_sat(10_000_000), 2),
+    (Amount::from_sat(50_000_000), 3),
+    (Amount::from_sat(100_000_000), 4),
+    (Amount::from_sat(1_000_000_000), 5),
+];
+
+/// Confirmations required of the funding of an escrow of `amount` before resolution,
+/// from 1 below 0.01 BTC to 6 from 10 BTC.
+pub(crate) fn default_min_confirmations(amount: Amount) -> u32 {
+    MIN_CONFIRMATIONS_TIERS
+        .iter()
+        .find(|(below, _)| amount < *below)
+        .map_or(6, |(_, confirmations)| *confirmations)
+}
+
+/// Role of a participant in the escrow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Role {
+    /// Pays for the goods or services.
+    Buyer,
+
+    /// Delivers the goods or services.
+    Seller,
+}
+
+/// Identifier of an escrow negotiation: the tagged hash of its [`Offer`].
+///
+/// Two offers only share an ID if they are the same proposal, down to the expiry second,
+/// so concurrent escrows with the same counterparty never get their messages mixed up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct SessionId(sha256::Hash);
+
+impl SessionId {
+    /// The ID of the negotiation of `offer`.
+    pub(crate) fn new(offer: &Offer) -> Result<Self, Error> {
+        let digest = tagged_hash(SESSION_ID_TAG, serialize(offer)?.as_bytes());
+        Ok(Self(sha256::Hash::from_byte_array(digest)))
+    }
+
+    /// The canonical ID of the escrow paid to `script_pubkey` by the funding `outpoint`.
+    ///
+    /// It only depends on the escrow's output, so both participants derive it on their own
+    /// once the escrow is funded, whatever offer their sessions started from.
+    pub(crate) fn funded(script_pubkey: &Script, outpoint: OutPoint) -> Self {
+        let mut message = script_pubkey.to_bytes();
+        message.extend(consensus::serialize(&outpoint));
+        Self(sha256::Hash::from_byte_array(tagged_hash(
+            FUNDED_SESSION_ID_TAG,
+            &message,
+        )))
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for SessionId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(Self)
+            .map_err(|_| Error::Protocol(format!("Invalid session ID {s}")))
+    }
+}
+
+/// Escrow parameters proposed by the offerer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Offer {
+    /// Message version, see [`PROTOCOL_VERSION`].
+    pub(crate) version: u8,
+    /// Network of the escrow.
+    pub(crate) network: Network,
+    /// Offerer's Nostr public key.
+    pub(crate) offerer: NostrPublicKey,
+    /// Offerer's role in the escrow.
+    pub(crate) role: Role,
+    /// Counterparty the offer is addressed to, if any.
+    pub(crate) counterparty: Option<NostrPublicKey>,
+    /// Buyer's escrow amount.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount_buyer: Amount,
+    /// Seller's escrow amount.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) amount_seller: Amount,
+    /// Arbitrator's Nostr public key, for dispute escrows.
+    pub(crate) arbitrator: Option<NostrPublicKey>,
+    /// Timelock duration in blocks, for dispute escrows.
+    pub(crate) timelock_duration: Option<u32>,
+    /// Time after which the offer can't be accepted.
+    pub(crate) expires_at: Timestamp,
+    /// Block height used as the escrow transaction's anti-fee-sniping lock time,
+    /// if any, so both parties build the same transaction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) lock_time_height: Option<u32>,
+    /// Version of the [`ScriptTemplate`] of the escrow.
+    ///
+    /// Kept as a number so offers from newer versions fail with a clear error.
+    /// Offers predating script templates use [`ScriptTemplate::V1`].
+    #[serde(default = "legacy_script_template")]
+    pub(crate) script_template: u8,
+    /// Fiat amounts of the escrow, if it is denominated in fiat.
+    ///
+    /// The sat amounts above are then quoted at the price of the terms,
+    /// and locked at the price of the [`Acceptance`], see [`Offer::locked_amounts`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) fiat: Option<FiatTerms>,
+    /// Fee of the hosting platform, paid by the resolution, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) platform_fee: Option<PlatformFee>,
+    /// Confirmations required of the funding before the escrow can be resolved,
+    /// [`default_min_confirmations`] of the escrow amount if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) min_confirmations: Option<u32>,
+}
+
+/// Script template version of offers predating script templates.
+fn legacy_script_template() -> u8 {
+    ScriptTemplate::V1.into()
+}
+
+impl Offer {
+    /// Validates the escrow parameters.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported offer version {}",
+                self.version
+            )));
+        }
+        self.script_template()?;
+        if self.amount_buyer == Amount::ZERO && self.amount_seller == Amount::ZERO {
+            return Err(Error::Protocol("Offer has no escrow amount".to_string()));
+        }
+        self.amount_buyer
+            .checked_add(self.amount_seller)
+            .ok_or(Error::Rounding)?;
+        match (self.arbitrator, self.timelock_duration) {
+            (None, None) => {}
+            (Some(arbitrator), Some(timelock_duration)) => {
+                if arbitrator == self.offerer || Some(arbitrator) == self.counterparty {
+                    return Err(Error::Protocol(
+                        "Arbitrator must not be a participant".to_string(),
+                    ));
+                }
+                // Relative timelocks in blocks are 16 bits.
+                if timelock_duration == 0 || timelock_duration > u16::MAX as u32 {
+                    return Err(Error::Protocol(format!(
+                        "Invalid timelock of {timelock_duration} blocks"
+                    )));
+                }
+            }
+            _ => {
+                return Err(Error::Protocol(
+                    "Arbitrator and timelock must be set together".to_string(),
+                ));
+            }
+        }
+        self.lock_time()?;
+        if let Some(fiat) = &self.fiat {
+            fiat.validate()?;
+            if fiat.amounts(&fiat.price)? != (self.amount_buyer, self.amount_seller) {
+                return Err(Error::Protocol(
+                    "Offer amounts do not match its fiat amounts".to_string(),
+                ));
+            }
+        }
+        if self.counterparty == Some(self.offerer) {
+            return Err(Error::Protocol(
+                "Offerer can't be the counterparty".to_string(),
+            ));
+        }
+        if let Some(platform_fee) = &self.platform_fee {
+            platform_fee.validate(self.network)?;
+        }
+        if let Some(min_confirmations) = self.min_confirmations
+            && (min_confirmations == 0 || min_confirmations > MAX_MIN_CONFIRMATIONS)
+        {
+            return Err(Error::Protocol(format!(
+                "Invalid minimum of {min_confirmations} confirmations"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Confirmations required of the funding before the escrow can be resolved.
+    pub(crate) fn min_confirmations(&self) -> u32 {
+        self.min_confirmations
+            .unwrap_or_else(|| default_min_confirmations(self.amount_buyer + self.amount_seller))
+    }
+
+    /// Denominates the offer in fiat: `amount_buyer` and `amount_seller`,
+    /// quoted in sats at `price` until the acceptance locks them.
+    pub(crate) fn in_fiat(
+        self,
+        amount_buyer: FiatAmount,
+        amount_seller: FiatAmount,
+        price: Price,
+    ) -> Result<Self, Error> {
+        let fiat = FiatTerms {
+            amount_buyer,
+            amount_seller,
+            price,
+        };
+        fiat.validate()?;
+        let (amount_buyer, amount_seller) = fiat.amounts(&price)?;
+        let offer = Self {
+            amount_buyer,
+            amount_seller,
+            fiat: Some(fiat),
+            ..self
+        };
+        offer.validate()?;
+        Ok(offer)
+    }
+
+    /// The buyer's and seller's amounts of the escrow, given the `price` of the acceptance.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the offer is denominated in fiat and `price` is missing
+    /// or doesn't check out against the offer, or if the offer is in bitcoin and has a `price`.
+    pub(crate) fn locked_amounts(&self, price: Option<&Price>) -> Result<(Amount, Amount), Error> {
+        match (&self.fiat, price) {
+            (None, None) => Ok((self.amount_buyer, self.amount_seller)),
+            (Some(fiat), Some(price)) => {
+                fiat.check_price(price)?;
+                fiat.amounts(price)
+            }
+            (Some(_), None) => Err(Error::Protocol(
+                "Fiat escrows need the price at acceptance".to_string(),
+            )),
+            (None, Some(_)) => Err(Error::Protocol(
+                "Bitcoin escrows must not have a price".to_string(),
+            )),
+        }
+    }
+
+    /// Remaining validity of the offer at `now`, [`None`] once expired.
+    pub(crate) fn remaining_validity(&self, now: Timestamp) -> Option<Duration> {
+        self.expires_at
+            .as_u64()
+            .checked_sub(now.as_u64())
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Whether the offer has expired at `now`.
+    pub(crate) fn is_expired(&self, now: Timestamp) -> bool {
+        self.remaining_validity(now).is_none()
+    }
+
+    /// Errors if the offer has expired at `now`.
+    pub(crate) fn ensure_not_expired(&self, now: Timestamp) -> Result<(), Error> {
+        if self.is_expired(now) {
+            return Err(Error::Expired(self.expires_at));
+        }
+        Ok(())
+    }
+
+    /// Returns the buyer's and seller's Nostr public keys, given the acceptor's.
+    pub(crate) fn participants<'a>(
+        &'a self,
+        acceptor: &'a NostrPublicKey,
+    ) -> (&'a NostrPublicKey, &'a NostrPublicKey) {
+        match self.role {
+            Role::Buyer => (&self.offerer, acceptor),
+            Role::Seller => (acceptor, &self.offerer),
+        }
+    }
+
+    /// The [`ScriptTemplate`] of the escrow.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the template version is unknown to this version of scrow.
+    pub(crate) fn script_template(&self) -> Result<ScriptTemplate, Error> {
+        ScriptTemplate::try_from(self.script_template)
+    }
+
+    /// The [`EscrowConfig`] of the escrow once the acceptor is known,
+    /// with the buyer as first participant.
+    pub(crate) fn escrow_config(&self, acceptor: &NostrPublicKey) -> Result<EscrowConfig, Error> {
+        let (npub_buyer, npub_seller) = self.participants(acceptor);
+        Ok(EscrowConfig {
+            npub_1: *npub_buyer,
+            npub_2: *npub_seller,
+            npub_arbitrator: self.arbitrator,
+            timelock_duration: self.timelock_duration,
+            network: self.network,
+            template: self.script_template()?,
+        })
+    }
+
+    /// Derives the escrow [`Address`] once the acceptor is known,
+    /// using the offer's [`ScriptTemplate`].
+    pub(crate) fn escrow_address(&self, acceptor: &NostrPublicKey) -> Result<Address, Error> {
+        self.escrow_config(acceptor)?.address()
+    }
+
+    /// The lock time of the escrow transaction.
+    pub(crate) fn lock_time(&self) -> Result<absolute::LockTime, Error> {
+        match self.lock_time_height {
+            Some(height) => anti_fee_sniping_lock_time(height),
+            None => Ok(absolute::LockTime::ZERO),
+        }
+    }
+
+    /// Builds the unsigned escrow [`Transaction`] once the acceptor, the `price` of the acceptance
+    /// and the funding [`Txid`] are known.
+    ///
+    /// Both parties get the same transaction, including its lock time,
+    /// and the [`PlatformFee`] if any, paid by both in proportion to their amounts.
+    pub(crate) fn escrow_tx(
+        &self,
+        acceptor: &NostrPublicKey,
+        price: Option<&Price>,
+        funding_txid: Txid,
+        fee: Amount,
+    ) -> Result<Transaction, Error> {
+        let (npub_buyer, npub_seller) = self.participants(acceptor);
+        let (amount_buyer, amount_seller) = self.locked_amounts(price)?;
+        let mut tx = escrow_tx(
+            npub_buyer,
+            npub_seller,
+            self.timelock_duration,
+            amount_buyer,
+            amount_seller,
+            funding_txid,
+            fee,
+            self.network,
+            self.lock_time()?,
+        )?;
+        if let Some(platform_fee) = &self.platform_fee {
+            platform_fee.apply(&mut tx, amount_buyer, amount_seller, self.network)?;
+        }
+        Ok(tx)
+    }
+
+    /// The [`SessionId`] of the negotiation of this offer.
+    pub(crate) fn session_id(&self) -> Result<SessionId, Error> {
+        SessionId::new(self)
+    }
+
+    /// Builds and signs the offer [`Event`].
+    ///
+    /// The event is tagged with the offer's [`SessionId`].
+    pub(crate) fn to_event(&self, nsec: &NostrSecretKey) -> Result<Event, Error> {
+        let keys = Keys::new(nsec.clone());
+        if keys.public_key() != self.offerer {
+            return Err(Error::Protocol(
+                "Offer must be signed by the offerer".to_string(),
+            ));
+        }
+        let mut tags = vec![
+            Tag::hashtag(OFFER_HASHTAG),
+            Tag::expiration(self.expires_at),
+            Tag::identifier(self.session_id()?.to_string()),
+        ];
+        if let Some(counterparty) = self.counterparty {
+            tags.push(Tag::public_key(counterparty));
+        }
+        Ok(
+            EventBuilder::new(Kind::Custom(OFFER_KIND), canonical::encode(self)?)
+                .tags(tags)
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses and validates an offer [`Event`].
+    pub(crate) fn from_event(event: &Event) -> Result<Self, Error> {
+        check_event(event, OFFER_KIND)?;
+        let offer: Offer = canonical::decode(&event.content)?;
+        if offer.offerer != event.pubkey {
+            return Err(Error::Protocol(
+                "Offer is not signed by the offerer".to_string(),
+            ));
+        }
+        offer.validate()?;
+        check_session_tag(&event.tags, &offer.session_id()?)?;
+        Ok(offer)
+    }
+}
+
+/// Acceptor's answer to an [`Offer`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Acceptance {
+    /// Message version, see [`PROTOCOL_VERSION`].
+    pub(crate) version: u8,
+    /// ID of the accepted offer [`Event`].
+    pub(crate) offer_id: EventId,
+    /// [`SessionId`] of the accepted offer.
+    pub(crate) session_id: SessionId,
+    /// Acceptor's Nostr public key.
+    pub(crate) acceptor: NostrPublicKey,
+    /// Acceptor's resolution address, derived from their `npub`.
+    pub(crate) resolution_address: Address<NetworkUnchecked>,
+    /// Escrow address derived by the acceptor.
+    pub(crate) escrow_address: Address<NetworkUnchecked>,
+    /// Price quoted by the acceptor, locking the amounts of offers denominated in fiat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) price: Option<Price>,
+}
+
+impl Acceptance {
+    /// Creates the acceptance of an [`Offer`] by `acceptor`,
+    /// at `price` if the offer is denominated in fiat.
+    pub(crate) fn new(
+        offer_id: EventId,
+        offer: &Offer,
+        acceptor: NostrPublicKey,
+        price: Option<Price>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            version: PROTOCOL_VERSION,
+            offer_id,
+            session_id: offer.session_id()?,
+            acceptor,
+            resolution_address: npub_to_address(&acceptor, offer.network)?.into_unchecked(),
+            escrow_address: offer.escrow_address(&acceptor)?.into_unchecked(),
+            price,
+        })
+    }
+
+    /// Validates the acceptance against the accepted [`Offer`],
+    /// returning the agreed escrow [`Address`].
+    pub(crate) fn validate(&self, offer_id: EventId, offer: &Offer) -> Result<Address, Error> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported acceptance version {}",
+                self.version
+            )));
+        }
+        if self.offer_id != offer_id {
+            return Err(Error::Protocol(
+                "Acceptance is for another offer".to_string(),
+            ));
+        }
+        if self.session_id != offer.session_id()? {
+            return Err(Error::Protocol(
+                "Acceptance is for another session".to_string(),
+            ));
+        }
+        if self.acceptor == offer.offerer || Some(self.acceptor) == offer.arbitrator {
+            return Err(Error::Protocol(
+                "Acceptor must not be the offerer or the arbitrator".to_string(),
+            ));
+        }
+        if offer
+            .counterparty
+            .is_some_and(|counterparty| counterparty != self.acceptor)
+        {
+            return Err(Error::Protocol(
+                "Offer is addressed to another counterparty".to_string(),
+            ));
+        }
+        let resolution_address = npub_to_address(&self.acceptor, offer.network)?;
+        if self
+            .resolution_address
+            .clone()
+            .require_network(offer.network)
+            .ok()
+            != Some(resolution_address)
+        {
+            return Err(Error::Protocol(
+                "Resolution address does not match the acceptor".to_string(),
+            ));
+        }
+        let escrow_address = offer.escrow_address(&self.acceptor)?;
+        if self
+            .escrow_address
+            .clone()
+            .require_network(offer.network)
+            .ok()
+            .as_ref()
+            != Some(&escrow_address)
+        {
+            return Err(Error::Protocol(
+                "Escrow address does not match the offer".to_string(),
+            ));
+        }
+        offer.locked_amounts(self.price.as_ref())?;
+        Ok(escrow_address)
+    }
+
+    /// Builds and signs the acceptance [`Event`].
+    pub(crate) fn to_event(&self, nsec: &NostrSecretKey, offer: &Offer) -> Result<Event, Error> {
+        let keys = Keys::new(nsec.clone());
+        if keys.public_key() != self.acceptor {
+            return Err(Error::Protocol(
+                "Acceptance must be signed by the acceptor".to_string(),
+            ));
+        }
+        Ok(
+            EventBuilder::new(Kind::Custom(ACCEPTANCE_KIND), canonical::encode(self)?)
+                .tags([
+                    Tag::event(self.offer_id),
+                    Tag::public_key(offer.offerer),
+                    Tag::identifier(self.session_id.to_string()),
+                ])
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses an acceptance [`Event`].
+    ///
+    /// The acceptance still has to be validated against its offer with [`Acceptance::validate`].
+    pub(crate) fn from_event(event: &Event) -> Result<Self, Error> {
+        check_event(event, ACCEPTANCE_KIND)?;
+        let acceptance: Acceptance = canonical::decode(&event.content)?;
+        if acceptance.acceptor != event.pubkey {
+            return Err(Error::Protocol(
+                "Acceptance is not signed by the acceptor".to_string(),
+            ));
+        }
+        let offer_id = acceptance.offer_id.to_hex();
+        if !event
+            .tags
+            .iter()
+            .any(|tag| tag.as_slice() == ["e", offer_id.as_str()])
+        {
+            return Err(Error::Protocol(
+                "Acceptance does not reference its offer".to_string(),
+            ));
+        }
+        check_session_tag(&event.tags, &acceptance.session_id)?;
+        Ok(acceptance)
+    }
+
+    /// The [`Filter`] of the acceptances of the negotiation `session_id`.
+    pub(crate) fn filter(session_id: &SessionId) -> Filter {
+        Filter::new()
+            .kind(Kind::Custom(ACCEPTANCE_KIND))
+            .identifier(session_id.to_string())
+    }
+}
+
+/// State of an escrow negotiation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(tag = "state", rename_all = "snake_case")
+)]
+pub(crate) enum Handshake {
+    /// Offer published, waiting for an acceptance.
+    Offered { offer_id: EventId, offer: Offer },
+
+    /// Offer expired before being accepted.
+    Expired { offer_id: EventId, offer: Offer },
+
+    /// Both parties agreed on the escrow.
+    Agreed {
+        offer_id: EventId,
+        offer: Offer,
+        acceptance: Box<Acceptance>,
+        #[cfg_attr(feature = "serde-types", serde(with = "checked_address"))]
+        escrow_address: Address,
+    },
+}
+
+impl Handshake {
+    /// Starts a negotiation as the offerer.
+    ///
+    /// Returns the handshake and the offer [`Event`] to publish.
+    pub(crate) fn offer(
+        nsec: &NostrSecretKey,
+        offer: Offer,
+        now: Timestamp,
+    ) -> Result<(Self, Event), Error> {
+        offer.validate()?;
+        offer.ensure_not_expired(now)?;
+        let event = offer.to_event(nsec)?;
+        #[cfg(debug_assertions)]
+        session_span(&offer.session_id()?).in_scope(|| trace!(?offer, "created offer"));
+        let handshake = Handshake::Offered {
+            offer_id: event.id,
+            offer,
+        };
+        Ok((handshake, event))
+    }
+
+    /// Accepts an offer [`Event`] as the counterparty,
+    /// at the current `price` if the offer is denominated in fiat.
+    ///
+    /// Returns the agreed handshake and the acceptance [`Event`] to publish.
+    pub(crate) fn accept(
+        nsec: &NostrSecretKey,
+        offer_event: &Event,
+        price: Option<Price>,
+        now: Timestamp,
+    ) -> Result<(Self, Event), Error> {
+        let offer = Offer::from_event(offer_event)?;
+        offer.ensure_not_expired(now)?;
+        let acceptor = Keys::new(nsec.clone()).public_key();
+        let acceptance = Acceptance::new(offer_event.id, &offer, acceptor, price)?;
+        let escrow_address = acceptance.validate(offer_event.id, &offer)?;
+        let event = acceptance.to_event(nsec, &offer)?;
+        #[cfg(debug_assertions)]
+        session_span(&acceptance.session_id).in_scope(|| trace!(%escrow_address, "accepted offer"));
+        let handshake = Handshake::Agreed {
+            offer_id: offer_event.id,
+            offer,
+            acceptance: Box::new(acceptance),
+            escrow_address,
+        };
+        Ok((handshake, event))
+    }
+
+    /// Completes the negotiation as the offerer once the acceptance [`Event`] arrives.
+    ///
+    /// The acceptance must have been created before the offer expired
+    /// and be received within [`ACCEPTANCE_GRACE_PERIOD`] of the expiry.
+    pub(crate) fn receive(self, acceptance_event: &Event, now: Timestamp) -> Result<Self, Error> {
+        let (offer_id, offer) = match self {
+            Handshake::Offered { offer_id, offer } => (offer_id, offer),
+            Handshake::Expired { offer, .. } => return Err(Error::Expired(offer.expires_at)),
+            Handshake::Agreed { .. } => {
+                return Err(Error::Protocol("Negotiation is already agreed".to_string()));
+            }
+        };
+        if acceptance_event.created_at > offer.expires_at
+            || now > offer.expires_at + ACCEPTANCE_GRACE_PERIOD
+        {
+            return Err(Error::Expired(offer.expires_at));
+        }
+        let acceptance = Acceptance::from_event(acceptance_event)?;
+        let escrow_address = acceptance.validate(offer_id, &offer)?;
+        #[cfg(debug_assertions)]
+        session_span(&acceptance.session_id)
+            .in_scope(|| trace!(%escrow_address, "received acceptance"));
+        Ok(Handshake::Agreed {
+            offer_id,
+            offer,
+            acceptance: Box::new(acceptance),
+            escrow_address,
+        })
+    }
+
+    /// Moves a pending negotiation to [`Handshake::Expired`] once its offer expired at `now`.
+    pub(crate) fn expire(self, now: Timestamp) -> Self {
+        match self {
+            Handshake::Offered { offer_id, offer } if offer.is_expired(now) => {
+                Handshake::Expired { offer_id, offer }
+            }
+            handshake => handshake,
+        }
+    }
+
+    /// The negotiated [`Offer`].
+    pub(crate) fn negotiated_offer(&self) -> &Offer {
+        match self {
+            Handshake::Offered { offer, .. }
+            | Handshake::Expired { offer, .. }
+            | Handshake::Agreed { offer, .. } => offer,
+        }
+    }
+
+    /// The [`SessionId`] of the negotiation.
+    pub(crate) fn session_id(&self) -> Result<SessionId, Error> {
+        self.negotiated_offer().session_id()
+    }
+
+    /// Errors unless the funding reached the [`Offer::min_confirmations`] of the
+    /// negotiated offer with its `funding_confirmations`, so the escrow can be resolved.
+    pub(crate) fn ensure_resolvable(&self, funding_confirmations: u32) -> Result<(), Error> {
+        let required = self.negotiated_offer().min_confirmations();
+        if funding_confirmations < required {
+            return Err(Error::Protocol(format!(
+                "Funding has {funding_confirmations} of the {required} confirmations required to resolve"
+            )));
+        }
+        Ok(())
+    }
+
+    /// The agreed escrow [`Address`], if any.
+    pub(crate) fn escrow_address(&self) -> Option<&Address> {
+        match self {
+            Handshake::Offered { .. } | Handshake::Expired { .. } => None,
+            Handshake::Agreed { escrow_address, .. } => Some(escrow_address),
+        }
+    }
+
+    /// The price of the escrow amounts, if denominated in fiat:
+    /// the acceptance's once agreed, the offer's until then.
+    pub(crate) fn price(&self) -> Option<&Price> {
+        match self {
+            Handshake::Agreed { acceptance, .. } => acceptance.price.as_ref(),
+            Handshake::Offered { offer, .. } | Handshake::Expired { offer, .. } => {
+                offer.fiat.as_ref().map(|fiat| &fiat.price)
+            }
+        }
+    }
+
+    /// The buyer's and seller's amounts, locked once agreed and quoted until then.
+    pub(crate) fn amounts(&self) -> Result<(Amount, Amount), Error> {
+        match self {
+            Handshake::Agreed {
+                offer, acceptance, ..
+            } => offer.locked_amounts(acceptance.price.as_ref()),
+            Handshake::Offered { offer, .. } | Handshake::Expired { offer, .. } => {
+                Ok((offer.amount_buyer, offer.amount_seller))
+            }
+        }
+    }
+}
+
+/// A negotiation persisted by one of its participants,
+/// with the signatures collected so far.
+#[cfg(feature = "serde-types")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Session {
+    /// Format version, see [`SESSION_VERSION`].
+    pub(crate) version: u8,
+    /// State of the negotiation.
+    pub(crate) handshake: Handshake,
+    /// Signatures of the escrow spends.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) signatures: Vec<LeafSignatures>,
+    /// Funding of the escrow seen so far, flagging under- and overfunding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) funding: Option<Funding>,
+    /// Participants' cancellations of the escrow, see [`cancel`](crate::cancel).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) cancellations: Vec<Cancellation>,
+    /// Participants' key rotations, oldest first, see [`rotation`](crate::rotation).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) rotations: Vec<KeyRotation>,
+    /// Spend of the escrow by a transaction the session didn't prepare, see [`Conflict`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) conflict: Option<Conflict>,
+    /// Risk of the unconfirmed funding being reversed, see [`mempool`](crate::mempool).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) funding_risk: Option<FundingRisk>,
+    /// Messages sent by the session, tracked until acknowledged, see [`receipts`](crate::receipts).
+    #[serde(default, skip_serializing_if = "Outbox::is_empty")]
+    pub(crate) outbox: Outbox,
+    /// What happened to the escrow, oldest first, see [`history`](crate::history).
+    #[serde(default, skip_serializing_if = "History::is_empty")]
+    pub(crate) history: History,
+    /// Gift wrapped messages received, see [`gift_wrap`](crate::gift_wrap).
+    #[serde(default, skip_serializing_if = "ReplayGuard::is_empty")]
+    pub(crate) received: ReplayGuard,
+}
+
+#[cfg(feature = "serde-types")]
+impl Session {
+    /// Creates a session for `handshake`, with no signatures yet.
+    pub(crate) fn new(handshake: Handshake) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            handshake,
+            signatures: Vec::new(),
+            funding: None,
+            cancellations: Vec::new(),
+            rotations: Vec::new(),
+            conflict: None,
+            funding_risk: None,
+            outbox: Outbox::default(),
+            history: History::default(),
+            received: ReplayGuard::default(),
+        }
+    }
+
+    /// The [`SessionId`] of the negotiation.
+    pub(crate) fn id(&self) -> Result<SessionId, Error> {
+        self.handshake.session_id()
+    }
+
+    /// The canonical [`SessionId`] of the escrow, once funded, see [`SessionId::funded`].
+    ///
+    /// It is derived from the agreed escrow address and the original funding output,
+    /// so key rotations and top-ups don't change it.
+    pub(crate) fn canonical_id(&self) -> Option<SessionId> {
+        let escrow_address = self.handshake.escrow_address()?;
+        let funding = self.funding.as_ref()?.outputs.first()?;
+        Some(SessionId::funded(
+            &escrow_address.script_pubkey(),
+            funding.outpoint,
+        ))
+    }
+
+    /// Merges `other`, a session of the same funded escrow, into this one.
+    ///
+    /// Signatures, funding outputs and conflicts are merged whatever negotiation `other`
+    /// comes from, since they are about the same escrow output.
+    /// Cancellations and key rotations are bound to their [`SessionId`],
+    /// so they are only merged from a session of the same negotiation.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `other` is not funded by the same output, or holds key rotations that
+    /// can't be merged: from another negotiation, or diverging from this session's.
+    pub(crate) fn merge(&mut self, other: Session) -> Result<(), Error> {
+        let canonical_id = self
+            .canonical_id()
+            .ok_or_else(|| Error::Protocol("Escrow is not funded yet".to_string()))?;
+        if other.canonical_id() != Some(canonical_id) {
+            return Err(Error::Protocol(
+                "Sessions are not about the same funded escrow".to_string(),
+            ));
+        }
+        let same_negotiation = self.id()? == other.id()?;
+        if other.rotations.starts_with(&self.rotations) {
+            if !same_negotiation && other.rotations.len() > self.rotations.len() {
+                return Err(Error::Protocol(
+                    "Key rotations of another negotiation can't be merged".to_string(),
+                ));
+            }
+            self.rotations = other.rotations;
+        } else if !self.rotations.starts_with(&other.rotations) {
+            return Err(Error::Protocol(
+                "Sessions rotated keys differently".to_string(),
+            ));
+        }
+        for mut signatures in other.signatures {
+            signatures.session_id = None;
+            self.add_signatures(signatures)?;
+        }
+        if let (Some(funding), Some(other_funding)) = (self.funding.as_mut(), other.funding) {
+            for output in other_funding.outputs {
+                if !funding.outputs.contains(&output) {
+                    funding.outputs.push(output);
+                }
+            }
+        }
+        if same_negotiation {
+            for cancellation in other.cancellations {
+                if !self
+                    .cancellations
+                    .iter()
+                    .any(|c| c.npub == cancellation.npub)
+                {
+                    self.cancellations.push(cancellation);
+                }
+            }
+        }
+        if self.conflict.is_none() {
+            self.conflict = other.conflict;
+        }
+        if self.funding_risk.is_none() {
+            self.funding_risk = other.funding_risk;
+        }
+        self.history.merge(other.history);
+        Ok(())
+    }
+
+    /// Adds the `signatures` of a leaf spend of this session,
+    /// merging them with any already collected for the same input.
+    ///
+    /// Signing is deterministic, so a signer's signature delivered again is ignored,
+    /// while another signature of the same signer for the same input is rejected.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the signatures belong to another session or conflict with collected ones.
+    pub(crate) fn add_signatures(&mut self, mut signatures: LeafSignatures) -> Result<(), Error> {
+        let id = self.id()?;
+        if signatures
+            .session_id
+            .is_some_and(|session_id| session_id != id)
+        {
+            return Err(Error::Protocol(
+                "Signatures are for another session".to_string(),
+            ));
+        }
+        signatures.session_id = Some(id);
+        match self.signatures.iter_mut().find(|s| {
+            s.txid == signatures.txid
+                && s.input_index == signatures.input_index
+                && s.escrow_script == signatures.escrow_script
+        }) {
+            Some(existing) => {
+                if let Some(conflict) = signatures.signatures.iter().find(|signature| {
+                    existing
+                        .signatures
+                        .iter()
+                        .any(|s| s.npub == signature.npub && s != *signature)
+                }) {
+                    return Err(Error::Protocol(format!(
+                        "Conflicting signature of {} for {}",
+                        conflict.npub, signatures.txid
+                    )));
+                }
+                for signature in signatures.signatures {
+                    existing.insert(signature.npub, signature.signature);
+                }
+            }
+            None => self.signatures.push(signatures),
+        }
+        Ok(())
+    }
+
+    /// Adds `signatures` received from another participant, see [`Session::add_signatures`],
+    /// once checked against the `tx` built locally, spending `prevouts`,
+    /// through the leaf of `context`, see [`LeafSignatures::verify`].
+    ///
+    /// Returns whether any signature was new, `false` if they were all delivered before.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the signatures are not bound to this session, or don't sign the local `tx`.
+    pub(crate) fn receive_signatures(
+        &mut self,
+        signatures: LeafSignatures,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        context: &EscrowContext,
+    ) -> Result<bool, Error> {
+        if signatures.session_id != Some(self.id()?) {
+            return Err(Error::Protocol(
+                "Signatures are not bound to this session".to_string(),
+            ));
+        }
+        signatures.verify(tx, prevouts, context)?;
+        let collected = |session: &Self| -> usize {
+            session.signatures.iter().map(|s| s.signatures.len()).sum()
+        };
+        let before = collected(self);
+        self.add_signatures(signatures)?;
+        Ok(collected(self) > before)
+    }
+
+    /// Adds a `batch` of signatures received together, such as those of every input of a
+    /// batch spend, like [`Session::receive_signatures`] but verified together,
+    /// see [`verify_leaf_signatures`].
+    ///
+    /// Nothing is added unless every signature is valid.
+    ///
+    /// # Errors
+    ///
+    /// Errors if any signatures are not bound to this session, or don't sign the local `tx`.
+    pub(crate) fn receive_signature_batch(
+        &mut self,
+        batch: Vec<LeafSignatures>,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        context: &EscrowContext,
+    ) -> Result<bool, Error> {
+        let id = self.id()?;
+        if batch
+            .iter()
+            .any(|signatures| signatures.session_id != Some(id))
+        {
+            return Err(Error::Protocol(
+                "Signatures are not bound to this session".to_string(),
+            ));
+        }
+        verify_leaf_signatures(&batch, tx, prevouts, context)?;
+        let collected = |session: &Self| -> usize {
+            session.signatures.iter().map(|s| s.signatures.len()).sum()
+        };
+        let before = collected(self);
+        for signatures in batch {
+            self.add_signatures(signatures)?;
+        }
+        Ok(collected(self) > before)
+    }
+
+    /// Records a `tx` funding the agreed escrow, returning the updated [`FundingStatus`].
+    ///
+    /// The escrow is expected to hold both parties' amounts.
+    pub(crate) fn record_funding(&mut self, tx: &Transaction) -> Result<FundingStatus, Error> {
+        let Handshake::Agreed { escrow_address, .. } = &self.handshake else {
+            return Err(Error::Protocol("Escrow is not agreed yet".to_string()));
+        };
+        let (amount_buyer, amount_seller) = self.handshake.amounts()?;
+        let expected = amount_buyer + amount_seller;
+        let funding = self.funding.get_or_insert_with(|| Funding::new(expected));
+        funding.add_tx(tx, escrow_address);
+        Ok(funding.status())
+    }
+
+    /// Shows `amount` of the escrow in bitcoin and, if denominated in fiat,
+    /// at the price of the negotiation, such as `0.001 BTC (R$ 500.00)`.
+    pub(crate) fn display_amount(&self, amount: Amount) -> String {
+        display_amount(amount, self.handshake.price())
+    }
+
+    /// Transactions signed in this session, the only expected spends of the escrow.
+    pub(crate) fn expected_spends(&self) -> Vec<Txid> {
+        let mut txids = Vec::new();
+        for signatures in &self.signatures {
+            if !txids.contains(&signatures.txid) {
+                txids.push(signatures.txid);
+            }
+        }
+        txids
+    }
+
+    /// Records the `spend` of the escrow seen on chain,
+    /// returning the [`Conflict`] if it is none of the [`Session::expected_spends`].
+    pub(crate) fn record_spend(&mut self, spend: &SpendAudit) -> Option<&Conflict> {
+        self.conflict = Conflict::detect(spend, &self.expected_spends());
+        self.conflict.as_ref()
+    }
+
+    /// Records the [`FundingRisk`] seen in the mempool by
+    /// [`check_funding`](crate::mempool::check_funding), [`None`] once the funding confirmed,
+    /// returning the risk the session is now at.
+    pub(crate) fn record_funding_risk(
+        &mut self,
+        risk: Option<FundingRisk>,
+    ) -> Option<&FundingRisk> {
+        self.funding_risk = risk;
+        self.funding_risk.as_ref()
+    }
+
+    /// Whether the unconfirmed funding can still be reversed, so the seller must not ship yet.
+    pub(crate) fn is_at_risk(&self) -> bool {
+        self.funding_risk.is_some()
+    }
+
+    /// Confirmations required of the funding before the escrow can be resolved,
+    /// see [`Offer::min_confirmations`].
+    pub(crate) fn min_confirmations(&self) -> u32 {
+        self.handshake.negotiated_offer().min_confirmations()
+    }
+
+    /// The [`ConfirmationProgress`] of the funding towards [`Session::min_confirmations`],
+    /// given the escrow's [`WatchStatus`].
+    pub(crate) fn funding_progress(&self, status: WatchStatus) -> ConfirmationProgress {
+        status.funding_progress(self.min_confirmations())
+    }
+
+    /// Errors unless the funding reached [`Session::min_confirmations`] with its
+    /// `funding_confirmations`, so the escrow can be resolved.
+    pub(crate) fn ensure_resolvable(&self, funding_confirmations: u32) -> Result<(), Error> {
+        self.handshake.ensure_resolvable(funding_confirmations)
+    }
+
+    /// Signs input `index` of the resolution `tx` through the `escrow_script` leaf
+    /// with the participant's `nsec`, once it satisfies the `invariants`,
+    /// adding the signature to the session.
+    ///
+    /// # Errors
+    ///
+    /// Errors before signing if the funding has fewer than [`Session::min_confirmations`]
+    /// with its `funding_confirmations`, see [`Session::ensure_resolvable`],
+    /// or if `tx` breaks any of the `invariants`.
+    pub(crate) fn sign(
+        &mut self,
+        tx: &Transaction,
+        index: usize,
+        escrow_script: EscrowScript,
+        invariants: &SigningInvariants,
+        nsec: &SecretNsec,
+        funding_confirmations: u32,
+    ) -> Result<schnorr::Signature, Error> {
+        self.ensure_resolvable(funding_confirmations)?;
+        let signature = BatchSigner::new(tx, invariants)?.sign_leaf(
+            index,
+            &self.escrow_context()?,
+            escrow_script,
+            nsec,
+        )?;
+        let mut signatures = LeafSignatures::new(tx.compute_txid(), index, escrow_script);
+        signatures.insert(nsec.public_key(), signature);
+        self.add_signatures(signatures)?;
+        Ok(signature)
+    }
+
+    /// Adds a participant's `cancellation`, returning whether the session is now cancelled.
+    ///
+    /// `funding_confirmed` tells whether a funding transaction of the escrow confirmed.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the funding confirmed, since the escrow can then only be resolved,
+    /// or the cancellation is not from a participant of this session.
+    pub(crate) fn cancel(
+        &mut self,
+        cancellation: Cancellation,
+        funding_confirmed: bool,
+    ) -> Result<bool, Error> {
+        if funding_confirmed {
+            return Err(Error::Protocol(
+                "Escrow funding is confirmed, it can only be resolved".to_string(),
+            ));
+        }
+        cancellation.verify(&self.handshake)?;
+        if !self
+            .cancellations
+            .iter()
+            .any(|c| c.npub == cancellation.npub)
+        {
+            self.cancellations.push(cancellation);
+        }
+        Ok(self.is_cancelled())
+    }
+
+    /// Whether every participant cancelled the escrow.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        is_cancelled(&self.handshake, &self.cancellations)
+    }
+
+    /// The [`EscrowConfig`] of the escrow currently holding the coins,
+    /// with every key rotation applied.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow is not agreed yet or a rotation doesn't verify.
+    pub(crate) fn escrow_config(&self) -> Result<EscrowConfig, Error> {
+        let Handshake::Agreed {
+            offer, acceptance, ..
+        } = &self.handshake
+        else {
+            return Err(Error::Protocol("Escrow is not agreed yet".to_string()));
+        };
+        let mut config = offer.escrow_config(&acceptance.acceptor)?;
+        for rotation in &self.rotations {
+            config = rotation.rotated_config(&config)?;
+        }
+        Ok(config)
+    }
+
+    /// The [`EscrowContext`] of the [`Session::escrow_config`], to compute once
+    /// and reuse for every signature and witness of the session.
+    pub(crate) fn escrow_context(&self) -> Result<EscrowContext, Error> {
+        self.escrow_config()?.context()
+    }
+
+    /// Records a participant's key `rotation`, returning the [`EscrowConfig`] of the escrow
+    /// the coins migrate to.
+    ///
+    /// The session keeps its [`SessionId`], so it is saved under the same [`Storage`] key.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the rotation is for another session or doesn't replace a current participant.
+    pub(crate) fn rotate(&mut self, rotation: KeyRotation) -> Result<EscrowConfig, Error> {
+        let config = self.escrow_config()?;
+        rotation.verify(&self.id()?, &config)?;
+        let rotated = rotation.rotated_config(&config)?;
+        self.rotations.push(rotation);
+        Ok(rotated)
+    }
+
+    /// Serializes the session as JSON.
+    pub(crate) fn to_json(&self) -> Result<String, Error> {
+        serialize(self)
+    }
+
+    /// Parses a session serialized with [`Session::to_json`], see [`Session::check`].
+    pub(crate) fn from_json(json: &str) -> Result<Self, Error> {
+        let session = deserialize::<Self>(json)?;
+        session.check()?;
+        Ok(session)
+    }
+
+    /// Checks a session read from outside the app.
+    ///
+    /// An agreed escrow address is derived again from the offer and acceptance,
+    /// and key rotations are verified in order, so a tampered session is rejected.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.version != SESSION_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported session version {}",
+                self.version
+            )));
+        }
+        if let Handshake::Agreed {
+            offer_id,
+            offer,
+            acceptance,
+            escrow_address,
+        } = &self.handshake
+            && acceptance.validate(*offer_id, offer)? != *escrow_address
+        {
+            return Err(Error::Protocol(
+                "Session escrow address does not match the negotiation".to_string(),
+            ));
+        }
+        if !self.rotations.is_empty() {
+            self.escrow_config()?;
+        }
+        Ok(())
+    }
+
+    /// [`Storage`] key of the session `id`.
+    fn key(id: &SessionId) -> String {
+        format!("{SESSION_KEY_PREFIX}{id}")
+    }
+
+    /// The IDs of the sessions persisted in `storage`.
+    pub(crate) fn list(storage: &impl Storage) -> Result<Vec<SessionId>, Error> {
+        match storage.get(SESSIONS_KEY)? {
+            Some(json) => deserialize(&json),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Loads the session `id` from `storage`, if it was saved.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the session is encrypted, see [`Vault::storage`](crate::vault::Vault::storage).
+    pub(crate) fn load(storage: &impl Storage, id: &SessionId) -> Result<Option<Self>, Error> {
+        let Some(json) = storage.get(&Self::key(id))? else {
+            return Ok(None);
+        };
+        if is_encrypted(&json) {
+            return Err(Error::Storage(format!("Session {id} is locked")));
+        }
+        let session = Self::from_json(&json)?;
+        if session.id()? != *id {
+            return Err(Error::Storage(format!(
+                "Session {id} is stored under another ID"
+            )));
+        }
+        Ok(Some(session))
+    }
+
+    /// Looks up the sessions persisted in `storage` of the funded escrow `canonical_id`,
+    /// see [`Session::canonical_id`], merged into the first one saved.
+    ///
+    /// Returns `None` if no session of the escrow is persisted.
+    pub(crate) fn find_funded(
+        storage: &impl Storage,
+        canonical_id: &SessionId,
+    ) -> Result<Option<Self>, Error> {
+        let mut found: Option<Self> = None;
+        for id in Self::list(storage)? {
+            let Some(session) = Self::load(storage, &id)? else {
+                continue;
+            };
+            if session.canonical_id() != Some(*canonical_id) {
+                continue;
+            }
+            match found.as_mut() {
+                Some(found) => found.merge(session)?,
+                None => found = Some(session),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Expires the pending negotiations persisted in `storage` that can no longer
+    /// receive an acceptance at `now`, see [`ACCEPTANCE_GRACE_PERIOD`].
+    ///
+    /// Returns how many expired.
+    pub(crate) fn expire_offers(storage: &impl Storage, now: Timestamp) -> Result<usize, Error> {
+        let mut expired = 0;
+        for id in Self::list(storage)? {
+            let Some(mut session) = Self::load(storage, &id)? else {
+                continue;
+            };
+            let handshake = session
+                .handshake
+                .clone()
+                .expire(now - ACCEPTANCE_GRACE_PERIOD);
+            if handshake != session.handshake {
+                session.handshake = handshake;
+                session.save(storage)?;
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Saves the session to `storage`, next to the other sessions.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        let id = self.id()?;
+        storage.set(&Self::key(&id), &self.to_json()?)?;
+        let mut ids = Self::list(storage)?;
+        if !ids.contains(&id) {
+            ids.push(id);
+            storage.set(SESSIONS_KEY, &serialize(&ids)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serde of an agreed escrow [`Address`].
+///
+/// The network is checked by [`Session::from_json`], which derives the address again.
+#[cfg(feature = "serde-types")]
+mod checked_address {
+    use bitcoin::{Address, address::NetworkUnchecked};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        address: &Address,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        address.serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Address, D::Error> {
+        Address::<NetworkUnchecked>::deserialize(deserializer).map(Address::assume_checked)
+    }
+}
+
+/// Checks an [`Event`]'s kind, ID and signature.
+pub(crate) fn check_event(event: &Event, kind: u16) -> Result<(), Error> {
+    if event.kind != Kind::Custom(kind) {
+        return Err(Error::Protocol(format!(
+            "Expected event kind {kind}, got {}",
+            event.kind
+        )));
+    }
+    event.verify()?;
+    Ok(())
+}
+
+/// Checks a NIP-59 rumor's kind.
+///
+/// Rumors are unsigned: their ID and author are checked against the seal they came in
+/// by [`unwrap`](crate::gift_wrap::unwrap).
+pub(crate) fn check_rumor(rumor: &UnsignedEvent, kind: u16) -> Result<(), Error> {
+    if rumor.kind != Kind::Custom(kind) {
+        return Err(Error::Protocol(format!(
+            "Expected rumor kind {kind}, got {}",
+            rumor.kind
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that an event's `tags` hold the `d` tag of `session_id`.
+pub(crate) fn check_session_tag(tags: &Tags, session_id: &SessionId) -> Result<(), Error> {
+    let session_id = session_id.to_string();
+    if !tags
+        .iter()
+        .any(|tag| tag.as_slice() == ["d", session_id.as_str()])
+    {
+        return Err(Error::Protocol(
+            "Event is not tagged with its session".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Serializes a message as JSON.
+pub(crate) fn serialize<T: Serialize>(message: &T) -> Result<String, Error> {
+    serde_json::to_string(message).map_err(|e| Error::Protocol(e.to_string()))
+}
+
+/// Parses a JSON message.
+pub(crate) fn deserialize<T: for<'de> Deserialize<'de>>(content: &str) -> Result<T, Error> {
+    serde_json::from_str(content).map_err(|e| Error::Protocol(format!("Malformed message: {e}")))
+}
+
+/// A regtest [`Offer`] of `offerer`, selling 100 000 sats with a 10 000 sats deposit,
+/// valid from now, disputed through `arbitrator` with a 144 blocks timelock if any.
+///
+/// Tests override the fields they need with the struct update syntax.
+#[cfg(test)]
+pub(crate) fn offer(offerer: NostrPublicKey, arbitrator: Option<NostrPublicKey>) -> Offer {
+    Offer {
+        version: PROTOCOL_VERSION,
+        network: Network::Regtest,
+        offerer,
+        role: Role::Seller,
+        counterparty: None,
+        amount_buyer: Amount::from_sat(100_000),
+        amount_seller: Amount::from_sat(10_000),
+        arbitrator,
+        timelock_duration: arbitrator.map(|_| 144),
+        expires_at: Timestamp::now() + DEFAULT_OFFER_VALIDITY,
+        lock_time_height: None,
+        script_template: crate::scripts::CURRENT_SCRIPT_TEMPLATE.into(),
+        fiat: None,
+        platform_fee: None,
+        min_confirmations: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::JsonUtil;
+
+    use super::*;
+    use crate::{
+        price::{Currency, PriceProvider},
+        scripts::escrow_address,
+    };
+
+    fn now() -> Timestamp {
+        Timestamp::now()
+    }
+
+    #[test]
+    fn handshake_agrees_on_escrow_address() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+
+        let (handshake_a, offer_event) =
+            Handshake::offer(keys_a.secret_key(), offer, now()).unwrap();
+        assert!(handshake_a.escrow_address().is_none());
+
+        // The offer goes through a relay as JSON.
+        let offer_event = Event::from_json(offer_event.as_json()).unwrap();
+        let (handshake_b, acceptance_event) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).unwrap();
+        let handshake_a = handshake_a.receive(&acceptance_event, now()).unwrap();
+
+        let expected = escrow_address(
+            &keys_b.public_key(),
+            &keys_a.public_key(),
+            Some(&keys_arbitrator.public_key()),
+            Some(144),
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(handshake_a.escrow_address(), Some(&expected));
+        assert_eq!(handshake_b.escrow_address(), Some(&expected));
+        assert!(handshake_a.receive(&acceptance_event, now()).is_err());
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn session_roundtrip() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let (_, offer_event) = Handshake::offer(keys_a.secret_key(), offer, now()).unwrap();
+        let (handshake, _) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).unwrap();
+
+        let mut session = Session::new(handshake);
+        let funding_tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: Amount::from_sat(120_000),
+                script_pubkey: session.handshake.escrow_address().unwrap().script_pubkey(),
+            }],
+        };
+        assert_eq!(
+            session.record_funding(&funding_tx).unwrap(),
+            FundingStatus::Overfunded {
+                excess: Amount::from_sat(10_000)
+            }
+        );
+        let json = session.to_json().unwrap();
+        assert!(json.contains(r#""state":"agreed""#));
+        assert_eq!(Session::from_json(&json).unwrap(), session);
+
+        let other_address = npub_to_address(&keys_b.public_key(), Network::Regtest).unwrap();
+        let mut tampered = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        tampered["handshake"]["escrow_address"] = other_address.to_string().into();
+        assert!(Session::from_json(&tampered.to_string()).is_err());
+
+        let mut future = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        future["version"] = (SESSION_VERSION + 1).into();
+        assert!(Session::from_json(&future.to_string()).is_err());
+
+        // A spend the session didn't sign is recorded as a conflict and persisted.
+        let signed = LeafSignatures::new(
+            Txid::from_byte_array([1; 32]),
+            0,
+            crate::scripts::EscrowScript::A,
+        );
+        session.add_signatures(signed).unwrap();
+        let mut spend = SpendAudit {
+            txid: Txid::from_byte_array([1; 32]),
+            input_index: 0,
+            path: None,
+            signers: Vec::new(),
+            payouts: Vec::new(),
+            confirmed_height: None,
+        };
+        assert!(session.record_spend(&spend).is_none());
+        spend.txid = Txid::from_byte_array([2; 32]);
+        assert!(session.record_spend(&spend).is_some());
+        let json = session.to_json().unwrap();
+        assert_eq!(Session::from_json(&json).unwrap(), session);
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn concurrent_sessions_are_separated() {
+        use crate::{scripts::EscrowScript, storage::MemoryStorage};
+
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer_1 = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let offer_2 = Offer {
+            amount_buyer: Amount::from_sat(200_000),
+            ..offer_1.clone()
+        };
+        let (session_1, session_2) = (offer_1.session_id().unwrap(), offer_2.session_id().unwrap());
+        assert_ne!(session_1, session_2);
+        assert_eq!(
+            session_1.to_string().parse::<SessionId>().unwrap(),
+            session_1
+        );
+
+        // Both negotiations run side by side with the same counterparty.
+        let (handshake_1, offer_event_1) =
+            Handshake::offer(keys_a.secret_key(), offer_1.clone(), now()).unwrap();
+        let (handshake_2, offer_event_2) =
+            Handshake::offer(keys_a.secret_key(), offer_2, now()).unwrap();
+        let (_, acceptance_event_1) =
+            Handshake::accept(keys_b.secret_key(), &offer_event_1, None, now()).unwrap();
+        let (_, acceptance_event_2) =
+            Handshake::accept(keys_b.secret_key(), &offer_event_2, None, now()).unwrap();
+        let d_tag = ["d".to_string(), session_1.to_string()];
+        assert!(offer_event_1.tags.iter().any(|tag| tag.as_slice() == d_tag));
+        assert!(
+            acceptance_event_1
+                .tags
+                .iter()
+                .any(|tag| tag.as_slice() == d_tag)
+        );
+        assert!(
+            handshake_1
+                .clone()
+                .receive(&acceptance_event_2, now())
+                .is_err()
+        );
+        let handshake_1 = handshake_1.receive(&acceptance_event_1, now()).unwrap();
+        let handshake_2 = handshake_2.receive(&acceptance_event_2, now()).unwrap();
+
+        // An acceptance claiming another session is rejected.
+        let mut acceptance =
+            Acceptance::new(offer_event_1.id, &offer_1, keys_b.public_key(), None).unwrap();
+        acceptance.session_id = session_2;
+        assert!(acceptance.validate(offer_event_1.id, &offer_1).is_err());
+
+        // Signatures can't be mixed up between sessions.
+        let mut session = Session::new(handshake_1);
+        let mut signatures = LeafSignatures::new(Txid::all_zeros(), 0, EscrowScript::A);
+        session.add_signatures(signatures.clone()).unwrap();
+        assert_eq!(session.signatures[0].session_id, Some(session_1));
+        signatures.session_id = Some(session_2);
+        assert!(session.add_signatures(signatures).is_err());
+
+        let storage = MemoryStorage::default();
+        session.save(&storage).unwrap();
+        Session::new(handshake_2).save(&storage).unwrap();
+        session.save(&storage).unwrap();
+        assert_eq!(Session::list(&storage).unwrap(), vec![session_1, session_2]);
+        assert_eq!(Session::load(&storage, &session_1).unwrap(), Some(session));
+        assert_eq!(
+            Session::load(&storage, &session_2)
+                .unwrap()
+                .map(|session| session.id().unwrap()),
+            Some(session_2)
+        );
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn replayed_signatures_are_rejected() {
+        use crate::{
+            invariants::SigningInvariants,
+            scripts::EscrowScript,
+            secret::SecretNsec,
+            sign::{BatchSigner, SignerSignature},
+        };
+
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let session = |offer: Offer| {
+            let (_, offer_event) = Handshake::offer(keys_a.secret_key(), offer, now()).unwrap();
+            let (handshake, _) =
+                Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).unwrap();
+            Session::new(handshake)
+        };
+        let offer_1 = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let mut session_1 = session(offer_1.clone());
+        let session_2 = session(Offer {
+            amount_buyer: Amount::from_sat(200_000),
+            ..offer_1
+        });
+        let context = session_1.escrow_context().unwrap();
+        let spend = |funding_txid: Txid| Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::new(funding_txid, 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(109_000),
+                script_pubkey: npub_to_address(&keys_a.public_key(), Network::Regtest)
+                    .unwrap()
+                    .script_pubkey(),
+            }],
+        };
+        let prevouts = [TxOut {
+            value: Amount::from_sat(110_000),
+            script_pubkey: context.address().script_pubkey(),
+        }];
+        let sign = |tx: &Transaction, keys: &Keys, session: &Session| {
+            let nsec = SecretNsec::from(keys.secret_key().clone());
+            let invariants = SigningInvariants::of_local_tx(tx, prevouts.to_vec(), vec![None]);
+            let signature = BatchSigner::new(tx, &invariants)
+                .unwrap()
+                .sign_leaf(0, &context, EscrowScript::A, &nsec)
+                .unwrap();
+            let mut signatures = LeafSignatures::new(tx.compute_txid(), 0, EscrowScript::A);
+            signatures.session_id = Some(session.id().unwrap());
+            signatures.insert(keys.public_key(), signature);
+            signatures
+        };
+        let tx = spend(Txid::from_byte_array([1; 32]));
+
+        let received = sign(&tx, &keys_b, &session_1);
+        assert!(
+            session_1
+                .receive_signatures(received.clone(), &tx, &prevouts, &context)
+                .unwrap()
+        );
+        // The same signature delivered again adds nothing.
+        assert!(
+            !session_1
+                .receive_signatures(received.clone(), &tx, &prevouts, &context)
+                .unwrap()
+        );
+
+        // Signatures of another session, of another escrow spend, or unbound, are rejected.
+        let replayed = sign(&tx, &keys_b, &session_2);
+        assert!(
+            session_1
+                .receive_signatures(replayed, &tx, &prevouts, &context)
+                .is_err()
+        );
+        let previous = spend(Txid::from_byte_array([2; 32]));
+        let mut replayed = sign(&previous, &keys_b, &session_1);
+        replayed.txid = tx.compute_txid();
+        assert!(
+            session_1
+                .receive_signatures(replayed, &tx, &prevouts, &context)
+                .is_err()
+        );
+        let unbound = LeafSignatures {
+            session_id: None,
+            ..received.clone()
+        };
+        assert!(
+            session_1
+                .receive_signatures(unbound, &tx, &prevouts, &context)
+                .is_err()
+        );
+        // So is a signature of someone who doesn't sign the leaf.
+        let outsider = sign(&tx, &keys_arbitrator, &session_1);
+        assert!(
+            session_1
+                .receive_signatures(outsider, &tx, &prevouts, &context)
+                .is_err()
+        );
+
+        // A second, different signature of the same signer conflicts with the first.
+        let mut conflicting = received;
+        conflicting.signatures = vec![SignerSignature {
+            npub: keys_b.public_key(),
+            signature: sign(&tx, &keys_a, &session_1).signatures[0].signature,
+        }];
+        assert!(session_1.add_signatures(conflicting).is_err());
+        assert_eq!(session_1.signatures[0].signatures.len(), 1);
+
+        // A batch is only added if every signature in it is valid.
+        let previous = spend(Txid::from_byte_array([2; 32]));
+        let mut replayed = sign(&previous, &keys_a, &session_1);
+        replayed.txid = tx.compute_txid();
+        let batch = vec![sign(&tx, &keys_b, &session_1), replayed];
+        assert!(
+            session_1
+                .receive_signature_batch(batch, &tx, &prevouts, &context)
+                .is_err()
+        );
+        assert_eq!(session_1.signatures[0].signatures.len(), 1);
+        let batch = vec![sign(&tx, &keys_a, &session_1)];
+        assert!(
+            session_1
+                .receive_signature_batch(batch, &tx, &prevouts, &context)
+                .unwrap()
+        );
+        assert_eq!(session_1.signatures[0].signatures.len(), 2);
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn funded_sessions_converge() {
+        use crate::{scripts::EscrowScript, storage::MemoryStorage};
+
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        // The participants started from different offers of the same escrow.
+        let offer_1 = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let offer_2 = Offer {
+            expires_at: offer_1.expires_at + Duration::from_secs(60),
+            ..offer_1.clone()
+        };
+        let session = |offer: Offer| {
+            let (_, offer_event) = Handshake::offer(keys_a.secret_key(), offer, now()).unwrap();
+            let (handshake, _) =
+                Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).unwrap();
+            Session::new(handshake)
+        };
+        let (mut session_1, mut session_2) = (session(offer_1), session(offer_2));
+        assert_ne!(session_1.id().unwrap(), session_2.id().unwrap());
+        assert_eq!(session_1.canonical_id(), None);
+        assert!(session_1.clone().merge(session_2.clone()).is_err());
+
+        let funding_tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: Amount::from_sat(110_000),
+                script_pubkey: session_1
+                    .handshake
+                    .escrow_address()
+                    .unwrap()
+                    .script_pubkey(),
+            }],
+        };
+        session_1.record_funding(&funding_tx).unwrap();
+        session_2.record_funding(&funding_tx).unwrap();
+        let canonical_id = session_1.canonical_id().unwrap();
+        assert_eq!(session_2.canonical_id(), Some(canonical_id));
+        assert_eq!(
+            canonical_id,
+            SessionId::funded(
+                &funding_tx.output[0].script_pubkey,
+                OutPoint::new(funding_tx.compute_txid(), 0)
+            )
+        );
+        assert_ne!(canonical_id, session_1.id().unwrap());
+
+        // Each participant signed in their own session, the lookup merges them.
+        let spend = Txid::from_byte_array([1; 32]);
+        session_1
+            .add_signatures(LeafSignatures::new(spend, 0, EscrowScript::A))
+            .unwrap();
+        session_2
+            .add_signatures(LeafSignatures::new(spend, 0, EscrowScript::B))
+            .unwrap();
+        let storage = MemoryStorage::default();
+        session_1.save(&storage).unwrap();
+        session_2.save(&storage).unwrap();
+        let merged = Session::find_funded(&storage, &canonical_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.id().unwrap(), session_1.id().unwrap());
+        assert_eq!(merged.signatures.len(), 2);
+        assert!(
+            merged
+                .signatures
+                .iter()
+                .all(|s| s.session_id == merged.id().ok())
+        );
+        assert_eq!(merged.funding, session_1.funding);
+        assert!(
+            Session::find_funded(&storage, &session_1.id().unwrap())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn invalid_messages_are_rejected() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+
+        // Arbitrator without timelock.
+        let mut invalid = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        invalid.timelock_duration = None;
+        assert!(Handshake::offer(keys_a.secret_key(), invalid, now()).is_err());
+
+        // Offer signed by someone else than the offerer.
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        assert!(offer.to_event(keys_b.secret_key()).is_err());
+
+        // Offer addressed to another counterparty.
+        let addressed = Offer {
+            counterparty: Some(keys_arbitrator.public_key()),
+            arbitrator: None,
+            timelock_duration: None,
+            ..offer.clone()
+        };
+        let (_, offer_event) = Handshake::offer(keys_a.secret_key(), addressed, now()).unwrap();
+        assert!(Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).is_err());
+
+        // Tampered escrow address.
+        let (handshake_a, offer_event) =
+            Handshake::offer(keys_a.secret_key(), offer.clone(), now()).unwrap();
+        let mut acceptance =
+            Acceptance::new(offer_event.id, &offer, keys_b.public_key(), None).unwrap();
+        acceptance.escrow_address = acceptance.resolution_address.clone();
+        let acceptance_event = acceptance.to_event(keys_b.secret_key(), &offer).unwrap();
+        assert!(matches!(
+            handshake_a.receive(&acceptance_event, now()),
+            Err(Error::Protocol(_))
+        ));
+
+        // Wrong event kind.
+        assert!(Acceptance::from_event(&offer_event).is_err());
+
+        // Messages are signed as canonical JSON.
+        for content in [&offer_event.content, &acceptance_event.content] {
+            let value = canonical::decode::<serde_json::Value>(content).unwrap();
+            assert_eq!(&canonical::encode(&value).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn expired_offers_are_rejected() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let expired_at = offer.expires_at + Duration::from_secs(1);
+        let created_at = offer.expires_at - DEFAULT_OFFER_VALIDITY;
+        assert_eq!(
+            offer.remaining_validity(created_at),
+            Some(DEFAULT_OFFER_VALIDITY)
+        );
+        assert_eq!(offer.remaining_validity(expired_at), None);
+        assert!(Handshake::offer(keys_a.secret_key(), offer.clone(), expired_at).is_err());
+
+        // Expiry is published with the offer.
+        let (handshake_a, offer_event) =
+            Handshake::offer(keys_a.secret_key(), offer.clone(), now()).unwrap();
+        let expiration = offer.expires_at.to_string();
+        assert!(
+            offer_event
+                .tags
+                .iter()
+                .any(|tag| tag.as_slice() == ["expiration", expiration.as_str()])
+        );
+        assert!(matches!(
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, expired_at),
+            Err(Error::Expired(_))
+        ));
+
+        // Acceptance created in time but delivered too late.
+        let (_, acceptance_event) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).unwrap();
+        let too_late = offer.expires_at + ACCEPTANCE_GRACE_PERIOD + Duration::from_secs(1);
+        assert!(
+            handshake_a
+                .clone()
+                .receive(&acceptance_event, too_late)
+                .is_err()
+        );
+
+        // Pending negotiations expire, agreed ones don't.
+        let handshake_a = handshake_a.expire(expired_at);
+        assert!(matches!(handshake_a, Handshake::Expired { .. }));
+        assert!(handshake_a.receive(&acceptance_event, now()).is_err());
+        let (handshake_b, _) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).unwrap();
+        assert!(handshake_b.expire(expired_at).escrow_address().is_some());
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn stored_offers_expire_after_grace_period() {
+        use crate::storage::MemoryStorage;
+
+        let keys_a = Keys::generate();
+        let offer = offer(keys_a.public_key(), None);
+        let (handshake, _) = Handshake::offer(keys_a.secret_key(), offer.clone(), now()).unwrap();
+        let storage = MemoryStorage::default();
+        Session::new(handshake).save(&storage).unwrap();
+        let id = offer.session_id().unwrap();
+
+        let expired_at = offer.expires_at + Duration::from_secs(1);
+        assert_eq!(Session::expire_offers(&storage, expired_at).unwrap(), 0);
+        let too_late = expired_at + ACCEPTANCE_GRACE_PERIOD;
+        assert_eq!(Session::expire_offers(&storage, too_late).unwrap(), 1);
+        let session = Session::load(&storage, &id).unwrap().unwrap();
+        assert!(matches!(session.handshake, Handshake::Expired { .. }));
+        assert_eq!(Session::expire_offers(&storage, too_late).unwrap(), 0);
+    }
+
+    #[test]
+    fn both_parties_build_same_escrow_tx() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer = Offer {
+            lock_time_height: Some(850_000),
+            ..offer(keys_a.public_key(), Some(keys_arbitrator.public_key()))
+        };
+        let (_, offer_event) = Handshake::offer(keys_a.secret_key(), offer.clone(), now()).unwrap();
+        let received = Offer::from_event(&offer_event).unwrap();
+        assert_eq!(received.lock_time_height, Some(850_000));
+
+        let funding_txid = "602ae1accd9626bde16d19cbe8663cbe37a4e95839d0cddb10b84dcc82f07799"
+            .parse::<Txid>()
+            .unwrap();
+        let fee = Amount::from_sat(1_000);
+        let tx_a = offer
+            .escrow_tx(&keys_b.public_key(), None, funding_txid, fee)
+            .unwrap();
+        let tx_b = received
+            .escrow_tx(&keys_b.public_key(), None, funding_txid, fee)
+            .unwrap();
+        assert_eq!(tx_a, tx_b);
+        assert_eq!(
+            tx_a.lock_time,
+            absolute::LockTime::from_height(850_000).unwrap()
+        );
+
+        let invalid = Offer {
+            lock_time_height: Some(500_000_000),
+            ..offer
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn fiat_offer_locks_amounts_at_acceptance() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let quoted = Price {
+            currency: Currency::Brl,
+            cents_per_btc: 50_000_000,
+            provider: PriceProvider::CoinGecko,
+            time: now(),
+        };
+        let reais = |amount| FiatAmount::parse(Currency::Brl, amount).unwrap();
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()))
+            .in_fiat(reais("500"), reais("50"), quoted)
+            .unwrap();
+        assert_eq!(offer.amount_buyer, Amount::from_sat(100_000));
+        let tampered = Offer {
+            amount_buyer: Amount::from_sat(1_000),
+            ..offer.clone()
+        };
+        assert!(tampered.validate().is_err());
+
+        let (handshake_a, offer_event) =
+            Handshake::offer(keys_a.secret_key(), offer.clone(), now()).unwrap();
+        assert_eq!(handshake_a.price(), Some(&quoted));
+
+        // Fiat offers can't be accepted without a price, nor at a price far from the offer's.
+        assert!(Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).is_err());
+        let price = |cents_per_btc| Price {
+            cents_per_btc,
+            ..quoted
+        };
+        assert!(
+            Handshake::accept(
+                keys_b.secret_key(),
+                &offer_event,
+                Some(price(40_000_000)),
+                now()
+            )
+            .is_err()
+        );
+
+        // The price at acceptance locks the amounts on both sides.
+        let (handshake_b, acceptance_event) = Handshake::accept(
+            keys_b.secret_key(),
+            &offer_event,
+            Some(price(51_000_000)),
+            now(),
+        )
+        .unwrap();
+        let handshake_a = handshake_a.receive(&acceptance_event, now()).unwrap();
+        let locked = (Amount::from_sat(98_039), Amount::from_sat(9_804));
+        assert_eq!(handshake_a.amounts().unwrap(), locked);
+        assert_eq!(handshake_b.amounts().unwrap(), locked);
+        let fee = Amount::from_sat(1_000);
+        let acceptor = keys_b.public_key();
+        assert!(
+            offer
+                .escrow_tx(&acceptor, None, Txid::all_zeros(), fee)
+                .is_err()
+        );
+        let tx = offer
+            .escrow_tx(&acceptor, handshake_b.price(), Txid::all_zeros(), fee)
+            .unwrap();
+        assert_eq!(
+            tx.output.iter().map(|output| output.value).sum::<Amount>() + fee,
+            locked.0 + locked.1
+        );
+
+        #[cfg(feature = "serde-types")]
+        {
+            let session = Session::new(handshake_a);
+            assert_eq!(
+                session.display_amount(locked.0),
+                "0.00098039 BTC (R$ 500.00)"
+            );
+        }
+    }
+
+    #[test]
+    fn script_template_versions() {
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let expected = offer.escrow_address(&keys_b.public_key()).unwrap();
+
+        // Offers predating script templates derive the same address.
+        let mut legacy = serde_json::to_value(&offer).unwrap();
+        legacy.as_object_mut().unwrap().remove("script_template");
+        let legacy: Offer = deserialize(&legacy.to_string()).unwrap();
+        assert_eq!(legacy.script_template().unwrap(), ScriptTemplate::V1);
+        assert_eq!(
+            legacy.escrow_address(&keys_b.public_key()).unwrap(),
+            expected
+        );
+
+        // Unknown templates are rejected instead of deriving another address.
+        let future = Offer {
+            script_template: u8::from(ScriptTemplate::V2) + 1,
+            ..offer
+        };
+        assert!(matches!(
+            future.validate(),
+            Err(Error::UnsupportedScriptTemplate(_))
+        ));
+        let future_event = EventBuilder::new(Kind::Custom(OFFER_KIND), serialize(&future).unwrap())
+            .tags([Tag::identifier(future.session_id().unwrap().to_string())])
+            .sign_with_keys(&keys_a)
+            .unwrap();
+        assert!(matches!(
+            Handshake::accept(keys_b.secret_key(), &future_event, None, now()),
+            Err(Error::UnsupportedScriptTemplate(_))
+        ));
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn min_confirmations() {
+        assert_eq!(default_min_confirmations(Amount::from_sat(110_000)), 1);
+        assert_eq!(default_min_confirmations(Amount::from_sat(20_000_000)), 3);
+        assert_eq!(
+            default_min_confirmations(Amount::from_btc(10.0).unwrap()),
+            6
+        );
+
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        assert_eq!(offer.min_confirmations(), 1);
+        for invalid in [0, MAX_MIN_CONFIRMATIONS + 1] {
+            let invalid = Offer {
+                min_confirmations: Some(invalid),
+                ..offer.clone()
+            };
+            assert!(invalid.validate().is_err());
+        }
+
+        let offer = Offer {
+            min_confirmations: Some(3),
+            ..offer
+        };
+        let (_, offer_event) = Handshake::offer(keys_a.secret_key(), offer, now()).unwrap();
+        let (handshake, _) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, now()).unwrap();
+        let mut session = Session::new(handshake);
+        assert_eq!(session.min_confirmations(), 3);
+        assert!(session.ensure_resolvable(2).is_err());
+        session.ensure_resolvable(3).unwrap();
+
+        // Nothing is signed before the funding has the required confirmations.
+        let context = session.escrow_context().unwrap();
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(109_000),
+                script_pubkey: npub_to_address(&keys_a.public_key(), Network::Regtest)
+                    .unwrap()
+                    .script_pubkey(),
+            }],
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(110_000),
+            script_pubkey: context.address().script_pubkey(),
+        }];
+        let invariants = SigningInvariants::of_local_tx(&tx, prevouts, vec![None]);
+        let nsec = SecretNsec::from(keys_a.secret_key().clone());
+        assert!(
+            session
+                .sign(&tx, 0, EscrowScript::A, &invariants, &nsec, 2)
+                .is_err()
+        );
+        assert!(session.signatures.is_empty());
+        session
+            .sign(&tx, 0, EscrowScript::A, &invariants, &nsec, 3)
+            .unwrap();
+        assert_eq!(session.signatures[0].signatures.len(), 1);
+
+        let progress = session.funding_progress(WatchStatus::Funded {
+            confirmations: 1,
+            timelock_height: None,
+        });
+        assert_eq!(progress.confirmations, 1);
+        assert!(!progress.is_complete());
+    }
+}
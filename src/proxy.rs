@@ -0,0 +1,233 @@
+//! SOCKS5 proxies for chain and relay connections, to route escrow traffic over Tor.
+//!
+//! Proxies are configured as text, one per line:
+//! `host:port` applies to every [`Backend`],
+//! and `backend = host:port` overrides it for a single one,
+//! with `backend = direct` connecting to it without a proxy.
+//!
+//! Proxies only apply to native builds.
+//! Browsers don't let web pages open SOCKS connections,
+//! so in WASM the browser's own proxy settings apply instead.
+//! Natively, Esplora clients from [`create_client`](crate::esplora::create_client) use them.
+//! There is no native Electrum client or relay transport to route yet,
+//! so `electrum` and `relays` overrides are rejected rather than silently ignored.
+use std::{fmt, str::FromStr};
+
+use crate::error::Error;
+
+/// Default SOCKS5 port of a local Tor daemon.
+pub(crate) const TOR_PROXY: &str = "127.0.0.1:9050";
+
+/// Value of a per-backend override that disables the proxy.
+const DIRECT: &str = "direct";
+
+/// A SOCKS5 proxy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Socks5Proxy {
+    /// Host name or IP address, IPv6 addresses in brackets.
+    host: String,
+    /// Port.
+    port: u16,
+}
+
+impl Socks5Proxy {
+    /// Proxy URL for HTTP clients.
+    ///
+    /// Uses `socks5h` so host names, `.onion` included, are resolved by the proxy
+    /// and DNS queries don't leak outside of Tor.
+    pub(crate) fn url(&self) -> String {
+        format!("socks5h://{self}")
+    }
+}
+
+impl fmt::Display for Socks5Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+impl FromStr for Socks5Proxy {
+    type Err = Error;
+
+    /// Parses `host:port`, optionally prefixed by `socks5://` or `socks5h://`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::WrongInputs(format!("Invalid SOCKS5 proxy {s}"));
+        let address = s
+            .strip_prefix("socks5h://")
+            .or_else(|| s.strip_prefix("socks5://"))
+            .unwrap_or(s);
+        let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+        let port = port.parse().map_err(|_| invalid())?;
+        let is_ipv6 = host.starts_with('[') && host.ends_with(']');
+        if host.is_empty() || port == 0 || (host.contains(':') && !is_ipv6) {
+            return Err(invalid());
+        }
+        if host.contains(|c: char| c.is_whitespace() || c == '/' || c == '@') {
+            return Err(invalid());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+/// A kind of server scrow connects to natively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Backend {
+    /// Esplora HTTP APIs.
+    Esplora,
+}
+
+impl Backend {
+    /// Name of the backend in the proxy configuration.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Backend::Esplora => "esplora",
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    /// Parses a backend name, rejecting the backends scrow has no native client of yet.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "esplora" => Ok(Backend::Esplora),
+            "electrum" | "relays" => Err(Error::WrongInputs(format!(
+                "Proxy of {s} is not supported, there is no native {s} client yet"
+            ))),
+            _ => Err(Error::WrongInputs(format!("Unknown proxy backend {s}"))),
+        }
+    }
+}
+
+/// Proxies to connect to each [`Backend`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ProxySettings {
+    /// Proxy of the backends without an override.
+    default: Option<Socks5Proxy>,
+    /// Per-backend overrides, [`None`] to connect directly.
+    overrides: Vec<(Backend, Option<Socks5Proxy>)>,
+}
+
+impl ProxySettings {
+    /// Parses the proxy configuration, see the [module documentation](self).
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Errors on invalid proxies, unknown backends, and backends or defaults set twice.
+    pub(crate) fn parse(config: &str) -> Result<Self, Error> {
+        let mut settings = Self::default();
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((backend, proxy)) => {
+                    let backend = backend.trim().parse::<Backend>()?;
+                    if settings.overrides.iter().any(|(b, _)| *b == backend) {
+                        return Err(Error::WrongInputs(format!(
+                            "Proxy of {} set twice",
+                            backend.name()
+                        )));
+                    }
+                    let proxy = match proxy.trim() {
+                        DIRECT => None,
+                        proxy => Some(proxy.parse()?),
+                    };
+                    settings.overrides.push((backend, proxy));
+                }
+                None => {
+                    if settings.default.is_some() {
+                        return Err(Error::WrongInputs("Default proxy set twice".to_string()));
+                    }
+                    settings.default = Some(line.parse()?);
+                }
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Serializes the settings to the proxy configuration.
+    pub(crate) fn to_config(&self) -> String {
+        self.default
+            .iter()
+            .map(ToString::to_string)
+            .chain(self.overrides.iter().map(|(backend, proxy)| {
+                let proxy = proxy
+                    .as_ref()
+                    .map_or_else(|| DIRECT.to_string(), ToString::to_string);
+                format!("{} = {proxy}", backend.name())
+            }))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The proxy to connect to `backend` through, [`None`] to connect directly.
+    pub(crate) fn proxy(&self, backend: Backend) -> Option<&Socks5Proxy> {
+        match self.overrides.iter().find(|(b, _)| *b == backend) {
+            Some((_, proxy)) => proxy.as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_settings() {
+        assert_eq!(ProxySettings::parse("").unwrap(), ProxySettings::default());
+        assert_eq!(ProxySettings::default().proxy(Backend::Esplora), None);
+
+        let tor = TOR_PROXY.parse::<Socks5Proxy>().unwrap();
+        assert_eq!(tor.url(), "socks5h://127.0.0.1:9050");
+        assert_eq!(
+            "socks5h://[::1]:9150".parse::<Socks5Proxy>().unwrap().url(),
+            "socks5h://[::1]:9150"
+        );
+
+        let settings = ProxySettings::parse("# Tor\nsocks5://127.0.0.1:9050\n").unwrap();
+        assert_eq!(settings.proxy(Backend::Esplora), Some(&tor));
+        let direct = ProxySettings::parse("127.0.0.1:9050\nesplora = direct").unwrap();
+        assert_eq!(direct.proxy(Backend::Esplora), None);
+        let settings = ProxySettings::parse("esplora = localhost:9150").unwrap();
+        assert_eq!(
+            settings.proxy(Backend::Esplora).unwrap().to_string(),
+            "localhost:9150"
+        );
+        for settings in [settings, direct] {
+            assert_eq!(
+                ProxySettings::parse(&settings.to_config()).unwrap(),
+                settings
+            );
+        }
+        assert_eq!(
+            ProxySettings::parse(" 127.0.0.1:9050 \n# Tor")
+                .unwrap()
+                .to_config(),
+            "127.0.0.1:9050"
+        );
+
+        for invalid in [
+            "127.0.0.1",
+            "127.0.0.1:0",
+            "127.0.0.1:65536",
+            "::1:9050",
+            "http://127.0.0.1:9050",
+            "127.0.0.1:9050\n127.0.0.1:9150",
+            "esplora = direct\nesplora = 127.0.0.1:9050",
+            "bitcoind = 127.0.0.1:9050",
+            // Not routed natively yet, so rejected rather than ignored.
+            "relays = 127.0.0.1:9050",
+            "electrum = direct",
+        ] {
+            assert!(ProxySettings::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Delivery receipts of the protocol messages sent to the counterparty.
+//!
+//! Publishing an offer, an acceptance or signatures to relays doesn't tell whether
+//! the counterparty ever got them. Each message sent is tracked in the [`Outbox`]
+//! of its [`Session`]: [`DeliveryStatus::Sent`] until relays accept it,
+//! [`DeliveryStatus::SeenOnRelay`] then, and [`DeliveryStatus::Acked`] once the peer
+//! answered with a [`Receipt`] naming the event.
+//! Messages not acknowledged yet are published again with an exponential backoff,
+//! see [`Session::due_messages`].
+
+use std::time::Duration;
+
+use nostr::{
+    Event, EventBuilder, EventId, Filter, Keys, Kind, Tag, Timestamp, UnsignedEvent,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::participants,
+    canonical,
+    error::Error,
+    gift_wrap::{fetch_filter, receive_wrapped, wrap_to_recipients},
+    history::HistoryEvent,
+    protocol::{OFFER_KIND, Session, SessionId, check_rumor, check_session_tag},
+    secret::SecretNsec,
+};
+
+/// Version of the [`Receipt`] message.
+pub(crate) const RECEIPT_VERSION: u8 = 1;
+
+/// Nostr event kind of a [`Receipt`].
+pub(crate) const RECEIPT_KIND: u16 = 8_390;
+
+/// Delay before the first retry of a message not acknowledged.
+pub(crate) const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest delay between two retries.
+pub(crate) const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// A participant's acknowledgement of a protocol message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Receipt {
+    /// Message version, see [`RECEIPT_VERSION`].
+    pub(crate) version: u8,
+    /// Negotiation of the message.
+    pub(crate) session_id: SessionId,
+    /// The acknowledged event.
+    pub(crate) event_id: EventId,
+    /// Nostr public key of the participant acknowledging it.
+    pub(crate) npub: NostrPublicKey,
+}
+
+impl Receipt {
+    /// The receipt of the message `event_id` of the negotiation `session_id` by `npub`.
+    ///
+    /// Gift wrapped messages are acknowledged by the ID of their rumor,
+    /// which is the ID of the event their author sent.
+    pub(crate) fn new(session_id: SessionId, event_id: EventId, npub: NostrPublicKey) -> Self {
+        Self {
+            version: RECEIPT_VERSION,
+            session_id,
+            event_id,
+            npub,
+        }
+    }
+
+    /// Builds and signs the receipt [`Event`], addressed to the `author` of the message.
+    pub(crate) fn to_event(
+        &self,
+        nsec: &NostrSecretKey,
+        author: &NostrPublicKey,
+    ) -> Result<Event, Error> {
+        let keys = Keys::new(nsec.clone());
+        if keys.public_key() != self.npub {
+            return Err(Error::Protocol(
+                "Receipt must be signed by the acknowledging participant".to_string(),
+            ));
+        }
+        Ok(
+            EventBuilder::new(Kind::Custom(RECEIPT_KIND), canonical::encode(self)?)
+                .tags([
+                    Tag::identifier(self.session_id.to_string()),
+                    Tag::event(self.event_id),
+                    Tag::public_key(*author),
+                ])
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses a receipt rumor, checking it is from the participant it names.
+    pub(crate) fn from_rumor(rumor: &UnsignedEvent) -> Result<Self, Error> {
+        check_rumor(rumor, RECEIPT_KIND)?;
+        let receipt: Receipt = canonical::decode(&rumor.content)?;
+        if receipt.version != RECEIPT_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported receipt version {}",
+                receipt.version
+            )));
+        }
+        if receipt.npub != rumor.pubkey {
+            return Err(Error::Protocol(
+                "Receipt is not from the acknowledging participant".to_string(),
+            ));
+        }
+        check_session_tag(&rumor.tags, &receipt.session_id)?;
+        Ok(receipt)
+    }
+
+    /// The [`Filter`] of the receipts in the negotiation `session_id`.
+    pub(crate) fn filter(session_id: &SessionId) -> Filter {
+        Filter::new()
+            .kind(Kind::Custom(RECEIPT_KIND))
+            .identifier(session_id.to_string())
+    }
+}
+
+/// How far a message sent got.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DeliveryStatus {
+    /// Queued, no relay accepted it yet.
+    Sent,
+    /// Accepted by enough relays.
+    SeenOnRelay,
+    /// Acknowledged by the peer.
+    Acked,
+}
+
+/// A message sent by the session, tracked until acknowledged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct OutgoingMessage {
+    /// The signed event.
+    pub(crate) event: Event,
+    /// How far it got.
+    pub(crate) status: DeliveryStatus,
+    /// Times it was published.
+    pub(crate) attempts: u32,
+    /// When it is published again if not acknowledged by then.
+    pub(crate) retry_at: Timestamp,
+}
+
+/// The messages sent by a session, see [`OutgoingMessage`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Outbox {
+    messages: Vec<OutgoingMessage>,
+}
+
+impl Outbox {
+    /// Whether no message was sent.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Queues `event` to be published, right away. Queuing it again does nothing.
+    pub(crate) fn push(&mut self, event: Event, now: Timestamp) {
+        if self.status(&event.id).is_some() {
+            return;
+        }
+        self.messages.push(OutgoingMessage {
+            event,
+            status: DeliveryStatus::Sent,
+            attempts: 0,
+            retry_at: now,
+        });
+    }
+
+    /// The [`DeliveryStatus`] of the message `event_id`, if it was sent.
+    pub(crate) fn status(&self, event_id: &EventId) -> Option<DeliveryStatus> {
+        self.messages
+            .iter()
+            .find(|message| message.event.id == *event_id)
+            .map(|message| message.status)
+    }
+
+    /// The messages to publish at `now`: not acknowledged, and due for a retry.
+    pub(crate) fn due(&self, now: Timestamp) -> impl Iterator<Item = &Event> {
+        self.messages
+            .iter()
+            .filter(move |message| {
+                message.status != DeliveryStatus::Acked && message.retry_at <= now
+            })
+            .map(|message| &message.event)
+    }
+
+    /// Records an attempt to publish the message `event_id` at `now`,
+    /// scheduling the next one with an exponential backoff.
+    pub(crate) fn record_attempt(&mut self, event_id: &EventId, published: bool, now: Timestamp) {
+        let Some(message) = self.messages.iter_mut().find(|m| m.event.id == *event_id) else {
+            return;
+        };
+        message.attempts += 1;
+        if published && message.status == DeliveryStatus::Sent {
+            message.status = DeliveryStatus::SeenOnRelay;
+        }
+        let backoff = RETRY_DELAY
+            .saturating_mul(1_u32 << (message.attempts - 1).min(16))
+            .min(MAX_RETRY_DELAY);
+        message.retry_at = now + backoff;
+    }
+
+    /// Records the `receipt` of one of the messages, returning whether it acknowledged one.
+    fn acknowledge(&mut self, receipt: &Receipt) -> bool {
+        match self
+            .messages
+            .iter_mut()
+            .find(|message| message.event.id == receipt.event_id)
+        {
+            Some(message) if message.event.pubkey != receipt.npub => {
+                message.status = DeliveryStatus::Acked;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A message due to be published, see [`Session::due_messages`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct DueMessage {
+    /// ID of the message.
+    pub(crate) event_id: EventId,
+    /// How far it got so far.
+    pub(crate) status: DeliveryStatus,
+    /// The events to publish: the offer as is, or the message gift wrapped to each recipient.
+    pub(crate) events: Vec<Event>,
+}
+
+impl Session {
+    /// Queues `event`, sent by this session, to be published and tracked until acknowledged.
+    pub(crate) fn send(&mut self, event: Event, now: Timestamp) {
+        self.history.record(
+            HistoryEvent::MessageSent {
+                event_id: event.id,
+                kind: event.kind.as_u16(),
+            },
+            now,
+        );
+        self.outbox.push(event, now);
+    }
+
+    /// The [`DeliveryStatus`] of the message `event_id` sent by this session, if any.
+    pub(crate) fn delivery_status(&self, event_id: &EventId) -> Option<DeliveryStatus> {
+        self.outbox.status(event_id)
+    }
+
+    /// The messages of this session to publish at `now`, signed by `nsec`.
+    ///
+    /// Offers are published as is, to be discovered, and other messages are gift wrapped
+    /// to their recipients. Each attempt is then recorded with [`Session::record_published`].
+    pub(crate) fn due_messages(
+        &self,
+        nsec: &SecretNsec,
+        now: Timestamp,
+    ) -> Result<Vec<DueMessage>, Error> {
+        self.outbox
+            .due(now)
+            .map(|event| {
+                let events = if event.kind == Kind::Custom(OFFER_KIND) {
+                    vec![event.clone()]
+                } else {
+                    wrap_to_recipients(event, nsec, now)?
+                };
+                Ok(DueMessage {
+                    event_id: event.id,
+                    status: self
+                        .delivery_status(&event.id)
+                        .unwrap_or(DeliveryStatus::Sent),
+                    events,
+                })
+            })
+            .collect()
+    }
+
+    /// Records an attempt at `now` to publish the message `event_id`,
+    /// `published` if relays accepted it, scheduling the next one with a backoff.
+    pub(crate) fn record_published(&mut self, event_id: &EventId, published: bool, now: Timestamp) {
+        self.outbox.record_attempt(event_id, published, now);
+    }
+
+    /// The [`Filter`] of the gift wraps addressed to `npub` that may hold receipts of this
+    /// session not fetched yet, recording they are fetched at `now`.
+    pub(crate) fn receipt_filter(
+        &mut self,
+        npub: &NostrPublicKey,
+        now: Timestamp,
+    ) -> Result<Filter, Error> {
+        let filter = Receipt::filter(&self.id()?);
+        Ok(fetch_filter(npub, &filter, &mut self.received, now))
+    }
+
+    /// Records a `receipt` from the counterparty, returning whether it acknowledged a message.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the receipt is for another session or not from a participant.
+    pub(crate) fn receive_receipt(&mut self, receipt: &Receipt) -> Result<bool, Error> {
+        if receipt.session_id != self.id()? {
+            return Err(Error::Protocol(
+                "Receipt is for another session".to_string(),
+            ));
+        }
+        if !participants(&self.handshake).contains(&receipt.npub) {
+            return Err(Error::Protocol(
+                "Receipt is not from a participant".to_string(),
+            ));
+        }
+        Ok(self.outbox.acknowledge(receipt))
+    }
+}
+
+/// Receives the [`Receipt`]s of `session` from `gift_wraps` addressed to `nsec`,
+/// as fetched from the relays with [`fetch_filter`], and records them,
+/// returning the IDs of the messages newly acknowledged.
+///
+/// Receipts that fail to parse or verify are skipped, and the ones already received
+/// by the session are not received again.
+pub(crate) fn receive_receipts(
+    session: &mut Session,
+    gift_wraps: &[Event],
+    nsec: &SecretNsec,
+) -> Result<Vec<EventId>, Error> {
+    let filter = Receipt::filter(&session.id()?);
+    let rumors = receive_wrapped(gift_wraps, nsec, &filter, &mut session.received);
+    let mut acked = Vec::new();
+    for rumor in &rumors {
+        let Ok(receipt) = Receipt::from_rumor(rumor) else {
+            continue;
+        };
+        if session.receive_receipt(&receipt).unwrap_or(false) {
+            session.history.record(
+                HistoryEvent::MessageAcked {
+                    event_id: receipt.event_id,
+                    npub: receipt.npub,
+                },
+                rumor.created_at,
+            );
+            acked.push(receipt.event_id);
+        }
+    }
+    Ok(acked)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Amount;
+
+    use super::*;
+    use crate::{
+        gift_wrap::{unwrap, wrap},
+        protocol::{DEFAULT_OFFER_VALIDITY, Handshake, Offer, Role, offer},
+    };
+
+    #[test]
+    fn delivery_receipts() {
+        let buyer = Keys::generate();
+        let seller = Keys::generate();
+        let now = Timestamp::now();
+        let offer = Offer {
+            role: Role::Buyer,
+            amount_seller: Amount::ZERO,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(buyer.public_key(), None)
+        };
+        let (_, offer_event) = Handshake::offer(buyer.secret_key(), offer, now).unwrap();
+        let (agreed, acceptance_event) =
+            Handshake::accept(seller.secret_key(), &offer_event, None, now).unwrap();
+        let mut session = Session::new(agreed);
+        session.send(acceptance_event.clone(), now);
+        session.send(acceptance_event.clone(), now);
+        assert_eq!(session.outbox.due(now).count(), 1);
+        assert_eq!(
+            session.delivery_status(&acceptance_event.id),
+            Some(DeliveryStatus::Sent)
+        );
+
+        // A failed attempt is retried after the first delay, then the delays double.
+        assert_eq!(session.outbox.due(now).count(), 1);
+        session
+            .outbox
+            .record_attempt(&acceptance_event.id, false, now);
+        assert_eq!(session.outbox.due(now).count(), 0);
+        assert_eq!(session.outbox.due(now + RETRY_DELAY).count(), 1);
+        session
+            .outbox
+            .record_attempt(&acceptance_event.id, true, now);
+        assert_eq!(
+            session.delivery_status(&acceptance_event.id),
+            Some(DeliveryStatus::SeenOnRelay)
+        );
+        assert_eq!(session.outbox.due(now + RETRY_DELAY).count(), 0);
+        assert_eq!(session.outbox.due(now + RETRY_DELAY * 2).count(), 1);
+
+        // The buyer acknowledges the acceptance, which stops the retries.
+        let receipt = Receipt::new(
+            session.id().unwrap(),
+            acceptance_event.id,
+            buyer.public_key(),
+        );
+        assert!(
+            receipt
+                .to_event(seller.secret_key(), &seller.public_key())
+                .is_err()
+        );
+        let event = receipt
+            .to_event(buyer.secret_key(), &seller.public_key())
+            .unwrap();
+        let gift_wrap = wrap(
+            &event,
+            &SecretNsec::from(buyer.secret_key().clone()),
+            &seller.public_key(),
+            now,
+        )
+        .unwrap();
+        assert_eq!(
+            Receipt::from_rumor(
+                &unwrap(&gift_wrap, &SecretNsec::from(seller.secret_key().clone())).unwrap()
+            )
+            .unwrap(),
+            receipt
+        );
+        let outsider = Receipt {
+            npub: Keys::generate().public_key(),
+            ..receipt.clone()
+        };
+        assert!(session.receive_receipt(&outsider).is_err());
+        // The sender can't acknowledge their own message.
+        let own = Receipt {
+            npub: seller.public_key(),
+            ..receipt.clone()
+        };
+        assert!(!session.receive_receipt(&own).unwrap());
+        assert!(session.receive_receipt(&receipt).unwrap());
+        assert_eq!(
+            session.delivery_status(&acceptance_event.id),
+            Some(DeliveryStatus::Acked)
+        );
+        assert_eq!(session.outbox.due(now + MAX_RETRY_DELAY).count(), 0);
+
+        let json = session.to_json().unwrap();
+        assert_eq!(Session::from_json(&json).unwrap(), session);
+    }
+}
@@ -0,0 +1,197 @@
+//! BIP-39 recovery phrases of the identities generated in scrow.
+//!
+//! An identity generated by the [`Keystore`](crate::accounts::Keystore) comes with a
+//! [`RecoveryPhrase`] the user writes down, and its nsec is derived from the phrase
+//! with NIP-06, at `m/44'/1237'/<account>'/0/0`.
+//! Any NIP-06 wallet recovers the same identity from the phrase, and so does
+//! [`Keystore::recover`](crate::accounts::Keystore::recover) on another device.
+
+use std::fmt;
+
+use nostr::{Keys, bip39::Mnemonic, key::SecretKey as NostrSecretKey, nips::nip06::FromMnemonic};
+use zeroize::Zeroize;
+
+use crate::{error::Error, logging::REDACTED, secret::SecretNsec};
+
+/// Word counts of the recovery phrases scrow generates.
+pub(crate) const WORD_COUNTS: [usize; 2] = [12, 24];
+
+/// Derives the nsec of the NIP-06 `account` of the BIP-39 mnemonic `words`,
+/// with the optional BIP-39 `passphrase`, empty if none.
+///
+/// # Errors
+///
+/// Errors if `words` is not a valid English BIP-39 mnemonic.
+pub(crate) fn nsec_from_mnemonic(
+    words: &str,
+    passphrase: &str,
+    account: u32,
+) -> Result<SecretNsec, Error> {
+    RecoveryPhrase::parse(words)?.to_nsec(passphrase, account)
+}
+
+/// A BIP-39 mnemonic, wiped from memory when dropped.
+pub(crate) struct RecoveryPhrase(String);
+
+impl RecoveryPhrase {
+    /// Generates a random phrase of `words` words, 12 or 24.
+    pub(crate) fn generate(words: usize) -> Result<Self, Error> {
+        if !WORD_COUNTS.contains(&words) {
+            return Err(Error::WrongInputs(format!(
+                "Recovery phrases have 12 or 24 words, not {words}"
+            )));
+        }
+        // 32 bits of entropy per 3 words.
+        let mut entropy = NostrSecretKey::generate().secret_bytes();
+        let mnemonic = Mnemonic::from_entropy(&entropy[..words / 3 * 4]);
+        entropy.zeroize();
+        let mnemonic = mnemonic.map_err(|e| Error::WrongInputs(format!("Invalid entropy: {e}")))?;
+        Ok(Self(mnemonic.to_string()))
+    }
+
+    /// Parses a phrase typed by the user, whatever its case and spacing.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a word is not in the English BIP-39 list or the checksum doesn't match,
+    /// which catches most typos.
+    pub(crate) fn parse(phrase: &str) -> Result<Self, Error> {
+        let mut normalized = phrase
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let parsed = Mnemonic::parse_normalized(&normalized).map(|_| ());
+        if let Err(e) = parsed {
+            normalized.zeroize();
+            return Err(Error::WrongInputs(format!("Invalid recovery phrase: {e}")));
+        }
+        Ok(Self(normalized))
+    }
+
+    /// The words of the phrase, in order.
+    pub(crate) fn words(&self) -> impl Iterator<Item = &str> {
+        self.0.split(' ')
+    }
+
+    /// The nsec of the NIP-06 `account` of the phrase, with the optional BIP-39 `passphrase`.
+    pub(crate) fn to_nsec(&self, passphrase: &str, account: u32) -> Result<SecretNsec, Error> {
+        let keys = Keys::from_mnemonic_advanced(
+            self.0.as_str(),
+            Some(passphrase),
+            Some(account),
+            None,
+            None,
+        )
+        .map_err(|e| Error::WrongInputs(format!("Could not derive the nsec: {e}")))?;
+        Ok(SecretNsec::from(keys.secret_key().clone()))
+    }
+}
+
+impl Drop for RecoveryPhrase {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for RecoveryPhrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RecoveryPhrase").field(&REDACTED).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_nsec;
+
+    #[test]
+    fn recovery_phrase() {
+        // NIP-06 test vector.
+        let phrase = RecoveryPhrase::parse(
+            "  Leader monkey parrot ring guide accident before fence cannon height naive bean ",
+        )
+        .unwrap();
+        assert_eq!(phrase.words().count(), 12);
+        assert_eq!(
+            phrase.to_nsec("", 0).unwrap().public_key().to_hex(),
+            "17162c921dc4d2518f9a101db33695df1afb56ab82f5ff3e5da6eec3ca5cd917"
+        );
+        assert_ne!(
+            phrase.to_nsec("", 1).unwrap().public_key(),
+            phrase.to_nsec("", 0).unwrap().public_key()
+        );
+        assert_ne!(
+            phrase.to_nsec("extra", 0).unwrap().public_key(),
+            phrase.to_nsec("", 0).unwrap().public_key()
+        );
+        // A swapped word breaks the checksum.
+        assert!(
+            RecoveryPhrase::parse(
+                "monkey leader parrot ring guide accident before fence cannon height naive bean"
+            )
+            .is_err()
+        );
+        assert!(!format!("{phrase:?}").contains("leader"));
+
+        for words in WORD_COUNTS {
+            let generated = RecoveryPhrase::generate(words).unwrap();
+            assert_eq!(generated.words().count(), words);
+            let typed = generated.words().collect::<Vec<_>>().join("  ");
+            let recovered = RecoveryPhrase::parse(&typed).unwrap();
+            assert_eq!(
+                recovered.to_nsec("", 0).unwrap().public_key(),
+                generated.to_nsec("", 0).unwrap().public_key()
+            );
+        }
+        assert!(RecoveryPhrase::generate(15).is_err());
+    }
+
+    #[test]
+    fn nip06_vectors() {
+        let vectors = [
+            (
+                "leader monkey parrot ring guide accident before fence cannon height naive bean",
+                "7f7ff03d123792d6ac594bfa67bf6d0c0ab55b6b1fdb6249303fe861f1ccba9a",
+                "17162c921dc4d2518f9a101db33695df1afb56ab82f5ff3e5da6eec3ca5cd917",
+            ),
+            (
+                "what bleak badge arrange retreat wolf trade produce cricket blur garlic valid \
+                 proud rude strong choose busy staff weather area salt hollow arm fade",
+                "c15d739894c81a2fcfd3a2df85a0d2c0dbc47a280d092799f144d73d7ae78add",
+                "d41b22899549e1f3d335a31002cfd382174006e166d3e658e3a5eecdb6463573",
+            ),
+        ];
+        for (words, secret, public) in vectors {
+            let nsec = nsec_from_mnemonic(words, "", 0).unwrap();
+            assert_eq!(
+                nsec.with_nostr_secret_key(|secret_key| secret_key.to_secret_hex()),
+                secret
+            );
+            assert_eq!(nsec.public_key().to_hex(), public);
+        }
+        assert!(nsec_from_mnemonic("leader monkey parrot", "", 0).is_err());
+    }
+
+    #[test]
+    fn nsec_from_mnemonic_roundtrip() {
+        for words in WORD_COUNTS {
+            let phrase = RecoveryPhrase::generate(words).unwrap();
+            let typed = phrase.words().collect::<Vec<_>>().join(" ");
+            for (passphrase, account) in [("", 0), ("", 7), ("correct horse", 0)] {
+                let nsec = nsec_from_mnemonic(&typed, passphrase, account).unwrap();
+                assert_eq!(
+                    nsec.public_key(),
+                    phrase.to_nsec(passphrase, account).unwrap().public_key()
+                );
+                // The derived key goes through the `nsec` inputs as hex.
+                let hex = nsec.with_nostr_secret_key(|secret_key| secret_key.to_secret_hex());
+                assert_eq!(parse_nsec(&hex).unwrap().public_key(), nsec.public_key());
+            }
+            assert_ne!(
+                nsec_from_mnemonic(&typed, "", 0).unwrap().public_key(),
+                nsec_from_mnemonic(&typed, "", 1).unwrap().public_key()
+            );
+        }
+    }
+}
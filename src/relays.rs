@@ -0,0 +1,901 @@
+//! Nostr relay pool management.
+//!
+//! Keeps a configurable set of relays, tracks their health,
+//! and publishes with quorum semantics so that a single relay being down
+//! does not lose signature-exchange messages.
+//!
+//! Messages to each relay are spaced out by a [`RateLimit`] so publishing many session events
+//! doesn't get the client banned, and relays requiring NIP-42 authentication are answered
+//! by [`RelayAuth`] when the transport has keys to sign with.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, Tag};
+use serde_json::Value;
+
+use crate::{
+    error::Error,
+    runtime::{Instant, sleep},
+};
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web::WebSocketTransport;
+
+/// Relays used when none are configured.
+pub(crate) const DEFAULT_RELAYS: [&str; 3] = [
+    "wss://relay.damus.io",
+    "wss://nos.lol",
+    "wss://relay.primal.net",
+];
+
+/// Number of relays that must accept an event by default.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub(crate) const DEFAULT_QUORUM: usize = 2;
+
+/// Time to wait for a relay before considering it offline.
+#[cfg(target_arch = "wasm32")]
+pub(crate) const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Messages sent to a single relay by default, well below the limits of public relays.
+pub(crate) const DEFAULT_RATE_LIMIT: RateLimit = RateLimit {
+    burst: 10,
+    window: Duration::from_secs(10),
+};
+
+/// Prefix of the rejections of relays requiring NIP-42 authentication.
+const AUTH_REQUIRED_PREFIX: &str = "auth-required:";
+
+/// Parses and normalizes a relay URL.
+///
+/// Only `ws://` and `wss://` URLs are accepted. Trailing slashes are removed.
+pub(crate) fn parse_relay_url(url: &str) -> Result<String, Error> {
+    let url = url.trim().trim_end_matches('/');
+    let host = url
+        .strip_prefix("wss://")
+        .or_else(|| url.strip_prefix("ws://"))
+        .ok_or_else(|| Error::WrongInputs(format!("Relay URL must start with wss:// ({url})")))?;
+    if host.is_empty() || host.contains(char::is_whitespace) {
+        return Err(Error::WrongInputs(format!("Invalid relay URL ({url})")));
+    }
+    Ok(url.to_string())
+}
+
+/// Parses a list of relay URLs separated by newlines, commas or spaces.
+///
+/// Duplicates are removed, keeping the first occurrence.
+pub(crate) fn parse_relays(config: &str) -> Result<Vec<String>, Error> {
+    let mut relays = Vec::new();
+    for url in config
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|url| !url.is_empty())
+    {
+        let url = parse_relay_url(url)?;
+        if !relays.contains(&url) {
+            relays.push(url);
+        }
+    }
+    Ok(relays)
+}
+
+/// Health of a relay as of the last interaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub(crate) enum RelayStatus {
+    /// Not contacted yet.
+    #[default]
+    Unknown,
+
+    /// Last interaction succeeded.
+    Online,
+
+    /// Last interaction failed.
+    Offline,
+}
+
+/// A relay in a [`RelayPool`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Relay {
+    /// Normalized relay URL.
+    pub(crate) url: String,
+    /// Health as of the last interaction.
+    pub(crate) status: RelayStatus,
+    /// Round-trip latency measured by the last health check.
+    pub(crate) latency: Option<Duration>,
+    /// Consecutive failed interactions.
+    pub(crate) failures: u32,
+}
+
+impl Relay {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            status: RelayStatus::Unknown,
+            latency: None,
+            failures: 0,
+        }
+    }
+
+    fn record(&mut self, success: bool) {
+        if success {
+            self.status = RelayStatus::Online;
+            self.failures = 0;
+        } else {
+            self.status = RelayStatus::Offline;
+            self.failures = self.failures.saturating_add(1);
+        }
+    }
+}
+
+/// Message sent by a relay, as defined in NIP-01.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RelayMessage {
+    /// An event matching a subscription.
+    Event {
+        subscription_id: String,
+        event: Box<Event>,
+    },
+
+    /// Acceptance or rejection of a published event.
+    Ok {
+        event_id: EventId,
+        accepted: bool,
+        message: String,
+    },
+
+    /// End of the stored events of a subscription.
+    EndOfStoredEvents { subscription_id: String },
+
+    /// A subscription closed by the relay.
+    Closed {
+        subscription_id: String,
+        message: String,
+    },
+
+    /// A human-readable notice.
+    Notice { message: String },
+
+    /// A NIP-42 authentication challenge.
+    Auth { challenge: String },
+}
+
+/// Serializes a NIP-01 `EVENT` client message.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub(crate) fn event_message(event: &Event) -> String {
+    format!(r#"["EVENT",{}]"#, event.as_json())
+}
+
+/// Serializes a NIP-01 `REQ` client message.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub(crate) fn req_message(subscription_id: &str, filter: &Filter) -> String {
+    format!(
+        r#"[{},{},{}]"#,
+        Value::from("REQ"),
+        Value::from(subscription_id),
+        filter.as_json()
+    )
+}
+
+/// Serializes a NIP-42 `AUTH` client message.
+pub(crate) fn auth_message(event: &Event) -> String {
+    format!(r#"["AUTH",{}]"#, event.as_json())
+}
+
+/// Parses a NIP-01 relay message.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub(crate) fn parse_relay_message(message: &str) -> Result<RelayMessage, Error> {
+    let malformed = || Error::Relay(format!("Malformed relay message: {message}"));
+    let value = serde_json::from_str::<Value>(message).map_err(|_| malformed())?;
+    let array = value.as_array().ok_or_else(malformed)?;
+    let text = |index: usize| {
+        array
+            .get(index)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(malformed)
+    };
+    match array.first().and_then(Value::as_str) {
+        Some("EVENT") => {
+            let event = array.get(2).ok_or_else(malformed)?;
+            Ok(RelayMessage::Event {
+                subscription_id: text(1)?,
+                event: Box::new(Event::from_json(event.to_string()).map_err(|_| malformed())?),
+            })
+        }
+        Some("OK") => Ok(RelayMessage::Ok {
+            event_id: EventId::from_hex(&text(1)?).map_err(|_| malformed())?,
+            accepted: array
+                .get(2)
+                .and_then(Value::as_bool)
+                .ok_or_else(malformed)?,
+            message: text(3).unwrap_or_default(),
+        }),
+        Some("EOSE") => Ok(RelayMessage::EndOfStoredEvents {
+            subscription_id: text(1)?,
+        }),
+        Some("CLOSED") => Ok(RelayMessage::Closed {
+            subscription_id: text(1)?,
+            message: text(2).unwrap_or_default(),
+        }),
+        Some("NOTICE") => Ok(RelayMessage::Notice { message: text(1)? }),
+        Some("AUTH") => Ok(RelayMessage::Auth {
+            challenge: text(1)?,
+        }),
+        _ => Err(malformed()),
+    }
+}
+
+/// Signs the NIP-42 authentication [`Event`] answering the `challenge` of the relay `url`.
+pub(crate) fn auth_event(keys: &Keys, url: &str, challenge: &str) -> Result<Event, Error> {
+    let tag = |tag: [&str; 2]| Tag::parse(tag).map_err(|e| Error::Relay(format!("{url}: {e}")));
+    Ok(EventBuilder::new(Kind::Authentication, "")
+        .tags([tag(["relay", url])?, tag(["challenge", challenge])?])
+        .sign_with_keys(keys)?)
+}
+
+/// What to do with a relay message, as decided by [`RelayAuth::step`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AuthStep {
+    /// Send this client message to the relay.
+    Send(String),
+
+    /// Send the original request again, now that the client is authenticated.
+    Resend,
+
+    /// Wait for the next message.
+    Skip,
+
+    /// Hand the message to the exchange.
+    Handle(RelayMessage),
+}
+
+/// NIP-42 authentication over a single relay connection.
+///
+/// Answers challenges with the transport's keys, holds back the `auth-required:`
+/// rejections of the original request, and asks to resend it once authenticated.
+/// Without keys every message is handed to the exchange as is.
+#[derive(Debug)]
+pub(crate) struct RelayAuth<'a> {
+    keys: Option<&'a Keys>,
+    url: &'a str,
+    /// Authentication event waiting for the relay's `OK`.
+    pending: Option<EventId>,
+    authenticated: bool,
+    /// Whether the original request was rejected for lack of authentication.
+    retry: bool,
+}
+
+impl<'a> RelayAuth<'a> {
+    /// Starts authenticating to the relay `url` with `keys`, if any.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) fn new(keys: Option<&'a Keys>, url: &'a str) -> Self {
+        Self {
+            keys,
+            url,
+            pending: None,
+            authenticated: false,
+            retry: false,
+        }
+    }
+
+    /// Decides what to do with a `message` of the relay.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the relay rejects the authentication.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) fn step(&mut self, message: RelayMessage) -> Result<AuthStep, Error> {
+        let Some(keys) = self.keys else {
+            return Ok(AuthStep::Handle(message));
+        };
+        match message {
+            RelayMessage::Auth { challenge } => {
+                let event = auth_event(keys, self.url, &challenge)?;
+                self.pending = Some(event.id);
+                Ok(AuthStep::Send(auth_message(&event)))
+            }
+            RelayMessage::Ok {
+                event_id,
+                accepted,
+                message,
+            } if self.pending == Some(event_id) => {
+                self.pending = None;
+                if !accepted {
+                    return Err(Error::Relay(format!(
+                        "{}: authentication failed: {message}",
+                        self.url
+                    )));
+                }
+                self.authenticated = true;
+                Ok(if std::mem::take(&mut self.retry) {
+                    AuthStep::Resend
+                } else {
+                    AuthStep::Skip
+                })
+            }
+            RelayMessage::Ok {
+                accepted: false,
+                message,
+                ..
+            }
+            | RelayMessage::Closed { message, .. }
+                if !self.authenticated && message.starts_with(AUTH_REQUIRED_PREFIX) =>
+            {
+                self.retry = true;
+                Ok(AuthStep::Skip)
+            }
+            message => Ok(AuthStep::Handle(message)),
+        }
+    }
+}
+
+/// Maximum number of messages sent to a relay within a sliding window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RateLimit {
+    /// Messages allowed within `window`.
+    pub(crate) burst: usize,
+    /// Length of the window.
+    pub(crate) window: Duration,
+}
+
+/// Spaces out the messages sent to each relay according to a [`RateLimit`].
+#[derive(Debug, Default)]
+struct RateLimiter {
+    limit: Option<RateLimit>,
+    /// When the last messages were sent to each relay, oldest first.
+    sent: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// Time to wait before sending another message to `url`.
+    fn delay(&self, url: &str) -> Duration {
+        let Some(limit) = self.limit else {
+            return Duration::ZERO;
+        };
+        match self.sent.get(url) {
+            Some(sent) if sent.len() >= limit.burst.max(1) => {
+                sent.front().map_or(Duration::ZERO, |oldest| {
+                    limit.window.saturating_sub(oldest.elapsed())
+                })
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Waits until a message can be sent to `url`, then records it.
+    async fn acquire(&mut self, url: &str) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        let delay = self.delay(url);
+        if !delay.is_zero() {
+            #[cfg(debug_assertions)]
+            trace!(%url, ?delay, "rate limiting relay");
+            sleep(delay).await;
+        }
+        let sent = self.sent.entry(url.to_string()).or_default();
+        while sent.len() >= limit.burst.max(1) {
+            sent.pop_front();
+        }
+        sent.push_back(Instant::now());
+    }
+}
+
+/// Connection to individual relays.
+///
+/// Implemented over WebSockets in the browser and by mocks in tests.
+pub(crate) trait RelayTransport {
+    /// Measures the round-trip latency to a relay.
+    async fn ping(&self, url: &str) -> Result<Duration, Error>;
+
+    /// Publishes an [`Event`] to a relay, returning once the relay accepted it.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    async fn publish(&self, url: &str, event: &Event) -> Result<(), Error>;
+
+    /// Fetches the stored events matching a [`Filter`] from a relay.
+    async fn fetch(&self, url: &str, filter: &Filter) -> Result<Vec<Event>, Error>;
+}
+
+/// Result of publishing an [`Event`] to a [`RelayPool`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub(crate) struct PublishOutcome {
+    /// Relays that accepted the event.
+    pub(crate) accepted: Vec<String>,
+    /// Relays that rejected the event or could not be reached, with the reason.
+    pub(crate) rejected: Vec<(String, String)>,
+}
+
+/// A configurable set of relays sharing a [`RelayTransport`].
+#[derive(Debug)]
+pub(crate) struct RelayPool<T> {
+    transport: T,
+    relays: Vec<Relay>,
+    limiter: RateLimiter,
+}
+
+impl<T: RelayTransport> RelayPool<T> {
+    /// Creates a pool from already parsed relay URLs, limited to [`DEFAULT_RATE_LIMIT`].
+    pub(crate) fn new(transport: T, urls: Vec<String>) -> Self {
+        Self {
+            transport,
+            relays: urls.into_iter().map(Relay::new).collect(),
+            limiter: RateLimiter {
+                limit: Some(DEFAULT_RATE_LIMIT),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Creates a pool from a relay list as parsed by [`parse_relays`].
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) fn from_config(transport: T, config: &str) -> Result<Self, Error> {
+        Ok(Self::new(transport, parse_relays(config)?))
+    }
+
+    /// Relays in the pool, with their health.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) fn relays(&self) -> &[Relay] {
+        &self.relays
+    }
+
+    /// Pings every relay, updating their status and latency.
+    ///
+    /// Returns the number of relays online.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) async fn check_health(&mut self) -> usize {
+        for relay in self.relays.iter_mut() {
+            self.limiter.acquire(&relay.url).await;
+            let result = self.transport.ping(&relay.url).await;
+            #[cfg(debug_assertions)]
+            trace!(url = %relay.url, ?result, "relay health check");
+            relay.latency = result.as_ref().ok().copied();
+            relay.record(result.is_ok());
+        }
+        self.relays
+            .iter()
+            .filter(|relay| relay.status == RelayStatus::Online)
+            .count()
+    }
+
+    /// Publishes an [`Event`] to every relay.
+    ///
+    /// The `quorum` is capped at the number of relays in the pool.
+    ///
+    /// # Errors
+    ///
+    /// Errors if fewer than `quorum` relays accepted the event.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) async fn publish(
+        &mut self,
+        event: &Event,
+        quorum: usize,
+    ) -> Result<PublishOutcome, Error> {
+        let required = quorum.clamp(1, self.relays.len().max(1));
+        let mut outcome = PublishOutcome::default();
+        for relay in self.relays.iter_mut() {
+            self.limiter.acquire(&relay.url).await;
+            let result = self.transport.publish(&relay.url, event).await;
+            relay.record(result.is_ok());
+            match result {
+                Ok(()) => outcome.accepted.push(relay.url.clone()),
+                Err(e) => outcome.rejected.push((relay.url.clone(), e.to_string())),
+            }
+        }
+        #[cfg(debug_assertions)]
+        trace!(event_id = %event.id, ?outcome, "published event");
+        if outcome.accepted.len() < required {
+            return Err(Error::RelayQuorum {
+                accepted: outcome.accepted.len(),
+                required,
+            });
+        }
+        Ok(outcome)
+    }
+
+    /// Fetches the events matching a [`Filter`] from every relay.
+    ///
+    /// Events are verified, deduplicated and sorted by creation time.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no relay could be reached.
+    pub(crate) async fn fetch(&mut self, filter: &Filter) -> Result<Vec<Event>, Error> {
+        let mut ids = HashSet::new();
+        let mut events = Vec::new();
+        let mut reached = 0;
+        for relay in self.relays.iter_mut() {
+            self.limiter.acquire(&relay.url).await;
+            let result = self.transport.fetch(&relay.url, filter).await;
+            relay.record(result.is_ok());
+            let Ok(found) = result else { continue };
+            reached += 1;
+            events.extend(found.into_iter().filter(|event| {
+                filter.match_event(event) && event.verify().is_ok() && ids.insert(event.id)
+            }));
+        }
+        if reached == 0 {
+            return Err(Error::Relay("No relay could be reached".to_string()));
+        }
+        events.sort_by_key(|event| event.created_at);
+        Ok(events)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    //! [`RelayTransport`] over browser WebSockets.
+
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use gloo_net::websocket::{Message, futures::WebSocket};
+    use nostr::{Event, Filter, Keys};
+
+    use super::{
+        AuthStep, RELAY_TIMEOUT, RelayAuth, RelayMessage, RelayTransport, event_message,
+        parse_relay_message, req_message,
+    };
+    use crate::{
+        error::Error,
+        runtime::{Instant, timeout},
+    };
+
+    /// Subscription ID used for one-shot requests.
+    const SUBSCRIPTION_ID: &str = "scrow";
+
+    /// [`RelayTransport`] opening one browser WebSocket per request.
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct WebSocketTransport {
+        /// Keys answering NIP-42 challenges, if any.
+        auth: Option<Keys>,
+    }
+
+    impl WebSocketTransport {
+        /// A transport authenticating with `keys` to relays that require it.
+        pub(crate) fn with_auth(keys: Keys) -> Self {
+            Self { auth: Some(keys) }
+        }
+
+        /// Sends `request` and feeds every relay message to `handle`
+        /// until it returns a result or [`RELAY_TIMEOUT`] elapses.
+        ///
+        /// NIP-42 challenges are answered along the way, see [`RelayAuth`].
+        async fn exchange<R>(
+            &self,
+            url: &str,
+            request: String,
+            mut handle: impl FnMut(RelayMessage) -> Option<Result<R, Error>>,
+        ) -> Result<R, Error> {
+            let relay_error = |e: &dyn std::fmt::Display| Error::Relay(format!("{url}: {e}"));
+            let exchange = async {
+                let mut socket = WebSocket::open(url).map_err(|e| relay_error(&e))?;
+                socket
+                    .send(Message::Text(request.clone()))
+                    .await
+                    .map_err(|e| relay_error(&e))?;
+                let mut auth = RelayAuth::new(self.auth.as_ref(), url);
+                while let Some(message) = socket.next().await {
+                    let Message::Text(text) = message.map_err(|e| relay_error(&e))? else {
+                        continue;
+                    };
+                    // Skip messages this client does not handle.
+                    let Ok(message) = parse_relay_message(&text) else {
+                        continue;
+                    };
+                    let reply = match auth.step(message)? {
+                        AuthStep::Send(reply) => reply,
+                        AuthStep::Resend => request.clone(),
+                        AuthStep::Skip => continue,
+                        AuthStep::Handle(message) => match handle(message) {
+                            Some(result) => return result,
+                            None => continue,
+                        },
+                    };
+                    socket
+                        .send(Message::Text(reply))
+                        .await
+                        .map_err(|e| relay_error(&e))?;
+                }
+                Err(relay_error(&"connection closed"))
+            };
+            timeout(RELAY_TIMEOUT, exchange)
+                .await
+                .unwrap_or_else(|| Err(relay_error(&"timed out")))
+        }
+    }
+
+    impl RelayTransport for WebSocketTransport {
+        async fn ping(&self, url: &str) -> Result<Duration, Error> {
+            let start = Instant::now();
+            let request = req_message(SUBSCRIPTION_ID, &Filter::new().limit(0));
+            self.exchange(url, request, |message| match message {
+                RelayMessage::EndOfStoredEvents { .. }
+                | RelayMessage::Closed { .. }
+                | RelayMessage::Notice { .. } => Some(Ok(())),
+                _ => None,
+            })
+            .await?;
+            Ok(start.elapsed())
+        }
+
+        async fn publish(&self, url: &str, event: &Event) -> Result<(), Error> {
+            self.exchange(url, event_message(event), |message| match message {
+                RelayMessage::Ok {
+                    event_id,
+                    accepted,
+                    message,
+                } if event_id == event.id => Some(if accepted {
+                    Ok(())
+                } else {
+                    Err(Error::Relay(format!("{url}: {message}")))
+                }),
+                _ => None,
+            })
+            .await
+        }
+
+        async fn fetch(&self, url: &str, filter: &Filter) -> Result<Vec<Event>, Error> {
+            let mut events = Vec::new();
+            self.exchange(
+                url,
+                req_message(SUBSCRIPTION_ID, filter),
+                |message| match message {
+                    RelayMessage::Event { event, .. } => {
+                        events.push(*event);
+                        None
+                    }
+                    RelayMessage::EndOfStoredEvents { .. } => Some(Ok(())),
+                    RelayMessage::Closed { message, .. } => {
+                        Some(Err(Error::Relay(format!("{url}: {message}"))))
+                    }
+                    _ => None,
+                },
+            )
+            .await?;
+            Ok(events)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap};
+
+    use nostr::{EventBuilder, Keys};
+
+    use super::*;
+
+    /// In-memory [`RelayTransport`] where some relays are down.
+    #[derive(Debug, Default)]
+    struct MockTransport {
+        down: Vec<String>,
+        stored: RefCell<HashMap<String, Vec<Event>>>,
+    }
+
+    impl MockTransport {
+        fn check(&self, url: &str) -> Result<(), Error> {
+            if self.down.iter().any(|down| down == url) {
+                return Err(Error::Relay(format!("{url}: timed out")));
+            }
+            Ok(())
+        }
+    }
+
+    impl RelayTransport for MockTransport {
+        async fn ping(&self, url: &str) -> Result<Duration, Error> {
+            self.check(url)?;
+            Ok(Duration::from_millis(url.len() as u64))
+        }
+
+        async fn publish(&self, url: &str, event: &Event) -> Result<(), Error> {
+            self.check(url)?;
+            self.stored
+                .borrow_mut()
+                .entry(url.to_string())
+                .or_default()
+                .push(event.clone());
+            Ok(())
+        }
+
+        async fn fetch(&self, url: &str, _filter: &Filter) -> Result<Vec<Event>, Error> {
+            self.check(url)?;
+            Ok(self.stored.borrow().get(url).cloned().unwrap_or_default())
+        }
+    }
+
+    fn mock_pool(down: &[&str]) -> RelayPool<MockTransport> {
+        let transport = MockTransport {
+            down: down.iter().map(|url| url.to_string()).collect(),
+            ..Default::default()
+        };
+        RelayPool::from_config(transport, &DEFAULT_RELAYS.join("\n")).unwrap()
+    }
+
+    #[test]
+    fn relay_config() {
+        let relays = parse_relays("wss://nos.lol/, wss://relay.damus.io\n\nwss://nos.lol").unwrap();
+        assert_eq!(relays, vec!["wss://nos.lol", "wss://relay.damus.io"]);
+        assert!(parse_relays("https://nos.lol").is_err());
+        assert!(parse_relay_url("wss://").is_err());
+
+        let pool = mock_pool(&[]);
+        let urls = pool.relays().iter().map(|relay| relay.url.as_str());
+        assert_eq!(urls.collect::<Vec<_>>(), DEFAULT_RELAYS);
+    }
+
+    #[tokio::test]
+    async fn publish_with_quorum() {
+        let event = EventBuilder::text_note("signature")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+
+        // One relay down still reaches the default quorum.
+        let mut pool = mock_pool(&["wss://nos.lol"]);
+        assert_eq!(pool.check_health().await, 2);
+        assert_eq!(pool.relays()[1].status, RelayStatus::Offline);
+        let outcome = pool.publish(&event, DEFAULT_QUORUM).await.unwrap();
+        assert_eq!(outcome.accepted.len(), 2);
+        assert_eq!(outcome.rejected[0].0, "wss://nos.lol");
+
+        // Duplicates across relays are returned once.
+        let events = pool.fetch(&Filter::new()).await.unwrap();
+        assert_eq!(events, vec![event.clone()]);
+
+        // Two relays down do not.
+        let mut pool = mock_pool(&["wss://nos.lol", "wss://relay.damus.io"]);
+        assert!(matches!(
+            pool.publish(&event, DEFAULT_QUORUM).await,
+            Err(Error::RelayQuorum {
+                accepted: 1,
+                required: 2
+            })
+        ));
+        assert_eq!(pool.relays()[0].failures, 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let limit = RateLimit {
+            burst: 2,
+            window: Duration::from_secs(3_600),
+        };
+        let mut pool = mock_pool(&[]);
+        pool.limiter.limit = Some(limit);
+        pool.limiter.acquire("wss://nos.lol").await;
+        assert_eq!(pool.limiter.delay("wss://nos.lol"), Duration::ZERO);
+        pool.limiter.acquire("wss://nos.lol").await;
+        assert!(pool.limiter.delay("wss://nos.lol") > Duration::from_secs(3_000));
+        assert_eq!(pool.limiter.delay("wss://relay.damus.io"), Duration::ZERO);
+
+        let limit = RateLimit {
+            burst: 1,
+            window: Duration::from_millis(20),
+        };
+        let mut pool = mock_pool(&[]);
+        pool.limiter.limit = Some(limit);
+        let event = EventBuilder::text_note("signature")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            pool.publish(&event, DEFAULT_QUORUM).await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn nip42_auth() {
+        let keys = Keys::generate();
+        let url = "wss://auth.relay";
+        let event = EventBuilder::text_note("signature")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let auth_required = RelayMessage::Ok {
+            event_id: event.id,
+            accepted: false,
+            message: "auth-required: sign in first".to_string(),
+        };
+
+        // Without keys, challenges and rejections go to the exchange.
+        let mut auth = RelayAuth::new(None, url);
+        assert_eq!(
+            auth.step(auth_required.clone()).unwrap(),
+            AuthStep::Handle(auth_required.clone())
+        );
+
+        // With keys, the rejected request is resent once the relay accepts the authentication.
+        let mut auth = RelayAuth::new(Some(&keys), url);
+        let AuthStep::Send(reply) = auth
+            .step(RelayMessage::Auth {
+                challenge: "challenge".to_string(),
+            })
+            .unwrap()
+        else {
+            panic!("expected an AUTH message");
+        };
+        let value = serde_json::from_str::<Value>(&reply).unwrap();
+        assert_eq!(value[0], "AUTH");
+        let auth_event = Event::from_json(value[1].to_string()).unwrap();
+        assert_eq!(auth_event.kind, Kind::Authentication);
+        assert_eq!(auth_event.pubkey, keys.public_key());
+        auth_event.verify().unwrap();
+        assert_eq!(auth.step(auth_required).unwrap(), AuthStep::Skip);
+        let auth_ok = RelayMessage::Ok {
+            event_id: auth_event.id,
+            accepted: true,
+            message: String::new(),
+        };
+        assert_eq!(auth.step(auth_ok).unwrap(), AuthStep::Resend);
+        let accepted = RelayMessage::Ok {
+            event_id: event.id,
+            accepted: true,
+            message: String::new(),
+        };
+        assert_eq!(
+            auth.step(accepted.clone()).unwrap(),
+            AuthStep::Handle(accepted)
+        );
+
+        // A rejected authentication fails the exchange.
+        let mut auth = RelayAuth::new(Some(&keys), url);
+        let AuthStep::Send(reply) = auth
+            .step(RelayMessage::Auth {
+                challenge: "challenge".to_string(),
+            })
+            .unwrap()
+        else {
+            panic!("expected an AUTH message");
+        };
+        let value = serde_json::from_str::<Value>(&reply).unwrap();
+        let auth_event = Event::from_json(value[1].to_string()).unwrap();
+        assert!(
+            auth.step(RelayMessage::Ok {
+                event_id: auth_event.id,
+                accepted: false,
+                message: "restricted".to_string(),
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn relay_messages() {
+        let event = EventBuilder::text_note("hello")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        assert_eq!(
+            event_message(&event),
+            format!(r#"["EVENT",{}]"#, event.as_json())
+        );
+
+        let message = format!(r#"["EVENT","sub",{}]"#, event.as_json());
+        assert_eq!(
+            parse_relay_message(&message).unwrap(),
+            RelayMessage::Event {
+                subscription_id: "sub".to_string(),
+                event: Box::new(event.clone())
+            }
+        );
+        let message = format!(r#"["OK","{}",false,"blocked: spam"]"#, event.id);
+        assert_eq!(
+            parse_relay_message(&message).unwrap(),
+            RelayMessage::Ok {
+                event_id: event.id,
+                accepted: false,
+                message: "blocked: spam".to_string()
+            }
+        );
+        assert_eq!(
+            parse_relay_message(r#"["AUTH","challenge"]"#).unwrap(),
+            RelayMessage::Auth {
+                challenge: "challenge".to_string()
+            }
+        );
+        assert!(parse_relay_message(r#"["AUTH"]"#).is_err());
+        assert!(parse_relay_message("not json").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Reputation of counterparties from post-escrow feedback over Nostr.
+//!
+//! Once an escrow is settled, each party can publish a signed [`Feedback`] event about
+//! the other: whether the escrow completed or went to dispute, and a rating.
+//! Events are tagged with the [`SessionId`] and the counterparty's `npub`,
+//! so the feedback about anyone can be fetched from the configured relays
+//! and aggregated into a [`Reputation`], through the `reputation` API method.
+//!
+//! Relays can't check that the escrow behind a feedback ever existed, so a [`Reputation`]
+//! counts at most one feedback per author and session, and reports how many distinct
+//! authors it comes from: a score vouched for by a handful of `npub`s is worth little.
+
+use std::collections::HashSet;
+
+use nostr::{
+    Event, EventBuilder, Filter, Keys, Kind, Tag,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::participants,
+    canonical,
+    error::Error,
+    protocol::{Handshake, SessionId, check_event, check_session_tag},
+};
+
+/// Version of the [`Feedback`] message.
+pub(crate) const FEEDBACK_VERSION: u8 = 1;
+
+/// Nostr event kind of a [`Feedback`].
+pub(crate) const FEEDBACK_KIND: u16 = 8_388;
+
+/// Highest [`Feedback::rating`], the lowest being 1.
+pub(crate) const MAX_RATING: u8 = 5;
+
+/// How an escrow ended, from the author's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Outcome {
+    /// The escrow was released without a dispute.
+    Completed,
+    /// The escrow needed the arbitrator, or was left to its timelock.
+    Disputed,
+}
+
+/// A participant's signed feedback about the counterparty of a settled escrow.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Feedback {
+    /// Message version, see [`FEEDBACK_VERSION`].
+    pub(crate) version: u8,
+    /// Negotiation of the escrow.
+    pub(crate) session_id: SessionId,
+    /// Nostr public key of the participant giving the feedback.
+    pub(crate) author: NostrPublicKey,
+    /// Nostr public key of the participant the feedback is about.
+    pub(crate) counterparty: NostrPublicKey,
+    /// How the escrow ended.
+    pub(crate) outcome: Outcome,
+    /// Rating of the counterparty, from 1 to [`MAX_RATING`].
+    pub(crate) rating: u8,
+    /// Free-form comment.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) comment: String,
+}
+
+impl Feedback {
+    /// Records the feedback of `author` about the other party of the agreed `handshake`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow is not agreed, `author` is not one of its parties,
+    /// or the rating is out of range.
+    pub(crate) fn new(
+        handshake: &Handshake,
+        author: NostrPublicKey,
+        outcome: Outcome,
+        rating: u8,
+        comment: impl Into<String>,
+    ) -> Result<Self, Error> {
+        if !matches!(handshake, Handshake::Agreed { .. }) {
+            return Err(Error::Protocol("Escrow is not agreed yet".to_string()));
+        }
+        let parties = participants(handshake);
+        if !parties.contains(&author) {
+            return Err(Error::Protocol(
+                "Feedback is not from a participant".to_string(),
+            ));
+        }
+        let counterparty = parties
+            .into_iter()
+            .find(|npub| *npub != author)
+            .ok_or_else(|| Error::Protocol("Escrow has no counterparty".to_string()))?;
+        let feedback = Self {
+            version: FEEDBACK_VERSION,
+            session_id: handshake.session_id()?,
+            author,
+            counterparty,
+            outcome,
+            rating,
+            comment: comment.into(),
+        };
+        feedback.verify()?;
+        Ok(feedback)
+    }
+
+    /// Checks the version and the rating, and that the feedback is not about its author.
+    pub(crate) fn verify(&self) -> Result<(), Error> {
+        if self.version != FEEDBACK_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported feedback version {}",
+                self.version
+            )));
+        }
+        if !(1..=MAX_RATING).contains(&self.rating) {
+            return Err(Error::WrongInputs(format!(
+                "Rating must be between 1 and {MAX_RATING}, got {}",
+                self.rating
+            )));
+        }
+        if self.author == self.counterparty {
+            return Err(Error::Protocol(
+                "Feedback is about its own author".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Builds and signs the feedback [`Event`], tagged with the counterparty.
+    pub(crate) fn to_event(&self, nsec: &NostrSecretKey) -> Result<Event, Error> {
+        let keys = Keys::new(nsec.clone());
+        if keys.public_key() != self.author {
+            return Err(Error::Protocol(
+                "Feedback must be signed by its author".to_string(),
+            ));
+        }
+        let tags = [
+            Tag::identifier(self.session_id.to_string()),
+            Tag::public_key(self.counterparty),
+        ];
+        Ok(
+            EventBuilder::new(Kind::Custom(FEEDBACK_KIND), canonical::encode(self)?)
+                .tags(tags)
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses and verifies a feedback [`Event`], checking it is signed by its author.
+    pub(crate) fn from_event(event: &Event) -> Result<Self, Error> {
+        check_event(event, FEEDBACK_KIND)?;
+        let feedback: Feedback = canonical::decode(&event.content)?;
+        if feedback.author != event.pubkey {
+            return Err(Error::Protocol(
+                "Feedback is not signed by its author".to_string(),
+            ));
+        }
+        check_session_tag(&event.tags, &feedback.session_id)?;
+        feedback.verify()?;
+        Ok(feedback)
+    }
+
+    /// The [`Filter`] of the feedback about `npub`.
+    pub(crate) fn filter(npub: &NostrPublicKey) -> Filter {
+        Filter::new()
+            .kind(Kind::Custom(FEEDBACK_KIND))
+            .pubkey(*npub)
+    }
+}
+
+/// Feedback about an `npub`, aggregated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Reputation {
+    /// Escrows rated as completed.
+    pub(crate) completed: usize,
+    /// Escrows rated as disputed.
+    pub(crate) disputed: usize,
+    /// Distinct authors of the feedback.
+    pub(crate) authors: usize,
+    /// Sum of the ratings.
+    rating_sum: u32,
+}
+
+impl Reputation {
+    /// Aggregates the `feedback` about `npub`.
+    ///
+    /// Feedback about someone else or that doesn't verify is ignored,
+    /// and only the first feedback of each author on a session counts.
+    pub(crate) fn new<'a>(
+        npub: &NostrPublicKey,
+        feedback: impl IntoIterator<Item = &'a Feedback>,
+    ) -> Self {
+        let mut reputation = Self::default();
+        let mut counted = HashSet::new();
+        let mut authors = HashSet::new();
+        for feedback in feedback {
+            if feedback.counterparty != *npub
+                || feedback.verify().is_err()
+                || !counted.insert((feedback.author, feedback.session_id))
+            {
+                continue;
+            }
+            authors.insert(feedback.author);
+            match feedback.outcome {
+                Outcome::Completed => reputation.completed += 1,
+                Outcome::Disputed => reputation.disputed += 1,
+            }
+            reputation.rating_sum += u32::from(feedback.rating);
+        }
+        reputation.authors = authors.len();
+        reputation
+    }
+
+    /// Number of escrows rated.
+    pub(crate) fn escrows(&self) -> usize {
+        self.completed + self.disputed
+    }
+
+    /// Average rating, from 1 to [`MAX_RATING`], if any escrow was rated.
+    pub(crate) fn average_rating(&self) -> Option<f64> {
+        let escrows = u32::try_from(self.escrows()).ok().filter(|n| *n > 0)?;
+        Some(f64::from(self.rating_sum) / f64::from(escrows))
+    }
+
+    /// Score from 0 to 100, if any escrow was rated.
+    ///
+    /// The average rating scaled to 0–100, times the share of completed escrows,
+    /// so disputes weigh on the score whatever their rating.
+    pub(crate) fn score(&self) -> Option<u8> {
+        let average = self.average_rating()?;
+        let rating = (average - 1.0) / f64::from(MAX_RATING - 1);
+        let completed = self.completed as f64 / self.escrows() as f64;
+        Some((100.0 * rating * completed).round() as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Amount;
+    use nostr::Timestamp;
+
+    use super::*;
+    use crate::protocol::{DEFAULT_OFFER_VALIDITY, Offer, Role, offer};
+
+    #[test]
+    fn reputation() {
+        let buyer = Keys::generate();
+        let seller = Keys::generate();
+        let now = Timestamp::now();
+        let offer = Offer {
+            role: Role::Buyer,
+            amount_seller: Amount::ZERO,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(buyer.public_key(), None)
+        };
+        let (offered, offer_event) = Handshake::offer(buyer.secret_key(), offer, now).unwrap();
+        assert!(Feedback::new(&offered, buyer.public_key(), Outcome::Completed, 5, "").is_err());
+        let (agreed, _) = Handshake::accept(seller.secret_key(), &offer_event, None, now).unwrap();
+
+        let feedback =
+            Feedback::new(&agreed, buyer.public_key(), Outcome::Completed, 5, "fast").unwrap();
+        assert_eq!(feedback.counterparty, seller.public_key());
+        assert!(Feedback::new(&agreed, buyer.public_key(), Outcome::Completed, 0, "").is_err());
+        assert!(
+            Feedback::new(
+                &agreed,
+                Keys::generate().public_key(),
+                Outcome::Completed,
+                5,
+                ""
+            )
+            .is_err()
+        );
+        assert!(feedback.to_event(seller.secret_key()).is_err());
+        let event = feedback.to_event(buyer.secret_key()).unwrap();
+        assert_eq!(Feedback::from_event(&event).unwrap(), feedback);
+
+        // The same feedback twice counts once, feedback about someone else not at all.
+        let disputed = Feedback {
+            author: Keys::generate().public_key(),
+            outcome: Outcome::Disputed,
+            rating: 3,
+            ..feedback.clone()
+        };
+        let about_buyer =
+            Feedback::new(&agreed, seller.public_key(), Outcome::Completed, 1, "").unwrap();
+        let reputation = Reputation::new(
+            &seller.public_key(),
+            [&feedback, &feedback, &disputed, &about_buyer],
+        );
+        assert_eq!(reputation.completed, 1);
+        assert_eq!(reputation.disputed, 1);
+        assert_eq!(reputation.authors, 2);
+        assert_eq!(reputation.average_rating(), Some(4.0));
+        assert_eq!(reputation.score(), Some(38));
+        assert_eq!(Reputation::new(&buyer.public_key(), []).score(), None);
+    }
+}
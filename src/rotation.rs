@@ -0,0 +1,474 @@
+//! Key rotation of unresolved escrows.
+//!
+//! When a participant fears their nsec leaked, the escrow coins move to a new escrow
+//! where a replacement npub takes the old one's place:
+//!
+//! 1. The rotating participant sends a [`KeyRotation`] signed with the old key,
+//!    carrying a signature of the new key that proves its possession,
+//!    tagging the other parties of the escrow, who find it with [`KeyRotation::filter`].
+//! 2. Both participants sign the [`KeyRotation::migration_tx`] through the collaborative
+//!    leaf `A`, or the MuSig2 key path, paying the whole escrow minus the fee
+//!    to the rotated escrow.
+//! 3. Both record the rotation in their [`Session`](crate::protocol::Session),
+//!    which keeps its [`SessionId`], so the escrow continues under the same negotiation.
+//!
+//! Terms are kept: amounts and arbitrator don't change, and a dispute escrow only gets
+//! the timelock left on the original, see [`remaining_timelock`].
+//!
+//! Whoever stole the old nsec can sign a rotation too, so the counterparty must confirm
+//! the new npub out of band before signing the migration.
+use bitcoin::{Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, absolute, transaction};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+use nostr::{
+    Event, EventBuilder, Filter, Keys, Kind, Tag,
+    key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey},
+};
+use secp256k1::{Message, SECP256K1, schnorr};
+use serde::{Deserialize, Serialize};
+
+#[cfg(debug_assertions)]
+use crate::logging::session_span;
+use crate::{
+    canonical,
+    error::Error,
+    message::tagged_hash,
+    protocol::{SessionId, check_event, check_session_tag},
+    scripts::EscrowConfig,
+    secret::SecretNsec,
+    util::npub_to_x_only_public_key,
+};
+
+/// Version of the [`KeyRotation`] message.
+pub(crate) const KEY_ROTATION_VERSION: u8 = 1;
+
+/// Nostr event kind of a [`KeyRotation`].
+pub(crate) const KEY_ROTATION_KIND: u16 = 8_387;
+
+/// Tag of the hash signed by the replacement key.
+const KEY_ROTATION_TAG: &[u8] = b"scrow/key-rotation";
+
+/// A participant's request to replace their key in an unresolved escrow.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct KeyRotation {
+    /// Message version, see [`KEY_ROTATION_VERSION`].
+    pub(crate) version: u8,
+    /// Negotiation of the escrow.
+    pub(crate) session_id: SessionId,
+    /// Nostr public key being replaced.
+    pub(crate) old_npub: NostrPublicKey,
+    /// Replacement Nostr public key.
+    pub(crate) new_npub: NostrPublicKey,
+    /// Timelock duration of the rotated escrow in blocks, for dispute escrows.
+    pub(crate) timelock_duration: Option<u32>,
+    /// Fee of the [`KeyRotation::migration_tx`].
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub(crate) fee: Amount,
+    /// Signature of the rotation by `new_npub`, proving its possession.
+    pub(crate) proof: schnorr::Signature,
+}
+
+impl KeyRotation {
+    /// Replaces `old_npub` with the public key of `new_nsec` in the escrow `config`
+    /// of the negotiation `session_id`.
+    ///
+    /// A dispute escrow keeps the `timelock_duration` left on the original,
+    /// see [`remaining_timelock`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if `old_npub` is not a participant of `config`,
+    /// or the replacement key is already part of the escrow.
+    pub(crate) fn new(
+        session_id: SessionId,
+        config: &EscrowConfig,
+        old_npub: NostrPublicKey,
+        new_nsec: SecretNsec,
+        timelock_duration: Option<u32>,
+        fee: Amount,
+    ) -> Result<Self, Error> {
+        let new_npub = new_nsec.public_key();
+        let digest = rotation_message(&session_id, &old_npub, &new_npub, timelock_duration, fee)?;
+        let proof = new_nsec.with_keypair(|keypair| SECP256K1.sign_schnorr(&digest, keypair));
+        let rotation = Self {
+            version: KEY_ROTATION_VERSION,
+            session_id,
+            old_npub,
+            new_npub,
+            timelock_duration,
+            fee,
+            proof,
+        };
+        rotation.verify(&session_id, config)?;
+        #[cfg(debug_assertions)]
+        session_span(&session_id).in_scope(|| trace!(%old_npub, %new_npub, "rotated key"));
+        Ok(rotation)
+    }
+
+    /// Verifies that the rotation is for the negotiation `session_id`,
+    /// replaces a participant of `config` and is signed by the replacement key.
+    pub(crate) fn verify(
+        &self,
+        session_id: &SessionId,
+        config: &EscrowConfig,
+    ) -> Result<(), Error> {
+        if self.version != KEY_ROTATION_VERSION {
+            return Err(Error::Protocol(format!(
+                "Unsupported key rotation version {}",
+                self.version
+            )));
+        }
+        if self.session_id != *session_id {
+            return Err(Error::Protocol(
+                "Key rotation is for another session".to_string(),
+            ));
+        }
+        if self.old_npub != config.npub_1 && self.old_npub != config.npub_2 {
+            return Err(Error::Protocol(
+                "Key rotation is not from a participant".to_string(),
+            ));
+        }
+        if [
+            Some(config.npub_1),
+            Some(config.npub_2),
+            config.npub_arbitrator,
+        ]
+        .contains(&Some(self.new_npub))
+        {
+            return Err(Error::Protocol(
+                "Replacement key is already part of the escrow".to_string(),
+            ));
+        }
+        if self.timelock_duration.is_some() != config.timelock_duration.is_some()
+            || self
+                .timelock_duration
+                .is_some_and(|timelock| timelock == 0 || timelock > u32::from(u16::MAX))
+            || self.timelock_duration > config.timelock_duration
+        {
+            return Err(Error::Protocol(
+                "Key rotation must keep at most the escrow's timelock".to_string(),
+            ));
+        }
+        let digest = rotation_message(
+            &self.session_id,
+            &self.old_npub,
+            &self.new_npub,
+            self.timelock_duration,
+            self.fee,
+        )?;
+        SECP256K1.verify_schnorr(
+            &self.proof,
+            &digest,
+            &npub_to_x_only_public_key(&self.new_npub)?,
+        )?;
+        Ok(())
+    }
+
+    /// The [`EscrowConfig`] of the rotated escrow, replacing the old key in `config`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the rotation doesn't verify against `config`, see [`KeyRotation::verify`].
+    pub(crate) fn rotated_config(&self, config: &EscrowConfig) -> Result<EscrowConfig, Error> {
+        self.verify(&self.session_id, config)?;
+        let replace = |npub: NostrPublicKey| {
+            if npub == self.old_npub {
+                self.new_npub
+            } else {
+                npub
+            }
+        };
+        Ok(EscrowConfig {
+            npub_1: replace(config.npub_1),
+            npub_2: replace(config.npub_2),
+            timelock_duration: self.timelock_duration,
+            ..*config
+        })
+    }
+
+    /// Builds the unsigned transaction moving the `amount` of the escrow `config`,
+    /// held at `funding`, into the rotated escrow.
+    ///
+    /// It spends the collaborative leaf `A` or the key path, so both participants sign it,
+    /// and has no lock time so both build the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the rotation doesn't verify against `config` or the fee leaves a dust output.
+    pub(crate) fn migration_tx(
+        &self,
+        config: &EscrowConfig,
+        funding: OutPoint,
+        amount: Amount,
+    ) -> Result<Transaction, Error> {
+        let script_pubkey = self.rotated_config(config)?.address()?.script_pubkey();
+        let value = amount
+            .checked_sub(self.fee)
+            .filter(|value| *value >= script_pubkey.minimal_non_dust())
+            .ok_or_else(|| {
+                Error::WrongInputs(format!("A fee of {} leaves a dust escrow", self.fee))
+            })?;
+        Ok(Transaction {
+            version: transaction::Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: funding,
+                sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey,
+            }],
+        })
+    }
+
+    /// Builds and signs the rotation [`Event`] with the old key, addressed to the
+    /// counterparty and arbitrator of `config`.
+    pub(crate) fn to_event(
+        &self,
+        nsec: &NostrSecretKey,
+        config: &EscrowConfig,
+    ) -> Result<Event, Error> {
+        let keys = Keys::new(nsec.clone());
+        if keys.public_key() != self.old_npub {
+            return Err(Error::Protocol(
+                "Key rotation must be signed by the replaced key".to_string(),
+            ));
+        }
+        let mut tags = vec![Tag::identifier(self.session_id.to_string())];
+        tags.extend(
+            [
+                Some(config.npub_1),
+                Some(config.npub_2),
+                config.npub_arbitrator,
+            ]
+            .into_iter()
+            .flatten()
+            .filter(|npub| *npub != self.old_npub)
+            .map(Tag::public_key),
+        );
+        Ok(
+            EventBuilder::new(Kind::Custom(KEY_ROTATION_KIND), canonical::encode(self)?)
+                .tags(tags)
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses a rotation [`Event`], checking it is signed by the replaced key.
+    ///
+    /// The rotation still has to be verified against its escrow with [`KeyRotation::verify`].
+    pub(crate) fn from_event(event: &Event) -> Result<Self, Error> {
+        check_event(event, KEY_ROTATION_KIND)?;
+        let rotation: KeyRotation = canonical::decode(&event.content)?;
+        if rotation.old_npub != event.pubkey {
+            return Err(Error::Protocol(
+                "Key rotation is not signed by the replaced key".to_string(),
+            ));
+        }
+        check_session_tag(&event.tags, &rotation.session_id)?;
+        Ok(rotation)
+    }
+
+    /// The [`Filter`] of the key rotations in the negotiation `session_id`.
+    pub(crate) fn filter(session_id: &SessionId) -> Filter {
+        Filter::new()
+            .kind(Kind::Custom(KEY_ROTATION_KIND))
+            .identifier(session_id.to_string())
+    }
+}
+
+/// Digest of a key rotation, signed by the replacement key.
+fn rotation_message(
+    session_id: &SessionId,
+    old_npub: &NostrPublicKey,
+    new_npub: &NostrPublicKey,
+    timelock_duration: Option<u32>,
+    fee: Amount,
+) -> Result<Message, Error> {
+    let message = canonical::encode(&(
+        session_id,
+        old_npub,
+        new_npub,
+        timelock_duration,
+        fee.to_sat(),
+    ))?;
+    Ok(Message::from_digest(tagged_hash(
+        KEY_ROTATION_TAG,
+        message.as_bytes(),
+    )))
+}
+
+/// Timelock in blocks left on an escrow with a relative `timelock_duration`
+/// whose funding confirmed at `funding_height`, once the migration confirms
+/// in the block after `tip_height`.
+///
+/// An expired timelock gives a single block, so the arbitrator can still act right away
+/// while the rotated escrow stays a valid dispute escrow.
+pub(crate) fn remaining_timelock(
+    timelock_duration: u32,
+    funding_height: u32,
+    tip_height: u32,
+) -> u32 {
+    funding_height
+        .saturating_add(timelock_duration)
+        .saturating_sub(tip_height.saturating_add(1))
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, Txid, hashes::Hash};
+    use nostr::JsonUtil;
+
+    use crate::scripts::ScriptTemplate;
+
+    use super::*;
+
+    #[test]
+    fn rotate_key() {
+        let (nsec_1, nsec_2, nsec_new) = (
+            SecretNsec::generate(),
+            SecretNsec::generate(),
+            SecretNsec::generate(),
+        );
+        let (keys_1, npub_new) = (Keys::generate(), nsec_new.public_key());
+        let config = EscrowConfig {
+            npub_1: keys_1.public_key(),
+            npub_2: nsec_2.public_key(),
+            npub_arbitrator: Some(nsec_1.public_key()),
+            timelock_duration: Some(144),
+            network: Network::Regtest,
+            template: ScriptTemplate::V1,
+        };
+        let session_id = "0000000000000000000000000000000000000000000000000000000000000001"
+            .parse::<SessionId>()
+            .unwrap();
+        let timelock = remaining_timelock(144, 100, 199);
+        assert_eq!(timelock, 44);
+        assert_eq!(remaining_timelock(144, 100, 500), 1);
+
+        let rotation = KeyRotation::new(
+            session_id,
+            &config,
+            keys_1.public_key(),
+            nsec_new.duplicate(),
+            Some(timelock),
+            Amount::from_sat(500),
+        )
+        .unwrap();
+        let rotated = rotation.rotated_config(&config).unwrap();
+        assert_eq!(rotated.npub_1, npub_new);
+        assert_eq!(rotated.npub_2, config.npub_2);
+        assert_eq!(rotated.timelock_duration, Some(44));
+
+        let funding = OutPoint::new(Txid::all_zeros(), 0);
+        let tx = rotation
+            .migration_tx(&config, funding, Amount::from_sat(100_000))
+            .unwrap();
+        assert_eq!(tx.output[0].value, Amount::from_sat(99_500));
+        assert_eq!(
+            tx.output[0].script_pubkey,
+            rotated.address().unwrap().script_pubkey()
+        );
+        assert!(
+            rotation
+                .migration_tx(&config, funding, Amount::from_sat(600))
+                .is_err()
+        );
+
+        // The rotation goes through a relay, signed by the old key.
+        let event = rotation.to_event(keys_1.secret_key(), &config).unwrap();
+        let received =
+            KeyRotation::from_event(&Event::from_json(event.as_json()).unwrap()).unwrap();
+        assert_eq!(received, rotation);
+        assert!(received.verify(&session_id, &config).is_ok());
+        assert!(
+            rotation
+                .to_event(&NostrSecretKey::generate(), &config)
+                .is_err()
+        );
+
+        // Tampered terms break the replacement key's proof.
+        let longer = KeyRotation {
+            timelock_duration: Some(144),
+            ..rotation.clone()
+        };
+        assert!(longer.verify(&session_id, &config).is_err());
+        // The rotated key is no longer a participant.
+        assert!(rotation.verify(&session_id, &rotated).is_err());
+        // Only participants rotate, and never into the escrow's own keys.
+        assert!(
+            KeyRotation::new(
+                session_id,
+                &config,
+                npub_new,
+                SecretNsec::generate(),
+                Some(timelock),
+                Amount::from_sat(500),
+            )
+            .is_err()
+        );
+        assert!(
+            KeyRotation::new(
+                session_id,
+                &config,
+                keys_1.public_key(),
+                nsec_1,
+                Some(timelock),
+                Amount::from_sat(500),
+            )
+            .is_err()
+        );
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn rotated_session_continues() {
+        use nostr::Timestamp;
+
+        use crate::{
+            protocol::{Handshake, Session, offer},
+            storage::MemoryStorage,
+        };
+
+        let (keys_a, keys_b, keys_arbitrator) =
+            (Keys::generate(), Keys::generate(), Keys::generate());
+        let offer = offer(keys_a.public_key(), Some(keys_arbitrator.public_key()));
+        let (_, offer_event) =
+            Handshake::offer(keys_a.secret_key(), offer, Timestamp::now()).unwrap();
+        let (agreed, _) =
+            Handshake::accept(keys_b.secret_key(), &offer_event, None, Timestamp::now()).unwrap();
+        let mut session = Session::new(agreed);
+        let id = session.id().unwrap();
+        let config = session.escrow_config().unwrap();
+
+        let nsec_new = SecretNsec::generate();
+        let rotation = KeyRotation::new(
+            id,
+            &config,
+            keys_a.public_key(),
+            nsec_new.duplicate(),
+            Some(100),
+            Amount::from_sat(500),
+        )
+        .unwrap();
+        let rotated = session.rotate(rotation.clone()).unwrap();
+        assert_eq!(session.escrow_config().unwrap(), rotated);
+        // The old key can't rotate again.
+        assert!(session.rotate(rotation).is_err());
+
+        // The rotated escrow is saved and loaded as the same session.
+        let storage = MemoryStorage::default();
+        session.save(&storage).unwrap();
+        assert_eq!(Session::list(&storage).unwrap(), [id]);
+        let loaded = Session::load(&storage, &id).unwrap().unwrap();
+        assert_eq!(loaded.escrow_config().unwrap(), rotated);
+        assert_eq!(loaded, session);
+
+        let mut tampered =
+            serde_json::from_str::<serde_json::Value>(&session.to_json().unwrap()).unwrap();
+        tampered["rotations"][0]["new_npub"] = keys_b.public_key().to_hex().into();
+        assert!(Session::from_json(&tampered.to_string()).is_err());
+    }
+}
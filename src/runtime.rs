@@ -0,0 +1,261 @@
+//! Async IO that runs natively and in the browser.
+//!
+//! Chain, relay and identity code sleeps, times out, measures latency and makes HTTP
+//! requests through this module only, so it compiles to both targets unchanged.
+//! Natively it is backed by tokio and reqwest, and in the browser by `setTimeout`,
+//! `fetch` through gloo, and wasm-bindgen-futures.
+use std::{
+    future::{Future, poll_fn},
+    pin::Pin,
+    task::Poll,
+    time::Duration,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use native::{Instant, get_text, post_json, post_json_with_headers, post_text, sleep};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web::{Instant, get_text, post_json, post_text, sleep};
+
+/// [`esplora_client::Sleeper`] waiting with [`sleep`], so Esplora retries work on both targets.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Sleeper;
+
+impl esplora_client::Sleeper for Sleeper {
+    // Natively, Esplora calls run on multi-threaded runtimes, such as the mobile exports'.
+    #[cfg(not(target_arch = "wasm32"))]
+    type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+    #[cfg(target_arch = "wasm32")]
+    type Sleep = Pin<Box<dyn Future<Output = ()>>>;
+
+    fn sleep(duration: Duration) -> Self::Sleep {
+        Box::pin(sleep(duration))
+    }
+}
+
+/// Runs all `futures` concurrently on the current task, returning their outputs in order.
+pub(crate) async fn join_all<F: Future>(futures: impl IntoIterator<Item = F>) -> Vec<F::Output> {
+    let mut futures = futures
+        .into_iter()
+        .map(|future| Some(Box::pin(future)))
+        .collect::<Vec<_>>();
+    let mut outputs = futures.iter().map(|_| None).collect::<Vec<_>>();
+    poll_fn(|cx| {
+        let mut pending = false;
+        for (slot, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            let Some(future) = slot else { continue };
+            match future.as_mut().poll(cx) {
+                Poll::Ready(value) => {
+                    *output = Some(value);
+                    *slot = None;
+                }
+                Poll::Pending => pending = true,
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await;
+    outputs
+        .into_iter()
+        .map(|output| output.expect("every future completed"))
+        .collect()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    //! Async IO over tokio and reqwest.
+
+    use std::time::Duration;
+
+    use crate::error::Error;
+
+    /// Resolves after `duration`.
+    pub(crate) async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+
+    /// A point in time, to measure elapsed durations.
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Instant(std::time::Instant);
+
+    impl Instant {
+        /// The current time.
+        pub(crate) fn now() -> Self {
+            Self(std::time::Instant::now())
+        }
+
+        /// Time elapsed since this instant.
+        pub(crate) fn elapsed(&self) -> Duration {
+            self.0.elapsed()
+        }
+    }
+
+    /// Fetches the body of `url` as text, failing on non-success statuses.
+    pub(crate) async fn get_text(url: &str) -> Result<String, Error> {
+        let http_error = |e: reqwest::Error| Error::Http(format!("{url}: {e}"));
+        reqwest::get(url)
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(http_error)?
+            .text()
+            .await
+            .map_err(http_error)
+    }
+
+    /// Posts the JSON `body` to `url` and returns the response body as text,
+    /// failing on non-success statuses.
+    pub(crate) async fn post_json(url: &str, body: &str) -> Result<String, Error> {
+        post(url, "application/json", body, &[]).await
+    }
+
+    /// Posts the JSON `body` to `url` with extra `headers`, such as a signature,
+    /// and returns the response body as text, failing on non-success statuses.
+    pub(crate) async fn post_json_with_headers(
+        url: &str,
+        body: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<String, Error> {
+        post(url, "application/json", body, headers).await
+    }
+
+    /// Posts the plain text `body` to `url` and returns the response body as text,
+    /// failing on non-success statuses.
+    pub(crate) async fn post_text(url: &str, body: &str) -> Result<String, Error> {
+        post(url, "text/plain", body, &[]).await
+    }
+
+    /// Posts `body` of `content_type` with `headers` to `url`
+    /// and returns the response body as text.
+    async fn post(
+        url: &str,
+        content_type: &str,
+        body: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<String, Error> {
+        let http_error = |e: reqwest::Error| Error::Http(format!("{url}: {e}"));
+        let request = reqwest::Client::new()
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, content_type);
+        headers
+            .iter()
+            .fold(request, |request, (name, value)| {
+                request.header(*name, *value)
+            })
+            .body(body.to_string())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(http_error)?
+            .text()
+            .await
+            .map_err(http_error)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    //! Async IO over browser APIs.
+
+    use std::time::Duration;
+
+    use gloo_net::http::Request;
+
+    use crate::error::Error;
+
+    /// Resolves after `duration` using the browser's `setTimeout`.
+    pub(crate) async fn sleep(duration: Duration) {
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            if let Some(window) = web_sys::window() {
+                let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                    &resolve,
+                    duration.as_millis().try_into().unwrap_or(i32::MAX),
+                );
+            }
+        });
+        let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+    }
+
+    /// A point in time, to measure elapsed durations.
+    ///
+    /// [`std::time::Instant`] is not available in the browser, so this uses `Date.now()`.
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Instant(f64);
+
+    impl Instant {
+        /// The current time.
+        pub(crate) fn now() -> Self {
+            Self(js_sys::Date::now())
+        }
+
+        /// Time elapsed since this instant.
+        pub(crate) fn elapsed(&self) -> Duration {
+            let elapsed = (js_sys::Date::now() - self.0).max(0.0);
+            Duration::from_secs_f64(elapsed / 1_000.0)
+        }
+    }
+
+    /// Fetches the body of `url` as text with `fetch`, failing on non-success statuses.
+    pub(crate) async fn get_text(url: &str) -> Result<String, Error> {
+        let http_error = |e: &dyn std::fmt::Display| Error::Http(format!("{url}: {e}"));
+        let response = Request::get(url).send().await.map_err(|e| http_error(&e))?;
+        if !response.ok() {
+            return Err(http_error(&format!("HTTP status {}", response.status())));
+        }
+        response.text().await.map_err(|e| http_error(&e))
+    }
+
+    /// Posts the JSON `body` to `url` with `fetch` and returns the response body as text,
+    /// failing on non-success statuses.
+    pub(crate) async fn post_json(url: &str, body: &str) -> Result<String, Error> {
+        post(url, "application/json", body).await
+    }
+
+    /// Posts the plain text `body` to `url` with `fetch` and returns the response body as text,
+    /// failing on non-success statuses.
+    pub(crate) async fn post_text(url: &str, body: &str) -> Result<String, Error> {
+        post(url, "text/plain", body).await
+    }
+
+    /// Posts `body` of `content_type` to `url` with `fetch` and returns the response body as text.
+    async fn post(url: &str, content_type: &str, body: &str) -> Result<String, Error> {
+        let http_error = |e: &dyn std::fmt::Display| Error::Http(format!("{url}: {e}"));
+        let response = Request::post(url)
+            .header("Content-Type", content_type)
+            .body(body)
+            .map_err(|e| http_error(&e))?
+            .send()
+            .await
+            .map_err(|e| http_error(&e))?;
+        if !response.ok() {
+            return Err(http_error(&format!("HTTP status {}", response.status())));
+        }
+        response.text().await.map_err(|e| http_error(&e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sleep_and_elapsed() {
+        let start = Instant::now();
+        sleep(Duration::from_millis(10)).await;
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn join_all_runs_concurrently() {
+        let start = Instant::now();
+        let outputs = join_all((1..=3).map(|n| async move {
+            sleep(Duration::from_millis(100)).await;
+            n
+        }))
+        .await;
+        assert_eq!(outputs, vec![1, 2, 3]);
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+}
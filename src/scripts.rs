@@ -3,17 +3,22 @@
 use std::sync::LazyLock;
 
 use bitcoin::{
-    Address, Network, ScriptBuf, Sequence, XOnlyPublicKey,
+    Address, Network, Script, ScriptBuf, Sequence, TapLeafHash, XOnlyPublicKey,
     hashes::{Hash, sha256},
     opcodes::all::*,
-    taproot::{LeafVersion, TaprootBuilder, TaprootBuilderError, TaprootSpendInfo},
+    taproot::{
+        ControlBlock, LeafVersion, TapNodeHash, TaprootBuilder, TaprootBuilderError,
+        TaprootSpendInfo,
+    },
 };
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::trace;
 use nostr::key::PublicKey as NostrPublicKey;
 use secp256k1::SECP256K1;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
 
-use crate::{error::Error, util::npub_to_x_only_public_key};
+use crate::{error::Error, musig::KeyAggContext, util::npub_to_x_only_public_key};
 
 /// A verifiably unspendable public key, produced by hashing a fixed string to a curve group
 /// generator.
@@ -65,6 +70,23 @@ pub(crate) fn escrow_spend_info(
     npub_2: &NostrPublicKey,
     npub_arbitrator: Option<&NostrPublicKey>,
     timelock_duration: Option<u32>,
+) -> Result<TaprootSpendInfo, Error> {
+    escrow_spend_info_with_internal_key(
+        npub_1,
+        npub_2,
+        npub_arbitrator,
+        timelock_duration,
+        *UNSPENDABLE_PUBLIC_KEY,
+    )
+}
+
+/// Like [`escrow_spend_info`], but with the given Taproot `internal_key`.
+fn escrow_spend_info_with_internal_key(
+    npub_1: &NostrPublicKey,
+    npub_2: &NostrPublicKey,
+    npub_arbitrator: Option<&NostrPublicKey>,
+    timelock_duration: Option<u32>,
+    internal_key: XOnlyPublicKey,
 ) -> Result<TaprootSpendInfo, Error> {
     // Collaborative Path
     if npub_arbitrator.is_none() && timelock_duration.is_none() {
@@ -81,7 +103,7 @@ pub(crate) fn escrow_spend_info(
 
         TaprootBuilder::new()
             .add_leaf_with_ver(0, script_1, LeafVersion::TapScript)?
-            .finalize(SECP256K1, *UNSPENDABLE_PUBLIC_KEY)
+            .finalize(SECP256K1, internal_key)
             // FIXME(@storopoli): better error here.
             .map_err(|_| Error::TaprootBuilder(TaprootBuilderError::EmptyTree))
     }
@@ -117,7 +139,7 @@ pub(crate) fn escrow_spend_info(
             .add_leaf_with_ver(1, script_1, LeafVersion::TapScript)?
             .add_leaf_with_ver(2, script_2, LeafVersion::TapScript)?
             .add_leaf_with_ver(2, script_3, LeafVersion::TapScript)?
-            .finalize(SECP256K1, *UNSPENDABLE_PUBLIC_KEY)
+            .finalize(SECP256K1, internal_key)
             // FIXME(@storopoli): better error here.
             .map_err(|_| Error::TaprootBuilder(TaprootBuilderError::EmptyTree))
     }
@@ -179,10 +201,7 @@ pub(crate) fn escrow_scripts(
             .push_opcode(OP_CHECKSIG)
             .into_script()),
         EscrowScript::B => {
-            let npub_arbitrator = npub_arbitrator.unwrap();
-            let pk_arbitrator = npub_to_x_only_public_key(npub_arbitrator)?;
-            // Timelock.
-            let sequence = Sequence::from_consensus(timelock_duration.unwrap());
+            let (pk_arbitrator, sequence) = dispute_keys(npub_arbitrator, timelock_duration)?;
             Ok(ScriptBuf::builder()
                 .push_sequence(sequence)
                 .push_opcode(OP_CSV)
@@ -194,10 +213,7 @@ pub(crate) fn escrow_scripts(
                 .into_script())
         }
         EscrowScript::C => {
-            let npub_arbitrator = npub_arbitrator.unwrap();
-            let pk_arbitrator = npub_to_x_only_public_key(npub_arbitrator)?;
-            // Timelock.
-            let sequence = Sequence::from_consensus(timelock_duration.unwrap());
+            let (pk_arbitrator, sequence) = dispute_keys(npub_arbitrator, timelock_duration)?;
             Ok(ScriptBuf::builder()
                 .push_sequence(sequence)
                 .push_opcode(OP_CSV)
@@ -211,6 +227,24 @@ pub(crate) fn escrow_scripts(
     }
 }
 
+/// The arbitrator key and timelock of the dispute leaves `B` and `C`.
+///
+/// Fails instead of panicking when either is missing,
+/// as they may come from a counterparty's proposal.
+fn dispute_keys(
+    npub_arbitrator: Option<&NostrPublicKey>,
+    timelock_duration: Option<u32>,
+) -> Result<(XOnlyPublicKey, Sequence), Error> {
+    let npub_arbitrator = npub_arbitrator
+        .ok_or_else(|| Error::WrongInputs("Dispute script needs an arbitrator".to_string()))?;
+    let timelock_duration = timelock_duration
+        .ok_or_else(|| Error::WrongInputs("Dispute script needs a timelock".to_string()))?;
+    Ok((
+        npub_to_x_only_public_key(npub_arbitrator)?,
+        Sequence::from_consensus(timelock_duration),
+    ))
+}
+
 /// The escrow script type.
 ///
 /// 1. `A`: 2-of-2 multisig between the two parties without timelocks.
@@ -232,12 +266,30 @@ pub(crate) fn escrow_scripts(
 ///        B     C
 /// ```
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
 pub(crate) enum EscrowScript {
     A,
     B,
     C,
 }
 
+/// How an escrow output is spent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub(crate) enum SpendPath {
+    /// Key path spend.
+    ///
+    /// Only dispute escrows with [`ScriptTemplate::V2`] have a spendable internal key,
+    /// the MuSig2 aggregate of both participants' keys, see [`EscrowConfig::key_agg`].
+    KeyPath,
+    /// Script path spend through one of the escrow leaves.
+    Leaf(EscrowScript),
+}
+
 /// Creates an escrow-resolution 2-of-3 multisig P2TR [`Address`] from 2 [`NostrPublicKey`]s,
 /// an optional arbitrator [`NostrPublicKey`] and an optional timelock duration in blocks.
 ///
@@ -282,6 +334,372 @@ pub(crate) fn escrow_address(
     Ok(Address::p2tr(SECP256K1, internal_key, merkle_root, network))
 }
 
+/// Version of the script template turning the escrow parameters into leaf scripts and a
+/// script tree.
+///
+/// Escrows record the template they were created with, so their address and witnesses can
+/// still be derived after the current template changes.
+/// Unknown versions are rejected, as deriving with another template would yield another address.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(try_from = "u8", into = "u8")
+)]
+pub(crate) enum ScriptTemplate {
+    /// Leaves `A`, `B` and `C` as built by [`escrow_scripts`],
+    /// in the tree of [`escrow_spend_info`].
+    #[default]
+    V1 = 1,
+    /// Like [`ScriptTemplate::V1`], but dispute escrows use the MuSig2 aggregate of both
+    /// participants' keys as internal key, so cooperative closes are key path spends
+    /// that don't reveal the arbitrator leaves.
+    ///
+    /// Collaborative escrows have no leaf to hide, so they are the same as in [`ScriptTemplate::V1`].
+    V2 = 2,
+}
+
+/// The [`ScriptTemplate`] of new escrows.
+pub(crate) const CURRENT_SCRIPT_TEMPLATE: ScriptTemplate = ScriptTemplate::V1;
+
+impl ScriptTemplate {
+    /// The [`TaprootSpendInfo`] of an escrow output under this template.
+    pub(crate) fn spend_info(
+        self,
+        npub_1: &NostrPublicKey,
+        npub_2: &NostrPublicKey,
+        npub_arbitrator: Option<&NostrPublicKey>,
+        timelock_duration: Option<u32>,
+    ) -> Result<TaprootSpendInfo, Error> {
+        match self {
+            ScriptTemplate::V2 if npub_arbitrator.is_some() => {
+                let internal_key = KeyAggContext::new(&[*npub_1, *npub_2])?.internal_key();
+                escrow_spend_info_with_internal_key(
+                    npub_1,
+                    npub_2,
+                    npub_arbitrator,
+                    timelock_duration,
+                    internal_key,
+                )
+            }
+            ScriptTemplate::V1 | ScriptTemplate::V2 => {
+                escrow_spend_info(npub_1, npub_2, npub_arbitrator, timelock_duration)
+            }
+        }
+    }
+
+    /// The locking script of an escrow leaf under this template.
+    pub(crate) fn script(
+        self,
+        npub_1: &NostrPublicKey,
+        npub_2: &NostrPublicKey,
+        npub_arbitrator: Option<&NostrPublicKey>,
+        timelock_duration: Option<u32>,
+        escrow_script: EscrowScript,
+    ) -> Result<ScriptBuf, Error> {
+        match self {
+            ScriptTemplate::V1 | ScriptTemplate::V2 => escrow_scripts(
+                npub_1,
+                npub_2,
+                npub_arbitrator,
+                timelock_duration,
+                escrow_script,
+            ),
+        }
+    }
+}
+
+impl TryFrom<u8> for ScriptTemplate {
+    type Error = Error;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(ScriptTemplate::V1),
+            2 => Ok(ScriptTemplate::V2),
+            version => Err(Error::UnsupportedScriptTemplate(version)),
+        }
+    }
+}
+
+impl From<ScriptTemplate> for u8 {
+    fn from(template: ScriptTemplate) -> Self {
+        template as u8
+    }
+}
+
+/// How the two participants' keys are assigned to `npub_1` and `npub_2` of an [`EscrowConfig`].
+///
+/// # Leaf ordering rule
+///
+/// The tap tree of an escrow only depends on its leaf scripts and their depths:
+/// [`TaprootBuilder`] hashes every pair of sibling nodes in lexicographic order,
+/// as required by [BIP-341](https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki),
+/// so the order leaves are added in does not matter.
+/// Leaves `B` and `C` are siblings, so swapping the participants swaps them without
+/// changing the merkle root.
+/// Leaf `A` however checks `npub_2` first and `npub_1` last, so both parties must agree
+/// on which participant is `npub_1` to derive the same address.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub(crate) enum KeyOrdering {
+    /// Keys are used in the order given, such as buyer first and seller second.
+    #[default]
+    AsGiven,
+    /// Keys are sorted by their x-only serialization, so any order yields the same escrow.
+    Sorted,
+}
+
+impl KeyOrdering {
+    /// Orders the participants' keys by this rule.
+    pub(crate) fn order(
+        self,
+        npub_1: NostrPublicKey,
+        npub_2: NostrPublicKey,
+    ) -> (NostrPublicKey, NostrPublicKey) {
+        match self {
+            KeyOrdering::Sorted if npub_2.to_bytes() < npub_1.to_bytes() => (npub_2, npub_1),
+            KeyOrdering::AsGiven | KeyOrdering::Sorted => (npub_1, npub_2),
+        }
+    }
+}
+
+/// The parameters that fully determine an escrow output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct EscrowConfig {
+    /// First participant's Nostr public key.
+    pub(crate) npub_1: NostrPublicKey,
+    /// Second participant's Nostr public key.
+    pub(crate) npub_2: NostrPublicKey,
+    /// Arbitrator's Nostr public key, if the escrow has a dispute path.
+    pub(crate) npub_arbitrator: Option<NostrPublicKey>,
+    /// Dispute timelock duration in blocks, if the escrow has a dispute path.
+    pub(crate) timelock_duration: Option<u32>,
+    /// Network of the escrow address.
+    pub(crate) network: Network,
+    /// Script template of the escrow, [`ScriptTemplate::V1`] for configs predating templates.
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub(crate) template: ScriptTemplate,
+}
+
+impl EscrowConfig {
+    /// The [`TaprootSpendInfo`] of the escrow output.
+    pub(crate) fn spend_info(&self) -> Result<TaprootSpendInfo, Error> {
+        self.template.spend_info(
+            &self.npub_1,
+            &self.npub_2,
+            self.npub_arbitrator.as_ref(),
+            self.timelock_duration,
+        )
+    }
+
+    /// Reorders the participants by `ordering`, see [`KeyOrdering`].
+    ///
+    /// With [`KeyOrdering::Sorted`], leaves `B` and `C` may swap participants,
+    /// so use [`EscrowConfig::signers`] rather than assuming which leaf is whose.
+    pub(crate) fn with_key_ordering(self, ordering: KeyOrdering) -> Self {
+        let (npub_1, npub_2) = ordering.order(self.npub_1, self.npub_2);
+        Self {
+            npub_1,
+            npub_2,
+            ..self
+        }
+    }
+
+    /// The merkle root of the escrow's tap tree, committed to by its output key.
+    ///
+    /// Every escrow has at least leaf `A`, so it is never [`None`].
+    pub(crate) fn merkle_root(&self) -> Result<Option<TapNodeHash>, Error> {
+        Ok(self.spend_info()?.merkle_root())
+    }
+
+    /// The escrow [`Address`].
+    pub(crate) fn address(&self) -> Result<Address, Error> {
+        let spend_info = self.spend_info()?;
+        Ok(Address::p2tr(
+            SECP256K1,
+            spend_info.internal_key(),
+            spend_info.merkle_root(),
+            self.network,
+        ))
+    }
+
+    /// The locking script of the given [`EscrowScript`] leaf.
+    pub(crate) fn script(&self, escrow_script: EscrowScript) -> Result<ScriptBuf, Error> {
+        if escrow_script != EscrowScript::A && self.npub_arbitrator.is_none() {
+            return Err(Error::WrongInputs(format!(
+                "Leaf {escrow_script:?} requires an arbitrator"
+            )));
+        }
+        self.template.script(
+            &self.npub_1,
+            &self.npub_2,
+            self.npub_arbitrator.as_ref(),
+            self.timelock_duration,
+            escrow_script,
+        )
+    }
+
+    /// Whether the escrow can be spent through the key path.
+    pub(crate) fn has_key_path(&self) -> bool {
+        self.template == ScriptTemplate::V2 && self.npub_arbitrator.is_some()
+    }
+
+    /// The MuSig2 [`KeyAggContext`] of both participants, tweaked into the escrow output key,
+    /// to sign a cooperative key path spend.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow has no key path, see [`EscrowConfig::has_key_path`].
+    pub(crate) fn key_agg(&self) -> Result<KeyAggContext, Error> {
+        if !self.has_key_path() {
+            return Err(Error::WrongInputs(
+                "Only dispute escrows with script template 2 have a key path".to_string(),
+            ));
+        }
+        KeyAggContext::new(&[self.npub_1, self.npub_2])?.with_merkle_root(self.merkle_root()?)
+    }
+
+    /// The leaves present in the escrow's script tree.
+    pub(crate) fn leaves(&self) -> &'static [EscrowScript] {
+        if self.npub_arbitrator.is_some() {
+            &[EscrowScript::A, EscrowScript::B, EscrowScript::C]
+        } else {
+            &[EscrowScript::A]
+        }
+    }
+
+    /// The keys that must sign the given [`EscrowScript`] leaf,
+    /// in witness order.
+    pub(crate) fn signers(
+        &self,
+        escrow_script: EscrowScript,
+    ) -> Result<[NostrPublicKey; 2], Error> {
+        match (escrow_script, self.npub_arbitrator) {
+            (EscrowScript::A, _) => Ok([self.npub_1, self.npub_2]),
+            (EscrowScript::B, Some(arbitrator)) => Ok([self.npub_1, arbitrator]),
+            (EscrowScript::C, Some(arbitrator)) => Ok([self.npub_2, arbitrator]),
+            (escrow_script, None) => Err(Error::WrongInputs(format!(
+                "Leaf {escrow_script:?} requires an arbitrator"
+            ))),
+        }
+    }
+
+    /// Precomputes the [`EscrowContext`] of the escrow.
+    pub(crate) fn context(&self) -> Result<EscrowContext, Error> {
+        EscrowContext::new(*self)
+    }
+}
+
+/// A leaf of an [`EscrowContext`], with everything needed to sign and spend it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ContextLeaf {
+    /// Which leaf this is.
+    pub(crate) escrow_script: EscrowScript,
+    /// Locking script of the leaf.
+    pub(crate) script: ScriptBuf,
+    /// Hash of the leaf, committed to by its sighashes.
+    pub(crate) leaf_hash: TapLeafHash,
+    /// Control block proving the leaf is in the escrow's tap tree.
+    pub(crate) control_block: ControlBlock,
+}
+
+/// The Taproot data of an [`EscrowConfig`], computed once.
+///
+/// Building the tap tree and control blocks hashes every leaf, so a session
+/// computes the context once and passes it by reference to signing, combining and
+/// auditing, rather than going through [`EscrowConfig::spend_info`] on each call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EscrowContext {
+    /// The escrow terms.
+    config: EscrowConfig,
+    /// Spend info of the escrow output.
+    spend_info: TaprootSpendInfo,
+    /// Address of the escrow output.
+    address: Address,
+    /// Every leaf of the tap tree, in [`EscrowConfig::leaves`] order.
+    leaves: Vec<ContextLeaf>,
+}
+
+impl EscrowContext {
+    /// Computes the spend info, address, leaf scripts and control blocks of `config`.
+    pub(crate) fn new(config: EscrowConfig) -> Result<Self, Error> {
+        let spend_info = config.spend_info()?;
+        let address = Address::p2tr(
+            SECP256K1,
+            spend_info.internal_key(),
+            spend_info.merkle_root(),
+            config.network,
+        );
+        let leaves = config
+            .leaves()
+            .iter()
+            .map(|escrow_script| {
+                let script = config.script(*escrow_script)?;
+                let control_block = spend_info
+                    .control_block(&(script.clone(), LeafVersion::TapScript))
+                    .ok_or_else(|| {
+                        Error::WrongInputs(format!("Leaf {escrow_script:?} is not in the tap tree"))
+                    })?;
+                Ok(ContextLeaf {
+                    escrow_script: *escrow_script,
+                    leaf_hash: TapLeafHash::from_script(&script, LeafVersion::TapScript),
+                    script,
+                    control_block,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        #[cfg(debug_assertions)]
+        trace!(address = %address, leaves = %leaves.len(), "escrow context computed");
+        Ok(Self {
+            config,
+            spend_info,
+            address,
+            leaves,
+        })
+    }
+
+    /// The escrow terms.
+    pub(crate) fn config(&self) -> &EscrowConfig {
+        &self.config
+    }
+
+    /// The [`TaprootSpendInfo`] of the escrow output.
+    pub(crate) fn spend_info(&self) -> &TaprootSpendInfo {
+        &self.spend_info
+    }
+
+    /// The escrow [`Address`].
+    pub(crate) fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// The given [`EscrowScript`] leaf.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow has no such leaf, i.e. `B` or `C` without an arbitrator.
+    pub(crate) fn leaf(&self, escrow_script: EscrowScript) -> Result<&ContextLeaf, Error> {
+        self.leaves
+            .iter()
+            .find(|leaf| leaf.escrow_script == escrow_script)
+            .ok_or_else(|| {
+                Error::WrongInputs(format!("Leaf {escrow_script:?} requires an arbitrator"))
+            })
+    }
+
+    /// The leaf whose locking script is `script`, if any.
+    pub(crate) fn find_leaf(&self, script: &Script) -> Option<&ContextLeaf> {
+        self.leaves
+            .iter()
+            .find(|leaf| leaf.script.as_script() == script)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -338,4 +756,135 @@ mod tests {
             "tb1paxkfvp7rra9707t8l2mk5mwuljrq6dgs0w6yey56q3d5gynp7u7s838an7".to_string()
         );
     }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn script_template_compatibility() {
+        let config = EscrowConfig {
+            npub_1: NostrPublicKey::from_str(KEY_A).unwrap(),
+            npub_2: NostrPublicKey::from_str(KEY_B).unwrap(),
+            npub_arbitrator: Some(NostrPublicKey::from_str(KEY_C).unwrap()),
+            timelock_duration: Some(100),
+            network: Network::Testnet,
+            template: ScriptTemplate::V1,
+        };
+        assert_eq!(
+            config.address().unwrap().to_string(),
+            "tb1paxkfvp7rra9707t8l2mk5mwuljrq6dgs0w6yey56q3d5gynp7u7s838an7"
+        );
+
+        // Configs predating script templates are V1.
+        let mut json = serde_json::to_value(config).unwrap();
+        assert_eq!(json["template"], 1);
+        json.as_object_mut().unwrap().remove("template");
+        let legacy: EscrowConfig = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(legacy, config);
+
+        json["template"] = 3.into();
+        assert!(serde_json::from_value::<EscrowConfig>(json).is_err());
+        assert!(matches!(
+            ScriptTemplate::try_from(3),
+            Err(Error::UnsupportedScriptTemplate(3))
+        ));
+    }
+
+    #[test]
+    fn musig_internal_key() {
+        let v1 = EscrowConfig {
+            npub_1: NostrPublicKey::from_str(KEY_A).unwrap(),
+            npub_2: NostrPublicKey::from_str(KEY_B).unwrap(),
+            npub_arbitrator: Some(NostrPublicKey::from_str(KEY_C).unwrap()),
+            timelock_duration: Some(100),
+            network: Network::Testnet,
+            template: ScriptTemplate::V1,
+        };
+        let v2 = EscrowConfig {
+            template: ScriptTemplate::V2,
+            ..v1
+        };
+        let (v1_info, v2_info) = (v1.spend_info().unwrap(), v2.spend_info().unwrap());
+        // Same leaves, but a spendable internal key.
+        assert_eq!(v1_info.merkle_root(), v2_info.merkle_root());
+        assert_eq!(v1_info.internal_key(), *UNSPENDABLE_PUBLIC_KEY);
+        assert_ne!(v1.address().unwrap(), v2.address().unwrap());
+        assert!(!v1.has_key_path() && v1.key_agg().is_err());
+
+        let key_agg = v2.key_agg().unwrap();
+        assert_eq!(key_agg.internal_key(), v2_info.internal_key());
+        assert_eq!(key_agg.output_key(), v2_info.output_key().to_inner());
+
+        // Collaborative escrows are unchanged.
+        let collaborative = EscrowConfig {
+            npub_arbitrator: None,
+            timelock_duration: None,
+            ..v2
+        };
+        assert_eq!(
+            collaborative.address().unwrap().to_string(),
+            "tb1pw9lk5k85v58rn2s8ccdxcp62khvqyj9rzdg6el5f5nagdfesv88sez0tc9"
+        );
+        assert!(collaborative.key_agg().is_err());
+    }
+
+    #[test]
+    fn stable_merkle_tree() {
+        let (npub_1, npub_2, npub_arb) = (
+            NostrPublicKey::from_str(KEY_A).unwrap(),
+            NostrPublicKey::from_str(KEY_B).unwrap(),
+            NostrPublicKey::from_str(KEY_C).unwrap(),
+        );
+
+        // Leaf insertion order does not change the tree.
+        let [a, b, c] = [EscrowScript::A, EscrowScript::B, EscrowScript::C].map(|leaf| {
+            escrow_scripts(&npub_1, &npub_2, Some(&npub_arb), Some(100), leaf).unwrap()
+        });
+        let root = |leaves: [&ScriptBuf; 3]| {
+            TaprootBuilder::new()
+                .add_leaf(1, leaves[0].clone())
+                .unwrap()
+                .add_leaf(2, leaves[1].clone())
+                .unwrap()
+                .add_leaf(2, leaves[2].clone())
+                .unwrap()
+                .finalize(SECP256K1, *UNSPENDABLE_PUBLIC_KEY)
+                .unwrap()
+                .merkle_root()
+        };
+        assert_eq!(root([&a, &b, &c]), root([&a, &c, &b]));
+
+        for template in [ScriptTemplate::V1, ScriptTemplate::V2] {
+            for npub_arbitrator in [None, Some(npub_arb)] {
+                let config = EscrowConfig {
+                    npub_1,
+                    npub_2,
+                    npub_arbitrator,
+                    timelock_duration: npub_arbitrator.map(|_| 100),
+                    network: Network::Testnet,
+                    template,
+                };
+                let swapped = EscrowConfig {
+                    npub_1: npub_2,
+                    npub_2: npub_1,
+                    ..config
+                };
+                assert_eq!(
+                    config.merkle_root().unwrap(),
+                    config.spend_info().unwrap().merkle_root()
+                );
+                // Leaf A depends on the order of the keys as given...
+                assert_ne!(config.address().unwrap(), swapped.address().unwrap());
+                // ...but not once they are sorted.
+                let sorted = config.with_key_ordering(KeyOrdering::Sorted);
+                assert_eq!(sorted, swapped.with_key_ordering(KeyOrdering::Sorted));
+                assert_eq!(
+                    sorted.address().unwrap(),
+                    swapped
+                        .with_key_ordering(KeyOrdering::Sorted)
+                        .address()
+                        .unwrap()
+                );
+                assert_eq!(config.with_key_ordering(KeyOrdering::AsGiven), config);
+            }
+        }
+    }
 }
@@ -0,0 +1,114 @@
+//! Secret key material that is wiped from memory after use.
+
+use std::fmt;
+
+use nostr::key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey};
+use secp256k1::{Keypair, SECP256K1, XOnlyPublicKey, constants::SECRET_KEY_SIZE};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::logging::REDACTED;
+
+/// A Nostr secret key that is zeroized when dropped.
+///
+/// It is not [`Clone`], so every copy of the secret has to be made explicitly
+/// with [`SecretNsec::duplicate`].
+/// Signing APIs take it by value, so the secret is wiped as soon as they are done.
+pub(crate) struct SecretNsec([u8; SECRET_KEY_SIZE]);
+
+impl SecretNsec {
+    /// Generates a random secret key.
+    pub(crate) fn generate() -> Self {
+        Self::from(NostrSecretKey::generate())
+    }
+
+    /// Generates a random secret key whose full public key has the given `parity`.
+    ///
+    /// Nostr keys are x-only, so the parity is otherwise invisible.
+    #[cfg(test)]
+    pub(crate) fn generate_with_parity(parity: secp256k1::Parity) -> Self {
+        loop {
+            let nsec = Self::generate();
+            if nsec.with_keypair(|keypair| keypair.x_only_public_key().1) == parity {
+                return nsec;
+            }
+        }
+    }
+
+    /// Runs `f` with the [`Keypair`] of the secret key, erasing the key pair afterwards.
+    pub(crate) fn with_keypair<T>(&self, f: impl FnOnce(&Keypair) -> T) -> T {
+        let mut keypair =
+            Keypair::from_seckey_slice(SECP256K1, &self.0).expect("secret key is always valid");
+        let result = f(&keypair);
+        keypair.non_secure_erase();
+        result
+    }
+
+    /// Runs `f` with the secret key as a [`NostrSecretKey`], for the `nostr` crate's APIs,
+    /// erasing the copy afterwards.
+    pub(crate) fn with_nostr_secret_key<T>(&self, f: impl FnOnce(&NostrSecretKey) -> T) -> T {
+        let nsec = NostrSecretKey::from_slice(&self.0).expect("secret key is always valid");
+        let result = f(&nsec);
+        // A `NostrSecretKey` erases its secret when dropped.
+        drop(nsec);
+        result
+    }
+
+    /// The [`XOnlyPublicKey`] of the secret key.
+    pub(crate) fn x_only_public_key(&self) -> XOnlyPublicKey {
+        self.with_keypair(|keypair| keypair.x_only_public_key().0)
+    }
+
+    /// The [`NostrPublicKey`] of the secret key.
+    pub(crate) fn public_key(&self) -> NostrPublicKey {
+        self.x_only_public_key().into()
+    }
+
+    /// An explicit copy of the secret key, zeroized independently.
+    pub(crate) fn duplicate(&self) -> Self {
+        Self(self.0)
+    }
+}
+
+impl From<NostrSecretKey> for SecretNsec {
+    fn from(nsec: NostrSecretKey) -> Self {
+        Self(nsec.secret_bytes())
+    }
+}
+
+impl Zeroize for SecretNsec {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretNsec {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SecretNsec {}
+
+impl fmt::Debug for SecretNsec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretNsec({REDACTED})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_nsec() {
+        let nsec = NostrSecretKey::generate();
+        let npub = NostrPublicKey::from(nsec.x_only_public_key(SECP256K1).0);
+        let mut secret = SecretNsec::from(nsec.clone());
+        assert_eq!(secret.public_key(), npub);
+        assert_eq!(secret.duplicate().public_key(), npub);
+        assert!(!format!("{secret:?}").contains(&nsec.to_secret_hex()));
+
+        secret.zeroize();
+        assert_eq!(secret.0, [0; SECRET_KEY_SIZE]);
+    }
+}
@@ -0,0 +1,461 @@
+//! Persisted user settings.
+//!
+//! [`Settings`] hold the preferences that outlive a session: the theme, the unit amounts
+//! are shown in, the network and relays the app starts with, and the [`FeeRateLimits`]
+//! every transaction builder enforces.
+//! They are saved in [`Storage`] under [`SETTINGS_KEY`], and the app's global signals
+//! are initialized from them, so every component picks them up reactively.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{Amount, FeeRate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    esplora::FeeEstimate,
+    i18n::Language,
+    invariants::DEFAULT_MAX_FEE_RATE,
+    network::Chain,
+    price::Price,
+    proxy::ProxySettings,
+    relays::{DEFAULT_RELAYS, parse_relays},
+    storage::Storage,
+    units::{Denomination, format_amount},
+};
+
+/// [`Storage`] key of the [`Settings`].
+pub(crate) const SETTINGS_KEY: &str = "scrow.settings";
+
+/// Color theme of the app.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Theme {
+    /// Follows the operating system's preference.
+    #[default]
+    System,
+    /// Light.
+    Light,
+    /// Dark.
+    Dark,
+}
+
+impl Theme {
+    /// All themes, in display order.
+    pub(crate) const ALL: [Theme; 3] = [Theme::System, Theme::Light, Theme::Dark];
+
+    /// Name of the theme in the settings.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Theme::System => "system",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+
+    /// CSS class of the app's root element, see `assets/theme.css`.
+    pub(crate) fn class(self) -> &'static str {
+        match self {
+            Theme::System => "theme-system",
+            Theme::Light => "theme-light",
+            Theme::Dark => "theme-dark",
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Theme::System => "System",
+            Theme::Light => "Light",
+            Theme::Dark => "Dark",
+        })
+    }
+}
+
+impl FromStr for Theme {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Theme::ALL
+            .into_iter()
+            .find(|theme| theme.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| Error::WrongInputs(format!("Unknown theme {s}")))
+    }
+}
+
+/// Unit amounts are shown in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DisplayUnit {
+    /// Bitcoins, such as `0.001 BTC`.
+    #[default]
+    Btc,
+    /// Satoshis, such as `100,000 sat`.
+    Sat,
+    /// Millisatoshis, such as `100,000,000 msat`.
+    Msat,
+    /// The fiat value at the escrow's price, such as `R$ 500.00 (0.001 BTC)`.
+    Fiat,
+}
+
+impl DisplayUnit {
+    /// All units, in display order.
+    pub(crate) const ALL: [DisplayUnit; 4] = [
+        DisplayUnit::Btc,
+        DisplayUnit::Sat,
+        DisplayUnit::Msat,
+        DisplayUnit::Fiat,
+    ];
+
+    /// Name of the unit in the settings.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            DisplayUnit::Btc => "btc",
+            DisplayUnit::Sat => "sat",
+            DisplayUnit::Msat => "msat",
+            DisplayUnit::Fiat => "fiat",
+        }
+    }
+
+    /// The [`Denomination`] of the bitcoin amount, shown next to the fiat value in fiat.
+    pub(crate) fn denomination(self) -> Denomination {
+        match self {
+            DisplayUnit::Btc | DisplayUnit::Fiat => Denomination::Btc,
+            DisplayUnit::Sat => Denomination::Sat,
+            DisplayUnit::Msat => Denomination::Msat,
+        }
+    }
+
+    /// Shows `amount` in the unit, formatted for `language`.
+    ///
+    /// Fiat needs a `price`: without one, the amount is shown in bitcoins.
+    pub(crate) fn format(
+        self,
+        amount: Amount,
+        price: Option<&Price>,
+        language: Language,
+    ) -> String {
+        let bitcoin = format_amount(amount, self.denomination(), language);
+        match (self, price) {
+            (DisplayUnit::Fiat, Some(price)) => format!("{} ({bitcoin})", price.to_fiat(amount)),
+            _ => bitcoin,
+        }
+    }
+}
+
+impl fmt::Display for DisplayUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DisplayUnit::Btc => "BTC",
+            DisplayUnit::Sat => "sat",
+            DisplayUnit::Msat => "msat",
+            DisplayUnit::Fiat => "Fiat",
+        })
+    }
+}
+
+impl FromStr for DisplayUnit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DisplayUnit::ALL
+            .into_iter()
+            .find(|unit| unit.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| Error::WrongInputs(format!("Unknown display unit {s}")))
+    }
+}
+
+/// Fee rate of transactions built without an estimate from Esplora, before the limits.
+const FALLBACK_FEE_RATE: FeeRate = FeeRate::from_sat_per_vb_unchecked(3);
+
+/// Bounds on the fee rates of the transactions the app builds, in sat/vB,
+/// and the confirmation target of the default fee rate.
+///
+/// The ceiling protects from typos draining an escrow into fees, and the floor from
+/// transactions too cheap to ever be relayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct FeeRateLimits {
+    /// Lowest fee rate accepted.
+    pub(crate) min_sat_per_vb: u64,
+    /// Highest fee rate accepted.
+    pub(crate) max_sat_per_vb: u64,
+    /// Blocks to confirm within, for the default fee rate.
+    pub(crate) confirmation_target: u16,
+}
+
+impl Default for FeeRateLimits {
+    fn default() -> Self {
+        Self {
+            min_sat_per_vb: 1,
+            max_sat_per_vb: DEFAULT_MAX_FEE_RATE.to_sat_per_vb_floor(),
+            confirmation_target: 3,
+        }
+    }
+}
+
+impl FeeRateLimits {
+    /// Lowest fee rate accepted.
+    pub(crate) fn min(&self) -> FeeRate {
+        FeeRate::from_sat_per_vb(self.min_sat_per_vb).unwrap_or(FeeRate::MAX)
+    }
+
+    /// Highest fee rate accepted.
+    pub(crate) fn max(&self) -> FeeRate {
+        FeeRate::from_sat_per_vb(self.max_sat_per_vb).unwrap_or(FeeRate::MAX)
+    }
+
+    /// Checks that the limits are positive and ordered.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.min_sat_per_vb == 0 || self.min_sat_per_vb > self.max_sat_per_vb {
+            return Err(Error::WrongInputs(format!(
+                "Fee rate limits of {} to {} sat/vB are invalid",
+                self.min_sat_per_vb, self.max_sat_per_vb
+            )));
+        }
+        if FeeRate::from_sat_per_vb(self.max_sat_per_vb).is_none() {
+            return Err(Error::WrongInputs(format!(
+                "Fee rate limit of {} sat/vB is too high",
+                self.max_sat_per_vb
+            )));
+        }
+        if self.confirmation_target == 0 {
+            return Err(Error::WrongInputs(
+                "The confirmation target must be at least one block".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that `fee_rate` is within the limits.
+    pub(crate) fn check(&self, fee_rate: FeeRate) -> Result<FeeRate, Error> {
+        if fee_rate < self.min() {
+            return Err(Error::WrongInputs(format!(
+                "Fee rate of {} sat/vB is below the minimum of {} sat/vB",
+                fee_rate.to_sat_per_vb_floor(),
+                self.min_sat_per_vb
+            )));
+        }
+        if fee_rate > self.max() {
+            return Err(Error::WrongInputs(format!(
+                "Fee rate of {} sat/vB is above the maximum of {} sat/vB",
+                fee_rate.to_sat_per_vb_ceil(),
+                self.max_sat_per_vb
+            )));
+        }
+        Ok(fee_rate)
+    }
+
+    /// Checks that `sat_per_vb`, as entered by the user, is within the limits.
+    pub(crate) fn check_sat_per_vb(&self, sat_per_vb: u64) -> Result<FeeRate, Error> {
+        let fee_rate = FeeRate::from_sat_per_vb(sat_per_vb)
+            .ok_or_else(|| Error::WrongInputs(format!("Invalid fee rate {sat_per_vb} sat/vB")))?;
+        self.check(fee_rate)
+    }
+
+    /// The fee rate to confirm within the confirmation target, within the limits.
+    ///
+    /// Uses the estimate of the longest target within the confirmation target, or of the
+    /// shortest target there is, and a fallback without `estimates`.
+    pub(crate) fn default_fee_rate(&self, estimates: Option<&FeeEstimate>) -> FeeRate {
+        let estimate = estimates.and_then(|estimates| {
+            estimates
+                .iter()
+                .filter(|(target, _)| **target <= self.confirmation_target)
+                .max_by_key(|(target, _)| **target)
+                .or_else(|| estimates.iter().min_by_key(|(target, _)| **target))
+                .map(|(_, sat_per_vb)| sat_per_vb.ceil() as u64)
+        });
+        estimate
+            .and_then(FeeRate::from_sat_per_vb)
+            .unwrap_or(FALLBACK_FEE_RATE)
+            .clamp(self.min(), self.max())
+    }
+}
+
+/// The user's settings.
+///
+/// Fields missing from older saved settings take their default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    /// Color theme.
+    pub(crate) theme: Theme,
+    /// Unit amounts are shown in.
+    pub(crate) display_unit: DisplayUnit,
+    /// Network the app starts on.
+    pub(crate) network: Chain,
+    /// Nostr relay URLs the app starts with.
+    pub(crate) relays: Vec<String>,
+    /// Fee rates the transaction builders accept.
+    pub(crate) fee_rates: FeeRateLimits,
+    /// SOCKS5 proxy configuration of the native builds, see [`crate::proxy`].
+    pub(crate) proxies: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            display_unit: DisplayUnit::default(),
+            network: Chain::default(),
+            relays: Vec::from(DEFAULT_RELAYS.map(str::to_string)),
+            fee_rates: FeeRateLimits::default(),
+            proxies: String::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings from `storage`, the defaults if none were saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(SETTINGS_KEY)? {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|e| Error::Storage(format!("Invalid settings: {e}"))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the settings to `storage`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a relay URL, the fee rate limits or the proxies are invalid,
+    /// see [`parse_relays`] and [`ProxySettings::parse`].
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        parse_relays(&self.relays.join("\n"))?;
+        self.fee_rates.validate()?;
+        ProxySettings::parse(&self.proxies)?;
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::Storage(format!("Could not serialize settings: {e}")))?;
+        storage.set(SETTINGS_KEY, &json)
+    }
+
+    /// The relays, one per line, as in the relays setting.
+    pub(crate) fn relays_config(&self) -> String {
+        self.relays.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn settings() {
+        let storage = MemoryStorage::default();
+        assert_eq!(Settings::load(&storage).unwrap(), Settings::default());
+
+        let settings = Settings {
+            theme: "Dark".parse().unwrap(),
+            display_unit: "sat".parse().unwrap(),
+            network: Chain::Signet,
+            relays: vec!["wss://relay.example.com".to_string()],
+            fee_rates: FeeRateLimits {
+                min_sat_per_vb: 2,
+                max_sat_per_vb: 200,
+                confirmation_target: 6,
+            },
+            proxies: "esplora = 127.0.0.1:9050".to_string(),
+        };
+        settings.save(&storage).unwrap();
+        assert_eq!(Settings::load(&storage).unwrap(), settings);
+        assert!(
+            Settings {
+                relays: vec!["https://relay.example.com".to_string()],
+                ..Settings::default()
+            }
+            .save(&storage)
+            .is_err()
+        );
+        assert!(
+            Settings {
+                proxies: "bitcoind = 127.0.0.1:9050".to_string(),
+                ..Settings::default()
+            }
+            .save(&storage)
+            .is_err()
+        );
+
+        // Settings saved by older versions keep working.
+        storage.set(SETTINGS_KEY, r#"{"theme":"light"}"#).unwrap();
+        let settings = Settings::load(&storage).unwrap();
+        assert_eq!(settings.theme, Theme::Light);
+        assert_eq!(settings.relays, Settings::default().relays);
+
+        let amount = Amount::from_sat(100_000);
+        assert_eq!(
+            DisplayUnit::Sat.format(amount, None, Language::En),
+            "100,000 sat"
+        );
+        assert_eq!(
+            DisplayUnit::Msat.format(amount, None, Language::PtBr),
+            "100.000.000 msat"
+        );
+        assert_eq!(
+            DisplayUnit::Fiat.format(amount, None, Language::En),
+            "0.001 BTC"
+        );
+        assert!("purple".parse::<Theme>().is_err());
+    }
+
+    #[test]
+    fn fee_rate_limits() {
+        let limits = FeeRateLimits {
+            min_sat_per_vb: 2,
+            max_sat_per_vb: 200,
+            confirmation_target: 6,
+        };
+        assert!(limits.check_sat_per_vb(1).is_err());
+        assert!(limits.check_sat_per_vb(201).is_err());
+        assert_eq!(
+            limits.check_sat_per_vb(200).unwrap(),
+            FeeRate::from_sat_per_vb_unchecked(200)
+        );
+
+        // The estimate of the longest target within 6 blocks, clamped to the limits.
+        let estimates = FeeEstimate::from([(1, 40.2), (3, 20.0), (6, 10.4), (144, 1.0)]);
+        let default = |limits: FeeRateLimits| limits.default_fee_rate(Some(&estimates));
+        assert_eq!(default(limits), FeeRate::from_sat_per_vb_unchecked(11));
+        assert_eq!(
+            default(FeeRateLimits {
+                confirmation_target: 144,
+                ..limits
+            }),
+            limits.min()
+        );
+        assert_eq!(
+            default(FeeRateLimits {
+                max_sat_per_vb: 5,
+                ..limits
+            }),
+            FeeRate::from_sat_per_vb_unchecked(5)
+        );
+        assert_eq!(limits.default_fee_rate(None), FALLBACK_FEE_RATE);
+
+        // Invalid limits are not saved.
+        let storage = MemoryStorage::default();
+        for fee_rates in [
+            FeeRateLimits {
+                min_sat_per_vb: 0,
+                ..limits
+            },
+            FeeRateLimits {
+                min_sat_per_vb: 300,
+                ..limits
+            },
+            FeeRateLimits {
+                confirmation_target: 0,
+                ..limits
+            },
+        ] {
+            let settings = Settings {
+                fee_rates,
+                ..Settings::default()
+            };
+            assert!(settings.save(&storage).is_err());
+        }
+    }
+}
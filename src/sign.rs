@@ -1,43 +1,68 @@
 //! Signs Taproot Transactions using Nostr keys.
 
 use bitcoin::{
-    Script, ScriptBuf, TapLeafHash, TapSighashType, Transaction, TxOut, Witness,
+    Script, ScriptBuf, TapLeafHash, TapSighashType, Transaction, TxOut, Txid, Witness,
     hashes::Hash,
     key::TapTweak,
     sighash::{Prevouts, SighashCache},
-    taproot::{LeafVersion, TaprootSpendInfo},
+    taproot::{self, ControlBlock, LeafVersion, TaprootSpendInfo},
 };
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::{error, trace};
-use nostr::key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey};
+use nostr::key::PublicKey as NostrPublicKey;
+#[cfg(feature = "serde-types")]
+use nostr::{Event, EventBuilder, Keys, Kind, Tag, key::SecretKey as NostrSecretKey};
 use secp256k1::{Message, SECP256K1, schnorr};
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::Error,
-    scripts::{EscrowScript, escrow_scripts},
+    accounts::Keystore,
+    error::{Error, ResultExt},
+    invariants::SigningInvariants,
+    keys::Npub,
+    protocol::SessionId,
+    scripts::{EscrowConfig, EscrowContext, EscrowScript, escrow_scripts},
+    secret::SecretNsec,
+    tx::ExpiredEscrow,
+    util::npub_to_x_only_public_key,
 };
+#[cfg(feature = "serde-types")]
+use crate::{
+    canonical,
+    protocol::{check_event, check_session_tag},
+};
+
+/// Version of the [`LeafSignatures`] format.
+pub(crate) const SIGNATURES_VERSION: u8 = 1;
 
-/// Signs a [`Transaction`] with the given [`NostrSecretKey`].
+/// Nostr event kind of [`LeafSignatures`] sent to the other signers.
+#[cfg(feature = "serde-types")]
+pub(crate) const SIGNATURES_KIND: u16 = 8_389;
+
+/// Signs a [`Transaction`] with the secret of `account`, unlocked in the `keystore`,
+/// once it satisfies the `invariants`.
 ///
 /// It must be a P2TR key path spend transaction with a single input as the 0th vout.
 pub(crate) fn sign_resolution_tx(
     transaction: &Transaction,
-    nsec: &NostrSecretKey,
-    prevout: TxOut,
-) -> Transaction {
-    // Parse nsec to a bitcoin secret key.
-    let keypair = nsec.keypair(SECP256K1);
-
-    let mut sighasher = SighashCache::new(transaction);
-    let sighash_type = TapSighashType::Default;
-    let sighash = sighasher
-        .taproot_key_spend_signature_hash(0, &Prevouts::All(&[prevout]), sighash_type)
-        .expect("must create sighash");
-    let message = Message::from_digest(*sighash.as_byte_array());
-
-    // For key path spend, we need to apply taproot tweak.
-    let tweaked = keypair.tap_tweak(SECP256K1, None);
-    let signature = SECP256K1.sign_schnorr_no_aux_rand(&message, &tweaked.to_inner());
+    keystore: &Keystore,
+    account: &Npub,
+    invariants: &SigningInvariants,
+) -> Result<Transaction, Error> {
+    if transaction.input.len() != 1 {
+        return Err(Error::WrongInputs(format!(
+            "Expected a single input, got {}",
+            transaction.input.len()
+        )));
+    }
+    invariants.check(transaction)?;
+    let signature = sign_key_spend(
+        transaction,
+        0,
+        &keystore.nsec(account)?,
+        &invariants.prevouts,
+    )?;
     #[cfg(debug_assertions)]
     trace!(signature = %signature, txid = %transaction.compute_txid(), "Signature resolution transaction");
     let mut transaction = transaction.clone();
@@ -47,27 +72,168 @@ pub(crate) fn sign_resolution_tx(
     witness.push(signature.as_ref());
 
     transaction.input[0].witness = witness;
-    transaction
+    Ok(transaction)
+}
+
+/// Signs the key path spend of input `index` of `tx` with the [`SecretNsec`]
+/// whose `npub` address holds the spent output.
+///
+/// `prevouts` must hold the outputs spent by every input of `tx`, in input order.
+pub(crate) fn sign_key_spend(
+    tx: &Transaction,
+    index: usize,
+    nsec: &SecretNsec,
+    prevouts: &[TxOut],
+) -> Result<schnorr::Signature, Error> {
+    let message = key_spend_message(tx, index, prevouts)?;
+
+    // For key path spend, we need to apply taproot tweak.
+    Ok(nsec.with_keypair(|keypair| {
+        let mut tweaked = keypair.tap_tweak(SECP256K1, None).to_inner();
+        let signature = SECP256K1.sign_schnorr_no_aux_rand(&message, &tweaked);
+        tweaked.non_secure_erase();
+        signature
+    }))
+}
+
+/// The sighash [`Message`] of a key path spend of input `index`,
+/// to be signed with [`sign_key_spend`], or cooperatively with MuSig2 for escrows
+/// with a key path, see [`EscrowConfig::key_agg`].
+pub(crate) fn key_spend_message(
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+) -> Result<Message, Error> {
+    let sighash = SighashCache::new(tx)
+        .taproot_key_spend_signature_hash(index, &Prevouts::All(prevouts), TapSighashType::Default)
+        .context(format!("computing key path sighash for input {index}"))?;
+    Ok(Message::from_digest(*sighash.as_byte_array()))
+}
+
+/// The sighash [`Message`] of a spend of input `index` through the `locking_script` leaf,
+/// as signed by [`BatchSigner::sign`].
+pub(crate) fn script_spend_message(
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    locking_script: &Script,
+) -> Result<Message, Error> {
+    let leaf_hash = TapLeafHash::from_script(locking_script, LeafVersion::TapScript);
+    let sighash = SighashCache::new(tx)
+        .taproot_script_spend_signature_hash(
+            index,
+            &Prevouts::All(prevouts),
+            leaf_hash,
+            TapSighashType::Default,
+        )
+        .context(format!("computing sighash for input {index}"))?;
+    Ok(Message::from_digest(*sighash.as_byte_array()))
 }
 
-/// Signs an escrow P2TR [`Transaction`], given an input `index` using a [`NostrSecretKey`].
+/// Checks every signature of `batch` like [`LeafSignatures::verify`], at once.
+///
+/// The sighashes of the inputs share one [`SighashCache`], and every signature is checked
+/// so all the invalid ones are reported together.
+///
+/// Signatures are verified one by one rather than with the BIP-340 batch equation:
+/// without multi-scalar multiplication in [`secp256k1`], a batch is slower at every size,
+/// see the `verify` benchmark.
 ///
-/// The input is signed using the provided [`NostrSecretKey`], `prevouts`, and [`ScriptBuf`] locking script.
+/// # Errors
+///
+/// Errors if any set is for another transaction or has a signature by a non-signer,
+/// or listing every signature that doesn't match its sighash.
+pub(crate) fn verify_leaf_signatures(
+    batch: &[LeafSignatures],
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    context: &EscrowContext,
+) -> Result<(), Error> {
+    let txid = tx.compute_txid();
+    let mut cache = SighashCache::new(tx);
+    let mut invalid = Vec::new();
+    for signatures in batch {
+        if signatures.txid != txid {
+            return Err(Error::Protocol(
+                "Signatures are for another transaction".to_string(),
+            ));
+        }
+        let signers = context.config().signers(signatures.escrow_script)?;
+        let leaf = context.leaf(signatures.escrow_script)?;
+        let leaf_hash = TapLeafHash::from_script(&leaf.script, LeafVersion::TapScript);
+        let sighash = cache
+            .taproot_script_spend_signature_hash(
+                signatures.input_index,
+                &Prevouts::All(prevouts),
+                leaf_hash,
+                TapSighashType::Default,
+            )
+            .context(format!(
+                "computing sighash for input {}",
+                signatures.input_index
+            ))?;
+        let message = Message::from_digest(*sighash.as_byte_array());
+        for SignerSignature { npub, signature } in &signatures.signatures {
+            if !signers.contains(npub) {
+                return Err(Error::Protocol(format!(
+                    "{npub} does not sign leaf {:?}",
+                    signatures.escrow_script
+                )));
+            }
+            if SECP256K1
+                .verify_schnorr(signature, &message, &npub_to_x_only_public_key(npub)?)
+                .is_err()
+            {
+                invalid.push(format!(
+                    "Signature of {npub} does not match the sighash of {txid}:{}",
+                    signatures.input_index
+                ));
+            }
+        }
+    }
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Protocol(invalid.join("; ")))
+    }
+}
+
+/// Sets the witness of input `index` to the key path spend `signature`,
+/// such as a MuSig2 aggregate signature over [`key_spend_message`].
+pub(crate) fn with_key_spend_signature(
+    tx: &Transaction,
+    index: usize,
+    signature: schnorr::Signature,
+) -> Result<Transaction, Error> {
+    let mut tx = tx.clone();
+    tx.input
+        .get_mut(index)
+        .ok_or_else(|| Error::WrongInputs(format!("Transaction has no input {index}")))?
+        .witness = Witness::p2tr_key_spend(&taproot::Signature {
+        signature,
+        sighash_type: TapSighashType::Default,
+    });
+    Ok(tx)
+}
+
+/// Signs an escrow P2TR [`Transaction`], given an input `index`, as `account`.
+///
+/// The input is signed using the secret of `account`, unlocked in the `keystore`,
+/// the prevouts of the `invariants`, and [`ScriptBuf`] locking script,
+/// once the transaction satisfies the `invariants`.
 #[expect(clippy::too_many_arguments)]
 pub(crate) fn sign_escrow_tx(
     tx: &Transaction,
     index: usize,
-    nsec: &NostrSecretKey,
+    keystore: &Keystore,
+    account: &Npub,
     npub_1: &NostrPublicKey,
     npub_2: &NostrPublicKey,
     npub_arbitrator: Option<&NostrPublicKey>,
     timelock_duration: Option<u32>,
-    prevouts: Vec<TxOut>,
+    invariants: &SigningInvariants,
     escrow_script: EscrowScript,
 ) -> Result<schnorr::Signature, Error> {
-    // Parse nsec to a bitcoin secret key.
-    let keypair = nsec.keypair(SECP256K1);
-
     // get which escrow type.
     let locking_script = escrow_scripts(
         npub_1,
@@ -78,73 +244,333 @@ pub(crate) fn sign_escrow_tx(
     )?;
     #[cfg(debug_assertions)]
     trace!(%index, locking_script = %locking_script.to_asm_string(), "escrow locking script");
-    let leaf_hash = TapLeafHash::from_script(&locking_script, LeafVersion::TapScript);
 
-    let sighash_type = TapSighashType::Default;
-    let mut sighash_cache = SighashCache::new(tx);
-    let sighash = sighash_cache
-        .taproot_script_spend_signature_hash(
-            index,
-            &Prevouts::All(&prevouts),
-            leaf_hash,
-            sighash_type,
-        )
-        .expect("fail to create sighash");
-    let message = Message::from_digest_slice(sighash.as_byte_array())?;
+    BatchSigner::new(tx, invariants)?.sign(index, &locking_script, &keystore.nsec(account)?)
+}
 
-    // For script path, we use the UNTWEAKED keypair.
-    let signature = SECP256K1.sign_schnorr_no_aux_rand(&message, &keypair);
-    #[cfg(debug_assertions)]
-    trace!(%index, %signature, txid = %tx.compute_txid(), "Signature escrow transaction");
+/// Signs several script path inputs of the same [`Transaction`].
+///
+/// Owns a single [`SighashCache`], so the transaction parts shared by all sighashes
+/// are only hashed once no matter how many inputs, leaves or keys are signed.
+/// The transaction is checked against the [`SigningInvariants`] once, on creation,
+/// and can't change while it is borrowed.
+pub(crate) struct BatchSigner<'a> {
+    /// The sighash cache over the transaction being signed.
+    sighash_cache: SighashCache<&'a Transaction>,
+    /// The outputs spent by every input, in input order.
+    prevouts: Vec<TxOut>,
+}
 
-    #[cfg(debug_assertions)]
-    {
-        let verification =
-            SECP256K1.verify_schnorr(&signature, &message, &keypair.x_only_public_key().0);
-        if verification.is_err() {
-            error!("Signature verification failed: {:?}", verification.err());
+impl<'a> BatchSigner<'a> {
+    /// Creates a new [`BatchSigner`] for `tx` spending the prevouts of the `invariants`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `tx` breaks any of the `invariants`,
+    /// such as not having exactly one prevout per input.
+    pub(crate) fn new(tx: &'a Transaction, invariants: &SigningInvariants) -> Result<Self, Error> {
+        invariants.check(tx)?;
+        Ok(Self {
+            sighash_cache: SighashCache::new(tx),
+            prevouts: invariants.prevouts.clone(),
+        })
+    }
+
+    /// Signs input `index` through the `locking_script` leaf using a [`SecretNsec`].
+    pub(crate) fn sign(
+        &mut self,
+        index: usize,
+        locking_script: &Script,
+        nsec: &SecretNsec,
+    ) -> Result<schnorr::Signature, Error> {
+        let leaf_hash = TapLeafHash::from_script(locking_script, LeafVersion::TapScript);
+        self.sign_leaf_hash(index, leaf_hash, nsec)
+    }
+
+    /// Signs input `index` through the `escrow_script` leaf of `context`,
+    /// reusing its precomputed leaf hash.
+    pub(crate) fn sign_leaf(
+        &mut self,
+        index: usize,
+        context: &EscrowContext,
+        escrow_script: EscrowScript,
+        nsec: &SecretNsec,
+    ) -> Result<schnorr::Signature, Error> {
+        let leaf_hash = context.leaf(escrow_script)?.leaf_hash;
+        self.sign_leaf_hash(index, leaf_hash, nsec)
+    }
+
+    /// Signs input `index` through the leaf with `leaf_hash`.
+    fn sign_leaf_hash(
+        &mut self,
+        index: usize,
+        leaf_hash: TapLeafHash,
+        nsec: &SecretNsec,
+    ) -> Result<schnorr::Signature, Error> {
+        let sighash_type = TapSighashType::Default;
+        let sighash = self
+            .sighash_cache
+            .taproot_script_spend_signature_hash(
+                index,
+                &Prevouts::All(&self.prevouts),
+                leaf_hash,
+                sighash_type,
+            )
+            .context(format!("computing sighash for input {index}"))?;
+        let message = Message::from_digest_slice(sighash.as_byte_array())?;
+
+        // For script path, we use the UNTWEAKED keypair.
+        let signature =
+            nsec.with_keypair(|keypair| SECP256K1.sign_schnorr_no_aux_rand(&message, keypair));
+        #[cfg(debug_assertions)]
+        trace!(%index, %signature, "Signature escrow transaction");
+
+        #[cfg(debug_assertions)]
+        {
+            let verification =
+                SECP256K1.verify_schnorr(&signature, &message, &nsec.x_only_public_key());
+            if verification.is_err() {
+                error!("Signature verification failed: {:?}", verification.err());
+            }
+            assert!(verification.is_ok());
         }
-        assert!(verification.is_ok());
+
+        Ok(signature)
     }
 
-    Ok(signature)
+    /// Signs every `(index, locking_script, nsec)` tuple, in order.
+    pub(crate) fn sign_all(
+        &mut self,
+        requests: &[(usize, &Script, &SecretNsec)],
+    ) -> Result<Vec<schnorr::Signature>, Error> {
+        requests
+            .iter()
+            .map(|(index, locking_script, nsec)| self.sign(*index, locking_script, nsec))
+            .collect()
+    }
 }
 
 /// Types of escrow transactions.
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub(crate) enum EscrowType<'a> {
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", rename_all = "snake_case")
+)]
+pub(crate) enum EscrowType {
     /// Collaborative escrow transaction.
     ///
     /// No timelocks and no arbitrator.
     Collaborative {
-        participant_1: &'a NostrPublicKey,
-        participant_2: &'a NostrPublicKey,
+        participant_1: NostrPublicKey,
+        participant_2: NostrPublicKey,
     },
 
     /// Dispute escrow transaction.
     ///
     /// Timelocked and with an arbitrator.
     Dispute {
-        participant_1: &'a NostrPublicKey,
-        participant_2: &'a NostrPublicKey,
-        arbitrator: &'a NostrPublicKey,
+        participant_1: NostrPublicKey,
+        participant_2: NostrPublicKey,
+        arbitrator: NostrPublicKey,
     },
 }
 
+/// A signature of an escrow leaf by one of its signers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct SignerSignature {
+    /// The signer.
+    pub(crate) npub: NostrPublicKey,
+    /// The signer's signature.
+    pub(crate) signature: schnorr::Signature,
+}
+
+/// Signatures of an escrow leaf spend, collected from its signers before
+/// [`combine_signatures`] builds the witness.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct LeafSignatures {
+    /// Format version, see [`SIGNATURES_VERSION`].
+    pub(crate) version: u8,
+    /// Negotiation the signatures belong to, if known.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub(crate) session_id: Option<SessionId>,
+    /// ID of the signed transaction.
+    pub(crate) txid: Txid,
+    /// Index of the signed input.
+    pub(crate) input_index: usize,
+    /// The leaf being spent.
+    pub(crate) escrow_script: EscrowScript,
+    /// The signatures collected so far, at most one per signer.
+    pub(crate) signatures: Vec<SignerSignature>,
+}
+
+impl LeafSignatures {
+    /// Creates an empty set of signatures of input `input_index` of `txid`.
+    pub(crate) fn new(txid: Txid, input_index: usize, escrow_script: EscrowScript) -> Self {
+        Self {
+            version: SIGNATURES_VERSION,
+            session_id: None,
+            txid,
+            input_index,
+            escrow_script,
+            signatures: Vec::new(),
+        }
+    }
+
+    /// Adds the signature of `npub`, replacing any previous one.
+    pub(crate) fn insert(&mut self, npub: NostrPublicKey, signature: schnorr::Signature) {
+        self.signatures.retain(|s| s.npub != npub);
+        self.signatures.push(SignerSignature { npub, signature });
+    }
+
+    /// Checks that every signature is from a signer of the leaf and signs the sighash
+    /// of the local `tx`, spending `prevouts`, through the leaf of `context`.
+    ///
+    /// Signatures replayed from another escrow or transaction, or over a transaction that
+    /// differs from the one built locally, don't match the sighash and are rejected.
+    pub(crate) fn verify(
+        &self,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        context: &EscrowContext,
+    ) -> Result<(), Error> {
+        verify_leaf_signatures(std::slice::from_ref(self), tx, prevouts, context)
+    }
+
+    /// Builds and signs the [`Event`] sending the signatures of the session to the other
+    /// `signers`, tagged with the session.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the signatures are not bound to a session.
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn to_event(
+        &self,
+        nsec: &NostrSecretKey,
+        signers: &[NostrPublicKey],
+    ) -> Result<Event, Error> {
+        let session_id = self
+            .session_id
+            .ok_or_else(|| Error::Protocol("Signatures are not bound to a session".to_string()))?;
+        let keys = Keys::new(nsec.clone());
+        let mut tags = vec![Tag::identifier(session_id.to_string())];
+        tags.extend(
+            signers
+                .iter()
+                .filter(|npub| **npub != keys.public_key())
+                .map(|npub| Tag::public_key(*npub)),
+        );
+        Ok(
+            EventBuilder::new(Kind::Custom(SIGNATURES_KIND), canonical::encode(self)?)
+                .tags(tags)
+                .sign_with_keys(&keys)?,
+        )
+    }
+
+    /// Parses a signatures [`Event`], checking it is tagged with their session.
+    ///
+    /// The signatures still have to be verified with [`LeafSignatures::verify`].
+    #[cfg(feature = "serde-types")]
+    pub(crate) fn from_event(event: &Event) -> Result<Self, Error> {
+        check_event(event, SIGNATURES_KIND)?;
+        let signatures: LeafSignatures = canonical::decode(&event.content)?;
+        let session_id = signatures
+            .session_id
+            .ok_or_else(|| Error::Protocol("Signatures are not bound to a session".to_string()))?;
+        check_session_tag(&event.tags, &session_id)?;
+        Ok(signatures)
+    }
+
+    /// The signatures in the witness order of the leaf in `config`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the format version is unsupported or a signer is missing.
+    pub(crate) fn ordered(&self, config: &EscrowConfig) -> Result<Vec<&schnorr::Signature>, Error> {
+        if self.version != SIGNATURES_VERSION {
+            return Err(Error::WrongInputs(format!(
+                "Unsupported signatures version {}",
+                self.version
+            )));
+        }
+        config
+            .signers(self.escrow_script)?
+            .iter()
+            .map(|npub| {
+                self.signatures
+                    .iter()
+                    .find(|s| s.npub == *npub)
+                    .map(|s| &s.signature)
+                    .ok_or_else(|| Error::WrongInputs(format!("Missing signature of {npub}")))
+            })
+            .collect()
+    }
+}
+
 /// Combine one multiple [`schnorr::Signature`]s into a single [`Transaction`] input.
+///
+/// Fails if the transaction has no input `index`
+/// or if `locking_script` is not a leaf of `taproot_spend_info`.
 pub(crate) fn combine_signatures(
     mut transaction: Transaction,
     index: usize,
     signatures: Vec<&schnorr::Signature>,
     locking_script: &Script,
     taproot_spend_info: &TaprootSpendInfo,
-) -> Transaction {
+) -> Result<Transaction, Error> {
+    if index >= transaction.input.len() {
+        return Err(Error::WrongInputs(format!(
+            "Transaction has no input {index}"
+        )));
+    }
     let prevout_leaf = (ScriptBuf::from(locking_script), LeafVersion::TapScript);
     let control_block = taproot_spend_info
         .control_block(&prevout_leaf)
-        .expect("Unable to create Control block");
+        .ok_or_else(|| Error::WrongInputs("Script is not a leaf of the escrow".to_string()))?;
+
+    transaction.input[index].witness = leaf_witness(signatures, &prevout_leaf.0, &control_block);
+
+    Ok(transaction)
+}
+
+/// Combines the collected `signatures` into their input of `transaction`,
+/// like [`combine_signatures`] but with the leaf script and control block
+/// precomputed in `context`.
+///
+/// Fails if the signatures are for another transaction or a leaf `context` doesn't have,
+/// or a signer is missing.
+pub(crate) fn combine_leaf_signatures(
+    mut transaction: Transaction,
+    signatures: &LeafSignatures,
+    context: &EscrowContext,
+) -> Result<Transaction, Error> {
+    let index = signatures.input_index;
+    if signatures.txid != transaction.compute_txid() || index >= transaction.input.len() {
+        return Err(Error::WrongInputs(
+            "Signatures are for another transaction".to_string(),
+        ));
+    }
+    let leaf = context.leaf(signatures.escrow_script)?;
+    transaction.input[index].witness = leaf_witness(
+        signatures.ordered(context.config())?,
+        &leaf.script,
+        &leaf.control_block,
+    );
+    Ok(transaction)
+}
 
+/// The script path witness `<signatures...> <locking script> <control block>`.
+pub(crate) fn leaf_witness(
+    signatures: Vec<&schnorr::Signature>,
+    locking_script: &Script,
+    control_block: &ControlBlock,
+) -> Witness {
     // Construct the witness stack
     let mut witness = Witness::new();
 
@@ -154,14 +580,97 @@ pub(crate) fn combine_signatures(
     }
 
     // Push locking script
-    witness.push(prevout_leaf.0.as_bytes());
+    witness.push(locking_script.as_bytes());
 
     // Push control block
     witness.push(control_block.serialize());
 
-    transaction.input[index].witness = witness;
+    witness
+}
 
-    transaction
+/// Signs every input of a sweep [`Transaction`] built by
+/// [`build_sweep_tx`](crate::tx::build_sweep_tx) using a [`SecretNsec`], consuming it,
+/// once it satisfies the `invariants`.
+///
+/// Each input is signed against the leaf of its [`ExpiredEscrow`]
+/// through a single [`BatchSigner`].
+/// Returns one [`schnorr::Signature`] per input, in input order.
+pub(crate) fn sign_sweep_tx(
+    tx: &Transaction,
+    nsec: SecretNsec,
+    escrows: &[ExpiredEscrow],
+    invariants: &SigningInvariants,
+) -> Result<Vec<schnorr::Signature>, Error> {
+    if tx.input.len() != escrows.len() {
+        return Err(Error::WrongInputs(format!(
+            "Expected {} escrows, got {}",
+            tx.input.len(),
+            escrows.len()
+        )));
+    }
+
+    let prevouts = escrows
+        .iter()
+        .map(ExpiredEscrow::prevout)
+        .collect::<Result<Vec<_>, _>>()?;
+    if prevouts != invariants.prevouts {
+        return Err(Error::WrongInputs(
+            "Escrows do not match the prevouts of the signing invariants".to_string(),
+        ));
+    }
+    let locking_scripts = escrows
+        .iter()
+        .map(ExpiredEscrow::locking_script)
+        .collect::<Result<Vec<_>, _>>()?;
+    let requests = locking_scripts
+        .iter()
+        .enumerate()
+        .map(|(index, locking_script)| (index, locking_script.as_script(), &nsec))
+        .collect::<Vec<_>>();
+
+    let signatures = BatchSigner::new(tx, invariants)?.sign_all(&requests)?;
+    #[cfg(debug_assertions)]
+    trace!(txid = %tx.compute_txid(), inputs = %signatures.len(), "Signature sweep transaction");
+
+    Ok(signatures)
+}
+
+/// Combines the participant's and the arbitrator's [`schnorr::Signature`]s into
+/// every input of a sweep [`Transaction`].
+///
+/// `participant_signatures` and `arbitrator_signatures` must be in input order,
+/// as returned by [`sign_sweep_tx`].
+pub(crate) fn combine_sweep_signatures(
+    mut transaction: Transaction,
+    escrows: &[ExpiredEscrow],
+    participant_signatures: &[schnorr::Signature],
+    arbitrator_signatures: &[schnorr::Signature],
+) -> Result<Transaction, Error> {
+    if participant_signatures.len() != escrows.len() || arbitrator_signatures.len() != escrows.len()
+    {
+        return Err(Error::WrongInputs(format!(
+            "Expected {} signatures from each signer",
+            escrows.len()
+        )));
+    }
+
+    for (index, escrow) in escrows.iter().enumerate() {
+        let locking_script = escrow.locking_script()?;
+        let taproot_spend_info = escrow.spend_info()?;
+        // The arbitrator key is checked first, so its signature goes on top of the stack.
+        transaction = combine_signatures(
+            transaction,
+            index,
+            vec![
+                &participant_signatures[index],
+                &arbitrator_signatures[index],
+            ],
+            &locking_script,
+            &taproot_spend_info,
+        )?;
+    }
+
+    Ok(transaction)
 }
 
 #[cfg(test)]
@@ -169,28 +678,28 @@ mod tests {
     use std::sync::{LazyLock, Once};
 
     use bitcoin::{
-        Amount, BlockHash, Network, OutPoint, TxIn, absolute, consensus, hex::DisplayHex,
+        Amount, Network, OutPoint, Sequence, TxIn, absolute, consensus, hex::DisplayHex,
         transaction,
     };
 
-    use corepc_node::Node;
     use dioxus::logger::tracing::{debug, info};
     use nostr::nips::nip21::NostrURI;
     use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
     use crate::{
-        scripts::{escrow_address, escrow_spend_info},
+        invariants::{ApprovedOutputs, leaf_timelock},
+        musig::{AggregateNonce, aggregate_signatures, generate_nonce, partial_sign},
+        scripts::{CURRENT_SCRIPT_TEMPLATE, ScriptTemplate, escrow_address, escrow_spend_info},
+        testkit::{COINBASE_AMOUNT, RegtestEscrowHarness},
         tx::escrow_tx,
         util::{npub_to_address, npub_to_x_only_public_key},
     };
 
     use super::*;
 
-    static COINBASE_AMOUNT: LazyLock<Amount> = LazyLock::new(|| Amount::from_btc(50.0).unwrap());
     const FEE: Amount = Amount::from_sat(1_000);
-    static MULTISIG_AMOUNT: LazyLock<Amount> = LazyLock::new(|| *COINBASE_AMOUNT - FEE);
+    static MULTISIG_AMOUNT: LazyLock<Amount> = LazyLock::new(|| COINBASE_AMOUNT - FEE);
     // static ESCROW_AMOUNT: LazyLock<Amount> = LazyLock::new(|| *MULTISIG_AMOUNT - FEE);
-    const COINBASE_MATURITY: usize = 101;
 
     // Generated by https://nostrtool.com
     // const NSEC_1: &str = "nsec1hufm8kzq0c4l9zsja7daynm47mfq2fkn38cm38yrpjmv6zctz2ysjmqw36";
@@ -198,9 +707,9 @@ mod tests {
     // const NSEC_2: &str = "nsec1svda3gyta75ny0t7aqqv9ldh0hazt89qc48jjgw8wkv5wy9w6fgq34wv4z";
     // const NPUB_2: &str = "npub1xy4xk87gglf4psv3lr7aymvs09e44fq0zxcf6kc43lawusvz3cts270an7";
 
-    fn generate_nostr_keys() -> (NostrSecretKey, NostrPublicKey) {
-        let nsec = NostrSecretKey::generate();
-        let npub: NostrPublicKey = nsec.public_key(SECP256K1).x_only_public_key().0.into();
+    fn generate_nostr_keys() -> (SecretNsec, NostrPublicKey) {
+        let nsec = SecretNsec::generate();
+        let npub = nsec.public_key();
         trace!(derived_npub = %npub.to_nostr_uri().unwrap());
         (nsec, npub)
     }
@@ -222,87 +731,26 @@ mod tests {
     fn sign_collaborative_tx_flow() {
         init_tracing();
 
-        // Setup regtest node and clients.
-        let bitcoind = Node::from_downloaded().unwrap();
-        let btc_client = &bitcoind.client;
-
-        // Get network.
-        let network = btc_client
-            .get_blockchain_info()
-            .expect("must get blockchain info")
-            .chain;
-        let network = network.parse::<Network>().expect("network must be valid");
+        // Setup regtest node.
+        let harness = RegtestEscrowHarness::new();
+        let network = harness.network();
 
         // Generate nsec and npub.
         let (nsec_1, npub_1) = generate_nostr_keys();
         let (nsec_2, npub_2) = generate_nostr_keys();
         // Get the xonly pks.
-        let xonly_1 = nsec_1.x_only_public_key(SECP256K1).0;
-        let xonly_2 = nsec_2.x_only_public_key(SECP256K1).0;
+        let xonly_1 = nsec_1.x_only_public_key();
+        let xonly_2 = nsec_2.x_only_public_key();
         trace!(%xonly_1, %xonly_2, "xonly pks");
 
-        // Fund a SegWit-v1 P2TR address from the npub.
-        // Mine until maturity (101 blocks in Regtest).
-        let funded_address = npub_to_address(&npub_1, network).unwrap();
-        #[cfg(debug_assertions)]
-        trace!(%funded_address, "Funded address");
-        let coinbase_block = btc_client
-            .generate_to_address(COINBASE_MATURITY, &funded_address)
-            .expect("must be able to generate blocks")
-            .0
-            .first()
-            .expect("must be able to get the blocks")
-            .parse::<BlockHash>()
-            .expect("must parse");
-        let coinbase_txid = btc_client
-            .get_block(coinbase_block)
-            .expect("must be able to get coinbase block")
-            .coinbase()
-            .expect("must be able to get the coinbase transaction")
-            .compute_txid();
-
         // Send to the 2-of-2 multisig address.
         let escrow_address = escrow_address(&npub_1, &npub_2, None, None, network).unwrap();
         #[cfg(debug_assertions)]
         trace!(%escrow_address, "Escrow address");
 
-        // Create the transaction.
-        let funding_input = OutPoint {
-            txid: coinbase_txid,
-            vout: 0,
-        };
-        let inputs = vec![TxIn {
-            previous_output: funding_input,
-            ..Default::default()
-        }];
-        let outputs = vec![TxOut {
-            value: *MULTISIG_AMOUNT,
-            script_pubkey: escrow_address.script_pubkey(),
-        }];
-        let unsigned = Transaction {
-            version: transaction::Version(2),
-            input: inputs,
-            output: outputs,
-            lock_time: absolute::LockTime::ZERO,
-        };
-        trace!(transaction=%consensus::serialize(&unsigned).as_hex(), "Unsigned funding transaction");
-
-        // Sign the first input using Sighashes
-        let prevouts = TxOut {
-            value: *COINBASE_AMOUNT,
-            script_pubkey: funded_address.script_pubkey(),
-        };
-        let signed = sign_resolution_tx(&unsigned, &nsec_1, prevouts);
-        trace!(transaction=%consensus::serialize(&signed).as_hex(), "Signed funding");
-
-        // Test if the transaction is valid.
-        let result = btc_client.send_raw_transaction(&signed);
-        assert!(result.is_ok());
-        let txid = result.unwrap().txid().unwrap();
-        assert_eq!(txid, signed.compute_txid());
+        // Fund it from a coinbase and mine the funding transaction.
+        let txid = harness.fund_escrow(&nsec_1, &escrow_address, *MULTISIG_AMOUNT);
         debug!(%txid, "Sent to the escrow address");
-        // Mine 1 block to mine the transaction
-        btc_client.generate_to_address(1, &funded_address).unwrap();
 
         // Spend from the escrow address.
         let escrow_type = EscrowScript::A;
@@ -315,6 +763,7 @@ mod tests {
             txid,
             FEE,
             network,
+            absolute::LockTime::ZERO,
         )
         .unwrap();
         trace!(transaction=%consensus::serialize(&unsigned).as_hex(), "Unsigned escrow transaction");
@@ -324,27 +773,37 @@ mod tests {
             value: *MULTISIG_AMOUNT,
             script_pubkey,
         };
+        let invariants = SigningInvariants::of_local_tx(
+            &unsigned,
+            vec![prevouts.clone()],
+            vec![leaf_timelock(escrow_type, None).unwrap()],
+        );
+        let mut keystore = Keystore::default();
+        let account_1 = keystore.unlock(nsec_1.duplicate());
+        let account_2 = keystore.unlock(nsec_2.duplicate());
         let sig_1 = sign_escrow_tx(
             &unsigned,
             0,
-            &nsec_1,
+            &keystore,
+            &account_1,
             &npub_1,
             &npub_2,
             None,
             None,
-            vec![prevouts.clone()],
+            &invariants,
             escrow_type,
         )
         .unwrap();
         let sig_2 = sign_escrow_tx(
             &unsigned,
             0,
-            &nsec_2,
+            &keystore,
+            &account_2,
             &npub_1,
             &npub_2,
             None,
             None,
-            vec![prevouts.clone()],
+            &invariants,
             escrow_type,
         )
         .unwrap();
@@ -356,7 +815,7 @@ mod tests {
         let sighash = SighashCache::new(&unsigned)
             .taproot_script_spend_signature_hash(
                 0,
-                &Prevouts::All(&[prevouts.clone()]),
+                &Prevouts::All(std::slice::from_ref(&prevouts)),
                 tap_leaf_hash,
                 TapSighashType::Default,
             )
@@ -379,57 +838,31 @@ mod tests {
             vec![&sig_1, &sig_2],
             &locking_script,
             &taproot_spend_info,
-        );
+        )
+        .unwrap();
         trace!(transaction=%consensus::serialize(&signed).as_hex(), "Signed escrow");
         info!(total_size=%signed.total_size(), "Signed Script A resolution transaction");
-        let result = btc_client.send_raw_transaction(&signed);
-        assert!(result.is_ok());
+        harness.assert_accepted(&signed);
     }
 
     #[test]
     fn sign_dispute_tx_flow_1() {
         init_tracing();
 
-        // Setup regtest node and clients.
-        let bitcoind = Node::from_downloaded().unwrap();
-        let btc_client = &bitcoind.client;
-
-        // Get network.
-        let network = btc_client
-            .get_blockchain_info()
-            .expect("must get blockchain info")
-            .chain;
-        let network = network.parse::<Network>().expect("network must be valid");
+        // Setup regtest node.
+        let harness = RegtestEscrowHarness::new();
+        let network = harness.network();
 
         // Generate nsec and npub.
         let (nsec_1, npub_1) = generate_nostr_keys();
         let (nsec_2, npub_2) = generate_nostr_keys();
         let (nsec_arb, npub_arb) = generate_nostr_keys();
         // Get the xonly pks.
-        let xonly_1 = nsec_1.x_only_public_key(SECP256K1).0;
-        let xonly_2 = nsec_2.x_only_public_key(SECP256K1).0;
-        let xonly_arb = nsec_arb.x_only_public_key(SECP256K1).0;
+        let xonly_1 = nsec_1.x_only_public_key();
+        let xonly_2 = nsec_2.x_only_public_key();
+        let xonly_arb = nsec_arb.x_only_public_key();
         trace!(%xonly_1, %xonly_2, %xonly_arb, "xonly pks");
 
-        // Fund a SegWit-v1 P2TR address from the npub.
-        // Mine until maturity (101 blocks in Regtest).
-        let funded_address = npub_to_address(&npub_1, network).unwrap();
-        trace!(%funded_address, "Funded address");
-        let coinbase_block = btc_client
-            .generate_to_address(COINBASE_MATURITY, &funded_address)
-            .expect("must be able to generate blocks")
-            .0
-            .first()
-            .expect("must be able to get the blocks")
-            .parse::<BlockHash>()
-            .expect("must parse");
-        let coinbase_txid = btc_client
-            .get_block(coinbase_block)
-            .expect("must be able to get coinbase block")
-            .coinbase()
-            .expect("must be able to get the coinbase transaction")
-            .compute_txid();
-
         // Send to the 2-of-2 multisig address.
         let timelock_duration = 6;
         let escrow_address = escrow_address(
@@ -442,44 +875,9 @@ mod tests {
         .unwrap();
         trace!(%escrow_address, "Escrow address");
 
-        // Create the transaction.
-        let funding_input = OutPoint {
-            txid: coinbase_txid,
-            vout: 0,
-        };
-        let inputs = vec![TxIn {
-            previous_output: funding_input,
-            ..Default::default()
-        }];
-        let outputs = vec![TxOut {
-            value: *MULTISIG_AMOUNT,
-            script_pubkey: escrow_address.script_pubkey(),
-        }];
-        let unsigned = Transaction {
-            version: transaction::Version(2),
-            input: inputs,
-            output: outputs,
-            lock_time: absolute::LockTime::ZERO,
-        };
-        trace!(transaction=%consensus::serialize(&unsigned).as_hex(), "Unsigned funding transaction");
-
-        // Sign the first input using Sighashes
-        let prevouts = TxOut {
-            value: *COINBASE_AMOUNT,
-            script_pubkey: funded_address.script_pubkey(),
-        };
-        let signed = sign_resolution_tx(&unsigned, &nsec_1, prevouts);
-        info!(total_size=%signed.total_size(), "Signed Script B resolution transaction");
-        trace!(transaction=%consensus::serialize(&signed).as_hex(), "Signed funding");
-
-        // Test if the transaction is valid.
-        let result = btc_client.send_raw_transaction(&signed);
-        assert!(result.is_ok());
-        let txid = result.unwrap().txid().unwrap();
-        assert_eq!(txid, signed.compute_txid());
+        // Fund it from a coinbase and mine the funding transaction.
+        let txid = harness.fund_escrow(&nsec_1, &escrow_address, *MULTISIG_AMOUNT);
         debug!(%txid, "Sent to the escrow address");
-        // Mine 1 block to mine the transaction
-        btc_client.generate_to_address(1, &funded_address).unwrap();
 
         // Spend from the escrow address.
         let escrow_type = EscrowScript::B;
@@ -492,6 +890,7 @@ mod tests {
             txid,
             FEE,
             network,
+            absolute::LockTime::ZERO,
         )
         .unwrap();
         trace!(transaction=%consensus::serialize(&unsigned).as_hex(), "Unsigned escrow transaction");
@@ -501,27 +900,37 @@ mod tests {
             value: *MULTISIG_AMOUNT,
             script_pubkey,
         };
+        let invariants = SigningInvariants::of_local_tx(
+            &unsigned,
+            vec![prevouts.clone()],
+            vec![leaf_timelock(escrow_type, Some(timelock_duration)).unwrap()],
+        );
+        let mut keystore = Keystore::default();
+        let account_1 = keystore.unlock(nsec_1.duplicate());
+        let account_arb = keystore.unlock(nsec_arb.duplicate());
         let sig_1 = sign_escrow_tx(
             &unsigned,
             0,
-            &nsec_1, // First participant
+            &keystore,
+            &account_1, // First participant
             &npub_1,
             &npub_2,
             Some(&npub_arb),
             Some(timelock_duration),
-            vec![prevouts.clone()],
+            &invariants,
             escrow_type,
         )
         .unwrap();
         let sig_2 = sign_escrow_tx(
             &unsigned,
             0,
-            &nsec_arb, // Arbitrator
+            &keystore,
+            &account_arb, // Arbitrator
             &npub_1,
             &npub_2,
             Some(&npub_arb),
             Some(timelock_duration),
-            vec![prevouts.clone()],
+            &invariants,
             escrow_type,
         )
         .unwrap();
@@ -540,7 +949,7 @@ mod tests {
         let sighash = SighashCache::new(&unsigned)
             .taproot_script_spend_signature_hash(
                 0,
-                &Prevouts::All(&[prevouts.clone()]),
+                &Prevouts::All(std::slice::from_ref(&prevouts)),
                 tap_leaf_hash,
                 TapSighashType::Default,
             )
@@ -564,65 +973,36 @@ mod tests {
             vec![&sig_1, &sig_2],
             &locking_script,
             &taproot_spend_info,
-        );
+        )
+        .unwrap();
         trace!(transaction=%consensus::serialize(&signed).as_hex(), "Signed escrow");
 
         // First try to broadcast the transaction without the timelock has reached
-        let result = btc_client.send_raw_transaction(&signed);
-        assert!(result.is_err());
+        harness.assert_rejected(&signed);
 
         // Now let's move timelock_duration - 1 blocks and should pass
-        btc_client
-            .generate_to_address(timelock_duration as usize - 1, &funded_address)
-            .unwrap();
-        let result = btc_client.send_raw_transaction(&signed);
-        assert!(result.is_ok());
+        harness.mine(timelock_duration as usize - 1);
+        harness.assert_accepted(&signed);
     }
 
     #[test]
     fn sign_dispute_tx_flow_2() {
         init_tracing();
 
-        // Setup regtest node and clients.
-        let bitcoind = Node::from_downloaded().unwrap();
-        let btc_client = &bitcoind.client;
-
-        // Get network.
-        let network = btc_client
-            .get_blockchain_info()
-            .expect("must get blockchain info")
-            .chain;
-        let network = network.parse::<Network>().expect("network must be valid");
+        // Setup regtest node.
+        let harness = RegtestEscrowHarness::new();
+        let network = harness.network();
 
         // Generate nsec and npub.
         let (nsec_1, npub_1) = generate_nostr_keys();
         let (nsec_2, npub_2) = generate_nostr_keys();
         let (nsec_arb, npub_arb) = generate_nostr_keys();
         // Get the xonly pks.
-        let xonly_1 = nsec_1.x_only_public_key(SECP256K1).0;
-        let xonly_2 = nsec_2.x_only_public_key(SECP256K1).0;
-        let xonly_arb = nsec_arb.x_only_public_key(SECP256K1).0;
+        let xonly_1 = nsec_1.x_only_public_key();
+        let xonly_2 = nsec_2.x_only_public_key();
+        let xonly_arb = nsec_arb.x_only_public_key();
         trace!(%xonly_1, %xonly_2, %xonly_arb, "xonly pks");
 
-        // Fund a SegWit-v1 P2TR address from the npub.
-        // Mine until maturity (101 blocks in Regtest).
-        let funded_address = npub_to_address(&npub_1, network).unwrap();
-        trace!(%funded_address, "Funded address");
-        let coinbase_block = btc_client
-            .generate_to_address(COINBASE_MATURITY, &funded_address)
-            .expect("must be able to generate blocks")
-            .0
-            .first()
-            .expect("must be able to get the blocks")
-            .parse::<BlockHash>()
-            .expect("must parse");
-        let coinbase_txid = btc_client
-            .get_block(coinbase_block)
-            .expect("must be able to get coinbase block")
-            .coinbase()
-            .expect("must be able to get the coinbase transaction")
-            .compute_txid();
-
         // Send to the 2-of-2 multisig address.
         let timelock_duration = 6;
         let escrow_address = escrow_address(
@@ -635,44 +1015,9 @@ mod tests {
         .unwrap();
         trace!(%escrow_address, "Escrow address");
 
-        // Create the transaction.
-        let funding_input = OutPoint {
-            txid: coinbase_txid,
-            vout: 0,
-        };
-        let inputs = vec![TxIn {
-            previous_output: funding_input,
-            ..Default::default()
-        }];
-        let outputs = vec![TxOut {
-            value: *MULTISIG_AMOUNT,
-            script_pubkey: escrow_address.script_pubkey(),
-        }];
-        let unsigned = Transaction {
-            version: transaction::Version(2),
-            input: inputs,
-            output: outputs,
-            lock_time: absolute::LockTime::ZERO,
-        };
-        trace!(transaction=%consensus::serialize(&unsigned).as_hex(), "Unsigned funding transaction");
-
-        // Sign the first input using Sighashes
-        let prevouts = TxOut {
-            value: *COINBASE_AMOUNT,
-            script_pubkey: funded_address.script_pubkey(),
-        };
-        let signed = sign_resolution_tx(&unsigned, &nsec_1, prevouts);
-        trace!(transaction=%consensus::serialize(&signed).as_hex(), "Signed funding");
-        info!(total_size=%signed.total_size(), "Signed Script C resolution transaction");
-
-        // Test if the transaction is valid.
-        let result = btc_client.send_raw_transaction(&signed);
-        assert!(result.is_ok());
-        let txid = result.unwrap().txid().unwrap();
-        assert_eq!(txid, signed.compute_txid());
+        // Fund it from a coinbase and mine the funding transaction.
+        let txid = harness.fund_escrow(&nsec_1, &escrow_address, *MULTISIG_AMOUNT);
         debug!(%txid, "Sent to the escrow address");
-        // Mine 1 block to mine the transaction
-        btc_client.generate_to_address(1, &funded_address).unwrap();
 
         // Spend from the escrow address.
         let escrow_type = EscrowScript::C;
@@ -685,6 +1030,7 @@ mod tests {
             txid,
             FEE,
             network,
+            absolute::LockTime::ZERO,
         )
         .unwrap();
         trace!(transaction=%consensus::serialize(&unsigned).as_hex(), "Unsigned escrow transaction");
@@ -694,27 +1040,37 @@ mod tests {
             value: *MULTISIG_AMOUNT,
             script_pubkey,
         };
+        let invariants = SigningInvariants::of_local_tx(
+            &unsigned,
+            vec![prevouts.clone()],
+            vec![leaf_timelock(escrow_type, Some(timelock_duration)).unwrap()],
+        );
+        let mut keystore = Keystore::default();
+        let account_2 = keystore.unlock(nsec_2.duplicate());
+        let account_arb = keystore.unlock(nsec_arb.duplicate());
         let sig_1 = sign_escrow_tx(
             &unsigned,
             0,
-            &nsec_2, // Second participant
+            &keystore,
+            &account_2, // Second participant
             &npub_1,
             &npub_2,
             Some(&npub_arb),
             Some(timelock_duration),
-            vec![prevouts.clone()],
+            &invariants,
             escrow_type,
         )
         .unwrap();
         let sig_2 = sign_escrow_tx(
             &unsigned,
             0,
-            &nsec_arb, // Arbitrator
+            &keystore,
+            &account_arb, // Arbitrator
             &npub_1,
             &npub_2,
             Some(&npub_arb),
             Some(timelock_duration),
-            vec![prevouts.clone()],
+            &invariants,
             escrow_type,
         )
         .unwrap();
@@ -733,7 +1089,7 @@ mod tests {
         let sighash = SighashCache::new(&unsigned)
             .taproot_script_spend_signature_hash(
                 0,
-                &Prevouts::All(&[prevouts.clone()]),
+                &Prevouts::All(std::slice::from_ref(&prevouts)),
                 tap_leaf_hash,
                 TapSighashType::Default,
             )
@@ -757,18 +1113,341 @@ mod tests {
             vec![&sig_1, &sig_2],
             &locking_script,
             &taproot_spend_info,
-        );
+        )
+        .unwrap();
         trace!(transaction=%consensus::serialize(&signed).as_hex(), "Signed escrow");
 
         // First try to broadcast the transaction without the timelock has reached
-        let result = btc_client.send_raw_transaction(&signed);
-        assert!(result.is_err());
+        harness.assert_rejected(&signed);
 
         // Now let's move timelock_duration - 1 blocks and should pass
-        btc_client
-            .generate_to_address(timelock_duration as usize - 1, &funded_address)
+        harness.mine(timelock_duration as usize - 1);
+        harness.assert_accepted(&signed);
+    }
+
+    #[test]
+    fn sign_sweep_tx_signatures_verify() {
+        init_tracing();
+
+        let (nsec_1, npub_1) = generate_nostr_keys();
+        let (_, npub_2) = generate_nostr_keys();
+        let (nsec_arb, npub_arb) = generate_nostr_keys();
+        let funding_txid = "602ae1accd9626bde16d19cbe8663cbe37a4e95839d0cddb10b84dcc82f07799"
+            .parse::<bitcoin::Txid>()
             .unwrap();
-        let result = btc_client.send_raw_transaction(&signed);
-        assert!(result.is_ok());
+        let escrows = (0..3)
+            .map(|vout| ExpiredEscrow {
+                npub_1,
+                npub_2,
+                npub_arbitrator: npub_arb,
+                timelock_duration: 6 + vout,
+                outpoint: OutPoint::new(funding_txid, vout),
+                amount: *MULTISIG_AMOUNT,
+                escrow_script: EscrowScript::B,
+                template: CURRENT_SCRIPT_TEMPLATE,
+            })
+            .collect::<Vec<_>>();
+        let destination = npub_to_address(&npub_1, Network::Regtest).unwrap();
+        let unsigned = crate::tx::build_sweep_tx(
+            &escrows,
+            &destination,
+            bitcoin::FeeRate::from_sat_per_vb(1).unwrap(),
+        )
+        .unwrap();
+
+        let prevouts = escrows
+            .iter()
+            .map(|escrow| escrow.prevout().unwrap())
+            .collect::<Vec<_>>();
+        let invariants = SigningInvariants {
+            funding_outpoints: escrows.iter().map(|escrow| escrow.outpoint).collect(),
+            prevouts: prevouts.clone(),
+            approved_outputs: ApprovedOutputs::Destinations(vec![destination.script_pubkey()]),
+            max_fee: Amount::from_sat(10_000),
+            lock_time: absolute::LockTime::ZERO,
+            timelocks: escrows
+                .iter()
+                .map(|escrow| Some(escrow.timelock_duration))
+                .collect(),
+        };
+        let sigs_1 = sign_sweep_tx(&unsigned, nsec_1.duplicate(), &escrows, &invariants).unwrap();
+        let sigs_arb =
+            sign_sweep_tx(&unsigned, nsec_arb.duplicate(), &escrows, &invariants).unwrap();
+        assert_eq!(sigs_1.len(), 3);
+
+        // Outputs other than the approved destination are refused.
+        let elsewhere = SigningInvariants {
+            approved_outputs: ApprovedOutputs::Destinations(vec![]),
+            ..invariants
+        };
+        assert!(sign_sweep_tx(&unsigned, nsec_1.duplicate(), &escrows, &elsewhere).is_err());
+
+        // Manually verify each signature against its own leaf.
+        let xonly_1 = npub_to_x_only_public_key(&npub_1).unwrap();
+        let xonly_arb = npub_to_x_only_public_key(&npub_arb).unwrap();
+        for (index, escrow) in escrows.iter().enumerate() {
+            let leaf_hash =
+                TapLeafHash::from_script(&escrow.locking_script().unwrap(), LeafVersion::TapScript);
+            let sighash = SighashCache::new(&unsigned)
+                .taproot_script_spend_signature_hash(
+                    index,
+                    &Prevouts::All(&prevouts),
+                    leaf_hash,
+                    TapSighashType::Default,
+                )
+                .unwrap();
+            let message = Message::from_digest_slice(sighash.as_byte_array()).unwrap();
+            assert!(
+                SECP256K1
+                    .verify_schnorr(&sigs_1[index], &message, &xonly_1)
+                    .is_ok()
+            );
+            assert!(
+                SECP256K1
+                    .verify_schnorr(&sigs_arb[index], &message, &xonly_arb)
+                    .is_ok()
+            );
+        }
+
+        let signed = combine_sweep_signatures(unsigned, &escrows, &sigs_1, &sigs_arb).unwrap();
+        assert!(signed.input.iter().all(|txin| txin.witness.len() == 4));
+    }
+
+    #[test]
+    fn batch_signer_matches_single_signatures() {
+        init_tracing();
+
+        let (nsec_1, npub_1) = generate_nostr_keys();
+        let (nsec_2, npub_2) = generate_nostr_keys();
+        let (nsec_arb, npub_arb) = generate_nostr_keys();
+        let funding_txid = "602ae1accd9626bde16d19cbe8663cbe37a4e95839d0cddb10b84dcc82f07799"
+            .parse::<bitcoin::Txid>()
+            .unwrap();
+        let locking_script = |escrow_script| {
+            escrow_scripts(&npub_1, &npub_2, Some(&npub_arb), Some(6), escrow_script).unwrap()
+        };
+        let prevout = TxOut {
+            value: *MULTISIG_AMOUNT,
+            script_pubkey: escrow_address(
+                &npub_1,
+                &npub_2,
+                Some(&npub_arb),
+                Some(6),
+                Network::Regtest,
+            )
+            .unwrap()
+            .script_pubkey(),
+        };
+        let tx = Transaction {
+            version: transaction::Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: (0..2)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(funding_txid, vout),
+                    sequence: Sequence::from_height(6),
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![prevout.clone()],
+        };
+        let prevouts = vec![prevout.clone(), prevout];
+        let invariants = SigningInvariants::of_local_tx(&tx, prevouts.clone(), vec![None, Some(6)]);
+
+        let script_a = locking_script(EscrowScript::A);
+        let script_c = locking_script(EscrowScript::C);
+        let requests = [
+            (0, script_a.as_script(), &nsec_1),
+            (0, script_a.as_script(), &nsec_2),
+            (1, script_c.as_script(), &nsec_2),
+            (1, script_c.as_script(), &nsec_arb),
+        ];
+        let signatures = BatchSigner::new(&tx, &invariants)
+            .unwrap()
+            .sign_all(&requests)
+            .unwrap();
+
+        let mut keystore = Keystore::default();
+        for nsec in [&nsec_1, &nsec_2, &nsec_arb] {
+            keystore.unlock(nsec.duplicate());
+        }
+        let single = requests
+            .iter()
+            .map(|(index, _, nsec)| {
+                let escrow_script = if *index == 0 {
+                    EscrowScript::A
+                } else {
+                    EscrowScript::C
+                };
+                sign_escrow_tx(
+                    &tx,
+                    *index,
+                    &keystore,
+                    &Npub::from(nsec.public_key()),
+                    &npub_1,
+                    &npub_2,
+                    Some(&npub_arb),
+                    Some(6),
+                    &invariants,
+                    escrow_script,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(signatures, single);
+
+        // Signing and combining through the precomputed context gives the same results.
+        let context = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: Some(npub_arb),
+            timelock_duration: Some(6),
+            network: Network::Regtest,
+            template: ScriptTemplate::V1,
+        }
+        .context()
+        .unwrap();
+        assert_eq!(context.address().script_pubkey(), prevouts[0].script_pubkey);
+        let mut signer = BatchSigner::new(&tx, &invariants).unwrap();
+        let mut leaf_signatures = LeafSignatures::new(tx.compute_txid(), 1, EscrowScript::C);
+        for nsec in [&nsec_2, &nsec_arb] {
+            let signature = signer
+                .sign_leaf(1, &context, EscrowScript::C, nsec)
+                .unwrap();
+            leaf_signatures.insert(nsec.public_key(), signature);
+        }
+        let expected = combine_signatures(
+            tx.clone(),
+            1,
+            vec![&signatures[2], &signatures[3]],
+            &script_c,
+            context.spend_info(),
+        )
+        .unwrap();
+        assert_eq!(
+            combine_leaf_signatures(tx.clone(), &leaf_signatures, &context).unwrap(),
+            expected
+        );
+        leaf_signatures.txid = Txid::all_zeros();
+        assert!(combine_leaf_signatures(tx.clone(), &leaf_signatures, &context).is_err());
+
+        let missing_prevout = SigningInvariants {
+            prevouts: prevouts[..1].to_vec(),
+            ..invariants
+        };
+        assert!(BatchSigner::new(&tx, &missing_prevout).is_err());
+    }
+
+    #[test]
+    fn leaf_signatures_witness_order() {
+        let (nsec_1, npub_1) = generate_nostr_keys();
+        let (nsec_2, npub_2) = generate_nostr_keys();
+        let (_, npub_arb) = generate_nostr_keys();
+        let config = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: Some(npub_arb),
+            timelock_duration: Some(6),
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let message = Message::from_digest([1; 32]);
+        let signature_1 =
+            nsec_1.with_keypair(|keypair| SECP256K1.sign_schnorr_no_aux_rand(&message, keypair));
+        let signature_2 =
+            nsec_2.with_keypair(|keypair| SECP256K1.sign_schnorr_no_aux_rand(&message, keypair));
+
+        let mut signatures = LeafSignatures::new(Txid::all_zeros(), 0, EscrowScript::A);
+        signatures.insert(npub_2, signature_1);
+        assert!(signatures.ordered(&config).is_err());
+        signatures.insert(npub_1, signature_1);
+        signatures.insert(npub_2, signature_2);
+        assert_eq!(
+            signatures.ordered(&config).unwrap(),
+            vec![&signature_1, &signature_2]
+        );
+
+        #[cfg(feature = "serde-types")]
+        {
+            let json = serde_json::to_string(&signatures).unwrap();
+            assert!(json.contains(r#""escrow_script":"A""#));
+            assert_eq!(
+                serde_json::from_str::<LeafSignatures>(&json).unwrap(),
+                signatures
+            );
+        }
+    }
+
+    #[test]
+    fn sign_cooperative_key_path_flow() {
+        init_tracing();
+
+        let harness = RegtestEscrowHarness::new();
+        let network = harness.network();
+
+        let (nsec_1, npub_1) = generate_nostr_keys();
+        let (nsec_2, npub_2) = generate_nostr_keys();
+        let (_, npub_arbitrator) = generate_nostr_keys();
+        let config = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: Some(npub_arbitrator),
+            timelock_duration: Some(144),
+            network,
+            template: ScriptTemplate::V2,
+        };
+
+        // Fund the escrow address from a coinbase.
+        let escrow_address = config.address().unwrap();
+        let txid = harness.fund_escrow(&nsec_1, &escrow_address, *MULTISIG_AMOUNT);
+
+        // Close cooperatively through the key path, without waiting for the timelock.
+        let unsigned = escrow_tx(
+            &npub_1,
+            &npub_2,
+            None,
+            *MULTISIG_AMOUNT / 2,
+            *MULTISIG_AMOUNT / 2,
+            txid,
+            FEE,
+            network,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let prevouts = [TxOut {
+            value: *MULTISIG_AMOUNT,
+            script_pubkey: escrow_address.script_pubkey(),
+        }];
+        let message = key_spend_message(&unsigned, 0, &prevouts).unwrap();
+        let key_agg = config.key_agg().unwrap();
+        let (secret_nonce_1, public_nonce_1) = generate_nonce(&nsec_1, &key_agg, &message).unwrap();
+        let (secret_nonce_2, public_nonce_2) = generate_nonce(&nsec_2, &key_agg, &message).unwrap();
+        let aggregate_nonce = AggregateNonce::sum(&[public_nonce_1, public_nonce_2]).unwrap();
+        let partial_signatures = [
+            partial_sign(
+                &key_agg,
+                secret_nonce_1,
+                &nsec_1,
+                &aggregate_nonce,
+                &message,
+            )
+            .unwrap(),
+            partial_sign(
+                &key_agg,
+                secret_nonce_2,
+                &nsec_2,
+                &aggregate_nonce,
+                &message,
+            )
+            .unwrap(),
+        ];
+        let signature =
+            aggregate_signatures(&key_agg, &aggregate_nonce, &message, &partial_signatures)
+                .unwrap();
+
+        let signed = with_key_spend_signature(&unsigned, 0, signature).unwrap();
+        // A single signature, and no leaf revealed.
+        assert_eq!(signed.input[0].witness.len(), 1);
+        info!(total_size=%signed.total_size(), "Signed key path resolution transaction");
+        harness.assert_accepted(&signed);
     }
 }
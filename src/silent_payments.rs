@@ -0,0 +1,516 @@
+//! [BIP-352](https://github.com/bitcoin/bips/blob/master/bip-0352.mediawiki) silent payment
+//! payouts.
+//!
+//! A participant can be paid out to a [`SilentPaymentAddress`] instead of a reusable address.
+//! The address only holds a scan and a spend key, and the output actually paid is derived from
+//! the key of the escrow output the resolution spends, so it can't be linked to the address,
+//! nor to other payouts, and the address never shows up in the resolution.
+//!
+//! The sender's secret of BIP-352 is the secret key of the escrow output, which nobody knows for
+//! escrows without a key path. So only escrows with a key path, see
+//! [`EscrowConfig::has_key_path`], whose output key is the MuSig2 aggregate of both participants,
+//! can pay silent payment addresses. Each participant shares an [`EcdhShare`], its part of the
+//! Diffie-Hellman secret of the output key with the scan key, proving it used its own key,
+//! and the shares add up to the secret the recipient finds when scanning.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{
+    Amount, Network, OutPoint, ScriptBuf, Transaction,
+    bech32::{Bech32m, ByteIterExt, Fe32, Fe32IterExt, Hrp, primitives::decode::CheckedHrpstring},
+    consensus,
+    key::TweakedPublicKey,
+};
+use nostr::key::PublicKey as NostrPublicKey;
+use secp256k1::{Parity, PublicKey, SECP256K1, Scalar, SecretKey};
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error, message::tagged_hash, musig::KeyAggContext, scripts::EscrowConfig,
+    secret::SecretNsec, tx::verify_split_resolution, util::npub_to_address,
+};
+
+/// Human-readable part of mainnet silent payment addresses.
+const HRP_MAINNET: Hrp = Hrp::parse_unchecked("sp");
+
+/// Human-readable part of testnet and signet silent payment addresses.
+const HRP_TESTNET: Hrp = Hrp::parse_unchecked("tsp");
+
+/// Human-readable part of regtest silent payment addresses.
+const HRP_REGTEST: Hrp = Hrp::parse_unchecked("sprt");
+
+/// Size in bytes of the keys of a version 0 silent payment address.
+const ADDRESS_KEYS_SIZE: usize = 66;
+
+/// The human-readable part of silent payment addresses on `network`.
+fn hrp(network: Network) -> Hrp {
+    match network {
+        Network::Bitcoin => HRP_MAINNET,
+        Network::Regtest => HRP_REGTEST,
+        _ => HRP_TESTNET,
+    }
+}
+
+/// Hashes `data` with `tag` into a non-zero scalar.
+fn hash_to_scalar(tag: &[u8], data: &[u8]) -> Result<SecretKey, Error> {
+    Ok(SecretKey::from_slice(&tagged_hash(tag, data))?)
+}
+
+/// A version 0 silent payment address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SilentPaymentAddress {
+    /// Human-readable part, telling the network.
+    hrp: Hrp,
+    /// Key the recipient scans transactions with.
+    pub(crate) scan: PublicKey,
+    /// Key the recipient spends payments with.
+    pub(crate) spend: PublicKey,
+}
+
+impl SilentPaymentAddress {
+    /// Whether the address is for `network`.
+    ///
+    /// Testnets and signets share their addresses.
+    pub(crate) fn is_valid_for_network(&self, network: Network) -> bool {
+        self.hrp == hrp(network)
+    }
+}
+
+impl fmt::Display for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys = self
+            .scan
+            .serialize()
+            .into_iter()
+            .chain(self.spend.serialize())
+            .bytes_to_fes()
+            .with_checksum::<Bech32m>(&self.hrp)
+            .with_witness_version(Fe32::Q);
+        for c in keys.chars() {
+            fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SilentPaymentAddress {
+    type Err = Error;
+
+    /// Parses a version 0 silent payment address.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &dyn fmt::Display| {
+            Error::WrongInputs(format!("Invalid silent payment address: {reason}"))
+        };
+        let mut address = CheckedHrpstring::new::<Bech32m>(s.trim()).map_err(|e| invalid(&e))?;
+        // Known prefixes compare case-insensitively, and are kept lowercase.
+        let hrp = [HRP_MAINNET, HRP_TESTNET, HRP_REGTEST]
+            .into_iter()
+            .find(|hrp| *hrp == address.hrp())
+            .ok_or_else(|| invalid(&format!("unknown prefix {}", address.hrp())))?;
+        if address.remove_witness_version() != Some(Fe32::Q) {
+            return Err(invalid(&"unsupported version"));
+        }
+        let keys = address.byte_iter().collect::<Vec<_>>();
+        if keys.len() != ADDRESS_KEYS_SIZE {
+            return Err(invalid(&format!("{} bytes of keys", keys.len())));
+        }
+        Ok(Self {
+            hrp,
+            scan: PublicKey::from_slice(&keys[..33])?,
+            spend: PublicKey::from_slice(&keys[33..])?,
+        })
+    }
+}
+
+/// Proof that two points have the same discrete logarithm,
+/// with respect to the generator and to another base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+struct DleqProof {
+    /// The challenge.
+    e: SecretKey,
+    /// The response.
+    s: SecretKey,
+}
+
+impl DleqProof {
+    /// The challenge of a proof that `key` and `point` are the secret times the generator
+    /// and `base`, with commitments `r_1` and `r_2`.
+    fn challenge(
+        key: &PublicKey,
+        base: &PublicKey,
+        point: &PublicKey,
+        r_1: &PublicKey,
+        r_2: &PublicKey,
+    ) -> Result<SecretKey, Error> {
+        let data = [key, base, point, r_1, r_2]
+            .into_iter()
+            .flat_map(PublicKey::serialize)
+            .collect::<Vec<_>>();
+        hash_to_scalar(b"Scrow/DLEQ challenge", &data)
+    }
+
+    /// Proves that `point` is `secret` times `base`, without revealing `secret`.
+    fn prove(secret: &SecretKey, base: &PublicKey, point: &PublicKey) -> Result<Self, Error> {
+        let key = PublicKey::from_secret_key(SECP256K1, secret);
+        let mut data = secret.secret_bytes().to_vec();
+        data.extend_from_slice(&base.serialize());
+        data.extend_from_slice(&point.serialize());
+        let mut k = hash_to_scalar(b"Scrow/DLEQ nonce", &data)?;
+        let r_1 = PublicKey::from_secret_key(SECP256K1, &k);
+        let r_2 = base.mul_tweak(SECP256K1, &Scalar::from(k))?;
+        let e = Self::challenge(&key, base, point, &r_1, &r_2)?;
+        let s = secret
+            .mul_tweak(&Scalar::from(e))?
+            .add_tweak(&Scalar::from(k));
+        k.non_secure_erase();
+        Ok(Self { e, s: s? })
+    }
+
+    /// Verifies that `point` is the secret key of `key` times `base`.
+    fn verify(&self, key: &PublicKey, base: &PublicKey, point: &PublicKey) -> Result<(), Error> {
+        let minus_e = Scalar::from(self.e.negate());
+        let r_1 = PublicKey::from_secret_key(SECP256K1, &self.s)
+            .combine(&key.mul_tweak(SECP256K1, &minus_e)?)?;
+        let r_2 = base
+            .mul_tweak(SECP256K1, &Scalar::from(self.s))?
+            .combine(&point.mul_tweak(SECP256K1, &minus_e)?)?;
+        if Self::challenge(key, base, point, &r_1, &r_2)? != self.e {
+            return Err(Error::WrongInputs(
+                "Invalid proof of the silent payment share".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A participant's share of the Diffie-Hellman secret of the escrow output key
+/// with the scan key of a [`SilentPaymentAddress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct EcdhShare {
+    /// The participant.
+    pub(crate) npub: NostrPublicKey,
+    /// The scan key the share is for.
+    pub(crate) scan: PublicKey,
+    /// The participant's share of the secret key of the output key, times the scan key.
+    pub(crate) share: PublicKey,
+    /// Proof that the share was made with the participant's key.
+    proof: DleqProof,
+}
+
+impl EcdhShare {
+    /// The share of the [`SecretNsec`]'s participant in the escrow `config`
+    /// for the `scan` key.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow has no key path or the participant is not part of it.
+    pub(crate) fn new(
+        config: &EscrowConfig,
+        nsec: &SecretNsec,
+        scan: &PublicKey,
+    ) -> Result<Self, Error> {
+        let mut secret = config.key_agg()?.signer_secret(nsec)?;
+        let shared = scan
+            .mul_tweak(SECP256K1, &Scalar::from(secret))
+            .map_err(Error::from)
+            .and_then(|share| Ok((share, DleqProof::prove(&secret, scan, &share)?)));
+        secret.non_secure_erase();
+        let (share, proof) = shared?;
+        Ok(Self {
+            npub: nsec.public_key(),
+            scan: *scan,
+            share,
+            proof,
+        })
+    }
+
+    /// Verifies the share of a participant of `context`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the participant is not part of the aggregate, or the proof is invalid.
+    fn verify(&self, context: &KeyAggContext) -> Result<(), Error> {
+        let key = context.signer_key(&self.npub)?;
+        self.proof.verify(&key, &self.scan, &self.share)
+    }
+}
+
+/// The BIP-352 `input_hash` of a transaction spending `outpoints`,
+/// whose keys add up to `input_key`.
+fn input_hash(outpoints: &[OutPoint], input_key: &PublicKey) -> Result<SecretKey, Error> {
+    let mut data = outpoints
+        .iter()
+        .map(consensus::serialize)
+        .min()
+        .ok_or_else(|| Error::WrongInputs("Transaction has no inputs".to_string()))?;
+    data.extend_from_slice(&input_key.serialize());
+    hash_to_scalar(b"BIP0352/Inputs", &data)
+}
+
+/// The BIP-352 shared secret of a resolution spending the escrow `config` at `funding`
+/// with the `scan` key, from both participants' `shares`.
+///
+/// # Errors
+///
+/// Errors if the escrow has no key path, or a participant's share is missing or invalid.
+pub(crate) fn shared_secret(
+    config: &EscrowConfig,
+    funding: OutPoint,
+    scan: &PublicKey,
+    shares: &[EcdhShare],
+) -> Result<PublicKey, Error> {
+    let context = config.key_agg()?;
+    let mut points = Vec::with_capacity(3);
+    for npub in [config.npub_1, config.npub_2] {
+        let share = shares
+            .iter()
+            .find(|share| share.npub == npub && share.scan == *scan)
+            .ok_or_else(|| Error::WrongInputs(format!("Missing silent payment share of {npub}")))?;
+        share.verify(&context)?;
+        points.push(share.share);
+    }
+    if let Some(tweak) = context.output_tweak() {
+        points.push(scan.mul_tweak(SECP256K1, &Scalar::from(tweak))?);
+    }
+    let secret = PublicKey::combine_keys(&points.iter().collect::<Vec<_>>())?;
+    let input_key = context.output_key().public_key(Parity::Even);
+    let input_hash = input_hash(&[funding], &input_key)?;
+    Ok(secret.mul_tweak(SECP256K1, &Scalar::from(input_hash))?)
+}
+
+/// The script of the `k`th output paying `address` with `shared_secret`,
+/// counting from zero among the outputs to the same scan key.
+pub(crate) fn payment_script(
+    address: &SilentPaymentAddress,
+    shared_secret: &PublicKey,
+    k: u32,
+) -> Result<ScriptBuf, Error> {
+    let mut data = shared_secret.serialize().to_vec();
+    data.extend_from_slice(&k.to_be_bytes());
+    let t_k = hash_to_scalar(b"BIP0352/SharedSecret", &data)?;
+    let output_key = address.spend.add_exp_tweak(SECP256K1, &Scalar::from(t_k))?;
+    Ok(ScriptBuf::new_p2tr_tweaked(
+        TweakedPublicKey::dangerous_assume_tweaked(output_key.x_only_public_key().0),
+    ))
+}
+
+/// The scripts paying `addresses`, in order, from a resolution spending the escrow `config`
+/// at `funding`, given both participants' `shares` for every scan key.
+///
+/// # Errors
+///
+/// Errors if an address is for another network, the escrow has no key path,
+/// or a share is missing or invalid.
+pub(crate) fn payout_scripts(
+    config: &EscrowConfig,
+    funding: OutPoint,
+    addresses: &[SilentPaymentAddress],
+    shares: &[EcdhShare],
+) -> Result<Vec<ScriptBuf>, Error> {
+    addresses
+        .iter()
+        .enumerate()
+        .map(|(index, address)| {
+            if !address.is_valid_for_network(config.network) {
+                return Err(Error::WrongInputs(format!(
+                    "{address} is not a {} address",
+                    config.network
+                )));
+            }
+            let k = addresses[..index]
+                .iter()
+                .filter(|previous| previous.scan == address.scan)
+                .count() as u32;
+            let secret = shared_secret(config, funding, &address.scan, shares)?;
+            payment_script(address, &secret, k)
+        })
+        .collect()
+}
+
+/// Pays the payout of `npub` in the resolution `tx`, built with
+/// [`split_resolution_tx`](crate::tx::split_resolution_tx), to the silent payment `script`
+/// instead of its `npub` address.
+///
+/// # Errors
+///
+/// Errors if `tx` has no payout to `npub`.
+pub(crate) fn redirect_payout(
+    tx: &mut Transaction,
+    config: &EscrowConfig,
+    npub: &NostrPublicKey,
+    script: ScriptBuf,
+) -> Result<(), Error> {
+    let address = npub_to_address(npub, config.network)?.script_pubkey();
+    let output = tx
+        .output
+        .iter_mut()
+        .find(|output| output.script_pubkey == address)
+        .ok_or_else(|| Error::WrongInputs(format!("No payout to {npub}")))?;
+    output.script_pubkey = script;
+    Ok(())
+}
+
+/// Like [`verify_split_resolution`], but with the payouts of some participants
+/// redirected to silent payment scripts.
+///
+/// Returns the payouts of the first and second participant.
+pub(crate) fn verify_silent_resolution(
+    tx: &Transaction,
+    config: &EscrowConfig,
+    amount: Amount,
+    fee: Amount,
+    payouts: &[(NostrPublicKey, ScriptBuf)],
+) -> Result<(Amount, Amount), Error> {
+    let mut tx = tx.clone();
+    for (npub, script) in payouts {
+        let address = npub_to_address(npub, config.network)?.script_pubkey();
+        for output in &mut tx.output {
+            if output.script_pubkey == *script {
+                output.script_pubkey = address.clone();
+            }
+        }
+    }
+    verify_split_resolution(&tx, config, amount, fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Txid, absolute, hashes::Hash};
+
+    use super::*;
+    use crate::{
+        scripts::{EscrowScript, ScriptTemplate},
+        tx::{Split, split_resolution_tx},
+    };
+
+    impl SilentPaymentAddress {
+        /// The address of the `scan` and `spend` keys on `network`.
+        fn new(scan: PublicKey, spend: PublicKey, network: Network) -> Self {
+            Self {
+                hrp: hrp(network),
+                scan,
+                spend,
+            }
+        }
+    }
+
+    #[test]
+    fn silent_payment_address() {
+        let (scan, spend) = (SecretNsec::generate(), SecretNsec::generate());
+        let key = |nsec: &SecretNsec| nsec.with_keypair(|keypair| keypair.public_key());
+        let address = SilentPaymentAddress::new(key(&scan), key(&spend), Network::Regtest);
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("sprt1q"));
+        assert_eq!(encoded.parse::<SilentPaymentAddress>().unwrap(), address);
+        assert_eq!(
+            encoded
+                .to_uppercase()
+                .parse::<SilentPaymentAddress>()
+                .unwrap(),
+            address
+        );
+        assert!(address.is_valid_for_network(Network::Regtest));
+        assert!(!address.is_valid_for_network(Network::Bitcoin));
+        let signet = SilentPaymentAddress::new(key(&scan), key(&spend), Network::Signet);
+        assert!(signet.to_string().starts_with("tsp1q"));
+        assert!(signet.is_valid_for_network(Network::Testnet));
+
+        let mut tampered = encoded.clone();
+        tampered.pop();
+        tampered.push(if encoded.ends_with('q') { 'p' } else { 'q' });
+        assert!(tampered.parse::<SilentPaymentAddress>().is_err());
+        assert!(
+            "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"
+                .parse::<SilentPaymentAddress>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn silent_payout() {
+        let (nsec_1, nsec_2) = (
+            SecretNsec::generate_with_parity(Parity::Odd),
+            SecretNsec::generate_with_parity(Parity::Even),
+        );
+        let mut config = EscrowConfig {
+            npub_1: nsec_1.public_key(),
+            npub_2: nsec_2.public_key(),
+            npub_arbitrator: Some(SecretNsec::generate().public_key()),
+            timelock_duration: Some(144),
+            network: Network::Regtest,
+            template: ScriptTemplate::V2,
+        };
+        let funding = OutPoint::new(Txid::from_byte_array([7; 32]), 1);
+
+        // The second participant gets paid to a silent payment address.
+        let (scan, spend) = (SecretNsec::generate(), SecretNsec::generate());
+        let secret_key = |nsec: &SecretNsec| nsec.with_keypair(|keypair| keypair.secret_key());
+        let address = SilentPaymentAddress::new(
+            PublicKey::from_secret_key(SECP256K1, &secret_key(&scan)),
+            PublicKey::from_secret_key(SECP256K1, &secret_key(&spend)),
+            Network::Regtest,
+        );
+        let shares = [
+            EcdhShare::new(&config, &nsec_1, &address.scan).unwrap(),
+            EcdhShare::new(&config, &nsec_2, &address.scan).unwrap(),
+        ];
+        let scripts = payout_scripts(&config, funding, &[address, address], &shares).unwrap();
+        assert_ne!(scripts[0], scripts[1]);
+
+        // The recipient finds the same output when scanning the resolution.
+        let input_key = config
+            .key_agg()
+            .unwrap()
+            .output_key()
+            .public_key(Parity::Even);
+        let tweak = input_hash(&[funding], &input_key)
+            .unwrap()
+            .mul_tweak(&Scalar::from(secret_key(&scan)))
+            .unwrap();
+        let scanned = input_key
+            .mul_tweak(SECP256K1, &Scalar::from(tweak))
+            .unwrap();
+        assert_eq!(payment_script(&address, &scanned, 0).unwrap(), scripts[0]);
+        assert_eq!(payment_script(&address, &scanned, 1).unwrap(), scripts[1]);
+
+        // Shares must be proven and complete.
+        assert!(payout_scripts(&config, funding, &[address], &shares[..1]).is_err());
+        let mut forged = shares[1];
+        forged.share = forged.share.negate(SECP256K1);
+        assert!(payout_scripts(&config, funding, &[address], &[shares[0], forged]).is_err());
+        let mainnet = SilentPaymentAddress::new(address.scan, address.spend, Network::Bitcoin);
+        assert!(payout_scripts(&config, funding, &[mainnet], &shares).is_err());
+
+        let amount = Amount::from_sat(100_000);
+        let fee = Amount::from_sat(1_000);
+        let mut tx = split_resolution_tx(
+            &config,
+            funding,
+            amount,
+            Split::Percent(30),
+            fee,
+            EscrowScript::A,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        redirect_payout(&mut tx, &config, &config.npub_2, scripts[0].clone()).unwrap();
+        assert!(
+            tx.output
+                .iter()
+                .any(|output| output.script_pubkey == scripts[0])
+        );
+        assert!(verify_split_resolution(&tx, &config, amount, fee).is_err());
+        let payouts = [(config.npub_2, scripts[0].clone())];
+        assert_eq!(
+            verify_silent_resolution(&tx, &config, amount, fee, &payouts).unwrap(),
+            (Amount::from_sat(29_700), Amount::from_sat(69_300))
+        );
+
+        // Escrows without a key path can't pay silent payment addresses.
+        config.template = ScriptTemplate::V1;
+        assert!(EcdhShare::new(&config, &nsec_1, &address.scan).is_err());
+    }
+}
@@ -0,0 +1,435 @@
+//! Standardness checks before broadcasting a transaction.
+//!
+//! Bitcoin Core only relays transactions meeting its standardness policy, and refuses the
+//! others with terse messages such as `dust` or `bad-witness-nonstandard`, which mean little
+//! to a user in the browser. [`check_standard`] mirrors that policy, `IsStandardTx` and
+//! `IsWitnessStandard`, so a transaction the network would refuse is caught before the
+//! broadcast with an [`Error::NonStandard`] telling what to fix:
+//!
+//! - the version, weight and non-witness size of the transaction;
+//! - push-only input scripts of at most [`MAX_STANDARD_SCRIPTSIG_SIZE`] bytes;
+//! - witness items of P2WSH and tapscript spends, their count and size, the witness script
+//!   size, and no taproot annex;
+//! - sighash flags of the signatures, which must be defined types;
+//! - no dust outputs, and a single `OP_RETURN` output of at most [`MAX_OP_RETURN_RELAY`] bytes.
+//!
+//! The type of each spend is read from its prevout when known, from the shape of its witness
+//! otherwise.
+
+use bitcoin::{
+    EcdsaSighashType, Script, TapSighashType, Transaction, TxIn, TxOut, Weight,
+    secp256k1::ecdsa,
+    taproot::{
+        TAPROOT_CONTROL_BASE_SIZE, TAPROOT_CONTROL_NODE_SIZE, TAPROOT_LEAF_MASK,
+        TAPROOT_LEAF_TAPSCRIPT,
+    },
+};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+
+use crate::error::Error;
+
+/// Highest standard transaction version, `TX_MAX_STANDARD_VERSION` of Bitcoin Core.
+pub(crate) const MAX_STANDARD_VERSION: i32 = 3;
+
+/// Heaviest standard transaction, `MAX_STANDARD_TX_WEIGHT` of Bitcoin Core.
+pub(crate) const MAX_STANDARD_TX_WEIGHT: Weight = Weight::from_wu(400_000);
+
+/// Smallest standard size of a transaction without its witness,
+/// `MIN_STANDARD_TX_NONWITNESS_SIZE` of Bitcoin Core.
+pub(crate) const MIN_STANDARD_NON_WITNESS_SIZE: usize = 65;
+
+/// Largest standard input script, `MAX_STANDARD_SCRIPTSIG_SIZE` of Bitcoin Core.
+pub(crate) const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1_650;
+
+/// Most stack items of a P2WSH spend, besides the witness script.
+pub(crate) const MAX_STANDARD_P2WSH_STACK_ITEMS: usize = 100;
+
+/// Largest stack item of a P2WSH spend, besides the witness script.
+pub(crate) const MAX_STANDARD_P2WSH_STACK_ITEM_SIZE: usize = 80;
+
+/// Largest standard witness script of a P2WSH spend.
+pub(crate) const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3_600;
+
+/// Largest stack item of a tapscript spend, besides the script and the control block.
+pub(crate) const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: usize = 80;
+
+/// Largest standard `OP_RETURN` output script, `MAX_OP_RETURN_RELAY` of Bitcoin Core.
+pub(crate) const MAX_OP_RETURN_RELAY: usize = 83;
+
+/// How an input spends its prevout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Spend {
+    /// Without a witness, such as a legacy output.
+    Legacy,
+    /// A P2WPKH output.
+    P2wpkh,
+    /// A P2WSH output.
+    P2wsh,
+    /// The key path of a P2TR output.
+    KeyPath,
+    /// A script path of a P2TR output.
+    ScriptPath,
+    /// Another witness program, with no policy on its witness.
+    Other,
+}
+
+impl Spend {
+    /// The spend of `input`, from its `prevout` if known.
+    fn of(input: &TxIn, prevout: Option<&TxOut>) -> Self {
+        let witness = &input.witness;
+        let Some(prevout) = prevout else {
+            return Self::guess(input);
+        };
+        let script = &prevout.script_pubkey;
+        // Without its annex, a key path spend is a single signature.
+        let items = witness.len() - usize::from(witness.taproot_annex().is_some());
+        if script.is_p2wpkh() {
+            Spend::P2wpkh
+        } else if script.is_p2wsh() {
+            Spend::P2wsh
+        } else if script.is_p2tr() && items == 1 {
+            Spend::KeyPath
+        } else if script.is_p2tr() {
+            Spend::ScriptPath
+        } else if witness.is_empty() {
+            Spend::Legacy
+        } else {
+            Spend::Other
+        }
+    }
+
+    /// The spend of `input` from the shape of its witness, when its prevout is unknown.
+    fn guess(input: &TxIn) -> Self {
+        let witness = &input.witness;
+        match witness.len() {
+            0 => Spend::Legacy,
+            1 if matches!(witness[0].len(), 64 | 65) => Spend::KeyPath,
+            2 if witness[1].len() == 33 && witness[0].first() == Some(&0x30) => Spend::P2wpkh,
+            _ if witness
+                .taproot_control_block()
+                .is_some_and(is_control_block) =>
+            {
+                Spend::ScriptPath
+            }
+            _ => Spend::P2wsh,
+        }
+    }
+}
+
+/// Whether `bytes` look like the control block of a tapscript spend.
+fn is_control_block(bytes: &[u8]) -> bool {
+    bytes.len() >= TAPROOT_CONTROL_BASE_SIZE
+        && (bytes.len() - TAPROOT_CONTROL_BASE_SIZE).is_multiple_of(TAPROOT_CONTROL_NODE_SIZE)
+        && bytes[0] & TAPROOT_LEAF_MASK == TAPROOT_LEAF_TAPSCRIPT
+}
+
+/// Checks that the network relays `tx` under Bitcoin Core's standardness policy.
+///
+/// `prevouts` are the outputs spent by every input of `tx`, in input order, if known.
+///
+/// # Errors
+///
+/// Errors with [`Error::NonStandard`] describing the first rule `tx` breaks,
+/// or [`Error::WrongInputs`] if `prevouts` don't match its inputs.
+pub(crate) fn check_standard(tx: &Transaction, prevouts: Option<&[TxOut]>) -> Result<(), Error> {
+    if let Some(prevouts) = prevouts.filter(|prevouts| prevouts.len() != tx.input.len()) {
+        return Err(Error::WrongInputs(format!(
+            "{} prevouts for {} inputs",
+            prevouts.len(),
+            tx.input.len()
+        )));
+    }
+    let version = tx.version.0;
+    if !(1..=MAX_STANDARD_VERSION).contains(&version) {
+        return Err(Error::NonStandard(format!(
+            "version {version} is not standard, use version 2"
+        )));
+    }
+    let weight = tx.weight();
+    if weight > MAX_STANDARD_TX_WEIGHT {
+        return Err(Error::NonStandard(format!(
+            "it weighs {weight:#}, more than the {:#} relayed, split it in smaller transactions",
+            MAX_STANDARD_TX_WEIGHT
+        )));
+    }
+    let base_size = tx.base_size();
+    if base_size < MIN_STANDARD_NON_WITNESS_SIZE {
+        return Err(Error::NonStandard(format!(
+            "it is {base_size} bytes without its witness, less than the \
+             {MIN_STANDARD_NON_WITNESS_SIZE} relayed, add an output"
+        )));
+    }
+
+    for (index, input) in tx.input.iter().enumerate() {
+        let prevout = prevouts.map(|prevouts| &prevouts[index]);
+        check_input(index, input, prevout)?;
+    }
+
+    let mut op_returns = 0;
+    for (index, output) in tx.output.iter().enumerate() {
+        let script = &output.script_pubkey;
+        if script.is_op_return() {
+            op_returns += 1;
+            if op_returns > 1 {
+                return Err(Error::NonStandard(format!(
+                    "output {index} is a second OP_RETURN output, keep a single one"
+                )));
+            }
+            if script.len() > MAX_OP_RETURN_RELAY {
+                return Err(Error::NonStandard(format!(
+                    "the OP_RETURN output {index} is {} bytes, more than the \
+                     {MAX_OP_RETURN_RELAY} relayed, shorten its data",
+                    script.len()
+                )));
+            }
+            continue;
+        }
+        if !is_standard_output(script) {
+            return Err(Error::NonStandard(format!(
+                "output {index} pays to a non-standard script, pay to an address instead"
+            )));
+        }
+        let dust = script.minimal_non_dust();
+        if output.value < dust {
+            return Err(Error::NonStandard(format!(
+                "output {index} of {} is dust, pay it at least {dust} or drop it",
+                output.value
+            )));
+        }
+    }
+    #[cfg(debug_assertions)]
+    trace!(txid = %tx.compute_txid(), %weight, "standard transaction");
+    Ok(())
+}
+
+/// Checks the input script, witness and signatures of the input `index`.
+fn check_input(index: usize, input: &TxIn, prevout: Option<&TxOut>) -> Result<(), Error> {
+    let script_sig = &input.script_sig;
+    if script_sig.len() > MAX_STANDARD_SCRIPTSIG_SIZE {
+        return Err(Error::NonStandard(format!(
+            "the script of input {index} is {} bytes, more than the \
+             {MAX_STANDARD_SCRIPTSIG_SIZE} relayed",
+            script_sig.len()
+        )));
+    }
+    if !script_sig.is_push_only() {
+        return Err(Error::NonStandard(format!(
+            "the script of input {index} does more than pushing data"
+        )));
+    }
+
+    let witness = &input.witness;
+    let spend = Spend::of(input, prevout);
+    if matches!(spend, Spend::KeyPath | Spend::ScriptPath) && witness.taproot_annex().is_some() {
+        return Err(Error::NonStandard(format!(
+            "the witness of input {index} has a taproot annex, remove it"
+        )));
+    }
+    match spend {
+        Spend::Legacy | Spend::Other => Ok(()),
+        Spend::P2wpkh => check_ecdsa_signature(index, witness.nth(0).unwrap_or_default()),
+        Spend::P2wsh => {
+            let items = witness.len().saturating_sub(1);
+            let script_size = witness.last().map_or(0, <[u8]>::len);
+            if script_size > MAX_STANDARD_P2WSH_SCRIPT_SIZE {
+                return Err(Error::NonStandard(format!(
+                    "the witness script of input {index} is {script_size} bytes, more than the \
+                     {MAX_STANDARD_P2WSH_SCRIPT_SIZE} relayed"
+                )));
+            }
+            if items > MAX_STANDARD_P2WSH_STACK_ITEMS {
+                return Err(Error::NonStandard(format!(
+                    "the witness of input {index} has {items} items, more than the \
+                     {MAX_STANDARD_P2WSH_STACK_ITEMS} relayed"
+                )));
+            }
+            for item in witness.iter().take(items) {
+                check_item_size(index, item, MAX_STANDARD_P2WSH_STACK_ITEM_SIZE)?;
+                // A DER signature followed by its sighash flag.
+                let is_signature = item.split_last().is_some_and(|(_, der)| {
+                    der.first() == Some(&0x30) && ecdsa::Signature::from_der(der).is_ok()
+                });
+                if is_signature {
+                    check_ecdsa_signature(index, item)?;
+                }
+            }
+            Ok(())
+        }
+        Spend::KeyPath => check_schnorr_signature(index, witness.nth(0).unwrap_or_default()),
+        Spend::ScriptPath => {
+            // The script and the control block follow the stack items.
+            let items = witness.len().saturating_sub(2);
+            for item in witness.iter().take(items) {
+                check_item_size(index, item, MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE)?;
+                if item.len() == 65 {
+                    check_schnorr_signature(index, item)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Checks the size of a witness `item` of the input `index`.
+fn check_item_size(index: usize, item: &[u8], max: usize) -> Result<(), Error> {
+    if item.len() > max {
+        return Err(Error::NonStandard(format!(
+            "a witness item of input {index} is {} bytes, more than the {max} relayed",
+            item.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Checks the sighash flag of the ECDSA `signature` of the input `index`.
+fn check_ecdsa_signature(index: usize, signature: &[u8]) -> Result<(), Error> {
+    let Some(flag) = signature.last() else {
+        return Ok(());
+    };
+    EcdsaSighashType::from_standard(u32::from(*flag))
+        .map(|_| ())
+        .map_err(|_| {
+            Error::NonStandard(format!(
+                "the signature of input {index} has the undefined sighash flag {flag:#04x}, \
+                 sign it again with SIGHASH_ALL"
+            ))
+        })
+}
+
+/// Checks the length and sighash flag of the Schnorr `signature` of the input `index`.
+fn check_schnorr_signature(index: usize, signature: &[u8]) -> Result<(), Error> {
+    match signature.len() {
+        64 => Ok(()),
+        65 => match TapSighashType::from_consensus_u8(signature[64]) {
+            // The default sighash is implied by a 64-byte signature, never explicit.
+            Ok(sighash_type) if sighash_type != TapSighashType::Default => Ok(()),
+            _ => Err(Error::NonStandard(format!(
+                "the signature of input {index} has the invalid sighash flag {:#04x}, \
+                 sign it again with SIGHASH_DEFAULT",
+                signature[64]
+            ))),
+        },
+        _ => Err(Error::NonStandard(format!(
+            "the signature of input {index} is {} bytes instead of 64 or 65",
+            signature.len()
+        ))),
+    }
+}
+
+/// Whether `script` is a standard output script, besides `OP_RETURN` ones.
+fn is_standard_output(script: &Script) -> bool {
+    script.is_p2pkh()
+        || script.is_p2sh()
+        || script.is_witness_program()
+        || script.is_p2pk()
+        || script.is_multisig()
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, Witness, absolute,
+        secp256k1::{Message, Secp256k1, SecretKey},
+        transaction,
+    };
+
+    use super::*;
+
+    fn p2tr() -> ScriptBuf {
+        ScriptBuf::from_bytes([&[0x51, 0x20][..], &[1; 32]].concat())
+    }
+
+    fn tx(witness: &[&[u8]], outputs: &[(u64, ScriptBuf)]) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::from_slice(witness),
+            }],
+            output: outputs
+                .iter()
+                .map(|(value, script_pubkey)| TxOut {
+                    value: Amount::from_sat(*value),
+                    script_pubkey: script_pubkey.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn reason(result: Result<(), Error>) -> String {
+        match result {
+            Err(Error::NonStandard(reason)) => reason,
+            other => panic!("expected a non-standard transaction, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn standardness_policy() {
+        let prevouts = [TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: p2tr(),
+        }];
+        let payout = [(90_000, p2tr())];
+        let key_path = tx(&[&[0; 64]], &payout);
+        check_standard(&key_path, Some(&prevouts[..])).unwrap();
+        check_standard(&key_path, None).unwrap();
+        assert!(matches!(
+            check_standard(&key_path, Some(&[][..])),
+            Err(Error::WrongInputs(_))
+        ));
+
+        // Transaction rules.
+        let mut version = key_path.clone();
+        version.version = transaction::Version(4);
+        assert!(reason(check_standard(&version, None)).contains("version 4"));
+        let dust = tx(&[&[0; 64]], &[(90_000, p2tr()), (100, p2tr())]);
+        assert!(reason(check_standard(&dust, None)).contains("output 1"));
+        let op_return = |len: usize| {
+            ScriptBuf::from_bytes([&[0x6a, 0x4c, len as u8][..], &vec![0; len][..]].concat())
+        };
+        let one_op_return = tx(&[&[0; 64]], &[(90_000, p2tr()), (0, op_return(80))]);
+        check_standard(&one_op_return, None).unwrap();
+        let large = tx(&[&[0; 64]], &[(90_000, p2tr()), (0, op_return(81))]);
+        assert!(reason(check_standard(&large, None)).contains("OP_RETURN"));
+        let two = tx(
+            &[&[0; 64]],
+            &[(90_000, p2tr()), (0, op_return(1)), (0, op_return(1))],
+        );
+        assert!(reason(check_standard(&two, None)).contains("second OP_RETURN"));
+
+        // Schnorr signatures with an explicit sighash flag must use a defined one.
+        let mut signature = [0; 65];
+        signature[64] = 0x01;
+        check_standard(&tx(&[&signature], &payout), Some(&prevouts[..])).unwrap();
+        signature[64] = 0x00;
+        let explicit_default = tx(&[&signature], &payout);
+        assert!(reason(check_standard(&explicit_default, Some(&prevouts[..]))).contains("0x00"));
+        let annex = tx(&[&[0; 64], &[0x50, 1]], &payout);
+        assert!(reason(check_standard(&annex, Some(&prevouts[..]))).contains("annex"));
+
+        // Tapscript stack items are at most 80 bytes.
+        let control_block = [&[TAPROOT_LEAF_TAPSCRIPT][..], &[2; 32]].concat();
+        let script_path = tx(&[&[0; 64], &[0; 64], &[0xac], &control_block], &payout);
+        check_standard(&script_path, None).unwrap();
+        let oversized = tx(&[&[0; 81], &[0xac], &control_block], &payout);
+        assert!(reason(check_standard(&oversized, None)).contains("81 bytes"));
+
+        // ECDSA signatures must use a defined sighash flag.
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let der = secp
+            .sign_ecdsa(&Message::from_digest([2; 32]), &secret_key)
+            .serialize_der();
+        let public_key = secret_key.public_key(&secp).serialize();
+        let p2wpkh = |flag: u8| {
+            let signature = [&der[..], &[flag]].concat();
+            tx(&[&signature, &public_key], &payout)
+        };
+        check_standard(&p2wpkh(0x01), None).unwrap();
+        assert!(reason(check_standard(&p2wpkh(0x04), None)).contains("0x04"));
+    }
+}
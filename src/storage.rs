@@ -0,0 +1,141 @@
+//! Persistent key-value storage.
+//!
+//! Everything is stored on the user's device, as strings under fixed keys.
+//! In the browser this is the `localStorage` of the app's origin,
+//! and natively a directory with one file per key.
+
+#[cfg(test)]
+use std::{collections::HashMap, sync::Mutex};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, io, path::PathBuf};
+
+use crate::error::Error;
+
+/// Key-value store for app data.
+pub(crate) trait Storage {
+    /// Gets the value stored under `key`, if any.
+    fn get(&self, key: &str) -> Result<Option<String>, Error>;
+
+    /// Stores `value` under `key`, replacing any previous value.
+    fn set(&self, key: &str, value: &str) -> Result<(), Error>;
+
+    /// Removes the value stored under `key`, if any.
+    fn remove(&self, key: &str) -> Result<(), Error>;
+}
+
+/// [`Storage`] backed by the browser's `localStorage`.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LocalStorage;
+
+impl LocalStorage {
+    fn storage() -> Result<web_sys::Storage, Error> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| Error::Storage("localStorage is not available".to_string()))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        Self::storage()?
+            .get_item(key)
+            .map_err(|_| Error::Storage(format!("Could not read {key}")))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), Error> {
+        Self::storage()?
+            .set_item(key, value)
+            .map_err(|_| Error::Storage(format!("Could not write {key}")))
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        Self::storage()?
+            .remove_item(key)
+            .map_err(|_| Error::Storage(format!("Could not remove {key}")))
+    }
+}
+
+/// [`Storage`] in a directory, one file per key, for native builds such as the daemon.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub(crate) struct FileStorage {
+    dir: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+    /// Stores under `dir`, creating it if needed.
+    pub(crate) fn open(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|e| Error::Storage(format!("Could not create {}: {e}", dir.display())))?;
+        Ok(Self { dir })
+    }
+
+    /// Path of the file of `key`, which must be a plain file name.
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        let valid = !key.is_empty()
+            && !key.starts_with('.')
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        if !valid {
+            return Err(Error::Storage(format!("Invalid storage key {key}")));
+        }
+        Ok(self.dir.join(key))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        match fs::read_to_string(self.path(key)?) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::Storage(format!("Could not read {key}: {e}"))),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), Error> {
+        // Write to a temporary file first, so readers never see a partial value.
+        let path = self.path(key)?;
+        let temporary = self.dir.join(format!(".{key}.tmp"));
+        fs::write(&temporary, value)
+            .and_then(|()| fs::rename(&temporary, &path))
+            .map_err(|e| Error::Storage(format!("Could not write {key}: {e}")))
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        match fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(Error::Storage(format!("Could not remove {key}: {e}")))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// In-memory [`Storage`] for tests.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct MemoryStorage(Mutex<HashMap<String, String>>);
+
+#[cfg(test)]
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), Error> {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        self.0.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,383 @@
+//! Human-readable summaries of who can spend an escrow, and when.
+//!
+//! [`describe_escrow`] decodes every tap leaf script of the escrow with
+//! [`LeafRequirements::from_script`] and checks the Taproot internal key,
+//! so the summary states what the scripts enforce, never what the UI assumes they do.
+//! The [`ContractSummary`] is made of [`Clause`]s of [`Party`]s and a [`Timing`],
+//! rendered in any [`Language`] with [`ContractSummary::describe`],
+//! and [`Display`](fmt::Display)s in English.
+
+use std::{fmt, time::Duration};
+
+use nostr::key::PublicKey as NostrPublicKey;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    i18n::{Language, tr, tr_args},
+    musig::KeyAggContext,
+    network::NetworkProfile,
+    scripts::{EscrowConfig, SpendPath, UNSPENDABLE_PUBLIC_KEY},
+    trust::LeafRequirements,
+};
+
+/// A signer of an escrow spend.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub(crate) enum Party {
+    /// The first participant, the buyer in a negotiated escrow.
+    Participant1,
+    /// The second participant, the seller in a negotiated escrow.
+    Participant2,
+    /// The arbitrator.
+    Arbitrator,
+    /// A key that is none of the escrow's parties.
+    Unknown(NostrPublicKey),
+}
+
+impl Party {
+    /// The party of `npub` in the escrow `config`.
+    fn of(npub: &NostrPublicKey, config: &EscrowConfig) -> Self {
+        if *npub == config.npub_1 {
+            Party::Participant1
+        } else if *npub == config.npub_2 {
+            Party::Participant2
+        } else if Some(*npub) == config.npub_arbitrator {
+            Party::Arbitrator
+        } else {
+            Party::Unknown(*npub)
+        }
+    }
+
+    /// Position of the party when listing signers.
+    fn rank(&self) -> u8 {
+        match self {
+            Party::Participant1 => 0,
+            Party::Participant2 => 1,
+            Party::Arbitrator => 2,
+            Party::Unknown(_) => 3,
+        }
+    }
+
+    /// Name of the party in `language`.
+    pub(crate) fn name(&self, language: Language) -> String {
+        match self {
+            Party::Participant1 => tr(language, "party-participant-1"),
+            Party::Participant2 => tr(language, "party-participant-2"),
+            Party::Arbitrator => tr(language, "party-arbitrator"),
+            Party::Unknown(npub) => tr_args(language, "party-unknown", &[("key", &npub.to_hex())]),
+        }
+    }
+}
+
+impl fmt::Display for Party {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name(Language::En))
+    }
+}
+
+/// When a spending condition can be used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", rename_all = "snake_case")
+)]
+pub(crate) enum Timing {
+    /// As soon as the escrow is funded.
+    Anytime,
+    /// Once `blocks` were mined on top of the funding transaction.
+    AfterBlocks {
+        /// Relative timelock in blocks.
+        blocks: u32,
+        /// Expected time for the blocks to be mined, in seconds.
+        seconds: u64,
+    },
+}
+
+impl Timing {
+    /// Renders the timing in `language`.
+    pub(crate) fn describe(&self, language: Language) -> String {
+        match self {
+            Timing::Anytime => tr(language, "summary-anytime"),
+            Timing::AfterBlocks { blocks, seconds } => tr_args(
+                language,
+                "summary-after-blocks",
+                &[
+                    ("blocks", blocks),
+                    (
+                        "duration",
+                        &approximate(Duration::from_secs(*seconds), language),
+                    ),
+                ],
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(Language::En))
+    }
+}
+
+/// A way to spend the escrow, as enforced by its Taproot output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct Condition {
+    /// The key path or leaf enforcing the condition.
+    pub(crate) path: SpendPath,
+    /// Parties that must all sign.
+    pub(crate) signers: Vec<Party>,
+    /// When the condition can be used.
+    pub(crate) timing: Timing,
+}
+
+/// Sets of signers that can spend with the same [`Timing`], one sentence of a summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct Clause {
+    /// Alternative sets of parties, each of which can spend on its own.
+    pub(crate) signers: Vec<Vec<Party>>,
+    /// When they can spend.
+    pub(crate) timing: Timing,
+}
+
+impl Clause {
+    /// Renders the clause in `language`, naming parties with `name`.
+    fn describe(&self, language: Language, name: &impl Fn(Party) -> String) -> String {
+        let and = format!(" {} ", tr(language, "summary-and"));
+        let or = format!(", {} ", tr(language, "summary-or"));
+        let signers = self
+            .signers
+            .iter()
+            .map(|set| {
+                set.iter()
+                    .map(|party| name(*party))
+                    .collect::<Vec<_>>()
+                    .join(&and)
+            })
+            .collect::<Vec<_>>();
+        let mut signers = signers.join(&or);
+        if self.signers.len() > 1 && language == Language::En {
+            // English sets the alternatives off from the timing with a comma.
+            signers.push(',');
+        }
+        tr_args(
+            language,
+            "summary-clause",
+            &[
+                ("signers", &signers),
+                ("timing", &self.timing.describe(language)),
+            ],
+        )
+    }
+}
+
+/// Who can spend an escrow, and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct ContractSummary {
+    /// Every spending condition, key path first, then leaves in tree order.
+    pub(crate) conditions: Vec<Condition>,
+}
+
+impl ContractSummary {
+    /// The conditions grouped by [`Timing`], earliest first,
+    /// merging the paths that need the same signers.
+    pub(crate) fn clauses(&self) -> Vec<Clause> {
+        let mut clauses = Vec::<Clause>::new();
+        for condition in &self.conditions {
+            match clauses
+                .iter_mut()
+                .find(|clause| clause.timing == condition.timing)
+            {
+                Some(clause) if clause.signers.contains(&condition.signers) => {}
+                Some(clause) => clause.signers.push(condition.signers.clone()),
+                None => clauses.push(Clause {
+                    signers: vec![condition.signers.clone()],
+                    timing: condition.timing,
+                }),
+            }
+        }
+        clauses.sort_by_key(|clause| match clause.timing {
+            Timing::Anytime => 0,
+            Timing::AfterBlocks { blocks, .. } => u64::from(blocks) + 1,
+        });
+        clauses
+    }
+
+    /// Renders the summary in `language`, naming parties with `name`,
+    /// such as "the buyer" for [`Party::Participant1`].
+    pub(crate) fn describe(&self, language: Language, name: impl Fn(Party) -> String) -> String {
+        let clauses = self
+            .clauses()
+            .iter()
+            .map(|clause| clause.describe(language, &name))
+            .collect::<Vec<_>>();
+        tr_args(language, "summary", &[("clauses", &clauses.join("; "))])
+    }
+}
+
+impl fmt::Display for ContractSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(Language::En, |party| party.to_string()))
+    }
+}
+
+/// Summarizes who can spend the escrow of `config` and when,
+/// with timelocks converted to time at the block pace of `profile`.
+///
+/// # Errors
+///
+/// Errors if the escrow can't be built or one of its leaf scripts can't be decoded.
+pub(crate) fn describe_escrow(
+    config: &EscrowConfig,
+    profile: &NetworkProfile,
+) -> Result<ContractSummary, Error> {
+    let context = config.context()?;
+    let mut conditions = Vec::new();
+
+    let internal_key = context.spend_info().internal_key();
+    if internal_key != *UNSPENDABLE_PUBLIC_KEY {
+        let signers = if KeyAggContext::new(&[config.npub_1, config.npub_2])?.internal_key()
+            == internal_key
+        {
+            vec![Party::Participant1, Party::Participant2]
+        } else {
+            vec![Party::Unknown(NostrPublicKey::from(internal_key))]
+        };
+        conditions.push(Condition {
+            path: SpendPath::KeyPath,
+            signers,
+            timing: Timing::Anytime,
+        });
+    }
+
+    for escrow_script in config.leaves() {
+        let leaf = context.leaf(*escrow_script)?;
+        let depth = leaf.control_block.merkle_branch.len() as u8;
+        let requirements = LeafRequirements::from_script(depth, leaf.script.clone())?;
+        let mut signers = requirements
+            .signers
+            .iter()
+            .map(|npub| Party::of(npub, config))
+            .collect::<Vec<_>>();
+        signers.sort_by_key(Party::rank);
+        let timing = match requirements.timelock {
+            Some(blocks) => Timing::AfterBlocks {
+                blocks,
+                seconds: profile.block_interval.as_secs() * u64::from(blocks),
+            },
+            None => Timing::Anytime,
+        };
+        conditions.push(Condition {
+            path: SpendPath::Leaf(*escrow_script),
+            signers,
+            timing,
+        });
+    }
+
+    Ok(ContractSummary { conditions })
+}
+
+/// Rounds `duration` to the nearest minute, hour or day, for display in `language`.
+fn approximate(duration: Duration, language: Language) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+    let seconds = duration.as_secs();
+    let (count, unit) = if seconds >= DAY {
+        ((seconds + DAY / 2) / DAY, "day")
+    } else if seconds >= HOUR {
+        ((seconds + HOUR / 2) / HOUR, "hour")
+    } else {
+        (((seconds + MINUTE / 2) / MINUTE).max(1), "minute")
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    tr_args(
+        language,
+        &format!("summary-{unit}{plural}"),
+        &[("count", &count)],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Network;
+    use nostr::Keys;
+
+    use super::*;
+    use crate::{network::Chain, scripts::ScriptTemplate};
+
+    #[test]
+    fn summary_follows_scripts() {
+        let config = EscrowConfig {
+            npub_1: Keys::generate().public_key(),
+            npub_2: Keys::generate().public_key(),
+            npub_arbitrator: Some(Keys::generate().public_key()),
+            timelock_duration: Some(1440),
+            network: Network::Signet,
+            template: ScriptTemplate::V1,
+        };
+        let mainnet = NetworkProfile::from(Chain::Mainnet);
+        let summary = describe_escrow(&config, &mainnet).unwrap();
+        assert_eq!(summary.conditions.len(), 3);
+        assert_eq!(
+            summary.conditions[1].signers,
+            vec![Party::Participant1, Party::Arbitrator]
+        );
+        assert_eq!(
+            summary.to_string(),
+            "Funds can be spent by participant 1 and participant 2 at any time; \
+             by participant 1 and the arbitrator, or participant 2 and the arbitrator, \
+             after 1440 blocks (~10 days)."
+        );
+
+        // The key path of template 2 needs the same signers as leaf A.
+        let v2 = EscrowConfig {
+            template: ScriptTemplate::V2,
+            ..config
+        };
+        let mutinynet = NetworkProfile::from(Chain::Mutinynet);
+        let summary = describe_escrow(&v2, &mutinynet).unwrap();
+        assert_eq!(summary.conditions[0].path, SpendPath::KeyPath);
+        assert_eq!(summary.clauses().len(), 2);
+        assert_eq!(summary.clauses()[0].signers.len(), 1);
+        let names = |party| match party {
+            Party::Participant1 => "the buyer".to_string(),
+            Party::Participant2 => "the seller".to_string(),
+            party => party.to_string(),
+        };
+        assert!(
+            summary
+                .describe(Language::En, names)
+                .ends_with("or the seller and the arbitrator, after 1440 blocks (~12 hours).")
+        );
+        assert!(
+            summary
+                .describe(Language::PtBr, |party| party.name(Language::PtBr))
+                .ends_with(
+                    "após 1440 blocos (~12 horas), com as assinaturas de: \
+                     participante 1 e árbitro, ou participante 2 e árbitro."
+                )
+        );
+
+        let collaborative = EscrowConfig {
+            npub_arbitrator: None,
+            timelock_duration: None,
+            ..config
+        };
+        assert_eq!(
+            describe_escrow(&collaborative, &mainnet)
+                .unwrap()
+                .to_string(),
+            "Funds can be spent by participant 1 and participant 2 at any time."
+        );
+    }
+}
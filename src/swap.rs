@@ -0,0 +1,660 @@
+//! Lightning settlement of escrow payouts through submarine swaps.
+//!
+//! With [`Settlement::Lightning`], the resolution pays a participant's payout into a swap HTLC
+//! instead of their P2TR address. The swap provider pays the participant's BOLT11 [`Invoice`]
+//! and claims the HTLC with the preimage the payment reveals, so the participant receives
+//! the payout over Lightning:
+//!
+//! 1. The participant creates an invoice and requests a swap for it from the provider,
+//!    which answers with its claim key, the timeout, and the lockup address and amount.
+//! 2. [`SwapHtlc::new`] rebuilds the HTLC from the invoice, and
+//!    [`SwapHtlc::verify_lockup_address`] checks it against the provider's lockup address.
+//! 3. [`settle_payout`] points the participant's output of the resolution to the HTLC,
+//!    which the counterparty checks with [`verify_swap_payout`] before signing.
+//! 4. If the provider never pays the invoice, the participant takes the output back
+//!    after the timeout with [`htlc_refund_tx`] and [`sign_htlc_refund`].
+//!
+//! The HTLC follows the usual Taproot swap scripts, a claim leaf for the provider
+//! with the preimage and a refund leaf for the participant after an absolute timeout,
+//! under the unspendable internal key.
+
+use std::{fmt, str::FromStr};
+
+use bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    XOnlyPublicKey, absolute,
+    address::NetworkUnchecked,
+    bech32::{Bech32, Fe32, primitives::decode::CheckedHrpstring},
+    hashes::{Hash, ripemd160, sha256},
+    opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CLTV, OP_EQUALVERIFY, OP_HASH160, OP_SIZE},
+    taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo},
+    transaction,
+};
+use nostr::{Timestamp, key::PublicKey as NostrPublicKey};
+use secp256k1::{
+    Message, PublicKey, SECP256K1,
+    ecdsa::{RecoverableSignature, RecoveryId},
+};
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    invariants::SigningInvariants,
+    scripts::UNSPENDABLE_PUBLIC_KEY,
+    secret::SecretNsec,
+    sign::{BatchSigner, leaf_witness},
+    util::{npub_to_address, npub_to_x_only_public_key},
+};
+
+/// Bech32 characters of the invoice timestamp.
+const TIMESTAMP_LENGTH: usize = 7;
+
+/// Bech32 characters of the invoice signature and its recovery id.
+const SIGNATURE_LENGTH: usize = 104;
+
+/// Tag of the payment hash field.
+const TAG_PAYMENT_HASH: u8 = 1;
+
+/// Tag of the expiry field.
+const TAG_EXPIRY: u8 = 6;
+
+/// Tag of the payee field.
+const TAG_PAYEE: u8 = 19;
+
+/// Expiry of invoices without an expiry field, in seconds.
+const DEFAULT_EXPIRY: u64 = 3600;
+
+/// Millisatoshis in a bitcoin.
+const MSAT_PER_BTC: u128 = 100_000_000_000;
+
+/// Size in bytes of the preimage the claim leaf accepts.
+const PREIMAGE_SIZE: i64 = 32;
+
+/// The big-endian integer of `fes`.
+fn to_u64(fes: &[Fe32]) -> u64 {
+    fes.iter()
+        .fold(0, |acc, fe| (acc << 5) | u64::from(fe.to_u8()))
+}
+
+/// Packs `fes` into bytes, padding the last byte with zero bits.
+fn to_bytes(fes: &[Fe32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(fes.len() * 5 / 8 + 1);
+    let (mut acc, mut bits) = (0u32, 0);
+    for fe in fes {
+        acc = (acc << 5) | u32::from(fe.to_u8());
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    if bits > 0 {
+        bytes.push((acc << (8 - bits)) as u8);
+    }
+    bytes
+}
+
+/// The message the payee signs: the hash of the human-readable part and of the data.
+fn signing_message(hrp: &str, data: &[Fe32]) -> Message {
+    let preimage = [hrp.as_bytes(), &to_bytes(data)].concat();
+    Message::from_digest(sha256::Hash::hash(&preimage).to_byte_array())
+}
+
+/// The network and the amount in millisatoshis of an invoice's human-readable part.
+fn parse_hrp(hrp: &str) -> Result<(Network, Option<u64>), Error> {
+    let currency = hrp
+        .strip_prefix("ln")
+        .ok_or_else(|| Error::WrongInputs("Invoice must start with ln".to_string()))?;
+    let (network, amount) = [
+        ("bcrt", Network::Regtest),
+        ("bc", Network::Bitcoin),
+        ("tbs", Network::Signet),
+        ("tb", Network::Testnet),
+    ]
+    .into_iter()
+    .find_map(|(prefix, network)| {
+        currency
+            .strip_prefix(prefix)
+            .filter(|amount| amount.is_empty() || amount.starts_with(|c: char| c.is_ascii_digit()))
+            .map(|amount| (network, amount))
+    })
+    .ok_or_else(|| Error::WrongInputs(format!("Unknown invoice currency: {currency}")))?;
+    Ok((network, parse_amount(amount)?))
+}
+
+/// The amount in millisatoshis of an invoice, from its digits and multiplier.
+fn parse_amount(amount: &str) -> Result<Option<u64>, Error> {
+    if amount.is_empty() {
+        return Ok(None);
+    }
+    let invalid = || Error::WrongInputs(format!("Invalid invoice amount: {amount}"));
+    let (digits, divisor) = match amount.as_bytes()[amount.len() - 1] {
+        b'm' => (&amount[..amount.len() - 1], 1_000),
+        b'u' => (&amount[..amount.len() - 1], 1_000_000),
+        b'n' => (&amount[..amount.len() - 1], 1_000_000_000),
+        b'p' => (&amount[..amount.len() - 1], 1_000_000_000_000),
+        _ => (amount, 1),
+    };
+    if digits.starts_with('0') || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let msat = u128::from(digits.parse::<u64>().map_err(|_| invalid())?) * MSAT_PER_BTC;
+    if !msat.is_multiple_of(divisor) {
+        return Err(invalid());
+    }
+    Ok(Some(u64::try_from(msat / divisor).map_err(|_| invalid())?))
+}
+
+/// A BOLT11 Lightning invoice, with the fields a swap needs.
+///
+/// Parsing checks the checksum and the payee's signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Invoice {
+    /// The invoice as encoded.
+    encoded: String,
+    /// Network of the invoice.
+    pub(crate) network: Network,
+    /// Amount requested, in millisatoshis.
+    pub(crate) amount_msat: Option<u64>,
+    /// Creation time, in seconds since the Unix epoch.
+    pub(crate) timestamp: u64,
+    /// Seconds after the creation time the invoice expires.
+    pub(crate) expiry: u64,
+    /// Hash of the preimage the payment reveals.
+    pub(crate) payment_hash: sha256::Hash,
+    /// Node the invoice pays.
+    pub(crate) payee: PublicKey,
+}
+
+impl Invoice {
+    /// The amount requested, rounded up to the satoshi.
+    pub(crate) fn amount(&self) -> Option<Amount> {
+        self.amount_msat
+            .map(|msat| Amount::from_sat(msat.div_ceil(1_000)))
+    }
+
+    /// Whether the invoice has expired at `now`.
+    pub(crate) fn is_expired(&self, now: Timestamp) -> bool {
+        now.as_u64() >= self.timestamp.saturating_add(self.expiry)
+    }
+}
+
+impl fmt::Display for Invoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.encoded)
+    }
+}
+
+impl FromStr for Invoice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let encoded = s.trim().to_lowercase();
+        let encoded = encoded
+            .strip_prefix("lightning:")
+            .unwrap_or(&encoded)
+            .to_string();
+        let checked = CheckedHrpstring::new::<Bech32>(&encoded)
+            .map_err(|e| Error::WrongInputs(format!("Invalid invoice: {e}")))?;
+        let hrp = checked.hrp();
+        let (network, amount_msat) = parse_hrp(hrp.as_str())?;
+        let data: Vec<Fe32> = checked
+            .data_part_ascii_no_checksum()
+            .iter()
+            .map(|&c| Fe32::from_char_unchecked(c))
+            .collect();
+        if data.len() < TIMESTAMP_LENGTH + SIGNATURE_LENGTH {
+            return Err(Error::WrongInputs("Invoice is too short".to_string()));
+        }
+        let (signed, signature) = data.split_at(data.len() - SIGNATURE_LENGTH);
+        let timestamp = to_u64(&signed[..TIMESTAMP_LENGTH]);
+
+        let mut fields = &signed[TIMESTAMP_LENGTH..];
+        let (mut payment_hash, mut expiry, mut payee) = (None, DEFAULT_EXPIRY, None);
+        while !fields.is_empty() {
+            let truncated = || Error::WrongInputs("Invoice field is truncated".to_string());
+            let header = fields.get(..3).ok_or_else(truncated)?;
+            let length = usize::from(header[1].to_u8()) * 32 + usize::from(header[2].to_u8());
+            let value = fields.get(3..3 + length).ok_or_else(truncated)?;
+            fields = &fields[3 + length..];
+            // Fields of unexpected lengths are skipped, as BOLT 11 requires.
+            match header[0].to_u8() {
+                TAG_PAYMENT_HASH if length == 52 && payment_hash.is_none() => {
+                    let bytes: [u8; 32] = to_bytes(value)[..32].try_into().expect("32 bytes");
+                    payment_hash = Some(sha256::Hash::from_byte_array(bytes));
+                }
+                TAG_EXPIRY if length <= 12 => expiry = to_u64(value),
+                TAG_PAYEE if length == 53 => {
+                    payee = Some(PublicKey::from_slice(&to_bytes(value)[..33])?);
+                }
+                _ => {}
+            }
+        }
+        let payment_hash = payment_hash
+            .ok_or_else(|| Error::WrongInputs("Invoice has no payment hash".to_string()))?;
+
+        let signature = to_bytes(signature);
+        let recovery_id = RecoveryId::from_i32(i32::from(signature[64]))?;
+        let signature = RecoverableSignature::from_compact(&signature[..64], recovery_id)?;
+        let recovered =
+            SECP256K1.recover_ecdsa(&signing_message(hrp.as_str(), signed), &signature)?;
+        if payee.is_some_and(|payee| payee != recovered) {
+            return Err(Error::WrongInputs(
+                "Invoice is not signed by its payee".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            encoded,
+            network,
+            amount_msat,
+            timestamp,
+            expiry,
+            payment_hash,
+            payee: recovered,
+        })
+    }
+}
+
+/// The submarine-swap HTLC a payout is locked in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct SwapHtlc {
+    /// Payment hash of the invoice the provider pays.
+    pub(crate) payment_hash: sha256::Hash,
+    /// Key the provider claims the HTLC with.
+    pub(crate) claim_key: XOnlyPublicKey,
+    /// `npub` of the participant the payout is for, who can take it back after the timeout.
+    pub(crate) refund_npub: NostrPublicKey,
+    /// Block height after which the participant can take the payout back.
+    pub(crate) timeout: absolute::LockTime,
+    /// Amount the provider expects in the HTLC.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) lockup_amount: Amount,
+    /// Network of the HTLC.
+    pub(crate) network: Network,
+}
+
+impl SwapHtlc {
+    /// The HTLC paying `invoice` to `refund_npub` through the provider of `claim_key`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the invoice is for another network, has no amount or has expired,
+    /// or if `lockup_amount` doesn't cover it.
+    pub(crate) fn new(
+        invoice: &Invoice,
+        claim_key: XOnlyPublicKey,
+        refund_npub: NostrPublicKey,
+        timeout_height: u32,
+        lockup_amount: Amount,
+        network: Network,
+        now: Timestamp,
+    ) -> Result<Self, Error> {
+        if invoice.network != network {
+            return Err(Error::WrongInputs(format!(
+                "Invoice is for {}, not {network}",
+                invoice.network
+            )));
+        }
+        if invoice.is_expired(now) {
+            return Err(Error::WrongInputs("Invoice has expired".to_string()));
+        }
+        let amount = invoice
+            .amount()
+            .ok_or_else(|| Error::WrongInputs("Swap invoices must have an amount".to_string()))?;
+        if lockup_amount < amount {
+            return Err(Error::WrongInputs(format!(
+                "Lockup amount {lockup_amount} doesn't cover the invoice amount {amount}"
+            )));
+        }
+        let timeout = absolute::LockTime::from_height(timeout_height)
+            .map_err(|e| Error::WrongInputs(format!("Invalid swap timeout: {e}")))?;
+        Ok(Self {
+            payment_hash: invoice.payment_hash,
+            claim_key,
+            refund_npub,
+            timeout,
+            lockup_amount,
+            network,
+        })
+    }
+
+    /// The leaf the provider claims the HTLC through with the preimage.
+    pub(crate) fn claim_script(&self) -> ScriptBuf {
+        let hash = ripemd160::Hash::hash(self.payment_hash.as_byte_array());
+        ScriptBuf::builder()
+            .push_opcode(OP_SIZE)
+            .push_int(PREIMAGE_SIZE)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_HASH160)
+            .push_slice(hash.to_byte_array())
+            .push_opcode(OP_EQUALVERIFY)
+            .push_x_only_key(&self.claim_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// The leaf the participant takes the HTLC back through after the timeout.
+    pub(crate) fn refund_script(&self) -> Result<ScriptBuf, Error> {
+        let refund_key = npub_to_x_only_public_key(&self.refund_npub)?;
+        Ok(ScriptBuf::builder()
+            .push_x_only_key(&refund_key)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_lock_time(self.timeout)
+            .push_opcode(OP_CLTV)
+            .into_script())
+    }
+
+    /// The Taproot tree of the claim and refund leaves.
+    pub(crate) fn spend_info(&self) -> Result<TaprootSpendInfo, Error> {
+        TaprootBuilder::new()
+            .add_leaf(1, self.claim_script())?
+            .add_leaf(1, self.refund_script()?)?
+            .finalize(SECP256K1, *UNSPENDABLE_PUBLIC_KEY)
+            .map_err(|_| Error::WrongInputs("Could not finalize the swap tree".to_string()))
+    }
+
+    /// The address of the HTLC.
+    pub(crate) fn address(&self) -> Result<Address, Error> {
+        let spend_info = self.spend_info()?;
+        Ok(Address::p2tr(
+            SECP256K1,
+            spend_info.internal_key(),
+            spend_info.merkle_root(),
+            self.network,
+        ))
+    }
+
+    /// Checks that `lockup_address`, given by the provider, is this HTLC,
+    /// so the provider can't lock the payout under other terms.
+    pub(crate) fn verify_lockup_address(&self, lockup_address: &str) -> Result<(), Error> {
+        let lockup_address = lockup_address
+            .parse::<Address<NetworkUnchecked>>()?
+            .require_network(self.network)?;
+        if lockup_address != self.address()? {
+            return Err(Error::WrongInputs(
+                "Lockup address doesn't match the swap".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The HTLC output of the resolution.
+    fn txout(&self) -> Result<TxOut, Error> {
+        Ok(TxOut {
+            value: self.lockup_amount,
+            script_pubkey: self.address()?.script_pubkey(),
+        })
+    }
+}
+
+/// How a participant receives their payout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) enum Settlement {
+    /// Paid to the participant's P2TR address.
+    #[default]
+    Onchain,
+    /// Paid over Lightning through a submarine swap.
+    Lightning(SwapHtlc),
+}
+
+/// Points the participant's payout in the resolution `tx` to the swap of `settlement`.
+///
+/// # Errors
+///
+/// Errors if `tx` doesn't pay the participant exactly the lockup amount of the swap.
+pub(crate) fn settle_payout(
+    mut tx: Transaction,
+    settlement: &Settlement,
+) -> Result<Transaction, Error> {
+    let htlc = match settlement {
+        Settlement::Onchain => return Ok(tx),
+        Settlement::Lightning(htlc) => htlc,
+    };
+    let payout_script = npub_to_address(&htlc.refund_npub, htlc.network)?.script_pubkey();
+    let output = tx
+        .output
+        .iter_mut()
+        .find(|output| output.script_pubkey == payout_script)
+        .ok_or_else(|| {
+            Error::WrongInputs(format!("Resolution doesn't pay {}", htlc.refund_npub))
+        })?;
+    if output.value != htlc.lockup_amount {
+        return Err(Error::WrongInputs(format!(
+            "Payout of {} doesn't match the lockup amount {} of the swap",
+            output.value, htlc.lockup_amount
+        )));
+    }
+    output.script_pubkey = htlc.address()?.script_pubkey();
+    Ok(tx)
+}
+
+/// Checks that the resolution `tx` pays the swap `htlc`, before signing it.
+pub(crate) fn verify_swap_payout(tx: &Transaction, htlc: &SwapHtlc) -> Result<(), Error> {
+    let txout = htlc.txout()?;
+    if !tx.output.contains(&txout) {
+        return Err(Error::WrongInputs(
+            "Resolution doesn't pay the swap".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The transaction taking the unclaimed payout locked at `outpoint` back to `destination`
+/// after the timeout of `htlc`, paying `fee`.
+pub(crate) fn htlc_refund_tx(
+    htlc: &SwapHtlc,
+    outpoint: OutPoint,
+    destination: &Address,
+    fee: Amount,
+) -> Result<Transaction, Error> {
+    let value = htlc
+        .lockup_amount
+        .checked_sub(fee)
+        .ok_or_else(|| Error::WrongInputs(format!("Fee {fee} exceeds the swap amount")))?;
+    Ok(Transaction {
+        version: transaction::Version(2),
+        lock_time: htlc.timeout,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: destination.script_pubkey(),
+        }],
+    })
+}
+
+/// Signs the refund `tx` of [`htlc_refund_tx`] through the refund leaf with `nsec`.
+pub(crate) fn sign_htlc_refund(
+    mut tx: Transaction,
+    htlc: &SwapHtlc,
+    nsec: &SecretNsec,
+) -> Result<Transaction, Error> {
+    if nsec.public_key() != htlc.refund_npub {
+        return Err(Error::WrongInputs(
+            "Only the participant can refund the swap".to_string(),
+        ));
+    }
+    let refund_script = htlc.refund_script()?;
+    // The refund leaf is locked until the timeout, not by a relative timelock.
+    let invariants = SigningInvariants {
+        lock_time: htlc.timeout,
+        ..SigningInvariants::of_local_tx(&tx, vec![htlc.txout()?], vec![None])
+    };
+    let signature = BatchSigner::new(&tx, &invariants)?.sign(0, &refund_script, nsec)?;
+    let control_block = htlc
+        .spend_info()?
+        .control_block(&(refund_script.clone(), LeafVersion::TapScript))
+        .ok_or_else(|| Error::WrongInputs("Refund is not a leaf of the swap".to_string()))?;
+    tx.input[0].witness = leaf_witness(vec![&signature], &refund_script, &control_block);
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::bech32::{ByteIterExt, Fe32IterExt, Hrp};
+    use nostr::key::SecretKey as NostrSecretKey;
+    use secp256k1::{SecretKey, schnorr};
+
+    use super::*;
+    use crate::sign::script_spend_message;
+
+    /// Encodes a signed invoice of `hrp` for `payment_hash`.
+    fn encode_invoice(
+        hrp: &str,
+        timestamp: u64,
+        payment_hash: [u8; 32],
+        key: &SecretKey,
+    ) -> String {
+        let mut data: Vec<Fe32> = (0..TIMESTAMP_LENGTH)
+            .rev()
+            .map(|i| Fe32::try_from(((timestamp >> (5 * i)) & 31) as u8).unwrap())
+            .collect();
+        data.extend([TAG_PAYMENT_HASH, 1, 20].map(|fe| Fe32::try_from(fe).unwrap()));
+        data.extend(payment_hash.into_iter().bytes_to_fes());
+        let message = signing_message(hrp, &data);
+        let (recovery_id, signature) = SECP256K1
+            .sign_ecdsa_recoverable(&message, key)
+            .serialize_compact();
+        data.extend(
+            signature
+                .into_iter()
+                .chain([recovery_id.to_i32() as u8])
+                .bytes_to_fes(),
+        );
+        data.into_iter()
+            .with_checksum::<Bech32>(&Hrp::parse(hrp).unwrap())
+            .chars()
+            .collect()
+    }
+
+    #[test]
+    fn lightning_settlement() {
+        let node_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let preimage = [42; 32];
+        let payment_hash = sha256::Hash::hash(&preimage).to_byte_array();
+        let timestamp = 1_700_000_000;
+        let encoded = encode_invoice("lnbcrt25u", timestamp, payment_hash, &node_key);
+
+        let invoice: Invoice = encoded.parse().unwrap();
+        assert_eq!(invoice.network, Network::Regtest);
+        assert_eq!(invoice.amount(), Some(Amount::from_sat(2_500)));
+        assert_eq!(invoice.payment_hash.to_byte_array(), payment_hash);
+        assert_eq!(invoice.payee, node_key.public_key(SECP256K1));
+        assert_eq!(invoice.expiry, DEFAULT_EXPIRY);
+        assert_eq!(invoice.to_string(), encoded);
+        assert_eq!(
+            format!("lightning:{}", encoded.to_uppercase())
+                .parse::<Invoice>()
+                .unwrap(),
+            invoice
+        );
+        assert_eq!(parse_amount("10p").unwrap(), Some(1));
+        assert!(parse_amount("1p").is_err());
+        assert!(parse_amount("01m").is_err());
+
+        // A tampered invoice fails its checksum, and a re-signed one its payee.
+        let mut tampered = encoded.clone().into_bytes();
+        let last = tampered.len() - 10;
+        tampered[last] = if tampered[last] == b'q' { b'p' } else { b'q' };
+        assert!(
+            String::from_utf8(tampered)
+                .unwrap()
+                .parse::<Invoice>()
+                .is_err()
+        );
+
+        let provider = SecretNsec::from(NostrSecretKey::from_slice(&[3; 32]).unwrap());
+        let participant = SecretNsec::from(NostrSecretKey::from_slice(&[5; 32]).unwrap());
+        let claim_key = npub_to_x_only_public_key(&provider.public_key()).unwrap();
+        let lockup_amount = Amount::from_sat(2_600);
+        let now = Timestamp::from(timestamp + 60);
+        let new_htlc = |network, lockup_amount, now| {
+            SwapHtlc::new(
+                &invoice,
+                claim_key,
+                participant.public_key(),
+                1_000,
+                lockup_amount,
+                network,
+                now,
+            )
+        };
+        assert!(new_htlc(Network::Bitcoin, lockup_amount, now).is_err());
+        assert!(new_htlc(Network::Regtest, Amount::from_sat(2_499), now).is_err());
+        assert!(
+            new_htlc(
+                Network::Regtest,
+                lockup_amount,
+                Timestamp::from(timestamp + DEFAULT_EXPIRY)
+            )
+            .is_err()
+        );
+        let htlc = new_htlc(Network::Regtest, lockup_amount, now).unwrap();
+        let address = htlc.address().unwrap();
+        htlc.verify_lockup_address(&address.to_string()).unwrap();
+        let other = npub_to_address(&provider.public_key(), Network::Regtest).unwrap();
+        assert!(htlc.verify_lockup_address(&other.to_string()).is_err());
+
+        // The participant's payout moves to the HTLC, the other output stays.
+        let payout = npub_to_address(&participant.public_key(), Network::Regtest).unwrap();
+        let resolution = Transaction {
+            version: transaction::Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![
+                TxOut {
+                    value: lockup_amount,
+                    script_pubkey: payout.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: other.script_pubkey(),
+                },
+            ],
+        };
+        let onchain = settle_payout(resolution.clone(), &Settlement::Onchain).unwrap();
+        assert_eq!(onchain, resolution);
+        assert!(verify_swap_payout(&onchain, &htlc).is_err());
+        let settlement = Settlement::Lightning(htlc.clone());
+        let settled = settle_payout(resolution.clone(), &settlement).unwrap();
+        assert_eq!(settled.output[0].script_pubkey, address.script_pubkey());
+        assert_eq!(settled.output[1], resolution.output[1]);
+        verify_swap_payout(&settled, &htlc).unwrap();
+        let mut underpaid = resolution;
+        underpaid.output[0].value = Amount::from_sat(2_500);
+        assert!(settle_payout(underpaid, &settlement).is_err());
+
+        // Only the participant refunds, after the timeout, through the refund leaf.
+        let outpoint = OutPoint::new(settled.compute_txid(), 0);
+        let refund = htlc_refund_tx(&htlc, outpoint, &payout, Amount::from_sat(200)).unwrap();
+        assert_eq!(refund.lock_time, htlc.timeout);
+        assert_eq!(refund.output[0].value, Amount::from_sat(2_400));
+        assert!(sign_htlc_refund(refund.clone(), &htlc, &provider).is_err());
+        let signed = sign_htlc_refund(refund, &htlc, &participant).unwrap();
+        let witness = &signed.input[0].witness;
+        assert_eq!(witness.len(), 3);
+        let refund_script = htlc.refund_script().unwrap();
+        assert_eq!(witness.nth(1).unwrap(), refund_script.as_bytes());
+        let message =
+            script_spend_message(&signed, 0, &[htlc.txout().unwrap()], &refund_script).unwrap();
+        let signature = schnorr::Signature::from_slice(witness.nth(0).unwrap()).unwrap();
+        SECP256K1
+            .verify_schnorr(
+                &signature,
+                &message,
+                &npub_to_x_only_public_key(&participant.public_key()).unwrap(),
+            )
+            .unwrap();
+    }
+}
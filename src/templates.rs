@@ -0,0 +1,386 @@
+//! Escrow templates shared by communities.
+//!
+//! An [`EscrowTemplate`] is a standard escrow configuration, such as the amounts,
+//! arbitrator and dispute timelock a marketplace uses for its trades, that loads into the
+//! creation wizard. Templates are JSON documents following the published
+//! [`TEMPLATE_SCHEMA`], and leave the parties to be chosen when the escrow is created.
+//!
+//! Templates come from strangers, so [`EscrowTemplate::import`] checks them against the
+//! user's [`TemplatePolicy`] before anything reaches the wizard: the arbitrator must be one
+//! the user allows, and the timelock and fee rate must be within bounds.
+
+use std::time::Duration;
+
+use bitcoin::{Amount, Denomination};
+use nostr::key::PublicKey as NostrPublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address_book::AddressBook,
+    canonical,
+    draft::EscrowDraft,
+    error::Error,
+    network::{Chain, NetworkProfile},
+    settings::FeeRateLimits,
+    util::{blocks_for_duration, days_hours, parse_npub},
+};
+
+/// Version of the [`EscrowTemplate`] format.
+pub(crate) const TEMPLATE_VERSION: u8 = 1;
+
+/// JSON Schema of the [`EscrowTemplate`] format, published for template authors.
+pub(crate) const TEMPLATE_SCHEMA: &str = include_str!("../assets/escrow-template.schema.json");
+
+/// Largest template accepted, in bytes.
+pub(crate) const MAX_TEMPLATE_SIZE: usize = 16 * 1024;
+
+/// Longest template name, in characters.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Longest template description, in characters.
+const MAX_DESCRIPTION_LENGTH: usize = 1_000;
+
+/// Shortest dispute timelock accepted from a template by default.
+pub(crate) const MIN_TEMPLATE_TIMELOCK: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest dispute timelock accepted from a template by default.
+pub(crate) const MAX_TEMPLATE_TIMELOCK: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// A shared escrow configuration, see [`TEMPLATE_SCHEMA`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EscrowTemplate {
+    /// URL of the schema the template follows, if given.
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) schema: Option<String>,
+    /// Format version, see [`TEMPLATE_VERSION`].
+    pub(crate) version: u8,
+    /// Name shown in the wizard.
+    pub(crate) name: String,
+    /// What the template is for and the terms it assumes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) description: String,
+    /// Chain the template is meant for, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) chain: Option<Chain>,
+    /// Buyer's escrow amount, if standard.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bitcoin::amount::serde::as_sat::opt"
+    )]
+    pub(crate) amount_buyer: Option<Amount>,
+    /// Seller's escrow amount, if standard.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bitcoin::amount::serde::as_sat::opt"
+    )]
+    pub(crate) amount_seller: Option<Amount>,
+    /// Resolution fee rate in sats/vByte, if standard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) fee_rate: Option<u64>,
+    /// Arbitrator `npub`, [`None`] for collaborative escrows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) arbitrator: Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub(crate) timelock_days: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub(crate) timelock_hours: u32,
+}
+
+/// Whether `count` is zero, to skip it when serializing.
+fn is_zero(count: &u32) -> bool {
+    *count == 0
+}
+
+/// What the user accepts from imported templates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TemplatePolicy {
+    /// The chain escrows are created on.
+    pub(crate) chain: Chain,
+    /// Arbitrators templates may name.
+    pub(crate) arbitrators: Vec<NostrPublicKey>,
+    /// Shortest dispute timelock accepted.
+    pub(crate) min_timelock: Duration,
+    /// Longest dispute timelock accepted.
+    pub(crate) max_timelock: Duration,
+    /// Fee rates the user accepts, from the [`Settings`](crate::settings::Settings).
+    pub(crate) fee_rates: FeeRateLimits,
+}
+
+impl TemplatePolicy {
+    /// The default policy on `chain`, allowing the arbitrators of the `address_book`.
+    pub(crate) fn new(chain: Chain, address_book: &AddressBook, fee_rates: FeeRateLimits) -> Self {
+        Self {
+            chain,
+            arbitrators: address_book
+                .contacts()
+                .iter()
+                .map(|contact| contact.npub)
+                .collect(),
+            min_timelock: MIN_TEMPLATE_TIMELOCK,
+            max_timelock: MAX_TEMPLATE_TIMELOCK,
+            fee_rates,
+        }
+    }
+}
+
+impl EscrowTemplate {
+    /// Parses a template from `json` and checks it against `policy`,
+    /// see [`EscrowTemplate::validate`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if `json` is too large, is not a template, repeats a member name
+    /// or is refused by `policy`.
+    pub(crate) fn import(json: &str, policy: &TemplatePolicy) -> Result<Self, Error> {
+        if json.len() > MAX_TEMPLATE_SIZE {
+            return Err(Error::Template(format!(
+                "the template is larger than {MAX_TEMPLATE_SIZE} bytes"
+            )));
+        }
+        let template: Self = canonical::decode(json).map_err(|e| match e {
+            Error::Protocol(reason) => Error::Template(reason),
+            e => e,
+        })?;
+        template.validate(policy)?;
+        Ok(template)
+    }
+
+    /// Checks the template is well-formed and acceptable under `policy`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the template is of another version or chain, names an arbitrator the
+    /// policy doesn't allow, or has a timelock or fee rate out of the policy bounds.
+    pub(crate) fn validate(&self, policy: &TemplatePolicy) -> Result<(), Error> {
+        if self.version != TEMPLATE_VERSION {
+            return Err(Error::Template(format!(
+                "unsupported template version {}",
+                self.version
+            )));
+        }
+        check_text("name", &self.name, MAX_NAME_LENGTH)?;
+        if self.name.trim().is_empty() {
+            return Err(Error::Template("the template has no name".to_string()));
+        }
+        check_text("description", &self.description, MAX_DESCRIPTION_LENGTH)?;
+        if let Some(chain) = self.chain.filter(|chain| *chain != policy.chain) {
+            return Err(Error::Template(format!(
+                "the template is for {chain}, not {}",
+                policy.chain
+            )));
+        }
+        for amount in [self.amount_buyer, self.amount_seller]
+            .into_iter()
+            .flatten()
+        {
+            if amount > Amount::MAX_MONEY {
+                return Err(Error::Template(format!("amount {amount} is too large")));
+            }
+        }
+        if let Some(fee_rate) = self.fee_rate {
+            policy.fee_rates.check_sat_per_vb(fee_rate).map_err(|_| {
+                Error::Template(format!(
+                    "fee rate {fee_rate} sat/vB is outside your fee rate limits"
+                ))
+            })?;
+        }
+        if self.timelock_hours > 23 {
+            return Err(Error::Template(
+                "timelock hours must be between 0 and 23".to_string(),
+            ));
+        }
+        self.check_dispute(policy)
+    }
+
+    /// Checks the arbitrator is allowed and the timelock is within bounds.
+    fn check_dispute(&self, policy: &TemplatePolicy) -> Result<(), Error> {
+        let timelock = days_hours(self.timelock_days, self.timelock_hours);
+        let Some(arbitrator) = &self.arbitrator else {
+            if !timelock.is_zero() {
+                return Err(Error::Template(
+                    "a timelock needs an arbitrator".to_string(),
+                ));
+            }
+            return Ok(());
+        };
+        if !arbitrator.starts_with("npub1") {
+            return Err(Error::Template(
+                "the arbitrator must be an npub".to_string(),
+            ));
+        }
+        let npub = parse_npub(arbitrator)
+            .map_err(|_| Error::Template(format!("invalid arbitrator {arbitrator}")))?;
+        if !policy.arbitrators.contains(&npub) {
+            return Err(Error::Template(format!(
+                "arbitrator {arbitrator} is not in your address book"
+            )));
+        }
+        if timelock < policy.min_timelock || timelock > policy.max_timelock {
+            return Err(Error::Template(format!(
+                "the timelock must be between {} and {} days",
+                policy.min_timelock.as_secs_f64() / 86_400.0,
+                policy.max_timelock.as_secs_f64() / 86_400.0,
+            )));
+        }
+        blocks_for_duration(timelock, &NetworkProfile::from(policy.chain)).ok_or_else(|| {
+            Error::Template(format!("the timelock is too long for {}", policy.chain))
+        })?;
+        Ok(())
+    }
+
+    /// `draft` with the fields the template sets replaced, the parties and network kept.
+    pub(crate) fn apply(&self, draft: &EscrowDraft) -> EscrowDraft {
+        let btc = |amount: Amount| amount.to_string_in(Denomination::Bitcoin);
+        let count = |count: u32| match count {
+            0 => String::new(),
+            count => count.to_string(),
+        };
+        EscrowDraft {
+            amount_buyer: self.amount_buyer.map_or(draft.amount_buyer.clone(), btc),
+            amount_seller: self.amount_seller.map_or(draft.amount_seller.clone(), btc),
+            fee_rate: self
+                .fee_rate
+                .map_or(draft.fee_rate.clone(), |fee_rate| fee_rate.to_string()),
+            npub_arbitrator: self.arbitrator.clone().unwrap_or_default(),
+            timelock_days: count(self.timelock_days),
+            timelock_hours: count(self.timelock_hours),
+            ..draft.clone()
+        }
+    }
+}
+
+/// Checks the `field` text is at most `max_length` characters, without control or
+/// bidirectional formatting characters, other than line breaks, that could disguise it
+/// in the wizard.
+fn check_text(field: &str, text: &str, max_length: usize) -> Result<(), Error> {
+    if text.chars().count() > max_length {
+        return Err(Error::Template(format!(
+            "the {field} is longer than {max_length} characters"
+        )));
+    }
+    let disguising = |c: char| {
+        (c.is_control() && c != '\n')
+            || matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+    };
+    if text.chars().any(disguising) {
+        return Err(Error::Template(format!(
+            "the {field} contains control characters"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::{Keys, nips::nip19::ToBech32};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{address_book::Contact, draft::WizardStep, protocol::Role};
+
+    fn policy(arbitrator: NostrPublicKey) -> TemplatePolicy {
+        let mut address_book = AddressBook::default();
+        address_book.upsert(Contact {
+            npub: arbitrator,
+            label: "Arbitrator".to_string(),
+            payout_address: None,
+            trust_notes: String::new(),
+        });
+        TemplatePolicy::new(Chain::Signet, &address_book, FeeRateLimits::default())
+    }
+
+    #[test]
+    fn template_import() {
+        let arbitrator = Keys::generate().public_key();
+        let policy = policy(arbitrator);
+        let json = format!(
+            "{{\"$schema\":\"https://satoshiescrow.com/schemas/escrow-template.schema.json\",\
+             \"version\":1,\"name\":\"Freelance\",\"chain\":\"Signet\",\
+             \"amount_buyer\":100000,\"fee_rate\":2,\"arbitrator\":\"{}\",\
+             \"timelock_days\":7}}",
+            arbitrator.to_bech32().unwrap()
+        );
+        let template = EscrowTemplate::import(&json, &policy).unwrap();
+        assert_eq!(template.amount_buyer, Some(Amount::from_sat(100_000)));
+
+        let draft = template.apply(&EscrowDraft {
+            network: "Signet".to_string(),
+            role: Role::Buyer,
+            npub_buyer: Keys::generate().public_key().to_hex(),
+            npub_seller: Keys::generate().public_key().to_hex(),
+            amount_buyer: String::new(),
+            amount_seller: "0.0005".to_string(),
+            fee_rate: String::new(),
+            fee_rates: FeeRateLimits::default(),
+            npub_arbitrator: String::new(),
+            timelock_days: String::new(),
+            timelock_hours: "3".to_string(),
+        });
+        assert_eq!(draft.amount_buyer, "0.001");
+        assert_eq!(draft.amount_seller, "0.0005");
+        assert_eq!(draft.timelock_hours, "");
+        assert!(draft.validate(WizardStep::Review).is_ok());
+
+        // Unknown arbitrators, unsafe timelocks and ambiguous or foreign templates are refused.
+        let refused = |template: &str| EscrowTemplate::import(template, &policy).is_err();
+        let stranger = Keys::generate().public_key().to_bech32().unwrap();
+        assert!(refused(
+            &json.replace(&arbitrator.to_bech32().unwrap(), &stranger)
+        ));
+        assert!(refused(
+            &json.replace("\"timelock_days\":7", "\"timelock_hours\":1")
+        ));
+        assert!(refused(
+            &json.replace("\"timelock_days\":7", "\"timelock_days\":366")
+        ));
+        assert!(refused(&json.replace("Signet", "Mainnet")));
+        assert!(refused(&json.replace("\"version\":1", "\"version\":2")));
+        assert!(refused(
+            &json.replace("\"fee_rate\":2", "\"fee_rate\":2,\"fee_rate\":50")
+        ));
+        assert!(refused(&json.replace("\"fee_rate\":2", "\"fee\":2")));
+        assert!(refused(&json.replace("Freelance", "Free\u{202e}lance")));
+        assert!(refused(&" ".repeat(MAX_TEMPLATE_SIZE + 1)));
+        assert!(refused("{\"version\":1,\"name\":\"\"}"));
+        assert!(!refused("{\"version\":1,\"name\":\"Collaborative\"}"));
+    }
+
+    #[test]
+    fn schema_matches_template() {
+        let schema: Value = serde_json::from_str(TEMPLATE_SCHEMA).unwrap();
+        let template = EscrowTemplate {
+            schema: Some(schema["$id"].as_str().unwrap().to_string()),
+            version: TEMPLATE_VERSION,
+            name: "Full".to_string(),
+            description: "Every field".to_string(),
+            chain: Some(Chain::Mainnet),
+            amount_buyer: Some(Amount::ONE_SAT),
+            amount_seller: Some(Amount::ONE_SAT),
+            fee_rate: Some(1),
+            arbitrator: Some(Keys::generate().public_key().to_bech32().unwrap()),
+            timelock_days: 1,
+            timelock_hours: 1,
+        };
+        let Value::Object(fields) = serde_json::to_value(&template).unwrap() else {
+            panic!("templates are objects");
+        };
+        let mut fields = fields.keys().collect::<Vec<_>>();
+        let mut properties = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>();
+        fields.sort();
+        properties.sort();
+        assert_eq!(fields, properties);
+        assert_eq!(schema["properties"]["version"]["const"], TEMPLATE_VERSION);
+        let chains = schema["properties"]["chain"]["enum"].as_array().unwrap();
+        assert_eq!(
+            chains,
+            &Chain::ALL.map(|chain| Value::from(chain.name())).to_vec()
+        );
+    }
+}
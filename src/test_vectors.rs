@@ -0,0 +1,269 @@
+//! Deterministic test vectors of the escrow core, with fixed keys.
+//!
+//! The vectors live in `fixtures/test_vectors.json` so other implementations and the WASM
+//! backend can be checked bit-for-bit against this crate.
+//! For every escrow variant on regtest and signet, they hold the escrow address,
+//! the leaf scripts and hashes, the control blocks, and the sighash, signatures
+//! and signed transaction of a spend through every leaf.
+//!
+//! Signatures are deterministic since the signers never use auxiliary randomness.
+
+use bitcoin::{
+    Amount, Network, TapLeafHash, TapSighashType, Txid, absolute,
+    consensus::encode::serialize_hex,
+    hashes::Hash,
+    hex::DisplayHex,
+    sighash::{Prevouts, SighashCache},
+    taproot::LeafVersion,
+};
+use nostr::{key::PublicKey as NostrPublicKey, nips::nip19::ToBech32};
+use serde::Deserialize;
+
+use crate::{
+    accounts::Keystore,
+    invariants::{SigningInvariants, leaf_timelock},
+    scripts::{EscrowConfig, ScriptTemplate, UNSPENDABLE_PUBLIC_KEY},
+    sign::{LeafSignatures, combine_signatures, sign_escrow_tx},
+    tx::escrow_tx,
+    util::{parse_escrow_type, parse_nsec},
+};
+
+/// The test vectors, as JSON.
+pub(crate) const TEST_VECTORS: &str = include_str!("../fixtures/test_vectors.json");
+
+/// All test vectors.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Fixture {
+    /// The fixed keys used by the vectors.
+    pub(crate) keys: Vec<KeyVector>,
+    /// One vector per escrow variant and network.
+    pub(crate) vectors: Vec<EscrowVector>,
+}
+
+/// A fixed Nostr key pair.
+#[derive(Debug, Deserialize)]
+pub(crate) struct KeyVector {
+    /// Name of the key owner.
+    pub(crate) name: String,
+    /// Secret key, in hex.
+    pub(crate) nsec: String,
+    /// Public key, in hex.
+    pub(crate) npub: NostrPublicKey,
+    /// Secret key, in NIP-19 bech32.
+    pub(crate) nsec_bech32: String,
+    /// Public key, in NIP-19 bech32.
+    pub(crate) npub_bech32: String,
+}
+
+/// An escrow, its address, and a spend through each of its leaves.
+#[derive(Debug, Deserialize)]
+pub(crate) struct EscrowVector {
+    /// Escrow variant and network.
+    pub(crate) name: String,
+    pub(crate) network: Network,
+    pub(crate) npub_1: NostrPublicKey,
+    pub(crate) npub_2: NostrPublicKey,
+    pub(crate) npub_arbitrator: Option<NostrPublicKey>,
+    pub(crate) timelock_duration: Option<u32>,
+    pub(crate) escrow_address: String,
+    /// Merkle root of the script tree, in byte order.
+    pub(crate) merkle_root: String,
+    /// Tweaked output key.
+    pub(crate) output_key: String,
+    /// Funding transaction, paying the escrow at output 0.
+    pub(crate) funding_txid: Txid,
+    pub(crate) amount_1: u64,
+    pub(crate) amount_2: u64,
+    pub(crate) fee: u64,
+    pub(crate) prevout_script_pubkey: String,
+    /// The spend built by [`escrow_tx`], unsigned.
+    pub(crate) unsigned_tx: String,
+    pub(crate) leaves: Vec<LeafVector>,
+}
+
+/// A spend of an escrow through one of its leaves.
+#[derive(Debug, Deserialize)]
+pub(crate) struct LeafVector {
+    pub(crate) escrow_script: String,
+    pub(crate) script: String,
+    /// Leaf hash, in byte order.
+    pub(crate) leaf_hash: String,
+    pub(crate) control_block: String,
+    /// Signers of the leaf, in witness order.
+    pub(crate) signers: Vec<NostrPublicKey>,
+    /// Sighash of input 0 of the spend, in byte order.
+    pub(crate) sighash: String,
+    /// Signatures of the signers, in witness order.
+    pub(crate) signatures: Vec<String>,
+    pub(crate) signed_tx: String,
+}
+
+/// Parses the test vectors.
+pub(crate) fn fixture() -> Fixture {
+    serde_json::from_str(TEST_VECTORS).expect("valid test vectors")
+}
+
+#[test]
+fn keys_match_vectors() {
+    let fixture = fixture();
+    assert_eq!(fixture.keys.len(), 3);
+    for key in &fixture.keys {
+        assert_eq!(
+            parse_nsec(&key.nsec).unwrap().public_key(),
+            key.npub,
+            "{}",
+            key.name
+        );
+        assert_eq!(
+            parse_nsec(&key.nsec_bech32).unwrap().public_key(),
+            key.npub,
+            "{}",
+            key.name
+        );
+        assert_eq!(
+            key.npub.to_bech32().unwrap(),
+            key.npub_bech32,
+            "{}",
+            key.name
+        );
+    }
+}
+
+#[test]
+fn escrows_match_vectors() {
+    let fixture = fixture();
+    assert_eq!(fixture.vectors.len(), 4);
+    for vector in &fixture.vectors {
+        let name = vector.name.as_str();
+        let config = EscrowConfig {
+            npub_1: vector.npub_1,
+            npub_2: vector.npub_2,
+            npub_arbitrator: vector.npub_arbitrator,
+            timelock_duration: vector.timelock_duration,
+            network: vector.network,
+            template: ScriptTemplate::V1,
+        };
+        let address = config.address().unwrap();
+        assert_eq!(address.to_string(), vector.escrow_address, "{name}");
+        assert_eq!(
+            address.script_pubkey().to_hex_string(),
+            vector.prevout_script_pubkey,
+            "{name}"
+        );
+        let spend_info = config.spend_info().unwrap();
+        assert_eq!(spend_info.internal_key(), *UNSPENDABLE_PUBLIC_KEY, "{name}");
+        assert_eq!(
+            spend_info
+                .merkle_root()
+                .unwrap()
+                .as_byte_array()
+                .to_lower_hex_string(),
+            vector.merkle_root,
+            "{name}"
+        );
+        assert_eq!(
+            spend_info.output_key().to_inner().to_string(),
+            vector.output_key,
+            "{name}"
+        );
+
+        let tx = escrow_tx(
+            &vector.npub_1,
+            &vector.npub_2,
+            vector.timelock_duration,
+            Amount::from_sat(vector.amount_1),
+            Amount::from_sat(vector.amount_2),
+            vector.funding_txid,
+            Amount::from_sat(vector.fee),
+            vector.network,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        assert_eq!(serialize_hex(&tx), vector.unsigned_tx, "{name}");
+        let prevouts = vec![bitcoin::TxOut {
+            value: Amount::from_sat(vector.amount_1 + vector.amount_2),
+            script_pubkey: address.script_pubkey(),
+        }];
+
+        assert_eq!(vector.leaves.len(), config.leaves().len(), "{name}");
+        for leaf in &vector.leaves {
+            let escrow_script = parse_escrow_type(&leaf.escrow_script).unwrap();
+            let name = format!("{name} leaf {escrow_script:?}");
+            let script = config.script(escrow_script).unwrap();
+            assert_eq!(script.to_hex_string(), leaf.script, "{name}");
+            let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+            assert_eq!(
+                leaf_hash.as_byte_array().to_lower_hex_string(),
+                leaf.leaf_hash,
+                "{name}"
+            );
+            let control_block = spend_info
+                .control_block(&(script.clone(), LeafVersion::TapScript))
+                .unwrap();
+            assert_eq!(
+                control_block.serialize().to_lower_hex_string(),
+                leaf.control_block,
+                "{name}"
+            );
+            assert_eq!(
+                config.signers(escrow_script).unwrap().to_vec(),
+                leaf.signers,
+                "{name}"
+            );
+
+            let sighash = SighashCache::new(&tx)
+                .taproot_script_spend_signature_hash(
+                    0,
+                    &Prevouts::All(&prevouts),
+                    leaf_hash,
+                    TapSighashType::Default,
+                )
+                .unwrap();
+            assert_eq!(
+                sighash.as_byte_array().to_lower_hex_string(),
+                leaf.sighash,
+                "{name}"
+            );
+
+            let invariants = SigningInvariants::of_local_tx(
+                &tx,
+                prevouts.clone(),
+                vec![leaf_timelock(escrow_script, vector.timelock_duration).unwrap()],
+            );
+            let mut signatures = LeafSignatures::new(tx.compute_txid(), 0, escrow_script);
+            for (npub, expected) in leaf.signers.iter().zip(&leaf.signatures) {
+                let key = fixture.keys.iter().find(|key| key.npub == *npub).unwrap();
+                let mut keystore = Keystore::default();
+                let account = keystore.unlock(parse_nsec(&key.nsec).unwrap());
+                let signature = sign_escrow_tx(
+                    &tx,
+                    0,
+                    &keystore,
+                    &account,
+                    &vector.npub_1,
+                    &vector.npub_2,
+                    vector.npub_arbitrator.as_ref(),
+                    vector.timelock_duration,
+                    &invariants,
+                    escrow_script,
+                )
+                .unwrap();
+                assert_eq!(
+                    signature.as_ref().to_lower_hex_string(),
+                    *expected,
+                    "{name}"
+                );
+                signatures.insert(*npub, signature);
+            }
+            let signed = combine_signatures(
+                tx.clone(),
+                0,
+                signatures.ordered(&config).unwrap(),
+                &script,
+                &spend_info,
+            )
+            .unwrap();
+            assert_eq!(serialize_hex(&signed), leaf.signed_tx, "{name}");
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! End-to-end escrow testing against a regtest `bitcoind`.
+//!
+//! [`RegtestEscrowHarness`] spins up a node with `corepc-node`, funds `npub`s with mined
+//! coinbases, sends them to escrow addresses, mines blocks and asserts whether the node
+//! accepts resolution transactions, so escrow flows run against real consensus rules.
+//!
+//! It backs the signing tests, and is exposed with the `testkit` feature for the CI of
+//! integrators. The node binary is downloaded by `corepc-node` at build time.
+
+use bitcoin::{
+    Address, Amount, BlockHash, Network, OutPoint, Transaction, TxIn, TxOut, Txid, absolute,
+    transaction,
+};
+use corepc_node::{Client, Node};
+use nostr::key::PublicKey as NostrPublicKey;
+
+use crate::{
+    accounts::Keystore, invariants::SigningInvariants, secret::SecretNsec,
+    sign::sign_resolution_tx, util::npub_to_address,
+};
+
+/// Reward of the coinbases mined by the harness.
+pub(crate) const COINBASE_AMOUNT: Amount = Amount::from_sat(5_000_000_000);
+
+/// Blocks before a coinbase can be spent.
+pub(crate) const COINBASE_MATURITY: usize = 101;
+
+/// A regtest node to run escrows against.
+///
+/// The node is stopped when the harness is dropped.
+#[derive(Debug)]
+pub(crate) struct RegtestEscrowHarness {
+    /// The running node.
+    node: Node,
+    /// Network of the node.
+    network: Network,
+    /// Address blocks are mined to, unless they fund an `npub`.
+    mining_address: Address,
+}
+
+impl RegtestEscrowHarness {
+    /// Spins up a regtest node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node can't be started.
+    pub(crate) fn new() -> Self {
+        let node = Node::from_downloaded().expect("must start bitcoind");
+        let network = node
+            .client
+            .get_blockchain_info()
+            .expect("must get blockchain info")
+            .chain
+            .parse::<Network>()
+            .expect("network must be valid");
+        let mining_address = npub_to_address(&SecretNsec::generate().public_key(), network)
+            .expect("must derive the mining address");
+        Self {
+            node,
+            network,
+            mining_address,
+        }
+    }
+
+    /// Network of the node.
+    pub(crate) fn network(&self) -> Network {
+        self.network
+    }
+
+    /// RPC client of the node, for anything the harness doesn't cover.
+    pub(crate) fn client(&self) -> &Client {
+        &self.node.client
+    }
+
+    /// Mines `blocks` blocks.
+    pub(crate) fn mine(&self, blocks: usize) {
+        self.client()
+            .generate_to_address(blocks, &self.mining_address)
+            .expect("must be able to generate blocks");
+    }
+
+    /// Mines a coinbase to the P2TR address of `npub` and mines it to maturity.
+    ///
+    /// Returns the spendable coinbase output and its [`TxOut`].
+    pub(crate) fn fund_npub(&self, npub: &NostrPublicKey) -> (OutPoint, TxOut) {
+        let address = npub_to_address(npub, self.network).expect("must derive the address");
+        let coinbase_block = self
+            .client()
+            .generate_to_address(1, &address)
+            .expect("must be able to generate blocks")
+            .0
+            .first()
+            .expect("must be able to get the blocks")
+            .parse::<BlockHash>()
+            .expect("must parse");
+        let coinbase_txid = self
+            .client()
+            .get_block(coinbase_block)
+            .expect("must be able to get coinbase block")
+            .coinbase()
+            .expect("must be able to get the coinbase transaction")
+            .compute_txid();
+        self.mine(COINBASE_MATURITY - 1);
+        (
+            OutPoint::new(coinbase_txid, 0),
+            TxOut {
+                value: COINBASE_AMOUNT,
+                script_pubkey: address.script_pubkey(),
+            },
+        )
+    }
+
+    /// Funds `escrow_address` with `amount` from a coinbase of `nsec`'s `npub`,
+    /// the rest of the coinbase going to fees, and confirms it.
+    ///
+    /// Returns the funding transaction, whose first output pays the escrow.
+    pub(crate) fn fund_escrow(
+        &self,
+        nsec: &SecretNsec,
+        escrow_address: &Address,
+        amount: Amount,
+    ) -> Txid {
+        let (outpoint, prevout) = self.fund_npub(&nsec.public_key());
+        let unsigned = Transaction {
+            version: transaction::Version(2),
+            input: vec![TxIn {
+                previous_output: outpoint,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: amount,
+                script_pubkey: escrow_address.script_pubkey(),
+            }],
+            lock_time: absolute::LockTime::ZERO,
+        };
+        let invariants = SigningInvariants::of_local_tx(&unsigned, vec![prevout], vec![None]);
+        let mut keystore = Keystore::default();
+        let account = keystore.unlock(nsec.duplicate());
+        let signed = sign_resolution_tx(&unsigned, &keystore, &account, &invariants)
+            .expect("must sign the funding transaction");
+        let txid = self.assert_accepted(&signed);
+        self.mine(1);
+        txid
+    }
+
+    /// Broadcasts `tx` and asserts the node accepts it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node rejects `tx`.
+    pub(crate) fn assert_accepted(&self, tx: &Transaction) -> Txid {
+        let txid = self
+            .client()
+            .send_raw_transaction(tx)
+            .unwrap_or_else(|e| panic!("transaction {} rejected: {e}", tx.compute_txid()))
+            .txid()
+            .expect("must parse the txid");
+        assert_eq!(txid, tx.compute_txid());
+        txid
+    }
+
+    /// Broadcasts `tx` and asserts the node rejects it, such as before its timelock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node accepts `tx`.
+    pub(crate) fn assert_rejected(&self, tx: &Transaction) {
+        assert!(
+            self.client().send_raw_transaction(tx).is_err(),
+            "transaction {} accepted",
+            tx.compute_txid()
+        );
+    }
+}
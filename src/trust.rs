@@ -0,0 +1,383 @@
+//! Proofs that no single party can spend from an escrow.
+//!
+//! A [`TrustProof`] lists every leaf of the escrow's tap tree together with
+//! the keys and timelock it requires, as decoded from the leaf script itself.
+//! [`TrustProof::verify`] rebuilds the escrow address from those scripts,
+//! so the proof can be checked by anyone holding the address and the parties' `npub`s,
+//! without trusting whoever generated it.
+
+use std::fmt;
+
+use bitcoin::{
+    Address, Network, ScriptBuf, Sequence, XOnlyPublicKey,
+    address::NetworkUnchecked,
+    opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CSV, OP_DROP},
+    relative,
+    script::Instruction,
+    taproot::{LeafVersion, TaprootBuilder},
+};
+use nostr::key::PublicKey as NostrPublicKey;
+use secp256k1::SECP256K1;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    musig::KeyAggContext,
+    scripts::{EscrowConfig, UNSPENDABLE_PUBLIC_KEY},
+};
+
+/// Spending requirements of a single tap leaf.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LeafRequirements {
+    /// Depth of the leaf in the tap tree.
+    pub(crate) depth: u8,
+    /// The leaf script.
+    pub(crate) script: ScriptBuf,
+    /// Keys that must all sign, in script order.
+    pub(crate) signers: Vec<NostrPublicKey>,
+    /// Relative timelock in blocks that must pass before the leaf can be spent, if any.
+    pub(crate) timelock: Option<u32>,
+}
+
+impl LeafRequirements {
+    /// Decodes the requirements of a leaf `script` at `depth`.
+    ///
+    /// Only scripts made of an optional `<n> OP_CSV OP_DROP` prefix followed by
+    /// `<key> OP_CHECKSIGVERIFY ... <key> OP_CHECKSIG` are understood,
+    /// anything else can't be reasoned about and is rejected.
+    pub(crate) fn from_script(depth: u8, script: ScriptBuf) -> Result<Self, Error> {
+        let instructions = script
+            .instructions()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::TrustProof(format!("Undecodable leaf script: {e}")))?;
+        let unknown = || Error::TrustProof(format!("Unknown leaf script {script}"));
+
+        let (timelock, mut rest) = match instructions.as_slice() {
+            [
+                n,
+                Instruction::Op(OP_CSV),
+                Instruction::Op(OP_DROP),
+                rest @ ..,
+            ] => {
+                let n = n.script_num().ok_or_else(unknown)?;
+                let sequence = Sequence::from_consensus(u32::try_from(n).map_err(|_| unknown())?);
+                let timelock = sequence
+                    .to_relative_lock_time()
+                    .and_then(|lock_time| match lock_time {
+                        relative::LockTime::Blocks(height) => Some(u32::from(height.value())),
+                        relative::LockTime::Time(_) => None,
+                    })
+                    .ok_or_else(unknown)?;
+                (Some(timelock), rest)
+            }
+            rest => (None, rest),
+        };
+
+        let mut signers = Vec::new();
+        loop {
+            match rest {
+                [Instruction::PushBytes(key), Instruction::Op(op), tail @ ..]
+                    if *op == OP_CHECKSIGVERIFY || (*op == OP_CHECKSIG && tail.is_empty()) =>
+                {
+                    let key = XOnlyPublicKey::from_slice(key.as_bytes())?;
+                    signers.push(NostrPublicKey::from(key));
+                    if tail.is_empty() {
+                        break;
+                    }
+                    rest = tail;
+                }
+                _ => return Err(unknown()),
+            }
+        }
+
+        Ok(Self {
+            depth,
+            script,
+            signers,
+            timelock,
+        })
+    }
+}
+
+/// Machine-verifiable proof of who can spend from an escrow.
+///
+/// Serializes to JSON for sharing, and [`Display`](fmt::Display)s as a human-readable summary.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TrustProof {
+    /// The escrow address.
+    pub(crate) address: Address<NetworkUnchecked>,
+    /// Taproot internal key, which must be [`UNSPENDABLE_PUBLIC_KEY`]
+    /// or the MuSig2 aggregate of both participants' keys.
+    pub(crate) internal_key: XOnlyPublicKey,
+    /// The two participants' Nostr public keys.
+    pub(crate) participants: [NostrPublicKey; 2],
+    /// The arbitrator's Nostr public key, if any.
+    pub(crate) arbitrator: Option<NostrPublicKey>,
+    /// Every leaf of the tap tree, in tree order.
+    pub(crate) leaves: Vec<LeafRequirements>,
+}
+
+impl TrustProof {
+    /// Generates the proof for an [`EscrowConfig`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the escrow can't be built, or if it does not satisfy the claims of [`TrustProof::verify`].
+    pub(crate) fn generate(config: &EscrowConfig) -> Result<Self, Error> {
+        let spend_info = config.spend_info()?;
+        let leaves = config
+            .leaves()
+            .iter()
+            .map(|escrow_script| {
+                let script = config.script(*escrow_script)?;
+                let control_block = spend_info
+                    .control_block(&(script.clone(), LeafVersion::TapScript))
+                    .ok_or_else(|| {
+                        Error::TrustProof(format!("Leaf {escrow_script:?} is not in the tap tree"))
+                    })?;
+                LeafRequirements::from_script(control_block.merkle_branch.len() as u8, script)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let proof = Self {
+            address: config.address()?.into_unchecked(),
+            internal_key: spend_info.internal_key(),
+            participants: [config.npub_1, config.npub_2],
+            arbitrator: config.npub_arbitrator,
+            leaves,
+        };
+        proof.verify(config.network)?;
+        Ok(proof)
+    }
+
+    /// Verifies the proof for an escrow on `network`.
+    ///
+    /// Checks that:
+    ///
+    /// - the key path is disabled by the [`UNSPENDABLE_PUBLIC_KEY`] internal key,
+    ///   or needs both participants through their MuSig2 aggregate key;
+    /// - the leaves, and nothing else, rebuild the escrow address;
+    /// - every leaf requirement matches its script;
+    /// - no leaf can be spent without a participant, so the arbitrator can't spend alone;
+    /// - every leaf without a timelock needs both participants,
+    ///   so no participant can spend alone before the timelock.
+    pub(crate) fn verify(&self, network: Network) -> Result<(), Error> {
+        let address = self
+            .address
+            .clone()
+            .require_network(network)
+            .map_err(|_| Error::TrustProof(format!("Address is not valid on {network}")))?;
+
+        if self.internal_key != *UNSPENDABLE_PUBLIC_KEY && !self.is_cooperative_key_path()? {
+            return Err(Error::TrustProof(format!(
+                "Internal key {} can spend through the key path",
+                self.internal_key
+            )));
+        }
+
+        let mut builder = TaprootBuilder::new();
+        for leaf in &self.leaves {
+            if LeafRequirements::from_script(leaf.depth, leaf.script.clone())? != *leaf {
+                return Err(Error::TrustProof(format!(
+                    "Requirements do not match leaf script {}",
+                    leaf.script
+                )));
+            }
+            builder = builder.add_leaf(leaf.depth, leaf.script.clone())?;
+        }
+        let spend_info = builder
+            .finalize(SECP256K1, self.internal_key)
+            .map_err(|_| Error::TrustProof("Leaves do not form a complete tap tree".to_string()))?;
+        let expected = ScriptBuf::new_p2tr(SECP256K1, self.internal_key, spend_info.merkle_root());
+        if address.script_pubkey() != expected {
+            return Err(Error::TrustProof(
+                "Leaves do not rebuild the escrow address".to_string(),
+            ));
+        }
+
+        for (index, leaf) in self.leaves.iter().enumerate() {
+            let participants = self
+                .participants
+                .iter()
+                .filter(|participant| leaf.signers.contains(participant))
+                .count();
+            if participants == 0 {
+                return Err(Error::TrustProof(format!(
+                    "Leaf {} can be spent without any participant",
+                    index + 1
+                )));
+            }
+            if leaf.timelock.is_none() && participants < 2 {
+                return Err(Error::TrustProof(format!(
+                    "Leaf {} can be spent by a single participant without a timelock",
+                    index + 1
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the internal key is the MuSig2 aggregate of both participants' keys.
+    fn is_cooperative_key_path(&self) -> Result<bool, Error> {
+        Ok(KeyAggContext::new(&self.participants)?.internal_key() == self.internal_key)
+    }
+
+    /// Human-readable role of `npub` in the escrow.
+    fn role(&self, npub: &NostrPublicKey) -> String {
+        if *npub == self.participants[0] {
+            "participant 1".to_string()
+        } else if *npub == self.participants[1] {
+            "participant 2".to_string()
+        } else if Some(*npub) == self.arbitrator {
+            "the arbitrator".to_string()
+        } else {
+            format!("unknown key {}", npub.to_hex())
+        }
+    }
+}
+
+impl fmt::Display for TrustProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Escrow {}", self.address.assume_checked_ref())?;
+        if self.internal_key == *UNSPENDABLE_PUBLIC_KEY {
+            writeln!(f, "Key path: disabled (unspendable internal key)")?;
+        } else {
+            writeln!(
+                f,
+                "Key path: needs signatures from participant 1 and participant 2 (MuSig2)"
+            )?;
+        }
+        for (index, leaf) in self.leaves.iter().enumerate() {
+            let signers = leaf
+                .signers
+                .iter()
+                .map(|signer| self.role(signer))
+                .collect::<Vec<_>>()
+                .join(" and ");
+            write!(f, "Leaf {}: needs signatures from {signers}", index + 1)?;
+            match leaf.timelock {
+                Some(timelock) => writeln!(f, ", after {timelock} blocks")?,
+                None => writeln!(f, ", at any time")?,
+            }
+        }
+        write!(
+            f,
+            "The arbitrator can't spend alone, and no participant can spend alone before the timelock."
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::Keys;
+
+    use super::*;
+    use crate::scripts::{CURRENT_SCRIPT_TEMPLATE, ScriptTemplate};
+
+    fn dispute_config() -> EscrowConfig {
+        EscrowConfig {
+            npub_1: Keys::generate().public_key(),
+            npub_2: Keys::generate().public_key(),
+            npub_arbitrator: Some(Keys::generate().public_key()),
+            timelock_duration: Some(144),
+            network: Network::Testnet,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        }
+    }
+
+    #[test]
+    fn dispute_escrow_proof() {
+        let config = dispute_config();
+        let proof = TrustProof::generate(&config).unwrap();
+        assert_eq!(proof.leaves.len(), 3);
+        assert_eq!(proof.leaves[0].timelock, None);
+        assert_eq!(proof.leaves[0].signers, vec![config.npub_2, config.npub_1]);
+        assert_eq!(proof.leaves[1].timelock, Some(144));
+        assert_eq!(
+            proof.leaves[1].signers,
+            vec![config.npub_arbitrator.unwrap(), config.npub_1]
+        );
+
+        let json = serde_json::to_string(&proof).unwrap();
+        let decoded = serde_json::from_str::<TrustProof>(&json).unwrap();
+        assert!(decoded.verify(config.network).is_ok());
+        assert!(decoded.verify(Network::Bitcoin).is_err());
+        assert!(proof.to_string().contains("after 144 blocks"));
+    }
+
+    #[test]
+    fn collaborative_escrow_proof() {
+        let config = EscrowConfig {
+            npub_arbitrator: None,
+            timelock_duration: None,
+            ..dispute_config()
+        };
+        let proof = TrustProof::generate(&config).unwrap();
+        assert_eq!(proof.leaves.len(), 1);
+        assert_eq!(proof.leaves[0].depth, 0);
+        assert!(proof.verify(config.network).is_ok());
+    }
+
+    #[test]
+    fn tampered_proof_is_rejected() {
+        let config = dispute_config();
+        let proof = TrustProof::generate(&config).unwrap();
+
+        // Dropping a leaf no longer rebuilds the address.
+        let mut missing_leaf = proof.clone();
+        missing_leaf.leaves.pop();
+        assert!(missing_leaf.verify(config.network).is_err());
+
+        // Requirements must match the script.
+        let mut wrong_signers = proof.clone();
+        wrong_signers.leaves[1].signers.pop();
+        assert!(wrong_signers.verify(config.network).is_err());
+
+        // A leaf without participants is caught even if the proof is otherwise consistent.
+        let rogue_config = EscrowConfig {
+            npub_1: config.npub_arbitrator.unwrap(),
+            ..config
+        };
+        let rogue = TrustProof {
+            participants: [config.npub_1, config.npub_2],
+            ..TrustProof {
+                address: rogue_config.address().unwrap().into_unchecked(),
+                internal_key: rogue_config.spend_info().unwrap().internal_key(),
+                participants: [rogue_config.npub_1, rogue_config.npub_2],
+                arbitrator: rogue_config.npub_arbitrator,
+                leaves: rogue_config
+                    .leaves()
+                    .iter()
+                    .zip([1, 2, 2])
+                    .map(|(leaf, depth)| {
+                        LeafRequirements::from_script(depth, rogue_config.script(*leaf).unwrap())
+                            .unwrap()
+                    })
+                    .collect(),
+            }
+        };
+        assert!(matches!(
+            rogue.verify(config.network),
+            Err(Error::TrustProof(_))
+        ));
+    }
+
+    #[test]
+    fn cooperative_key_path_proof() {
+        let config = EscrowConfig {
+            template: ScriptTemplate::V2,
+            ..dispute_config()
+        };
+        let proof = TrustProof::generate(&config).unwrap();
+        assert_ne!(proof.internal_key, *UNSPENDABLE_PUBLIC_KEY);
+        assert!(proof.to_string().contains("(MuSig2)"));
+
+        // The key path must need both participants.
+        let arbitrator_key_path = TrustProof {
+            internal_key: KeyAggContext::new(&[config.npub_1, config.npub_arbitrator.unwrap()])
+                .unwrap()
+                .internal_key(),
+            ..proof
+        };
+        assert!(arbitrator_key_path.verify(config.network).is_err());
+    }
+}
@@ -1,25 +1,49 @@
 //! Creates Taproot Transactions using Nostr keys.
 
 use bitcoin::{
-    Address, Amount, Network, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid, absolute,
+    Address, Amount, FeeRate, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    Txid, Witness, absolute,
+    taproot::{LeafVersion, TaprootSpendInfo},
     transaction,
 };
 #[cfg(debug_assertions)]
 use dioxus::logger::tracing::trace;
 use nostr::key::PublicKey as NostPublicKey;
+use secp256k1::SECP256K1;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
 
-use crate::{error::Error, util::npub_to_address};
+use crate::{
+    error::Error,
+    scripts::{EscrowConfig, EscrowScript, ScriptTemplate, SpendPath},
+    util::npub_to_address,
+};
+
+/// Size in bytes of a BIP-340 Schnorr signature with [`TapSighashType::Default`](bitcoin::TapSighashType::Default).
+const SCHNORR_SIGNATURE_SIZE: usize = 64;
+
+/// Returns the lock time to use for anti-fee-sniping at the current block `height`.
+///
+/// A transaction with this lock time can't be mined in a reorg of blocks before `height`,
+/// which removes the incentive for miners to re-mine past blocks to take its fees.
+pub(crate) fn anti_fee_sniping_lock_time(height: u32) -> Result<absolute::LockTime, Error> {
+    absolute::LockTime::from_height(height)
+        .map_err(|_| Error::WrongInputs(format!("Invalid block height {height}")))
+}
 
 /// Creates a [`Transaction`] that swipe the resolution address to a `destination` [`Address`].
 ///
 /// Assumes that the resolution address is derived from the users' Nostr public key
 /// and has received a single input.
+///
+/// Pass [`absolute::LockTime::ZERO`] for no lock time, or [`anti_fee_sniping_lock_time`].
 pub(crate) fn resolution_tx(
     amount: Amount,
     funding_txid: Txid,
     vout: u32,
     destination: &Address,
     fee: Amount,
+    lock_time: absolute::LockTime,
 ) -> Transaction {
     // Parse stuff
     let prevout = OutPoint {
@@ -27,12 +51,21 @@ pub(crate) fn resolution_tx(
         vout,
     };
 
+    // A final sequence disables the lock time. Like Bitcoin Core's anti-fee-sniping,
+    // the lock time comes with a replace-by-fee signal, so a stuck resolution can be bumped.
+    let sequence = if lock_time == absolute::LockTime::ZERO {
+        Sequence::MAX
+    } else {
+        Sequence::ENABLE_RBF_NO_LOCKTIME
+    };
+
     // Create the transaction
     Transaction {
         version: transaction::Version(2),
-        lock_time: absolute::LockTime::ZERO,
+        lock_time,
         input: vec![TxIn {
             previous_output: prevout,
+            sequence,
             ..Default::default()
         }],
         output: vec![TxOut {
@@ -52,6 +85,9 @@ pub(crate) fn resolution_tx(
 /// # Errors
 ///
 /// Errors if could not create SegWit-v1 P2TR resolution addresses from supplied `npub`s.
+///
+/// Pass [`absolute::LockTime::ZERO`] for no lock time, or [`anti_fee_sniping_lock_time`].
+/// Both parties must use the same `lock_time` to produce the same transaction.
 #[expect(clippy::too_many_arguments)]
 pub(crate) fn escrow_tx(
     npub_1: &NostPublicKey,
@@ -62,6 +98,7 @@ pub(crate) fn escrow_tx(
     funding_txid: Txid,
     fee: Amount,
     network: Network,
+    lock_time: absolute::LockTime,
 ) -> Result<Transaction, Error> {
     // Parse stuff
     let prevout = OutPoint {
@@ -96,9 +133,10 @@ pub(crate) fn escrow_tx(
     trace!(%timelock_duration, "timelock duration");
 
     // Create the transaction
+    // The input sequence is never final, so the lock time is always enforced.
     let tx = Transaction {
         version: transaction::Version(2),
-        lock_time: absolute::LockTime::ZERO,
+        lock_time,
         input: vec![TxIn {
             previous_output: prevout,
             sequence: Sequence::from_consensus(timelock_duration),
@@ -119,11 +157,356 @@ pub(crate) fn escrow_tx(
     Ok(tx)
 }
 
+/// How a resolution splits the escrow between the two participants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde-types",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub(crate) enum Split {
+    /// Percentage of the payout to the first participant, the rest goes to the second.
+    ///
+    /// `Percent(70)` is a 70/30 split.
+    Percent(u8),
+    /// Exact payout to the first participant, the rest goes to the second.
+    First(
+        #[cfg_attr(
+            feature = "serde-types",
+            serde(with = "bitcoin::amount::serde::as_sat")
+        )]
+        Amount,
+    ),
+}
+
+impl Split {
+    /// Splits `payout`, the escrow amount minus the fee, into the payouts
+    /// of the first and second participant.
+    ///
+    /// The fee is shared in proportion to the payouts, and percentages round down
+    /// in favor of the second participant.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the percentage is over 100% or the first payout exceeds `payout`.
+    pub(crate) fn payouts(self, payout: Amount) -> Result<(Amount, Amount), Error> {
+        let payout_1 = match self {
+            Split::Percent(percent) if percent > 100 => {
+                return Err(Error::WrongInputs(format!(
+                    "Split of {percent}% is over 100%"
+                )));
+            }
+            Split::Percent(percent) => payout
+                .checked_mul(u64::from(percent))
+                .and_then(|amount| amount.checked_div(100))
+                .ok_or(Error::Rounding)?,
+            Split::First(amount) => amount,
+        };
+        let payout_2 = payout.checked_sub(payout_1).ok_or(Error::Rounding)?;
+        Ok((payout_1, payout_2))
+    }
+}
+
+/// Creates a [`Transaction`] that spends the escrow `funding` output of `amount`
+/// to both participants according to `split`, agreed collaboratively through
+/// [`EscrowScript::A`] or decided by the arbitrator through [`EscrowScript::B`] or [`EscrowScript::C`].
+///
+/// The outputs always sum to `amount` minus `fee`.
+/// A participant with a zero payout gets no output, so a 100/0 split has a single output.
+///
+/// Pass [`absolute::LockTime::ZERO`] for no lock time, or [`anti_fee_sniping_lock_time`].
+/// All signers must use the same `lock_time` to produce the same transaction.
+///
+/// # Errors
+///
+/// Errors if the split is invalid, the fee exceeds `amount`, a payout is dust,
+/// or `escrow_script` is a dispute leaf of an escrow without an arbitrator.
+pub(crate) fn split_resolution_tx(
+    config: &EscrowConfig,
+    funding: OutPoint,
+    amount: Amount,
+    split: Split,
+    fee: Amount,
+    escrow_script: EscrowScript,
+    lock_time: absolute::LockTime,
+) -> Result<Transaction, Error> {
+    let payout = amount.checked_sub(fee).ok_or(Error::Rounding)?;
+    let (payout_1, payout_2) = split.payouts(payout)?;
+    #[cfg(debug_assertions)]
+    trace!(%payout_1, %payout_2, %fee, "split resolution payouts");
+
+    let mut output = Vec::with_capacity(2);
+    for (npub, value) in [(&config.npub_1, payout_1), (&config.npub_2, payout_2)] {
+        if value == Amount::ZERO {
+            continue;
+        }
+        let script_pubkey = npub_to_address(npub, config.network)?.script_pubkey();
+        if value < script_pubkey.minimal_non_dust() {
+            return Err(Error::WrongInputs(format!(
+                "Payout of {value} to {npub} is dust"
+            )));
+        }
+        output.push(TxOut {
+            value,
+            script_pubkey,
+        });
+    }
+
+    // Dispute leaves need the relative timelock, the collaborative leaf only a non-final sequence.
+    let sequence = match escrow_script {
+        EscrowScript::A => Sequence::ENABLE_LOCKTIME_NO_RBF,
+        EscrowScript::B | EscrowScript::C => {
+            config.script(escrow_script)?;
+            Sequence::from_consensus(config.timelock_duration.unwrap_or_default())
+        }
+    };
+
+    Ok(Transaction {
+        version: transaction::Version(2),
+        lock_time,
+        input: vec![TxIn {
+            previous_output: funding,
+            sequence,
+            ..Default::default()
+        }],
+        output,
+    })
+}
+
+/// Checks that a resolution `tx` only pays the participants of `config`,
+/// and that its outputs sum to `amount` minus `fee`.
+///
+/// Returns the payouts of the first and second participant.
+///
+/// # Errors
+///
+/// Errors if an output pays anyone else or the outputs don't add up.
+pub(crate) fn verify_split_resolution(
+    tx: &Transaction,
+    config: &EscrowConfig,
+    amount: Amount,
+    fee: Amount,
+) -> Result<(Amount, Amount), Error> {
+    let script_pubkey_1 = npub_to_address(&config.npub_1, config.network)?.script_pubkey();
+    let script_pubkey_2 = npub_to_address(&config.npub_2, config.network)?.script_pubkey();
+    let (mut payout_1, mut payout_2) = (Amount::ZERO, Amount::ZERO);
+    for txout in &tx.output {
+        let payout = if txout.script_pubkey == script_pubkey_1 {
+            &mut payout_1
+        } else if txout.script_pubkey == script_pubkey_2 {
+            &mut payout_2
+        } else {
+            return Err(Error::WrongInputs(format!(
+                "Output to {} does not pay a participant",
+                txout.script_pubkey
+            )));
+        };
+        *payout = payout.checked_add(txout.value).ok_or(Error::Rounding)?;
+    }
+
+    let expected = amount.checked_sub(fee).ok_or(Error::Rounding)?;
+    if payout_1.checked_add(payout_2) != Some(expected) {
+        return Err(Error::Rounding);
+    }
+    Ok((payout_1, payout_2))
+}
+
+/// An escrow UTXO whose dispute timelock has expired and that can be swept
+/// through one of the arbitrator leaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct ExpiredEscrow {
+    /// First participant's Nostr public key.
+    pub(crate) npub_1: NostPublicKey,
+    /// Second participant's Nostr public key.
+    pub(crate) npub_2: NostPublicKey,
+    /// Arbitrator's Nostr public key.
+    pub(crate) npub_arbitrator: NostPublicKey,
+    /// Timelock duration in blocks.
+    pub(crate) timelock_duration: u32,
+    /// The escrow UTXO.
+    pub(crate) outpoint: OutPoint,
+    /// The amount locked in the escrow UTXO.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) amount: Amount,
+    /// Which dispute leaf is used to spend: [`EscrowScript::B`] or [`EscrowScript::C`].
+    pub(crate) escrow_script: EscrowScript,
+    /// Script template of the escrow.
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub(crate) template: ScriptTemplate,
+}
+
+impl ExpiredEscrow {
+    /// The tapscript of the leaf used to spend this escrow.
+    pub(crate) fn locking_script(&self) -> Result<ScriptBuf, Error> {
+        self.template.script(
+            &self.npub_1,
+            &self.npub_2,
+            Some(&self.npub_arbitrator),
+            Some(self.timelock_duration),
+            self.escrow_script,
+        )
+    }
+
+    /// The [`TaprootSpendInfo`] of the escrow address.
+    pub(crate) fn spend_info(&self) -> Result<TaprootSpendInfo, Error> {
+        self.template.spend_info(
+            &self.npub_1,
+            &self.npub_2,
+            Some(&self.npub_arbitrator),
+            Some(self.timelock_duration),
+        )
+    }
+
+    /// The [`TxOut`] being spent, needed for the sighash.
+    pub(crate) fn prevout(&self) -> Result<TxOut, Error> {
+        let spend_info = self.spend_info()?;
+        Ok(TxOut {
+            value: self.amount,
+            script_pubkey: ScriptBuf::new_p2tr(
+                SECP256K1,
+                spend_info.internal_key(),
+                spend_info.merkle_root(),
+            ),
+        })
+    }
+}
+
+/// Creates a consolidation [`Transaction`] that sweeps multiple timelock-expired escrows
+/// into a single `destination` [`Address`].
+///
+/// Every input gets the relative timelock of its escrow as `nSequence`,
+/// so that the `OP_CSV` in the chosen dispute leaf is satisfied.
+/// The fee is computed from the final virtual size of the transaction,
+/// assuming two Schnorr signatures per input.
+///
+/// # Errors
+///
+/// Errors if `escrows` is empty, if any escrow is not using a dispute leaf,
+/// or if the fee exceeds the total swept amount.
+pub(crate) fn build_sweep_tx(
+    escrows: &[ExpiredEscrow],
+    destination: &Address,
+    fee_rate: FeeRate,
+) -> Result<Transaction, Error> {
+    if escrows.is_empty() {
+        return Err(Error::WrongInputs("No escrows to sweep".to_string()));
+    }
+
+    let mut input = Vec::with_capacity(escrows.len());
+    let mut total = Amount::ZERO;
+    for escrow in escrows {
+        if escrow.escrow_script == EscrowScript::A {
+            return Err(Error::WrongInputs(format!(
+                "Escrow {} must be swept through a dispute leaf, got {:?}",
+                escrow.outpoint, escrow.escrow_script
+            )));
+        }
+        input.push(TxIn {
+            previous_output: escrow.outpoint,
+            sequence: Sequence::from_consensus(escrow.timelock_duration),
+            ..Default::default()
+        });
+        total = total.checked_add(escrow.amount).ok_or(Error::Rounding)?;
+    }
+
+    let mut tx = Transaction {
+        version: transaction::Version(2),
+        lock_time: absolute::LockTime::ZERO,
+        input,
+        output: vec![TxOut {
+            value: total,
+            script_pubkey: destination.script_pubkey(),
+        }],
+    };
+
+    // Fill the witnesses with placeholders of the final size to get the exact vsize.
+    for (txin, escrow) in tx.input.iter_mut().zip(escrows) {
+        txin.witness = placeholder_leaf_witness(&escrow.locking_script()?, &escrow.spend_info()?)?;
+    }
+    let fee = fee_rate.fee_vb(tx.vsize() as u64).ok_or(Error::Rounding)?;
+    #[cfg(debug_assertions)]
+    trace!(vsize = %tx.vsize(), %fee, %total, "sweep transaction fee");
+    for txin in tx.input.iter_mut() {
+        txin.witness = Witness::new();
+    }
+
+    tx.output[0].value = total.checked_sub(fee).ok_or(Error::Rounding)?;
+
+    Ok(tx)
+}
+
+/// Estimates the virtual size in vbytes of a transaction spending an escrow output
+/// through `path` into the two resolution addresses, as built by [`escrow_tx`].
+///
+/// Signatures are assumed to use [`TapSighashType::Default`](bitcoin::TapSighashType::Default),
+/// so the estimate is exact.
+pub(crate) fn estimate_spend_weight(config: &EscrowConfig, path: SpendPath) -> Result<u64, Error> {
+    let witness = match path {
+        SpendPath::KeyPath => {
+            let mut witness = Witness::new();
+            witness.push([0; SCHNORR_SIGNATURE_SIZE]);
+            witness
+        }
+        SpendPath::Leaf(escrow_script) => {
+            placeholder_leaf_witness(&config.script(escrow_script)?, &config.spend_info()?)?
+        }
+    };
+    // Every output is P2TR, so any P2TR script has the right size.
+    let script_pubkey = config.address()?.script_pubkey();
+    let tx = Transaction {
+        version: transaction::Version(2),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            witness,
+            ..Default::default()
+        }],
+        output: vec![
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: script_pubkey.clone(),
+            },
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey,
+            },
+        ],
+    };
+    #[cfg(debug_assertions)]
+    trace!(?path, weight = %tx.weight(), "escrow spend weight");
+
+    Ok(tx.weight().to_vbytes_ceil())
+}
+
+/// Creates a witness of the final size for a 2-of-2 leaf spend, with zeroed signatures.
+pub(crate) fn placeholder_leaf_witness(
+    locking_script: &ScriptBuf,
+    spend_info: &TaprootSpendInfo,
+) -> Result<Witness, Error> {
+    let control_block = spend_info
+        .control_block(&(locking_script.clone(), LeafVersion::TapScript))
+        .ok_or_else(|| Error::WrongInputs("Leaf is not in the escrow tree".to_string()))?;
+    let mut witness = Witness::new();
+    witness.push([0; SCHNORR_SIGNATURE_SIZE]);
+    witness.push([0; SCHNORR_SIGNATURE_SIZE]);
+    witness.push(locking_script.as_bytes());
+    witness.push(control_block.serialize());
+    Ok(witness)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use bitcoin::{consensus, hex::DisplayHex};
 
-    use crate::util::parse_npub;
+    use crate::{
+        scripts::CURRENT_SCRIPT_TEMPLATE,
+        util::{P2TR_TX_VBYTE_A, parse_npub},
+    };
 
     use super::*;
 
@@ -149,6 +532,7 @@ mod tests {
             funding_txid,
             fee,
             network,
+            absolute::LockTime::ZERO,
         )
         .unwrap();
         println!(
@@ -168,4 +552,235 @@ mod tests {
             resolution_address_2p.script_pubkey()
         );
     }
+
+    #[test]
+    fn resolution_lock_time() {
+        let npub =
+            parse_npub("npub1lfsec9a40ntx0hjr9wtuchclar7xcyhrf0gngaz3vt5dhnqdndaq099v6c").unwrap();
+        let destination = npub_to_address(&npub, Network::Bitcoin).unwrap();
+        let funding_txid = "602ae1accd9626bde16d19cbe8663cbe37a4e95839d0cddb10b84dcc82f07799"
+            .parse::<Txid>()
+            .unwrap();
+        let resolution = |lock_time| {
+            resolution_tx(
+                Amount::from_sat(100_000),
+                funding_txid,
+                0,
+                &destination,
+                Amount::from_sat(1_000),
+                lock_time,
+            )
+        };
+
+        let tx = resolution(absolute::LockTime::ZERO);
+        assert_eq!(tx.input[0].sequence, Sequence::MAX);
+        // Anti-fee-sniping enforces the lock time and signals replace-by-fee.
+        let tx = resolution(anti_fee_sniping_lock_time(850_000).unwrap());
+        assert_eq!(tx.input[0].sequence, Sequence::from_consensus(0xFFFF_FFFD));
+        assert!(tx.is_lock_time_enabled());
+        assert!(tx.is_explicitly_rbf());
+    }
+
+    #[test]
+    fn test_build_sweep_tx() {
+        // Taken from https://docs.rs/bitcoin/latest/bitcoin/struct.PublicKey.html
+        let npub_1 = NostPublicKey::from_str(
+            "8f47dcd43ba6d97fc9ed2e3bba09b175a45fac55f0683e8cf771e8ced4572354",
+        )
+        .unwrap();
+        let npub_2 = NostPublicKey::from_str(
+            "8bde91b10013e08949a318018fedbd896534a549a278e220169ee2a36517c7aa",
+        )
+        .unwrap();
+        let npub_arbitrator = NostPublicKey::from_str(
+            "2b8324c93575034047a52e9bca05a46d8347046b91a032eff07d5de8d3f2730b",
+        )
+        .unwrap();
+        let funding_txid = "602ae1accd9626bde16d19cbe8663cbe37a4e95839d0cddb10b84dcc82f07799"
+            .parse::<Txid>()
+            .unwrap();
+        let escrows = vec![
+            ExpiredEscrow {
+                npub_1,
+                npub_2,
+                npub_arbitrator,
+                timelock_duration: 144,
+                outpoint: OutPoint::new(funding_txid, 0),
+                amount: Amount::from_sat(100_000),
+                escrow_script: EscrowScript::B,
+                template: CURRENT_SCRIPT_TEMPLATE,
+            },
+            ExpiredEscrow {
+                npub_1,
+                npub_2,
+                npub_arbitrator,
+                timelock_duration: 1_008,
+                outpoint: OutPoint::new(funding_txid, 1),
+                amount: Amount::from_sat(50_000),
+                escrow_script: EscrowScript::C,
+                template: CURRENT_SCRIPT_TEMPLATE,
+            },
+        ];
+        let destination = npub_to_address(&npub_arbitrator, Network::Bitcoin).unwrap();
+        let fee_rate = FeeRate::from_sat_per_vb(2).unwrap();
+
+        let tx = build_sweep_tx(&escrows, &destination, fee_rate).unwrap();
+
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.input[0].sequence, Sequence::from_consensus(144));
+        assert_eq!(tx.input[1].sequence, Sequence::from_consensus(1_008));
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, destination.script_pubkey());
+        // 287 vbytes: two script path inputs with two signatures each at 2 sat/vB.
+        let fee = Amount::from_sat(150_000) - tx.output[0].value;
+        assert_eq!(fee, Amount::from_sat(574));
+        assert!(tx.input.iter().all(|txin| txin.witness.is_empty()));
+
+        // Collaborative leaf can't be swept.
+        let mut collaborative = escrows[0].clone();
+        collaborative.escrow_script = EscrowScript::A;
+        assert!(build_sweep_tx(&[collaborative], &destination, fee_rate).is_err());
+        assert!(build_sweep_tx(&[], &destination, fee_rate).is_err());
+    }
+
+    #[test]
+    fn spend_weight_per_path() {
+        let npub_1 =
+            parse_npub("npub1lfsec9a40ntx0hjr9wtuchclar7xcyhrf0gngaz3vt5dhnqdndaq099v6c").unwrap();
+        let npub_2 =
+            parse_npub("npub1ykkf8j4mt0z4hfz5eesqck6a9qcearxq2mlk6f78k3yxhjkpqnxqanyg69").unwrap();
+        let npub_arbitrator =
+            parse_npub("npub1nckhhhcxm8usszvxt6yku6efp4fpay3saglx6yhtu8pfv3kdqhqsfn0vd7").unwrap();
+        let collaborative = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Bitcoin,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let dispute = EscrowConfig {
+            npub_arbitrator: Some(npub_arbitrator),
+            timelock_duration: Some(144),
+            ..collaborative
+        };
+
+        let weight = |config, path| estimate_spend_weight(config, path).unwrap();
+        assert_eq!(weight(&collaborative, SpendPath::KeyPath), 154);
+        assert_eq!(
+            weight(&collaborative, SpendPath::Leaf(EscrowScript::A)),
+            P2TR_TX_VBYTE_A
+        );
+        // Deeper control block in the dispute tree.
+        assert_eq!(weight(&dispute, SpendPath::Leaf(EscrowScript::A)), 204);
+        assert_eq!(weight(&dispute, SpendPath::Leaf(EscrowScript::B)), 214);
+        assert_eq!(weight(&dispute, SpendPath::Leaf(EscrowScript::C)), 214);
+        assert!(estimate_spend_weight(&collaborative, SpendPath::Leaf(EscrowScript::B)).is_err());
+    }
+
+    #[test]
+    fn split_resolution() {
+        let npub_1 = NostPublicKey::from_str(
+            "8f47dcd43ba6d97fc9ed2e3bba09b175a45fac55f0683e8cf771e8ced4572354",
+        )
+        .unwrap();
+        let npub_2 = NostPublicKey::from_str(
+            "8bde91b10013e08949a318018fedbd896534a549a278e220169ee2a36517c7aa",
+        )
+        .unwrap();
+        let npub_arbitrator = NostPublicKey::from_str(
+            "2b8324c93575034047a52e9bca05a46d8347046b91a032eff07d5de8d3f2730b",
+        )
+        .unwrap();
+        let config = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: Some(npub_arbitrator),
+            timelock_duration: Some(144),
+            network: Network::Bitcoin,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let funding = OutPoint::new(
+            "602ae1accd9626bde16d19cbe8663cbe37a4e95839d0cddb10b84dcc82f07799"
+                .parse::<Txid>()
+                .unwrap(),
+            0,
+        );
+        let amount = Amount::from_sat(100_000);
+        let fee = Amount::from_sat(1_000);
+
+        // 70/30 agreed collaboratively.
+        let tx = split_resolution_tx(
+            &config,
+            funding,
+            amount,
+            Split::Percent(70),
+            fee,
+            EscrowScript::A,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0].value, Amount::from_sat(69_300));
+        assert_eq!(tx.output[1].value, Amount::from_sat(29_700));
+        assert_eq!(
+            verify_split_resolution(&tx, &config, amount, fee).unwrap(),
+            (Amount::from_sat(69_300), Amount::from_sat(29_700))
+        );
+        assert!(verify_split_resolution(&tx, &config, amount, Amount::from_sat(500)).is_err());
+
+        // Everything to the second participant, decided by the arbitrator.
+        let tx = split_resolution_tx(
+            &config,
+            funding,
+            amount,
+            Split::First(Amount::ZERO),
+            fee,
+            EscrowScript::C,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.input[0].sequence, Sequence::from_consensus(144));
+        assert_eq!(
+            verify_split_resolution(&tx, &config, amount, fee).unwrap(),
+            (Amount::ZERO, Amount::from_sat(99_000))
+        );
+
+        let split = |split, escrow_script, config: &EscrowConfig| {
+            split_resolution_tx(
+                config,
+                funding,
+                amount,
+                split,
+                fee,
+                escrow_script,
+                absolute::LockTime::ZERO,
+            )
+        };
+        assert!(split(Split::Percent(101), EscrowScript::A, &config).is_err());
+        assert!(
+            split(
+                Split::First(Amount::from_sat(99_001)),
+                EscrowScript::A,
+                &config
+            )
+            .is_err()
+        );
+        // 100 sats is dust for a P2TR output.
+        assert!(
+            split(
+                Split::First(Amount::from_sat(100)),
+                EscrowScript::A,
+                &config
+            )
+            .is_err()
+        );
+        let collaborative = EscrowConfig {
+            npub_arbitrator: None,
+            timelock_duration: None,
+            ..config
+        };
+        assert!(split(Split::Percent(50), EscrowScript::B, &collaborative).is_err());
+    }
 }
@@ -0,0 +1,193 @@
+//! Display of bitcoin [`Amount`]s in the user's denomination and locale.
+//!
+//! Amounts are formatted from their integer satoshis, never through floating point,
+//! so what the user reads is exactly what gets signed.
+//! Digits are grouped and the decimal separator follows the [`Language`]:
+//! `1,234.5 BTC` in English and `1.234,5 BTC` in Brazilian Portuguese.
+
+use std::fmt::{self, Write as _};
+
+use bitcoin::Amount;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::Language;
+
+/// Satoshis in a bitcoin.
+const SATS_PER_BTC: u64 = 100_000_000;
+
+/// Decimals of a bitcoin amount in satoshis.
+const BTC_DECIMALS: u8 = 8;
+
+/// Millisatoshis in a satoshi.
+const MSATS_PER_SAT: u128 = 1_000;
+
+/// Unit bitcoin amounts are shown in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Denomination {
+    /// Bitcoins, such as `0.001 BTC`.
+    #[default]
+    Btc,
+    /// Satoshis, such as `100,000 sat`.
+    Sat,
+    /// Millisatoshis, as Lightning wallets show them, such as `100,000,000 msat`.
+    Msat,
+}
+
+impl Denomination {
+    /// Symbol following the amount.
+    pub(crate) fn symbol(self) -> &'static str {
+        match self {
+            Denomination::Btc => "BTC",
+            Denomination::Sat => "sat",
+            Denomination::Msat => "msat",
+        }
+    }
+}
+
+impl fmt::Display for Denomination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// Thousands and decimal separators of `language`.
+fn separators(language: Language) -> (char, char) {
+    match language {
+        Language::En => (',', '.'),
+        Language::PtBr => ('.', ','),
+    }
+}
+
+/// `integer` with its digits grouped by thousands with `separator`.
+fn group(integer: u128, separator: char) -> String {
+    let digits = integer.to_string();
+    let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Formats `amount` in `denomination` for `language`, with every significant decimal,
+/// such as `0.001 BTC`, `100,000 sat` or `100,000,000 msat`.
+pub(crate) fn format_amount(
+    amount: Amount,
+    denomination: Denomination,
+    language: Language,
+) -> String {
+    let (thousands, decimal) = separators(language);
+    let sats = amount.to_sat();
+    match denomination {
+        Denomination::Btc => {
+            let mut text = group(u128::from(sats / SATS_PER_BTC), thousands);
+            let fraction = format!("{:08}", sats % SATS_PER_BTC);
+            let fraction = fraction.trim_end_matches('0');
+            if !fraction.is_empty() {
+                text.push(decimal);
+                text.push_str(fraction);
+            }
+            format!("{text} BTC")
+        }
+        Denomination::Sat => format!("{} sat", group(u128::from(sats), thousands)),
+        Denomination::Msat => format!(
+            "{} msat",
+            group(u128::from(sats) * MSATS_PER_SAT, thousands)
+        ),
+    }
+}
+
+/// Formats `amount` in bitcoins for `language` with exactly `decimals` decimals,
+/// rounding half up, such as `0.00100000 BTC` with 8 decimals or `0.0010 BTC` with 4.
+///
+/// Decimals past the 8 of a satoshi are zeros.
+pub(crate) fn format_btc_fixed(amount: Amount, decimals: u8, language: Language) -> String {
+    let (thousands, decimal) = separators(language);
+    let shown = decimals.min(BTC_DECIMALS);
+    let step = 10_u64.pow(u32::from(BTC_DECIMALS - shown));
+    // Rounded to the shown decimals, in units of the last one.
+    let units = (u128::from(amount.to_sat()) + u128::from(step / 2)) / u128::from(step);
+    let scale = u128::from(10_u64.pow(u32::from(shown)));
+    let mut text = group(units / scale, thousands);
+    if decimals > 0 {
+        text.push(decimal);
+        let _ = write!(
+            text,
+            "{:0width$}",
+            units % scale,
+            width = usize::from(shown)
+        );
+        text.extend(std::iter::repeat_n('0', usize::from(decimals - shown)));
+    }
+    format!("{text} BTC")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amount_display() {
+        let amount = Amount::from_sat(123_456_789_012);
+        assert_eq!(
+            format_amount(amount, Denomination::Btc, Language::En),
+            "1,234.56789012 BTC"
+        );
+        assert_eq!(
+            format_amount(amount, Denomination::Btc, Language::PtBr),
+            "1.234,56789012 BTC"
+        );
+        assert_eq!(
+            format_amount(amount, Denomination::Sat, Language::En),
+            "123,456,789,012 sat"
+        );
+        assert_eq!(
+            format_amount(amount, Denomination::Msat, Language::PtBr),
+            "123.456.789.012.000 msat"
+        );
+        assert_eq!(
+            format_amount(Amount::from_sat(100_000), Denomination::Btc, Language::En),
+            "0.001 BTC"
+        );
+        assert_eq!(
+            format_amount(Amount::ONE_BTC, Denomination::Btc, Language::En),
+            "1 BTC"
+        );
+        assert_eq!(
+            format_amount(Amount::ZERO, Denomination::Sat, Language::En),
+            "0 sat"
+        );
+        assert_eq!(
+            format_amount(Amount::MAX_MONEY, Denomination::Msat, Language::En),
+            "2,100,000,000,000,000,000 msat"
+        );
+
+        assert_eq!(
+            format_btc_fixed(Amount::from_sat(100_000), 8, Language::En),
+            "0.00100000 BTC"
+        );
+        assert_eq!(
+            format_btc_fixed(Amount::from_sat(98_039), 4, Language::PtBr),
+            "0,0010 BTC"
+        );
+        assert_eq!(
+            format_btc_fixed(Amount::from_sat(4_999), 4, Language::En),
+            "0.0000 BTC"
+        );
+        assert_eq!(
+            format_btc_fixed(Amount::from_sat(5_000), 4, Language::En),
+            "0.0001 BTC"
+        );
+        assert_eq!(
+            format_btc_fixed(Amount::from_sat(199_999_999), 0, Language::En),
+            "2 BTC"
+        );
+        assert_eq!(
+            format_btc_fixed(Amount::from_sat(1), 10, Language::En),
+            "0.0000000100 BTC"
+        );
+    }
+}
@@ -1,16 +1,22 @@
 //! Utility functions for Nostr keys and Bitcoin network.
 
-use bitcoin::{Address, Network, XOnlyPublicKey};
-use nostr::key::{PublicKey as NostrPublicKey, SecretKey as NostrSecretKey};
-use secp256k1::SECP256K1;
+use std::time::Duration;
 
-use crate::{error::Error, scripts::EscrowScript};
+use bitcoin::{
+    Address, Network, ScriptBuf, XOnlyPublicKey,
+    hashes::{Hash, HashEngine, sha256},
+    key::TapTweak,
+};
+use nostr::key::PublicKey as NostrPublicKey;
+use secp256k1::{Message, SECP256K1, schnorr};
 
-/// Number of Bitcoin blocks per day assuming 10-minute intervals.
-const BLOCKS_PER_DAY: u32 = 6 * 24;
-
-/// Number of Bitcoin blocks per hour assuming 10-minute intervals.
-const BLOCKS_PER_HOUR: u32 = 6;
+use crate::{
+    error::Error,
+    keys::Npub,
+    network::{Chain, NetworkProfile},
+    scripts::EscrowScript,
+    secret::SecretNsec,
+};
 
 /// P2TR Transaction virtual bytes for speding from a Nostr derived
 /// P2TR address using the key path spend.
@@ -31,30 +37,35 @@ pub(crate) const P2TR_TX_VBYTE_B: u64 = 213;
 /// NOTE: the amount is 212.75 but round it up.
 pub(crate) const P2TR_TX_VBYTE_C: u64 = 213;
 
-/// Converts `days` to blocks assuming that blocks comes in 10-minute intervals.
-pub(crate) fn days_to_blocks(days: u32) -> u32 {
-    days * BLOCKS_PER_DAY
-}
+/// Prefix of the challenge digest signed to prove ownership of an address.
+const ADDRESS_CHALLENGE_PREFIX: &[u8] = b"scrow address ownership:";
 
-/// Converts `hours` to blocks assuming that blocks comes in 10-minute intervals.
-pub(crate) fn hours_to_blocks(hours: u32) -> u32 {
-    hours * BLOCKS_PER_HOUR
+/// The [`Duration`] of `days` and `hours`.
+pub(crate) fn days_hours(days: u32, hours: u32) -> Duration {
+    Duration::from_secs(u64::from(days) * 86_400 + u64::from(hours) * 3_600)
 }
 
-/// Converts `days` and `hours` to blocks assuming that blocks comes in 10-minute intervals.
-#[allow(dead_code)]
-pub(crate) fn days_hours_to_blocks(days: u32, hours: u32) -> u32 {
-    days_to_blocks(days) + hours_to_blocks(hours)
+/// Number of blocks expected to be mined in `duration` on the chain of `profile`,
+/// rounded up so timelocks never expire early.
+///
+/// Returns [`None`] if the profile has no block interval or the blocks don't fit in
+/// a relative timelock, which counts up to [`u16::MAX`] blocks.
+pub(crate) fn blocks_for_duration(duration: Duration, profile: &NetworkProfile) -> Option<u32> {
+    let interval = profile.block_interval.as_millis();
+    if interval == 0 {
+        return None;
+    }
+    u16::try_from(duration.as_millis().div_ceil(interval))
+        .ok()
+        .map(u32::from)
 }
 
 /// Parses a network string into a [`Network`].
+///
+/// Custom signets such as Mutinynet parse to [`Network::Signet`],
+/// see [`Chain`] for their block timing.
 pub(crate) fn parse_network(network: &str) -> Result<Network, Error> {
-    match network {
-        "Mainnet" => Ok(Network::Bitcoin),
-        "Testnet" => Ok(Network::Testnet),
-        "Signet" => Ok(Network::Signet),
-        e => Err(Error::InvalidNetwork(e.to_string())),
-    }
+    Ok(network.parse::<Chain>()?.network())
 }
 
 /// Parses an escrow type string into a [`EscrowScript`].
@@ -67,33 +78,95 @@ pub(crate) fn parse_escrow_type(escrow_type: &str) -> Result<EscrowScript, Error
     }
 }
 
-/// Parses a [`NostrPublicKey`] from a string.
+/// Parses a [`NostrPublicKey`] from an `npub` or a hex string, see [`Npub`].
 pub(crate) fn parse_npub(input: &str) -> Result<NostrPublicKey, Error> {
-    Ok(NostrPublicKey::parse(input)?)
+    Ok(input.parse::<Npub>()?.nostr())
 }
 
-/// Parses a [`NostrSecretKey`] from a string.
-pub(crate) fn parse_nsec(input: &str) -> Result<NostrSecretKey, Error> {
-    Ok(NostrSecretKey::parse(input)?)
+/// Parses a [`SecretNsec`] from an `nsec` or a hex string.
+pub(crate) fn parse_nsec(input: &str) -> Result<SecretNsec, Error> {
+    input.parse()
 }
 
 /// Parses a [`NostrPublicKey`] to an [`XOnlyPublicKey`].
 pub(crate) fn npub_to_x_only_public_key(npub: &NostrPublicKey) -> Result<XOnlyPublicKey, Error> {
-    Ok(npub.xonly()?)
+    Npub::from(*npub).x_only_public_key()
 }
 
-/// Parses a [`NostrPublicKey`] to an [`XOnlyPublicKey`].
+/// Parses a [`SecretNsec`] to an [`XOnlyPublicKey`].
 #[allow(dead_code)]
-pub(crate) fn nsec_to_x_only_public_key(nsec: &NostrSecretKey) -> XOnlyPublicKey {
-    let (x_only_pk, _) = nsec.x_only_public_key(SECP256K1);
-    x_only_pk
+pub(crate) fn nsec_to_x_only_public_key(nsec: &SecretNsec) -> XOnlyPublicKey {
+    nsec.x_only_public_key()
 }
 
 /// Parses a [`NostrPublicKey`] to a P2TR [`Address`] key path spend, given a [`Network`].
 pub(crate) fn npub_to_address(npub: &NostrPublicKey, network: Network) -> Result<Address, Error> {
+    Npub::from(*npub).address(network)
+}
+
+/// Checks that a P2TR `address` is the BIP-86 key path address of `npub`.
+///
+/// Only compares scripts, so it holds for any [`Network`].
+///
+/// # Errors
+///
+/// Errors if the `npub` is invalid or the `address` is not derived from it.
+pub(crate) fn verify_address_ownership(
+    address: &Address,
+    npub: &NostrPublicKey,
+) -> Result<(), Error> {
     let x_only_pk = npub_to_x_only_public_key(npub)?;
-    let address = Address::p2tr(SECP256K1, x_only_pk, None, network);
-    Ok(address)
+    let expected = ScriptBuf::new_p2tr(SECP256K1, x_only_pk, None);
+    if address.script_pubkey() != expected {
+        return Err(Error::AddressNotOwned(address.to_string()));
+    }
+    Ok(())
+}
+
+/// Digest of an address ownership `challenge`.
+fn address_challenge_message(challenge: &str) -> Message {
+    let mut engine = sha256::Hash::engine();
+    engine.input(ADDRESS_CHALLENGE_PREFIX);
+    engine.input(challenge.as_bytes());
+    Message::from_digest(sha256::Hash::from_engine(engine).to_byte_array())
+}
+
+/// Signs an address ownership `challenge` with the key path key of `nsec`'s P2TR address.
+///
+/// The signature can only be produced by whoever can spend from the address.
+pub(crate) fn sign_address_challenge(nsec: SecretNsec, challenge: &str) -> schnorr::Signature {
+    nsec.with_keypair(|keypair| {
+        let mut tweaked = keypair.tap_tweak(SECP256K1, None).to_inner();
+        let signature =
+            SECP256K1.sign_schnorr_no_aux_rand(&address_challenge_message(challenge), &tweaked);
+        tweaked.non_secure_erase();
+        signature
+    })
+}
+
+/// Checks that `address` belongs to `npub`, and that its owner signed `challenge`.
+///
+/// Like [`verify_address_ownership`], but also proves control of the key,
+/// not only that the address was derived from a public `npub`.
+///
+/// # Errors
+///
+/// Errors if the `address` is not derived from `npub` or the `signature` is invalid.
+pub(crate) fn verify_address_ownership_with_challenge(
+    address: &Address,
+    npub: &NostrPublicKey,
+    challenge: &str,
+    signature: &schnorr::Signature,
+) -> Result<(), Error> {
+    verify_address_ownership(address, npub)?;
+    let x_only_pk = npub_to_x_only_public_key(npub)?;
+    let (output_key, _) = x_only_pk.tap_tweak(SECP256K1, None);
+    SECP256K1.verify_schnorr(
+        signature,
+        &address_challenge_message(challenge),
+        &output_key.to_inner(),
+    )?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -127,4 +200,117 @@ mod tests {
         let expected = "bc1pdx0h0xkeyhx79ethugtrutlxvcswffcwa9sx823dyn09wkexdwass7v98m";
         assert_eq!(address.to_string(), expected);
     }
+
+    #[test]
+    fn strict_key_parsing() {
+        let npub = "npub1tv7hxxwtw4gcz4n6fpduads7lsmynh5pjedgfhvdctnulrz9rsksjx28xe";
+        let hex = "5b3d7319cb755181567a485bceb61efc3649de81965a84dd8dc2e7cf8c451c2d";
+        let nsec = "nsec103m6x7a369k95rhtdn5w5mxsdpgyqprnysdtvhe6m0ef5xuz9d6s6emzda";
+
+        // npubs and hex keys parse the same, whitespace aside.
+        assert_eq!(parse_npub(npub).unwrap(), parse_npub(hex).unwrap());
+        assert_eq!(
+            parse_npub(&format!("  {npub}\n")).unwrap(),
+            parse_npub(&format!("{hex} ")).unwrap()
+        );
+        assert_eq!(
+            parse_nsec(&format!(" {nsec} ")).unwrap().public_key(),
+            parse_nsec(nsec).unwrap().public_key()
+        );
+
+        // A key of the wrong kind is rejected without echoing it.
+        let error = parse_npub(nsec).unwrap_err();
+        assert_eq!(error.code(), 100);
+        assert!(!error.to_string().contains(nsec));
+        assert_eq!(parse_nsec(npub).unwrap_err().code(), 100);
+
+        // Wrong lengths and characters.
+        for input in [
+            "",
+            "npub1",
+            &npub[..62],
+            &hex[..63],
+            &format!("{hex}00"),
+            &hex.replace('5', "g"),
+        ] {
+            assert!(parse_npub(input).is_err(), "{input}");
+        }
+
+        // A bad checksum is a Nostr key error.
+        let mut tampered = npub.to_string();
+        tampered.replace_range(62.., "f");
+        assert_eq!(parse_npub(&tampered).unwrap_err().code(), 201);
+    }
+
+    #[test]
+    fn address_ownership() {
+        let keys = nostr::Keys::generate();
+        let (nsec, npub) = (
+            SecretNsec::from(keys.secret_key().clone()),
+            keys.public_key(),
+        );
+        let other = nostr::Keys::generate().public_key();
+        let address = npub_to_address(&npub, Network::Testnet).unwrap();
+
+        assert!(verify_address_ownership(&address, &npub).is_ok());
+        assert!(matches!(
+            verify_address_ownership(&address, &other),
+            Err(Error::AddressNotOwned(_))
+        ));
+
+        let challenge = "escrow 42 payout";
+        let signature = sign_address_challenge(nsec, challenge);
+        assert!(
+            verify_address_ownership_with_challenge(&address, &npub, challenge, &signature).is_ok()
+        );
+        assert!(
+            verify_address_ownership_with_challenge(&address, &npub, "other", &signature).is_err()
+        );
+        assert!(
+            verify_address_ownership_with_challenge(&address, &other, challenge, &signature)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn blocks_for_duration_per_chain() {
+        let mainnet = NetworkProfile::from(Chain::Mainnet);
+        let mutinynet = NetworkProfile::from(Chain::Mutinynet);
+        assert_eq!(blocks_for_duration(days_hours(1, 0), &mainnet), Some(144));
+        assert_eq!(blocks_for_duration(days_hours(0, 3), &mainnet), Some(18));
+        assert_eq!(
+            blocks_for_duration(days_hours(1, 0), &mutinynet),
+            Some(2_880)
+        );
+        assert_eq!(blocks_for_duration(days_hours(0, 1), &mutinynet), Some(120));
+        for (days, hours, blocks) in [(0, 1, 6), (7, 12, 1_080), (30, 0, 4_320)] {
+            assert_eq!(
+                blocks_for_duration(days_hours(days, hours), &mainnet),
+                Some(blocks)
+            );
+        }
+
+        let regtest = NetworkProfile::from(Chain::Regtest);
+        assert_eq!(
+            blocks_for_duration(
+                Duration::from_secs(10),
+                &regtest.with_block_interval(Duration::from_secs(3))
+            ),
+            Some(4)
+        );
+        assert_eq!(
+            blocks_for_duration(
+                Duration::from_secs(10),
+                &regtest.with_block_interval(Duration::ZERO)
+            ),
+            None
+        );
+        // Relative timelocks are 16 bits: 455 days on mainnet, not 456.
+        assert_eq!(
+            blocks_for_duration(days_hours(455, 0), &mainnet),
+            Some(65_520)
+        );
+        assert_eq!(blocks_for_duration(days_hours(456, 0), &mainnet), None);
+        assert_eq!(blocks_for_duration(days_hours(23, 0), &mutinynet), None);
+    }
 }
@@ -0,0 +1,286 @@
+//! Encryption at rest of the session store.
+//!
+//! A [`Session`] holds partial signatures and unsigned transaction templates, which in some
+//! escrow configurations are enough for someone reading the device later to complete a spend.
+//! The [`Vault`] encrypts each stored session with NIP-44 under a random data key,
+//! itself encrypted with the keystore passphrase as a NIP-49 `ncryptsec` under [`VAULT_KEY`].
+//!
+//! Sessions are read and written through [`Vault::storage`], so signature material is only
+//! available once the [`Keystore`](crate::accounts::Keystore) unlocked the vault.
+//! The MuSig2 secret nonces kept between the signing rounds are only stored there too,
+//! see [`commit_nonce`](crate::musig::commit_nonce).
+//! The list of session IDs stays in clear, so the app can show how many escrows are locked.
+
+use nostr::{
+    key::SecretKey as NostrSecretKey,
+    nips::{
+        nip19::{FromBech32, ToBech32},
+        nip44::{self, Version},
+        nip49::{EncryptedSecretKey, KeySecurity},
+    },
+};
+
+use crate::{
+    backup::split_chunks,
+    error::Error,
+    musig::NONCE_KEY_PREFIX,
+    protocol::{SESSION_KEY_PREFIX, Session, deserialize, serialize},
+    secret::SecretNsec,
+    storage::Storage,
+};
+
+/// [`Storage`] key of the encrypted data key of the [`Vault`].
+pub(crate) const VAULT_KEY: &str = "scrow.vault";
+
+/// Prefix of the values encrypted by the [`Vault`].
+pub(crate) const ENCRYPTED_PREFIX: &str = "scrow.encrypted.v1:";
+
+/// NIP-49 scrypt cost of the passphrase, as a power of two.
+const LOG_N: u8 = if cfg!(test) { 4 } else { 16 };
+
+/// Whether a stored `value` was encrypted by a [`Vault`].
+pub(crate) fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// The error of reading sessions while the session store is locked.
+pub(crate) fn locked() -> Error {
+    Error::Storage("Session store is locked".to_string())
+}
+
+/// The unlocked data key of the session store.
+pub(crate) struct Vault {
+    key: SecretNsec,
+}
+
+impl Vault {
+    /// Whether `storage` has a vault.
+    pub(crate) fn exists(storage: &impl Storage) -> Result<bool, Error> {
+        Ok(storage.get(VAULT_KEY)?.is_some())
+    }
+
+    /// Creates the vault of `storage`, protected by `passphrase`,
+    /// and encrypts the sessions stored in clear.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the passphrase is empty or `storage` already has a vault.
+    pub(crate) fn create(storage: &impl Storage, passphrase: &str) -> Result<Self, Error> {
+        if Self::exists(storage)? {
+            return Err(Error::Storage(
+                "Session store is already encrypted".to_string(),
+            ));
+        }
+        let vault = Self {
+            key: SecretNsec::from(NostrSecretKey::generate()),
+        };
+        vault.store_key(storage, passphrase)?;
+        let encrypted = vault.storage(storage);
+        for id in Session::list(storage)? {
+            let key = format!("{SESSION_KEY_PREFIX}{id}");
+            if let Some(value) = storage.get(&key)?.filter(|value| !is_encrypted(value)) {
+                encrypted.set(&key, &value)?;
+            }
+        }
+        Ok(vault)
+    }
+
+    /// Unlocks the vault of `storage` with `passphrase`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `storage` has no vault or the passphrase is wrong.
+    pub(crate) fn unlock(storage: &impl Storage, passphrase: &str) -> Result<Self, Error> {
+        let ncryptsec = storage
+            .get(VAULT_KEY)?
+            .ok_or_else(|| Error::Storage("Session store is not encrypted".to_string()))?;
+        let encrypted = EncryptedSecretKey::from_bech32(&ncryptsec)
+            .map_err(|e| Error::Storage(format!("Invalid vault key: {e}")))?;
+        let key = encrypted
+            .to_secret_key(passphrase)
+            .map_err(|_| Error::WrongInputs("Wrong passphrase".to_string()))?;
+        Ok(Self {
+            key: SecretNsec::from(key),
+        })
+    }
+
+    /// Protects the vault of `storage` with `passphrase` instead of the previous one.
+    ///
+    /// Sessions stay encrypted under the same data key.
+    pub(crate) fn change_passphrase(
+        &self,
+        storage: &impl Storage,
+        passphrase: &str,
+    ) -> Result<(), Error> {
+        self.store_key(storage, passphrase)
+    }
+
+    /// `storage`, with the sessions encrypted by the vault.
+    pub(crate) fn storage<'a, S: Storage>(&'a self, storage: &'a S) -> VaultStorage<'a, S> {
+        VaultStorage {
+            vault: Some(self),
+            inner: storage,
+        }
+    }
+
+    /// Stores the data key under [`VAULT_KEY`], encrypted with `passphrase`.
+    fn store_key(&self, storage: &impl Storage, passphrase: &str) -> Result<(), Error> {
+        if passphrase.is_empty() {
+            return Err(Error::WrongInputs("Passphrase is empty".to_string()));
+        }
+        let ncryptsec = self.key.with_nostr_secret_key(|secret_key| {
+            EncryptedSecretKey::new(secret_key, passphrase, LOG_N, KeySecurity::Medium)
+        });
+        let ncryptsec = ncryptsec
+            .map_err(|e| Error::Storage(format!("Could not encrypt vault key: {e}")))?
+            .to_bech32()
+            .map_err(|e| Error::Storage(format!("Could not encode vault key: {e}")))?;
+        storage.set(VAULT_KEY, &ncryptsec)
+    }
+
+    /// Encrypts `value` to the data key.
+    fn encrypt(&self, value: &str) -> Result<String, Error> {
+        let npub = self.key.public_key();
+        let chunks = self.key.with_nostr_secret_key(|secret_key| {
+            split_chunks(value)
+                .map(|chunk| nip44::encrypt(secret_key, &npub, chunk, Version::V2))
+                .collect::<Result<Vec<_>, _>>()
+        });
+        let chunks = chunks.map_err(|e| Error::Storage(format!("Could not encrypt: {e}")))?;
+        Ok(format!("{ENCRYPTED_PREFIX}{}", serialize(&chunks)?))
+    }
+
+    /// Decrypts a `value` encrypted by [`Vault::encrypt`].
+    fn decrypt(&self, value: &str) -> Result<String, Error> {
+        let chunks = value
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .ok_or_else(|| Error::Storage("Value is not encrypted".to_string()))?;
+        let chunks = deserialize::<Vec<String>>(chunks)?;
+        let npub = self.key.public_key();
+        let value = self.key.with_nostr_secret_key(|secret_key| {
+            chunks
+                .iter()
+                .map(|chunk| nip44::decrypt(secret_key, &npub, chunk))
+                .collect::<Result<String, _>>()
+        });
+        value.map_err(|_| Error::Storage("Value could not be decrypted".to_string()))
+    }
+}
+
+/// [`Storage`] encrypting the sessions and MuSig2 secret nonces with a [`Vault`],
+/// and passing other keys through.
+///
+/// Sessions stored in clear before the vault was created are still read.
+pub(crate) struct VaultStorage<'a, S> {
+    vault: Option<&'a Vault>,
+    inner: &'a S,
+}
+
+impl<'a, S: Storage> VaultStorage<'a, S> {
+    /// `storage` without a vault, whose sessions stay in clear until one is created.
+    pub(crate) fn clear(storage: &'a S) -> Self {
+        Self {
+            vault: None,
+            inner: storage,
+        }
+    }
+
+    /// Whether the sessions are encrypted, so secrets can be stored.
+    pub(crate) fn encrypts(&self) -> bool {
+        self.vault.is_some()
+    }
+}
+
+impl<S: Storage> Storage for VaultStorage<'_, S> {
+    fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        match (self.inner.get(key)?, self.vault) {
+            (Some(value), Some(vault)) if is_encrypted(&value) => vault.decrypt(&value).map(Some),
+            (Some(value), None) if is_encrypted(&value) => Err(locked()),
+            (value, _) => Ok(value),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), Error> {
+        match self.vault {
+            Some(vault)
+                if key.starts_with(SESSION_KEY_PREFIX) || key.starts_with(NONCE_KEY_PREFIX) =>
+            {
+                self.inner.set(key, &vault.encrypt(value)?)
+            }
+            _ => self.inner.set(key, value),
+        }
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        self.inner.remove(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Amount;
+    use nostr::{Keys, Timestamp};
+
+    use super::*;
+    use crate::{
+        accounts::Keystore,
+        protocol::{DEFAULT_OFFER_VALIDITY, Handshake, Offer, offer},
+        storage::MemoryStorage,
+    };
+
+    fn session(now: Timestamp) -> Session {
+        let offerer = Keys::generate();
+        let offer = Offer {
+            amount_seller: Amount::ZERO,
+            expires_at: now + DEFAULT_OFFER_VALIDITY,
+            ..offer(offerer.public_key(), None)
+        };
+        let (handshake, _) = Handshake::offer(offerer.secret_key(), offer, now).unwrap();
+        Session::new(handshake)
+    }
+
+    #[test]
+    fn sessions_encrypted_at_rest() {
+        let now = Timestamp::now();
+        let storage = MemoryStorage::default();
+        let old = session(now);
+        old.save(&storage).unwrap();
+
+        let vault = Vault::create(&storage, "correct horse").unwrap();
+        assert!(Vault::create(&storage, "correct horse").is_err());
+        let new = session(now);
+        new.save(&vault.storage(&storage)).unwrap();
+        for session in [&old, &new] {
+            let id = session.id().unwrap();
+            let raw = storage
+                .get(&format!("{SESSION_KEY_PREFIX}{id}"))
+                .unwrap()
+                .unwrap();
+            assert!(is_encrypted(&raw));
+            assert!(!raw.contains(&session.handshake.negotiated_offer().offerer.to_hex()));
+            // Signature material needs the vault unlocked.
+            assert!(Session::load(&storage, &id).is_err());
+        }
+        assert_eq!(Session::list(&storage).unwrap().len(), 2);
+
+        assert!(Vault::unlock(&storage, "wrong horse").is_err());
+        let mut keystore = Keystore::default();
+        assert!(keystore.sessions(&storage).is_err());
+        keystore.unlock_sessions(&storage, "correct horse").unwrap();
+        let id = new.id().unwrap();
+        assert_eq!(
+            Session::load(&keystore.sessions(&storage).unwrap(), &id).unwrap(),
+            Some(new.clone())
+        );
+
+        vault.change_passphrase(&storage, "battery staple").unwrap();
+        assert!(Vault::unlock(&storage, "correct horse").is_err());
+        let vault = Vault::unlock(&storage, "battery staple").unwrap();
+        assert_eq!(
+            Session::load(&vault.storage(&storage), &old.id().unwrap()).unwrap(),
+            Some(old)
+        );
+        keystore.lock_sessions();
+        assert!(keystore.sessions(&storage).is_err());
+    }
+}
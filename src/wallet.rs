@@ -0,0 +1,588 @@
+//! Coins of the wallet funding escrows, the user's `npub`-derived address.
+//!
+//! Coins are the UTXOs of the address, listed from Esplora with [`list_coins`].
+//! Users label them, freeze the ones that must never fund an escrow, and pick coins by hand,
+//! so unrelated coins are not linked to a counterparty through a funding transaction.
+//! Labels and freezes are the [`CoinControl`], persisted as JSON in [`Storage`].
+//! Users leaving scrow sweep every coin to another wallet with [`sweep_wallet`].
+use std::collections::HashSet;
+
+use bitcoin::{
+    Address, Amount, FeeRate, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    Weight, Witness, absolute, transaction,
+};
+use secp256k1::SECP256K1;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cofunding::FundingInput,
+    error::{Error, ResultExt},
+    esplora::{EsploraClient, get_block_height},
+    secret::SecretNsec,
+    sign::sign_key_spend,
+    storage::Storage,
+    tx::anti_fee_sniping_lock_time,
+    util::npub_to_address,
+};
+
+/// [`Storage`] key of the [`CoinControl`].
+pub(crate) const COIN_CONTROL_KEY: &str = "scrow.coin_control";
+
+/// Weight of a transaction without inputs and outputs, with the SegWit marker and flag.
+const TX_OVERHEAD_WEIGHT: Weight = Weight::from_wu(42);
+
+/// Weight of a P2TR input spent through its key path.
+const P2TR_INPUT_WEIGHT: Weight = Weight::from_wu(230);
+
+/// Weight of a P2TR output, such as the escrow output and the change.
+const P2TR_OUTPUT_WEIGHT: Weight = Weight::from_wu(172);
+
+/// A UTXO of the wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Coin {
+    /// Where the coin is.
+    pub(crate) outpoint: OutPoint,
+    /// The coin's output.
+    pub(crate) prevout: TxOut,
+    /// Whether the transaction creating the coin is confirmed.
+    pub(crate) confirmed: bool,
+}
+
+impl Coin {
+    /// The coin as an input of a funding transaction.
+    pub(crate) fn funding_input(&self) -> FundingInput {
+        FundingInput {
+            outpoint: self.outpoint,
+            prevout: self.prevout.clone(),
+        }
+    }
+}
+
+/// The user's label and freeze of a coin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CoinLabel {
+    /// The labeled coin.
+    pub(crate) outpoint: OutPoint,
+    /// Where the coin came from, as told by the user.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) label: String,
+    /// Whether the coin must never be selected.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) frozen: bool,
+}
+
+/// Labels and freezes of coins, with at most one entry per coin.
+///
+/// Coins without a label that are not frozen have no entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct CoinControl {
+    coins: Vec<CoinLabel>,
+}
+
+impl CoinControl {
+    /// Loads the coin control from `storage`, or an empty one if none was saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(COIN_CONTROL_KEY)? {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|e| Error::Storage(format!("Invalid coin control: {e}"))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the coin control to `storage`.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::Storage(format!("Could not serialize coin control: {e}")))?;
+        storage.set(COIN_CONTROL_KEY, &json)
+    }
+
+    /// Every labeled or frozen coin.
+    pub(crate) fn labels(&self) -> &[CoinLabel] {
+        &self.coins
+    }
+
+    /// The label and freeze of the coin at `outpoint`, if any.
+    pub(crate) fn get(&self, outpoint: &OutPoint) -> Option<&CoinLabel> {
+        self.coins.iter().find(|coin| coin.outpoint == *outpoint)
+    }
+
+    /// The label of the coin at `outpoint`, empty if none.
+    pub(crate) fn label(&self, outpoint: &OutPoint) -> &str {
+        self.get(outpoint).map_or("", |coin| coin.label.as_str())
+    }
+
+    /// Whether the coin at `outpoint` is frozen.
+    pub(crate) fn is_frozen(&self, outpoint: &OutPoint) -> bool {
+        self.get(outpoint).is_some_and(|coin| coin.frozen)
+    }
+
+    /// Labels the coin at `outpoint`, or removes its label if `label` is blank.
+    pub(crate) fn set_label(&mut self, outpoint: OutPoint, label: &str) {
+        self.update(outpoint, |coin| coin.label = label.trim().to_string());
+    }
+
+    /// Freezes or unfreezes the coin at `outpoint`.
+    pub(crate) fn set_frozen(&mut self, outpoint: OutPoint, frozen: bool) {
+        self.update(outpoint, |coin| coin.frozen = frozen);
+    }
+
+    /// Updates the entry of the coin at `outpoint`, dropping it if it ends up empty.
+    fn update(&mut self, outpoint: OutPoint, update: impl FnOnce(&mut CoinLabel)) {
+        let index = match self.coins.iter().position(|coin| coin.outpoint == outpoint) {
+            Some(index) => index,
+            None => {
+                self.coins.push(CoinLabel {
+                    outpoint,
+                    label: String::new(),
+                    frozen: false,
+                });
+                self.coins.len() - 1
+            }
+        };
+        update(&mut self.coins[index]);
+        if self.coins[index].label.is_empty() && !self.coins[index].frozen {
+            self.coins.remove(index);
+        }
+    }
+}
+
+/// How the coins funding an escrow are picked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CoinSelection {
+    /// Confirmed coins first, largest first, skipping frozen coins.
+    #[default]
+    Automatic,
+    /// Exactly these coins, which must not be frozen.
+    Manual(Vec<OutPoint>),
+}
+
+/// Coins selected to pay an amount.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct SelectedCoins {
+    /// The selected coins, as funding inputs.
+    pub(crate) inputs: Vec<FundingInput>,
+    /// Change back to the wallet, unless it would be dust.
+    pub(crate) change: Option<TxOut>,
+    /// Fee of the funding transaction, including any change too small to keep.
+    #[cfg_attr(
+        feature = "serde-types",
+        serde(with = "bitcoin::amount::serde::as_sat")
+    )]
+    pub(crate) fee: Amount,
+}
+
+/// Selects `coins` paying `amount` to a P2TR output at `fee_rate`, with change to `change`.
+///
+/// # Errors
+///
+/// Errors if a manually selected coin is unknown, repeated or frozen,
+/// or the selected coins don't cover `amount` and the fee.
+pub(crate) fn select_coins(
+    coins: &[Coin],
+    control: &CoinControl,
+    selection: &CoinSelection,
+    amount: Amount,
+    fee_rate: FeeRate,
+    change: &Address,
+) -> Result<SelectedCoins, Error> {
+    let candidates = match selection {
+        CoinSelection::Automatic => {
+            let mut candidates = coins
+                .iter()
+                .filter(|coin| !control.is_frozen(&coin.outpoint))
+                .collect::<Vec<_>>();
+            candidates.sort_by_key(|coin| (!coin.confirmed, std::cmp::Reverse(coin.prevout.value)));
+            candidates
+        }
+        CoinSelection::Manual(outpoints) => {
+            let mut seen = HashSet::new();
+            outpoints
+                .iter()
+                .map(|outpoint| {
+                    if !seen.insert(outpoint) {
+                        return Err(Error::WrongInputs(format!(
+                            "Coin {outpoint} selected twice"
+                        )));
+                    }
+                    if control.is_frozen(outpoint) {
+                        return Err(Error::WrongInputs(format!("Coin {outpoint} is frozen")));
+                    }
+                    coins
+                        .iter()
+                        .find(|coin| coin.outpoint == *outpoint)
+                        .ok_or_else(|| Error::WrongInputs(format!("Unknown coin {outpoint}")))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let change_script = change.script_pubkey();
+    let fee = |inputs: usize, change: bool| {
+        let outputs = if change { 2 } else { 1 };
+        let weight =
+            TX_OVERHEAD_WEIGHT + P2TR_INPUT_WEIGHT * inputs as u64 + P2TR_OUTPUT_WEIGHT * outputs;
+        fee_rate.fee_wu(weight).ok_or(Error::Rounding)
+    };
+    let manual = matches!(selection, CoinSelection::Manual(_));
+    let mut selected = Vec::new();
+    let mut total = Amount::ZERO;
+    for coin in candidates {
+        selected.push(coin.funding_input());
+        total = total
+            .checked_add(coin.prevout.value)
+            .ok_or(Error::Rounding)?;
+        let fee_without_change = fee(selected.len(), false)?;
+        let needed = amount
+            .checked_add(fee_without_change)
+            .ok_or(Error::Rounding)?;
+        if manual || total < needed {
+            continue;
+        }
+        let fee_with_change = fee(selected.len(), true)?;
+        return Ok(selection_with_change(
+            selected,
+            total,
+            amount,
+            fee_without_change,
+            fee_with_change,
+            change_script,
+        ));
+    }
+    let fee_without_change = fee(selected.len(), false)?;
+    let needed = amount
+        .checked_add(fee_without_change)
+        .ok_or(Error::Rounding)?;
+    if selected.is_empty() || total < needed {
+        return Err(Error::FundingMismatch {
+            expected: needed,
+            actual: total,
+        });
+    }
+    let fee_with_change = fee(selected.len(), true)?;
+    Ok(selection_with_change(
+        selected,
+        total,
+        amount,
+        fee_without_change,
+        fee_with_change,
+        change_script,
+    ))
+}
+
+/// The selection of `inputs` worth `total`, covering `amount` and `fee_without_change`,
+/// with change if it is above the dust limit once `fee_with_change` is paid.
+fn selection_with_change(
+    inputs: Vec<FundingInput>,
+    total: Amount,
+    amount: Amount,
+    fee_without_change: Amount,
+    fee_with_change: Amount,
+    change_script: ScriptBuf,
+) -> SelectedCoins {
+    let change = amount
+        .checked_add(fee_with_change)
+        .and_then(|needed| total.checked_sub(needed))
+        .filter(|change| *change >= change_script.minimal_non_dust())
+        .map(|value| TxOut {
+            value,
+            script_pubkey: change_script,
+        });
+    let fee = match &change {
+        Some(_) => fee_with_change,
+        None => total - amount,
+    };
+    debug_assert!(fee >= fee_without_change);
+    SelectedCoins {
+        inputs,
+        change,
+        fee,
+    }
+}
+
+/// Lists the coins of `address` from Esplora, confirmed or in the mempool.
+///
+/// Only the most recent transactions Esplora returns in a single page are scanned,
+/// which covers the few coins an escrow wallet holds.
+pub(crate) async fn list_coins(
+    client: &EsploraClient,
+    address: &Address,
+) -> Result<Vec<Coin>, Error> {
+    let txs = client
+        .get_address_txs(address, None)
+        .await
+        .context(format!("listing the transactions of {address}"))?;
+    let spent = txs
+        .iter()
+        .flat_map(|tx| &tx.vin)
+        .map(|vin| OutPoint::new(vin.txid, vin.vout))
+        .collect::<HashSet<_>>();
+    let script_pubkey = address.script_pubkey();
+    Ok(txs
+        .iter()
+        .flat_map(|tx| {
+            tx.vout
+                .iter()
+                .enumerate()
+                .filter(|(_, vout)| vout.scriptpubkey == script_pubkey)
+                .map(|(index, vout)| Coin {
+                    outpoint: OutPoint::new(tx.txid, index as u32),
+                    prevout: TxOut {
+                        value: Amount::from_sat(vout.value),
+                        script_pubkey: vout.scriptpubkey.clone(),
+                    },
+                    confirmed: tx.status.confirmed,
+                })
+        })
+        .filter(|coin| !spent.contains(&coin.outpoint))
+        .collect())
+}
+
+/// Builds and signs a transaction spending all `coins` of the `npub`-derived address of
+/// `nsec` to `destination` at `fee_rate`, frozen coins included.
+///
+/// The transaction signals replaceability, so its fee can be bumped.
+/// Pass [`absolute::LockTime::ZERO`] for no lock time, or [`anti_fee_sniping_lock_time`].
+///
+/// # Errors
+///
+/// Errors if there are no coins, a coin is repeated or not on the address of `nsec`,
+/// or the coins don't cover the fee and an output above the dust limit.
+pub(crate) fn sweep_tx(
+    coins: &[Coin],
+    nsec: &SecretNsec,
+    destination: &Address,
+    fee_rate: FeeRate,
+    lock_time: absolute::LockTime,
+) -> Result<Transaction, Error> {
+    if coins.is_empty() {
+        return Err(Error::WrongInputs("The wallet has no coins".to_string()));
+    }
+    let script_pubkey = ScriptBuf::new_p2tr(SECP256K1, nsec.x_only_public_key(), None);
+    let mut seen = HashSet::new();
+    for coin in coins {
+        if !seen.insert(coin.outpoint) {
+            return Err(Error::WrongInputs(format!(
+                "Coin {} listed twice",
+                coin.outpoint
+            )));
+        }
+        if coin.prevout.script_pubkey != script_pubkey {
+            return Err(Error::WrongInputs(format!(
+                "Coin {} is not on the wallet address",
+                coin.outpoint
+            )));
+        }
+    }
+
+    let total = coins.iter().map(|coin| coin.prevout.value).sum::<Amount>();
+    let mut output = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: destination.script_pubkey(),
+    };
+    let weight = TX_OVERHEAD_WEIGHT + P2TR_INPUT_WEIGHT * coins.len() as u64 + output.weight();
+    let fee = fee_rate.fee_wu(weight).ok_or(Error::Rounding)?;
+    let dust = output.script_pubkey.minimal_non_dust();
+    output.value = total
+        .checked_sub(fee)
+        .filter(|value| *value >= dust)
+        .ok_or(Error::FundingMismatch {
+            expected: fee + dust,
+            actual: total,
+        })?;
+
+    let mut tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time,
+        input: coins
+            .iter()
+            .map(|coin| TxIn {
+                previous_output: coin.outpoint,
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            })
+            .collect(),
+        output: vec![output],
+    };
+    let prevouts = coins
+        .iter()
+        .map(|coin| coin.prevout.clone())
+        .collect::<Vec<_>>();
+    for index in 0..tx.input.len() {
+        let signature = sign_key_spend(&tx, index, nsec, &prevouts)?;
+        tx.input[index].witness = Witness::from_slice(&[signature.as_ref()]);
+    }
+    Ok(tx)
+}
+
+/// Sweeps every coin of the `npub`-derived wallet of `nsec` on `network` to `destination`,
+/// listing the coins from Esplora, see [`sweep_tx`].
+///
+/// Returns the signed transaction, to be broadcast by the caller.
+pub(crate) async fn sweep_wallet(
+    client: &EsploraClient,
+    nsec: &SecretNsec,
+    destination: &Address,
+    fee_rate: FeeRate,
+    network: Network,
+) -> Result<Transaction, Error> {
+    let address = npub_to_address(&nsec.public_key(), network)?;
+    let coins = list_coins(client, &address).await?;
+    let lock_time = anti_fee_sniping_lock_time(get_block_height(client).await?)?;
+    sweep_tx(&coins, nsec, destination, fee_rate, lock_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Txid, XOnlyPublicKey, hashes::Hash};
+    use secp256k1::schnorr;
+
+    use super::*;
+    use crate::{sign::key_spend_message, storage::MemoryStorage};
+
+    fn coin(address: &Address, seed: u8, amount: u64, confirmed: bool) -> Coin {
+        Coin {
+            outpoint: OutPoint::new(Txid::from_byte_array([seed; 32]), 0),
+            prevout: TxOut {
+                value: Amount::from_sat(amount),
+                script_pubkey: address.script_pubkey(),
+            },
+            confirmed,
+        }
+    }
+
+    #[test]
+    fn coin_control() {
+        let address =
+            npub_to_address(&SecretNsec::generate().public_key(), Network::Regtest).unwrap();
+        let coins = [
+            coin(&address, 1, 20_000, true),
+            coin(&address, 2, 80_000, true),
+            coin(&address, 3, 200_000, false),
+        ];
+        let storage = MemoryStorage::default();
+        let mut control = CoinControl::load(&storage).unwrap();
+        control.set_label(coins[1].outpoint, " From the exchange ");
+        control.set_frozen(coins[1].outpoint, true);
+        control.set_label(coins[0].outpoint, "Salary");
+        control.set_label(coins[0].outpoint, "");
+        assert_eq!(control.labels().len(), 1);
+        control.save(&storage).unwrap();
+        let control = CoinControl::load(&storage).unwrap();
+        assert_eq!(control.label(&coins[1].outpoint), "From the exchange");
+        assert!(control.is_frozen(&coins[1].outpoint));
+        assert!(!control.is_frozen(&coins[0].outpoint));
+
+        // Frozen coins are skipped, and confirmed ones come first.
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+        let amount = Amount::from_sat(15_000);
+        let selected = select_coins(
+            &coins,
+            &control,
+            &CoinSelection::Automatic,
+            amount,
+            fee_rate,
+            &address,
+        )
+        .unwrap();
+        assert_eq!(selected.inputs, [coins[0].funding_input()]);
+        let change = selected.change.as_ref().unwrap();
+        assert_eq!(
+            change.value + selected.fee + amount,
+            Amount::from_sat(20_000)
+        );
+        assert_eq!(selected.fee, Amount::from_sat(308));
+
+        let selected = select_coins(
+            &coins,
+            &control,
+            &CoinSelection::Automatic,
+            Amount::from_sat(100_000),
+            fee_rate,
+            &address,
+        )
+        .unwrap();
+        assert_eq!(
+            selected.inputs,
+            [coins[0].funding_input(), coins[2].funding_input()]
+        );
+
+        // Manual selections are taken as they are.
+        let manual = |outpoints: &[OutPoint], amount: u64| {
+            select_coins(
+                &coins,
+                &control,
+                &CoinSelection::Manual(outpoints.to_vec()),
+                Amount::from_sat(amount),
+                fee_rate,
+                &address,
+            )
+        };
+        let selected = manual(&[coins[2].outpoint, coins[0].outpoint], 15_000).unwrap();
+        assert_eq!(selected.inputs.len(), 2);
+        assert_eq!(selected.inputs[0], coins[2].funding_input());
+        // Change too small to keep goes to the fee.
+        let selected = manual(&[coins[0].outpoint], 19_700).unwrap();
+        assert_eq!(selected.change, None);
+        assert_eq!(selected.fee, Amount::from_sat(300));
+        assert!(manual(&[coins[1].outpoint], 15_000).is_err());
+        assert!(manual(&[coins[0].outpoint, coins[0].outpoint], 15_000).is_err());
+        assert!(manual(&[OutPoint::null()], 15_000).is_err());
+        assert_eq!(
+            manual(&[coins[0].outpoint], 20_000).unwrap_err().code(),
+            305
+        );
+        assert!(manual(&[], 1_000).is_err());
+    }
+
+    #[test]
+    fn sweep() {
+        let nsec = SecretNsec::generate();
+        let address = npub_to_address(&nsec.public_key(), Network::Regtest).unwrap();
+        let coins = [
+            coin(&address, 1, 20_000, true),
+            coin(&address, 2, 80_000, false),
+        ];
+        let destination =
+            npub_to_address(&SecretNsec::generate().public_key(), Network::Regtest).unwrap();
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+        let lock_time = absolute::LockTime::from_height(100).unwrap();
+        let tx = sweep_tx(&coins, &nsec, &destination, fee_rate, lock_time).unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.output.len(), 1);
+        // 42 + 2 * 230 + 172 weight units at 2 sat/vB.
+        assert_eq!(tx.output[0].value, Amount::from_sat(100_000 - 337));
+        assert_eq!(tx.output[0].script_pubkey, destination.script_pubkey());
+
+        // Every input is signed by the tweaked key of the wallet address.
+        let prevouts = coins
+            .iter()
+            .map(|coin| coin.prevout.clone())
+            .collect::<Vec<_>>();
+        let output_key =
+            XOnlyPublicKey::from_slice(&address.script_pubkey().as_bytes()[2..]).unwrap();
+        for index in 0..tx.input.len() {
+            let signature = schnorr::Signature::from_slice(&tx.input[index].witness[0]).unwrap();
+            let message = key_spend_message(&tx, index, &prevouts).unwrap();
+            assert!(
+                SECP256K1
+                    .verify_schnorr(&signature, &message, &output_key)
+                    .is_ok()
+            );
+        }
+
+        // Foreign, repeated or too few coins are refused.
+        let other =
+            npub_to_address(&SecretNsec::generate().public_key(), Network::Regtest).unwrap();
+        let sweep = |coins: &[Coin]| sweep_tx(coins, &nsec, &destination, fee_rate, lock_time);
+        assert!(sweep(&[coin(&other, 3, 20_000, true)]).is_err());
+        assert!(sweep(&[coins[0].clone(), coins[0].clone()]).is_err());
+        assert_eq!(
+            sweep(&[coin(&address, 3, 500, true)]).unwrap_err().code(),
+            305
+        );
+        assert!(sweep(&[]).is_err());
+    }
+}
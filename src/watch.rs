@@ -0,0 +1,581 @@
+//! Watch-only escrows, for auditors, accountants and platform operators.
+//!
+//! A [`WatchSession`] is built from public data alone: both participants' npubs,
+//! the arbitrator's, the timelock and the funding [`Txid`].
+//! It never holds an nsec, so it can only monitor the escrow, track its confirmations
+//! and generate audit reports, never sign or broadcast.
+//!
+//! When the transactions prepared for the escrow are known, a spend by any other transaction,
+//! such as the counterparty and arbitrator resolving through a dispute leaf,
+//! is reported as a [`WatchStatus::Conflict`] with the [`Conflict`] details.
+//!
+//! A funding paying a subtly different escrow, such as one derived with swapped keys or
+//! another timelock, is a [`WatchStatus::Mismatch`] whose report names the parameter
+//! that diverged, see [`NearMiss`](crate::audit::NearMiss).
+use std::fmt::Write as _;
+
+use bitcoin::{Address, Amount, Txid};
+#[cfg(debug_assertions)]
+use dioxus::logger::tracing::trace;
+#[cfg(feature = "serde-types")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{AuditReport, SpendAudit, audit_escrow},
+    error::{Error, ResultExt},
+    esplora::{EsploraClient, get_block_height},
+    i18n::Language,
+    keys::Npub,
+    network::NetworkProfile,
+    scripts::{EscrowConfig, SpendPath},
+    units::format_btc_fixed,
+};
+#[cfg(feature = "serde-types")]
+use crate::{
+    protocol::{deserialize, serialize},
+    storage::Storage,
+};
+
+/// Version of the persisted [`WatchSession`] format.
+pub(crate) const WATCH_SESSION_VERSION: u8 = 1;
+
+/// [`Storage`] key of the funding [`Txid`]s of the persisted [`WatchSession`]s.
+#[cfg(feature = "serde-types")]
+pub(crate) const WATCHED_KEY: &str = "scrow.watched";
+
+/// An escrow monitored from its public data, without any secret key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct WatchSession {
+    /// Format version, see [`WATCH_SESSION_VERSION`].
+    pub(crate) version: u8,
+    /// The watched escrow.
+    pub(crate) config: EscrowConfig,
+    /// Transaction funding the escrow.
+    pub(crate) funding_txid: Txid,
+    /// Free-form label, such as an order number.
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub(crate) label: String,
+}
+
+/// Where a watched escrow stands, at a given chain tip.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum WatchStatus {
+    /// The funding transaction doesn't pay the escrow,
+    /// though it may pay a [`NearMiss`](crate::audit::NearMiss) of it.
+    Mismatch,
+    /// The funding transaction is not confirmed yet.
+    Unconfirmed,
+    /// The escrow holds its funds.
+    Funded {
+        /// Confirmations of the funding transaction.
+        confirmations: u32,
+        /// Height from which the arbitrator can resolve a dispute escrow.
+        timelock_height: Option<u32>,
+    },
+    /// The escrow was spent.
+    Resolved {
+        /// How the escrow was spent, [`None`] if not through the claimed escrow.
+        path: Option<SpendPath>,
+        /// Confirmations of the spending transaction, 0 if unconfirmed.
+        confirmations: u32,
+    },
+    /// The escrow was spent by a transaction other than the expected ones.
+    Conflict {
+        /// The spending transaction.
+        txid: Txid,
+        /// How the escrow was spent, [`None`] if not through the claimed escrow.
+        path: Option<SpendPath>,
+        /// Confirmations of the spending transaction, 0 if unconfirmed.
+        confirmations: u32,
+    },
+}
+
+impl WatchStatus {
+    /// The [`ConfirmationProgress`] of the funding towards the `required` confirmations
+    /// before resolution, complete once the escrow was spent.
+    pub(crate) fn funding_progress(self, required: u32) -> ConfirmationProgress {
+        let confirmations = match self {
+            WatchStatus::Mismatch | WatchStatus::Unconfirmed => 0,
+            WatchStatus::Funded { confirmations, .. } => confirmations,
+            WatchStatus::Resolved { .. } | WatchStatus::Conflict { .. } => required,
+        };
+        ConfirmationProgress {
+            confirmations: confirmations.min(required),
+            required,
+        }
+    }
+}
+
+/// Confirmations of the funding out of the ones required before resolution,
+/// see [`Offer::min_confirmations`](crate::protocol::Offer::min_confirmations).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct ConfirmationProgress {
+    /// Confirmations so far, at most `required`.
+    pub(crate) confirmations: u32,
+    /// Confirmations required.
+    pub(crate) required: u32,
+}
+
+impl ConfirmationProgress {
+    /// Whether the funding has the required confirmations.
+    pub(crate) fn is_complete(&self) -> bool {
+        self.confirmations >= self.required
+    }
+
+    /// Describes the progress for the UI, such as `2 of 3 confirmations`.
+    pub(crate) fn description(&self) -> String {
+        format!("{} of {} confirmations", self.confirmations, self.required)
+    }
+}
+
+/// A spend of an escrow by a transaction other than the ones prepared for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(Serialize, Deserialize))]
+pub(crate) struct Conflict {
+    /// How the escrow was actually spent: leaf, signers and payouts.
+    pub(crate) spend: SpendAudit,
+    /// The transactions that were expected to spend it.
+    pub(crate) expected: Vec<Txid>,
+}
+
+impl Conflict {
+    /// The conflict of `spend`, if it is not one of the `expected` transactions.
+    pub(crate) fn detect(spend: &SpendAudit, expected: &[Txid]) -> Option<Self> {
+        if expected.contains(&spend.txid) {
+            return None;
+        }
+        #[cfg(debug_assertions)]
+        trace!(txid = %spend.txid, path = ?spend.path, "unexpected escrow spend");
+        Some(Self {
+            spend: spend.clone(),
+            expected: expected.to_vec(),
+        })
+    }
+
+    /// Describes the conflicting spend for the UI and logs.
+    pub(crate) fn description(&self) -> String {
+        let path = path_text(self.spend.path);
+        let signers = self
+            .spend
+            .signers
+            .iter()
+            .filter(|signer| signer.valid)
+            .map(|signer| Npub::from(signer.npub).to_string())
+            .collect::<Vec<_>>();
+        if signers.is_empty() {
+            format!("Escrow was spent by {} through {path}", self.spend.txid)
+        } else {
+            format!(
+                "Escrow was spent by {} through {path}, signed by {}",
+                self.spend.txid,
+                signers.join(" and ")
+            )
+        }
+    }
+}
+
+impl WatchSession {
+    /// Watches the escrow of `config`, funded by `funding_txid`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the keys and timelock don't describe a valid escrow.
+    pub(crate) fn new(
+        config: EscrowConfig,
+        funding_txid: Txid,
+        label: impl Into<String>,
+    ) -> Result<Self, Error> {
+        config.spend_info()?;
+        #[cfg(debug_assertions)]
+        trace!(%funding_txid, "watching escrow");
+        Ok(Self {
+            version: WATCH_SESSION_VERSION,
+            config,
+            funding_txid,
+            label: label.into(),
+        })
+    }
+
+    /// The watched escrow [`Address`].
+    pub(crate) fn address(&self) -> Result<Address, Error> {
+        self.config.address()
+    }
+
+    /// Audits the escrow on chain, returning the [`AuditReport`] and the escrow's
+    /// [`WatchStatus`] at the current chain tip, see [`WatchSession::status`].
+    pub(crate) async fn refresh(
+        &self,
+        client: &EsploraClient,
+        expected: Option<&[Txid]>,
+        profile: &NetworkProfile,
+    ) -> Result<(AuditReport, WatchStatus), Error> {
+        let report = audit_escrow(client, self.funding_txid, &self.config, profile).await?;
+        let tip_height = get_block_height(client)
+            .await
+            .context("fetching the chain tip")?;
+        let status = self.status(&report, tip_height, expected);
+        Ok((report, status))
+    }
+
+    /// The [`WatchStatus`] of the escrow given its audit `report` and the `tip_height`.
+    ///
+    /// With the `expected` spending transactions, such as the ones signed in a local
+    /// [`Session`](crate::protocol::Session), any other spend is a [`WatchStatus::Conflict`].
+    pub(crate) fn status(
+        &self,
+        report: &AuditReport,
+        tip_height: u32,
+        expected: Option<&[Txid]>,
+    ) -> WatchStatus {
+        let confirmations =
+            |height: Option<u32>| height.map_or(0, |height| tip_height.saturating_sub(height) + 1);
+        if !report.matches_config() {
+            return WatchStatus::Mismatch;
+        }
+        if let Some(spend) = &report.spend {
+            if expected.is_some_and(|expected| !expected.contains(&spend.txid)) {
+                return WatchStatus::Conflict {
+                    txid: spend.txid,
+                    path: spend.path,
+                    confirmations: confirmations(spend.confirmed_height),
+                };
+            }
+            return WatchStatus::Resolved {
+                path: spend.path,
+                confirmations: confirmations(spend.confirmed_height),
+            };
+        }
+        match report.confirmed_height {
+            None => WatchStatus::Unconfirmed,
+            Some(height) => WatchStatus::Funded {
+                confirmations: confirmations(Some(height)),
+                timelock_height: self
+                    .config
+                    .timelock_duration
+                    .map(|timelock| height.saturating_add(timelock)),
+            },
+        }
+    }
+
+    /// Renders an audit `report` of the escrow as plain text, for accountants and records.
+    pub(crate) fn audit_report(
+        &self,
+        report: &AuditReport,
+        status: WatchStatus,
+    ) -> Result<String, Error> {
+        let mut text = String::new();
+        // Writing to a `String` never fails.
+        let _ = writeln!(text, "Escrow audit report");
+        if !self.label.is_empty() {
+            let _ = writeln!(text, "Label: {}", self.label);
+        }
+        let _ = writeln!(text, "Network: {}", self.config.network);
+        let _ = writeln!(text, "Escrow address: {}", self.address()?);
+        let _ = writeln!(text, "Participant 1: {}", Npub::from(self.config.npub_1));
+        let _ = writeln!(text, "Participant 2: {}", Npub::from(self.config.npub_2));
+        if let (Some(arbitrator), Some(timelock)) =
+            (self.config.npub_arbitrator, self.config.timelock_duration)
+        {
+            let _ = writeln!(text, "Arbitrator: {}", Npub::from(arbitrator));
+            let _ = writeln!(text, "Timelock: {timelock} blocks");
+        }
+        let _ = writeln!(text, "Funding transaction: {}", report.funding_txid);
+        let _ = writeln!(text, "Status: {}", status_text(status));
+        if let (Some(outpoint), Some(amount)) = (report.outpoint, report.amount) {
+            let _ = writeln!(text, "Escrow output: {outpoint}, {}", btc(amount));
+        }
+        if let Some(near_miss) = &report.near_miss {
+            let _ = writeln!(
+                text,
+                "Near miss: {}, {}",
+                near_miss.description(),
+                btc(near_miss.amount)
+            );
+            let _ = writeln!(text, "Funded address: {}", near_miss.config.address()?);
+        }
+        if let Some(height) = report.confirmed_height {
+            let _ = writeln!(text, "Funding confirmed at height: {height}");
+        }
+        if let Some(spend) = &report.spend {
+            let _ = writeln!(text, "Spending transaction: {}", spend.txid);
+            if let Some(height) = spend.confirmed_height {
+                let _ = writeln!(text, "Spend confirmed at height: {height}");
+            }
+            for signer in &spend.signers {
+                let validity = if signer.valid { "valid" } else { "INVALID" };
+                let _ = writeln!(text, "Signature of {}: {validity}", Npub::from(signer.npub));
+            }
+            if report.is_valid_spend() {
+                let _ = writeln!(text, "Spent through a leaf signed by all of its signers");
+            }
+            for payout in &spend.payouts {
+                let recipient = Address::from_script(&payout.script_pubkey, self.config.network)
+                    .map_or_else(|_| payout.script_pubkey.to_string(), |a| a.to_string());
+                let _ = writeln!(text, "Payout: {} to {recipient}", btc(payout.value));
+            }
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl WatchSession {
+    /// [`Storage`] key of the watch session of `funding_txid`.
+    fn key(funding_txid: &Txid) -> String {
+        format!("scrow.watch.{funding_txid}")
+    }
+
+    /// The funding [`Txid`]s of the watch sessions persisted in `storage`.
+    pub(crate) fn list(storage: &impl Storage) -> Result<Vec<Txid>, Error> {
+        match storage.get(WATCHED_KEY)? {
+            Some(json) => deserialize(&json),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Loads the watch session of `funding_txid` from `storage`, if it was saved.
+    pub(crate) fn load(storage: &impl Storage, funding_txid: &Txid) -> Result<Option<Self>, Error> {
+        let Some(json) = storage.get(&Self::key(funding_txid))? else {
+            return Ok(None);
+        };
+        let session = deserialize::<Self>(&json)?;
+        if session.version != WATCH_SESSION_VERSION {
+            return Err(Error::Storage(format!(
+                "Unsupported watch session version {}",
+                session.version
+            )));
+        }
+        if session.funding_txid != *funding_txid {
+            return Err(Error::Storage(format!(
+                "Watch session {funding_txid} is stored under another transaction"
+            )));
+        }
+        Ok(Some(session))
+    }
+
+    /// Saves the watch session to `storage`, next to the other watch sessions.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        storage.set(&Self::key(&self.funding_txid), &serialize(self)?)?;
+        let mut txids = Self::list(storage)?;
+        if !txids.contains(&self.funding_txid) {
+            txids.push(self.funding_txid);
+            storage.set(WATCHED_KEY, &serialize(&txids)?)?;
+        }
+        Ok(())
+    }
+
+    /// Stops watching the escrow funded by `funding_txid`.
+    pub(crate) fn remove(storage: &impl Storage, funding_txid: &Txid) -> Result<(), Error> {
+        storage.remove(&Self::key(funding_txid))?;
+        let mut txids = Self::list(storage)?;
+        txids.retain(|txid| txid != funding_txid);
+        storage.set(WATCHED_KEY, &serialize(&txids)?)
+    }
+}
+
+/// Human-readable [`WatchStatus`].
+fn status_text(status: WatchStatus) -> String {
+    match status {
+        WatchStatus::Mismatch => "funding does not pay the escrow".to_string(),
+        WatchStatus::Unconfirmed => "funding unconfirmed".to_string(),
+        WatchStatus::Funded {
+            confirmations,
+            timelock_height: Some(height),
+        } => format!("funded, {confirmations} confirmations, disputable from height {height}"),
+        WatchStatus::Funded { confirmations, .. } => {
+            format!("funded, {confirmations} confirmations")
+        }
+        WatchStatus::Resolved {
+            path,
+            confirmations,
+        } => format!(
+            "resolved through {}, {confirmations} confirmations",
+            path_text(path)
+        ),
+        WatchStatus::Conflict {
+            txid,
+            path,
+            confirmations,
+        } => format!(
+            "CONFLICT: spent by unexpected transaction {txid} through {}, {confirmations} confirmations",
+            path_text(path)
+        ),
+    }
+}
+
+/// Human-readable spending path.
+fn path_text(path: Option<SpendPath>) -> String {
+    match path {
+        Some(SpendPath::KeyPath) => "cooperative key path".to_string(),
+        Some(SpendPath::Leaf(leaf)) => format!("leaf {leaf:?}"),
+        None => "an unknown script".to_string(),
+    }
+}
+
+/// An [`Amount`] in BTC with all 8 decimals, as accounting records expect.
+fn btc(amount: Amount) -> String {
+    format_btc_fixed(amount, 8, Language::En)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{OutPoint, ScriptBuf, TxOut, hashes::Hash};
+
+    use bitcoin::Network;
+
+    use super::*;
+    use crate::{
+        audit::{SignerAudit, SpendAudit},
+        scripts::{CURRENT_SCRIPT_TEMPLATE, EscrowScript},
+        secret::SecretNsec,
+    };
+
+    #[test]
+    fn watch_without_secrets() {
+        let (npub_1, npub_2, npub_arbitrator) = (
+            SecretNsec::generate().public_key(),
+            SecretNsec::generate().public_key(),
+            SecretNsec::generate().public_key(),
+        );
+        let funding_txid = Txid::all_zeros();
+        let config = EscrowConfig {
+            npub_1,
+            npub_2,
+            npub_arbitrator: Some(npub_arbitrator),
+            timelock_duration: Some(144),
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let session = WatchSession::new(config, funding_txid, "Order 42").unwrap();
+        let without_arbitrator = EscrowConfig {
+            npub_arbitrator: None,
+            ..config
+        };
+        assert!(WatchSession::new(without_arbitrator, funding_txid, "").is_err());
+
+        let mut report = AuditReport {
+            funding_txid,
+            outpoint: None,
+            amount: None,
+            confirmed_height: None,
+            spend: None,
+            near_miss: None,
+        };
+        assert_eq!(session.status(&report, 100, None), WatchStatus::Mismatch);
+        report.outpoint = Some(OutPoint::new(funding_txid, 0));
+        report.amount = Some(Amount::from_sat(100_000));
+        assert_eq!(session.status(&report, 100, None), WatchStatus::Unconfirmed);
+        report.confirmed_height = Some(100);
+        assert_eq!(
+            session.status(&report, 105, None),
+            WatchStatus::Funded {
+                confirmations: 6,
+                timelock_height: Some(244)
+            }
+        );
+        let progress = session.status(&report, 101, None).funding_progress(3);
+        assert_eq!(progress.description(), "2 of 3 confirmations");
+        assert!(!progress.is_complete());
+        assert!(
+            session
+                .status(&report, 105, None)
+                .funding_progress(3)
+                .is_complete()
+        );
+
+        let payout = TxOut {
+            value: Amount::from_sat(99_000),
+            script_pubkey: ScriptBuf::new(),
+        };
+        report.spend = Some(SpendAudit {
+            txid: Txid::all_zeros(),
+            input_index: 0,
+            path: Some(SpendPath::Leaf(EscrowScript::A)),
+            signers: vec![
+                SignerAudit {
+                    npub: npub_1,
+                    valid: true,
+                },
+                SignerAudit {
+                    npub: npub_2,
+                    valid: false,
+                },
+            ],
+            payouts: vec![payout],
+            confirmed_height: None,
+        });
+        let status = session.status(&report, 105, None);
+        assert_eq!(
+            status,
+            WatchStatus::Resolved {
+                path: Some(SpendPath::Leaf(EscrowScript::A)),
+                confirmations: 0
+            }
+        );
+
+        // Spent by another transaction than the expected one.
+        let other = Txid::from_byte_array([2; 32]);
+        assert_eq!(
+            session.status(&report, 105, Some(&[other])),
+            WatchStatus::Conflict {
+                txid: Txid::all_zeros(),
+                path: Some(SpendPath::Leaf(EscrowScript::A)),
+                confirmations: 0
+            }
+        );
+        assert_eq!(
+            session.status(&report, 105, Some(&[Txid::all_zeros()])),
+            status
+        );
+        let spend = report.spend.as_ref().unwrap();
+        assert!(Conflict::detect(spend, &[Txid::all_zeros()]).is_none());
+        let conflict = Conflict::detect(spend, &[other]).unwrap();
+        let description = conflict.description();
+        assert!(description.contains(&Npub::from(npub_1).to_string()));
+        assert!(!description.contains(&Npub::from(npub_2).to_string()));
+
+        let text = session.audit_report(&report, status).unwrap();
+        assert!(text.contains("Label: Order 42"));
+        assert!(text.contains(&session.address().unwrap().to_string()));
+        assert!(text.contains(&Npub::from(npub_arbitrator).to_string()));
+        assert!(text.contains("Escrow output: 0000000000000000000000000000000000000000000000000000000000000000:0, 0.00100000 BTC"));
+        assert!(text.contains(": INVALID"));
+        assert!(!text.contains("Spent through a leaf"));
+        assert!(text.contains("Payout: 0.00099000 BTC"));
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn watch_session_storage() {
+        use crate::storage::MemoryStorage;
+
+        let config = EscrowConfig {
+            npub_1: SecretNsec::generate().public_key(),
+            npub_2: SecretNsec::generate().public_key(),
+            npub_arbitrator: None,
+            timelock_duration: None,
+            network: Network::Regtest,
+            template: CURRENT_SCRIPT_TEMPLATE,
+        };
+        let session = WatchSession::new(config, Txid::all_zeros(), "").unwrap();
+        let storage = MemoryStorage::default();
+        session.save(&storage).unwrap();
+        session.save(&storage).unwrap();
+        assert_eq!(
+            WatchSession::list(&storage).unwrap(),
+            [session.funding_txid]
+        );
+        assert_eq!(
+            WatchSession::load(&storage, &session.funding_txid).unwrap(),
+            Some(session.clone())
+        );
+        WatchSession::remove(&storage, &session.funding_txid).unwrap();
+        assert!(WatchSession::list(&storage).unwrap().is_empty());
+        assert_eq!(
+            WatchSession::load(&storage, &session.funding_txid).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Webhooks notifying merchants of escrow state transitions, in native builds.
+//!
+//! Merchants running scrow server-side register [`Webhook`]s: URLs that get an HTTP POST
+//! of a JSON [`WebhookPayload`] whenever a watched escrow is funded, resolved, disputed,
+//! or its resolution confirmed, see [`Transition`].
+//! Transitions are derived from the [`WatchStatus`]es the watcher reports,
+//! so a status refresh that skips several states still notifies each of them in order.
+//!
+//! Payloads are signed with HMAC-SHA256 under the webhook's shared secret, sent in the
+//! [`SIGNATURE_HEADER`], and carry a timestamp so receivers can reject replays.
+
+use std::fmt;
+
+use bitcoin::{
+    Txid,
+    hashes::{Hash, HashEngine, hmac, sha256},
+};
+use nostr::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    logging::Redacted,
+    protocol::{deserialize, serialize},
+    runtime::post_json_with_headers,
+    scripts::{EscrowScript, SpendPath},
+    storage::Storage,
+    watch::{WatchSession, WatchStatus},
+};
+
+/// [`Storage`] key of the [`Webhooks`].
+pub(crate) const WEBHOOKS_KEY: &str = "scrow.webhooks";
+
+/// Header holding the [`Transition`] of a payload.
+pub(crate) const EVENT_HEADER: &str = "X-Scrow-Event";
+
+/// Header holding the signature of a payload, `sha256=` followed by the hex HMAC.
+pub(crate) const SIGNATURE_HEADER: &str = "X-Scrow-Signature";
+
+/// Default confirmations of a resolution for [`Transition::Confirmed`].
+pub(crate) const DEFAULT_CONFIRMATIONS: u32 = 6;
+
+/// A state transition of a watched escrow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Transition {
+    /// The funding transaction confirmed.
+    Funded,
+    /// The escrow was spent by the participants, or by an expected transaction.
+    Resolved,
+    /// The escrow was spent through a dispute leaf, or by an unexpected transaction.
+    Disputed,
+    /// The spend of the escrow reached the configured confirmations.
+    Confirmed,
+}
+
+impl Transition {
+    /// The transitions of an escrow whose status went from `previous` to `current`, in order,
+    /// given the `confirmations` a resolution needs to be [`Transition::Confirmed`].
+    pub(crate) fn between(
+        previous: Option<WatchStatus>,
+        current: WatchStatus,
+        confirmations: u32,
+    ) -> Vec<Self> {
+        let reached =
+            previous.map_or_else(Vec::new, |previous| milestones(previous, confirmations));
+        milestones(current, confirmations)
+            .into_iter()
+            .filter(|transition| !reached.contains(transition))
+            .collect()
+    }
+
+    /// Name of the transition, as in payloads.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Transition::Funded => "funded",
+            Transition::Resolved => "resolved",
+            Transition::Disputed => "disputed",
+            Transition::Confirmed => "confirmed",
+        }
+    }
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The transitions an escrow with `status` went through, in order,
+/// given the `required` confirmations of a resolution.
+fn milestones(status: WatchStatus, required: u32) -> Vec<Transition> {
+    let (spend, spend_confirmations) = match status {
+        WatchStatus::Mismatch | WatchStatus::Unconfirmed => return Vec::new(),
+        WatchStatus::Funded { .. } => return vec![Transition::Funded],
+        WatchStatus::Resolved {
+            path: Some(SpendPath::Leaf(EscrowScript::B | EscrowScript::C)),
+            confirmations,
+        }
+        | WatchStatus::Conflict { confirmations, .. } => (Transition::Disputed, confirmations),
+        WatchStatus::Resolved { confirmations, .. } => (Transition::Resolved, confirmations),
+    };
+    let mut milestones = vec![Transition::Funded, spend];
+    if spend_confirmations >= required.max(1) {
+        milestones.push(Transition::Confirmed);
+    }
+    milestones
+}
+
+/// The body of a webhook request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct WebhookPayload {
+    /// What happened to the escrow.
+    pub(crate) transition: Transition,
+    /// Transaction funding the escrow, identifying it.
+    pub(crate) funding_txid: Txid,
+    /// Label of the watched escrow, such as an order number.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) label: String,
+    /// The spending transaction, if it was unexpected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) conflict_txid: Option<Txid>,
+    /// Confirmations of the funding transaction, or of the spend once spent.
+    pub(crate) confirmations: u32,
+    /// When the transition was noticed, in seconds since the Unix epoch.
+    pub(crate) timestamp: u64,
+}
+
+impl WebhookPayload {
+    /// The payload of `transition` of the escrow of `watch`, now at `status`.
+    pub(crate) fn new(
+        watch: &WatchSession,
+        transition: Transition,
+        status: WatchStatus,
+        now: Timestamp,
+    ) -> Self {
+        let (conflict_txid, confirmations) = match status {
+            WatchStatus::Mismatch | WatchStatus::Unconfirmed => (None, 0),
+            WatchStatus::Funded { confirmations, .. }
+            | WatchStatus::Resolved { confirmations, .. } => (None, confirmations),
+            WatchStatus::Conflict {
+                txid,
+                confirmations,
+                ..
+            } => (Some(txid), confirmations),
+        };
+        Self {
+            transition,
+            funding_txid: watch.funding_txid,
+            label: watch.label.clone(),
+            conflict_txid,
+            confirmations,
+            timestamp: now.as_u64(),
+        }
+    }
+}
+
+/// An endpoint notified of escrow transitions.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Webhook {
+    /// URL the payloads are posted to.
+    pub(crate) url: String,
+    /// Secret shared with the receiver, keying the payload signatures.
+    pub(crate) secret: String,
+    /// Transitions to notify, all of them if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) transitions: Vec<Transition>,
+}
+
+impl Webhook {
+    /// A webhook posting every transition to `url`, signed with `secret`.
+    pub(crate) fn new(url: &str, secret: &str) -> Result<Self, Error> {
+        let url = url.trim();
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            return Err(Error::WrongInputs(format!(
+                "Webhook URL {url} must start with https:// or http://"
+            )));
+        }
+        if secret.is_empty() {
+            return Err(Error::WrongInputs("Webhook secret is empty".to_string()));
+        }
+        Ok(Self {
+            url: url.to_string(),
+            secret: secret.to_string(),
+            transitions: Vec::new(),
+        })
+    }
+
+    /// Whether the webhook is notified of `transition`.
+    pub(crate) fn notifies(&self, transition: Transition) -> bool {
+        self.transitions.is_empty() || self.transitions.contains(&transition)
+    }
+
+    /// Posts the signed `payload` to the webhook.
+    pub(crate) async fn send(&self, payload: &WebhookPayload) -> Result<(), Error> {
+        let body = serialize(payload)?;
+        let signature = sign_payload(&self.secret, &body);
+        let headers = [
+            (EVENT_HEADER, payload.transition.name()),
+            (SIGNATURE_HEADER, signature.as_str()),
+        ];
+        post_json_with_headers(&self.url, &body, &headers).await?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Webhook")
+            .field("url", &self.url)
+            .field("secret", &Redacted(&self.secret))
+            .field("transitions", &self.transitions)
+            .finish()
+    }
+}
+
+/// The configured webhooks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Webhooks {
+    /// Endpoints notified of the transitions.
+    pub(crate) webhooks: Vec<Webhook>,
+    /// Confirmations a resolution needs to be [`Transition::Confirmed`].
+    pub(crate) confirmations: u32,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self {
+            webhooks: Vec::new(),
+            confirmations: DEFAULT_CONFIRMATIONS,
+        }
+    }
+}
+
+impl Webhooks {
+    /// Loads the webhooks from `storage`, or none if none were saved.
+    pub(crate) fn load(storage: &impl Storage) -> Result<Self, Error> {
+        match storage.get(WEBHOOKS_KEY)? {
+            Some(json) => deserialize(&json),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the webhooks to `storage`.
+    pub(crate) fn save(&self, storage: &impl Storage) -> Result<(), Error> {
+        storage.set(WEBHOOKS_KEY, &serialize(self)?)
+    }
+
+    /// Notifies the webhooks of the transitions of the escrow of `watch`,
+    /// whose status went from `previous` to `current`.
+    ///
+    /// Every webhook gets every transition it subscribed to, even if another failed;
+    /// returns the URL and transition of the failed notifications with their error.
+    pub(crate) async fn notify(
+        &self,
+        watch: &WatchSession,
+        previous: Option<WatchStatus>,
+        current: WatchStatus,
+        now: Timestamp,
+    ) -> Vec<(String, Transition, Error)> {
+        let mut failures = Vec::new();
+        for transition in Transition::between(previous, current, self.confirmations) {
+            let payload = WebhookPayload::new(watch, transition, current, now);
+            for webhook in self.webhooks.iter().filter(|w| w.notifies(transition)) {
+                if let Err(e) = webhook.send(&payload).await {
+                    failures.push((webhook.url.clone(), transition, e));
+                }
+            }
+        }
+        failures
+    }
+}
+
+/// Signs a payload `body` with the webhook `secret`, as sent in the [`SIGNATURE_HEADER`].
+pub(crate) fn sign_payload(secret: &str, body: &str) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+    engine.input(body.as_bytes());
+    format!("sha256={}", hmac::Hmac::<sha256::Hash>::from_engine(engine))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhook_transitions() {
+        let funded = WatchStatus::Funded {
+            confirmations: 1,
+            timelock_height: None,
+        };
+        let resolved = |confirmations| WatchStatus::Resolved {
+            path: Some(SpendPath::Leaf(EscrowScript::A)),
+            confirmations,
+        };
+        let disputed = WatchStatus::Resolved {
+            path: Some(SpendPath::Leaf(EscrowScript::B)),
+            confirmations: 0,
+        };
+        assert!(Transition::between(None, WatchStatus::Unconfirmed, 6).is_empty());
+        assert_eq!(
+            Transition::between(Some(WatchStatus::Unconfirmed), funded, 6),
+            [Transition::Funded]
+        );
+        assert!(Transition::between(Some(funded), funded, 6).is_empty());
+        assert_eq!(
+            Transition::between(Some(funded), resolved(0), 6),
+            [Transition::Resolved]
+        );
+        assert_eq!(
+            Transition::between(Some(resolved(5)), resolved(6), 6),
+            [Transition::Confirmed]
+        );
+        // A refresh skipping states notifies each of them.
+        assert_eq!(
+            Transition::between(None, resolved(6), 6),
+            [
+                Transition::Funded,
+                Transition::Resolved,
+                Transition::Confirmed
+            ]
+        );
+        assert_eq!(
+            Transition::between(Some(funded), disputed, 6),
+            [Transition::Disputed]
+        );
+
+        let body = r#"{"transition":"funded"}"#;
+        let signature = sign_payload("secret", body);
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(sign_payload("secret", body), signature);
+        assert_ne!(sign_payload("other", body), signature);
+        assert_ne!(
+            sign_payload("secret", r#"{"transition":"resolved"}"#),
+            signature
+        );
+
+        assert!(Webhook::new("ftp://example.com", "secret").is_err());
+        assert!(Webhook::new("https://example.com/hook", "").is_err());
+        let webhook = Webhook::new(" https://example.com/hook ", "hunter2").unwrap();
+        assert_eq!(webhook.url, "https://example.com/hook");
+        assert!(!format!("{webhook:?}").contains("hunter2"));
+    }
+}